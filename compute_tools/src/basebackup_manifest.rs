@@ -0,0 +1,243 @@
+//! Incremental basebackups: reuse the files of the previous basebackup that are
+//! still intact in `pgdata`.
+//!
+//! An incremental basebackup ends with a [`MANIFEST_FILE_NAME`] file that lists
+//! the checksum, size and LSN of every regular file of the basebackup. On the
+//! next start, [`prepare_pgdata`] checks the files against it, removes every
+//! other file, and returns the manifest of the intact ones to send to the
+//! pageserver, which leaves them out of the tarball. Once the tarball is
+//! unpacked, [`remove_dropped_files`] removes the files that are no longer part
+//! of the basebackup.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use tracing::info;
+use utils::lsn::Lsn;
+
+/// Name of the file that ends an incremental basebackup.
+pub const MANIFEST_FILE_NAME: &str = "basebackup.manifest";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    checksum: u32,
+    size: u64,
+    lsn: Lsn,
+}
+
+/// The regular files of a basebackup, by path, in the format of the pageserver:
+/// `path:checksum:size:lsn` entries, with the checksum in hex.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest(BTreeMap<String, Entry>);
+
+impl Manifest {
+    /// The manifest as the argument of the `basebackup --incremental` command.
+    pub fn to_query_arg(&self) -> String {
+        self.0
+            .iter()
+            .map(|(path, entry)| {
+                format!("{path}:{:08x}:{}:{}", entry.checksum, entry.size, entry.lsn)
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn read(pgdata: &Path) -> Result<Option<Self>> {
+        match fs::read_to_string(pgdata.join(MANIFEST_FILE_NAME)) {
+            Ok(manifest) => Ok(Some(manifest.parse()?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("could not read the basebackup manifest"),
+        }
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut files = BTreeMap::new();
+        for line in s.lines().filter(|line| !line.is_empty()) {
+            let mut fields = line.split(':');
+            let (Some(path), Some(checksum), Some(size), Some(lsn), None) = (
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+            ) else {
+                anyhow::bail!("invalid manifest entry {line:?}");
+            };
+            let entry = Entry {
+                checksum: u32::from_str_radix(checksum, 16)
+                    .with_context(|| format!("invalid checksum in manifest entry {line:?}"))?,
+                size: size
+                    .parse()
+                    .with_context(|| format!("invalid size in manifest entry {line:?}"))?,
+                lsn: Lsn::from_str(lsn)
+                    .with_context(|| format!("invalid LSN in manifest entry {line:?}"))?,
+            };
+            files.insert(path.to_owned(), entry);
+        }
+        Ok(Self(files))
+    }
+}
+
+/// Keep the files of the previous basebackup in `pgdata` that still have the
+/// checksum and size of its manifest, and remove all the other files, such as
+/// the ones Postgres wrote since, or the `pg_dynshmem` symlink.
+///
+/// Returns the manifest of the kept files, or `None` if there is no previous
+/// basebackup to start from.
+pub fn prepare_pgdata(pgdata: &Path) -> Result<Option<Manifest>> {
+    let Some(previous) = Manifest::read(pgdata)? else {
+        return Ok(None);
+    };
+    let mut kept = Manifest::default();
+    let mut removed = 0;
+    for path in files(pgdata)? {
+        let relative = path
+            .strip_prefix(pgdata)
+            .expect("walked from pgdata")
+            .to_string_lossy()
+            .into_owned();
+        let intact = match previous.0.get(&relative) {
+            Some(entry) => {
+                let metadata = fs::symlink_metadata(&path)?;
+                (metadata.is_file()
+                    && metadata.len() == entry.size
+                    && file_checksum(&path)? == entry.checksum)
+                    .then_some(*entry)
+            }
+            None => None,
+        };
+        if let Some(entry) = intact {
+            kept.0.insert(relative, entry);
+        } else {
+            fs::remove_file(&path)
+                .with_context(|| format!("could not remove {}", path.display()))?;
+            removed += 1;
+        }
+    }
+    info!(
+        "kept {} files of the previous basebackup, removed {removed}",
+        kept.0.len()
+    );
+    Ok(Some(kept))
+}
+
+/// Remove the files of `previous` that are not in the manifest of the
+/// basebackup just unpacked in `pgdata`, and the database directories left
+/// empty.
+pub fn remove_dropped_files(pgdata: &Path, previous: &Manifest) -> Result<()> {
+    let current = Manifest::read(pgdata)?.context("the incremental basebackup has no manifest")?;
+    let base = pgdata.join("base");
+    for path in previous.0.keys() {
+        if current.0.contains_key(path) {
+            continue;
+        }
+        let path = pgdata.join(path);
+        fs::remove_file(&path).with_context(|| format!("could not remove {}", path.display()))?;
+        if let Some(dbdir) = path
+            .parent()
+            .filter(|dir| dir.parent() == Some(base.as_path()))
+        {
+            if fs::read_dir(dbdir)?.next().is_none() {
+                fs::remove_dir(dbdir)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The files under `dir`, including symlinks, which are not followed.
+fn files(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn file_checksum(path: &Path) -> Result<u32> {
+    let mut file = fs::File::open(path)?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut checksum = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(checksum);
+        }
+        checksum = crc32c::crc32c_append(checksum, &buf[..n]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepare_and_remove_dropped() {
+        let pgdata = camino_tempfile::tempdir().unwrap();
+        let pgdata = pgdata.path().as_std_path();
+        fs::create_dir_all(pgdata.join("base/5")).unwrap();
+        fs::create_dir_all(pgdata.join("pg_xact")).unwrap();
+        fs::write(pgdata.join("pg_xact/0000"), b"clog").unwrap();
+        fs::write(pgdata.join("base/5/16384_init"), b"init").unwrap();
+        fs::write(pgdata.join("base/5/PG_VERSION"), b"16\n").unwrap();
+        fs::write(pgdata.join("postgresql.conf"), b"").unwrap();
+        fs::write(
+            pgdata.join(MANIFEST_FILE_NAME),
+            format!(
+                "base/5/16384_init:{:08x}:4:0/16A9388\n\
+                 base/5/PG_VERSION:{:08x}:3:0/16A9388\n\
+                 pg_xact/0000:{:08x}:4:0/16A9388\n",
+                crc32c::crc32c(b"init"),
+                crc32c::crc32c(b"16\n"),
+                crc32c::crc32c(b"clog"),
+            ),
+        )
+        .unwrap();
+        // Written by Postgres since the basebackup.
+        fs::write(pgdata.join("pg_xact/0000"), b"clog2").unwrap();
+
+        let kept = prepare_pgdata(pgdata).unwrap().unwrap();
+        assert_eq!(
+            kept.0.keys().collect::<Vec<_>>(),
+            ["base/5/16384_init", "base/5/PG_VERSION"]
+        );
+        assert!(!pgdata.join("pg_xact/0000").exists());
+        assert!(!pgdata.join("postgresql.conf").exists());
+        assert!(!pgdata.join(MANIFEST_FILE_NAME).exists());
+
+        // The next basebackup no longer has the database.
+        fs::write(
+            pgdata.join(MANIFEST_FILE_NAME),
+            format!(
+                "pg_xact/0000:{:08x}:5:0/16B0000\n",
+                crc32c::crc32c(b"clog2")
+            ),
+        )
+        .unwrap();
+        remove_dropped_files(pgdata, &kept).unwrap();
+        assert!(!pgdata.join("base/5").exists());
+        assert!(pgdata.join("base").exists());
+    }
+
+    #[test]
+    fn no_previous_basebackup() {
+        let pgdata = camino_tempfile::tempdir().unwrap();
+        assert_eq!(prepare_pgdata(pgdata.path().as_std_path()).unwrap(), None);
+    }
+}
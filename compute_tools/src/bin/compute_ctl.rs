@@ -3,8 +3,9 @@
 //! `ExecStart` option. It will handle all the `Neon` specifics during compute node
 //! initialization:
 //! - `compute_ctl` accepts cluster (compute node) specification as a JSON file.
-//! - Every start is a fresh start, so the data directory is removed and
-//!   initialized again on each run.
+//! - Every start is a fresh start, so the data directory is initialized again
+//!   on each run. The files of the previous basebackup that are still intact
+//!   are kept, and the basebackup leaves them out; everything else is removed.
 //! - If remote_extension_config is provided, it will be used to fetch extensions list
 //!  and download `shared_preload_libraries` from the remote storage.
 //! - Next it will put configuration files into the `PGDATA` directory.
@@ -32,6 +32,7 @@ use nix::sys::signal::{kill, Signal};
 
 use remote_storage::{DownloadError, RemotePath};
 
+use crate::basebackup_manifest;
 use crate::checker::create_availability_check_data;
 use crate::logger::inlinify;
 use crate::pg_helpers::*;
@@ -321,8 +322,15 @@ impl ComputeNode {
 
     // Get basebackup from the libpq connection to pageserver using `connstr` and
     // unarchive it to `pgdata` directory overriding all its previous content.
+    // With a `previous` manifest, the basebackup leaves out the files of `pgdata`
+    // that are in it and still current.
     #[instrument(skip_all, fields(%lsn))]
-    fn try_get_basebackup(&self, compute_state: &ComputeState, lsn: Lsn) -> Result<()> {
+    fn try_get_basebackup(
+        &self,
+        compute_state: &ComputeState,
+        lsn: Lsn,
+        previous: Option<&basebackup_manifest::Manifest>,
+    ) -> Result<()> {
         let spec = compute_state.pspec.as_ref().expect("spec must be set");
         let start_time = Instant::now();
 
@@ -342,7 +350,7 @@ impl ComputeNode {
         let mut client = config.connect(NoTls)?;
         let pageserver_connect_micros = start_time.elapsed().as_micros() as u64;
 
-        let basebackup_cmd = match lsn {
+        let mut basebackup_cmd = match lsn {
             // HACK We don't use compression on first start (Lsn(0)) because there's no API for it
             Lsn(0) => format!("basebackup {} {}", spec.tenant_id, spec.timeline_id),
             _ => format!(
@@ -350,6 +358,10 @@ impl ComputeNode {
                 spec.tenant_id, spec.timeline_id, lsn
             ),
         };
+        if let Some(previous) = previous {
+            basebackup_cmd.push_str(" --incremental ");
+            basebackup_cmd.push_str(&previous.to_query_arg());
+        }
 
         let copyreader = client.copy_out(basebackup_cmd.as_str())?;
         let mut measured_reader = MeasuredReader::new(copyreader);
@@ -395,13 +407,21 @@ impl ComputeNode {
     }
 
     // Gets the basebackup in a retry loop
+    //
+    // An incremental basebackup isn't retried: a partially unpacked tarball
+    // leaves files that no longer match the manifest.
     #[instrument(skip_all, fields(%lsn))]
-    pub fn get_basebackup(&self, compute_state: &ComputeState, lsn: Lsn) -> Result<()> {
+    pub fn get_basebackup(
+        &self,
+        compute_state: &ComputeState,
+        lsn: Lsn,
+        previous: Option<&basebackup_manifest::Manifest>,
+    ) -> Result<()> {
         let mut retry_period_ms = 500.0;
         let mut attempts = 0;
-        let max_attempts = 10;
+        let max_attempts = if previous.is_some() { 0 } else { 10 };
         loop {
-            let result = self.try_get_basebackup(compute_state, lsn);
+            let result = self.try_get_basebackup(compute_state, lsn, previous);
             match result {
                 Ok(_) => {
                     return result;
@@ -589,8 +609,22 @@ impl ComputeNode {
         let spec = &pspec.spec;
         let pgdata_path = Path::new(&self.pgdata);
 
-        // Remove/create an empty pgdata directory and put configuration there.
-        self.create_pgdata()?;
+        // Keep the files of the previous basebackup that are still intact, so
+        // that the pageserver only sends the others. A standby asks for the
+        // latest LSN, which an incremental basebackup can't do.
+        let previous = match spec.mode {
+            ComputeMode::Replica => None,
+            _ => basebackup_manifest::prepare_pgdata(pgdata_path).unwrap_or_else(|e| {
+                warn!("could not reuse the previous basebackup: {e:#}");
+                None
+            }),
+        };
+
+        // Otherwise, remove/create an empty pgdata directory. Then put
+        // configuration there.
+        if previous.is_none() {
+            self.create_pgdata()?;
+        }
         config::write_postgres_conf(
             &pgdata_path.join("postgresql.conf"),
             &pspec.spec,
@@ -627,12 +661,34 @@ impl ComputeNode {
             "getting basebackup@{} from pageserver {}",
             lsn, &pspec.pageserver_connstr
         );
-        self.get_basebackup(compute_state, lsn).with_context(|| {
-            format!(
-                "failed to get basebackup@{} from pageserver {}",
-                lsn, &pspec.pageserver_connstr
-            )
-        })?;
+        let incremental_done = match &previous {
+            Some(previous) => match self
+                .get_basebackup(compute_state, lsn, Some(previous))
+                .and_then(|()| basebackup_manifest::remove_dropped_files(pgdata_path, previous))
+            {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("incremental basebackup failed, starting from an empty pgdata: {e:#}");
+                    self.create_pgdata()?;
+                    config::write_postgres_conf(
+                        &pgdata_path.join("postgresql.conf"),
+                        &pspec.spec,
+                        Some(extension_server_port),
+                    )?;
+                    false
+                }
+            },
+            None => false,
+        };
+        if !incremental_done {
+            self.get_basebackup(compute_state, lsn, None)
+                .with_context(|| {
+                    format!(
+                        "failed to get basebackup@{} from pageserver {}",
+                        lsn, &pspec.pageserver_connstr
+                    )
+                })?;
+        }
 
         // Update pg_hba.conf received with basebackup.
         update_pg_hba(pgdata_path)?;
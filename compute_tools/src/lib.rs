@@ -2,6 +2,7 @@
 //! configuration.
 #![deny(unsafe_code)]
 #![deny(clippy::undocumented_unsafe_blocks)]
+pub mod basebackup_manifest;
 pub mod checker;
 pub mod config;
 pub mod configurator;
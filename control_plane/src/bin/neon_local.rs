@@ -13,7 +13,7 @@ use control_plane::local_env::{
     InitForceMode, LocalEnv, NeonBroker, NeonLocalInitConf, NeonLocalInitPageserverConf,
     SafekeeperConf,
 };
-use control_plane::pageserver::PageServerNode;
+use control_plane::pageserver::{ImportWal, PageServerNode};
 use control_plane::safekeeper::SafekeeperNode;
 use control_plane::storage_controller::StorageController;
 use control_plane::{broker, local_env};
@@ -506,6 +506,7 @@ async fn handle_tenant(
                         ancestor_start_lsn: None,
                         existing_initdb_timeline_id: None,
                         pg_version: Some(pg_version),
+                        snapshot_id: None,
                     },
                 )
                 .await?;
@@ -581,6 +582,7 @@ async fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::Local
                 existing_initdb_timeline_id: None,
                 ancestor_start_lsn: None,
                 pg_version: Some(pg_version),
+                snapshot_id: None,
             };
             let timeline_info = storage_controller
                 .tenant_timeline_create(tenant_id, create_req)
@@ -619,11 +621,20 @@ async fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::Local
 
             // Parse pg_wal inputs
             let wal_tarfile = import_match.get_one::<PathBuf>("wal-tarfile").cloned();
+            let wal_archive = import_match.get_flag("wal-archive");
             let end_lsn = import_match
                 .get_one::<String>("end-lsn")
                 .map(|s| Lsn::from_str(s).unwrap());
+            let wal = match (wal_tarfile, wal_archive) {
+                (Some(_), true) => {
+                    bail!("--wal-tarfile and --wal-archive are mutually exclusive")
+                }
+                (Some(wal_tarfile), false) => Some(ImportWal::Tarfile(wal_tarfile)),
+                (None, true) => Some(ImportWal::Archive),
+                (None, false) => None,
+            };
             // TODO validate both or none are provided
-            let pg_wal = end_lsn.zip(wal_tarfile);
+            let pg_wal = end_lsn.zip(wal);
 
             let pg_version = import_match
                 .get_one::<u32>("pg-version")
@@ -678,6 +689,7 @@ async fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::Local
                 existing_initdb_timeline_id: None,
                 ancestor_start_lsn: start_lsn,
                 pg_version: None,
+                snapshot_id: None,
             };
             let timeline_info = storage_controller
                 .tenant_timeline_create(tenant_id, create_req)
@@ -861,6 +873,7 @@ async fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Re
             let remote_ext_config = sub_args.get_one::<String>("remote-ext-config");
 
             let allow_multiple = sub_args.get_flag("allow-multiple");
+            let keep_pgdata = sub_args.get_flag("keep-pgdata");
 
             // If --safekeepers argument is given, use only the listed safekeeper nodes.
             let safekeepers =
@@ -944,6 +957,7 @@ async fn handle_endpoint(ep_match: &ArgMatches, env: &local_env::LocalEnv) -> Re
                     remote_ext_config,
                     stripe_size.0 as usize,
                     create_test_user,
+                    keep_pgdata,
                 )
                 .await?;
         }
@@ -1407,6 +1421,12 @@ fn cli() -> Command {
         .help("If set, will create test user `user` and `neondb` database. Requires `update-catalog = true`")
         .required(false);
 
+    let keep_pgdata = Arg::new("keep-pgdata")
+        .help("Keep the data directory of the previous launch, so that compute_ctl takes an incremental basebackup.")
+        .long("keep-pgdata")
+        .action(ArgAction::SetTrue)
+        .required(false);
+
     let allow_multiple = Arg::new("allow-multiple")
         .help("Allow multiple primary endpoints running on the same branch. Shouldn't be used normally, but useful for tests.")
         .long("allow-multiple")
@@ -1470,6 +1490,11 @@ fn cli() -> Command {
                     .value_parser(value_parser!(PathBuf))
                     .help("Wal to add after base")
                 )
+                .arg(Arg::new("wal-archive")
+                    .long("wal-archive")
+                    .action(ArgAction::SetTrue)
+                    .help("Fetch the wal to add after base from the WAL archive of the pageserver, with its wal_archive_restore_command")
+                )
                 .arg(Arg::new("end-lsn").long("end-lsn")
                     .help("Lsn the basebackup ends at"))
                 .arg(pg_version_arg.clone())
@@ -1576,6 +1601,7 @@ fn cli() -> Command {
                     .arg(safekeepers_arg)
                     .arg(remote_ext_config_args)
                     .arg(create_test_user)
+                    .arg(keep_pgdata)
                     .arg(allow_multiple.clone())
                 )
                 .subcommand(Command::new("reconfigure")
@@ -499,6 +499,7 @@ impl Endpoint {
             .join(",")
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn start(
         &self,
         auth_token: &Option<String>,
@@ -507,6 +508,7 @@ impl Endpoint {
         remote_ext_config: Option<&String>,
         shard_stripe_size: usize,
         create_test_user: bool,
+        keep_pgdata: bool,
     ) -> Result<()> {
         if self.status() == EndpointStatus::Running {
             anyhow::bail!("The endpoint is already running");
@@ -514,9 +516,10 @@ impl Endpoint {
 
         let postgresql_conf = self.read_postgresql_conf()?;
 
-        // We always start the compute node from scratch, so if the Postgres
-        // data dir exists from a previous launch, remove it first.
-        if self.pgdata().exists() {
+        // We start the compute node from scratch, so if the Postgres data dir
+        // exists from a previous launch, remove it first. If asked to keep it,
+        // compute_ctl reuses the files of its previous basebackup instead.
+        if !keep_pgdata && self.pgdata().exists() {
             std::fs::remove_dir_all(self.pgdata())?;
         }
 
@@ -17,8 +17,8 @@ use anyhow::{bail, Context};
 use camino::Utf8PathBuf;
 use futures::SinkExt;
 use pageserver_api::models::{
-    self, AuxFilePolicy, LocationConfig, ShardParameters, TenantHistorySize, TenantInfo,
-    TimelineInfo,
+    self, AuxFilePolicy, LocationConfig, ReadOnlyReason, ShardParameters, TenantHistorySize,
+    TenantInfo, TimelineInfo,
 };
 use pageserver_api::shard::TenantShardId;
 use pageserver_client::mgmt_api;
@@ -36,6 +36,15 @@ use crate::{background_process, local_env::LocalEnv};
 /// Directory within .neon which will be used by default for LocalFs remote storage.
 pub const PAGESERVER_REMOTE_STORAGE_DIR: &str = "local_fs_remote_storage/pageserver";
 
+/// Where the WAL after the base backup comes from, in [`PageServerNode::timeline_import`].
+pub enum ImportWal {
+    /// A tarball of the `pg_wal` directory of the backup.
+    Tarfile(PathBuf),
+    /// The WAL archive of the pageserver, read with the Postgres-style restore command of its
+    /// `wal_archive_restore_command` config.
+    Archive,
+}
+
 //
 // Control routines for pageserver.
 //
@@ -354,6 +363,16 @@ impl PageServerNode {
                 .map(|x| x.parse::<bool>())
                 .transpose()
                 .context("Failed to parse 'trace_read_requests' as bool")?,
+            capture_wal: settings
+                .remove("capture_wal")
+                .map(|x| x.parse::<bool>())
+                .transpose()
+                .context("Failed to parse 'capture_wal' as bool")?,
+            branch_image_creation_threshold: settings
+                .remove("branch_image_creation_threshold")
+                .map(|x| x.parse::<u64>())
+                .transpose()
+                .context("Failed to parse 'branch_image_creation_threshold' as an integer")?,
             eviction_policy: settings
                 .remove("eviction_policy")
                 .map(serde_json::from_str)
@@ -383,6 +402,59 @@ impl PageServerNode {
                 .map(|x| x.parse::<AuxFilePolicy>())
                 .transpose()
                 .context("Failed to parse 'switch_aux_file_policy'")?,
+            ephemeral_bytes_limit: settings
+                .remove("ephemeral_bytes_limit")
+                .map(|x| x.parse::<u64>())
+                .transpose()
+                .context("Failed to parse 'ephemeral_bytes_limit' as an integer")?,
+            ancestor_prefetch: settings
+                .remove("ancestor_prefetch")
+                .map(|x| x.parse::<bool>())
+                .transpose()
+                .context("Failed to parse 'ancestor_prefetch' as bool")?,
+            overlap_repair_threshold: settings
+                .remove("overlap_repair_threshold")
+                .map(|x| x.parse::<usize>())
+                .transpose()
+                .context("Failed to parse 'overlap_repair_threshold' as an integer")?,
+            page_request_deadline: settings
+                .remove("page_request_deadline")
+                .map(|x| x.to_string()),
+            sequential_prefetch: settings
+                .remove("sequential_prefetch")
+                .map(|x| x.parse::<bool>())
+                .transpose()
+                .context("Failed to parse 'sequential_prefetch' as bool")?,
+            branch_creation_max_compaction_debt: settings
+                .remove("branch_creation_max_compaction_debt")
+                .map(|x| x.parse::<u64>())
+                .transpose()
+                .context("Failed to parse 'branch_creation_max_compaction_debt' as an integer")?,
+            branch_creation_debt_wait: settings
+                .remove("branch_creation_debt_wait")
+                .map(|x| x.to_string()),
+            alias: settings.remove("alias").map(|x| x.to_string()),
+            timeline_aliases: settings
+                .remove("timeline_aliases")
+                .map(serde_json::from_str)
+                .transpose()
+                .context("parse `timeline_aliases` from json")?,
+            gc_pinned_relations: settings
+                .remove("gc_pinned_relations")
+                .map(serde_json::from_str)
+                .transpose()
+                .context("parse `gc_pinned_relations` from json")?,
+            tags: settings
+                .remove("tags")
+                .map(serde_json::from_str)
+                .transpose()
+                .context("parse `tags` from json")?,
+            template: settings.remove("template").map(|x| x.to_string()),
+            read_only: settings
+                .remove("read_only")
+                .map(|x| x.parse::<ReadOnlyReason>())
+                .transpose()
+                .context("Failed to parse 'read_only'")?,
         };
         if !settings.is_empty() {
             bail!("Unrecognized tenant settings: {settings:?}")
@@ -477,6 +549,16 @@ impl PageServerNode {
                     .map(|x| x.parse::<bool>())
                     .transpose()
                     .context("Failed to parse 'trace_read_requests' as bool")?,
+                capture_wal: settings
+                    .remove("capture_wal")
+                    .map(|x| x.parse::<bool>())
+                    .transpose()
+                    .context("Failed to parse 'capture_wal' as bool")?,
+                branch_image_creation_threshold: settings
+                    .remove("branch_image_creation_threshold")
+                    .map(|x| x.parse::<u64>())
+                    .transpose()
+                    .context("Failed to parse 'branch_image_creation_threshold' as an integer")?,
                 eviction_policy: settings
                     .remove("eviction_policy")
                     .map(serde_json::from_str)
@@ -506,6 +588,61 @@ impl PageServerNode {
                     .map(|x| x.parse::<AuxFilePolicy>())
                     .transpose()
                     .context("Failed to parse 'switch_aux_file_policy'")?,
+                ephemeral_bytes_limit: settings
+                    .remove("ephemeral_bytes_limit")
+                    .map(|x| x.parse::<u64>())
+                    .transpose()
+                    .context("Failed to parse 'ephemeral_bytes_limit' as an integer")?,
+                ancestor_prefetch: settings
+                    .remove("ancestor_prefetch")
+                    .map(|x| x.parse::<bool>())
+                    .transpose()
+                    .context("Failed to parse 'ancestor_prefetch' as bool")?,
+                overlap_repair_threshold: settings
+                    .remove("overlap_repair_threshold")
+                    .map(|x| x.parse::<usize>())
+                    .transpose()
+                    .context("Failed to parse 'overlap_repair_threshold' as an integer")?,
+                page_request_deadline: settings
+                    .remove("page_request_deadline")
+                    .map(|x| x.to_string()),
+                sequential_prefetch: settings
+                    .remove("sequential_prefetch")
+                    .map(|x| x.parse::<bool>())
+                    .transpose()
+                    .context("Failed to parse 'sequential_prefetch' as bool")?,
+                branch_creation_max_compaction_debt: settings
+                    .remove("branch_creation_max_compaction_debt")
+                    .map(|x| x.parse::<u64>())
+                    .transpose()
+                    .context(
+                        "Failed to parse 'branch_creation_max_compaction_debt' as an integer",
+                    )?,
+                branch_creation_debt_wait: settings
+                    .remove("branch_creation_debt_wait")
+                    .map(|x| x.to_string()),
+                alias: settings.remove("alias").map(|x| x.to_string()),
+                timeline_aliases: settings
+                    .remove("timeline_aliases")
+                    .map(serde_json::from_str)
+                    .transpose()
+                    .context("parse `timeline_aliases` from json")?,
+                gc_pinned_relations: settings
+                    .remove("gc_pinned_relations")
+                    .map(serde_json::from_str)
+                    .transpose()
+                    .context("parse `gc_pinned_relations` from json")?,
+                tags: settings
+                    .remove("tags")
+                    .map(serde_json::from_str)
+                    .transpose()
+                    .context("parse `tags` from json")?,
+                template: settings.remove("template").map(|x| x.to_string()),
+                read_only: settings
+                    .remove("read_only")
+                    .map(|x| x.parse::<ReadOnlyReason>())
+                    .transpose()
+                    .context("Failed to parse 'read_only'")?,
             }
         };
 
@@ -555,6 +692,7 @@ impl PageServerNode {
             ancestor_timeline_id,
             pg_version,
             existing_initdb_timeline_id,
+            snapshot_id: None,
         };
         Ok(self
             .http_client
@@ -576,7 +714,7 @@ impl PageServerNode {
         tenant_id: TenantId,
         timeline_id: TimelineId,
         base: (Lsn, PathBuf),
-        pg_wal: Option<(Lsn, PathBuf)>,
+        pg_wal: Option<(Lsn, ImportWal)>,
         pg_version: u32,
     ) -> anyhow::Result<()> {
         let (client, conn) = self.page_server_psql_client().await?;
@@ -595,12 +733,14 @@ impl PageServerNode {
         let base_tarfile = tokio_util::io::ReaderStream::new(base_tarfile);
 
         // Init wal reader if necessary
-        let (end_lsn, wal_reader) = if let Some((end_lsn, wal_tarfile_path)) = pg_wal {
-            let wal_tarfile = tokio::fs::File::open(wal_tarfile_path).await?;
-            let wal_reader = tokio_util::io::ReaderStream::new(wal_tarfile);
-            (end_lsn, Some(wal_reader))
-        } else {
-            (start_lsn, None)
+        let (end_lsn, wal_reader, from_archive) = match pg_wal {
+            Some((end_lsn, ImportWal::Tarfile(wal_tarfile_path))) => {
+                let wal_tarfile = tokio::fs::File::open(wal_tarfile_path).await?;
+                let wal_reader = tokio_util::io::ReaderStream::new(wal_tarfile);
+                (end_lsn, Some(wal_reader), false)
+            }
+            Some((end_lsn, ImportWal::Archive)) => (end_lsn, None, true),
+            None => (start_lsn, None, false),
         };
 
         let copy_in = |reader, cmd| {
@@ -634,6 +774,14 @@ impl PageServerNode {
             )
             .await?;
         }
+        // Or fetch it from the archive
+        if from_archive {
+            client
+                .simple_query(&format!(
+                    "import wal_archive {tenant_id} {timeline_id} {start_lsn} {end_lsn}"
+                ))
+                .await?;
+        }
 
         Ok(())
     }
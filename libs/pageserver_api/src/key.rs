@@ -228,7 +228,8 @@ impl Key {
     };
 
     pub fn from_hex(s: &str) -> Result<Self> {
-        if s.len() != 36 {
+        // Non-ASCII characters would make the slicing below panic.
+        if s.len() != 36 || !s.is_ascii() {
             bail!("parse error");
         }
         Ok(Key {
@@ -682,6 +683,13 @@ mod tests {
         assert_eq!(key, Key::from_str(&format!("{key}")).unwrap());
     }
 
+    #[test]
+    fn from_hex_rejects_non_ascii() {
+        let s = format!("0{}", "é".repeat(17) + "0");
+        assert_eq!(s.len(), 36);
+        assert!(Key::from_hex(&s).is_err());
+    }
+
     #[test]
     fn test_metadata_keys() {
         let mut metadata_key = vec![AUX_KEY_PREFIX];
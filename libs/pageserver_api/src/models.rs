@@ -6,15 +6,16 @@ pub use utilization::PageserverUtilization;
 
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     io::{BufRead, Read},
     num::{NonZeroU64, NonZeroUsize},
+    ops::Range,
     str::FromStr,
     time::{Duration, SystemTime},
 };
 
 use byteorder::{BigEndian, ReadBytesExt};
-use postgres_ffi::BLCKSZ;
+use postgres_ffi::{Oid, BLCKSZ};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use utils::{
@@ -27,6 +28,7 @@ use utils::{
 
 use crate::controller_api::PlacementPolicy;
 use crate::{
+    key::Key,
     reltag::RelTag,
     shard::{ShardCount, ShardStripeSize, TenantShardId},
 };
@@ -197,6 +199,10 @@ pub struct TimelineCreateRequest {
     #[serde(default)]
     pub ancestor_start_lsn: Option<Lsn>,
     pub pg_version: Option<u32>,
+    /// Create the timeline from the layers of a snapshot in the remote storage, at the LSN and
+    /// postgres version of the snapshot.
+    #[serde(default)]
+    pub snapshot_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -298,6 +304,8 @@ pub struct TenantConfig {
     pub lagging_wal_timeout: Option<String>,
     pub max_lsn_wal_lag: Option<NonZeroU64>,
     pub trace_read_requests: Option<bool>,
+    pub capture_wal: Option<bool>,
+    pub branch_image_creation_threshold: Option<u64>,
     pub eviction_policy: Option<EvictionPolicy>,
     pub min_resident_size_override: Option<u64>,
     pub evictions_low_residence_duration_metric_threshold: Option<String>,
@@ -306,6 +314,129 @@ pub struct TenantConfig {
     pub timeline_get_throttle: Option<ThrottleConfig>,
     pub image_layer_creation_check_threshold: Option<u8>,
     pub switch_aux_file_policy: Option<AuxFilePolicy>,
+    pub ephemeral_bytes_limit: Option<u64>,
+    pub ancestor_prefetch: Option<bool>,
+    pub overlap_repair_threshold: Option<usize>,
+    pub page_request_deadline: Option<String>,
+    pub sequential_prefetch: Option<bool>,
+    pub branch_creation_max_compaction_debt: Option<u64>,
+    pub branch_creation_debt_wait: Option<String>,
+    pub alias: Option<String>,
+    pub timeline_aliases: Option<BTreeMap<String, TimelineId>>,
+    pub gc_pinned_relations: Option<Vec<PinnedRelation>>,
+    pub tags: Option<BTreeMap<String, String>>,
+    pub template: Option<String>,
+    pub read_only: Option<ReadOnlyReason>,
+}
+
+/// A version of a named tenant config template. Tenants created or attached with a
+/// `template` take the fields that their config doesn't set from it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TenantConfigTemplate {
+    pub name: String,
+    pub version: u32,
+    #[serde(with = "humantime_serde")]
+    pub created_at: SystemTime,
+    pub config: TenantConfig,
+}
+
+impl TenantConfigTemplate {
+    /// Split a template reference, `name` for the latest version or `name@version`.
+    pub fn parse_reference(reference: &str) -> anyhow::Result<(&str, Option<u32>)> {
+        match reference.split_once('@') {
+            Some((name, version)) => {
+                let version = version
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("invalid template version in {reference:?}"))?;
+                Ok((name, Some(version)))
+            }
+            None => Ok((reference, None)),
+        }
+    }
+
+    /// Whether the reference designates a version, rather than the latest one.
+    pub fn is_pinned(reference: &str) -> bool {
+        matches!(Self::parse_reference(reference), Ok((_, Some(_))))
+    }
+
+    /// The version that `version` designates among `versions`, oldest first: the latest if
+    /// unset.
+    pub fn find(versions: &[Self], version: Option<u32>) -> Option<&Self> {
+        match version {
+            Some(version) => versions.iter().find(|t| t.version == version),
+            None => versions.last(),
+        }
+    }
+
+    /// `config` with the fields that it doesn't set taken from this template, and its reference
+    /// pinned to this version.
+    pub fn apply(&self, config: &TenantConfig) -> anyhow::Result<TenantConfig> {
+        let (serde_json::Value::Object(mut fields), serde_json::Value::Object(template)) = (
+            serde_json::to_value(config)?,
+            serde_json::to_value(&self.config)?,
+        ) else {
+            bail!("tenant config is not an object");
+        };
+        for (field, value) in template {
+            if fields.get(&field).map_or(true, serde_json::Value::is_null) {
+                fields.insert(field, value);
+            }
+        }
+        let mut config: TenantConfig = serde_json::from_value(serde_json::Value::Object(fields))?;
+        config.template = Some(format!("{}@{}", self.name, self.version));
+        Ok(config)
+    }
+}
+
+/// A relation, or all the relations of a database, whose history GC keeps regardless of the
+/// GC horizon and the PITR interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinnedRelation {
+    pub spcnode: Oid,
+    pub dbnode: Oid,
+    /// All the relations of the database if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relnode: Option<Oid>,
+}
+
+impl PinnedRelation {
+    /// The keys of the pinned relations: all their forks, blocks and sizes.
+    pub fn key_range(&self) -> Range<Key> {
+        let (start_relnode, end_relnode) = match self.relnode {
+            Some(relnode) => (relnode, relnode),
+            None => (0, Oid::MAX),
+        };
+        let start = Key {
+            field1: 0x00,
+            field2: self.spcnode,
+            field3: self.dbnode,
+            field4: start_relnode,
+            field5: 0,
+            field6: 0,
+        };
+        let last = Key {
+            field1: 0x00,
+            field2: self.spcnode,
+            field3: self.dbnode,
+            field4: end_relnode,
+            field5: u8::MAX,
+            field6: u32::MAX,
+        };
+        start..last.next()
+    }
+
+    /// The keys to keep for reads of the pinned relations at any LSN: [`Self::key_range`], and
+    /// the relation directory of the database, which tells whether the relations exist.
+    pub fn key_ranges(&self) -> Vec<Range<Key>> {
+        let key_range = self.key_range();
+        let rel_dir = crate::key::rel_dir_to_key(self.spcnode, self.dbnode);
+        if key_range.contains(&rel_dir) {
+            vec![key_range]
+        } else {
+            vec![rel_dir..rel_dir.next(), key_range]
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -561,6 +692,201 @@ pub struct TenantDetails {
     pub timelines: Vec<TimelineId>,
 }
 
+/// A phase of the activation of a tenant, in the order they happen.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    enum_map::Enum,
+    strum_macros::IntoStaticStr,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum TenantActivationPhase {
+    /// Reading the tenant's configuration from local disk, at pageserver startup.
+    ConfigLoad,
+    /// Waiting for the warmup semaphore or an on-demand activation request.
+    Queued,
+    /// Listing the timelines in remote storage.
+    ListTimelines,
+    /// Downloading the index parts of the timelines.
+    DownloadIndexParts,
+    /// Scanning the local timeline directories and building the layer maps of the timelines.
+    LoadLayerMaps,
+    /// Removing local timelines that are not in remote storage, and resuming interrupted
+    /// timeline deletions.
+    Reconcile,
+    /// Starting the background loops and the timelines.
+    Activate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TenantActivationPhaseTime {
+    pub phase: TenantActivationPhase,
+    pub millis: u64,
+}
+
+/// Where the activation of a tenant spent its time, for the `activation_profile` API.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TenantActivationProfile {
+    /// Time from the construction of the tenant to it becoming active, if it did.
+    pub activated_after_millis: Option<u64>,
+    /// The phases that have completed, in order. Phases that the activation skipped, like
+    /// `queued` for tenants that activate eagerly, are missing.
+    pub phases: Vec<TenantActivationPhaseTime>,
+}
+
+/// A change of the state of a tenant, for the `state_transitions` API.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TenantStateTransition {
+    #[serde(with = "humantime_serde")]
+    pub at: SystemTime,
+    /// The names of the [`TenantState`] variants.
+    pub from: String,
+    pub to: String,
+    /// What caused the transition, like `set_stopping`.
+    pub cause: String,
+}
+
+/// A hold that blocks the destructive operations on a tenant: the deletion of the tenant, of
+/// its timelines, and of layers from remote storage by GC.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LegalHold {
+    pub reason: String,
+    #[serde(with = "humantime_serde")]
+    pub since: SystemTime,
+}
+
+/// Request body of `PUT /v1/tenant/:tenant_shard_id/legal_hold`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LegalHoldRequest {
+    pub reason: String,
+}
+
+/// Why the ingest of WAL into a tenant is rejected. Its data is still served, as of the last
+/// ingested LSN.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ReadOnlyReason {
+    /// The payments of the tenant are on hold.
+    Billing,
+    /// The disk usage based eviction offloaded the tenant to relieve the disk pressure.
+    Offloaded,
+}
+
+/// Request body of `PUT /v1/tenant/:tenant_shard_id/read_only`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadOnlyRequest {
+    pub reason: ReadOnlyReason,
+}
+
+/// Request body of `POST /v1/tenant/:tenant_shard_id/selftest`, optional.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TenantSelftestRequest {
+    /// Also upload the test page to remote storage and download it back.
+    #[serde(default)]
+    pub remote: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TenantSelftestResponse {
+    /// The scratch timeline of the test, which no longer exists.
+    pub timeline_id: TimelineId,
+    /// The stages that ran, in order.
+    pub stages: Vec<TenantSelftestStage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TenantSelftestStage {
+    pub name: String,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+}
+
+/// An attempt at a destructive operation that a [`LegalHold`] blocked. Repeated attempts of the
+/// same operation on the same target are folded into one entry.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LegalHoldViolation {
+    /// Like `timeline_deletion`.
+    pub operation: String,
+    /// The tenant or timeline that the operation was attempted on.
+    pub target: String,
+    #[serde(with = "humantime_serde")]
+    pub first_at: SystemTime,
+    #[serde(with = "humantime_serde")]
+    pub last_at: SystemTime,
+    pub count: u64,
+}
+
+/// Response of the `legal_hold` API.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LegalHoldStatus {
+    pub hold: Option<LegalHold>,
+    /// The recent violations, oldest first.
+    pub violations: Vec<LegalHoldViolation>,
+}
+
+/// The manifest of a tenant bundle, a directory holding a copy of all the data of a tenant:
+/// its config, and the index part and layers of each of its timelines. Bundles move tenants
+/// between pageserver installations that don't share a remote storage.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TenantBundleManifest {
+    pub format_version: u32,
+    pub tenant_id: TenantId,
+    #[serde(with = "humantime_serde")]
+    pub created_at: SystemTime,
+    pub config: TenantConfig,
+    pub timelines: Vec<TenantBundleTimeline>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TenantBundleTimeline {
+    pub timeline_id: TimelineId,
+    pub index_part: TenantBundleFile,
+    pub layers: Vec<TenantBundleFile>,
+}
+
+/// A file of a tenant bundle.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TenantBundleFile {
+    /// The path of the file, relative to the bundle directory.
+    pub path: String,
+    pub size: u64,
+    pub crc32c: u32,
+}
+
+/// Request body of `POST /v1/tenant/:tenant_shard_id/export`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TenantExportRequest {
+    /// The bundle directory, relative to the `bundle_dir` of the pageserver. It must be empty or
+    /// not exist yet.
+    pub path: String,
+}
+
+/// Request body of `POST /v1/tenant/:tenant_id/import`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TenantImportRequest {
+    /// The bundle directory, relative to the `bundle_dir` of the pageserver.
+    pub path: String,
+    /// The generation that the tenant will be attached with.
+    pub generation: u32,
+}
+
 /// This represents the output of the "timeline_detail" and "timeline_list" API calls.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TimelineInfo {
@@ -604,6 +930,62 @@ pub struct TimelineInfo {
     pub state: TimelineState,
 
     pub walreceiver_status: String,
+
+    /// Breakdown of the WAL ingest, unset until the walreceiver of the timeline connects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wal_ingest: Option<WalIngestInfo>,
+
+    /// Set while the timeline is being deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deletion_progress: Option<TimelineDeletionProgress>,
+}
+
+/// WAL ingest of a timeline since it was loaded on the pageserver.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalIngestInfo {
+    /// Rates over the last few seconds, zero when the ingest stalls.
+    pub bytes_per_second: f64,
+    pub records_per_second: f64,
+    pub bytes_received: u64,
+    pub records_ingested: u64,
+    /// Time spent decoding the WAL records, applying them to the timeline, and committing them.
+    pub decode_seconds: f64,
+    pub apply_seconds: f64,
+    pub commit_seconds: f64,
+    /// Connections to safekeepers, and how many of them ended with an error.
+    pub connections: u64,
+    pub connection_failures: u64,
+    /// Why the walreceiver last switched to another connection.
+    pub last_switch_reason: Option<String>,
+    #[serde(with = "humantime_serde")]
+    pub last_switch_at: Option<SystemTime>,
+}
+
+/// Steps of a timeline deletion, in the order they are performed.
+///
+/// The step is persisted before it is started, so that the deletion is resumed from it
+/// after a restart. Every step is idempotent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineDeletionStep {
+    /// Persist the record of the deletion locally.
+    Mark,
+    /// Shut down the timeline's tasks and stop its uploads.
+    StopTasks,
+    /// Mark the timeline deleted in its remote index, then delete its layers and the index.
+    DeleteRemote,
+    /// Delete the local timeline directory.
+    DeleteLocal,
+    /// Remove the deletion record and the timeline from the tenant.
+    Forget,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineDeletionProgress {
+    pub step: TimelineDeletionStep,
+    /// When the deletion was first requested, preserved across retries and restarts.
+    #[serde(with = "humantime_serde")]
+    pub started_at: SystemTime,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -750,6 +1132,10 @@ impl HistoricLayerInfo {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DownloadRemoteLayersTaskSpawnRequest {
     pub max_concurrent_downloads: NonZeroUsize,
+    /// Download bandwidth limit, averaged over the lifetime of the task. Layers that are already
+    /// resident don't count against it.
+    #[serde(default)]
+    pub max_bytes_per_second: Option<NonZeroU64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -759,6 +1145,11 @@ pub struct DownloadRemoteLayersTaskInfo {
     pub total_layer_count: u64,         // stable once `completed`
     pub successful_download_count: u64, // stable once `completed`
     pub failed_download_count: u64,     // stable once `completed`
+    #[serde(default)]
+    pub total_bytes: u64, // stable once `completed`
+    /// Bytes of the layers that are resident after a successful download.
+    #[serde(default)]
+    pub downloaded_bytes: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -773,18 +1164,400 @@ pub struct TimelineGcRequest {
     pub gc_horizon: Option<u64>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimelineWaitLsnRequest {
+    pub lsn: Lsn,
+    /// How long to wait, the pageserver's `wait_lsn_timeout` by default.
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    /// Also wait for the LSN to be flushed to disk, freezing the open layer if needed. That
+    /// creates small layers, so only ask for it when the LSN must be durable on the
+    /// pageserver, not just readable.
+    #[serde(default)]
+    pub flush: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimelineWaitLsnResponse {
+    /// Whether the LSN was ingested, and flushed to disk if requested, within the timeout.
+    pub reached: bool,
+    pub last_record_lsn: Lsn,
+    pub disk_consistent_lsn: Lsn,
+    /// Bytes of WAL between the last record LSN, or the disk consistent LSN if a flush was
+    /// requested, and the requested one, zero once reached.
+    pub lag_bytes: u64,
+    #[serde(with = "humantime_serde")]
+    pub waited: Duration,
+}
+
+/// Logical sizes of the relations of a timeline at an LSN, by database.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimelineRelationSizes {
+    pub lsn: Lsn,
+    pub databases: Vec<DatabaseSize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatabaseSize {
+    pub spcnode: u32,
+    pub dbnode: u32,
+    /// Sum of the sizes of the relations, in bytes.
+    pub size: u64,
+    pub relations: Vec<RelationSize>,
+}
+
+/// Size of one fork of a relation, in bytes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RelationSize {
+    pub relnode: u32,
+    pub forknum: u8,
+    pub size: u64,
+}
+
+/// A changed setting of the pageserver config file, identified by its path of keys,
+/// e.g. `remote_storage.concurrency_limit`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigChange {
+    pub key: String,
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigReloadResponse {
+    pub applied: Vec<ConfigChange>,
+    /// Changes which require a restart. If there are any, nothing is applied.
+    pub rejected: Vec<ConfigChange>,
+}
+
+/// The operations of a timeline's queue of uploads to and deletions from remote storage.
+/// Task IDs increase in the order the operations were scheduled.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadQueueInfo {
+    pub in_progress: Vec<InProgressUploadOp>,
+    pub queued: Vec<QueuedUploadOp>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InProgressUploadOp {
+    pub task_id: u64,
+    pub op: String,
+    pub retries: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuedUploadOp {
+    pub task_id: u64,
+    pub op: String,
+    /// The in-progress and queued operations which must complete before this one can start.
+    pub waits_for: Vec<u64>,
+}
+
+/// The keys changed by the WAL ingested at an LSN, one line of the stream returned by
+/// `GET /v1/tenant/:tenant_shard_id/timeline/:timeline_id/invalidations`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyInvalidation {
+    /// The changes are visible to reads at this LSN and above.
+    pub lsn: Lsn,
+    pub key_ranges: Vec<InvalidatedKeyRange>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvalidatedKeyRange {
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub start: Key,
+    /// Exclusive.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub end: Key,
+}
+
+impl From<Range<Key>> for InvalidatedKeyRange {
+    fn from(value: Range<Key>) -> Self {
+        InvalidatedKeyRange {
+            start: value.start,
+            end: value.end,
+        }
+    }
+}
+
+/// The key ranges of a timeline with the most delta layers stacked above their latest image
+/// layer, returned by `GET /v1/tenant/:tenant_shard_id/timeline/:timeline_id/layer_overlap`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerOverlapReport {
+    /// The hotspots have more than this many stacked delta layers.
+    pub threshold: usize,
+    /// Hotspots, deepest first.
+    pub hotspots: Vec<LayerOverlapHotspot>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerOverlapHotspot {
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub key_start: Key,
+    /// Exclusive.
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub key_end: Key,
+    /// The highest number of delta layers stacked above the latest image layer in the range.
+    pub depth: usize,
+    /// LSN of the latest image layer of the range, if there is one.
+    pub image_lsn: Option<Lsn>,
+}
+
+/// Statistics of the deltas written to the layers of a timeline since it was loaded, by
+/// relation, returned by `GET /v1/tenant/:tenant_shard_id/timeline/:timeline_id/key_space_stats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineKeySpaceStats {
+    #[serde(with = "humantime_serde")]
+    pub since: SystemTime,
+    /// Deltas flushed from in-memory layers: the WAL ingested for each relation.
+    pub flush: KeySpaceStats,
+    /// Deltas rewritten by L0 compaction.
+    pub compaction: KeySpaceStats,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeySpaceStats {
+    /// The relations with the most bytes of deltas first.
+    pub relations: Vec<RelationDeltaStats>,
+    /// The deltas of the keys that don't belong to a relation, like SLRUs and metadata.
+    pub other: DeltaStats,
+}
+
+/// Statistics of the deltas of a relation, summed over all its forks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelationDeltaStats {
+    pub spcnode: u32,
+    pub dbnode: u32,
+    pub relnode: u32,
+    #[serde(flatten)]
+    pub stats: DeltaStats,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaStats {
+    pub deltas: u64,
+    pub bytes: u64,
+    /// The number of distinct LSNs of the deltas, counted for each flush or compaction, and
+    /// summed.
+    pub distinct_lsns: u64,
+}
+
+/// The accesses to a timeline by hour, returned by
+/// `GET /v1/tenant/:tenant_shard_id/timeline/:timeline_id/access_log`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineAccessLog {
+    #[serde(with = "humantime_serde")]
+    pub retention: Duration,
+    /// The hours with accesses, oldest first. The last one may still be in progress.
+    pub hours: Vec<HourlyAccess>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HourlyAccess {
+    /// The start of the hour.
+    #[serde(with = "humantime_serde")]
+    pub hour: SystemTime,
+    /// Pages read by computes.
+    pub pages_read: u64,
+    /// Bytes of values written by ingest.
+    pub bytes_ingested: u64,
+    /// Estimate of the number of distinct keys read or written.
+    pub unique_keys: u64,
+}
+
+/// The response of `GET /v1/tenant/:tenant_shard_id/timeline/:timeline_id/explain_read`: the
+/// layers that a read of a key at an LSN would visit, without reading the values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExplainReadResponse {
+    /// The LSN of the image in the materialized page cache that the read would start from, if
+    /// any: the traversal stops above it.
+    pub cached_image_lsn: Option<Lsn>,
+    /// In the order of the traversal, from the newest LSNs to the oldest.
+    pub layers: Vec<ExplainReadLayer>,
+    pub outcome: ExplainReadOutcome,
+    /// Whether the values found include WAL records, which the walredo process applies.
+    pub redo_needed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplainReadOutcome {
+    /// The traversal reaches an image or a WAL record that initializes the page.
+    Complete,
+    /// The traversal reaches the image of the materialized page cache.
+    CachedImage,
+    /// No image was found: the read would fail.
+    Missing,
+    /// The traversal continues in an evicted layer. It isn't downloaded, so the rest of the
+    /// traversal is unknown.
+    Evicted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExplainReadLayer {
+    /// Layers of the ancestors are visited for LSNs below the branch points.
+    pub timeline_id: TimelineId,
+    /// The name of the layer file, or the LSN range of an in-memory layer.
+    pub layer: String,
+    pub kind: ExplainReadLayerKind,
+    pub resident: bool,
+    /// The LSN range of the layer.
+    pub lsn_start: Lsn,
+    pub lsn_end: Lsn,
+    /// The values of the key in the range, newest first, up to the one that ends the
+    /// traversal. Empty for an evicted layer.
+    pub values: Vec<ExplainReadValue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplainReadLayerKind {
+    InMemory,
+    Delta,
+    Image,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExplainReadValue {
+    pub lsn: Lsn,
+    /// The offset of the value in the layer file, or in the ephemeral file of an in-memory
+    /// layer.
+    pub offset: u64,
+    pub kind: ExplainReadValueKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplainReadValueKind {
+    Image,
+    WalRecord,
+    /// A WAL record that initializes the page, so that no older value is needed.
+    InitWalRecord,
+}
+
+impl ExplainReadValueKind {
+    /// Whether a read needs no older value than this one.
+    pub fn ends_traversal(&self) -> bool {
+        matches!(self, Self::Image | Self::InitWalRecord)
+    }
+}
+
+/// A commit sampled by the LSN/timestamp index of a timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LsnTimestampSample {
+    /// The end of the commit record.
+    pub lsn: Lsn,
+    #[serde(with = "humantime_serde")]
+    pub timestamp: SystemTime,
+}
+
+/// The response of `GET /v1/tenant/:tenant_shard_id/timeline/:timeline_id/lsn_by_timestamp`
+/// and `.../timestamp_by_lsn`: the sampled commits right before and after the requested point,
+/// between which the answer lies.
+///
+/// For `lsn_by_timestamp`, the last commit at or before the timestamp is at or above
+/// `before.lsn` and below `after.lsn`. For `timestamp_by_lsn`, the last commit at or below the
+/// LSN was at or after `before.timestamp` and before `after.timestamp`. Either side is missing
+/// past the first or the last sample.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LsnTimestampBracket {
+    pub before: Option<LsnTimestampSample>,
+    pub after: Option<LsnTimestampSample>,
+}
+
+/// A branch that was neither read nor written for the requested time, an entry of the response
+/// of `/v1/tenant/:tenant_shard_id/stale_timelines`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaleTimeline {
+    pub timeline_id: TimelineId,
+    pub ancestor_timeline_id: TimelineId,
+    /// The start of the last hour with page reads, if within the access log retention.
+    #[serde(default, with = "humantime_serde")]
+    pub last_read: Option<SystemTime>,
+    /// The start of the last hour with ingest, if within the access log retention.
+    #[serde(default, with = "humantime_serde")]
+    pub last_write: Option<SystemTime>,
+    /// Since when the pageserver tracks the accesses to the timeline.
+    #[serde(with = "humantime_serde")]
+    pub tracked_since: SystemTime,
+    /// Timelines with children are never deleted.
+    pub has_children: bool,
+    /// Whether the deletion of the timeline was started, or would be in a dry run.
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_error: Option<String>,
+}
+
+/// Resources used by the tasks of a tenant shard since it was attached, by task kind.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TenantResourceUsage {
+    pub tasks: Vec<TaskResourceUsage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskResourceUsage {
+    pub task_kind: String,
+    pub cpu_seconds: f64,
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+}
+
+/// The load of each tenant shard attached to a pageserver, for an external rebalancer to
+/// choose which tenants to move.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TenantLoadScores {
+    pub tenants: Vec<TenantLoadScore>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TenantLoadScore {
+    pub tenant_shard_id: TenantShardId,
+    /// Bytes of layer files on local disk.
+    pub resident_bytes: u64,
+    /// WAL ingested by all timelines, in bytes per second. The rates are computed between
+    /// two requests at least a few seconds apart, so they are `None` on the first one.
+    pub ingest_bytes_per_second: Option<f64>,
+    /// GetPage requests served, per second.
+    pub getpage_requests_per_second: Option<f64>,
+    /// Bytes of L0 delta layers waiting for compaction.
+    pub compaction_debt_bytes: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalRedoManagerProcessStatus {
     pub pid: u32,
     /// The strum-generated `into::<&'static str>()` for `pageserver::walredo::ProcessKind`.
     /// `ProcessKind` are a transitory thing, so, they have no enum representation in `pageserver_api`.
     pub kind: Cow<'static, str>,
+    /// The Postgres major version the process runs.
+    pub pg_version: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalRedoManagerStatus {
     pub last_redo_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// The first of `processes`, for clients that predate processes per Postgres version.
     pub process: Option<WalRedoManagerProcessStatus>,
+    /// A tenant runs a process for each Postgres version of its timelines.
+    #[serde(default)]
+    pub processes: Vec<WalRedoManagerProcessStatus>,
+}
+
+/// An item of the response of `GET /v1/pg_versions`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PgVersionUsage {
+    pub pg_version: u32,
+    /// Whether the binaries of this version are installed on the pageserver.
+    pub installed: bool,
+    /// Why the version can't be used, if it isn't installed.
+    pub error: Option<String>,
+    /// Timelines of attached tenants with this version.
+    pub timelines: usize,
+    /// Running walredo processes of this version.
+    pub walredo_processes: usize,
 }
 
 /// The progress of a secondary tenant is mostly useful when doing a long running download: e.g. initiating
@@ -806,6 +1579,40 @@ pub struct SecondaryProgress {
     pub bytes_total: u64,
 }
 
+/// Local state of a tenant that was found corrupt on pageserver startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartupRepair {
+    pub tenant_shard_id: TenantShardId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline_id: Option<TimelineId>,
+    /// What was found corrupt.
+    pub problem: String,
+    /// What was done to recover, or `None` if automatic repair was impossible and the
+    /// tenant was left Broken.
+    pub recovered: Option<String>,
+}
+
+/// What a secondary location last saw in the remote index_part of its tenant's timelines.
+///
+/// This is maintained by a periodic batched refresh on the pageserver, independently
+/// of the heatmap-driven downloads reported in [`SecondaryProgress`].
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct SecondaryRemoteIndex {
+    /// When the last successful refresh completed, `None` if there wasn't one yet.
+    pub refreshed_at: Option<serde_system_time::SystemTime>,
+
+    pub timelines: HashMap<TimelineId, SecondaryRemoteTimeline>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecondaryRemoteTimeline {
+    /// Generation of the most recent index_part.json object.
+    pub generation: Option<u32>,
+    pub disk_consistent_lsn: Lsn,
+    /// The timeline is being deleted by the attached location.
+    pub deleted: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TenantScanRemoteStorageShard {
     pub tenant_shard_id: TenantShardId,
@@ -846,6 +1653,7 @@ pub enum PagestreamFeMessage {
     GetPage(PagestreamGetPageRequest),
     DbSize(PagestreamDbSizeRequest),
     GetSlruSegment(PagestreamGetSlruSegmentRequest),
+    GetPageAtLeast(PagestreamGetPageAtLeastRequest),
 }
 
 // Wrapped in libpq CopyData
@@ -857,6 +1665,7 @@ pub enum PagestreamBeMessage {
     Error(PagestreamErrorResponse),
     DbSize(PagestreamDbSizeResponse),
     GetSlruSegment(PagestreamGetSlruSegmentResponse),
+    GetPageAtLeast(PagestreamGetPageAtLeastResponse),
 }
 
 // Keep in sync with `pagestore_client.h`
@@ -868,6 +1677,7 @@ enum PagestreamBeMessageTag {
     Error = 103,
     DbSize = 104,
     GetSlruSegment = 105,
+    GetPageAtLeast = 106,
 }
 impl TryFrom<u8> for PagestreamBeMessageTag {
     type Error = u8;
@@ -879,6 +1689,7 @@ impl TryFrom<u8> for PagestreamBeMessageTag {
             103 => Ok(PagestreamBeMessageTag::Error),
             104 => Ok(PagestreamBeMessageTag::DbSize),
             105 => Ok(PagestreamBeMessageTag::GetSlruSegment),
+            106 => Ok(PagestreamBeMessageTag::GetPageAtLeast),
             _ => Err(value),
         }
     }
@@ -911,10 +1722,123 @@ impl TryFrom<u8> for PagestreamBeMessageTag {
 // The Request structs below reflect the V2 interface. If V1 is used, the parse function
 // maps the old format requests to the new format.
 //
-#[derive(Clone, Copy)]
+// The V3 interface multiplexes the timelines of a tenant on one connection: it is started with
+// the tenant only, and each message, in both directions, is preceded by a [`PagestreamV3Header`]
+// that names the timeline of the request, and an ID for the client to match the responses with
+// the requests, which may come back in a different order. The messages themselves are the same
+// as in V2.
+//
+// Clients that know about several versions can agree on one with the pageserver with the
+// `pagestream_negotiate` command, see [`PagestreamNegotiation`], before starting the connection
+// with the command of that version.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum PagestreamProtocolVersion {
     V1,
     V2,
+    V3,
+}
+
+impl PagestreamProtocolVersion {
+    /// All the versions, oldest first.
+    pub const ALL: [Self; 3] = [Self::V1, Self::V2, Self::V3];
+
+    pub fn number(self) -> u32 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+            Self::V3 => 3,
+        }
+    }
+
+    pub fn from_number(number: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|v| v.number() == number)
+    }
+
+    /// The page_service command that starts a connection with this version.
+    pub fn command(self) -> &'static str {
+        match self {
+            Self::V1 => "pagestream",
+            Self::V2 => "pagestream_v2",
+            Self::V3 => "pagestream_v3",
+        }
+    }
+}
+
+/// An optional capability of pagestream connections, which a client only uses once the
+/// pageserver accepted it in the [`PagestreamNegotiation`].
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    strum_macros::Display,
+    strum_macros::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PagestreamFeature {
+    /// Requests for several pages in one message.
+    VectoredGet,
+    /// Messages that announce the pages the client will request soon.
+    PrefetchHints,
+    /// Compressed page images in the responses.
+    Compression,
+    /// GetPage requests served at any LSN at or above a minimum, see
+    /// [`PagestreamGetPageAtLeastRequest`].
+    LsnAtLeast,
+}
+
+/// The outcome of `pagestream_negotiate <min_version> <max_version> [<feature>,...]`: the
+/// newest version within the range that the pageserver supports, and the requested features
+/// that it supports with that version. The names of features that the pageserver doesn't know
+/// are ignored, so that clients can ask for new features from old pageservers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PagestreamNegotiation {
+    pub version: PagestreamProtocolVersion,
+    pub features: Vec<PagestreamFeature>,
+}
+
+/// A version of the pagestream protocol that the pageserver supports, in the response of
+/// `GET /v1/page_service/protocol`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PagestreamProtocolInfo {
+    pub version: u32,
+    /// The command that starts a connection with this version.
+    pub command: String,
+    /// The features that can be negotiated with this version.
+    pub features: Vec<PagestreamFeature>,
+}
+
+/// Precedes each message of the V3 protocol, see [`PagestreamProtocolVersion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PagestreamV3Header {
+    /// Chosen by the client, and echoed in the response.
+    pub request_id: u64,
+    pub timeline_id: TimelineId,
+}
+
+impl PagestreamV3Header {
+    pub const LEN: usize = 8 + 16;
+
+    pub fn serialize(&self, bytes: &mut BytesMut) {
+        bytes.put_u64(self.request_id);
+        bytes.put(&self.timeline_id.as_arr()[..]);
+    }
+
+    pub fn parse<R: std::io::Read>(body: &mut R) -> anyhow::Result<Self> {
+        let request_id = body.read_u64::<BigEndian>()?;
+        let mut timeline_id = [0u8; 16];
+        body.read_exact(&mut timeline_id)?;
+        Ok(Self {
+            request_id,
+            timeline_id: TimelineId::from(timeline_id),
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -954,6 +1878,22 @@ pub struct PagestreamGetSlruSegmentRequest {
     pub segno: u32,
 }
 
+/// A page at the latest LSN that the pageserver has, once it is at or above `min_lsn`,
+/// waiting up to `wait_budget_ms` for the WAL to arrive. Unlike [`PagestreamGetPageRequest`],
+/// the client doesn't need to wait for the replication of its writes before sending the
+/// request: the response tells the LSN at which the page was served.
+///
+/// Not available with protocol V1, and only sent once the pageserver accepted
+/// [`PagestreamFeature::LsnAtLeast`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct PagestreamGetPageAtLeastRequest {
+    pub min_lsn: Lsn,
+    /// The pageserver caps it at its `wait_lsn_timeout`.
+    pub wait_budget_ms: u32,
+    pub rel: RelTag,
+    pub blkno: u32,
+}
+
 #[derive(Debug)]
 pub struct PagestreamExistsResponse {
     pub exists: bool,
@@ -969,6 +1909,13 @@ pub struct PagestreamGetPageResponse {
     pub page: Bytes,
 }
 
+#[derive(Debug)]
+pub struct PagestreamGetPageAtLeastResponse {
+    /// The LSN at which the page was served, at or above the requested `min_lsn`.
+    pub lsn: Lsn,
+    pub page: Bytes,
+}
+
 #[derive(Debug)]
 pub struct PagestreamGetSlruSegmentResponse {
     pub segment: Bytes,
@@ -1047,6 +1994,17 @@ impl PagestreamFeMessage {
                 bytes.put_u8(req.kind);
                 bytes.put_u32(req.segno);
             }
+
+            Self::GetPageAtLeast(req) => {
+                bytes.put_u8(5);
+                bytes.put_u64(req.min_lsn.0);
+                bytes.put_u32(req.wait_budget_ms);
+                bytes.put_u32(req.rel.spcnode);
+                bytes.put_u32(req.rel.dbnode);
+                bytes.put_u32(req.rel.relnode);
+                bytes.put_u8(req.rel.forknum);
+                bytes.put_u32(req.blkno);
+            }
         }
 
         bytes.into()
@@ -1062,8 +2020,29 @@ impl PagestreamFeMessage {
         // serialization.
         let msg_tag = body.read_u8()?;
 
+        // Doesn't start with the pair of LSNs of the other requests.
+        if msg_tag == 5 {
+            if protocol_version == PagestreamProtocolVersion::V1 {
+                bail!("GetPageAtLeast requests need protocol V2 or later");
+            }
+            return Ok(PagestreamFeMessage::GetPageAtLeast(
+                PagestreamGetPageAtLeastRequest {
+                    min_lsn: Lsn::from(body.read_u64::<BigEndian>()?),
+                    wait_budget_ms: body.read_u32::<BigEndian>()?,
+                    rel: RelTag {
+                        spcnode: body.read_u32::<BigEndian>()?,
+                        dbnode: body.read_u32::<BigEndian>()?,
+                        relnode: body.read_u32::<BigEndian>()?,
+                        forknum: body.read_u8()?,
+                    },
+                    blkno: body.read_u32::<BigEndian>()?,
+                },
+            ));
+        }
+
         let (request_lsn, not_modified_since) = match protocol_version {
-            PagestreamProtocolVersion::V2 => (
+            // In V3, the caller has read the header already.
+            PagestreamProtocolVersion::V2 | PagestreamProtocolVersion::V3 => (
                 Lsn::from(body.read_u64::<BigEndian>()?),
                 Lsn::from(body.read_u64::<BigEndian>()?),
             ),
@@ -1081,7 +2060,7 @@ impl PagestreamFeMessage {
             }
         };
 
-        // The rest of the messages are the same between V1 and V2
+        // The rest of the messages are the same between V1, V2 and V3
         match msg_tag {
             0 => Ok(PagestreamFeMessage::Exists(PagestreamExistsRequest {
                 request_lsn,
@@ -1168,6 +2147,12 @@ impl PagestreamBeMessage {
                 bytes.put_u32((resp.segment.len() / BLCKSZ as usize) as u32);
                 bytes.put(&resp.segment[..]);
             }
+
+            Self::GetPageAtLeast(resp) => {
+                bytes.put_u8(Tag::GetPageAtLeast as u8);
+                bytes.put_u64(resp.lsn.0);
+                bytes.put(&resp.page[..]);
+            }
         }
 
         bytes.into()
@@ -1216,6 +2201,15 @@ impl PagestreamBeMessage {
                         segment: segment.into(),
                     })
                 }
+                Tag::GetPageAtLeast => {
+                    let lsn = Lsn::from(buf.read_u64::<BigEndian>()?);
+                    let mut page = vec![0; BLCKSZ as usize];
+                    buf.read_exact(&mut page)?;
+                    Self::GetPageAtLeast(PagestreamGetPageAtLeastResponse {
+                        lsn,
+                        page: page.into(),
+                    })
+                }
             };
         let remaining = buf.into_inner();
         if !remaining.is_empty() {
@@ -1235,6 +2229,7 @@ impl PagestreamBeMessage {
             Self::Error(_) => "Error",
             Self::DbSize(_) => "DbSize",
             Self::GetSlruSegment(_) => "GetSlruSegment",
+            Self::GetPageAtLeast(_) => "GetPageAtLeast",
         }
     }
 }
@@ -1245,6 +2240,52 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn pinned_relation_key_range() {
+        let rel = RelTag {
+            spcnode: 1663,
+            dbnode: 5,
+            relnode: 16384,
+            forknum: 0,
+        };
+        let pinned = PinnedRelation {
+            spcnode: 1663,
+            dbnode: 5,
+            relnode: Some(16384),
+        };
+        let range = pinned.key_range();
+        assert!(range.contains(&crate::key::rel_block_to_key(rel, 0)));
+        assert!(range.contains(&crate::key::rel_size_to_key(RelTag { forknum: 2, ..rel })));
+        let other = RelTag {
+            relnode: 16385,
+            ..rel
+        };
+        assert!(!range.contains(&crate::key::rel_block_to_key(other, 0)));
+        let rel_dir = crate::key::rel_dir_to_key(1663, 5);
+        assert!(pinned.key_ranges().iter().any(|r| r.contains(&rel_dir)));
+
+        let database = PinnedRelation {
+            relnode: None,
+            ..pinned
+        };
+        assert!(database
+            .key_range()
+            .contains(&crate::key::rel_block_to_key(other, 0)));
+        assert_eq!(database.key_ranges(), vec![database.key_range()]);
+        assert!(
+            !database.key_range().contains(&crate::key::rel_block_to_key(
+                RelTag { dbnode: 6, ..rel },
+                0
+            ))
+        );
+
+        let json = serde_json::to_value(pinned).unwrap();
+        assert_eq!(
+            json,
+            json!({"spcnode": 1663, "dbnode": 5, "relnode": 16384})
+        );
+    }
+
     #[test]
     fn test_pagestream() {
         // Test serialization/deserialization of PagestreamFeMessage
@@ -1285,6 +2326,17 @@ mod tests {
                 not_modified_since: Lsn(3),
                 dbnode: 7,
             }),
+            PagestreamFeMessage::GetPageAtLeast(PagestreamGetPageAtLeastRequest {
+                min_lsn: Lsn(4),
+                wait_budget_ms: 250,
+                rel: RelTag {
+                    forknum: 1,
+                    spcnode: 2,
+                    dbnode: 3,
+                    relnode: 4,
+                },
+                blkno: 7,
+            }),
         ];
         for msg in messages {
             let bytes = msg.serialize();
@@ -1295,6 +2347,66 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_pagestream_get_page_at_least() {
+        let msg = PagestreamFeMessage::GetPageAtLeast(PagestreamGetPageAtLeastRequest {
+            min_lsn: Lsn(0x10),
+            wait_budget_ms: 100,
+            rel: RelTag {
+                forknum: 0,
+                spcnode: 1663,
+                dbnode: 5,
+                relnode: 16384,
+            },
+            blkno: 3,
+        });
+        let bytes = msg.serialize();
+        assert!(
+            PagestreamFeMessage::parse(&mut bytes.reader(), PagestreamProtocolVersion::V1).is_err()
+        );
+
+        let resp = PagestreamBeMessage::GetPageAtLeast(PagestreamGetPageAtLeastResponse {
+            lsn: Lsn(0x20),
+            page: Bytes::from(vec![7u8; BLCKSZ as usize]),
+        });
+        match PagestreamBeMessage::deserialize(resp.serialize()).unwrap() {
+            PagestreamBeMessage::GetPageAtLeast(r) => {
+                assert_eq!(r.lsn, Lsn(0x20));
+                assert_eq!(r.page[..], [7u8; BLCKSZ as usize][..]);
+            }
+            other => panic!("unexpected message {}", other.kind()),
+        }
+    }
+
+    #[test]
+    fn test_pagestream_v3_header() {
+        let header = PagestreamV3Header {
+            request_id: 42,
+            timeline_id: TimelineId::generate(),
+        };
+        let msg = PagestreamFeMessage::Nblocks(PagestreamNblocksRequest {
+            request_lsn: Lsn(4),
+            not_modified_since: Lsn(3),
+            rel: RelTag {
+                forknum: 1,
+                spcnode: 2,
+                dbnode: 3,
+                relnode: 4,
+            },
+        });
+
+        let mut bytes = BytesMut::new();
+        header.serialize(&mut bytes);
+        assert_eq!(bytes.len(), PagestreamV3Header::LEN);
+        bytes.put(msg.serialize());
+
+        let mut reader = bytes.freeze().reader();
+        assert_eq!(PagestreamV3Header::parse(&mut reader).unwrap(), header);
+        let reconstructed =
+            PagestreamFeMessage::parse(&mut reader, PagestreamProtocolVersion::V3).unwrap();
+        assert_eq!(msg, reconstructed);
+    }
+
     #[test]
     fn test_tenantinfo_serde() {
         // Test serialization/deserialization of TenantInfo
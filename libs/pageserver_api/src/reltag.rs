@@ -1,9 +1,11 @@
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt;
+use std::str::FromStr;
 
 use postgres_ffi::pg_constants::GLOBALTABLESPACE_OID;
-use postgres_ffi::relfile_utils::forknumber_to_name;
+use postgres_ffi::relfile_utils::{forkname_to_number, forknumber_to_name};
 use postgres_ffi::Oid;
 
 ///
@@ -68,6 +70,33 @@ impl fmt::Display for RelTag {
     }
 }
 
+/// Parses the [`fmt::Display`] format.
+impl FromStr for RelTag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || -> anyhow::Result<RelTag> {
+            let mut parts = s.splitn(3, '/');
+            let (Some(spcnode), Some(dbnode), Some(rel)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                anyhow::bail!("expected <spcnode>/<dbnode>/<relnode>[_fsm|_vm|_init]");
+            };
+            let (relnode, forkname) = match rel.split_once('_') {
+                Some((relnode, forkname)) => (relnode, Some(forkname)),
+                None => (rel, None),
+            };
+            Ok(RelTag {
+                forknum: forkname_to_number(forkname)?,
+                spcnode: spcnode.parse()?,
+                dbnode: dbnode.parse()?,
+                relnode: relnode.parse()?,
+            })
+        };
+        parse().with_context(|| format!("invalid relation {s:?}"))
+    }
+}
+
 impl RelTag {
     pub fn to_segfile_name(&self, segno: u32) -> String {
         let mut name = if self.spcnode == GLOBALTABLESPACE_OID {
@@ -142,3 +171,31 @@ impl SlruKind {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postgres_ffi::relfile_utils::{FSM_FORKNUM, MAIN_FORKNUM};
+
+    #[test]
+    fn reltag_from_str() {
+        for forknum in [MAIN_FORKNUM, FSM_FORKNUM] {
+            let rel = RelTag {
+                forknum,
+                spcnode: 1663,
+                dbnode: 5,
+                relnode: 16384,
+            };
+            assert_eq!(rel.to_string().parse::<RelTag>().unwrap(), rel);
+        }
+        for invalid in [
+            "",
+            "1663/5",
+            "1663/5/16384/1",
+            "1663/5/16384_foo",
+            "1663/x/16384",
+        ] {
+            assert!(invalid.parse::<RelTag>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -335,6 +335,18 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
         &self.peer_addr
     }
 
+    /// The certificate chain presented by the client, if the connection is encrypted and the
+    /// TLS configuration requested one. Only available before the connection is split.
+    pub fn peer_certificates(&self) -> Option<&[rustls::pki_types::CertificateDer<'static>]> {
+        match &self.framed {
+            MaybeWriteOnly::Full(framed) => match framed.get_ref() {
+                MaybeTlsStream::Tls(stream) => stream.get_ref().1.peer_certificates(),
+                MaybeTlsStream::Unencrypted(_) => None,
+            },
+            MaybeWriteOnly::WriteOnly(_) | MaybeWriteOnly::Broken => None,
+        }
+    }
+
     /// Read full message or return None if connection is cleanly closed with no
     /// unprocessed data.
     pub async fn read_message(&mut self) -> Result<Option<FeMessage>, ConnectionError> {
@@ -496,6 +496,12 @@ pub enum BeMessage<'a> {
     DataRow(&'a [Option<&'a [u8]>]),
     // None errcode means internal_error will be sent.
     ErrorResponse(&'a str, Option<&'a [u8; 5]>),
+    /// ErrorResponse with a DETAIL field.
+    ErrorResponseWithDetail {
+        message: &'a str,
+        code: &'a [u8; 5],
+        detail: &'a str,
+    },
     /// Single byte - used in response to SSLRequest/GSSENCRequest.
     EncryptionResponse(bool),
     NoData,
@@ -834,6 +840,30 @@ impl<'a> BeMessage<'a> {
                 })?;
             }
 
+            BeMessage::ErrorResponseWithDetail {
+                message,
+                code,
+                detail,
+            } => {
+                buf.put_u8(b'E');
+                write_body(buf, |buf| {
+                    buf.put_u8(b'S'); // severity
+                    buf.put_slice(b"ERROR\0");
+
+                    buf.put_u8(b'C'); // SQLSTATE error code
+                    buf.put_slice(&terminate_code(code));
+
+                    buf.put_u8(b'M'); // the message
+                    write_cstr(message, buf)?;
+
+                    buf.put_u8(b'D'); // the detail
+                    write_cstr(detail, buf)?;
+
+                    buf.put_u8(0); // terminator
+                    Ok(())
+                })?;
+            }
+
             // NoticeResponse has the same format as ErrorResponse. From doc: "The frontend should display the
             // message but continue listening for ReadyForQuery or ErrorResponse"
             BeMessage::NoticeResponse(error_msg) => {
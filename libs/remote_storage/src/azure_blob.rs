@@ -4,7 +4,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::env;
 use std::io;
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -45,6 +45,10 @@ pub struct AzureBlobStorage {
 }
 
 impl AzureBlobStorage {
+    pub(crate) fn set_concurrency_limit(&self, limit: NonZeroUsize) {
+        self.concurrency_limiter.set_limit(limit.get());
+    }
+
     pub fn new(azure_config: &AzureConfig, timeout: Duration) -> Result<Self> {
         debug!(
             "Creating azure remote storage for azure container {}",
@@ -472,6 +472,17 @@ impl GenericRemoteStorage {
         })
     }
 
+    /// Change the number of requests of each kind that can be in flight at once, see
+    /// [`S3Config::concurrency_limit`]. Local file system storage has no limit.
+    pub fn set_concurrency_limit(&self, limit: NonZeroUsize) {
+        match self {
+            Self::LocalFs(_) => {}
+            Self::AwsS3(s) => s.set_concurrency_limit(limit),
+            Self::AzureBlob(s) => s.set_concurrency_limit(limit),
+            Self::Unreliable(s) => s.set_concurrency_limit(limit),
+        }
+    }
+
     pub fn unreliable_wrapper(s: Self, fail_first: u64) -> Self {
         Self::Unreliable(Arc::new(UnreliableWrapper::new(s, fail_first)))
     }
@@ -521,6 +532,12 @@ impl<const N: usize> From<[(&str, &str); N]> for StorageMetadata {
     }
 }
 
+impl StorageMetadata {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+}
+
 /// External backup storage configuration, enough for creating a client for that storage.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RemoteStorageConfig {
@@ -545,6 +562,17 @@ pub enum RemoteStorageKind {
     AzureContainer(AzureConfig),
 }
 
+impl RemoteStorageKind {
+    /// The limit of concurrent requests, for the kinds of storage which have one.
+    pub fn concurrency_limit(&self) -> Option<NonZeroUsize> {
+        match self {
+            RemoteStorageKind::LocalFs(_) => None,
+            RemoteStorageKind::AwsS3(config) => Some(config.concurrency_limit),
+            RemoteStorageKind::AzureContainer(config) => Some(config.concurrency_limit),
+        }
+    }
+}
+
 /// AWS S3 bucket coordinates and access credentials to manage the bucket contents (read and write).
 #[derive(Clone, PartialEq, Eq)]
 pub struct S3Config {
@@ -774,6 +802,8 @@ struct ConcurrencyLimiter {
     // The helps to ensure we don't exceed the thresholds.
     write: Arc<Semaphore>,
     read: Arc<Semaphore>,
+    /// The current limit, which can be changed at runtime with [`Self::set_limit`].
+    limit: std::sync::Mutex<usize>,
 }
 
 impl ConcurrencyLimiter {
@@ -806,7 +836,29 @@ impl ConcurrencyLimiter {
         Self {
             read: Arc::new(Semaphore::new(limit)),
             write: Arc::new(Semaphore::new(limit)),
+            limit: std::sync::Mutex::new(limit),
+        }
+    }
+
+    /// Requests in flight are not interrupted when the limit is lowered: the surplus permits
+    /// are taken away as these requests complete, by a task which must be able to spawn.
+    fn set_limit(&self, limit: usize) {
+        let mut current = self.limit.lock().unwrap();
+        if limit > *current {
+            self.read.add_permits(limit - *current);
+            self.write.add_permits(limit - *current);
+        } else if limit < *current {
+            let surplus = u32::try_from(*current - limit).expect("limits fit into u32");
+            for semaphore in [&self.read, &self.write] {
+                let semaphore = Arc::clone(semaphore);
+                tokio::spawn(async move {
+                    if let Ok(permits) = semaphore.acquire_many_owned(surplus).await {
+                        permits.forget();
+                    }
+                });
+            }
         }
+        *current = limit;
     }
 }
 
@@ -814,6 +866,25 @@ impl ConcurrencyLimiter {
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn concurrency_limit_change() {
+        let limiter = ConcurrencyLimiter::new(2);
+        let in_flight = limiter.acquire(RequestKind::Get).await.unwrap();
+
+        limiter.set_limit(4);
+        assert_eq!(limiter.read.available_permits(), 3);
+        assert_eq!(limiter.write.available_permits(), 4);
+
+        // lowering the limit waits for the requests in flight
+        limiter.set_limit(1);
+        tokio::task::yield_now().await;
+        assert_eq!(limiter.read.available_permits(), 0);
+        assert_eq!(limiter.write.available_permits(), 1);
+        drop(in_flight);
+        tokio::task::yield_now().await;
+        assert_eq!(limiter.read.available_permits(), 1);
+    }
+
     #[test]
     fn test_object_name() {
         let k = RemotePath::new(Utf8Path::new("a/b/c")).unwrap();
@@ -31,6 +31,8 @@ use super::{RemoteStorage, StorageMetadata};
 use crate::Etag;
 
 const LOCAL_FS_TEMP_FILE_SUFFIX: &str = "___temp";
+/// The metadata of a file is stored next to it, in a file with this extension.
+const STORAGE_METADATA_EXTENSION: &str = "metadata";
 
 #[derive(Debug, Clone)]
 pub struct LocalFs {
@@ -134,6 +136,7 @@ impl LocalFs {
         Ok(get_all_files(&self.storage_root)
             .await?
             .into_iter()
+            .filter(|path| !is_storage_metadata_path(path))
             .map(|path| {
                 path.strip_prefix(&self.storage_root)
                     .context("Failed to strip storage root prefix")
@@ -308,11 +311,8 @@ impl LocalFs {
                 )
             })?;
 
+        let storage_metadata_path = storage_metadata_path(&target_file_path);
         if let Some(storage_metadata) = metadata {
-            // FIXME: we must not be using metadata much, since this would forget the old metadata
-            // for new writes? or perhaps metadata is sticky; could consider removing if it's never
-            // used.
-            let storage_metadata_path = storage_metadata_path(&target_file_path);
             fs::write(
                 &storage_metadata_path,
                 serde_json::to_string(&storage_metadata.0)
@@ -324,6 +324,9 @@ impl LocalFs {
                     "Failed to write metadata to the local storage at '{storage_metadata_path}'",
                 )
             })?;
+        } else {
+            // Like in S3, overwriting a file replaces its metadata.
+            remove_file_if_exists(&storage_metadata_path).await?;
         }
 
         Ok(())
@@ -342,6 +345,7 @@ impl RemoteStorage for LocalFs {
             let mut result = Listing::default();
 
             // Filter out directories: in S3 directories don't exist, only the keys within them do.
+            // Neither do the files that hold the metadata of the others.
             let keys = self
                 .list_recursive(prefix)
                 .await
@@ -350,7 +354,7 @@ impl RemoteStorage for LocalFs {
                 .into_iter()
                 .filter(|k| {
                     let path = k.with_base(&self.storage_root);
-                    !path.is_dir()
+                    !path.is_dir() && !is_storage_metadata_path(&path)
                 })
                 .collect();
 
@@ -556,14 +560,11 @@ impl RemoteStorage for LocalFs {
 
     async fn delete(&self, path: &RemotePath, _cancel: &CancellationToken) -> anyhow::Result<()> {
         let file_path = path.with_base(&self.storage_root);
-        match fs::remove_file(&file_path).await {
-            Ok(()) => Ok(()),
-            // The file doesn't exist. This shouldn't yield an error to mirror S3's behaviour.
-            // See https://docs.aws.amazon.com/AmazonS3/latest/API/API_DeleteObject.html
-            // > If there isn't a null version, Amazon S3 does not remove any objects but will still respond that the command was successful.
-            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
-            Err(e) => Err(anyhow::anyhow!(e)),
-        }
+        // The file doesn't exist. This shouldn't yield an error to mirror S3's behaviour.
+        // See https://docs.aws.amazon.com/AmazonS3/latest/API/API_DeleteObject.html
+        // > If there isn't a null version, Amazon S3 does not remove any objects but will still respond that the command was successful.
+        remove_file_if_exists(&file_path).await?;
+        remove_file_if_exists(&storage_metadata_path(&file_path)).await
     }
 
     async fn delete_objects<'a>(
@@ -593,6 +594,18 @@ impl RemoteStorage for LocalFs {
                 to_path = to_path
             )
         })?;
+        let from_metadata_path = storage_metadata_path(&from_path);
+        let to_metadata_path = storage_metadata_path(&to_path);
+        match fs::copy(&from_metadata_path, &to_metadata_path).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                remove_file_if_exists(&to_metadata_path).await?
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("Failed to copy metadata to '{to_metadata_path}'")))
+            }
+        }
         Ok(())
     }
 
@@ -608,7 +621,19 @@ impl RemoteStorage for LocalFs {
 }
 
 fn storage_metadata_path(original_path: &Utf8Path) -> Utf8PathBuf {
-    path_with_suffix_extension(original_path, "metadata")
+    path_with_suffix_extension(original_path, STORAGE_METADATA_EXTENSION)
+}
+
+fn is_storage_metadata_path(path: &Utf8Path) -> bool {
+    path.extension() == Some(STORAGE_METADATA_EXTENSION)
+}
+
+async fn remove_file_if_exists(path: &Utf8Path) -> anyhow::Result<()> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(anyhow::anyhow!(e)),
+    }
 }
 
 async fn create_target_directory(target_file_path: &Utf8Path) -> anyhow::Result<()> {
@@ -925,6 +950,48 @@ mod fs_tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn metadata_follows_its_file() -> anyhow::Result<()> {
+        let (storage, cancel) = create_storage()?;
+        let metadata = StorageMetadata::from([("one", "1")]);
+        let upload_target =
+            upload_dummy_file(&storage, "upload_1", Some(metadata.clone()), &cancel).await?;
+        let metadata_path = storage_metadata_path(&upload_target.with_base(&storage.storage_root));
+        assert!(metadata_path.exists());
+
+        let listing = storage
+            .list(None, ListingMode::NoDelimiter, None, &cancel)
+            .await?;
+        assert_eq!(listing.keys, vec![upload_target.clone()]);
+
+        let copy_target = RemotePath::from_string("timelines/some_timeline/copy_1")?;
+        storage.copy(&upload_target, &copy_target, &cancel).await?;
+        let copy = storage.download(&copy_target, &cancel).await?;
+        assert_eq!(copy.metadata, Some(metadata));
+
+        // Overwriting a file without metadata drops the previous one.
+        let body = Bytes::from_static(b"new contents");
+        storage
+            .upload(
+                futures::stream::once(futures::future::ready(Ok(body.clone()))),
+                body.len(),
+                &upload_target,
+                None,
+                &cancel,
+            )
+            .await?;
+        assert_eq!(
+            storage.download(&upload_target, &cancel).await?.metadata,
+            None
+        );
+        assert!(!metadata_path.exists());
+
+        storage.delete(&copy_target, &cancel).await?;
+        assert!(!storage_metadata_path(&copy_target.with_base(&storage.storage_root)).exists());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn list() -> anyhow::Result<()> {
         // No delimiter: should recursively list everything
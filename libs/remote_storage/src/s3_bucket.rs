@@ -7,7 +7,7 @@
 use std::{
     borrow::Cow,
     collections::HashMap,
-    num::NonZeroU32,
+    num::{NonZeroU32, NonZeroUsize},
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
@@ -74,6 +74,10 @@ struct GetObjectRequest {
     range: Option<String>,
 }
 impl S3Bucket {
+    pub(crate) fn set_concurrency_limit(&self, limit: NonZeroUsize) {
+        self.concurrency_limiter.set_limit(limit.get());
+    }
+
     /// Creates the S3 storage, errors if incorrect AWS S3 configuration provided.
     pub fn new(remote_storage_config: &S3Config, timeout: Duration) -> anyhow::Result<Self> {
         tracing::debug!(
@@ -4,7 +4,7 @@
 use bytes::Bytes;
 use futures::stream::Stream;
 use std::collections::HashMap;
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::sync::Mutex;
 use std::time::SystemTime;
 use std::{collections::hash_map::Entry, sync::Arc};
@@ -37,6 +37,14 @@ enum RemoteOp {
 }
 
 impl UnreliableWrapper {
+    pub(crate) fn set_concurrency_limit(&self, limit: NonZeroUsize) {
+        match &self.inner {
+            GenericRemoteStorage::AwsS3(s) => s.set_concurrency_limit(limit),
+            GenericRemoteStorage::AzureBlob(s) => s.set_concurrency_limit(limit),
+            GenericRemoteStorage::LocalFs(_) | GenericRemoteStorage::Unreliable(_) => {}
+        }
+    }
+
     pub fn new(inner: crate::GenericRemoteStorage, attempts_to_fail: u64) -> Self {
         assert!(attempts_to_fail > 0);
         let inner = match inner {
@@ -10,7 +10,11 @@ use jsonwebtoken::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{http::error::ApiError, id::TenantId};
+use crate::{
+    http::error::ApiError,
+    id::{TenantId, TimelineId},
+    lsn::Lsn,
+};
 
 /// Algorithm to use. We require EdDSA.
 const STORAGE_TOKEN_ALGORITHM: Algorithm = Algorithm::EdDSA;
@@ -35,17 +39,56 @@ pub enum Scope {
     Admin,
 }
 
+/// The LSNs that a [`Scope::Tenant`] token may read at, on page_service.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum LsnAccess {
+    /// Any LSN, including the latest one. For primary computes.
+    ReadWrite,
+    /// Only LSNs from `start` up to and including `end`, and never the latest LSN. For
+    /// read-only computes: static computes at a fixed LSN, or replicas if `end` is unset.
+    ReadOnly {
+        start: Lsn,
+        #[serde(default)]
+        end: Option<Lsn>,
+    },
+}
+
+impl LsnAccess {
+    /// Whether reading at `lsn` is allowed, `None` meaning the latest LSN.
+    pub fn allows(&self, lsn: Option<Lsn>) -> bool {
+        match (self, lsn) {
+            (LsnAccess::ReadWrite, _) => true,
+            (LsnAccess::ReadOnly { .. }, None) => false,
+            (LsnAccess::ReadOnly { start, end }, Some(lsn)) => {
+                lsn >= *start && end.map_or(true, |end| lsn <= end)
+            }
+        }
+    }
+}
+
 /// JWT payload. See docs/authentication.md for the format
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Claims {
     #[serde(default)]
     pub tenant_id: Option<TenantId>,
+    /// Restricts a [`Scope::Tenant`] token to one timeline of the tenant.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline_id: Option<TimelineId>,
+    /// Restricts the LSNs a [`Scope::Tenant`] token may read at. Unrestricted if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lsn_access: Option<LsnAccess>,
     pub scope: Scope,
 }
 
 impl Claims {
     pub fn new(tenant_id: Option<TenantId>, scope: Scope) -> Self {
-        Self { tenant_id, scope }
+        Self {
+            tenant_id,
+            timeline_id: None,
+            lsn_access: None,
+            scope,
+        }
     }
 }
 
@@ -195,6 +238,8 @@ MC4CAQAwBQYDK2VwBCIEID/Drmc1AA6U/znNRWpF3zEGegOATQxfkdWxitcOMsIH
     fn test_decode() {
         let expected_claims = Claims {
             tenant_id: Some(TenantId::from_str("3d1f7595b468230304e0b73cecbcb081").unwrap()),
+            timeline_id: None,
+            lsn_access: None,
             scope: Scope::Tenant,
         };
 
@@ -221,6 +266,8 @@ MC4CAQAwBQYDK2VwBCIEID/Drmc1AA6U/znNRWpF3zEGegOATQxfkdWxitcOMsIH
     fn test_encode() {
         let claims = Claims {
             tenant_id: Some(TenantId::from_str("3d1f7595b468230304e0b73cecbcb081").unwrap()),
+            timeline_id: None,
+            lsn_access: None,
             scope: Scope::Tenant,
         };
 
@@ -232,4 +279,33 @@ MC4CAQAwBQYDK2VwBCIEID/Drmc1AA6U/znNRWpF3zEGegOATQxfkdWxitcOMsIH
 
         assert_eq!(decoded.claims, claims);
     }
+
+    #[test]
+    fn test_timeline_claims() {
+        let token = r#"{
+            "scope": "tenant",
+            "tenant_id": "3d1f7595b468230304e0b73cecbcb081",
+            "timeline_id": "de200bd42b49cc1814412c7e592dd6e9",
+            "lsn_access": {"mode": "read_only", "start": "0/16B5A50", "end": "0/16B9188"}
+        }"#;
+        let claims: Claims = serde_json::from_str(token).unwrap();
+        assert_eq!(
+            claims.timeline_id,
+            Some(TimelineId::from_str("de200bd42b49cc1814412c7e592dd6e9").unwrap())
+        );
+        let lsn_access = claims.lsn_access.unwrap();
+        assert!(lsn_access.allows(Some(Lsn(0x16B5A50))));
+        assert!(lsn_access.allows(Some(Lsn(0x16B9188))));
+        assert!(!lsn_access.allows(Some(Lsn(0x16B5A4F))));
+        assert!(!lsn_access.allows(Some(Lsn(0x16B9189))));
+        assert!(!lsn_access.allows(None));
+        assert!(LsnAccess::ReadWrite.allows(None));
+
+        // Tokens without the new claims are unchanged when encoded again.
+        let claims = Claims::new(None, Scope::PageServerApi);
+        assert_eq!(
+            serde_json::to_string(&claims).unwrap(),
+            r#"{"tenant_id":null,"scope":"pageserverapi"}"#
+        );
+    }
 }
@@ -182,6 +182,23 @@ pub fn overwrite(
             nix::errno::Errno::EINVAL as i32,
         ));
     };
+    overwrite_no_parent_fsync(final_path, tmp_path, content)?;
+
+    let final_parent_dirfd = std::fs::OpenOptions::new()
+        .read(true)
+        .open(final_path_parent)?;
+
+    final_parent_dirfd.sync_all()?;
+    Ok(())
+}
+
+/// Like [`overwrite`], but leaves the fsync of the parent directory, which makes the rename
+/// durable, to the caller.
+pub fn overwrite_no_parent_fsync(
+    final_path: &Utf8Path,
+    tmp_path: &Utf8Path,
+    content: &[u8],
+) -> std::io::Result<()> {
     std::fs::remove_file(tmp_path).or_else(crate::fs_ext::ignore_not_found)?;
     let mut file = std::fs::OpenOptions::new()
         .write(true)
@@ -193,14 +210,7 @@ pub fn overwrite(
     file.sync_all()?;
     drop(file); // don't keep the fd open for longer than we have to
 
-    std::fs::rename(tmp_path, final_path)?;
-
-    let final_parent_dirfd = std::fs::OpenOptions::new()
-        .read(true)
-        .open(final_path_parent)?;
-
-    final_parent_dirfd.sync_all()?;
-    Ok(())
+    std::fs::rename(tmp_path, final_path)
 }
 
 #[cfg(test)]
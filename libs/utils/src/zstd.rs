@@ -1,4 +1,5 @@
 use std::io::SeekFrom;
+use std::path::Path;
 
 use anyhow::{Context, Result};
 use async_compression::{
@@ -12,6 +13,7 @@ use tokio::{
     fs::{File, OpenOptions},
     io::AsyncBufRead,
     io::AsyncSeekExt,
+    io::AsyncWrite,
     io::AsyncWriteExt,
 };
 use tokio_tar::{Archive, Builder, HeaderMode};
@@ -28,6 +30,27 @@ pub async fn create_zst_tarball(path: &Utf8Path, tarball: &Utf8Path) -> Result<(
         .await
         .with_context(|| format!("tempfile creation {tarball}"))?;
 
+    let zstd = ZstdEncoder::with_quality_and_params(
+        file,
+        Level::Default,
+        &[CParameter::enable_long_distance_matching(true)],
+    );
+    let mut zstd = write_tarball(path, &[], zstd).await?;
+    zstd.shutdown().await?;
+    let mut compressed = zstd.into_inner();
+    let compressed_len = compressed.metadata().await?.len();
+    compressed.seek(SeekFrom::Start(0)).await?;
+    Ok((compressed, compressed_len))
+}
+
+/// Writes a tarball of the directory at `path` into `writer`, and returns the writer.
+///
+/// The entries are sorted to get a more consistent listing, except that the files at the
+/// relative paths in `first` come first, in that order.
+pub async fn write_tarball<W>(path: &Utf8Path, first: &[&str], writer: W) -> Result<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
     let mut paths = Vec::new();
     for entry in WalkDir::new(path) {
         let entry = entry?;
@@ -39,14 +62,15 @@ pub async fn create_zst_tarball(path: &Utf8Path, tarball: &Utf8Path) -> Result<(
         let path = entry.into_path();
         paths.push(path);
     }
-    // Do a sort to get a more consistent listing
-    paths.sort_unstable();
-    let zstd = ZstdEncoder::with_quality_and_params(
-        file,
-        Level::Default,
-        &[CParameter::enable_long_distance_matching(true)],
-    );
-    let mut builder = Builder::new(zstd);
+    paths.sort_by_cached_key(|p| {
+        let rank = p
+            .strip_prefix(path)
+            .ok()
+            .and_then(|rel_path| first.iter().position(|first| rel_path == Path::new(first)))
+            .unwrap_or(first.len());
+        (rank, p.clone())
+    });
+    let mut builder = Builder::new(writer);
     // Use reproducible header mode
     builder.mode(HeaderMode::Deterministic);
     for p in paths {
@@ -58,12 +82,7 @@ pub async fn create_zst_tarball(path: &Utf8Path, tarball: &Utf8Path) -> Result<(
         }
         builder.append_path_with_name(&p, rel_path).await?;
     }
-    let mut zstd = builder.into_inner().await?;
-    zstd.shutdown().await?;
-    let mut compressed = zstd.into_inner();
-    let compressed_len = compressed.metadata().await?.len();
-    compressed.seek(SeekFrom::Start(0)).await?;
-    Ok((compressed, compressed_len))
+    Ok(builder.into_inner().await?)
 }
 
 /// Creates a Zstandard tarball.
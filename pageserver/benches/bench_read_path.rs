@@ -0,0 +1,283 @@
+//! Benchmarks of the read path: `Timeline::get_vectored` on a timeline of the tenant harness,
+//! which finds the layers of the keys in the layer map, reads their values from the layer files
+//! and reconstructs the pages. The WAL records are neon records, which the harness applies
+//! in-process like the walredo manager does: `bench_walredo` covers postgres redo.
+//!
+//! We exercise:
+//! - `reconstruct/{depth}`: a page image and `depth` WAL records of one key. The image is in a
+//!   delta layer of its own and the records in delta layers of 8, so that the read traverses
+//!   all of them.
+//! - `get-single/{n}` and `get-vectored/{n}`: reading the images of `n` consecutive keys with a
+//!   call per key, like single page requests, or with one vectored call.
+//! - `page-cache/{hit_pct}`: block reads of a layer file, of which `hit_pct` percent go to a hot
+//!   set that fits in the page cache, and the rest to a cold set several times larger than it.
+//!   The cold reads are served by the kernel's page cache rather than the disk.
+//! - `remote-cold/{mib}`: the read of one page from an evicted layer of `mib` MiB, which is
+//!   downloaded on demand from the local filesystem remote storage of the harness.
+//!
+//! Run with `cargo bench --features testing --bench bench_read_path`.
+
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use pageserver::context::{DownloadBehavior, RequestContext};
+use pageserver::page_cache::{self, PAGE_SZ};
+use pageserver::repository::{Key, Value};
+use pageserver::task_mgr::TaskKind;
+use pageserver::tenant::block_io::FileBlockReader;
+use pageserver::tenant::harness::{evict_layers, get_vectored, put_and_flush, TenantHarness};
+use pageserver::virtual_file::{self, VirtualFile};
+use pageserver::walrecord::NeonWalRecord;
+use pageserver::DEFAULT_PG_VERSION;
+use pageserver_api::key::rel_block_to_key;
+use pageserver_api::reltag::RelTag;
+use postgres_ffi::relfile_utils::{MAIN_FORKNUM, VISIBILITYMAP_FORKNUM};
+use rand::prelude::{Rng, SeedableRng, StdRng};
+use tokio::runtime::Runtime;
+use utils::id::TimelineId;
+use utils::lsn::Lsn;
+
+/// Small enough for the cold set of the page cache benchmark to be cheap to write.
+const PAGE_CACHE_PAGES: usize = 2048;
+
+const INITDB_LSN: Lsn = Lsn(0x08);
+
+fn init() -> Runtime {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        virtual_file::init(1000, virtual_file::api::IoEngineKind::StdFs);
+        page_cache::init(PAGE_CACHE_PAGES);
+    });
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn ctx(download: DownloadBehavior) -> RequestContext {
+    RequestContext::new(TaskKind::DebugTool, download)
+}
+
+fn page_image(fill: u8) -> Bytes {
+    Bytes::from(vec![fill; PAGE_SZ])
+}
+
+fn rel(forknum: u8) -> RelTag {
+    RelTag {
+        forknum,
+        spcnode: 1663,
+        dbnode: 13010,
+        relnode: 16384,
+    }
+}
+
+/// The keys of the first `n` blocks of a relation, and their images at `lsn`.
+fn images(n: usize, lsn: Lsn) -> Vec<(Key, Lsn, Value)> {
+    (0..n)
+        .map(|blknum| {
+            let key = rel_block_to_key(rel(MAIN_FORKNUM), blknum as u32);
+            (key, lsn, Value::Image(page_image(blknum as u8)))
+        })
+        .collect()
+}
+
+fn bench_reconstruct(c: &mut Criterion) {
+    let rt = init();
+    let harness = TenantHarness::create("bench_read_path_reconstruct").unwrap();
+    let (tenant, ctx) = rt.block_on(harness.load());
+    let key = rel_block_to_key(rel(VISIBILITYMAP_FORKNUM), 0);
+
+    let mut group = c.benchmark_group("reconstruct");
+    for depth in [1u32, 8, 32, 128] {
+        let timeline = rt
+            .block_on(tenant.create_test_timeline(
+                TimelineId::generate(),
+                INITDB_LSN,
+                DEFAULT_PG_VERSION,
+                &ctx,
+            ))
+            .unwrap();
+        let image_lsn = Lsn(0x10);
+        let records = (0..depth)
+            .map(|i| {
+                let record = NeonWalRecord::ClearVisibilityMapFlags {
+                    new_heap_blkno: Some(i),
+                    old_heap_blkno: None,
+                    flags: 1,
+                };
+                (
+                    key,
+                    image_lsn + 8 * (i as u64 + 1),
+                    Value::WalRecord(record),
+                )
+            })
+            .collect::<Vec<_>>();
+        let request_lsn = records.last().unwrap().1;
+        rt.block_on(async {
+            let image = (key, image_lsn, Value::Image(page_image(0xff)));
+            put_and_flush(&timeline, &[image], &ctx).await.unwrap();
+            for layer in records.chunks(8) {
+                put_and_flush(&timeline, layer, &ctx).await.unwrap();
+            }
+        });
+
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, _| {
+            b.iter(|| {
+                rt.block_on(async {
+                    let pages = get_vectored(&timeline, key..key.next(), request_lsn, &ctx)
+                        .await
+                        .unwrap();
+                    black_box(pages)
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_gets(c: &mut Criterion) {
+    let rt = init();
+    let harness = TenantHarness::create("bench_read_path_gets").unwrap();
+    let (tenant, ctx) = rt.block_on(harness.load());
+
+    // `get_vectored` reads at most 32 keys per call.
+    let n_max = 32;
+    let lsn = Lsn(0x10);
+    let timeline = rt
+        .block_on(tenant.create_test_timeline(
+            TimelineId::generate(),
+            INITDB_LSN,
+            DEFAULT_PG_VERSION,
+            &ctx,
+        ))
+        .unwrap();
+    let values = images(n_max, lsn);
+    rt.block_on(put_and_flush(&timeline, &values, &ctx))
+        .unwrap();
+    let first = values[0].0;
+
+    let mut group = c.benchmark_group("get-single");
+    for n in [1usize, 8, n_max] {
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+            b.iter(|| {
+                rt.block_on(async {
+                    for (key, _, _) in &values[..n] {
+                        let page = get_vectored(&timeline, *key..key.next(), lsn, &ctx)
+                            .await
+                            .unwrap();
+                        black_box(page);
+                    }
+                })
+            })
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("get-vectored");
+    for n in [1usize, 8, n_max] {
+        let keys = first..first.add(n as u32);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &keys, |b, keys| {
+            b.iter(|| {
+                rt.block_on(async {
+                    let pages = get_vectored(&timeline, keys.clone(), lsn, &ctx)
+                        .await
+                        .unwrap();
+                    black_box(pages)
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_page_cache(c: &mut Criterion) {
+    let rt = init();
+    let dir = camino_tempfile::tempdir_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+    let ctx = ctx(DownloadBehavior::Error);
+
+    let hot_blocks = PAGE_CACHE_PAGES / 2;
+    let total_blocks = PAGE_CACHE_PAGES * 4;
+    let path = dir.path().join("page-cache");
+    std::fs::write(&path, vec![0u8; total_blocks * PAGE_SZ]).unwrap();
+    let file = rt.block_on(VirtualFile::open(&path)).unwrap();
+    let block_reader = FileBlockReader::new(&file, page_cache::next_file_id());
+
+    let mut group = c.benchmark_group("page-cache");
+    for hit_pct in [100u32, 90, 50, 0] {
+        // Start each mix with the hot set cached.
+        rt.block_on(async {
+            for blknum in 0..hot_blocks {
+                block_reader.read_blk(blknum as u32, &ctx).await.unwrap();
+            }
+        });
+        let mut rng = StdRng::seed_from_u64(42);
+        group.bench_with_input(BenchmarkId::from_parameter(hit_pct), &hit_pct, |b, _| {
+            b.iter(|| {
+                let blknum = if rng.gen_range(0..100) < hit_pct {
+                    rng.gen_range(0..hot_blocks)
+                } else {
+                    rng.gen_range(hot_blocks..total_blocks)
+                };
+                rt.block_on(async {
+                    let block = block_reader.read_blk(blknum as u32, &ctx).await.unwrap();
+                    black_box(block[0]);
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_remote_cold(c: &mut Criterion) {
+    let rt = init();
+    let download_ctx = ctx(DownloadBehavior::Download);
+    let harness = TenantHarness::create("bench_read_path_remote_cold").unwrap();
+    let (tenant, ctx) = rt.block_on(harness.load());
+
+    let mut group = c.benchmark_group("remote-cold");
+    group.sample_size(10);
+    for mib in [1usize, 8] {
+        let lsn = Lsn(0x10);
+        let timeline = rt
+            .block_on(tenant.create_test_timeline(
+                TimelineId::generate(),
+                INITDB_LSN,
+                DEFAULT_PG_VERSION,
+                &ctx,
+            ))
+            .unwrap();
+        let values = images(mib * 1024 * 1024 / PAGE_SZ, lsn);
+        rt.block_on(put_and_flush(&timeline, &values, &ctx))
+            .unwrap();
+        let key = values[values.len() / 2].0;
+
+        group.bench_with_input(BenchmarkId::from_parameter(mib), &mib, |b, _| {
+            // Only the read is timed, not the eviction before it.
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    rt.block_on(evict_layers(&timeline)).unwrap();
+                    let started_at = Instant::now();
+                    let page = rt
+                        .block_on(get_vectored(&timeline, key..key.next(), lsn, &download_ctx))
+                        .unwrap();
+                    elapsed += started_at.elapsed();
+                    black_box(page);
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_reconstruct,
+    bench_gets,
+    bench_page_cache,
+    bench_remote_cold
+);
+criterion_main!(benches);
@@ -262,6 +262,19 @@ impl Client {
         Ok(())
     }
 
+    /// All the versions of a tenant config template, oldest first.
+    pub async fn tenant_config_template(&self, name: &str) -> Result<Vec<TenantConfigTemplate>> {
+        let uri = format!(
+            "{}/v1/tenant_config_template/{name}",
+            self.mgmt_api_endpoint
+        );
+        self.request(Method::GET, &uri, ())
+            .await?
+            .json()
+            .await
+            .map_err(Error::ReceiveBody)
+    }
+
     pub async fn tenant_secondary_download(
         &self,
         tenant_id: TenantShardId,
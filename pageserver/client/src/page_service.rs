@@ -3,8 +3,10 @@ use std::pin::Pin;
 use futures::SinkExt;
 use pageserver_api::{
     models::{
-        PagestreamBeMessage, PagestreamFeMessage, PagestreamGetPageRequest,
-        PagestreamGetPageResponse,
+        PagestreamBeMessage, PagestreamFeMessage, PagestreamFeature,
+        PagestreamGetPageAtLeastRequest, PagestreamGetPageAtLeastResponse,
+        PagestreamGetPageRequest, PagestreamGetPageResponse, PagestreamNegotiation,
+        PagestreamProtocolVersion,
     },
     reltag::RelTag,
 };
@@ -28,6 +30,9 @@ pub struct BasebackupRequest {
     pub timeline_id: TimelineId,
     pub lsn: Option<Lsn>,
     pub gzip: bool,
+    /// The manifest of the files the client already has, to only receive the
+    /// changed ones. Entries are separated by commas.
+    pub incremental: Option<String>,
 }
 
 impl Client {
@@ -53,6 +58,51 @@ impl Client {
         })
     }
 
+    /// Agree with the pageserver on the newest pagestream protocol version within
+    /// `min..=max`, and on which of `features` to use with it. Pageservers that predate the
+    /// negotiation fail it, and only support the V1 and V2 versions.
+    pub async fn negotiate_pagestream(
+        &self,
+        min: PagestreamProtocolVersion,
+        max: PagestreamProtocolVersion,
+        features: &[PagestreamFeature],
+    ) -> anyhow::Result<PagestreamNegotiation> {
+        let features = features
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let query = format!(
+            "pagestream_negotiate {} {} {features}",
+            min.number(),
+            max.number()
+        );
+        let row = self
+            .client
+            .simple_query(query.trim_end())
+            .await?
+            .into_iter()
+            .find_map(|msg| match msg {
+                tokio_postgres::SimpleQueryMessage::Row(row) => Some(row),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("no row in the pagestream_negotiate response"))?;
+
+        let version = row
+            .get("version")
+            .and_then(|v| v.parse().ok())
+            .and_then(PagestreamProtocolVersion::from_number)
+            .ok_or_else(|| anyhow::anyhow!("invalid negotiated version"))?;
+        let features = row
+            .get("features")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .map(|f| f.parse::<PagestreamFeature>())
+            .collect::<Result<_, _>>()?;
+        Ok(PagestreamNegotiation { version, features })
+    }
+
     pub async fn pagestream(
         self,
         tenant_id: TenantId,
@@ -80,8 +130,9 @@ impl Client {
             timeline_id,
             lsn,
             gzip,
+            incremental,
         } = req;
-        let mut args = Vec::with_capacity(5);
+        let mut args = Vec::with_capacity(7);
         args.push("basebackup".to_string());
         args.push(format!("{tenant_id}"));
         args.push(format!("{timeline_id}"));
@@ -91,6 +142,10 @@ impl Client {
         if *gzip {
             args.push("--gzip".to_string())
         }
+        if let Some(manifest) = incremental {
+            args.push("--incremental".to_string());
+            args.push(manifest.clone());
+        }
         Ok(self.client.copy_out(&args.join(" ")).await?)
     }
 }
@@ -157,7 +212,8 @@ impl PagestreamClient {
             PagestreamBeMessage::Exists(_)
             | PagestreamBeMessage::Nblocks(_)
             | PagestreamBeMessage::DbSize(_)
-            | PagestreamBeMessage::GetSlruSegment(_) => {
+            | PagestreamBeMessage::GetSlruSegment(_)
+            | PagestreamBeMessage::GetPageAtLeast(_) => {
                 anyhow::bail!(
                     "unexpected be message kind in response to getpage request: {}",
                     msg.kind()
@@ -165,4 +221,27 @@ impl PagestreamClient {
             }
         }
     }
+
+    /// Only for pageservers that accepted [`PagestreamFeature::LsnAtLeast`].
+    pub async fn getpage_at_least(
+        &mut self,
+        req: PagestreamGetPageAtLeastRequest,
+    ) -> anyhow::Result<PagestreamGetPageAtLeastResponse> {
+        let req = PagestreamFeMessage::GetPageAtLeast(req);
+        let mut req = tokio_stream::once(Ok(req.serialize()));
+
+        self.copy_both.send_all(&mut req).await?;
+
+        let next: Option<Result<bytes::Bytes, _>> = self.copy_both.next().await;
+        let next: bytes::Bytes = next.unwrap()?;
+
+        match PagestreamBeMessage::deserialize(next)? {
+            PagestreamBeMessage::GetPageAtLeast(p) => Ok(p),
+            PagestreamBeMessage::Error(e) => anyhow::bail!("Error: {:?}", e),
+            msg => anyhow::bail!(
+                "unexpected be message kind in response to getpage_at_least request: {}",
+                msg.kind()
+            ),
+        }
+    }
 }
@@ -1,10 +1,13 @@
 use clap::{Parser, Subcommand};
-use pageserver_compaction::simulator::MockTimeline;
+use pageserver_compaction::simulator::recorded::RecordedTimeline;
+use pageserver_compaction::simulator::{read_amplification, MockTimeline, READ_AMP_SAMPLES};
 use rand::Rng;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
+use std::time::Duration;
 
+use utils::id::TimelineId;
 use utils::project_git_version;
 
 project_git_version!(GIT_VERSION);
@@ -25,6 +28,7 @@ struct CliOpts {
 enum Commands {
     RunSuite,
     Simulate(SimulateCmd),
+    Replay(ReplayCmd),
 }
 
 #[derive(Clone, clap::ValueEnum)]
@@ -47,6 +51,98 @@ struct SimulateCmd {
     logical_size: u64,
 }
 
+/// Replay the layer map or heatmap of a timeline with other compaction, GC and eviction settings
+#[derive(Parser)]
+struct ReplayCmd {
+    /// Layer map of the timeline, from the pageserver's layer API, or heatmap of its tenant
+    path: PathBuf,
+    /// Timeline to replay, if the heatmap has several
+    #[arg(long)]
+    timeline_id: Option<TimelineId>,
+
+    /// Length of the records that the delta layers are turned into
+    #[arg(long, default_value_t = 8192)]
+    record_len: u64,
+
+    /// Target size of the layer files
+    #[arg(long, default_value_t = 256 * 1024 * 1024)]
+    target_file_size: u64,
+    /// Number of L0 flushes between compactions
+    #[arg(long, default_value_t = 4)]
+    tiers_per_level: u64,
+    /// Bytes of WAL to retain for PITR. No GC if not set.
+    #[arg(long)]
+    gc_horizon: Option<u64>,
+    /// Evict layers which were not accessed for this long, e.g. "24h"
+    #[arg(long, value_parser = humantime::parse_duration)]
+    eviction_threshold: Option<Duration>,
+
+    /// Directory to write the statistics and the animation to
+    #[arg(long, default_value = ".")]
+    results_dir: PathBuf,
+}
+
+async fn replay(cmd: &ReplayCmd) -> anyhow::Result<()> {
+    let recorded = RecordedTimeline::load(&cmd.path, cmd.timeline_id)?;
+    let keyspace = recorded.keyspace();
+
+    let mut s = String::new();
+    {
+        use std::fmt::Write;
+        writeln!(s, "RECORDED:")?;
+        writeln!(s, "files:             {:>10}", recorded.layers.len())?;
+        writeln!(
+            s,
+            "size:           {:>10} MB",
+            recorded.size() / (1024 * 1024)
+        )?;
+        writeln!(
+            s,
+            "read amp:          {:>10.2}",
+            read_amplification(&recorded.mock_layers(), &keyspace, READ_AMP_SAMPLES)
+        )?;
+        if let Some(threshold) = cmd.eviction_threshold {
+            match recorded.resident_size(threshold) {
+                Some(resident) => writeln!(
+                    s,
+                    "resident after eviction: {:>4} MB",
+                    resident / (1024 * 1024)
+                )?,
+                None => writeln!(s, "no access times recorded, cannot project eviction")?,
+            }
+        }
+    }
+
+    let mut executor = MockTimeline::new();
+    executor.target_file_size = cmd.target_file_size;
+    executor.tiers_per_level = cmd.tiers_per_level;
+    executor.gc_horizon = cmd.gc_horizon;
+    println!(
+        "replaying {} layers of {}...",
+        recorded.layers.len(),
+        cmd.path.display()
+    );
+    recorded.replay(&mut executor, cmd.record_len).await?;
+    if let Some(horizon) = cmd.gc_horizon {
+        executor.gc(horizon).await?;
+    }
+    println!("done!");
+    s.push_str(&executor.stats()?);
+
+    print!("{s}");
+    std::fs::create_dir_all(&cmd.results_dir)?;
+    std::fs::write(cmd.results_dir.join("stats.txt"), s)?;
+
+    let animation_path = cmd.results_dir.join("compaction-animation.html");
+    executor.draw_history(std::fs::File::create(&animation_path)?)?;
+    println!(
+        "animation: file://{}",
+        animation_path.canonicalize()?.display()
+    );
+
+    Ok(())
+}
+
 async fn simulate(cmd: &SimulateCmd, results_path: &Path) -> anyhow::Result<()> {
     let mut executor = MockTimeline::new();
 
@@ -209,6 +305,9 @@ async fn main() -> anyhow::Result<()> {
         Commands::RunSuite => {
             run_suite().await?;
         }
+        Commands::Replay(cmd) => {
+            replay(&cmd).await?;
+        }
     };
     Ok(())
 }
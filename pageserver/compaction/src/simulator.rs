@@ -1,4 +1,5 @@
 mod draw;
+pub mod recorded;
 
 use draw::{LayerTraceEvent, LayerTraceFile, LayerTraceOp};
 
@@ -25,7 +26,10 @@ use crate::interface::CompactionLayer;
 pub struct MockTimeline {
     // Parameters for the compaction algorithm
     pub target_file_size: u64,
-    tiers_per_level: u64,
+    pub tiers_per_level: u64,
+    /// If set, GC runs after each compaction, and removes the layers that are older than this
+    /// many bytes of WAL and covered by newer image layers.
+    pub gc_horizon: Option<u64>,
 
     num_l0_flushes: u64,
     last_compact_at_flush: u64,
@@ -187,6 +191,7 @@ impl MockTimeline {
         MockTimeline {
             target_file_size: 256 * 1024 * 1024,
             tiers_per_level: 4,
+            gc_horizon: None,
 
             num_l0_flushes: 0,
             last_compact_at_flush: 0,
@@ -239,6 +244,7 @@ impl MockTimeline {
         });
         self.total_len += len;
         self.end_lsn += len;
+        self.wal_ingested += len;
 
         if self.total_len > self.target_file_size {
             self.flush_l0();
@@ -249,10 +255,68 @@ impl MockTimeline {
         if self.num_l0_flushes - self.last_compact_at_flush >= self.tiers_per_level {
             self.compact().await?;
             self.last_compact_at_flush = self.num_l0_flushes;
+            if let Some(horizon) = self.gc_horizon {
+                self.gc(horizon).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the layers that end before the GC cutoff, `horizon` bytes of WAL behind the last
+    /// ingested record, if image layers between their end and the cutoff cover their key range.
+    pub async fn gc(&mut self, horizon: u64) -> anyhow::Result<()> {
+        let ctx = MockRequestContext {};
+        let cutoff = Lsn(self.end_lsn.0.saturating_sub(horizon));
+
+        self.live_layers.retain(|l| !l.is_deleted());
+        let images: Vec<(Lsn, Range<Key>)> = self
+            .live_layers
+            .iter()
+            .filter(|l| !l.is_delta() && l.lsn_range().start <= cutoff)
+            .map(|l| (l.lsn_range().start, l.key_range().clone()))
+            .collect();
+
+        let mut garbage = Vec::new();
+        for l in self.live_layers.iter() {
+            if l.lsn_range().end > cutoff {
+                continue;
+            }
+            // An image layer is only superseded by an image layer at a later LSN.
+            let newer_images = images.iter().filter(|(lsn, _)| {
+                *lsn >= l.lsn_range().end && (l.is_delta() || *lsn > l.lsn_range().start)
+            });
+            if covers(newer_images.map(|(_, key_range)| key_range), l.key_range()) {
+                garbage.push(l.clone());
+            }
         }
+        for l in garbage {
+            interface::CompactionJobExecutor::delete_layer(self, &l, &ctx).await?;
+        }
+        self.live_layers.retain(|l| !l.is_deleted());
         Ok(())
     }
 
+    /// Total size of the layers on disk.
+    pub fn live_size(&self) -> u64 {
+        self.live_layers
+            .iter()
+            .filter(|l| !l.is_deleted())
+            .map(|l| l.file_size())
+            .sum()
+    }
+
+    /// Average number of layers that a read of the latest version of a key visits, over
+    /// `samples` keys evenly spread over the keyspace.
+    pub fn read_amplification(&self, samples: u64) -> f64 {
+        let live_layers: Vec<MockLayer> = self
+            .live_layers
+            .iter()
+            .filter(|l| !l.is_deleted())
+            .cloned()
+            .collect();
+        read_amplification(&live_layers, &self.keyspace, samples)
+    }
+
     pub fn flush_l0(&mut self) {
         if self.records.is_empty() {
             return;
@@ -295,6 +359,11 @@ impl MockTimeline {
         self.last_flush_lsn = self.end_lsn;
     }
 
+    // Add `key_range` to the keyspace, without ingesting any records to it
+    pub fn add_keyspace(&mut self, key_range: &Range<Key>) {
+        crate::helpers::union_to_keyspace(&mut self.keyspace, vec![key_range.clone()]);
+    }
+
     // Ingest `num_records' records to the timeline, with random keys
     // uniformly distributed in `key_range`
     pub fn ingest_uniform(
@@ -303,11 +372,10 @@ impl MockTimeline {
         len: u64,
         key_range: &Range<Key>,
     ) -> anyhow::Result<()> {
-        crate::helpers::union_to_keyspace(&mut self.keyspace, vec![key_range.clone()]);
+        self.add_keyspace(key_range);
         let mut rng = rand::thread_rng();
         for _ in 0..num_records {
             self.ingest_record(rng.gen_range(key_range.clone()), len);
-            self.wal_ingested += len;
         }
         Ok(())
     }
@@ -343,6 +411,16 @@ impl MockTimeline {
             "storage amp:       {:>10.2}",
             (self.bytes_written - self.bytes_deleted) as f64 / self.wal_ingested as f64
         )?;
+        writeln!(
+            s,
+            "live size:      {:>10} MB",
+            self.live_size() / (1024 * 1024)
+        )?;
+        writeln!(
+            s,
+            "read amp:          {:>10.2}",
+            self.read_amplification(READ_AMP_SAMPLES)
+        )?;
 
         Ok(s)
     }
@@ -352,6 +430,68 @@ impl MockTimeline {
     }
 }
 
+/// Number of keys sampled for the read amplification in [`MockTimeline::stats`].
+pub const READ_AMP_SAMPLES: u64 = 1000;
+
+/// Do the `ranges` together cover all of `key_range`?
+fn covers<'a>(ranges: impl Iterator<Item = &'a Range<Key>>, key_range: &Range<Key>) -> bool {
+    let mut ranges: Vec<&Range<Key>> = ranges.collect();
+    ranges.sort_by_key(|r| r.start);
+    let mut covered_to = key_range.start;
+    for r in ranges {
+        if r.start > covered_to {
+            break;
+        }
+        covered_to = std::cmp::max(covered_to, r.end);
+        if covered_to >= key_range.end {
+            return true;
+        }
+    }
+    covered_to >= key_range.end
+}
+
+/// Average number of `layers` that a read of the latest version of a key visits: the delta
+/// layers above the newest image layer containing the key, and that image layer. The keys are
+/// `samples` keys evenly spread over `keyspace`.
+pub fn read_amplification<L: CompactionLayer<Key>>(
+    layers: &[L],
+    keyspace: &KeySpace,
+    samples: u64,
+) -> f64 {
+    let total_keys: u64 = keyspace.iter().map(|r| r.end - r.start).sum();
+    if total_keys == 0 || samples == 0 {
+        return 0.0;
+    }
+    let step = std::cmp::max(total_keys / samples, 1);
+
+    let mut sampled = 0;
+    let mut visited = 0;
+    let mut offset = 0;
+    for range in keyspace {
+        let mut key = range.start + offset;
+        while key < range.end {
+            let image_lsn = layers
+                .iter()
+                .filter(|l| !l.is_delta() && l.key_range().contains(&key))
+                .map(|l| l.lsn_range().start)
+                .max();
+            let deltas = layers
+                .iter()
+                .filter(|l| {
+                    l.is_delta()
+                        && l.key_range().contains(&key)
+                        && image_lsn.map_or(true, |lsn| l.lsn_range().end > lsn)
+                })
+                .count();
+            visited += deltas + usize::from(image_lsn.is_some());
+            sampled += 1;
+            key += step;
+        }
+        offset = key - range.end;
+    }
+    visited as f64 / sampled as f64
+}
+
 impl Default for MockTimeline {
     fn default() -> Self {
         Self::new()
@@ -597,6 +737,7 @@ impl interface::CompactionJobExecutor for MockTimeline {
         let layer = std::pin::pin!(layer);
         info!("deleting layer: {}", layer.short_id());
         self.num_deleted_layers += 1;
+        self.layers_deleted += 1;
         self.bytes_deleted += layer.file_size();
         layer.mark_deleted();
 
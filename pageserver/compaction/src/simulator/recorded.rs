@@ -0,0 +1,333 @@
+//! Layer maps recorded from real timelines, to replay them with other compaction, GC and
+//! eviction settings in the simulator.
+//!
+//! A recording is either the layer map of a timeline, as returned by the pageserver's
+//! `/v1/tenant/:tenant_shard_id/timeline/:timeline_id/layer` API, or the heatmap of a tenant,
+//! as uploaded to remote storage by its attached location. Only the names, sizes and access
+//! times of the layers are used.
+//!
+//! The simulator knows nothing about the pageserver's keys, so the recorded keys are mapped to
+//! simulator keys in order. The range between two consecutive boundaries of recorded layers
+//! gets as many simulator keys as there are pages in the image layers over it, so that the
+//! images created by the simulator have about the recorded sizes.
+//!
+//! The delta layers are turned back into WAL: each one into records of a fixed length, with
+//! keys uniformly distributed over its key range, at LSNs evenly spread over its LSN range.
+//! Replaying them in LSN order into an empty timeline ingests as much WAL as the recorded
+//! timeline retains, spread over the keyspace in the same way.
+
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, ensure, Context};
+use pageserver_api::key::Key as RecordedKey;
+use pageserver_api::models::{HistoricLayerInfo, LayerMapInfo};
+use rand::Rng;
+use serde::Deserialize;
+use utils::id::TimelineId;
+use utils::lsn::Lsn;
+
+use super::{Key, KeySpace, MockDeltaLayer, MockImageLayer, MockLayer, MockTimeline};
+
+const PAGE_SIZE: u64 = 8192;
+
+/// A layer of a recorded layer map.
+pub struct RecordedLayer {
+    pub key_range: Range<RecordedKey>,
+    /// For image layers, an empty range at the layer's LSN, like in the simulator.
+    pub lsn_range: Range<Lsn>,
+    pub is_delta: bool,
+    pub file_size: u64,
+    /// When the layer was last accessed, if known.
+    pub access_time: Option<SystemTime>,
+}
+
+pub struct RecordedTimeline {
+    pub layers: Vec<RecordedLayer>,
+    key_map: KeyMap,
+}
+
+/// The heatmap of a tenant, as serialized by the pageserver. Only the fields that the simulator
+/// uses.
+#[derive(Deserialize)]
+struct HeatMapTenant {
+    timelines: Vec<HeatMapTimeline>,
+}
+
+#[derive(Deserialize)]
+struct HeatMapTimeline {
+    timeline_id: String,
+    layers: Vec<HeatMapLayer>,
+}
+
+#[derive(Deserialize)]
+struct HeatMapLayer {
+    name: String,
+    metadata: HeatMapLayerMetadata,
+    /// Seconds since the epoch.
+    access_time: u64,
+}
+
+#[derive(Deserialize)]
+struct HeatMapLayerMetadata {
+    file_size: u64,
+}
+
+impl RecordedTimeline {
+    pub fn load(path: &Path, timeline_id: Option<TimelineId>) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("read recorded layer map {}", path.display()))?;
+        Self::parse(&json, timeline_id)
+    }
+
+    /// Parse a layer map or a heatmap. `timeline_id` selects the timeline of a heatmap, and
+    /// can be omitted if the heatmap has only one.
+    pub fn parse(json: &str, timeline_id: Option<TimelineId>) -> anyhow::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).context("parse json")?;
+        let layers = if value.get("historic_layers").is_some() {
+            let layer_map: LayerMapInfo =
+                serde_json::from_value(value).context("parse layer map")?;
+            layer_map
+                .historic_layers
+                .iter()
+                .map(RecordedLayer::from_layer_info)
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else if value.get("timelines").is_some() {
+            let heatmap: HeatMapTenant = serde_json::from_value(value).context("parse heatmap")?;
+            let timeline = match timeline_id {
+                Some(timeline_id) => heatmap
+                    .timelines
+                    .into_iter()
+                    .find(|t| t.timeline_id == timeline_id.to_string())
+                    .with_context(|| format!("timeline {timeline_id} not found in heatmap"))?,
+                None => {
+                    ensure!(
+                        heatmap.timelines.len() == 1,
+                        "heatmap has {} timelines, select one with its id",
+                        heatmap.timelines.len()
+                    );
+                    heatmap.timelines.into_iter().next().unwrap()
+                }
+            };
+            timeline
+                .layers
+                .iter()
+                .map(RecordedLayer::from_heatmap_layer)
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            bail!("neither a layer map nor a heatmap");
+        };
+        ensure!(!layers.is_empty(), "no layers recorded");
+
+        let key_map = KeyMap::new(&layers);
+        Ok(RecordedTimeline { layers, key_map })
+    }
+
+    /// Total size of the layers.
+    pub fn size(&self) -> u64 {
+        self.layers.iter().map(|l| l.file_size).sum()
+    }
+
+    /// Total size of the layers that would be resident with an eviction threshold of
+    /// `threshold`: those accessed within `threshold` of the last access to any layer. Layers
+    /// without a recorded access count as evicted. `None` if no access times were recorded.
+    pub fn resident_size(&self, threshold: Duration) -> Option<u64> {
+        let last_access = self.layers.iter().filter_map(|l| l.access_time).max()?;
+        let resident = self
+            .layers
+            .iter()
+            .filter(|l| {
+                l.access_time.is_some_and(|access_time| {
+                    last_access
+                        .duration_since(access_time)
+                        .map_or(true, |age| age <= threshold)
+                })
+            })
+            .map(|l| l.file_size)
+            .sum();
+        Some(resident)
+    }
+
+    /// The keyspace of the timeline, in simulator keys.
+    pub fn keyspace(&self) -> KeySpace {
+        vec![0..self.key_map.end()]
+    }
+
+    /// The recorded layers, with their keys mapped to simulator keys.
+    pub fn mock_layers(&self) -> Vec<MockLayer> {
+        self.layers
+            .iter()
+            .map(|l| {
+                let key_range = self.key_map.map_range(&l.key_range);
+                if l.is_delta {
+                    MockLayer::Delta(Arc::new(MockDeltaLayer {
+                        key_range,
+                        lsn_range: l.lsn_range.clone(),
+                        file_size: l.file_size,
+                        deleted: Mutex::new(false),
+                        records: Vec::new(),
+                    }))
+                } else {
+                    MockLayer::Image(Arc::new(MockImageLayer {
+                        key_range,
+                        lsn_range: l.lsn_range.clone(),
+                        file_size: l.file_size,
+                        deleted: Mutex::new(false),
+                    }))
+                }
+            })
+            .collect()
+    }
+
+    /// Ingest the WAL of the recorded delta layers into `timeline`, as records of `record_len`
+    /// bytes, compacting it as needed.
+    pub async fn replay(&self, timeline: &mut MockTimeline, record_len: u64) -> anyhow::Result<()> {
+        ensure!(record_len > 0, "record length must be positive");
+        timeline.add_keyspace(&(0..self.key_map.end()));
+
+        for (_, key) in self.wal_records(record_len) {
+            timeline.ingest_record(key, record_len);
+            timeline.compact_if_needed().await?;
+        }
+        timeline.flush_l0();
+        timeline.compact_if_needed().await?;
+        Ok(())
+    }
+
+    /// The records of the recorded delta layers, in LSN order.
+    fn wal_records(&self, record_len: u64) -> Vec<(Lsn, Key)> {
+        let mut rng = rand::thread_rng();
+        let mut records = Vec::new();
+        for l in self.layers.iter().filter(|l| l.is_delta) {
+            let key_range = self.key_map.map_range(&l.key_range);
+            let num_records = std::cmp::max(l.file_size / record_len, 1);
+            let lsn_step = (l.lsn_range.end.0 - l.lsn_range.start.0) as f64 / num_records as f64;
+            for i in 0..num_records {
+                let lsn = l.lsn_range.start + (i as f64 * lsn_step) as u64;
+                records.push((lsn, rng.gen_range(key_range.clone())));
+            }
+        }
+        records.sort_by_key(|(lsn, _)| *lsn);
+        records
+    }
+}
+
+impl RecordedLayer {
+    fn from_layer_info(info: &HistoricLayerInfo) -> anyhow::Result<Self> {
+        let (layer_file_size, access_stats) = match info {
+            HistoricLayerInfo::Delta {
+                layer_file_size,
+                access_stats,
+                ..
+            } => (*layer_file_size, access_stats),
+            HistoricLayerInfo::Image {
+                layer_file_size,
+                access_stats,
+                ..
+            } => (*layer_file_size, access_stats),
+        };
+        let last_access = access_stats
+            .accesses_history
+            .recent()
+            .or(access_stats.first.as_ref());
+        let access_time = last_access
+            .map(|a| SystemTime::UNIX_EPOCH + Duration::from_millis(a.when_millis_since_epoch));
+        Self::new(info.layer_file_name(), layer_file_size, access_time)
+    }
+
+    fn from_heatmap_layer(layer: &HeatMapLayer) -> anyhow::Result<Self> {
+        let access_time = SystemTime::UNIX_EPOCH + Duration::from_secs(layer.access_time);
+        Self::new(&layer.name, layer.metadata.file_size, Some(access_time))
+    }
+
+    /// Parse the key and LSN ranges of a layer from its file name:
+    ///
+    /// ```text
+    ///    <key start>-<key end>__<LSN start>-<LSN end>[-<generation>]
+    ///    <key start>-<key end>__<LSN>[-<generation>]
+    /// ```
+    fn new(name: &str, file_size: u64, access_time: Option<SystemTime>) -> anyhow::Result<Self> {
+        let parse = || -> anyhow::Result<Self> {
+            let (keys, lsns) = name.split_once("__").context("no '__'")?;
+            let (key_start, key_end) = keys.split_once('-').context("no key range")?;
+            let key_range = RecordedKey::from_hex(key_start)?..RecordedKey::from_hex(key_end)?;
+            ensure!(key_range.start < key_range.end, "empty key range");
+
+            let mut lsns: Vec<&str> = lsns.split('-').collect();
+            // The generation suffix is 8 hex digits, LSNs are 16.
+            if lsns.len() > 1 && lsns.last().is_some_and(|s| s.len() == 8) {
+                lsns.pop();
+            }
+            let (lsn_range, is_delta) = match lsns[..] {
+                [lsn] => {
+                    let lsn = Lsn::from_hex(lsn)?;
+                    (lsn..lsn, false)
+                }
+                [start, end] => (Lsn::from_hex(start)?..Lsn::from_hex(end)?, true),
+                _ => bail!("invalid LSN range"),
+            };
+            ensure!(
+                !is_delta || lsn_range.start < lsn_range.end,
+                "empty LSN range"
+            );
+            Ok(RecordedLayer {
+                key_range,
+                lsn_range,
+                is_delta,
+                file_size,
+                access_time,
+            })
+        };
+        parse().with_context(|| format!("invalid layer name {name}"))
+    }
+}
+
+/// Maps the boundaries of the recorded layers' key ranges to simulator keys.
+struct KeyMap {
+    boundaries: Vec<RecordedKey>,
+    keys: Vec<Key>,
+}
+
+impl KeyMap {
+    fn new(layers: &[RecordedLayer]) -> Self {
+        let mut boundaries: Vec<RecordedKey> = layers
+            .iter()
+            .flat_map(|l| [l.key_range.start, l.key_range.end])
+            .collect();
+        boundaries.sort();
+        boundaries.dedup();
+
+        // Spread the pages of each image layer evenly over the ranges between the boundaries
+        // it spans.
+        let mut pages = vec![0.0; boundaries.len() - 1];
+        for l in layers.iter().filter(|l| !l.is_delta) {
+            let start = boundaries.binary_search(&l.key_range.start).unwrap();
+            let end = boundaries.binary_search(&l.key_range.end).unwrap();
+            let per_range = (l.file_size / PAGE_SIZE) as f64 / (end - start) as f64;
+            for p in &mut pages[start..end] {
+                *p += per_range;
+            }
+        }
+
+        let mut keys = Vec::with_capacity(boundaries.len());
+        let mut key = 0;
+        keys.push(key);
+        for p in pages {
+            // Every range gets at least one key, so that no layer has an empty key range.
+            key += std::cmp::max(p.round() as u64, 1);
+            keys.push(key);
+        }
+        KeyMap { boundaries, keys }
+    }
+
+    fn map_range(&self, key_range: &Range<RecordedKey>) -> Range<Key> {
+        let map = |key| self.keys[self.boundaries.binary_search(key).unwrap()];
+        map(&key_range.start)..map(&key_range.end)
+    }
+
+    fn end(&self) -> Key {
+        *self.keys.last().unwrap()
+    }
+}
@@ -1,5 +1,8 @@
+use std::time::Duration;
+
 use once_cell::sync::OnceCell;
 use pageserver_compaction::interface::CompactionLayer;
+use pageserver_compaction::simulator::recorded::RecordedTimeline;
 use pageserver_compaction::simulator::MockTimeline;
 use utils::logging;
 
@@ -72,3 +75,79 @@ async fn test_simple_updates() {
         println!("layer {}: {}", l.short_id(), l.file_size());
     }
 }
+
+#[tokio::test]
+async fn test_gc() {
+    setup_logging();
+    let mut executor = MockTimeline::new();
+    executor.target_file_size = 500_000; // 500 KB
+
+    for _ in 1..100 {
+        // A small keyspace, so that compaction creates image layers.
+        executor.ingest_uniform(100, 500, &(0..100)).unwrap();
+        executor.compact_if_needed().await.unwrap();
+    }
+    executor.flush_l0();
+    executor.compact().await.unwrap();
+
+    let size_before = executor.live_size();
+    let read_amp_before = executor.read_amplification(100);
+    executor.gc(0).await.unwrap();
+
+    // Everything below the newest images is garbage.
+    assert!(executor.live_size() < size_before);
+    assert!(executor.read_amplification(100) <= read_amp_before);
+    for l in executor.live_layers.iter() {
+        println!("layer {}: {}", l.short_id(), l.file_size());
+    }
+}
+
+#[tokio::test]
+async fn test_replay_heatmap() {
+    setup_logging();
+    let start = "000000000000000000000000000000000000";
+    let mid = "000000067F0000400500000A000000000000";
+    let end = "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF";
+    let heatmap = format!(
+        r#"{{
+  "generation": 1,
+  "timelines": [{{
+    "timeline_id": "de200bd42b49cc1814412c7e592dd6e9",
+    "layers": [
+      {{"name": "{start}-{mid}__0000000000000010", "metadata": {{"file_size": 8192000}},
+        "access_time": 1000}},
+      {{"name": "{mid}-{end}__0000000000000010-00000001", "metadata": {{"file_size": 819200}},
+        "access_time": 5000}},
+      {{"name": "{start}-{end}__0000000000000010-0000000000100000",
+        "metadata": {{"file_size": 2000000}}, "access_time": 5000}},
+      {{"name": "{start}-{end}__0000000000100000-0000000000200000-00000001",
+        "metadata": {{"file_size": 2000000}}, "access_time": 5000}}
+    ]
+  }}]
+}}"#
+    );
+    let recorded = RecordedTimeline::parse(&heatmap, None).unwrap();
+    assert_eq!(recorded.layers.len(), 4);
+    assert_eq!(recorded.size(), 8192000 + 819200 + 2 * 2000000);
+    // The image layers get as many keys as they have pages.
+    assert!(recorded.keyspace()[0].end >= 1000 + 100);
+    assert_eq!(
+        recorded.resident_size(Duration::from_secs(3600)),
+        Some(819200 + 2 * 2000000)
+    );
+    assert_eq!(
+        recorded.resident_size(Duration::from_secs(24 * 3600)),
+        Some(recorded.size())
+    );
+
+    let mut executor = MockTimeline::new();
+    executor.target_file_size = 500_000; // 500 KB
+    executor.gc_horizon = Some(1_000_000);
+    recorded.replay(&mut executor, 1000).await.unwrap();
+
+    println!("{}", executor.stats().unwrap());
+    assert!(executor.live_size() > 0);
+    assert!(executor.read_amplification(100) > 0.0);
+
+    assert!(RecordedTimeline::parse(r#"{"something": "else"}"#, None).is_err());
+}
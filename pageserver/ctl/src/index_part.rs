@@ -16,7 +16,9 @@ pub(crate) async fn main(cmd: &IndexPartCmd) -> anyhow::Result<()> {
     match cmd {
         IndexPartCmd::Dump { path } => {
             let bytes = tokio::fs::read(path).await.context("read file")?;
-            let des: IndexPart = IndexPart::from_s3_bytes(&bytes).context("deserialize")?;
+            let des: IndexPart = IndexPart::from_encoded_bytes(&bytes, None)
+                .await
+                .context("deserialize")?;
             #[derive(serde::Serialize)]
             struct Output<'a> {
                 layer_metadata: &'a HashMap<LayerName, IndexLayerMetadata>,
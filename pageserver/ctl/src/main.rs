@@ -22,7 +22,7 @@ use pageserver::{
     context::{DownloadBehavior, RequestContext},
     page_cache,
     task_mgr::TaskKind,
-    tenant::{dump_layerfile_from_path, metadata::TimelineMetadata},
+    tenant::{dump_layerfile_from_path, metadata::TimelineMetadata, verify_layerfile_from_path},
     virtual_file,
 };
 use pageserver_api::shard::TenantShardId;
@@ -80,6 +80,10 @@ struct MetadataCmd {
 struct PrintLayerFileCmd {
     /// Pageserver data path
     path: Utf8PathBuf,
+    /// Check the structure of the index of the layer file instead of printing it, and fail if
+    /// any problem is found
+    #[arg(long)]
+    check: bool,
 }
 
 /// Roll back the time for the specified prefix using S3 history.
@@ -144,7 +148,7 @@ async fn main() -> anyhow::Result<()> {
                     "Failed to read input file as a pg control one: {e:#}\n\
                     Attempting to read it as layer file"
                 );
-                print_layerfile(&cmd.path).await?;
+                print_layerfile(&cmd.path, cmd.check).await?;
             }
         }
         Commands::TimeTravelRemotePrefix(cmd) => {
@@ -199,12 +203,24 @@ fn read_pg_control_file(control_file_path: &Utf8Path) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn print_layerfile(path: &Utf8Path) -> anyhow::Result<()> {
+async fn print_layerfile(path: &Utf8Path, check: bool) -> anyhow::Result<()> {
     // Basic initialization of things that don't change after startup
     virtual_file::init(10, virtual_file::api::IoEngineKind::StdFs);
     page_cache::init(100);
     let ctx = RequestContext::new(TaskKind::DebugTool, DownloadBehavior::Error);
-    dump_layerfile_from_path(path, true, &ctx).await
+    if !check {
+        return dump_layerfile_from_path(path, true, &ctx).await;
+    }
+
+    let findings = verify_layerfile_from_path(path, &ctx).await?;
+    for finding in &findings {
+        println!("{finding}");
+    }
+    if !findings.is_empty() {
+        anyhow::bail!("found {} problems in the index of {path}", findings.len());
+    }
+    println!("no problems found in the index of {path}");
+    Ok(())
 }
 
 fn handle_metadata(
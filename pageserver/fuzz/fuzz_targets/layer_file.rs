@@ -0,0 +1,111 @@
+#![no_main]
+
+//! Reads the input as both a delta and an image layer file, like compaction and the read path
+//! do: the summary in the first block, then the B-tree index, then the blobs it points to.
+
+use camino::Utf8PathBuf;
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+use pageserver::context::{DownloadBehavior, RequestContext};
+use pageserver::page_cache;
+use pageserver::repository::{Key, Value, KEY_SIZE};
+use pageserver::task_mgr::TaskKind;
+use pageserver::tenant::block_io::{BlockCursor, FileBlockReader};
+use pageserver::tenant::disk_btree::{DiskBtreeReader, VisitDirection};
+use pageserver::tenant::storage_layer::delta_layer::{self, BlobRef, DELTA_KEY_SIZE};
+use pageserver::tenant::storage_layer::image_layer;
+use pageserver::virtual_file::{self, VirtualFile};
+use utils::bin_ser::BeSer;
+
+static RUNTIME: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
+    virtual_file::init(10, virtual_file::api::IoEngineKind::StdFs);
+    page_cache::init(100);
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+});
+
+fuzz_target!(|data: &[u8]| {
+    let path = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+        .unwrap()
+        .join(format!("pageserver-fuzz-layer-{}", std::process::id()));
+    std::fs::write(&path, data).unwrap();
+
+    RUNTIME.block_on(async {
+        let ctx = RequestContext::new(TaskKind::DebugTool, DownloadBehavior::Error);
+        let file = VirtualFile::open(&path).await.unwrap();
+        // A new file id for each input, so that no blocks of the previous one are cached.
+        let block_reader = FileBlockReader::new(&file, page_cache::next_file_id());
+        let _ = read_delta_layer(&block_reader, &ctx).await;
+        let _ = read_image_layer(&block_reader, &ctx).await;
+    });
+});
+
+async fn read_delta_layer(
+    block_reader: &FileBlockReader<'_>,
+    ctx: &RequestContext,
+) -> anyhow::Result<()> {
+    let summary_blk = block_reader.read_blk(0, ctx).await?;
+    let summary = delta_layer::Summary::des_prefix(summary_blk.as_ref())?;
+    drop(summary_blk);
+
+    let tree_reader = DiskBtreeReader::<_, DELTA_KEY_SIZE>::new(
+        summary.index_start_blk,
+        summary.index_root_blk,
+        block_reader,
+    );
+    let mut values = Vec::new();
+    tree_reader
+        .visit(
+            &[0u8; DELTA_KEY_SIZE],
+            VisitDirection::Forwards,
+            |key, value| {
+                values.push((Key::from_slice(&key[..KEY_SIZE]), BlobRef(value)));
+                true
+            },
+            ctx,
+        )
+        .await?;
+
+    let cursor = BlockCursor::new_fileblockreader(block_reader);
+    for (_key, blob_ref) in values {
+        let blob = cursor.read_blob(blob_ref.pos(), ctx).await?;
+        Value::des(&blob)?;
+    }
+    Ok(())
+}
+
+async fn read_image_layer(
+    block_reader: &FileBlockReader<'_>,
+    ctx: &RequestContext,
+) -> anyhow::Result<()> {
+    let summary_blk = block_reader.read_blk(0, ctx).await?;
+    let summary = image_layer::Summary::des_prefix(summary_blk.as_ref())?;
+    drop(summary_blk);
+
+    let tree_reader = DiskBtreeReader::<_, KEY_SIZE>::new(
+        summary.index_start_blk,
+        summary.index_root_blk,
+        block_reader,
+    );
+    let mut offsets = Vec::new();
+    tree_reader
+        .visit(
+            &[0u8; KEY_SIZE],
+            VisitDirection::Forwards,
+            |key, offset| {
+                let _ = Key::from_slice(key);
+                offsets.push(offset);
+                true
+            },
+            ctx,
+        )
+        .await?;
+
+    let cursor = BlockCursor::new_fileblockreader(block_reader);
+    for offset in offsets {
+        cursor.read_blob(offset, ctx).await?;
+    }
+    Ok(())
+}
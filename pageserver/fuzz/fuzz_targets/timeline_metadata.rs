@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pageserver::tenant::metadata::TimelineMetadata;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(metadata) = TimelineMetadata::from_bytes(data) else {
+        return;
+    };
+    // Whatever is accepted can be written back, and reads back the same.
+    let bytes = metadata.to_bytes().expect("serialize parsed metadata");
+    let reparsed = TimelineMetadata::from_bytes(&bytes).expect("parse serialized metadata");
+    assert_eq!(reparsed.to_bytes().unwrap(), bytes);
+});
@@ -243,6 +243,7 @@ async fn client(
                 timeline_id: timeline.timeline_id,
                 lsn,
                 gzip,
+                incremental: None,
             })
             .await
             .with_context(|| format!("start basebackup for {timeline}"))
@@ -0,0 +1,78 @@
+use pageserver_api::models::{
+    PagestreamFeature, PagestreamGetPageAtLeastRequest, PagestreamProtocolVersion,
+};
+use pageserver_api::reltag::RelTag;
+use utils::id::TenantTimelineId;
+use utils::lsn::Lsn;
+
+/// A single GetPage request served at any LSN at or above `--min-lsn`. Prints the LSN at
+/// which the page was read, as JSON.
+#[derive(clap::Parser)]
+pub(crate) struct Args {
+    #[clap(long, default_value = "postgres://postgres@localhost:64000")]
+    page_service_connstring: String,
+    #[clap(long)]
+    rel: RelTag,
+    #[clap(long)]
+    blkno: u32,
+    #[clap(long)]
+    min_lsn: Lsn,
+    /// How long the pageserver may wait for `--min-lsn` to arrive. It caps it at its
+    /// `wait_lsn_timeout`.
+    #[clap(long, default_value = "60000")]
+    wait_budget_ms: u32,
+    target: TenantTimelineId,
+}
+
+#[derive(serde::Serialize)]
+struct Output {
+    lsn: Lsn,
+    page_size: usize,
+}
+
+pub(crate) fn main(args: Args) -> anyhow::Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    rt.block_on(main_impl(args))
+}
+
+async fn main_impl(args: Args) -> anyhow::Result<()> {
+    let client =
+        pageserver_client::page_service::Client::new(args.page_service_connstring.clone()).await?;
+    let negotiation = client
+        .negotiate_pagestream(
+            PagestreamProtocolVersion::V2,
+            PagestreamProtocolVersion::V2,
+            &[PagestreamFeature::LsnAtLeast],
+        )
+        .await?;
+    anyhow::ensure!(
+        negotiation
+            .features
+            .contains(&PagestreamFeature::LsnAtLeast),
+        "the pageserver doesn't serve GetPageAtLeast requests"
+    );
+
+    let mut client = client
+        .pagestream(args.target.tenant_id, args.target.timeline_id)
+        .await?;
+    let res = client
+        .getpage_at_least(PagestreamGetPageAtLeastRequest {
+            min_lsn: args.min_lsn,
+            wait_budget_ms: args.wait_budget_ms,
+            rel: args.rel,
+            blkno: args.blkno,
+        })
+        .await?;
+    client.shutdown().await;
+
+    let output = Output {
+        lsn: res.lsn,
+        page_size: res.page.len(),
+    };
+    println!("{}", serde_json::to_string(&output).unwrap());
+    Ok(())
+}
@@ -0,0 +1,661 @@
+use anyhow::Context;
+use pageserver_api::key::{is_rel_block_key, key_to_rel_block, Key};
+use pageserver_api::keyspace::KeySpaceAccum;
+use pageserver_api::models::{PagestreamGetPageRequest, TimelineCreateRequest, TimelineInfo};
+use pageserver_api::shard::TenantShardId;
+use pageserver_client::mgmt_api::{self, ForceAwaitLogicalSize};
+use tokio_util::sync::CancellationToken;
+use utils::id::{TenantTimelineId, TimelineId};
+use utils::lsn::Lsn;
+
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::util::key_distribution::{KeyDistribution, KeyDistributionArgs};
+use crate::util::tokio_thread_local_stats::AllThreadLocalStats;
+use crate::util::{request_stats, tokio_thread_local_stats};
+
+/// Synthetic GetPage workload, with configurable key distributions, reads behind the latest
+/// LSN and over branches of the targets, optionally alongside an ingest workload.
+///
+/// The ingest workload inserts rows through a compute of one of the targets, whose WAL the
+/// pageserver then ingests: the output has the latencies of both the GetPage requests and the
+/// ingest transactions, and the WAL ingested by the targets during the run.
+#[derive(clap::Parser)]
+pub(crate) struct Args {
+    #[clap(long, default_value = "http://localhost:9898")]
+    mgmt_api_endpoint: String,
+    #[clap(long, default_value = "postgres://postgres@localhost:64000")]
+    page_service_connstring: String,
+    #[clap(long)]
+    pageserver_jwt: Option<String>,
+    #[clap(long, default_value = "1")]
+    num_clients: NonZeroUsize,
+    #[clap(long)]
+    runtime: Option<humantime::Duration>,
+    /// Each client sends requests at the given rate.
+    ///
+    /// If a request takes too long and we should be issuing a new request already,
+    /// we skip that request and account it as `MISSED`.
+    #[clap(long)]
+    per_client_rate: Option<usize>,
+    #[clap(flatten)]
+    key_distribution: KeyDistributionArgs,
+    /// Read at the given number of bytes of WAL behind the last record LSN of each timeline,
+    /// instead of at the latest LSN. The reads don't go below the GC cutoff or the branch point.
+    #[clap(long)]
+    lsn_lag: Option<u64>,
+    /// Create the given number of branches of each target, at its last record LSN, and spread
+    /// the requests over the targets and their branches.
+    #[clap(long, default_value = "0")]
+    branch_fan_out: usize,
+    /// Keep the branches of `--branch-fan-out` at the end of the `--runtime` instead of deleting
+    /// them.
+    #[clap(long)]
+    keep_branches: bool,
+    /// Connection string of a compute of one of the targets, to run the ingest workload against.
+    #[clap(long)]
+    ingest_connstring: Option<String>,
+    #[clap(long, default_value = "1")]
+    ingest_clients: NonZeroUsize,
+    /// Each ingest client commits transactions at the given rate.
+    #[clap(long)]
+    ingest_rate: Option<usize>,
+    #[clap(long, default_value = "100")]
+    ingest_rows_per_txn: i32,
+    /// The size of the inserted rows, rounded up to a multiple of 16 bytes.
+    #[clap(long, default_value = "128")]
+    ingest_row_size: i32,
+    #[clap(long)]
+    limit_to_first_n_targets: Option<usize>,
+    targets: Option<Vec<TenantTimelineId>>,
+}
+
+const INGEST_CREATE_TABLE: &str = "create table if not exists pagebench_ingest \
+    (id bigserial primary key, payload bytea not null)";
+/// `md5` has 16 bytes, as 32 hex digits.
+const INGEST_INSERT: &str = "insert into pagebench_ingest (payload) \
+    select decode(repeat(md5(random()::text), $2), 'hex') from generate_series(1, $1)";
+
+#[derive(Debug, Default)]
+struct LiveStats {
+    completed_requests: AtomicU64,
+    missed: AtomicU64,
+    completed_ingest_txns: AtomicU64,
+    ingest_missed: AtomicU64,
+}
+
+/// The rel block keys of a timeline, by their ordinals.
+struct TimelineKeys {
+    /// The start of each range, and the number of keys up to its end.
+    ranges: Vec<(i128, u64)>,
+}
+
+impl TimelineKeys {
+    fn len(&self) -> u64 {
+        self.ranges.last().map_or(0, |(_, end)| *end)
+    }
+
+    fn key(&self, ordinal: u64) -> Key {
+        let idx = self.ranges.partition_point(|(_, end)| *end <= ordinal);
+        let before = idx.checked_sub(1).map_or(0, |prev| self.ranges[prev].1);
+        Key::from_i128(self.ranges[idx].0 + i128::from(ordinal - before))
+    }
+}
+
+/// A timeline the requests are sent to.
+struct Target {
+    timeline: TenantTimelineId,
+    keys: TimelineKeys,
+    distribution: KeyDistribution,
+    /// The last record LSN of the timeline, refreshed every second.
+    last_record_lsn: AtomicU64,
+    /// The lowest LSN that can be read, refreshed with `last_record_lsn`.
+    min_lsn: AtomicU64,
+}
+
+impl Target {
+    fn refresh(&self, info: &TimelineInfo) {
+        let min_lsn = info
+            .latest_gc_cutoff_lsn
+            .max(info.ancestor_lsn.unwrap_or(Lsn(0)));
+        self.last_record_lsn
+            .store(info.last_record_lsn.0, Ordering::Relaxed);
+        self.min_lsn.store(min_lsn.0, Ordering::Relaxed);
+    }
+
+    fn request(&self, lsn_lag: Option<u64>) -> PagestreamGetPageRequest {
+        let key = {
+            let mut rng = rand::thread_rng();
+            self.keys.key(self.distribution.sample(&mut rng))
+        };
+        let (rel, blkno) = key_to_rel_block(key).expect("we filter non-rel-block keys out");
+        let last_record_lsn = Lsn(self.last_record_lsn.load(Ordering::Relaxed));
+        let (request_lsn, not_modified_since) = match lsn_lag {
+            None => (Lsn::MAX, last_record_lsn),
+            Some(lag) => {
+                let min_lsn = Lsn(self.min_lsn.load(Ordering::Relaxed));
+                let lsn = Lsn(last_record_lsn.0.saturating_sub(lag)).max(min_lsn);
+                (lsn, lsn)
+            }
+        };
+        PagestreamGetPageRequest {
+            request_lsn,
+            not_modified_since,
+            rel,
+            blkno,
+        }
+    }
+}
+
+/// Paces a client to a rate of requests per second, if any.
+struct Pacer {
+    period: Option<Duration>,
+    start: Instant,
+    ticks: u64,
+}
+
+impl Pacer {
+    fn new(rate: Option<usize>) -> Self {
+        Pacer {
+            period: rate.map(|rate| Duration::from_secs_f64(1.0 / rate as f64)),
+            start: Instant::now(),
+            ticks: 0,
+        }
+    }
+
+    /// The number of requests the client was too late for since the last one.
+    fn missed(&mut self) -> u64 {
+        let Some(period) = self.period else {
+            return 0;
+        };
+        let periods_passed =
+            u64::try_from(self.start.elapsed().as_micros() / period.as_micros()).unwrap();
+        let missed = periods_passed.saturating_sub(self.ticks);
+        self.ticks = self.ticks.max(periods_passed);
+        missed
+    }
+
+    /// Wait for the time of the next request.
+    async fn wait(&mut self) {
+        self.ticks += 1;
+        if let Some(period) = self.period {
+            let next_at = self.start
+                + Duration::from_micros(self.ticks * u64::try_from(period.as_micros()).unwrap());
+            tokio::time::sleep_until(next_at.into()).await;
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Output {
+    getpage: request_stats::Output,
+    ingest: Option<request_stats::Output>,
+    /// The WAL ingested by the targets, not their branches, during the run.
+    wal_ingested_bytes: u64,
+}
+
+tokio_thread_local_stats::declare!(STATS: request_stats::Stats);
+
+pub(crate) fn main(args: Args) -> anyhow::Result<()> {
+    tokio_thread_local_stats::main!(STATS, move |thread_local_stats| {
+        main_impl(args, thread_local_stats)
+    })
+}
+
+async fn main_impl(
+    args: Args,
+    all_thread_local_stats: AllThreadLocalStats<request_stats::Stats>,
+) -> anyhow::Result<()> {
+    let args: &'static Args = Box::leak(Box::new(args));
+
+    let mgmt_api_client = Arc::new(mgmt_api::Client::new(
+        args.mgmt_api_endpoint.clone(),
+        args.pageserver_jwt.as_deref(),
+    ));
+
+    // discover targets
+    let timelines: Vec<TenantTimelineId> = crate::util::cli::targets::discover(
+        &mgmt_api_client,
+        crate::util::cli::targets::Spec {
+            limit_to_first_n_targets: args.limit_to_first_n_targets,
+            targets: args.targets.clone(),
+        },
+    )
+    .await?;
+
+    // The branches are deleted however the run ends, also if creating them fails midway.
+    let mut branches = Vec::new();
+    let res = async {
+        create_branches(args, &mgmt_api_client, &timelines, &mut branches).await?;
+        run(
+            args,
+            &mgmt_api_client,
+            &timelines,
+            &branches,
+            all_thread_local_stats,
+        )
+        .await
+    }
+    .await;
+    let deleted = if args.keep_branches {
+        Ok(())
+    } else {
+        delete_branches(&mgmt_api_client, &branches).await
+    };
+    res.and(deleted)
+}
+
+/// Create `args.branch_fan_out` branches of each of the `timelines`, adding them to `branches`
+/// as they are created.
+async fn create_branches(
+    args: &Args,
+    mgmt_api_client: &mgmt_api::Client,
+    timelines: &[TenantTimelineId],
+    branches: &mut Vec<TenantTimelineId>,
+) -> anyhow::Result<()> {
+    for timeline in timelines {
+        for _ in 0..args.branch_fan_out {
+            let branch = TenantTimelineId {
+                tenant_id: timeline.tenant_id,
+                timeline_id: TimelineId::generate(),
+            };
+            mgmt_api_client
+                .timeline_create(
+                    TenantShardId::unsharded(timeline.tenant_id),
+                    &TimelineCreateRequest {
+                        new_timeline_id: branch.timeline_id,
+                        ancestor_timeline_id: Some(timeline.timeline_id),
+                        existing_initdb_timeline_id: None,
+                        ancestor_start_lsn: None,
+                        pg_version: None,
+                        snapshot_id: None,
+                    },
+                )
+                .await
+                .with_context(|| format!("create a branch of {timeline}"))?;
+            branches.push(branch);
+        }
+    }
+    if !branches.is_empty() {
+        info!("created {} branches", branches.len());
+    }
+    Ok(())
+}
+
+/// Delete the `branches`, all of those that can be deleted if some can't.
+async fn delete_branches(
+    mgmt_api_client: &mgmt_api::Client,
+    branches: &[TenantTimelineId],
+) -> anyhow::Result<()> {
+    let mut failed = 0;
+    for branch in branches {
+        let res = mgmt_api_client
+            .timeline_delete(
+                TenantShardId::unsharded(branch.tenant_id),
+                branch.timeline_id,
+            )
+            .await;
+        if let Err(e) = res {
+            warn!("failed to delete branch {branch}: {e}");
+            failed += 1;
+        }
+    }
+    if !branches.is_empty() {
+        info!("deleted {} branches", branches.len() - failed);
+    }
+    anyhow::ensure!(failed == 0, "failed to delete {failed} branches");
+    Ok(())
+}
+
+async fn run(
+    args: &'static Args,
+    mgmt_api_client: &Arc<mgmt_api::Client>,
+    timelines: &[TenantTimelineId],
+    branches: &[TenantTimelineId],
+    all_thread_local_stats: AllThreadLocalStats<request_stats::Stats>,
+) -> anyhow::Result<()> {
+    let mut js = JoinSet::new();
+    for timeline in timelines.iter().chain(branches) {
+        let mgmt_api_client = Arc::clone(mgmt_api_client);
+        let timeline = *timeline;
+        js.spawn(async move {
+            let target = load_target(&mgmt_api_client, timeline, &args.key_distribution)
+                .await
+                .with_context(|| format!("load target {timeline}"))?;
+            anyhow::Ok(Arc::new(target))
+        });
+    }
+    let mut targets = Vec::new();
+    while let Some(res) = js.join_next().await {
+        targets.push(res.unwrap()?);
+    }
+    targets.sort_by_key(|target| target.timeline);
+    let targets = Arc::new(targets);
+    let start_lsns = last_record_lsns(&targets, timelines);
+
+    let live_stats = Arc::new(LiveStats::default());
+    let ingest_stats = Arc::new(Mutex::new(request_stats::Stats::new()));
+    let cancel = CancellationToken::new();
+
+    let num_ingest_clients = if args.ingest_connstring.is_some() {
+        args.ingest_clients.get()
+    } else {
+        0
+    };
+    let num_live_stats_dump = 1;
+    let num_work_sender_tasks = args.num_clients.get() * targets.len() + num_ingest_clients;
+    let num_main_impl = 1;
+
+    let start_work_barrier = Arc::new(tokio::sync::Barrier::new(
+        num_live_stats_dump + num_work_sender_tasks + num_main_impl,
+    ));
+
+    // Aborted when dropped, on whichever path the run ends.
+    let mut live_stats_dump = JoinSet::new();
+    live_stats_dump.spawn({
+        let stats = Arc::clone(&live_stats);
+        let targets = Arc::clone(&targets);
+        let mgmt_api_client = Arc::clone(mgmt_api_client);
+        let timelines = timelines.to_vec();
+        let start_work_barrier = Arc::clone(&start_work_barrier);
+        async move {
+            start_work_barrier.wait().await;
+            let mut last_lsns = last_record_lsns(&targets, &timelines);
+            loop {
+                let start = std::time::Instant::now();
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                for target in targets.iter() {
+                    match timeline_info(&mgmt_api_client, target.timeline).await {
+                        Ok(info) => target.refresh(&info),
+                        Err(e) => warn!("failed to refresh the LSNs of {}: {e:#}", target.timeline),
+                    }
+                }
+                let lsns = last_record_lsns(&targets, &timelines);
+                let wal_ingested = wal_between(&last_lsns, &lsns);
+                last_lsns = lsns;
+
+                let completed_requests = stats.completed_requests.swap(0, Ordering::Relaxed);
+                let missed = stats.missed.swap(0, Ordering::Relaxed);
+                let completed_ingest_txns = stats.completed_ingest_txns.swap(0, Ordering::Relaxed);
+                let ingest_missed = stats.ingest_missed.swap(0, Ordering::Relaxed);
+                let elapsed = start.elapsed().as_secs_f64();
+                info!(
+                    "RPS: {:.0}   MISSED: {:.0}   INGEST TPS: {:.0}   INGEST MISSED: {:.0}   WAL INGESTED: {:.1} MiB/s",
+                    completed_requests as f64 / elapsed,
+                    missed as f64 / elapsed,
+                    completed_ingest_txns as f64 / elapsed,
+                    ingest_missed as f64 / elapsed,
+                    wal_ingested as f64 / elapsed / (1024.0 * 1024.0),
+                );
+            }
+        }
+    });
+
+    info!("spawning workers");
+    let mut workers = JoinSet::new();
+    for target in targets.iter() {
+        for _ in 0..args.num_clients.get() {
+            workers.spawn(getpage_worker(
+                args,
+                Arc::clone(target),
+                Arc::clone(&live_stats),
+                Arc::clone(&start_work_barrier),
+                cancel.clone(),
+            ));
+        }
+    }
+    if let Some(ingest_connstring) = &args.ingest_connstring {
+        let (client, connection) =
+            tokio_postgres::connect(ingest_connstring, tokio_postgres::NoTls)
+                .await
+                .context("connect to the compute for ingest")?;
+        tokio::spawn(connection);
+        client
+            .batch_execute(INGEST_CREATE_TABLE)
+            .await
+            .context("create the ingest table")?;
+        drop(client);
+
+        for _ in 0..num_ingest_clients {
+            workers.spawn(ingest_worker(
+                args,
+                ingest_connstring,
+                Arc::clone(&ingest_stats),
+                Arc::clone(&live_stats),
+                Arc::clone(&start_work_barrier),
+                cancel.clone(),
+            ));
+        }
+    }
+    let workers = async move {
+        while let Some(res) = workers.join_next().await {
+            res.unwrap()?;
+        }
+        anyhow::Ok(())
+    };
+
+    info!("waiting for everything to become ready");
+    start_work_barrier.wait().await;
+    info!("work started");
+    if let Some(runtime) = args.runtime {
+        tokio::time::sleep(runtime.into()).await;
+        info!("runtime over, signalling cancellation");
+        cancel.cancel();
+        workers.await?;
+        info!("work sender exited");
+    } else {
+        workers.await?;
+        unreachable!("work sender never terminates");
+    }
+
+    live_stats_dump.abort_all();
+    for target in targets.iter() {
+        let info = timeline_info(mgmt_api_client, target.timeline).await?;
+        target.refresh(&info);
+    }
+    let wal_ingested_bytes = wal_between(&start_lsns, &last_record_lsns(&targets, timelines));
+
+    let output = Output {
+        getpage: {
+            let mut agg_stats = request_stats::Stats::new();
+            for stats in all_thread_local_stats.lock().unwrap().iter() {
+                let stats = stats.lock().unwrap();
+                agg_stats.add(&stats);
+            }
+            agg_stats.output()
+        },
+        ingest: args
+            .ingest_connstring
+            .as_ref()
+            .map(|_| ingest_stats.lock().unwrap().output()),
+        wal_ingested_bytes,
+    };
+
+    let output = serde_json::to_string_pretty(&output).unwrap();
+    println!("{output}");
+
+    anyhow::Ok(())
+}
+
+async fn timeline_info(
+    mgmt_api_client: &mgmt_api::Client,
+    timeline: TenantTimelineId,
+) -> anyhow::Result<TimelineInfo> {
+    let info = mgmt_api_client
+        .timeline_info(
+            TenantShardId::unsharded(timeline.tenant_id),
+            timeline.timeline_id,
+            ForceAwaitLogicalSize::No,
+        )
+        .await?;
+    Ok(info)
+}
+
+async fn load_target(
+    mgmt_api_client: &mgmt_api::Client,
+    timeline: TenantTimelineId,
+    key_distribution: &KeyDistributionArgs,
+) -> anyhow::Result<Target> {
+    let partitioning = mgmt_api_client
+        .keyspace(
+            TenantShardId::unsharded(timeline.tenant_id),
+            timeline.timeline_id,
+        )
+        .await?;
+    let mut filtered = KeySpaceAccum::new();
+    for r in partitioning.keys.ranges.iter() {
+        let mut i = r.start;
+        while i != r.end {
+            if is_rel_block_key(&i) {
+                filtered.add_key(i);
+            }
+            i = i.next();
+        }
+    }
+    let mut ranges = Vec::new();
+    let mut len = 0;
+    for r in filtered.to_keyspace().ranges {
+        len += u64::try_from(r.end.to_i128() - r.start.to_i128()).unwrap();
+        ranges.push((r.start.to_i128(), len));
+    }
+    let keys = TimelineKeys { ranges };
+
+    let target = Target {
+        timeline,
+        distribution: key_distribution.build(keys.len())?,
+        keys,
+        last_record_lsn: AtomicU64::new(0),
+        min_lsn: AtomicU64::new(0),
+    };
+    target.refresh(&timeline_info(mgmt_api_client, timeline).await?);
+    Ok(target)
+}
+
+/// The last record LSNs of the `timelines` among the `targets`.
+fn last_record_lsns(targets: &[Arc<Target>], timelines: &[TenantTimelineId]) -> Vec<u64> {
+    targets
+        .iter()
+        .filter(|target| timelines.contains(&target.timeline))
+        .map(|target| target.last_record_lsn.load(Ordering::Relaxed))
+        .collect()
+}
+
+fn wal_between(start_lsns: &[u64], end_lsns: &[u64]) -> u64 {
+    start_lsns
+        .iter()
+        .zip(end_lsns)
+        .map(|(start, end)| end.saturating_sub(*start))
+        .sum()
+}
+
+async fn getpage_worker(
+    args: &'static Args,
+    target: Arc<Target>,
+    live_stats: Arc<LiveStats>,
+    start_work_barrier: Arc<tokio::sync::Barrier>,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    let client =
+        pageserver_client::page_service::Client::new(args.page_service_connstring.clone()).await?;
+    let mut client = client
+        .pagestream(target.timeline.tenant_id, target.timeline.timeline_id)
+        .await?;
+
+    start_work_barrier.wait().await;
+    let mut pacer = Pacer::new(args.per_client_rate);
+    while !cancel.is_cancelled() {
+        live_stats
+            .missed
+            .fetch_add(pacer.missed(), Ordering::Relaxed);
+
+        let req = target.request(args.lsn_lag);
+        let start = Instant::now();
+        client
+            .getpage(req)
+            .await
+            .with_context(|| format!("getpage on {}", target.timeline))?;
+        let latency = start.elapsed();
+        live_stats
+            .completed_requests
+            .fetch_add(1, Ordering::Relaxed);
+        STATS.with(|stats| stats.borrow().lock().unwrap().observe(latency))?;
+
+        pacer.wait().await;
+    }
+    Ok(())
+}
+
+async fn ingest_worker(
+    args: &'static Args,
+    connstring: &'static str,
+    ingest_stats: Arc<Mutex<request_stats::Stats>>,
+    live_stats: Arc<LiveStats>,
+    start_work_barrier: Arc<tokio::sync::Barrier>,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    let (client, connection) = tokio_postgres::connect(connstring, tokio_postgres::NoTls)
+        .await
+        .context("connect to the compute for ingest")?;
+    tokio::spawn(connection);
+    let insert = client.prepare(INGEST_INSERT).await?;
+    let md5_repeats = (args.ingest_row_size + 15) / 16;
+
+    start_work_barrier.wait().await;
+    let mut pacer = Pacer::new(args.ingest_rate);
+    while !cancel.is_cancelled() {
+        live_stats
+            .ingest_missed
+            .fetch_add(pacer.missed(), Ordering::Relaxed);
+
+        let start = Instant::now();
+        client
+            .execute(&insert, &[&args.ingest_rows_per_txn, &md5_repeats])
+            .await
+            .context("insert the ingest rows")?;
+        let latency = start.elapsed();
+        live_stats
+            .completed_ingest_txns
+            .fetch_add(1, Ordering::Relaxed);
+        ingest_stats.lock().unwrap().observe(latency)?;
+
+        pacer.wait().await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pageserver_api::key::rel_block_to_key;
+    use pageserver_api::reltag::RelTag;
+
+    #[test]
+    fn timeline_keys() {
+        let rel = |relnode| RelTag {
+            forknum: 0,
+            spcnode: 1663,
+            dbnode: 13010,
+            relnode,
+        };
+        let start = |relnode| rel_block_to_key(rel(relnode), 0).to_i128();
+        // Three blocks of a first relation, and two of a second one.
+        let keys = TimelineKeys {
+            ranges: vec![(start(16384), 3), (start(16385), 5)],
+        };
+        assert_eq!(keys.len(), 5);
+        let expected = [(16384, 0), (16384, 1), (16384, 2), (16385, 0), (16385, 1)];
+        for (ordinal, (relnode, blkno)) in expected.into_iter().enumerate() {
+            assert_eq!(
+                keys.key(ordinal as u64),
+                rel_block_to_key(rel(relnode), blkno),
+                "ordinal {ordinal}"
+            );
+        }
+    }
+}
@@ -3,6 +3,7 @@ use utils::logging;
 
 /// Re-usable pieces of code that aren't CLI-specific.
 mod util {
+    pub(crate) mod key_distribution;
     pub(crate) mod request_stats;
     #[macro_use]
     pub(crate) mod tokio_thread_local_stats;
@@ -15,8 +16,10 @@ mod util {
 /// The pagebench CLI sub-commands, dispatched in [`main`] below.
 mod cmd {
     pub(super) mod basebackup;
+    pub(super) mod getpage_at_least;
     pub(super) mod getpage_latest_lsn;
     pub(super) mod ondemand_download_churn;
+    pub(super) mod synthetic_workload;
     pub(super) mod trigger_initial_size_calculation;
 }
 
@@ -25,8 +28,10 @@ mod cmd {
 enum Args {
     Basebackup(cmd::basebackup::Args),
     GetPageLatestLsn(cmd::getpage_latest_lsn::Args),
+    GetPageAtLeast(cmd::getpage_at_least::Args),
     TriggerInitialSizeCalculation(cmd::trigger_initial_size_calculation::Args),
     OndemandDownloadChurn(cmd::ondemand_download_churn::Args),
+    SyntheticWorkload(cmd::synthetic_workload::Args),
 }
 
 fn main() {
@@ -42,10 +47,12 @@ fn main() {
     match args {
         Args::Basebackup(args) => cmd::basebackup::main(args),
         Args::GetPageLatestLsn(args) => cmd::getpage_latest_lsn::main(args),
+        Args::GetPageAtLeast(args) => cmd::getpage_at_least::main(args),
         Args::TriggerInitialSizeCalculation(args) => {
             cmd::trigger_initial_size_calculation::main(args)
         }
         Args::OndemandDownloadChurn(args) => cmd::ondemand_download_churn::main(args),
+        Args::SyntheticWorkload(args) => cmd::synthetic_workload::main(args),
     }
     .unwrap()
 }
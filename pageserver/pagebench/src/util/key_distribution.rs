@@ -0,0 +1,254 @@
+//! Distributions of the keys requested by a benchmark, over the ordinals `0..n` of the keys of
+//! a timeline.
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub(crate) enum KeyDistributionKind {
+    /// Every key is equally likely.
+    Uniform,
+    /// The probability of the k-th key is proportional to `1 / k^zipf_exponent`: the first keys
+    /// of the keyspace are the hottest.
+    Zipfian,
+    /// The keys of the first `hot_fraction` of the keyspace are requested with
+    /// `hot_probability`, uniformly, and the others uniformly too.
+    Hotspot,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct KeyDistributionArgs {
+    /// The distribution of the requested keys over the keyspace of each timeline.
+    #[clap(long, value_enum, default_value_t = KeyDistributionKind::Uniform)]
+    key_distribution: KeyDistributionKind,
+    /// The skew of the zipfian distribution, the higher the more skewed.
+    #[clap(long, default_value_t = 0.99)]
+    zipf_exponent: f64,
+    /// The fraction of the keyspace that is hot, for the hotspot distribution.
+    #[clap(long, default_value_t = 0.1)]
+    hot_fraction: f64,
+    /// The probability of requesting a hot key, for the hotspot distribution.
+    #[clap(long, default_value_t = 0.9)]
+    hot_probability: f64,
+}
+
+impl KeyDistributionArgs {
+    /// The distribution over `n` keys.
+    pub(crate) fn build(&self, n: u64) -> anyhow::Result<KeyDistribution> {
+        anyhow::ensure!(n > 0, "the keyspace is empty");
+        match self.key_distribution {
+            KeyDistributionKind::Uniform => Ok(KeyDistribution::Uniform { n }),
+            KeyDistributionKind::Zipfian => {
+                anyhow::ensure!(self.zipf_exponent > 0.0, "zipf_exponent must be positive");
+                Ok(KeyDistribution::Zipfian(Zipf::new(n, self.zipf_exponent)))
+            }
+            KeyDistributionKind::Hotspot => {
+                anyhow::ensure!(
+                    (0.0..=1.0).contains(&self.hot_fraction)
+                        && (0.0..=1.0).contains(&self.hot_probability),
+                    "hot_fraction and hot_probability must be between 0 and 1"
+                );
+                let hot = ((n as f64 * self.hot_fraction) as u64).clamp(1, n);
+                Ok(KeyDistribution::Hotspot {
+                    n,
+                    hot,
+                    hot_probability: self.hot_probability,
+                })
+            }
+        }
+    }
+}
+
+pub(crate) enum KeyDistribution {
+    Uniform {
+        n: u64,
+    },
+    Zipfian(Zipf),
+    Hotspot {
+        n: u64,
+        hot: u64,
+        hot_probability: f64,
+    },
+}
+
+impl KeyDistribution {
+    /// The ordinal of a key, in `0..n`.
+    pub(crate) fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        match self {
+            KeyDistribution::Uniform { n } => rng.gen_range(0..*n),
+            KeyDistribution::Zipfian(zipf) => zipf.sample(rng) - 1,
+            KeyDistribution::Hotspot {
+                n,
+                hot,
+                hot_probability,
+            } => {
+                if *hot == *n || rng.gen_bool(*hot_probability) {
+                    rng.gen_range(0..*hot)
+                } else {
+                    rng.gen_range(*hot..*n)
+                }
+            }
+        }
+    }
+}
+
+/// Zipf distribution over `1..=n`, sampled in constant time by rejection-inversion, see
+/// "Rejection-inversion to generate variates from monotone discrete distributions",
+/// W. Hörmann and G. Derflinger, 1996.
+pub(crate) struct Zipf {
+    n: f64,
+    exponent: f64,
+    h_integral_x1: f64,
+    h_integral_n: f64,
+    s: f64,
+}
+
+impl Zipf {
+    fn new(n: u64, exponent: f64) -> Self {
+        let mut zipf = Zipf {
+            n: n as f64,
+            exponent,
+            h_integral_x1: 0.0,
+            h_integral_n: 0.0,
+            s: 0.0,
+        };
+        zipf.h_integral_x1 = zipf.h_integral(1.5) - 1.0;
+        zipf.h_integral_n = zipf.h_integral(zipf.n + 0.5);
+        zipf.s = 2.0 - zipf.h_integral_inverse(zipf.h_integral(2.5) - zipf.h(2.0));
+        zipf
+    }
+
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        loop {
+            let u = self.h_integral_n + rng.gen::<f64>() * (self.h_integral_x1 - self.h_integral_n);
+            let x = self.h_integral_inverse(u);
+            let k = (x + 0.5).floor().clamp(1.0, self.n);
+            if k - x <= self.s || u >= self.h_integral(k + 0.5) - self.h(k) {
+                return k as u64;
+            }
+        }
+    }
+
+    fn h(&self, x: f64) -> f64 {
+        (-self.exponent * x.ln()).exp()
+    }
+
+    fn h_integral(&self, x: f64) -> f64 {
+        let log_x = x.ln();
+        helper2((1.0 - self.exponent) * log_x) * log_x
+    }
+
+    fn h_integral_inverse(&self, x: f64) -> f64 {
+        let t = (x * (1.0 - self.exponent)).max(-1.0);
+        (helper1(t) * x).exp()
+    }
+}
+
+/// `ln(1 + x) / x`, also close to 0.
+fn helper1(x: f64) -> f64 {
+    if x.abs() > 1e-8 {
+        x.ln_1p() / x
+    } else {
+        1.0 - x * (0.5 - x * (1.0 / 3.0 - 0.25 * x))
+    }
+}
+
+/// `(e^x - 1) / x`, also close to 0.
+fn helper2(x: f64) -> f64 {
+    if x.abs() > 1e-8 {
+        x.exp_m1() / x
+    } else {
+        1.0 + x * 0.5 * (1.0 + x / 3.0 * (1.0 + 0.25 * x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const SAMPLES: usize = 100_000;
+
+    /// How many of the samples of `distribution` fall on each of its `n` keys.
+    fn histogram(distribution: &KeyDistribution, n: u64) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut counts = vec![0; n as usize];
+        for _ in 0..SAMPLES {
+            counts[distribution.sample(&mut rng) as usize] += 1;
+        }
+        counts
+    }
+
+    fn args(key_distribution: KeyDistributionKind) -> KeyDistributionArgs {
+        KeyDistributionArgs {
+            key_distribution,
+            zipf_exponent: 0.99,
+            hot_fraction: 0.1,
+            hot_probability: 0.9,
+        }
+    }
+
+    #[test]
+    fn uniform() {
+        let n = 10;
+        let distribution = args(KeyDistributionKind::Uniform).build(n).unwrap();
+        for count in histogram(&distribution, n) {
+            let expected = SAMPLES / n as usize;
+            assert!(count.abs_diff(expected) < expected / 10, "{count}");
+        }
+    }
+
+    #[test]
+    fn zipfian() {
+        let n = 100;
+        let distribution = args(KeyDistributionKind::Zipfian).build(n).unwrap();
+        let counts = histogram(&distribution, n);
+        // The k-th key is requested about `k^0.99` times less than the first one.
+        let k1 = counts[0] as f64;
+        for k in [2, 4, 10] {
+            let expected = k1 / (k as f64).powf(0.99);
+            let actual = counts[k - 1] as f64;
+            assert!(
+                (actual - expected).abs() < expected * 0.1,
+                "key {k}: {actual}"
+            );
+        }
+        // The hottest 10% of the keys get more than half of the requests.
+        assert!(counts[..10].iter().sum::<usize>() > SAMPLES / 2);
+
+        // A single key is always the one requested.
+        let distribution = args(KeyDistributionKind::Zipfian).build(1).unwrap();
+        assert_eq!(histogram(&distribution, 1), [SAMPLES]);
+    }
+
+    #[test]
+    fn hotspot() {
+        let n = 1000;
+        let distribution = args(KeyDistributionKind::Hotspot).build(n).unwrap();
+        let counts = histogram(&distribution, n);
+        let hot = counts[..100].iter().sum::<usize>() as f64 / SAMPLES as f64;
+        assert!((hot - 0.9).abs() < 0.01, "{hot}");
+        // Both sets are sampled uniformly.
+        assert!(counts[..100].iter().all(|count| *count > 0));
+        assert!(counts[100..].iter().all(|count| *count > 0));
+
+        // The hot set has at least one key, and may be the whole keyspace.
+        let mut all_hot = args(KeyDistributionKind::Hotspot);
+        all_hot.hot_fraction = 1.0;
+        let distribution = all_hot.build(3).unwrap();
+        assert!(histogram(&distribution, 3).iter().all(|count| *count > 0));
+        let distribution = args(KeyDistributionKind::Hotspot).build(1).unwrap();
+        assert_eq!(histogram(&distribution, 1), [SAMPLES]);
+    }
+
+    #[test]
+    fn invalid_args() {
+        assert!(args(KeyDistributionKind::Uniform).build(0).is_err());
+        let mut zipfian = args(KeyDistributionKind::Zipfian);
+        zipfian.zipf_exponent = 0.0;
+        assert!(zipfian.build(10).is_err());
+        let mut hotspot = args(KeyDistributionKind::Hotspot);
+        hotspot.hot_probability = 1.5;
+        assert!(hotspot.build(10).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use utils::auth::{AuthError, Claims, Scope};
-use utils::id::TenantId;
+use utils::id::{TenantId, TimelineId};
+use utils::lsn::Lsn;
 
 pub fn check_permission(claims: &Claims, tenant_id: Option<TenantId>) -> Result<(), AuthError> {
     match (&claims.scope, tenant_id) {
@@ -23,3 +24,32 @@ pub fn check_permission(claims: &Claims, tenant_id: Option<TenantId>) -> Result<
         )),
     }
 }
+
+/// Check that `claims`, which passed [`check_permission`] for the tenant, allow access to
+/// `timeline_id` of that tenant.
+pub fn check_timeline_permission(
+    claims: &Claims,
+    timeline_id: TimelineId,
+) -> Result<(), AuthError> {
+    match (&claims.scope, claims.timeline_id) {
+        (Scope::Tenant, Some(allowed)) if allowed != timeline_id => {
+            Err(AuthError("Timeline id mismatch. Permission denied".into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check that `claims`, which passed [`check_permission`] for the tenant, allow reading at
+/// `lsn`, `None` meaning the latest LSN.
+pub fn check_lsn_permission(claims: &Claims, lsn: Option<Lsn>) -> Result<(), AuthError> {
+    match (&claims.scope, &claims.lsn_access) {
+        (Scope::Tenant, Some(lsn_access)) if !lsn_access.allows(lsn) => Err(AuthError(
+            match lsn {
+                Some(lsn) => format!("Reading at LSN {lsn} is not allowed. Permission denied"),
+                None => "Reading at the latest LSN is not allowed. Permission denied".to_string(),
+            }
+            .into(),
+        )),
+        _ => Ok(()),
+    }
+}
@@ -10,13 +10,27 @@
 //! This module is responsible for creation of such tarball
 //! from data stored in object storage.
 //!
+//! A compute that restarts with its previous data directory still on disk can
+//! ask for an incremental basebackup instead, by presenting a
+//! [`BasebackupManifest`] of the files it has. The tarball then leaves out the
+//! files that have the same checksum and size in the manifest, and ends with a
+//! [`MANIFEST_FILE_NAME`] file that lists every file of the basebackup, so that
+//! the compute can remove the ones that are no longer part of it. The SLRU
+//! segments and relation files that no WAL has touched since the LSN of their
+//! manifest entry are left out without being reconstructed at all.
+//!
 use anyhow::{anyhow, Context};
 use bytes::{BufMut, Bytes, BytesMut};
 use fail::fail_point;
-use pageserver_api::key::{key_to_slru_block, Key};
+use pageserver_api::key::{key_to_slru_block, rel_key_range, slru_segment_key_range, Key};
+use pageserver_api::keyspace::KeySpace;
 use postgres_ffi::pg_constants;
+use std::collections::BTreeMap;
 use std::fmt::Write as FmtWrite;
+use std::ops::Range;
+use std::str::FromStr;
 use std::time::SystemTime;
+use strum::IntoEnumIterator;
 use tokio::io;
 use tokio::io::AsyncWrite;
 use tracing::*;
@@ -24,6 +38,7 @@ use tracing::*;
 use tokio_tar::{Builder, EntryType, Header};
 
 use crate::context::RequestContext;
+use crate::metrics::{BASEBACKUP_INCREMENTAL_SKIPPED_BYTES, BASEBACKUP_INCREMENTAL_SKIPPED_FILES};
 use crate::pgdatadir_mapping::Version;
 use crate::tenant::Timeline;
 use pageserver_api::reltag::{RelTag, SlruKind};
@@ -46,9 +61,98 @@ pub enum BasebackupError {
     Client(#[source] io::Error),
 }
 
+/// Name of the file that ends an incremental basebackup.
+pub const MANIFEST_FILE_NAME: &str = "basebackup.manifest";
+
+/// A regular file of a basebackup, as the compute has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The crc32c checksum of the contents.
+    pub checksum: u32,
+    pub size: u64,
+    /// The LSN of the basebackup that the contents are from.
+    pub lsn: Lsn,
+}
+
+/// The regular files of a data directory, by path.
+///
+/// Formatted as `path:checksum:size:lsn` entries, with the checksum in hex,
+/// separated by newlines. Commas are accepted as separators too, so that a
+/// manifest fits in the `basebackup` command: none of the paths of a basebackup
+/// contain either, nor a colon.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BasebackupManifest(BTreeMap<String, ManifestEntry>);
+
+impl BasebackupManifest {
+    /// The entry of `path`, unless its contents can be from after `lsn`.
+    fn get(&self, path: &str, lsn: Lsn) -> Option<&ManifestEntry> {
+        self.0.get(path).filter(|entry| entry.lsn <= lsn)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for BasebackupManifest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut files = BTreeMap::new();
+        for entry in s.split([',', '\n']).filter(|entry| !entry.is_empty()) {
+            let mut fields = entry.split(':');
+            let (Some(path), Some(checksum), Some(size), Some(lsn), None) = (
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+            ) else {
+                anyhow::bail!("invalid manifest entry {entry:?}");
+            };
+            let checksum = u32::from_str_radix(checksum, 16)
+                .with_context(|| format!("invalid checksum in manifest entry {entry:?}"))?;
+            let size = size
+                .parse()
+                .with_context(|| format!("invalid size in manifest entry {entry:?}"))?;
+            let lsn = Lsn::from_str(lsn)
+                .with_context(|| format!("invalid LSN in manifest entry {entry:?}"))?;
+            files.insert(
+                path.to_owned(),
+                ManifestEntry {
+                    checksum,
+                    size,
+                    lsn,
+                },
+            );
+        }
+        Ok(Self(files))
+    }
+}
+
+impl std::fmt::Display for BasebackupManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (path, entry) in &self.0 {
+            writeln!(
+                f,
+                "{path}:{:08x}:{}:{}",
+                entry.checksum, entry.size, entry.lsn
+            )?;
+        }
+        Ok(())
+    }
+}
+
 /// Create basebackup with non-rel data in it.
 /// Only include relational data if 'full_backup' is true.
 ///
+/// If `previous` is given, leave out the files that it already has, and end the
+/// tarball with the manifest of the whole basebackup.
+///
 /// Currently we use empty 'req_lsn' in two cases:
 ///  * During the basebackup right after timeline creation
 ///  * When working without safekeepers. In this situation it is important to match the lsn
@@ -60,11 +164,18 @@ pub async fn send_basebackup_tarball<'a, W>(
     req_lsn: Option<Lsn>,
     prev_lsn: Option<Lsn>,
     full_backup: bool,
+    previous: Option<&'a BasebackupManifest>,
     ctx: &'a RequestContext,
 ) -> Result<(), BasebackupError>
 where
     W: AsyncWrite + Send + Sync + Unpin,
 {
+    if full_backup && previous.is_some() {
+        return Err(BasebackupError::Server(anyhow!(
+            "a full basebackup cannot be incremental"
+        )));
+    }
+
     // Compute postgres doesn't have any previous WAL files, but the first
     // record that it's going to write needs to include the LSN of the
     // previous record (xl_prev). We include prev_record_lsn in the
@@ -111,12 +222,24 @@ where
     };
 
     info!(
-        "taking basebackup lsn={}, prev_lsn={} (full_backup={})",
-        backup_lsn, prev_lsn, full_backup
+        "taking basebackup lsn={}, prev_lsn={} (full_backup={}, incremental={})",
+        backup_lsn,
+        prev_lsn,
+        full_backup,
+        previous.is_some()
     );
 
     let basebackup = Basebackup {
-        ar: Builder::new_non_terminated(write),
+        ar: Archive {
+            builder: Builder::new_non_terminated(write),
+            incremental: previous.map(|previous| Incremental {
+                previous,
+                lsn: backup_lsn,
+                manifest: BasebackupManifest::default(),
+                skipped_files: 0,
+                skipped_bytes: 0,
+            }),
+        },
         timeline,
         lsn: backup_lsn,
         prev_record_lsn: prev_lsn,
@@ -129,6 +252,14 @@ where
         .await
 }
 
+/// The SLRU segment of a file of a basebackup, like `pg_xact/0000`.
+fn parse_slru_segment_path(path: &str) -> Option<(SlruKind, u32)> {
+    let (dir, segname) = path.rsplit_once('/')?;
+    let kind = SlruKind::iter().find(|kind| kind.to_str() == dir)?;
+    let segno = u32::from_str_radix(segname, 16).ok()?;
+    Some((kind, segno))
+}
+
 /// This is short-living object only for the time of tarball creation,
 /// created mostly to avoid passing a lot of parameters between various functions
 /// used for constructing tarball.
@@ -136,7 +267,7 @@ struct Basebackup<'a, W>
 where
     W: AsyncWrite + Send + Sync + Unpin,
 {
-    ar: Builder<&'a mut W>,
+    ar: Archive<'a, W>,
     timeline: &'a Timeline,
     lsn: Lsn,
     prev_record_lsn: Lsn,
@@ -144,13 +275,121 @@ where
     ctx: &'a RequestContext,
 }
 
+/// The tarball being written, which leaves out the unchanged files of an
+/// incremental basebackup.
+struct Archive<'a, W>
+where
+    W: AsyncWrite + Send + Sync + Unpin,
+{
+    builder: Builder<&'a mut W>,
+    incremental: Option<Incremental<'a>>,
+}
+
+struct Incremental<'a> {
+    /// The files the compute already has.
+    previous: &'a BasebackupManifest,
+    /// The LSN of this basebackup.
+    lsn: Lsn,
+    /// All the files of this basebackup, sent or not.
+    manifest: BasebackupManifest,
+    skipped_files: u64,
+    skipped_bytes: u64,
+}
+
+impl Incremental<'_> {
+    /// Record that the compute keeps its copy of `path`, whose contents are the same at the LSN
+    /// of this basebackup.
+    fn keep(&mut self, path: String, entry: ManifestEntry) {
+        self.skipped_files += 1;
+        self.skipped_bytes += entry.size;
+        self.manifest.0.insert(
+            path,
+            ManifestEntry {
+                lsn: self.lsn,
+                ..entry
+            },
+        );
+    }
+
+    /// The entry of `path` in the previous manifest, if the contents of the file can't have
+    /// changed since, without reading them.
+    async fn unmodified(
+        &self,
+        path: &str,
+        size: u64,
+        key_range: &Range<Key>,
+        timeline: &Timeline,
+    ) -> Option<ManifestEntry> {
+        let entry = self.previous.get(path, self.lsn)?;
+        if entry.size != size
+            || timeline
+                .may_have_modified(key_range, entry.lsn, self.lsn)
+                .await
+        {
+            return None;
+        }
+        Some(*entry)
+    }
+}
+
+impl<'a, W> Archive<'a, W>
+where
+    W: AsyncWrite + Send + Sync + Unpin,
+{
+    async fn append(&mut self, header: &Header, data: &[u8]) -> io::Result<()> {
+        if let Some(incremental) = &mut self.incremental {
+            if !header.entry_type().is_dir() {
+                let path = header.path()?.to_string_lossy().into_owned();
+                let entry = ManifestEntry {
+                    checksum: crc32c::crc32c(data),
+                    size: data.len() as u64,
+                    lsn: incremental.lsn,
+                };
+                let unchanged = incremental
+                    .previous
+                    .get(&path, incremental.lsn)
+                    .is_some_and(|previous| {
+                        previous.checksum == entry.checksum && previous.size == entry.size
+                    });
+                if unchanged {
+                    incremental.keep(path, entry);
+                    return Ok(());
+                }
+                incremental.manifest.0.insert(path, entry);
+            }
+        }
+        self.builder.append(header, data).await
+    }
+
+    async fn finish(mut self) -> Result<(), BasebackupError> {
+        if let Some(incremental) = self.incremental.take() {
+            let manifest = incremental.manifest.to_string();
+            let header = new_tar_header(MANIFEST_FILE_NAME, manifest.len() as u64)?;
+            self.builder
+                .append(&header, manifest.as_bytes())
+                .await
+                .map_err(BasebackupError::Client)?;
+
+            info!(
+                "incremental basebackup left out {} of {} files, {} bytes",
+                incremental.skipped_files,
+                incremental.manifest.len(),
+                incremental.skipped_bytes
+            );
+            BASEBACKUP_INCREMENTAL_SKIPPED_FILES.inc_by(incremental.skipped_files);
+            BASEBACKUP_INCREMENTAL_SKIPPED_BYTES.inc_by(incremental.skipped_bytes);
+        }
+        self.builder.finish().await.map_err(BasebackupError::Client)
+    }
+}
+
 /// A sink that accepts SLRU blocks ordered by key and forwards
 /// full segments to the archive.
 struct SlruSegmentsBuilder<'a, 'b, W>
 where
     W: AsyncWrite + Send + Sync + Unpin,
 {
-    ar: &'a mut Builder<&'b mut W>,
+    ar: &'a mut Archive<'b, W>,
     buf: Vec<u8>,
     current_segment: Option<(SlruKind, u32)>,
     total_blocks: usize,
@@ -160,7 +399,7 @@ impl<'a, 'b, W> SlruSegmentsBuilder<'a, 'b, W>
 where
     W: AsyncWrite + Send + Sync + Unpin,
 {
-    fn new(ar: &'a mut Builder<&'b mut W>) -> Self {
+    fn new(ar: &'a mut Archive<'b, W>) -> Self {
         Self {
             ar,
             buf: Vec::new(),
@@ -259,7 +498,7 @@ where
         for dir in PGDATA_SUBDIRS.iter() {
             let header = new_tar_header_dir(dir)?;
             self.ar
-                .append(&header, &mut io::empty())
+                .append(&header, &[])
                 .await
                 .context("could not add directory to basebackup tarball")?;
         }
@@ -276,22 +515,43 @@ where
             } else {
                 let header = new_tar_header(filepath, 0)?;
                 self.ar
-                    .append(&header, &mut io::empty())
+                    .append(&header, &[])
                     .await
                     .context("could not add config file to basebackup tarball")?;
             }
         }
         if !lazy_slru_download {
             // Gather non-relational files from object storage pages.
-            let slru_partitions = self
+            let mut slru_keyspace = self
                 .timeline
                 .get_slru_keyspace(Version::Lsn(self.lsn), self.ctx)
                 .await
-                .map_err(|e| BasebackupError::Server(e.into()))?
-                .partition(
-                    self.timeline.get_shard_identity(),
-                    Timeline::MAX_GET_VECTORED_KEYS * BLCKSZ as u64,
-                );
+                .map_err(|e| BasebackupError::Server(e.into()))?;
+            if let Some(incremental) = &mut self.ar.incremental {
+                // Leave out the segments that the compute has, if no WAL has touched them
+                // since, before reading them.
+                let previous = incremental.previous;
+                for path in previous.0.keys() {
+                    let Some((kind, segno)) = parse_slru_segment_path(path) else {
+                        continue;
+                    };
+                    let key_range = slru_segment_key_range(kind, segno);
+                    let segment =
+                        slru_keyspace.remove_overlapping_with(&KeySpace::single(key_range.clone()));
+                    let size = segment.total_raw_size() as u64 * BLCKSZ as u64;
+                    match incremental
+                        .unmodified(path, size, &key_range, self.timeline)
+                        .await
+                    {
+                        Some(entry) if !segment.is_empty() => incremental.keep(path.clone(), entry),
+                        _ => slru_keyspace.merge(&segment),
+                    }
+                }
+            }
+            let slru_partitions = slru_keyspace.partition(
+                self.timeline.get_shard_identity(),
+                Timeline::MAX_GET_VECTORED_KEYS * BLCKSZ as u64,
+            );
 
             let mut slru_builder = SlruSegmentsBuilder::new(&mut self.ar);
 
@@ -399,7 +659,7 @@ where
 
         // Generate pg_control and bootstrap WAL segment.
         self.add_pgcontrol_file().await?;
-        self.ar.finish().await.map_err(BasebackupError::Client)?;
+        self.ar.finish().await?;
         debug!("all tarred up!");
         Ok(())
     }
@@ -417,7 +677,7 @@ where
             let file_name = dst.to_segfile_name(0);
             let header = new_tar_header(&file_name, 0)?;
             self.ar
-                .append(&header, &mut io::empty())
+                .append(&header, &[])
                 .await
                 .map_err(BasebackupError::Client)?;
             return Ok(());
@@ -428,23 +688,36 @@ where
         let mut seg = 0;
         while startblk < nblocks {
             let endblk = std::cmp::min(startblk + RELSEG_SIZE, nblocks);
+            let file_name = dst.to_segfile_name(seg as u32);
 
-            let mut segment_data: Vec<u8> = vec![];
-            for blknum in startblk..endblk {
-                let img = self
-                    .timeline
-                    .get_rel_page_at_lsn(src, blknum, Version::Lsn(self.lsn), self.ctx)
-                    .await
-                    .map_err(|e| BasebackupError::Server(e.into()))?;
-                segment_data.extend_from_slice(&img[..]);
+            // Leave out the segments that the compute has, if no WAL has touched the relation
+            // since, before reading them.
+            let mut unmodified = None;
+            if let Some(incremental) = &self.ar.incremental {
+                let size = (endblk - startblk) as u64 * BLCKSZ as u64;
+                unmodified = incremental
+                    .unmodified(&file_name, size, &rel_key_range(src), self.timeline)
+                    .await;
             }
+            if let (Some(entry), Some(incremental)) = (unmodified, &mut self.ar.incremental) {
+                incremental.keep(file_name, entry);
+            } else {
+                let mut segment_data: Vec<u8> = vec![];
+                for blknum in startblk..endblk {
+                    let img = self
+                        .timeline
+                        .get_rel_page_at_lsn(src, blknum, Version::Lsn(self.lsn), self.ctx)
+                        .await
+                        .map_err(|e| BasebackupError::Server(e.into()))?;
+                    segment_data.extend_from_slice(&img[..]);
+                }
 
-            let file_name = dst.to_segfile_name(seg as u32);
-            let header = new_tar_header(&file_name, segment_data.len() as u64)?;
-            self.ar
-                .append(&header, segment_data.as_slice())
-                .await
-                .map_err(BasebackupError::Client)?;
+                let header = new_tar_header(&file_name, segment_data.len() as u64)?;
+                self.ar
+                    .append(&header, segment_data.as_slice())
+                    .await
+                    .map_err(BasebackupError::Client)?;
+            }
 
             seg += 1;
             startblk = endblk;
@@ -541,7 +814,7 @@ where
             let path = format!("base/{}", dbnode);
             let header = new_tar_header_dir(&path)?;
             self.ar
-                .append(&header, &mut io::empty())
+                .append(&header, &[])
                 .await
                 .map_err(BasebackupError::Client)?;
 
@@ -707,3 +980,44 @@ fn new_tar_header_dir(path: &str) -> anyhow::Result<Header> {
     header.set_cksum();
     Ok(header)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_roundtrip() {
+        let manifest: BasebackupManifest =
+            "pg_xact/0000:0badcafe:8192:0/16A9388,global/pg_filenode.map:1f:512:0/14EE210\n"
+                .parse()
+                .unwrap();
+        assert_eq!(manifest.len(), 2);
+        let entry = ManifestEntry {
+            checksum: 0x0badcafe,
+            size: 8192,
+            lsn: Lsn(0x16A9388),
+        };
+        assert_eq!(manifest.get("pg_xact/0000", Lsn(0x16A9388)), Some(&entry));
+        assert_eq!(manifest.get("pg_xact/0000", Lsn(0x16A9387)), None);
+        assert_eq!(manifest.get("pg_xact/0001", Lsn::MAX), None);
+
+        let formatted = manifest.to_string();
+        assert_eq!(
+            formatted,
+            "global/pg_filenode.map:0000001f:512:0/14EE210\npg_xact/0000:0badcafe:8192:0/16A9388\n"
+        );
+        assert_eq!(formatted.parse::<BasebackupManifest>().unwrap(), manifest);
+
+        assert!("".parse::<BasebackupManifest>().unwrap().is_empty());
+        assert!("pg_xact/0000".parse::<BasebackupManifest>().is_err());
+        assert!("pg_xact/0000:1f:8192"
+            .parse::<BasebackupManifest>()
+            .is_err());
+        assert!("pg_xact/0000:xyz:8192:0/16A9388"
+            .parse::<BasebackupManifest>()
+            .is_err());
+        assert!("pg_xact/0000:1f:8192:0/16A9388:1"
+            .parse::<BasebackupManifest>()
+            .is_err());
+    }
+}
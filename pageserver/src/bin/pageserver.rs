@@ -17,7 +17,7 @@ use pageserver::control_plane_client::ControlPlaneClient;
 use pageserver::disk_usage_eviction_task::{self, launch_disk_usage_global_eviction_task};
 use pageserver::metrics::{STARTUP_DURATION, STARTUP_IS_LOADING};
 use pageserver::task_mgr::WALRECEIVER_RUNTIME;
-use pageserver::tenant::{secondary, TenantSharedResources};
+use pageserver::tenant::{remote_timeline_client::hot_tier, secondary, TenantSharedResources};
 use remote_storage::GenericRemoteStorage;
 use tokio::signal::unix::SignalKind;
 use tokio::time::Instant;
@@ -25,12 +25,13 @@ use tracing::*;
 
 use metrics::set_build_info_metric;
 use pageserver::{
-    config::{defaults::*, PageServerConf},
+    config::{self, defaults::*, reload::ConfigSource, PageServerConf},
     context::{DownloadBehavior, RequestContext},
     deletion_queue::DeletionQueue,
-    http, page_cache, page_service, task_mgr,
+    http, layer_verification, page_cache, page_service, read_priority, task_mgr,
     task_mgr::TaskKind,
     task_mgr::{BACKGROUND_RUNTIME, COMPUTE_REQUEST_RUNTIME, MGMT_REQUEST_RUNTIME},
+    temp_file_janitor,
     tenant::mgr,
     virtual_file,
 };
@@ -86,13 +87,14 @@ fn main() -> anyhow::Result<()> {
     env::set_current_dir(&workdir)
         .with_context(|| format!("Failed to set application's current dir to '{workdir}'"))?;
 
-    let conf = match initialize_config(&cfg_file_path, arg_matches, &workdir)? {
-        ControlFlow::Continue(conf) => conf,
-        ControlFlow::Break(()) => {
-            info!("Pageserver config init successful");
-            return Ok(());
-        }
-    };
+    let (conf, config_source, effective_config) =
+        match initialize_config(&cfg_file_path, arg_matches, &workdir)? {
+            ControlFlow::Continue(initialized) => initialized,
+            ControlFlow::Break(()) => {
+                info!("Pageserver config init successful");
+                return Ok(());
+            }
+        };
 
     // Initialize logging.
     //
@@ -139,8 +141,13 @@ fn main() -> anyhow::Result<()> {
     // Basic initialization of things that don't change after startup
     virtual_file::init(conf.max_file_descriptors, conf.virtual_file_io_engine);
     page_cache::init(conf.page_cache_size);
+    read_priority::init(&conf.read_priority);
+    // Must happen before any of the runtimes is used.
+    info!(?conf.runtimes, "starting with runtimes config");
+    task_mgr::configure_runtimes(conf.runtimes.clone())?;
 
-    start_pageserver(launch_ts, conf).context("Failed to start pageserver")?;
+    start_pageserver(launch_ts, conf, config_source, effective_config)
+        .context("Failed to start pageserver")?;
 
     scenario.teardown();
     Ok(())
@@ -150,7 +157,7 @@ fn initialize_config(
     cfg_file_path: &Utf8Path,
     arg_matches: clap::ArgMatches,
     workdir: &Utf8Path,
-) -> anyhow::Result<ControlFlow<(), &'static PageServerConf>> {
+) -> anyhow::Result<ControlFlow<(), (&'static PageServerConf, ConfigSource, toml_edit::Document)>> {
     let init = arg_matches.get_flag("init");
 
     let file_contents: Option<toml_edit::Document> = match std::fs::File::open(cfg_file_path) {
@@ -173,24 +180,24 @@ fn initialize_config(
         }
     };
 
-    let mut effective_config = file_contents.unwrap_or_else(|| {
+    let file_contents = file_contents.unwrap_or_else(|| {
         DEFAULT_CONFIG_FILE
             .parse()
             .expect("unit tests ensure this works")
     });
 
     // Patch with overrides from the command line
+    let mut overrides = Vec::new();
     if let Some(values) = arg_matches.get_many::<String>("config-override") {
         for option_line in values {
             let doc = toml_edit::Document::from_str(option_line).with_context(|| {
                 format!("Option '{option_line}' could not be parsed as a toml document")
             })?;
-
-            for (key, item) in doc.iter() {
-                effective_config.insert(key, item.clone());
-            }
+            overrides.push(doc);
         }
     }
+    let config_source = ConfigSource::new(cfg_file_path.to_owned(), overrides);
+    let effective_config = config_source.apply_overrides(file_contents);
 
     debug!("Resulting toml: {effective_config}");
 
@@ -209,7 +216,7 @@ fn initialize_config(
     Ok(if init {
         ControlFlow::Break(())
     } else {
-        ControlFlow::Continue(Box::leak(Box::new(conf)))
+        ControlFlow::Continue((Box::leak(Box::new(conf)), config_source, effective_config))
     })
 }
 
@@ -261,6 +268,8 @@ fn startup_checkpoint(started_at: Instant, phase: &str, human_phase: &str) {
 fn start_pageserver(
     launch_ts: &'static LaunchTimestamp,
     conf: &'static PageServerConf,
+    config_source: ConfigSource,
+    effective_config: toml_edit::Document,
 ) -> anyhow::Result<()> {
     // Monotonic time for later calculating startup duration
     let started_startup_at = Instant::now();
@@ -285,6 +294,7 @@ fn start_pageserver(
     .unwrap();
     pageserver::preinitialize_metrics();
     pageserver::metrics::wal_redo::set_process_kind_metric(conf.walredo_process_kind);
+    pageserver::pg_versions::discover(conf);
 
     // If any failpoints were set from FAILPOINTS environment variable,
     // print them to the log for debugging purposes
@@ -327,14 +337,19 @@ fn start_pageserver(
     let broker_client = WALRECEIVER_RUNTIME
         .block_on(async {
             // Note: we do not attempt connecting here (but validate endpoints sanity).
-            storage_broker::connect(conf.broker_endpoint.clone(), conf.broker_keepalive_interval)
+            let mut endpoints = vec![conf.broker_endpoint.clone()];
+            endpoints.extend(conf.broker_fallback_endpoints.iter().cloned());
+            storage_broker::connect_cluster(&endpoints, conf.broker_keepalive_interval)
         })
         .with_context(|| {
             format!(
-                "create broker client for uri={:?} keepalive_interval={:?}",
-                &conf.broker_endpoint, conf.broker_keepalive_interval,
+                "create broker client for uri={:?} fallbacks={:?} keepalive_interval={:?}",
+                &conf.broker_endpoint,
+                &conf.broker_fallback_endpoints,
+                conf.broker_keepalive_interval,
             )
         })?;
+    pageserver::timeline_events::launch_publisher(conf, broker_client.clone());
 
     // Initialize authentication for incoming connections
     let http_auth;
@@ -362,6 +377,20 @@ fn start_pageserver(
     info!("Using auth for http API: {:#?}", conf.http_auth_type);
     info!("Using auth for pg connections: {:#?}", conf.pg_auth_type);
 
+    let pg_tls_config = match &conf.page_service_tls {
+        Some(tls_config) => {
+            info!(
+                "Loading TLS certificate for pg connections from {}",
+                tls_config.cert_path
+            );
+            Some(
+                page_service::tls::load_server_config(tls_config)
+                    .context("load page_service TLS configuration")?,
+            )
+        }
+        None => None,
+    };
+
     match var("NEON_AUTH_TOKEN") {
         Ok(v) => {
             info!("Loaded JWT token for authentication with Safekeeper");
@@ -384,6 +413,12 @@ fn start_pageserver(
 
     // Set up remote storage client
     let remote_storage = create_remote_storage_client(conf)?;
+    if let Some(hot_tier_config) = &conf.hot_tier {
+        if remote_storage.is_none() {
+            anyhow::bail!("hot_tier requires remote_storage to be configured");
+        }
+        hot_tier::init(hot_tier_config)?;
+    }
 
     // Set up deletion queue
     let (deletion_queue, deletion_workers) = DeletionQueue::new(
@@ -436,6 +471,14 @@ fn start_pageserver(
     ))?;
     let tenant_manager = Arc::new(tenant_manager);
 
+    config::reload::init(
+        conf,
+        config_source,
+        effective_config,
+        remote_storage.clone(),
+        tenant_manager.clone(),
+    );
+
     BACKGROUND_RUNTIME.spawn({
         let shutdown_pageserver = shutdown_pageserver.clone();
         let drive_init = async move {
@@ -543,6 +586,18 @@ fn start_pageserver(
         )?;
     }
 
+    mgr::spawn_detached_tenants_janitor(tenant_manager.clone());
+
+    temp_file_janitor::launch_temp_file_janitor(conf, background_jobs_barrier.clone());
+
+    if remote_storage.is_some() {
+        layer_verification::launch_layer_verification_task(
+            conf,
+            tenant_manager.clone(),
+            background_jobs_barrier.clone(),
+        );
+    }
+
     // Start up the service to handle HTTP mgmt API request. We created the
     // listener earlier already.
     {
@@ -661,6 +716,7 @@ fn start_pageserver(
                     pg_auth,
                     pageserver_listener,
                     conf.pg_auth_type,
+                    pg_tls_config,
                     libpq_ctx,
                     task_mgr::shutdown_token(),
                 )
@@ -678,13 +734,35 @@ fn start_pageserver(
             let mut sigint = tokio::signal::unix::signal(SignalKind::interrupt()).unwrap();
             let mut sigterm = tokio::signal::unix::signal(SignalKind::terminate()).unwrap();
             let mut sigquit = tokio::signal::unix::signal(SignalKind::quit()).unwrap();
-            let signal = tokio::select! {
-                _ = sigquit.recv() => {
-                    info!("Got signal SIGQUIT. Terminating in immediate shutdown mode",);
-                    std::process::exit(111);
+            let mut sighup = tokio::signal::unix::signal(SignalKind::hangup()).unwrap();
+            let signal = loop {
+                tokio::select! {
+                    _ = sigquit.recv() => {
+                        info!("Got signal SIGQUIT. Terminating in immediate shutdown mode",);
+                        std::process::exit(111);
+                    }
+                    _ = sigint.recv() => break "SIGINT",
+                    _ = sigterm.recv() => break "SIGTERM",
+                    _ = sighup.recv() => {
+                        info!("Got signal SIGHUP. Reloading config");
+                        // Reading the config blocks, don't hold up the signal handling with it.
+                        tokio::task::spawn_blocking(|| match config::reload::reload() {
+                            Ok(res) if !res.rejected.is_empty() => {
+                                let keys = res
+                                    .rejected
+                                    .iter()
+                                    .map(|c| c.key.as_str())
+                                    .collect::<Vec<_>>();
+                                error!(
+                                    "Config reload rejected, nothing was applied: changes to {keys:?} require a restart"
+                                );
+                            }
+                            // Logged by reload()
+                            Ok(_) => {}
+                            Err(e) => error!("Failed to reload config: {e}"),
+                        });
+                    }
                 }
-                _ = sigint.recv() => { "SIGINT" },
-                _ = sigterm.recv() => { "SIGTERM" },
             };
 
             info!("Got signal {signal}. Terminating gracefully in fast shutdown mode",);
@@ -30,23 +30,34 @@ use utils::{
     logging::LogFormat,
 };
 
+use crate::page_service::tls::PageServiceTlsConfig;
+use crate::read_priority::ReadPriorityConfig;
+use crate::task_mgr::RuntimesConfig;
+use crate::tenant::getpage_latency::GetPageLatencySloConfig;
+use crate::tenant::remote_timeline_client::hot_tier::HotTierConfig;
+use crate::tenant::timeline::upload_pacing::UploadBacklogPacingConfig;
+use crate::tenant::timeline::walreceiver::WalReceiverProtocol;
 use crate::tenant::timeline::GetVectoredImpl;
 use crate::tenant::vectored_blob_io::MaxVectoredReadBytes;
-use crate::tenant::{config::TenantConfOpt, timeline::GetImpl};
+use crate::tenant::{config::TenantConfOpt, data_dirs::DataDirPlacement, timeline::GetImpl};
 use crate::tenant::{
     TENANTS_SEGMENT_NAME, TENANT_DELETED_MARKER_FILE_NAME, TIMELINES_SEGMENT_NAME,
 };
 use crate::{disk_usage_eviction_task::DiskUsageEvictionTaskConfig, virtual_file::io_engine};
 use crate::{tenant::config::TenantConf, virtual_file};
 use crate::{
-    IGNORED_TENANT_FILE_NAME, TENANT_CONFIG_NAME, TENANT_HEATMAP_BASENAME,
-    TENANT_LOCATION_CONFIG_NAME, TIMELINE_DELETE_MARK_SUFFIX,
+    DETACHED_TENANT_FILE_NAME, IGNORED_TENANT_FILE_NAME, INITDB_CACHE_DIR_NAME, TENANT_CONFIG_NAME,
+    TENANT_CONFIG_TEMPLATES_NAME, TENANT_FORMAT_VERSION_NAME, TENANT_HEATMAP_BASENAME,
+    TENANT_LEGAL_HOLD_NAME, TENANT_LOCATION_CONFIG_NAME, TIMELINE_DELETE_MARK_SUFFIX,
 };
 
 use self::defaults::DEFAULT_CONCURRENT_TENANT_WARMUP;
+use self::reload::Reloadable;
 
 use self::defaults::DEFAULT_VIRTUAL_FILE_IO_ENGINE;
 
+pub mod reload;
+
 pub mod defaults {
     use crate::tenant::config::defaults::*;
     use const_format::formatcp;
@@ -99,8 +110,36 @@ pub mod defaults {
 
     pub const DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB: usize = 0;
 
+    pub const DEFAULT_EPHEMERAL_DIR_MAX_BYTES: u64 = 0;
+
     pub const DEFAULT_WALREDO_PROCESS_KIND: &str = "sync";
 
+    pub const DEFAULT_DETACHED_TENANT_RETENTION: &str = "1 hour";
+
+    pub const DEFAULT_SECONDARY_INDEX_REFRESH_PERIOD: &str = "10 min";
+    pub const DEFAULT_SECONDARY_INDEX_REFRESH_CONCURRENCY: usize = 16;
+    pub const DEFAULT_SECONDARY_INDEX_REFRESH_BATCH_SIZE: usize = 1000;
+
+    pub const DEFAULT_METADATA_FSYNC_BATCH_WINDOW: &str = "0s";
+
+    pub const DEFAULT_EVICTION_BOUNCE_WINDOW: &str = "5 min";
+    pub const DEFAULT_EVICTION_BOUNCE_COOLDOWN: &str = "1 hour";
+
+    pub const DEFAULT_INDEX_PART_COMPRESSION_THRESHOLD: usize = 0;
+
+    pub const DEFAULT_STARTUP_REPAIR: bool = false;
+
+    pub const DEFAULT_LAYER_VERIFICATION_PERIOD: &str = "0s";
+
+    pub const DEFAULT_TEMP_FILE_JANITOR_PERIOD: &str = "10m";
+    pub const DEFAULT_TEMP_FILE_MAX_AGE: &str = "1h";
+
+    pub const DEFAULT_TENANT_DATA_DIR_PLACEMENT: &str = "free-space";
+
+    pub const DEFAULT_TIMELINE_ACCESS_LOG_RETENTION: &str = "7 days";
+
+    pub const DEFAULT_INITDB_CACHE: bool = true;
+
     ///
     /// Default built-in configuration file.
     ///
@@ -168,6 +207,59 @@ pub mod defaults {
 
 #ephemeral_bytes_per_memory_kb = {DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB}
 
+#ephemeral_dir = '..'
+#ephemeral_dir_max_bytes = {DEFAULT_EPHEMERAL_DIR_MAX_BYTES}
+
+#bundle_dir = '..'
+#wal_archive_restore_command = '..'
+
+#runtimes = {{ background = {{ worker_threads = .., cpus = "..", numa_node = .. }} }}
+
+#read_priority = {{ background_concurrency = .., foreground_latency_budget = "..", max_background_delay = ".." }}
+
+#walredo_recycle = {{ max_requests = .., max_rss_bytes = .., max_lifetime = ".." }}
+
+#detached_tenant_retention = '{DEFAULT_DETACHED_TENANT_RETENTION}'
+
+#secondary_index_refresh_period = '{DEFAULT_SECONDARY_INDEX_REFRESH_PERIOD}'
+#secondary_index_refresh_concurrency = {DEFAULT_SECONDARY_INDEX_REFRESH_CONCURRENCY}
+#secondary_index_refresh_batch_size = {DEFAULT_SECONDARY_INDEX_REFRESH_BATCH_SIZE}
+
+#metadata_fsync_batch_window = '{DEFAULT_METADATA_FSYNC_BATCH_WINDOW}'
+
+#eviction_bounce_window = '{DEFAULT_EVICTION_BOUNCE_WINDOW}'
+#eviction_bounce_cooldown = '{DEFAULT_EVICTION_BOUNCE_COOLDOWN}'
+
+#index_part_compression_threshold = {DEFAULT_INDEX_PART_COMPRESSION_THRESHOLD}
+
+#startup_repair = {DEFAULT_STARTUP_REPAIR}
+
+#layer_verification_period = '{DEFAULT_LAYER_VERIFICATION_PERIOD}'
+
+#temp_file_janitor_period = '{DEFAULT_TEMP_FILE_JANITOR_PERIOD}'
+#temp_file_max_age = '{DEFAULT_TEMP_FILE_MAX_AGE}'
+
+#broker_fallback_endpoints = []
+
+#upload_backlog_pacing = {{ high_bytes = .., low_bytes = .., max_paced_bytes = .. }}
+
+#wal_receiver_protocol = {{ type = "postgres" }}
+
+#tenant_tag_labels = []
+
+#getpage_latency_slo = {{ threshold = "10ms", objective = 0.999 }}
+
+#tenant_data_dirs = []
+#tenant_data_dir_placement = '{DEFAULT_TENANT_DATA_DIR_PLACEMENT}'
+
+#timeline_access_log_retention = '{DEFAULT_TIMELINE_ACCESS_LOG_RETENTION}'
+
+#initdb_cache = {DEFAULT_INITDB_CACHE}
+
+#hot_tier = {{ max_age = "..", remote_storage = {{ bucket_name = "..", bucket_region = ".." }} }}
+
+#page_service_tls = {{ cert_path = "..", key_path = "..", client_ca_path = ".." }}
+
 [remote_storage]
 
 "#
@@ -216,10 +308,17 @@ pub struct PageServerConf {
     /// Path to a file or directory containing public key(s) for verifying JWT tokens.
     /// Used for both mgmt and compute auth, if enabled.
     pub auth_validation_public_key_path: Option<Utf8PathBuf>,
+    /// TLS for libpq connections from compute, see [`crate::page_service::tls`].
+    pub page_service_tls: Option<PageServiceTlsConfig>,
 
     pub remote_storage_config: Option<RemoteStorageConfig>,
 
-    pub default_tenant_conf: TenantConf,
+    /// Low-latency storage that new layers are uploaded to, before they are migrated to
+    /// `remote_storage_config`, see [`crate::tenant::remote_timeline_client::hot_tier`].
+    pub hot_tier: Option<HotTierConfig>,
+
+    /// Can be changed at runtime, see [`reload`].
+    pub default_tenant_conf: Reloadable<TenantConf>,
 
     /// Storage broker endpoints to connect to.
     pub broker_endpoint: Uri,
@@ -233,6 +332,7 @@ pub struct PageServerConf {
     pub concurrent_tenant_warmup: ConfigurableSemaphore,
 
     /// Number of concurrent [`Tenant::gather_size_inputs`](crate::tenant::Tenant::gather_size_inputs) allowed.
+    /// The extra worker tasks of the logical size calculations take permits of it too.
     pub concurrent_tenant_size_logical_size_queries: ConfigurableSemaphore,
     /// Limit of concurrent [`Tenant::gather_size_inputs`] issued by module `eviction_task`.
     /// The number of permits is the same as `concurrent_tenant_size_logical_size_queries`.
@@ -249,7 +349,8 @@ pub struct PageServerConf {
     pub metric_collection_bucket: Option<RemoteStorageConfig>,
     pub synthetic_size_calculation_interval: Duration,
 
-    pub disk_usage_based_eviction: Option<DiskUsageEvictionTaskConfig>,
+    /// Can be changed at runtime, see [`reload`].
+    pub disk_usage_based_eviction: Reloadable<Option<DiskUsageEvictionTaskConfig>>,
 
     pub test_remote_failures: u64,
 
@@ -301,7 +402,108 @@ pub struct PageServerConf {
     /// Setting this to zero disables limits on total ephemeral layer size.
     pub ephemeral_bytes_per_memory_kb: usize,
 
+    /// Directory for the ephemeral files of the in-memory layers, e.g. on a scratch disk or a
+    /// tmpfs, instead of the timeline directories. See [`crate::tenant::ephemeral_file`].
+    pub ephemeral_dir: Option<Utf8PathBuf>,
+    /// Bytes of ephemeral files in `ephemeral_dir` above which the new ones are created in the
+    /// timeline directories instead. Zero means no limit.
+    pub ephemeral_dir_max_bytes: u64,
+
+    /// Directory under which tenants are exported to bundles and imported from them, see
+    /// [`crate::tenant::bundle`]. The bundle paths of the API are relative to it, and the API
+    /// is disabled if unset.
+    pub bundle_dir: Option<Utf8PathBuf>,
+
+    /// The command that fetches a segment of the WAL archive of `import wal_archive`, like the
+    /// `restore_command` of Postgres, see [`crate::import_datadir::WalArchive`]. The import
+    /// is disabled if unset.
+    pub wal_archive_restore_command: Option<String>,
+
     pub walredo_process_kind: crate::walredo::ProcessKind,
+
+    /// Limits after which walredo processes are replaced with new ones.
+    pub walredo_recycle: crate::walredo::RecycleConfig,
+
+    /// Worker thread counts and CPU pinning of the tokio runtimes.
+    pub runtimes: RuntimesConfig,
+
+    /// Prioritization of foreground reads over background reads, see [`crate::read_priority`].
+    pub read_priority: ReadPriorityConfig,
+
+    /// How long the local data of a tenant detached with `keep_local` is kept around
+    /// for a re-attach, before it is purged.
+    pub detached_tenant_retention: Duration,
+
+    /// How often the remote index_part of each secondary tenant's timelines is refreshed.
+    pub secondary_index_refresh_period: Duration,
+    /// Maximum number of secondary tenants whose remote index is refreshed concurrently,
+    /// across all tenants on this pageserver.
+    pub secondary_index_refresh_concurrency: usize,
+    /// Maximum number of secondary tenants picked up by one round of the index refresh.
+    pub secondary_index_refresh_batch_size: usize,
+
+    /// Fsyncs of the same directory issued within this window, by timeline creation and
+    /// tenant config persistence, are coalesced into one.  Zero disables the batching.
+    pub metadata_fsync_batch_window: Duration,
+
+    /// A layer that gets downloaded again within this window after its eviction is bouncing:
+    /// the eviction task leaves it resident, and disk usage based eviction evicts it last, for
+    /// `eviction_bounce_cooldown` times the number of bounces in a row, up to four.  Zero
+    /// disables the protection.
+    pub eviction_bounce_window: Duration,
+    pub eviction_bounce_cooldown: Duration,
+
+    /// Index parts whose JSON is at least this many bytes long are uploaded zstd-compressed,
+    /// which speeds up the attach of timelines with very many layers.  Zero disables the
+    /// compression, which pageservers that predate it can't read.
+    pub index_part_compression_threshold: usize,
+
+    /// On startup, try to repair tenants whose local state is found corrupt, instead of
+    /// marking them Broken. See [`crate::tenant::startup_repair`].
+    pub startup_repair: bool,
+
+    /// Every period, compare a random resident layer with its copy in remote storage. Zero
+    /// disables the verification. See [`crate::layer_verification`].
+    pub layer_verification_period: Duration,
+
+    /// Every period, remove the temporary files and directories that weren't modified for
+    /// `temp_file_max_age`. Zero disables the janitor. See [`crate::temp_file_janitor`].
+    pub temp_file_janitor_period: Duration,
+    pub temp_file_max_age: Duration,
+
+    /// Other instances of the storage broker cluster that `broker_endpoint` belongs to.
+    /// Broker requests are balanced between the reachable instances.
+    pub broker_fallback_endpoints: Vec<Uri>,
+
+    /// Slow down layer flushes and compactions of timelines with a deep upload queue. See
+    /// [`crate::tenant::timeline::upload_pacing`].
+    pub upload_backlog_pacing: Option<UploadBacklogPacingConfig>,
+
+    /// The protocol to stream WAL from safekeepers with.
+    pub wal_receiver_protocol: WalReceiverProtocol,
+
+    /// Keys of the tenant config `tags` that are propagated to metric labels and tenant-scoped
+    /// spans. See [`crate::tenant::tags`].
+    pub tenant_tag_labels: Vec<String>,
+
+    /// The GetPage latency SLO whose burn rate is exported. See
+    /// [`crate::tenant::getpage_latency`].
+    pub getpage_latency_slo: GetPageLatencySloConfig,
+
+    /// Directories, typically on different disks, to place the data of new tenant shards in,
+    /// instead of the tenants directory of the workdir. See [`crate::tenant::data_dirs`].
+    pub tenant_data_dirs: Vec<Utf8PathBuf>,
+
+    /// How new tenant shards are assigned one of the `tenant_data_dirs`.
+    pub tenant_data_dir_placement: DataDirPlacement,
+
+    /// How long the hourly rollups of the accesses to each timeline are kept, see
+    /// [`crate::tenant::timeline::access_log`]. Zero disables them.
+    pub timeline_access_log_retention: Duration,
+
+    /// Whether root timelines are bootstrapped from a cached initdb output instead of running
+    /// initdb each time, see [`crate::tenant::initdb_cache`].
+    pub initdb_cache: bool,
 }
 
 /// We do not want to store this in a PageServerConf because the latter may be logged
@@ -361,7 +563,9 @@ struct PageServerConfigBuilder {
 
     //
     auth_validation_public_key_path: BuilderValue<Option<Utf8PathBuf>>,
+    page_service_tls: BuilderValue<Option<PageServiceTlsConfig>>,
     remote_storage_config: BuilderValue<Option<RemoteStorageConfig>>,
+    hot_tier: BuilderValue<Option<HotTierConfig>>,
 
     id: BuilderValue<NodeId>,
 
@@ -408,7 +612,57 @@ struct PageServerConfigBuilder {
 
     ephemeral_bytes_per_memory_kb: BuilderValue<usize>,
 
+    ephemeral_dir: BuilderValue<Option<Utf8PathBuf>>,
+    ephemeral_dir_max_bytes: BuilderValue<u64>,
+
+    bundle_dir: BuilderValue<Option<Utf8PathBuf>>,
+    wal_archive_restore_command: BuilderValue<Option<String>>,
+
     walredo_process_kind: BuilderValue<crate::walredo::ProcessKind>,
+
+    walredo_recycle: BuilderValue<crate::walredo::RecycleConfig>,
+
+    runtimes: BuilderValue<RuntimesConfig>,
+
+    read_priority: BuilderValue<ReadPriorityConfig>,
+
+    detached_tenant_retention: BuilderValue<Duration>,
+
+    secondary_index_refresh_period: BuilderValue<Duration>,
+    secondary_index_refresh_concurrency: BuilderValue<usize>,
+    secondary_index_refresh_batch_size: BuilderValue<usize>,
+
+    metadata_fsync_batch_window: BuilderValue<Duration>,
+
+    eviction_bounce_window: BuilderValue<Duration>,
+    eviction_bounce_cooldown: BuilderValue<Duration>,
+
+    index_part_compression_threshold: BuilderValue<usize>,
+
+    startup_repair: BuilderValue<bool>,
+
+    layer_verification_period: BuilderValue<Duration>,
+
+    temp_file_janitor_period: BuilderValue<Duration>,
+    temp_file_max_age: BuilderValue<Duration>,
+
+    broker_fallback_endpoints: BuilderValue<Vec<Uri>>,
+
+    upload_backlog_pacing: BuilderValue<Option<UploadBacklogPacingConfig>>,
+
+    wal_receiver_protocol: BuilderValue<WalReceiverProtocol>,
+
+    tenant_tag_labels: BuilderValue<Vec<String>>,
+
+    getpage_latency_slo: BuilderValue<GetPageLatencySloConfig>,
+
+    tenant_data_dirs: BuilderValue<Vec<Utf8PathBuf>>,
+
+    tenant_data_dir_placement: BuilderValue<DataDirPlacement>,
+
+    timeline_access_log_retention: BuilderValue<Duration>,
+
+    initdb_cache: BuilderValue<bool>,
 }
 
 impl PageServerConfigBuilder {
@@ -436,7 +690,9 @@ impl PageServerConfigBuilder {
             http_auth_type: Set(AuthType::Trust),
             pg_auth_type: Set(AuthType::Trust),
             auth_validation_public_key_path: Set(None),
+            page_service_tls: Set(None),
             remote_storage_config: Set(None),
+            hot_tier: Set(None),
             id: NotSet,
             broker_endpoint: Set(storage_broker::DEFAULT_ENDPOINT
                 .parse()
@@ -498,7 +754,80 @@ impl PageServerConfigBuilder {
             validate_vectored_get: Set(DEFAULT_VALIDATE_VECTORED_GET),
             ephemeral_bytes_per_memory_kb: Set(DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB),
 
+            ephemeral_dir: Set(None),
+            ephemeral_dir_max_bytes: Set(DEFAULT_EPHEMERAL_DIR_MAX_BYTES),
+
+            bundle_dir: Set(None),
+            wal_archive_restore_command: Set(None),
+
             walredo_process_kind: Set(DEFAULT_WALREDO_PROCESS_KIND.parse().unwrap()),
+
+            walredo_recycle: Set(crate::walredo::RecycleConfig::default()),
+
+            runtimes: Set(RuntimesConfig::default()),
+
+            read_priority: Set(ReadPriorityConfig::default()),
+
+            detached_tenant_retention: Set(humantime::parse_duration(
+                DEFAULT_DETACHED_TENANT_RETENTION,
+            )
+            .unwrap()),
+
+            secondary_index_refresh_period: Set(humantime::parse_duration(
+                DEFAULT_SECONDARY_INDEX_REFRESH_PERIOD,
+            )
+            .unwrap()),
+            secondary_index_refresh_concurrency: Set(DEFAULT_SECONDARY_INDEX_REFRESH_CONCURRENCY),
+            secondary_index_refresh_batch_size: Set(DEFAULT_SECONDARY_INDEX_REFRESH_BATCH_SIZE),
+
+            metadata_fsync_batch_window: Set(humantime::parse_duration(
+                DEFAULT_METADATA_FSYNC_BATCH_WINDOW,
+            )
+            .unwrap()),
+
+            eviction_bounce_window: Set(
+                humantime::parse_duration(DEFAULT_EVICTION_BOUNCE_WINDOW).unwrap()
+            ),
+            eviction_bounce_cooldown: Set(humantime::parse_duration(
+                DEFAULT_EVICTION_BOUNCE_COOLDOWN,
+            )
+            .unwrap()),
+
+            index_part_compression_threshold: Set(DEFAULT_INDEX_PART_COMPRESSION_THRESHOLD),
+
+            startup_repair: Set(DEFAULT_STARTUP_REPAIR),
+
+            layer_verification_period: Set(humantime::parse_duration(
+                DEFAULT_LAYER_VERIFICATION_PERIOD,
+            )
+            .unwrap()),
+
+            temp_file_janitor_period: Set(humantime::parse_duration(
+                DEFAULT_TEMP_FILE_JANITOR_PERIOD,
+            )
+            .unwrap()),
+            temp_file_max_age: Set(humantime::parse_duration(DEFAULT_TEMP_FILE_MAX_AGE).unwrap()),
+
+            broker_fallback_endpoints: Set(Vec::new()),
+
+            upload_backlog_pacing: Set(None),
+
+            wal_receiver_protocol: Set(WalReceiverProtocol::default()),
+
+            tenant_tag_labels: Set(Vec::new()),
+
+            getpage_latency_slo: Set(GetPageLatencySloConfig::default()),
+
+            tenant_data_dirs: Set(Vec::new()),
+
+            tenant_data_dir_placement: Set(DEFAULT_TENANT_DATA_DIR_PLACEMENT.parse().unwrap()),
+
+            timeline_access_log_retention: Set(humantime::parse_duration(
+                DEFAULT_TIMELINE_ACCESS_LOG_RETENTION,
+            )
+            .expect("cannot parse default timeline access log retention")),
+
+            initdb_cache: Set(DEFAULT_INITDB_CACHE),
         }
     }
 }
@@ -559,10 +888,18 @@ impl PageServerConfigBuilder {
         self.auth_validation_public_key_path = BuilderValue::Set(auth_validation_public_key_path)
     }
 
+    pub fn page_service_tls(&mut self, page_service_tls: Option<PageServiceTlsConfig>) {
+        self.page_service_tls = BuilderValue::Set(page_service_tls)
+    }
+
     pub fn remote_storage_config(&mut self, remote_storage_config: Option<RemoteStorageConfig>) {
         self.remote_storage_config = BuilderValue::Set(remote_storage_config)
     }
 
+    pub fn hot_tier(&mut self, hot_tier: Option<HotTierConfig>) {
+        self.hot_tier = BuilderValue::Set(hot_tier)
+    }
+
     pub fn broker_endpoint(&mut self, broker_endpoint: Uri) {
         self.broker_endpoint = BuilderValue::Set(broker_endpoint)
     }
@@ -686,10 +1023,122 @@ impl PageServerConfigBuilder {
         self.ephemeral_bytes_per_memory_kb = BuilderValue::Set(value);
     }
 
+    pub fn ephemeral_dir(&mut self, value: Option<Utf8PathBuf>) {
+        self.ephemeral_dir = BuilderValue::Set(value);
+    }
+
+    pub fn ephemeral_dir_max_bytes(&mut self, value: u64) {
+        self.ephemeral_dir_max_bytes = BuilderValue::Set(value);
+    }
+
+    pub fn bundle_dir(&mut self, value: Option<Utf8PathBuf>) {
+        self.bundle_dir = BuilderValue::Set(value);
+    }
+
+    pub fn wal_archive_restore_command(&mut self, value: Option<String>) {
+        self.wal_archive_restore_command = BuilderValue::Set(value);
+    }
+
     pub fn get_walredo_process_kind(&mut self, value: crate::walredo::ProcessKind) {
         self.walredo_process_kind = BuilderValue::Set(value);
     }
 
+    pub fn walredo_recycle(&mut self, value: crate::walredo::RecycleConfig) {
+        self.walredo_recycle = BuilderValue::Set(value);
+    }
+
+    pub fn runtimes(&mut self, value: RuntimesConfig) {
+        self.runtimes = BuilderValue::Set(value);
+    }
+
+    pub fn read_priority(&mut self, value: ReadPriorityConfig) {
+        self.read_priority = BuilderValue::Set(value);
+    }
+
+    pub fn detached_tenant_retention(&mut self, value: Duration) {
+        self.detached_tenant_retention = BuilderValue::Set(value);
+    }
+
+    pub fn secondary_index_refresh_period(&mut self, value: Duration) {
+        self.secondary_index_refresh_period = BuilderValue::Set(value);
+    }
+
+    pub fn secondary_index_refresh_concurrency(&mut self, value: usize) {
+        self.secondary_index_refresh_concurrency = BuilderValue::Set(value);
+    }
+
+    pub fn secondary_index_refresh_batch_size(&mut self, value: usize) {
+        self.secondary_index_refresh_batch_size = BuilderValue::Set(value);
+    }
+
+    pub fn metadata_fsync_batch_window(&mut self, value: Duration) {
+        self.metadata_fsync_batch_window = BuilderValue::Set(value);
+    }
+
+    pub fn eviction_bounce_window(&mut self, value: Duration) {
+        self.eviction_bounce_window = BuilderValue::Set(value);
+    }
+
+    pub fn eviction_bounce_cooldown(&mut self, value: Duration) {
+        self.eviction_bounce_cooldown = BuilderValue::Set(value);
+    }
+
+    pub fn index_part_compression_threshold(&mut self, value: usize) {
+        self.index_part_compression_threshold = BuilderValue::Set(value);
+    }
+
+    pub fn startup_repair(&mut self, value: bool) {
+        self.startup_repair = BuilderValue::Set(value);
+    }
+
+    pub fn layer_verification_period(&mut self, value: Duration) {
+        self.layer_verification_period = BuilderValue::Set(value);
+    }
+
+    pub fn temp_file_janitor_period(&mut self, value: Duration) {
+        self.temp_file_janitor_period = BuilderValue::Set(value);
+    }
+
+    pub fn temp_file_max_age(&mut self, value: Duration) {
+        self.temp_file_max_age = BuilderValue::Set(value);
+    }
+
+    pub fn broker_fallback_endpoints(&mut self, value: Vec<Uri>) {
+        self.broker_fallback_endpoints = BuilderValue::Set(value);
+    }
+
+    pub fn upload_backlog_pacing(&mut self, value: Option<UploadBacklogPacingConfig>) {
+        self.upload_backlog_pacing = BuilderValue::Set(value);
+    }
+
+    pub fn wal_receiver_protocol(&mut self, value: WalReceiverProtocol) {
+        self.wal_receiver_protocol = BuilderValue::Set(value);
+    }
+
+    pub fn tenant_tag_labels(&mut self, value: Vec<String>) {
+        self.tenant_tag_labels = BuilderValue::Set(value);
+    }
+
+    pub fn getpage_latency_slo(&mut self, value: GetPageLatencySloConfig) {
+        self.getpage_latency_slo = BuilderValue::Set(value);
+    }
+
+    pub fn tenant_data_dirs(&mut self, value: Vec<Utf8PathBuf>) {
+        self.tenant_data_dirs = BuilderValue::Set(value);
+    }
+
+    pub fn tenant_data_dir_placement(&mut self, value: DataDirPlacement) {
+        self.tenant_data_dir_placement = BuilderValue::Set(value);
+    }
+
+    pub fn timeline_access_log_retention(&mut self, value: Duration) {
+        self.timeline_access_log_retention = BuilderValue::Set(value);
+    }
+
+    pub fn initdb_cache(&mut self, value: bool) {
+        self.initdb_cache = BuilderValue::Set(value);
+    }
+
     pub fn build(self) -> anyhow::Result<PageServerConf> {
         let default = Self::default_values();
 
@@ -722,7 +1171,9 @@ impl PageServerConfigBuilder {
                 http_auth_type,
                 pg_auth_type,
                 auth_validation_public_key_path,
+                page_service_tls,
                 remote_storage_config,
+                hot_tier,
                 id,
                 broker_endpoint,
                 broker_keepalive_interval,
@@ -732,7 +1183,6 @@ impl PageServerConfigBuilder {
                 metric_collection_endpoint,
                 metric_collection_bucket,
                 synthetic_size_calculation_interval,
-                disk_usage_based_eviction,
                 test_remote_failures,
                 ondemand_download_behavior_treat_error_as_warn,
                 background_task_maximum_delay,
@@ -747,12 +1197,46 @@ impl PageServerConfigBuilder {
                 max_vectored_read_bytes,
                 validate_vectored_get,
                 ephemeral_bytes_per_memory_kb,
+                ephemeral_dir,
+                ephemeral_dir_max_bytes,
+                bundle_dir,
+                wal_archive_restore_command,
                 walredo_process_kind,
+                walredo_recycle,
+                runtimes,
+                read_priority,
+                detached_tenant_retention,
+                secondary_index_refresh_period,
+                secondary_index_refresh_concurrency,
+                secondary_index_refresh_batch_size,
+                metadata_fsync_batch_window,
+                eviction_bounce_window,
+                eviction_bounce_cooldown,
+                index_part_compression_threshold,
+                startup_repair,
+                layer_verification_period,
+                temp_file_janitor_period,
+                temp_file_max_age,
+                broker_fallback_endpoints,
+                upload_backlog_pacing,
+                wal_receiver_protocol,
+                tenant_tag_labels,
+                getpage_latency_slo,
+                tenant_data_dirs,
+                tenant_data_dir_placement,
+                timeline_access_log_retention,
+                initdb_cache,
             }
             CUSTOM LOGIC
             {
                 // TenantConf is handled separately
-                default_tenant_conf: TenantConf::default(),
+                default_tenant_conf: Reloadable::new(TenantConf::default()),
+                disk_usage_based_eviction: Reloadable::new(
+                    self
+                        .disk_usage_based_eviction
+                        .ok_or("disk_usage_based_eviction",
+                               default.disk_usage_based_eviction)?
+                ),
                 concurrent_tenant_warmup: ConfigurableSemaphore::new({
                     self
                         .concurrent_tenant_warmup
@@ -801,6 +1285,14 @@ impl PageServerConf {
         self.workdir.join("deletion")
     }
 
+    pub(crate) fn tenant_config_templates_path(&self) -> Utf8PathBuf {
+        self.workdir.join(TENANT_CONFIG_TEMPLATES_NAME)
+    }
+
+    pub(crate) fn initdb_cache_path(&self) -> Utf8PathBuf {
+        self.workdir.join(INITDB_CACHE_DIR_NAME)
+    }
+
     pub fn metadata_path(&self) -> Utf8PathBuf {
         self.workdir.join("metadata.json")
     }
@@ -831,6 +1323,11 @@ impl PageServerConf {
             .join(IGNORED_TENANT_FILE_NAME)
     }
 
+    pub fn tenant_detached_mark_file_path(&self, tenant_shard_id: &TenantShardId) -> Utf8PathBuf {
+        self.tenant_path(tenant_shard_id)
+            .join(DETACHED_TENANT_FILE_NAME)
+    }
+
     /// Points to a place in pageserver's local directory,
     /// where certain tenant's tenantconf file should be located.
     ///
@@ -850,6 +1347,19 @@ impl PageServerConf {
             .join(TENANT_HEATMAP_BASENAME)
     }
 
+    pub(crate) fn tenant_format_version_path(
+        &self,
+        tenant_shard_id: &TenantShardId,
+    ) -> Utf8PathBuf {
+        self.tenant_path(tenant_shard_id)
+            .join(TENANT_FORMAT_VERSION_NAME)
+    }
+
+    pub(crate) fn tenant_legal_hold_path(&self, tenant_shard_id: &TenantShardId) -> Utf8PathBuf {
+        self.tenant_path(tenant_shard_id)
+            .join(TENANT_LEGAL_HOLD_NAME)
+    }
+
     pub fn timelines_path(&self, tenant_shard_id: &TenantShardId) -> Utf8PathBuf {
         self.tenant_path(tenant_shard_id)
             .join(TIMELINES_SEGMENT_NAME)
@@ -899,6 +1409,22 @@ impl PageServerConf {
             .join(connection_id.to_string())
     }
 
+    pub fn wal_captures_path(&self) -> Utf8PathBuf {
+        self.workdir.join("wal_captures")
+    }
+
+    pub fn wal_capture_path(
+        &self,
+        tenant_shard_id: &TenantShardId,
+        timeline_id: &TimelineId,
+        connection_id: &ConnectionId,
+    ) -> Utf8PathBuf {
+        self.wal_captures_path()
+            .join(tenant_shard_id.to_string())
+            .join(timeline_id.to_string())
+            .join(connection_id.to_string())
+    }
+
     /// Turns storage remote path of a file into its local path.
     pub fn local_path(&self, remote_path: &RemotePath) -> Utf8PathBuf {
         remote_path.with_base(&self.workdir)
@@ -908,13 +1434,10 @@ impl PageServerConf {
     // Postgres distribution paths
     //
     pub fn pg_distrib_dir(&self, pg_version: u32) -> anyhow::Result<Utf8PathBuf> {
-        let path = self.pg_distrib_dir.clone();
-
-        #[allow(clippy::manual_range_patterns)]
-        match pg_version {
-            14 | 15 | 16 => Ok(path.join(format!("v{pg_version}"))),
-            _ => bail!("Unsupported postgres version: {}", pg_version),
+        if !crate::pg_versions::SUPPORTED_PG_VERSIONS.contains(&pg_version) {
+            bail!("Unsupported postgres version: {}", pg_version);
         }
+        Ok(self.pg_distrib_dir.join(format!("v{pg_version}")))
     }
 
     pub fn pg_bin_dir(&self, pg_version: u32) -> anyhow::Result<Utf8PathBuf> {
@@ -957,6 +1480,13 @@ impl PageServerConf {
                 "remote_storage" => {
                     builder.remote_storage_config(RemoteStorageConfig::from_toml(item)?)
                 }
+                "hot_tier" => builder.hot_tier(Some(HotTierConfig::from_toml(item)?)),
+                "page_service_tls" => {
+                    builder.page_service_tls(Some(
+                        deserialize_from_item("page_service_tls", item)
+                            .context("parse page_service_tls")?
+                    ))
+                }
                 "tenant_config" => {
                     t_conf = TenantConfOpt::try_from(item.to_owned()).context(format!("failed to parse: '{key}'"))?;
                 }
@@ -1044,9 +1574,98 @@ impl PageServerConf {
                 "ephemeral_bytes_per_memory_kb" => {
                     builder.get_ephemeral_bytes_per_memory_kb(parse_toml_u64("ephemeral_bytes_per_memory_kb", item)? as usize)
                 }
+                // Relative to the workdir, like the tenants directory.
+                "ephemeral_dir" => builder.ephemeral_dir(Some(workdir.join(parse_toml_string(key, item)?))),
+                "ephemeral_dir_max_bytes" => builder.ephemeral_dir_max_bytes(parse_toml_u64(key, item)?),
+                "bundle_dir" => builder.bundle_dir(Some(workdir.join(parse_toml_string(key, item)?))),
+                "wal_archive_restore_command" => builder.wal_archive_restore_command(Some(parse_toml_string(key, item)?)),
                 "walredo_process_kind" => {
                     builder.get_walredo_process_kind(parse_toml_from_str("walredo_process_kind", item)?)
                 }
+                "walredo_recycle" => {
+                    builder.walredo_recycle(
+                        deserialize_from_item("walredo_recycle", item)
+                            .context("parse walredo_recycle")?
+                    )
+                }
+                "runtimes" => {
+                    builder.runtimes(
+                        deserialize_from_item("runtimes", item)
+                            .context("parse runtimes")?
+                    )
+                }
+                "read_priority" => {
+                    builder.read_priority(
+                        deserialize_from_item("read_priority", item)
+                            .context("parse read_priority")?
+                    )
+                }
+                "detached_tenant_retention" => builder.detached_tenant_retention(parse_toml_duration(key, item)?),
+                "secondary_index_refresh_period" => builder.secondary_index_refresh_period(parse_toml_duration(key, item)?),
+                "secondary_index_refresh_concurrency" => {
+                    builder.secondary_index_refresh_concurrency(parse_toml_u64(key, item)? as usize)
+                }
+                "secondary_index_refresh_batch_size" => {
+                    builder.secondary_index_refresh_batch_size(parse_toml_u64(key, item)? as usize)
+                }
+                "metadata_fsync_batch_window" => builder.metadata_fsync_batch_window(parse_toml_duration(key, item)?),
+                "eviction_bounce_window" => builder.eviction_bounce_window(parse_toml_duration(key, item)?),
+                "eviction_bounce_cooldown" => builder.eviction_bounce_cooldown(parse_toml_duration(key, item)?),
+                "index_part_compression_threshold" => {
+                    builder.index_part_compression_threshold(parse_toml_u64(key, item)? as usize)
+                }
+                "startup_repair" => builder.startup_repair(parse_toml_bool(key, item)?),
+                "layer_verification_period" => builder.layer_verification_period(parse_toml_duration(key, item)?),
+                "temp_file_janitor_period" => builder.temp_file_janitor_period(parse_toml_duration(key, item)?),
+                "temp_file_max_age" => builder.temp_file_max_age(parse_toml_duration(key, item)?),
+                "broker_fallback_endpoints" => builder.broker_fallback_endpoints(
+                    deserialize_from_item::<Vec<String>>(key, item)?
+                        .iter()
+                        .map(|endpoint| endpoint.parse())
+                        .collect::<Result<_, _>>()
+                        .context("failed to parse broker fallback endpoints")?,
+                ),
+                "upload_backlog_pacing" => {
+                    let pacing: UploadBacklogPacingConfig = deserialize_from_item(key, item)
+                        .context("parse upload_backlog_pacing")?;
+                    ensure!(
+                        pacing.low_bytes <= pacing.high_bytes,
+                        "upload_backlog_pacing: low_bytes must not exceed high_bytes"
+                    );
+                    builder.upload_backlog_pacing(Some(pacing))
+                }
+                "wal_receiver_protocol" => builder.wal_receiver_protocol(
+                    deserialize_from_item(key, item).context("parse wal_receiver_protocol")?,
+                ),
+                "tenant_tag_labels" => {
+                    let labels: Vec<String> =
+                        deserialize_from_item(key, item).context("parse tenant_tag_labels")?;
+                    for label in &labels {
+                        crate::tenant::tags::validate_tag_key(label)
+                            .context("tenant_tag_labels")?;
+                    }
+                    builder.tenant_tag_labels(labels)
+                }
+                "getpage_latency_slo" => {
+                    let slo: GetPageLatencySloConfig =
+                        deserialize_from_item(key, item).context("parse getpage_latency_slo")?;
+                    ensure!(
+                        slo.objective > 0.0 && slo.objective < 1.0,
+                        "getpage_latency_slo: objective must be between 0 and 1"
+                    );
+                    builder.getpage_latency_slo(slo)
+                }
+                "tenant_data_dirs" => {
+                    let data_dirs: Vec<Utf8PathBuf> =
+                        deserialize_from_item(key, item).context("parse tenant_data_dirs")?;
+                    // Relative paths are relative to the workdir, like the tenants directory.
+                    builder.tenant_data_dirs(data_dirs.iter().map(|dir| workdir.join(dir)).collect())
+                }
+                "tenant_data_dir_placement" => builder.tenant_data_dir_placement(
+                    parse_toml_from_str("tenant_data_dir_placement", item)?,
+                ),
+                "timeline_access_log_retention" => builder.timeline_access_log_retention(parse_toml_duration(key, item)?),
+                "initdb_cache" => builder.initdb_cache(parse_toml_bool(key, item)?),
                 _ => bail!("unrecognized pageserver option '{key}'"),
             }
         }
@@ -1065,12 +1684,12 @@ impl PageServerConf {
             );
         }
 
-        conf.default_tenant_conf = t_conf.merge(TenantConf::default());
+        conf.default_tenant_conf = Reloadable::new(t_conf.merge(TenantConf::default()));
 
         Ok(conf)
     }
 
-    #[cfg(test)]
+    #[cfg(any(feature = "testing", test))]
     pub fn test_repo_dir(test_name: &str) -> Utf8PathBuf {
         let test_output_dir = std::env::var("TEST_OUTPUT").unwrap_or("../tmp_check".into());
         Utf8PathBuf::from(format!("{test_output_dir}/test_{test_name}"))
@@ -1094,8 +1713,10 @@ impl PageServerConf {
             http_auth_type: AuthType::Trust,
             pg_auth_type: AuthType::Trust,
             auth_validation_public_key_path: None,
+            page_service_tls: None,
             remote_storage_config: None,
-            default_tenant_conf: TenantConf::default(),
+            hot_tier: None,
+            default_tenant_conf: Reloadable::new(TenantConf::default()),
             broker_endpoint: storage_broker::DEFAULT_ENDPOINT.parse().unwrap(),
             broker_keepalive_interval: Duration::from_secs(5000),
             log_format: LogFormat::from_str(defaults::DEFAULT_LOG_FORMAT).unwrap(),
@@ -1111,7 +1732,7 @@ impl PageServerConf {
             metric_collection_endpoint: defaults::DEFAULT_METRIC_COLLECTION_ENDPOINT,
             metric_collection_bucket: None,
             synthetic_size_calculation_interval: Duration::from_secs(60),
-            disk_usage_based_eviction: None,
+            disk_usage_based_eviction: Reloadable::new(None),
             test_remote_failures: 0,
             ondemand_download_behavior_treat_error_as_warn: false,
             background_task_maximum_delay: Duration::ZERO,
@@ -1130,7 +1751,47 @@ impl PageServerConf {
             ),
             validate_vectored_get: defaults::DEFAULT_VALIDATE_VECTORED_GET,
             ephemeral_bytes_per_memory_kb: defaults::DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB,
+            ephemeral_dir: None,
+            ephemeral_dir_max_bytes: defaults::DEFAULT_EPHEMERAL_DIR_MAX_BYTES,
+            bundle_dir: None,
+            wal_archive_restore_command: None,
             walredo_process_kind: defaults::DEFAULT_WALREDO_PROCESS_KIND.parse().unwrap(),
+            walredo_recycle: crate::walredo::RecycleConfig::default(),
+            runtimes: RuntimesConfig::default(),
+            read_priority: ReadPriorityConfig::default(),
+            detached_tenant_retention: Duration::ZERO,
+            secondary_index_refresh_period: humantime::parse_duration(
+                defaults::DEFAULT_SECONDARY_INDEX_REFRESH_PERIOD,
+            )
+            .unwrap(),
+            secondary_index_refresh_concurrency:
+                defaults::DEFAULT_SECONDARY_INDEX_REFRESH_CONCURRENCY,
+            secondary_index_refresh_batch_size:
+                defaults::DEFAULT_SECONDARY_INDEX_REFRESH_BATCH_SIZE,
+            metadata_fsync_batch_window: Duration::ZERO,
+            eviction_bounce_window: humantime::parse_duration(
+                defaults::DEFAULT_EVICTION_BOUNCE_WINDOW,
+            )
+            .unwrap(),
+            eviction_bounce_cooldown: humantime::parse_duration(
+                defaults::DEFAULT_EVICTION_BOUNCE_COOLDOWN,
+            )
+            .unwrap(),
+            index_part_compression_threshold: defaults::DEFAULT_INDEX_PART_COMPRESSION_THRESHOLD,
+            startup_repair: defaults::DEFAULT_STARTUP_REPAIR,
+            layer_verification_period: Duration::ZERO,
+            temp_file_janitor_period: Duration::ZERO,
+            temp_file_max_age: humantime::parse_duration(defaults::DEFAULT_TEMP_FILE_MAX_AGE)
+                .unwrap(),
+            broker_fallback_endpoints: Vec::new(),
+            upload_backlog_pacing: None,
+            wal_receiver_protocol: WalReceiverProtocol::default(),
+            tenant_tag_labels: Vec::new(),
+            getpage_latency_slo: GetPageLatencySloConfig::default(),
+            tenant_data_dirs: Vec::new(),
+            tenant_data_dir_placement: defaults::DEFAULT_TENANT_DATA_DIR_PLACEMENT.parse().unwrap(),
+            timeline_access_log_retention: Duration::ZERO,
+            initdb_cache: defaults::DEFAULT_INITDB_CACHE,
         }
     }
 }
@@ -1266,6 +1927,7 @@ mod tests {
     use utils::serde_percent::Percent;
 
     use super::*;
+    use crate::task_mgr::{CpuList, RuntimeConfig};
     use crate::DEFAULT_PG_VERSION;
 
     const ALL_BASE_VALUES_TOML: &str = r#"
@@ -1325,8 +1987,10 @@ background_task_maximum_delay = '334 s'
                 http_auth_type: AuthType::Trust,
                 pg_auth_type: AuthType::Trust,
                 auth_validation_public_key_path: None,
+                page_service_tls: None,
                 remote_storage_config: None,
-                default_tenant_conf: TenantConf::default(),
+                hot_tier: None,
+                default_tenant_conf: Reloadable::new(TenantConf::default()),
                 broker_endpoint: storage_broker::DEFAULT_ENDPOINT.parse().unwrap(),
                 broker_keepalive_interval: humantime::parse_duration(
                     storage_broker::DEFAULT_KEEPALIVE_INTERVAL
@@ -1349,7 +2013,7 @@ background_task_maximum_delay = '334 s'
                 synthetic_size_calculation_interval: humantime::parse_duration(
                     defaults::DEFAULT_SYNTHETIC_SIZE_CALCULATION_INTERVAL
                 )?,
-                disk_usage_based_eviction: None,
+                disk_usage_based_eviction: Reloadable::new(None),
                 test_remote_failures: 0,
                 ondemand_download_behavior_treat_error_as_warn: false,
                 background_task_maximum_delay: humantime::parse_duration(
@@ -1370,7 +2034,56 @@ background_task_maximum_delay = '334 s'
                 ),
                 validate_vectored_get: defaults::DEFAULT_VALIDATE_VECTORED_GET,
                 ephemeral_bytes_per_memory_kb: defaults::DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB,
+                ephemeral_dir: None,
+                ephemeral_dir_max_bytes: defaults::DEFAULT_EPHEMERAL_DIR_MAX_BYTES,
+                bundle_dir: None,
+                wal_archive_restore_command: None,
                 walredo_process_kind: defaults::DEFAULT_WALREDO_PROCESS_KIND.parse().unwrap(),
+                walredo_recycle: crate::walredo::RecycleConfig::default(),
+                runtimes: RuntimesConfig::default(),
+                read_priority: ReadPriorityConfig::default(),
+                detached_tenant_retention: humantime::parse_duration(
+                    defaults::DEFAULT_DETACHED_TENANT_RETENTION
+                )?,
+                secondary_index_refresh_period: humantime::parse_duration(
+                    defaults::DEFAULT_SECONDARY_INDEX_REFRESH_PERIOD
+                )?,
+                secondary_index_refresh_concurrency:
+                    defaults::DEFAULT_SECONDARY_INDEX_REFRESH_CONCURRENCY,
+                secondary_index_refresh_batch_size:
+                    defaults::DEFAULT_SECONDARY_INDEX_REFRESH_BATCH_SIZE,
+                metadata_fsync_batch_window: humantime::parse_duration(
+                    defaults::DEFAULT_METADATA_FSYNC_BATCH_WINDOW
+                )?,
+                eviction_bounce_window: humantime::parse_duration(
+                    defaults::DEFAULT_EVICTION_BOUNCE_WINDOW
+                )?,
+                eviction_bounce_cooldown: humantime::parse_duration(
+                    defaults::DEFAULT_EVICTION_BOUNCE_COOLDOWN
+                )?,
+                index_part_compression_threshold:
+                    defaults::DEFAULT_INDEX_PART_COMPRESSION_THRESHOLD,
+                startup_repair: defaults::DEFAULT_STARTUP_REPAIR,
+                layer_verification_period: humantime::parse_duration(
+                    defaults::DEFAULT_LAYER_VERIFICATION_PERIOD
+                )?,
+                temp_file_janitor_period: humantime::parse_duration(
+                    defaults::DEFAULT_TEMP_FILE_JANITOR_PERIOD
+                )?,
+                temp_file_max_age: humantime::parse_duration(defaults::DEFAULT_TEMP_FILE_MAX_AGE)?,
+                broker_fallback_endpoints: Vec::new(),
+                upload_backlog_pacing: None,
+                wal_receiver_protocol: WalReceiverProtocol::default(),
+                tenant_tag_labels: Vec::new(),
+                getpage_latency_slo: GetPageLatencySloConfig::default(),
+                tenant_data_dirs: Vec::new(),
+                tenant_data_dir_placement: defaults::DEFAULT_TENANT_DATA_DIR_PLACEMENT
+                    .parse()
+                    .unwrap(),
+                timeline_access_log_retention: humantime::parse_duration(
+                    defaults::DEFAULT_TIMELINE_ACCESS_LOG_RETENTION
+                )?,
+                initdb_cache: defaults::DEFAULT_INITDB_CACHE,
             },
             "Correct defaults should be used when no config values are provided"
         );
@@ -1409,8 +2122,10 @@ background_task_maximum_delay = '334 s'
                 http_auth_type: AuthType::Trust,
                 pg_auth_type: AuthType::Trust,
                 auth_validation_public_key_path: None,
+                page_service_tls: None,
                 remote_storage_config: None,
-                default_tenant_conf: TenantConf::default(),
+                hot_tier: None,
+                default_tenant_conf: Reloadable::new(TenantConf::default()),
                 broker_endpoint: storage_broker::DEFAULT_ENDPOINT.parse().unwrap(),
                 broker_keepalive_interval: Duration::from_secs(5),
                 log_format: LogFormat::Json,
@@ -1425,7 +2140,7 @@ background_task_maximum_delay = '334 s'
                 metric_collection_endpoint: Some(Url::parse("http://localhost:80/metrics")?),
                 metric_collection_bucket: None,
                 synthetic_size_calculation_interval: Duration::from_secs(333),
-                disk_usage_based_eviction: None,
+                disk_usage_based_eviction: Reloadable::new(None),
                 test_remote_failures: 0,
                 ondemand_download_behavior_treat_error_as_warn: false,
                 background_task_maximum_delay: Duration::from_secs(334),
@@ -1444,7 +2159,56 @@ background_task_maximum_delay = '334 s'
                 ),
                 validate_vectored_get: defaults::DEFAULT_VALIDATE_VECTORED_GET,
                 ephemeral_bytes_per_memory_kb: defaults::DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB,
+                ephemeral_dir: None,
+                ephemeral_dir_max_bytes: defaults::DEFAULT_EPHEMERAL_DIR_MAX_BYTES,
+                bundle_dir: None,
+                wal_archive_restore_command: None,
                 walredo_process_kind: defaults::DEFAULT_WALREDO_PROCESS_KIND.parse().unwrap(),
+                walredo_recycle: crate::walredo::RecycleConfig::default(),
+                runtimes: RuntimesConfig::default(),
+                read_priority: ReadPriorityConfig::default(),
+                detached_tenant_retention: humantime::parse_duration(
+                    defaults::DEFAULT_DETACHED_TENANT_RETENTION
+                )?,
+                secondary_index_refresh_period: humantime::parse_duration(
+                    defaults::DEFAULT_SECONDARY_INDEX_REFRESH_PERIOD
+                )?,
+                secondary_index_refresh_concurrency:
+                    defaults::DEFAULT_SECONDARY_INDEX_REFRESH_CONCURRENCY,
+                secondary_index_refresh_batch_size:
+                    defaults::DEFAULT_SECONDARY_INDEX_REFRESH_BATCH_SIZE,
+                metadata_fsync_batch_window: humantime::parse_duration(
+                    defaults::DEFAULT_METADATA_FSYNC_BATCH_WINDOW
+                )?,
+                eviction_bounce_window: humantime::parse_duration(
+                    defaults::DEFAULT_EVICTION_BOUNCE_WINDOW
+                )?,
+                eviction_bounce_cooldown: humantime::parse_duration(
+                    defaults::DEFAULT_EVICTION_BOUNCE_COOLDOWN
+                )?,
+                index_part_compression_threshold:
+                    defaults::DEFAULT_INDEX_PART_COMPRESSION_THRESHOLD,
+                startup_repair: defaults::DEFAULT_STARTUP_REPAIR,
+                layer_verification_period: humantime::parse_duration(
+                    defaults::DEFAULT_LAYER_VERIFICATION_PERIOD
+                )?,
+                temp_file_janitor_period: humantime::parse_duration(
+                    defaults::DEFAULT_TEMP_FILE_JANITOR_PERIOD
+                )?,
+                temp_file_max_age: humantime::parse_duration(defaults::DEFAULT_TEMP_FILE_MAX_AGE)?,
+                broker_fallback_endpoints: Vec::new(),
+                upload_backlog_pacing: None,
+                wal_receiver_protocol: WalReceiverProtocol::default(),
+                tenant_tag_labels: Vec::new(),
+                getpage_latency_slo: GetPageLatencySloConfig::default(),
+                tenant_data_dirs: Vec::new(),
+                tenant_data_dir_placement: defaults::DEFAULT_TENANT_DATA_DIR_PLACEMENT
+                    .parse()
+                    .unwrap(),
+                timeline_access_log_retention: humantime::parse_duration(
+                    defaults::DEFAULT_TIMELINE_ACCESS_LOG_RETENTION
+                )?,
+                initdb_cache: defaults::DEFAULT_INITDB_CACHE,
             },
             "Should be able to parse all basic config values correctly"
         );
@@ -1588,7 +2352,7 @@ trace_read_requests = {trace_read_requests}"#,
 
         let conf = PageServerConf::parse_and_validate(&toml, &workdir)?;
         assert_eq!(
-            conf.default_tenant_conf.trace_read_requests, trace_read_requests,
+            conf.default_tenant_conf.load().trace_read_requests, trace_read_requests,
             "Tenant config from pageserver config file should be parsed and udpated values used as defaults for all tenants",
         );
 
@@ -1665,12 +2429,13 @@ threshold = "20m"
         );
         assert_eq!(
             conf.default_tenant_conf
+                .load()
                 .evictions_low_residence_duration_metric_threshold,
             Duration::from_secs(20 * 60)
         );
         assert_eq!(conf.id, NodeId(222));
         assert_eq!(
-            conf.disk_usage_based_eviction,
+            **conf.disk_usage_based_eviction.load(),
             Some(DiskUsageEvictionTaskConfig {
                 max_usage_pct: Percent::new(80).unwrap(),
                 min_avail_bytes: 0,
@@ -1678,10 +2443,12 @@ threshold = "20m"
                 #[cfg(feature = "testing")]
                 mock_statvfs: None,
                 eviction_order: crate::disk_usage_eviction_task::EvictionOrder::AbsoluteAccessed,
+                forecast_horizon: None,
+                offload: None,
             })
         );
 
-        match &conf.default_tenant_conf.eviction_policy {
+        match &conf.default_tenant_conf.load().eviction_policy {
             EvictionPolicy::LayerAccessThreshold(eviction_threshold) => {
                 assert_eq!(eviction_threshold.period, Duration::from_secs(20 * 60));
                 assert_eq!(eviction_threshold.threshold, Duration::from_secs(20 * 60));
@@ -1715,7 +2482,7 @@ threshold = "20m"
         let toml: Document = pageserver_conf_toml.parse().unwrap();
         let conf = PageServerConf::parse_and_validate(&toml, &workdir).unwrap();
 
-        match &conf.default_tenant_conf.eviction_policy {
+        match &conf.default_tenant_conf.load().eviction_policy {
             EvictionPolicy::OnlyImitiate(t) => {
                 assert_eq!(t.period, Duration::from_secs(20 * 60));
                 assert_eq!(t.threshold, Duration::from_secs(20 * 60));
@@ -1724,6 +2491,211 @@ threshold = "20m"
         }
     }
 
+    #[test]
+    fn parse_runtimes_config() {
+        let tempdir = tempdir().unwrap();
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir).unwrap();
+
+        let pageserver_conf_toml = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+
+[runtimes]
+compute_request = {{ worker_threads = 8, cpus = "0-3,6,4-5,7" }}
+
+[runtimes.background]
+worker_threads = 2
+numa_node = 1
+"#,
+        );
+        let toml: Document = pageserver_conf_toml.parse().unwrap();
+        let conf = PageServerConf::parse_and_validate(&toml, &workdir).unwrap();
+
+        assert_eq!(
+            conf.runtimes.compute_request,
+            RuntimeConfig {
+                worker_threads: Some(NonZeroUsize::new(8).unwrap()),
+                cpus: Some("0-7".parse().unwrap()),
+                numa_node: None,
+            }
+        );
+        assert_eq!(
+            conf.runtimes.background,
+            RuntimeConfig {
+                worker_threads: Some(NonZeroUsize::new(2).unwrap()),
+                cpus: None,
+                numa_node: Some(1),
+            }
+        );
+        assert_eq!(conf.runtimes.walreceiver, RuntimeConfig::default());
+
+        for invalid in ["", "1-", "3-1", "a", "0,,1"] {
+            assert!(
+                invalid.parse::<CpuList>().is_err(),
+                "{invalid:?} should not parse"
+            );
+        }
+
+        let toml: Document = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+
+[runtimes.compute_request]
+worker_threads = 0
+"#
+        )
+        .parse()
+        .unwrap();
+        PageServerConf::parse_and_validate(&toml, &workdir)
+            .expect_err("zero worker threads should be rejected");
+    }
+
+    #[test]
+    fn parse_read_priority_config() {
+        let tempdir = tempdir().unwrap();
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir).unwrap();
+
+        let pageserver_conf_toml = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+
+[read_priority]
+background_concurrency = 4
+foreground_latency_budget = "5ms"
+"#,
+        );
+        let toml: Document = pageserver_conf_toml.parse().unwrap();
+        let conf = PageServerConf::parse_and_validate(&toml, &workdir).unwrap();
+
+        assert_eq!(
+            conf.read_priority,
+            ReadPriorityConfig {
+                background_concurrency: 4,
+                foreground_latency_budget: Duration::from_millis(5),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_hot_tier_config() {
+        let tempdir = tempdir().unwrap();
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir).unwrap();
+        let hot_storage_path = tempdir.path().join("hot_storage");
+
+        let pageserver_conf_toml = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+
+[hot_tier]
+max_age = "10m"
+
+[hot_tier.remote_storage]
+local_path = "{hot_storage_path}"
+"#,
+        );
+        let toml: Document = pageserver_conf_toml.parse().unwrap();
+        let conf = PageServerConf::parse_and_validate(&toml, &workdir).unwrap();
+
+        let hot_tier = conf.hot_tier.expect("hot tier should be configured");
+        assert_eq!(hot_tier.max_age, Duration::from_secs(600));
+        assert_eq!(
+            hot_tier.remote_storage.storage,
+            RemoteStorageKind::LocalFs(hot_storage_path)
+        );
+    }
+
+    #[test]
+    fn parse_page_service_tls_config() {
+        let tempdir = tempdir().unwrap();
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir).unwrap();
+
+        let pageserver_conf_toml = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+
+[page_service_tls]
+cert_path = "server.crt"
+key_path = "server.key"
+"#,
+        );
+        let toml: Document = pageserver_conf_toml.parse().unwrap();
+        let conf = PageServerConf::parse_and_validate(&toml, &workdir).unwrap();
+
+        assert_eq!(
+            conf.page_service_tls,
+            Some(PageServiceTlsConfig {
+                cert_path: Utf8PathBuf::from("server.crt"),
+                key_path: Utf8PathBuf::from("server.key"),
+                client_ca_path: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_upload_backlog_pacing_config() {
+        let tempdir = tempdir().unwrap();
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir).unwrap();
+
+        let pageserver_conf_toml = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+upload_backlog_pacing = {{ high_bytes = 2000, low_bytes = 1000, max_paced_bytes = 3000 }}
+"#,
+        );
+        let toml: Document = pageserver_conf_toml.parse().unwrap();
+        let conf = PageServerConf::parse_and_validate(&toml, &workdir).unwrap();
+        assert_eq!(
+            conf.upload_backlog_pacing,
+            Some(UploadBacklogPacingConfig {
+                high_bytes: 2000,
+                low_bytes: 1000,
+                max_paced_bytes: 3000,
+            })
+        );
+
+        let pageserver_conf_toml = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+upload_backlog_pacing = {{ high_bytes = 1000, low_bytes = 2000 }}
+"#,
+        );
+        let toml: Document = pageserver_conf_toml.parse().unwrap();
+        PageServerConf::parse_and_validate(&toml, &workdir).unwrap_err();
+    }
+
+    #[test]
+    fn parse_wal_receiver_protocol() {
+        let tempdir = tempdir().unwrap();
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir).unwrap();
+
+        let pageserver_conf_toml = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+wal_receiver_protocol = {{ type = "grpc", compression = true }}
+"#,
+        );
+        let toml: Document = pageserver_conf_toml.parse().unwrap();
+        let conf = PageServerConf::parse_and_validate(&toml, &workdir).unwrap();
+        assert_eq!(
+            conf.wal_receiver_protocol,
+            WalReceiverProtocol::Grpc {
+                compression: true,
+                window_bytes: 16 * 1024 * 1024,
+            }
+        );
+
+        let pageserver_conf_toml = format!(
+            r#"pg_distrib_dir = "{pg_distrib_dir}"
+id = 222
+wal_receiver_protocol = {{ type = "postgres" }}
+"#,
+        );
+        let toml: Document = pageserver_conf_toml.parse().unwrap();
+        let conf = PageServerConf::parse_and_validate(&toml, &workdir).unwrap();
+        assert_eq!(conf.wal_receiver_protocol, WalReceiverProtocol::Postgres);
+    }
+
     fn prepare_fs(tempdir: &Utf8TempDir) -> anyhow::Result<(Utf8PathBuf, Utf8PathBuf)> {
         let tempdir_path = tempdir.path();
 
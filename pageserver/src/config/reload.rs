@@ -0,0 +1,372 @@
+//! Reloading the pageserver configuration without a restart.
+//!
+//! Most of [`PageServerConf`] is read once at startup, and changing it requires a restart, which
+//! is downtime for all tenants of the pageserver. The settings below can be changed at runtime
+//! instead, with SIGHUP or the `/v1/reload_config` API:
+//! - `tenant_config`, the defaults of tenant configurations. They include the eviction policy
+//!   and the periods of compaction and GC, which background loops pick up on their next
+//!   iteration.
+//! - `disk_usage_based_eviction`, its thresholds and period. Enabling or disabling it requires
+//!   a restart.
+//! - `remote_storage.concurrency_limit`.
+//!
+//! The page cache is allocated at startup, so `page_cache_size` is not among them.
+//!
+//! A reload reads the config file again, applies the command line overrides given at startup,
+//! and compares the result with the current configuration. If anything else than the settings
+//! above changed, the reload is rejected as a whole, with a report of the changes which require
+//! a restart.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use arc_swap::{ArcSwap, Guard};
+use camino::Utf8PathBuf;
+use once_cell::sync::OnceCell;
+use pageserver_api::models::{ConfigChange, ConfigReloadResponse};
+use remote_storage::GenericRemoteStorage;
+use serde_json::Value;
+use toml_edit::Document;
+use tracing::{info, warn};
+
+use super::defaults::DEFAULT_CONFIG_FILE;
+use super::PageServerConf;
+use crate::tenant::mgr::TenantManager;
+
+/// A setting which can be changed by a config reload.
+pub struct Reloadable<T>(ArcSwap<T>);
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Reloadable(ArcSwap::from_pointee(value))
+    }
+
+    pub fn load(&self) -> Guard<Arc<T>> {
+        self.0.load()
+    }
+
+    fn load_full(&self) -> Arc<T> {
+        self.0.load_full()
+    }
+
+    fn store(&self, value: Arc<T>) {
+        self.0.store(value)
+    }
+}
+
+impl<T> Clone for Reloadable<T> {
+    fn clone(&self) -> Self {
+        Reloadable(ArcSwap::new(self.0.load_full()))
+    }
+}
+
+impl<T: Debug> Debug for Reloadable<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.load().fmt(f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Reloadable<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self.load() == **other.load()
+    }
+}
+
+impl<T: Eq> Eq for Reloadable<T> {}
+
+/// Settings which can be changed by a reload, as paths of keys in the config file.
+const RELOADABLE: &[&[&str]] = &[
+    &["tenant_config"],
+    &["disk_usage_based_eviction"],
+    &["remote_storage", "concurrency_limit"],
+];
+
+/// Where the configuration comes from: the config file, patched with the `-c` overrides from
+/// the command line.
+pub struct ConfigSource {
+    path: Utf8PathBuf,
+    overrides: Vec<Document>,
+}
+
+impl ConfigSource {
+    pub fn new(path: Utf8PathBuf, overrides: Vec<Document>) -> Self {
+        ConfigSource { path, overrides }
+    }
+
+    pub fn apply_overrides(&self, mut config: Document) -> Document {
+        for doc in &self.overrides {
+            for (key, item) in doc.iter() {
+                config.insert(key, item.clone());
+            }
+        }
+        config
+    }
+
+    fn read(&self) -> anyhow::Result<Document> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DEFAULT_CONFIG_FILE.to_owned(),
+            Err(e) => return Err(e).with_context(|| format!("read config file {}", self.path)),
+        };
+        let doc = contents.parse().context("parse config file toml")?;
+        Ok(self.apply_overrides(doc))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReloadError {
+    #[error("config reload is not initialized")]
+    NotInitialized,
+    #[error("failed to read config: {0:#}")]
+    Read(anyhow::Error),
+    #[error("invalid config: {0:#}")]
+    Invalid(anyhow::Error),
+}
+
+struct Reloader {
+    conf: &'static PageServerConf,
+    source: ConfigSource,
+    /// The effective configuration, as of startup or the last successful reload.
+    current: Mutex<Document>,
+    remote_storage: Option<GenericRemoteStorage>,
+    tenant_manager: Arc<TenantManager>,
+}
+
+static RELOADER: OnceCell<Reloader> = OnceCell::new();
+
+/// Enable config reloads. `current` is the configuration `conf` was parsed from.
+pub fn init(
+    conf: &'static PageServerConf,
+    source: ConfigSource,
+    current: Document,
+    remote_storage: Option<GenericRemoteStorage>,
+    tenant_manager: Arc<TenantManager>,
+) {
+    let reloader = Reloader {
+        conf,
+        source,
+        current: Mutex::new(current),
+        remote_storage,
+        tenant_manager,
+    };
+    if RELOADER.set(reloader).is_err() {
+        panic!("config reload initialized twice");
+    }
+}
+
+/// Reload the configuration. Changes which require a restart are reported in
+/// [`ConfigReloadResponse::rejected`], in which case nothing is applied.
+///
+/// Blocks on reading the config file, but must be called within a tokio runtime.
+pub fn reload() -> Result<ConfigReloadResponse, ReloadError> {
+    let reloader = RELOADER.get().ok_or(ReloadError::NotInitialized)?;
+    let mut current = reloader.current.lock().unwrap();
+
+    let new = reloader.source.read().map_err(ReloadError::Read)?;
+    let new_conf = PageServerConf::parse_and_validate(&new, &reloader.conf.workdir)
+        .map_err(ReloadError::Invalid)?;
+
+    let (applied, mut rejected) = diff(&current, &new).map_err(ReloadError::Invalid)?;
+    // The eviction task is launched at startup only if configured.
+    let eviction_toggled = reloader.conf.disk_usage_based_eviction.load().is_some()
+        != new_conf.disk_usage_based_eviction.load().is_some();
+    let (applied, toggled): (Vec<_>, Vec<_>) = applied
+        .into_iter()
+        .partition(|change| !(eviction_toggled && change.key == "disk_usage_based_eviction"));
+    rejected.extend(toggled);
+
+    if !rejected.is_empty() {
+        let keys = rejected.iter().map(|c| c.key.as_str()).collect::<Vec<_>>();
+        warn!("rejecting config reload, changes to {keys:?} require a restart");
+        return Ok(ConfigReloadResponse {
+            applied: Vec::new(),
+            rejected,
+        });
+    }
+
+    for change in &applied {
+        reloader.apply(&change.key, &new_conf);
+    }
+    *current = new;
+
+    let keys = applied.iter().map(|c| c.key.as_str()).collect::<Vec<_>>();
+    info!("reloaded config, applied changes to {keys:?}");
+    Ok(ConfigReloadResponse {
+        applied,
+        rejected: Vec::new(),
+    })
+}
+
+impl Reloader {
+    fn apply(&self, key: &str, new_conf: &PageServerConf) {
+        match key {
+            "tenant_config" => {
+                self.conf
+                    .default_tenant_conf
+                    .store(new_conf.default_tenant_conf.load_full());
+                for tenant in self.tenant_manager.get_attached_active_tenant_shards() {
+                    tenant.default_tenant_conf_updated();
+                }
+            }
+            "disk_usage_based_eviction" => self
+                .conf
+                .disk_usage_based_eviction
+                .store(new_conf.disk_usage_based_eviction.load_full()),
+            "remote_storage.concurrency_limit" => {
+                let limit = new_conf
+                    .remote_storage_config
+                    .as_ref()
+                    .and_then(|config| config.storage.concurrency_limit());
+                if let (Some(storage), Some(limit)) = (&self.remote_storage, limit) {
+                    storage.set_concurrency_limit(limit);
+                }
+            }
+            _ => unreachable!("{key} is not reloadable"),
+        }
+    }
+}
+
+/// Compare two configurations, returning the changes to reloadable settings and the others.
+fn diff(old: &Document, new: &Document) -> anyhow::Result<(Vec<ConfigChange>, Vec<ConfigChange>)> {
+    let to_json = |doc: &Document| -> anyhow::Result<Value> {
+        toml_edit::de::from_document(doc.clone()).context("convert config")
+    };
+    let (old, new) = (to_json(old)?, to_json(new)?);
+
+    let (mut applied, mut rejected) = (Vec::new(), Vec::new());
+    diff_values(
+        &mut Vec::new(),
+        Some(&old),
+        Some(&new),
+        &mut applied,
+        &mut rejected,
+    );
+    Ok((applied, rejected))
+}
+
+fn diff_values<'a>(
+    path: &mut Vec<&'a str>,
+    old: Option<&'a Value>,
+    new: Option<&'a Value>,
+    applied: &mut Vec<ConfigChange>,
+    rejected: &mut Vec<ConfigChange>,
+) {
+    if old == new {
+        return;
+    }
+    let reloadable = RELOADABLE.iter().any(|r| *r == path.as_slice());
+    // Tables which contain reloadable settings are compared key by key.
+    let contains_reloadable = RELOADABLE
+        .iter()
+        .any(|r| r.len() > path.len() && r[..path.len()] == path[..]);
+
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new)))
+            if !reloadable && contains_reloadable =>
+        {
+            let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                path.push(key);
+                diff_values(path, old.get(key), new.get(key), applied, rejected);
+                path.pop();
+            }
+        }
+        _ => {
+            let change = ConfigChange {
+                key: path.join("."),
+                old: old.cloned(),
+                new: new.cloned(),
+            };
+            if reloadable {
+                applied.push(change);
+            } else {
+                rejected.push(change);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(changes: &[ConfigChange]) -> Vec<&str> {
+        changes.iter().map(|c| c.key.as_str()).collect()
+    }
+
+    #[test]
+    fn diff_reports_immutable_changes() {
+        let old: Document = r#"
+id = 10
+page_cache_size = 100
+
+[remote_storage]
+local_path = "/tmp/remote"
+concurrency_limit = 10
+
+[tenant_config]
+gc_period = "1h"
+"#
+        .parse()
+        .unwrap();
+
+        // only reloadable settings
+        let new: Document = r#"
+id = 10
+page_cache_size = 100 # comments and formatting don't matter
+
+[remote_storage]
+local_path = "/tmp/remote"
+concurrency_limit = 20
+
+[tenant_config]
+gc_period = "10m"
+compaction_period = "10s"
+"#
+        .parse()
+        .unwrap();
+        let (applied, rejected) = diff(&old, &new).unwrap();
+        assert_eq!(
+            keys(&applied),
+            ["remote_storage.concurrency_limit", "tenant_config"]
+        );
+        assert!(rejected.is_empty());
+
+        let new: Document = r#"
+id = 10
+page_cache_size = 200
+disk_usage_based_eviction = { max_usage_pct = 80, min_avail_bytes = 0, period = "10s" }
+
+[remote_storage]
+local_path = "/tmp/elsewhere"
+concurrency_limit = 10
+
+[tenant_config]
+gc_period = "1h"
+"#
+        .parse()
+        .unwrap();
+        let (applied, rejected) = diff(&old, &new).unwrap();
+        assert_eq!(keys(&applied), ["disk_usage_based_eviction"]);
+        assert_eq!(
+            keys(&rejected),
+            ["page_cache_size", "remote_storage.local_path"]
+        );
+        assert_eq!(rejected[0].old, Some(Value::from(100)));
+        assert_eq!(rejected[0].new, Some(Value::from(200)));
+    }
+
+    #[test]
+    fn page_cache_size_is_not_reloadable() {
+        let old: Document = "page_cache_size = 100".parse().unwrap();
+        for new in ["page_cache_size = 200", ""] {
+            let new: Document = new.parse().unwrap();
+            let (applied, rejected) = diff(&old, &new).unwrap();
+            assert!(applied.is_empty());
+            assert_eq!(keys(&rejected), ["page_cache_size"]);
+        }
+    }
+}
@@ -9,38 +9,46 @@
 //!    statistics, which we, in turn, need to guide layer eviction policy design.
 //! 2. How should we behave if, to produce the page image, we need to
 //!    on-demand download a layer file ([`DownloadBehavior`]).
+//! 3. Is anyone still waiting for the page? The request may have been cancelled,
+//!    or be past its deadline.
 //!
 //! [`RequestContext`] satisfies those needs.
 //! The current implementation is a small `struct` that is passed through
 //! the call chain by reference.
 //!
+//! ### Cancellation
+//!
+//! A context can carry a [`CancellationToken`] and a deadline, set with
+//! [`RequestContextBuilder::cancel`] and [`RequestContextBuilder::deadline`]: the
+//! page_service sets the `page_request_deadline` of the tenant on each request, and cancels
+//! the requests in flight when the compute closes the connection, which is how computes
+//! give up on their requests. The management API likewise cancels the context of a request
+//! when its client disconnects. The read path checks [`RequestContext::is_cancelled`]
+//! between layers, while it waits for an on-demand download, and before WAL redo, and gives
+//! up with `PageReconstructError::RequestCancelled`. The reads given up are counted by
+//! [`ReadStage`] in `pageserver_read_cancelled_total`.
+//!
+//! Cancellation of a context propagates to its [`RequestContext::attached_child`]ren,
+//! which also inherit its deadline, but not to its [`RequestContext::detached_child`]ren.
+//! Lifecycle requests (detach tenant, delete timeline) are still signalled through the
+//! cancellation tokens of the tenants and timelines.
+//!
 //! ### Future Work
 //!
 //! However, we do not intend to stop here, since there are other needs that
 //! require carrying information from high to low levels of the app.
 //!
-//! Most importantly, **cancellation signaling** in response to
-//! 1. timeouts (page_service max response time) and
-//! 2. lifecycle requests (detach tenant, delete timeline).
-//!
-//! Related to that, there is sometimes a need to ensure that all tokio tasks spawned
+//! There is sometimes a need to ensure that all tokio tasks spawned
 //! by the transitive callees of a request have finished. The keyword here
 //! is **Structured Concurrency**, and right now, we use `task_mgr` in most places,
 //! `TaskHandle` in some places, and careful code review around `FuturesUnordered`
 //! or `JoinSet` in other places.
 //!
-//! We do not yet have a systematic cancellation story in pageserver, and it is
-//! pretty clear that [`RequestContext`] will be responsible for that.
-//! So, the API already prepares for this role through the
-//! [`RequestContext::detached_child`] and [`RequestContext::attached_child`]  methods.
-//! See their doc comments for details on how we will use them in the future.
-//!
 //! It is not clear whether or how we will enforce Structured Concurrency, and
 //! what role [`RequestContext`] will play there.
 //! So, the API doesn't prepare us for this topic.
 //!
 //! Other future uses of `RequestContext`:
-//! - Communicate compute & IO priorities (user-initiated request vs. background-loop)
 //! - Request IDs for distributed tracing
 //! - Request/Timeline/Tenant-scoped log levels
 //!
@@ -86,9 +94,14 @@
 //! [`RequestContext`] argument. Functions in the middle of the call chain
 //! only need to pass it on.
 
-use crate::task_mgr::TaskKind;
+use std::time::Instant;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{metrics::READ_CANCELLED, read_priority::ReadPriority, task_mgr::TaskKind};
 
 pub(crate) mod optional_counter;
+pub(crate) mod read_path;
 
 // The main structure of this module, see module-level comment.
 #[derive(Debug)]
@@ -98,6 +111,21 @@ pub struct RequestContext {
     access_stats_behavior: AccessStatsBehavior,
     page_content_kind: PageContentKind,
     pub micros_spent_throttled: optional_counter::MicroSecondsCounterU32,
+    pub(crate) read_path: read_path::ReadPathRecorder,
+    cancel: CancellationToken,
+    deadline: Option<Instant>,
+}
+
+/// Where the read path gave up a cancelled request, see [`RequestContext::is_cancelled`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, strum_macros::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum ReadStage {
+    /// Between two layers, or two timelines of the ancestry.
+    Traversal,
+    /// While waiting for the on-demand download of a layer.
+    Download,
+    /// Before the WAL redo of the records collected from the layers.
+    WalRedo,
 }
 
 /// The kind of access to the page cache.
@@ -154,6 +182,9 @@ impl RequestContextBuilder {
                 access_stats_behavior: AccessStatsBehavior::Update,
                 page_content_kind: PageContentKind::Unknown,
                 micros_spent_throttled: Default::default(),
+                read_path: Default::default(),
+                cancel: CancellationToken::new(),
+                deadline: None,
             },
         }
     }
@@ -168,6 +199,10 @@ impl RequestContextBuilder {
                 access_stats_behavior: original.access_stats_behavior,
                 page_content_kind: original.page_content_kind,
                 micros_spent_throttled: Default::default(),
+                read_path: original.read_path.clone(),
+                // The same request: cancelled together.
+                cancel: original.cancel.clone(),
+                deadline: original.deadline,
             },
         }
     }
@@ -191,6 +226,22 @@ impl RequestContextBuilder {
         self
     }
 
+    /// Cancel the context, and its attached children, when `cancel` is.
+    pub(crate) fn cancel(mut self, cancel: &CancellationToken) -> Self {
+        self.inner.cancel = cancel.child_token();
+        self
+    }
+
+    /// Consider the context cancelled from `deadline` on. The earlier of the deadlines
+    /// applies, when there is one already.
+    pub(crate) fn deadline(mut self, deadline: Instant) -> Self {
+        self.inner.deadline = Some(match self.inner.deadline {
+            Some(existing) => existing.min(deadline),
+            None => deadline,
+        });
+        self
+    }
+
     pub fn build(self) -> RequestContext {
         self.inner
     }
@@ -204,10 +255,10 @@ impl RequestContext {
     /// form a tree (not implemented yet since cancellation will be
     /// the first feature that requires a tree).
     ///
-    /// # Future: Cancellation
+    /// # Cancellation
     ///
-    /// The only reason why a context like this one can be canceled is
-    /// because someone explicitly canceled it.
+    /// The only reasons why a context like this one can be canceled are
+    /// the token and deadline given to its [`RequestContextBuilder`].
     /// It has no parent, so it cannot inherit cancellation from there.
     pub fn new(task_kind: TaskKind, download_behavior: DownloadBehavior) -> Self {
         RequestContextBuilder::new(task_kind)
@@ -220,10 +271,10 @@ impl RequestContext {
     /// Use this when spawning new background activity that should complete
     /// even if the current request is canceled.
     ///
-    /// # Future: Cancellation
+    /// # Cancellation
     ///
-    /// Cancellation of `self` will not propagate to the child context returned
-    /// by this method.
+    /// Cancellation of `self` does not propagate to the child context returned
+    /// by this method, and the child has no deadline.
     ///
     /// # Future: Structured Concurrency
     ///
@@ -240,9 +291,10 @@ impl RequestContext {
     ///
     /// Use this when fanning-out work to other async tasks.
     ///
-    /// # Future: Cancellation
+    /// # Cancellation
     ///
-    /// Cancelling a context will propagate to its attached children.
+    /// Cancelling a context propagates to its attached children, which also
+    /// inherit its deadline.
     ///
     /// # Future: Structured Concurrency
     ///
@@ -257,7 +309,11 @@ impl RequestContext {
     /// The method to wait for child tasks would return an error, indicating
     /// that the child task was not started because the context was canceled.
     pub fn attached_child(&self) -> Self {
-        self.child_impl(self.task_kind(), self.download_behavior())
+        let mut child = self.child_impl(self.task_kind(), self.download_behavior());
+        child.cancel = self.cancel.child_token();
+        child.deadline = self.deadline;
+        child.read_path = self.read_path.clone();
+        child
     }
 
     /// Use this function when you should be creating a child context using
@@ -291,4 +347,95 @@ impl RequestContext {
     pub(crate) fn page_content_kind(&self) -> PageContentKind {
         self.page_content_kind
     }
+
+    pub(crate) fn read_priority(&self) -> ReadPriority {
+        ReadPriority::of_task_kind(self.task_kind)
+    }
+
+    /// Whether the request was cancelled, or is past its deadline: nobody is waiting for the
+    /// result anymore, and the read path should stop spending IO on it.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled() || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// Resolves once [`Self::is_cancelled`] turns true.
+    pub(crate) async fn cancelled(&self) {
+        match self.deadline {
+            Some(deadline) => {
+                tokio::select! {
+                    _ = self.cancel.cancelled() => {}
+                    _ = tokio::time::sleep_until(deadline.into()) => {}
+                }
+            }
+            None => self.cancel.cancelled().await,
+        }
+    }
+
+    /// Count a read given up at `stage` because the request [`Self::is_cancelled`].
+    pub(crate) fn record_given_up(&self, stage: ReadStage) {
+        READ_CANCELLED
+            .with_label_values(&[self.task_kind.into(), stage.into()])
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn cancellation_propagates_to_attached_children() {
+        let cancel = CancellationToken::new();
+        let ctx = RequestContextBuilder::new(TaskKind::PageRequestHandler)
+            .cancel(&cancel)
+            .build();
+        let attached = ctx.attached_child();
+        let detached = ctx.detached_child(TaskKind::LayerDownload, DownloadBehavior::Download);
+        assert!(!attached.is_cancelled());
+
+        cancel.cancel();
+        assert!(ctx.is_cancelled());
+        assert!(attached.is_cancelled());
+        assert!(!detached.is_cancelled());
+    }
+
+    #[test]
+    fn read_path_is_shared_with_extended_and_attached_children() {
+        use read_path::ReadPathClass;
+
+        let ctx = RequestContext::new(TaskKind::PageRequestHandler, DownloadBehavior::Download);
+        let extended = RequestContextBuilder::extend(&ctx)
+            .page_content_kind(PageContentKind::DeltaLayerValue)
+            .build();
+        extended.read_path.record(ReadPathClass::LocalLayer);
+        ctx.attached_child()
+            .read_path
+            .record(ReadPathClass::PageCache);
+        ctx.detached_child(TaskKind::LayerDownload, DownloadBehavior::Download)
+            .read_path
+            .record(ReadPathClass::RemoteDownload);
+        assert_eq!(ctx.read_path.take(), ReadPathClass::LocalLayer);
+    }
+
+    #[test]
+    fn deadline_is_inherited_and_only_shortened() {
+        let now = Instant::now();
+        let ctx = RequestContextBuilder::new(TaskKind::PageRequestHandler)
+            .deadline(now + Duration::from_secs(3600))
+            .build();
+        assert!(!ctx.is_cancelled());
+        assert!(!ctx.attached_child().is_cancelled());
+
+        let past = RequestContextBuilder::extend(&ctx)
+            .deadline(now)
+            .deadline(now + Duration::from_secs(7200))
+            .build();
+        assert!(past.is_cancelled());
+        assert!(past.attached_child().is_cancelled());
+        assert!(!past
+            .detached_child(TaskKind::LayerDownload, DownloadBehavior::Download)
+            .is_cancelled());
+    }
 }
@@ -0,0 +1,82 @@
+//! Classification of the read path of a request, by the most expensive source of data it
+//! used, for the GetPage latency metrics in [`crate::tenant::getpage_latency`].
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// Where the data of a read came from, from the cheapest to the most expensive.
+#[derive(
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+    strum_macros::IntoStaticStr,
+    strum_macros::EnumCount,
+    strum_macros::EnumIter,
+    strum_macros::FromRepr,
+)]
+#[strum(serialize_all = "snake_case")]
+#[repr(u8)]
+pub(crate) enum ReadPathClass {
+    /// Only in-memory layers: no read from a layer file.
+    InMemoryLayer,
+    /// Layer file blocks, or the materialized page, found in the page cache.
+    PageCache,
+    /// Layer file blocks read from local disk.
+    LocalLayer,
+    /// WAL redo of at least [`REDO_HEAVY_RECORDS`] records.
+    RedoHeavy,
+    /// A layer file downloaded on-demand from remote storage.
+    RemoteDownload,
+}
+
+/// The number of WAL records to redo from which a read counts as [`ReadPathClass::RedoHeavy`].
+pub(crate) const REDO_HEAVY_RECORDS: usize = 16;
+
+/// Records the most expensive [`ReadPathClass`] of the reads since the last [`Self::take`].
+///
+/// Clones share the recording: the extended and attached child contexts of a request record
+/// into the recorder of the request.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ReadPathRecorder(Arc<AtomicU8>);
+
+impl ReadPathRecorder {
+    pub(crate) fn record(&self, class: ReadPathClass) {
+        self.0.fetch_max(class as u8, Ordering::Relaxed);
+    }
+
+    /// The class of the reads since the last call, reset for the next ones.
+    pub(crate) fn take(&self) -> ReadPathClass {
+        let class = self
+            .0
+            .swap(ReadPathClass::InMemoryLayer as u8, Ordering::Relaxed);
+        ReadPathClass::from_repr(class).expect("only valid classes are recorded")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_expensive_class_wins() {
+        let recorder = ReadPathRecorder::default();
+        assert_eq!(recorder.take(), ReadPathClass::InMemoryLayer);
+        recorder.record(ReadPathClass::LocalLayer);
+        recorder.record(ReadPathClass::PageCache);
+        assert_eq!(recorder.take(), ReadPathClass::LocalLayer);
+        // Reset by take.
+        recorder.record(ReadPathClass::PageCache);
+        assert_eq!(recorder.take(), ReadPathClass::PageCache);
+    }
+
+    #[test]
+    fn clones_share_the_recording() {
+        let recorder = ReadPathRecorder::default();
+        recorder.clone().record(ReadPathClass::RemoteDownload);
+        assert_eq!(recorder.take(), ReadPathClass::RemoteDownload);
+    }
+}
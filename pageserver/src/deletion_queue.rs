@@ -165,6 +165,12 @@ struct TenantDeletionList {
     /// when reconstructing a full key
     timelines: HashMap<TimelineId, Vec<String>>,
 
+    /// Like `timelines`, for objects in the hot tier, see
+    /// [`crate::tenant::remote_timeline_client::hot_tier`].
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    hot_tier_timelines: HashMap<TimelineId, Vec<String>>,
+
     /// The generation in which this deletion was emitted: note that this may not be the
     /// same as the generation of any layers being deleted.  The generation of the layer
     /// has already been absorbed into the keys in `objects`
@@ -173,7 +179,11 @@ struct TenantDeletionList {
 
 impl TenantDeletionList {
     pub(crate) fn len(&self) -> usize {
-        self.timelines.values().map(|v| v.len()).sum()
+        self.timelines
+            .values()
+            .chain(self.hot_tier_timelines.values())
+            .map(|v| v.len())
+            .sum()
     }
 }
 
@@ -268,6 +278,7 @@ impl DeletionList {
         tenant: &TenantShardId,
         timeline: &TimelineId,
         generation: Generation,
+        hot_tier: bool,
         objects: &mut Vec<RemotePath>,
     ) -> bool {
         if objects.is_empty() {
@@ -281,6 +292,7 @@ impl DeletionList {
             .entry(*tenant)
             .or_insert_with(|| TenantDeletionList {
                 timelines: HashMap::new(),
+                hot_tier_timelines: HashMap::new(),
                 generation,
             });
 
@@ -290,7 +302,12 @@ impl DeletionList {
             return false;
         }
 
-        let timeline_entry = tenant_entry.timelines.entry(*timeline).or_default();
+        let timelines = if hot_tier {
+            &mut tenant_entry.hot_tier_timelines
+        } else {
+            &mut tenant_entry.timelines
+        };
+        let timeline_entry = timelines.entry(*timeline).or_default();
 
         let timeline_remote_path = remote_timeline_path(tenant, timeline);
 
@@ -303,20 +320,32 @@ impl DeletionList {
         true
     }
 
-    fn into_remote_paths(self) -> Vec<RemotePath> {
+    /// The paths of the objects to delete from the remote storage, and from the hot tier.
+    fn into_remote_paths(self) -> (Vec<RemotePath>, Vec<RemotePath>) {
         let mut result = Vec::new();
+        let mut hot_tier_result = Vec::new();
         for (tenant, tenant_deletions) in self.tenants.into_iter() {
-            for (timeline, timeline_layers) in tenant_deletions.timelines.into_iter() {
-                let timeline_remote_path = remote_timeline_path(&tenant, &timeline);
-                result.extend(
-                    timeline_layers
-                        .into_iter()
-                        .map(|l| timeline_remote_path.join(&Utf8PathBuf::from(l))),
-                );
+            let TenantDeletionList {
+                timelines,
+                hot_tier_timelines,
+                ..
+            } = tenant_deletions;
+            for (result, timelines) in [
+                (&mut result, timelines),
+                (&mut hot_tier_result, hot_tier_timelines),
+            ] {
+                for (timeline, timeline_layers) in timelines.into_iter() {
+                    let timeline_remote_path = remote_timeline_path(&tenant, &timeline);
+                    result.extend(
+                        timeline_layers
+                            .into_iter()
+                            .map(|l| timeline_remote_path.join(&Utf8PathBuf::from(l))),
+                    );
+                }
             }
         }
 
-        result
+        (result, hot_tier_result)
     }
 
     async fn save(&self, conf: &'static PageServerConf) -> anyhow::Result<()> {
@@ -524,6 +553,54 @@ impl DeletionQueueClient {
                 layers,
                 generation: current_generation,
                 objects: Vec::new(),
+                hot_tier: false,
+            }),
+        )
+    }
+
+    /// Like [`Self::push_layers`], for the copies of layers in the hot tier, see
+    /// [`crate::tenant::remote_timeline_client::hot_tier`].
+    pub(crate) async fn push_hot_tier_layers(
+        &self,
+        tenant_shard_id: TenantShardId,
+        timeline_id: TimelineId,
+        current_generation: Generation,
+        layers: Vec<(LayerName, LayerFileMetadata)>,
+    ) -> Result<(), DeletionQueueError> {
+        if current_generation.is_none() {
+            debug!("Enqueuing hot tier deletions in legacy mode, skipping queue");
+
+            let layer_paths = layers
+                .into_iter()
+                .map(|(layer, meta)| {
+                    remote_layer_path(
+                        &tenant_shard_id.tenant_id,
+                        &timeline_id,
+                        meta.shard,
+                        &layer,
+                        meta.generation,
+                    )
+                })
+                .collect();
+            self.executor_tx
+                .send(DeleterMessage::DeleteHotTier(layer_paths))
+                .await
+                .map_err(|_| DeletionQueueError::ShuttingDown)?;
+            return self.flush_immediate().await;
+        }
+
+        metrics::DELETION_QUEUE
+            .keys_submitted
+            .inc_by(layers.len() as u64);
+        self.do_push(
+            &self.tx,
+            ListWriterQueueMessage::Delete(DeletionOp {
+                tenant_shard_id,
+                timeline_id,
+                layers,
+                generation: current_generation,
+                objects: Vec::new(),
+                hot_tier: true,
             }),
         )
     }
@@ -1157,8 +1234,8 @@ mod test {
 
 /// A lightweight queue which can issue ordinary DeletionQueueClient objects, but doesn't do any persistence
 /// or coalescing, and doesn't actually execute any deletions unless you call pump() to kick it.
-#[cfg(test)]
-pub(crate) mod mock {
+#[cfg(any(feature = "testing", test))]
+pub mod mock {
     use tracing::info;
 
     use super::*;
@@ -1192,6 +1269,10 @@ pub(crate) mod mock {
                             executed += 1;
                         }
                     }
+                    DeleterMessage::DeleteHotTier(objects) => {
+                        // The mock has no hot tier
+                        debug!("Dropping {} hot tier deletions", objects.len());
+                    }
                     DeleterMessage::Flush(flush_op) => {
                         flush_op.notify();
                     }
@@ -1200,6 +1281,10 @@ pub(crate) mod mock {
 
             while let Ok(msg) = self.rx.try_recv() {
                 match msg {
+                    ListWriterQueueMessage::Delete(op) if op.hot_tier => {
+                        // The mock has no hot tier
+                        debug!("Dropping {} hot tier deletions", op.layers.len());
+                    }
                     ListWriterQueueMessage::Delete(op) => {
                         let mut objects = op.objects;
                         for (layer, meta) in op.layers {
@@ -1310,7 +1395,7 @@ pub(crate) mod mock {
         let mut objects = [object].to_vec();
 
         let mut example = DeletionList::new(1);
-        example.push(&tenant_id, &timeline_id, generation, &mut objects);
+        example.push(&tenant_id, &timeline_id, generation, false, &mut objects);
 
         let encoded = serde_json::to_string(&example)?;
 
@@ -17,6 +17,7 @@ use tracing::warn;
 use utils::backoff;
 
 use crate::metrics;
+use crate::tenant::remote_timeline_client::hot_tier;
 
 use super::DeletionQueueError;
 use super::FlushOp;
@@ -25,6 +26,9 @@ const AUTOFLUSH_INTERVAL: Duration = Duration::from_secs(10);
 
 pub(super) enum DeleterMessage {
     Delete(Vec<RemotePath>),
+    /// Objects in the hot tier, see [`crate::tenant::remote_timeline_client::hot_tier`]. They
+    /// are deleted right away rather than accumulated: they come in batches already.
+    DeleteHotTier(Vec<RemotePath>),
     Flush(FlushOp),
 }
 
@@ -38,6 +42,7 @@ pub(super) struct Deleter {
 
     cancel: CancellationToken,
     remote_storage: GenericRemoteStorage,
+    hot_tier: Option<GenericRemoteStorage>,
 }
 
 impl Deleter {
@@ -51,11 +56,16 @@ impl Deleter {
             rx,
             cancel,
             accumulator: Vec::new(),
+            hot_tier: hot_tier::get().map(|hot_tier| hot_tier.storage.clone()),
         }
     }
 
     /// Wrap the remote `delete_objects` with a failpoint
-    async fn remote_delete(&self) -> Result<(), anyhow::Error> {
+    async fn remote_delete(
+        &self,
+        storage: &GenericRemoteStorage,
+        paths: &[RemotePath],
+    ) -> Result<(), anyhow::Error> {
         // A backoff::retry is used here for two reasons:
         // - To provide a backoff rather than busy-polling the API on errors
         // - To absorb transient 429/503 conditions without hitting our error
@@ -72,9 +82,7 @@ impl Deleter {
                     Err(anyhow::anyhow!("failpoint: deletion-queue-before-execute"))
                 });
 
-                self.remote_storage
-                    .delete_objects(&self.accumulator, &self.cancel)
-                    .await
+                storage.delete_objects(paths, &self.cancel).await
             },
             TimeoutOrCancel::caused_by_cancel,
             3,
@@ -90,7 +98,10 @@ impl Deleter {
     /// Block until everything in accumulator has been executed
     async fn flush(&mut self) -> Result<(), DeletionQueueError> {
         while !self.accumulator.is_empty() && !self.cancel.is_cancelled() {
-            match self.remote_delete().await {
+            match self
+                .remote_delete(&self.remote_storage, &self.accumulator)
+                .await
+            {
                 Ok(()) => {
                     // Note: we assume that the remote storage layer returns Ok(()) if some
                     // or all of the deleted objects were already gone.
@@ -128,6 +139,36 @@ impl Deleter {
         }
     }
 
+    async fn delete_hot_tier(&self, list: Vec<RemotePath>) -> Result<(), DeletionQueueError> {
+        let Some(hot_tier) = &self.hot_tier else {
+            // The objects are left for the lifecycle rule of the hot tier bucket.
+            warn!(
+                "Dropping {} hot tier deletions, no hot tier is configured",
+                list.len()
+            );
+            metrics::DELETION_QUEUE
+                .keys_dropped
+                .inc_by(list.len() as u64);
+            return Ok(());
+        };
+        for chunk in list.chunks(MAX_KEYS_PER_DELETE) {
+            while let Err(e) = self.remote_delete(hot_tier, chunk).await {
+                if self.cancel.is_cancelled() {
+                    return Err(DeletionQueueError::ShuttingDown);
+                }
+                warn!("DeleteObjects request to the hot tier failed: {e:#}, will continue trying");
+                metrics::DELETION_QUEUE
+                    .remote_errors
+                    .with_label_values(&["execute"])
+                    .inc();
+            }
+            metrics::DELETION_QUEUE
+                .keys_executed
+                .inc_by(chunk.len() as u64);
+        }
+        Ok(())
+    }
+
     pub(super) async fn background(&mut self) -> Result<(), DeletionQueueError> {
         self.accumulator.reserve(MAX_KEYS_PER_DELETE);
 
@@ -168,6 +209,7 @@ impl Deleter {
                         }
                     }
                 }
+                DeleterMessage::DeleteHotTier(list) => self.delete_hot_tier(list).await?,
                 DeleterMessage::Flush(flush_op) => {
                     // If flush() errors, we drop the flush_op and the caller will get
                     // an error recv()'ing their oneshot channel.
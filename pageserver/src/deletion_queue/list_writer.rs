@@ -61,6 +61,8 @@ pub(super) struct DeletionOp {
     // to do it for you.
     pub(super) layers: Vec<(LayerName, LayerFileMetadata)>,
     pub(super) objects: Vec<RemotePath>,
+    /// The objects are in the hot tier rather than in the remote storage.
+    pub(super) hot_tier: bool,
 
     /// The _current_ generation of the Tenant shard attachment in which we are enqueuing
     /// this deletion.
@@ -414,6 +416,7 @@ impl ListWriter {
                         &op.tenant_shard_id,
                         &op.timeline_id,
                         op.generation,
+                        op.hot_tier,
                         &mut layer_paths,
                     ) {
                         self.flush().await;
@@ -421,6 +424,7 @@ impl ListWriter {
                             &op.tenant_shard_id,
                             &op.timeline_id,
                             op.generation,
+                            op.hot_tier,
                             &mut layer_paths,
                         );
                         if !retry_succeeded {
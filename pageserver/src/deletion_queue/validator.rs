@@ -327,11 +327,17 @@ where
         let mut executing_lists = Vec::new();
         for list in self.validated_lists.drain(..) {
             let list_path = self.conf.deletion_list_path(list.sequence);
-            let objects = list.into_remote_paths();
+            let (objects, hot_tier_objects) = list.into_remote_paths();
             self.tx
                 .send(DeleterMessage::Delete(objects))
                 .await
                 .map_err(|_| DeletionQueueError::ShuttingDown)?;
+            if !hot_tier_objects.is_empty() {
+                self.tx
+                    .send(DeleterMessage::DeleteHotTier(hot_tier_objects))
+                    .await
+                    .map_err(|_| DeletionQueueError::ShuttingDown)?;
+            }
             executing_lists.push(list_path);
         }
 
@@ -8,6 +8,11 @@
 //!
 //! The loop runs periodically at a configurable `period`.
 //!
+//! With `tenant_data_dirs` configured, each iteration is repeated for the filesystem of every
+//! data dir, evicting only layers of the tenant shards placed in it, see
+//! [`crate::tenant::data_dirs`]. The available bytes of each are exported in the
+//! `pageserver_disk_usage_data_dir_avail_bytes` metric.
+//!
 //! Each loop iteration uses `statvfs` to determine filesystem-level space usage.
 //! It compares the returned usage data against two different types of thresholds.
 //! The iteration tries to evict layers until app-internal accounting says we should be below the thresholds.
@@ -36,17 +41,54 @@
 //! during page reconstruction.
 //! An alternative default for all tenants can be specified in the `tenant_config` section of the config.
 //! Lastly, each tenant can have an override in their respective tenant config (`min_resident_size_override`).
+//!
+//! Layers outside of the reservation that were downloaded again soon after their last eviction
+//! are only evicted after all the other layers outside of it, so that a layer that keeps getting
+//! read doesn't thrash between evicted and downloaded, see
+//! [`crate::config::PageServerConf::eviction_bounce_window`].
+//!
+//! # Forecasting
+//!
+//! Each iteration also estimates the rate at which the filesystem fills up, from the change
+//! in available bytes since the previous iteration, which nets out ingest, compaction and GC,
+//! but not the evictions of the task itself. From it, the task forecasts the time until the
+//! thresholds above are crossed, and until the filesystem is full, see [`DiskUsageForecast`].
+//! The forecast is exposed in the `pageserver_disk_usage_forecast_*` metrics and by the
+//! `/v1/disk_usage_eviction/forecast` API.
+//!
+//! With `forecast_horizon` configured, the task doesn't wait for the thresholds to be crossed:
+//! it evicts as soon as they are forecast to be crossed within the horizon, by setting aside
+//! the bytes that are expected to be written within the horizon.
+//!
+//! # Offloading
+//!
+//! Evicting layers can't relieve the pressure when the resident layers are mostly the ones
+//! being written, or being read again right away. With `offload` configured, an iteration
+//! after which there is still pressure offloads the coldest tenant shards of the data dir to
+//! remote storage instead, until the pressure is relieved or `max_tenants_per_iteration` are
+//! offloaded, see [`crate::tenant::offload`]. Rather than failing the ingest for every tenant
+//! once the disk is full, the cold tenants stop ingesting, and their reads download the layers
+//! on demand. Only the tenant shards that were neither read nor written for `min_idle` are
+//! offloaded, as far as the access log of their timelines knows: nothing is offloaded if the
+//! access log is disabled or if `min_idle` exceeds its retention, `timeline_access_log_retention`,
+//! as every tenant shard would then look idle. The offloaded tenant shards are reactivated once
+//! an iteration finds no pressure anymore, and are listed by the
+//! `/v1/disk_usage_eviction/offloaded` API, with the details of the offloading if it happened
+//! since the pageserver started.
 
 // Implementation notes:
 // - The `#[allow(dead_code)]` above various structs are to suppress warnings about only the Debug impl
 //   reading these fields. We use the Debug impl for semi-structured logging, though.
 
 use std::{
+    collections::HashMap,
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
 use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use pageserver_api::models::ReadOnlyReason;
 use pageserver_api::shard::TenantShardId;
 use remote_storage::GenericRemoteStorage;
 use serde::{Deserialize, Serialize};
@@ -58,9 +100,10 @@ use utils::{completion, id::TimelineId};
 
 use crate::{
     config::PageServerConf,
-    metrics::disk_usage_based_eviction::METRICS,
+    metrics::{disk_usage_based_eviction::METRICS, EVICTIONS_PREVENTED_BOUNCING},
     task_mgr::{self, TaskKind, BACKGROUND_RUNTIME},
     tenant::{
+        data_dirs,
         mgr::TenantManager,
         remote_timeline_client::LayerFileMetadata,
         secondary::SecondaryTenant,
@@ -79,6 +122,28 @@ pub struct DiskUsageEvictionTaskConfig {
     /// Select sorting for evicted layers
     #[serde(default)]
     pub eviction_order: EvictionOrder,
+    /// Start evicting when the thresholds are forecast to be crossed within this time, see
+    /// module-level comment.
+    #[serde(default, with = "humantime_serde")]
+    pub forecast_horizon: Option<Duration>,
+    /// Offload the coldest tenants when evicting layers doesn't relieve the pressure, see
+    /// module-level comment.
+    #[serde(default)]
+    pub offload: Option<TenantOffloadConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TenantOffloadConfig {
+    /// Only the tenants that were neither read nor written for this long are offloaded.
+    #[serde(with = "humantime_serde")]
+    pub min_idle: Duration,
+    /// How many tenants an iteration offloads at most, per data dir.
+    #[serde(default = "default_max_tenants_per_iteration")]
+    pub max_tenants_per_iteration: usize,
+}
+
+fn default_max_tenants_per_iteration() -> usize {
+    1
 }
 
 /// Selects the sort order for eviction candidates *after* per tenant `min_resident_size`
@@ -116,15 +181,20 @@ impl EvictionOrder {
     fn sort(&self, candidates: &mut [(MinResidentSizePartition, EvictionCandidate)]) {
         use EvictionOrder::*;
 
+        candidates.sort_unstable_by(|(a_partition, a), (b_partition, b)| {
+            a_partition
+                .cmp(b_partition)
+                .then_with(|| self.cmp_access(a, b))
+        });
+    }
+
+    /// Orders the candidates by their access alone, the first to be evicted first.
+    fn cmp_access(&self, a: &EvictionCandidate, b: &EvictionCandidate) -> std::cmp::Ordering {
+        use EvictionOrder::*;
+
         match self {
-            AbsoluteAccessed => {
-                candidates.sort_unstable_by_key(|(partition, candidate)| {
-                    (*partition, candidate.last_activity_ts)
-                });
-            }
-            RelativeAccessed { .. } => candidates.sort_unstable_by_key(|(partition, candidate)| {
-                (*partition, candidate.relative_last_activity)
-            }),
+            AbsoluteAccessed => a.last_activity_ts.cmp(&b.last_activity_ts),
+            RelativeAccessed { .. } => a.relative_last_activity.cmp(&b.relative_last_activity),
         }
     }
 
@@ -184,6 +254,127 @@ impl EvictionOrder {
 pub struct State {
     /// Exclude http requests and background task from running at the same time.
     mutex: tokio::sync::Mutex<()>,
+    /// The forecast of the latest iteration of the background task.
+    forecast: std::sync::Mutex<Option<DiskUsageForecast>>,
+    /// The tenant shards offloaded by the background task since the pageserver started.
+    offloaded: std::sync::Mutex<HashMap<TenantShardId, TenantOffloadDetails>>,
+}
+
+impl State {
+    pub fn forecast(&self) -> Option<DiskUsageForecast> {
+        *self.forecast.lock().unwrap()
+    }
+
+    /// The attached tenant shards that are offloaded, see module-level comment.
+    pub fn offloaded_tenants(
+        &self,
+        tenant_manager: &TenantManager,
+    ) -> anyhow::Result<Vec<OffloadedTenant>> {
+        let tenants = tenant_manager
+            .list_tenants()
+            .context("get list of tenants")?;
+        let details = self.offloaded.lock().unwrap();
+        let mut offloaded = tenants
+            .into_iter()
+            .filter(|(tenant_shard_id, _state, _gen)| {
+                tenant_manager
+                    .get_attached_tenant_shard(*tenant_shard_id)
+                    .is_ok_and(|tenant| {
+                        tenant.tenant_specific_overrides().read_only
+                            == Some(ReadOnlyReason::Offloaded)
+                    })
+            })
+            .map(|(tenant_shard_id, _state, _gen)| OffloadedTenant {
+                tenant_shard_id,
+                details: details.get(&tenant_shard_id).cloned(),
+            })
+            .collect::<Vec<_>>();
+        offloaded.sort_by_key(|tenant| tenant.tenant_shard_id);
+        Ok(offloaded)
+    }
+}
+
+/// A tenant shard offloaded by the disk usage based eviction, see module-level comment.
+#[derive(Debug, Clone, Serialize)]
+pub struct OffloadedTenant {
+    pub tenant_shard_id: TenantShardId,
+    /// Unknown if the tenant shard was offloaded before the pageserver started.
+    pub details: Option<TenantOffloadDetails>,
+}
+
+/// Why and when a tenant shard was offloaded.
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantOffloadDetails {
+    #[serde(with = "humantime_serde")]
+    pub offloaded_at: SystemTime,
+    /// Until when the tenant shard may have been read or written, see
+    /// [`crate::tenant::Tenant::last_activity`].
+    #[serde(with = "humantime_serde")]
+    pub last_activity: SystemTime,
+    /// The data dir whose filesystem was under pressure.
+    pub data_dir: String,
+    /// The available bytes of that filesystem, after the evictions of the iteration.
+    pub avail_bytes: u64,
+    /// The bytes of the local layers evicted by the offloading.
+    pub evicted_bytes: u64,
+}
+
+/// Forecast of the disk usage, see module-level comment.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DiskUsageForecast {
+    /// Rate at which the available bytes shrink. Negative if they grow.
+    pub fill_rate_bytes_per_second: f64,
+    /// Time until the eviction thresholds are crossed, if the filesystem is filling up.
+    pub seconds_until_pressure: Option<f64>,
+    /// Time until the filesystem is full, if it is filling up.
+    pub seconds_until_full: Option<f64>,
+}
+
+impl DiskUsageForecast {
+    fn new(fill_rate_bytes_per_second: f64, bytes_until_pressure: u64, avail_bytes: u64) -> Self {
+        let seconds_until = |bytes: u64| {
+            (fill_rate_bytes_per_second > 0.0).then(|| bytes as f64 / fill_rate_bytes_per_second)
+        };
+        DiskUsageForecast {
+            fill_rate_bytes_per_second,
+            seconds_until_pressure: seconds_until(bytes_until_pressure),
+            seconds_until_full: seconds_until(avail_bytes),
+        }
+    }
+}
+
+/// Estimates the rate at which the filesystem fills up, from the available bytes observed by
+/// consecutive iterations, see module-level comment.
+#[derive(Debug, Default)]
+struct FillRate {
+    /// When the previous iteration finished, and the available bytes then.
+    last: Option<(Instant, u64)>,
+    /// Exponential moving average of the fill rate, in bytes per second.
+    bytes_per_second: Option<f64>,
+}
+
+impl FillRate {
+    /// Weight of the latest observation in the moving average.
+    const SMOOTHING: f64 = 0.2;
+
+    /// Observe the available bytes at the start of an iteration.
+    fn observe(&mut self, now: Instant, avail_bytes: u64) -> Option<f64> {
+        let (then, last_avail_bytes) = self.last?;
+        let elapsed = now.duration_since(then).as_secs_f64();
+        if elapsed > 0.0 {
+            let rate = (last_avail_bytes as f64 - avail_bytes as f64) / elapsed;
+            self.bytes_per_second = Some(match self.bytes_per_second {
+                Some(average) => average + Self::SMOOTHING * (rate - average),
+                None => rate,
+            });
+        }
+        self.bytes_per_second
+    }
+
+    /// Record the available bytes at the end of an iteration, after its evictions.
+    fn finish_iteration(&mut self, now: Instant, avail_bytes: u64) {
+        self.last = Some((now, avail_bytes));
+    }
 }
 
 pub fn launch_disk_usage_global_eviction_task(
@@ -193,10 +384,10 @@ pub fn launch_disk_usage_global_eviction_task(
     tenant_manager: Arc<TenantManager>,
     background_jobs_barrier: completion::Barrier,
 ) -> anyhow::Result<()> {
-    let Some(task_config) = &conf.disk_usage_based_eviction else {
+    if conf.disk_usage_based_eviction.load().is_none() {
         info!("disk usage based eviction task not configured");
         return Ok(());
-    };
+    }
 
     info!("launching disk usage based eviction task");
 
@@ -216,7 +407,7 @@ pub fn launch_disk_usage_global_eviction_task(
                 _ = background_jobs_barrier.wait() => { }
             };
 
-            disk_usage_eviction_task(&state, task_config, &storage, tenant_manager, cancel).await;
+            disk_usage_eviction_task(&state, conf, &storage, tenant_manager, cancel).await;
             Ok(())
         },
     );
@@ -227,7 +418,7 @@ pub fn launch_disk_usage_global_eviction_task(
 #[instrument(skip_all)]
 async fn disk_usage_eviction_task(
     state: &State,
-    task_config: &DiskUsageEvictionTaskConfig,
+    conf: &'static PageServerConf,
     storage: &GenericRemoteStorage,
     tenant_manager: Arc<TenantManager>,
     cancel: CancellationToken,
@@ -236,9 +427,13 @@ async fn disk_usage_eviction_task(
         info!("disk usage based eviction task finishing");
     };
 
+    // The config can be changed by a reload, but not removed: that requires a restart.
+    let task_config =
+        || Option::clone(&conf.disk_usage_based_eviction.load()).expect("checked at launch");
+
     use crate::tenant::tasks::random_init_delay;
     {
-        if random_init_delay(task_config.period, &cancel)
+        if random_init_delay(task_config().period, &cancel)
             .await
             .is_err()
         {
@@ -247,9 +442,11 @@ async fn disk_usage_eviction_task(
     }
 
     let mut iteration_no = 0;
+    let mut fill_rates = HashMap::new();
     loop {
         iteration_no += 1;
         let start = Instant::now();
+        let task_config = &task_config();
 
         async {
             let res = disk_usage_eviction_task_iteration(
@@ -257,6 +454,7 @@ async fn disk_usage_eviction_task(
                 task_config,
                 storage,
                 &tenant_manager,
+                &mut fill_rates,
                 &cancel,
             )
             .await;
@@ -292,17 +490,115 @@ async fn disk_usage_eviction_task_iteration(
     task_config: &DiskUsageEvictionTaskConfig,
     storage: &GenericRemoteStorage,
     tenant_manager: &Arc<TenantManager>,
+    fill_rates: &mut HashMap<Utf8PathBuf, FillRate>,
     cancel: &CancellationToken,
 ) -> anyhow::Result<()> {
-    let tenants_dir = tenant_manager.get_conf().tenants_path();
-    let usage_pre = filesystem_level_usage::get(&tenants_dir, task_config)
+    let conf = tenant_manager.get_conf();
+    let tenants_path = conf.tenants_path();
+    let placements = data_dirs::placements(conf).context("get tenant data dir placements")?;
+
+    // Each data dir is accounted for separately, the forecast is the one of the data dir
+    // that is the closest to the pressure.
+    let mut forecast: Option<DiskUsageForecast> = None;
+    for data_dir in data_dirs::data_dirs(conf) {
+        // Without data dirs configured, the tenants directory holds all the tenant shards.
+        let filter = (!conf.tenant_data_dirs.is_empty()).then_some(DataDirFilter {
+            data_dir: &data_dir,
+            tenants_path: &tenants_path,
+            placements: &placements,
+        });
+        let fill_rate = fill_rates.entry(data_dir.clone()).or_default();
+        let data_dir_forecast = data_dir_iteration(
+            state,
+            task_config,
+            storage,
+            tenant_manager,
+            &data_dir,
+            filter.as_ref(),
+            fill_rate,
+            cancel,
+        )
+        .instrument(tracing::info_span!("data_dir", %data_dir))
+        .await?;
+
+        let until_pressure =
+            |f: &DiskUsageForecast| f.seconds_until_pressure.unwrap_or(f64::INFINITY);
+        if let Some(data_dir_forecast) = data_dir_forecast {
+            if forecast.map_or(true, |f| {
+                until_pressure(&data_dir_forecast) < until_pressure(&f)
+            }) {
+                forecast = Some(data_dir_forecast);
+            }
+        }
+    }
+
+    if let Some(forecast) = forecast {
+        METRICS
+            .forecast_fill_rate
+            .set(forecast.fill_rate_bytes_per_second);
+        METRICS
+            .forecast_seconds_until_pressure
+            .set(forecast.seconds_until_pressure.unwrap_or(f64::INFINITY));
+        METRICS
+            .forecast_seconds_until_full
+            .set(forecast.seconds_until_full.unwrap_or(f64::INFINITY));
+        *state.forecast.lock().unwrap() = Some(forecast);
+    }
+
+    Ok(())
+}
+
+/// Runs an iteration of the task for the filesystem of one data dir, and returns its forecast.
+#[allow(clippy::too_many_arguments)]
+async fn data_dir_iteration(
+    state: &State,
+    task_config: &DiskUsageEvictionTaskConfig,
+    storage: &GenericRemoteStorage,
+    tenant_manager: &Arc<TenantManager>,
+    data_dir: &Utf8Path,
+    filter: Option<&DataDirFilter<'_>>,
+    fill_rate: &mut FillRate,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Option<DiskUsageForecast>> {
+    let mut usage_pre = filesystem_level_usage::get(data_dir, task_config)
         .context("get filesystem-level disk usage before evictions")?;
+    METRICS
+        .data_dir_avail_bytes
+        .with_label_values(&[data_dir.as_str()])
+        .set(usage_pre.avail_bytes());
+
+    let mut forecast = None;
+    if let Some(bytes_per_second) = fill_rate.observe(Instant::now(), usage_pre.avail_bytes()) {
+        let data_dir_forecast = DiskUsageForecast::new(
+            bytes_per_second,
+            usage_pre.bytes_until_pressure(),
+            usage_pre.avail_bytes(),
+        );
+        forecast = Some(data_dir_forecast);
+
+        if let (Some(horizon), Some(seconds_until_pressure)) = (
+            task_config.forecast_horizon,
+            data_dir_forecast.seconds_until_pressure,
+        ) {
+            if seconds_until_pressure < horizon.as_secs_f64() {
+                warn!(
+                    forecast=?data_dir_forecast,
+                    "disk usage thresholds forecast to be crossed within {}",
+                    humantime::format_duration(horizon)
+                );
+                // Evict as if the bytes written within the horizon were already used.
+                usage_pre.set_reserved_bytes((bytes_per_second * horizon.as_secs_f64()) as u64);
+            }
+        }
+    }
+
     let res = disk_usage_eviction_task_iteration_impl(
         state,
         storage,
         usage_pre,
         tenant_manager,
         task_config.eviction_order,
+        filter,
         cancel,
     )
     .await;
@@ -310,12 +606,15 @@ async fn disk_usage_eviction_task_iteration(
         Ok(outcome) => {
             debug!(?outcome, "disk_usage_eviction_iteration finished");
             match outcome {
-                IterationOutcome::NoPressure | IterationOutcome::Cancelled => {
+                IterationOutcome::NoPressure => {
+                    reactivate_offloaded_tenants(state, tenant_manager, filter, cancel).await?;
+                }
+                IterationOutcome::Cancelled => {
                     // nothing to do, select statement below will handle things
                 }
                 IterationOutcome::Finished(outcome) => {
                     // Verify with statvfs whether we made any real progress
-                    let after = filesystem_level_usage::get(&tenants_dir, task_config)
+                    let after = filesystem_level_usage::get(data_dir, task_config)
                         // It's quite unlikely to hit the error here. Keep the code simple and bail out.
                         .context("get filesystem-level disk usage after evictions")?;
 
@@ -328,6 +627,19 @@ async fn disk_usage_eviction_task_iteration(
                         // TODO: deltas between the three different usages would be helpful,
                         // consider MiB, GiB, TiB
                         warn!(?outcome, ?after, "disk usage still high");
+
+                        if let Some(offload) = &task_config.offload {
+                            offload_cold_tenants(
+                                state,
+                                task_config,
+                                offload,
+                                tenant_manager,
+                                data_dir,
+                                filter,
+                                cancel,
+                            )
+                            .await?;
+                        }
                     } else {
                         info!(?outcome, ?after, "disk usage pressure relieved");
                     }
@@ -339,9 +651,172 @@ async fn disk_usage_eviction_task_iteration(
         }
     }
 
+    let usage_post = filesystem_level_usage::get(data_dir, task_config)
+        .context("get filesystem-level disk usage after the iteration")?;
+    fill_rate.finish_iteration(Instant::now(), usage_post.avail_bytes());
+
+    Ok(forecast)
+}
+
+/// Offloads the coldest tenant shards of the data dir, after an iteration whose evictions didn't
+/// relieve the pressure, see module-level comment.
+async fn offload_cold_tenants(
+    state: &State,
+    task_config: &DiskUsageEvictionTaskConfig,
+    offload: &TenantOffloadConfig,
+    tenant_manager: &TenantManager,
+    data_dir: &Utf8Path,
+    filter: Option<&DataDirFilter<'_>>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    // Without the accesses of the whole `min_idle`, the last activity of the tenant shards is
+    // when the tracking started, and they'd all look idle.
+    let retention = tenant_manager.get_conf().timeline_access_log_retention;
+    if retention.is_zero() || offload.min_idle > retention {
+        warn!(
+            min_idle = ?offload.min_idle,
+            ?retention,
+            "not offloading: the access log doesn't cover min_idle"
+        );
+        return Ok(());
+    }
+    let Some(idle_cutoff) = SystemTime::now().checked_sub(offload.min_idle) else {
+        return Ok(());
+    };
+
+    let tenants = tenant_manager
+        .list_tenants()
+        .context("get list of tenants")?;
+    let mut candidates = Vec::new();
+    for (tenant_shard_id, _state, _gen) in tenants {
+        if filter.is_some_and(|filter| !filter.contains(&tenant_shard_id)) {
+            continue;
+        }
+        let Ok(tenant) = tenant_manager.get_attached_tenant_shard(tenant_shard_id) else {
+            continue;
+        };
+        if !tenant.is_active()
+            || tenant.cancel.is_cancelled()
+            || tenant.tenant_specific_overrides().read_only.is_some()
+        {
+            continue;
+        }
+        if let Some(last_activity) = tenant.last_activity() {
+            if last_activity <= idle_cutoff {
+                candidates.push((last_activity, tenant));
+            }
+        }
+    }
+    candidates.sort_by_key(|(last_activity, _tenant)| *last_activity);
+
+    for (last_activity, tenant) in candidates
+        .into_iter()
+        .take(offload.max_tenants_per_iteration)
+    {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let usage = filesystem_level_usage::get(data_dir, task_config)
+            .context("get filesystem-level disk usage before offloading")?;
+        if !usage.has_pressure() {
+            break;
+        }
+
+        let tenant_shard_id = *tenant.get_tenant_shard_id();
+        let span = tracing::info_span!(
+            "offload",
+            tenant_id = %tenant_shard_id.tenant_id,
+            shard_id = %tenant_shard_id.shard_slug()
+        );
+        warn!(
+            parent: &span,
+            ?usage,
+            "offloading tenant idle since {}",
+            humantime::format_rfc3339(last_activity)
+        );
+        match tenant.offload().instrument(span.clone()).await {
+            Ok(evicted_bytes) => {
+                METRICS.tenants_offloaded.inc();
+                state.offloaded.lock().unwrap().insert(
+                    tenant_shard_id,
+                    TenantOffloadDetails {
+                        offloaded_at: SystemTime::now(),
+                        last_activity,
+                        data_dir: data_dir.to_string(),
+                        avail_bytes: usage.avail_bytes(),
+                        evicted_bytes,
+                    },
+                );
+            }
+            Err(e) => warn!(parent: &span, "failed to offload tenant: {e:#}"),
+        }
+    }
+
     Ok(())
 }
 
+/// Reactivates the offloaded tenant shards of the data dir, after an iteration that found no
+/// pressure, see module-level comment.
+async fn reactivate_offloaded_tenants(
+    state: &State,
+    tenant_manager: &TenantManager,
+    filter: Option<&DataDirFilter<'_>>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let tenants = tenant_manager
+        .list_tenants()
+        .context("get list of tenants")?;
+    for (tenant_shard_id, _state, _gen) in tenants {
+        if cancel.is_cancelled() {
+            break;
+        }
+        if filter.is_some_and(|filter| !filter.contains(&tenant_shard_id)) {
+            continue;
+        }
+        let Ok(tenant) = tenant_manager.get_attached_tenant_shard(tenant_shard_id) else {
+            continue;
+        };
+        if !tenant.is_active() || tenant.cancel.is_cancelled() {
+            continue;
+        }
+
+        let span = tracing::info_span!(
+            "reactivate",
+            tenant_id = %tenant_shard_id.tenant_id,
+            shard_id = %tenant_shard_id.shard_slug()
+        );
+        match tenant.reactivate().instrument(span.clone()).await {
+            Ok(true) => {
+                state.offloaded.lock().unwrap().remove(&tenant_shard_id);
+                info!(parent: &span, "reactivated offloaded tenant, disk usage pressure ended");
+            }
+            Ok(false) => {}
+            Err(e) => warn!(parent: &span, "failed to reactivate tenant: {e:#}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Restricts an eviction iteration to the tenant shards placed in one data dir, see
+/// [`crate::tenant::data_dirs`].
+pub(crate) struct DataDirFilter<'a> {
+    data_dir: &'a Utf8Path,
+    /// Where the tenant shards without a placement are.
+    tenants_path: &'a Utf8Path,
+    placements: &'a HashMap<TenantShardId, Utf8PathBuf>,
+}
+
+impl DataDirFilter<'_> {
+    fn contains(&self, tenant_shard_id: &TenantShardId) -> bool {
+        let data_dir = self
+            .placements
+            .get(tenant_shard_id)
+            .map_or(self.tenants_path, |data_dir| data_dir.as_path());
+        data_dir == self.data_dir
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum IterationOutcome<U> {
@@ -389,6 +864,7 @@ pub(crate) async fn disk_usage_eviction_task_iteration_impl<U: Usage>(
     usage_pre: U,
     tenant_manager: &Arc<TenantManager>,
     eviction_order: EvictionOrder,
+    filter: Option<&DataDirFilter<'_>>,
     cancel: &CancellationToken,
 ) -> anyhow::Result<IterationOutcome<U>> {
     // use tokio's mutex to get a Sync guard (instead of std::sync::Mutex)
@@ -410,7 +886,7 @@ pub(crate) async fn disk_usage_eviction_task_iteration_impl<U: Usage>(
 
     let (candidates, collection_time) = {
         let started_at = std::time::Instant::now();
-        match collect_eviction_candidates(tenant_manager, eviction_order, cancel).await? {
+        match collect_eviction_candidates(tenant_manager, eviction_order, filter, cancel).await? {
             EvictionCandidates::Cancelled => {
                 return Ok(IterationOutcome::Cancelled);
             }
@@ -459,6 +935,12 @@ pub(crate) async fn disk_usage_eviction_task_iteration_impl<U: Usage>(
         select_victims(&candidates, usage_pre).into_amount_and_planned();
 
     METRICS.layers_selected.inc_by(evicted_amount as u64);
+    let prevented_bouncing = count_prevented_bouncing(&candidates, evicted_amount, |a, b| {
+        eviction_order.cmp_access(a, b)
+    });
+    EVICTIONS_PREVENTED_BOUNCING
+        .with_label_values(&["disk_usage"])
+        .inc_by(prevented_bouncing as u64);
 
     // phase2: evict layers
 
@@ -650,6 +1132,14 @@ impl EvictionLayer {
             Self::Secondary(sl) => sl.metadata.file_size(),
         }
     }
+
+    /// Secondary locations download layers ahead of reads, so they don't bounce.
+    fn is_bouncing(&self) -> bool {
+        match self {
+            Self::Attached(l) => l.is_bouncing(),
+            Self::Secondary(_) => false,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -700,6 +1190,8 @@ impl std::fmt::Debug for EvictionCandidate {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum MinResidentSizePartition {
     Above,
+    /// Above the min resident size, but bouncing, see [`Layer::is_bouncing`].
+    Bouncing,
     Below,
 }
 
@@ -813,6 +1305,7 @@ enum EvictionCandidates {
 async fn collect_eviction_candidates(
     tenant_manager: &Arc<TenantManager>,
     eviction_order: EvictionOrder,
+    filter: Option<&DataDirFilter<'_>>,
     cancel: &CancellationToken,
 ) -> anyhow::Result<EvictionCandidates> {
     const LOG_DURATION_THRESHOLD: std::time::Duration = std::time::Duration::from_secs(10);
@@ -831,6 +1324,9 @@ async fn collect_eviction_candidates(
         if cancel.is_cancelled() {
             return Ok(EvictionCandidates::Cancelled);
         }
+        if filter.is_some_and(|filter| !filter.contains(&tenant_id)) {
+            continue;
+        }
         let tenant = match tenant_manager.get_attached_tenant_shard(tenant_id) {
             Ok(tenant) if tenant.is_active() => tenant,
             Ok(_) => {
@@ -921,10 +1417,12 @@ async fn collect_eviction_candidates(
                     candidate.relative_last_activity =
                         eviction_order.relative_last_activity(total, i);
 
-                    let partition = if cumsum > min_resident_size as i128 {
-                        MinResidentSizePartition::Above
-                    } else {
+                    let partition = if cumsum <= min_resident_size as i128 {
                         MinResidentSizePartition::Below
+                    } else if candidate.layer.is_bouncing() {
+                        MinResidentSizePartition::Bouncing
+                    } else {
+                        MinResidentSizePartition::Above
                     };
                     cumsum += i128::from(candidate.layer.get_file_size());
 
@@ -959,8 +1457,10 @@ async fn collect_eviction_candidates(
 
     let mut secondary_tenants = Vec::new();
     tenant_manager.foreach_secondary_tenants(
-        |_tenant_shard_id: &TenantShardId, state: &Arc<SecondaryTenant>| {
-            secondary_tenants.push(state.clone());
+        |tenant_shard_id: &TenantShardId, state: &Arc<SecondaryTenant>| {
+            if filter.map_or(true, |filter| filter.contains(tenant_shard_id)) {
+                secondary_tenants.push(state.clone());
+            }
         },
     );
 
@@ -1063,6 +1563,25 @@ fn select_victims<U: Usage>(
     }
 }
 
+/// Counts the bouncing candidates that weren't selected, but that come before the last selected
+/// one in access order: without the bouncing exemption, they would have been evicted instead.
+fn count_prevented_bouncing<T>(
+    candidates: &[(MinResidentSizePartition, T)],
+    selected: usize,
+    cmp_access: impl Fn(&T, &T) -> std::cmp::Ordering,
+) -> usize {
+    let Some((_, last_selected)) = selected.checked_sub(1).map(|i| &candidates[i]) else {
+        return 0;
+    };
+    candidates[selected..]
+        .iter()
+        .filter(|(partition, candidate)| {
+            *partition == MinResidentSizePartition::Bouncing
+                && cmp_access(candidate, last_selected).is_lt()
+        })
+        .count()
+}
+
 struct VictimSelection<U> {
     amount: usize,
     usage_pre: U,
@@ -1182,18 +1701,39 @@ mod filesystem_level_usage {
         total_bytes: u64,
         /// Free filesystem space
         avail_bytes: u64,
+        /// Free space that is counted as used, because it's forecast to be used soon.
+        reserved_bytes: u64,
+    }
+
+    impl Usage<'_> {
+        pub fn avail_bytes(&self) -> u64 {
+            self.avail_bytes
+        }
+
+        pub fn set_reserved_bytes(&mut self, bytes: u64) {
+            self.reserved_bytes = bytes;
+        }
+
+        /// How many bytes can be written until there is pressure, ignoring the reservation.
+        pub fn bytes_until_pressure(&self) -> u64 {
+            let max_usage_bytes =
+                (self.total_bytes as f64 * self.config.max_usage_pct.get() as f64 / 100.0) as u64;
+            let min_avail_bytes = std::cmp::max(
+                self.config.min_avail_bytes,
+                self.total_bytes.saturating_sub(max_usage_bytes),
+            );
+            self.avail_bytes.saturating_sub(min_avail_bytes)
+        }
     }
 
     impl super::Usage for Usage<'_> {
         fn has_pressure(&self) -> bool {
+            let avail_bytes = self.avail_bytes.saturating_sub(self.reserved_bytes);
             let usage_pct =
-                (100.0 * (1.0 - ((self.avail_bytes as f64) / (self.total_bytes as f64)))) as u64;
+                (100.0 * (1.0 - ((avail_bytes as f64) / (self.total_bytes as f64)))) as u64;
 
             let pressures = [
-                (
-                    "min_avail_bytes",
-                    self.avail_bytes < self.config.min_avail_bytes,
-                ),
+                ("min_avail_bytes", avail_bytes < self.config.min_avail_bytes),
                 (
                     "max_usage_pct",
                     usage_pct >= self.config.max_usage_pct.get() as u64,
@@ -1241,6 +1781,7 @@ mod filesystem_level_usage {
             config,
             total_bytes,
             avail_bytes,
+            reserved_bytes: 0,
         })
     }
 
@@ -1259,9 +1800,12 @@ mod filesystem_level_usage {
                 #[cfg(feature = "testing")]
                 mock_statvfs: None,
                 eviction_order: EvictionOrder::default(),
+                forecast_horizon: None,
+                offload: None,
             },
             total_bytes: 100_000,
             avail_bytes: 0,
+            reserved_bytes: 0,
         };
 
         assert!(usage.has_pressure(), "expected pressure at 100%");
@@ -1284,12 +1828,96 @@ mod filesystem_level_usage {
         usage.add_available_bytes(16_000);
         assert!(!usage.has_pressure());
     }
+
+    #[test]
+    fn reserved_bytes_pressure() {
+        use super::EvictionOrder;
+        use super::Usage as _;
+        use std::time::Duration;
+        use utils::serde_percent::Percent;
+
+        let mut usage = Usage {
+            config: &DiskUsageEvictionTaskConfig {
+                max_usage_pct: Percent::new(85).unwrap(),
+                min_avail_bytes: 20_000,
+                period: Duration::MAX,
+                #[cfg(feature = "testing")]
+                mock_statvfs: None,
+                eviction_order: EvictionOrder::default(),
+                forecast_horizon: Some(Duration::from_secs(3600)),
+                offload: None,
+            },
+            total_bytes: 100_000,
+            avail_bytes: 50_000,
+            reserved_bytes: 0,
+        };
+
+        // min_avail_bytes is stricter than max_usage_pct here.
+        assert_eq!(usage.bytes_until_pressure(), 30_000);
+        assert!(!usage.has_pressure());
+
+        usage.set_reserved_bytes(31_000);
+        assert!(usage.has_pressure(), "expected pressure within the horizon");
+
+        usage.add_available_bytes(1_000);
+        assert!(!usage.has_pressure());
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn fill_rate_forecast() {
+        let mut fill_rate = FillRate::default();
+        let start = Instant::now();
+        assert_eq!(fill_rate.observe(start, 10_000), None);
+
+        // 1000 bytes written in 10s, then the iteration evicted 500 bytes.
+        fill_rate.finish_iteration(start, 10_000);
+        let rate = fill_rate.observe(start + Duration::from_secs(10), 9_000);
+        assert_eq!(rate, Some(100.0));
+        fill_rate.finish_iteration(start + Duration::from_secs(10), 9_500);
+
+        // The evictions don't count as a shrinking fill rate: 500 bytes written in 10s.
+        let rate = fill_rate
+            .observe(start + Duration::from_secs(20), 9_000)
+            .unwrap();
+        assert!((rate - 90.0).abs() < 1e-9, "{rate}");
+
+        let forecast = DiskUsageForecast::new(100.0, 3_000, 9_000);
+        assert_eq!(forecast.seconds_until_pressure, Some(30.0));
+        assert_eq!(forecast.seconds_until_full, Some(90.0));
+        let forecast = DiskUsageForecast::new(-5.0, 3_000, 9_000);
+        assert_eq!(forecast.seconds_until_pressure, None);
+        assert_eq!(forecast.seconds_until_full, None);
+    }
+
+    #[test]
+    fn prevented_bouncing_count() {
+        use MinResidentSizePartition::*;
+
+        // The candidates are sorted by partition, then by access, here their last access.
+        let candidates = [
+            (Above, 10),
+            (Above, 30),
+            (Bouncing, 20),
+            (Bouncing, 40),
+            (Below, 5),
+        ];
+        let count = |selected| count_prevented_bouncing(&candidates, selected, u32::cmp);
+
+        assert_eq!(count(0), 0);
+        // The bouncing layers were accessed after the only selected one.
+        assert_eq!(count(1), 0);
+        // The bouncing layer accessed at 20 would have been evicted before the one at 30.
+        assert_eq!(count(2), 1);
+        // Once bouncing layers are evicted, the remaining ones were accessed later.
+        assert_eq!(count(3), 0);
+        assert_eq!(count(5), 0);
+    }
+
     #[test]
     fn relative_equal_bounds() {
         let order = EvictionOrder::RelativeAccessed {
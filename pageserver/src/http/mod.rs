@@ -1,2 +1,3 @@
 pub mod routes;
+mod tenant_ops;
 pub use routes::make_router;
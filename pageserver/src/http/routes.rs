@@ -14,13 +14,24 @@ use hyper::header;
 use hyper::StatusCode;
 use hyper::{Body, Request, Response, Uri};
 use metrics::launch_timestamp::LaunchTimestamp;
+use pageserver_api::key::rel_block_to_key;
+use pageserver_api::models::LayerOverlapReport;
+use pageserver_api::models::LegalHoldRequest;
 use pageserver_api::models::LocationConfig;
 use pageserver_api::models::LocationConfigListResponse;
+use pageserver_api::models::ReadOnlyReason;
+use pageserver_api::models::ReadOnlyRequest;
 use pageserver_api::models::ShardParameters;
+use pageserver_api::models::TenantConfig;
+use pageserver_api::models::TenantConfigTemplate;
 use pageserver_api::models::TenantDetails;
+use pageserver_api::models::TenantExportRequest;
+use pageserver_api::models::TenantImportRequest;
+use pageserver_api::models::TenantLoadScores;
 use pageserver_api::models::TenantLocationConfigResponse;
 use pageserver_api::models::TenantScanRemoteStorageResponse;
 use pageserver_api::models::TenantScanRemoteStorageShard;
+use pageserver_api::models::TenantSelftestRequest;
 use pageserver_api::models::TenantShardLocation;
 use pageserver_api::models::TenantShardSplitRequest;
 use pageserver_api::models::TenantShardSplitResponse;
@@ -29,6 +40,7 @@ use pageserver_api::models::{
     DownloadRemoteLayersTaskSpawnRequest, LocationConfigMode, TenantAttachRequest,
     TenantLoadRequest, TenantLocationConfigRequest,
 };
+use pageserver_api::reltag::{BlockNumber, RelTag};
 use pageserver_api::shard::ShardCount;
 use pageserver_api::shard::TenantShardId;
 use remote_storage::DownloadError;
@@ -44,12 +56,16 @@ use utils::http::endpoint::request_span;
 use utils::http::json::json_request_or_empty_body;
 use utils::http::request::{get_request_param, must_get_query_param, parse_query_param};
 
-use crate::context::{DownloadBehavior, RequestContext};
+use crate::config::reload::ReloadError;
+use crate::context::{DownloadBehavior, RequestContext, RequestContextBuilder};
 use crate::deletion_queue::DeletionQueueClient;
+use crate::http::tenant_ops::{check_if_match, config_etag, TenantOp, TenantOps};
 use crate::metrics::{StorageTimeOperation, STORAGE_TIME_GLOBAL};
-use crate::pgdatadir_mapping::LsnForTimestamp;
-use crate::task_mgr::TaskKind;
+use crate::pgdatadir_mapping::{CalculateLogicalSizeError, LsnForTimestamp, Version};
+use crate::task_mgr::{self, TaskKind};
+use crate::tenant::bundle::{self, BundleError};
 use crate::tenant::config::{LocationConf, TenantConfOpt};
+use crate::tenant::config_templates::{self, ConfigTemplates, TemplateError};
 use crate::tenant::mgr::GetActiveTenantError;
 use crate::tenant::mgr::{
     GetTenantError, TenantManager, TenantMapError, TenantMapInsertError, TenantSlotError,
@@ -60,19 +76,24 @@ use crate::tenant::remote_timeline_client;
 use crate::tenant::remote_timeline_client::download_index_part;
 use crate::tenant::remote_timeline_client::list_remote_tenant_shards;
 use crate::tenant::remote_timeline_client::list_remote_timelines;
+use crate::tenant::remote_timeline_client::snapshot::SnapshotId;
 use crate::tenant::secondary::SecondaryController;
 use crate::tenant::size::ModelInputs;
+use crate::tenant::stale_timelines::StaleTimelinesError;
 use crate::tenant::storage_layer::LayerAccessStatsReset;
 use crate::tenant::storage_layer::LayerName;
+use crate::tenant::timeline::invalidations;
 use crate::tenant::timeline::CompactFlags;
 use crate::tenant::timeline::Timeline;
+use crate::tenant::timeline::{WaitLsnError, WaitLsnWaiter};
 use crate::tenant::SpawnMode;
 use crate::tenant::{LogicalSizeCalculationCause, PageReconstructError};
 use crate::{config::PageServerConf, tenant::mgr};
 use crate::{disk_usage_eviction_task, tenant};
 use pageserver_api::models::{
     StatusResponse, TenantConfigRequest, TenantCreateRequest, TenantCreateResponse, TenantInfo,
-    TimelineCreateRequest, TimelineGcRequest, TimelineInfo,
+    TimelineCreateRequest, TimelineGcRequest, TimelineInfo, TimelineRelationSizes,
+    TimelineWaitLsnRequest, TimelineWaitLsnResponse,
 };
 use utils::{
     auth::SwappableJwtAuth,
@@ -110,6 +131,8 @@ pub struct State {
     deletion_queue_client: DeletionQueueClient,
     secondary_controller: SecondaryController,
     latest_utilization: tokio::sync::Mutex<Option<(std::time::Instant, bytes::Bytes)>>,
+    tenant_ops: TenantOps,
+    config_templates: ConfigTemplates,
 }
 
 impl State {
@@ -128,6 +151,7 @@ impl State {
             .iter()
             .map(|v| v.parse().unwrap())
             .collect::<Vec<_>>();
+        let config_templates = ConfigTemplates::load(conf.tenant_config_templates_path())?;
         Ok(Self {
             conf,
             tenant_manager,
@@ -139,6 +163,8 @@ impl State {
             deletion_queue_client,
             secondary_controller,
             latest_utilization: Default::default(),
+            tenant_ops: TenantOps::default(),
+            config_templates,
         })
     }
 }
@@ -163,6 +189,73 @@ fn check_permission(request: &Request<Body>, tenant_id: Option<TenantId>) -> Res
     })
 }
 
+/// Parse the `tenant_shard_id` path parameter, which may also be an alias set in the
+/// tenant's config. A shard of a tenant with several shards on this pageserver is addressed
+/// by appending its shard suffix to the alias, like to an ID: `{alias}-{number}{count}`.
+///
+/// The caller's permission is checked before telling whether an alias exists: only callers
+/// allowed to access any tenant learn that an alias is unknown.
+fn parse_tenant_shard_id_param(request: &Request<Body>) -> Result<TenantShardId, ApiError> {
+    let param = get_request_param(request, "tenant_shard_id")?;
+    if let Ok(tenant_shard_id) = TenantShardId::from_str(param) {
+        return Ok(tenant_shard_id);
+    }
+
+    let (alias, shard_suffix) = match param.rsplit_once('-') {
+        Some((alias, suffix)) if is_shard_suffix(suffix) => (alias, Some(suffix)),
+        _ => (param, None),
+    };
+    let found = get_state(request)
+        .tenant_manager
+        .find_tenant_shards_by_alias(alias);
+    match found.first() {
+        Some(tenant_shard_id) => check_permission(request, Some(tenant_shard_id.tenant_id))?,
+        None => check_permission(request, None)?,
+    }
+
+    let not_found = || ApiError::NotFound(anyhow!("no tenant with ID or alias {param:?}").into());
+    match (shard_suffix, found.as_slice()) {
+        (Some(suffix), _) => found
+            .iter()
+            .find(|id| !id.is_unsharded() && id.shard_slug().to_string() == suffix)
+            .copied()
+            .ok_or_else(not_found),
+        (None, []) => Err(not_found()),
+        (None, [tenant_shard_id]) => Ok(*tenant_shard_id),
+        (None, _) => Err(ApiError::BadRequest(anyhow!(
+            "alias {param:?} matches several tenant shards, add the shard suffix to it: {}",
+            found
+                .iter()
+                .map(|id| format!("{alias}-{}", id.shard_slug()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// Whether `suffix` is the shard part of a [`TenantShardId`]: the shard number and count in hex.
+fn is_shard_suffix(suffix: &str) -> bool {
+    suffix.len() == 4 && suffix.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Parse the `timeline_id` path parameter, which may also be an alias set in the config
+/// of the tenant. Like for tenant aliases, the caller's permission is checked first.
+fn parse_timeline_id_param(
+    request: &Request<Body>,
+    tenant_shard_id: &TenantShardId,
+) -> Result<TimelineId, ApiError> {
+    let param = get_request_param(request, "timeline_id")?;
+    if let Ok(timeline_id) = TimelineId::from_str(param) {
+        return Ok(timeline_id);
+    }
+
+    check_permission(request, Some(tenant_shard_id.tenant_id))?;
+    get_state(request)
+        .tenant_manager
+        .resolve_timeline_alias(tenant_shard_id, param)
+        .ok_or_else(|| ApiError::NotFound(anyhow!("no timeline with ID or alias {param:?}").into()))
+}
+
 impl From<PageReconstructError> for ApiError {
     fn from(pre: PageReconstructError) -> ApiError {
         match pre {
@@ -173,6 +266,7 @@ impl From<PageReconstructError> for ApiError {
             PageReconstructError::Cancelled => {
                 ApiError::InternalServerError(anyhow::anyhow!("request was cancelled"))
             }
+            PageReconstructError::RequestCancelled => ApiError::Timeout(format!("{pre}").into()),
             PageReconstructError::AncestorStopping(_) => {
                 ApiError::ResourceUnavailable(format!("{pre}").into())
             }
@@ -182,6 +276,37 @@ impl From<PageReconstructError> for ApiError {
     }
 }
 
+impl From<TemplateError> for ApiError {
+    fn from(e: TemplateError) -> ApiError {
+        match e {
+            TemplateError::NotFound(_) => ApiError::NotFound(e.into()),
+            TemplateError::Invalid(e) => ApiError::BadRequest(e),
+            TemplateError::Other(e) => ApiError::InternalServerError(e),
+        }
+    }
+}
+
+impl From<StaleTimelinesError> for ApiError {
+    fn from(e: StaleTimelinesError) -> ApiError {
+        ApiError::BadRequest(e.into())
+    }
+}
+
+impl From<BundleError> for ApiError {
+    fn from(e: BundleError) -> ApiError {
+        match e {
+            BundleError::Sharded(_)
+            | BundleError::NotEmpty(_)
+            | BundleError::Invalid(_)
+            | BundleError::InvalidPath(_) => ApiError::BadRequest(e.into()),
+            BundleError::NotConfigured => ApiError::PreconditionFailed(e.to_string().into()),
+            BundleError::AlreadyExists(_) => ApiError::Conflict(e.to_string()),
+            BundleError::Cancelled => ApiError::ShuttingDown,
+            BundleError::Other(e) => ApiError::InternalServerError(e),
+        }
+    }
+}
+
 impl From<TenantMapInsertError> for ApiError {
     fn from(tmie: TenantMapInsertError) -> ApiError {
         match tmie {
@@ -226,6 +351,7 @@ impl From<UpsertLocationError> for ApiError {
             BadRequest(e) => ApiError::BadRequest(e),
             Unavailable(_) => ApiError::ShuttingDown,
             e @ InProgress => ApiError::Conflict(format!("{e}")),
+            AliasConflict(e) => ApiError::Conflict(format!("{e:#}")),
             Flush(e) | Other(e) => ApiError::InternalServerError(e),
         }
     }
@@ -297,6 +423,7 @@ impl From<crate::tenant::DeleteTimelineError> for ApiError {
                     .into_boxed_str(),
             ),
             a @ AlreadyInProgress(_) => ApiError::Conflict(a.to_string()),
+            LegalHold(e) => ApiError::Conflict(e.to_string()),
             Other(e) => ApiError::InternalServerError(e),
         }
     }
@@ -324,6 +451,7 @@ impl From<crate::tenant::delete::DeleteTenantError> for ApiError {
             Get(g) => ApiError::from(g),
             e @ AlreadyInProgress => ApiError::Conflict(e.to_string()),
             Timeline(t) => ApiError::from(t),
+            LegalHold(e) => ApiError::Conflict(e.to_string()),
             NotAttached => ApiError::NotFound(anyhow::anyhow!("Tenant is not attached").into()),
             SlotError(e) => e.into(),
             SlotUpsertError(e) => e.into(),
@@ -433,6 +561,10 @@ async fn build_timeline_info_common(
         state,
 
         walreceiver_status,
+
+        wal_ingest: timeline.wal_ingest_stats.info(),
+
+        deletion_progress: *timeline.deletion_progress.lock().unwrap(),
     };
     Ok(info)
 }
@@ -447,6 +579,17 @@ async fn status_handler(
     json_response(StatusCode::OK, StatusResponse { id: config.id })
 }
 
+async fn page_service_protocol_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+    json_response(
+        StatusCode::OK,
+        crate::page_service::negotiation::protocol_info(),
+    )
+}
+
 async fn reload_auth_validation_keys_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
@@ -477,15 +620,44 @@ async fn reload_auth_validation_keys_handler(
     }
 }
 
+async fn reload_config_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+
+    let response = tokio::task::spawn_blocking(crate::config::reload::reload)
+        .await
+        .map_err(|e| ApiError::InternalServerError(e.into()))?
+        .map_err(|e| match e {
+            ReloadError::NotInitialized => ApiError::ResourceUnavailable(e.to_string().into()),
+            ReloadError::Read(_) | ReloadError::Invalid(_) => {
+                ApiError::PreconditionFailed(e.to_string().into())
+            }
+        })?;
+    let status = if response.rejected.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::CONFLICT
+    };
+    json_response(status, response)
+}
+
 async fn timeline_create_handler(
     mut request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     let request_data: TimelineCreateRequest = json_request(&mut request).await?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let new_timeline_id = request_data.new_timeline_id;
+    let snapshot_id = request_data
+        .snapshot_id
+        .as_deref()
+        .map(SnapshotId::from_str)
+        .transpose()
+        .map_err(ApiError::BadRequest)?;
 
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Error);
 
@@ -500,6 +672,8 @@ async fn timeline_create_handler(
 
         if let Some(ancestor_id) = request_data.ancestor_timeline_id.as_ref() {
             tracing::info!(%ancestor_id, "starting to branch");
+        } else if let Some(snapshot_id) = snapshot_id.as_ref() {
+            tracing::info!(%snapshot_id, "creating from snapshot");
         } else {
             tracing::info!("bootstrapping");
         }
@@ -511,6 +685,7 @@ async fn timeline_create_handler(
                 request_data.ancestor_start_lsn,
                 request_data.pg_version.unwrap_or(crate::DEFAULT_PG_VERSION),
                 request_data.existing_initdb_timeline_id,
+                snapshot_id,
                 state.broker_client.clone(),
                 &ctx,
             )
@@ -549,10 +724,22 @@ async fn timeline_create_handler(
                 StatusCode::SERVICE_UNAVAILABLE,
                 HttpErrorBody::from_msg(e.to_string()),
             ),
+            Err(e @ tenant::CreateTimelineError::PgVersion(_)) => json_response(
+                StatusCode::BAD_REQUEST,
+                HttpErrorBody::from_msg(e.to_string()),
+            ),
+            Err(tenant::CreateTimelineError::Snapshot(err)) => json_response(
+                StatusCode::BAD_REQUEST,
+                HttpErrorBody::from_msg(format!("{err:#}")),
+            ),
             Err(tenant::CreateTimelineError::ShuttingDown) => json_response(
                 StatusCode::SERVICE_UNAVAILABLE,
                 HttpErrorBody::from_msg("tenant shutting down".to_string()),
             ),
+            Err(e @ tenant::CreateTimelineError::CompactionDebt { .. }) => json_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                HttpErrorBody::from_msg(e.to_string()),
+            ),
             Err(tenant::CreateTimelineError::Other(err)) => Err(ApiError::InternalServerError(err)),
         }
     }
@@ -570,7 +757,7 @@ async fn timeline_list_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     let include_non_incremental_logical_size: Option<bool> =
         parse_query_param(&request, "include-non-incremental-logical-size")?;
     let force_await_initial_logical_size: Option<bool> =
@@ -618,8 +805,8 @@ async fn timeline_preserve_initdb_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
     let state = get_state(&request);
 
@@ -658,8 +845,8 @@ async fn timeline_detail_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     let include_non_incremental_logical_size: Option<bool> =
         parse_query_param(&request, "include-non-incremental-logical-size")?;
     let force_await_initial_logical_size: Option<bool> =
@@ -706,7 +893,7 @@ async fn get_lsn_by_timestamp_handler(
     request: Request<Body>,
     cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
     let state = get_state(&request);
 
@@ -717,7 +904,7 @@ async fn get_lsn_by_timestamp_handler(
         )));
     }
 
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     let timestamp_raw = must_get_query_param(&request, "timestamp")?;
     let timestamp = humantime::parse_rfc3339(&timestamp_raw)
         .with_context(|| format!("Invalid time: {:?}", timestamp_raw))
@@ -757,7 +944,7 @@ async fn get_timestamp_of_lsn_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
     let state = get_state(&request);
 
@@ -768,7 +955,7 @@ async fn get_timestamp_of_lsn_handler(
         )));
     }
 
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
 
     let lsn_str = must_get_query_param(&request, "lsn")?;
     let lsn = Lsn::from_str(&lsn_str)
@@ -792,6 +979,170 @@ async fn get_timestamp_of_lsn_handler(
     }
 }
 
+async fn lsn_by_timestamp_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let timestamp_raw = must_get_query_param(&request, "timestamp")?;
+    let timestamp = humantime::parse_rfc3339(&timestamp_raw)
+        .with_context(|| format!("Invalid time: {:?}", timestamp_raw))
+        .map_err(ApiError::BadRequest)?;
+    if timestamp < postgres_ffi::from_pg_timestamp(0) {
+        return Err(ApiError::BadRequest(anyhow!(
+            "Time {timestamp_raw:?} is before the Postgres epoch"
+        )));
+    }
+
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
+    let bracket = timeline.lsn_time_bracket_by_timestamp(postgres_ffi::to_pg_timestamp(timestamp));
+    if bracket.before.is_none() && bracket.after.is_none() {
+        return Err(ApiError::NotFound(
+            anyhow!("No commit timestamps sampled on timeline {timeline_id}").into(),
+        ));
+    }
+    json_response(StatusCode::OK, bracket)
+}
+
+async fn timestamp_by_lsn_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let lsn_str = must_get_query_param(&request, "lsn")?;
+    let lsn = Lsn::from_str(&lsn_str)
+        .with_context(|| format!("Invalid LSN: {lsn_str:?}"))
+        .map_err(ApiError::BadRequest)?;
+
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
+    let bracket = timeline.lsn_time_bracket_by_lsn(lsn);
+    if bracket.before.is_none() && bracket.after.is_none() {
+        return Err(ApiError::NotFound(
+            anyhow!("No commit timestamps sampled on timeline {timeline_id}").into(),
+        ));
+    }
+    json_response(StatusCode::OK, bracket)
+}
+
+async fn timeline_explain_read_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let key_str = must_get_query_param(&request, "key")?;
+    let key = crate::repository::Key::from_hex(&key_str)
+        .with_context(|| format!("Invalid key: {key_str:?}"))
+        .map_err(ApiError::BadRequest)?;
+    let lsn_str = must_get_query_param(&request, "lsn")?;
+    let lsn = Lsn::from_str(&lsn_str)
+        .with_context(|| format!("Invalid LSN: {lsn_str:?}"))
+        .map_err(ApiError::BadRequest)?;
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
+    let last_record_lsn = timeline.get_last_record_lsn();
+    if lsn > last_record_lsn {
+        return Err(ApiError::BadRequest(anyhow!(
+            "LSN {lsn} is above the last record LSN {last_record_lsn}"
+        )));
+    }
+    let explanation = timeline
+        .explain_read(key, lsn, &ctx)
+        .await
+        .map_err(ApiError::InternalServerError)?;
+    json_response(StatusCode::OK, explanation)
+}
+
+async fn timeline_relation_sizes_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    if !tenant_shard_id.is_shard_zero() {
+        // Relation sizes are only stored on shard zero
+        return Err(ApiError::BadRequest(anyhow!(
+            "Relation sizes are only available on shard zero"
+        )));
+    }
+
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    let lsn: Option<Lsn> = parse_query_param(&request, "lsn")?;
+
+    async {
+        let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+        let timeline =
+            active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+                .await?;
+
+        let lsn = lsn.unwrap_or_else(|| timeline.get_last_record_lsn());
+        check_lsn_is_readable(&timeline, lsn, None)?;
+
+        let databases = timeline
+            .get_relation_sizes(lsn, &ctx)
+            .await
+            .map_err(|e| match e {
+                CalculateLogicalSizeError::Cancelled => ApiError::ShuttingDown,
+                CalculateLogicalSizeError::Other(e) => ApiError::InternalServerError(e),
+            })?;
+
+        json_response(
+            StatusCode::OK,
+            TimelineRelationSizes { lsn, databases },
+        )
+    }
+    .instrument(info_span!("timeline_relation_sizes", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), %timeline_id))
+    .await
+}
+
+/// Reads at `lsn` see all of its WAL, and all the page versions it needs are retained.
+///
+/// `key` is the key that the request reads, if it reads a single key: the history of the keys
+/// of pinned relations is readable below the GC cutoff.
+fn check_lsn_is_readable(
+    timeline: &Timeline,
+    lsn: Lsn,
+    key: Option<&crate::repository::Key>,
+) -> Result<(), ApiError> {
+    let last_record_lsn = timeline.get_last_record_lsn();
+    if lsn > last_record_lsn {
+        return Err(ApiError::BadRequest(anyhow!(
+            "LSN {lsn} is not ingested yet, last record LSN is {last_record_lsn}"
+        )));
+    }
+    let latest_gc_cutoff_lsn = *timeline.get_latest_gc_cutoff_lsn();
+    let gc_cutoff_lsn = match key {
+        Some(key) => timeline.get_gc_cutoff_for_key(key, latest_gc_cutoff_lsn),
+        None => latest_gc_cutoff_lsn,
+    };
+    if lsn < gc_cutoff_lsn {
+        return Err(ApiError::BadRequest(anyhow!(
+            "LSN {lsn} is below the GC cutoff {gc_cutoff_lsn}"
+        )));
+    }
+    Ok(())
+}
+
 async fn tenant_attach_handler(
     mut request: Request<Body>,
     _cancel: CancellationToken,
@@ -854,8 +1205,8 @@ async fn timeline_delete_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let state = get_state(&request);
@@ -887,6 +1238,7 @@ async fn tenant_detach_handler(
     let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
     check_permission(&request, Some(tenant_id))?;
     let detach_ignored: Option<bool> = parse_query_param(&request, "detach_ignored")?;
+    let keep_local: Option<bool> = parse_query_param(&request, "keep_local")?;
 
     // This is a legacy API (`/location_conf` is the replacement).  It only supports unsharded tenants
     let tenant_shard_id = TenantShardId::unsharded(tenant_id);
@@ -899,6 +1251,7 @@ async fn tenant_detach_handler(
             conf,
             tenant_shard_id,
             detach_ignored.unwrap_or(false),
+            keep_local.unwrap_or(false),
             &state.deletion_queue_client,
         )
         .instrument(info_span!("tenant_detach", %tenant_id, shard_id=%tenant_shard_id.shard_slug()))
@@ -911,7 +1264,7 @@ async fn tenant_reset_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let drop_cache: Option<bool> = parse_query_param(&request, "drop_cache")?;
@@ -1005,7 +1358,7 @@ async fn tenant_status(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
     let state = get_state(&request);
 
@@ -1061,7 +1414,7 @@ async fn tenant_delete_handler(
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
     // TODO openapi spec
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let state = get_state(&request);
@@ -1095,7 +1448,7 @@ async fn tenant_size_handler(
     request: Request<Body>,
     cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
     let inputs_only: Option<bool> = parse_query_param(&request, "inputs_only")?;
     let retention_period: Option<u64> = parse_query_param(&request, "retention_period")?;
@@ -1178,7 +1531,7 @@ async fn tenant_shard_split_handler(
 ) -> Result<Response<Body>, ApiError> {
     let req: TenantShardSplitRequest = json_request(&mut request).await?;
 
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     let state = get_state(&request);
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Warn);
 
@@ -1205,8 +1558,8 @@ async fn layer_map_info_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     let reset: LayerAccessStatsReset =
         parse_query_param(&request, "reset")?.unwrap_or(LayerAccessStatsReset::NoReset);
     let state = get_state(&request);
@@ -1221,12 +1574,59 @@ async fn layer_map_info_handler(
     json_response(StatusCode::OK, layer_map_info)
 }
 
+async fn timeline_upload_queue_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    let state = get_state(&request);
+
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
+    let Some(remote_client) = timeline.remote_client.as_ref() else {
+        return Err(ApiError::PreconditionFailed(
+            "timeline has no remote storage".into(),
+        ));
+    };
+    let info = remote_client
+        .upload_queue_info()
+        .map_err(|e| ApiError::ResourceUnavailable(format!("upload queue: {e}").into()))?;
+
+    json_response(StatusCode::OK, info)
+}
+
+async fn layer_manifest_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    let state = get_state(&request);
+
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
+    let entries = timeline
+        .layer_manifest
+        .read()
+        .await
+        .map_err(ApiError::InternalServerError)?;
+
+    json_response(StatusCode::OK, entries)
+}
+
 async fn layer_download_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     let layer_file_name = get_request_param(&request, "layer_file_name")?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
     let layer_name = LayerName::from_str(layer_file_name)
@@ -1255,9 +1655,9 @@ async fn evict_timeline_layer_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     let layer_file_name = get_request_param(&request, "layer_file_name")?;
     let state = get_state(&request);
 
@@ -1366,11 +1766,11 @@ async fn tenant_create_handler(
         .expect("bug")
         .start_timer();
 
-    let tenant_conf =
-        TenantConfOpt::try_from(&request_data.config).map_err(ApiError::BadRequest)?;
-
     let state = get_state(&request);
 
+    let tenant_conf = state.config_templates.apply(&request_data.config)?;
+    let tenant_conf = TenantConfOpt::try_from(&tenant_conf).map_err(ApiError::BadRequest)?;
+
     let generation = get_request_generation(state, request_data.generation)?;
 
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Warn);
@@ -1411,7 +1811,7 @@ async fn get_tenant_config_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
     let state = get_state(&request);
 
@@ -1419,7 +1819,7 @@ async fn get_tenant_config_handler(
         .tenant_manager
         .get_attached_tenant_shard(tenant_shard_id)?;
 
-    let response = HashMap::from([
+    let mut response = HashMap::from([
         (
             "tenant_specific_overrides",
             serde_json::to_value(tenant.tenant_specific_overrides())
@@ -1433,56 +1833,379 @@ async fn get_tenant_config_handler(
                 .map_err(ApiError::InternalServerError)?,
         ),
     ]);
+    // The template may be missing here, when the tenant was set up on another pageserver.
+    let template = tenant
+        .tenant_specific_overrides()
+        .template
+        .and_then(|reference| state.config_templates.get(&reference).ok());
+    if let Some(template) = template {
+        let tenant_config = TenantConfig::from(tenant.tenant_specific_overrides());
+        let overrides = config_templates::overrides(&template.config, &tenant_config)
+            .map_err(ApiError::InternalServerError)?;
+        response.insert(
+            "template_overrides",
+            serde_json::to_value(overrides)
+                .context("serializing template overrides")
+                .map_err(ApiError::InternalServerError)?,
+        );
+    }
+    let etag = config_etag(&tenant.tenant_specific_overrides())?;
 
-    json_response(StatusCode::OK, response)
+    let mut response = json_response(StatusCode::OK, response)?;
+    response.headers_mut().insert(
+        header::ETAG,
+        header::HeaderValue::from_str(&etag)
+            .map_err(|e| ApiError::InternalServerError(e.into()))?,
+    );
+    Ok(response)
 }
 
-async fn update_tenant_config_handler(
-    mut request: Request<Body>,
+async fn tenant_resource_usage_handler(
+    request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let request_data: TenantConfigRequest = json_request(&mut request).await?;
-    let tenant_id = request_data.tenant_id;
-    check_permission(&request, Some(tenant_id))?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
 
-    let new_tenant_conf =
-        TenantConfOpt::try_from(&request_data.config).map_err(ApiError::BadRequest)?;
+    // Only report the usage of tenants attached here: a detached tenant's usage is gone.
+    state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
 
-    let state = get_state(&request);
+    json_response(
+        StatusCode::OK,
+        task_mgr::resource_usage::get(&tenant_shard_id),
+    )
+}
 
-    let tenant_shard_id = TenantShardId::unsharded(tenant_id);
+async fn tenant_activation_profile_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
 
     let tenant = state
         .tenant_manager
         .get_attached_tenant_shard(tenant_shard_id)?;
-    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
-
-    // This is a legacy API that only operates on attached tenants: the preferred
-    // API to use is the location_config/ endpoint, which lets the caller provide
-    // the full LocationConf.
-    let location_conf = LocationConf::attached_single(
-        new_tenant_conf.clone(),
-        tenant.get_generation(),
-        &ShardParameters::default(),
-    );
-
-    crate::tenant::Tenant::persist_tenant_config(state.conf, &tenant_shard_id, &location_conf)
-        .await
-        .map_err(ApiError::InternalServerError)?;
-    tenant.set_new_tenant_config(new_tenant_conf);
 
-    json_response(StatusCode::OK, ())
+    json_response(StatusCode::OK, tenant.activation_profile.get())
 }
 
-async fn put_tenant_location_config_handler(
-    mut request: Request<Body>,
+async fn tenant_state_transitions_handler(
+    request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-
-    let request_data: TenantLocationConfigRequest = json_request(&mut request).await?;
-    let flush = parse_query_param(&request, "flush_ms")?.map(Duration::from_millis);
-    let lazy = parse_query_param(&request, "lazy")?.unwrap_or(false);
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+
+    json_response(StatusCode::OK, tenant.state_transitions.get())
+}
+
+async fn tenant_legal_hold_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+
+    json_response(StatusCode::OK, tenant.legal_hold.status())
+}
+
+async fn tenant_legal_hold_put_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let request_data: LegalHoldRequest = json_request(&mut request).await?;
+    if request_data.reason.is_empty() {
+        return Err(ApiError::BadRequest(anyhow!("a legal hold needs a reason")));
+    }
+    let state = get_state(&request);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    let hold = tenant
+        .set_legal_hold(request_data.reason)
+        .await
+        .map_err(ApiError::InternalServerError)?;
+
+    json_response(StatusCode::OK, hold)
+}
+
+async fn tenant_legal_hold_delete_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    tenant
+        .release_legal_hold()
+        .await
+        .map_err(ApiError::InternalServerError)?;
+
+    json_response(StatusCode::OK, ())
+}
+
+async fn tenant_read_only_put_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let request_data: ReadOnlyRequest = json_request(&mut request).await?;
+    let state = get_state(&request);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    tenant
+        .set_read_only(Some(request_data.reason))
+        .await
+        .map_err(ApiError::InternalServerError)?;
+
+    json_response(StatusCode::OK, ())
+}
+
+async fn tenant_read_only_delete_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    tenant
+        .set_read_only(None)
+        .await
+        .map_err(ApiError::InternalServerError)?;
+
+    json_response(StatusCode::OK, ())
+}
+
+/// Smoke test of the read and write path of the tenant shard, see [`crate::tenant::selftest`].
+async fn tenant_selftest_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let request_data: TenantSelftestRequest = json_request_or_empty_body(&mut request)
+        .await?
+        .unwrap_or_default();
+    let state = get_state(&request);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+    let response = tenant
+        .selftest(request_data.remote, &ctx)
+        .instrument(info_span!("selftest", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug()))
+        .await
+        .map_err(ApiError::InternalServerError)?;
+
+    json_response(StatusCode::OK, response)
+}
+
+async fn tenant_export_handler(
+    mut request: Request<Body>,
+    cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    // The bundle is written to the filesystem of the pageserver.
+    check_permission(&request, None)?;
+    let request_data: TenantExportRequest = json_request(&mut request).await?;
+    let state = get_state(&request);
+    let path = bundle::resolve_bundle_path(state.conf, &request_data.path)?;
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    let manifest = bundle::export_tenant(&tenant, &path, &cancel).await?;
+
+    json_response(StatusCode::OK, manifest)
+}
+
+async fn tenant_import_handler(
+    mut request: Request<Body>,
+    cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&request, "tenant_id")?;
+    // The bundle is read from the filesystem of the pageserver.
+    check_permission(&request, None)?;
+    let request_data: TenantImportRequest = json_request(&mut request).await?;
+    let state = get_state(&request);
+    let path = bundle::resolve_bundle_path(state.conf, &request_data.path)?;
+
+    // Like time travel, importing is only safe while the tenant isn't attached anywhere.
+    if state
+        .tenant_manager
+        .manages_tenant_shard(TenantShardId::unsharded(tenant_id))
+    {
+        return Err(ApiError::BadRequest(anyhow!(
+            "Tenant {tenant_id} is already attached at this pageserver"
+        )));
+    }
+    let Some(storage) = state.remote_storage.as_ref() else {
+        return Err(ApiError::InternalServerError(anyhow!(
+            "remote storage not configured, cannot import a tenant"
+        )));
+    };
+
+    let manifest = bundle::import_tenant(
+        state.conf,
+        storage,
+        tenant_id,
+        &path,
+        Generation::new(request_data.generation),
+        &cancel,
+    )
+    .await?;
+
+    json_response(StatusCode::OK, manifest)
+}
+
+async fn tenant_config_templates_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+    let state = get_state(&request);
+
+    json_response(StatusCode::OK, state.config_templates.list())
+}
+
+async fn tenant_config_template_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+    let name: String = parse_request_param(&request, "name")?;
+    let state = get_state(&request);
+
+    json_response(StatusCode::OK, state.config_templates.versions(&name)?)
+}
+
+async fn tenant_config_template_put_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+    let name: String = parse_request_param(&request, "name")?;
+    let config: TenantConfig = json_request(&mut request).await?;
+    let state = get_state(&request);
+
+    let template = state.config_templates.put(&name, config).await?;
+
+    json_response(StatusCode::OK, template)
+}
+
+async fn update_tenant_config_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let request_data: TenantConfigRequest = json_request(&mut request).await?;
+    let tenant_id = request_data.tenant_id;
+    check_permission(&request, Some(tenant_id))?;
+
+    let state = get_state(&request);
+
+    let new_tenant_conf = state.config_templates.apply(&request_data.config)?;
+    let mut new_tenant_conf =
+        TenantConfOpt::try_from(&new_tenant_conf).map_err(ApiError::BadRequest)?;
+    let if_match = request
+        .headers()
+        .get(header::IF_MATCH)
+        .map(|v| v.to_str())
+        .transpose()
+        .map_err(|e| ApiError::BadRequest(anyhow!("invalid If-Match header: {e}")))?;
+
+    let tenant_shard_id = TenantShardId::unsharded(tenant_id);
+
+    // The tenant id is in the body, so this can't go through tenant_op_handler.
+    let _op_guard = state
+        .tenant_ops
+        .start(tenant_shard_id, TenantOp::UpdateConfig)?;
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    check_if_match(if_match, &config_etag(&tenant.tenant_specific_overrides())?)?;
+
+    // The read-only mode is unset with its own API, so that the config updates of the callers
+    // that don't know about it don't lift it.
+    if new_tenant_conf.read_only.is_none() {
+        new_tenant_conf.read_only = tenant.tenant_specific_overrides().read_only;
+    }
+
+    state
+        .tenant_manager
+        .claim_tenant_alias(&tenant_shard_id, &new_tenant_conf)
+        .map_err(|e| ApiError::Conflict(format!("{e:#}")))?;
+
+    // This is a legacy API that only operates on attached tenants: the preferred
+    // API to use is the location_config/ endpoint, which lets the caller provide
+    // the full LocationConf.
+    let location_conf = LocationConf::attached_single(
+        new_tenant_conf.clone(),
+        tenant.get_generation(),
+        &ShardParameters::default(),
+    );
+
+    crate::tenant::Tenant::persist_tenant_config(state.conf, &tenant_shard_id, &location_conf)
+        .await
+        .map_err(ApiError::InternalServerError)?;
+    tenant.set_new_tenant_config(new_tenant_conf);
+
+    json_response(StatusCode::OK, ())
+}
+
+async fn put_tenant_location_config_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+
+    let request_data: TenantLocationConfigRequest = json_request(&mut request).await?;
+    let flush = parse_query_param(&request, "flush_ms")?.map(Duration::from_millis);
+    let lazy = parse_query_param(&request, "lazy")?.unwrap_or(false);
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Warn);
@@ -1494,7 +2217,13 @@ async fn put_tenant_location_config_handler(
     if let LocationConfigMode::Detached = request_data.config.mode {
         if let Err(e) = state
             .tenant_manager
-            .detach_tenant(conf, tenant_shard_id, true, &state.deletion_queue_client)
+            .detach_tenant(
+                conf,
+                tenant_shard_id,
+                true,
+                false,
+                &state.deletion_queue_client,
+            )
             .instrument(info_span!("tenant_detach",
                 tenant_id = %tenant_shard_id.tenant_id,
                 shard_id = %tenant_shard_id.shard_slug()
@@ -1511,8 +2240,29 @@ async fn put_tenant_location_config_handler(
         return json_response(StatusCode::OK, ());
     }
 
-    let location_conf =
-        LocationConf::try_from(&request_data.config).map_err(ApiError::BadRequest)?;
+    let location_config = request_data.config;
+    // Templates are resolved where the config is set, see `config_templates`.
+    if let Some(reference) = &location_config.tenant_conf.template {
+        if !TenantConfigTemplate::is_pinned(reference) {
+            return Err(ApiError::BadRequest(anyhow!(
+                "location config references template {reference:?} without a version"
+            )));
+        }
+    }
+    let mut location_conf =
+        LocationConf::try_from(&location_config).map_err(ApiError::BadRequest)?;
+    // The caller carries the read-only mode of the tenant in the config, like the storage
+    // controller does, except for the offloading, which is local to this pageserver.
+    if location_conf.tenant_conf.read_only.is_none() {
+        if let Ok(tenant) = state
+            .tenant_manager
+            .get_attached_tenant_shard(tenant_shard_id)
+        {
+            if tenant.tenant_specific_overrides().read_only == Some(ReadOnlyReason::Offloaded) {
+                location_conf.tenant_conf.read_only = Some(ReadOnlyReason::Offloaded);
+            }
+        }
+    }
 
     // lazy==true queues up for activation or jumps the queue like normal when a compute connects,
     // similar to at startup ordering.
@@ -1595,7 +2345,7 @@ async fn get_location_config_handler(
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
     let state = get_state(&request);
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     let slot = state.tenant_manager.get(tenant_shard_id);
 
     let Some(slot) = slot else {
@@ -1619,7 +2369,7 @@ async fn tenant_time_travel_remote_storage_handler(
     request: Request<Body>,
     cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
 
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
@@ -1691,7 +2441,7 @@ async fn handle_tenant_break(
     r: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&r, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&r)?;
 
     let state = get_state(&r);
     state
@@ -1708,8 +2458,8 @@ async fn timeline_gc_handler(
     mut request: Request<Body>,
     cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let gc_req: TimelineGcRequest = json_request(&mut request).await?;
@@ -1730,8 +2480,8 @@ async fn timeline_compact_handler(
     request: Request<Body>,
     cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let state = get_state(&request);
@@ -1762,8 +2512,8 @@ async fn timeline_checkpoint_handler(
     request: Request<Body>,
     cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let state = get_state(&request);
@@ -1794,12 +2544,78 @@ async fn timeline_checkpoint_handler(
     .await
 }
 
+/// Wait until the timeline has ingested, and optionally flushed, the given LSN, e.g. so that
+/// a branch created afterwards contains the caller's last write.
+async fn timeline_wait_lsn_handler(
+    mut request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    let body: TimelineWaitLsnRequest = json_request(&mut request).await?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+
+    let state = get_state(&request);
+    let timeout = body.timeout.unwrap_or(state.conf.wait_lsn_timeout);
+
+    async {
+        let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+        let timeline = active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id).await?;
+
+        let started_at = std::time::Instant::now();
+        match timeline
+            .wait_lsn_timeout(body.lsn, WaitLsnWaiter::HttpEndpoint, timeout, &ctx)
+            .await
+        {
+            Ok(()) => {
+                if body.flush && timeline.get_disk_consistent_lsn() < body.lsn {
+                    // Ingestion flushes the open layer only once it is large or old enough,
+                    // which can take much longer than the caller is willing to wait.
+                    let remaining = timeout.saturating_sub(started_at.elapsed());
+                    if let Ok(res) = tokio::time::timeout(remaining, timeline.freeze_and_flush()).await {
+                        res.map_err(ApiError::InternalServerError)?;
+                    }
+                }
+            }
+            Err(WaitLsnError::Timeout(_)) => {}
+            Err(WaitLsnError::Shutdown) => return Err(ApiError::ShuttingDown),
+            Err(WaitLsnError::BadState) => {
+                return Err(ApiError::ResourceUnavailable("timeline is not active".into()))
+            }
+        }
+
+        let last_record_lsn = timeline.get_last_record_lsn();
+        let disk_consistent_lsn = timeline.get_disk_consistent_lsn();
+        let current_lsn = if body.flush {
+            disk_consistent_lsn
+        } else {
+            last_record_lsn
+        };
+        let reached = current_lsn >= body.lsn;
+        let response = TimelineWaitLsnResponse {
+            reached,
+            last_record_lsn,
+            disk_consistent_lsn,
+            lag_bytes: body.lsn.0.saturating_sub(current_lsn.0),
+            waited: started_at.elapsed(),
+        };
+        let status = if reached {
+            StatusCode::OK
+        } else {
+            StatusCode::REQUEST_TIMEOUT
+        };
+        json_response(status, response)
+    }
+    .instrument(info_span!("timeline_wait_lsn", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), %timeline_id, lsn = %body.lsn))
+    .await
+}
+
 async fn timeline_download_remote_layers_handler_post(
     mut request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     let body: DownloadRemoteLayersTaskSpawnRequest = json_request(&mut request).await?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
@@ -1818,9 +2634,9 @@ async fn timeline_download_remote_layers_handler_get(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     let state = get_state(&request);
 
     let timeline =
@@ -1838,9 +2654,9 @@ async fn timeline_detach_ancestor_handler(
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
     use crate::tenant::timeline::detach_ancestor::Options;
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
 
     let span = tracing::info_span!("detach_ancestor", tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), %timeline_id);
 
@@ -1935,77 +2751,279 @@ async fn deletion_queue_flush(
     }
 }
 
-/// Try if `GetPage@Lsn` is successful, useful for manual debugging.
-async fn getpage_at_lsn_handler(
+/// A page of a relation as of an LSN, for tools which check or inspect the data of a timeline
+/// without a compute.
+async fn timeline_rel_page_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let rel: RelTag = parse_query_param(&request, "rel")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'rel' query parameter")))?;
+    if rel.relnode == 0 {
+        return Err(ApiError::BadRequest(anyhow!("invalid relnode 0")));
+    }
+    let blkno: BlockNumber = parse_query_param(&request, "blkno")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'blkno' query parameter")))?;
+    let lsn: Lsn = parse_query_param(&request, "lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'lsn' query parameter")))?;
+
+    async {
+        let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+        let timeline = active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id).await?;
+
+        let shard = timeline.get_shard_identity();
+        let key = rel_block_to_key(rel, blkno);
+        if !shard.is_key_local(&key) {
+            return Err(ApiError::BadRequest(anyhow!(
+                "block {blkno} of {rel} is stored on shard {}",
+                shard.get_shard_number(&key).0
+            )));
+        }
+        check_lsn_is_readable(&timeline, lsn, Some(&key))?;
+
+        let version = Version::Lsn(lsn);
+        // Only shard zero stores relation sizes.
+        if shard.is_shard_zero() {
+            if !timeline.get_rel_exists(rel, version, &ctx).await? {
+                return Err(ApiError::NotFound(anyhow!("relation {rel} does not exist at {lsn}").into()));
+            }
+            let nblocks = timeline.get_rel_size(rel, version, &ctx).await?;
+            if blkno >= nblocks {
+                return Err(ApiError::NotFound(
+                    anyhow!("block {blkno} is beyond the end of {rel} at {lsn}, which has {nblocks} blocks").into(),
+                ));
+            }
+        }
+        let page = timeline.get_rel_page_at_lsn(rel, blkno, version, &ctx).await?;
+
+        Result::<_, ApiError>::Ok(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(hyper::Body::from(page))
+                .unwrap(),
+        )
+    }
+    .instrument(info_span!("timeline_rel_page", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), %timeline_id, %rel, blkno, %lsn))
+    .await
+}
+
+/// Try if `GetPage@Lsn` is successful, useful for manual debugging.
+async fn getpage_at_lsn_handler(
+    request: Request<Body>,
+    cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    struct Key(crate::repository::Key);
+
+    impl std::str::FromStr for Key {
+        type Err = anyhow::Error;
+
+        fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+            crate::repository::Key::from_hex(s).map(Key)
+        }
+    }
+
+    let key: Key = parse_query_param(&request, "key")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'key' query parameter")))?;
+    let lsn: Lsn = parse_query_param(&request, "lsn")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'lsn' query parameter")))?;
+
+    async {
+        // Give up the read if the client disconnects.
+        let ctx = RequestContextBuilder::new(TaskKind::MgmtRequest)
+            .cancel(&cancel)
+            .build();
+        let timeline = active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id).await?;
+
+        let page = timeline.get(key.0, lsn, &ctx).await?;
+
+        Result::<_, ApiError>::Ok(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(hyper::Body::from(page))
+                .unwrap(),
+        )
+    }
+    .instrument(info_span!("timeline_get", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), %timeline_id))
+    .await
+}
+
+async fn timeline_collect_keyspace(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let at_lsn: Option<Lsn> = parse_query_param(&request, "at_lsn")?;
+
+    async {
+        let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+        let timeline = active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id).await?;
+        let at_lsn = at_lsn.unwrap_or_else(|| timeline.get_last_record_lsn());
+        let (dense_ks, sparse_ks) = timeline
+            .collect_keyspace(at_lsn, &ctx)
+            .await
+            .map_err(|e| ApiError::InternalServerError(e.into()))?;
+
+        // This API is currently used by pagebench. Pagebench will iterate all keys within the keyspace.
+        // Therefore, we split dense/sparse keys in this API.
+        let res = pageserver_api::models::partitioning::Partitioning { keys: dense_ks, sparse_keys: sparse_ks, at_lsn };
+
+        json_response(StatusCode::OK, res)
+    }
+    .instrument(info_span!("timeline_collect_keyspace", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), %timeline_id))
+    .await
+}
+
+/// The key ranges with the most delta layers stacked above their latest image layer.
+async fn timeline_layer_overlap_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    let threshold: usize = parse_query_param(&request, "threshold")?.unwrap_or(0);
+    let limit: usize = parse_query_param(&request, "limit")?.unwrap_or(10);
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
+    let mut hotspots = timeline.analyze_layer_overlap(threshold).await;
+    hotspots.truncate(limit);
+
+    json_response(
+        StatusCode::OK,
+        LayerOverlapReport {
+            threshold,
+            hotspots,
+        },
+    )
+}
+
+/// Statistics of the deltas written to the layers of the timeline, by relation.
+async fn timeline_key_space_stats_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    let limit: usize = parse_query_param(&request, "limit")?.unwrap_or(100);
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
+
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
+
+    json_response(StatusCode::OK, timeline.key_space_stats.get(limit))
+}
+
+fn parse_idle_days_param(request: &Request<Body>) -> Result<Duration, ApiError> {
+    let idle_days: u32 = parse_query_param(request, "idle_days")?
+        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'idle_days' query parameter")))?;
+    Ok(Duration::from_secs(24 * 60 * 60) * idle_days)
+}
+
+/// The branches of the tenant shard that were neither read nor written for `idle_days`, see
+/// [`crate::tenant::stale_timelines`].
+async fn tenant_stale_timelines_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let idle = parse_idle_days_param(&request)?;
+    let state = get_state(&request);
+
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+    json_response(StatusCode::OK, tenant.stale_timelines(idle)?)
+}
+
+/// Start the deletion of the stale branches without children, or only list those that would be
+/// deleted with `dry_run=true`.
+async fn tenant_stale_timelines_delete_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let idle = parse_idle_days_param(&request)?;
+    let dry_run = parse_query_param(&request, "dry_run")?.unwrap_or(false);
     let state = get_state(&request);
 
-    struct Key(crate::repository::Key);
-
-    impl std::str::FromStr for Key {
-        type Err = anyhow::Error;
+    let tenant = state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)?;
+    tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
 
-        fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-            crate::repository::Key::from_hex(s).map(Key)
-        }
-    }
+    let stale = tenant
+        .delete_stale_timelines(idle, dry_run)
+        .instrument(info_span!("delete_stale_timelines", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), dry_run))
+        .await?;
 
-    let key: Key = parse_query_param(&request, "key")?
-        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'key' query parameter")))?;
-    let lsn: Lsn = parse_query_param(&request, "lsn")?
-        .ok_or_else(|| ApiError::BadRequest(anyhow!("missing 'lsn' query parameter")))?;
+    json_response(StatusCode::OK, stale)
+}
 
-    async {
-        let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
-        let timeline = active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id).await?;
+/// Hourly rollups of the page reads and of the ingest of the timeline.
+async fn timeline_access_log_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
+    check_permission(&request, Some(tenant_shard_id.tenant_id))?;
+    let state = get_state(&request);
 
-        let page = timeline.get(key.0, lsn, &ctx).await?;
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
 
-        Result::<_, ApiError>::Ok(
-            Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, "application/octet-stream")
-                .body(hyper::Body::from(page))
-                .unwrap(),
-        )
-    }
-    .instrument(info_span!("timeline_get", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), %timeline_id))
-    .await
+    json_response(StatusCode::OK, timeline.access_log.get())
 }
 
-async fn timeline_collect_keyspace(
+/// Stream the keys changed by ingest, as newline-delimited
+/// [`pageserver_api::models::KeyInvalidation`]s, until the client disconnects or the timeline
+/// shuts down.
+async fn timeline_invalidations_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
-    let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    let timeline_id = parse_timeline_id_param(&request, &tenant_shard_id)?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
     let state = get_state(&request);
 
-    let at_lsn: Option<Lsn> = parse_query_param(&request, "at_lsn")?;
-
-    async {
-        let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
-        let timeline = active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id).await?;
-        let at_lsn = at_lsn.unwrap_or_else(|| timeline.get_last_record_lsn());
-        let (dense_ks, sparse_ks) = timeline
-            .collect_keyspace(at_lsn, &ctx)
-            .await
-            .map_err(|e| ApiError::InternalServerError(e.into()))?;
-
-        // This API is currently used by pagebench. Pagebench will iterate all keys within the keyspace.
-        // Therefore, we split dense/sparse keys in this API.
-        let res = pageserver_api::models::partitioning::Partitioning { keys: dense_ks, sparse_keys: sparse_ks, at_lsn };
+    let timeline =
+        active_timeline_of_active_tenant(&state.tenant_manager, tenant_shard_id, timeline_id)
+            .await?;
+    let rx = timeline.invalidations.subscribe();
+    let body = Body::wrap_stream(invalidations::json_lines(rx, timeline.cancel.clone()));
 
-        json_response(StatusCode::OK, res)
-    }
-    .instrument(info_span!("timeline_collect_keyspace", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), %timeline_id))
-    .await
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(body)
+        .map_err(|e| ApiError::InternalServerError(e.into()))
 }
 
 async fn active_timeline_of_active_tenant(
@@ -2092,6 +3110,7 @@ async fn disk_usage_eviction_run(
         usage,
         &state.tenant_manager,
         config.eviction_order,
+        None,
         &cancel,
     )
     .await;
@@ -2103,12 +3122,34 @@ async fn disk_usage_eviction_run(
     json_response(StatusCode::OK, res)
 }
 
+async fn disk_usage_forecast_handler(
+    r: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&r, None)?;
+    let state = get_state(&r);
+    json_response(StatusCode::OK, state.disk_usage_eviction_state.forecast())
+}
+
+async fn disk_usage_offloaded_handler(
+    r: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&r, None)?;
+    let state = get_state(&r);
+    let offloaded = state
+        .disk_usage_eviction_state
+        .offloaded_tenants(&state.tenant_manager)
+        .map_err(ApiError::InternalServerError)?;
+    json_response(StatusCode::OK, offloaded)
+}
+
 async fn secondary_upload_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
     let state = get_state(&request);
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     state
         .secondary_controller
         .upload_tenant(tenant_shard_id)
@@ -2196,7 +3237,7 @@ async fn secondary_download_handler(
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
     let state = get_state(&request);
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
     let wait = parse_query_param(&request, "wait_ms")?.map(Duration::from_millis);
 
     // We don't need this to issue the download request, but:
@@ -2235,12 +3276,96 @@ async fn secondary_download_handler(
     json_response(status, progress)
 }
 
+async fn load_scores_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&request, None)?;
+    let state = get_state(&request);
+
+    let tenant_shard_ids = state
+        .tenant_manager
+        .list_tenants()
+        .map_err(|_| {
+            ApiError::ResourceUnavailable("Tenant map is initializing or shutting down".into())
+        })?
+        .into_iter()
+        .filter(|(_, tenant_state, _)| matches!(tenant_state, TenantState::Active))
+        .map(|(id, _, _)| id);
+
+    let mut tenants = Vec::new();
+    for tenant_shard_id in tenant_shard_ids {
+        // Detached since the listing: skip it.
+        let Ok(tenant) = state
+            .tenant_manager
+            .get_attached_tenant_shard(tenant_shard_id)
+        else {
+            continue;
+        };
+        tenants.push(crate::tenant::rebalance::load_score(&tenant).await);
+    }
+
+    json_response(StatusCode::OK, TenantLoadScores { tenants })
+}
+
+async fn rebalance_prepare_give_up_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, None)?;
+    let state = get_state(&request);
+
+    async {
+        let tenant = state
+            .tenant_manager
+            .get_attached_tenant_shard(tenant_shard_id)?;
+        tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
+
+        crate::tenant::rebalance::prepare_give_up(&tenant)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+        state
+            .secondary_controller
+            .upload_tenant(tenant_shard_id)
+            .await
+            .map_err(ApiError::InternalServerError)?;
+
+        json_response(StatusCode::OK, ())
+    }
+    .instrument(info_span!("rebalance_prepare_give_up", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug()))
+    .await
+}
+
+/// Like the secondary download API, but refuses tenant shards attached here: they are not
+/// moving to this pageserver.
+async fn rebalance_prepare_receive_handler(
+    request: Request<Body>,
+    cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+    check_permission(&request, None)?;
+    let state = get_state(&request);
+
+    if state
+        .tenant_manager
+        .get_attached_tenant_shard(tenant_shard_id)
+        .is_ok()
+    {
+        return Err(ApiError::Conflict(format!(
+            "Shard {tenant_shard_id} is already attached to this pageserver"
+        )));
+    }
+
+    secondary_download_handler(request, cancel).await
+}
+
 async fn secondary_status_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
     let state = get_state(&request);
-    let tenant_shard_id: TenantShardId = parse_request_param(&request, "tenant_shard_id")?;
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
 
     let Some(secondary_tenant) = state
         .tenant_manager
@@ -2256,6 +3381,27 @@ async fn secondary_status_handler(
     json_response(StatusCode::OK, progress)
 }
 
+async fn secondary_remote_index_handler(
+    request: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    let state = get_state(&request);
+    let tenant_shard_id = parse_tenant_shard_id_param(&request)?;
+
+    let Some(secondary_tenant) = state
+        .tenant_manager
+        .get_secondary_tenant_shard(tenant_shard_id)
+    else {
+        return Err(ApiError::NotFound(
+            anyhow::anyhow!("Shard {} not found", tenant_shard_id).into(),
+        ));
+    };
+
+    let remote_index = secondary_tenant.remote_index.lock().unwrap().clone();
+
+    json_response(StatusCode::OK, remote_index)
+}
+
 async fn handler_404(_: Request<Body>) -> Result<Response<Body>, ApiError> {
     json_response(
         StatusCode::NOT_FOUND,
@@ -2306,6 +3452,31 @@ async fn put_io_engine_handler(
     json_response(StatusCode::OK, ())
 }
 
+/// See [`crate::tenant::startup_repair`].
+async fn get_startup_repairs(
+    r: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&r, None)?;
+
+    json_response(StatusCode::OK, crate::tenant::startup_repair::list())
+}
+
+/// The supported postgres versions, whether they are installed, and what uses them.
+///
+/// See [`crate::pg_versions`].
+async fn pg_versions_handler(
+    r: Request<Body>,
+    _cancel: CancellationToken,
+) -> Result<Response<Body>, ApiError> {
+    check_permission(&r, None)?;
+    let state = get_state(&r);
+    json_response(
+        StatusCode::OK,
+        crate::pg_versions::usage(state.conf, &state.tenant_manager),
+    )
+}
+
 /// Polled by control plane.
 ///
 /// See [`crate::utilization`].
@@ -2331,9 +3502,9 @@ async fn get_utilization(
     // avoid needless statvfs calls even though those should be non-blocking fast.
     // regenerate at most 1Hz to allow polling at any rate.
     if !still_valid {
-        let path = state.conf.tenants_path();
-        let doc = crate::utilization::regenerate(path.as_std_path())
-            .map_err(ApiError::InternalServerError)?;
+        let data_dirs = crate::tenant::data_dirs::data_dirs(state.conf);
+        let doc =
+            crate::utilization::regenerate(&data_dirs).map_err(ApiError::InternalServerError)?;
 
         let mut buf = Vec::new();
         serde_json::to_writer(&mut buf, &doc)
@@ -2465,6 +3636,33 @@ where
     }
 }
 
+/// Like [`api_handler`], for the handlers of mutating operations on a tenant shard: the
+/// request is rejected if another operation is in progress on the tenant shard, see
+/// [`crate::http::tenant_ops`].
+async fn tenant_op_handler<R, H>(
+    op: TenantOp,
+    request: Request<Body>,
+    handler: H,
+) -> Result<Response<Body>, ApiError>
+where
+    R: std::future::Future<Output = Result<Response<Body>, ApiError>> + Send + 'static,
+    H: FnOnce(Request<Body>, CancellationToken) -> R + Send + Sync + 'static,
+{
+    let tenant_shard_id = if get_request_param(&request, "tenant_shard_id").is_ok() {
+        parse_tenant_shard_id_param(&request)?
+    } else {
+        TenantShardId::unsharded(parse_request_param(&request, "tenant_id")?)
+    };
+    // The guard moves into the handler task, which runs to completion even if the client
+    // goes away, so that the operation stays in progress for as long as it actually is.
+    let guard = get_state(&request).tenant_ops.start(tenant_shard_id, op)?;
+    api_handler(request, move |r, cancel| async move {
+        let _guard = guard;
+        handler(r, cancel).await
+    })
+    .await
+}
+
 pub fn make_router(
     state: Arc<State>,
     launch_ts: &'static LaunchTimestamp,
@@ -2495,19 +3693,25 @@ pub fn make_router(
         .data(state)
         .get("/metrics", |r| request_span(r, prometheus_metrics_handler))
         .get("/v1/status", |r| api_handler(r, status_handler))
+        .get("/v1/page_service/protocol", |r| {
+            api_handler(r, page_service_protocol_handler)
+        })
         .put("/v1/failpoints", |r| {
             testing_api_handler("manage failpoints", r, failpoints_handler)
         })
         .post("/v1/reload_auth_validation_keys", |r| {
             api_handler(r, reload_auth_validation_keys_handler)
         })
+        .post("/v1/reload_config", |r| {
+            api_handler(r, reload_config_handler)
+        })
         .get("/v1/tenant", |r| api_handler(r, tenant_list_handler))
         .post("/v1/tenant", |r| api_handler(r, tenant_create_handler))
         .get("/v1/tenant/:tenant_shard_id", |r| {
             api_handler(r, tenant_status)
         })
         .delete("/v1/tenant/:tenant_shard_id", |r| {
-            api_handler(r, tenant_delete_handler)
+            tenant_op_handler(TenantOp::Delete, r, tenant_delete_handler)
         })
         .get("/v1/tenant/:tenant_shard_id/synthetic_size", |r| {
             api_handler(r, tenant_size_handler)
@@ -2516,13 +3720,65 @@ pub fn make_router(
             api_handler(r, update_tenant_config_handler)
         })
         .put("/v1/tenant/:tenant_shard_id/shard_split", |r| {
-            api_handler(r, tenant_shard_split_handler)
+            tenant_op_handler(TenantOp::ShardSplit, r, tenant_shard_split_handler)
         })
         .get("/v1/tenant/:tenant_shard_id/config", |r| {
             api_handler(r, get_tenant_config_handler)
         })
+        .get("/v1/tenant/:tenant_shard_id/resource_usage", |r| {
+            api_handler(r, tenant_resource_usage_handler)
+        })
+        .get("/v1/tenant/:tenant_shard_id/activation_profile", |r| {
+            api_handler(r, tenant_activation_profile_handler)
+        })
+        .get("/v1/tenant/:tenant_shard_id/state_transitions", |r| {
+            api_handler(r, tenant_state_transitions_handler)
+        })
+        .get("/v1/tenant/:tenant_shard_id/legal_hold", |r| {
+            api_handler(r, tenant_legal_hold_handler)
+        })
+        .put("/v1/tenant/:tenant_shard_id/legal_hold", |r| {
+            api_handler(r, tenant_legal_hold_put_handler)
+        })
+        .delete("/v1/tenant/:tenant_shard_id/legal_hold", |r| {
+            api_handler(r, tenant_legal_hold_delete_handler)
+        })
+        .put("/v1/tenant/:tenant_shard_id/read_only", |r| {
+            tenant_op_handler(TenantOp::UpdateConfig, r, tenant_read_only_put_handler)
+        })
+        .delete("/v1/tenant/:tenant_shard_id/read_only", |r| {
+            tenant_op_handler(TenantOp::UpdateConfig, r, tenant_read_only_delete_handler)
+        })
+        .post("/v1/tenant/:tenant_shard_id/selftest", |r| {
+            api_handler(r, tenant_selftest_handler)
+        })
+        .get("/v1/tenant/:tenant_shard_id/stale_timelines", |r| {
+            api_handler(r, tenant_stale_timelines_handler)
+        })
+        .delete("/v1/tenant/:tenant_shard_id/stale_timelines", |r| {
+            api_handler(r, tenant_stale_timelines_delete_handler)
+        })
+        .post("/v1/tenant/:tenant_shard_id/export", |r| {
+            tenant_op_handler(TenantOp::Export, r, tenant_export_handler)
+        })
+        .post("/v1/tenant/:tenant_id/import", |r| {
+            tenant_op_handler(TenantOp::Import, r, tenant_import_handler)
+        })
+        .get("/v1/tenant_config_template", |r| {
+            api_handler(r, tenant_config_templates_handler)
+        })
+        .get("/v1/tenant_config_template/:name", |r| {
+            api_handler(r, tenant_config_template_handler)
+        })
+        .put("/v1/tenant_config_template/:name", |r| {
+            api_handler(r, tenant_config_template_put_handler)
+        })
         .put("/v1/tenant/:tenant_shard_id/location_config", |r| {
-            api_handler(r, put_tenant_location_config_handler)
+            tenant_op_handler(
+                TenantOp::LocationConfig,
+                r,
+                put_tenant_location_config_handler,
+            )
         })
         .get("/v1/location_config", |r| {
             api_handler(r, list_location_config_handler)
@@ -2532,7 +3788,13 @@ pub fn make_router(
         })
         .put(
             "/v1/tenant/:tenant_shard_id/time_travel_remote_storage",
-            |r| api_handler(r, tenant_time_travel_remote_storage_handler),
+            |r| {
+                tenant_op_handler(
+                    TenantOp::TimeTravelRemoteStorage,
+                    r,
+                    tenant_time_travel_remote_storage_handler,
+                )
+            },
         )
         .get("/v1/tenant/:tenant_shard_id/timeline", |r| {
             api_handler(r, timeline_list_handler)
@@ -2541,19 +3803,19 @@ pub fn make_router(
             api_handler(r, timeline_create_handler)
         })
         .post("/v1/tenant/:tenant_id/attach", |r| {
-            api_handler(r, tenant_attach_handler)
+            tenant_op_handler(TenantOp::Attach, r, tenant_attach_handler)
         })
         .post("/v1/tenant/:tenant_id/detach", |r| {
-            api_handler(r, tenant_detach_handler)
+            tenant_op_handler(TenantOp::Detach, r, tenant_detach_handler)
         })
         .post("/v1/tenant/:tenant_shard_id/reset", |r| {
-            api_handler(r, tenant_reset_handler)
+            tenant_op_handler(TenantOp::Reset, r, tenant_reset_handler)
         })
         .post("/v1/tenant/:tenant_id/load", |r| {
-            api_handler(r, tenant_load_handler)
+            tenant_op_handler(TenantOp::Load, r, tenant_load_handler)
         })
         .post("/v1/tenant/:tenant_id/ignore", |r| {
-            api_handler(r, tenant_ignore_handler)
+            tenant_op_handler(TenantOp::Ignore, r, tenant_ignore_handler)
         })
         .post(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/preserve_initdb_archive",
@@ -2570,6 +3832,22 @@ pub fn make_router(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/get_timestamp_of_lsn",
             |r| api_handler(r, get_timestamp_of_lsn_handler),
         )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/lsn_by_timestamp",
+            |r| api_handler(r, lsn_by_timestamp_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/timestamp_by_lsn",
+            |r| api_handler(r, timestamp_by_lsn_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/explain_read",
+            |r| api_handler(r, timeline_explain_read_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/relation_sizes",
+            |r| api_handler(r, timeline_relation_sizes_handler),
+        )
         .put(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/do_gc",
             |r| api_handler(r, timeline_gc_handler),
@@ -2582,6 +3860,19 @@ pub fn make_router(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/checkpoint",
             |r| testing_api_handler("run timeline checkpoint", r, timeline_checkpoint_handler),
         )
+        .post(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/wait_lsn",
+            |r| api_handler(r, timeline_wait_lsn_handler),
+        )
+        .post(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/download_layers",
+            |r| api_handler(r, timeline_download_remote_layers_handler_post),
+        )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/download_layers",
+            |r| api_handler(r, timeline_download_remote_layers_handler_get),
+        )
+        // Old name of download_layers, kept for compatibility.
         .post(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/download_remote_layers",
             |r| api_handler(r, timeline_download_remote_layers_handler_post),
@@ -2601,6 +3892,14 @@ pub fn make_router(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/layer",
             |r| api_handler(r, layer_map_info_handler),
         )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/upload_queue",
+            |r| api_handler(r, timeline_upload_queue_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/layer_manifest",
+            |r| api_handler(r, layer_manifest_handler),
+        )
         .get(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/layer/:layer_file_name",
             |r| api_handler(r, layer_download_handler),
@@ -2618,15 +3917,32 @@ pub fn make_router(
         .put("/v1/disk_usage_eviction/run", |r| {
             api_handler(r, disk_usage_eviction_run)
         })
+        .get("/v1/disk_usage_eviction/forecast", |r| {
+            api_handler(r, disk_usage_forecast_handler)
+        })
+        .get("/v1/disk_usage_eviction/offloaded", |r| {
+            api_handler(r, disk_usage_offloaded_handler)
+        })
         .put("/v1/deletion_queue/flush", |r| {
             api_handler(r, deletion_queue_flush)
         })
         .get("/v1/tenant/:tenant_shard_id/secondary/status", |r| {
             api_handler(r, secondary_status_handler)
         })
+        .get("/v1/tenant/:tenant_shard_id/secondary/remote_index", |r| {
+            api_handler(r, secondary_remote_index_handler)
+        })
         .post("/v1/tenant/:tenant_shard_id/secondary/download", |r| {
             api_handler(r, secondary_download_handler)
         })
+        .post(
+            "/v1/tenant/:tenant_shard_id/rebalance/prepare_give_up",
+            |r| api_handler(r, rebalance_prepare_give_up_handler),
+        )
+        .post(
+            "/v1/tenant/:tenant_shard_id/rebalance/prepare_receive",
+            |r| api_handler(r, rebalance_prepare_receive_handler),
+        )
         .put("/v1/tenant/:tenant_shard_id/break", |r| {
             testing_api_handler("set tenant state to broken", r, handle_tenant_break)
         })
@@ -2638,11 +3954,36 @@ pub fn make_router(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/getpage",
             |r| testing_api_handler("getpage@lsn", r, getpage_at_lsn_handler),
         )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/rel_page",
+            |r| api_handler(r, timeline_rel_page_handler),
+        )
         .get(
             "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/keyspace",
             |r| api_handler(r, timeline_collect_keyspace),
         )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/layer_overlap",
+            |r| api_handler(r, timeline_layer_overlap_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/key_space_stats",
+            |r| api_handler(r, timeline_key_space_stats_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/access_log",
+            |r| api_handler(r, timeline_access_log_handler),
+        )
+        .get(
+            "/v1/tenant/:tenant_shard_id/timeline/:timeline_id/invalidations",
+            |r| api_handler(r, timeline_invalidations_handler),
+        )
         .put("/v1/io_engine", |r| api_handler(r, put_io_engine_handler))
         .get("/v1/utilization", |r| api_handler(r, get_utilization))
+        .get("/v1/pg_versions", |r| api_handler(r, pg_versions_handler))
+        .get("/v1/load_scores", |r| api_handler(r, load_scores_handler))
+        .get("/v1/startup_repairs", |r| {
+            api_handler(r, get_startup_repairs)
+        })
         .any(handler_404))
 }
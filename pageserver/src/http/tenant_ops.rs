@@ -0,0 +1,228 @@
+//! Serialization of the management API operations on a tenant shard.
+//!
+//! The management API lets callers attach, detach, delete, reconfigure or split a tenant
+//! shard concurrently, and the tenant manager only guards its slots while it transitions
+//! them: two conflicting requests, like a delete racing with an attach, could both pass their
+//! checks before either got to change the slot. [`TenantOps`] allows a single mutating
+//! operation per tenant shard at a time, and the HTTP layer rejects the others with a typed
+//! [`TenantOpConflict`]. The routes that only take a tenant id operate on the unsharded
+//! tenant shard id, which conflicts with the operations on any shard of the tenant.
+//!
+//! Conflicts are 503s, which callers like the storage controller retry: the operation in
+//! progress will be done, or will have failed, later.
+//!
+//! The tenant config also has a version, its [`config_etag`], which `GET
+//! /v1/tenant/:tenant_shard_id/config` returns in the `ETag` header. `PUT /v1/tenant/config`
+//! requests may send it back in an `If-Match` header, to only apply their update if nobody
+//! else changed the config since they read it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use pageserver_api::shard::TenantShardId;
+use utils::http::error::ApiError;
+use utils::id::TenantId;
+
+use crate::tenant::config::TenantConfOpt;
+
+/// A mutating management API operation on a tenant shard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum TenantOp {
+    Attach,
+    Detach,
+    Delete,
+    Load,
+    Ignore,
+    Reset,
+    LocationConfig,
+    UpdateConfig,
+    ShardSplit,
+    TimeTravelRemoteStorage,
+    Export,
+    Import,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub(crate) enum TenantOpConflict {
+    #[error("{op} of tenant {tenant_shard_id} is already in progress")]
+    AlreadyInProgress {
+        tenant_shard_id: TenantShardId,
+        op: &'static str,
+    },
+    #[error("cannot {requested} tenant {tenant_shard_id}: {in_progress} of {in_progress_on} is in progress")]
+    Conflict {
+        tenant_shard_id: TenantShardId,
+        requested: &'static str,
+        in_progress_on: TenantShardId,
+        in_progress: &'static str,
+    },
+}
+
+impl From<TenantOpConflict> for ApiError {
+    fn from(value: TenantOpConflict) -> Self {
+        ApiError::ResourceUnavailable(value.to_string().into())
+    }
+}
+
+type InProgress = Arc<Mutex<HashMap<TenantId, Vec<(TenantShardId, TenantOp)>>>>;
+
+/// The mutating operations in progress, by tenant.
+#[derive(Default)]
+pub(crate) struct TenantOps {
+    in_progress: InProgress,
+}
+
+impl TenantOps {
+    /// Start `op` on `tenant_shard_id`, if no other operation is in progress on it, or on the
+    /// whole tenant. The operation is in progress until the returned guard is dropped.
+    pub(crate) fn start(
+        &self,
+        tenant_shard_id: TenantShardId,
+        op: TenantOp,
+    ) -> Result<TenantOpGuard, TenantOpConflict> {
+        let mut in_progress = self.in_progress.lock().unwrap();
+        let ops = in_progress.entry(tenant_shard_id.tenant_id).or_default();
+        let conflicting = ops.iter().find(|(shard, _)| {
+            *shard == tenant_shard_id || shard.is_unsharded() || tenant_shard_id.is_unsharded()
+        });
+        match conflicting {
+            Some((shard, current)) if *shard == tenant_shard_id && *current == op => {
+                Err(TenantOpConflict::AlreadyInProgress {
+                    tenant_shard_id,
+                    op: op.into(),
+                })
+            }
+            Some((shard, current)) => Err(TenantOpConflict::Conflict {
+                tenant_shard_id,
+                requested: op.into(),
+                in_progress_on: *shard,
+                in_progress: (*current).into(),
+            }),
+            None => {
+                ops.push((tenant_shard_id, op));
+                Ok(TenantOpGuard {
+                    in_progress: self.in_progress.clone(),
+                    tenant_shard_id,
+                })
+            }
+        }
+    }
+}
+
+pub(crate) struct TenantOpGuard {
+    in_progress: InProgress,
+    tenant_shard_id: TenantShardId,
+}
+
+impl Drop for TenantOpGuard {
+    fn drop(&mut self) {
+        let mut in_progress = self.in_progress.lock().unwrap();
+        let tenant_id = self.tenant_shard_id.tenant_id;
+        if let Some(ops) = in_progress.get_mut(&tenant_id) {
+            ops.retain(|(shard, _)| *shard != self.tenant_shard_id);
+            if ops.is_empty() {
+                in_progress.remove(&tenant_id);
+            }
+        }
+    }
+}
+
+/// The version of a tenant config, as an HTTP entity tag.
+pub(crate) fn config_etag(config: &TenantConfOpt) -> Result<String, ApiError> {
+    let bytes = serde_json::to_vec(config)
+        .map_err(|e| ApiError::InternalServerError(anyhow::anyhow!("serialize config: {e}")))?;
+    Ok(format!("\"{:08x}\"", crc32c::crc32c(&bytes)))
+}
+
+/// Check an `If-Match` precondition against the entity tag of the current config.
+pub(crate) fn check_if_match(if_match: Option<&str>, current_etag: &str) -> Result<(), ApiError> {
+    let Some(if_match) = if_match else {
+        return Ok(());
+    };
+    let matches = if_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == current_etag);
+    if matches {
+        Ok(())
+    } else {
+        Err(ApiError::PreconditionFailed(
+            format!("tenant config version is {current_etag}, not {if_match}").into_boxed_str(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pageserver_api::shard::{ShardCount, ShardNumber};
+
+    #[test]
+    fn conflicting_operations() {
+        let ops = TenantOps::default();
+        let shard_a = TenantShardId::unsharded(utils::id::TenantId::generate());
+        let shard_b = TenantShardId::unsharded(utils::id::TenantId::generate());
+
+        let guard = ops.start(shard_a, TenantOp::Attach).unwrap();
+        assert!(matches!(
+            ops.start(shard_a, TenantOp::Attach),
+            Err(TenantOpConflict::AlreadyInProgress { .. })
+        ));
+        assert_eq!(
+            ops.start(shard_a, TenantOp::Delete).err(),
+            Some(TenantOpConflict::Conflict {
+                tenant_shard_id: shard_a,
+                requested: "delete",
+                in_progress_on: shard_a,
+                in_progress: "attach",
+            })
+        );
+        // Other tenants are independent.
+        let _other = ops.start(shard_b, TenantOp::Delete).unwrap();
+
+        drop(guard);
+        ops.start(shard_a, TenantOp::Delete).unwrap();
+    }
+
+    #[test]
+    fn tenant_wide_operations() {
+        let ops = TenantOps::default();
+        let tenant_id = utils::id::TenantId::generate();
+        let unsharded = TenantShardId::unsharded(tenant_id);
+        let shards = [0, 1].map(|number| TenantShardId {
+            tenant_id,
+            shard_number: ShardNumber(number),
+            shard_count: ShardCount::new(2),
+        });
+
+        // Shards of a tenant are independent of each other...
+        let guard = ops.start(shards[0], TenantOp::LocationConfig).unwrap();
+        let other = ops.start(shards[1], TenantOp::LocationConfig).unwrap();
+        // ...but not of the operations on the whole tenant.
+        assert!(matches!(
+            ops.start(unsharded, TenantOp::Delete),
+            Err(TenantOpConflict::Conflict { .. })
+        ));
+        drop(guard);
+        drop(other);
+
+        let _guard = ops.start(unsharded, TenantOp::UpdateConfig).unwrap();
+        assert!(matches!(
+            ops.start(shards[1], TenantOp::UpdateConfig),
+            Err(TenantOpConflict::Conflict { .. })
+        ));
+    }
+
+    #[test]
+    fn if_match() {
+        let etag = config_etag(&TenantConfOpt::default()).unwrap();
+        check_if_match(None, &etag).unwrap();
+        check_if_match(Some("*"), &etag).unwrap();
+        check_if_match(Some(&format!("\"other\", {etag}")), &etag).unwrap();
+        assert!(matches!(
+            check_if_match(Some("\"other\""), &etag),
+            Err(ApiError::PreconditionFailed(_))
+        ));
+    }
+}
@@ -2,7 +2,17 @@
 //! Import data and WAL from a PostgreSQL data directory and WAL segments into
 //! a neon Timeline.
 //!
-use std::path::{Path, PathBuf};
+//! Besides the tarball of the initdb-produced datadir of new timelines, which is streamed into
+//! the import without being extracted, a timeline can be imported from a
+//! tarball made by vanilla `pg_basebackup`, to migrate an existing Postgres installation
+//! without a logical dump and restore. The backup starts at the `START WAL LOCATION` of its
+//! `backup_label`; the WAL from there comes either from the `pg_wal` tarball of the backup,
+//! or from a WAL archive, fetched one segment at a time with a [`WalArchive`] command like
+//! the `restore_command` of Postgres, which is part of the pageserver config.
+//!
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
 
 use anyhow::{bail, ensure, Context, Result};
 use bytes::Bytes;
@@ -12,7 +22,6 @@ use pageserver_api::key::rel_block_to_key;
 use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio_tar::Archive;
 use tracing::*;
-use walkdir::WalkDir;
 
 use crate::context::RequestContext;
 use crate::metrics::WAL_INGEST;
@@ -21,8 +30,10 @@ use crate::tenant::Timeline;
 use crate::walingest::WalIngest;
 use crate::walrecord::DecodedWALRecord;
 use pageserver_api::reltag::{RelTag, SlruKind};
+use postgres_ffi::dispatch_pgversion;
 use postgres_ffi::pg_constants;
 use postgres_ffi::relfile_utils::*;
+use postgres_ffi::v14::xlog_utils::{IsXLogFileName, XLogFromFileName};
 use postgres_ffi::waldecoder::WalStreamDecoder;
 use postgres_ffi::ControlFileData;
 use postgres_ffi::DBState_DB_SHUTDOWNED;
@@ -31,10 +42,15 @@ use postgres_ffi::XLogFileName;
 use postgres_ffi::{BLCKSZ, WAL_SEGMENT_SIZE};
 use utils::lsn::Lsn;
 
+/// The path of the control file in a data directory. The initdb archives of the pageserver
+/// start with it, so that [`get_lsn_from_controlfile_in_tar`] only decompresses their first
+/// entry.
+pub const CONTROLFILE_PATH: &str = "global/pg_control";
+
 // Returns checkpoint LSN from controlfile
 pub fn get_lsn_from_controlfile(path: &Utf8Path) -> Result<Lsn> {
     // Read control file to extract the LSN
-    let controlfile_path = path.join("global").join("pg_control");
+    let controlfile_path = path.join(CONTROLFILE_PATH);
     let controlfile_buf = std::fs::read(&controlfile_path)
         .with_context(|| format!("reading controlfile: {controlfile_path}"))?;
     let controlfile = ControlFileData::decode(&controlfile_buf)?;
@@ -43,44 +59,86 @@ pub fn get_lsn_from_controlfile(path: &Utf8Path) -> Result<Lsn> {
     Ok(Lsn(lsn))
 }
 
+/// Returns the checkpoint LSN from the controlfile in a tarball of a data directory, reading
+/// the tarball up to the controlfile.
+pub async fn get_lsn_from_controlfile_in_tar(
+    reader: &mut (impl AsyncRead + Send + Unpin),
+) -> Result<Lsn> {
+    let mut entries = Archive::new(reader).entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        if entry.header().entry_type() == tokio_tar::EntryType::Regular
+            && entry.path()? == Path::new(CONTROLFILE_PATH)
+        {
+            let controlfile = ControlFileData::decode(&read_all_bytes(&mut entry).await?)?;
+            return Ok(Lsn(controlfile.checkPoint));
+        }
+    }
+    bail!("pg_control file not found")
+}
+
 ///
-/// Import all relation data pages from local disk into the repository.
+/// Import all relation data pages and the WAL from a tarball of a data directory into the
+/// repository, as it is read.
 ///
 /// This is currently only used to import a cluster freshly created by initdb.
 /// The code that deals with the checkpoint would not work right if the
 /// cluster was not shut down cleanly.
-pub async fn import_timeline_from_postgres_datadir(
+///
+/// The WAL can only be imported after the data files, wherever the tarball has it: the WAL
+/// segments are kept in memory until then. initdb writes a single one.
+pub async fn import_timeline_from_initdb_tar(
     tline: &Timeline,
-    pgdata_path: &Utf8Path,
+    reader: &mut (impl AsyncRead + Send + Sync + Unpin),
     pgdata_lsn: Lsn,
     ctx: &RequestContext,
 ) -> Result<()> {
     let mut pg_control: Option<ControlFileData> = None;
+    let mut wal_segments = BTreeMap::new();
 
     // TODO this shoud be start_lsn, which is not necessarily equal to end_lsn (aka lsn)
     // Then fishing out pg_control would be unnecessary
     let mut modification = tline.begin_modification(pgdata_lsn);
     modification.init_empty()?;
 
-    // Import all but pg_wal
-    let all_but_wal = WalkDir::new(pgdata_path)
-        .into_iter()
-        .filter_entry(|entry| !entry.path().ends_with("pg_wal"));
-    for entry in all_but_wal {
-        let entry = entry?;
-        let metadata = entry.metadata().expect("error getting dir entry metadata");
-        if metadata.is_file() {
-            let absolute_path = entry.path();
-            let relative_path = absolute_path.strip_prefix(pgdata_path)?;
-
-            let mut file = tokio::fs::File::open(absolute_path).await?;
-            let len = metadata.len() as usize;
-            if let Some(control_file) =
-                import_file(&mut modification, relative_path, &mut file, len, ctx).await?
-            {
-                pg_control = Some(control_file);
+    let mut entries = Archive::new(reader).entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let header = entry.header();
+        let len = header.entry_size()? as usize;
+        let file_path = header.path()?.into_owned();
+
+        match header.entry_type() {
+            tokio_tar::EntryType::Regular if file_path.starts_with("pg_wal") => {
+                let file_name = file_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if IsXLogFileName(&file_name) {
+                    let segno = XLogFromFileName(&file_name, WAL_SEGMENT_SIZE).0;
+                    wal_segments.insert(segno, read_all_bytes(&mut entry).await?);
+                } else {
+                    debug!("skipping {:?}", file_path);
+                }
+            }
+            tokio_tar::EntryType::Regular => {
+                if let Some(control_file) =
+                    import_file(&mut modification, &file_path, &mut entry, len, ctx).await?
+                {
+                    pg_control = Some(control_file);
+                }
+                modification.flush(ctx).await?;
+            }
+            tokio_tar::EntryType::Directory => {
+                debug!("directory {:?}", file_path);
+            }
+            _ => {
+                bail!(
+                    "entry {} in initdb tar archive is of unexpected type: {:?}",
+                    file_path.display(),
+                    header.entry_type()
+                );
             }
-            modification.flush(ctx).await?;
         }
     }
 
@@ -102,7 +160,7 @@ pub async fn import_timeline_from_postgres_datadir(
     // this reads the checkpoint record itself, advancing the tip of the timeline to
     // *after* the checkpoint record. And crucially, it initializes the 'prev_lsn'.
     import_wal(
-        &pgdata_path.join("pg_wal"),
+        wal_segments,
         tline,
         Lsn(pg_control.checkPointCopy.redo),
         pgdata_lsn,
@@ -261,10 +319,10 @@ async fn import_slru(
     Ok(())
 }
 
-/// Scan PostgreSQL WAL files in given directory and load all records between
-/// 'startpoint' and 'endpoint' into the repository.
+/// Load all records between 'startpoint' and 'endpoint' of the given PostgreSQL WAL segments,
+/// by segment number, into the repository.
 async fn import_wal(
-    walpath: &Utf8Path,
+    mut segments: BTreeMap<u64, Bytes>,
     tline: &Timeline,
     startpoint: Lsn,
     endpoint: Lsn,
@@ -279,34 +337,22 @@ async fn import_wal(
     let mut walingest = WalIngest::new(tline, startpoint, ctx).await?;
 
     while last_lsn <= endpoint {
-        // FIXME: assume postgresql tli 1 for now
-        let filename = XLogFileName(1, segno, WAL_SEGMENT_SIZE);
-        let mut buf = Vec::new();
-
-        // Read local file
-        let mut path = walpath.join(&filename);
-
-        // It could be as .partial
-        if !PathBuf::from(&path).exists() {
-            path = walpath.join(filename + ".partial");
-        }
-
-        // Slurp the WAL file
-        let mut file = std::fs::File::open(&path)?;
-
-        if offset > 0 {
-            use std::io::Seek;
-            file.seek(std::io::SeekFrom::Start(offset as u64))?;
-        }
-
-        use std::io::Read;
-        let nread = file.read_to_end(&mut buf)?;
-        if nread != WAL_SEGMENT_SIZE - offset {
-            // Maybe allow this for .partial files?
-            error!("read only {} bytes from WAL file", nread);
+        let segment = segments.remove(&segno).with_context(|| {
+            // FIXME: assume postgresql tli 1 for now
+            format!(
+                "WAL segment {} not found",
+                XLogFileName(1, segno, WAL_SEGMENT_SIZE)
+            )
+        })?;
+        if segment.len() != WAL_SEGMENT_SIZE {
+            error!("read only {} bytes from WAL file", segment.len());
         }
+        ensure!(
+            segment.len() >= offset,
+            "WAL segment {segno} ends before {startpoint}"
+        );
 
-        waldecoder.feed_bytes(&buf);
+        waldecoder.feed_bytes(&segment[offset..]);
 
         let mut nrecords = 0;
         let mut modification = tline.begin_modification(last_lsn);
@@ -363,6 +409,21 @@ pub async fn import_basebackup_from_tar(
         let file_path = header.path()?.into_owned();
 
         match header.entry_type() {
+            tokio_tar::EntryType::Regular if file_path == Path::new("backup_label") => {
+                // Made by pg_basebackup: the backup only becomes consistent once the WAL since
+                // its start is replayed on top of it.
+                let label =
+                    BackupLabel::parse(std::str::from_utf8(&read_all_bytes(&mut entry).await?)?)?;
+                ensure!(
+                    label.start_lsn == base_lsn,
+                    "backup starts at {}, not at {base_lsn}",
+                    label.start_lsn
+                );
+                info!(
+                    "importing backup started at {} on timeline {}",
+                    label.start_lsn, label.start_tli
+                );
+            }
             tokio_tar::EntryType::Regular => {
                 if let Some(res) =
                     import_file(&mut modification, file_path.as_ref(), &mut entry, len, ctx).await?
@@ -421,13 +482,17 @@ pub async fn import_wal_from_tar(
 
             match header.entry_type() {
                 tokio_tar::EntryType::Regular => {
-                    // FIXME: assume postgresql tli 1 for now
-                    let expected_filename = XLogFileName(1, segno, WAL_SEGMENT_SIZE);
                     let file_name = file_path
                         .file_name()
                         .expect("missing wal filename")
                         .to_string_lossy();
-                    ensure!(expected_filename == file_name);
+                    // The backup may have been taken on any Postgres timeline.
+                    ensure!(
+                        IsXLogFileName(&file_name)
+                            && XLogFromFileName(&file_name, WAL_SEGMENT_SIZE).0 == segno,
+                        "expected WAL segment {}, found {file_name}",
+                        XLogFileName(1, segno, WAL_SEGMENT_SIZE)
+                    );
 
                     debug!("processing wal file {:?}", file_path);
                     read_all_bytes(&mut entry).await?
@@ -484,6 +549,160 @@ pub async fn import_wal_from_tar(
     Ok(())
 }
 
+/// A WAL archive, read like Postgres does in archive recovery: `restore_command` is run by
+/// the shell for each segment, with `%f` replaced by the segment file name and `%p` by the
+/// path to copy it to. A non-zero exit status means that the archive has no such segment.
+///
+/// The command comes from [`crate::config::PageServerConf::wal_archive_restore_command`],
+/// never from a client, as it runs on the pageserver host.
+pub struct WalArchive {
+    restore_command: String,
+}
+
+impl WalArchive {
+    pub fn new(restore_command: String) -> Self {
+        WalArchive { restore_command }
+    }
+
+    async fn fetch(&self, file_name: &str) -> Result<Option<Bytes>> {
+        let scratch_dir = camino_tempfile::tempdir()?;
+        let path = scratch_dir.path().join(file_name);
+        let command = expand_restore_command(&self.restore_command, file_name, path.as_str())?;
+        let status = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .status()
+            .await
+            .with_context(|| format!("run restore_command {command:?}"))?;
+        if !status.success() {
+            info!("restore_command {command:?} failed with {status}");
+            return Ok(None);
+        }
+        let segment = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("read {file_name} restored by {command:?}"))?;
+        Ok(Some(Bytes::from(segment)))
+    }
+}
+
+fn expand_restore_command(template: &str, file_name: &str, path: &str) -> Result<String> {
+    let mut command = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            command.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('f') => command.push_str(file_name),
+            Some('p') => command.push_str(path),
+            Some('%') => command.push('%'),
+            other => bail!(
+                "unsupported placeholder %{} in restore_command",
+                other.unwrap_or(' ')
+            ),
+        }
+    }
+    Ok(command)
+}
+
+/// Import the WAL from `start_lsn` to `end_lsn` from `archive`, on the Postgres timeline of
+/// the checkpoint at `start_lsn`.
+pub async fn import_wal_from_archive(
+    tline: &Timeline,
+    archive: &WalArchive,
+    start_lsn: Lsn,
+    end_lsn: Lsn,
+    ctx: &RequestContext,
+) -> Result<()> {
+    let checkpoint = tline.get_checkpoint(start_lsn, ctx).await?;
+    let pg_tli = dispatch_pgversion!(
+        tline.pg_version,
+        pgv::CheckPoint::decode(&checkpoint)?.ThisTimeLineID,
+        bail!("unknown Postgres version {}", tline.pg_version)
+    );
+
+    let mut waldecoder = WalStreamDecoder::new(start_lsn, tline.pg_version);
+    let mut segno = start_lsn.segment_number(WAL_SEGMENT_SIZE);
+    let mut offset = start_lsn.segment_offset(WAL_SEGMENT_SIZE);
+    let mut last_lsn = start_lsn;
+    let mut walingest = WalIngest::new(tline, start_lsn, ctx).await?;
+
+    info!("importing wal from archive until {end_lsn}, on Postgres timeline {pg_tli}");
+    while last_lsn < end_lsn {
+        let file_name = XLogFileName(pg_tli, segno, WAL_SEGMENT_SIZE);
+        let segment = archive.fetch(&file_name).await?.with_context(|| {
+            format!(
+                "WAL segment {file_name} is missing from the archive, imported up to {last_lsn}"
+            )
+        })?;
+        ensure!(
+            segment.len() == WAL_SEGMENT_SIZE,
+            "WAL segment {file_name} is {} bytes long, expected {WAL_SEGMENT_SIZE}",
+            segment.len()
+        );
+        waldecoder.feed_bytes(&segment[offset..]);
+
+        let mut modification = tline.begin_modification(last_lsn);
+        let mut decoded = DecodedWALRecord::default();
+        while last_lsn < end_lsn {
+            let Some((lsn, recdata)) = waldecoder.poll_decode()? else {
+                break;
+            };
+            walingest
+                .ingest_record(recdata, lsn, &mut modification, &mut decoded, ctx)
+                .await?;
+            WAL_INGEST.records_committed.inc();
+            modification.commit(ctx).await?;
+            last_lsn = lsn;
+        }
+
+        debug!("imported records of {file_name} up to {last_lsn}");
+        segno += 1;
+        offset = 0;
+    }
+
+    info!("reached end of WAL at {}", last_lsn);
+    Ok(())
+}
+
+/// The contents of the `backup_label` of a backup made by `pg_basebackup` that matter for the
+/// import.
+#[derive(Debug, PartialEq, Eq)]
+struct BackupLabel {
+    /// Where WAL replay starts: the REDO pointer of the backup checkpoint.
+    start_lsn: Lsn,
+    start_tli: u32,
+}
+
+impl BackupLabel {
+    fn parse(label: &str) -> Result<Self> {
+        let mut start_lsn = None;
+        let mut start_tli = None;
+        for line in label.lines() {
+            if let Some(rest) = line.strip_prefix("START WAL LOCATION: ") {
+                // e.g. "START WAL LOCATION: 0/2000028 (file 000000010000000000000002)"
+                let lsn = rest.split_whitespace().next().unwrap_or_default();
+                start_lsn = Some(
+                    Lsn::from_str(lsn)
+                        .with_context(|| format!("parse backup_label line {line:?}"))?,
+                );
+            } else if let Some(rest) = line.strip_prefix("START TIMELINE: ") {
+                start_tli = Some(
+                    rest.trim()
+                        .parse()
+                        .with_context(|| format!("parse backup_label line {line:?}"))?,
+                );
+            }
+        }
+        Ok(BackupLabel {
+            start_lsn: start_lsn.context("no START WAL LOCATION in backup_label")?,
+            // Older versions don't write the timeline: it's the one of the start segment.
+            start_tli: start_tli.unwrap_or(1),
+        })
+    }
+}
+
 async fn import_file(
     modification: &mut DatadirModification<'_>,
     file_path: &Path,
@@ -629,3 +848,57 @@ async fn read_all_bytes(reader: &mut (impl AsyncRead + Unpin)) -> Result<Bytes>
     reader.read_to_end(&mut buf).await?;
     Ok(Bytes::from(buf))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_backup_label() {
+        let label = "START WAL LOCATION: 0/2000028 (file 000000020000000000000002)
+CHECKPOINT LOCATION: 0/2000060
+BACKUP METHOD: streamed
+BACKUP FROM: primary
+START TIME: 2024-05-02 10:00:00 UTC
+LABEL: pg_basebackup base backup
+START TIMELINE: 2
+";
+        assert_eq!(
+            BackupLabel::parse(label).unwrap(),
+            BackupLabel {
+                start_lsn: Lsn(0x2000028),
+                start_tli: 2,
+            }
+        );
+        assert!(BackupLabel::parse("LABEL: nothing\n").is_err());
+    }
+
+    #[test]
+    fn restore_command_placeholders() {
+        assert_eq!(
+            expand_restore_command(
+                "cp /archive/%f \"%p\" # 100%%",
+                "000000010000000000000002",
+                "/tmp/x/000000010000000000000002"
+            )
+            .unwrap(),
+            "cp /archive/000000010000000000000002 \"/tmp/x/000000010000000000000002\" # 100%"
+        );
+        assert!(expand_restore_command("cp %r %p", "f", "p").is_err());
+    }
+
+    #[tokio::test]
+    async fn lsn_from_initdb_tar() {
+        use async_compression::tokio::bufread::ZstdDecoder;
+
+        let file = tokio::fs::File::open("test_data/sk_wal_segment_from_pgbench/initdb.tar.zst")
+            .await
+            .unwrap();
+        let mut reader = ZstdDecoder::new(tokio::io::BufReader::new(file));
+        let lsn = get_lsn_from_controlfile_in_tar(&mut reader).await.unwrap();
+        assert!(lsn.is_valid());
+
+        let mut empty = tokio::io::empty();
+        assert!(get_lsn_from_controlfile_in_tar(&mut empty).await.is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Background verification of resident layers against their copy in remote storage.
+//!
+//! A layer that got corrupted on its way to remote storage, or that rots on the local disk,
+//! is only noticed when it's read: possibly long after the fact, and possibly after the intact
+//! copy is gone. With `layer_verification_period` set, the task started by
+//! [`launch_layer_verification_task`] picks a random resident layer of a random active tenant
+//! every period, downloads its copy from remote storage, and compares the sizes and CRC32C
+//! checksums of both. A divergence is logged as an error and counted in
+//! `pageserver_layer_verifications_total{result="mismatch"}`, and is left for an operator to
+//! investigate: the task can't tell which copy is the corrupt one.
+//!
+//! Layers whose upload hasn't completed yet are skipped. One layer per period keeps the extra
+//! remote storage traffic low.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use camino::Utf8Path;
+use futures::StreamExt;
+use pageserver_api::models::TenantState;
+use rand::seq::SliceRandom;
+use remote_storage::DownloadError;
+use tokio::io::AsyncReadExt;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn, Instrument};
+use utils::completion;
+
+use crate::config::PageServerConf;
+use crate::metrics::LAYER_VERIFICATIONS;
+use crate::task_mgr::{self, TaskKind, BACKGROUND_RUNTIME};
+use crate::tenant::mgr::TenantManager;
+use crate::tenant::storage_layer::AsLayerDesc as _;
+
+/// Size and CRC32C of the contents of a layer file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Checksum {
+    size: u64,
+    crc32c: u32,
+}
+
+impl Checksum {
+    fn new() -> Self {
+        Checksum { size: 0, crc32c: 0 }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.size += bytes.len() as u64;
+        self.crc32c = crc32c::crc32c_append(self.crc32c, bytes);
+    }
+}
+
+async fn local_checksum(path: &Utf8Path) -> anyhow::Result<Checksum> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("open {path}"))?;
+    let mut checksum = Checksum::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .await
+            .with_context(|| format!("read {path}"))?;
+        if n == 0 {
+            return Ok(checksum);
+        }
+        checksum.update(&buf[..n]);
+    }
+}
+
+async fn stream_checksum(
+    mut stream: impl futures::Stream<Item = std::io::Result<bytes::Bytes>> + Unpin,
+) -> std::io::Result<Checksum> {
+    let mut checksum = Checksum::new();
+    while let Some(chunk) = stream.next().await {
+        checksum.update(&chunk?);
+    }
+    Ok(checksum)
+}
+
+pub fn launch_layer_verification_task(
+    conf: &'static PageServerConf,
+    tenant_manager: Arc<TenantManager>,
+    background_jobs_barrier: completion::Barrier,
+) {
+    let period = conf.layer_verification_period;
+    if period == Duration::ZERO {
+        info!("layer verification not configured");
+        return;
+    }
+
+    task_mgr::spawn(
+        BACKGROUND_RUNTIME.handle(),
+        TaskKind::LayerVerification,
+        None,
+        None,
+        "layer verification",
+        false,
+        async move {
+            let cancel = task_mgr::shutdown_token();
+
+            tokio::select! {
+                _ = cancel.cancelled() => { return Ok(()); },
+                _ = background_jobs_barrier.wait() => { }
+            };
+
+            loop {
+                if tokio::time::timeout(period, cancel.cancelled())
+                    .await
+                    .is_ok()
+                {
+                    return Ok(());
+                }
+                if let Err(e) = verify_random_layer(&tenant_manager, &cancel).await {
+                    warn!("failed to verify a layer: {e:#}");
+                }
+            }
+        }
+        .instrument(tracing::info_span!("layer_verification")),
+    );
+}
+
+/// Compare a random resident layer with its copy in remote storage, see module-level comment.
+async fn verify_random_layer(
+    tenant_manager: &TenantManager,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let tenants = tenant_manager
+        .list_tenants()?
+        .into_iter()
+        .filter(|(_, state, _)| matches!(state, TenantState::Active))
+        .map(|(tenant_shard_id, _, _)| tenant_shard_id)
+        .collect::<Vec<_>>();
+    let Some(tenant_shard_id) = tenants.choose(&mut rand::thread_rng()).copied() else {
+        return Ok(());
+    };
+    let tenant = tenant_manager.get_attached_tenant_shard(tenant_shard_id)?;
+
+    let timelines = tenant.list_timelines();
+    let Some(timeline) = timelines.choose(&mut rand::thread_rng()).cloned() else {
+        return Ok(());
+    };
+    let Some(remote_client) = timeline.remote_client.as_ref() else {
+        return Ok(());
+    };
+
+    let layers = timeline
+        .layers
+        .read()
+        .await
+        .likely_resident_layers()
+        .collect::<Vec<_>>();
+    let Some(layer) = layers.choose(&mut rand::thread_rng()).cloned() else {
+        return Ok(());
+    };
+    // Keep the layer from being evicted while we read it.
+    let Some(layer) = layer.keep_resident().await else {
+        return Ok(());
+    };
+    let layer_name = layer.layer_desc().layer_name();
+    let Some((storage, remote_path, _)) = remote_client.uploaded_layer_location(&layer_name) else {
+        return Ok(());
+    };
+
+    let local = local_checksum(layer.local_path()).await?;
+    let remote = match storage.download(&remote_path, cancel).await {
+        Ok(download) => stream_checksum(download.download_stream)
+            .await
+            .with_context(|| format!("download {remote_path}"))?,
+        // Deleted, or migrated out of the hot tier, since we looked it up.
+        Err(DownloadError::NotFound) => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("download {remote_path}")),
+    };
+
+    if local == remote {
+        LAYER_VERIFICATIONS.with_label_values(&["match"]).inc();
+    } else {
+        LAYER_VERIFICATIONS.with_label_values(&["mismatch"]).inc();
+        error!(
+            tenant_id = %tenant_shard_id.tenant_id,
+            shard_id = %tenant_shard_id.shard_slug(),
+            timeline_id = %timeline.timeline_id,
+            layer = %layer_name,
+            %remote_path,
+            local_size = local.size,
+            local_crc32c = local.crc32c,
+            remote_size = remote.size,
+            remote_crc32c = remote.crc32c,
+            "local layer differs from its copy in remote storage"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_and_remote_checksums_agree() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let path = dir.path().join("layer");
+        let content = (0..200_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(&path, &content).unwrap();
+
+        let chunks = content
+            .chunks(3000)
+            .map(|c| Ok(bytes::Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        let remote = stream_checksum(futures::stream::iter(chunks))
+            .await
+            .unwrap();
+        let local = local_checksum(&path).await.unwrap();
+        assert_eq!(local, remote);
+        assert_eq!(local.size, content.len() as u64);
+
+        let mut corrupt = content;
+        corrupt[12345] ^= 1;
+        let corrupt = stream_checksum(futures::stream::iter(vec![Ok(bytes::Bytes::from(corrupt))]))
+            .await
+            .unwrap();
+        assert_ne!(local, corrupt);
+    }
+}
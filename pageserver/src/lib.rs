@@ -11,20 +11,26 @@ pub mod deletion_queue;
 pub mod disk_usage_eviction_task;
 pub mod http;
 pub mod import_datadir;
+pub mod layer_verification;
 pub use pageserver_api::keyspace;
 pub mod aux_file;
 pub mod metrics;
 pub mod page_cache;
 pub mod page_service;
+pub mod pg_versions;
 pub mod pgdatadir_mapping;
+pub mod read_priority;
 pub mod repository;
 pub mod span;
 pub(crate) mod statvfs;
 pub mod task_mgr;
+pub mod temp_file_janitor;
 pub mod tenant;
+pub mod timeline_events;
 pub mod trace;
 pub mod utilization;
 pub mod virtual_file;
+pub mod wal_capture;
 pub mod walingest;
 pub mod walrecord;
 pub mod walredo;
@@ -130,6 +136,22 @@ pub(crate) const TENANT_LOCATION_CONFIG_NAME: &str = "config-v1";
 /// tenant path while in secondary mode.
 pub(crate) const TENANT_HEATMAP_BASENAME: &str = "heatmap-v1.json";
 
+/// Per-tenant version of the local on-disk format, see [`tenant::format_migrations`].
+/// Full path: `tenants/<tenant_id>/format_version`.
+pub(crate) const TENANT_FORMAT_VERSION_NAME: &str = "format_version";
+
+/// Per-tenant legal hold, see [`tenant::legal_hold`].
+/// Full path: `tenants/<tenant_id>/legal_hold.json`.
+pub(crate) const TENANT_LEGAL_HOLD_NAME: &str = "legal_hold.json";
+
+/// Tenant config templates, see [`tenant::config_templates`].
+/// Full path: `tenant_config_templates.json`.
+pub(crate) const TENANT_CONFIG_TEMPLATES_NAME: &str = "tenant_config_templates.json";
+
+/// Cached archives of initdb outputs, see [`tenant::initdb_cache`].
+/// Full path: `initdb_cache/`.
+pub(crate) const INITDB_CACHE_DIR_NAME: &str = "initdb_cache";
+
 /// A suffix used for various temporary files. Any temporary files found in the
 /// data directory at pageserver startup can be automatically removed.
 pub(crate) const TEMP_FILE_SUFFIX: &str = "___temp";
@@ -149,6 +171,13 @@ pub(crate) const TIMELINE_DELETE_MARK_SUFFIX: &str = "___delete";
 /// Full path: `tenants/<tenant_id>/___ignored_tenant`.
 pub const IGNORED_TENANT_FILE_NAME: &str = "___ignored_tenant";
 
+/// A marker file left by a detach that kept the tenant's local data, so that a re-attach
+/// on the same pageserver doesn't have to download it again. Like [`IGNORED_TENANT_FILE_NAME`],
+/// it prevents the tenant from being loaded on restart. Contains the time of the detach,
+/// the data is purged `detached_tenant_retention` after it.
+/// Full path: `tenants/<tenant_id>/___detached_tenant`.
+pub const DETACHED_TENANT_FILE_NAME: &str = "___detached_tenant";
+
 pub fn is_temporary(path: &Utf8Path) -> bool {
     match path.file_name() {
         Some(name) => name.ends_with(TEMP_FILE_SUFFIX),
@@ -1,12 +1,14 @@
 use enum_map::EnumMap;
 use metrics::{
-    register_counter_vec, register_gauge_vec, register_histogram, register_histogram_vec,
-    register_int_counter, register_int_counter_pair_vec, register_int_counter_vec,
-    register_int_gauge, register_int_gauge_vec, register_uint_gauge, register_uint_gauge_vec,
-    Counter, CounterVec, GaugeVec, Histogram, HistogramVec, IntCounter, IntCounterPair,
-    IntCounterPairVec, IntCounterVec, IntGauge, IntGaugeVec, UIntGauge, UIntGaugeVec,
+    register_counter_vec, register_gauge, register_gauge_vec, register_histogram,
+    register_histogram_vec, register_int_counter, register_int_counter_pair_vec,
+    register_int_counter_vec, register_int_gauge, register_int_gauge_vec, register_uint_gauge,
+    register_uint_gauge_vec, Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramVec,
+    IntCounter, IntCounterPair, IntCounterPairVec, IntCounterVec, IntGauge, IntGaugeVec, UIntGauge,
+    UIntGaugeVec,
 };
 use once_cell::sync::Lazy;
+use pageserver_api::models::TenantActivationPhase;
 use pageserver_api::shard::TenantShardId;
 use strum::{EnumCount, IntoEnumIterator, VariantNames};
 use strum_macros::{EnumVariantNames, IntoStaticStr};
@@ -89,6 +91,39 @@ pub(crate) static STORAGE_TIME_GLOBAL: Lazy<HistogramVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+/// Phases of writing out new layer files, see [`LAYER_WRITE_PHASE_TIME`].
+#[derive(Debug, Clone, Copy, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum LayerWritePhase {
+    /// Writing the layer files, including their fsyncs if done one by one.
+    Write,
+    /// Fsyncing the layer files written without fsync, in parallel.
+    FsyncLayers,
+    /// Fsyncing the timeline directory.
+    FsyncDir,
+}
+
+/// Breakdown of the time of layer flushes, compactions and image layer creations, by phase of
+/// writing out their new layer files.
+pub(crate) static LAYER_WRITE_PHASE_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "pageserver_layer_write_phase_seconds",
+        "Time spent writing out new layer files, by operation and phase",
+        &["operation", "phase"],
+        CRITICAL_OP_BUCKETS.into(),
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static BACKGROUND_READ_WAIT_TIME: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "pageserver_background_read_wait_seconds",
+        "Time background reads spent queued behind other background reads and foreground reads",
+        CRITICAL_OP_BUCKETS.into(),
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) static READ_NUM_LAYERS_VISITED: Lazy<Histogram> = Lazy::new(|| {
     register_histogram!(
         "pageserver_layers_visited_per_read_global",
@@ -328,6 +363,15 @@ static PAGE_CACHE_READ_ACCESSES: Lazy<IntCounterVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+pub(crate) static READ_CANCELLED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_read_cancelled_total",
+        "Reads given up mid-flight because the request was cancelled or past its deadline",
+        &["task_kind", "stage"]
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) static PAGE_CACHE: Lazy<PageCacheMetrics> = Lazy::new(|| PageCacheMetrics {
     map: EnumMap::from_array(std::array::from_fn(|task_kind| {
         let task_kind = <TaskKind as enum_map::Enum>::from_usize(task_kind);
@@ -576,6 +620,128 @@ pub(crate) static REMOTE_ONDEMAND_DOWNLOADED_BYTES: Lazy<IntCounter> = Lazy::new
     .unwrap()
 });
 
+pub(crate) static REMOTE_HOT_TIER_MIGRATED_LAYERS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_remote_hot_tier_migrated_layers_total",
+        "Total layers migrated from the hot tier to the standard remote storage",
+    )
+    .unwrap()
+});
+
+pub(crate) static LAYER_VERIFICATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_layer_verifications_total",
+        "Resident layers compared with their copy in remote storage, by result",
+        &["result"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static TENANT_STATE_INVALID_TRANSITIONS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_tenant_state_invalid_transitions_total",
+        "Tenant state transitions refused because they are not in the transition table"
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static TEMP_FILES_REMOVED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_temp_files_removed_total",
+        "Stale temporary files and directories removed by the temp file janitor"
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static TEMP_FILES_REMOVED_BYTES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_temp_files_removed_bytes_total",
+        "Bytes reclaimed by the temp file janitor"
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static LAYER_OVERLAP_REPAIRS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_layer_overlap_repairs_total",
+        "Key ranges with stacked delta layers for which compaction scheduled image layer creation"
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static UPLOAD_BACKLOG_PACED_SECONDS: Lazy<CounterVec> = Lazy::new(|| {
+    register_counter_vec!(
+        "pageserver_upload_backlog_paced_seconds_total",
+        "Time that layer flushes and compactions waited for the upload queue to drain",
+        &["operation"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static UPLOAD_BACKLOG_PACED_TIMELINES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "pageserver_upload_backlog_paced_timelines",
+        "Number of timelines whose layer production is paced by their upload backlog"
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static FORMAT_MIGRATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_format_migrations_total",
+        "Migrations of the on-disk format of tenant directories, by outcome",
+        &["outcome"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static TAGGED_GETPAGE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_tagged_getpage_requests_total",
+        "Getpage requests, by the propagated tags of the tenant",
+        &["tag", "value"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static TAGGED_WAL_BYTES_RECEIVED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_tagged_wal_bytes_received_total",
+        "Bytes of WAL received from safekeepers, by the propagated tags of the tenant",
+        &["tag", "value"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static GETPAGE_LATENCY_BY_READ_PATH: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "pageserver_getpage_latency_by_read_path_seconds",
+        "Latency of GetPage requests, by the most expensive source of data they used",
+        &["read_path", "tenant_id", "shard_id"],
+        // Coarse, as there is a histogram for each read path of each tenant shard.
+        vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0],
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static GETPAGE_SLO_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_getpage_slo_requests_total",
+        "GetPage requests, by whether they met the latency SLO",
+        &["outcome", "tenant_id", "shard_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static GETPAGE_SLO_BURN_RATE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "pageserver_getpage_slo_burn_rate",
+        "Rate at which GetPage requests spend the error budget of the latency SLO, over the last minutes",
+        &["tenant_id", "shard_id"]
+    )
+    .expect("failed to define a metric")
+});
+
 static CURRENT_LOGICAL_SIZE: Lazy<UIntGaugeVec> = Lazy::new(|| {
     register_uint_gauge_vec!(
         "pageserver_current_logical_size",
@@ -649,6 +815,7 @@ pub(crate) mod initial_logical_size {
         EmptyInitial,
         SkippedConcurrencyLimiter,
         AfterBackgroundTasksRateLimit,
+        FromCheckpoint,
     }
 
     impl StartCalculation {
@@ -752,6 +919,15 @@ pub(crate) static TENANT_SYNTHETIC_SIZE_METRIC: Lazy<UIntGaugeVec> = Lazy::new(|
     .expect("Failed to register pageserver_tenant_synthetic_cached_size_bytes metric")
 });
 
+pub(crate) static COMPACTION_DEBT: Lazy<UIntGaugeVec> = Lazy::new(|| {
+    register_uint_gauge_vec!(
+        "pageserver_compaction_debt_bytes",
+        "Bytes of L0 delta layers waiting for compaction, by tenant shard",
+        &["tenant_id", "shard_id"]
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) static EVICTION_ITERATION_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
     register_histogram_vec!(
         "pageserver_eviction_iteration_duration_seconds_global",
@@ -762,6 +938,17 @@ pub(crate) static EVICTION_ITERATION_DURATION: Lazy<HistogramVec> = Lazy::new(||
     .expect("failed to define a metric")
 });
 
+/// Evictions prevented because the layer was bouncing between evicted and downloaded, see
+/// [`crate::config::PageServerConf::eviction_bounce_window`].
+pub(crate) static EVICTIONS_PREVENTED_BOUNCING: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_evictions_prevented_bouncing",
+        "Number of layer evictions that were skipped because the layer was downloaded again soon after its last eviction",
+        &["task"]
+    )
+    .expect("failed to define a metric")
+});
+
 static EVICTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
         "pageserver_evictions",
@@ -771,6 +958,33 @@ static EVICTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+static ANCESTOR_PREFETCH_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_ancestor_prefetch_bytes",
+        "Bytes of ancestor layers downloaded ahead of time because reads on this timeline hit them",
+        &["tenant_id", "shard_id", "timeline_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static WAL_INGEST_SECONDS: Lazy<CounterVec> = Lazy::new(|| {
+    register_counter_vec!(
+        "pageserver_wal_ingest_seconds_total",
+        "Time spent ingesting WAL into the timeline, by phase: decode, apply or commit",
+        &["tenant_id", "shard_id", "timeline_id", "phase"]
+    )
+    .expect("failed to define a metric")
+});
+
+static WALRECEIVER_RECONNECTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_walreceiver_reconnects_total",
+        "Number of times the walreceiver of the timeline connected to a safekeeper again",
+        &["tenant_id", "shard_id", "timeline_id"]
+    )
+    .expect("failed to define a metric")
+});
+
 static EVICTIONS_WITH_LOW_RESIDENCE_DURATION: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
         "pageserver_evictions_with_low_residence_duration",
@@ -817,6 +1031,22 @@ pub(crate) static TIMELINE_EPHEMERAL_BYTES: Lazy<UIntGauge> = Lazy::new(|| {
     .expect("Failed to register metric")
 });
 
+pub(crate) static EPHEMERAL_STAGING_BYTES: Lazy<UIntGauge> = Lazy::new(|| {
+    register_uint_gauge!(
+        "pageserver_ephemeral_staging_bytes",
+        "Number of bytes of the ephemeral files in the staging area, ephemeral_dir.  Approximate, lazily updated."
+    )
+    .expect("Failed to register metric")
+});
+
+pub(crate) static EPHEMERAL_STAGING_FALLBACKS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_ephemeral_staging_fallbacks_total",
+        "Number of ephemeral files created in the timeline directory because the staging area was full or failed"
+    )
+    .expect("Failed to register metric")
+});
+
 /// Metrics related to the lifecycle of a [`crate::tenant::Tenant`] object: things
 /// like how long it took to load.
 ///
@@ -828,6 +1058,9 @@ pub(crate) struct TenantMetrics {
     pub(crate) activation: Histogram,
     pub(crate) preload: Histogram,
     pub(crate) attach: Histogram,
+    /// How long did the phases of the activation take? See
+    /// [`crate::tenant::activation_profile`].
+    pub(crate) activation_phase: EnumMap<TenantActivationPhase, Histogram>,
 
     /// How many tenants are included in the initial startup of the pagesrever?
     pub(crate) startup_scheduled: IntCounter,
@@ -835,6 +1068,13 @@ pub(crate) struct TenantMetrics {
 }
 
 pub(crate) static TENANT: Lazy<TenantMetrics> = Lazy::new(|| {
+    let activation_phase = register_histogram_vec!(
+        "pageserver_tenant_activation_phase_seconds",
+        "Time taken by the phases of tenant activation, in seconds",
+        &["phase"],
+        CRITICAL_OP_BUCKETS.into()
+    )
+    .expect("Failed to register metric");
     TenantMetrics {
     activation: register_histogram!(
         "pageserver_tenant_activation_seconds",
@@ -854,6 +1094,11 @@ pub(crate) static TENANT: Lazy<TenantMetrics> = Lazy::new(|| {
         CRITICAL_OP_BUCKETS.into()
     )
     .expect("Failed to register metric"),
+    activation_phase: EnumMap::from_array(std::array::from_fn(|i| {
+        let phase = <TenantActivationPhase as enum_map::Enum>::from_usize(i);
+        let phase_str: &'static str = phase.into();
+        activation_phase.with_label_values(&[phase_str])
+    })),
     startup_scheduled: register_int_counter!(
         "pageserver_tenant_startup_scheduled",
         "Number of tenants included in pageserver startup (doesn't count tenants attached later)"
@@ -1076,6 +1321,24 @@ pub(crate) static STORAGE_IO_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+pub(crate) static TASK_CPU_SECONDS: Lazy<CounterVec> = Lazy::new(|| {
+    register_counter_vec!(
+        "pageserver_task_cpu_seconds_total",
+        "CPU time used by the tasks of a tenant, by task kind",
+        &["tenant_id", "shard_id", "task_kind"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static TASK_IO_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_task_io_bytes_total",
+        "Bytes read/written by the tasks of a tenant, by task kind",
+        &["operation", "tenant_id", "shard_id", "task_kind"]
+    )
+    .expect("failed to define a metric")
+});
+
 #[cfg(not(test))]
 pub(crate) mod virtual_file_descriptor_cache {
     use super::*;
@@ -1387,6 +1650,47 @@ static COMPUTE_STARTUP_BUCKETS: Lazy<[f64; 28]> = Lazy::new(|| {
     .map(|ms| (ms as f64) / 1000.0)
 });
 
+pub(crate) static PAGE_SERVICE_PREFETCH_BLOCKS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_page_service_prefetch_blocks_total",
+        "Blocks read ahead of the sequential scans of page_service connections"
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static PAGE_SERVICE_PREFETCH_HITS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_page_service_prefetch_hits_total",
+        "GetPage requests for blocks that were read ahead"
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static LEGAL_HOLD_VIOLATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_legal_hold_violations_total",
+        "Destructive operations that a tenant legal hold blocked",
+        &["operation"]
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static BASEBACKUP_INCREMENTAL_SKIPPED_FILES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_basebackup_incremental_skipped_files_total",
+        "Files left out of incremental basebackups because the compute already had them"
+    )
+    .expect("failed to define a metric")
+});
+
+pub(crate) static BASEBACKUP_INCREMENTAL_SKIPPED_BYTES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "pageserver_basebackup_incremental_skipped_bytes_total",
+        "Bytes left out of incremental basebackups because the compute already had them"
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) struct BasebackupQueryTime(HistogramVec);
 pub(crate) static BASEBACKUP_QUERY_TIME: Lazy<BasebackupQueryTime> = Lazy::new(|| {
     BasebackupQueryTime({
@@ -1721,6 +2025,15 @@ pub(crate) static REMOTE_OPERATION_TIME: Lazy<HistogramVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+pub(crate) static TIMELINE_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_timeline_events_total",
+        "Timeline lifecycle events for computes, by whether they were published to the broker",
+        &["result"]
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) static TENANT_TASK_EVENTS: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!(
         "pageserver_tenant_task_events",
@@ -1936,6 +2249,7 @@ pub(crate) static WAL_REDO_PROCESS_LAUNCH_DURATION_HISTOGRAM: Lazy<Histogram> =
 pub(crate) struct WalRedoProcessCounters {
     pub(crate) started: IntCounter,
     pub(crate) killed_by_cause: enum_map::EnumMap<WalRedoKillCause, IntCounter>,
+    pub(crate) recycled_by_reason: enum_map::EnumMap<WalRedoRecycleReason, IntCounter>,
     pub(crate) active_stderr_logger_tasks_started: IntCounter,
     pub(crate) active_stderr_logger_tasks_finished: IntCounter,
 }
@@ -1947,6 +2261,14 @@ pub(crate) enum WalRedoKillCause {
     Startup,
 }
 
+/// Why a walredo process was replaced with a new one, see [`crate::walredo::RecycleConfig`].
+#[derive(Debug, Clone, Copy, enum_map::Enum, strum_macros::IntoStaticStr)]
+pub(crate) enum WalRedoRecycleReason {
+    MaxRequests,
+    MaxLifetime,
+    MaxRss,
+}
+
 impl Default for WalRedoProcessCounters {
     fn default() -> Self {
         let started = register_int_counter!(
@@ -1962,6 +2284,13 @@ impl Default for WalRedoProcessCounters {
         )
         .unwrap();
 
+        let recycled = register_int_counter_vec!(
+            "pageserver_wal_redo_process_recycled_total",
+            "Number of WAL redo processes taken out of rotation for reaching a recycling limit",
+            &["reason"],
+        )
+        .unwrap();
+
         let active_stderr_logger_tasks_started = register_int_counter!(
             "pageserver_walredo_stderr_logger_tasks_started_total",
             "Number of active walredo stderr logger tasks that have started",
@@ -1981,6 +2310,11 @@ impl Default for WalRedoProcessCounters {
                 let cause_str: &'static str = cause.into();
                 killed.with_label_values(&[cause_str])
             })),
+            recycled_by_reason: EnumMap::from_array(std::array::from_fn(|i| {
+                let reason = <WalRedoRecycleReason as enum_map::Enum>::from_usize(i);
+                let reason_str: &'static str = reason.into();
+                recycled.with_label_values(&[reason_str])
+            })),
             active_stderr_logger_tasks_started,
             active_stderr_logger_tasks_finished,
         }
@@ -2118,6 +2452,14 @@ pub(crate) struct TimelineMetrics {
     pub directory_entries_count_gauge: Lazy<UIntGauge, Box<dyn Send + Fn() -> UIntGauge>>,
     pub evictions: IntCounter,
     pub evictions_with_low_residence_duration: std::sync::RwLock<EvictionsWithLowResidenceDuration>,
+    /// Ancestor layers downloaded for this timeline, see
+    /// [`crate::tenant::timeline::ancestor_prefetch`].
+    pub ancestor_prefetch_bytes: IntCounter,
+    /// See [`crate::tenant::timeline::walreceiver::ingest_stats`].
+    pub wal_ingest_decode_seconds: Counter,
+    pub wal_ingest_apply_seconds: Counter,
+    pub wal_ingest_commit_seconds: Counter,
+    pub walreceiver_reconnects: IntCounter,
 }
 
 impl TimelineMetrics {
@@ -2208,6 +2550,18 @@ impl TimelineMetrics {
             .unwrap();
         let evictions_with_low_residence_duration = evictions_with_low_residence_duration_builder
             .build(&tenant_id, &shard_id, &timeline_id);
+        let ancestor_prefetch_bytes = ANCESTOR_PREFETCH_BYTES
+            .get_metric_with_label_values(&[&tenant_id, &shard_id, &timeline_id])
+            .unwrap();
+        let [wal_ingest_decode_seconds, wal_ingest_apply_seconds, wal_ingest_commit_seconds] =
+            ["decode", "apply", "commit"].map(|phase| {
+                WAL_INGEST_SECONDS
+                    .get_metric_with_label_values(&[&tenant_id, &shard_id, &timeline_id, phase])
+                    .unwrap()
+            });
+        let walreceiver_reconnects = WALRECEIVER_RECONNECTS
+            .get_metric_with_label_values(&[&tenant_id, &shard_id, &timeline_id])
+            .unwrap();
 
         TimelineMetrics {
             tenant_id,
@@ -2229,6 +2583,11 @@ impl TimelineMetrics {
             evictions_with_low_residence_duration: std::sync::RwLock::new(
                 evictions_with_low_residence_duration,
             ),
+            ancestor_prefetch_bytes,
+            wal_ingest_decode_seconds,
+            wal_ingest_apply_seconds,
+            wal_ingest_commit_seconds,
+            walreceiver_reconnects,
         }
     }
 
@@ -2264,6 +2623,12 @@ impl TimelineMetrics {
             let _ = metric.remove_label_values(&[tenant_id, shard_id, timeline_id]);
         }
         let _ = EVICTIONS.remove_label_values(&[tenant_id, shard_id, timeline_id]);
+        let _ = ANCESTOR_PREFETCH_BYTES.remove_label_values(&[tenant_id, shard_id, timeline_id]);
+        for phase in ["decode", "apply", "commit"] {
+            let _ =
+                WAL_INGEST_SECONDS.remove_label_values(&[tenant_id, shard_id, timeline_id, phase]);
+        }
+        let _ = WALRECEIVER_RECONNECTS.remove_label_values(&[tenant_id, shard_id, timeline_id]);
 
         self.evictions_with_low_residence_duration
             .write()
@@ -2312,6 +2677,13 @@ pub(crate) fn remove_tenant_metrics(tenant_shard_id: &TenantShardId) {
         let _ = TENANT_SYNTHETIC_SIZE_METRIC.remove_label_values(&[&tid]);
     }
 
+    crate::task_mgr::resource_usage::remove(tenant_shard_id);
+    crate::tenant::getpage_latency::remove(tenant_shard_id);
+    let _ = COMPACTION_DEBT.remove_label_values(&[
+        &tenant_shard_id.tenant_id.to_string(),
+        &tenant_shard_id.shard_slug().to_string(),
+    ]);
+
     // we leave the BROKEN_TENANTS_SET entry if any
 }
 
@@ -2840,6 +3212,11 @@ pub(crate) mod disk_usage_based_eviction {
         pub(crate) layers_collected: IntCounter,
         pub(crate) layers_selected: IntCounter,
         pub(crate) layers_evicted: IntCounter,
+        pub(crate) forecast_fill_rate: Gauge,
+        pub(crate) forecast_seconds_until_pressure: Gauge,
+        pub(crate) forecast_seconds_until_full: Gauge,
+        pub(crate) data_dir_avail_bytes: UIntGaugeVec,
+        pub(crate) tenants_offloaded: IntCounter,
     }
 
     impl Default for Metrics {
@@ -2876,12 +3253,48 @@ pub(crate) mod disk_usage_based_eviction {
             )
             .unwrap();
 
+            let forecast_fill_rate = register_gauge!(
+                "pageserver_disk_usage_forecast_fill_rate_bytes_per_second",
+                "Rate at which the available bytes of the filesystem shrink, negative if they grow"
+            )
+            .unwrap();
+
+            let forecast_seconds_until_pressure = register_gauge!(
+                "pageserver_disk_usage_forecast_seconds_until_pressure",
+                "Forecast time until the disk usage based eviction thresholds are crossed"
+            )
+            .unwrap();
+
+            let forecast_seconds_until_full = register_gauge!(
+                "pageserver_disk_usage_forecast_seconds_until_full",
+                "Forecast time until the filesystem is full"
+            )
+            .unwrap();
+
+            let data_dir_avail_bytes = register_uint_gauge_vec!(
+                "pageserver_disk_usage_data_dir_avail_bytes",
+                "Available bytes of the filesystem of each tenant data dir, as of the last iteration",
+                &["data_dir"]
+            )
+            .unwrap();
+
+            let tenants_offloaded = register_int_counter!(
+                "pageserver_disk_usage_based_eviction_offloaded_tenants_total",
+                "Amount of tenant shards offloaded because evictions didn't relieve the disk pressure"
+            )
+            .unwrap();
+
             Self {
                 tenant_collection_time,
                 tenant_layer_count,
                 layers_collected,
                 layers_selected,
                 layers_evicted,
+                forecast_fill_rate,
+                forecast_seconds_until_pressure,
+                forecast_seconds_until_full,
+                data_dir_avail_bytes,
+                tenants_offloaded,
             }
         }
     }
@@ -2931,6 +3344,14 @@ pub fn preinitialize_metrics() {
         &tokio_epoll_uring::THREAD_LOCAL_LAUNCH_SUCCESSES,
         &REMOTE_ONDEMAND_DOWNLOADED_LAYERS,
         &REMOTE_ONDEMAND_DOWNLOADED_BYTES,
+        &REMOTE_HOT_TIER_MIGRATED_LAYERS,
+        &TEMP_FILES_REMOVED,
+        &TEMP_FILES_REMOVED_BYTES,
+        &TENANT_STATE_INVALID_TRANSITIONS,
+        &BASEBACKUP_INCREMENTAL_SKIPPED_FILES,
+        &BASEBACKUP_INCREMENTAL_SKIPPED_BYTES,
+        &PAGE_SERVICE_PREFETCH_BLOCKS,
+        &PAGE_SERVICE_PREFETCH_HITS,
     ]
     .into_iter()
     .for_each(|c| {
@@ -2956,11 +3377,14 @@ pub fn preinitialize_metrics() {
     }
 
     // countervecs
-    [&BACKGROUND_LOOP_PERIOD_OVERRUN_COUNT]
-        .into_iter()
-        .for_each(|c| {
-            Lazy::force(c);
-        });
+    [
+        &BACKGROUND_LOOP_PERIOD_OVERRUN_COUNT,
+        &LEGAL_HOLD_VIOLATIONS,
+    ]
+    .into_iter()
+    .for_each(|c| {
+        Lazy::force(c);
+    });
 
     // gauges
     WALRECEIVER_ACTIVE_MANAGERS.get();
@@ -46,6 +46,13 @@
 //!   Unless there's high cache pressure, the page should now be cached.
 //!   (TODO: allow downgrading the write guard to a read guard to ensure forward progress.)
 //!
+//! # Read Priority
+//!
+//! Pages read by background tasks (see [`crate::read_priority`]) are admitted with the lowest
+//! usage count, and hits by background reads don't increase the usage count of a page. A
+//! background task that scans a lot of data thus mostly recycles the slots it filled itself,
+//! instead of evicting the pages that foreground reads use.
+//!
 //! # Locking
 //!
 //! There are two levels of locking involved: There's one lock for the "mapping"
@@ -88,6 +95,7 @@ use utils::{id::TimelineId, lsn::Lsn};
 use crate::{
     context::RequestContext,
     metrics::{page_cache_eviction_metrics, PageCacheSizeMetrics},
+    read_priority::ReadPriority,
     repository::Key,
 };
 
@@ -408,7 +416,7 @@ impl PageCache {
         };
 
         if let Some(guard) = self
-            .try_lock_for_read(&mut cache_key, &mut Some(permit))
+            .try_lock_for_read(&mut cache_key, &mut Some(permit), ctx.read_priority())
             .await
         {
             if let CacheKey::MaterializedPage {
@@ -584,6 +592,7 @@ impl PageCache {
         &self,
         cache_key: &mut CacheKey,
         permit: &mut Option<PinnedSlotsPermit>,
+        priority: ReadPriority,
     ) -> Option<PageReadGuard> {
         let cache_key_orig = cache_key.clone();
         if let Some(slot_idx) = self.search_mapping(cache_key) {
@@ -593,7 +602,9 @@ impl PageCache {
             let slot = &self.slots[slot_idx];
             let inner = slot.inner.read().await;
             if inner.key.as_ref() == Some(cache_key) {
-                slot.inc_usage_count();
+                if priority == ReadPriority::Foreground {
+                    slot.inc_usage_count();
+                }
                 return Some(PageReadGuard {
                     _permit: inner.coalesce_readers_permit(permit.take().unwrap()),
                     slot_guard: inner,
@@ -641,6 +652,7 @@ impl PageCache {
         ctx: &RequestContext,
     ) -> anyhow::Result<ReadBufResult> {
         let mut permit = Some(self.try_get_pinned_slot_permit().await?);
+        let priority = ctx.read_priority();
 
         let (read_access, hit) = match cache_key {
             CacheKey::MaterializedPage { .. } => {
@@ -658,7 +670,10 @@ impl PageCache {
         let mut is_first_iteration = true;
         loop {
             // First check if the key already exists in the cache.
-            if let Some(read_guard) = self.try_lock_for_read(cache_key, &mut permit).await {
+            if let Some(read_guard) = self
+                .try_lock_for_read(cache_key, &mut permit, priority)
+                .await
+            {
                 debug_assert!(permit.is_none());
                 if is_first_iteration {
                     hit.inc();
@@ -692,7 +707,10 @@ impl PageCache {
             // Make the slot ready
             let slot = &self.slots[slot_idx];
             inner.key = Some(cache_key.clone());
-            slot.set_usage_count(1);
+            slot.set_usage_count(match priority {
+                ReadPriority::Foreground => 1,
+                ReadPriority::Background => 0,
+            });
 
             debug_assert!(
                 {
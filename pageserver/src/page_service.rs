@@ -1,11 +1,19 @@
 //! The Page Service listens for client connections and serves their GetPage@LSN
 //! requests.
 
+pub(crate) mod multiplex;
+pub(crate) mod negotiation;
+pub(crate) mod prefetch;
+pub mod tls;
+
 use anyhow::Context;
 use async_compression::tokio::write::GzipEncoder;
 use bytes::Buf;
+use bytes::BufMut;
 use bytes::Bytes;
+use bytes::BytesMut;
 use futures::stream::FuturesUnordered;
+use futures::FutureExt;
 use futures::Stream;
 use futures::StreamExt;
 use pageserver_api::key::Key;
@@ -13,9 +21,10 @@ use pageserver_api::models::TenantState;
 use pageserver_api::models::{
     PagestreamBeMessage, PagestreamDbSizeRequest, PagestreamDbSizeResponse,
     PagestreamErrorResponse, PagestreamExistsRequest, PagestreamExistsResponse,
-    PagestreamFeMessage, PagestreamGetPageRequest, PagestreamGetPageResponse,
-    PagestreamGetSlruSegmentRequest, PagestreamGetSlruSegmentResponse, PagestreamNblocksRequest,
-    PagestreamNblocksResponse, PagestreamProtocolVersion,
+    PagestreamFeMessage, PagestreamGetPageAtLeastRequest, PagestreamGetPageAtLeastResponse,
+    PagestreamGetPageRequest, PagestreamGetPageResponse, PagestreamGetSlruSegmentRequest,
+    PagestreamGetSlruSegmentResponse, PagestreamNblocksRequest, PagestreamNblocksResponse,
+    PagestreamProtocolVersion, PagestreamV3Header,
 };
 use pageserver_api::shard::ShardIndex;
 use pageserver_api::shard::ShardNumber;
@@ -46,12 +55,12 @@ use utils::{
     simple_rcu::RcuReadGuard,
 };
 
-use crate::auth::check_permission;
+use crate::auth::{check_lsn_permission, check_permission, check_timeline_permission};
 use crate::basebackup;
-use crate::basebackup::BasebackupError;
+use crate::basebackup::{BasebackupError, BasebackupManifest};
 use crate::config::PageServerConf;
-use crate::context::{DownloadBehavior, RequestContext};
-use crate::import_datadir::import_wal_from_tar;
+use crate::context::{DownloadBehavior, RequestContext, RequestContextBuilder};
+use crate::import_datadir::{import_wal_from_archive, import_wal_from_tar, WalArchive};
 use crate::metrics;
 use crate::metrics::LIVE_CONNECTIONS_COUNT;
 use crate::pgdatadir_mapping::Version;
@@ -59,19 +68,23 @@ use crate::span::debug_assert_current_span_has_tenant_and_timeline_id;
 use crate::span::debug_assert_current_span_has_tenant_and_timeline_id_no_shard_id;
 use crate::task_mgr;
 use crate::task_mgr::TaskKind;
+use crate::tenant::getpage_latency::ConnectionGetPageStats;
 use crate::tenant::mgr;
 use crate::tenant::mgr::get_active_tenant_with_timeout;
 use crate::tenant::mgr::GetActiveTenantError;
 use crate::tenant::mgr::ShardSelector;
+use crate::tenant::offload;
 use crate::tenant::timeline::WaitLsnError;
 use crate::tenant::GetTimelineError;
 use crate::tenant::PageReconstructError;
 use crate::tenant::Timeline;
 use crate::trace::Tracer;
-use pageserver_api::key::rel_block_to_key;
-use pageserver_api::reltag::SlruKind;
+use multiplex::FairQueue;
+use pageserver_api::key::{rel_block_to_key, rel_dir_to_key, rel_size_to_key};
+use pageserver_api::reltag::{RelTag, SlruKind};
 use postgres_ffi::pg_constants::DEFAULTTABLESPACE_OID;
-use postgres_ffi::BLCKSZ;
+use postgres_ffi::{BlockNumber, BLCKSZ};
+use prefetch::SequentialPrefetch;
 
 // How long we may wait for a [`TenantSlot::InProgress`]` and/or a [`Tenant`] which
 // is not yet in state [`TenantState::Active`].
@@ -140,6 +153,7 @@ pub async fn libpq_listener_main(
     auth: Option<Arc<SwappableJwtAuth>>,
     listener: TcpListener,
     auth_type: AuthType,
+    tls_config: Option<Arc<rustls::ServerConfig>>,
     listener_ctx: RequestContext,
     cancel: CancellationToken,
 ) -> anyhow::Result<()> {
@@ -185,6 +199,7 @@ pub async fn libpq_listener_main(
                         local_auth,
                         socket,
                         auth_type,
+                        tls_config.clone(),
                         connection_ctx,
                     ),
                 );
@@ -208,6 +223,7 @@ async fn page_service_conn_main(
     auth: Option<Arc<SwappableJwtAuth>>,
     socket: tokio::net::TcpStream,
     auth_type: AuthType,
+    tls_config: Option<Arc<rustls::ServerConfig>>,
     connection_ctx: RequestContext,
 ) -> anyhow::Result<()> {
     // Immediately increment the gauge, then create a job to decrement it on task exit.
@@ -261,7 +277,7 @@ async fn page_service_conn_main(
     // But it's in a shared crate, so, we store connection_ctx inside PageServerHandler
     // and create the per-query context in process_query ourselves.
     let mut conn_handler = PageServerHandler::new(conf, broker_client, auth, connection_ctx);
-    let pgbackend = PostgresBackend::new_from_io(socket, peer_addr, auth_type, None)?;
+    let pgbackend = PostgresBackend::new_from_io(socket, peer_addr, auth_type, tls_config)?;
 
     match pgbackend
         .run(&mut conn_handler, task_mgr::shutdown_watcher)
@@ -291,10 +307,13 @@ struct HandlerTimeline {
 }
 
 struct PageServerHandler {
-    _conf: &'static PageServerConf,
+    conf: &'static PageServerConf,
     broker_client: storage_broker::BrokerClientChannel,
     auth: Option<Arc<SwappableJwtAuth>>,
     claims: Option<Claims>,
+    /// The tenants that the client certificate grants access to, if the client presented one,
+    /// see [`tls`].
+    certificate_tenants: Option<Vec<TenantId>>,
 
     /// The context created for the lifetime of the connection
     /// services by this PageServerHandler.
@@ -304,11 +323,37 @@ struct PageServerHandler {
 
     /// See [`Self::cache_timeline`] for usage.
     ///
-    /// Note on size: the typical size of this map is 1.  The largest size we expect
+    /// Note on size: the typical size of the map of each timeline is 1.  The largest size we expect
     /// to see is the number of shards divided by the number of pageservers (typically < 2),
     /// or the ratio used when splitting shards (i.e. how many children created from one)
-    /// parent shard, where a "large" number might be ~8.
-    shard_timelines: HashMap<ShardIndex, HandlerTimeline>,
+    /// parent shard, where a "large" number might be ~8. Only V3 connections have more than
+    /// one timeline, see [`multiplex`].
+    shard_timelines: HashMap<TimelineId, HashMap<ShardIndex, HandlerTimeline>>,
+
+    /// The GetPage requests of the connection by read path, logged when it ends.
+    getpage_stats: ConnectionGetPageStats,
+
+    /// Reads ahead of the sequential scans of the connection, per timeline.
+    prefetch: HashMap<TimelineId, SequentialPrefetch>,
+}
+
+/// A request read from a pagestream connection.
+struct PagestreamRequest {
+    timeline_id: TimelineId,
+    /// Only V3 requests have one, to be echoed in the response.
+    request_id: Option<u64>,
+    msg: PagestreamFeMessage,
+}
+
+impl Drop for PageServerHandler {
+    fn drop(&mut self) {
+        if self.getpage_stats.requests() > 0 {
+            info!(
+                "connection served GetPage requests by read path: {}",
+                self.getpage_stats
+            );
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -379,6 +424,17 @@ impl From<WaitLsnError> for QueryError {
     }
 }
 
+/// How the LSN at which a GetPage request is served is chosen.
+enum GetPageLsn {
+    /// See [`PageServerHandler::wait_or_get_last_lsn`].
+    Exact {
+        request_lsn: Lsn,
+        not_modified_since: Lsn,
+    },
+    /// The last record LSN, once it reaches `min_lsn`, see [`PagestreamGetPageAtLeastRequest`].
+    AtLeast { min_lsn: Lsn, wait_budget: Duration },
+}
+
 impl PageServerHandler {
     pub fn new(
         conf: &'static PageServerConf,
@@ -387,12 +443,15 @@ impl PageServerHandler {
         connection_ctx: RequestContext,
     ) -> Self {
         PageServerHandler {
-            _conf: conf,
+            conf,
             broker_client,
             auth,
             claims: None,
+            certificate_tenants: None,
             connection_ctx,
             shard_timelines: HashMap::new(),
+            getpage_stats: ConnectionGetPageStats::default(),
+            prefetch: HashMap::new(),
         }
     }
 
@@ -428,6 +487,7 @@ impl PageServerHandler {
         cancellation_sources.extend(
             self.shard_timelines
                 .values()
+                .flat_map(|shards| shards.values())
                 .map(|ht| Either::Right(ht.timeline.cancel.cancelled())),
         );
         FuturesUnordered::from_iter(cancellation_sources)
@@ -441,6 +501,7 @@ impl PageServerHandler {
             || self
                 .shard_timelines
                 .values()
+                .flat_map(|shards| shards.values())
                 .any(|ht| ht.timeline.cancel.is_cancelled() || ht.timeline.is_stopping())
     }
 
@@ -538,12 +599,14 @@ impl PageServerHandler {
         }
     }
 
-    #[instrument(skip_all)]
+    /// Serve the requests of a pagestream connection. `timeline_id` is the timeline of the
+    /// connection, or `None` for V3 connections, whose requests each name their timeline.
+    #[instrument(skip_all, fields(tags))]
     async fn handle_pagerequests<IO>(
         &mut self,
         pgb: &mut PostgresBackend<IO>,
         tenant_id: TenantId,
-        timeline_id: TimelineId,
+        timeline_id: Option<TimelineId>,
         protocol_version: PagestreamProtocolVersion,
         ctx: RequestContext,
     ) -> Result<(), QueryError>
@@ -559,109 +622,249 @@ impl PageServerHandler {
             &task_mgr::shutdown_token(),
         )
         .await?;
-
-        // Make request tracer if needed
-        let mut tracer = if tenant.get_trace_read_requests() {
-            let connection_id = ConnectionId::generate();
-            let path =
-                tenant
-                    .conf
-                    .trace_path(&tenant.tenant_shard_id(), &timeline_id, &connection_id);
-            Some(Tracer::new(path))
-        } else {
-            None
+        tracing::Span::current().record("tags", tenant.tag_labels().as_field());
+
+        // Make request tracer if needed. The traces are per timeline, so V3 connections
+        // aren't traced.
+        let mut tracer = match timeline_id {
+            Some(timeline_id) if tenant.get_trace_read_requests() => {
+                let connection_id = ConnectionId::generate();
+                let path =
+                    tenant
+                        .conf
+                        .trace_path(&tenant.tenant_shard_id(), &timeline_id, &connection_id);
+                Some(Tracer::new(path))
+            }
+            _ => None,
         };
 
+        // Read once per connection, rather than for each request.
+        let page_request_deadline = tenant.get_page_request_deadline();
+
+        // Computes give up on their requests by closing the connection: then the requests in
+        // flight are cancelled.
+        let client_gone = CancellationToken::new();
+        let ctx = RequestContextBuilder::extend(&ctx)
+            .cancel(&client_gone)
+            .build();
+        let mut disconnected = None;
+
         // switch client to COPYBOTH
         pgb.write_message_noflush(&BeMessage::CopyBothResponse)?;
         self.flush_cancellable(pgb, &tenant.cancel).await?;
 
+        let mut queue = FairQueue::new();
         loop {
-            let msg = tokio::select! {
-                biased;
+            if queue.is_empty() {
+                let msg = tokio::select! {
+                    biased;
 
-                _ = self.await_connection_cancelled() => {
-                    // We were requested to shut down.
-                    info!("shutdown request received in page handler");
-                    return Err(QueryError::Shutdown)
+                    _ = self.await_connection_cancelled() => {
+                        // We were requested to shut down.
+                        info!("shutdown request received in page handler");
+                        return Err(QueryError::Shutdown)
+                    }
+
+                    msg = pgb.read_message() => { msg }
+                };
+                match Self::parse_pagestream_request(
+                    msg?,
+                    timeline_id,
+                    protocol_version,
+                    &mut tracer,
+                )? {
+                    Some(request) => queue.push(request.timeline_id, request),
+                    None => break, // client disconnected
                 }
+            }
 
-                msg = pgb.read_message() => { msg }
-            };
+            if protocol_version == PagestreamProtocolVersion::V3 {
+                // Take in the requests that the client has sent already, so that each timeline
+                // gets its turn, see [`multiplex`].
+                while queue.len() < multiplex::MAX_QUEUED {
+                    let Some(msg) = pgb.read_message().now_or_never() else {
+                        break;
+                    };
+                    match Self::parse_pagestream_request(
+                        msg?,
+                        timeline_id,
+                        protocol_version,
+                        &mut tracer,
+                    )? {
+                        Some(request) => queue.push(request.timeline_id, request),
+                        None => return Ok(()), // client disconnected
+                    }
+                }
+            }
 
-            let copy_data_bytes = match msg? {
-                Some(FeMessage::CopyData(bytes)) => bytes,
-                Some(FeMessage::Terminate) => break,
-                Some(m) => {
-                    return Err(QueryError::Other(anyhow::anyhow!(
-                        "unexpected message: {m:?} during COPY"
-                    )));
+            let (
+                timeline_id,
+                PagestreamRequest {
+                    request_id,
+                    msg: neon_fe_msg,
+                    ..
+                },
+            ) = queue.pop().expect("the queue is not empty");
+
+            let parent_span = match request_id {
+                Some(request_id) => {
+                    tracing::info_span!("pagestream_request", %timeline_id, request_id)
                 }
-                None => break, // client disconnected
+                None => tracing::Span::current(),
             };
 
-            trace!("query: {copy_data_bytes:?}");
-
-            // Trace request if needed
-            if let Some(t) = tracer.as_mut() {
-                t.trace(&copy_data_bytes)
+            // The timelines of V3 requests are only known now. An unauthorized one fails the
+            // request, rather than the connection with the other timelines on it.
+            if let Err(e) = self.check_timeline_permission(timeline_id) {
+                parent_span.in_scope(|| warn!("request denied: {e}"));
+                let response_msg = PagestreamBeMessage::Error(PagestreamErrorResponse {
+                    message: e.to_string(),
+                });
+                let response =
+                    serialize_pagestream_response(request_id, timeline_id, &response_msg);
+                pgb.write_message_noflush(&BeMessage::CopyData(&response))?;
+                self.flush_cancellable(pgb, &tenant.cancel).await?;
+                continue;
             }
 
-            let neon_fe_msg =
-                PagestreamFeMessage::parse(&mut copy_data_bytes.reader(), protocol_version)?;
-
-            // TODO: We could create a new per-request context here, with unique ID.
-            // Currently we use the same per-timeline context for all requests
-
-            let (response, span) = match neon_fe_msg {
-                PagestreamFeMessage::Exists(req) => {
-                    let span = tracing::info_span!("handle_get_rel_exists_request", rel = %req.rel, req_lsn = %req.request_lsn);
-                    (
-                        self.handle_get_rel_exists_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
-                        span,
-                    )
-                }
-                PagestreamFeMessage::Nblocks(req) => {
-                    let span = tracing::info_span!("handle_get_nblocks_request", rel = %req.rel, req_lsn = %req.request_lsn);
-                    (
-                        self.handle_get_nblocks_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
-                        span,
-                    )
-                }
-                PagestreamFeMessage::GetPage(req) => {
-                    // shard_id is filled in by the handler
-                    let span = tracing::info_span!("handle_get_page_at_lsn_request", rel = %req.rel, blkno = %req.blkno, req_lsn = %req.request_lsn);
-                    (
-                        self.handle_get_page_at_lsn_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
-                        span,
-                    )
-                }
-                PagestreamFeMessage::DbSize(req) => {
-                    let span = tracing::info_span!("handle_db_size_request", dbnode = %req.dbnode, req_lsn = %req.request_lsn);
-                    (
-                        self.handle_db_size_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
-                        span,
-                    )
-                }
-                PagestreamFeMessage::GetSlruSegment(req) => {
-                    let span = tracing::info_span!("handle_get_slru_segment_request", kind = %req.kind, segno = %req.segno, req_lsn = %req.request_lsn);
-                    (
-                        self.handle_get_slru_segment_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
-                        span,
-                    )
+            // Primaries request the latest LSN as Lsn::MAX.
+            let request_lsn = match &neon_fe_msg {
+                PagestreamFeMessage::Exists(req) => req.request_lsn,
+                PagestreamFeMessage::Nblocks(req) => req.request_lsn,
+                PagestreamFeMessage::GetPage(req) => req.request_lsn,
+                PagestreamFeMessage::DbSize(req) => req.request_lsn,
+                PagestreamFeMessage::GetSlruSegment(req) => req.request_lsn,
+                // Served at the latest LSN.
+                PagestreamFeMessage::GetPageAtLeast(_) => Lsn::MAX,
+            };
+            self.check_lsn_permission((request_lsn != Lsn::MAX).then_some(request_lsn))?;
+
+            // Each request gets its own context, with the deadline of the tenant.
+            // TODO: We could give it a unique ID.
+            let mut ctx = RequestContextBuilder::extend(&ctx);
+            if let Some(deadline) = page_request_deadline {
+                ctx = ctx.deadline(std::time::Instant::now() + deadline);
+            }
+            let ctx = ctx.build();
+
+            // Keep reading while the request is served, to notice when the client is gone.
+            let (response, span) = {
+                let mut request = pin!(async {
+                    match neon_fe_msg {
+                        PagestreamFeMessage::Exists(req) => {
+                            let span = tracing::info_span!(parent: &parent_span, "handle_get_rel_exists_request", rel = %req.rel, req_lsn = %req.request_lsn);
+                            (
+                                self.handle_get_rel_exists_request(
+                                    tenant_id,
+                                    timeline_id,
+                                    &req,
+                                    &ctx,
+                                )
+                                .instrument(span.clone())
+                                .await,
+                                span,
+                            )
+                        }
+                        PagestreamFeMessage::Nblocks(req) => {
+                            let span = tracing::info_span!(parent: &parent_span, "handle_get_nblocks_request", rel = %req.rel, req_lsn = %req.request_lsn);
+                            (
+                                self.handle_get_nblocks_request(tenant_id, timeline_id, &req, &ctx)
+                                    .instrument(span.clone())
+                                    .await,
+                                span,
+                            )
+                        }
+                        PagestreamFeMessage::GetPage(req) => {
+                            // shard_id is filled in by the handler
+                            let span = tracing::info_span!(parent: &parent_span, "handle_get_page_at_lsn_request", rel = %req.rel, blkno = %req.blkno, req_lsn = %req.request_lsn);
+                            (
+                                self.handle_get_page_at_lsn_request(
+                                    tenant_id,
+                                    timeline_id,
+                                    &req,
+                                    &ctx,
+                                )
+                                .instrument(span.clone())
+                                .await,
+                                span,
+                            )
+                        }
+                        PagestreamFeMessage::DbSize(req) => {
+                            let span = tracing::info_span!(parent: &parent_span, "handle_db_size_request", dbnode = %req.dbnode, req_lsn = %req.request_lsn);
+                            (
+                                self.handle_db_size_request(tenant_id, timeline_id, &req, &ctx)
+                                    .instrument(span.clone())
+                                    .await,
+                                span,
+                            )
+                        }
+                        PagestreamFeMessage::GetSlruSegment(req) => {
+                            let span = tracing::info_span!(parent: &parent_span, "handle_get_slru_segment_request", kind = %req.kind, segno = %req.segno, req_lsn = %req.request_lsn);
+                            (
+                                self.handle_get_slru_segment_request(
+                                    tenant_id,
+                                    timeline_id,
+                                    &req,
+                                    &ctx,
+                                )
+                                .instrument(span.clone())
+                                .await,
+                                span,
+                            )
+                        }
+                        PagestreamFeMessage::GetPageAtLeast(req) => {
+                            // shard_id is filled in by the handler
+                            let span = tracing::info_span!(parent: &parent_span, "handle_get_page_at_least_request", rel = %req.rel, blkno = %req.blkno, min_lsn = %req.min_lsn);
+                            (
+                                self.handle_get_page_at_least_request(
+                                    tenant_id,
+                                    timeline_id,
+                                    &req,
+                                    &ctx,
+                                )
+                                .instrument(span.clone())
+                                .await,
+                                span,
+                            )
+                        }
+                    }
+                });
+                loop {
+                    tokio::select! {
+                        biased;
+
+                        r = &mut request => break r,
+
+                        msg = pgb.read_message(),
+                            if disconnected.is_none() && queue.len() < multiplex::MAX_QUEUED =>
+                        {
+                            let parsed = msg.map_err(QueryError::from).and_then(|msg| {
+                                Self::parse_pagestream_request(
+                                    msg,
+                                    timeline_id,
+                                    protocol_version,
+                                    &mut tracer,
+                                )
+                            });
+                            match parsed {
+                                Ok(Some(queued)) => queue.push(queued.timeline_id, queued),
+                                Ok(None) => disconnected = Some(Ok(())),
+                                Err(e) => disconnected = Some(Err(e)),
+                            }
+                            if disconnected.is_some() {
+                                client_gone.cancel();
+                            }
+                        }
+                    }
                 }
             };
 
+            if let Some(result) = disconnected {
+                // Nobody to send the response to.
+                span.in_scope(|| debug!("client disconnected, dropping the response"));
+                return result;
+            }
+
             match response {
                 Err(PageStreamError::Shutdown) => {
                     // If we fail to fulfil a request during shutdown, which may be _because_ of
@@ -692,14 +895,24 @@ impl PageServerHandler {
                         // here includes cancellation which is not an error.
                         let full = utils::error::report_compact_sources(&e);
                         span.in_scope(|| {
-                            error!("error reading relation or page version: {full:#}")
+                            if matches!(
+                                e,
+                                PageStreamError::Read(PageReconstructError::RequestCancelled)
+                            ) {
+                                // Not a bug: the read took longer than the deadline of the tenant.
+                                info!("request given up: {full:#}")
+                            } else {
+                                error!("error reading relation or page version: {full:#}")
+                            }
                         });
                         PagestreamBeMessage::Error(PagestreamErrorResponse {
                             message: e.to_string(),
                         })
                     });
 
-                    pgb.write_message_noflush(&BeMessage::CopyData(&response_msg.serialize()))?;
+                    let response =
+                        serialize_pagestream_response(request_id, timeline_id, &response_msg);
+                    pgb.write_message_noflush(&BeMessage::CopyData(&response))?;
                     self.flush_cancellable(pgb, &tenant.cancel).await?;
                 }
             }
@@ -707,6 +920,53 @@ impl PageServerHandler {
         Ok(())
     }
 
+    /// Parse a message of a pagestream connection, or return `None` when the client is done.
+    fn parse_pagestream_request(
+        msg: Option<FeMessage>,
+        timeline_id: Option<TimelineId>,
+        protocol_version: PagestreamProtocolVersion,
+        tracer: &mut Option<Tracer>,
+    ) -> Result<Option<PagestreamRequest>, QueryError> {
+        let copy_data_bytes = match msg {
+            Some(FeMessage::CopyData(bytes)) => bytes,
+            Some(FeMessage::Terminate) => return Ok(None),
+            Some(m) => {
+                return Err(QueryError::Other(anyhow::anyhow!(
+                    "unexpected message: {m:?} during COPY"
+                )));
+            }
+            None => return Ok(None),
+        };
+
+        trace!("query: {copy_data_bytes:?}");
+
+        // Trace request if needed
+        if let Some(t) = tracer.as_mut() {
+            t.trace(&copy_data_bytes)
+        }
+
+        let mut reader = copy_data_bytes.reader();
+        let (timeline_id, request_id) = match (protocol_version, timeline_id) {
+            (PagestreamProtocolVersion::V3, _) => {
+                let header = PagestreamV3Header::parse(&mut reader)?;
+                (header.timeline_id, Some(header.request_id))
+            }
+            (_, Some(timeline_id)) => (timeline_id, None),
+            (_, None) => {
+                return Err(QueryError::Other(anyhow::anyhow!(
+                    "pagestream connection without a timeline"
+                )))
+            }
+        };
+        let msg = PagestreamFeMessage::parse(&mut reader, protocol_version)?;
+
+        Ok(Some(PagestreamRequest {
+            timeline_id,
+            request_id,
+            msg,
+        }))
+    }
+
     #[allow(clippy::too_many_arguments)]
     #[instrument(skip_all, fields(%base_lsn, end_lsn=%_end_lsn, %pg_version))]
     async fn handle_import_basebackup<IO>(
@@ -830,6 +1090,45 @@ impl PageServerHandler {
         Ok(())
     }
 
+    #[instrument(skip_all, fields(shard_id, %start_lsn, %end_lsn))]
+    async fn handle_import_wal_archive(
+        &self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        start_lsn: Lsn,
+        end_lsn: Lsn,
+        archive: WalArchive,
+        ctx: RequestContext,
+    ) -> Result<(), QueryError> {
+        let timeline = self
+            .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+            .await?;
+        let last_record_lsn = timeline.get_last_record_lsn();
+        if last_record_lsn != start_lsn {
+            return Err(QueryError::Other(
+                anyhow::anyhow!("Cannot import WAL from Lsn {start_lsn} because timeline does not start from the same lsn: {last_record_lsn}"))
+            );
+        }
+
+        info!("importing wal from archive");
+        import_wal_from_archive(&timeline, &archive, start_lsn, end_lsn, &ctx).await?;
+        info!("wal import complete");
+
+        if timeline.get_last_record_lsn() < end_lsn {
+            return Err(QueryError::Other(anyhow::anyhow!(
+                "WAL archive ends at {}, before {end_lsn}",
+                timeline.get_last_record_lsn()
+            )));
+        }
+
+        // Like for `import wal`, persisting the data is enough.
+        info!("flushing layers");
+        timeline.freeze_and_flush().await?;
+
+        info!("done");
+        Ok(())
+    }
+
     /// Helper function to handle the LSN from client request.
     ///
     /// Each GetPage (and Exists and Nblocks) request includes information about
@@ -852,11 +1151,15 @@ impl PageServerHandler {
     /// not_modified_hint such that there are in fact later page versions, the
     /// behavior is undefined: the pageserver may return any of the page versions
     /// or an error.
+    ///
+    /// `key` is the key that the request reads, if it reads a single key: the history of the
+    /// keys of pinned relations is readable below the GC cutoff.
     async fn wait_or_get_last_lsn(
         timeline: &Timeline,
         request_lsn: Lsn,
         not_modified_since: Lsn,
         latest_gc_cutoff_lsn: &RcuReadGuard<Lsn>,
+        key: Option<Key>,
         ctx: &RequestContext,
     ) -> Result<Lsn, PageStreamError> {
         let last_record_lsn = timeline.get_last_record_lsn();
@@ -872,7 +1175,11 @@ impl PageServerHandler {
             ));
         }
 
-        if request_lsn < **latest_gc_cutoff_lsn {
+        if request_lsn < **latest_gc_cutoff_lsn
+            && key.map_or(true, |key| {
+                request_lsn < timeline.get_gc_cutoff_for_key(&key, **latest_gc_cutoff_lsn)
+            })
+        {
             // Check explicitly for INVALID just to get a less scary error message if the
             // request is obviously bogus
             return Err(if request_lsn == Lsn::INVALID {
@@ -887,6 +1194,7 @@ impl PageServerHandler {
 
         // Wait for WAL up to 'not_modified_since' to arrive, if necessary
         if not_modified_since > last_record_lsn {
+            offload::reactivate_for_wait_lsn(timeline, not_modified_since).await;
             timeline
                 .wait_lsn(
                     not_modified_since,
@@ -928,6 +1236,7 @@ impl PageServerHandler {
             req.request_lsn,
             req.not_modified_since,
             &latest_gc_cutoff_lsn,
+            Some(rel_dir_to_key(req.rel.spcnode, req.rel.dbnode)),
             ctx,
         )
         .await?;
@@ -961,6 +1270,7 @@ impl PageServerHandler {
             req.request_lsn,
             req.not_modified_since,
             &latest_gc_cutoff_lsn,
+            Some(rel_size_to_key(req.rel)),
             ctx,
         )
         .await?;
@@ -994,6 +1304,7 @@ impl PageServerHandler {
             req.request_lsn,
             req.not_modified_since,
             &latest_gc_cutoff_lsn,
+            None,
             ctx,
         )
         .await?;
@@ -1012,15 +1323,20 @@ impl PageServerHandler {
     /// looks up such a Timeline synchronously and without touching any global state.
     fn get_cached_timeline_for_page(
         &mut self,
-        req: &PagestreamGetPageRequest,
+        timeline_id: TimelineId,
+        rel: RelTag,
+        blkno: BlockNumber,
     ) -> Result<&Arc<Timeline>, Key> {
-        let key = if let Some((first_idx, first_timeline)) = self.shard_timelines.iter().next() {
+        let shard_timelines = self.shard_timelines.get(&timeline_id);
+        let key = if let Some((first_idx, first_timeline)) =
+            shard_timelines.and_then(|shards| shards.iter().next())
+        {
             // Fastest path: single sharded case
             if first_idx.shard_count.count() == 1 {
                 return Ok(&first_timeline.timeline);
             }
 
-            let key = rel_block_to_key(req.rel, req.blkno);
+            let key = rel_block_to_key(rel, blkno);
             let shard_num = first_timeline
                 .timeline
                 .get_shard_identity()
@@ -1038,13 +1354,13 @@ impl PageServerHandler {
             };
 
             // Fast-ish path: timeline is in the connection handler's local cache
-            if let Some(found) = self.shard_timelines.get(&shard_index) {
+            if let Some(found) = shard_timelines.and_then(|shards| shards.get(&shard_index)) {
                 return Ok(&found.timeline);
             }
 
             key
         } else {
-            rel_block_to_key(req.rel, req.blkno)
+            rel_block_to_key(rel, blkno)
         };
 
         Err(key)
@@ -1054,9 +1370,8 @@ impl PageServerHandler {
     /// use in future requests without having to traverse [`crate::tenant::mgr::TenantManager`]
     /// again.
     ///
-    /// Note that all the Timelines in this cache are for the same timeline_id: they're differ
-    /// in which shard they belong to.  When we serve a getpage@lsn request, we choose a shard
-    /// based on key.
+    /// The cache is per timeline_id: the Timelines of a timeline_id differ in which shard they
+    /// belong to.  When we serve a getpage@lsn request, we choose a shard based on key.
     ///
     /// The typical size of this cache is 1, as we generally create shards to distribute work
     /// across pageservers, so don't tend to have multiple shards for the same tenant on the
@@ -1070,9 +1385,12 @@ impl PageServerHandler {
             .enter()
             .map_err(|_| GetActiveTimelineError::Tenant(GetActiveTenantError::Cancelled))?;
 
+        let timeline_id = timeline.timeline_id;
         let shard_index = timeline.tenant_shard_id.to_index();
         let entry = self
             .shard_timelines
+            .entry(timeline_id)
+            .or_default()
             .entry(shard_index)
             .or_insert(HandlerTimeline {
                 timeline,
@@ -1107,14 +1425,18 @@ impl PageServerHandler {
         // This is a borrow-checker workaround: we can't return from inside of the  `if let Some` because
         // that would be an immutable-borrow-self return, whereas later in the function we will use a mutable
         // ref to salf.  So instead, we first build a bool, and then return while not borrowing self.
-        let have_cached = if let Some((idx, _tl)) = self.shard_timelines.iter().next() {
+        let have_cached = if let Some((idx, _tl)) = self
+            .shard_timelines
+            .get(&timeline_id)
+            .and_then(|shards| shards.iter().next())
+        {
             idx.shard_number == ShardNumber(0)
         } else {
             false
         };
 
         if have_cached {
-            let entry = self.shard_timelines.iter().next().unwrap();
+            let entry = self.shard_timelines[&timeline_id].iter().next().unwrap();
             Ok(&entry.1.timeline)
         } else {
             let timeline = self
@@ -1132,7 +1454,52 @@ impl PageServerHandler {
         req: &PagestreamGetPageRequest,
         ctx: &RequestContext,
     ) -> Result<PagestreamBeMessage, PageStreamError> {
-        let timeline = match self.get_cached_timeline_for_page(req) {
+        let lsn = GetPageLsn::Exact {
+            request_lsn: req.request_lsn,
+            not_modified_since: req.not_modified_since,
+        };
+        let (_, page) = self
+            .get_page(tenant_id, timeline_id, req.rel, req.blkno, lsn, ctx)
+            .await?;
+
+        Ok(PagestreamBeMessage::GetPage(PagestreamGetPageResponse {
+            page,
+        }))
+    }
+
+    #[instrument(skip_all, fields(shard_id))]
+    async fn handle_get_page_at_least_request(
+        &mut self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        req: &PagestreamGetPageAtLeastRequest,
+        ctx: &RequestContext,
+    ) -> Result<PagestreamBeMessage, PageStreamError> {
+        let lsn = GetPageLsn::AtLeast {
+            min_lsn: req.min_lsn,
+            wait_budget: Duration::from_millis(req.wait_budget_ms as u64)
+                .min(self.conf.wait_lsn_timeout),
+        };
+        let (lsn, page) = self
+            .get_page(tenant_id, timeline_id, req.rel, req.blkno, lsn, ctx)
+            .await?;
+
+        Ok(PagestreamBeMessage::GetPageAtLeast(
+            PagestreamGetPageAtLeastResponse { lsn, page },
+        ))
+    }
+
+    /// Read a page for a GetPage request, and return the LSN at which it was read.
+    async fn get_page(
+        &mut self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        rel: RelTag,
+        blkno: BlockNumber,
+        lsn: GetPageLsn,
+        ctx: &RequestContext,
+    ) -> Result<(Lsn, Bytes), PageStreamError> {
+        let timeline = match self.get_cached_timeline_for_page(timeline_id, rel, blkno) {
             Ok(tl) => {
                 set_tracing_field_shard_id(tl);
                 tl
@@ -1164,24 +1531,64 @@ impl PageServerHandler {
         let _timer = timeline
             .query_metrics
             .start_timer(metrics::SmgrQueryType::GetPageAtLsn, ctx);
+        timeline.tagged_metrics.load().inc_getpage_requests();
+        let started_at = std::time::Instant::now();
 
         let latest_gc_cutoff_lsn = timeline.get_latest_gc_cutoff_lsn();
-        let lsn = Self::wait_or_get_last_lsn(
-            timeline,
-            req.request_lsn,
-            req.not_modified_since,
-            &latest_gc_cutoff_lsn,
-            ctx,
-        )
-        .await?;
+        let lsn = match lsn {
+            GetPageLsn::Exact {
+                request_lsn,
+                not_modified_since,
+            } => {
+                Self::wait_or_get_last_lsn(
+                    timeline,
+                    request_lsn,
+                    not_modified_since,
+                    &latest_gc_cutoff_lsn,
+                    Some(rel_block_to_key(rel, blkno)),
+                    ctx,
+                )
+                .await?
+            }
+            GetPageLsn::AtLeast {
+                min_lsn,
+                wait_budget,
+            } => {
+                if min_lsn > timeline.get_last_record_lsn() {
+                    offload::reactivate_for_wait_lsn(timeline, min_lsn).await;
+                    timeline
+                        .wait_lsn_timeout(
+                            min_lsn,
+                            crate::tenant::timeline::WaitLsnWaiter::PageService,
+                            wait_budget,
+                            ctx,
+                        )
+                        .await?;
+                }
+                // Like the requests of primaries, which are for the latest LSN.
+                timeline.get_last_record_lsn()
+            }
+        };
 
         let page = timeline
-            .get_rel_page_at_lsn(req.rel, req.blkno, Version::Lsn(lsn), ctx)
+            .get_rel_page_at_lsn(rel, blkno, Version::Lsn(lsn), ctx)
             .await?;
-
-        Ok(PagestreamBeMessage::GetPage(PagestreamGetPageResponse {
-            page,
-        }))
+        timeline
+            .access_log
+            .record_read(&rel_block_to_key(rel, blkno));
+
+        let read_path = ctx.read_path.take();
+        let met_slo = timeline
+            .getpage_latency
+            .observe(read_path, started_at.elapsed());
+        let timeline = Arc::clone(timeline);
+        self.prefetch
+            .entry(timeline.timeline_id)
+            .or_insert_with(SequentialPrefetch::new)
+            .on_get_page(&timeline, rel, blkno, lsn, ctx);
+        self.getpage_stats.observe(read_path, met_slo);
+
+        Ok((lsn, page))
     }
 
     #[instrument(skip_all, fields(shard_id))]
@@ -1204,6 +1611,7 @@ impl PageServerHandler {
             req.request_lsn,
             req.not_modified_since,
             &latest_gc_cutoff_lsn,
+            None,
             ctx,
         )
         .await?;
@@ -1232,6 +1640,7 @@ impl PageServerHandler {
         prev_lsn: Option<Lsn>,
         full_backup: bool,
         gzip: bool,
+        incremental: Option<&BasebackupManifest>,
         ctx: &RequestContext,
     ) -> Result<(), QueryError>
     where
@@ -1254,6 +1663,7 @@ impl PageServerHandler {
         if let Some(lsn) = lsn {
             // Backup was requested at a particular LSN. Wait for it to arrive.
             info!("waiting for {}", lsn);
+            offload::reactivate_for_wait_lsn(&timeline, lsn).await;
             timeline
                 .wait_lsn(
                     lsn,
@@ -1283,6 +1693,7 @@ impl PageServerHandler {
                 lsn,
                 prev_lsn,
                 full_backup,
+                None,
                 ctx,
             )
             .await
@@ -1305,6 +1716,7 @@ impl PageServerHandler {
                     lsn,
                     prev_lsn,
                     full_backup,
+                    incremental,
                     ctx,
                 )
                 .await
@@ -1321,6 +1733,7 @@ impl PageServerHandler {
                     lsn,
                     prev_lsn,
                     full_backup,
+                    incremental,
                     ctx,
                 )
                 .await
@@ -1349,6 +1762,21 @@ impl PageServerHandler {
     // when accessing management api supply None as an argument
     // when using to authorize tenant pass corresponding tenant id
     fn check_permission(&self, tenant_id: Option<TenantId>) -> Result<(), QueryError> {
+        if let Some(certificate_tenants) = &self.certificate_tenants {
+            match tenant_id {
+                Some(tenant_id) if certificate_tenants.contains(&tenant_id) => {}
+                Some(tenant_id) => {
+                    return Err(QueryError::Unauthorized(
+                        format!("client certificate is not valid for tenant {tenant_id}").into(),
+                    ))
+                }
+                None => {
+                    return Err(QueryError::Unauthorized(
+                        "client certificate only grants access to tenants".into(),
+                    ))
+                }
+            }
+        }
         if self.auth.is_none() {
             // auth is set to Trust, nothing to check so just return ok
             return Ok(());
@@ -1363,6 +1791,26 @@ impl PageServerHandler {
         check_permission(claims, tenant_id).map_err(|e| QueryError::Unauthorized(e.0))
     }
 
+    /// Check the timeline restriction of the token, after [`Self::check_permission`].
+    fn check_timeline_permission(&self, timeline_id: TimelineId) -> Result<(), QueryError> {
+        match &self.claims {
+            Some(claims) => check_timeline_permission(claims, timeline_id)
+                .map_err(|e| QueryError::Unauthorized(e.0)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the LSN restriction of the token for reading at `lsn`, `None` meaning the
+    /// latest LSN, after [`Self::check_permission`].
+    fn check_lsn_permission(&self, lsn: Option<Lsn>) -> Result<(), QueryError> {
+        match &self.claims {
+            Some(claims) => {
+                check_lsn_permission(claims, lsn).map_err(|e| QueryError::Unauthorized(e.0))
+            }
+            None => Ok(()),
+        }
+    }
+
     /// Shorthand for getting a reference to a Timeline of an Active tenant.
     async fn get_active_tenant_timeline(
         &self,
@@ -1420,9 +1868,17 @@ where
 
     fn startup(
         &mut self,
-        _pgb: &mut PostgresBackend<IO>,
+        pgb: &mut PostgresBackend<IO>,
         _sm: &FeStartupPacket,
     ) -> Result<(), QueryError> {
+        // The certificate chain was verified during the TLS handshake; its first certificate
+        // is the client's own.
+        if let Some(cert) = pgb.peer_certificates().and_then(|certs| certs.first()) {
+            let tenants = tls::tenants_of_certificate(cert)
+                .map_err(|e| QueryError::Unauthorized(format!("{e:#}").into()))?;
+            debug!("client certificate grants access to tenants {tenants:?}");
+            self.certificate_tenants = Some(tenants);
+        }
         Ok(())
     }
 
@@ -1439,7 +1895,53 @@ where
 
         let ctx = self.connection_ctx.attached_child();
         debug!("process query {query_string:?}");
-        if query_string.starts_with("pagestream_v2 ") {
+        if query_string.starts_with("pagestream_negotiate ") {
+            let (_, params_raw) = query_string.split_at("pagestream_negotiate ".len());
+            let params = params_raw.split(' ').collect::<Vec<_>>();
+            let negotiation = negotiation::negotiate(&params)
+                .map_err(|e| QueryError::Other(anyhow::anyhow!(e)))?;
+            debug!(
+                version = negotiation.version.number(),
+                features = ?negotiation.features,
+                "negotiated pagestream protocol"
+            );
+
+            let features = negotiation
+                .features
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::int8_col(b"version"),
+                RowDescriptor::text_col(b"command"),
+                RowDescriptor::text_col(b"features"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(negotiation.version.number().to_string().as_bytes()),
+                Some(negotiation.version.command().as_bytes()),
+                Some(features.as_bytes()),
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("pagestream_v3 ") {
+            let (_, params_raw) = query_string.split_at("pagestream_v3 ".len());
+            let params = params_raw.split(' ').collect::<Vec<_>>();
+            if params.len() != 1 {
+                return Err(QueryError::Other(anyhow::anyhow!(
+                    "invalid param number for pagestream command"
+                )));
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+
+            tracing::Span::current().record("tenant_id", field::display(tenant_id));
+
+            // The timelines are checked per request.
+            self.check_permission(Some(tenant_id))?;
+
+            self.handle_pagerequests(pgb, tenant_id, None, PagestreamProtocolVersion::V3, ctx)
+                .await?;
+        } else if query_string.starts_with("pagestream_v2 ") {
             let (_, params_raw) = query_string.split_at("pagestream_v2 ".len());
             let params = params_raw.split(' ').collect::<Vec<_>>();
             if params.len() != 2 {
@@ -1457,11 +1959,12 @@ where
                 .record("timeline_id", field::display(timeline_id));
 
             self.check_permission(Some(tenant_id))?;
+            self.check_timeline_permission(timeline_id)?;
 
             self.handle_pagerequests(
                 pgb,
                 tenant_id,
-                timeline_id,
+                Some(timeline_id),
                 PagestreamProtocolVersion::V2,
                 ctx,
             )
@@ -1484,11 +1987,12 @@ where
                 .record("timeline_id", field::display(timeline_id));
 
             self.check_permission(Some(tenant_id))?;
+            self.check_timeline_permission(timeline_id)?;
 
             self.handle_pagerequests(
                 pgb,
                 tenant_id,
-                timeline_id,
+                Some(timeline_id),
                 PagestreamProtocolVersion::V1,
                 ctx,
             )
@@ -1513,6 +2017,7 @@ where
                 .record("timeline_id", field::display(timeline_id));
 
             self.check_permission(Some(tenant_id))?;
+            self.check_timeline_permission(timeline_id)?;
 
             let lsn = if params.len() >= 3 {
                 Some(
@@ -1522,19 +2027,30 @@ where
             } else {
                 None
             };
-
-            let gzip = if params.len() >= 4 {
-                if params[3] == "--gzip" {
-                    true
-                } else {
-                    return Err(QueryError::Other(anyhow::anyhow!(
-                        "Parameter in position 3 unknown {}",
-                        params[3],
-                    )));
+            self.check_lsn_permission(lsn)?;
+
+            let mut gzip = false;
+            let mut incremental = None;
+            let mut flags = params.iter().enumerate().skip(3);
+            while let Some((i, param)) = flags.next() {
+                match *param {
+                    "--gzip" => gzip = true,
+                    "--incremental" => {
+                        let (_, manifest) = flags.next().ok_or_else(|| {
+                            QueryError::Other(anyhow::anyhow!("--incremental requires a manifest"))
+                        })?;
+                        incremental = Some(
+                            BasebackupManifest::from_str(manifest)
+                                .context("Failed to parse basebackup manifest")?,
+                        );
+                    }
+                    _ => {
+                        return Err(QueryError::Other(anyhow::anyhow!(
+                            "Parameter in position {i} unknown {param}",
+                        )));
+                    }
                 }
-            } else {
-                false
-            };
+            }
 
             let metric_recording = metrics::BASEBACKUP_QUERY_TIME.start_recording(&ctx);
             let res = async {
@@ -1546,6 +2062,7 @@ where
                     None,
                     false,
                     gzip,
+                    incremental.as_ref(),
                     &ctx,
                 )
                 .await?;
@@ -1577,6 +2094,7 @@ where
                 .record("timeline_id", field::display(timeline_id));
 
             self.check_permission(Some(tenant_id))?;
+            self.check_timeline_permission(timeline_id)?;
             async {
                 let timeline = self
                     .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
@@ -1640,6 +2158,8 @@ where
             };
 
             self.check_permission(Some(tenant_id))?;
+            self.check_timeline_permission(timeline_id)?;
+            self.check_lsn_permission(lsn)?;
 
             // Check that the timeline exists
             self.handle_basebackup_request(
@@ -1650,6 +2170,7 @@ where
                 prev_lsn,
                 true,
                 false,
+                None,
                 &ctx,
             )
             .await?;
@@ -1689,6 +2210,9 @@ where
                 .record("timeline_id", field::display(timeline_id));
 
             self.check_permission(Some(tenant_id))?;
+            self.check_timeline_permission(timeline_id)?;
+            // Imports write at the end of the timeline: read-only tokens may not.
+            self.check_lsn_permission(None)?;
 
             match self
                 .handle_import_basebackup(
@@ -1711,6 +2235,52 @@ where
                     ))?
                 }
             };
+        } else if query_string.starts_with("import wal_archive ") {
+            // Import the WAL following a basebackup from a WAL archive, fetching the
+            // segments with the `wal_archive_restore_command` of the pageserver config.
+            //
+            // Example: import wal_archive $TENANT $TIMELINE $START_LSN $END_LSN
+            let (_, params_raw) = query_string.split_at("import wal_archive ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 4 {
+                return Err(QueryError::Other(anyhow::anyhow!(
+                    "invalid param number for import wal_archive command"
+                )));
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))?;
+            let start_lsn = Lsn::from_str(params[2])
+                .with_context(|| format!("Failed to parse Lsn from {}", params[2]))?;
+            let end_lsn = Lsn::from_str(params[3])
+                .with_context(|| format!("Failed to parse Lsn from {}", params[3]))?;
+            let Some(restore_command) = &self.conf.wal_archive_restore_command else {
+                return Err(QueryError::Other(anyhow::anyhow!(
+                    "the pageserver has no wal_archive_restore_command"
+                )));
+            };
+            let archive = WalArchive::new(restore_command.clone());
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            match self
+                .handle_import_wal_archive(tenant_id, timeline_id, start_lsn, end_lsn, archive, ctx)
+                .await
+            {
+                Ok(()) => pgb.write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?,
+                Err(e) => {
+                    error!("error importing WAL archive between {start_lsn} and {end_lsn}: {e:?}");
+                    pgb.write_message_noflush(&BeMessage::ErrorResponse(
+                        &e.to_string(),
+                        Some(e.pg_error_code()),
+                    ))?
+                }
+            };
         } else if query_string.starts_with("import wal ") {
             // Import the `pg_wal` section of a basebackup.
             //
@@ -1737,6 +2307,9 @@ where
                 .record("timeline_id", field::display(timeline_id));
 
             self.check_permission(Some(tenant_id))?;
+            self.check_timeline_permission(timeline_id)?;
+            // Imports write at the end of the timeline: read-only tokens may not.
+            self.check_lsn_permission(None)?;
 
             match self
                 .handle_import_wal(pgb, tenant_id, timeline_id, start_lsn, end_lsn, ctx)
@@ -1857,6 +2430,26 @@ impl From<GetActiveTimelineError> for QueryError {
     }
 }
 
+/// The response to a pagestream request, prefixed with the header of the request for V3.
+fn serialize_pagestream_response(
+    request_id: Option<u64>,
+    timeline_id: TimelineId,
+    response: &PagestreamBeMessage,
+) -> Bytes {
+    let body = response.serialize();
+    let Some(request_id) = request_id else {
+        return body;
+    };
+    let mut bytes = BytesMut::with_capacity(PagestreamV3Header::LEN + body.len());
+    PagestreamV3Header {
+        request_id,
+        timeline_id,
+    }
+    .serialize(&mut bytes);
+    bytes.put(body);
+    bytes.freeze()
+}
+
 fn set_tracing_field_shard_id(timeline: &Timeline) {
     debug_assert_current_span_has_tenant_and_timeline_id_no_shard_id();
     tracing::Span::current().record(
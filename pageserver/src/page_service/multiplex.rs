@@ -0,0 +1,115 @@
+//! Fair scheduling of the requests of the timelines multiplexed on one connection.
+//!
+//! With the V3 pagestream protocol, a compute addresses all the timelines of a tenant that it
+//! reads from over a single connection, see [`pageserver_api::models::PagestreamProtocolVersion`].
+//! The connection reads ahead up to [`MAX_QUEUED`] requests, and serves them from a
+//! [`FairQueue`]: one request of each timeline in turn, in the order they arrived for each
+//! timeline. A timeline with a long burst of requests, like a sequential scan, doesn't hold back
+//! the requests of the others for longer than one request each.
+
+use std::collections::{HashMap, VecDeque};
+
+use utils::id::TimelineId;
+
+/// How many requests a connection reads ahead of the one it serves.
+pub(super) const MAX_QUEUED: usize = 64;
+
+/// Round-robin queue of requests across timelines.
+pub(super) struct FairQueue<T> {
+    queues: HashMap<TimelineId, VecDeque<T>>,
+    /// The timelines with queued requests, in the order of their turns.
+    turns: VecDeque<TimelineId>,
+    len: usize,
+}
+
+impl<T> FairQueue<T> {
+    pub(super) fn new() -> Self {
+        Self {
+            queues: HashMap::new(),
+            turns: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(super) fn push(&mut self, timeline_id: TimelineId, request: T) {
+        let queue = self.queues.entry(timeline_id).or_default();
+        if queue.is_empty() {
+            self.turns.push_back(timeline_id);
+        }
+        queue.push_back(request);
+        self.len += 1;
+    }
+
+    /// The next request of the timeline whose turn it is.
+    pub(super) fn pop(&mut self) -> Option<(TimelineId, T)> {
+        let timeline_id = self.turns.pop_front()?;
+        let queue = self
+            .queues
+            .get_mut(&timeline_id)
+            .expect("timelines with a turn have a queue");
+        let request = queue
+            .pop_front()
+            .expect("timelines with a turn have requests");
+        if queue.is_empty() {
+            self.queues.remove(&timeline_id);
+        } else {
+            self.turns.push_back(timeline_id);
+        }
+        self.len -= 1;
+        Some((timeline_id, request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timelines_take_turns() {
+        let (a, b, c) = (
+            TimelineId::generate(),
+            TimelineId::generate(),
+            TimelineId::generate(),
+        );
+        let mut queue = FairQueue::new();
+        for i in 0..4 {
+            queue.push(a, i);
+        }
+        queue.push(b, 10);
+        queue.push(b, 11);
+        assert_eq!(queue.len(), 6);
+
+        assert_eq!(queue.pop(), Some((a, 0)));
+        assert_eq!(queue.pop(), Some((b, 10)));
+        // A timeline that arrives later waits for the turns already taken.
+        queue.push(c, 20);
+        assert_eq!(queue.pop(), Some((a, 1)));
+        assert_eq!(queue.pop(), Some((b, 11)));
+        assert_eq!(queue.pop(), Some((c, 20)));
+        assert_eq!(queue.pop(), Some((a, 2)));
+        assert_eq!(queue.pop(), Some((a, 3)));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn single_timeline_is_fifo() {
+        let a = TimelineId::generate();
+        let mut queue = FairQueue::new();
+        queue.push(a, 0);
+        assert_eq!(queue.pop(), Some((a, 0)));
+        queue.push(a, 1);
+        queue.push(a, 2);
+        assert_eq!(queue.pop(), Some((a, 1)));
+        assert_eq!(queue.pop(), Some((a, 2)));
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+//! Negotiation of the pagestream protocol version and features of a connection.
+//!
+//! Before starting a pagestream connection, a client that knows about several protocol versions
+//! sends `pagestream_negotiate <min_version> <max_version> [<feature>,...]`. The response names
+//! the newest version in the range that this pageserver supports, the command that starts a
+//! connection with it, and the requested features that the pageserver supports with it, see
+//! [`PagestreamNegotiation`]. The client then starts the connection with that command, and only
+//! sends the messages of the accepted features.
+//!
+//! Old computes never negotiate, and keep using the commands of the versions they know. New
+//! computes fall back to a version they know when talking to a pageserver that predates the
+//! negotiation, which fails the unknown command. This lets new versions and message types be
+//! rolled out on either side first.
+//!
+//! [`FEATURES`] is the compatibility matrix: the features that this pageserver supports, and
+//! the oldest version that each needs. It is exposed by `GET /v1/page_service/protocol`.
+
+use pageserver_api::models::{
+    PagestreamFeature, PagestreamNegotiation, PagestreamProtocolInfo, PagestreamProtocolVersion,
+};
+
+/// The features that this pageserver supports, with the oldest protocol version that each
+/// needs. Features are added here once their messages are served.
+const FEATURES: &[(PagestreamFeature, PagestreamProtocolVersion)] =
+    &[(PagestreamFeature::LsnAtLeast, PagestreamProtocolVersion::V2)];
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub(crate) enum NegotiationError {
+    #[error("invalid pagestream_negotiate command: {0}")]
+    Invalid(String),
+    #[error(
+        "no common pagestream protocol version: the client supports {min}..={max}, the pageserver {}..={}",
+        PagestreamProtocolVersion::ALL[0].number(),
+        PagestreamProtocolVersion::ALL[PagestreamProtocolVersion::ALL.len() - 1].number()
+    )]
+    NoCommonVersion { min: u32, max: u32 },
+}
+
+/// Negotiate the parameters of `pagestream_negotiate`.
+pub(crate) fn negotiate(params: &[&str]) -> Result<PagestreamNegotiation, NegotiationError> {
+    negotiate_with(FEATURES, params)
+}
+
+fn negotiate_with(
+    supported: &[(PagestreamFeature, PagestreamProtocolVersion)],
+    params: &[&str],
+) -> Result<PagestreamNegotiation, NegotiationError> {
+    let (min, max, requested) = match params {
+        [min, max] => (min, max, ""),
+        [min, max, requested] => (min, max, *requested),
+        _ => {
+            return Err(NegotiationError::Invalid(format!(
+                "expected 2 or 3 parameters, got {}",
+                params.len()
+            )))
+        }
+    };
+    let parse_version = |v: &str| {
+        v.parse::<u32>()
+            .map_err(|e| NegotiationError::Invalid(format!("version {v:?}: {e}")))
+    };
+    let (min, max) = (parse_version(min)?, parse_version(max)?);
+
+    let version = PagestreamProtocolVersion::ALL
+        .into_iter()
+        .rev()
+        .find(|v| (min..=max).contains(&v.number()))
+        .ok_or(NegotiationError::NoCommonVersion { min, max })?;
+
+    let mut features = Vec::new();
+    for name in requested.split(',').filter(|name| !name.is_empty()) {
+        // Features that this pageserver doesn't know are for a newer one.
+        let Ok(feature) = name.parse::<PagestreamFeature>() else {
+            continue;
+        };
+        let accepted = supported
+            .iter()
+            .any(|(f, min_version)| *f == feature && version >= *min_version);
+        if accepted && !features.contains(&feature) {
+            features.push(feature);
+        }
+    }
+
+    Ok(PagestreamNegotiation { version, features })
+}
+
+/// The compatibility matrix, for diagnostics.
+pub(crate) fn protocol_info() -> Vec<PagestreamProtocolInfo> {
+    protocol_info_with(FEATURES)
+}
+
+fn protocol_info_with(
+    supported: &[(PagestreamFeature, PagestreamProtocolVersion)],
+) -> Vec<PagestreamProtocolInfo> {
+    PagestreamProtocolVersion::ALL
+        .into_iter()
+        .map(|version| PagestreamProtocolInfo {
+            version: version.number(),
+            command: version.command().to_owned(),
+            features: supported
+                .iter()
+                .filter(|(_, min_version)| version >= *min_version)
+                .map(|(feature, _)| *feature)
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPPORTED: &[(PagestreamFeature, PagestreamProtocolVersion)] = &[(
+        PagestreamFeature::VectoredGet,
+        PagestreamProtocolVersion::V3,
+    )];
+
+    #[test]
+    fn newest_common_version() {
+        let negotiation = negotiate_with(SUPPORTED, &["1", "2"]).unwrap();
+        assert_eq!(negotiation.version, PagestreamProtocolVersion::V2);
+        assert!(negotiation.features.is_empty());
+
+        // Versions newer than the pageserver's are ignored.
+        let negotiation = negotiate_with(SUPPORTED, &["2", "7"]).unwrap();
+        assert_eq!(negotiation.version, PagestreamProtocolVersion::V3);
+
+        assert_eq!(
+            negotiate_with(SUPPORTED, &["4", "7"]),
+            Err(NegotiationError::NoCommonVersion { min: 4, max: 7 })
+        );
+        assert!(matches!(
+            negotiate_with(SUPPORTED, &["2"]),
+            Err(NegotiationError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn features() {
+        let negotiation = negotiate_with(
+            SUPPORTED,
+            &["1", "3", "vectored_get,compression,teleportation"],
+        )
+        .unwrap();
+        assert_eq!(negotiation.features, vec![PagestreamFeature::VectoredGet]);
+
+        // A feature is only accepted with the versions that support it.
+        let negotiation = negotiate_with(SUPPORTED, &["1", "2", "vectored_get"]).unwrap();
+        assert!(negotiation.features.is_empty());
+
+        let info = protocol_info_with(SUPPORTED);
+        assert_eq!(info.len(), 3);
+        assert_eq!(info[1].command, "pagestream_v2");
+        assert!(info[1].features.is_empty());
+        assert_eq!(info[2].features, vec![PagestreamFeature::VectoredGet]);
+    }
+}
@@ -0,0 +1,319 @@
+//! Read-ahead for the GetPage requests of sequential scans.
+//!
+//! Sequential scans, VACUUM and index builds read relations block after block, and the
+//! compute only prefetches the few blocks that postgres asks for. When a connection requests
+//! [`TRIGGER_RUN`] consecutive blocks of a relation, [`SequentialPrefetch`] starts reading the
+//! next blocks in the background, at the LSN of the request. By the time they are requested,
+//! their layers are downloaded and in the page cache, and the WAL redo results are in the
+//! materialized page cache.
+//!
+//! The read-ahead window starts at [`MIN_WINDOW`] blocks. Whenever the scan gets within half a
+//! window of the end of the blocks read ahead, the window doubles, up to [`MAX_WINDOW`]. Any
+//! other request for the relation starts over.
+//!
+//! Enabled per tenant with the `sequential_prefetch` tenant config. The blocks read ahead are
+//! counted in `pageserver_page_service_prefetch_blocks_total`, and the requests for them in
+//! `pageserver_page_service_prefetch_hits_total`.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use pageserver_api::key::rel_block_to_key;
+use pageserver_api::reltag::RelTag;
+use postgres_ffi::BlockNumber;
+use tokio::sync::mpsc;
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{debug, info_span, Instrument};
+use utils::lsn::Lsn;
+
+use crate::context::{DownloadBehavior, RequestContext, RequestContextBuilder};
+use crate::metrics::{PAGE_SERVICE_PREFETCH_BLOCKS, PAGE_SERVICE_PREFETCH_HITS};
+use crate::pgdatadir_mapping::Version;
+use crate::task_mgr::{self, TaskKind};
+use crate::tenant::Timeline;
+
+/// How many consecutive blocks make a sequential scan.
+const TRIGGER_RUN: u32 = 4;
+
+const MIN_WINDOW: u32 = 8;
+
+/// 8 MiB of pages.
+const MAX_WINDOW: u32 = 1024;
+
+/// Read-ahead jobs waiting for the worker of the connection. When it falls behind, the
+/// following jobs are dropped, and the scan reads those blocks itself.
+const QUEUE_LEN: usize = 4;
+
+/// The sequential scan that the connection is doing, if any.
+#[derive(Debug, PartialEq, Eq)]
+struct Scan {
+    rel: RelTag,
+    /// The block that continues the scan.
+    next_blkno: BlockNumber,
+    /// How many consecutive blocks were requested.
+    run: u32,
+    window: u32,
+    /// The end of the blocks read ahead.
+    prefetched_end: BlockNumber,
+}
+
+/// Tracks the GetPage requests of a connection, and reads ahead of its sequential scans.
+pub(super) struct SequentialPrefetch {
+    scan: Option<Scan>,
+    worker: Option<Worker>,
+}
+
+struct Worker {
+    jobs: mpsc::Sender<Job>,
+    /// Stops the worker when the connection ends.
+    _cancel: DropGuard,
+}
+
+struct Job {
+    timeline: Arc<Timeline>,
+    rel: RelTag,
+    blocks: Range<BlockNumber>,
+    lsn: Lsn,
+}
+
+impl SequentialPrefetch {
+    pub(super) fn new() -> Self {
+        Self {
+            scan: None,
+            worker: None,
+        }
+    }
+
+    /// Called after each GetPage request that `timeline` served at `lsn`.
+    pub(super) fn on_get_page(
+        &mut self,
+        timeline: &Arc<Timeline>,
+        rel: RelTag,
+        blkno: BlockNumber,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) {
+        if !timeline.get_sequential_prefetch() {
+            self.scan = None;
+            return;
+        }
+
+        if self.is_prefetched(rel, blkno) {
+            PAGE_SERVICE_PREFETCH_HITS.inc();
+        }
+        let Some(blocks) = self.observe(rel, blkno) else {
+            return;
+        };
+
+        let worker = self.worker.get_or_insert_with(|| Worker::spawn(ctx));
+        let job = Job {
+            timeline: Arc::clone(timeline),
+            rel,
+            blocks,
+            lsn,
+        };
+        if worker.jobs.try_send(job).is_err() {
+            debug!("read-ahead worker is behind, skipping blocks");
+        }
+    }
+
+    fn is_prefetched(&self, rel: RelTag, blkno: BlockNumber) -> bool {
+        matches!(&self.scan, Some(scan) if scan.rel == rel
+            && scan.next_blkno == blkno
+            && blkno < scan.prefetched_end)
+    }
+
+    /// Account for a request of `blkno` of `rel`, and return the blocks to read ahead.
+    fn observe(&mut self, rel: RelTag, blkno: BlockNumber) -> Option<Range<BlockNumber>> {
+        let scan = match &mut self.scan {
+            Some(scan) if scan.rel == rel && scan.next_blkno == blkno => scan,
+            _ => {
+                self.scan = Some(Scan {
+                    rel,
+                    next_blkno: blkno.saturating_add(1),
+                    run: 1,
+                    window: MIN_WINDOW,
+                    prefetched_end: blkno.saturating_add(1),
+                });
+                return None;
+            }
+        };
+        scan.next_blkno = blkno.saturating_add(1);
+        scan.run += 1;
+
+        if scan.run < TRIGGER_RUN {
+            return None;
+        }
+        if blkno < scan.prefetched_end {
+            if scan.prefetched_end - blkno > scan.window / 2 {
+                // Still far enough ahead.
+                return None;
+            }
+            scan.window = (scan.window * 2).min(MAX_WINDOW);
+        }
+
+        let start = scan.prefetched_end.max(blkno.saturating_add(1));
+        let end = blkno.saturating_add(1).saturating_add(scan.window);
+        scan.prefetched_end = end;
+        (start < end).then_some(start..end)
+    }
+}
+
+impl Worker {
+    fn spawn(ctx: &RequestContext) -> Self {
+        let (jobs, mut rx) = mpsc::channel::<Job>(QUEUE_LEN);
+        let cancel = CancellationToken::new();
+        let ctx = RequestContextBuilder::extend(
+            &ctx.detached_child(TaskKind::PageRequestPrefetch, DownloadBehavior::Download),
+        )
+        .cancel(&cancel)
+        .build();
+
+        let worker_cancel = cancel.clone();
+        task_mgr::spawn(
+            &tokio::runtime::Handle::current(),
+            TaskKind::PageRequestPrefetch,
+            None,
+            None,
+            "sequential prefetch",
+            false,
+            async move {
+                loop {
+                    let job = tokio::select! {
+                        _ = worker_cancel.cancelled() => break,
+                        _ = task_mgr::shutdown_watcher() => break,
+                        job = rx.recv() => match job {
+                            Some(job) => job,
+                            None => break,
+                        },
+                    };
+                    let span = info_span!(
+                        "sequential_prefetch",
+                        tenant_id = %job.timeline.tenant_shard_id.tenant_id,
+                        shard_id = %job.timeline.tenant_shard_id.shard_slug(),
+                        timeline_id = %job.timeline.timeline_id,
+                        rel = %job.rel,
+                    );
+                    job.run(&ctx).instrument(span).await;
+                }
+                Ok(())
+            },
+        );
+
+        Self {
+            jobs,
+            _cancel: cancel.drop_guard(),
+        }
+    }
+}
+
+impl Job {
+    async fn run(self, ctx: &RequestContext) {
+        let Ok(_gate) = self.timeline.gate.enter() else {
+            return;
+        };
+        let version = Version::Lsn(self.lsn);
+        let nblocks = match self.timeline.get_rel_size(self.rel, version, ctx).await {
+            Ok(nblocks) => nblocks,
+            Err(e) => {
+                debug!("read-ahead failed to get the relation size: {e:#}");
+                return;
+            }
+        };
+
+        let shard = self.timeline.get_shard_identity();
+        for blkno in self.blocks.start..self.blocks.end.min(nblocks) {
+            if ctx.is_cancelled() || self.timeline.cancel.is_cancelled() {
+                return;
+            }
+            if !shard.is_key_local(&rel_block_to_key(self.rel, blkno)) {
+                continue;
+            }
+            match self
+                .timeline
+                .get_rel_page_at_lsn(self.rel, blkno, version, ctx)
+                .await
+            {
+                Ok(_) => PAGE_SERVICE_PREFETCH_BLOCKS.inc(),
+                Err(e) => {
+                    debug!("read-ahead of block {blkno} failed: {e:#}");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rel(relnode: u32) -> RelTag {
+        RelTag {
+            spcnode: 1663,
+            dbnode: 5,
+            relnode,
+            forknum: 0,
+        }
+    }
+
+    #[test]
+    fn reads_ahead_of_sequential_scans() {
+        let mut prefetch = SequentialPrefetch::new();
+        let r = rel(16384);
+
+        for blkno in 0..TRIGGER_RUN - 1 {
+            assert_eq!(prefetch.observe(r, blkno), None);
+        }
+        let last = TRIGGER_RUN - 1;
+        assert_eq!(
+            prefetch.observe(r, last),
+            Some(last + 1..last + 1 + MIN_WINDOW)
+        );
+
+        // Nothing more until the scan gets within half a window of the end.
+        let end = last + 1 + MIN_WINDOW;
+        let mut blkno = last + 1;
+        while end - blkno > MIN_WINDOW / 2 {
+            assert!(prefetch.is_prefetched(r, blkno));
+            assert_eq!(prefetch.observe(r, blkno), None);
+            blkno += 1;
+        }
+        // Then the window doubles.
+        assert_eq!(
+            prefetch.observe(r, blkno),
+            Some(end..blkno + 1 + 2 * MIN_WINDOW)
+        );
+    }
+
+    #[test]
+    fn window_is_bounded() {
+        let mut prefetch = SequentialPrefetch::new();
+        let r = rel(16384);
+        let mut largest = 0;
+        for blkno in 0..100_000 {
+            if let Some(blocks) = prefetch.observe(r, blkno) {
+                largest = largest.max(blocks.end - blkno - 1);
+            }
+        }
+        assert_eq!(largest, MAX_WINDOW);
+    }
+
+    #[test]
+    fn other_requests_start_over() {
+        let mut prefetch = SequentialPrefetch::new();
+        for blkno in 0..TRIGGER_RUN {
+            prefetch.observe(rel(1), blkno);
+        }
+        assert!(prefetch.is_prefetched(rel(1), TRIGGER_RUN));
+
+        // Another relation, or a jump in the same one.
+        assert_eq!(prefetch.observe(rel(2), TRIGGER_RUN), None);
+        assert!(!prefetch.is_prefetched(rel(1), TRIGGER_RUN));
+        for blkno in 0..TRIGGER_RUN {
+            prefetch.observe(rel(1), blkno);
+        }
+        assert_eq!(prefetch.observe(rel(1), 1000), None);
+        assert_eq!(prefetch.observe(rel(1), 1001), None);
+    }
+}
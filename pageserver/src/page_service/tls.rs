@@ -0,0 +1,132 @@
+//! TLS for the page_service listener.
+//!
+//! Without a trusted network fabric between computes and pageservers, the page traffic can be
+//! encrypted with TLS: computes request it with the postgres `SSLRequest`, and once TLS is
+//! configured, connections without it are rejected.
+//!
+//! With a client CA configured, computes must also present a certificate signed by it, which
+//! names the tenants it may access: a connection may only access the tenants whose id is the
+//! subject common name or one of the DNS subject alternative names of its certificate. This is
+//! checked like the tenant claim of the JWT, see `PageServerHandler::check_permission`.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use utils::id::TenantId;
+use x509_parser::extensions::GeneralName;
+
+/// Configuration of TLS on the page_service listener, see module-level comment.
+///
+/// ```toml
+/// [page_service_tls]
+/// cert_path = "server.crt"
+/// key_path = "server.key"
+/// client_ca_path = "compute-ca.crt"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PageServiceTlsConfig {
+    /// PEM certificate chain of the pageserver.
+    pub cert_path: Utf8PathBuf,
+    /// PEM private key of the pageserver.
+    pub key_path: Utf8PathBuf,
+    /// PEM certificates of the CAs that sign the certificates of computes. Client certificates
+    /// are not requested if unset.
+    #[serde(default)]
+    pub client_ca_path: Option<Utf8PathBuf>,
+}
+
+fn load_certs(path: &Utf8Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let bytes = std::fs::read(path).with_context(|| format!("read {path}"))?;
+    let certs = rustls_pemfile::certs(&mut &bytes[..])
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("parse certificates in {path}"))?;
+    anyhow::ensure!(!certs.is_empty(), "no certificates in {path}");
+    Ok(certs)
+}
+
+/// Build the TLS configuration of the page_service listener.
+pub fn load_server_config(config: &PageServiceTlsConfig) -> anyhow::Result<Arc<ServerConfig>> {
+    let cert_chain = load_certs(&config.cert_path)?;
+    let key_bytes =
+        std::fs::read(&config.key_path).with_context(|| format!("read {}", config.key_path))?;
+    let key = rustls_pemfile::private_key(&mut &key_bytes[..])
+        .with_context(|| format!("parse private key in {}", config.key_path))?
+        .with_context(|| format!("no private key in {}", config.key_path))?;
+
+    let builder = ServerConfig::builder();
+    let builder = match &config.client_ca_path {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(client_ca_path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("add CA certificate from {client_ca_path}"))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .context("build client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let server_config = builder
+        .with_single_cert(cert_chain, key)
+        .context("invalid certificate or private key")?;
+    Ok(Arc::new(server_config))
+}
+
+/// The tenants that a verified client certificate grants access to.
+pub(crate) fn tenants_of_certificate(cert: &CertificateDer<'_>) -> anyhow::Result<Vec<TenantId>> {
+    let (_, cert) =
+        x509_parser::parse_x509_certificate(cert).context("parse client certificate")?;
+
+    let mut names = Vec::new();
+    for common_name in cert.subject().iter_common_name() {
+        names.push(common_name.as_str().context("decode common name")?);
+    }
+    if let Some(san) = cert
+        .subject_alternative_name()
+        .context("parse subject alternative names")?
+    {
+        for name in &san.value.general_names {
+            if let GeneralName::DNSName(name) = name {
+                names.push(name);
+            }
+        }
+    }
+
+    let mut tenants = names
+        .into_iter()
+        .filter_map(|name| name.parse::<TenantId>().ok())
+        .collect::<Vec<_>>();
+    tenants.sort();
+    tenants.dedup();
+    Ok(tenants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tenants_from_certificate_names() {
+        let tenant_a = TenantId::generate();
+        let tenant_b = TenantId::generate();
+        let cert = rcgen::generate_simple_self_signed(vec![
+            tenant_a.to_string(),
+            "compute.local".to_string(),
+            tenant_b.to_string(),
+        ])
+        .unwrap();
+        let der = CertificateDer::from(cert.serialize_der().unwrap());
+
+        let mut expected = vec![tenant_a, tenant_b];
+        expected.sort();
+        assert_eq!(tenants_of_certificate(&der).unwrap(), expected);
+    }
+}
@@ -0,0 +1,136 @@
+//! The Postgres distributions used for initdb and WAL redo.
+//!
+//! Each timeline has its own Postgres major version, and a tenant can have timelines of several
+//! versions. The binaries of each version are installed in `pg_distrib_dir/v{pg_version}`, and
+//! a pageserver may not have all of them. The installed versions are discovered at startup, and
+//! checked again when a timeline is created and when a walredo process is launched, so that a
+//! missing version fails with an error that names it, rather than a failed spawn.
+//!
+//! `GET /v1/pg_versions` reports the installed versions, and how many timelines and walredo
+//! processes use each of them.
+
+use std::collections::BTreeMap;
+
+use camino::Utf8PathBuf;
+use pageserver_api::models::PgVersionUsage;
+use tracing::{info, warn};
+
+use crate::config::PageServerConf;
+use crate::tenant::mgr::TenantManager;
+
+/// The major versions that the pageserver supports.
+pub const SUPPORTED_PG_VERSIONS: [u32; 3] = [14, 15, 16];
+
+/// The binaries that a distribution must have.
+const REQUIRED_BINARIES: [&str; 2] = ["postgres", "initdb"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum PgVersionError {
+    #[error("unsupported postgres version {0}, supported versions are {SUPPORTED_PG_VERSIONS:?}")]
+    Unsupported(u32),
+    #[error("postgres {pg_version} is not installed on this pageserver: {path} is missing")]
+    NotInstalled { pg_version: u32, path: Utf8PathBuf },
+}
+
+/// Check that the binaries of `pg_version` are installed.
+pub fn check_installed(conf: &PageServerConf, pg_version: u32) -> Result<(), PgVersionError> {
+    let bin_dir = conf
+        .pg_bin_dir(pg_version)
+        .map_err(|_| PgVersionError::Unsupported(pg_version))?;
+    for binary in REQUIRED_BINARIES {
+        let path = bin_dir.join(binary);
+        if !path.is_file() {
+            return Err(PgVersionError::NotInstalled { pg_version, path });
+        }
+    }
+    Ok(())
+}
+
+/// Log the installed versions, and warn about the missing ones. Called once at startup.
+pub fn discover(conf: &PageServerConf) {
+    let mut installed = Vec::new();
+    for pg_version in SUPPORTED_PG_VERSIONS {
+        match check_installed(conf, pg_version) {
+            Ok(()) => installed.push(pg_version),
+            Err(e) => warn!("{e}, timelines of this version can't be created or served"),
+        }
+    }
+    info!("installed postgres versions: {installed:?}");
+}
+
+/// The usage of each supported version by the attached tenants, and the versions that are not
+/// supported but used anyway.
+pub(crate) fn usage(conf: &PageServerConf, tenant_manager: &TenantManager) -> Vec<PgVersionUsage> {
+    let unused = |pg_version| PgVersionUsage {
+        pg_version,
+        installed: false,
+        error: None,
+        timelines: 0,
+        walredo_processes: 0,
+    };
+    let mut usage = BTreeMap::from_iter(
+        SUPPORTED_PG_VERSIONS.map(|pg_version| (pg_version, unused(pg_version))),
+    );
+
+    for tenant in tenant_manager.get_attached_active_tenant_shards() {
+        for timeline in tenant.list_timelines() {
+            let pg_version = timeline.pg_version;
+            usage
+                .entry(pg_version)
+                .or_insert_with(|| unused(pg_version))
+                .timelines += 1;
+        }
+        if let Some(status) = tenant.wal_redo_manager_status() {
+            for process in status.processes {
+                usage
+                    .entry(process.pg_version)
+                    .or_insert_with(|| unused(process.pg_version))
+                    .walredo_processes += 1;
+            }
+        }
+    }
+
+    for version in usage.values_mut() {
+        match check_installed(conf, version.pg_version) {
+            Ok(()) => version.installed = true,
+            Err(e) => version.error = Some(e.to_string()),
+        }
+    }
+    usage.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_binaries() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let conf = PageServerConf {
+            pg_distrib_dir: dir.path().to_owned(),
+            ..PageServerConf::dummy_conf(dir.path().join("repo"))
+        };
+
+        let bin_dir = dir.path().join("v15").join("bin");
+        std::fs::create_dir_all(&bin_dir).unwrap();
+        std::fs::write(bin_dir.join("postgres"), "").unwrap();
+        assert!(matches!(
+            check_installed(&conf, 15),
+            Err(PgVersionError::NotInstalled { pg_version: 15, path }) if path == bin_dir.join("initdb")
+        ));
+
+        std::fs::write(bin_dir.join("initdb"), "").unwrap();
+        check_installed(&conf, 15).unwrap();
+        for pg_version in SUPPORTED_PG_VERSIONS.into_iter().filter(|v| *v != 15) {
+            assert!(matches!(
+                check_installed(&conf, pg_version),
+                Err(PgVersionError::NotInstalled { .. })
+            ));
+        }
+
+        assert!(matches!(
+            check_installed(&conf, 13),
+            Err(PgVersionError::Unsupported(13))
+        ));
+    }
+}
@@ -11,6 +11,7 @@ use crate::context::RequestContext;
 use crate::keyspace::{KeySpace, KeySpaceAccum};
 use crate::metrics::WAL_INGEST;
 use crate::span::debug_assert_current_span_has_tenant_and_timeline_id_no_shard_id;
+use crate::tenant::timeline::invalidations::InvalidationAccum;
 use crate::walrecord::NeonWalRecord;
 use crate::{aux_file, repository::*};
 use anyhow::{ensure, Context};
@@ -24,24 +25,33 @@ use pageserver_api::key::{
     AUX_FILES_KEY, CHECKPOINT_KEY, CONTROLFILE_KEY, DBDIR_KEY, TWOPHASEDIR_KEY,
 };
 use pageserver_api::keyspace::SparseKeySpace;
-use pageserver_api::models::AuxFilePolicy;
+use pageserver_api::models::{AuxFilePolicy, DatabaseSize, RelationSize};
 use pageserver_api::reltag::{BlockNumber, RelTag, SlruKind};
 use postgres_ffi::relfile_utils::{FSM_FORKNUM, VISIBILITYMAP_FORKNUM};
 use postgres_ffi::BLCKSZ;
 use postgres_ffi::{Oid, TimestampTz, TransactionId};
 use serde::{Deserialize, Serialize};
-use std::collections::{hash_map, HashMap, HashSet};
+use std::collections::{hash_map, HashMap, HashSet, VecDeque};
 use std::ops::ControlFlow;
 use std::ops::Range;
+use std::sync::{Arc, Mutex};
 use strum::IntoEnumIterator;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, trace, warn};
+use tracing::{debug, trace, warn, Instrument};
 use utils::bin_ser::DeserializeError;
 use utils::vec_map::{VecMap, VecMapOrdering};
 use utils::{bin_ser::BeSer, lsn::Lsn};
 
 const MAX_AUX_FILE_DELTAS: usize = 1024;
 
+/// The number of relation size keys in a partition of [`Timeline::get_current_logical_size_parallel`].
+const LOGICAL_SIZE_PARTITION_KEYS: usize = 256;
+
+/// The most worker tasks of a [`Timeline::get_current_logical_size_parallel`] calculation.
+const MAX_LOGICAL_SIZE_WORKERS: usize = 8;
+
 #[derive(Debug)]
 pub enum LsnForTimestamp {
     /// Found commits both before and after the given timestamp
@@ -415,6 +425,23 @@ impl Timeline {
 
         let mut found_smaller = false;
         let mut found_larger = false;
+
+        // Only search between the sampled commits around the timestamp, see
+        // [`crate::tenant::timeline::lsn_time_index`]: the commits up to the one before are
+        // all smaller, and the one after is larger.
+        let bracket = self.lsn_time_bracket_by_timestamp(search_timestamp);
+        if let Some(before) = bracket
+            .before
+            .filter(|s| s.lsn >= min_lsn && s.lsn <= max_lsn)
+        {
+            low = before.lsn.0 / 8 + 1;
+            found_smaller = true;
+        }
+        if let Some(after) = bracket.after.filter(|s| s.lsn <= max_lsn) {
+            high = std::cmp::max(low, (after.lsn.0 + 7) / 8);
+            found_larger = true;
+        }
+
         while low < high {
             if cancel.is_cancelled() {
                 return Err(PageReconstructError::Cancelled);
@@ -762,27 +789,175 @@ impl Timeline {
     ) -> Result<u64, CalculateLogicalSizeError> {
         debug_assert_current_span_has_tenant_and_timeline_id_no_shard_id();
 
+        let relsize_keys = self.list_rel_size_keys(lsn, ctx).await?;
+        let total_size = self.sum_rel_sizes(&relsize_keys, lsn, ctx).await?;
+        Ok(total_size * BLCKSZ as u64)
+    }
+
+    /// Does the same as [`Self::get_current_logical_size_non_incremental`], with the relation
+    /// sizes read in parallel: their keys are split into partitions of
+    /// [`LOGICAL_SIZE_PARTITION_KEYS`], which up to [`MAX_LOGICAL_SIZE_WORKERS`] worker tasks
+    /// take in turn.
+    ///
+    /// The calling task is the first worker. The others each hold a permit of `limit`, the
+    /// `concurrent_tenant_size_logical_size_queries` semaphore, so that the calculations and
+    /// their workers together stay within that limit. They are only started if a permit is
+    /// available right away: the synthetic size calculations hold a permit while they wait for
+    /// the logical sizes, so waiting for more could deadlock.
+    ///
+    /// The speedup thus depends on the spare permits. With the default single permit, a
+    /// synthetic size calculation reads serially, and an initial calculation gets one extra
+    /// worker, only while no synthetic size calculation runs. Raising the limit lets the
+    /// calculations read with up to [`MAX_LOGICAL_SIZE_WORKERS`] tasks.
+    ///
+    /// # Cancel-Safety
+    ///
+    /// This method is cancellation-safe.
+    pub(crate) async fn get_current_logical_size_parallel(
+        self: &Arc<Self>,
+        lsn: Lsn,
+        limit: &Arc<Semaphore>,
+        ctx: &RequestContext,
+    ) -> Result<u64, CalculateLogicalSizeError> {
+        debug_assert_current_span_has_tenant_and_timeline_id_no_shard_id();
+
+        let relsize_keys = self.list_rel_size_keys(lsn, ctx).await?;
+        let partitions = relsize_keys
+            .chunks(LOGICAL_SIZE_PARTITION_KEYS)
+            .map(<[Key]>::to_vec)
+            .collect::<VecDeque<_>>();
+        let num_workers = partitions.len().min(MAX_LOGICAL_SIZE_WORKERS);
+        let partitions = Arc::new(Mutex::new(partitions));
+
+        // Dropping the set aborts the workers, e.g. if the calculation is cancelled.
+        let mut workers = JoinSet::new();
+        for _ in 1..num_workers {
+            let Ok(permit) = Arc::clone(limit).try_acquire_owned() else {
+                break;
+            };
+            let timeline = Arc::clone(self);
+            let partitions = Arc::clone(&partitions);
+            let ctx = ctx.attached_child();
+            workers.spawn(
+                async move {
+                    let _permit = permit;
+                    let _guard = timeline
+                        .gate
+                        .enter()
+                        .map_err(|_| CalculateLogicalSizeError::Cancelled)?;
+                    timeline.sum_partitions(&partitions, lsn, &ctx).await
+                }
+                .in_current_span(),
+            );
+        }
+        debug!(
+            partitions = relsize_keys.len().div_ceil(LOGICAL_SIZE_PARTITION_KEYS),
+            workers = workers.len() + 1,
+            "calculating logical size in parallel"
+        );
+
+        let mut total_size = self.sum_partitions(&partitions, lsn, ctx).await?;
+        while let Some(res) = workers.join_next().await {
+            total_size += res.context("logical size calculation worker")??;
+        }
+        Ok(total_size * BLCKSZ as u64)
+    }
+
+    /// The keys of the sizes of all relations at `lsn`, sorted.
+    async fn list_rel_size_keys(
+        &self,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<Vec<Key>, CalculateLogicalSizeError> {
         // Fetch list of database dirs and iterate them
         let buf = self.get(DBDIR_KEY, lsn, ctx).await?;
         let dbdir = DbDirectory::des(&buf).context("deserialize db directory")?;
 
-        let mut total_size: u64 = 0;
+        let mut relsize_keys = Vec::new();
         for (spcnode, dbnode) in dbdir.dbdirs.keys() {
-            for rel in self
+            if self.cancel.is_cancelled() {
+                return Err(CalculateLogicalSizeError::Cancelled);
+            }
+            let rels = self
                 .list_rels(*spcnode, *dbnode, Version::Lsn(lsn), ctx)
+                .await?;
+            relsize_keys.extend(rels.into_iter().map(rel_size_to_key));
+        }
+        relsize_keys.sort_unstable();
+        Ok(relsize_keys)
+    }
+
+    /// The total number of blocks of the relations of `relsize_keys` at `lsn`.
+    async fn sum_rel_sizes(
+        &self,
+        relsize_keys: &[Key],
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<u64, CalculateLogicalSizeError> {
+        let mut total_size: u64 = 0;
+        for relsize_key in relsize_keys {
+            if self.cancel.is_cancelled() {
+                return Err(CalculateLogicalSizeError::Cancelled);
+            }
+            let mut buf = self.get(*relsize_key, lsn, ctx).await?;
+            let relsize = buf.get_u32_le();
+
+            total_size += relsize as u64;
+        }
+        Ok(total_size)
+    }
+
+    /// Sum the relation sizes of the `partitions` until there are none left.
+    async fn sum_partitions(
+        &self,
+        partitions: &Mutex<VecDeque<Vec<Key>>>,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<u64, CalculateLogicalSizeError> {
+        let mut total_size = 0;
+        loop {
+            let Some(relsize_keys) = partitions.lock().unwrap().pop_front() else {
+                return Ok(total_size);
+            };
+            total_size += self.sum_rel_sizes(&relsize_keys, lsn, ctx).await?;
+        }
+    }
+
+    /// Sizes of all relations at `lsn`, by database, sorted by OIDs. Like
+    /// [`Self::get_current_logical_size_non_incremental`], this reads the relation sizes the
+    /// pageserver tracks, not the relation pages.
+    pub(crate) async fn get_relation_sizes(
+        &self,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> Result<Vec<DatabaseSize>, CalculateLogicalSizeError> {
+        let mut databases = Vec::new();
+        for (spcnode, dbnode) in self.list_dbdirs(lsn, ctx).await?.into_keys() {
+            let mut relations = Vec::new();
+            for rel in self
+                .list_rels(spcnode, dbnode, Version::Lsn(lsn), ctx)
                 .await?
             {
                 if self.cancel.is_cancelled() {
                     return Err(CalculateLogicalSizeError::Cancelled);
                 }
-                let relsize_key = rel_size_to_key(rel);
-                let mut buf = self.get(relsize_key, lsn, ctx).await?;
-                let relsize = buf.get_u32_le();
-
-                total_size += relsize as u64;
+                let nblocks = self.get_rel_size(rel, Version::Lsn(lsn), ctx).await?;
+                relations.push(RelationSize {
+                    relnode: rel.relnode,
+                    forknum: rel.forknum,
+                    size: nblocks as u64 * BLCKSZ as u64,
+                });
             }
+            relations.sort_by_key(|r| (r.relnode, r.forknum));
+            databases.push(DatabaseSize {
+                spcnode,
+                dbnode,
+                size: relations.iter().map(|r| r.size).sum(),
+                relations,
+            });
         }
-        Ok(total_size * BLCKSZ as u64)
+        databases.sort_by_key(|db| (db.spcnode, db.dbnode));
+        Ok(databases)
     }
 
     ///
@@ -1010,7 +1185,7 @@ impl<'a> DatadirModification<'a> {
         Ok(())
     }
 
-    #[cfg(test)]
+    #[cfg(any(feature = "testing", test))]
     pub fn init_empty_test_timeline(&mut self) -> anyhow::Result<()> {
         self.init_empty()?;
         self.put_control_file(bytes::Bytes::from_static(
@@ -1545,6 +1720,7 @@ impl<'a> DatadirModification<'a> {
                     Err(
                         e @ (PageReconstructError::AncestorStopping(_)
                         | PageReconstructError::Cancelled
+                        | PageReconstructError::RequestCancelled
                         | PageReconstructError::AncestorLsnTimeout(_)),
                     ) => {
                         // Important that we do not interpret a shutdown error as "not found" and thereby
@@ -1656,6 +1832,19 @@ impl<'a> DatadirModification<'a> {
         let pending_nblocks = self.pending_nblocks;
         self.pending_nblocks = 0;
 
+        let invalidations = self.tline.invalidations.has_subscribers().then(|| {
+            let mut changes = InvalidationAccum::default();
+            for (key, vals) in &self.pending_updates {
+                for (lsn, _) in vals {
+                    changes.add_key(*lsn, *key);
+                }
+            }
+            for (range, lsn) in &self.pending_deletions {
+                changes.add_range(*lsn, range.clone());
+            }
+            changes
+        });
+
         if !self.pending_updates.is_empty() {
             // The put_batch call below expects expects the inputs to be sorted by Lsn,
             // so we do that first.
@@ -1684,6 +1873,10 @@ impl<'a> DatadirModification<'a> {
             writer.finish_write(pending_lsn);
         }
 
+        if let Some(invalidations) = invalidations {
+            self.tline.invalidations.publish(invalidations);
+        }
+
         if pending_nblocks != 0 {
             writer.update_current_logical_size(pending_nblocks * i64::from(BLCKSZ));
         }
@@ -1905,6 +2098,48 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn logical_size_parallel() -> anyhow::Result<()> {
+        let harness = TenantHarness::create("logical_size_parallel")?;
+        let (tenant, ctx) = harness.load().await;
+        let tline = tenant
+            .create_test_timeline(TimelineId::generate(), Lsn(0x10), DEFAULT_PG_VERSION, &ctx)
+            .await?;
+
+        // Enough relations for several partitions, in two databases.
+        let mut modification = tline.begin_modification(Lsn(0x20));
+        for relnode in 1..=1000 {
+            let rel = RelTag {
+                spcnode: 1663,
+                dbnode: if relnode % 2 == 0 { 5 } else { 16384 },
+                relnode,
+                forknum: 0,
+            };
+            modification
+                .put_rel_creation(rel, relnode % 7, &ctx)
+                .await?;
+        }
+        modification.commit(&ctx).await?;
+
+        let expected = tline
+            .get_current_logical_size_non_incremental(Lsn(0x20), &ctx)
+            .await?;
+        let blocks: u64 = (1..=1000).map(|relnode| relnode % 7).sum();
+        assert_eq!(expected, blocks * BLCKSZ as u64);
+
+        // With extra workers, and without when the semaphore has no permits left.
+        for permits in [4, 0] {
+            let limit = Arc::new(Semaphore::new(permits));
+            let size = tline
+                .get_current_logical_size_parallel(Lsn(0x20), &limit, &ctx)
+                .await?;
+            assert_eq!(size, expected);
+            assert_eq!(limit.available_permits(), permits);
+        }
+
+        Ok(())
+    }
+
     /*
         fn assert_current_logical_size<R: Repository>(timeline: &DatadirTimeline<R>, lsn: Lsn) {
             let incremental = timeline.get_current_logical_size();
@@ -0,0 +1,334 @@
+//! Priority classes for the read path.
+//!
+//! Compute GetPage requests and background tasks (logical size calculation, image layer
+//! creation, warmup, ...) reconstruct pages through the same code path, and compete for the
+//! same page cache, disk bandwidth and walredo processes. A background task that reads a whole
+//! timeline can add a lot of latency to the GetPage requests running at the same time.
+//!
+//! Reads are classified into a [`ReadPriority`] by the task kind of their [`RequestContext`].
+//! Foreground reads are never queued here. Background reads go through a queue of their own:
+//!
+//! * At most [`ReadPriorityConfig::background_concurrency`] background reads run at once.
+//! * The degradation budget, [`ReadPriorityConfig::foreground_latency_budget`], is the
+//!   foreground read latency up to which background reads run freely. While the recent average
+//!   latency of the foreground reads of a tenant is above it, a background read of the tenant
+//!   first waits for the tenant's foreground reads in progress to finish, for at most
+//!   [`ReadPriorityConfig::max_background_delay`], so that background work can't be starved
+//!   completely. The foreground reads are tracked per tenant, in [`ForegroundReads`], so that a
+//!   busy tenant doesn't delay the background work of all the others.
+//!
+//! The page cache admits the pages read by background reads with the lowest usage count, and
+//! doesn't promote pages on background hits, so that background scans don't evict the pages
+//! that foreground reads work on. See [`crate::page_cache`].
+
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use once_cell::sync::OnceCell;
+use tokio::{
+    sync::{Notify, Semaphore, SemaphorePermit},
+    time::Instant,
+};
+
+use crate::{context::RequestContext, metrics::BACKGROUND_READ_WAIT_TIME, task_mgr::TaskKind};
+
+static READ_SCHEDULER: OnceCell<ReadScheduler> = OnceCell::new();
+
+/// Initialize the read scheduler. This must be called at most once, at page server startup.
+pub fn init(config: &ReadPriorityConfig) {
+    if READ_SCHEDULER.set(ReadScheduler::new(config)).is_err() {
+        panic!("read scheduler already initialized");
+    }
+}
+
+/// Get a handle to the read scheduler. Uses the default configuration if [`init`] wasn't
+/// called, like in unit tests.
+pub(crate) fn get() -> &'static ReadScheduler {
+    READ_SCHEDULER.get_or_init(|| ReadScheduler::new(&ReadPriorityConfig::default()))
+}
+
+/// Configuration of the read scheduler, see module-level comment.
+///
+/// ```toml
+/// [read_priority]
+/// background_concurrency = 32
+/// foreground_latency_budget = "10ms"
+/// max_background_delay = "100ms"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReadPriorityConfig {
+    /// Maximum number of background reads in progress at once. Zero means no limit.
+    pub background_concurrency: usize,
+    /// Average foreground read latency above which background reads give way to foreground
+    /// reads. Zero disables it.
+    #[serde(with = "humantime_serde")]
+    pub foreground_latency_budget: Duration,
+    /// Maximum time a background read gives way to foreground reads.
+    #[serde(with = "humantime_serde")]
+    pub max_background_delay: Duration,
+}
+
+impl Default for ReadPriorityConfig {
+    fn default() -> Self {
+        Self {
+            background_concurrency: 32,
+            foreground_latency_budget: Duration::from_millis(10),
+            max_background_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReadPriority {
+    /// Reads that someone is waiting for: compute requests, WAL ingest, management API calls.
+    Foreground,
+    /// Reads of background tasks.
+    Background,
+}
+
+impl ReadPriority {
+    pub(crate) fn of_task_kind(task_kind: TaskKind) -> Self {
+        use TaskKind::*;
+        match task_kind {
+            PageRequestHandler
+            | WalReceiverConnectionHandler
+            | WalReceiverConnectionPoller
+            | MgmtRequest
+            | OndemandLogicalSizeCalculation
+            | DebugTool => ReadPriority::Foreground,
+            #[cfg(any(feature = "testing", test))]
+            UnitTest => ReadPriority::Foreground,
+            Startup
+            | LibpqEndpointListener
+            | HttpEndpointListener
+            | WalReceiverManager
+            | GarbageCollector
+            | Compaction
+            | Eviction
+            | IngestHousekeeping
+            | DiskUsageEviction
+            | DetachedTenantsJanitor
+            | PageRequestPrefetch
+            | LayerVerification
+            | TempFileJanitor
+            | SecondaryDownloads
+            | SecondaryUploads
+            | SecondaryIndexRefresh
+            | InitialLogicalSizeCalculation
+            | LayerFlushTask
+            | RemoteUploadTask
+            | InitialLoad
+            | Attach
+            | TimelineDeletionWorker
+            | MetricsCollection
+            | DownloadAllRemoteLayers
+            | CalculateSyntheticSize
+            | EphemeralFilePreWarmPageCache
+            | LayerDownload
+            | DetachAncestor
+            | BranchImageCreation
+            | TimelineEventPublisher => ReadPriority::Background,
+        }
+    }
+}
+
+pub(crate) struct ReadScheduler {
+    config: ReadPriorityConfig,
+    background: Option<Semaphore>,
+}
+
+/// The foreground reads of a tenant, which the background reads of the tenant give way to.
+/// All timelines of a tenant share the same instance.
+#[derive(Default)]
+pub(crate) struct ForegroundReads {
+    in_progress: AtomicUsize,
+    idle: Notify,
+    /// Exponentially weighted moving average of the latency of foreground reads, in
+    /// microseconds.
+    latency_micros: AtomicU64,
+}
+
+/// Held for the duration of a read.
+pub(crate) enum ReadPermit<'a> {
+    Foreground {
+        reads: &'a ForegroundReads,
+        started_at: Instant,
+    },
+    Background {
+        _permit: Option<SemaphorePermit<'a>>,
+    },
+}
+
+impl ReadScheduler {
+    fn new(config: &ReadPriorityConfig) -> Self {
+        Self {
+            config: config.clone(),
+            background: (config.background_concurrency > 0)
+                .then(|| Semaphore::new(config.background_concurrency)),
+        }
+    }
+
+    /// Wait until a read with the priority of `ctx` may start, on a tenant with the foreground
+    /// reads `foreground`.
+    pub(crate) async fn admit<'a>(
+        &'a self,
+        ctx: &RequestContext,
+        foreground: &'a ForegroundReads,
+    ) -> ReadPermit<'a> {
+        match ctx.read_priority() {
+            ReadPriority::Foreground => {
+                foreground.in_progress.fetch_add(1, Ordering::Relaxed);
+                ReadPermit::Foreground {
+                    reads: foreground,
+                    started_at: Instant::now(),
+                }
+            }
+            ReadPriority::Background => {
+                let started_at = Instant::now();
+                let permit = match &self.background {
+                    Some(semaphore) => Some(
+                        semaphore
+                            .acquire()
+                            .await
+                            .expect("semaphore is never closed"),
+                    ),
+                    None => None,
+                };
+                if foreground.over_budget(self.config.foreground_latency_budget) {
+                    let _ = tokio::time::timeout(
+                        self.config.max_background_delay,
+                        foreground.drained(),
+                    )
+                    .await;
+                }
+                BACKGROUND_READ_WAIT_TIME.observe(started_at.elapsed().as_secs_f64());
+                ReadPermit::Background { _permit: permit }
+            }
+        }
+    }
+}
+
+impl ForegroundReads {
+    fn over_budget(&self, budget: Duration) -> bool {
+        !budget.is_zero()
+            && self.in_progress.load(Ordering::Relaxed) > 0
+            && self.latency_micros.load(Ordering::Relaxed) > budget.as_micros() as u64
+    }
+
+    async fn drained(&self) {
+        loop {
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            // Register before checking, to not miss a notification in between.
+            notified.as_mut().enable();
+            if self.in_progress.load(Ordering::Relaxed) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    fn done(&self, latency: Duration) {
+        let sample = latency.as_micros() as u64;
+        let _ = self
+            .latency_micros
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+                Some(avg - avg / 8 + sample / 8)
+            });
+        if self.in_progress.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+}
+
+impl Drop for ReadPermit<'_> {
+    fn drop(&mut self) {
+        if let ReadPermit::Foreground { reads, started_at } = self {
+            reads.done(started_at.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::DownloadBehavior;
+
+    fn scheduler(budget: Duration, max_delay: Duration) -> ReadScheduler {
+        ReadScheduler::new(&ReadPriorityConfig {
+            background_concurrency: 1,
+            foreground_latency_budget: budget,
+            max_background_delay: max_delay,
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn background_gives_way_to_slow_foreground() {
+        let scheduler = scheduler(Duration::from_millis(1), Duration::from_secs(10));
+        let foreground = ForegroundReads::default();
+        let fg_ctx = RequestContext::new(TaskKind::PageRequestHandler, DownloadBehavior::Error);
+        let bg_ctx = RequestContext::new(TaskKind::Compaction, DownloadBehavior::Error);
+
+        // Make foreground reads slow on average.
+        for _ in 0..32 {
+            let permit = scheduler.admit(&fg_ctx, &foreground).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(permit);
+        }
+
+        let fg_permit = scheduler.admit(&fg_ctx, &foreground).await;
+        let started_at = Instant::now();
+        let background = scheduler.admit(&bg_ctx, &foreground);
+        tokio::pin!(background);
+        tokio::select! {
+            _ = &mut background => panic!("background read didn't wait"),
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+        drop(fg_permit);
+        let _bg_permit = background.await;
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn background_delay_is_bounded() {
+        let scheduler = scheduler(Duration::from_millis(1), Duration::from_millis(100));
+        let foreground = ForegroundReads::default();
+        let fg_ctx = RequestContext::new(TaskKind::PageRequestHandler, DownloadBehavior::Error);
+        let bg_ctx = RequestContext::new(TaskKind::Compaction, DownloadBehavior::Error);
+
+        for _ in 0..32 {
+            let permit = scheduler.admit(&fg_ctx, &foreground).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(permit);
+        }
+
+        let _fg_permit = scheduler.admit(&fg_ctx, &foreground).await;
+        let started_at = Instant::now();
+        let _bg_permit = scheduler.admit(&bg_ctx, &foreground).await;
+        assert!(started_at.elapsed() >= Duration::from_millis(100));
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn background_ignores_other_tenants() {
+        let scheduler = scheduler(Duration::from_millis(1), Duration::from_secs(10));
+        let busy_tenant = ForegroundReads::default();
+        let idle_tenant = ForegroundReads::default();
+        let fg_ctx = RequestContext::new(TaskKind::PageRequestHandler, DownloadBehavior::Error);
+        let bg_ctx = RequestContext::new(TaskKind::Compaction, DownloadBehavior::Error);
+
+        for _ in 0..32 {
+            let permit = scheduler.admit(&fg_ctx, &busy_tenant).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(permit);
+        }
+
+        let _fg_permit = scheduler.admit(&fg_ctx, &busy_tenant).await;
+        let started_at = Instant::now();
+        let _bg_permit = scheduler.admit(&bg_ctx, &idle_tenant).await;
+        assert_eq!(started_at.elapsed(), Duration::ZERO);
+    }
+}
@@ -39,6 +39,7 @@ use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
+use anyhow::Context;
 use futures::FutureExt;
 use pageserver_api::shard::TenantShardId;
 use tokio::task::JoinHandle;
@@ -47,13 +48,15 @@ use tokio_util::sync::CancellationToken;
 
 use tracing::{debug, error, info, warn};
 
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 
 use utils::env;
 use utils::id::TimelineId;
 
 use crate::metrics::set_tokio_runtime_setup;
 
+pub(crate) mod resource_usage;
+
 //
 // There are four runtimes:
 //
@@ -102,6 +105,11 @@ use crate::metrics::set_tokio_runtime_setup;
 // other operations, if the upload tasks e.g. get blocked on locks. It shouldn't
 // happen, but still.
 //
+// The number of worker threads of each runtime, and the CPUs they run on, can be
+// configured in the `[runtimes]` section of pageserver.toml, see [`RuntimesConfig`].
+// Pinning e.g. the background runtime to a separate set of CPUs than the compute
+// request runtime keeps compaction CPU spikes from affecting GetPage latency.
+//
 
 pub(crate) static TOKIO_WORKER_THREADS: Lazy<NonZeroUsize> = Lazy::new(|| {
     // replicates tokio-1.28.1::loom::sys::num_cpus which is not available publicly
@@ -146,15 +154,168 @@ impl FromStr for TokioRuntimeMode {
     }
 }
 
+/// Configuration of a single tokio runtime, see [`RuntimesConfig`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// Number of worker threads. Defaults to the number of CPUs, or `TOKIO_WORKER_THREADS`.
+    pub worker_threads: Option<NonZeroUsize>,
+    /// Pin the runtime's threads to these CPUs, e.g. `"0-3,8"`.
+    pub cpus: Option<CpuList>,
+    /// Pin the runtime's threads to the CPUs of this NUMA node.
+    /// Mutually exclusive with `cpus`.
+    pub numa_node: Option<usize>,
+}
+
+impl RuntimeConfig {
+    fn worker_threads(&self) -> NonZeroUsize {
+        self.worker_threads.unwrap_or(*TOKIO_WORKER_THREADS)
+    }
+
+    /// Replace `numa_node` with the list of its CPUs, so that only `cpus` needs to be
+    /// looked at when building the runtime.
+    fn resolve(mut self, name: &str) -> anyhow::Result<Self> {
+        if let Some(node) = self.numa_node.take() {
+            anyhow::ensure!(
+                self.cpus.is_none(),
+                "runtime {name}: cpus and numa_node are mutually exclusive"
+            );
+            self.cpus = Some(CpuList::of_numa_node(node).with_context(|| {
+                format!("runtime {name}: failed to get the CPUs of NUMA node {node}")
+            })?);
+        }
+        if self.cpus.is_some() {
+            anyhow::ensure!(
+                cfg!(target_os = "linux"),
+                "runtime {name}: CPU pinning is only supported on Linux"
+            );
+        }
+        Ok(self)
+    }
+}
+
+/// Configuration of the pageserver's tokio runtimes, the `[runtimes]` section of pageserver.toml:
+///
+/// ```toml
+/// [runtimes]
+/// compute_request = { worker_threads = 8, cpus = "0-7" }
+/// background = { worker_threads = 4, numa_node = 1 }
+/// ```
+///
+/// Ignored if `NEON_PAGESERVER_USE_ONE_RUNTIME` is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimesConfig {
+    pub compute_request: RuntimeConfig,
+    pub mgmt_request: RuntimeConfig,
+    pub walreceiver: RuntimeConfig,
+    pub background: RuntimeConfig,
+}
+
+impl RuntimesConfig {
+    fn total_worker_threads(&self) -> NonZeroUsize {
+        [
+            &self.compute_request,
+            &self.mgmt_request,
+            &self.walreceiver,
+            &self.background,
+        ]
+        .into_iter()
+        .map(RuntimeConfig::worker_threads)
+        .reduce(|a, b| a.checked_add(b.get()).unwrap())
+        .unwrap()
+    }
+}
+
+/// A set of CPU indices, written as a comma-separated list of CPUs and CPU ranges,
+/// like in `/sys/devices/system/node/node0/cpulist`.
+#[derive(Debug, Clone, PartialEq, Eq, serde_with::DeserializeFromStr)]
+pub struct CpuList(Vec<usize>);
+
+impl CpuList {
+    fn of_numa_node(node: usize) -> anyhow::Result<Self> {
+        let path = format!("/sys/devices/system/node/node{node}/cpulist");
+        let cpulist = std::fs::read_to_string(&path).with_context(|| format!("read {path}"))?;
+        cpulist.trim().parse().map_err(|e| anyhow::anyhow!("{e}"))
+    }
+}
+
+impl FromStr for CpuList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_cpu = |cpu: &str| {
+            cpu.trim()
+                .parse::<usize>()
+                .map_err(|e| format!("invalid CPU {cpu:?} in cpu list {s:?}: {e}"))
+        };
+        let mut cpus = Vec::new();
+        for item in s.split(',') {
+            match item.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse_cpu(first)?, parse_cpu(last)?);
+                    if first > last {
+                        return Err(format!("invalid CPU range {item:?} in cpu list {s:?}"));
+                    }
+                    cpus.extend(first..=last);
+                }
+                None => cpus.push(parse_cpu(item)?),
+            }
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        Ok(CpuList(cpus))
+    }
+}
+
+static RUNTIMES_CONFIG: OnceCell<RuntimesConfig> = OnceCell::new();
+
+/// Install the configuration of the runtimes. Must be called before any of the
+/// runtimes is used, otherwise they are already running with the default configuration.
+pub fn configure_runtimes(config: RuntimesConfig) -> anyhow::Result<()> {
+    let config = RuntimesConfig {
+        compute_request: config.compute_request.resolve("compute_request")?,
+        mgmt_request: config.mgmt_request.resolve("mgmt_request")?,
+        walreceiver: config.walreceiver.resolve("walreceiver")?,
+        background: config.background.resolve("background")?,
+    };
+    RUNTIMES_CONFIG
+        .set(config)
+        .map_err(|_| anyhow::anyhow!("runtimes have already been started"))
+}
+
+fn runtimes_config() -> &'static RuntimesConfig {
+    RUNTIMES_CONFIG.get_or_init(RuntimesConfig::default)
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpus: &CpuList) {
+    use nix::sched::{sched_setaffinity, CpuSet};
+    use nix::unistd::Pid;
+
+    let mut cpu_set = CpuSet::new();
+    for &cpu in &cpus.0 {
+        if let Err(e) = cpu_set.set(cpu) {
+            warn!("cannot pin thread to CPU {cpu}: {e}");
+        }
+    }
+    if let Err(e) = sched_setaffinity(Pid::from_raw(0), &cpu_set) {
+        warn!("failed to pin thread to CPUs {:?}: {e}", cpus.0);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpus: &CpuList) {
+    unreachable!("rejected by RuntimeConfig::resolve")
+}
+
 static ONE_RUNTIME: Lazy<Option<tokio::runtime::Runtime>> = Lazy::new(|| {
     let thread_name = "pageserver-tokio";
     let Some(mode) = env::var("NEON_PAGESERVER_USE_ONE_RUNTIME") else {
         // If the env var is not set, leave this static as None.
         set_tokio_runtime_setup(
             "multiple-runtimes",
-            NUM_MULTIPLE_RUNTIMES
-                .checked_mul(*TOKIO_WORKER_THREADS)
-                .unwrap(),
+            runtimes_config().total_worker_threads(),
         );
         return None;
     };
@@ -187,18 +348,25 @@ static ONE_RUNTIME: Lazy<Option<tokio::runtime::Runtime>> = Lazy::new(|| {
 ///
 /// The result is is that `$varname.spawn()` will use `ONE_RUNTIME` if
 /// `NEON_PAGESERVER_USE_ONE_RUNTIME` is set, and will use the separate runtime
-/// otherwise.
+/// otherwise. The separate runtime is built according to the `$config` field of
+/// [`RuntimesConfig`].
 macro_rules! pageserver_runtime {
-    ($varname:ident, $name:literal) => {
+    ($varname:ident, $name:literal, $config:ident) => {
         pub static $varname: Lazy<&'static tokio::runtime::Runtime> = Lazy::new(|| {
             if let Some(runtime) = &*ONE_RUNTIME {
                 return runtime;
             }
             static RUNTIME: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
-                tokio::runtime::Builder::new_multi_thread()
+                let config = &runtimes_config().$config;
+                let mut builder = tokio::runtime::Builder::new_multi_thread();
+                builder
                     .thread_name($name)
-                    .worker_threads(TOKIO_WORKER_THREADS.get())
-                    .enable_all()
+                    .worker_threads(config.worker_threads().get())
+                    .enable_all();
+                if let Some(cpus) = config.cpus.clone() {
+                    builder.on_thread_start(move || pin_current_thread(&cpus));
+                }
+                builder
                     .build()
                     .expect(std::concat!("Failed to create runtime ", $name))
             });
@@ -207,13 +375,15 @@ macro_rules! pageserver_runtime {
     };
 }
 
-pageserver_runtime!(COMPUTE_REQUEST_RUNTIME, "compute request worker");
-pageserver_runtime!(MGMT_REQUEST_RUNTIME, "mgmt request worker");
-pageserver_runtime!(WALRECEIVER_RUNTIME, "walreceiver worker");
-pageserver_runtime!(BACKGROUND_RUNTIME, "background op worker");
-// Bump this number when adding a new pageserver_runtime!
-// SAFETY: it's obviously correct
-const NUM_MULTIPLE_RUNTIMES: NonZeroUsize = unsafe { NonZeroUsize::new_unchecked(4) };
+// Add a field to RuntimesConfig when adding a new pageserver_runtime!
+pageserver_runtime!(
+    COMPUTE_REQUEST_RUNTIME,
+    "compute request worker",
+    compute_request
+);
+pageserver_runtime!(MGMT_REQUEST_RUNTIME, "mgmt request worker", mgmt_request);
+pageserver_runtime!(WALRECEIVER_RUNTIME, "walreceiver worker", walreceiver);
+pageserver_runtime!(BACKGROUND_RUNTIME, "background op worker", background);
 
 #[derive(Debug, Clone, Copy)]
 pub struct PageserverTaskId(u64);
@@ -275,6 +445,10 @@ pub enum TaskKind {
     // associated with one later, after receiving a command from the client.
     PageRequestHandler,
 
+    /// Reads ahead of the sequential scans of a PageRequestHandler connection, see
+    /// [`crate::page_service::prefetch`].
+    PageRequestPrefetch,
+
     /// Manages the WAL receiver connection for one timeline.
     /// It subscribes to events from storage_broker and decides which safekeeper to connect to.
     /// Once the decision has been made, it establishes the connection using the `tokio-postgres` library.
@@ -322,15 +496,30 @@ pub enum TaskKind {
     // Ingest housekeeping (flushing ephemeral layers on time threshold or disk pressure)
     IngestHousekeeping,
 
+    /// See [`crate::tenant::remote_timeline_client::hot_tier`].
+    HotTierMigration,
+
     /// See [`crate::disk_usage_eviction_task`].
     DiskUsageEviction,
 
+    /// See [`crate::tenant::mgr::spawn_detached_tenants_janitor`].
+    DetachedTenantsJanitor,
+
+    /// See [`crate::layer_verification`].
+    LayerVerification,
+
+    /// See [`crate::temp_file_janitor`].
+    TempFileJanitor,
+
     /// See [`crate::tenant::secondary`].
     SecondaryDownloads,
 
     /// See [`crate::tenant::secondary`].
     SecondaryUploads,
 
+    /// See [`crate::tenant::secondary`].
+    SecondaryIndexRefresh,
+
     // Initial logical size calculation
     InitialLogicalSizeCalculation,
 
@@ -368,10 +557,16 @@ pub enum TaskKind {
 
     LayerDownload,
 
-    #[cfg(test)]
+    #[cfg(any(feature = "testing", test))]
     UnitTest,
 
     DetachAncestor,
+
+    /// See [`crate::tenant::timeline::branch_images`].
+    BranchImageCreation,
+
+    /// See [`crate::timeline_events`].
+    TimelineEventPublisher,
 }
 
 #[derive(Default)]
@@ -396,6 +591,9 @@ struct PageServerTask {
     tenant_shard_id: Option<TenantShardId>,
     timeline_id: Option<TimelineId>,
 
+    /// Where the resources used by the task are accounted, for tasks of a tenant.
+    usage: Option<Arc<resource_usage::Usage>>,
+
     mutable: Mutex<MutableTaskState>,
 }
 
@@ -423,6 +621,7 @@ where
         cancel: cancel.clone(),
         tenant_shard_id,
         timeline_id,
+        usage: tenant_shard_id.map(|id| resource_usage::for_task(id, kind)),
         mutable: Mutex::new(MutableTaskState { join_handle: None }),
     });
 
@@ -461,6 +660,7 @@ async fn task_wrapper<F>(
 {
     debug!("Starting task '{}'", task_name);
 
+    let usage = task.usage.clone();
     let result = SHUTDOWN_TOKEN
         .scope(
             shutdown_token,
@@ -468,7 +668,7 @@ async fn task_wrapper<F>(
                 // We use AssertUnwindSafe here so that the payload function
                 // doesn't need to be UnwindSafe. We don't do anything after the
                 // unwinding that would expose us to unwind-unsafe behavior.
-                AssertUnwindSafe(future).catch_unwind()
+                AssertUnwindSafe(resource_usage::Accounted::new(future, usage)).catch_unwind()
             }),
         )
         .await;
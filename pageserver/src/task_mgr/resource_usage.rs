@@ -0,0 +1,177 @@
+//! Accounting of the resources used by the tasks of each tenant.
+//!
+//! A few busy tenants can slow down all others on a pageserver, and the usual suspects are
+//! their compaction and GC. To tell which tenants they are, tasks spawned with
+//! [`super::spawn`] for a tenant shard account, per task kind:
+//! - the CPU time of the thread while polling the task's future. Work offloaded to
+//!   `spawn_blocking` or to other tasks is not included.
+//! - the bytes read and written through [`crate::virtual_file::VirtualFile`].
+//!
+//! The usage is exported as metrics, and by the `/v1/tenant/:tenant_shard_id/resource_usage`
+//! API. It counts from when the tenant shard was attached to this pageserver.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use metrics::{Counter, IntCounter};
+use nix::time::{clock_gettime, ClockId};
+use once_cell::sync::Lazy;
+use pageserver_api::models::{TaskResourceUsage, TenantResourceUsage};
+use pageserver_api::shard::TenantShardId;
+use pin_project_lite::pin_project;
+
+use super::{TaskKind, CURRENT_TASK};
+use crate::metrics::{TASK_CPU_SECONDS, TASK_IO_BYTES};
+
+/// Resources used by the tasks of one kind of a tenant shard.
+pub(super) struct Usage {
+    cpu_seconds: Counter,
+    read_bytes: IntCounter,
+    written_bytes: IntCounter,
+}
+
+static USAGE: Lazy<Mutex<HashMap<TenantShardId, HashMap<TaskKind, Arc<Usage>>>>> =
+    Lazy::new(Default::default);
+
+pub(super) fn for_task(tenant_shard_id: TenantShardId, kind: TaskKind) -> Arc<Usage> {
+    let mut usage = USAGE.lock().unwrap();
+    let by_kind = usage.entry(tenant_shard_id).or_default();
+    Arc::clone(by_kind.entry(kind).or_insert_with(|| {
+        let tenant_id = tenant_shard_id.tenant_id.to_string();
+        let shard_id = tenant_shard_id.shard_slug().to_string();
+        let kind: &'static str = kind.into();
+        Arc::new(Usage {
+            cpu_seconds: TASK_CPU_SECONDS.with_label_values(&[&tenant_id, &shard_id, kind]),
+            read_bytes: TASK_IO_BYTES.with_label_values(&["read", &tenant_id, &shard_id, kind]),
+            written_bytes: TASK_IO_BYTES.with_label_values(&["write", &tenant_id, &shard_id, kind]),
+        })
+    }))
+}
+
+/// The resources used by the tasks of a tenant shard so far, by task kind.
+pub(crate) fn get(tenant_shard_id: &TenantShardId) -> TenantResourceUsage {
+    let usage = USAGE.lock().unwrap();
+    let mut tasks = usage
+        .get(tenant_shard_id)
+        .into_iter()
+        .flatten()
+        .map(|(kind, usage)| TaskResourceUsage {
+            task_kind: <&'static str>::from(*kind).to_owned(),
+            cpu_seconds: usage.cpu_seconds.get(),
+            read_bytes: usage.read_bytes.get(),
+            written_bytes: usage.written_bytes.get(),
+        })
+        .collect::<Vec<_>>();
+    tasks.sort_by(|a, b| a.task_kind.cmp(&b.task_kind));
+    TenantResourceUsage { tasks }
+}
+
+/// Forget the usage of a tenant shard, once all its tasks have exited.
+pub(crate) fn remove(tenant_shard_id: &TenantShardId) {
+    let Some(by_kind) = USAGE.lock().unwrap().remove(tenant_shard_id) else {
+        return;
+    };
+    let tenant_id = tenant_shard_id.tenant_id.to_string();
+    let shard_id = tenant_shard_id.shard_slug().to_string();
+    for kind in by_kind.into_keys() {
+        let kind: &'static str = kind.into();
+        let _ = TASK_CPU_SECONDS.remove_label_values(&[&tenant_id, &shard_id, kind]);
+        for op in ["read", "write"] {
+            let _ = TASK_IO_BYTES.remove_label_values(&[op, &tenant_id, &shard_id, kind]);
+        }
+    }
+}
+
+/// Account bytes read by the current task.
+pub(crate) fn record_read(bytes: usize) {
+    let _ = CURRENT_TASK.try_with(|task| {
+        if let Some(usage) = &task.usage {
+            usage.read_bytes.inc_by(bytes as u64);
+        }
+    });
+}
+
+/// Account bytes written by the current task.
+pub(crate) fn record_write(bytes: usize) {
+    let _ = CURRENT_TASK.try_with(|task| {
+        if let Some(usage) = &task.usage {
+            usage.written_bytes.inc_by(bytes as u64);
+        }
+    });
+}
+
+fn thread_cpu_time() -> Duration {
+    match clock_gettime(ClockId::CLOCK_THREAD_CPUTIME_ID) {
+        Ok(ts) => Duration::new(ts.tv_sec() as u64, ts.tv_nsec() as u32),
+        Err(_) => Duration::ZERO,
+    }
+}
+
+pin_project! {
+    /// Accounts the CPU time of polling `inner` to `usage`.
+    pub(super) struct Accounted<F> {
+        #[pin]
+        inner: F,
+        usage: Option<Arc<Usage>>,
+    }
+}
+
+impl<F> Accounted<F> {
+    pub(super) fn new(inner: F, usage: Option<Arc<Usage>>) -> Self {
+        Accounted { inner, usage }
+    }
+}
+
+impl<F: Future> Future for Accounted<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let Some(usage) = this.usage else {
+            return this.inner.poll(cx);
+        };
+        // A poll runs on a single thread, so the thread's CPU time spent in it is the task's.
+        let start = thread_cpu_time();
+        let res = this.inner.poll(cx);
+        let used = thread_cpu_time().saturating_sub(start);
+        usage.cpu_seconds.inc_by(used.as_secs_f64());
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::id::TenantId;
+
+    #[tokio::test]
+    async fn accounts_cpu_time_of_polls() {
+        let tenant_shard_id = TenantShardId::unsharded(TenantId::generate());
+        let usage = for_task(tenant_shard_id, TaskKind::Compaction);
+
+        Accounted::new(
+            async {
+                let start = thread_cpu_time();
+                while thread_cpu_time() - start < Duration::from_millis(20) {
+                    std::hint::spin_loop();
+                }
+            },
+            Some(Arc::clone(&usage)),
+        )
+        .await;
+        usage.read_bytes.inc_by(100);
+
+        let reported = get(&tenant_shard_id);
+        assert_eq!(reported.tasks.len(), 1);
+        assert_eq!(reported.tasks[0].task_kind, "Compaction");
+        assert!(reported.tasks[0].cpu_seconds >= 0.02);
+        assert_eq!(reported.tasks[0].read_bytes, 100);
+
+        remove(&tenant_shard_id);
+        assert!(get(&tenant_shard_id).tasks.is_empty());
+    }
+}
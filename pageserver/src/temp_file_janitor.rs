@@ -0,0 +1,318 @@
+//! Periodic removal of stale temporary files and directories.
+//!
+//! Files are written under a temporary name, with [`crate::TEMP_FILE_SUFFIX`] or the extension
+//! of layer downloads, and renamed into place once complete. A crash or a failed operation
+//! leaves the temporaries behind: the `basebackup-*` directories of timeline creations, initdb
+//! archives, layers being written or downloaded, renamed tenant directories awaiting deletion.
+//! They are otherwise only removed when the tenant or timeline is loaded, which may not happen
+//! again for a long time on a pageserver that isn't restarted.
+//!
+//! Every `temp_file_janitor_period`, the task started by [`launch_temp_file_janitor`] scans
+//! the tenants directory and the tenant data dirs down to the timeline directories, and removes the temporaries that
+//! weren't modified for `temp_file_max_age`. The age is what keeps the temporaries of
+//! operations in progress safe, as they are written to continuously: the threshold must be
+//! well above the longest pause of any of them. A temporary directory was last modified when
+//! the newest file in it was. The removed temporaries and their sizes are
+//! counted in `pageserver_temp_files_removed_total` and
+//! `pageserver_temp_files_removed_bytes_total`.
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use camino::Utf8Path;
+use tracing::{info, warn, Instrument};
+use utils::completion;
+
+use crate::config::PageServerConf;
+use crate::metrics::{TEMP_FILES_REMOVED, TEMP_FILES_REMOVED_BYTES};
+use crate::task_mgr::{self, TaskKind, BACKGROUND_RUNTIME};
+use crate::tenant::data_dirs;
+use crate::tenant::remote_timeline_client::is_temp_download_file;
+
+/// `tenants/{tenant_shard_id}/timelines/{timeline_id}/`: the deepest directory with
+/// temporaries, counting from the tenants directory.
+const MAX_DEPTH: usize = 3;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Reclaimed {
+    files: u64,
+    bytes: u64,
+}
+
+pub fn launch_temp_file_janitor(
+    conf: &'static PageServerConf,
+    background_jobs_barrier: completion::Barrier,
+) {
+    let period = conf.temp_file_janitor_period;
+    if period == Duration::ZERO {
+        info!("temp file janitor disabled");
+        return;
+    }
+    let max_age = conf.temp_file_max_age;
+
+    task_mgr::spawn(
+        BACKGROUND_RUNTIME.handle(),
+        TaskKind::TempFileJanitor,
+        None,
+        None,
+        "temp file janitor",
+        false,
+        async move {
+            let cancel = task_mgr::shutdown_token();
+
+            tokio::select! {
+                _ = cancel.cancelled() => { return Ok(()); },
+                _ = background_jobs_barrier.wait() => { }
+            };
+
+            loop {
+                let data_dirs = data_dirs::data_dirs(conf);
+                let res = tokio::task::spawn_blocking(move || {
+                    let mut reclaimed = Reclaimed::default();
+                    for data_dir in &data_dirs {
+                        let r = remove_stale_temporaries(data_dir, max_age, SystemTime::now());
+                        reclaimed.files += r.files;
+                        reclaimed.bytes += r.bytes;
+                    }
+                    reclaimed
+                })
+                .await;
+                match res {
+                    Ok(reclaimed) if reclaimed.files > 0 => info!(
+                        files = reclaimed.files,
+                        bytes = reclaimed.bytes,
+                        "removed stale temporary files"
+                    ),
+                    Ok(_) => {}
+                    Err(e) => warn!("temp file janitor pass panicked: {e}"),
+                }
+
+                if tokio::time::timeout(period, cancel.cancelled())
+                    .await
+                    .is_ok()
+                {
+                    return Ok(());
+                }
+            }
+        }
+        .instrument(tracing::info_span!("temp_file_janitor")),
+    );
+}
+
+fn is_temporary(path: &Utf8Path) -> bool {
+    crate::is_temporary(path) || is_temp_download_file(path)
+}
+
+/// Remove the temporaries under `tenants_path` that weren't modified for `max_age`, and
+/// return how many there were. Errors are logged and don't stop the scan.
+fn remove_stale_temporaries(
+    tenants_path: &Utf8Path,
+    max_age: Duration,
+    now: SystemTime,
+) -> Reclaimed {
+    let mut reclaimed = Reclaimed::default();
+    scan_dir(tenants_path, 0, max_age, now, &mut reclaimed);
+    TEMP_FILES_REMOVED.inc_by(reclaimed.files);
+    TEMP_FILES_REMOVED_BYTES.inc_by(reclaimed.bytes);
+    reclaimed
+}
+
+fn scan_dir(
+    dir: &Utf8Path,
+    depth: usize,
+    max_age: Duration,
+    now: SystemTime,
+    reclaimed: &mut Reclaimed,
+) {
+    let entries = match dir.read_dir_utf8() {
+        Ok(entries) => entries,
+        // Deleted concurrently, with its tenant or timeline.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            warn!("failed to list {dir}: {e}");
+            return;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("failed to list {dir}: {e}");
+                return;
+            }
+        };
+        let path = entry.path();
+        let metadata = match path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                warn!("failed to stat {path}: {e}");
+                continue;
+            }
+        };
+
+        if !is_temporary(path) {
+            if metadata.is_dir() && depth < MAX_DEPTH {
+                scan_dir(path, depth + 1, max_age, now, reclaimed);
+            }
+            continue;
+        }
+
+        let modified = match last_modified(path, &metadata) {
+            Ok(modified) => modified,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                warn!("failed to get the modification time of {path}: {e}");
+                continue;
+            }
+        };
+        let age = now.duration_since(modified).unwrap_or(Duration::ZERO);
+        if age < max_age {
+            continue;
+        }
+        match remove(path, &metadata) {
+            Ok(bytes) => {
+                info!(?age, bytes, "removed stale temporary {path}");
+                reclaimed.files += 1;
+                reclaimed.bytes += bytes;
+            }
+            Err(e) => warn!("failed to remove stale temporary {path}: {e:#}"),
+        }
+    }
+}
+
+/// When `path` was last modified: for a directory, when the newest file in it was, as writing
+/// a file doesn't change the modification time of the directories above it.
+fn last_modified(path: &Utf8Path, metadata: &std::fs::Metadata) -> std::io::Result<SystemTime> {
+    if metadata.is_symlink() {
+        // A renamed tenant directory that links into a data dir.
+        let target = path.metadata()?;
+        return Ok(metadata.modified()?.max(newest_modified(path, &target)?));
+    }
+    newest_modified(path, metadata)
+}
+
+fn newest_modified(path: &Utf8Path, metadata: &std::fs::Metadata) -> std::io::Result<SystemTime> {
+    let mut modified = metadata.modified()?;
+    if metadata.is_dir() {
+        for entry in path.read_dir_utf8()? {
+            let entry = entry?;
+            let metadata = entry.path().symlink_metadata()?;
+            modified = modified.max(newest_modified(entry.path(), &metadata)?);
+        }
+    }
+    Ok(modified)
+}
+
+/// Remove a file or a directory, and return the size of the files it contained.
+fn remove(path: &Utf8Path, metadata: &std::fs::Metadata) -> anyhow::Result<u64> {
+    if metadata.is_symlink() {
+        // A renamed tenant directory that links into a data dir.
+        let bytes = dir_size(path).with_context(|| format!("size of {path}"))?;
+        data_dirs::remove_tenant_dir(path, true)?;
+        Ok(bytes)
+    } else if metadata.is_dir() {
+        let bytes = dir_size(path).with_context(|| format!("size of {path}"))?;
+        std::fs::remove_dir_all(path)?;
+        Ok(bytes)
+    } else {
+        std::fs::remove_file(path)?;
+        Ok(metadata.len())
+    }
+}
+
+fn dir_size(dir: &Utf8Path) -> std::io::Result<u64> {
+    let mut bytes = 0;
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        let metadata = entry.path().symlink_metadata()?;
+        if metadata.is_dir() {
+            bytes += dir_size(entry.path())?;
+        } else {
+            bytes += metadata.len();
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_only_stale_temporaries() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let tenants = dir.path().join("tenants");
+        let timelines = tenants.join("tenant").join("timelines");
+        let timeline = timelines.join("timeline");
+        std::fs::create_dir_all(&timeline).unwrap();
+
+        let layer = timeline.join("layer");
+        std::fs::write(&layer, [0; 10]).unwrap();
+        std::fs::write(timeline.join("layer.abcd.___temp"), [0; 20]).unwrap();
+        std::fs::write(timeline.join("layer.temp_download"), [0; 30]).unwrap();
+        let basebackup = timelines.join("basebackup-timeline.___temp");
+        std::fs::create_dir_all(basebackup.join("base")).unwrap();
+        std::fs::write(basebackup.join("base").join("1"), [0; 40]).unwrap();
+        std::fs::write(basebackup.join("PG_VERSION"), [0; 2]).unwrap();
+
+        // Nothing is old enough yet.
+        let now = SystemTime::now();
+        let reclaimed = remove_stale_temporaries(&tenants, Duration::from_secs(3600), now);
+        assert_eq!(reclaimed, Reclaimed::default());
+
+        let later = now + Duration::from_secs(7200);
+        let reclaimed = remove_stale_temporaries(&tenants, Duration::from_secs(3600), later);
+        assert_eq!(
+            reclaimed,
+            Reclaimed {
+                files: 3,
+                bytes: 20 + 30 + 40 + 2
+            }
+        );
+        assert!(layer.exists());
+        assert!(!basebackup.exists());
+        assert_eq!(timeline.read_dir_utf8().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn directory_age_is_its_newest_file() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let tenants = dir.path().join("tenants");
+        let basebackup = tenants
+            .join("tenant")
+            .join("timelines")
+            .join("basebackup-timeline.___temp");
+        let base = basebackup.join("base");
+        std::fs::create_dir_all(&base).unwrap();
+        let file = base.join("1");
+        std::fs::write(&file, [0; 40]).unwrap();
+
+        let now = SystemTime::now();
+        let set_modified = |path: &Utf8Path, modified| {
+            std::fs::File::open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap()
+        };
+        let hours_ago = |hours| now - Duration::from_secs(hours * 3600);
+
+        // The directories are old, but the file in them is still being written.
+        set_modified(&basebackup, hours_ago(2));
+        set_modified(&base, hours_ago(2));
+        let reclaimed = remove_stale_temporaries(&tenants, Duration::from_secs(3600), now);
+        assert_eq!(reclaimed, Reclaimed::default());
+        assert!(file.exists());
+
+        set_modified(&file, hours_ago(2));
+        let reclaimed = remove_stale_temporaries(&tenants, Duration::from_secs(3600), now);
+        assert_eq!(
+            reclaimed,
+            Reclaimed {
+                files: 1,
+                bytes: 40
+            }
+        );
+        assert!(!basebackup.exists());
+    }
+}
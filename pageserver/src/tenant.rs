@@ -13,13 +13,17 @@
 
 use anyhow::{bail, Context};
 use arc_swap::ArcSwap;
+use async_compression::tokio::bufread::ZstdDecoder;
+use async_compression::tokio::write::ZstdEncoder;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use enumset::EnumSet;
 use futures::stream::FuturesUnordered;
 use futures::FutureExt;
 use futures::StreamExt;
+use metrics::UIntGauge;
 use pageserver_api::models;
+use pageserver_api::models::TenantActivationPhase;
 use pageserver_api::models::TimelineState;
 use pageserver_api::models::WalRedoManagerStatus;
 use pageserver_api::shard::ShardIdentity;
@@ -30,7 +34,7 @@ use remote_storage::GenericRemoteStorage;
 use remote_storage::TimeoutOrCancel;
 use std::fmt;
 use storage_broker::BrokerClientChannel;
-use tokio::io::BufReader;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::sync::watch;
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
@@ -44,8 +48,7 @@ use utils::sync::gate::Gate;
 use utils::sync::gate::GateGuard;
 use utils::timeout::timeout_cancellable;
 use utils::timeout::TimeoutCancellableError;
-use utils::zstd::create_zst_tarball;
-use utils::zstd::extract_zst_tarball;
+use utils::zstd::write_tarball;
 
 use self::config::AttachedLocationConfig;
 use self::config::AttachmentMode;
@@ -58,6 +61,7 @@ use self::mgr::GetTenantError;
 use self::mgr::TenantsMap;
 use self::remote_timeline_client::upload::upload_index_part;
 use self::remote_timeline_client::RemoteTimelineClient;
+use self::storage_layer::inmemory_layer::EphemeralResources;
 use self::timeline::uninit::TimelineCreateGuard;
 use self::timeline::uninit::TimelineExclusionError;
 use self::timeline::uninit::UninitializedTimeline;
@@ -73,8 +77,10 @@ use crate::import_datadir;
 use crate::is_uninit_mark;
 use crate::metrics::TENANT;
 use crate::metrics::{
-    remove_tenant_metrics, BROKEN_TENANTS_SET, TENANT_STATE_METRIC, TENANT_SYNTHETIC_SIZE_METRIC,
+    remove_tenant_metrics, BROKEN_TENANTS_SET, COMPACTION_DEBT, TENANT_STATE_METRIC,
+    TENANT_SYNTHETIC_SIZE_METRIC,
 };
+use crate::read_priority::ForegroundReads;
 use crate::repository::GcResult;
 use crate::task_mgr;
 use crate::task_mgr::TaskKind;
@@ -82,6 +88,7 @@ use crate::tenant::config::LocationMode;
 use crate::tenant::config::TenantConfOpt;
 pub use crate::tenant::remote_timeline_client::index::IndexPart;
 use crate::tenant::remote_timeline_client::remote_initdb_archive_path;
+use crate::tenant::remote_timeline_client::snapshot::{self, SnapshotId};
 use crate::tenant::remote_timeline_client::MaybeDeletedIndexPart;
 use crate::tenant::remote_timeline_client::INITDB_PATH;
 use crate::tenant::storage_layer::DeltaLayer;
@@ -103,9 +110,8 @@ use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
 use crate::span;
-use crate::tenant::timeline::delete::DeleteTimelineFlow;
+use crate::tenant::timeline::delete::{read_deletion_progress, DeleteTimelineFlow};
 use crate::tenant::timeline::uninit::cleanup_timeline_directory;
-use crate::virtual_file::VirtualFile;
 use crate::walredo::PostgresRedoManager;
 use crate::TEMP_FILE_SUFFIX;
 use once_cell::sync::Lazy;
@@ -114,7 +120,6 @@ use tokio::sync::Semaphore;
 
 static INIT_DB_SEMAPHORE: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(8));
 use utils::{
-    crashsafe,
     generation::Generation,
     id::TimelineId,
     lsn::{Lsn, RecordLsn},
@@ -152,16 +157,32 @@ pub mod vectored_blob_io;
 
 pub mod disk_btree;
 pub(crate) mod ephemeral_file;
+pub(crate) mod format_migrations;
+pub(crate) mod getpage_latency;
 pub mod layer_map;
 
 pub mod metadata;
 pub mod remote_timeline_client;
 pub mod storage_layer;
 
+pub(crate) mod activation_profile;
+pub(crate) mod bundle;
 pub mod config;
+pub(crate) mod config_templates;
+pub(crate) mod crash_points;
+pub(crate) mod data_dirs;
 pub mod delete;
+pub(crate) mod initdb_cache;
+pub(crate) mod legal_hold;
 pub mod mgr;
+pub(crate) mod offload;
+pub(crate) mod rebalance;
 pub mod secondary;
+pub(crate) mod selftest;
+pub(crate) mod stale_timelines;
+pub(crate) mod startup_repair;
+pub(crate) mod state_machine;
+pub(crate) mod tags;
 pub mod tasks;
 pub mod upload_queue;
 
@@ -171,6 +192,10 @@ pub mod size;
 
 pub(crate) mod throttle;
 
+pub(crate) mod group_fsync;
+
+pub(crate) mod par_fsync;
+
 pub(crate) use crate::span::debug_assert_current_span_has_tenant_and_timeline_id;
 pub(crate) use timeline::{LogicalSizeCalculationCause, PageReconstructError, Timeline};
 
@@ -255,8 +280,23 @@ pub struct Tenant {
     /// <https://github.com/neondatabase/neon/issues/4025>
     constructed_at: Instant,
 
+    /// Where the activation spent its time, see [`activation_profile`].
+    pub(crate) activation_profile: activation_profile::ActivationProfile,
+
+    /// Samples of the counters the load score rates are computed from, see [`rebalance`].
+    pub(crate) load_sampler: rebalance::LoadSampler,
+
+    /// `pageserver_compaction_debt_bytes` of the tenant shard, see [`Tenant::update_compaction_debt`].
+    compaction_debt_gauge: UIntGauge,
+
     state: watch::Sender<TenantState>,
 
+    /// The recent changes of `state`, which all go through it, see [`state_machine`].
+    pub(crate) state_transitions: state_machine::StateTransitions,
+
+    /// Blocks the destructive operations on the tenant while set, see [`legal_hold`].
+    pub(crate) legal_hold: legal_hold::LegalHoldState,
+
     // Overridden tenant-specific config parameters.
     // We keep TenantConfOpt sturct here to preserve the information
     // about parameters that are not set.
@@ -282,13 +322,16 @@ pub struct Tenant {
     /// **Lock order**: if acquring both, acquire`timelines` before `timelines_creating`
     timelines_creating: std::sync::Mutex<HashSet<TimelineId>>,
 
-    // This mutex prevents creation of new timelines during GC.
-    // Adding yet another mutex (in addition to `timelines`) is needed because holding
+    // This lock prevents creation of new timelines during GC.
+    // Adding yet another lock (in addition to `timelines`) is needed because holding
     // `timelines` mutex during all GC iteration
     // may block for a long time `get_timeline`, `get_timelines_state`,... and other operations
     // with timelines, which in turn may cause dropping replication connection, expiration of wait_for_lsn
     // timeout...
-    gc_cs: tokio::sync::Mutex<()>,
+    // GC takes it for writing. Branch creations take it for reading, so that concurrent ones
+    // don't wait for each other, and their directory fsyncs can be batched together, see
+    // [`group_fsync`].
+    gc_cs: tokio::sync::RwLock<()>,
     walredo_mgr: Option<Arc<WalRedoManager>>,
 
     // provides access to timeline data sitting in the remote storage
@@ -323,6 +366,14 @@ pub struct Tenant {
     pub(crate) timeline_get_throttle:
         Arc<throttle::Throttle<&'static crate::metrics::tenant_throttling::TimelineGet>>,
 
+    /// Accounting of the in-memory layers of all [`Tenant::timelines`], to enforce
+    /// [`TenantConf::ephemeral_bytes_limit`].
+    pub(crate) ephemeral_resources: Arc<EphemeralResources>,
+
+    /// The foreground reads of all [`Tenant::timelines`], which their background reads give
+    /// way to, see [`crate::read_priority`].
+    pub(crate) foreground_reads: Arc<ForegroundReads>,
+
     /// An ongoing timeline detach must be checked during attempts to GC or compact a timeline.
     ongoing_timeline_detach: std::sync::Mutex<Option<(TimelineId, utils::completion::Barrier)>>,
 }
@@ -335,7 +386,7 @@ impl std::fmt::Debug for Tenant {
 
 pub(crate) enum WalRedoManager {
     Prod(PostgresRedoManager),
-    #[cfg(test)]
+    #[cfg(any(feature = "testing", test))]
     Test(harness::TestRedoManager),
 }
 
@@ -345,7 +396,7 @@ impl From<PostgresRedoManager> for WalRedoManager {
     }
 }
 
-#[cfg(test)]
+#[cfg(any(feature = "testing", test))]
 impl From<harness::TestRedoManager> for WalRedoManager {
     fn from(mgr: harness::TestRedoManager) -> Self {
         Self::Test(mgr)
@@ -356,7 +407,17 @@ impl WalRedoManager {
     pub(crate) fn maybe_quiesce(&self, idle_timeout: Duration) {
         match self {
             Self::Prod(mgr) => mgr.maybe_quiesce(idle_timeout),
-            #[cfg(test)]
+            #[cfg(any(feature = "testing", test))]
+            Self::Test(_) => {
+                // Not applicable to test redo manager
+            }
+        }
+    }
+
+    pub(crate) fn maybe_recycle(&self) {
+        match self {
+            Self::Prod(mgr) => mgr.maybe_recycle(),
+            #[cfg(any(feature = "testing", test))]
             Self::Test(_) => {
                 // Not applicable to test redo manager
             }
@@ -379,7 +440,7 @@ impl WalRedoManager {
                 mgr.request_redo(key, lsn, base_img, records, pg_version)
                     .await
             }
-            #[cfg(test)]
+            #[cfg(any(feature = "testing", test))]
             Self::Test(mgr) => {
                 mgr.request_redo(key, lsn, base_img, records, pg_version)
                     .await
@@ -390,7 +451,7 @@ impl WalRedoManager {
     pub(crate) fn status(&self) -> Option<WalRedoManagerStatus> {
         match self {
             WalRedoManager::Prod(m) => Some(m.status()),
-            #[cfg(test)]
+            #[cfg(any(feature = "testing", test))]
             WalRedoManager::Test(_) => None,
         }
     }
@@ -430,6 +491,9 @@ pub enum DeleteTimelineError {
     #[error("Timeline deletion is already in progress")]
     AlreadyInProgress(Arc<tokio::sync::Mutex<DeleteTimelineFlow>>),
 
+    #[error(transparent)]
+    LegalHold(#[from] legal_hold::LegalHoldError),
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -440,6 +504,7 @@ impl Debug for DeleteTimelineError {
             Self::NotFound => write!(f, "NotFound"),
             Self::HasChildren(c) => f.debug_tuple("HasChildren").field(c).finish(),
             Self::AlreadyInProgress(_) => f.debug_tuple("AlreadyInProgress").finish(),
+            Self::LegalHold(e) => f.debug_tuple("LegalHold").field(e).finish(),
             Self::Other(e) => f.debug_tuple("Other").field(e).finish(),
         }
     }
@@ -448,6 +513,7 @@ impl Debug for DeleteTimelineError {
 pub enum SetStoppingError {
     AlreadyStopping(completion::Barrier),
     Broken,
+    InvalidTransition(state_machine::InvalidTransition),
 }
 
 impl Debug for SetStoppingError {
@@ -455,6 +521,7 @@ impl Debug for SetStoppingError {
         match self {
             Self::AlreadyStopping(_) => f.debug_tuple("AlreadyStopping").finish(),
             Self::Broken => write!(f, "Broken"),
+            Self::InvalidTransition(e) => f.debug_tuple("InvalidTransition").field(e).finish(),
         }
     }
 }
@@ -469,8 +536,14 @@ pub enum CreateTimelineError {
     AncestorLsn(anyhow::Error),
     #[error("ancestor timeline is not active")]
     AncestorNotActive,
+    #[error(transparent)]
+    PgVersion(#[from] crate::pg_versions::PgVersionError),
+    #[error(transparent)]
+    Snapshot(anyhow::Error),
     #[error("tenant shutting down")]
     ShuttingDown,
+    #[error("compaction debt of {debt} bytes is above the branch creation limit of {limit} bytes")]
+    CompactionDebt { debt: u64, limit: u64 },
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -556,6 +629,13 @@ impl Tenant {
                 .as_ref()
                 .unwrap()
                 .init_upload_queue(index_part)?;
+            if index_part.legal_hold != self.legal_hold.get() {
+                timeline
+                    .remote_client
+                    .as_ref()
+                    .unwrap()
+                    .set_legal_hold(self.legal_hold.get())?;
+            }
         } else if self.remote_storage.is_some() {
             // No data on the remote storage, but we have local metadata file. We can end up
             // here with timeline_create being interrupted before finishing index part upload.
@@ -704,15 +784,21 @@ impl Tenant {
                                 error!("attach failed, setting tenant state to Broken: {err:?}");
                             }
                         }
-                        t.state.send_modify(|state| {
+                        t.state.send_if_modified(|state| {
+                            // The attach task owns the tenant state until activation is complete.
                             // The Stopping case is for when we have passed control on to DeleteTenantFlow:
                             // if it errors, we will call make_broken when tenant is already in Stopping.
-                            assert!(
-                            matches!(*state, TenantState::Attaching | TenantState::Stopping { .. }),
-                            "the attach task owns the tenant state until activation is complete"
-                        );
-
-                            *state = TenantState::broken_from_reason(err.to_string());
+                            match t.state_transitions.apply(
+                                state,
+                                TenantState::broken_from_reason(err.to_string()),
+                                state_machine::TransitionCause::AttachFailed,
+                            ) {
+                                Ok(()) => true,
+                                Err(e) => {
+                                    error!("{e}");
+                                    false
+                                }
+                            }
                         });
                     };
 
@@ -741,11 +827,12 @@ impl Tenant {
                 }
 
                 let attach_type = if matches!(mode, SpawnMode::Lazy) {
+                    let queued_at = Instant::now();
                     // Before doing any I/O, wait for at least one of:
                     // - A client attempting to access to this tenant (on-demand loading)
                     // - A permit becoming available in the warmup semaphore (background warmup)
 
-                    tokio::select!(
+                    let attach_type = tokio::select!(
                         permit = tenant_clone.activate_now_sem.acquire() => {
                             let _ = permit.expect("activate_now_sem is never closed");
                             tracing::info!("Activating tenant (on-demand)");
@@ -770,7 +857,11 @@ impl Tenant {
                             make_broken(&tenant_clone, anyhow::anyhow!("Shut down while Attaching"), BrokenVerbosity::Info);
                             return Ok(());
                         },
-                    )
+                    );
+                    tenant_clone
+                        .activation_profile
+                        .record(TenantActivationPhase::Queued, queued_at.elapsed());
+                    attach_type
                 } else {
                     // SpawnMode::{Create,Eager} always cause jumping ahead of the
                     // concurrent_tenant_warmup queue
@@ -899,12 +990,15 @@ impl Tenant {
         // Get list of remote timelines
         // download index files for every tenant timeline
         info!("listing remote timelines");
+        let started_at = Instant::now();
         let (remote_timeline_ids, other_keys) = remote_timeline_client::list_remote_timelines(
             remote_storage,
             self.tenant_shard_id,
             cancel.clone(),
         )
         .await?;
+        self.activation_profile
+            .record(TenantActivationPhase::ListTimelines, started_at.elapsed());
 
         let deleting = other_keys.contains(TENANT_DELETED_MARKER_FILE_NAME);
         info!(
@@ -919,15 +1013,17 @@ impl Tenant {
             }
         }
 
+        let started_at = Instant::now();
+        let timelines =
+            Self::load_timeline_metadata(self, remote_timeline_ids, remote_storage, cancel).await?;
+        self.activation_profile.record(
+            TenantActivationPhase::DownloadIndexParts,
+            started_at.elapsed(),
+        );
+
         Ok(TenantPreload {
             deleting,
-            timelines: Self::load_timeline_metadata(
-                self,
-                remote_timeline_ids,
-                remote_storage,
-                cancel,
-            )
-            .await?,
+            timelines,
         })
     }
 
@@ -1001,15 +1097,61 @@ impl Tenant {
                         "timeline {} is deleted, picking to resume deletion",
                         timeline_id
                     );
-                    timelines_to_resume_deletions.push((timeline_id, index_part, preload.client));
+                    let progress =
+                        read_deletion_progress(self.conf, self.tenant_shard_id, timeline_id).await;
+                    timelines_to_resume_deletions.push((
+                        timeline_id,
+                        index_part,
+                        preload.client,
+                        progress,
+                    ));
                 }
             }
         }
 
+        // A delete mark without the deleted flag in the index part means that we stopped
+        // before the deletion reached remote storage: resume it as well.
+        for timeline_id in remote_index_and_client.keys().copied().collect::<Vec<_>>() {
+            let Some(progress) =
+                read_deletion_progress(self.conf, self.tenant_shard_id, timeline_id).await
+            else {
+                continue;
+            };
+            info!(%timeline_id, step=?progress.step, "timeline has a delete mark, picking to resume deletion");
+            let (index_part, client) = remote_index_and_client
+                .remove(&timeline_id)
+                .expect("just listed it");
+            timeline_ancestors.remove(&timeline_id);
+            timelines_to_resume_deletions.push((timeline_id, index_part, client, Some(progress)));
+        }
+
+        // The tenant is held if its directory or any of its index parts holds it. This has to be
+        // known before loading the timelines, which carry it into their next index uploads.
+        let legal_hold_path = self.conf.tenant_legal_hold_path(&self.tenant_shard_id);
+        let local_hold = legal_hold::load(&legal_hold_path).await?;
+        let remote_hold = remote_index_and_client
+            .values()
+            .find_map(|(index_part, _)| index_part.legal_hold.clone());
+        if local_hold.is_none() {
+            if let Some(hold) = &remote_hold {
+                legal_hold::persist(
+                    &legal_hold_path,
+                    Some(hold),
+                    self.conf.metadata_fsync_batch_window,
+                )
+                .await?;
+            }
+        }
+        if let Some(hold) = local_hold.or(remote_hold) {
+            info!(reason = %hold.reason, "tenant is under legal hold");
+            self.legal_hold.set(Some(hold));
+        }
+
         // For every timeline, download the metadata file, scan the local directory,
         // and build a layer map that contains an entry for each remote and local
         // layer file.
         let sorted_timelines = tree_sort_timelines(timeline_ancestors, |m| m.ancestor_timeline())?;
+        let started_at = Instant::now();
         for (timeline_id, remote_metadata) in sorted_timelines {
             let (index_part, remote_client) = remote_index_and_client
                 .remove(&timeline_id)
@@ -1024,6 +1166,8 @@ impl Tenant {
                     remote_client: Some(remote_client),
                     deletion_queue_client: self.deletion_queue_client.clone(),
                     timeline_get_throttle: self.timeline_get_throttle.clone(),
+                    ephemeral_resources: self.ephemeral_resources.clone(),
+                    foreground_reads: self.foreground_reads.clone(),
                 },
                 ctx,
             )
@@ -1036,8 +1180,23 @@ impl Tenant {
             })?;
         }
 
+        self.activation_profile
+            .record(TenantActivationPhase::LoadLayerMaps, started_at.elapsed());
+
+        // The local filesystem contents are a cache of what's in the remote IndexPart;
+        // IndexPart is the source of truth. Clean up before resuming deletions, which
+        // write to the timelines directory.
+        let started_at = Instant::now();
+        let deleting_timelines = timelines_to_resume_deletions
+            .iter()
+            .map(|(timeline_id, ..)| *timeline_id)
+            .collect();
+        self.clean_up_timelines(&existent_timelines, &deleting_timelines)?;
+
         // Walk through deleted timelines, resume deletion
-        for (timeline_id, index_part, remote_timeline_client) in timelines_to_resume_deletions {
+        for (timeline_id, index_part, remote_timeline_client, progress) in
+            timelines_to_resume_deletions
+        {
             remote_timeline_client
                 .init_upload_queue_stopped_to_continue_deletion(&index_part)
                 .context("init queue stopped")
@@ -1049,16 +1208,15 @@ impl Tenant {
                 &index_part.metadata,
                 Some(remote_timeline_client),
                 self.deletion_queue_client.clone(),
+                progress,
             )
             .instrument(tracing::info_span!("timeline_delete", %timeline_id))
             .await
             .context("resume_deletion")
             .map_err(LoadLocalTimelineError::ResumeDeletion)?;
         }
-
-        // The local filesystem contents are a cache of what's in the remote IndexPart;
-        // IndexPart is the source of truth.
-        self.clean_up_timelines(&existent_timelines)?;
+        self.activation_profile
+            .record(TenantActivationPhase::Reconcile, started_at.elapsed());
 
         fail::fail_point!("attach-before-activate", |_| {
             anyhow::bail!("attach-before-activate");
@@ -1073,7 +1231,13 @@ impl Tenant {
     /// Check for any local timeline directories that are temporary, or do not correspond to a
     /// timeline that still exists: this can happen if we crashed during a deletion/creation, or
     /// if a timeline was deleted while the tenant was attached to a different pageserver.
-    fn clean_up_timelines(&self, existent_timelines: &HashSet<TimelineId>) -> anyhow::Result<()> {
+    ///
+    /// The delete marks of `deleting_timelines` are kept, their deletion is being resumed.
+    fn clean_up_timelines(
+        &self,
+        existent_timelines: &HashSet<TimelineId>,
+        deleting_timelines: &HashSet<TimelineId>,
+    ) -> anyhow::Result<()> {
         let timelines_dir = self.conf.timelines_path(&self.tenant_shard_id);
 
         let entries = match timelines_dir.read_dir_utf8() {
@@ -1091,10 +1255,14 @@ impl Tenant {
             let entry = entry.context("read timeline dir entry")?;
             let entry_path = entry.path();
 
-            let purge = if crate::is_temporary(entry_path)
+            let purge = if crate::is_delete_mark(entry_path) {
+                match TimelineId::try_from(entry_path.file_stem()) {
+                    Ok(i) => !deleting_timelines.contains(&i),
+                    Err(_) => true,
+                }
+            } else if crate::is_temporary(entry_path)
                 // TODO: remove uninit mark code (https://github.com/neondatabase/neon/issues/5718)
                 || is_uninit_mark(entry_path)
-                || crate::is_delete_mark(entry_path)
             {
                 true
             } else {
@@ -1369,7 +1537,7 @@ impl Tenant {
     /// The timeline is has state value `Active` but its background loops are not running.
     // This makes the various functions which anyhow::ensure! for Active state work in tests.
     // Our current tests don't need the background loops.
-    #[cfg(test)]
+    #[cfg(any(feature = "testing", test))]
     pub async fn create_test_timeline(
         &self,
         new_timeline_id: TimelineId,
@@ -1426,6 +1594,7 @@ impl Tenant {
         mut ancestor_start_lsn: Option<Lsn>,
         pg_version: u32,
         load_existing_initdb: Option<TimelineId>,
+        snapshot_id: Option<SnapshotId>,
         broker_client: storage_broker::BrokerClientChannel,
         ctx: &RequestContext,
     ) -> Result<Arc<Timeline>, CreateTimelineError> {
@@ -1461,9 +1630,10 @@ impl Tenant {
                 debug!("timeline {new_timeline_id} already exists");
 
                 // Idempotency: creating the same timeline twice is not an error, unless
-                // the second creation has different parameters.
+                // the second creation has different parameters. The version of a timeline
+                // created from a snapshot is the one of the snapshot.
                 if existing.get_ancestor_timeline_id() != ancestor_timeline_id
-                    || existing.pg_version != pg_version
+                    || (snapshot_id.is_none() && existing.pg_version != pg_version)
                     || (ancestor_start_lsn.is_some()
                         && ancestor_start_lsn != Some(existing.get_ancestor_lsn()))
                 {
@@ -1491,8 +1661,13 @@ impl Tenant {
 
         pausable_failpoint!("timeline-creation-after-uninit");
 
-        let loaded_timeline = match ancestor_timeline_id {
-            Some(ancestor_timeline_id) => {
+        let loaded_timeline = match (ancestor_timeline_id, snapshot_id.as_ref()) {
+            (Some(_), Some(_)) => {
+                return Err(CreateTimelineError::Snapshot(anyhow::anyhow!(
+                    "a timeline can't be created both from a snapshot and an ancestor"
+                )));
+            }
+            (Some(ancestor_timeline_id), None) => {
                 let ancestor_timeline = self
                     .get_timeline(ancestor_timeline_id, false)
                     .context("Cannot branch off the timeline that's not present in pageserver")?;
@@ -1502,6 +1677,8 @@ impl Tenant {
                 if !ancestor_timeline.is_active() {
                     return Err(CreateTimelineError::AncestorNotActive);
                 }
+                crate::pg_versions::check_installed(self.conf, ancestor_timeline.pg_version)?;
+                self.wait_for_branch_admission().await?;
 
                 if let Some(lsn) = ancestor_start_lsn.as_mut() {
                     *lsn = lsn.align();
@@ -1543,7 +1720,17 @@ impl Tenant {
                 )
                 .await?
             }
-            None => {
+            (None, Some(snapshot_id)) => {
+                if load_existing_initdb.is_some() {
+                    return Err(CreateTimelineError::Snapshot(anyhow::anyhow!(
+                        "a timeline can't be created both from a snapshot and an existing initdb"
+                    )));
+                }
+                self.snapshot_timeline(new_timeline_id, snapshot_id, create_guard)
+                    .await?
+            }
+            (None, None) => {
+                crate::pg_versions::check_installed(self.conf, pg_version)?;
                 self.bootstrap_timeline(
                     new_timeline_id,
                     pg_version,
@@ -1560,15 +1747,18 @@ impl Tenant {
         // not send a success to the caller until it is.  The same applies to handling retries,
         // see the handling of [`TimelineExclusionError::AlreadyExists`] above.
         if let Some(remote_client) = loaded_timeline.remote_client.as_ref() {
-            let kind = ancestor_timeline_id
-                .map(|_| "branched")
-                .unwrap_or("bootstrapped");
+            let kind = match (ancestor_timeline_id, &snapshot_id) {
+                (Some(_), _) => "branched",
+                (None, Some(_)) => "snapshot",
+                (None, None) => "bootstrapped",
+            };
             remote_client.wait_completion().await.with_context(|| {
                 format!("wait for {} timeline initial uploads to complete", kind)
             })?;
         }
 
         loaded_timeline.activate(self.clone(), broker_client, None, ctx);
+        loaded_timeline.launch_branch_image_creation(ctx);
 
         Ok(loaded_timeline)
     }
@@ -1577,11 +1767,96 @@ impl Tenant {
         self: Arc<Self>,
         timeline_id: TimelineId,
     ) -> Result<(), DeleteTimelineError> {
+        self.legal_hold
+            .check(legal_hold::Operation::TimelineDeletion, &timeline_id)?;
+
         DeleteTimelineFlow::run(&self, timeline_id, false).await?;
 
         Ok(())
     }
 
+    /// Put the tenant under legal hold, or change the reason of the hold it is under. This only
+    /// holds this shard, see [`legal_hold`].
+    pub(crate) async fn set_legal_hold(&self, reason: String) -> anyhow::Result<models::LegalHold> {
+        let _guard = self.legal_hold.update_lock.lock().await;
+
+        let since = self
+            .legal_hold
+            .get()
+            .map_or_else(std::time::SystemTime::now, |hold| hold.since);
+        let hold = models::LegalHold { reason, since };
+        self.legal_hold.set(Some(hold.clone()));
+        self.persist_legal_hold(Some(&hold)).await?;
+
+        info!(reason = %hold.reason, "tenant put under legal hold");
+        Ok(hold)
+    }
+
+    /// Release the legal hold of the tenant. It is only lifted once the release is persisted.
+    pub(crate) async fn release_legal_hold(&self) -> anyhow::Result<()> {
+        let _guard = self.legal_hold.update_lock.lock().await;
+
+        if !self.legal_hold.is_held() {
+            return Ok(());
+        }
+        self.persist_legal_hold(None).await?;
+        self.legal_hold.set(None);
+
+        info!("tenant legal hold released");
+        Ok(())
+    }
+
+    async fn persist_legal_hold(&self, hold: Option<&models::LegalHold>) -> anyhow::Result<()> {
+        legal_hold::persist(
+            &self.conf.tenant_legal_hold_path(&self.tenant_shard_id),
+            hold,
+            self.conf.metadata_fsync_batch_window,
+        )
+        .await?;
+
+        let timelines: Vec<_> = self.timelines.lock().unwrap().values().cloned().collect();
+        for timeline in timelines {
+            if let Some(remote_client) = &timeline.remote_client {
+                remote_client
+                    .schedule_legal_hold_update_and_wait(hold.cloned())
+                    .await
+                    .with_context(|| {
+                        format!("upload the index part of timeline {}", timeline.timeline_id)
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reject the ingest of WAL into the tenant while `reason` is set, or accept it again. The
+    /// walreceivers of the timelines disconnect, and don't reconnect until the mode is unset,
+    /// while the data of the timelines is still served. The mode is persisted in the tenant
+    /// config, so it survives restarts and re-attachments.
+    pub(crate) async fn set_read_only(
+        &self,
+        reason: Option<models::ReadOnlyReason>,
+    ) -> anyhow::Result<()> {
+        let attached = self.tenant_conf.load_full();
+        if attached.tenant_conf.read_only == reason {
+            return Ok(());
+        }
+        let mut tenant_conf = attached.tenant_conf.clone();
+        tenant_conf.read_only = reason;
+        let location_conf = LocationConf {
+            mode: LocationMode::Attached(attached.location),
+            shard: self.shard_identity,
+            tenant_conf: tenant_conf.clone(),
+        };
+        Self::persist_tenant_config(self.conf, &self.tenant_shard_id, &location_conf).await?;
+        self.set_new_tenant_config(tenant_conf);
+
+        match reason {
+            Some(reason) => info!(%reason, "tenant set read-only"),
+            None => info!("tenant no longer read-only"),
+        }
+        Ok(())
+    }
+
     /// perform one garbage collection iteration, removing old data files from disk.
     /// this function is periodically called by gc task.
     /// also it can be explicitly requested through page server api 'do_gc' command.
@@ -1623,6 +1898,15 @@ impl Tenant {
             }
         }
 
+        // GC drops page versions, which a legal hold forbids.
+        if let Err(e) = self
+            .legal_hold
+            .check(legal_hold::Operation::Gc, &self.tenant_shard_id)
+        {
+            info!("Skipping GC: {e}");
+            return Ok(GcResult::default());
+        }
+
         self.gc_iteration_internal(target_timeline_id, horizon, pitr, cancel, ctx)
             .await
     }
@@ -1670,6 +1954,9 @@ impl Tenant {
         };
 
         for (timeline_id, timeline) in &timelines_to_compact {
+            // Paced here rather than in `compact`, so that compactions requested through the
+            // API are not.
+            timeline.wait_for_upload_backlog_to_compact(cancel).await;
             timeline
                 .compact(cancel, EnumSet::empty(), ctx)
                 .instrument(info_span!("compact_timeline", %timeline_id))
@@ -1679,6 +1966,56 @@ impl Tenant {
         Ok(())
     }
 
+    /// The compaction debt of the tenant shard: the bytes of the L0 delta layers of all its
+    /// timelines, which wait for compaction. Also published as `pageserver_compaction_debt_bytes`.
+    pub(crate) async fn update_compaction_debt(&self) -> u64 {
+        let mut debt = 0;
+        for timeline in self.list_timelines() {
+            debt += timeline.compaction_debt().await;
+        }
+        self.compaction_debt_gauge.set(debt);
+        debt
+    }
+
+    /// New branches pile more work onto a tenant whose compaction is behind, so their creation
+    /// waits, up to `branch_creation_debt_wait`, for the compaction debt to be under
+    /// `branch_creation_max_compaction_debt`, and fails otherwise.
+    async fn wait_for_branch_admission(&self) -> Result<(), CreateTimelineError> {
+        const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+        let limit = self.get_branch_creation_max_compaction_debt();
+        if limit == 0 {
+            return Ok(());
+        }
+        let deadline = Instant::now() + self.get_branch_creation_debt_wait();
+        let mut logged = false;
+        loop {
+            let debt = self.update_compaction_debt().await;
+            if debt <= limit {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                warn!(
+                    debt,
+                    limit, "refusing to create a branch: compaction debt is too high"
+                );
+                return Err(CreateTimelineError::CompactionDebt { debt, limit });
+            }
+            if !logged {
+                info!(
+                    debt,
+                    limit, "waiting for compaction before creating a branch"
+                );
+                logged = true;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL.min(deadline - now)) => {}
+                _ = self.cancel.cancelled() => return Err(CreateTimelineError::ShuttingDown),
+            }
+        }
+    }
+
     // Call through to all timelines to freeze ephemeral layers if needed.  Usually
     // this happens during ingest: this background housekeeping is for freezing layers
     // that are open but haven't been written to for some time.
@@ -1704,6 +2041,32 @@ impl Tenant {
 
         for timeline in &timelines {
             timeline.maybe_freeze_ephemeral_layer().await;
+
+            if let Err(e) = timeline.persist_access_log().await {
+                warn!(timeline_id=%timeline.timeline_id, "failed to persist the access log: {e:#}");
+            }
+        }
+    }
+
+    /// Migrate the layers of the active timelines that have been in the hot tier for long
+    /// enough, see [`remote_timeline_client::hot_tier`].
+    async fn migrate_hot_layers(&self) {
+        let timelines = self
+            .timelines
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|timeline| timeline.is_active())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for timeline in &timelines {
+            let Some(remote_client) = &timeline.remote_client else {
+                continue;
+            };
+            if let Err(e) = remote_client.migrate_hot_layers(&self.cancel).await {
+                warn!(timeline_id=%timeline.timeline_id, "failed to migrate layers out of the hot tier: {e:#}");
+            }
         }
     }
 
@@ -1736,26 +2099,31 @@ impl Tenant {
         span::debug_assert_current_span_has_tenant_id();
 
         let mut activating = false;
-        self.state.send_modify(|current_state| {
+        self.state.send_if_modified(|current_state| {
             use pageserver_api::models::ActivatingFrom;
-            match &*current_state {
-                TenantState::Activating(_) | TenantState::Active | TenantState::Broken { .. } | TenantState::Stopping { .. } => {
-                    panic!("caller is responsible for calling activate() only on Loading / Attaching tenants, got {state:?}", state = current_state);
-                }
-                TenantState::Loading => {
-                    *current_state = TenantState::Activating(ActivatingFrom::Loading);
-                }
-                TenantState::Attaching => {
-                    *current_state = TenantState::Activating(ActivatingFrom::Attaching);
-                }
+            // The caller is responsible for calling activate() only on Loading / Attaching
+            // tenants: from any other state, the transition is refused.
+            let activating_from = match current_state {
+                TenantState::Attaching => ActivatingFrom::Attaching,
+                _ => ActivatingFrom::Loading,
+            };
+            if let Err(e) = self.state_transitions.apply(
+                current_state,
+                TenantState::Activating(activating_from),
+                state_machine::TransitionCause::Activate,
+            ) {
+                error!("not activating: {e}");
+                return false;
             }
             debug!(tenant_id = %self.tenant_shard_id.tenant_id, shard_id = %self.tenant_shard_id.shard_slug(), "Activating tenant");
             activating = true;
             // Continue outside the closure. We need to grab timelines.lock()
             // and we plan to turn it into a tokio::sync::Mutex in a future patch.
+            true
         });
 
         if activating {
+            let started_at = Instant::now();
             let timelines_accessor = self.timelines.lock().unwrap();
             let timelines_to_activate = timelines_accessor
                 .values()
@@ -1777,15 +2145,22 @@ impl Tenant {
                 activated_timelines += 1;
             }
 
-            self.state.send_modify(move |current_state| {
-                assert!(
-                    matches!(current_state, TenantState::Activating(_)),
-                    "set_stopping and set_broken wait for us to leave Activating state",
-                );
-                *current_state = TenantState::Active;
+            self.state.send_if_modified(move |current_state| {
+                // set_stopping and set_broken wait for us to leave Activating state
+                if let Err(e) = self.state_transitions.apply(
+                    current_state,
+                    TenantState::Active,
+                    state_machine::TransitionCause::Activated,
+                ) {
+                    error!("{e}");
+                    return false;
+                }
 
                 let elapsed = self.constructed_at.elapsed();
                 let total_timelines = timelines_accessor.len();
+                self.activation_profile
+                    .record(TenantActivationPhase::Activate, started_at.elapsed());
+                self.activation_profile.record_activated(elapsed);
 
                 // log a lot of stuff, because some tenants sometimes suffer from user-visible
                 // times to activate. see https://github.com/neondatabase/neon/issues/4025
@@ -1796,10 +2171,12 @@ impl Tenant {
                     activated_timelines,
                     total_timelines,
                     post_state = <&'static str>::from(&*current_state),
+                    phases = %self.activation_profile,
                     "activation attempt finished"
                 );
 
                 TENANT.activation.observe(elapsed.as_secs_f64());
+                true
             });
         }
     }
@@ -1853,6 +2230,10 @@ impl Tenant {
             Err(SetStoppingError::Broken) => {
                 // assume that this is acceptable
             }
+            Err(SetStoppingError::InvalidTransition(e)) => {
+                // a bug, but shut down what we can
+                error!("Tenant::shutdown: {e}");
+            }
             Err(SetStoppingError::AlreadyStopping(other)) => {
                 // give caller the option to wait for this this shutdown
                 info!("Tenant::shutdown: AlreadyStopping");
@@ -1935,46 +2316,45 @@ impl Tenant {
 
         // we now know we're done activating, let's see whether this task is the winner to transition into Stopping
         let mut err = None;
-        let stopping = self.state.send_if_modified(|current_state| match current_state {
-            TenantState::Activating(_) => {
-                unreachable!("1we ensured above that we're done with activation, and, there is no re-activation")
-            }
-            TenantState::Attaching => {
-                if !allow_transition_from_attaching {
-                    unreachable!("2we ensured above that we're done with activation, and, there is no re-activation")
-                };
-                *current_state = TenantState::Stopping { progress };
-                true
-            }
-            TenantState::Loading => {
-                if !allow_transition_from_loading {
-                    unreachable!("3we ensured above that we're done with activation, and, there is no re-activation")
-                };
-                *current_state = TenantState::Stopping { progress };
-                true
-            }
-            TenantState::Active => {
-                // FIXME: due to time-of-check vs time-of-use issues, it can happen that new timelines
-                // are created after the transition to Stopping. That's harmless, as the Timelines
-                // won't be accessible to anyone afterwards, because the Tenant is in Stopping state.
-                *current_state = TenantState::Stopping { progress };
-                // Continue stopping outside the closure. We need to grab timelines.lock()
-                // and we plan to turn it into a tokio::sync::Mutex in a future patch.
-                true
-            }
-            TenantState::Broken { reason, .. } => {
-                info!(
+        let stopping = self
+            .state
+            .send_if_modified(|current_state| match current_state {
+                // We ensured above that we're done with activation, and there is no re-activation:
+                // the state can only be Loading or Attaching if the caller allowed it.
+                TenantState::Activating(_)
+                | TenantState::Attaching
+                | TenantState::Loading
+                | TenantState::Active => {
+                    // FIXME: due to time-of-check vs time-of-use issues, it can happen that new timelines
+                    // are created after the transition to Stopping. That's harmless, as the Timelines
+                    // won't be accessible to anyone afterwards, because the Tenant is in Stopping state.
+                    match self.state_transitions.apply(
+                        current_state,
+                        TenantState::Stopping { progress },
+                        state_machine::TransitionCause::SetStopping,
+                    ) {
+                        // Continue stopping outside the closure. We need to grab timelines.lock()
+                        // and we plan to turn it into a tokio::sync::Mutex in a future patch.
+                        Ok(()) => true,
+                        Err(e) => {
+                            err = Some(SetStoppingError::InvalidTransition(e));
+                            false
+                        }
+                    }
+                }
+                TenantState::Broken { reason, .. } => {
+                    info!(
                     "Cannot set tenant to Stopping state, it is in Broken state due to: {reason}"
                 );
-                err = Some(SetStoppingError::Broken);
-                false
-            }
-            TenantState::Stopping { progress } => {
-                info!("Tenant is already in Stopping state");
-                err = Some(SetStoppingError::AlreadyStopping(progress.clone()));
-                false
-            }
-        });
+                    err = Some(SetStoppingError::Broken);
+                    false
+                }
+                TenantState::Stopping { progress } => {
+                    info!("Tenant is already in Stopping state");
+                    err = Some(SetStoppingError::AlreadyStopping(progress.clone()));
+                    false
+                }
+            });
         match (stopping, err) {
             (true, None) => {} // continue
             (false, Some(err)) => return Err(err),
@@ -2026,31 +2406,28 @@ impl Tenant {
 
     pub(crate) fn set_broken_no_wait(&self, reason: impl Display) {
         let reason = reason.to_string();
-        self.state.send_modify(|current_state| {
-            match *current_state {
-                TenantState::Activating(_) | TenantState::Loading | TenantState::Attaching => {
-                    unreachable!("we ensured above that we're done with activation, and, there is no re-activation")
-                }
-                TenantState::Active => {
-                    if cfg!(feature = "testing") {
-                        warn!("Changing Active tenant to Broken state, reason: {}", reason);
-                        *current_state = TenantState::broken_from_reason(reason);
-                    } else {
-                        unreachable!("not allowed to call set_broken on Active tenants in non-testing builds")
-                    }
-                }
-                TenantState::Broken { .. } => {
-                    warn!("Tenant is already in Broken state");
+        self.state.send_if_modified(|current_state| {
+            if let TenantState::Broken { .. } = current_state {
+                warn!("Tenant is already in Broken state");
+                return false;
+            }
+            // Stopping is the only "expected" state, Active only in testing builds, and the
+            // transition table refuses the states of activation: there is no re-activation.
+            let from: &'static str = (&*current_state).into();
+            match self.state_transitions.apply(
+                current_state,
+                TenantState::broken_from_reason(reason.clone()),
+                state_machine::TransitionCause::SetBroken,
+            ) {
+                Ok(()) => {
+                    warn!("Marking {from} tenant as Broken state, reason: {reason}");
+                    true
                 }
-                // This is the only "expected" path, any other path is a bug.
-                TenantState::Stopping { .. } => {
-                    warn!(
-                        "Marking Stopping tenant as Broken state, reason: {}",
-                        reason
-                    );
-                    *current_state = TenantState::broken_from_reason(reason);
+                Err(e) => {
+                    error!("{e}");
+                    false
                 }
-           }
+            }
         });
     }
 
@@ -2199,6 +2576,7 @@ impl Tenant {
 
             for child_shard in child_shards {
                 upload_index_part(
+                    self.conf,
                     remote_storage,
                     child_shard,
                     &timeline.timeline_id,
@@ -2270,91 +2648,116 @@ impl Tenant {
 
     pub fn effective_config(&self) -> TenantConf {
         self.tenant_specific_overrides()
-            .merge(self.conf.default_tenant_conf.clone())
+            .merge(TenantConf::clone(&self.conf.default_tenant_conf.load()))
     }
 
     pub fn get_checkpoint_distance(&self) -> u64 {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .checkpoint_distance
-            .unwrap_or(self.conf.default_tenant_conf.checkpoint_distance)
+            .unwrap_or(self.conf.default_tenant_conf.load().checkpoint_distance)
     }
 
     pub fn get_checkpoint_timeout(&self) -> Duration {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .checkpoint_timeout
-            .unwrap_or(self.conf.default_tenant_conf.checkpoint_timeout)
+            .unwrap_or(self.conf.default_tenant_conf.load().checkpoint_timeout)
     }
 
     pub fn get_compaction_target_size(&self) -> u64 {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .compaction_target_size
-            .unwrap_or(self.conf.default_tenant_conf.compaction_target_size)
+            .unwrap_or(self.conf.default_tenant_conf.load().compaction_target_size)
     }
 
     pub fn get_compaction_period(&self) -> Duration {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .compaction_period
-            .unwrap_or(self.conf.default_tenant_conf.compaction_period)
+            .unwrap_or(self.conf.default_tenant_conf.load().compaction_period)
     }
 
     pub fn get_compaction_threshold(&self) -> usize {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .compaction_threshold
-            .unwrap_or(self.conf.default_tenant_conf.compaction_threshold)
+            .unwrap_or(self.conf.default_tenant_conf.load().compaction_threshold)
     }
 
     pub fn get_gc_horizon(&self) -> u64 {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .gc_horizon
-            .unwrap_or(self.conf.default_tenant_conf.gc_horizon)
+            .unwrap_or(self.conf.default_tenant_conf.load().gc_horizon)
     }
 
     pub fn get_gc_period(&self) -> Duration {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .gc_period
-            .unwrap_or(self.conf.default_tenant_conf.gc_period)
+            .unwrap_or(self.conf.default_tenant_conf.load().gc_period)
     }
 
     pub fn get_image_creation_threshold(&self) -> usize {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
-        tenant_conf
-            .image_creation_threshold
-            .unwrap_or(self.conf.default_tenant_conf.image_creation_threshold)
+        tenant_conf.image_creation_threshold.unwrap_or(
+            self.conf
+                .default_tenant_conf
+                .load()
+                .image_creation_threshold,
+        )
     }
 
     pub fn get_pitr_interval(&self) -> Duration {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .pitr_interval
-            .unwrap_or(self.conf.default_tenant_conf.pitr_interval)
+            .unwrap_or(self.conf.default_tenant_conf.load().pitr_interval)
     }
 
     pub fn get_trace_read_requests(&self) -> bool {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         tenant_conf
             .trace_read_requests
-            .unwrap_or(self.conf.default_tenant_conf.trace_read_requests)
+            .unwrap_or(self.conf.default_tenant_conf.load().trace_read_requests)
     }
 
     pub fn get_min_resident_size_override(&self) -> Option<u64> {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
-        tenant_conf
-            .min_resident_size_override
-            .or(self.conf.default_tenant_conf.min_resident_size_override)
+        tenant_conf.min_resident_size_override.or(self
+            .conf
+            .default_tenant_conf
+            .load()
+            .min_resident_size_override)
+    }
+
+    pub(crate) fn get_branch_creation_max_compaction_debt(&self) -> u64 {
+        let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
+        tenant_conf.branch_creation_max_compaction_debt.unwrap_or(
+            self.conf
+                .default_tenant_conf
+                .load()
+                .branch_creation_max_compaction_debt,
+        )
+    }
+
+    pub(crate) fn get_branch_creation_debt_wait(&self) -> Duration {
+        let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
+        tenant_conf.branch_creation_debt_wait.unwrap_or(
+            self.conf
+                .default_tenant_conf
+                .load()
+                .branch_creation_debt_wait,
+        )
     }
 
     pub fn get_heatmap_period(&self) -> Option<Duration> {
         let tenant_conf = self.tenant_conf.load().tenant_conf.clone();
         let heatmap_period = tenant_conf
             .heatmap_period
-            .unwrap_or(self.conf.default_tenant_conf.heatmap_period);
+            .unwrap_or(self.conf.default_tenant_conf.load().heatmap_period);
         if heatmap_period.is_zero() {
             None
         } else {
@@ -2362,6 +2765,21 @@ impl Tenant {
         }
     }
 
+    /// The deadline of the page_service requests, from their start.
+    pub(crate) fn get_page_request_deadline(&self) -> Option<Duration> {
+        let deadline = self
+            .tenant_conf
+            .load()
+            .tenant_conf
+            .page_request_deadline
+            .unwrap_or(self.conf.default_tenant_conf.load().page_request_deadline);
+        if deadline.is_zero() {
+            None
+        } else {
+            Some(deadline)
+        }
+    }
+
     pub fn set_new_tenant_config(&self, new_tenant_conf: TenantConfOpt) {
         // Use read-copy-update in order to avoid overwriting the location config
         // state if this races with [`Tenant::set_new_location_config`]. Note that
@@ -2404,15 +2822,46 @@ impl Tenant {
         psconf: &'static PageServerConf,
         overrides: &TenantConfOpt,
     ) -> throttle::Config {
+        overrides.timeline_get_throttle.clone().unwrap_or(
+            psconf
+                .default_tenant_conf
+                .load()
+                .timeline_get_throttle
+                .clone(),
+        )
+    }
+
+    fn get_ephemeral_bytes_limit(
+        psconf: &'static PageServerConf,
+        overrides: &TenantConfOpt,
+    ) -> u64 {
         overrides
-            .timeline_get_throttle
-            .clone()
-            .unwrap_or(psconf.default_tenant_conf.timeline_get_throttle.clone())
+            .ephemeral_bytes_limit
+            .unwrap_or(psconf.default_tenant_conf.load().ephemeral_bytes_limit)
+    }
+
+    /// Apply a change of the pageserver-wide defaults of tenant configurations, after a
+    /// config reload.
+    pub(crate) fn default_tenant_conf_updated(&self) {
+        let tenant_conf = self.tenant_specific_overrides();
+        self.tenant_conf_updated(&tenant_conf);
+        for timeline in self.list_timelines() {
+            timeline.tenant_conf_updated(&tenant_conf);
+        }
+    }
+
+    /// The tags of the tenant that are propagated to metrics and spans, see [`tags`].
+    pub(crate) fn tag_labels(&self) -> tags::TagLabels {
+        tags::TagLabels::select(self.conf, &self.tenant_conf.load().tenant_conf)
     }
 
     pub(crate) fn tenant_conf_updated(&self, new_conf: &TenantConfOpt) {
         let conf = Self::get_timeline_get_throttle_config(self.conf, new_conf);
-        self.timeline_get_throttle.reconfigure(conf)
+        self.timeline_get_throttle.reconfigure(conf);
+        self.ephemeral_resources.max_dirty_bytes.store(
+            Self::get_ephemeral_bytes_limit(self.conf, new_conf),
+            Ordering::Relaxed,
+        );
     }
 
     /// Helper function to create a new Timeline struct.
@@ -2541,9 +2990,17 @@ impl Tenant {
             // using now here is good enough approximation to catch tenants with really long
             // activation times.
             constructed_at: Instant::now(),
+            activation_profile: Default::default(),
+            load_sampler: Default::default(),
+            compaction_debt_gauge: COMPACTION_DEBT.with_label_values(&[
+                &tenant_shard_id.tenant_id.to_string(),
+                &tenant_shard_id.shard_slug().to_string(),
+            ]),
+            state_transitions: Default::default(),
+            legal_hold: Default::default(),
             timelines: Mutex::new(HashMap::new()),
             timelines_creating: Mutex::new(HashSet::new()),
-            gc_cs: tokio::sync::Mutex::new(()),
+            gc_cs: tokio::sync::RwLock::new(()),
             walredo_mgr,
             remote_storage,
             deletion_queue_client,
@@ -2559,6 +3016,10 @@ impl Tenant {
                 Tenant::get_timeline_get_throttle_config(conf, &attached_conf.tenant_conf),
                 &crate::metrics::tenant_throttling::TIMELINE_GET,
             )),
+            ephemeral_resources: Arc::new(EphemeralResources::new(
+                Tenant::get_ephemeral_bytes_limit(conf, &attached_conf.tenant_conf),
+            )),
+            foreground_reads: Arc::new(ForegroundReads::default()),
             tenant_conf: Arc::new(ArcSwap::from_pointee(attached_conf)),
             ongoing_timeline_detach: std::sync::Mutex::default(),
         }
@@ -2637,6 +3098,7 @@ impl Tenant {
             &config_path,
             &legacy_config_path,
             location_conf,
+            conf.metadata_fsync_batch_window,
         )
         .await
     }
@@ -2647,6 +3109,7 @@ impl Tenant {
         config_path: &Utf8Path,
         legacy_config_path: &Utf8Path,
         location_conf: &LocationConf,
+        fsync_batch_window: Duration,
     ) -> anyhow::Result<()> {
         if let LocationMode::Attached(attach_conf) = &location_conf.mode {
             // The modern-style LocationConf config file requires a generation to be set. In case someone
@@ -2663,6 +3126,7 @@ impl Tenant {
                     tenant_shard_id,
                     legacy_config_path,
                     &location_conf.tenant_conf,
+                    fsync_batch_window,
                 )
                 .await?;
 
@@ -2689,9 +3153,14 @@ impl Tenant {
         let tenant_shard_id = *tenant_shard_id;
         let config_path = config_path.to_owned();
         let conf_content = conf_content.into_bytes();
-        VirtualFile::crashsafe_overwrite(config_path.clone(), temp_path, conf_content)
-            .await
-            .with_context(|| format!("write tenant {tenant_shard_id} config to {config_path}"))?;
+        group_fsync::overwrite(
+            config_path.clone(),
+            temp_path,
+            conf_content,
+            fsync_batch_window,
+        )
+        .await
+        .with_context(|| format!("write tenant {tenant_shard_id} config to {config_path}"))?;
 
         Ok(())
     }
@@ -2701,6 +3170,7 @@ impl Tenant {
         tenant_shard_id: &TenantShardId,
         target_config_path: &Utf8Path,
         tenant_conf: &TenantConfOpt,
+        fsync_batch_window: Duration,
     ) -> anyhow::Result<()> {
         debug!("persisting tenantconf to {target_config_path}");
 
@@ -2719,11 +3189,16 @@ impl Tenant {
         let tenant_shard_id = *tenant_shard_id;
         let target_config_path = target_config_path.to_owned();
         let conf_content = conf_content.into_bytes();
-        VirtualFile::crashsafe_overwrite(target_config_path.clone(), temp_path, conf_content)
-            .await
-            .with_context(|| {
-                format!("write tenant {tenant_shard_id} config to {target_config_path}")
-            })?;
+        group_fsync::overwrite(
+            target_config_path.clone(),
+            temp_path,
+            conf_content,
+            fsync_batch_window,
+        )
+        .await
+        .with_context(|| {
+            format!("write tenant {tenant_shard_id} config to {target_config_path}")
+        })?;
         Ok(())
     }
 
@@ -2739,7 +3214,7 @@ impl Tenant {
     //                 +-----baz-------->
     //
     //
-    // 1. Grab 'gc_cs' mutex to prevent new timelines from being created while Timeline's
+    // 1. Grab 'gc_cs' lock to prevent new timelines from being created while Timeline's
     //    `gc_infos` are being refreshed
     // 2. Scan collected timelines, and on each timeline, make note of the
     //    all the points where other timelines have been branched off.
@@ -2884,9 +3359,9 @@ impl Tenant {
             anyhow::bail!("shutting down");
         }
 
-        // grab mutex to prevent new timelines from being created here; avoid doing long operations
-        // because that will stall branch creation.
-        let gc_cs = self.gc_cs.lock().await;
+        // grab the lock to prevent new timelines from being created here; avoid doing long
+        // operations because that will stall branch creation.
+        let gc_cs = self.gc_cs.write().await;
 
         // Scan all timelines. For each timeline, remember the timeline ID and
         // the branch point where it was created.
@@ -2954,6 +3429,7 @@ impl Tenant {
                 ))
                 .map(|&x| x.1)
                 .collect();
+            let pinned_relations = timeline.get_gc_pinned_relations();
 
             {
                 let mut target = timeline.gc_info.write().unwrap();
@@ -2963,6 +3439,7 @@ impl Tenant {
                         *target = GcInfo {
                             retain_lsns: branchpoints,
                             cutoffs,
+                            pinned_relations,
                         };
                     }
                     None => {
@@ -2972,6 +3449,7 @@ impl Tenant {
                         //
                         // in both cases, refreshing the branchpoints is correct.
                         target.retain_lsns = branchpoints;
+                        target.pinned_relations = pinned_relations;
                     }
                 };
             }
@@ -3029,8 +3507,9 @@ impl Tenant {
 
         // We will validate our ancestor LSN in this function.  Acquire the GC lock so that
         // this check cannot race with GC, and the ancestor LSN is guaranteed to remain
-        // valid while we are creating the branch.
-        let _gc_cs = self.gc_cs.lock().await;
+        // valid while we are creating the branch. Other branch creations hold it too: their
+        // timeline directory fsyncs and initial index uploads overlap with ours.
+        let _gc_cs = self.gc_cs.read().await;
 
         // If no start LSN is specified, we branch the new timeline from the source timeline's last record LSN
         let start_lsn = start_lsn.unwrap_or_else(|| {
@@ -3151,8 +3630,8 @@ impl Tenant {
 
     async fn upload_initdb(
         &self,
-        timelines_path: &Utf8PathBuf,
-        pgdata_path: &Utf8PathBuf,
+        initdb_tar_zst_path: &Utf8Path,
+        tar_zst_size: u64,
         timeline_id: &TimelineId,
     ) -> anyhow::Result<()> {
         let Some(storage) = &self.remote_storage else {
@@ -3160,21 +3639,10 @@ impl Tenant {
             return Ok(());
         };
 
-        let temp_path = timelines_path.join(format!(
-            "{INITDB_PATH}.upload-{timeline_id}.{TEMP_FILE_SUFFIX}"
-        ));
-
-        scopeguard::defer! {
-            if let Err(e) = fs::remove_file(&temp_path) {
-                error!("Failed to remove temporary initdb archive '{temp_path}': {e}");
-            }
-        }
-
-        let (pgdata_zstd, tar_zst_size) = create_zst_tarball(pgdata_path, &temp_path).await?;
         const INITDB_TAR_ZST_WARN_LIMIT: u64 = 2 * 1024 * 1024;
         if tar_zst_size > INITDB_TAR_ZST_WARN_LIMIT {
             warn!(
-                "compressed {temp_path} size of {tar_zst_size} is above limit {INITDB_TAR_ZST_WARN_LIMIT}."
+                "compressed {initdb_tar_zst_path} size of {tar_zst_size} is above limit {INITDB_TAR_ZST_WARN_LIMIT}."
             );
         }
 
@@ -3186,7 +3654,7 @@ impl Tenant {
                     storage,
                     &self.tenant_shard_id.tenant_id,
                     timeline_id,
-                    pgdata_zstd.try_clone().await?,
+                    tokio::fs::File::open(initdb_tar_zst_path).await?,
                     tar_zst_size,
                     &self.cancel,
                 )
@@ -3203,8 +3671,13 @@ impl Tenant {
         .and_then(|x| x)
     }
 
-    /// - run initdb to init temporary instance and get bootstrap data
-    /// - after initialization completes, tar up the temp dir and upload it to S3.
+    /// - run initdb to init temporary instance and get bootstrap data, unless its tarball is
+    ///   in the [`initdb_cache`]
+    /// - import the temp dir, tarred straight into the import. On shard zero with remote
+    ///   storage, or with the cache, the tarball is also compressed into an archive as it goes.
+    /// - upload the archive to S3, on shard zero only.
+    ///
+    /// An existing initdb archive is imported as it is decompressed, without extracting it.
     ///
     /// The caller is responsible for activating the returned timeline.
     async fn bootstrap_timeline(
@@ -3234,12 +3707,22 @@ impl Tenant {
 
         // this new directory is very temporary, set to remove it immediately after bootstrap, we don't need it
         scopeguard::defer! {
-            if let Err(e) = fs::remove_dir_all(&pgdata_path) {
+            // It is not created when an existing initdb is loaded, and removed once imported.
+            if let Err(e) = fs::remove_dir_all(&pgdata_path).or_else(fs_ext::ignore_not_found) {
                 // this is unlikely, but we will remove the directory on pageserver restart or another bootstrap call
                 error!("Failed to remove temporary initdb directory '{pgdata_path}': {e}");
             }
         }
-        if let Some(existing_initdb_timeline_id) = load_existing_initdb {
+        fn remove_initdb_tar_zst(path: Utf8PathBuf) {
+            if let Err(e) = fs::remove_file(&path).or_else(fs_ext::ignore_not_found) {
+                error!("Failed to remove temporary initdb archive '{path}': {e}");
+            }
+        }
+        // Shard zero keeps the initdb archive in remote storage, for the other shards and for
+        // timelines created later with `existing_initdb_timeline_id`.
+        let upload = self.remote_storage.is_some() && self.tenant_shard_id().is_shard_zero();
+
+        let source = if let Some(existing_initdb_timeline_id) = load_existing_initdb {
             let Some(storage) = &self.remote_storage else {
                 bail!("no storage configured but load_existing_initdb set to {existing_initdb_timeline_id}");
             };
@@ -3257,39 +3740,77 @@ impl Tenant {
                     .await
                     .context("copy initdb tar")?;
             }
-            let (initdb_tar_zst_path, initdb_tar_zst) =
-                self::remote_timeline_client::download_initdb_tar_zst(
+            let (initdb_tar_zst_path, _) = self::remote_timeline_client::download_initdb_tar_zst(
+                self.conf,
+                storage,
+                &self.tenant_shard_id,
+                &existing_initdb_timeline_id,
+                &self.cancel,
+            )
+            .await
+            .context("download initdb tar")?;
+            InitdbSource::Archive {
+                path: scopeguard::guard(initdb_tar_zst_path, remove_initdb_tar_zst as fn(_)),
+                upload: false,
+            }
+        } else {
+            let initdb_tar_zst_path = scopeguard::guard(
+                timelines_path.join(format!(
+                    "{INITDB_PATH}.upload-{timeline_id}.{TEMP_FILE_SUFFIX}"
+                )),
+                remove_initdb_tar_zst as fn(_),
+            );
+
+            let fingerprint = if self.conf.initdb_cache {
+                initdb_cache::fingerprint(self.conf, pg_version)
+                    .await
+                    .map_err(|e| warn!("not using the initdb cache: {e:#}"))
+                    .ok()
+            } else {
+                None
+            };
+            let cached = match fingerprint {
+                Some(fingerprint) => initdb_cache::clone_into(
                     self.conf,
-                    storage,
-                    &self.tenant_shard_id,
-                    &existing_initdb_timeline_id,
-                    &self.cancel,
+                    pg_version,
+                    fingerprint,
+                    &initdb_tar_zst_path,
                 )
                 .await
-                .context("download initdb tar")?;
+                .unwrap_or_else(|e| {
+                    warn!("failed to read the initdb cache: {e:#}");
+                    None
+                }),
+                None => None,
+            };
 
-            scopeguard::defer! {
-                if let Err(e) = fs::remove_file(&initdb_tar_zst_path) {
-                    error!("Failed to remove temporary initdb archive '{initdb_tar_zst_path}': {e}");
+            if cached.is_some() {
+                info!("bootstrapping from the cached initdb output");
+                InitdbSource::Archive {
+                    path: initdb_tar_zst_path,
+                    upload,
+                }
+            } else {
+                // Init temporarily repo to get bootstrap data, this creates a directory in the `pgdata_path` path
+                run_initdb(self.conf, &pgdata_path, pg_version, &self.cancel).await?;
+                InitdbSource::Dir {
+                    // The archive is only written out if it is uploaded or cached.
+                    archive: (upload || fingerprint.is_some()).then_some(initdb_tar_zst_path),
+                    fingerprint,
+                    upload,
                 }
             }
-
-            let buf_read =
-                BufReader::with_capacity(remote_timeline_client::BUFFER_SIZE, initdb_tar_zst);
-            extract_zst_tarball(&pgdata_path, buf_read)
-                .await
-                .context("extract initdb tar")?;
-        } else {
-            // Init temporarily repo to get bootstrap data, this creates a directory in the `pgdata_path` path
-            run_initdb(self.conf, &pgdata_path, pg_version, &self.cancel).await?;
-
-            // Upload the created data dir to S3
-            if self.tenant_shard_id().is_shard_zero() {
-                self.upload_initdb(&timelines_path, &pgdata_path, &timeline_id)
-                    .await?;
+        };
+        let pgdata_lsn = match &source {
+            InitdbSource::Archive { path, .. } => {
+                let mut reader = open_initdb_tar_zst(path).await?;
+                import_datadir::get_lsn_from_controlfile_in_tar(&mut reader)
+                    .await
+                    .context("read initdb controlfile")?
             }
+            InitdbSource::Dir { .. } => import_datadir::get_lsn_from_controlfile(&pgdata_path)?,
         }
-        let pgdata_lsn = import_datadir::get_lsn_from_controlfile(&pgdata_path)?.align();
+        .align();
 
         // Import the contents of the data directory at the initial checkpoint
         // LSN, and any WAL after that.
@@ -3317,16 +3838,62 @@ impl Tenant {
         let tenant_shard_id = raw_timeline.owning_tenant.tenant_shard_id;
         let unfinished_timeline = raw_timeline.raw_timeline()?;
 
-        import_datadir::import_timeline_from_postgres_datadir(
-            unfinished_timeline,
-            &pgdata_path,
-            pgdata_lsn,
-            ctx,
-        )
-        .await
-        .with_context(|| {
-            format!("Failed to import pgdatadir for timeline {tenant_shard_id}/{timeline_id}")
-        })?;
+        let (initdb_tar_zst_path, tar_zst_size) = match source {
+            InitdbSource::Archive { path, upload } => {
+                import_initdb_tar_zst(unfinished_timeline, &path, pgdata_lsn, ctx)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to import pgdatadir for timeline {tenant_shard_id}/{timeline_id}"
+                        )
+                    })?;
+                let tar_zst_size = if upload {
+                    Some(fs::metadata(&*path)?.len())
+                } else {
+                    None
+                };
+                (Some(path), tar_zst_size)
+            }
+            InitdbSource::Dir {
+                archive,
+                fingerprint,
+                upload,
+            } => {
+                let archived = import_initdb_dir(
+                    unfinished_timeline,
+                    &pgdata_path,
+                    archive.as_deref().map(Utf8PathBuf::as_path),
+                    pgdata_lsn,
+                    ctx,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to import pgdatadir for timeline {tenant_shard_id}/{timeline_id}"
+                    )
+                })?;
+                // If the removal fails, the guard above retries it.
+                let _ = tokio::fs::remove_dir_all(&pgdata_path).await;
+
+                match archive.zip(archived) {
+                    Some((path, tar_zst_size)) => {
+                        if let Some(fingerprint) = fingerprint {
+                            if let Err(e) =
+                                initdb_cache::store(self.conf, pg_version, fingerprint, &path).await
+                            {
+                                warn!("failed to cache the initdb output: {e:#}");
+                            }
+                        }
+                        (Some(path), upload.then_some(tar_zst_size))
+                    }
+                    None => (None, None),
+                }
+            }
+        };
+        // Upload the created data dir to S3
+        if let (Some(path), Some(tar_zst_size)) = (&initdb_tar_zst_path, tar_zst_size) {
+            self.upload_initdb(path, tar_zst_size, &timeline_id).await?;
+        }
 
         // Flush the new layer files to disk, before we make the timeline as available to
         // the outside world.
@@ -3353,6 +3920,121 @@ impl Tenant {
         Ok(timeline)
     }
 
+    /// Create a timeline at the LSN of a snapshot, with the layers of the snapshot, see
+    /// [`snapshot`]. Requires remote storage, where the snapshot is, and an unsharded tenant:
+    /// the layers of a snapshot are not split into shards.
+    ///
+    /// The caller is responsible for activating the returned timeline.
+    async fn snapshot_timeline(
+        &self,
+        timeline_id: TimelineId,
+        snapshot_id: &SnapshotId,
+        timeline_create_guard: TimelineCreateGuard<'_>,
+    ) -> Result<Arc<Timeline>, CreateTimelineError> {
+        let Some(remote_storage) = self.remote_storage.as_ref() else {
+            return Err(CreateTimelineError::Snapshot(anyhow::anyhow!(
+                "timelines can only be created from snapshots with remote storage"
+            )));
+        };
+        if !self.tenant_shard_id.is_unsharded() {
+            return Err(CreateTimelineError::Snapshot(anyhow::anyhow!(
+                "timelines of sharded tenants can't be created from snapshots"
+            )));
+        }
+
+        let manifest =
+            match snapshot::download_manifest(remote_storage, snapshot_id, &self.cancel).await {
+                Ok(manifest) => manifest,
+                Err(DownloadError::NotFound) => {
+                    return Err(CreateTimelineError::Snapshot(anyhow::anyhow!(
+                        "snapshot {snapshot_id} not found"
+                    )))
+                }
+                Err(DownloadError::Cancelled) => return Err(CreateTimelineError::ShuttingDown),
+                Err(e) => return Err(CreateTimelineError::Other(e.into())),
+            };
+        manifest
+            .validate()
+            .with_context(|| format!("invalid snapshot {snapshot_id}"))
+            .map_err(CreateTimelineError::Snapshot)?;
+        crate::pg_versions::check_installed(self.conf, manifest.pg_version)?;
+
+        // Take the reference before any index refers to the snapshot.
+        snapshot::add_ref(
+            remote_storage,
+            snapshot_id,
+            &self.tenant_shard_id,
+            &timeline_id,
+            &self.cancel,
+        )
+        .await?;
+
+        let new_metadata = TimelineMetadata::new(
+            manifest.lsn,
+            None,
+            None,
+            Lsn(0),
+            manifest.lsn,
+            manifest.lsn,
+            manifest.pg_version,
+        );
+        let start_lsn = manifest.lsn + 1;
+        let prepared = async {
+            let raw_timeline = self
+                .prepare_new_timeline(
+                    timeline_id,
+                    &new_metadata,
+                    timeline_create_guard,
+                    start_lsn,
+                    None,
+                )
+                .await?;
+            let layers = raw_timeline
+                .raw_timeline()?
+                .init_layer_map_from_snapshot(&manifest.layers, start_lsn);
+            anyhow::Ok((raw_timeline, layers))
+        }
+        .await;
+        let (raw_timeline, layers) = match prepared {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                // No index refers to the snapshot yet.
+                if let Err(e) = snapshot::remove_ref(
+                    remote_storage,
+                    snapshot_id,
+                    &self.tenant_shard_id,
+                    &timeline_id,
+                    &self.cancel,
+                )
+                .await
+                {
+                    warn!("failed to release the reference to snapshot {snapshot_id}: {e:#}");
+                }
+                return Err(CreateTimelineError::Other(e));
+            }
+        };
+
+        // From here on, the reference is kept if the creation fails: the index may have been
+        // uploaded, and the timeline then exists in remote storage, until it is deleted.
+        let unfinished_timeline = raw_timeline.raw_timeline()?;
+        if let Some(remote_client) = unfinished_timeline.remote_client.as_ref() {
+            remote_client
+                .schedule_adding_snapshot_layers_and_wait(snapshot_id, &layers)
+                .await
+                .context("upload index with the snapshot layers")?;
+        }
+
+        info!(
+            %snapshot_id,
+            lsn = %manifest.lsn,
+            layers = layers.len(),
+            "created timeline from snapshot"
+        );
+
+        let timeline = raw_timeline.finish_creation()?;
+        Ok(timeline)
+    }
+
     /// Call this before constructing a timeline, to build its required structures
     fn build_timeline_resources(&self, timeline_id: TimelineId) -> TimelineResources {
         let remote_client = if let Some(remote_storage) = self.remote_storage.as_ref() {
@@ -3373,6 +4055,8 @@ impl Tenant {
             remote_client,
             deletion_queue_client: self.deletion_queue_client.clone(),
             timeline_get_throttle: self.timeline_get_throttle.clone(),
+            ephemeral_resources: self.ephemeral_resources.clone(),
+            foreground_reads: self.foreground_reads.clone(),
         }
     }
 
@@ -3394,6 +4078,7 @@ impl Tenant {
         let resources = self.build_timeline_resources(new_timeline_id);
         if let Some(remote_client) = &resources.remote_client {
             remote_client.init_upload_queue_for_empty_remote(new_metadata)?;
+            remote_client.set_legal_hold(self.legal_hold.get())?;
         }
 
         let timeline_struct = self
@@ -3429,7 +4114,10 @@ impl Tenant {
     }
 
     async fn create_timeline_files(&self, timeline_path: &Utf8Path) -> anyhow::Result<()> {
-        crashsafe::create_dir(timeline_path).context("Failed to create timeline directory")?;
+        crash_points::check(&self.tenant_shard_id, "create timeline directory")?;
+        group_fsync::create_dir(timeline_path, self.conf.metadata_fsync_batch_window)
+            .await
+            .context("Failed to create timeline directory")?;
 
         fail::fail_point!("after-timeline-dir-creation", |_| {
             anyhow::bail!("failpoint after-timeline-dir-creation");
@@ -3669,6 +4357,153 @@ async fn run_initdb(
     Ok(())
 }
 
+/// Size of the pipe from the decompression of an initdb archive to its import. The
+/// decompression waits for the import whenever the pipe is full.
+const INITDB_IMPORT_PIPE_SIZE: usize = 1024 * 1024;
+
+/// Where the bootstrap of a timeline imports the output of initdb from.
+enum InitdbSource {
+    /// An initdb archive, removed once imported, and uploaded if `upload` is set.
+    Archive {
+        path: scopeguard::ScopeGuard<Utf8PathBuf, fn(Utf8PathBuf)>,
+        upload: bool,
+    },
+    /// The data directory that initdb just created, tarred as it is imported. The tarball is
+    /// also compressed into `archive` if given, to be cached under `fingerprint` and uploaded
+    /// if `upload` is set.
+    Dir {
+        archive: Option<scopeguard::ScopeGuard<Utf8PathBuf, fn(Utf8PathBuf)>>,
+        fingerprint: Option<u32>,
+        upload: bool,
+    },
+}
+
+/// The tar stream of the initdb archive at `path`.
+async fn open_initdb_tar_zst(path: &Utf8Path) -> anyhow::Result<impl AsyncRead + Send + Unpin> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("open initdb archive {path}"))?;
+    Ok(ZstdDecoder::new(BufReader::with_capacity(
+        remote_timeline_client::BUFFER_SIZE,
+        file,
+    )))
+}
+
+/// Import the data directory that initdb created at `pgdata_path`, tarred into a pipe that the
+/// import reads from, with the control file first. If `archive` is given, the tarball is also
+/// compressed into a new file there as it goes, and the size of the file is returned.
+async fn import_initdb_dir(
+    timeline: &Timeline,
+    pgdata_path: &Utf8Path,
+    archive: Option<&Utf8Path>,
+    pgdata_lsn: Lsn,
+    ctx: &RequestContext,
+) -> anyhow::Result<Option<u64>> {
+    let mut encoder = match archive {
+        Some(path) => Some(ZstdEncoder::with_quality_and_params(
+            tokio::fs::File::create(path)
+                .await
+                .with_context(|| format!("create initdb archive {path}"))?,
+            async_compression::Level::Default,
+            &[async_compression::zstd::CParameter::enable_long_distance_matching(true)],
+        )),
+        None => None,
+    };
+    let (tar_writer, mut tar_reader) = tokio::io::duplex(INITDB_IMPORT_PIPE_SIZE);
+    let (pipe_writer, mut pipe_reader) = tokio::io::duplex(INITDB_IMPORT_PIPE_SIZE);
+    let tar = async move {
+        let mut tar_writer =
+            write_tarball(pgdata_path, &[import_datadir::CONTROLFILE_PATH], tar_writer).await?;
+        tar_writer.shutdown().await?;
+        anyhow::Ok(())
+    };
+    // Copies the tarball into the pipe of the import, and into the archive.
+    let tee = async {
+        let mut pipe_writer = Some(pipe_writer);
+        let mut buf = vec![0; remote_timeline_client::BUFFER_SIZE];
+        loop {
+            let n = tar_reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            if let Some(encoder) = &mut encoder {
+                encoder.write_all(&buf[..n]).await?;
+            }
+            if let Some(writer) = &mut pipe_writer {
+                match writer.write_all(&buf[..n]).await {
+                    Ok(()) => {}
+                    // The import doesn't read the padding after the end of the archive.
+                    Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => pipe_writer = None,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        if let Some(mut writer) = pipe_writer {
+            writer.shutdown().await?;
+        }
+        Ok(())
+    };
+    let import = async move {
+        let res = import_datadir::import_timeline_from_initdb_tar(
+            timeline,
+            &mut pipe_reader,
+            pgdata_lsn,
+            ctx,
+        )
+        .await;
+        // Closing the pipe stops the tee from writing to it if the import returned early.
+        drop(pipe_reader);
+        res
+    };
+    let (tarred, teed, imported) = tokio::join!(tar, tee, import);
+    imported?;
+    teed.context("tee the initdb tarball")?;
+    tarred.context("tar the initdb output")?;
+
+    match encoder {
+        Some(mut encoder) => {
+            encoder.shutdown().await?;
+            Ok(Some(encoder.into_inner().metadata().await?.len()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Import the initdb archive at `path`, decompressed into a pipe that the import reads from.
+async fn import_initdb_tar_zst(
+    timeline: &Timeline,
+    path: &Utf8Path,
+    pgdata_lsn: Lsn,
+    ctx: &RequestContext,
+) -> anyhow::Result<()> {
+    let mut decoder = open_initdb_tar_zst(path).await?;
+    let (mut pipe_writer, mut pipe_reader) = tokio::io::duplex(INITDB_IMPORT_PIPE_SIZE);
+    let decompress = async move {
+        tokio::io::copy(&mut decoder, &mut pipe_writer).await?;
+        pipe_writer.shutdown().await
+    };
+    let import = async move {
+        let res = import_datadir::import_timeline_from_initdb_tar(
+            timeline,
+            &mut pipe_reader,
+            pgdata_lsn,
+            ctx,
+        )
+        .await;
+        // Closing the pipe stops the decompression if the import returned early.
+        drop(pipe_reader);
+        res
+    };
+    let (decompressed, imported) = tokio::join!(decompress, import);
+    imported?;
+    match decompressed {
+        Ok(()) => Ok(()),
+        // The import doesn't read the padding after the end of the archive.
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        Err(e) => Err(anyhow::Error::new(e).context("decompress initdb archive")),
+    }
+}
+
 /// Dump contents of a layer file to stdout.
 pub async fn dump_layerfile_from_path(
     path: &Utf8Path,
@@ -3700,17 +4535,46 @@ pub async fn dump_layerfile_from_path(
     Ok(())
 }
 
-#[cfg(test)]
-pub(crate) mod harness {
+/// Check the structure of the index of a layer file, see [`disk_btree::DiskBtreeReader::verify`].
+pub async fn verify_layerfile_from_path(
+    path: &Utf8Path,
+    ctx: &RequestContext,
+) -> anyhow::Result<Vec<disk_btree::VerifyFinding>> {
+    use std::os::unix::fs::FileExt;
+
+    let file = File::open(path)?;
+    let mut header_buf = [0u8; 2];
+    file.read_exact_at(&mut header_buf, 0)?;
+
+    match u16::from_be_bytes(header_buf) {
+        crate::IMAGE_FILE_MAGIC => ImageLayer::new_for_path(path, file)?.verify(ctx).await,
+        crate::DELTA_FILE_MAGIC => DeltaLayer::new_for_path(path, file)?.verify(ctx).await,
+        magic => bail!("unrecognized magic identifier: {:?}", magic),
+    }
+}
+
+/// A [`Tenant`] on top of local remote storage and a mock WAL redo manager, for tests.
+///
+/// With the `testing` feature, the harness is also available to the integration tests of the
+/// other crates of the workspace.
+#[cfg(any(feature = "testing", test))]
+pub mod harness {
     use bytes::{Bytes, BytesMut};
     use once_cell::sync::OnceCell;
     use pageserver_api::models::ShardParameters;
     use pageserver_api::shard::ShardIndex;
     use utils::logging;
 
+    use std::ops::Range;
+
     use crate::deletion_queue::mock::MockDeletionQueue;
+    use crate::keyspace::KeySpace;
+    use crate::tenant::storage_layer::EvictionError;
     use crate::walredo::apply_neon;
-    use crate::{repository::Key, walrecord::NeonWalRecord};
+    use crate::{
+        repository::{Key, Value},
+        walrecord::NeonWalRecord,
+    };
 
     use super::*;
     use hex_literal::hex;
@@ -3747,6 +4611,8 @@ pub(crate) mod harness {
                 lagging_wal_timeout: Some(tenant_conf.lagging_wal_timeout),
                 max_lsn_wal_lag: Some(tenant_conf.max_lsn_wal_lag),
                 trace_read_requests: Some(tenant_conf.trace_read_requests),
+                capture_wal: Some(tenant_conf.capture_wal),
+                branch_image_creation_threshold: Some(tenant_conf.branch_image_creation_threshold),
                 eviction_policy: Some(tenant_conf.eviction_policy),
                 min_resident_size_override: tenant_conf.min_resident_size_override,
                 evictions_low_residence_duration_metric_threshold: Some(
@@ -3759,6 +4625,21 @@ pub(crate) mod harness {
                     tenant_conf.image_layer_creation_check_threshold,
                 ),
                 switch_aux_file_policy: Some(tenant_conf.switch_aux_file_policy),
+                ephemeral_bytes_limit: Some(tenant_conf.ephemeral_bytes_limit),
+                ancestor_prefetch: Some(tenant_conf.ancestor_prefetch),
+                overlap_repair_threshold: Some(tenant_conf.overlap_repair_threshold),
+                page_request_deadline: Some(tenant_conf.page_request_deadline),
+                sequential_prefetch: Some(tenant_conf.sequential_prefetch),
+                branch_creation_max_compaction_debt: Some(
+                    tenant_conf.branch_creation_max_compaction_debt,
+                ),
+                branch_creation_debt_wait: Some(tenant_conf.branch_creation_debt_wait),
+                alias: None,
+                timeline_aliases: None,
+                gc_pinned_relations: None,
+                tags: None,
+                template: None,
+                read_only: None,
             }
         }
     }
@@ -3776,7 +4657,7 @@ pub(crate) mod harness {
 
     static LOG_HANDLE: OnceCell<()> = OnceCell::new();
 
-    pub(crate) fn setup_logging() {
+    pub fn setup_logging() {
         LOG_HANDLE.get_or_init(|| {
             logging::init(
                 logging::LogFormat::Test,
@@ -3848,7 +4729,7 @@ pub(crate) mod harness {
             info_span!("TenantHarness", tenant_id=%self.tenant_shard_id.tenant_id, shard_id=%self.tenant_shard_id.shard_slug())
         }
 
-        pub(crate) async fn load(&self) -> (Arc<Tenant>, RequestContext) {
+        pub async fn load(&self) -> (Arc<Tenant>, RequestContext) {
             let ctx = RequestContext::new(TaskKind::UnitTest, DownloadBehavior::Error);
             (
                 self.do_try_load(&ctx)
@@ -3859,10 +4740,7 @@ pub(crate) mod harness {
         }
 
         #[instrument(skip_all, fields(tenant_id=%self.tenant_shard_id.tenant_id, shard_id=%self.tenant_shard_id.shard_slug()))]
-        pub(crate) async fn do_try_load(
-            &self,
-            ctx: &RequestContext,
-        ) -> anyhow::Result<Arc<Tenant>> {
+        pub async fn do_try_load(&self, ctx: &RequestContext) -> anyhow::Result<Arc<Tenant>> {
             let walredo_mgr = Arc::new(WalRedoManager::from(TestRedoManager));
 
             let tenant = Arc::new(Tenant::new(
@@ -3897,10 +4775,176 @@ pub(crate) mod harness {
         pub fn timeline_path(&self, timeline_id: &TimelineId) -> Utf8PathBuf {
             self.conf.timeline_path(&self.tenant_shard_id, timeline_id)
         }
+
+        /// Run `fut`, executing the deletions pushed to the mock deletion queue meanwhile.
+        pub async fn with_deletion_queue<F: std::future::Future>(&self, fut: F) -> F::Output {
+            let pump = async {
+                loop {
+                    self.deletion_queue.pump().await;
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            };
+            tokio::select! {
+                res = fut => res,
+                _ = pump => unreachable!(),
+            }
+        }
+    }
+
+    /// Write the values to `timeline`, in LSN order, and flush them to a new layer file.
+    ///
+    /// For the tests and benchmarks of other crates, which can't use the timeline writer.
+    pub async fn put_and_flush(
+        timeline: &Timeline,
+        values: &[(Key, Lsn, Value)],
+        ctx: &RequestContext,
+    ) -> anyhow::Result<()> {
+        let mut writer = timeline.writer().await;
+        for (key, lsn, value) in values {
+            writer.put(*key, *lsn, value, ctx).await?;
+            writer.finish_write(*lsn);
+        }
+        drop(writer);
+        timeline.freeze_and_flush().await
+    }
+
+    /// Read the pages of `keys` at `lsn` with `Timeline::get_vectored`, like the page service.
+    pub async fn get_vectored(
+        timeline: &Timeline,
+        keys: Range<Key>,
+        lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<Bytes>> {
+        let pages = timeline
+            .get_vectored(KeySpace::single(keys), lsn, ctx)
+            .await?;
+        pages
+            .into_values()
+            .map(|page| page.map_err(anyhow::Error::from))
+            .collect()
+    }
+
+    /// Evict the layers of `timeline` once they are uploaded, for the next reads to download
+    /// them.
+    pub async fn evict_layers(timeline: &Timeline) -> anyhow::Result<()> {
+        if let Some(remote_client) = &timeline.remote_client {
+            remote_client.wait_completion().await?;
+        }
+        let layers = timeline
+            .layers
+            .read()
+            .await
+            .likely_resident_layers()
+            .collect::<Vec<_>>();
+        for layer in layers {
+            match layer.evict_and_wait(Duration::from_secs(120)).await {
+                Ok(()) | Err(EvictionError::NotFound) => {}
+                Err(e) => return Err(e).with_context(|| format!("evict {layer}")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a tenant lifecycle operation recovers from a crash at any of the
+    /// [`crash_points`] it passes.
+    ///
+    /// For each crash point in turn, `setup` prepares a fresh tenant, and `op` runs on it until
+    /// it crashes at that point. The operation is then abandoned, and the tenant shut down without
+    /// flushing and loaded again, like by a restarted pageserver. `recover` must bring the loaded
+    /// tenant to the state `op` was meant to reach, and check that state. The simulation ends when
+    /// `op` completes without crashing, and `recover` checks the result of that run too.
+    ///
+    /// Returns the number of crash points passed by `op`.
+    #[cfg(test)]
+    pub(crate) async fn simulate_crashes<S, SFut, O, OFut, R, RFut>(
+        test_name: &'static str,
+        setup: S,
+        op: O,
+        recover: R,
+    ) -> anyhow::Result<usize>
+    where
+        S: Fn(Arc<Tenant>) -> SFut,
+        SFut: std::future::Future<Output = anyhow::Result<()>>,
+        O: Fn(Arc<Tenant>) -> OFut,
+        OFut: std::future::Future<Output = anyhow::Result<()>>,
+        R: Fn(Arc<Tenant>) -> RFut,
+        RFut: std::future::Future<Output = anyhow::Result<()>>,
+    {
+        for crash_at in 0.. {
+            let harness = TenantHarness::create(test_name)?;
+            let tenant_shard_id = harness.tenant_shard_id;
+            let (tenant, ctx) = harness.load().await;
+            harness
+                .with_deletion_queue(setup(Arc::clone(&tenant)))
+                .await
+                .context("setup")?;
+
+            let mut crashed = crash_points::arm(tenant_shard_id, crash_at);
+            let res = harness
+                .with_deletion_queue(async {
+                    tokio::select! {
+                        biased;
+                        _ = crashed.wait_for(|crashed| *crashed) => None,
+                        res = op(Arc::clone(&tenant)).instrument(harness.span()) => Some(res),
+                    }
+                })
+                .await;
+
+            if !crash_points::crashed(&tenant_shard_id) {
+                let crash_points = crash_points::disarm(&tenant_shard_id);
+                res.expect("completed without crashing")
+                    .context("operation failed")?;
+                harness
+                    .with_deletion_queue(recover(tenant))
+                    .await
+                    .context("check completed operation")?;
+                return Ok(crash_points);
+            }
+
+            // Nothing more happens on the crashed tenant, and the pageserver restarts.
+            if tenant
+                .shutdown(Default::default(), timeline::ShutdownMode::Hard)
+                .instrument(harness.span())
+                .await
+                .is_err()
+            {
+                anyhow::bail!("tenant was already shutting down");
+            }
+            crash_points::disarm(&tenant_shard_id);
+            drop(tenant);
+
+            let tenant = harness
+                .do_try_load(&ctx)
+                .await
+                .with_context(|| format!("load after crash point #{crash_at}"))?;
+            harness
+                .with_deletion_queue(recover(tenant))
+                .await
+                .with_context(|| format!("recover from crash point #{crash_at}"))?;
+        }
+        unreachable!()
+    }
+
+    /// Check that nothing but the directories of the tenant's timelines is left in its
+    /// timelines directory: no temporary files, uninit or delete marks.
+    #[cfg(test)]
+    pub(crate) fn assert_timelines_dir_clean(tenant: &Tenant) -> anyhow::Result<()> {
+        let timelines_path = tenant.conf.timelines_path(&tenant.tenant_shard_id);
+        for entry in timelines_path.read_dir_utf8()? {
+            let entry = entry?;
+            let timeline_id = TimelineId::try_from(entry.file_name())
+                .with_context(|| format!("unexpected file {}", entry.path()))?;
+            anyhow::ensure!(
+                entry.file_type()?.is_dir() && tenant.get_timeline(timeline_id, false).is_ok(),
+                "leftover timeline directory {}",
+                entry.path()
+            );
+        }
+        Ok(())
     }
 
     // Mock WAL redo manager that doesn't do much
-    pub(crate) struct TestRedoManager;
+    pub struct TestRedoManager;
 
     impl TestRedoManager {
         /// # Cancel-Safety
@@ -4167,6 +5211,51 @@ mod tests {
         tline.freeze_and_flush().await
     }
 
+    #[tokio::test]
+    async fn test_concurrent_branch_creations() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("test_concurrent_branch_creations")?
+            .load()
+            .await;
+        let tline = tenant
+            .create_test_timeline(TIMELINE_ID, Lsn(0x10), DEFAULT_PG_VERSION, &ctx)
+            .await?;
+        make_some_layers(tline.as_ref(), Lsn(0x20), &ctx).await?;
+
+        // Another branch creation is in progress, and holds gc_cs: the new ones don't wait for it,
+        // nor for each other. GC waits for all of them.
+        let in_progress = tenant.gc_cs.read().await;
+        let branch_ids: Vec<_> = (0..10).map(|_| TimelineId::generate()).collect();
+        let branches = tokio::time::timeout(
+            Duration::from_secs(10),
+            futures::future::try_join_all(
+                branch_ids
+                    .iter()
+                    .map(|id| tenant.branch_timeline_test(&tline, *id, Some(Lsn(0x20)), &ctx)),
+            ),
+        )
+        .await
+        .expect("branch creations waited for the one in progress")?;
+        assert!(tenant.gc_cs.try_write().is_err());
+        drop(in_progress);
+
+        for branch in branches {
+            assert_eq!(branch.get_ancestor_lsn(), Lsn(0x20));
+            branch
+                .remote_client
+                .as_ref()
+                .unwrap()
+                .wait_completion()
+                .await?;
+        }
+        for id in branch_ids {
+            assert!(tenant
+                .conf
+                .timeline_path(&tenant.tenant_shard_id, &id)
+                .is_dir());
+        }
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_prohibit_branch_creation_on_garbage_collected_data() -> anyhow::Result<()> {
         let (tenant, ctx) =
@@ -5489,6 +6578,110 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_timeline_creation_crashes() -> anyhow::Result<()> {
+        let crash_points = simulate_crashes(
+            "test_timeline_creation_crashes",
+            |_| async { Ok(()) },
+            |tenant| async move {
+                let ctx = RequestContext::new(TaskKind::UnitTest, DownloadBehavior::Error);
+                tenant
+                    .create_test_timeline(TIMELINE_ID, Lsn(0x10), DEFAULT_PG_VERSION, &ctx)
+                    .await?;
+                Ok(())
+            },
+            |tenant| async move {
+                let ctx = RequestContext::new(TaskKind::UnitTest, DownloadBehavior::Error);
+                let timeline_path = tenant
+                    .conf
+                    .timeline_path(&tenant.tenant_shard_id, &TIMELINE_ID);
+                if tenant.get_timeline(TIMELINE_ID, false).is_err() {
+                    // The crash came before the index part was uploaded: the creation left
+                    // nothing behind, and can be retried.
+                    anyhow::ensure!(!timeline_path.exists(), "timeline directory left behind");
+                    tenant
+                        .create_test_timeline(TIMELINE_ID, Lsn(0x10), DEFAULT_PG_VERSION, &ctx)
+                        .await?;
+                }
+
+                let tline = tenant.get_timeline(TIMELINE_ID, true)?;
+                assert_eq!(tline.get_disk_consistent_lsn(), Lsn(0x10));
+                assert!(timeline_path.exists());
+                assert_timelines_dir_clean(&tenant)
+            },
+        )
+        .await?;
+        // directory creation, a layer upload and index part uploads
+        assert!(crash_points >= 3, "only {crash_points} crash points");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timeline_deletion_crashes() -> anyhow::Result<()> {
+        async fn delete(tenant: &Arc<Tenant>) -> Result<(), DeleteTimelineError> {
+            DeleteTimelineFlow::run(tenant, TIMELINE_ID, true)
+                .instrument(info_span!("timeline_delete", tenant_id=%tenant.tenant_shard_id.tenant_id, shard_id=%tenant.tenant_shard_id.shard_slug(), timeline_id=%TIMELINE_ID))
+                .await
+        }
+
+        let crash_points = simulate_crashes(
+            "test_timeline_deletion_crashes",
+            |tenant| async move {
+                let ctx = RequestContext::new(TaskKind::UnitTest, DownloadBehavior::Error);
+                let tline = tenant
+                    .create_test_timeline(TIMELINE_ID, Lsn(0x10), DEFAULT_PG_VERSION, &ctx)
+                    .await?;
+                make_some_layers(&tline, Lsn(0x20), &ctx).await?;
+                tline
+                    .remote_client
+                    .as_ref()
+                    .unwrap()
+                    .wait_completion()
+                    .await
+            },
+            |tenant| async move {
+                delete(&tenant).await?;
+                Ok(())
+            },
+            |tenant| async move {
+                // A deletion which persisted its first step is resumed by the load, in the
+                // background. One which didn't left the timeline intact, and is retried.
+                tokio::time::timeout(Duration::from_secs(30), async {
+                    while let Ok(tline) = tenant.get_timeline(TIMELINE_ID, false) {
+                        anyhow::ensure!(!tline.is_broken(), "resumed deletion failed");
+                        if tline.deletion_progress.lock().unwrap().is_none() {
+                            delete(&tenant).await?;
+                        } else {
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                        }
+                    }
+                    Ok(())
+                })
+                .await
+                .context("resumed deletion did not finish")??;
+
+                let (conf, tenant_shard_id) = (tenant.conf, tenant.tenant_shard_id);
+                assert!(!conf.timeline_path(&tenant_shard_id, &TIMELINE_ID).exists());
+                assert!(!conf
+                    .timeline_delete_mark_file_path(tenant_shard_id, TIMELINE_ID)
+                    .exists());
+                let remote_index = remote_timeline_client::remote_index_path(
+                    &tenant_shard_id,
+                    &TIMELINE_ID,
+                    tenant.generation,
+                );
+                assert!(!remote_index
+                    .with_base(&conf.workdir.join("localfs"))
+                    .exists());
+                assert_timelines_dir_clean(&tenant)
+            },
+        )
+        .await?;
+        // delete mark steps, the deleted index part, remote and local deletion
+        assert!(crash_points >= 8, "only {crash_points} crash points");
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_read_at_max_lsn() -> anyhow::Result<()> {
         let names_algorithms = [
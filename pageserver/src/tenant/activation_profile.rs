@@ -0,0 +1,119 @@
+//! Where the activation of a tenant spends its time.
+//!
+//! Some tenants take a user-visible time to activate, see
+//! <https://github.com/neondatabase/neon/issues/4025>. The total time is in
+//! `pageserver_tenant_activation_seconds`, and [`ActivationProfile`] breaks it down into the
+//! [`TenantActivationPhase`]s, so that a slow activation can be attributed to remote storage,
+//! local disk or the warmup queue. Each phase is also observed in
+//! `pageserver_tenant_activation_phase_seconds`, and the profile of a tenant can be queried
+//! with the `/v1/tenant/:tenant_shard_id/activation_profile` API.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use enum_map::EnumMap;
+use pageserver_api::models::{
+    TenantActivationPhase, TenantActivationPhaseTime, TenantActivationProfile,
+};
+
+use crate::metrics::TENANT;
+
+/// The activation profile of a [`super::Tenant`]. A tenant object is activated at most once:
+/// attaching a tenant again constructs a new one, with an empty profile.
+#[derive(Default)]
+pub(crate) struct ActivationProfile {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    phases: EnumMap<TenantActivationPhase, Option<Duration>>,
+    activated_after: Option<Duration>,
+}
+
+impl ActivationProfile {
+    /// Record that `phase` completed after `elapsed`.
+    pub(crate) fn record(&self, phase: TenantActivationPhase, elapsed: Duration) {
+        TENANT.activation_phase[phase].observe(elapsed.as_secs_f64());
+        self.inner.lock().unwrap().phases[phase] = Some(elapsed);
+    }
+
+    /// Record that the tenant became active, `elapsed` after its construction.
+    pub(crate) fn record_activated(&self, elapsed: Duration) {
+        self.inner.lock().unwrap().activated_after = Some(elapsed);
+    }
+
+    pub(crate) fn get(&self) -> TenantActivationProfile {
+        let inner = self.inner.lock().unwrap();
+        TenantActivationProfile {
+            activated_after_millis: inner.activated_after.map(|d| d.as_millis() as u64),
+            phases: inner
+                .phases
+                .iter()
+                .filter_map(|(phase, elapsed)| {
+                    Some(TenantActivationPhaseTime {
+                        phase,
+                        millis: elapsed.as_ref()?.as_millis() as u64,
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for ActivationProfile {
+    /// Formats the completed phases like `list_timelines=12ms download_index_parts=345ms`,
+    /// for logging.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        let mut first = true;
+        for (phase, elapsed) in inner.phases.iter() {
+            let Some(elapsed) = elapsed else {
+                continue;
+            };
+            if !first {
+                write!(f, " ")?;
+            }
+            first = false;
+            let phase: &'static str = phase.into();
+            write!(f, "{phase}={}ms", elapsed.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_lists_completed_phases_in_order() {
+        let profile = ActivationProfile::default();
+        profile.record(
+            TenantActivationPhase::LoadLayerMaps,
+            Duration::from_millis(30),
+        );
+        profile.record(
+            TenantActivationPhase::ListTimelines,
+            Duration::from_millis(10),
+        );
+
+        let got = profile.get();
+        assert_eq!(got.activated_after_millis, None);
+        let phases: Vec<_> = got.phases.iter().map(|p| (p.phase, p.millis)).collect();
+        assert_eq!(
+            phases,
+            vec![
+                (TenantActivationPhase::ListTimelines, 10),
+                (TenantActivationPhase::LoadLayerMaps, 30)
+            ]
+        );
+        assert_eq!(
+            profile.to_string(),
+            "list_timelines=10ms load_layer_maps=30ms"
+        );
+
+        profile.record_activated(Duration::from_millis(50));
+        assert_eq!(profile.get().activated_after_millis, Some(50));
+    }
+}
@@ -21,6 +21,9 @@ use crate::virtual_file::VirtualFile;
 use std::cmp::min;
 use std::io::{Error, ErrorKind};
 
+/// The most memory reserved for a blob before reading it.
+const MAX_RESERVE: usize = 1024 * 1024;
+
 impl<'a> BlockCursor<'a> {
     /// Read a blob into a new buffer.
     pub async fn read_blob(
@@ -40,7 +43,8 @@ impl<'a> BlockCursor<'a> {
         dstbuf: &mut Vec<u8>,
         ctx: &RequestContext,
     ) -> Result<(), std::io::Error> {
-        let mut blknum = (offset / PAGE_SZ as u64) as u32;
+        let out_of_range = || Error::new(ErrorKind::InvalidData, "blob offset out of range");
+        let mut blknum = u32::try_from(offset / PAGE_SZ as u64).map_err(|_| out_of_range())?;
         let mut off = (offset % PAGE_SZ as u64) as usize;
 
         let mut buf = self.read_blk(blknum, ctx).await?;
@@ -58,7 +62,7 @@ impl<'a> BlockCursor<'a> {
             if thislen < 4 {
                 // it is split across two pages
                 len_buf[..thislen].copy_from_slice(&buf[off..PAGE_SZ]);
-                blknum += 1;
+                blknum = blknum.checked_add(1).ok_or_else(out_of_range)?;
                 buf = self.read_blk(blknum, ctx).await?;
                 len_buf[thislen..].copy_from_slice(&buf[0..4 - thislen]);
                 off = 4 - thislen;
@@ -71,7 +75,9 @@ impl<'a> BlockCursor<'a> {
         };
 
         dstbuf.clear();
-        dstbuf.reserve(len);
+        // Don't trust the length with a large allocation up front: if it's corrupted, reading
+        // the payload fails at the end of the file instead.
+        dstbuf.reserve(min(len, MAX_RESERVE));
 
         // Read the payload
         let mut remain = len;
@@ -79,7 +85,7 @@ impl<'a> BlockCursor<'a> {
             let mut page_remain = PAGE_SZ - off;
             if page_remain == 0 {
                 // continue on next page
-                blknum += 1;
+                blknum = blknum.checked_add(1).ok_or_else(out_of_range)?;
                 buf = self.read_blk(blknum, ctx).await?;
                 off = 0;
                 page_remain = PAGE_SZ;
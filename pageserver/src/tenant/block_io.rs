@@ -4,6 +4,7 @@
 
 use super::ephemeral_file::EphemeralFile;
 use super::storage_layer::delta_layer::{Adapter, DeltaLayerInner};
+use crate::context::read_path::ReadPathClass;
 use crate::context::RequestContext;
 use crate::page_cache::{self, FileId, PageReadGuard, PageWriteGuard, ReadBufResult, PAGE_SZ};
 use crate::virtual_file::VirtualFile;
@@ -203,8 +204,12 @@ impl<'a> FileBlockReader<'a> {
                     format!("Failed to read immutable buf: {e:#}"),
                 )
             })? {
-            ReadBufResult::Found(guard) => Ok(guard.into()),
+            ReadBufResult::Found(guard) => {
+                ctx.read_path.record(ReadPathClass::PageCache);
+                Ok(guard.into())
+            }
             ReadBufResult::NotFound(write_guard) => {
+                ctx.read_path.record(ReadPathClass::LocalLayer);
                 // Read the page from disk into the buffer
                 let write_guard = self.fill_buffer(write_guard, blknum).await?;
                 Ok(write_guard.mark_valid().into())
@@ -0,0 +1,533 @@
+//! Tenant bundles, to move tenants between pageserver installations.
+//!
+//! Tenants normally move between pageservers by being attached somewhere else, which needs
+//! both pageservers to use the same remote storage. A bundle is a directory holding a copy of
+//! all the data of an unsharded tenant, which can be moved by any means, like to another S3
+//! bucket or account:
+//!
+//! ```text
+//! manifest.json
+//! timelines/<timeline_id>/index_part.json
+//! timelines/<timeline_id>/<layer file name>
+//! ```
+//!
+//! The [`TenantBundleManifest`] lists every file with its size and CRC32C, along with the
+//! tenant config. It is written last by [`export_tenant`], so that a bundle without a manifest
+//! is incomplete.
+//!
+//! [`import_tenant`] checks every file of a bundle against the manifest before uploading
+//! anything, and then uploads the layers and index parts of the timelines to the remote storage
+//! of this installation, in the generation that the tenant is going to be attached with. The
+//! tenant is then attached as usual, with the config of the manifest. The remote storage must
+//! not have any timeline of the tenant yet: a failed import has to be cleaned up before it is
+//! retried. Snapshots and the hot tier are specific to an installation: the imported index
+//! parts reference all the layers in the regular timeline paths, and no snapshot.
+//!
+//! Bundles are only written and read under the `bundle_dir` of the pageserver config: the
+//! API takes paths relative to it, see [`resolve_bundle_path`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use futures::StreamExt;
+use pageserver_api::models::{self, TenantBundleFile, TenantBundleManifest, TenantBundleTimeline};
+use pageserver_api::shard::{ShardIndex, TenantShardId};
+use remote_storage::{DownloadError, GenericRemoteStorage, RemotePath};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use utils::crashsafe::path_with_suffix_extension;
+use utils::generation::Generation;
+use utils::id::{TenantId, TimelineId};
+
+use crate::config::PageServerConf;
+use crate::tenant::remote_timeline_client::index::IndexPart;
+use crate::tenant::remote_timeline_client::{
+    download::list_remote_timelines, remote_layer_path, upload::upload_index_part,
+    MaybeDeletedIndexPart,
+};
+use crate::tenant::storage_layer::LayerName;
+use crate::tenant::{Tenant, Timeline, TIMELINES_SEGMENT_NAME};
+use crate::virtual_file::VirtualFile;
+use crate::TEMP_FILE_SUFFIX;
+
+/// The version of the bundle format, bumped on incompatible changes.
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum BundleError {
+    #[error("tenant {0} is sharded: only unsharded tenants can be exported")]
+    Sharded(TenantShardId),
+    #[error("bundle directory {0} is not empty")]
+    NotEmpty(Utf8PathBuf),
+    #[error("invalid bundle: {0}")]
+    Invalid(String),
+    #[error("bundle_dir is not configured")]
+    NotConfigured,
+    #[error("invalid bundle path {0:?}: it must be relative, without '..'")]
+    InvalidPath(String),
+    #[error("remote storage already has timelines of tenant {0}")]
+    AlreadyExists(TenantId),
+    #[error("cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Resolves a bundle path of the API to a directory under the `bundle_dir` of the config.
+/// The path must be relative and must not leave `bundle_dir`.
+pub(crate) fn resolve_bundle_path(
+    conf: &PageServerConf,
+    path: &str,
+) -> Result<Utf8PathBuf, BundleError> {
+    let Some(bundle_dir) = conf.bundle_dir.as_ref() else {
+        return Err(BundleError::NotConfigured);
+    };
+    if !is_contained_path(Utf8Path::new(path)) {
+        return Err(BundleError::InvalidPath(path.to_string()));
+    }
+    Ok(bundle_dir.join(path))
+}
+
+/// Whether a path is relative, not empty, and only made of normal components, so that joining
+/// it to a directory stays within that directory.
+fn is_contained_path(path: &Utf8Path) -> bool {
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|c| matches!(c, Utf8Component::Normal(_)))
+}
+
+/// The path of a layer in a bundle, relative to its directory.
+fn layer_path(timeline_id: &TimelineId, layer_name: &LayerName) -> String {
+    format!("{TIMELINES_SEGMENT_NAME}/{timeline_id}/{layer_name}")
+}
+
+/// The path of the index part of a timeline in a bundle, relative to its directory.
+fn index_part_path(timeline_id: &TimelineId) -> String {
+    format!(
+        "{TIMELINES_SEGMENT_NAME}/{timeline_id}/{}",
+        IndexPart::FILE_NAME
+    )
+}
+
+/// Export `tenant` to a bundle in `dir`, which must be empty or not exist yet. The layers are
+/// read from remote storage, after the in-memory layers of every timeline are flushed and
+/// uploaded.
+pub(crate) async fn export_tenant(
+    tenant: &Arc<Tenant>,
+    dir: &Utf8Path,
+    cancel: &CancellationToken,
+) -> Result<TenantBundleManifest, BundleError> {
+    let tenant_shard_id = tenant.tenant_shard_id();
+    if !tenant_shard_id.is_unsharded() {
+        return Err(BundleError::Sharded(tenant_shard_id));
+    }
+    let storage = tenant
+        .remote_storage
+        .as_ref()
+        .context("remote storage not configured")?;
+
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("create bundle directory {dir}"))?;
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("read bundle directory {dir}"))?;
+    if entries
+        .next_entry()
+        .await
+        .context("read bundle directory")?
+        .is_some()
+    {
+        return Err(BundleError::NotEmpty(dir.to_owned()));
+    }
+
+    let mut timelines = Vec::new();
+    for timeline in tenant.list_timelines() {
+        if let Some(exported) = export_timeline(storage, &timeline, dir, cancel).await? {
+            timelines.push(exported);
+        }
+    }
+
+    let manifest = TenantBundleManifest {
+        format_version: FORMAT_VERSION,
+        tenant_id: tenant_shard_id.tenant_id,
+        created_at: SystemTime::now(),
+        config: models::TenantConfig::from(tenant.get_tenant_conf()),
+        timelines,
+    };
+    let path = dir.join(MANIFEST_FILE_NAME);
+    let temp_path = path_with_suffix_extension(&path, TEMP_FILE_SUFFIX);
+    let content = serde_json::to_vec_pretty(&manifest).context("serialize bundle manifest")?;
+    VirtualFile::crashsafe_overwrite(path, temp_path, content)
+        .await
+        .context("write bundle manifest")?;
+
+    info!(
+        timelines = manifest.timelines.len(),
+        "exported tenant to bundle {dir}"
+    );
+    Ok(manifest)
+}
+
+/// Export a timeline to `dir`. Returns `None` for a timeline that is being deleted.
+async fn export_timeline(
+    storage: &GenericRemoteStorage,
+    timeline: &Arc<Timeline>,
+    dir: &Utf8Path,
+    cancel: &CancellationToken,
+) -> Result<Option<TenantBundleTimeline>, BundleError> {
+    let timeline_id = timeline.timeline_id;
+    let remote_client = timeline
+        .remote_client
+        .as_ref()
+        .context("remote storage not configured")?;
+
+    timeline
+        .freeze_and_flush()
+        .await
+        .with_context(|| format!("flush timeline {timeline_id}"))?;
+    remote_client
+        .wait_completion()
+        .await
+        .with_context(|| format!("wait for the uploads of timeline {timeline_id}"))?;
+
+    let index_part = match remote_client.download_index_file(cancel).await {
+        Ok(MaybeDeletedIndexPart::IndexPart(index_part)) => index_part,
+        Ok(MaybeDeletedIndexPart::Deleted(_)) => return Ok(None),
+        Err(DownloadError::Cancelled) => return Err(BundleError::Cancelled),
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context(format!("download index part of timeline {timeline_id}"))
+                .into())
+        }
+    };
+
+    let timeline_dir = dir
+        .join(TIMELINES_SEGMENT_NAME)
+        .join(timeline_id.to_string());
+    tokio::fs::create_dir_all(&timeline_dir)
+        .await
+        .with_context(|| format!("create directory {timeline_dir}"))?;
+
+    let mut layers = Vec::with_capacity(index_part.layer_metadata.len());
+    for (layer_name, metadata) in &index_part.layer_metadata {
+        // The layer may be in a snapshot, or in the hot tier.
+        let (storage, remote_path) = match remote_client.uploaded_layer_location(layer_name) {
+            Some((storage, remote_path, _)) => (storage, remote_path),
+            None => (
+                storage,
+                remote_layer_path(
+                    &timeline.tenant_shard_id.tenant_id,
+                    &timeline_id,
+                    metadata.shard,
+                    layer_name,
+                    metadata.generation,
+                ),
+            ),
+        };
+        let path = layer_path(&timeline_id, layer_name);
+        let file = download_file(storage, &remote_path, dir, path, cancel).await?;
+        if file.size != metadata.file_size {
+            return Err(anyhow::anyhow!(
+                "layer {remote_path} has size {}, but the index part says {}",
+                file.size,
+                metadata.file_size
+            )
+            .into());
+        }
+        layers.push(file);
+    }
+
+    let path = index_part_path(&timeline_id);
+    let content = index_part.to_s3_bytes().context("serialize index part")?;
+    let index_part = TenantBundleFile {
+        path: path.clone(),
+        size: content.len() as u64,
+        crc32c: crc32c::crc32c(&content),
+    };
+    let path = dir.join(path);
+    let temp_path = path_with_suffix_extension(&path, TEMP_FILE_SUFFIX);
+    VirtualFile::crashsafe_overwrite(path, temp_path, content)
+        .await
+        .context("write index part")?;
+
+    Ok(Some(TenantBundleTimeline {
+        timeline_id,
+        index_part,
+        layers,
+    }))
+}
+
+/// Download `remote_path` to `path`, relative to the bundle directory `dir`.
+async fn download_file(
+    storage: &GenericRemoteStorage,
+    remote_path: &RemotePath,
+    dir: &Utf8Path,
+    path: String,
+    cancel: &CancellationToken,
+) -> Result<TenantBundleFile, BundleError> {
+    let download = match storage.download(remote_path, cancel).await {
+        Ok(download) => download,
+        Err(DownloadError::Cancelled) => return Err(BundleError::Cancelled),
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context(format!("download {remote_path}"))
+                .into())
+        }
+    };
+
+    let local_path = dir.join(&path);
+    let mut file = tokio::fs::File::create(&local_path)
+        .await
+        .with_context(|| format!("create {local_path}"))?;
+    let mut stream = download.download_stream;
+    let mut size = 0;
+    let mut crc32c = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.with_context(|| format!("download {remote_path}"))?;
+        size += chunk.len() as u64;
+        crc32c = crc32c::crc32c_append(crc32c, &chunk);
+        file.write_all(&chunk)
+            .await
+            .with_context(|| format!("write {local_path}"))?;
+    }
+    file.sync_all()
+        .await
+        .with_context(|| format!("fsync {local_path}"))?;
+
+    Ok(TenantBundleFile { path, size, crc32c })
+}
+
+/// Check that a file of the bundle in `dir` has the size and checksum of the manifest.
+async fn verify_file(dir: &Utf8Path, expected: &TenantBundleFile) -> Result<(), BundleError> {
+    if !is_contained_path(Utf8Path::new(&expected.path)) {
+        return Err(BundleError::Invalid(format!(
+            "file {} is outside of the bundle",
+            expected.path
+        )));
+    }
+    let path = dir.join(&expected.path);
+    let mut file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|e| BundleError::Invalid(format!("cannot open {}: {e}", expected.path)))?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+    let mut crc32c = 0;
+    loop {
+        let n = file
+            .read(&mut buf)
+            .await
+            .with_context(|| format!("read {path}"))?;
+        if n == 0 {
+            break;
+        }
+        size += n as u64;
+        crc32c = crc32c::crc32c_append(crc32c, &buf[..n]);
+    }
+    if size != expected.size || crc32c != expected.crc32c {
+        return Err(BundleError::Invalid(format!(
+            "{} has size {size} and checksum {crc32c:#x}, expected {} and {:#x}",
+            expected.path, expected.size, expected.crc32c
+        )));
+    }
+    Ok(())
+}
+
+/// Read the manifest of the bundle in `dir`, and check all its files.
+async fn read_manifest(dir: &Utf8Path) -> Result<TenantBundleManifest, BundleError> {
+    let content = tokio::fs::read(dir.join(MANIFEST_FILE_NAME))
+        .await
+        .map_err(|e| BundleError::Invalid(format!("cannot read the manifest: {e}")))?;
+    let manifest: TenantBundleManifest = serde_json::from_slice(&content)
+        .map_err(|e| BundleError::Invalid(format!("cannot parse the manifest: {e}")))?;
+    if manifest.format_version != FORMAT_VERSION {
+        return Err(BundleError::Invalid(format!(
+            "unsupported format version {}",
+            manifest.format_version
+        )));
+    }
+
+    for timeline in &manifest.timelines {
+        verify_file(dir, &timeline.index_part).await?;
+        for layer in &timeline.layers {
+            verify_file(dir, layer).await?;
+        }
+    }
+    Ok(manifest)
+}
+
+/// Import the bundle in `dir` into the remote storage, as tenant `tenant_id` in `generation`.
+/// Returns the manifest, whose config the tenant is to be attached with.
+pub(crate) async fn import_tenant(
+    conf: &'static PageServerConf,
+    storage: &GenericRemoteStorage,
+    tenant_id: TenantId,
+    dir: &Utf8Path,
+    generation: Generation,
+    cancel: &CancellationToken,
+) -> Result<TenantBundleManifest, BundleError> {
+    let manifest = read_manifest(dir).await?;
+    if manifest.tenant_id != tenant_id {
+        return Err(BundleError::Invalid(format!(
+            "the bundle is of tenant {}",
+            manifest.tenant_id
+        )));
+    }
+
+    let tenant_shard_id = TenantShardId::unsharded(tenant_id);
+    let (existing, _) = list_remote_timelines(storage, tenant_shard_id, cancel.clone())
+        .await
+        .context("list remote timelines")?;
+    if !existing.is_empty() {
+        return Err(BundleError::AlreadyExists(tenant_id));
+    }
+
+    for timeline in &manifest.timelines {
+        let timeline_id = timeline.timeline_id;
+        if timeline.index_part.path != index_part_path(&timeline_id) {
+            return Err(BundleError::Invalid(format!(
+                "unexpected index part {} of timeline {timeline_id}",
+                timeline.index_part.path
+            )));
+        }
+        let content = tokio::fs::read(dir.join(&timeline.index_part.path))
+            .await
+            .with_context(|| format!("read index part of timeline {timeline_id}"))?;
+        let mut index_part = IndexPart::from_s3_bytes(&content).map_err(|e| {
+            BundleError::Invalid(format!("cannot parse index part of {timeline_id}: {e}"))
+        })?;
+        index_part.snapshots.clear();
+        index_part.hot_layers.clear();
+
+        let files: HashMap<&str, &TenantBundleFile> = timeline
+            .layers
+            .iter()
+            .map(|file| (file.path.as_str(), file))
+            .collect();
+        for (layer_name, metadata) in index_part.layer_metadata.iter_mut() {
+            let path = layer_path(&timeline_id, layer_name);
+            let Some(file) = files.get(path.as_str()) else {
+                return Err(BundleError::Invalid(format!("{path} is missing")));
+            };
+            let remote_path = remote_layer_path(
+                &tenant_id,
+                &timeline_id,
+                ShardIndex::unsharded(),
+                layer_name,
+                generation,
+            );
+            upload_file(storage, dir, file, &remote_path, cancel).await?;
+            metadata.generation = generation;
+            metadata.shard = ShardIndex::unsharded();
+        }
+
+        upload_index_part(
+            conf,
+            storage,
+            &tenant_shard_id,
+            &timeline_id,
+            generation,
+            &index_part,
+            cancel,
+        )
+        .await
+        .with_context(|| format!("upload index part of timeline {timeline_id}"))?;
+    }
+
+    info!(
+        timelines = manifest.timelines.len(),
+        "imported tenant from bundle {dir}"
+    );
+    Ok(manifest)
+}
+
+async fn upload_file(
+    storage: &GenericRemoteStorage,
+    dir: &Utf8Path,
+    file: &TenantBundleFile,
+    remote_path: &RemotePath,
+    cancel: &CancellationToken,
+) -> Result<(), BundleError> {
+    if cancel.is_cancelled() {
+        return Err(BundleError::Cancelled);
+    }
+    let path = dir.join(&file.path);
+    let source = tokio::fs::File::open(&path)
+        .await
+        .with_context(|| format!("open {path}"))?;
+    let size = usize::try_from(file.size).context("file size")?;
+    let reader = tokio_util::io::ReaderStream::with_capacity(source, 64 * 1024);
+    storage
+        .upload(reader, size, remote_path, None, cancel)
+        .await
+        .with_context(|| format!("upload {path} to {remote_path}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verify_file_checks_size_and_checksum() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let content = b"some layer contents";
+        tokio::fs::write(dir.path().join("layer"), content)
+            .await
+            .unwrap();
+
+        let mut expected = TenantBundleFile {
+            path: "layer".to_string(),
+            size: content.len() as u64,
+            crc32c: crc32c::crc32c(content),
+        };
+        verify_file(dir.path(), &expected).await.unwrap();
+
+        expected.crc32c ^= 1;
+        let err = verify_file(dir.path(), &expected).await.unwrap_err();
+        assert!(matches!(err, BundleError::Invalid(_)), "{err}");
+
+        expected.crc32c ^= 1;
+        expected.size += 1;
+        let err = verify_file(dir.path(), &expected).await.unwrap_err();
+        assert!(matches!(err, BundleError::Invalid(_)), "{err}");
+
+        expected.path = "missing".to_string();
+        let err = verify_file(dir.path(), &expected).await.unwrap_err();
+        assert!(matches!(err, BundleError::Invalid(_)), "{err}");
+
+        expected.path = "../layer".to_string();
+        let err = verify_file(dir.path(), &expected).await.unwrap_err();
+        assert!(matches!(err, BundleError::Invalid(_)), "{err}");
+    }
+
+    #[test]
+    fn bundle_paths_stay_in_bundle_dir() {
+        let mut conf = PageServerConf::dummy_conf(Utf8PathBuf::from("/repo"));
+        let err = resolve_bundle_path(&conf, "bundle").unwrap_err();
+        assert!(matches!(err, BundleError::NotConfigured), "{err}");
+
+        conf.bundle_dir = Some(Utf8PathBuf::from("/repo/bundles"));
+        assert_eq!(
+            resolve_bundle_path(&conf, "tenant/bundle").unwrap(),
+            Utf8PathBuf::from("/repo/bundles/tenant/bundle")
+        );
+        for path in [
+            "",
+            "/tmp/bundle",
+            "../bundle",
+            "tenant/../../bundle",
+            "./bundle",
+        ] {
+            let err = resolve_bundle_path(&conf, path).unwrap_err();
+            assert!(matches!(err, BundleError::InvalidPath(_)), "{path}: {err}");
+        }
+    }
+}
@@ -8,18 +8,25 @@
 //! We cannot use global or default config instead, because wrong settings
 //! may lead to a data loss.
 //!
-use anyhow::bail;
+use anyhow::{bail, Context};
 use pageserver_api::models::AuxFilePolicy;
 use pageserver_api::models::CompactionAlgorithm;
 use pageserver_api::models::EvictionPolicy;
+use pageserver_api::models::PinnedRelation;
+use pageserver_api::models::ReadOnlyReason;
 use pageserver_api::models::{self, ThrottleConfig};
-use pageserver_api::shard::{ShardCount, ShardIdentity, ShardNumber, ShardStripeSize};
+use pageserver_api::shard::{
+    ShardCount, ShardIdentity, ShardNumber, ShardStripeSize, TenantShardId,
+};
 use serde::de::IntoDeserializer;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::num::NonZeroU64;
+use std::str::FromStr;
 use std::time::Duration;
 use utils::generation::Generation;
+use utils::id::TimelineId;
 
 pub mod defaults {
 
@@ -61,6 +68,8 @@ pub mod defaults {
     // By default ingest enough WAL for two new L0 layers before checking if new image
     // image layers should be created.
     pub const DEFAULT_IMAGE_LAYER_CREATION_CHECK_THRESHOLD: u8 = 2;
+    // Far above the image creation threshold: only pathological delta stacks are repaired.
+    pub const DEFAULT_OVERLAP_REPAIR_THRESHOLD: usize = 100;
 
     pub const DEFAULT_INGEST_BATCH_SIZE: u64 = 100;
 }
@@ -350,6 +359,14 @@ pub struct TenantConf {
     /// to avoid eager reconnects.
     pub max_lsn_wal_lag: NonZeroU64,
     pub trace_read_requests: bool,
+    /// Capture the WAL records ingested by the walreceiver to files, to replay them when
+    /// debugging ingest. See [`crate::wal_capture`].
+    pub capture_wal: bool,
+    /// Create image layers at the branch point of new branches, so that reads on the branch
+    /// don't go through long delta chains in the ancestor: for the whole keyspace if it is
+    /// smaller than this many bytes, otherwise for the key ranges with deep delta chains only.
+    /// 0 disables it.
+    pub branch_image_creation_threshold: u64,
     pub eviction_policy: EvictionPolicy,
     pub min_resident_size_override: Option<u64>,
     // See the corresponding metric's help string.
@@ -374,6 +391,40 @@ pub struct TenantConf {
     /// Switch to a new aux file policy. Switching this flag requires the user has not written any aux file into
     /// the storage before, and this flag cannot be switched back. Otherwise there will be data corruptions.
     pub switch_aux_file_policy: AuxFilePolicy,
+
+    /// Limit on the total size of the open and frozen in-memory layers of all timelines of
+    /// the tenant shard.  When it is exceeded, the largest layers are frozen early.
+    /// Zero means no limit, only the pageserver-wide `ephemeral_bytes_per_memory_kb` applies.
+    pub ephemeral_bytes_limit: u64,
+
+    /// If true, the ancestor layers that reads on a branch hit often are downloaded ahead of
+    /// time when they get evicted, see [`crate::tenant::timeline::ancestor_prefetch`].
+    pub ancestor_prefetch: bool,
+
+    /// Compaction creates image layers for the key ranges with more than this many delta
+    /// layers stacked above their latest image, regardless of `image_creation_threshold` and
+    /// `image_layer_creation_check_threshold`. 0 disables it. See
+    /// [`crate::tenant::timeline::overlap_analysis`].
+    pub overlap_repair_threshold: usize,
+
+    /// Reads for page_service requests that take longer than this are given up, and the
+    /// request fails with an error. Zero means no deadline. Read when the compute connects,
+    /// changes apply to new connections. See [`crate::context`].
+    #[serde(with = "humantime_serde")]
+    pub page_request_deadline: Duration,
+
+    /// If true, GetPage requests that read a relation sequentially make the pageserver read
+    /// the next blocks ahead of them, see [`crate::page_service::prefetch`].
+    pub sequential_prefetch: bool,
+
+    /// Branches can't be created while the compaction debt of the tenant shard, the bytes of
+    /// its L0 delta layers, is above this. 0 disables the limit.
+    pub branch_creation_max_compaction_debt: u64,
+
+    /// How long the creation of a branch waits for compaction to bring the debt under
+    /// `branch_creation_max_compaction_debt`, before it fails.
+    #[serde(with = "humantime_serde")]
+    pub branch_creation_debt_wait: Duration,
 }
 
 /// Same as TenantConf, but this struct preserves the information about
@@ -442,6 +493,14 @@ pub struct TenantConfOpt {
     #[serde(default)]
     pub trace_read_requests: Option<bool>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub capture_wal: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub branch_image_creation_threshold: Option<u64>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub eviction_policy: Option<EvictionPolicy>,
@@ -473,6 +532,72 @@ pub struct TenantConfOpt {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub switch_aux_file_policy: Option<AuxFilePolicy>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub ephemeral_bytes_limit: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub ancestor_prefetch: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub overlap_repair_threshold: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(with = "humantime_serde")]
+    #[serde(default)]
+    pub page_request_deadline: Option<Duration>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub sequential_prefetch: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub branch_creation_max_compaction_debt: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(with = "humantime_serde")]
+    #[serde(default)]
+    pub branch_creation_debt_wait: Option<Duration>,
+
+    /// Name which can be used instead of the tenant ID in management API paths.
+    /// Not inherited from the pageserver's defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub alias: Option<String>,
+
+    /// Names which can be used instead of timeline IDs in management API paths.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub timeline_aliases: Option<BTreeMap<String, TimelineId>>,
+
+    /// Relations whose history GC keeps regardless of `gc_horizon` and `pitr_interval`, like
+    /// audit tables. Not inherited from the pageserver's defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub gc_pinned_relations: Option<Vec<PinnedRelation>>,
+
+    /// Free-form labels of the tenant, like its team or tier. Those allowed by the
+    /// `tenant_tag_labels` pageserver config are propagated to metrics and logs.
+    /// Not inherited from the pageserver's defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub tags: Option<BTreeMap<String, String>>,
+
+    /// The config template the tenant was set up from, as `name@version`, see
+    /// [`crate::tenant::config_templates`]. Not inherited from the pageserver's defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub template: Option<String>,
+
+    /// Set while the ingest of WAL into the tenant is rejected, see
+    /// [`crate::tenant::Tenant::set_read_only`]. Not inherited from the pageserver's defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub read_only: Option<ReadOnlyReason>,
 }
 
 impl TenantConfOpt {
@@ -512,6 +637,10 @@ impl TenantConfOpt {
             trace_read_requests: self
                 .trace_read_requests
                 .unwrap_or(global_conf.trace_read_requests),
+            capture_wal: self.capture_wal.unwrap_or(global_conf.capture_wal),
+            branch_image_creation_threshold: self
+                .branch_image_creation_threshold
+                .unwrap_or(global_conf.branch_image_creation_threshold),
             eviction_policy: self.eviction_policy.unwrap_or(global_conf.eviction_policy),
             min_resident_size_override: self
                 .min_resident_size_override
@@ -533,6 +662,27 @@ impl TenantConfOpt {
             switch_aux_file_policy: self
                 .switch_aux_file_policy
                 .unwrap_or(global_conf.switch_aux_file_policy),
+            ephemeral_bytes_limit: self
+                .ephemeral_bytes_limit
+                .unwrap_or(global_conf.ephemeral_bytes_limit),
+            ancestor_prefetch: self
+                .ancestor_prefetch
+                .unwrap_or(global_conf.ancestor_prefetch),
+            overlap_repair_threshold: self
+                .overlap_repair_threshold
+                .unwrap_or(global_conf.overlap_repair_threshold),
+            page_request_deadline: self
+                .page_request_deadline
+                .unwrap_or(global_conf.page_request_deadline),
+            sequential_prefetch: self
+                .sequential_prefetch
+                .unwrap_or(global_conf.sequential_prefetch),
+            branch_creation_max_compaction_debt: self
+                .branch_creation_max_compaction_debt
+                .unwrap_or(global_conf.branch_creation_max_compaction_debt),
+            branch_creation_debt_wait: self
+                .branch_creation_debt_wait
+                .unwrap_or(global_conf.branch_creation_debt_wait),
         }
     }
 }
@@ -564,6 +714,8 @@ impl Default for TenantConf {
             max_lsn_wal_lag: NonZeroU64::new(DEFAULT_MAX_WALRECEIVER_LSN_WAL_LAG)
                 .expect("cannot parse default max walreceiver Lsn wal lag"),
             trace_read_requests: false,
+            capture_wal: false,
+            branch_image_creation_threshold: 0,
             eviction_policy: EvictionPolicy::NoEviction,
             min_resident_size_override: None,
             evictions_low_residence_duration_metric_threshold: humantime::parse_duration(
@@ -575,6 +727,13 @@ impl Default for TenantConf {
             timeline_get_throttle: crate::tenant::throttle::Config::disabled(),
             image_layer_creation_check_threshold: DEFAULT_IMAGE_LAYER_CREATION_CHECK_THRESHOLD,
             switch_aux_file_policy: AuxFilePolicy::V1,
+            ephemeral_bytes_limit: 0,
+            ancestor_prefetch: false,
+            overlap_repair_threshold: DEFAULT_OVERLAP_REPAIR_THRESHOLD,
+            page_request_deadline: Duration::ZERO,
+            sequential_prefetch: false,
+            branch_creation_max_compaction_debt: 0,
+            branch_creation_debt_wait: Duration::ZERO,
         }
     }
 }
@@ -592,10 +751,46 @@ impl TryFrom<&'_ models::TenantConfig> for TenantConfOpt {
         // Use serde_path_to_error to deserialize the JSON Value into TenantConfOpt
         let tenant_conf: TenantConfOpt = serde_path_to_error::deserialize(deserializer)?;
 
+        if let Some(alias) = &tenant_conf.alias {
+            validate_alias(alias).context("alias")?;
+        }
+        for alias in tenant_conf.timeline_aliases.iter().flat_map(BTreeMap::keys) {
+            validate_alias(alias).context("timeline_aliases")?;
+        }
+        for (key, value) in tenant_conf.tags.iter().flatten() {
+            super::tags::validate_tag(key, value).context("tags")?;
+        }
+
         Ok(tenant_conf)
     }
 }
 
+/// Aliases share the path segment with hex IDs, so they must not be mistaken for one, or for
+/// the alias of a shard, and must be usable in a URL without escaping.
+fn validate_alias(alias: &str) -> anyhow::Result<()> {
+    const MAX_ALIAS_LEN: usize = 63;
+
+    if alias.is_empty() || alias.len() > MAX_ALIAS_LEN {
+        bail!("alias {alias:?} must be between 1 and {MAX_ALIAS_LEN} characters long");
+    }
+    if !alias
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+    {
+        bail!("alias {alias:?} may only contain lowercase letters, digits, '-' and '_'");
+    }
+    if TimelineId::from_str(alias).is_ok() || TenantShardId::from_str(alias).is_ok() {
+        bail!("alias {alias:?} can't be told apart from an ID");
+    }
+    // Shards are addressed by appending their suffix to the alias of the tenant.
+    if let Some((_, suffix)) = alias.rsplit_once('-') {
+        if suffix.len() == 4 && suffix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("alias {alias:?} can't be told apart from a shard suffix");
+        }
+    }
+    Ok(())
+}
+
 impl TryFrom<toml_edit::Item> for TenantConfOpt {
     type Error = anyhow::Error;
 
@@ -640,6 +835,8 @@ impl From<TenantConfOpt> for models::TenantConfig {
             lagging_wal_timeout: value.lagging_wal_timeout.map(humantime),
             max_lsn_wal_lag: value.max_lsn_wal_lag,
             trace_read_requests: value.trace_read_requests,
+            capture_wal: value.capture_wal,
+            branch_image_creation_threshold: value.branch_image_creation_threshold,
             eviction_policy: value.eviction_policy,
             min_resident_size_override: value.min_resident_size_override,
             evictions_low_residence_duration_metric_threshold: value
@@ -650,6 +847,19 @@ impl From<TenantConfOpt> for models::TenantConfig {
             timeline_get_throttle: value.timeline_get_throttle.map(ThrottleConfig::from),
             image_layer_creation_check_threshold: value.image_layer_creation_check_threshold,
             switch_aux_file_policy: value.switch_aux_file_policy,
+            ephemeral_bytes_limit: value.ephemeral_bytes_limit,
+            ancestor_prefetch: value.ancestor_prefetch,
+            overlap_repair_threshold: value.overlap_repair_threshold,
+            page_request_deadline: value.page_request_deadline.map(humantime),
+            sequential_prefetch: value.sequential_prefetch,
+            branch_creation_max_compaction_debt: value.branch_creation_max_compaction_debt,
+            branch_creation_debt_wait: value.branch_creation_debt_wait.map(humantime),
+            alias: value.alias,
+            timeline_aliases: value.timeline_aliases,
+            gc_pinned_relations: value.gc_pinned_relations,
+            tags: value.tags,
+            template: value.template,
+            read_only: value.read_only,
         }
     }
 }
@@ -708,4 +918,44 @@ mod tests {
             Some(Duration::from_secs(5))
         );
     }
+
+    #[test]
+    fn test_try_from_models_tenant_config_aliases() {
+        let timeline_id = TimelineId::from_str("11223344556677881122334455667788").unwrap();
+        let tenant_config = models::TenantConfig {
+            alias: Some("staging".to_string()),
+            timeline_aliases: Some(BTreeMap::from([("main".to_string(), timeline_id)])),
+            ..TenantConfig::default()
+        };
+        let tenant_conf_opt = TenantConfOpt::try_from(&tenant_config).unwrap();
+        assert_eq!(tenant_conf_opt.alias.as_deref(), Some("staging"));
+
+        for bad in [
+            "",
+            "Main",
+            "main/branch",
+            "11223344556677881122334455667788",
+            "11223344556677881122334455667788-0102",
+            "deadbeefdeadbeefdeadbeefdeadbeef",
+            "staging-0102",
+        ] {
+            let tenant_config = models::TenantConfig {
+                alias: Some(bad.to_string()),
+                ..TenantConfig::default()
+            };
+            assert!(
+                TenantConfOpt::try_from(&tenant_config).is_err(),
+                "accepted alias {bad:?}"
+            );
+
+            let tenant_config = models::TenantConfig {
+                timeline_aliases: Some(BTreeMap::from([(bad.to_string(), timeline_id)])),
+                ..TenantConfig::default()
+            };
+            assert!(
+                TenantConfOpt::try_from(&tenant_config).is_err(),
+                "accepted timeline alias {bad:?}"
+            );
+        }
+    }
 }
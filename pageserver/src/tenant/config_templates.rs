@@ -0,0 +1,297 @@
+//! Named tenant config templates.
+//!
+//! A template is a [`TenantConfig`] stored on the pageserver under a name, like `free-tier`
+//! or `enterprise`. Requests that create or configure a tenant may reference one in
+//! the `template` field of their config, as `name` for the latest version of the template, or
+//! as `name@version`. The fields that the request doesn't set are taken from the template, and
+//! the reference is pinned to the version that was used. The tenant config is complete after
+//! that: later versions of the template don't change existing tenants, and the tenant doesn't
+//! depend on the template being present on the pageservers it moves to.
+//!
+//! Templates are resolved once, where the config is set: by `/v1/tenant` and `/v1/tenant/config`
+//! here, or by the storage controller, which fetches the template from a pageserver and stores
+//! the resolved config. `/v1/location_config` takes the resolved config as it is, and rejects
+//! the references that are not pinned, so that moving a tenant can't change its config.
+//!
+//! Each update of a template adds a version. The tenant config API shows the fields in which
+//! a tenant differs from the template version it was set up from, see [`overrides`].
+//!
+//! Templates are managed with the `/v1/tenant_config_template` API, and persisted in
+//! `tenant_config_templates.json` in the workdir of the pageserver.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context};
+use camino::Utf8PathBuf;
+use pageserver_api::models::{TenantConfig, TenantConfigTemplate};
+use serde::Serialize;
+use serde_json::Value;
+use utils::crashsafe::path_with_suffix_extension;
+
+use super::config::TenantConfOpt;
+use super::group_fsync;
+use crate::TEMP_FILE_SUFFIX;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum TemplateError {
+    #[error("no tenant config template {0:?}")]
+    NotFound(String),
+    #[error(transparent)]
+    Invalid(anyhow::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// All the versions of all the templates, oldest first.
+type Templates = BTreeMap<String, Vec<TenantConfigTemplate>>;
+
+pub(crate) struct ConfigTemplates {
+    path: Utf8PathBuf,
+    templates: RwLock<Templates>,
+    /// Serializes the updates, which are persisted before they take effect.
+    update_lock: tokio::sync::Mutex<()>,
+}
+
+impl ConfigTemplates {
+    /// Load the templates persisted at `path`, if any. Runs at startup.
+    pub(crate) fn load(path: Utf8PathBuf) -> anyhow::Result<Self> {
+        let templates = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("parse tenant config templates {path}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Templates::new(),
+            Err(e) => {
+                return Err(
+                    anyhow::Error::new(e).context(format!("read tenant config templates {path}"))
+                )
+            }
+        };
+        Ok(Self {
+            path,
+            templates: RwLock::new(templates),
+            update_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// The latest version of each template.
+    pub(crate) fn list(&self) -> Vec<TenantConfigTemplate> {
+        self.templates
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|versions| versions.last().cloned())
+            .collect()
+    }
+
+    /// All the versions of the template `name`, oldest first.
+    pub(crate) fn versions(&self, name: &str) -> Result<Vec<TenantConfigTemplate>, TemplateError> {
+        self.templates
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| TemplateError::NotFound(name.to_owned()))
+    }
+
+    /// The template version that `reference` designates, as `name` or `name@version`.
+    pub(crate) fn get(&self, reference: &str) -> Result<TenantConfigTemplate, TemplateError> {
+        let (name, version) =
+            TenantConfigTemplate::parse_reference(reference).map_err(TemplateError::Invalid)?;
+        let templates = self.templates.read().unwrap();
+        let versions = templates
+            .get(name)
+            .ok_or_else(|| TemplateError::NotFound(reference.to_owned()))?;
+        TenantConfigTemplate::find(versions, version)
+            .cloned()
+            .ok_or_else(|| TemplateError::NotFound(reference.to_owned()))
+    }
+
+    /// Add a version of the template `name`, creating it if needed.
+    pub(crate) async fn put(
+        &self,
+        name: &str,
+        config: TenantConfig,
+    ) -> Result<TenantConfigTemplate, TemplateError> {
+        validate_name(name).map_err(TemplateError::Invalid)?;
+        validate_template_config(&config).map_err(TemplateError::Invalid)?;
+
+        let _guard = self.update_lock.lock().await;
+        let mut templates = self.templates.read().unwrap().clone();
+        let versions = templates.entry(name.to_owned()).or_default();
+        let template = TenantConfigTemplate {
+            name: name.to_owned(),
+            version: versions.last().map_or(1, |t| t.version + 1),
+            created_at: SystemTime::now(),
+            config,
+        };
+        versions.push(template.clone());
+
+        let content = serde_json::to_vec(&templates)
+            .context("serialize tenant config templates")
+            .map_err(TemplateError::Other)?;
+        let temp_path = path_with_suffix_extension(&self.path, TEMP_FILE_SUFFIX);
+        group_fsync::overwrite(self.path.clone(), temp_path, content, Duration::ZERO)
+            .await
+            .with_context(|| format!("write tenant config templates {}", self.path))
+            .map_err(TemplateError::Other)?;
+
+        *self.templates.write().unwrap() = templates;
+        Ok(template)
+    }
+
+    /// Fill the fields that `config` doesn't set from the template it references, if any,
+    /// and pin the reference to the version used.
+    pub(crate) fn apply(&self, config: &TenantConfig) -> Result<TenantConfig, TemplateError> {
+        let Some(reference) = &config.template else {
+            return Ok(config.clone());
+        };
+        let template = self.get(reference)?;
+        template.apply(config).map_err(TemplateError::Other)
+    }
+}
+
+/// A field in which a tenant config differs from its template.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Override {
+    pub(crate) template: Value,
+    pub(crate) tenant: Value,
+}
+
+/// The fields in which `tenant` differs from `template`, by name.
+pub(crate) fn overrides(
+    template: &TenantConfig,
+    tenant: &TenantConfig,
+) -> anyhow::Result<BTreeMap<String, Override>> {
+    // Compare the parsed values, not how they were spelled, like "1d" and "86400s".
+    let normalize = |config: &TenantConfig| -> anyhow::Result<Value> {
+        Ok(serde_json::to_value(TenantConfig::from(
+            TenantConfOpt::try_from(config)?,
+        ))?)
+    };
+    let (Value::Object(template), Value::Object(tenant)) =
+        (normalize(template)?, normalize(tenant)?)
+    else {
+        bail!("tenant config is not an object");
+    };
+    Ok(tenant
+        .into_iter()
+        .filter(|(field, _)| field != "template")
+        .filter_map(|(field, tenant)| {
+            let template = template.get(&field).cloned().unwrap_or(Value::Null);
+            (template != tenant).then_some((field, Override { template, tenant }))
+        })
+        .collect())
+}
+
+fn validate_name(name: &str) -> anyhow::Result<()> {
+    const MAX_NAME_LEN: usize = 63;
+
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        bail!("template name {name:?} must be between 1 and {MAX_NAME_LEN} characters long");
+    }
+    if !name
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+    {
+        bail!("template name {name:?} may only contain lowercase letters, digits, '-' and '_'");
+    }
+    Ok(())
+}
+
+/// Templates are shared by tenants, so they can't set the fields that identify a tenant, or
+/// its state.
+fn validate_template_config(config: &TenantConfig) -> anyhow::Result<()> {
+    if config.alias.is_some()
+        || config.timeline_aliases.is_some()
+        || config.gc_pinned_relations.is_some()
+        || config.template.is_some()
+        || config.read_only.is_some()
+    {
+        bail!(
+            "templates can't set alias, timeline_aliases, gc_pinned_relations, template or read_only"
+        );
+    }
+    TenantConfOpt::try_from(config)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(gc_horizon: u64, pitr_interval: &str) -> TenantConfig {
+        TenantConfig {
+            gc_horizon: Some(gc_horizon),
+            pitr_interval: Some(pitr_interval.to_owned()),
+            ..TenantConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn templates_are_versioned_and_applied() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let path = dir.path().join("templates.json");
+        let templates = ConfigTemplates::load(path.clone()).unwrap();
+
+        templates.put("free-tier", template(1, "1d")).await.unwrap();
+        let v2 = templates.put("free-tier", template(2, "2d")).await.unwrap();
+        assert_eq!(v2.version, 2);
+
+        // The request's own fields win, and the latest version is pinned.
+        let config = TenantConfig {
+            gc_horizon: Some(42),
+            template: Some("free-tier".to_owned()),
+            ..TenantConfig::default()
+        };
+        let applied = templates.apply(&config).unwrap();
+        assert_eq!(applied.gc_horizon, Some(42));
+        assert_eq!(applied.pitr_interval.as_deref(), Some("2d"));
+        assert_eq!(applied.template.as_deref(), Some("free-tier@2"));
+
+        let overrides = overrides(&templates.get("free-tier@2").unwrap().config, &applied).unwrap();
+        assert_eq!(
+            overrides.into_iter().collect::<Vec<_>>(),
+            vec![(
+                "gc_horizon".to_owned(),
+                Override {
+                    template: Value::from(2),
+                    tenant: Value::from(42),
+                }
+            )]
+        );
+
+        // Older versions stay available, also after a restart.
+        let templates = ConfigTemplates::load(path).unwrap();
+        let config = TenantConfig {
+            template: Some("free-tier@1".to_owned()),
+            ..TenantConfig::default()
+        };
+        assert_eq!(templates.apply(&config).unwrap().gc_horizon, Some(1));
+        assert_eq!(templates.versions("free-tier").unwrap().len(), 2);
+        assert!(matches!(
+            templates.get("free-tier@3"),
+            Err(TemplateError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn templates_cant_identify_tenants() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let templates = ConfigTemplates::load(dir.path().join("templates.json")).unwrap();
+
+        let config = TenantConfig {
+            alias: Some("prod".to_owned()),
+            ..TenantConfig::default()
+        };
+        assert!(matches!(
+            templates.put("enterprise", config).await,
+            Err(TemplateError::Invalid(_))
+        ));
+        assert!(matches!(
+            templates.put("Enterprise", TenantConfig::default()).await,
+            Err(TemplateError::Invalid(_))
+        ));
+        assert!(templates.list().is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+//! Simulated crashes, to test that tenant lifecycle operations recover from a crash at any
+//! point.
+//!
+//! Timeline creation and deletion call [`check`] right before each change they make to local
+//! disk or remote storage: creating and removing directories and marks, uploads, and remote
+//! deletions. L0 compaction calls it before writing each of its checkpoints. The unit test
+//! harness arms a crash at the n-th of these crash points passed by a tenant, and from then on
+//! every crash point of that tenant fails, as if the process had died. Cleanups on error paths
+//! check [`crashed`] and are skipped, like they would be by a real crash.
+//!
+//! Outside of unit tests, the crash points compile to nothing.
+
+#[cfg(not(test))]
+use pageserver_api::shard::TenantShardId;
+
+#[cfg(test)]
+mod simulation {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use once_cell::sync::Lazy;
+    use pageserver_api::shard::TenantShardId;
+    use tokio::sync::watch;
+    use tracing::info;
+
+    struct Simulation {
+        /// Number of crash points passed so far.
+        hits: usize,
+        /// Crash at this crash point, counting from zero.
+        crash_at: usize,
+        crashed: watch::Sender<bool>,
+    }
+
+    static SIMULATIONS: Lazy<Mutex<HashMap<TenantShardId, Simulation>>> =
+        Lazy::new(Default::default);
+
+    /// Crash at the `crash_at`-th crash point passed by the tenant. The returned receiver turns
+    /// `true` when the crash happens.
+    pub(crate) fn arm(tenant_shard_id: TenantShardId, crash_at: usize) -> watch::Receiver<bool> {
+        let (crashed, rx) = watch::channel(false);
+        let simulation = Simulation {
+            hits: 0,
+            crash_at,
+            crashed,
+        };
+        let prev = SIMULATIONS
+            .lock()
+            .unwrap()
+            .insert(tenant_shard_id, simulation);
+        assert!(prev.is_none(), "crash simulation armed twice");
+        rx
+    }
+
+    /// Stop simulating a crash, returning the number of crash points passed.
+    pub(crate) fn disarm(tenant_shard_id: &TenantShardId) -> usize {
+        SIMULATIONS
+            .lock()
+            .unwrap()
+            .remove(tenant_shard_id)
+            .map(|simulation| simulation.hits)
+            .unwrap_or(0)
+    }
+
+    /// A crash point: fails if a simulated crash of the tenant happened here or earlier.
+    pub(crate) fn check(
+        tenant_shard_id: &TenantShardId,
+        point: &'static str,
+    ) -> std::io::Result<()> {
+        let mut simulations = SIMULATIONS.lock().unwrap();
+        let Some(simulation) = simulations.get_mut(tenant_shard_id) else {
+            return Ok(());
+        };
+        if !*simulation.crashed.borrow() {
+            let hit = simulation.hits;
+            simulation.hits += 1;
+            if hit != simulation.crash_at {
+                return Ok(());
+            }
+            info!("simulated crash at {point} (crash point #{hit})");
+            simulation.crashed.send_replace(true);
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("simulated crash at {point}"),
+        ))
+    }
+
+    /// Whether a simulated crash of the tenant happened, in which case nothing should be
+    /// cleaned up.
+    pub(crate) fn crashed(tenant_shard_id: &TenantShardId) -> bool {
+        SIMULATIONS
+            .lock()
+            .unwrap()
+            .get(tenant_shard_id)
+            .is_some_and(|simulation| *simulation.crashed.borrow())
+    }
+}
+
+#[cfg(test)]
+pub(crate) use simulation::{arm, check, crashed, disarm};
+
+/// A crash point: fails if a simulated crash of the tenant happened here or earlier.
+#[cfg(not(test))]
+#[inline(always)]
+pub(crate) fn check(_tenant_shard_id: &TenantShardId, _point: &'static str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Whether a simulated crash of the tenant happened, in which case nothing should be cleaned up.
+#[cfg(not(test))]
+#[inline(always)]
+pub(crate) fn crashed(_tenant_shard_id: &TenantShardId) -> bool {
+    false
+}
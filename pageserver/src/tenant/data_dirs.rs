@@ -0,0 +1,352 @@
+//! Placement of tenant data across several data directories.
+//!
+//! A pageserver can be configured with `tenant_data_dirs`, typically one per disk, to use
+//! several devices without RAID. The directory of a tenant shard then lives in one of the
+//! data dirs, as `<data_dir>/<tenant_shard_id>-<random suffix>`, and the tenants directory of
+//! the workdir holds a symlink to it. The rest of the pageserver keeps addressing the files of
+//! a tenant through [`PageServerConf::tenant_path`], and renaming the link for a deletion
+//! doesn't move any data. The random suffix makes sure that a tenant shard re-created while
+//! its previous directory is being purged doesn't reuse it.
+//!
+//! The data dir of a new tenant shard is picked by the [`DataDirPlacement`] policy. Shard
+//! split children go to the data dir of their parent, because their layers are hard links
+//! to the parent's. Tenant directories created before data dirs were configured stay in the
+//! tenants directory.
+//!
+//! The disk usage based eviction task accounts the usage of each data dir separately, and
+//! relieves the pressure on one by evicting layers of the tenant shards placed in it.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use pageserver_api::shard::TenantShardId;
+use tracing::*;
+
+use crate::config::PageServerConf;
+use crate::statvfs::Statvfs;
+
+/// How a new tenant shard is assigned one of the configured data dirs.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    strum_macros::EnumString,
+    strum_macros::Display,
+    serde_with::DeserializeFromStr,
+    serde_with::SerializeDisplay,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum DataDirPlacement {
+    /// Cycle through the data dirs.
+    RoundRobin,
+    /// Hash the tenant shard id, so that a tenant shard always lands on the same data dir.
+    TenantHash,
+    /// Pick the data dir with the most available space.
+    FreeSpace,
+}
+
+/// The next data dir of the [`DataDirPlacement::RoundRobin`] policy.
+static NEXT_DATA_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// The directories holding tenant data: the tenants directory of the workdir, for the tenant
+/// shards that aren't placed in a data dir, followed by the configured data dirs.
+pub(crate) fn data_dirs(conf: &PageServerConf) -> Vec<Utf8PathBuf> {
+    std::iter::once(conf.tenants_path())
+        .chain(conf.tenant_data_dirs.iter().cloned())
+        .collect()
+}
+
+/// Create the configured data dirs that don't exist yet, and remove the orphans in them.
+pub(crate) fn init(conf: &PageServerConf) -> anyhow::Result<()> {
+    for data_dir in &conf.tenant_data_dirs {
+        if !data_dir.exists() {
+            utils::crashsafe::create_dir_all(data_dir)
+                .with_context(|| format!("Failed to create tenant data dir at '{data_dir}'"))?;
+        }
+    }
+    remove_orphans(conf)
+}
+
+/// Whether `name` is that of a tenant directory in a data dir, see [`create_tenant_dir`].
+fn is_tenant_dir_name(name: &str) -> bool {
+    let Some((tenant_shard_id, suffix)) = name.rsplit_once('-') else {
+        return false;
+    };
+    suffix.len() == 16
+        && suffix.bytes().all(|b| b.is_ascii_hexdigit())
+        && tenant_shard_id.parse::<TenantShardId>().is_ok()
+}
+
+/// Create the directory of a tenant shard, if it doesn't exist yet.
+///
+/// With data dirs configured, the directory is created in the data dir of `parent` if given,
+/// or in the one picked by the placement policy, and linked from the tenants directory.
+pub(crate) fn create_tenant_dir(
+    conf: &PageServerConf,
+    tenant_shard_id: &TenantShardId,
+    parent: Option<&TenantShardId>,
+) -> anyhow::Result<()> {
+    let tenant_path = conf.tenant_path(tenant_shard_id);
+    let create_in_place =
+        |path: &Utf8Path| std::fs::create_dir_all(path).with_context(|| format!("Creating {path}"));
+
+    if conf.tenant_data_dirs.is_empty() || tenant_path.symlink_metadata().is_ok() {
+        return create_in_place(&tenant_path);
+    }
+
+    let data_dir = match parent {
+        Some(parent) => match tenant_data_dir(conf, parent)? {
+            Some(data_dir) => data_dir,
+            // Hard links can't cross filesystems: stay next to the parent.
+            None => return create_in_place(&tenant_path),
+        },
+        None => pick_data_dir(conf, tenant_shard_id)?,
+    };
+
+    let target = data_dir.join(format!("{tenant_shard_id}-{:016x}", rand::random::<u64>()));
+    create_in_place(&target)?;
+    // A crash before the link is created leaves an orphan behind, see [`remove_orphans`].
+    std::os::unix::fs::symlink(&target, &tenant_path)
+        .with_context(|| format!("Linking {tenant_path} to {target}"))?;
+    info!("placed tenant shard {tenant_shard_id} in {data_dir}");
+    Ok(())
+}
+
+fn pick_data_dir(
+    conf: &PageServerConf,
+    tenant_shard_id: &TenantShardId,
+) -> anyhow::Result<Utf8PathBuf> {
+    let data_dirs = &conf.tenant_data_dirs;
+    let index = match conf.tenant_data_dir_placement {
+        DataDirPlacement::RoundRobin => NEXT_DATA_DIR.fetch_add(1, Ordering::Relaxed),
+        DataDirPlacement::TenantHash => {
+            crc32c::crc32c(tenant_shard_id.to_string().as_bytes()) as usize
+        }
+        DataDirPlacement::FreeSpace => {
+            let mut best = (0, 0);
+            for (i, data_dir) in data_dirs.iter().enumerate() {
+                let avail_bytes = available_bytes(data_dir)?;
+                if avail_bytes > best.1 {
+                    best = (i, avail_bytes);
+                }
+            }
+            best.0
+        }
+    };
+    Ok(data_dirs[index % data_dirs.len()].clone())
+}
+
+fn available_bytes(data_dir: &Utf8Path) -> anyhow::Result<u64> {
+    let stat = Statvfs::get(data_dir, None).with_context(|| format!("statvfs {data_dir}"))?;
+    let blocksize = if stat.fragment_size() > 0 {
+        stat.fragment_size()
+    } else {
+        stat.block_size()
+    };
+    Ok(stat.blocks_available() * blocksize)
+}
+
+/// The data dir that a tenant shard is placed in, or `None` if its directory isn't a link
+/// into one.
+pub(crate) fn tenant_data_dir(
+    conf: &PageServerConf,
+    tenant_shard_id: &TenantShardId,
+) -> anyhow::Result<Option<Utf8PathBuf>> {
+    let tenant_path = conf.tenant_path(tenant_shard_id);
+    Ok(read_link(&tenant_path)?.and_then(|target| target.parent().map(Utf8Path::to_owned)))
+}
+
+/// The data dirs of all the tenant shards placed in one.
+pub(crate) fn placements(
+    conf: &PageServerConf,
+) -> anyhow::Result<HashMap<TenantShardId, Utf8PathBuf>> {
+    let mut placements = HashMap::new();
+    for (link, target) in list_links(conf)? {
+        let Some(tenant_shard_id) = link
+            .file_name()
+            .and_then(|name| name.parse::<TenantShardId>().ok())
+        else {
+            continue;
+        };
+        if let Some(data_dir) = target.parent() {
+            placements.insert(tenant_shard_id, data_dir.to_owned());
+        }
+    }
+    Ok(placements)
+}
+
+/// The links in the tenants directory, and their targets. Includes the links renamed to a
+/// temporary name for their deletion.
+fn list_links(conf: &PageServerConf) -> anyhow::Result<Vec<(Utf8PathBuf, Utf8PathBuf)>> {
+    let tenants_path = conf.tenants_path();
+    let mut links = Vec::new();
+    for entry in tenants_path
+        .read_dir_utf8()
+        .with_context(|| format!("Failed to list tenants dir {tenants_path}"))?
+    {
+        let link = entry?.into_path();
+        if let Some(target) = read_link(&link)? {
+            links.push((link, target));
+        }
+    }
+    Ok(links)
+}
+
+/// The target of `path`, or `None` if it isn't a symlink or doesn't exist.
+fn read_link(path: &Utf8Path) -> anyhow::Result<Option<Utf8PathBuf>> {
+    match std::fs::read_link(path) {
+        Ok(target) => Utf8PathBuf::try_from(target)
+            .map(Some)
+            .with_context(|| format!("Non-UTF-8 target of {path}")),
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::InvalidInput | std::io::ErrorKind::NotFound
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e).with_context(|| format!("Reading link {path}")),
+    }
+}
+
+/// Remove a tenant directory, which is either a plain directory or a link into a data dir.
+/// With `recursive`, its content is removed too, otherwise it must be empty.
+///
+/// The link is removed before its target: a crash in between leaves an orphan behind, see
+/// [`remove_orphans`].
+pub(crate) fn remove_tenant_dir(path: &Utf8Path, recursive: bool) -> std::io::Result<()> {
+    let remove_dir = |path: &std::path::Path| {
+        if recursive {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_dir(path)
+        }
+    };
+    match std::fs::read_link(path) {
+        Ok(target) => {
+            std::fs::remove_file(path)?;
+            remove_dir(&target)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => remove_dir(path.as_std_path()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Remove the directories in the data dirs that no link points to. They are left behind by
+/// crashes between the creation of a tenant directory and its link, or between the removal of
+/// a link and its target.
+fn remove_orphans(conf: &PageServerConf) -> anyhow::Result<()> {
+    if conf.tenant_data_dirs.is_empty() {
+        return Ok(());
+    }
+    let linked = list_links(conf)?
+        .into_iter()
+        .map(|(_, target)| target)
+        .collect::<HashSet<_>>();
+    for data_dir in &conf.tenant_data_dirs {
+        for entry in data_dir
+            .read_dir_utf8()
+            .with_context(|| format!("Failed to list tenant data dir {data_dir}"))?
+        {
+            let path = entry?.into_path();
+            // Leave alone what we didn't create, like the `lost+found` of a filesystem.
+            if linked.contains(&path) || !path.file_name().is_some_and(is_tenant_dir_name) {
+                continue;
+            }
+            info!("removing orphaned tenant directory {path}");
+            if let Err(e) = std::fs::remove_dir_all(&path) {
+                error!("Failed to remove orphaned tenant directory '{path}': {e:#}");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino_tempfile::Utf8TempDir;
+    use pageserver_api::shard::{ShardCount, ShardNumber};
+    use utils::id::TenantId;
+
+    fn test_conf(placement: DataDirPlacement) -> (Utf8TempDir, PageServerConf) {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let mut conf = PageServerConf::dummy_conf(dir.path().join("repo"));
+        conf.tenant_data_dirs = vec![dir.path().join("disk0"), dir.path().join("disk1")];
+        conf.tenant_data_dir_placement = placement;
+        std::fs::create_dir_all(conf.tenants_path()).unwrap();
+        init(&conf).unwrap();
+        (dir, conf)
+    }
+
+    #[test]
+    fn tenant_hash_placement_is_stable() {
+        let (_dir, conf) = test_conf(DataDirPlacement::TenantHash);
+        let tenant_shard_id = TenantShardId::unsharded(TenantId::generate());
+
+        create_tenant_dir(&conf, &tenant_shard_id, None).unwrap();
+        let data_dir = tenant_data_dir(&conf, &tenant_shard_id).unwrap().unwrap();
+        assert_eq!(data_dir, pick_data_dir(&conf, &tenant_shard_id).unwrap());
+        assert!(conf.tenant_path(&tenant_shard_id).is_dir());
+
+        // Children of a shard split stay in the data dir of their parent.
+        let child = TenantShardId {
+            tenant_id: tenant_shard_id.tenant_id,
+            shard_number: ShardNumber(1),
+            shard_count: ShardCount::new(2),
+        };
+        create_tenant_dir(&conf, &child, Some(&tenant_shard_id)).unwrap();
+        assert_eq!(
+            tenant_data_dir(&conf, &child).unwrap(),
+            Some(data_dir.clone())
+        );
+
+        let placements = placements(&conf).unwrap();
+        assert_eq!(placements.len(), 2);
+        assert_eq!(placements[&child], data_dir);
+    }
+
+    #[test]
+    fn tenant_dir_names() {
+        let tenant_shard_id = TenantShardId {
+            tenant_id: TenantId::generate(),
+            shard_number: ShardNumber(1),
+            shard_count: ShardCount::new(4),
+        };
+        assert!(is_tenant_dir_name(&format!(
+            "{tenant_shard_id}-00c0ffee00c0ffee"
+        )));
+        assert!(!is_tenant_dir_name(&format!("{tenant_shard_id}-c0ffee")));
+        assert!(!is_tenant_dir_name(&tenant_shard_id.to_string()));
+        assert!(!is_tenant_dir_name("lost+found"));
+        assert!(!is_tenant_dir_name("lost+found-00c0ffee00c0ffee"));
+    }
+
+    #[test]
+    fn remove_and_orphans() {
+        let (_dir, conf) = test_conf(DataDirPlacement::RoundRobin);
+        let tenant_shard_id = TenantShardId::unsharded(TenantId::generate());
+        create_tenant_dir(&conf, &tenant_shard_id, None).unwrap();
+        let tenant_path = conf.tenant_path(&tenant_shard_id);
+        std::fs::write(tenant_path.join("config-v1"), "").unwrap();
+        let target = read_link(&tenant_path).unwrap().unwrap();
+
+        // A target without its link is left behind by a crash during a removal.
+        std::fs::remove_file(&tenant_path).unwrap();
+        let foreign = conf.tenant_data_dirs[0].join("lost+found");
+        std::fs::create_dir(&foreign).unwrap();
+        init(&conf).unwrap();
+        assert!(!target.exists());
+        assert!(foreign.exists());
+
+        create_tenant_dir(&conf, &tenant_shard_id, None).unwrap();
+        let target = read_link(&tenant_path).unwrap().unwrap();
+        assert!(remove_tenant_dir(&tenant_path, false).is_ok());
+        assert!(!tenant_path.exists() && !target.exists());
+    }
+}
@@ -21,6 +21,8 @@ use crate::{
 };
 
 use super::{
+    data_dirs,
+    legal_hold::{LegalHoldError, Operation},
     mgr::{GetTenantError, TenantSlotError, TenantSlotUpsertError, TenantsMap},
     remote_timeline_client::{FAILED_REMOTE_OP_RETRIES, FAILED_UPLOAD_WARN_THRESHOLD},
     span,
@@ -51,6 +53,9 @@ pub(crate) enum DeleteTenantError {
     #[error("Timeline {0}")]
     Timeline(#[from] DeleteTimelineError),
 
+    #[error(transparent)]
+    LegalHold(#[from] LegalHoldError),
+
     #[error("Cancelled")]
     Cancelled,
 
@@ -258,7 +263,10 @@ async fn cleanup_remaining_fs_traces(
         ))?
     });
 
-    rm(conf.tenant_path(tenant_shard_id), true).await?;
+    let tenant_path = conf.tenant_path(tenant_shard_id);
+    data_dirs::remove_tenant_dir(&tenant_path, false)
+        .or_else(fs_ext::ignore_not_found)
+        .with_context(|| format!("failed to delete {tenant_path}"))?;
 
     Ok(())
 }
@@ -306,6 +314,10 @@ impl DeleteTenantFlow {
 
         pausable_failpoint!("tenant-delete-before-run");
 
+        tenant
+            .legal_hold
+            .check(Operation::TenantDeletion, &tenant.tenant_shard_id)?;
+
         let mut guard = Self::prepare(&tenant).await?;
 
         if let Err(e) =
@@ -24,6 +24,8 @@ use bytes::{BufMut, Bytes, BytesMut};
 use either::Either;
 use futures::Stream;
 use hex;
+use serde::Serialize;
+use std::collections::HashSet;
 use std::{
     cmp::Ordering,
     io,
@@ -91,10 +93,14 @@ impl Value {
             | b[4] as u64
     }
 
-    fn to_blknum(self) -> u32 {
+    fn to_blknum(self) -> Result<u32> {
         let b = &self.0;
-        assert!(b[0] == 0x80);
-        (b[1] as u32) << 24 | (b[2] as u32) << 16 | (b[3] as u32) << 8 | b[4] as u32
+        if b[0] != 0x80 {
+            return Err(DiskBtreeError::Corrupted(
+                "child pointer is not a block number",
+            ));
+        }
+        Ok((b[1] as u32) << 24 | (b[2] as u32) << 16 | (b[3] as u32) << 8 | b[4] as u32)
     }
 }
 
@@ -109,12 +115,64 @@ pub enum DiskBtreeError {
     #[error("Could not push to new leaf node")]
     FailedToPushToNewLeafNode,
 
+    #[error("Corrupted B-tree: {0}")]
+    Corrupted(&'static str),
+
     #[error("IoError: {0}")]
     Io(#[from] io::Error),
 }
 
 pub type Result<T> = result::Result<T, DiskBtreeError>;
 
+/// A problem found in a tree by [`DiskBtreeReader::verify`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyFinding {
+    /// The block number of the node, relative to the start of the tree.
+    pub node_blknum: u32,
+    pub problem: VerifyProblem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VerifyProblem {
+    /// The node can't be parsed, its subtree isn't verified.
+    Malformed { reason: String },
+    /// The node isn't exactly one level below its parent.
+    WrongLevel { level: u8, expected: u8 },
+    /// A key isn't greater than the previous key of the node.
+    UnorderedKey { idx: usize },
+    /// A key is outside of the range that the parent of the node assigns to it.
+    KeyOutOfBounds { idx: usize },
+    /// A child pointer isn't a block number, or doesn't point below its node: children are
+    /// written before their parents.
+    BadChildPointer { idx: usize },
+    /// The node is the child of more than one parent.
+    SharedNode,
+    /// A value of a leaf is outside of the range of values expected by the caller.
+    ValueOutOfBounds { idx: usize, value: u64 },
+}
+
+impl std::fmt::Display for VerifyFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "blk #{}: ", self.node_blknum)?;
+        match &self.problem {
+            VerifyProblem::Malformed { reason } => write!(f, "malformed node: {reason}"),
+            VerifyProblem::WrongLevel { level, expected } => {
+                write!(f, "node at level {level}, expected {expected}")
+            }
+            VerifyProblem::UnorderedKey { idx } => write!(f, "key {idx} is out of order"),
+            VerifyProblem::KeyOutOfBounds { idx } => {
+                write!(f, "key {idx} is outside of the range of the node")
+            }
+            VerifyProblem::BadChildPointer { idx } => write!(f, "bad child pointer {idx}"),
+            VerifyProblem::SharedNode => write!(f, "node has several parents"),
+            VerifyProblem::ValueOutOfBounds { idx, value } => {
+                write!(f, "value {idx} is out of bounds: {value}")
+            }
+        }
+    }
+}
+
 /// This is the on-disk representation.
 struct OnDiskNode<'a, const L: usize> {
     // Fixed-width fields
@@ -133,15 +191,26 @@ struct OnDiskNode<'a, const L: usize> {
 
 impl<'a, const L: usize> OnDiskNode<'a, L> {
     ///
-    /// Interpret a PAGE_SZ page as a node.
+    /// Interpret a PAGE_SZ page as a node. The node must not be above `max_level` in the
+    /// tree: each child is below its parent, so that a corrupted tree can't form a cycle.
     ///
-    fn deparse(buf: &[u8]) -> Result<OnDiskNode<L>> {
+    fn deparse(buf: &[u8], max_level: u8) -> Result<OnDiskNode<L>> {
         let mut cursor = std::io::Cursor::new(buf);
         let num_children = cursor.read_u16::<BE>()?;
         let level = cursor.read_u8()?;
         let prefix_len = cursor.read_u8()?;
         let suffix_len = cursor.read_u8()?;
 
+        if num_children == 0 {
+            return Err(DiskBtreeError::Corrupted("node has no children"));
+        }
+        if level > max_level {
+            return Err(DiskBtreeError::Corrupted("node is not below its parent"));
+        }
+        if prefix_len as usize + suffix_len as usize != L {
+            return Err(DiskBtreeError::Corrupted("wrong key length"));
+        }
+
         let mut off = cursor.position();
         let prefix_off = off as usize;
         off += prefix_len as u64;
@@ -154,6 +223,10 @@ impl<'a, const L: usize> OnDiskNode<'a, L> {
         let values_len = num_children as usize * VALUE_SZ;
         //off += values_len as u64;
 
+        if values_off + values_len > buf.len() {
+            return Err(DiskBtreeError::Corrupted("node overflows its page"));
+        }
+
         let prefix = &buf[prefix_off..prefix_off + prefix_len as usize];
         let keys = &buf[keys_off..keys_off + keys_len];
         let values = &buf[values_off..values_off + values_len];
@@ -239,6 +312,13 @@ where
         }
     }
 
+    /// The block number of a node in the file.
+    fn blknum(&self, node_blknum: u32) -> Result<u32> {
+        self.start_blk
+            .checked_add(node_blknum)
+            .ok_or(DiskBtreeError::Corrupted("block number out of range"))
+    }
+
     ///
     /// Read the value for given key. Returns the value, or None if it doesn't exist.
     ///
@@ -272,20 +352,18 @@ where
     ) -> impl Stream<Item = std::result::Result<(Vec<u8>, u64), DiskBtreeError>> + 'a {
         try_stream! {
             let mut stack = Vec::new();
-            stack.push((self.root_blk, None));
+            stack.push((self.root_blk, u8::MAX, None));
             let block_cursor = self.reader.block_cursor();
-            while let Some((node_blknum, opt_iter)) = stack.pop() {
+            while let Some((node_blknum, max_level, opt_iter)) = stack.pop() {
                 // Locate the node.
                 let node_buf = block_cursor
-                    .read_blk(self.start_blk + node_blknum, ctx)
+                    .read_blk(self.blknum(node_blknum)?, ctx)
                     .await?;
 
-                let node = OnDiskNode::deparse(node_buf.as_ref())?;
+                let node = OnDiskNode::deparse(node_buf.as_ref(), max_level)?;
                 let prefix_len = node.prefix_len as usize;
                 let suffix_len = node.suffix_len as usize;
 
-                assert!(node.num_children > 0);
-
                 let mut keybuf = Vec::new();
                 keybuf.extend(node.prefix);
                 keybuf.resize(prefix_len + suffix_len, 0);
@@ -334,8 +412,8 @@ where
                         // leaf
                         yield (keybuf.clone(), value.to_u64());
                     } else {
-                        stack.push((node_blknum, Some(iter)));
-                        stack.push((value.to_blknum(), None));
+                        stack.push((node_blknum, max_level, Some(iter)));
+                        stack.push((value.to_blknum()?, node.level - 1, None));
                         break;
                     }
                 }
@@ -359,20 +437,18 @@ where
         V: FnMut(&[u8], u64) -> bool,
     {
         let mut stack = Vec::new();
-        stack.push((self.root_blk, None));
+        stack.push((self.root_blk, u8::MAX, None));
         let block_cursor = self.reader.block_cursor();
-        while let Some((node_blknum, opt_iter)) = stack.pop() {
+        while let Some((node_blknum, max_level, opt_iter)) = stack.pop() {
             // Locate the node.
             let node_buf = block_cursor
-                .read_blk(self.start_blk + node_blknum, ctx)
+                .read_blk(self.blknum(node_blknum)?, ctx)
                 .await?;
 
-            let node = OnDiskNode::deparse(node_buf.as_ref())?;
+            let node = OnDiskNode::deparse(node_buf.as_ref(), max_level)?;
             let prefix_len = node.prefix_len as usize;
             let suffix_len = node.suffix_len as usize;
 
-            assert!(node.num_children > 0);
-
             let mut keybuf = Vec::new();
             keybuf.extend(node.prefix);
             keybuf.resize(prefix_len + suffix_len, 0);
@@ -442,8 +518,8 @@ where
                         return Ok(false);
                     }
                 } else {
-                    stack.push((node_blknum, Some(iter)));
-                    stack.push((value.to_blknum(), None));
+                    stack.push((node_blknum, max_level, Some(iter)));
+                    stack.push((value.to_blknum()?, node.level - 1, None));
                     break;
                 }
             }
@@ -456,14 +532,14 @@ where
         let mut stack = Vec::new();
         let ctx = RequestContext::new(TaskKind::DebugTool, DownloadBehavior::Error);
 
-        stack.push((self.root_blk, String::new(), 0, 0, 0));
+        stack.push((self.root_blk, u8::MAX, String::new(), 0, 0, 0));
 
         let block_cursor = self.reader.block_cursor();
 
-        while let Some((blknum, path, depth, child_idx, key_off)) = stack.pop() {
-            let blk = block_cursor.read_blk(self.start_blk + blknum, &ctx).await?;
+        while let Some((blknum, max_level, path, depth, child_idx, key_off)) = stack.pop() {
+            let blk = block_cursor.read_blk(self.blknum(blknum)?, &ctx).await?;
             let buf: &[u8] = blk.as_ref();
-            let node = OnDiskNode::<L>::deparse(buf)?;
+            let node = OnDiskNode::<L>::deparse(buf, max_level)?;
 
             if child_idx == 0 {
                 print!("{:indent$}", "", indent = depth * 2);
@@ -480,7 +556,14 @@ where
 
             if child_idx + 1 < node.num_children {
                 let key_off = key_off + node.suffix_len as usize;
-                stack.push((blknum, path.clone(), depth, child_idx + 1, key_off));
+                stack.push((
+                    blknum,
+                    max_level,
+                    path.clone(),
+                    depth,
+                    child_idx + 1,
+                    key_off,
+                ));
             }
             let key = &node.keys[key_off..key_off + node.suffix_len as usize];
             let val = node.value(child_idx as usize);
@@ -489,11 +572,149 @@ where
             println!("{}: {}", hex::encode(key), hex::encode(val.0));
 
             if node.level > 0 {
-                stack.push((val.to_blknum(), hex::encode(node.prefix), depth + 1, 0, 0));
+                stack.push((
+                    val.to_blknum()?,
+                    node.level - 1,
+                    hex::encode(node.prefix),
+                    depth + 1,
+                    0,
+                    0,
+                ));
             }
         }
         Ok(())
     }
+
+    ///
+    /// Check the structure of the whole tree: the ordering of the keys of each node, that
+    /// they are within the range assigned to the node by its parent, the child pointers and
+    /// levels, and that the values of the leaves are within `values`.
+    ///
+    /// Unlike the other methods, a malformed tree doesn't make this fail: the problems are
+    /// returned as findings, and only I/O errors are returned as errors.
+    ///
+    pub async fn verify(
+        &self,
+        values: Range<u64>,
+        ctx: &RequestContext,
+    ) -> Result<Vec<VerifyFinding>> {
+        struct Pending {
+            blknum: u32,
+            level: Option<u8>,
+            /// Inclusive lower and exclusive upper bound of the keys of the node.
+            lower: Option<Vec<u8>>,
+            upper: Option<Vec<u8>>,
+        }
+
+        let mut findings = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![Pending {
+            blknum: self.root_blk,
+            level: None,
+            lower: None,
+            upper: None,
+        }];
+        let block_cursor = self.reader.block_cursor();
+
+        while let Some(pending) = stack.pop() {
+            let node_blknum = pending.blknum;
+            let mut found = |problem| {
+                findings.push(VerifyFinding {
+                    node_blknum,
+                    problem,
+                })
+            };
+            if !seen.insert(node_blknum) {
+                found(VerifyProblem::SharedNode);
+                continue;
+            }
+
+            let blknum = match self.blknum(node_blknum) {
+                Ok(blknum) => blknum,
+                Err(DiskBtreeError::Corrupted(reason)) => {
+                    found(VerifyProblem::Malformed {
+                        reason: reason.to_string(),
+                    });
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let blk = block_cursor.read_blk(blknum, ctx).await?;
+            let node = match OnDiskNode::<L>::deparse(blk.as_ref(), u8::MAX) {
+                Ok(node) => node,
+                Err(DiskBtreeError::Corrupted(reason)) => {
+                    found(VerifyProblem::Malformed {
+                        reason: reason.to_string(),
+                    });
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(expected) = pending.level {
+                if node.level != expected {
+                    found(VerifyProblem::WrongLevel {
+                        level: node.level,
+                        expected,
+                    });
+                    continue;
+                }
+            }
+
+            let suffix_len = node.suffix_len as usize;
+            let key = |idx: usize| {
+                let mut key = node.prefix.to_vec();
+                key.extend_from_slice(&node.keys[idx * suffix_len..(idx + 1) * suffix_len]);
+                key
+            };
+            let mut children = Vec::new();
+            for idx in 0..node.num_children as usize {
+                let this_key = key(idx);
+                if idx > 0 && this_key <= key(idx - 1) {
+                    found(VerifyProblem::UnorderedKey { idx });
+                }
+                if pending
+                    .lower
+                    .as_ref()
+                    .is_some_and(|lower| &this_key < lower)
+                    || pending
+                        .upper
+                        .as_ref()
+                        .is_some_and(|upper| &this_key >= upper)
+                {
+                    found(VerifyProblem::KeyOutOfBounds { idx });
+                }
+
+                let value = node.value(idx);
+                if node.level == 0 {
+                    let value = value.to_u64();
+                    if !values.contains(&value) {
+                        found(VerifyProblem::ValueOutOfBounds { idx, value });
+                    }
+                    continue;
+                }
+                match value.to_blknum() {
+                    Ok(child_blknum) if child_blknum < node_blknum => {
+                        let upper = if idx + 1 < node.num_children as usize {
+                            Some(key(idx + 1))
+                        } else {
+                            pending.upper.clone()
+                        };
+                        children.push(Pending {
+                            blknum: child_blknum,
+                            level: Some(node.level - 1),
+                            lower: Some(this_key),
+                            upper,
+                        });
+                    }
+                    _ => found(VerifyProblem::BadChildPointer { idx }),
+                }
+            }
+            // Visit the children in order, which doesn't matter except for the order of the
+            // findings.
+            stack.extend(children.into_iter().rev());
+        }
+        Ok(findings)
+    }
 }
 
 ///
@@ -824,6 +1045,149 @@ pub(crate) mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn corrupted_nodes() -> Result<()> {
+        async fn visit_all(disk: TestDisk, root_blk: u32, ctx: &RequestContext) -> Result<bool> {
+            let reader = DiskBtreeReader::<_, 8>::new(0, root_blk, disk);
+            reader
+                .visit(&[0u8; 8], VisitDirection::Forwards, |_, _| true, ctx)
+                .await
+        }
+
+        let mut disk = TestDisk::new();
+        let mut writer = DiskBtreeBuilder::<_, 8>::new(&mut disk);
+        for i in 0..10000u64 {
+            writer.append(&i.to_be_bytes(), i)?;
+        }
+        let (root_blk, _writer) = writer.finish()?;
+        assert!(root_blk > 0, "expected more than one level");
+
+        let ctx = RequestContext::new(TaskKind::UnitTest, DownloadBehavior::Error);
+        assert!(visit_all(disk.clone(), root_blk, &ctx).await?);
+
+        // more children than fit in the page
+        let mut corrupted = disk.clone();
+        let mut root = corrupted.blocks[root_blk as usize].to_vec();
+        root[0..2].copy_from_slice(&u16::MAX.to_be_bytes());
+        corrupted.blocks[root_blk as usize] = root.into();
+        let res = visit_all(corrupted, root_blk, &ctx).await;
+        assert!(matches!(res, Err(DiskBtreeError::Corrupted(_))), "{res:?}");
+
+        // a leaf which claims to be above the root, as if there was a cycle
+        let mut corrupted = disk.clone();
+        let mut leaf = corrupted.blocks[0].to_vec();
+        leaf[2] = u8::MAX;
+        corrupted.blocks[0] = leaf.into();
+        let res = visit_all(corrupted, root_blk, &ctx).await;
+        assert!(matches!(res, Err(DiskBtreeError::Corrupted(_))), "{res:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verify() -> Result<()> {
+        async fn verify(disk: TestDisk, root_blk: u32) -> Result<Vec<VerifyFinding>> {
+            let ctx = RequestContext::new(TaskKind::UnitTest, DownloadBehavior::Error);
+            let reader = DiskBtreeReader::<_, 8>::new(0, root_blk, disk);
+            reader.verify(0..10000, &ctx).await
+        }
+
+        let mut disk = TestDisk::new();
+        let mut writer = DiskBtreeBuilder::<_, 8>::new(&mut disk);
+        for i in 0..10000u64 {
+            writer.append(&i.to_be_bytes(), i)?;
+        }
+        let (root_blk, _writer) = writer.finish()?;
+        assert_eq!(verify(disk.clone(), root_blk).await?, vec![]);
+
+        // Overwrite the suffix of the key `idx` of a leaf with the one of `key`, and return the
+        // offset of the values of the leaf.
+        fn set_key(leaf: &mut [u8], idx: usize, key: u64) -> usize {
+            let node = OnDiskNode::<8>::deparse(leaf, 0).unwrap();
+            let (prefix_len, suffix_len) = (node.prefix_len as usize, node.suffix_len as usize);
+            let values_off = NODE_HDR_SIZE + prefix_len + node.num_children as usize * suffix_len;
+            let key_off = NODE_HDR_SIZE + prefix_len + idx * suffix_len;
+            leaf[key_off..key_off + suffix_len].copy_from_slice(&key.to_be_bytes()[prefix_len..]);
+            values_off
+        }
+
+        // swapped keys, and a value out of bounds
+        let mut corrupted = disk.clone();
+        let mut leaf = corrupted.blocks[0].to_vec();
+        set_key(&mut leaf, 0, 1);
+        let values_off = set_key(&mut leaf, 1, 0);
+        leaf[values_off..values_off + VALUE_SZ].copy_from_slice(&Value::from_u64(20000).0);
+        corrupted.blocks[0] = leaf.into();
+        assert_eq!(
+            verify(corrupted, root_blk).await?,
+            vec![
+                VerifyFinding {
+                    node_blknum: 0,
+                    problem: VerifyProblem::ValueOutOfBounds {
+                        idx: 0,
+                        value: 20000
+                    },
+                },
+                VerifyFinding {
+                    node_blknum: 0,
+                    problem: VerifyProblem::UnorderedKey { idx: 1 },
+                },
+            ]
+        );
+
+        // a key below the range of its leaf
+        let mut corrupted = disk.clone();
+        let mut leaf = corrupted.blocks[1].to_vec();
+        set_key(&mut leaf, 0, 0);
+        corrupted.blocks[1] = leaf.into();
+        assert_eq!(
+            verify(corrupted, root_blk).await?,
+            vec![VerifyFinding {
+                node_blknum: 1,
+                problem: VerifyProblem::KeyOutOfBounds { idx: 0 },
+            }]
+        );
+
+        // a root that points to itself, and a malformed leaf
+        let mut corrupted = disk.clone();
+        let mut root = corrupted.blocks[root_blk as usize].to_vec();
+        let root_node = OnDiskNode::<8>::deparse(&root, u8::MAX)?;
+        let values_off = NODE_HDR_SIZE
+            + root_node.prefix_len as usize
+            + root_node.num_children as usize * root_node.suffix_len as usize;
+        root[values_off..values_off + VALUE_SZ].copy_from_slice(&Value::from_blknum(root_blk).0);
+        corrupted.blocks[root_blk as usize] = root.into();
+        let mut leaf = corrupted.blocks[2].to_vec();
+        leaf[0..2].copy_from_slice(&0u16.to_be_bytes());
+        corrupted.blocks[2] = leaf.into();
+        let findings = verify(corrupted, root_blk).await?;
+        assert!(findings.contains(&VerifyFinding {
+            node_blknum: root_blk,
+            problem: VerifyProblem::BadChildPointer { idx: 0 },
+        }));
+        assert!(findings.contains(&VerifyFinding {
+            node_blknum: 2,
+            problem: VerifyProblem::Malformed {
+                reason: "node has no children".to_string()
+            },
+        }));
+
+        // a root past the end of the block numbers
+        let ctx = RequestContext::new(TaskKind::UnitTest, DownloadBehavior::Error);
+        let reader = DiskBtreeReader::<_, 8>::new(u32::MAX, root_blk, disk);
+        assert_eq!(
+            reader.verify(0..10000, &ctx).await?,
+            vec![VerifyFinding {
+                node_blknum: root_blk,
+                problem: VerifyProblem::Malformed {
+                    reason: "block number out of range".to_string()
+                },
+            }]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn basic() -> Result<()> {
         let mut disk = TestDisk::new();
@@ -1,13 +1,30 @@
 //! Implementation of append-only file data structure
 //! used to keep in-memory layers spilled on disk.
+//!
+//! Ephemeral files live in the timeline directory, or in the staging area if `ephemeral_dir` is
+//! configured, e.g. a fast scratch disk or a tmpfs. The staging area is a subdirectory of
+//! `ephemeral_dir` per node, see [`staging_dir`], so that pageservers can share a scratch disk.
+//! Their content never needs to survive a crash: after a restart, the WAL that they held is
+//! ingested again from the safekeepers, and the leftover files are deleted. So they are never
+//! fsynced.
+//!
+//! The bytes in the staging area are accounted with those of the in-memory layers, see
+//! [`STAGING_RESOURCES`]. Above `ephemeral_dir_max_bytes`, the staged layers are frozen, and
+//! the new ephemeral files are created in the timeline directory until there is room again.
+//! They are also created there if the staging area fails, so that a full or broken scratch
+//! disk doesn't stop ingest.
 
 use crate::config::PageServerConf;
 use crate::context::RequestContext;
+use crate::metrics::EPHEMERAL_STAGING_FALLBACKS;
 use crate::page_cache;
 use crate::tenant::block_io::{BlockCursor, BlockLease, BlockReader};
+use crate::tenant::storage_layer::inmemory_layer::STAGING_RESOURCES;
 use crate::virtual_file::{self, VirtualFile};
-use camino::Utf8PathBuf;
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
 use pageserver_api::shard::TenantShardId;
+use tracing::warn;
 
 use std::io;
 use std::sync::atomic::AtomicU64;
@@ -17,6 +34,9 @@ pub struct EphemeralFile {
     _tenant_shard_id: TenantShardId,
     _timeline_id: TimelineId,
 
+    /// Whether the file is in the staging area, see [`staging_dir`].
+    staged: bool,
+
     rw: page_caching::RW,
 }
 
@@ -33,32 +53,55 @@ impl EphemeralFile {
         let filename_disambiguator =
             NEXT_FILENAME.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
-        let filename = conf
-            .timeline_path(&tenant_shard_id, &timeline_id)
-            .join(Utf8PathBuf::from(format!(
-                "ephemeral-{filename_disambiguator}"
-            )));
-
-        let file = VirtualFile::open_with_options(
-            &filename,
-            virtual_file::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true),
-        )
-        .await?;
+        let filename = Utf8PathBuf::from(format!("ephemeral-{filename_disambiguator}"));
 
-        Ok(EphemeralFile {
+        let open = |path: Utf8PathBuf| async move {
+            VirtualFile::open_with_options(
+                &path,
+                virtual_file::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true),
+            )
+            .await
+        };
+        let new = |file, staged| EphemeralFile {
             _tenant_shard_id: tenant_shard_id,
             _timeline_id: timeline_id,
+            staged,
             rw: page_caching::RW::new(file),
-        })
+        };
+
+        if let Some(staging_dir) = staging_dir(conf) {
+            if STAGING_RESOURCES.has_room() {
+                match open(staging_dir.join(&filename)).await {
+                    Ok(file) => return Ok(new(file, true)),
+                    Err(e) => {
+                        warn!("failed to create an ephemeral file in {staging_dir}, using the timeline directory: {e}");
+                        EPHEMERAL_STAGING_FALLBACKS.inc();
+                    }
+                }
+            } else {
+                EPHEMERAL_STAGING_FALLBACKS.inc();
+            }
+        }
+
+        let file = open(
+            conf.timeline_path(&tenant_shard_id, &timeline_id)
+                .join(filename),
+        )
+        .await?;
+        Ok(new(file, false))
     }
 
     pub(crate) fn len(&self) -> u64 {
         self.rw.bytes_written()
     }
 
+    pub(crate) fn is_staged(&self) -> bool {
+        self.staged
+    }
+
     pub(crate) fn page_cache_file_id(&self) -> page_cache::FileId {
         self.rw.page_cache_file_id()
     }
@@ -106,6 +149,38 @@ pub fn is_ephemeral_file(filename: &str) -> bool {
     }
 }
 
+/// The staging area of this node, if `ephemeral_dir` is configured: a subdirectory named
+/// after the node id, which no other pageserver sharing `ephemeral_dir` writes to.
+fn staging_dir(conf: &PageServerConf) -> Option<Utf8PathBuf> {
+    let ephemeral_dir = conf.ephemeral_dir.as_ref()?;
+    Some(ephemeral_dir.join(format!("pageserver-{}", conf.id)))
+}
+
+/// Create the staging area if configured, and delete the ephemeral files left behind in it by a
+/// previous run.
+pub(crate) fn init_staging_dir(conf: &PageServerConf) -> anyhow::Result<()> {
+    let Some(staging_dir) = staging_dir(conf) else {
+        return Ok(());
+    };
+    utils::crashsafe::create_dir_all(&staging_dir)
+        .with_context(|| format!("create ephemeral dir {staging_dir}"))?;
+    remove_leftovers(&staging_dir)
+}
+
+fn remove_leftovers(staging_dir: &Utf8Path) -> anyhow::Result<()> {
+    for entry in staging_dir
+        .read_dir_utf8()
+        .with_context(|| format!("list ephemeral dir {staging_dir}"))?
+    {
+        let entry = entry?;
+        if is_ephemeral_file(entry.file_name()) {
+            std::fs::remove_file(entry.path())
+                .with_context(|| format!("remove leftover ephemeral file {}", entry.path()))?;
+        }
+    }
+    Ok(())
+}
+
 impl BlockReader for EphemeralFile {
     fn block_cursor(&self) -> super::block_io::BlockCursor<'_> {
         BlockCursor::new(super::block_io::BlockReaderRef::EphemeralFile(self))
@@ -207,4 +282,51 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_ephemeral_staging_dir() -> Result<(), io::Error> {
+        let (conf, tenant_id, timeline_id, ctx) = harness("ephemeral_staging_dir")?;
+        let ephemeral_dir = conf.workdir.join("scratch");
+        let mut staged_conf = conf.clone();
+        staged_conf.ephemeral_dir = Some(ephemeral_dir.clone());
+        let staged_conf: &'static PageServerConf = Box::leak(Box::new(staged_conf));
+        let staging_dir = staging_dir(staged_conf).unwrap();
+        assert_eq!(
+            staging_dir,
+            ephemeral_dir.join(format!("pageserver-{}", conf.id))
+        );
+
+        // Leftovers of a previous run are deleted, other files are left alone, and so are the
+        // files of the other pageservers sharing the directory.
+        let other_node_dir = ephemeral_dir.join("pageserver-999");
+        fs::create_dir_all(&staging_dir)?;
+        fs::create_dir_all(&other_node_dir)?;
+        fs::write(staging_dir.join("ephemeral-7"), b"leftover")?;
+        fs::write(staging_dir.join("other"), b"other")?;
+        fs::write(other_node_dir.join("ephemeral-7"), b"not ours")?;
+        fs::write(ephemeral_dir.join("ephemeral-8"), b"not ours")?;
+        init_staging_dir(staged_conf).unwrap();
+        assert!(!staging_dir.join("ephemeral-7").exists());
+        assert!(staging_dir.join("other").exists());
+        assert!(other_node_dir.join("ephemeral-7").exists());
+        assert!(ephemeral_dir.join("ephemeral-8").exists());
+
+        let mut file = EphemeralFile::create(staged_conf, tenant_id, timeline_id).await?;
+        assert!(file.is_staged());
+        let pos = file.write_blob(b"foo", &ctx).await?;
+        assert_eq!(
+            b"foo",
+            file.block_cursor().read_blob(pos, &ctx).await?.as_slice()
+        );
+        drop(file);
+        assert_eq!(fs::read_dir(&staging_dir)?.count(), 1);
+
+        // A broken staging area falls back to the timeline directory.
+        let mut broken_conf = conf.clone();
+        broken_conf.ephemeral_dir = Some(ephemeral_dir.join("ephemeral-8"));
+        let broken_conf: &'static PageServerConf = Box::leak(Box::new(broken_conf));
+        let file = EphemeralFile::create(broken_conf, tenant_id, timeline_id).await?;
+        assert!(!file.is_staged());
+        Ok(())
+    }
 }
@@ -0,0 +1,274 @@
+//! Versioned migrations of the local on-disk format of tenants.
+//!
+//! Each tenant directory records the version of the format of its contents in a
+//! `format_version` file. A directory without one predates versioning, and is at version 0.
+//! Changes to the layout of the tenant directory, or to the formats of the files in it, bump
+//! [`CURRENT_FORMAT_VERSION`] and register a [`Migration`] from the previous version, instead
+//! of keeping ad-hoc compat code around forever.
+//!
+//! Migrations run once when a tenant directory is loaded on startup, before its config is read,
+//! and when one comes into use at runtime, by a location config or a load. The pending
+//! migrations run in order, and the version is persisted after each of them: after a crash, the
+//! interrupted migration runs again, so migrations must be idempotent. A migration that fails
+//! is rolled back if it provides a rollback, and the tenant is left at the version before it,
+//! and is broken on startup, or fails the request at runtime.
+//!
+//! A tenant directory at a newer version than [`CURRENT_FORMAT_VERSION`] was written by a newer
+//! pageserver: we refuse to use it rather than misread it.
+
+use std::time::Instant;
+
+use anyhow::{bail, Context};
+use camino::Utf8Path;
+use pageserver_api::shard::TenantShardId;
+use tracing::{error, info, warn};
+use utils::crashsafe;
+use utils::id::TimelineId;
+
+use crate::config::PageServerConf;
+use crate::metrics::FORMAT_MIGRATIONS;
+use crate::{METADATA_FILE_NAME, TEMP_FILE_SUFFIX};
+
+/// The format version of the tenant directories written by this pageserver.
+pub(crate) const CURRENT_FORMAT_VERSION: u32 = 1;
+
+/// The tenant directory couldn't be brought to [`CURRENT_FORMAT_VERSION`]: none of its contents,
+/// including its config, can be used by this pageserver.
+#[derive(Debug, thiserror::Error)]
+#[error("{0:#}")]
+pub(crate) struct MigrationError(anyhow::Error);
+
+/// A change of the on-disk format of a tenant directory.
+pub(crate) struct Migration {
+    /// The format version after this migration: it migrates from the version before.
+    version: u32,
+    description: &'static str,
+    run: fn(&PageServerConf, &TenantShardId) -> anyhow::Result<()>,
+    /// Undo the effects of a failed run, if that's possible.
+    rollback: Option<fn(&PageServerConf, &TenantShardId) -> anyhow::Result<()>>,
+}
+
+/// All the migrations, by increasing version, up to [`CURRENT_FORMAT_VERSION`].
+static MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "remove legacy timeline metadata files",
+    run: remove_legacy_metadata_files,
+    rollback: None,
+}];
+
+/// Bring the tenant directory to [`CURRENT_FORMAT_VERSION`], see module-level comment.
+pub(crate) fn migrate(
+    conf: &PageServerConf,
+    tenant_shard_id: &TenantShardId,
+) -> Result<(), MigrationError> {
+    migrate_with(conf, tenant_shard_id, MIGRATIONS).map_err(MigrationError)
+}
+
+fn migrate_with(
+    conf: &PageServerConf,
+    tenant_shard_id: &TenantShardId,
+    migrations: &[Migration],
+) -> anyhow::Result<()> {
+    let current = migrations.last().map(|m| m.version).unwrap_or(0);
+    let path = conf.tenant_format_version_path(tenant_shard_id);
+    let version = read_version(&path)?;
+    if version > current {
+        bail!(
+            "tenant directory format version {version} is newer than version {current} supported by this pageserver, refusing to use it"
+        );
+    }
+
+    let pending = migrations
+        .iter()
+        .filter(|m| m.version > version)
+        .collect::<Vec<_>>();
+    for (i, migration) in pending.iter().enumerate() {
+        info!(
+            tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(),
+            "Running format migration {}/{} to version {}: {}",
+            i + 1,
+            pending.len(),
+            migration.version,
+            migration.description
+        );
+        let started_at = Instant::now();
+        if let Err(e) = (migration.run)(conf, tenant_shard_id) {
+            let e = e.context(format!(
+                "format migration to version {}: {}",
+                migration.version, migration.description
+            ));
+            match migration.rollback {
+                Some(rollback) => match rollback(conf, tenant_shard_id) {
+                    Ok(()) => {
+                        warn!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), "Rolled back failed {e:#}");
+                        FORMAT_MIGRATIONS.with_label_values(&["rolled_back"]).inc();
+                    }
+                    Err(rollback_e) => {
+                        error!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), "Failed to roll back failed {e:#}: {rollback_e:#}");
+                        FORMAT_MIGRATIONS.with_label_values(&["failed"]).inc();
+                    }
+                },
+                None => {
+                    error!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), "Failed {e:#}, it can't be rolled back");
+                    FORMAT_MIGRATIONS.with_label_values(&["failed"]).inc();
+                }
+            }
+            return Err(e);
+        }
+        write_version(&path, migration.version)?;
+        FORMAT_MIGRATIONS.with_label_values(&["applied"]).inc();
+        info!(
+            tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(),
+            "Migrated tenant directory to format version {} in {:?}",
+            migration.version,
+            started_at.elapsed()
+        );
+    }
+    Ok(())
+}
+
+fn read_version(path: &Utf8Path) -> anyhow::Result<u32> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .with_context(|| format!("parse format version {content:?} in {path}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(anyhow::Error::new(e).context(format!("read format version from {path}"))),
+    }
+}
+
+fn write_version(path: &Utf8Path, version: u32) -> anyhow::Result<()> {
+    let temp_path = crashsafe::path_with_suffix_extension(path, TEMP_FILE_SUFFIX);
+    crashsafe::overwrite(path, &temp_path, format!("{version}\n").as_bytes())
+        .with_context(|| format!("write format version to {path}"))
+}
+
+/// Version 1: timeline metadata is only stored in the remote index, the local `metadata`
+/// files of the timelines are unused.
+fn remove_legacy_metadata_files(
+    conf: &PageServerConf,
+    tenant_shard_id: &TenantShardId,
+) -> anyhow::Result<()> {
+    let timelines = match conf.timelines_path(tenant_shard_id).read_dir_utf8() {
+        Ok(iter) => {
+            let mut timelines = Vec::new();
+            for res in iter {
+                let p = res?;
+                let Some(timeline_id) = p.file_name().parse::<TimelineId>().ok() else {
+                    // skip any entries that aren't TimelineId, such as
+                    // - *.___temp dirs
+                    // - unfinished initdb uploads (test_non_uploaded_root_timeline_is_deleted_after_restart)
+                    continue;
+                };
+                timelines.push(timeline_id);
+            }
+            timelines
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(anyhow::anyhow!(e)),
+    };
+    for timeline_id in timelines {
+        let timeline_path = &conf.timeline_path(tenant_shard_id, &timeline_id);
+        let metadata_path = timeline_path.join(METADATA_FILE_NAME);
+        match std::fs::remove_file(&metadata_path) {
+            Ok(()) => {
+                crashsafe::fsync(timeline_path)
+                    .context("fsync timeline dir after removing legacy metadata file")?;
+                info!("removed legacy metadata file at {metadata_path}");
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // something removed the file earlier, or it was never there
+            }
+            Err(e) => {
+                anyhow::bail!("remove legacy metadata file: {e}: {metadata_path}");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_conf(test_name: &str) -> (PageServerConf, TenantShardId) {
+        let conf = PageServerConf::dummy_conf(PageServerConf::test_repo_dir(test_name));
+        let tenant_shard_id = TenantShardId::unsharded(utils::id::TenantId::generate());
+        std::fs::create_dir_all(conf.tenant_path(&tenant_shard_id)).unwrap();
+        (conf, tenant_shard_id)
+    }
+
+    fn version(conf: &PageServerConf, tenant_shard_id: &TenantShardId) -> u32 {
+        read_version(&conf.tenant_format_version_path(tenant_shard_id)).unwrap()
+    }
+
+    fn touch(conf: &PageServerConf, tenant_shard_id: &TenantShardId) -> anyhow::Result<()> {
+        std::fs::write(conf.tenant_path(tenant_shard_id).join("migrated"), b"")?;
+        Ok(())
+    }
+
+    fn fail(_: &PageServerConf, _: &TenantShardId) -> anyhow::Result<()> {
+        bail!("failpoint")
+    }
+
+    fn mark_rolled_back(
+        conf: &PageServerConf,
+        tenant_shard_id: &TenantShardId,
+    ) -> anyhow::Result<()> {
+        std::fs::write(conf.tenant_path(tenant_shard_id).join("rolled_back"), b"")?;
+        Ok(())
+    }
+
+    #[test]
+    fn migrations_run_once_in_order() {
+        let (conf, tenant_shard_id) = test_conf("migrations_run_once_in_order");
+        assert_eq!(version(&conf, &tenant_shard_id), 0);
+
+        migrate(&conf, &tenant_shard_id).unwrap();
+        assert_eq!(version(&conf, &tenant_shard_id), CURRENT_FORMAT_VERSION);
+
+        let migrations = [
+            Migration {
+                version: CURRENT_FORMAT_VERSION + 1,
+                description: "touch",
+                run: touch,
+                rollback: None,
+            },
+            Migration {
+                version: CURRENT_FORMAT_VERSION + 2,
+                description: "fail",
+                run: fail,
+                rollback: Some(mark_rolled_back),
+            },
+        ];
+        let err = migrate_with(&conf, &tenant_shard_id, &migrations).unwrap_err();
+        assert!(format!("{err:#}").contains("fail"), "{err:#}");
+        // The first migration is kept, the second one is rolled back.
+        assert_eq!(version(&conf, &tenant_shard_id), CURRENT_FORMAT_VERSION + 1);
+        let tenant_path = conf.tenant_path(&tenant_shard_id);
+        assert!(tenant_path.join("migrated").exists());
+        assert!(tenant_path.join("rolled_back").exists());
+
+        // A migration that already ran doesn't run again.
+        let migrations = [Migration {
+            version: CURRENT_FORMAT_VERSION + 1,
+            description: "fail",
+            run: fail,
+            rollback: None,
+        }];
+        migrate_with(&conf, &tenant_shard_id, &migrations).unwrap();
+    }
+
+    #[test]
+    fn too_new_format_is_refused() {
+        let (conf, tenant_shard_id) = test_conf("too_new_format_is_refused");
+        write_version(
+            &conf.tenant_format_version_path(&tenant_shard_id),
+            CURRENT_FORMAT_VERSION + 1,
+        )
+        .unwrap();
+        let err = migrate(&conf, &tenant_shard_id).unwrap_err();
+        assert!(format!("{err:#}").contains("newer than version"), "{err:#}");
+    }
+}
@@ -0,0 +1,273 @@
+//! GetPage latency per tenant shard, by read path, and the burn rate of the GetPage latency SLO.
+//!
+//! The aggregate latency of `pageserver_smgr_query_seconds` doesn't tell which storage tier a
+//! regression comes from. So each GetPage request is also classified by the most expensive
+//! source of data it used, its [`ReadPathClass`], and its latency is observed in a histogram of
+//! that class for the tenant shard.
+//!
+//! The SLO is that a fraction `objective` of the GetPage requests complete within `threshold`,
+//! see [`GetPageLatencySloConfig`]. Requests are counted as good or bad against it, and the
+//! burn rate, the rate at which the error budget `1 - objective` is spent (1 means exactly
+//! within budget), is computed over a sliding window of [`BURN_RATE_WINDOW`] and exported as
+//! a gauge. Each page service connection also sums up its requests by class and logs them
+//! when it ends.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use metrics::{Gauge, Histogram, IntCounter};
+use once_cell::sync::Lazy;
+use pageserver_api::shard::TenantShardId;
+use serde::{Deserialize, Serialize};
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::config::PageServerConf;
+use crate::context::read_path::ReadPathClass;
+use crate::metrics::{GETPAGE_LATENCY_BY_READ_PATH, GETPAGE_SLO_BURN_RATE, GETPAGE_SLO_REQUESTS};
+
+/// The window over which the burn rate is computed.
+pub(crate) const BURN_RATE_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// The GetPage latency SLO.
+///
+/// ```toml
+/// getpage_latency_slo = { threshold = "10ms", objective = 0.999 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GetPageLatencySloConfig {
+    /// Requests slower than this are bad.
+    #[serde(with = "humantime_serde")]
+    pub threshold: Duration,
+    /// The fraction of requests that should be good, lower than 1.
+    pub objective: f64,
+}
+
+impl Default for GetPageLatencySloConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_millis(10),
+            objective: 0.999,
+        }
+    }
+}
+
+/// The GetPage latency metrics of a tenant shard, shared by its timelines.
+pub(crate) struct GetPageLatency {
+    by_read_path: [Histogram; ReadPathClass::COUNT],
+    good: IntCounter,
+    bad: IntCounter,
+    burn_rate: Gauge,
+    slo: GetPageLatencySloConfig,
+    window: BurnRateWindow,
+}
+
+static LATENCIES: Lazy<Mutex<HashMap<TenantShardId, Arc<GetPageLatency>>>> =
+    Lazy::new(Default::default);
+
+pub(crate) fn for_tenant_shard(
+    conf: &PageServerConf,
+    tenant_shard_id: TenantShardId,
+) -> Arc<GetPageLatency> {
+    let mut latencies = LATENCIES.lock().unwrap();
+    Arc::clone(latencies.entry(tenant_shard_id).or_insert_with(|| {
+        let tenant_id = tenant_shard_id.tenant_id.to_string();
+        let shard_id = tenant_shard_id.shard_slug().to_string();
+        Arc::new(GetPageLatency {
+            by_read_path: std::array::from_fn(|i| {
+                let class: &'static str = ReadPathClass::from_repr(i as u8).unwrap().into();
+                GETPAGE_LATENCY_BY_READ_PATH.with_label_values(&[class, &tenant_id, &shard_id])
+            }),
+            good: GETPAGE_SLO_REQUESTS.with_label_values(&["good", &tenant_id, &shard_id]),
+            bad: GETPAGE_SLO_REQUESTS.with_label_values(&["bad", &tenant_id, &shard_id]),
+            burn_rate: GETPAGE_SLO_BURN_RATE.with_label_values(&[&tenant_id, &shard_id]),
+            slo: conf.getpage_latency_slo,
+            window: BurnRateWindow::new(Instant::now()),
+        })
+    }))
+}
+
+/// Forget the metrics of a tenant shard, once it has shut down.
+pub(crate) fn remove(tenant_shard_id: &TenantShardId) {
+    if LATENCIES.lock().unwrap().remove(tenant_shard_id).is_none() {
+        return;
+    }
+    let tenant_id = tenant_shard_id.tenant_id.to_string();
+    let shard_id = tenant_shard_id.shard_slug().to_string();
+    for class in ReadPathClass::iter() {
+        let class: &'static str = class.into();
+        let _ = GETPAGE_LATENCY_BY_READ_PATH.remove_label_values(&[class, &tenant_id, &shard_id]);
+    }
+    for outcome in ["good", "bad"] {
+        let _ = GETPAGE_SLO_REQUESTS.remove_label_values(&[outcome, &tenant_id, &shard_id]);
+    }
+    let _ = GETPAGE_SLO_BURN_RATE.remove_label_values(&[&tenant_id, &shard_id]);
+}
+
+impl GetPageLatency {
+    /// Observe a GetPage request, returning whether it met the SLO.
+    pub(crate) fn observe(&self, class: ReadPathClass, latency: Duration) -> bool {
+        self.by_read_path[class as usize].observe(latency.as_secs_f64());
+        let good = latency <= self.slo.threshold;
+        if good {
+            self.good.inc();
+        } else {
+            self.bad.inc();
+        }
+
+        let now = Instant::now();
+        self.window.observe(now, !good);
+        let bad_fraction = self.window.bad_fraction(now);
+        let error_budget = 1.0 - self.slo.objective;
+        self.burn_rate.set(bad_fraction / error_budget);
+        good
+    }
+
+    /// The number of GetPage requests observed so far.
+    pub(crate) fn requests(&self) -> u64 {
+        self.good.get() + self.bad.get()
+    }
+}
+
+/// Counts of requests and bad requests, over the current [`BURN_RATE_WINDOW`] and the previous
+/// one. The counts over the sliding window are estimated by weighting the previous window
+/// with the part of it that is still in the sliding window.
+///
+/// The windows are numbered from `origin`, and the counts are atomics, as every GetPage is
+/// observed: the requests observed by other threads while the window moves on may be counted
+/// in either window.
+struct BurnRateWindow {
+    origin: Instant,
+    /// The number of the current window.
+    current_window: AtomicU64,
+    current: WindowCounts,
+    previous: WindowCounts,
+}
+
+#[derive(Default)]
+struct WindowCounts {
+    total: AtomicU64,
+    bad: AtomicU64,
+}
+
+impl BurnRateWindow {
+    fn new(now: Instant) -> Self {
+        Self {
+            origin: now,
+            current_window: AtomicU64::new(0),
+            current: WindowCounts::default(),
+            previous: WindowCounts::default(),
+        }
+    }
+
+    /// The number of the window of `now`, and how far into it `now` is.
+    fn window_of(&self, now: Instant) -> (u64, Duration) {
+        let elapsed = now.saturating_duration_since(self.origin);
+        let window = (elapsed.as_nanos() / BURN_RATE_WINDOW.as_nanos()) as u64;
+        (window, elapsed - BURN_RATE_WINDOW * window as u32)
+    }
+
+    fn observe(&self, now: Instant, bad: bool) {
+        let (window, _) = self.window_of(now);
+        let current_window = self.current_window.load(Ordering::Acquire);
+        if window > current_window
+            && self
+                .current_window
+                .compare_exchange(current_window, window, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            // Only the requests of the window just before count in the sliding window.
+            let (total, bad) = if window == current_window + 1 {
+                (
+                    self.current.total.swap(0, Ordering::Relaxed),
+                    self.current.bad.swap(0, Ordering::Relaxed),
+                )
+            } else {
+                self.current.total.store(0, Ordering::Relaxed);
+                self.current.bad.store(0, Ordering::Relaxed);
+                (0, 0)
+            };
+            self.previous.total.store(total, Ordering::Relaxed);
+            self.previous.bad.store(bad, Ordering::Relaxed);
+        }
+
+        self.current.total.fetch_add(1, Ordering::Relaxed);
+        if bad {
+            self.current.bad.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn bad_fraction(&self, now: Instant) -> f64 {
+        let (_, into_window) = self.window_of(now);
+        let previous_weight = 1.0 - into_window.as_secs_f64() / BURN_RATE_WINDOW.as_secs_f64();
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed) as f64;
+        let total = count(&self.current.total) + count(&self.previous.total) * previous_weight;
+        let bad = count(&self.current.bad) + count(&self.previous.bad) * previous_weight;
+        if total == 0.0 {
+            0.0
+        } else {
+            bad / total
+        }
+    }
+}
+
+/// The GetPage requests of a page service connection, logged when it ends.
+#[derive(Default)]
+pub(crate) struct ConnectionGetPageStats {
+    by_read_path: [u64; ReadPathClass::COUNT],
+    slo_violations: u64,
+}
+
+impl ConnectionGetPageStats {
+    pub(crate) fn observe(&mut self, class: ReadPathClass, met_slo: bool) {
+        self.by_read_path[class as usize] += 1;
+        if !met_slo {
+            self.slo_violations += 1;
+        }
+    }
+
+    pub(crate) fn requests(&self) -> u64 {
+        self.by_read_path.iter().sum()
+    }
+}
+
+/// Formats as the number of requests of each class, then the violations.
+impl fmt::Display for ConnectionGetPageStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (class, count) in ReadPathClass::iter().zip(self.by_read_path) {
+            let class: &'static str = class.into();
+            write!(f, "{class}={count} ")?;
+        }
+        write!(f, "slo_violations={}", self.slo_violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burn_rate_window_slides() {
+        let start = Instant::now();
+        let window = BurnRateWindow::new(start);
+        for i in 0..100 {
+            window.observe(start, i < 10);
+        }
+        assert_eq!(window.bad_fraction(start), 0.1);
+
+        // Half-way through the next window, half of the previous one still counts.
+        let now = start + BURN_RATE_WINDOW + BURN_RATE_WINDOW / 2;
+        for _ in 0..50 {
+            window.observe(now, false);
+        }
+        assert!((window.bad_fraction(now) - 5.0 / 100.0).abs() < 1e-9);
+
+        // After two windows without requests, the old ones are forgotten.
+        let later = now + 2 * BURN_RATE_WINDOW;
+        window.observe(later, true);
+        assert_eq!(window.bad_fraction(later), 1.0);
+    }
+}
@@ -0,0 +1,142 @@
+//! Group commit for fsyncs of directories.
+//!
+//! Creating a timeline or persisting a tenant's config each end with an fsync of the parent
+//! directory, to make the new directory entry durable. When many such operations run at once,
+//! e.g. when creating a batch of small branches, these fsyncs dominate the latency. With a
+//! non-zero `metadata_fsync_batch_window`, fsyncs of the same directory requested within
+//! the window are coalesced into one: the first caller waits for the window to pass, then a
+//! single fsync is issued, and its result is reported to everyone who joined.
+//!
+//! A caller that arrives after the fsync of a batch was started doesn't join it, because
+//! its changes might not be covered by that fsync: it starts a new batch.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use once_cell::sync::Lazy;
+use tokio::sync::watch;
+use utils::crashsafe;
+
+use crate::virtual_file::VirtualFile;
+
+type FsyncResult = Result<(), (std::io::ErrorKind, String)>;
+
+/// Batches which are still accepting callers, by directory.
+static PENDING: Lazy<Mutex<HashMap<Utf8PathBuf, watch::Receiver<Option<FsyncResult>>>>> =
+    Lazy::new(Default::default);
+
+/// Fsync the directory at `path`, sharing the fsync with other callers that ask for
+/// the same directory within `window`.  A zero `window` disables batching.
+pub(crate) async fn fsync_dir(path: &Utf8Path, window: Duration) -> std::io::Result<()> {
+    if window.is_zero() {
+        return crashsafe::fsync_async(path).await;
+    }
+
+    let mut rx = {
+        let mut pending = PENDING.lock().unwrap();
+        match pending.get(path) {
+            Some(rx) => rx.clone(),
+            None => {
+                let (tx, rx) = watch::channel(None);
+                pending.insert(path.to_owned(), rx.clone());
+
+                // The fsync runs in its own task, so that it completes for the others
+                // even if the caller that started the batch is cancelled.
+                let path = path.to_owned();
+                tokio::spawn(async move {
+                    tokio::time::sleep(window).await;
+                    PENDING.lock().unwrap().remove(&path);
+                    let res = crashsafe::fsync_async(&path)
+                        .await
+                        .map_err(|e| (e.kind(), format!("fsync {path}: {e}")));
+                    tx.send_replace(Some(res));
+                });
+
+                rx
+            }
+        }
+    };
+
+    let res = rx
+        .wait_for(Option::is_some)
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "fsync task exited"))?
+        .clone()
+        .expect("waited for Some");
+
+    res.map_err(|(kind, msg)| std::io::Error::new(kind, msg))
+}
+
+/// Like [`VirtualFile::crashsafe_overwrite`], but the final fsync of the parent directory goes
+/// through [`fsync_dir`].
+pub(crate) async fn overwrite(
+    final_path: Utf8PathBuf,
+    tmp_path: Utf8PathBuf,
+    content: Vec<u8>,
+    window: Duration,
+) -> std::io::Result<()> {
+    if window.is_zero() {
+        return VirtualFile::crashsafe_overwrite(final_path, tmp_path, content).await;
+    }
+    let Some(final_path_parent) = final_path.parent().map(Utf8Path::to_owned) else {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+    };
+
+    VirtualFile::crashsafe_overwrite_no_parent_fsync(final_path, tmp_path, content).await?;
+    fsync_dir(&final_path_parent, window).await
+}
+
+/// Like [`utils::crashsafe::create_dir`], but the fsync of the parent directory goes
+/// through [`fsync_dir`].
+pub(crate) async fn create_dir(path: &Utf8Path, window: Duration) -> std::io::Result<()> {
+    let Some(parent) = path.parent() else {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+    };
+
+    tokio::fs::create_dir(path).await?;
+    crashsafe::fsync_async(path).await?;
+    fsync_dir(parent, window).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_fsyncs_share_result() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let window = Duration::from_millis(50);
+
+        let results = futures::future::join_all((0..10).map(|i| {
+            let path = dir.path().join(format!("timeline-{i}"));
+            async move { create_dir(&path, window).await }
+        }))
+        .await;
+        for res in results {
+            res.unwrap();
+        }
+        for i in 0..10 {
+            assert!(dir.path().join(format!("timeline-{i}")).is_dir());
+        }
+
+        // errors are reported to every caller of the batch
+        let missing = dir.path().join("missing");
+        let (a, b) = tokio::join!(fsync_dir(&missing, window), fsync_dir(&missing, window));
+        assert_eq!(a.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(b.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+        // the batch is gone once completed: the next caller starts a new one
+        assert!(!PENDING.lock().unwrap().contains_key(dir.path()));
+        overwrite(
+            dir.path().join("config"),
+            dir.path().join("config.___temp"),
+            b"foo".to_vec(),
+            window,
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read(dir.path().join("config")).unwrap(), b"foo");
+    }
+}
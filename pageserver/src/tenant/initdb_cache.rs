@@ -0,0 +1,288 @@
+//! Cache of the archives of initdb outputs, to bootstrap root timelines without running initdb
+//! each time.
+//!
+//! initdb produces the same data directory for the same Postgres binaries and arguments, except
+//! for the system identifier in `pg_control`, which is replaced with a fresh one in each copy of
+//! a cached archive.
+//!
+//! The archives are kept in [`crate::INITDB_CACHE_DIR_NAME`] in the workdir, one per Postgres
+//! version. They are named after the [`fingerprint`] of the initdb invocation that made them,
+//! and the CRC32C of their content. An archive with another fingerprint than the current one is
+//! stale: it is replaced by the next bootstrap. An archive whose content doesn't match its
+//! checksum is removed.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use async_compression::tokio::bufread::ZstdDecoder;
+use async_compression::tokio::write::ZstdEncoder;
+use camino::Utf8Path;
+use futures::StreamExt;
+use postgres_ffi::ControlFileData;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{info, warn};
+use utils::crashsafe::path_with_suffix_extension;
+use utils::fs_ext;
+
+use crate::config::PageServerConf;
+use crate::import_datadir::CONTROLFILE_PATH;
+use crate::virtual_file::VirtualFile;
+use crate::TEMP_FILE_SUFFIX;
+
+/// Bump when the way `run_initdb` runs initdb changes, to make the cached archives stale.
+const INITDB_INVOCATION_VERSION: u32 = 1;
+
+const ARCHIVE_SUFFIX: &str = ".tar.zst";
+
+/// Identifies the output of initdb for `pg_version`: it changes with the content of the
+/// Postgres binaries, and with the configuration that initdb is run with.
+pub(crate) async fn fingerprint(conf: &PageServerConf, pg_version: u32) -> anyhow::Result<u32> {
+    let bin_dir = conf.pg_bin_dir(pg_version)?;
+    let mut description = format!(
+        "{INITDB_INVOCATION_VERSION}\0{pg_version}\0{}",
+        conf.superuser
+    );
+    for binary in ["initdb", "postgres"] {
+        let path = bin_dir.join(binary);
+        let content = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("read {path}"))?;
+        write!(description, "\0{binary}:{:08x}", crc32c::crc32c(&content))?;
+    }
+    Ok(crc32c::crc32c(description.as_bytes()))
+}
+
+/// A system identifier made like initdb makes them, from the current time.
+fn new_system_identifier() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    // initdb takes the low bits from its PID, which are random here.
+    (now.as_secs() << 32) | ((now.subsec_micros() as u64) << 12) | (rand::random::<u64>() & 0xFFF)
+}
+
+/// Write the cached archive `content` to `dest`, with `system_identifier` in its `pg_control`.
+/// Returns the size of the new archive.
+async fn write_with_system_identifier(
+    content: &[u8],
+    dest: &Utf8Path,
+    system_identifier: u64,
+) -> anyhow::Result<u64> {
+    let mut entries = tokio_tar::Archive::new(ZstdDecoder::new(content)).entries()?;
+    let file = tokio::fs::File::create(dest)
+        .await
+        .with_context(|| format!("create {dest}"))?;
+    let mut builder = tokio_tar::Builder::new(ZstdEncoder::with_quality_and_params(
+        file,
+        async_compression::Level::Default,
+        &[async_compression::zstd::CParameter::enable_long_distance_matching(true)],
+    ));
+    let mut found_control_file = false;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let mut header = entry.header().clone();
+        let mut data = Vec::with_capacity(header.entry_size()? as usize);
+        entry.read_to_end(&mut data).await?;
+        if header.path()? == Path::new(CONTROLFILE_PATH) {
+            let mut pg_control = ControlFileData::decode(&data)?;
+            pg_control.system_identifier = system_identifier;
+            data = pg_control.encode().to_vec();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            found_control_file = true;
+        }
+        builder.append(&header, &data[..]).await?;
+    }
+    anyhow::ensure!(found_control_file, "pg_control not found");
+    let mut encoder = builder.into_inner().await?;
+    encoder.shutdown().await?;
+    Ok(encoder.into_inner().metadata().await?.len())
+}
+
+fn archive_prefix(pg_version: u32, fingerprint: u32) -> String {
+    format!("v{pg_version}-{fingerprint:08x}-")
+}
+
+/// Copy the cached archive of the initdb output with `fingerprint` to `dest`, with a fresh
+/// system identifier, if there is a valid one. Returns the size of the copy.
+pub(crate) async fn clone_into(
+    conf: &PageServerConf,
+    pg_version: u32,
+    fingerprint: u32,
+    dest: &Utf8Path,
+) -> anyhow::Result<Option<u64>> {
+    let dir = conf.initdb_cache_path();
+    let prefix = archive_prefix(pg_version, fingerprint);
+    let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("read {dir}")),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        let Some(checksum) = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|rest| rest.strip_suffix(ARCHIVE_SUFFIX))
+            .and_then(|checksum| u32::from_str_radix(checksum, 16).ok())
+        else {
+            continue;
+        };
+
+        let path = dir.join(file_name.to_str().unwrap());
+        let content = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("read {path}"))?;
+        if crc32c::crc32c(&content) != checksum {
+            warn!("removing cached initdb archive {path}, its content doesn't match its checksum");
+            tokio::fs::remove_file(&path)
+                .await
+                .or_else(fs_ext::ignore_not_found)
+                .with_context(|| format!("remove {path}"))?;
+            return Ok(None);
+        }
+        let size = write_with_system_identifier(&content, dest, new_system_identifier())
+            .await
+            .with_context(|| format!("copy {path} to {dest}"))?;
+        return Ok(Some(size));
+    }
+    Ok(None)
+}
+
+/// Cache the archive of the initdb output with `fingerprint` at `src`, and remove the stale
+/// archives of `pg_version`.
+pub(crate) async fn store(
+    conf: &PageServerConf,
+    pg_version: u32,
+    fingerprint: u32,
+    src: &Utf8Path,
+) -> anyhow::Result<()> {
+    let dir = conf.initdb_cache_path();
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("create {dir}"))?;
+
+    let content = tokio::fs::read(src)
+        .await
+        .with_context(|| format!("read {src}"))?;
+    let file_name = format!(
+        "{}{:08x}{ARCHIVE_SUFFIX}",
+        archive_prefix(pg_version, fingerprint),
+        crc32c::crc32c(&content)
+    );
+    let path = dir.join(&file_name);
+    let temp_path = path_with_suffix_extension(&path, TEMP_FILE_SUFFIX);
+    VirtualFile::crashsafe_overwrite(path, temp_path, content)
+        .await
+        .context("write cached initdb archive")?;
+
+    // The archives of other fingerprints, and the leftovers of interrupted writes.
+    let version_prefix = format!("v{pg_version}-");
+    let mut entries = tokio::fs::read_dir(&dir)
+        .await
+        .with_context(|| format!("read {dir}"))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if name.starts_with(&version_prefix) && name != file_name {
+            info!("removing stale cached initdb archive {name}");
+            tokio::fs::remove_file(dir.join(name))
+                .await
+                .or_else(fs_ext::ignore_not_found)
+                .with_context(|| format!("remove {name}"))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYSTEM_IDENTIFIER: u64 = 42;
+
+    /// An initdb archive with a `pg_control` and a `PG_VERSION` file of `version`.
+    async fn archive(version: &[u8]) -> Vec<u8> {
+        let pg_control = ControlFileData {
+            system_identifier: SYSTEM_IDENTIFIER,
+            ..Default::default()
+        }
+        .encode();
+        let mut builder = tokio_tar::Builder::new(ZstdEncoder::new(Vec::new()));
+        for (path, data) in [(CONTROLFILE_PATH, &pg_control[..]), ("PG_VERSION", version)] {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_path(path).unwrap();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o600);
+            header.set_cksum();
+            builder.append(&header, data).await.unwrap();
+        }
+        let mut encoder = builder.into_inner().await.unwrap();
+        encoder.shutdown().await.unwrap();
+        encoder.into_inner()
+    }
+
+    /// The system identifier and the `PG_VERSION` of the archive at `path`.
+    async fn read_archive(path: &Utf8Path) -> (u64, Vec<u8>) {
+        let content = std::fs::read(path).unwrap();
+        let mut entries = tokio_tar::Archive::new(ZstdDecoder::new(&content[..]))
+            .entries()
+            .unwrap();
+        let (mut system_identifier, mut version) = (None, None);
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry.unwrap();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).await.unwrap();
+            match entry.header().path().unwrap().to_str().unwrap() {
+                CONTROLFILE_PATH => {
+                    let pg_control = ControlFileData::decode(&data).unwrap();
+                    system_identifier = Some(pg_control.system_identifier);
+                }
+                "PG_VERSION" => version = Some(data),
+                path => panic!("unexpected entry {path}"),
+            }
+        }
+        (system_identifier.unwrap(), version.unwrap())
+    }
+
+    #[tokio::test]
+    async fn store_and_clone() {
+        let workdir = camino_tempfile::tempdir().unwrap();
+        let conf = PageServerConf::dummy_conf(workdir.path().to_owned());
+        let src = workdir.path().join("src");
+        let dest = workdir.path().join("dest");
+
+        assert_eq!(clone_into(&conf, 16, 1, &dest).await.unwrap(), None);
+
+        std::fs::write(&src, archive(b"old").await).unwrap();
+        store(&conf, 16, 1, &src).await.unwrap();
+        std::fs::write(&src, archive(b"16").await).unwrap();
+        store(&conf, 16, 2, &src).await.unwrap();
+        store(&conf, 15, 1, &src).await.unwrap();
+
+        // The archive of the first fingerprint was replaced.
+        assert_eq!(clone_into(&conf, 16, 1, &dest).await.unwrap(), None);
+        let size = clone_into(&conf, 16, 2, &dest).await.unwrap();
+        assert_eq!(size, Some(std::fs::metadata(&dest).unwrap().len()));
+        let (system_identifier, version) = read_archive(&dest).await;
+        assert_eq!(version, b"16");
+        // Each copy has a system identifier of its own.
+        assert_ne!(system_identifier, SYSTEM_IDENTIFIER);
+        clone_into(&conf, 15, 1, &dest).await.unwrap().unwrap();
+        assert_ne!(read_archive(&dest).await.0, system_identifier);
+
+        // A corrupt archive is removed.
+        let cached = std::fs::read_dir(conf.initdb_cache_path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.to_str().unwrap().contains("/v16-"))
+            .unwrap();
+        std::fs::write(&cached, b"corrupt").unwrap();
+        assert_eq!(clone_into(&conf, 16, 2, &dest).await.unwrap(), None);
+        assert!(!cached.exists());
+    }
+}
@@ -0,0 +1,239 @@
+//! Legal hold of a tenant.
+//!
+//! While a tenant is under a [`LegalHold`], nothing may destroy its data: the deletion of the
+//! tenant or of its timelines fails with a [`LegalHoldError`], and GC skips the tenant, so that
+//! no page version is dropped. Each attempt is recorded as a [`LegalHoldViolation`], logged,
+//! and counted in `pageserver_legal_hold_violations_total`.
+//!
+//! Compaction still runs, and still deletes layers from remote storage: it only deletes the
+//! layers that it replaced with new ones holding the same page versions, so the data of the
+//! tenant is kept, but not the layer files themselves.
+//!
+//! The hold is set and released with the `/v1/tenant/:tenant_shard_id/legal_hold` API, per
+//! shard: holding a sharded tenant takes a request to each of its shards. It is persisted both
+//! in the tenant directory, and in the `index_part.json` of every timeline, so that it survives
+//! a restart of the pageserver as well as an attach somewhere else: on attach, a tenant is held
+//! if any of them holds it.
+//!
+//! Setting the hold takes effect in memory first, and then gets persisted. Releasing it works
+//! the other way around, so that a failure leaves the tenant held.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use camino::Utf8Path;
+use pageserver_api::models::{LegalHold, LegalHoldStatus, LegalHoldViolation};
+use tracing::warn;
+use utils::crashsafe::path_with_suffix_extension;
+
+use crate::metrics::LEGAL_HOLD_VIOLATIONS;
+use crate::tenant::group_fsync;
+use crate::TEMP_FILE_SUFFIX;
+
+/// How many violations are kept. Repeated attempts of the same operation on the same target,
+/// like those of GC, take a single entry.
+const HISTORY_LEN: usize = 32;
+
+/// The operations that a legal hold blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::IntoStaticStr, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum Operation {
+    Gc,
+    TimelineDeletion,
+    TenantDeletion,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("tenant is under legal hold: {reason}")]
+pub struct LegalHoldError {
+    pub reason: String,
+}
+
+/// The legal hold of a [`super::Tenant`], and the attempts to violate it.
+#[derive(Default)]
+pub(crate) struct LegalHoldState {
+    hold: RwLock<Option<LegalHold>>,
+    violations: Mutex<VecDeque<LegalHoldViolation>>,
+    /// Serializes the changes of the hold, which are persisted in several places.
+    pub(crate) update_lock: tokio::sync::Mutex<()>,
+}
+
+impl LegalHoldState {
+    pub(crate) fn get(&self) -> Option<LegalHold> {
+        self.hold.read().unwrap().clone()
+    }
+
+    pub(crate) fn is_held(&self) -> bool {
+        self.hold.read().unwrap().is_some()
+    }
+
+    /// Only changes the hold in memory, see the module docs for the order of persisting it.
+    pub(crate) fn set(&self, hold: Option<LegalHold>) {
+        *self.hold.write().unwrap() = hold;
+    }
+
+    /// Check that `operation` on `target` is allowed, and record a violation if it isn't.
+    pub(crate) fn check(
+        &self,
+        operation: Operation,
+        target: &impl std::fmt::Display,
+    ) -> Result<(), LegalHoldError> {
+        let Some(hold) = self.get() else {
+            return Ok(());
+        };
+
+        let target = target.to_string();
+        warn!(%operation, %target, reason = %hold.reason, "blocked by legal hold");
+        LEGAL_HOLD_VIOLATIONS
+            .with_label_values(&[operation.into()])
+            .inc();
+        record(
+            &mut self.violations.lock().unwrap(),
+            operation,
+            target,
+            SystemTime::now(),
+        );
+
+        Err(LegalHoldError {
+            reason: hold.reason,
+        })
+    }
+
+    pub(crate) fn status(&self) -> LegalHoldStatus {
+        LegalHoldStatus {
+            hold: self.get(),
+            violations: self.violations.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+fn record(
+    violations: &mut VecDeque<LegalHoldViolation>,
+    operation: Operation,
+    target: String,
+    now: SystemTime,
+) {
+    let operation: &'static str = operation.into();
+    if let Some(last) = violations.back_mut() {
+        if last.operation == operation && last.target == target {
+            last.last_at = now;
+            last.count += 1;
+            return;
+        }
+    }
+    if violations.len() == HISTORY_LEN {
+        violations.pop_front();
+    }
+    violations.push_back(LegalHoldViolation {
+        operation: operation.to_owned(),
+        target,
+        first_at: now,
+        last_at: now,
+        count: 1,
+    });
+}
+
+/// Read the hold persisted in the tenant directory, if any.
+pub(crate) async fn load(path: &Utf8Path) -> anyhow::Result<Option<LegalHold>> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .with_context(|| format!("parse legal hold {path}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow::Error::new(e).context(format!("read legal hold {path}"))),
+    }
+}
+
+/// Persist `hold` in the tenant directory, or remove the file when there is none.
+pub(crate) async fn persist(
+    path: &Utf8Path,
+    hold: Option<&LegalHold>,
+    fsync_batch_window: Duration,
+) -> anyhow::Result<()> {
+    match hold {
+        Some(hold) => {
+            let content = serde_json::to_vec(hold)?;
+            let temp_path = path_with_suffix_extension(path, TEMP_FILE_SUFFIX);
+            group_fsync::overwrite(path.to_owned(), temp_path, content, fsync_batch_window)
+                .await
+                .with_context(|| format!("write legal hold {path}"))
+        }
+        None => {
+            match tokio::fs::remove_file(path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => {
+                    return Err(anyhow::Error::new(e).context(format!("remove legal hold {path}")))
+                }
+            }
+            let parent = path.parent().expect("tenant directory");
+            utils::crashsafe::fsync_async(parent)
+                .await
+                .with_context(|| format!("fsync {parent}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_violations_are_folded() {
+        let mut violations = VecDeque::new();
+        let t0 = SystemTime::UNIX_EPOCH;
+        let t1 = t0 + Duration::from_secs(1);
+
+        record(&mut violations, Operation::Gc, "t".to_owned(), t0);
+        record(&mut violations, Operation::Gc, "t".to_owned(), t1);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].count, 2);
+        assert_eq!(violations[0].first_at, t0);
+        assert_eq!(violations[0].last_at, t1);
+
+        record(
+            &mut violations,
+            Operation::TimelineDeletion,
+            "t".to_owned(),
+            t1,
+        );
+        record(&mut violations, Operation::Gc, "t".to_owned(), t1);
+        assert_eq!(violations.len(), 3);
+        assert_eq!(violations[1].operation, "timeline_deletion");
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut violations = VecDeque::new();
+        for i in 0..HISTORY_LEN * 2 {
+            record(
+                &mut violations,
+                Operation::TimelineDeletion,
+                i.to_string(),
+                SystemTime::UNIX_EPOCH,
+            );
+        }
+        assert_eq!(violations.len(), HISTORY_LEN);
+        assert_eq!(violations[0].target, HISTORY_LEN.to_string());
+    }
+
+    #[test]
+    fn check_only_fails_when_held() {
+        let state = LegalHoldState::default();
+        assert!(state.check(Operation::TenantDeletion, &"t").is_ok());
+        assert!(state.status().violations.is_empty());
+
+        state.set(Some(LegalHold {
+            reason: "litigation".to_owned(),
+            since: SystemTime::UNIX_EPOCH,
+        }));
+        let err = state.check(Operation::TenantDeletion, &"t").unwrap_err();
+        assert_eq!(err.reason, "litigation");
+        assert_eq!(state.status().violations.len(), 1);
+
+        state.set(None);
+        assert!(state.check(Operation::TenantDeletion, &"t").is_ok());
+    }
+}
@@ -6,9 +6,22 @@
 //!
 //! The module contains all structs and related helper methods related to timeline metadata.
 //!
+//! Besides the fixed fields of the body, the metadata can hold [`MetadataField`]s, each
+//! stored as a tag, a length and the value. These are accessed with
+//! [`TimelineMetadata::get_field`] and [`TimelineMetadata::set_field`]. The fields with
+//! tags that a pageserver does not know about, written by a newer version, are kept as
+//! they are when it writes the metadata back. So adding a field needs no format version
+//! bump, and can be rolled back.
+//!
+//! The metadata without fields is written in the format version 4, which all the
+//! pageservers read. Only the metadata with fields uses the format version 5.
+//!
 //! [`remote_timeline_client`]: super::remote_timeline_client
 
-use anyhow::ensure;
+use std::collections::BTreeMap;
+
+use anyhow::{ensure, Context};
+use serde::de::DeserializeOwned;
 use serde::{de::Error, Deserialize, Serialize, Serializer};
 use utils::bin_ser::SerializeError;
 use utils::{bin_ser::BeSer, id::TimelineId, lsn::Lsn};
@@ -16,9 +29,15 @@ use utils::{bin_ser::BeSer, id::TimelineId, lsn::Lsn};
 /// Use special format number to enable backward compatibility.
 const METADATA_FORMAT_VERSION: u16 = 4;
 
+/// The format with [`MetadataField`]s after the body of [`METADATA_FORMAT_VERSION`].
+const METADATA_FIELDS_FORMAT_VERSION: u16 = 5;
+
 /// Previous supported format versions.
 const METADATA_OLD_FORMAT_VERSION: u16 = 3;
 
+/// The tag and the length before the value of each field.
+const METADATA_FIELD_HDR_SIZE: usize = 4;
+
 /// We assume that a write of up to METADATA_MAX_SIZE bytes is atomic.
 ///
 /// This is the same assumption that PostgreSQL makes with the control file,
@@ -32,6 +51,16 @@ const METADATA_MAX_SIZE: usize = 512;
 pub struct TimelineMetadata {
     hdr: TimelineMetadataHeader,
     body: TimelineMetadataBodyV2,
+    /// The encoded [`MetadataField`]s by tag, including the ones unknown to this version.
+    fields: BTreeMap<u16, Vec<u8>>,
+}
+
+/// A typed field of the [`TimelineMetadata`], beyond the fixed ones.
+///
+/// The value is serialized with [`BeSer`]. A tag must never be reused for another type,
+/// even after the field is removed: older metadata may still have it.
+pub trait MetadataField: Serialize + DeserializeOwned {
+    const TAG: u16;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -108,6 +137,7 @@ impl TimelineMetadata {
                 initdb_lsn,
                 pg_version,
             },
+            fields: BTreeMap::new(),
         }
     }
 
@@ -138,7 +168,11 @@ impl TimelineMetadata {
 
         hdr.format_version = METADATA_FORMAT_VERSION;
 
-        Ok(Self { hdr, body })
+        Ok(Self {
+            hdr,
+            body,
+            fields: BTreeMap::new(),
+        })
     }
 
     pub fn from_bytes(metadata_bytes: &[u8]) -> anyhow::Result<Self> {
@@ -150,7 +184,7 @@ impl TimelineMetadata {
 
         let metadata_size = hdr.size as usize;
         ensure!(
-            metadata_size <= METADATA_MAX_SIZE,
+            (METADATA_HDR_SIZE..=METADATA_MAX_SIZE).contains(&metadata_size),
             "corrupted metadata file"
         );
         let calculated_checksum = crc32c::crc32c(&metadata_bytes[METADATA_HDR_SIZE..metadata_size]);
@@ -159,27 +193,69 @@ impl TimelineMetadata {
             "metadata checksum mismatch"
         );
 
-        if hdr.format_version != METADATA_FORMAT_VERSION {
-            // If metadata has the old format,
-            // upgrade it and return the result
-            TimelineMetadata::upgrade_timeline_metadata(metadata_bytes)
-        } else {
-            let body =
-                TimelineMetadataBodyV2::des(&metadata_bytes[METADATA_HDR_SIZE..metadata_size])?;
+        let body_bytes = &metadata_bytes[METADATA_HDR_SIZE..metadata_size];
+        let (body, fields) = match hdr.format_version {
+            METADATA_FORMAT_VERSION => (TimelineMetadataBodyV2::des(body_bytes)?, BTreeMap::new()),
+            METADATA_FIELDS_FORMAT_VERSION => {
+                let body = TimelineMetadataBodyV2::des_prefix(body_bytes)?;
+                let fields_start = body.serialized_size()? as usize;
+                let fields = Self::decode_fields(&body_bytes[fields_start..])?;
+                (body, fields)
+            }
+            _ => {
+                // If metadata has the old format,
+                // upgrade it and return the result
+                return TimelineMetadata::upgrade_timeline_metadata(metadata_bytes);
+            }
+        };
+        ensure!(
+            body.disk_consistent_lsn.is_aligned(),
+            "disk_consistent_lsn is not aligned"
+        );
+        Ok(TimelineMetadata { hdr, body, fields })
+    }
+
+    fn decode_fields(mut buf: &[u8]) -> anyhow::Result<BTreeMap<u16, Vec<u8>>> {
+        let mut fields = BTreeMap::new();
+        while !buf.is_empty() {
+            ensure!(
+                buf.len() >= METADATA_FIELD_HDR_SIZE,
+                "truncated metadata field"
+            );
+            let tag = u16::from_be_bytes([buf[0], buf[1]]);
+            let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+            let value = buf[METADATA_FIELD_HDR_SIZE..]
+                .get(..len)
+                .with_context(|| format!("truncated metadata field {tag}"))?;
             ensure!(
-                body.disk_consistent_lsn.is_aligned(),
-                "disk_consistent_lsn is not aligned"
+                fields.insert(tag, value.to_vec()).is_none(),
+                "duplicate metadata field {tag}"
             );
-            Ok(TimelineMetadata { hdr, body })
+            buf = &buf[METADATA_FIELD_HDR_SIZE + len..];
         }
+        Ok(fields)
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, SerializeError> {
-        let body_bytes = self.body.ser()?;
+        let mut body_bytes = self.body.ser()?;
+        for (tag, value) in &self.fields {
+            let len = u16::try_from(value.len()).map_err(|_| SerializeError::BadInput)?;
+            body_bytes.extend_from_slice(&tag.to_be_bytes());
+            body_bytes.extend_from_slice(&len.to_be_bytes());
+            body_bytes.extend_from_slice(value);
+        }
         let metadata_size = METADATA_HDR_SIZE + body_bytes.len();
+        if metadata_size > METADATA_MAX_SIZE {
+            return Err(SerializeError::BadInput);
+        }
+        let format_version = if self.fields.is_empty() {
+            METADATA_FORMAT_VERSION
+        } else {
+            METADATA_FIELDS_FORMAT_VERSION
+        };
         let hdr = TimelineMetadataHeader {
             size: metadata_size as u16,
-            format_version: METADATA_FORMAT_VERSION,
+            format_version,
             checksum: crc32c::crc32c(&body_bytes),
         };
         let hdr_bytes = hdr.ser()?;
@@ -237,6 +313,32 @@ impl TimelineMetadata {
         self.body.pg_version
     }
 
+    /// The value of the field `F`, if the metadata has it.
+    pub fn get_field<F: MetadataField>(&self) -> anyhow::Result<Option<F>> {
+        self.fields
+            .get(&F::TAG)
+            .map(|value| F::des(value).with_context(|| format!("decode metadata field {}", F::TAG)))
+            .transpose()
+    }
+
+    /// Set the field `F`. Fails if the metadata would no longer fit in
+    /// `METADATA_MAX_SIZE` bytes, leaving it unchanged.
+    pub fn set_field<F: MetadataField>(&mut self, value: &F) -> Result<(), SerializeError> {
+        let previous = self.fields.insert(F::TAG, value.ser()?);
+        if let Err(e) = self.to_bytes() {
+            match previous {
+                Some(previous) => self.fields.insert(F::TAG, previous),
+                None => self.fields.remove(&F::TAG),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn remove_field<F: MetadataField>(&mut self) {
+        self.fields.remove(&F::TAG);
+    }
+
     // Checksums make it awkward to build a valid instance by hand.  This helper
     // provides a TimelineMetadata with a valid checksum in its header.
     #[cfg(test)]
@@ -335,6 +437,123 @@ mod tests {
         );
     }
 
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestField {
+        lsn: Lsn,
+        name: String,
+    }
+
+    impl MetadataField for TestField {
+        const TAG: u16 = 0xfffe;
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct NewerField(u64);
+
+    impl MetadataField for NewerField {
+        const TAG: u16 = 0xffff;
+    }
+
+    #[test]
+    fn metadata_fields_roundtrip() {
+        let mut metadata = TimelineMetadata::example();
+        assert_eq!(metadata.get_field::<TestField>().unwrap(), None);
+
+        let field = TestField {
+            lsn: Lsn(0x100),
+            name: "test".to_owned(),
+        };
+        metadata.set_field(&field).unwrap();
+        let bytes = metadata.to_bytes().unwrap();
+        let hdr = TimelineMetadataHeader::des_prefix(&bytes).unwrap();
+        assert_eq!(hdr.format_version, METADATA_FIELDS_FORMAT_VERSION);
+
+        let deserialized = TimelineMetadata::from_bytes(&bytes).unwrap();
+        assert_eq!(deserialized.body, metadata.body);
+        assert_eq!(deserialized.get_field::<TestField>().unwrap(), Some(field));
+
+        // Without fields, the metadata is written in the format that older versions read.
+        metadata.remove_field::<TestField>();
+        let bytes = metadata.to_bytes().unwrap();
+        let hdr = TimelineMetadataHeader::des_prefix(&bytes).unwrap();
+        assert_eq!(hdr.format_version, METADATA_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn metadata_keeps_unknown_fields() {
+        // Written by a newer version that knows about NewerField.
+        let mut metadata = TimelineMetadata::example();
+        metadata.set_field(&NewerField(42)).unwrap();
+        let bytes = metadata.to_bytes().unwrap();
+
+        // This version only knows about TestField, and writes the metadata back.
+        let mut metadata = TimelineMetadata::from_bytes(&bytes).unwrap();
+        metadata
+            .set_field(&TestField {
+                lsn: Lsn(0),
+                name: String::new(),
+            })
+            .unwrap();
+        let bytes = metadata.to_bytes().unwrap();
+
+        let metadata = TimelineMetadata::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            metadata.get_field::<NewerField>().unwrap(),
+            Some(NewerField(42))
+        );
+    }
+
+    #[test]
+    fn metadata_fields_must_fit() {
+        let mut metadata = TimelineMetadata::example();
+        let field = TestField {
+            lsn: Lsn(0),
+            name: "x".repeat(METADATA_MAX_SIZE),
+        };
+        assert!(metadata.set_field(&field).is_err());
+        assert_eq!(metadata.get_field::<TestField>().unwrap(), None);
+        metadata.to_bytes().unwrap();
+    }
+
+    #[test]
+    fn metadata_with_truncated_field_is_rejected() {
+        let mut metadata = TimelineMetadata::example();
+        metadata.set_field(&NewerField(42)).unwrap();
+        let mut bytes = metadata.to_bytes().unwrap();
+
+        // Cut the last byte of the value off, and fix the size and the checksum.
+        let hdr = TimelineMetadataHeader::des_prefix(&bytes).unwrap();
+        let size = hdr.size as usize - 1;
+        let hdr = TimelineMetadataHeader {
+            checksum: crc32c::crc32c(&bytes[METADATA_HDR_SIZE..size]),
+            size: size as u16,
+            format_version: hdr.format_version,
+        };
+        bytes[..METADATA_HDR_SIZE].copy_from_slice(&hdr.ser().unwrap());
+        bytes[size] = 0;
+
+        let err = TimelineMetadata::from_bytes(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("truncated metadata field {}", NewerField::TAG)
+        );
+    }
+
+    #[test]
+    fn metadata_with_corrupted_size_is_rejected() {
+        // size smaller than the header itself, with a matching checksum of the empty body
+        let hdr = TimelineMetadataHeader {
+            checksum: crc32c::crc32c(&[]),
+            size: 0,
+            format_version: METADATA_FORMAT_VERSION,
+        };
+        let mut metadata_bytes = vec![0u8; METADATA_MAX_SIZE];
+        metadata_bytes[..METADATA_HDR_SIZE].copy_from_slice(&hdr.ser().unwrap());
+
+        let err = TimelineMetadata::from_bytes(&metadata_bytes).unwrap_err();
+        assert_eq!(err.to_string(), "corrupted metadata file");
+    }
+
     // Generate old version metadata and read it with current code.
     // Ensure that it is upgraded correctly
     #[test]
@@ -6,8 +6,9 @@ use futures::StreamExt;
 use itertools::Itertools;
 use pageserver_api::key::Key;
 use pageserver_api::models::LocationConfigMode;
+use pageserver_api::models::TenantActivationPhase;
 use pageserver_api::shard::{
-    ShardCount, ShardIdentity, ShardNumber, ShardStripeSize, TenantShardId,
+    ShardCount, ShardIdentity, ShardIndex, ShardNumber, ShardStripeSize, TenantShardId,
 };
 use pageserver_api::upcall_api::ReAttachResponseTenant;
 use rand::{distributions::Alphanumeric, Rng};
@@ -16,7 +17,7 @@ use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap};
 use std::ops::Deref;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use sysinfo::SystemExt;
 use tokio::fs;
 use utils::timeout::{timeout_cancellable, TimeoutCancellableError};
@@ -41,21 +42,29 @@ use crate::metrics::{TENANT, TENANT_MANAGER as METRICS};
 use crate::task_mgr::{self, TaskKind};
 use crate::tenant::config::{
     AttachedLocationConfig, AttachmentMode, LocationConf, LocationMode, SecondaryLocationConfig,
+    TenantConfOpt,
 };
 use crate::tenant::delete::DeleteTenantFlow;
 use crate::tenant::span::debug_assert_current_span_has_tenant_id;
 use crate::tenant::storage_layer::inmemory_layer;
 use crate::tenant::timeline::ShutdownMode;
 use crate::tenant::{AttachedTenantConf, SpawnMode, Tenant, TenantState};
-use crate::{InitializationOrder, IGNORED_TENANT_FILE_NAME, METADATA_FILE_NAME, TEMP_FILE_SUFFIX};
+use crate::virtual_file::VirtualFile;
+use crate::{
+    InitializationOrder, DETACHED_TENANT_FILE_NAME, IGNORED_TENANT_FILE_NAME, TEMP_FILE_SUFFIX,
+};
 
 use utils::crashsafe::path_with_suffix_extension;
 use utils::fs_ext::PathExt;
 use utils::generation::Generation;
 use utils::id::{TenantId, TimelineId};
 
+use super::data_dirs;
 use super::delete::DeleteTenantError;
+use super::ephemeral_file;
+use super::format_migrations;
 use super::secondary::SecondaryTenant;
+use super::startup_repair;
 use super::timeline::detach_ancestor::PreparedTimelineDetach;
 use super::TenantSharedResources;
 
@@ -127,6 +136,8 @@ pub(crate) enum ShardSelector {
     First,
     /// Pick the shard that holds this key
     Page(Key),
+    /// Pick the shard with this index
+    Known(ShardIndex),
 }
 
 /// A convenience for use with the re_attach ControlPlaneClient function: rather
@@ -205,6 +216,9 @@ impl TenantsMap {
                         ShardSelector::Zero if slot.0.shard_number == ShardNumber(0) => {
                             return Some(*slot.0)
                         }
+                        ShardSelector::Known(shard) if slot.0.to_index() == shard => {
+                            return Some(*slot.0)
+                        }
                         ShardSelector::Page(key) => {
                             // First slot we see for this tenant, calculate the expected shard number
                             // for the key: we will use this for checking if this and subsequent
@@ -302,13 +316,63 @@ fn spawn_background_purge(tmp_path: Utf8PathBuf) {
         "tenant_files_delete",
         false,
         async move {
-            fs::remove_dir_all(tmp_path.as_path())
-                .await
-                .with_context(|| format!("tenant directory {:?} deletion", tmp_path))
+            tokio::task::spawn_blocking(move || {
+                data_dirs::remove_tenant_dir(&tmp_path, true)
+                    .with_context(|| format!("tenant directory {:?} deletion", tmp_path))
+            })
+            .await?
         },
     );
 }
 
+/// Read the time at which a tenant was detached with `keep_local`, or `None` if it wasn't.
+async fn read_detached_mark(
+    conf: &PageServerConf,
+    tenant_shard_id: &TenantShardId,
+) -> anyhow::Result<Option<SystemTime>> {
+    let path = conf.tenant_detached_mark_file_path(tenant_shard_id);
+    match fs::read_to_string(&path).await {
+        Ok(content) => humantime::parse_rfc3339(content.trim())
+            .map(Some)
+            .with_context(|| format!("parse detached mark {path}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("read detached mark {path}")),
+    }
+}
+
+/// Spawn a task that purges the local data of tenants detached with `keep_local`,
+/// once they have not been re-attached for `detached_tenant_retention`.
+pub fn spawn_detached_tenants_janitor(tenant_manager: Arc<TenantManager>) {
+    task_mgr::spawn(
+        task_mgr::BACKGROUND_RUNTIME.handle(),
+        TaskKind::DetachedTenantsJanitor,
+        None,
+        None,
+        "detached tenants janitor",
+        false,
+        async move {
+            let cancel = task_mgr::shutdown_token();
+            // Check often enough to not keep the data much longer than the retention period.
+            let period = tenant_manager
+                .conf
+                .detached_tenant_retention
+                .clamp(Duration::from_secs(1), Duration::from_secs(600));
+            loop {
+                if let Err(e) = tenant_manager.purge_expired_detached_tenants().await {
+                    warn!("Failed to purge detached tenants: {e:#}");
+                }
+                if tokio::time::timeout(period, cancel.cancelled())
+                    .await
+                    .is_ok()
+                {
+                    return Ok(());
+                }
+            }
+        }
+        .instrument(info_span!("detached_tenants_janitor")),
+    );
+}
+
 static TENANTS: Lazy<std::sync::RwLock<TenantsMap>> =
     Lazy::new(|| std::sync::RwLock::new(TenantsMap::Initializing));
 
@@ -332,6 +396,12 @@ pub struct TenantManager {
     // tenants have their own cancellation tokens, which we fire individually in [`Self::shutdown`], or
     // when the tenant detaches.
     cancel: CancellationToken,
+
+    /// The tenants which claimed the aliases set in their config, see [`TenantConfOpt::alias`].
+    /// A claim only holds while the config of the tenant's shards sets the alias: the claims of
+    /// tenants which dropped the alias, failed to apply it, or were removed are superseded by
+    /// the next claim of the alias.
+    tenant_aliases: std::sync::RwLock<HashMap<String, TenantId>>,
 }
 
 fn emergency_generations(
@@ -424,7 +494,7 @@ fn load_tenant_config(
         info!("Found temporary tenant directory, removing: {tenant_dir_path}");
         // No need to use safe_remove_tenant_dir_all because this is already
         // a temporary path
-        if let Err(e) = std::fs::remove_dir_all(&tenant_dir_path) {
+        if let Err(e) = data_dirs::remove_tenant_dir(&tenant_dir_path, true) {
             error!(
                 "Failed to remove temporary directory '{}': {:?}",
                 tenant_dir_path, e
@@ -439,7 +509,7 @@ fn load_tenant_config(
         .with_context(|| format!("Failed to check whether {tenant_dir_path:?} is an empty dir"))?;
     if is_empty {
         info!("removing empty tenant directory {tenant_dir_path:?}");
-        if let Err(e) = std::fs::remove_dir(&tenant_dir_path) {
+        if let Err(e) = data_dirs::remove_tenant_dir(&tenant_dir_path, false) {
             error!(
                 "Failed to remove empty tenant directory '{}': {e:#}",
                 tenant_dir_path
@@ -460,52 +530,11 @@ fn load_tenant_config(
         }
     };
 
-    // Clean up legacy `metadata` files.
-    // Doing it here because every single tenant directory is visited here.
-    // In any later code, there's different treatment of tenant dirs
-    // ... depending on whether the tenant is in re-attach response or not
-    // ... epending on whether the tenant is ignored or not
     assert_eq!(
         &conf.tenant_path(&tenant_shard_id),
         &tenant_dir_path,
         "later use of conf....path() methods would be dubious"
     );
-    let timelines: Vec<TimelineId> = match conf.timelines_path(&tenant_shard_id).read_dir_utf8() {
-        Ok(iter) => {
-            let mut timelines = Vec::new();
-            for res in iter {
-                let p = res?;
-                let Some(timeline_id) = p.file_name().parse::<TimelineId>().ok() else {
-                    // skip any entries that aren't TimelineId, such as
-                    // - *.___temp dirs
-                    // - unfinished initdb uploads (test_non_uploaded_root_timeline_is_deleted_after_restart)
-                    continue;
-                };
-                timelines.push(timeline_id);
-            }
-            timelines
-        }
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
-        Err(e) => return Err(anyhow::anyhow!(e)),
-    };
-    for timeline_id in timelines {
-        let timeline_path = &conf.timeline_path(&tenant_shard_id, &timeline_id);
-        let metadata_path = timeline_path.join(METADATA_FILE_NAME);
-        match std::fs::remove_file(&metadata_path) {
-            Ok(()) => {
-                crashsafe::fsync(timeline_path)
-                    .context("fsync timeline dir after removing legacy metadata file")?;
-                info!("removed legacy metadata file at {metadata_path}");
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                // something removed the file earlier, or it was never there
-                // We don't care, this software version doesn't write it again, so, we're good.
-            }
-            Err(e) => {
-                anyhow::bail!("remove legacy metadata file: {e}: {metadata_path}");
-            }
-        }
-    }
 
     let tenant_ignore_mark_file = tenant_dir_path.join(IGNORED_TENANT_FILE_NAME);
     if tenant_ignore_mark_file.exists() {
@@ -513,10 +542,18 @@ fn load_tenant_config(
         return Ok(None);
     }
 
-    Ok(Some((
-        tenant_shard_id,
-        Tenant::load_tenant_config(conf, &tenant_shard_id),
-    )))
+    // Local data of a detached tenant, kept for a re-attach. It's purged by the janitor.
+    let tenant_detached_mark_file = tenant_dir_path.join(DETACHED_TENANT_FILE_NAME);
+    if tenant_detached_mark_file.exists() {
+        info!("Found a detached mark file {tenant_detached_mark_file:?}, skipping the tenant");
+        return Ok(None);
+    }
+
+    // The config may only be readable once the tenant directory is migrated.
+    let location_conf = format_migrations::migrate(conf, &tenant_shard_id)
+        .map_err(anyhow::Error::from)
+        .and_then(|()| Tenant::load_tenant_config(conf, &tenant_shard_id));
+    Ok(Some((tenant_shard_id, location_conf)))
 }
 
 /// Initial stage of load: walk the local tenants directory, clean up any temp files,
@@ -524,12 +561,21 @@ fn load_tenant_config(
 ///
 /// Do this in parallel, because we expect 10k+ tenants, so serial execution can take
 /// seconds even on reasonably fast drives.
+///
+/// Also returns how long loading each tenant's configuration took, for its activation profile.
+#[allow(clippy::type_complexity)]
 async fn init_load_tenant_configs(
     conf: &'static PageServerConf,
-) -> anyhow::Result<HashMap<TenantShardId, anyhow::Result<LocationConf>>> {
+) -> anyhow::Result<(
+    HashMap<TenantShardId, anyhow::Result<LocationConf>>,
+    HashMap<TenantShardId, Duration>,
+)> {
     let tenants_dir = conf.tenants_path();
 
     let dentries = tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<Utf8DirEntry>> {
+        data_dirs::init(conf)?;
+        ephemeral_file::init_staging_dir(conf)?;
+
         let dir_entries = tenants_dir
             .read_dir_utf8()
             .with_context(|| format!("Failed to list tenants dir {tenants_dir:?}"))?;
@@ -539,19 +585,26 @@ async fn init_load_tenant_configs(
     .await??;
 
     let mut configs = HashMap::new();
+    let mut load_times = HashMap::new();
 
     let mut join_set = JoinSet::new();
     for dentry in dentries {
-        join_set.spawn_blocking(move || load_tenant_config(conf, dentry));
+        join_set.spawn_blocking(move || {
+            let started_at = Instant::now();
+            let loaded = load_tenant_config(conf, dentry);
+            (loaded, started_at.elapsed())
+        });
     }
 
     while let Some(r) = join_set.join_next().await {
-        if let Some((tenant_id, tenant_config)) = r?? {
+        let (loaded, elapsed) = r?;
+        if let Some((tenant_id, tenant_config)) = loaded? {
             configs.insert(tenant_id, tenant_config);
+            load_times.insert(tenant_id, elapsed);
         }
     }
 
-    Ok(configs)
+    Ok((configs, load_times))
 }
 
 /// Initialize repositories with locally available timelines.
@@ -579,9 +632,13 @@ pub async fn init_tenant_mgr(
         max_ephemeral_layer_bytes,
         std::sync::atomic::Ordering::Relaxed,
     );
+    inmemory_layer::STAGING_RESOURCES.max_dirty_bytes.store(
+        conf.ephemeral_dir_max_bytes,
+        std::sync::atomic::Ordering::Relaxed,
+    );
 
     // Scan local filesystem for attached tenants
-    let tenant_configs = init_load_tenant_configs(conf).await?;
+    let (tenant_configs, config_load_times) = init_load_tenant_configs(conf).await?;
 
     // Determine which tenants are to be secondary or attached, and in which generation
     let tenant_modes = init_load_generations(conf, &tenant_configs, &resources, &cancel).await?;
@@ -603,7 +660,27 @@ pub async fn init_tenant_mgr(
 
         let mut location_conf = match location_conf {
             Ok(l) => l,
+            Err(e)
+                if conf.startup_repair
+                    && tenant_shard_id.is_unsharded()
+                    && !e.is::<format_migrations::MigrationError>() =>
+            {
+                // The config is rewritten below, with the mode and generation from the re-attach
+                // response applied on top of the defaults. A tenant directory that failed its
+                // format migration is broken instead: its config may well be fine, but it can't
+                // be read.
+                startup_repair::record(
+                    tenant_shard_id,
+                    None,
+                    format!("failed to {e:#}"),
+                    Some("tenant config reset to defaults, config overrides are lost".to_string()),
+                );
+                LocationConf::default()
+            }
             Err(e) => {
+                if conf.startup_repair {
+                    startup_repair::record(tenant_shard_id, None, format!("failed to {e:#}"), None);
+                }
                 warn!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), "Marking tenant broken, failed to {e:#}");
 
                 tenants.insert(
@@ -707,8 +784,18 @@ pub async fn init_tenant_mgr(
     );
     // For those shards that have live configurations, construct `Tenant` or `SecondaryTenant` objects and start them running
     for (tenant_shard_id, location_conf, config_write_result) in config_write_results {
-        // Errors writing configs are fatal
-        config_write_result?;
+        if let Err(e) = config_write_result {
+            warn!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), "Marking tenant broken, failed to persist config: {e:#}");
+            tenants.insert(
+                tenant_shard_id,
+                TenantSlot::Attached(Tenant::create_broken_tenant(
+                    conf,
+                    tenant_shard_id,
+                    format!("failed to persist config: {e:#}"),
+                )),
+            );
+            continue;
+        }
 
         let tenant_dir_path = conf.tenant_path(&tenant_shard_id);
         let shard_identity = location_conf.shard;
@@ -726,7 +813,14 @@ pub async fn init_tenant_mgr(
                     SpawnMode::Lazy,
                     &ctx,
                 ) {
-                    Ok(tenant) => TenantSlot::Attached(tenant),
+                    Ok(tenant) => {
+                        if let Some(elapsed) = config_load_times.get(&tenant_shard_id) {
+                            tenant
+                                .activation_profile
+                                .record(TenantActivationPhase::ConfigLoad, *elapsed);
+                        }
+                        TenantSlot::Attached(tenant)
+                    }
                     Err(e) => {
                         error!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), "Failed to start tenant: {e:#}");
                         continue;
@@ -754,6 +848,13 @@ pub async fn init_tenant_mgr(
 
     info!("Processed {} local tenants at startup", tenants.len());
 
+    let tenant_aliases = tenants
+        .iter()
+        .filter_map(|(tenant_shard_id, slot)| {
+            Some((slot_tenant_conf(slot)?.alias?, tenant_shard_id.tenant_id))
+        })
+        .collect();
+
     let mut tenants_map = TENANTS.write().unwrap();
     assert!(matches!(&*tenants_map, &TenantsMap::Initializing));
 
@@ -764,6 +865,7 @@ pub async fn init_tenant_mgr(
         tenants: &TENANTS,
         resources,
         cancel: CancellationToken::new(),
+        tenant_aliases: std::sync::RwLock::new(tenant_aliases),
     })
 }
 
@@ -969,6 +1071,9 @@ pub(crate) enum UpsertLocationError {
     #[error("Tenant is already being modified")]
     InProgress,
 
+    #[error("{0}")]
+    AliasConflict(anyhow::Error),
+
     #[error("Failed to flush: {0}")]
     Flush(anyhow::Error),
 
@@ -1032,6 +1137,71 @@ impl TenantManager {
         peek_slot.is_some()
     }
 
+    /// Shards on this pageserver of the tenant which uses the given alias, see
+    /// [`TenantConfOpt::alias`].
+    pub(crate) fn find_tenant_shards_by_alias(&self, alias: &str) -> Vec<TenantShardId> {
+        let Some(tenant_id) = self.tenant_aliases.read().unwrap().get(alias).copied() else {
+            return Vec::new();
+        };
+        self.tenant_shards_with_alias(tenant_id, alias)
+    }
+
+    /// Shards of the tenant whose config sets the given alias.
+    fn tenant_shards_with_alias(&self, tenant_id: TenantId, alias: &str) -> Vec<TenantShardId> {
+        let locked = self.tenants.read().unwrap();
+        let TenantsMap::Open(map) = &*locked else {
+            return Vec::new();
+        };
+
+        map.range(TenantShardId::tenant_range(tenant_id))
+            .filter(|(_, slot)| {
+                slot_tenant_conf(slot).is_some_and(|conf| conf.alias.as_deref() == Some(alias))
+            })
+            .map(|(tenant_shard_id, _)| *tenant_shard_id)
+            .collect()
+    }
+
+    /// Look up a timeline alias in the config of the given tenant shard, see
+    /// [`TenantConfOpt::timeline_aliases`].
+    pub(crate) fn resolve_timeline_alias(
+        &self,
+        tenant_shard_id: &TenantShardId,
+        alias: &str,
+    ) -> Option<TimelineId> {
+        let locked = self.tenants.read().unwrap();
+        let slot = tenant_map_peek_slot(&locked, tenant_shard_id, TenantSlotPeekMode::Read)
+            .ok()
+            .flatten()?;
+
+        slot_tenant_conf(slot)?
+            .timeline_aliases?
+            .get(alias)
+            .copied()
+    }
+
+    /// Claim the alias set by `tenant_conf` for the tenant, before applying the config. Fails if
+    /// another tenant on this pageserver uses the alias.
+    pub(crate) fn claim_tenant_alias(
+        &self,
+        tenant_shard_id: &TenantShardId,
+        tenant_conf: &TenantConfOpt,
+    ) -> anyhow::Result<()> {
+        let Some(alias) = &tenant_conf.alias else {
+            return Ok(());
+        };
+
+        let mut aliases = self.tenant_aliases.write().unwrap();
+        if let Some(&holder) = aliases.get(alias) {
+            if holder != tenant_shard_id.tenant_id
+                && !self.tenant_shards_with_alias(holder, alias).is_empty()
+            {
+                anyhow::bail!("alias {alias:?} is already used by tenant {holder}");
+            }
+        }
+        aliases.insert(alias.clone(), tenant_shard_id.tenant_id);
+        Ok(())
+    }
+
     #[instrument(skip_all, fields(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug()))]
     pub(crate) async fn upsert_location(
         &self,
@@ -1044,6 +1214,9 @@ impl TenantManager {
         debug_assert_current_span_has_tenant_id();
         info!("configuring tenant location to state {new_location_config:?}");
 
+        self.claim_tenant_alias(&tenant_shard_id, &new_location_config.tenant_conf)
+            .map_err(UpsertLocationError::AliasConflict)?;
+
         enum FastPathModified {
             Attached(Arc<Tenant>),
             Secondary(Arc<SecondaryTenant>),
@@ -1216,15 +1389,37 @@ impl TenantManager {
         // timelines/ subdir to already exist.
         //
         // Does not need to be fsync'd because local storage is just a cache.
+        data_dirs::create_tenant_dir(self.conf, &tenant_shard_id, None)?;
         tokio::fs::create_dir_all(&timelines_path)
             .await
             .with_context(|| format!("Creating {timelines_path}"))?;
 
+        // A tenant directory that comes into use at runtime, like one kept on detach, is migrated
+        // before it's used, like those loaded on startup.
+        let conf = self.conf;
+        tokio::task::spawn_blocking(move || format_migrations::migrate(conf, &tenant_shard_id))
+            .await
+            .context("format migrations task panicked")?
+            .map_err(anyhow::Error::from)?;
+
         // Before activating either secondary or attached mode, persist the
         // configuration, so that on restart we will re-attach (or re-start
         // secondary) on the tenant.
         Tenant::persist_tenant_config(self.conf, &tenant_shard_id, &new_location_config).await?;
 
+        // If the tenant was detached with `keep_local`, we pick up its local data here:
+        // it must no longer be skipped on startup or purged.
+        let detached_mark = self.conf.tenant_detached_mark_file_path(&tenant_shard_id);
+        match tokio::fs::remove_file(&detached_mark).await {
+            Ok(()) => info!("Reusing local data kept on detach"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("Removing {detached_mark}"))
+                    .into())
+            }
+        }
+
         let new_slot = match &new_location_config.mode {
             LocationMode::Secondary(secondary_config) => {
                 let shard_identity = new_location_config.shard;
@@ -1819,13 +2014,13 @@ impl TenantManager {
         let mut child_prefixes = Vec::new();
         let mut create_dirs = Vec::new();
 
-        for child in child_shards {
+        for child in &child_shards {
             let child_prefix = self.conf.tenant_path(&child);
             create_dirs.push(child_prefix.clone());
             create_dirs.extend(
                 parent_timelines
                     .iter()
-                    .map(|t| self.conf.timeline_path(&child, t)),
+                    .map(|t| self.conf.timeline_path(child, t)),
             );
 
             child_prefixes.push(child_prefix);
@@ -1833,7 +2028,13 @@ impl TenantManager {
 
         // Since we will do a large number of small filesystem metadata operations, batch them into
         // spawn_blocking calls rather than doing each one as a tokio::fs round-trip.
+        let conf = self.conf;
+        let parent_shard_id = *parent_shard.get_tenant_shard_id();
         let jh = tokio::task::spawn_blocking(move || -> anyhow::Result<usize> {
+            // The children must be placed in the data dir of the parent, to hard link its layers.
+            for child in &child_shards {
+                data_dirs::create_tenant_dir(conf, child, Some(&parent_shard_id))?;
+            }
             for dir in &create_dirs {
                 if let Err(e) = std::fs::create_dir_all(dir) {
                     // Ignore AlreadyExists errors, drop out on all other errors
@@ -1918,13 +2119,22 @@ impl TenantManager {
         shutdown_all_tenants0(self.tenants).await
     }
 
+    /// With `keep_local`, the tenant's local directory is left in place, so that a re-attach on
+    /// this pageserver doesn't need to download its layers again.
     pub(crate) async fn detach_tenant(
         &self,
         conf: &'static PageServerConf,
         tenant_shard_id: TenantShardId,
         detach_ignored: bool,
+        keep_local: bool,
         deletion_queue_client: &DeletionQueueClient,
     ) -> Result<(), TenantStateError> {
+        if keep_local {
+            return self
+                .detach_tenant_keep_local(conf, tenant_shard_id, deletion_queue_client)
+                .await;
+        }
+
         let tmp_path = self
             .detach_tenant0(
                 conf,
@@ -1939,6 +2149,92 @@ impl TenantManager {
         Ok(())
     }
 
+    async fn detach_tenant_keep_local(
+        &self,
+        conf: &'static PageServerConf,
+        tenant_shard_id: TenantShardId,
+        deletion_queue_client: &DeletionQueueClient,
+    ) -> Result<(), TenantStateError> {
+        let removal_result = remove_tenant_from_memory(self.tenants, tenant_shard_id, async {
+            // Detaching again restarts the retention period.
+            let detached_mark = conf.tenant_detached_mark_file_path(&tenant_shard_id);
+            let temp_path = path_with_suffix_extension(&detached_mark, TEMP_FILE_SUFFIX);
+            let content = humantime::format_rfc3339(SystemTime::now()).to_string();
+            VirtualFile::crashsafe_overwrite(detached_mark, temp_path, content.into_bytes())
+                .await
+                .context("Failed to write detached mark file")?;
+            Ok(())
+        })
+        .await;
+
+        // Flush pending deletions, so that they have a good chance of passing validation
+        // before this tenant is potentially re-attached elsewhere.
+        deletion_queue_client.flush_advisory();
+
+        removal_result
+    }
+
+    /// Purge the local directories of tenants detached with `keep_local` longer than
+    /// `detached_tenant_retention` ago.
+    async fn purge_expired_detached_tenants(&self) -> anyhow::Result<()> {
+        let tenants_path = self.conf.tenants_path();
+        let mut dir = fs::read_dir(&tenants_path)
+            .await
+            .with_context(|| format!("Failed to list tenants dir {tenants_path}"))?;
+
+        while let Some(entry) = dir.next_entry().await? {
+            let Some(tenant_shard_id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<TenantShardId>().ok())
+            else {
+                continue;
+            };
+
+            let detached_at = match read_detached_mark(self.conf, &tenant_shard_id).await {
+                Ok(Some(detached_at)) => detached_at,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), "{e:#}");
+                    continue;
+                }
+            };
+            if detached_at.elapsed().unwrap_or_default() < self.conf.detached_tenant_retention {
+                continue;
+            }
+
+            // Hold the slot while purging, so that we don't race with a re-attach. If the
+            // tenant is present in the map, it has been re-attached already.
+            let Ok(_slot_guard) = tenant_map_acquire_slot_impl(
+                &tenant_shard_id,
+                self.tenants,
+                TenantSlotAcquireMode::MustNotExist,
+            ) else {
+                continue;
+            };
+            // A re-attach removes the mark: check again now that we hold the slot.
+            if !matches!(
+                read_detached_mark(self.conf, &tenant_shard_id).await,
+                Ok(Some(_))
+            ) {
+                continue;
+            }
+
+            info!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(),
+                "Purging local data of tenant detached at {}", humantime::format_rfc3339(detached_at));
+            let tenant_path = self.conf.tenant_path(&tenant_shard_id);
+            match safe_rename_tenant_dir(&tenant_path).await {
+                Ok(tmp_path) => spawn_background_purge(tmp_path),
+                Err(e) => {
+                    error!(tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(),
+                        "Failed to move detached tenant directory '{tenant_path}': {e:?}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     async fn detach_tenant0(
         &self,
         conf: &'static PageServerConf,
@@ -2296,12 +2592,27 @@ pub(crate) async fn load_tenant(
         })?;
     }
 
+    let tenant_detached_mark = conf.tenant_detached_mark_file_path(&tenant_shard_id);
+    if tenant_detached_mark.exists() {
+        std::fs::remove_file(&tenant_detached_mark).with_context(|| {
+            format!(
+                "Failed to remove tenant detached mark {tenant_detached_mark:?} during tenant loading"
+            )
+        })?;
+    }
+
     let resources = TenantSharedResources {
         broker_client,
         remote_storage,
         deletion_queue_client,
     };
 
+    // Ignored tenant directories aren't migrated on startup.
+    tokio::task::spawn_blocking(move || format_migrations::migrate(conf, &tenant_shard_id))
+        .await
+        .context("format migrations task panicked")?
+        .map_err(|e| TenantMapInsertError::Other(e.into()))?;
+
     let mut location_conf =
         Tenant::load_tenant_config(conf, &tenant_shard_id).map_err(TenantMapInsertError::Other)?;
     location_conf.attach_in_generation(AttachmentMode::Single, generation);
@@ -2670,6 +2981,15 @@ impl Drop for SlotGuard {
     }
 }
 
+/// Tenant-specific config overrides of a slot, if it holds a tenant.
+fn slot_tenant_conf(slot: &TenantSlot) -> Option<TenantConfOpt> {
+    match slot {
+        TenantSlot::Attached(tenant) => Some(tenant.tenant_specific_overrides()),
+        TenantSlot::Secondary(secondary) => Some(secondary.get_tenant_conf()),
+        TenantSlot::InProgress(_) => None,
+    }
+}
+
 enum TenantSlotPeekMode {
     /// In Read mode, peek will be permitted to see the slots even if the pageserver is shutting down
     Read,
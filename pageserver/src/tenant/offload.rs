@@ -0,0 +1,141 @@
+//! Offloading of tenant shards to remote storage, when evicting layers doesn't relieve the disk
+//! pressure, see [`crate::disk_usage_eviction_task`].
+//!
+//! An offloaded tenant shard is [read-only](Tenant::set_read_only) with
+//! [`ReadOnlyReason::Offloaded`]: its walreceivers disconnect, and its local layers are evicted
+//! once flushed and uploaded. Its pages are still served, by downloading the layers on demand.
+//! The mode is persisted, so the tenant shard stays offloaded across restarts, until it's
+//! [reactivated](Tenant::reactivate): by the disk usage based eviction once the pressure ends,
+//! when a compute waits for WAL that isn't ingested yet, see [`reactivate_for_wait_lsn`], or
+//! with `DELETE /v1/tenant/:tenant_shard_id/read_only`.
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use pageserver_api::models::{ReadOnlyReason, TimelineState};
+use tracing::{info, warn};
+use utils::lsn::Lsn;
+
+use super::mgr::{self, ShardSelector};
+use super::storage_layer::{AsLayerDesc, EvictionError};
+use super::{Tenant, Timeline};
+
+/// The granularity of the access log.
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// Layers that are being read are left resident rather than waited for.
+const EVICTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a read waits for the tenant shard to be active to reactivate it.
+const REACTIVATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Tenant {
+    /// Until when the tenant shard may have been read or written, as far as the [access
+    /// logs](super::timeline::access_log) of its active timelines know. Accesses from before the
+    /// tracking started are unknown, so a tenant shard is idle at most since then. `None` if
+    /// there are no active timelines.
+    pub(crate) fn last_activity(&self) -> Option<SystemTime> {
+        self.list_timelines()
+            .iter()
+            .filter(|timeline| timeline.current_state() == TimelineState::Active)
+            .filter_map(|timeline| {
+                let activity = timeline.access_log.activity();
+                [activity.last_read, activity.last_write]
+                    .into_iter()
+                    .flatten()
+                    .map(|hour| hour + HOUR)
+                    .chain([activity.tracked_since])
+                    .max()
+            })
+            .max()
+    }
+
+    /// Offload the tenant shard, see the module docs, and return the bytes of the evicted layers.
+    pub(crate) async fn offload(&self) -> anyhow::Result<u64> {
+        self.set_read_only(Some(ReadOnlyReason::Offloaded))
+            .await
+            .context("set read-only")?;
+
+        let mut evicted_bytes = 0;
+        let mut not_evicted = 0;
+        for timeline in self.list_timelines() {
+            if timeline.current_state() != TimelineState::Active {
+                continue;
+            }
+            // The WAL ingested until the walreceiver disconnected must be uploaded, as the
+            // layers that aren't can't be evicted.
+            timeline
+                .freeze_and_flush()
+                .await
+                .with_context(|| format!("flush timeline {}", timeline.timeline_id))?;
+            if let Some(remote_client) = &timeline.remote_client {
+                remote_client
+                    .wait_completion()
+                    .await
+                    .with_context(|| format!("upload timeline {}", timeline.timeline_id))?;
+            }
+
+            let layers = timeline
+                .layers
+                .read()
+                .await
+                .likely_resident_layers()
+                .collect::<Vec<_>>();
+            for layer in layers {
+                let file_size = layer.layer_desc().file_size;
+                match layer.evict_and_wait(EVICTION_TIMEOUT).await {
+                    Ok(()) => evicted_bytes += file_size,
+                    Err(EvictionError::NotFound) => {}
+                    Err(EvictionError::Downloaded | EvictionError::Timeout) => not_evicted += 1,
+                }
+            }
+        }
+
+        if not_evicted > 0 {
+            warn!(not_evicted, "layers in use stayed resident");
+        }
+        info!(evicted_bytes, "tenant offloaded");
+        Ok(evicted_bytes)
+    }
+
+    /// Lift the offloading of the tenant shard, so that its walreceivers reconnect. Returns
+    /// whether it was offloaded: other read-only modes are left alone.
+    pub(crate) async fn reactivate(&self) -> anyhow::Result<bool> {
+        if self.tenant_specific_overrides().read_only != Some(ReadOnlyReason::Offloaded) {
+            return Ok(false);
+        }
+        self.set_read_only(None).await.context("unset read-only")?;
+        Ok(true)
+    }
+}
+
+/// Reactivate the tenant shard of `timeline` if it's offloaded and a compute is about to wait
+/// for `lsn`, which isn't ingested yet: the compute wrote since the offloading, and its reads
+/// would wait for WAL that no walreceiver is going to ingest.
+pub(crate) async fn reactivate_for_wait_lsn(timeline: &Timeline, lsn: Lsn) {
+    if lsn <= timeline.get_last_record_lsn()
+        || timeline.get_read_only() != Some(ReadOnlyReason::Offloaded)
+    {
+        return;
+    }
+    let tenant_shard_id = timeline.tenant_shard_id;
+    let tenant = match mgr::get_active_tenant_with_timeout(
+        tenant_shard_id.tenant_id,
+        ShardSelector::Known(tenant_shard_id.to_index()),
+        REACTIVATE_TIMEOUT,
+        &timeline.cancel,
+    )
+    .await
+    {
+        Ok(tenant) => tenant,
+        Err(e) => {
+            warn!("cannot reactivate offloaded tenant: {e:#}");
+            return;
+        }
+    };
+    match tenant.reactivate().await {
+        Ok(true) => info!(%lsn, "reactivated offloaded tenant for a read at a new LSN"),
+        Ok(false) => {}
+        Err(e) => warn!("failed to reactivate offloaded tenant: {e:#}"),
+    }
+}
@@ -0,0 +1,48 @@
+//! Parallel fsyncs of new layer files.
+//!
+//! Compaction and image layer creation write out many layer files at once. Fsyncing each of
+//! them when it's finished waits for the disk once per file. Instead, they finish the files
+//! with `finish_unsynced`, and make them durable at the end with
+//! [`crate::tenant::Timeline::fsync_new_layers`]: the files are fsynced concurrently with
+//! [`par_fsync`], then the timeline directory is fsynced once.
+
+use camino::Utf8Path;
+use futures::{StreamExt, TryStreamExt};
+
+use crate::virtual_file::VirtualFile;
+
+/// Bounds the fsyncs in flight for one operation, so that a large compaction doesn't fill up
+/// the disk's queue at the expense of other tenants.
+const MAX_CONCURRENT_FSYNCS: usize = 16;
+
+/// Fsync the files at `paths`, at most [`MAX_CONCURRENT_FSYNCS`] at a time.
+pub(crate) async fn par_fsync(paths: &[&Utf8Path]) -> std::io::Result<()> {
+    futures::stream::iter(paths)
+        .map(|path| async move { VirtualFile::open(path).await?.sync_all().await })
+        .buffer_unordered(MAX_CONCURRENT_FSYNCS)
+        .try_collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fsyncs_all_files() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let paths = (0..40)
+            .map(|i| {
+                let path = dir.path().join(format!("layer-{i}"));
+                std::fs::write(&path, b"data").unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+        let paths = paths.iter().map(|p| p.as_path()).collect::<Vec<_>>();
+        par_fsync(&paths).await.unwrap();
+
+        let missing = dir.path().join("missing");
+        let err = par_fsync(&[paths[0], missing.as_path()]).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+}
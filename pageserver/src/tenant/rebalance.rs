@@ -0,0 +1,192 @@
+//! Hooks for an external rebalancer, which live-migrates tenant shards between pageservers
+//! to balance their load.
+//!
+//! The rebalancer needs to know which tenants are busy, and to make migrations cheap:
+//! - `GET /v1/load_scores` reports the [`TenantLoadScore`] of all the attached tenant shards
+//!   in a single call: resident bytes, ingest and read rates, and compaction debt.
+//! - `POST /v1/tenant/:tenant_shard_id/rebalance/prepare_give_up`, on the pageserver the
+//!   tenant shard is attached to, flushes and uploads everything with [`prepare_give_up`], and
+//!   uploads a heatmap, so that the destination can download the layers it needs before the
+//!   tenant is attached there.
+//! - `POST /v1/tenant/:tenant_shard_id/rebalance/prepare_receive`, on the destination, where the
+//!   tenant shard has a secondary location, downloads the layers of the latest heatmap.
+//!
+//! The migration itself is still done with the `location_config` API.
+//!
+//! The rates are computed from cumulative counters sampled when the scores are requested. A
+//! tenant keeps its last sample, and only takes a new one once it is [`MIN_SAMPLE_INTERVAL`]
+//! old, so that the rates don't depend on how often, or by how many clients, the scores are
+//! polled.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use pageserver_api::models::TenantLoadScore;
+use utils::id::TimelineId;
+use utils::lsn::Lsn;
+
+use super::Tenant;
+
+/// The minimum interval between the samples that rates are computed from.
+pub(crate) const MIN_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The last sample of the counters of a [`Tenant`], and the rates computed with it.
+#[derive(Default)]
+pub(crate) struct LoadSampler {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    last: Option<Sample>,
+    rates: Option<Rates>,
+}
+
+struct Sample {
+    at: Instant,
+    /// Last record LSN of each timeline: timelines created or deleted between two samples
+    /// don't count towards the ingest rate.
+    ingested: HashMap<TimelineId, Lsn>,
+    getpage_requests: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rates {
+    ingest_bytes_per_second: f64,
+    getpage_requests_per_second: f64,
+}
+
+impl Rates {
+    fn between(earlier: &Sample, later: &Sample) -> Self {
+        let secs = later.at.duration_since(earlier.at).as_secs_f64();
+        let ingested: u64 = later
+            .ingested
+            .iter()
+            .filter_map(|(timeline_id, lsn)| {
+                let earlier_lsn = earlier.ingested.get(timeline_id)?;
+                Some(lsn.0.saturating_sub(earlier_lsn.0))
+            })
+            .sum();
+        let getpage_requests = later
+            .getpage_requests
+            .saturating_sub(earlier.getpage_requests);
+        Rates {
+            ingest_bytes_per_second: ingested as f64 / secs,
+            getpage_requests_per_second: getpage_requests as f64 / secs,
+        }
+    }
+}
+
+impl LoadSampler {
+    /// Take `sample` if the last one is old enough, and return the latest rates.
+    fn update(&self, sample: Sample) -> Option<Rates> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        match &inner.last {
+            Some(last) if sample.at.duration_since(last.at) < MIN_SAMPLE_INTERVAL => {}
+            Some(last) => {
+                inner.rates = Some(Rates::between(last, &sample));
+                inner.last = Some(sample);
+            }
+            None => inner.last = Some(sample),
+        }
+        inner.rates
+    }
+}
+
+/// The current load of an attached tenant shard.
+pub(crate) async fn load_score(tenant: &Tenant) -> TenantLoadScore {
+    let timelines = tenant.list_timelines();
+
+    let mut resident_bytes = 0;
+    let mut ingested = HashMap::with_capacity(timelines.len());
+    for timeline in &timelines {
+        resident_bytes += timeline.resident_physical_size();
+        ingested.insert(timeline.timeline_id, timeline.get_last_record_lsn());
+    }
+    let compaction_debt_bytes = tenant.update_compaction_debt().await;
+    // The GetPage latency metrics are shared by the timelines of the tenant shard.
+    let getpage_requests = timelines
+        .first()
+        .map(|timeline| timeline.getpage_latency.requests())
+        .unwrap_or(0);
+
+    let rates = tenant.load_sampler.update(Sample {
+        at: Instant::now(),
+        ingested,
+        getpage_requests,
+    });
+
+    TenantLoadScore {
+        tenant_shard_id: tenant.tenant_shard_id(),
+        resident_bytes,
+        ingest_bytes_per_second: rates.map(|r| r.ingest_bytes_per_second),
+        getpage_requests_per_second: rates.map(|r| r.getpage_requests_per_second),
+        compaction_debt_bytes,
+    }
+}
+
+/// Flush the in-memory layers of all the timelines of an active tenant shard, and wait for
+/// their uploads, so that a location the tenant shard moves to finds all its data in remote
+/// storage. The caller uploads a heatmap afterwards.
+pub(crate) async fn prepare_give_up(tenant: &Tenant) -> anyhow::Result<()> {
+    for timeline in tenant.list_timelines() {
+        timeline
+            .freeze_and_flush()
+            .await
+            .with_context(|| format!("flush timeline {}", timeline.timeline_id))?;
+        if let Some(remote_client) = &timeline.remote_client {
+            remote_client.wait_completion().await.with_context(|| {
+                format!("wait for uploads of timeline {}", timeline.timeline_id)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_between_samples() {
+        let sampler = LoadSampler::default();
+        let timeline_a = TimelineId::generate();
+        let timeline_b = TimelineId::generate();
+        let start = Instant::now();
+
+        let first = Sample {
+            at: start,
+            ingested: HashMap::from([(timeline_a, Lsn(0x1000))]),
+            getpage_requests: 100,
+        };
+        assert_eq!(sampler.update(first), None);
+
+        // A sample taken too soon after the last one is ignored.
+        let too_soon = Sample {
+            at: start + MIN_SAMPLE_INTERVAL / 2,
+            ingested: HashMap::from([(timeline_a, Lsn(0x2000))]),
+            getpage_requests: 200,
+        };
+        assert_eq!(sampler.update(too_soon), None);
+
+        // The new timeline doesn't count towards the ingest rate yet.
+        let second = Sample {
+            at: start + MIN_SAMPLE_INTERVAL,
+            ingested: HashMap::from([
+                (timeline_a, Lsn(0x1000 + 10 * 1024)),
+                (timeline_b, Lsn(0x9000)),
+            ]),
+            getpage_requests: 150,
+        };
+        assert_eq!(
+            sampler.update(second),
+            Some(Rates {
+                ingest_bytes_per_second: 1024.0,
+                getpage_requests_per_second: 5.0,
+            })
+        );
+    }
+}
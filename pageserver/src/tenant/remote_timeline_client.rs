@@ -172,6 +172,16 @@
 //!   transitioning it from `TenantState::Attaching` to `TenantState::Active` state.
 //!   This starts the timelines' WAL-receivers and the tenant's GC & Compaction loops.
 //!
+//! # Hot Tier
+//!
+//! Optionally, new layers are uploaded to a low-latency hot tier, and migrated to the standard
+//! remote storage as they age. See [`hot_tier`].
+//!
+//! # Snapshots
+//!
+//! A timeline can be created from a snapshot in the remote storage, and refer to its layers
+//! without copying them. See [`snapshot`].
+//!
 //! # Operating Without Remote Storage
 //!
 //! If no remote storage configuration is provided, the [`RemoteTimelineClient`] is
@@ -181,7 +191,9 @@
 //! [`Timeline::load_layer_map`]: super::Timeline::load_layer_map
 
 pub(crate) mod download;
+pub mod hot_tier;
 pub mod index;
+pub mod snapshot;
 pub(crate) mod upload;
 
 use anyhow::Context;
@@ -189,6 +201,7 @@ use camino::Utf8Path;
 use chrono::{NaiveDateTime, Utc};
 
 pub(crate) use download::download_initdb_tar_zst;
+use pageserver_api::models::{LegalHold, PinnedRelation, UploadQueueInfo};
 use pageserver_api::shard::{ShardIndex, TenantShardId};
 use scopeguard::ScopeGuard;
 use tokio_util::sync::CancellationToken;
@@ -197,7 +210,7 @@ use utils::backoff::{
     self, exponential_backoff, DEFAULT_BASE_BACKOFF_SECONDS, DEFAULT_MAX_BACKOFF_SECONDS,
 };
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
@@ -230,22 +243,29 @@ use crate::{
     task_mgr::BACKGROUND_RUNTIME,
     tenant::metadata::TimelineMetadata,
     tenant::upload_queue::{
-        UploadOp, UploadQueue, UploadQueueInitialized, UploadQueueStopped, UploadTask,
+        NotInitialized, UploadOp, UploadQueue, UploadQueueInitialized, UploadQueueStopped,
+        UploadTask,
     },
     TENANT_HEATMAP_BASENAME,
 };
 
 use utils::id::{TenantId, TimelineId};
 
+use self::index::GcPinnedCutoff;
 use self::index::IndexPart;
+use self::index::LogicalSizeCheckpoint;
 
+use super::crash_points;
 use super::metadata::MetadataUpdate;
 use super::storage_layer::{Layer, LayerName, ResidentLayer};
+use super::timeline::lsn_time_index::LsnTimeSample;
 use super::upload_queue::SetDeletedFlagProgress;
 use super::Generation;
 
 pub(crate) use download::{
-    download_index_part, is_temp_download_file, list_remote_tenant_shards, list_remote_timelines,
+    download_index_part, download_index_part_generation, download_latest_index_part,
+    is_temp_download_file, list_latest_index_generations, list_remote_tenant_shards,
+    list_remote_timelines,
 };
 pub(crate) use index::LayerFileMetadata;
 
@@ -321,6 +341,9 @@ pub struct RemoteTimelineClient {
 
     storage_impl: GenericRemoteStorage,
 
+    /// Where new layers are uploaded, if configured, see [`hot_tier`].
+    hot_tier: Option<&'static hot_tier::HotTier>,
+
     deletion_queue_client: DeletionQueueClient,
 
     cancel: CancellationToken,
@@ -353,6 +376,7 @@ impl RemoteTimelineClient {
             timeline_id,
             generation,
             storage_impl: remote_storage,
+            hot_tier: hot_tier::get(),
             deletion_queue_client,
             upload_queue: Mutex::new(UploadQueue::Uninitialized),
             metrics: Arc::new(RemoteTimelineClientMetrics::new(
@@ -396,10 +420,12 @@ impl RemoteTimelineClient {
         &self,
         index_part: &IndexPart,
     ) -> anyhow::Result<()> {
-        // FIXME: consider newtype for DeletedIndexPart.
-        let deleted_at = index_part.deleted_at.ok_or(anyhow::anyhow!(
-            "bug: it is responsibility of the caller to provide index part from MaybeDeletedIndexPart::Deleted"
-        ))?;
+        // The index is not marked deleted yet if we stopped before the deletion reached
+        // remote storage. Marking it is then the first thing the resumed deletion does.
+        let deleted_at = match index_part.deleted_at {
+            Some(deleted_at) => SetDeletedFlagProgress::Successful(deleted_at),
+            None => SetDeletedFlagProgress::NotRunning,
+        };
 
         let mut upload_queue = self.upload_queue.lock().unwrap();
         upload_queue.initialize_with_current_remote_index_part(index_part)?;
@@ -409,7 +435,7 @@ impl RemoteTimelineClient {
         upload_queue
             .stopped_mut()
             .expect("stopped above")
-            .deleted_at = SetDeletedFlagProgress::Successful(deleted_at);
+            .deleted_at = deleted_at;
 
         Ok(())
     }
@@ -450,6 +476,103 @@ impl RemoteTimelineClient {
             .unwrap_or(false)
     }
 
+    /// Record the logical size of the timeline at an LSN, to be persisted with the next upload
+    /// of the index part. Older checkpoints than the current one are ignored.
+    pub(crate) fn update_logical_size_checkpoint(
+        &self,
+        checkpoint: LogicalSizeCheckpoint,
+    ) -> anyhow::Result<()> {
+        let mut guard = self.upload_queue.lock().unwrap();
+        let upload_queue = guard.initialized_mut()?;
+        if upload_queue
+            .latest_logical_size
+            .map_or(true, |latest| latest.lsn < checkpoint.lsn)
+        {
+            upload_queue.latest_logical_size = Some(checkpoint);
+        }
+        Ok(())
+    }
+
+    /// Set the legal hold of the tenant, to be persisted with the next upload of the index part.
+    pub(crate) fn set_legal_hold(&self, hold: Option<LegalHold>) -> anyhow::Result<()> {
+        let mut guard = self.upload_queue.lock().unwrap();
+        let upload_queue = guard.initialized_mut()?;
+        upload_queue.latest_legal_hold = hold;
+        Ok(())
+    }
+
+    /// Record the GC cutoffs of the pinned relations, to be persisted with the next upload of
+    /// the index part. Relations that weren't pinned before get `gc_cutoff`, relations that are
+    /// no longer pinned are forgotten.
+    pub(crate) fn update_gc_pinned_cutoffs(
+        &self,
+        pinned_relations: &[PinnedRelation],
+        gc_cutoff: Lsn,
+    ) -> anyhow::Result<()> {
+        let mut guard = self.upload_queue.lock().unwrap();
+        let upload_queue = guard.initialized_mut()?;
+        let latest = std::mem::take(&mut upload_queue.latest_gc_pinned_cutoffs);
+        upload_queue.latest_gc_pinned_cutoffs = pinned_relations
+            .iter()
+            .map(|relation| {
+                latest
+                    .iter()
+                    .find(|cutoff| cutoff.relation == *relation)
+                    .copied()
+                    .unwrap_or(GcPinnedCutoff {
+                        relation: *relation,
+                        lsn: gc_cutoff,
+                    })
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// The GC cutoffs of the pinned relations, see [`GcPinnedCutoff`].
+    pub(crate) fn gc_pinned_cutoffs(&self) -> Vec<GcPinnedCutoff> {
+        self.upload_queue
+            .lock()
+            .unwrap()
+            .initialized_mut()
+            .map(|uq| uq.latest_gc_pinned_cutoffs.clone())
+            .unwrap_or_default()
+    }
+
+    /// Record the sampled commit timestamps up to the `disk_consistent_lsn` of the next upload of
+    /// the index part, to be persisted with it.
+    pub(crate) fn update_lsn_time_samples(
+        &self,
+        samples: Vec<LsnTimeSample>,
+    ) -> anyhow::Result<()> {
+        let mut guard = self.upload_queue.lock().unwrap();
+        let upload_queue = guard.initialized_mut()?;
+        upload_queue.latest_lsn_time_samples = samples;
+        Ok(())
+    }
+
+    /// The sampled commit timestamps of the index part, see
+    /// [`crate::tenant::timeline::lsn_time_index`].
+    pub(crate) fn lsn_time_samples(&self) -> Vec<LsnTimeSample> {
+        self.upload_queue
+            .lock()
+            .unwrap()
+            .initialized_mut()
+            .map(|uq| uq.latest_lsn_time_samples.clone())
+            .unwrap_or_default()
+    }
+
+    /// The latest logical size checkpoint, if it was taken at `lsn`.
+    pub(crate) fn logical_size_checkpoint_at(&self, lsn: Lsn) -> Option<u64> {
+        self.upload_queue
+            .lock()
+            .unwrap()
+            .initialized_mut()
+            .ok()
+            .and_then(|uq| uq.latest_logical_size)
+            .filter(|checkpoint| checkpoint.lsn == lsn)
+            .map(|checkpoint| checkpoint.size)
+    }
+
     fn update_remote_physical_size_gauge(&self, current_remote_index_part: Option<&IndexPart>) {
         let size: u64 = if let Some(current_remote_index_part) = current_remote_index_part {
             current_remote_index_part
@@ -464,6 +587,33 @@ impl RemoteTimelineClient {
         self.metrics.remote_physical_size_set(size);
     }
 
+    pub(crate) fn upload_queue_info(&self) -> Result<UploadQueueInfo, NotInitialized> {
+        let mut guard = self.upload_queue.lock().unwrap();
+        match &mut *guard {
+            UploadQueue::Uninitialized => Err(NotInitialized::Uninitialized),
+            UploadQueue::Stopped(_) => Err(NotInitialized::Stopped),
+            UploadQueue::Initialized(qi) => Ok(qi.info()),
+        }
+    }
+
+    /// Size of the layers that are queued or being uploaded.
+    pub(crate) fn upload_backlog_bytes(&self) -> u64 {
+        let guard = self.upload_queue.lock().unwrap();
+        let UploadQueue::Initialized(upload_queue) = &*guard else {
+            return 0;
+        };
+        upload_queue
+            .inprogress_tasks
+            .values()
+            .map(|task| &task.op)
+            .chain(upload_queue.queued_operations.iter().map(|(_, op)| op))
+            .map(|op| match op {
+                UploadOp::UploadLayer(_, metadata) => metadata.file_size(),
+                _ => 0,
+            })
+            .sum()
+    }
+
     pub fn get_remote_physical_size(&self) -> u64 {
         self.metrics.remote_physical_size_get()
     }
@@ -521,6 +671,20 @@ impl RemoteTimelineClient {
         cancel: &CancellationToken,
         ctx: &RequestContext,
     ) -> anyhow::Result<u64> {
+        let (is_hot, snapshot_id) = match self.upload_queue.lock().unwrap().initialized_mut() {
+            Ok(upload_queue) => (
+                upload_queue.latest_hot_layers.contains_key(layer_file_name),
+                upload_queue.snapshot_of(layer_file_name).cloned(),
+            ),
+            Err(_) => (false, None),
+        };
+        let hot_storage = self.hot_tier.map(|hot_tier| &hot_tier.storage);
+        let (storage, fallback) = match hot_storage {
+            _ if snapshot_id.is_some() => (&self.storage_impl, None),
+            Some(hot_storage) if is_hot => (hot_storage, Some(&self.storage_impl)),
+            _ => (&self.storage_impl, hot_storage),
+        };
+
         let downloaded_size = {
             let _unfinished_gauge_guard = self.metrics.call_begin(
                 &RemoteOpFileKind::Layer,
@@ -531,11 +695,13 @@ impl RemoteTimelineClient {
             );
             download::download_layer_file(
                 self.conf,
-                &self.storage_impl,
+                storage,
+                fallback,
                 self.tenant_shard_id,
                 self.timeline_id,
                 layer_file_name,
                 layer_metadata,
+                snapshot_id.as_ref(),
                 cancel,
                 ctx,
             )
@@ -553,6 +719,49 @@ impl RemoteTimelineClient {
         Ok(downloaded_size)
     }
 
+    /// Where the uploaded copy of a layer is: the storage, in the hot tier or not, and the path,
+    /// which is in a snapshot for the layers of snapshots. Returns `None` if the layer isn't in
+    /// the index, or if its upload hasn't completed yet.
+    pub(crate) fn uploaded_layer_location(
+        &self,
+        layer_file_name: &LayerName,
+    ) -> Option<(&GenericRemoteStorage, RemotePath, LayerFileMetadata)> {
+        let mut guard = self.upload_queue.lock().unwrap();
+        let upload_queue = guard.initialized_mut().ok()?;
+        let metadata = upload_queue.latest_files.get(layer_file_name)?.clone();
+
+        let uploading = upload_queue
+            .inprogress_tasks
+            .values()
+            .map(|task| &task.op)
+            .chain(upload_queue.queued_operations.iter().map(|(_, op)| op))
+            .any(|op| {
+                matches!(op, UploadOp::UploadLayer(layer, _)
+                    if &layer.layer_desc().layer_name() == layer_file_name)
+            });
+        if uploading {
+            return None;
+        }
+
+        let storage = match self.hot_tier {
+            Some(hot_tier) if upload_queue.latest_hot_layers.contains_key(layer_file_name) => {
+                &hot_tier.storage
+            }
+            _ => &self.storage_impl,
+        };
+        let remote_path = match upload_queue.snapshot_of(layer_file_name) {
+            Some(snapshot_id) => snapshot::remote_snapshot_layer_path(snapshot_id, layer_file_name),
+            None => remote_layer_path(
+                &self.tenant_shard_id.tenant_id,
+                &self.timeline_id,
+                metadata.shard,
+                layer_file_name,
+                metadata.generation,
+            ),
+        };
+        Some((storage, remote_path, metadata))
+    }
+
     //
     // Upload operations.
     //
@@ -642,8 +851,11 @@ impl RemoteTimelineClient {
 
         let index_part = IndexPart::from(&*upload_queue);
         let op = UploadOp::UploadMetadata(Box::new(index_part), disk_consistent_lsn);
-        self.metric_begin(&op);
-        upload_queue.queued_operations.push_back(op);
+        // The metrics count a superseded index upload and the one replacing it as one.
+        if !upload_queue.pop_queued_index_upload() {
+            self.metric_begin(&op);
+        }
+        upload_queue.push_op(op);
         upload_queue.latest_files_changes_since_metadata_upload_scheduled = 0;
 
         // Launch the task immediately, if possible
@@ -709,6 +921,63 @@ impl RemoteTimelineClient {
         Self::wait_completion0(barrier).await
     }
 
+    /// Schedules uploading a new version of `index_part.json` with the given layers of a
+    /// snapshot added, and waits for it to complete. The caller must hold a reference to the
+    /// snapshot, see [`snapshot::add_ref`].
+    pub(crate) async fn schedule_adding_snapshot_layers_and_wait(
+        self: &Arc<Self>,
+        snapshot_id: &snapshot::SnapshotId,
+        layers: &[Layer],
+    ) -> anyhow::Result<()> {
+        let barrier = {
+            let mut guard = self.upload_queue.lock().unwrap();
+            let upload_queue = guard.initialized_mut()?;
+
+            let snapshot_layers = upload_queue
+                .latest_snapshots
+                .entry(snapshot_id.clone())
+                .or_default();
+            for layer in layers {
+                snapshot_layers.insert(layer.layer_desc().layer_name());
+            }
+            for layer in layers {
+                upload_queue
+                    .latest_files
+                    .insert(layer.layer_desc().layer_name(), layer.metadata());
+            }
+
+            self.schedule_index_upload(upload_queue);
+
+            let barrier = self.schedule_barrier0(upload_queue);
+            self.launch_queued_tasks(upload_queue);
+            barrier
+        };
+
+        Self::wait_completion0(barrier).await
+    }
+
+    /// Schedules uploading a new version of `index_part.json` with the given legal hold of the
+    /// tenant, and waits for it to complete.
+    pub(crate) async fn schedule_legal_hold_update_and_wait(
+        self: &Arc<Self>,
+        hold: Option<LegalHold>,
+    ) -> anyhow::Result<()> {
+        let barrier = {
+            let mut guard = self.upload_queue.lock().unwrap();
+            let upload_queue = guard.initialized_mut()?;
+
+            upload_queue.latest_legal_hold = hold;
+
+            self.schedule_index_upload(upload_queue);
+
+            let barrier = self.schedule_barrier0(upload_queue);
+            self.launch_queued_tasks(upload_queue);
+            barrier
+        };
+
+        Self::wait_completion0(barrier).await
+    }
+
     /// Launch an upload operation in the background; the file is added to be included in next
     /// `index_part.json` upload.
     pub(crate) fn schedule_layer_file_upload(
@@ -733,6 +1002,15 @@ impl RemoteTimelineClient {
         upload_queue
             .latest_files
             .insert(layer.layer_desc().layer_name(), metadata.clone());
+        if self.hot_tier.is_some() {
+            upload_queue
+                .latest_hot_layers
+                .insert(layer.layer_desc().layer_name(), Utc::now().naive_utc());
+        }
+        // A layer of the timeline replaces the layer of a snapshot with the same name.
+        for layers in upload_queue.latest_snapshots.values_mut() {
+            layers.remove(&layer.layer_desc().layer_name());
+        }
         upload_queue.latest_files_changes_since_metadata_upload_scheduled += 1;
 
         info!(
@@ -743,7 +1021,7 @@ impl RemoteTimelineClient {
 
         let op = UploadOp::UploadLayer(layer, metadata);
         self.metric_begin(&op);
-        upload_queue.queued_operations.push_back(op);
+        upload_queue.push_op(op);
     }
 
     /// Launch a delete operation in the background.
@@ -810,9 +1088,17 @@ impl RemoteTimelineClient {
             .into_iter()
             .filter_map(|name| {
                 let meta = upload_queue.latest_files.remove(&name);
+                upload_queue.latest_hot_layers.remove(&name);
+                // The snapshot keeps the layer, and the timeline keeps its reference.
+                for layers in upload_queue.latest_snapshots.values_mut() {
+                    layers.remove(&name);
+                }
 
                 if let Some(meta) = meta {
                     upload_queue.latest_files_changes_since_metadata_upload_scheduled += 1;
+                    upload_queue
+                        .unlinked_since_metadata_upload_scheduled
+                        .insert(name.clone());
                     Some((name, meta))
                 } else {
                     // This can only happen if we forgot to to schedule the file upload
@@ -883,6 +1169,21 @@ impl RemoteTimelineClient {
             retain
         });
 
+        // Leaking a layer is better than deleting one that the remote index may still need,
+        // like the input of a compaction whose outputs are not in the remote index yet.
+        with_metadata.retain(|(name, meta)| {
+            match upload_queue.check_deletion_ordering(name, meta) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::error!(
+                        "refusing to delete layer {name}{}: {e}",
+                        meta.generation.get_suffix()
+                    );
+                    false
+                }
+            }
+        });
+
         for (name, meta) in &with_metadata {
             info!(
                 "scheduling deletion of layer {}{} (shard {})",
@@ -915,7 +1216,7 @@ impl RemoteTimelineClient {
             layers: with_metadata,
         });
         self.metric_begin(&op);
-        upload_queue.queued_operations.push_back(op);
+        upload_queue.push_op(op);
     }
 
     /// Schedules a compaction update to the remote `index_part.json`.
@@ -976,7 +1277,7 @@ impl RemoteTimelineClient {
         let (sender, receiver) = tokio::sync::watch::channel(());
         let barrier_op = UploadOp::Barrier(sender);
 
-        upload_queue.queued_operations.push_back(barrier_op);
+        upload_queue.push_op(barrier_op);
         // Don't count this kind of operation!
 
         // Launch the task immediately, if possible
@@ -1016,7 +1317,7 @@ impl RemoteTimelineClient {
             // made cancellable.
             if !upload_queue.shutting_down {
                 upload_queue.shutting_down = true;
-                upload_queue.queued_operations.push_back(UploadOp::Shutdown);
+                upload_queue.push_op(UploadOp::Shutdown);
                 // this operation is not counted similar to Barrier
 
                 self.launch_queued_tasks(upload_queue);
@@ -1085,9 +1386,13 @@ impl RemoteTimelineClient {
 
         pausable_failpoint!("persist_deleted_index_part");
 
+        crash_points::check(&self.tenant_shard_id, "persist deleted index part")
+            .map_err(anyhow::Error::from)?;
+
         backoff::retry(
             || {
                 upload::upload_index_part(
+                    self.conf,
                     &self.storage_impl,
                     &self.tenant_shard_id,
                     &self.timeline_id,
@@ -1218,13 +1523,28 @@ impl RemoteTimelineClient {
 
         backoff::retry(
             || async {
-                upload::copy_timeline_layer(
+                let res = upload::copy_timeline_layer(
                     &self.storage_impl,
                     &source_remote_path,
                     &target_remote_path,
                     cancel,
                 )
-                .await
+                .await;
+                match (res, self.hot_tier) {
+                    // The source layer may not have been migrated out of the hot tier yet.
+                    (Err(_), Some(hot_tier)) => {
+                        hot_tier::copy_layer(
+                            &hot_tier.storage,
+                            &source_remote_path,
+                            &self.storage_impl,
+                            &target_remote_path,
+                            adopted.metadata().file_size(),
+                            cancel,
+                        )
+                        .await
+                    }
+                    (res, _) => res,
+                }
             },
             TimeoutOrCancel::caused_by_cancel,
             FAILED_UPLOAD_WARN_THRESHOLD,
@@ -1264,7 +1584,7 @@ impl RemoteTimelineClient {
     pub(crate) async fn delete_all(self: &Arc<Self>) -> anyhow::Result<()> {
         debug_assert_current_span_has_tenant_and_timeline_id();
 
-        let layers: Vec<RemotePath> = {
+        let (layers, snapshot_refs): (Vec<RemotePath>, Vec<RemotePath>) = {
             let mut locked = self.upload_queue.lock().unwrap();
             let stopped = locked.stopped_mut()?;
 
@@ -1274,10 +1594,18 @@ impl RemoteTimelineClient {
 
             debug_assert!(stopped.upload_queue_for_deletion.no_pending_work());
 
-            stopped
-                .upload_queue_for_deletion
+            let upload_queue = &mut stopped.upload_queue_for_deletion;
+            // The layers of snapshots are not ours to delete.
+            let snapshot_layers: HashSet<LayerName> = upload_queue
+                .latest_snapshots
+                .values()
+                .flatten()
+                .cloned()
+                .collect();
+            let layers = upload_queue
                 .latest_files
                 .drain()
+                .filter(|(file_name, _)| !snapshot_layers.contains(file_name))
                 .map(|(file_name, meta)| {
                     remote_layer_path(
                         &self.tenant_shard_id.tenant_id,
@@ -1287,7 +1615,19 @@ impl RemoteTimelineClient {
                         meta.generation,
                     )
                 })
-                .collect()
+                .collect();
+            let snapshot_refs = upload_queue
+                .latest_snapshots
+                .keys()
+                .map(|snapshot_id| {
+                    snapshot::remote_snapshot_ref_path(
+                        snapshot_id,
+                        &self.tenant_shard_id,
+                        &self.timeline_id,
+                    )
+                })
+                .collect();
+            (layers, snapshot_refs)
         };
 
         let layer_deletion_count = layers.len();
@@ -1301,6 +1641,14 @@ impl RemoteTimelineClient {
             .push_immediate(vec![initdb_path])
             .await?;
 
+        // Release the references to snapshots while the index part still records them: if we
+        // fail after this, a retry releases them again.
+        if !snapshot_refs.is_empty() {
+            self.deletion_queue_client
+                .push_immediate(snapshot_refs)
+                .await?;
+        }
+
         // Do not delete index part yet, it is needed for possible retry. If we remove it first
         // and retry will arrive to different pageserver there wont be any traces of it on remote storage
         let timeline_storage_path = remote_timeline_path(&self.tenant_shard_id, &self.timeline_id);
@@ -1380,6 +1728,8 @@ impl RemoteTimelineClient {
             ))?
         });
 
+        crash_points::check(&self.tenant_shard_id, "delete remote index part")?;
+
         debug!("enqueuing index part deletion");
         self.deletion_queue_client
             .push_immediate([latest_index].to_vec())
@@ -1406,102 +1756,88 @@ impl RemoteTimelineClient {
     ///
     /// The caller needs to already hold the `upload_queue` lock.
     fn launch_queued_tasks(self: &Arc<Self>, upload_queue: &mut UploadQueueInitialized) {
-        while let Some(next_op) = upload_queue.queued_operations.front() {
-            // Can we run this task now?
-            let can_run_now = match next_op {
-                UploadOp::UploadLayer(..) => {
-                    // Can always be scheduled.
-                    true
-                }
-                UploadOp::UploadMetadata(_, _) => {
-                    // These can only be performed after all the preceding operations
-                    // have finished.
-                    upload_queue.inprogress_tasks.is_empty()
-                }
-                UploadOp::Delete(_) => {
-                    // Wait for preceding uploads to finish. Concurrent deletions are OK, though.
-                    upload_queue.num_inprogress_deletions == upload_queue.inprogress_tasks.len()
-                }
-
-                UploadOp::Barrier(_) | UploadOp::Shutdown => {
-                    upload_queue.inprogress_tasks.is_empty()
-                }
-            };
-
-            // If we cannot launch this task, don't look any further.
-            //
-            // In some cases, we could let some non-frontmost tasks to "jump the queue" and launch
-            // them now, but we don't try to do that currently.  For example, if the frontmost task
-            // is an index-file upload that cannot proceed until preceding uploads have finished, we
-            // could still start layer uploads that were scheduled later.
-            if !can_run_now {
+        // Launch every operation which doesn't depend on one in progress or queued before it,
+        // see `UploadOp::depends_on`. Popping a barrier can unblock more of them, hence the
+        // loop.
+        loop {
+            let ready = upload_queue.ready_operations();
+            if ready.is_empty() {
                 break;
             }
-
-            if let UploadOp::Shutdown = next_op {
+            if let Some((_, UploadOp::Shutdown)) = upload_queue.queued_operations.get(ready[0]) {
                 // leave the op in the queue but do not start more tasks; it will be dropped when
                 // the stop is called.
                 upload_queue.shutdown_ready.close();
                 break;
             }
 
-            // We can launch this task. Remove it from the queue first.
-            let next_op = upload_queue.queued_operations.pop_front().unwrap();
-
-            debug!("starting op: {}", next_op);
+            // Remove them from the queue first, from the back so that the positions stay valid.
+            let mut ready_ops = ready
+                .into_iter()
+                .rev()
+                .map(|i| upload_queue.queued_operations.remove(i).unwrap())
+                .collect::<Vec<_>>();
+            ready_ops.reverse();
 
-            // Update the counters
-            match next_op {
-                UploadOp::UploadLayer(_, _) => {
-                    upload_queue.num_inprogress_layer_uploads += 1;
-                }
-                UploadOp::UploadMetadata(_, _) => {
-                    upload_queue.num_inprogress_metadata_uploads += 1;
-                }
-                UploadOp::Delete(_) => {
-                    upload_queue.num_inprogress_deletions += 1;
-                }
-                UploadOp::Barrier(sender) => {
-                    sender.send_replace(());
-                    continue;
-                }
-                UploadOp::Shutdown => unreachable!("shutdown is intentionally never popped off"),
-            };
+            for (task_id, next_op) in ready_ops {
+                self.launch_op(upload_queue, task_id, next_op);
+            }
+        }
+    }
 
-            // Assign unique ID to this task
-            upload_queue.task_counter += 1;
-            let upload_task_id = upload_queue.task_counter;
+    fn launch_op(
+        self: &Arc<Self>,
+        upload_queue: &mut UploadQueueInitialized,
+        upload_task_id: u64,
+        next_op: UploadOp,
+    ) {
+        debug!("starting op: {}", next_op);
 
-            // Add it to the in-progress map
-            let task = Arc::new(UploadTask {
-                task_id: upload_task_id,
-                op: next_op,
-                retries: AtomicU32::new(0),
-            });
-            upload_queue
-                .inprogress_tasks
-                .insert(task.task_id, Arc::clone(&task));
-
-            // Spawn task to perform the task
-            let self_rc = Arc::clone(self);
-            let tenant_shard_id = self.tenant_shard_id;
-            let timeline_id = self.timeline_id;
-            task_mgr::spawn(
-                &self.runtime,
-                TaskKind::RemoteUploadTask,
-                Some(self.tenant_shard_id),
-                Some(self.timeline_id),
-                "remote upload",
-                false,
-                async move {
-                    self_rc.perform_upload_task(task).await;
-                    Ok(())
-                }
-                .instrument(info_span!(parent: None, "remote_upload", tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), %timeline_id, %upload_task_id)),
-            );
+        // Update the counters
+        match next_op {
+            UploadOp::UploadLayer(_, _) => {
+                upload_queue.num_inprogress_layer_uploads += 1;
+            }
+            UploadOp::UploadMetadata(_, _) => {
+                upload_queue.num_inprogress_metadata_uploads += 1;
+            }
+            UploadOp::Delete(_) => {
+                upload_queue.num_inprogress_deletions += 1;
+            }
+            UploadOp::Barrier(sender) => {
+                sender.send_replace(());
+                return;
+            }
+            UploadOp::Shutdown => unreachable!("shutdown is intentionally never popped off"),
+        };
 
-            // Loop back to process next task
-        }
+        // Add it to the in-progress map
+        let task = Arc::new(UploadTask {
+            task_id: upload_task_id,
+            op: next_op,
+            retries: AtomicU32::new(0),
+        });
+        upload_queue
+            .inprogress_tasks
+            .insert(task.task_id, Arc::clone(&task));
+
+        // Spawn task to perform the task
+        let self_rc = Arc::clone(self);
+        let tenant_shard_id = self.tenant_shard_id;
+        let timeline_id = self.timeline_id;
+        task_mgr::spawn(
+            &self.runtime,
+            TaskKind::RemoteUploadTask,
+            Some(self.tenant_shard_id),
+            Some(self.timeline_id),
+            "remote upload",
+            false,
+            async move {
+                self_rc.perform_upload_task(task).await;
+                Ok(())
+            }
+            .instrument(info_span!(parent: None, "remote_upload", tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), %timeline_id, %upload_task_id)),
+        );
     }
 
     ///
@@ -1534,6 +1870,13 @@ impl RemoteTimelineClient {
                 return;
             }
 
+            if let Err(e) = crash_points::check(&self.tenant_shard_id, "remote upload task") {
+                // A crashed pageserver does nothing more, wait to be shut down.
+                info!("{e}");
+                cancel.cancelled().await;
+                continue;
+            }
+
             let upload_result: anyhow::Result<()> = match &task.op {
                 UploadOp::UploadLayer(ref layer, ref layer_metadata) => {
                     let local_path = layer.local_path();
@@ -1550,8 +1893,15 @@ impl RemoteTimelineClient {
                         layer_metadata.generation,
                     );
 
+                    // The layer was recorded to be in the hot tier when it was scheduled, if
+                    // there is one.
+                    let storage = match self.hot_tier {
+                        Some(hot_tier) => &hot_tier.storage,
+                        None => &self.storage_impl,
+                    };
+
                     upload::upload_timeline_layer(
-                        &self.storage_impl,
+                        storage,
                         local_path,
                         &remote_path,
                         layer_metadata.file_size(),
@@ -1575,6 +1925,7 @@ impl RemoteTimelineClient {
                     };
 
                     let res = upload::upload_index_part(
+                        self.conf,
                         &self.storage_impl,
                         &self.tenant_shard_id,
                         &self.timeline_id,
@@ -1831,6 +2182,12 @@ impl RemoteTimelineClient {
                         latest_files_changes_since_metadata_upload_scheduled: 0,
                         latest_metadata: initialized.latest_metadata.clone(),
                         latest_lineage: initialized.latest_lineage.clone(),
+                        latest_logical_size: initialized.latest_logical_size,
+                        latest_hot_layers: initialized.latest_hot_layers.clone(),
+                        latest_snapshots: initialized.latest_snapshots.clone(),
+                        latest_legal_hold: initialized.latest_legal_hold.clone(),
+                        latest_gc_pinned_cutoffs: initialized.latest_gc_pinned_cutoffs.clone(),
+                        latest_lsn_time_samples: initialized.latest_lsn_time_samples.clone(),
                         projected_remote_consistent_lsn: None,
                         visible_remote_consistent_lsn: initialized
                             .visible_remote_consistent_lsn
@@ -1876,7 +2233,7 @@ impl RemoteTimelineClient {
                 drop(qi.inprogress_tasks);
 
                 // Tear down queued ops
-                for op in qi.queued_operations.into_iter() {
+                for (_, op) in qi.queued_operations.into_iter() {
                     self.metric_end(&op);
                     // Dropping UploadOp::Barrier() here will make wait_completion() return with an Err()
                     // which is exactly what we want to happen.
@@ -1992,11 +2349,13 @@ mod tests {
         tenant::{
             harness::{TenantHarness, TIMELINE_ID},
             storage_layer::layer::local_layer_path,
+            upload_queue::DeletionOrderingViolation,
             Tenant, Timeline,
         },
         DEFAULT_PG_VERSION,
     };
 
+    use itertools::Itertools;
     use std::collections::HashSet;
 
     pub(super) fn dummy_contents(name: &str) -> Vec<u8> {
@@ -2082,6 +2441,7 @@ mod tests {
                 timeline_id: TIMELINE_ID,
                 generation,
                 storage_impl: self.harness.remote_storage.clone(),
+                hot_tier: None,
                 deletion_queue_client: self.harness.deletion_queue.new_client(),
                 upload_queue: Mutex::new(UploadQueue::Uninitialized),
                 metrics: Arc::new(RemoteTimelineClientMetrics::new(
@@ -2313,6 +2673,347 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn upload_scheduling_out_of_order() {
+        let test_setup = TestSetup::new("upload_scheduling_out_of_order")
+            .await
+            .unwrap();
+        let span = test_setup.span();
+        let _guard = span.enter();
+
+        let TestSetup {
+            harness,
+            tenant: _tenant,
+            timeline,
+            tenant_ctx: _tenant_ctx,
+        } = test_setup;
+
+        let client = timeline.remote_client.as_ref().unwrap();
+        let remote_timeline_dir = harness.remote_fs_dir.join(
+            harness
+                .timeline_path(&TIMELINE_ID)
+                .strip_prefix(&harness.conf.workdir)
+                .unwrap(),
+        );
+        let generation = harness.generation;
+        let shard = harness.shard;
+
+        let layers = [
+            ("000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016B59D8-00000000016B5A51".parse().unwrap(), dummy_contents("foo")),
+            ("000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016B59D9-00000000016B5A52".parse().unwrap(), dummy_contents("bar")),
+        ]
+        .into_iter()
+        .map(|(name, contents): (LayerName, Vec<u8>)| {
+            let local_path = local_layer_path(
+                harness.conf,
+                &timeline.tenant_shard_id,
+                &timeline.timeline_id,
+                &name,
+                &generation,
+            );
+            std::fs::write(&local_path, &contents).unwrap();
+
+            Layer::for_resident(
+                harness.conf,
+                &timeline,
+                local_path,
+                name,
+                LayerFileMetadata::new(contents.len() as u64, generation, shard),
+            )
+        })
+        .collect::<Vec<_>>();
+
+        // The index upload waits for the layer upload before it, but the layer upload after it
+        // doesn't wait for the index upload.
+        client
+            .schedule_layer_file_upload(layers[0].clone())
+            .unwrap();
+        client
+            .schedule_index_upload_for_full_metadata_update(&dummy_metadata(Lsn(0x20)))
+            .unwrap();
+        client
+            .schedule_layer_file_upload(layers[1].clone())
+            .unwrap();
+        {
+            let mut guard = client.upload_queue.lock().unwrap();
+            let upload_queue = guard.initialized_mut().unwrap();
+            assert_eq!(upload_queue.queued_operations.len(), 1);
+            assert_eq!(upload_queue.num_inprogress_layer_uploads, 2);
+        }
+
+        // The deletion waits for the index uploads before it, and for the upload of the layer.
+        client
+            .schedule_layer_file_deletion(&[layers[0].layer_desc().layer_name()])
+            .unwrap();
+        {
+            let mut guard = client.upload_queue.lock().unwrap();
+            let upload_queue = guard.initialized_mut().unwrap();
+            let info = upload_queue.info();
+            let [upload0, upload1] = &info.in_progress[..] else {
+                panic!("unexpected in-progress ops {:?}", info.in_progress);
+            };
+            let [index, delete] = &info.queued[..] else {
+                panic!("unexpected queued ops {:?}", info.queued);
+            };
+            assert!(upload0.op.contains(&layers[0].to_string()));
+            assert!(upload1.op.contains(&layers[1].to_string()));
+            assert!(delete.op.starts_with("Delete"));
+
+            // The index upload scheduled with the deletion superseded the one which hadn't
+            // started, and waits for the layer upload scheduled after that one too.
+            assert_eq!(index.waits_for, [upload0.task_id, upload1.task_id]);
+            assert_eq!(delete.waits_for, [upload0.task_id, index.task_id]);
+        }
+
+        client.wait_completion().await.unwrap();
+        harness.deletion_queue.pump().await;
+
+        let index_part = match client
+            .download_index_file(&CancellationToken::new())
+            .await
+            .unwrap()
+        {
+            MaybeDeletedIndexPart::IndexPart(index_part) => index_part,
+            MaybeDeletedIndexPart::Deleted(_) => panic!("unexpectedly got deleted index part"),
+        };
+        assert!(index_part
+            .layer_metadata
+            .contains_key(&layers[1].layer_desc().layer_name()));
+        assert!(!index_part
+            .layer_metadata
+            .contains_key(&layers[0].layer_desc().layer_name()));
+        assert!(!remote_timeline_dir
+            .join(layers[0].layer_desc().layer_name().to_string() + &generation.get_suffix())
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn compaction_inputs_deleted_after_outputs_in_index() {
+        let test_setup = TestSetup::new("compaction_inputs_deleted_after_outputs_in_index")
+            .await
+            .unwrap();
+        let span = test_setup.span();
+        let _guard = span.enter();
+
+        let TestSetup {
+            harness,
+            tenant: _tenant,
+            timeline,
+            tenant_ctx: _tenant_ctx,
+        } = test_setup;
+
+        let client = timeline.remote_client.as_ref().unwrap();
+        let remote_timeline_dir = harness.remote_fs_dir.join(
+            harness
+                .timeline_path(&TIMELINE_ID)
+                .strip_prefix(&harness.conf.workdir)
+                .unwrap(),
+        );
+        let generation = harness.generation;
+        let shard = harness.shard;
+
+        let download_index_layers = || async {
+            match client
+                .download_index_file(&CancellationToken::new())
+                .await
+                .unwrap()
+            {
+                MaybeDeletedIndexPart::IndexPart(index_part) => index_part
+                    .layer_metadata
+                    .keys()
+                    .map(|name| name.to_string())
+                    .sorted()
+                    .collect::<Vec<_>>(),
+                MaybeDeletedIndexPart::Deleted(_) => panic!("unexpectedly got deleted index part"),
+            }
+        };
+        let initial_layers = download_index_layers().await;
+
+        let [input0, input1, output] = [
+            ("000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016B59D8-00000000016B5A51", "input0"),
+            ("000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016B5A51-00000000016B5A52", "input1"),
+            ("000000000000000000000000000000000000-030000000000000000000000000000000002__00000000016B59D8-00000000016B5A52", "output"),
+        ]
+        .map(|(name, contents)| {
+            let name: LayerName = name.parse().unwrap();
+            let contents = dummy_contents(contents);
+            let local_path = local_layer_path(
+                harness.conf,
+                &timeline.tenant_shard_id,
+                &timeline.timeline_id,
+                &name,
+                &generation,
+            );
+            std::fs::write(&local_path, &contents).unwrap();
+
+            Layer::for_resident(
+                harness.conf,
+                &timeline,
+                local_path,
+                name,
+                LayerFileMetadata::new(contents.len() as u64, generation, shard),
+            )
+        });
+
+        client.schedule_layer_file_upload(input0.clone()).unwrap();
+        client.schedule_layer_file_upload(input1.clone()).unwrap();
+        client
+            .schedule_index_upload_for_full_metadata_update(&dummy_metadata(Lsn(0x20)))
+            .unwrap();
+        client.wait_completion().await.unwrap();
+
+        let inputs = [input0.clone(), input1.clone()];
+        let mut expected_remote = initial_layers.clone();
+        expected_remote.extend(
+            inputs
+                .iter()
+                .map(|l| l.layer_desc().layer_name().to_string()),
+        );
+        expected_remote.push("index_part.json".to_string());
+
+        // Compact the inputs into the output, and delete the inputs like `Layer::drop` does.
+        client
+            .schedule_compaction_update(
+                &inputs.clone().map(|l| l.drop_eviction_guard()),
+                &[output.clone()],
+            )
+            .unwrap();
+        client
+            .schedule_deletion_of_unlinked(
+                inputs
+                    .iter()
+                    .map(|l| (l.layer_desc().layer_name(), l.metadata()))
+                    .collect(),
+            )
+            .unwrap();
+        {
+            let mut guard = client.upload_queue.lock().unwrap();
+            let upload_queue = guard.initialized_mut().unwrap();
+            let info = upload_queue.info();
+            let [upload] = &info.in_progress[..] else {
+                panic!("unexpected in-progress ops {:?}", info.in_progress);
+            };
+            let [index, delete] = &info.queued[..] else {
+                panic!("unexpected queued ops {:?}", info.queued);
+            };
+            assert!(upload.op.contains(&output.to_string()));
+            assert!(delete.op.starts_with("Delete"));
+
+            // Crash before the output is uploaded, or before the index referencing it is: the
+            // remote index still references the inputs, which are not deleted yet.
+            assert_eq!(index.waits_for, [upload.task_id]);
+            // Crash before the inputs are deleted: the remote index references the output.
+            assert_eq!(delete.waits_for, [index.task_id]);
+        }
+        assert_remote_files(
+            &expected_remote
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>(),
+            &remote_timeline_dir,
+            generation,
+        );
+
+        client.wait_completion().await.unwrap();
+        harness.deletion_queue.pump().await;
+
+        let mut expected_index = initial_layers.clone();
+        expected_index.push(output.layer_desc().layer_name().to_string());
+        expected_index.sort();
+        assert_eq!(download_index_layers().await, expected_index);
+        let mut expected_remote = expected_index;
+        expected_remote.push("index_part.json".to_string());
+        assert_remote_files(
+            &expected_remote
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>(),
+            &remote_timeline_dir,
+            generation,
+        );
+    }
+
+    #[tokio::test]
+    async fn deletion_before_unlinking_index_is_refused() {
+        let test_setup = TestSetup::new("deletion_before_unlinking_index_is_refused")
+            .await
+            .unwrap();
+        let span = test_setup.span();
+        let _guard = span.enter();
+
+        let TestSetup {
+            harness,
+            tenant: _tenant,
+            timeline,
+            tenant_ctx: _tenant_ctx,
+        } = test_setup;
+
+        let client = timeline.remote_client.as_ref().unwrap();
+        let remote_timeline_dir = harness.remote_fs_dir.join(
+            harness
+                .timeline_path(&TIMELINE_ID)
+                .strip_prefix(&harness.conf.workdir)
+                .unwrap(),
+        );
+        let generation = harness.generation;
+
+        let name: LayerName = "000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016B59D8-00000000016B5A51".parse().unwrap();
+        let contents = dummy_contents("foo");
+        let local_path = local_layer_path(
+            harness.conf,
+            &timeline.tenant_shard_id,
+            &timeline.timeline_id,
+            &name,
+            &generation,
+        );
+        std::fs::write(&local_path, &contents).unwrap();
+        let metadata = LayerFileMetadata::new(contents.len() as u64, generation, harness.shard);
+        let layer = Layer::for_resident(
+            harness.conf,
+            &timeline,
+            local_path,
+            name.clone(),
+            metadata.clone(),
+        );
+
+        client.schedule_layer_file_upload(layer).unwrap();
+        client
+            .schedule_index_upload_for_full_metadata_update(&dummy_metadata(Lsn(0x20)))
+            .unwrap();
+        client.wait_completion().await.unwrap();
+
+        // Still referenced by the index: the deletion is not scheduled.
+        client
+            .schedule_deletion_of_unlinked(vec![(name.clone(), metadata.clone())])
+            .unwrap();
+        {
+            let mut guard = client.upload_queue.lock().unwrap();
+            let upload_queue = guard.initialized_mut().unwrap();
+            assert!(upload_queue.no_pending_work());
+
+            // Unlinked, but the index upload which stops referencing it is not scheduled yet.
+            upload_queue.latest_files.remove(&name);
+            upload_queue
+                .unlinked_since_metadata_upload_scheduled
+                .insert(name.clone());
+            assert_eq!(
+                upload_queue.check_deletion_ordering(&name, &metadata),
+                Err(DeletionOrderingViolation::IndexUploadNotScheduled)
+            );
+            client.schedule_index_upload(upload_queue);
+            assert_eq!(
+                upload_queue.check_deletion_ordering(&name, &metadata),
+                Ok(())
+            );
+        }
+        client.wait_completion().await.unwrap();
+        harness.deletion_queue.pump().await;
+
+        assert!(remote_timeline_dir
+            .join(name.to_string() + &generation.get_suffix())
+            .exists());
+    }
+
     #[tokio::test]
     async fn bytes_unfinished_gauge_for_layer_file_uploads() {
         // Setup
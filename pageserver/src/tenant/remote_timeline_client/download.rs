@@ -3,8 +3,9 @@
 //! The functions in this module retry failed operations automatically, according
 //! to the FAILED_DOWNLOAD_RETRIES constant.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::num::NonZeroU32;
 use std::str::FromStr;
 
 use anyhow::{anyhow, Context};
@@ -20,17 +21,18 @@ use utils::backoff;
 use crate::config::PageServerConf;
 use crate::context::RequestContext;
 use crate::span::debug_assert_current_span_has_tenant_and_timeline_id;
+use crate::tenant::remote_timeline_client::snapshot::{remote_snapshot_layer_path, SnapshotId};
 use crate::tenant::remote_timeline_client::{remote_layer_path, remote_timelines_path};
 use crate::tenant::storage_layer::layer::local_layer_path;
 use crate::tenant::storage_layer::LayerName;
-use crate::tenant::Generation;
+use crate::tenant::{Generation, TENANTS_SEGMENT_NAME, TIMELINES_SEGMENT_NAME};
 use crate::virtual_file::{on_fatal_io_error, MaybeFatalIo, VirtualFile};
 use crate::TEMP_FILE_SUFFIX;
 use remote_storage::{DownloadError, GenericRemoteStorage, ListingMode, RemotePath};
 use utils::crashsafe::path_with_suffix_extension;
 use utils::id::{TenantId, TimelineId};
 
-use super::index::{IndexPart, LayerFileMetadata};
+use super::index::{IndexPart, IndexPartEncoding, LayerFileMetadata};
 use super::{
     parse_remote_index_path, remote_index_path, remote_initdb_archive_path,
     remote_initdb_preserved_archive_path, remote_tenant_path, FAILED_DOWNLOAD_WARN_THRESHOLD,
@@ -41,15 +43,23 @@ use super::{
 /// If 'metadata' is given, we will validate that the downloaded file's size matches that
 /// in the metadata. (In the future, we might do more cross-checks, like CRC validation)
 ///
+/// If the layer is not found in `storage`, it is downloaded from `fallback`, if given: with a
+/// hot tier, a layer can be migrated between the tiers after the caller looked up its location.
+///
+/// If `snapshot_id` is given, the layer is downloaded from that snapshot rather than from the
+/// timeline's remote path.
+///
 /// Returns the size of the downloaded file.
 #[allow(clippy::too_many_arguments)]
 pub async fn download_layer_file<'a>(
     conf: &'static PageServerConf,
     storage: &'a GenericRemoteStorage,
+    fallback: Option<&'a GenericRemoteStorage>,
     tenant_shard_id: TenantShardId,
     timeline_id: TimelineId,
     layer_file_name: &'a LayerName,
     layer_metadata: &'a LayerFileMetadata,
+    snapshot_id: Option<&'a SnapshotId>,
     cancel: &CancellationToken,
     ctx: &RequestContext,
 ) -> Result<u64, DownloadError> {
@@ -64,13 +74,16 @@ pub async fn download_layer_file<'a>(
         &layer_metadata.generation,
     );
 
-    let remote_path = remote_layer_path(
-        &tenant_shard_id.tenant_id,
-        &timeline_id,
-        layer_metadata.shard,
-        layer_file_name,
-        layer_metadata.generation,
-    );
+    let remote_path = match snapshot_id {
+        Some(snapshot_id) => remote_snapshot_layer_path(snapshot_id, layer_file_name),
+        None => remote_layer_path(
+            &tenant_shard_id.tenant_id,
+            &timeline_id,
+            layer_metadata.shard,
+            layer_file_name,
+            layer_metadata.generation,
+        ),
+    };
 
     // Perform a rename inspired by durable_rename from file_utils.c.
     // The sequence:
@@ -84,12 +97,24 @@ pub async fn download_layer_file<'a>(
     // If pageserver crashes the temp file will be deleted on startup and re-downloaded.
     let temp_file_path = path_with_suffix_extension(&local_path, TEMP_DOWNLOAD_EXTENSION);
 
-    let bytes_amount = download_retry(
+    let description = format!("download {remote_path:?}");
+    let mut res = download_retry(
         || async { download_object(storage, &remote_path, &temp_file_path, cancel, ctx).await },
-        &format!("download {remote_path:?}"),
+        &description,
         cancel,
     )
-    .await?;
+    .await;
+    if let (Err(DownloadError::NotFound), Some(fallback)) = (&res, fallback) {
+        res = download_retry(
+            || async {
+                download_object(fallback, &remote_path, &temp_file_path, cancel, ctx).await
+            },
+            &description,
+            cancel,
+        )
+        .await;
+    }
+    let bytes_amount = res?;
 
     let expected = layer_metadata.file_size();
     if expected != bytes_amount {
@@ -337,7 +362,7 @@ async fn do_download_index_part(
 ) -> Result<(IndexPart, Generation), DownloadError> {
     let remote_path = remote_index_path(tenant_shard_id, timeline_id, index_generation);
 
-    let index_part_bytes = download_retry_forever(
+    let (index_part_bytes, metadata) = download_retry_forever(
         || async {
             let download = storage.download(&remote_path, cancel).await?;
 
@@ -348,16 +373,20 @@ async fn do_download_index_part(
 
             tokio::io::copy_buf(&mut stream, &mut bytes).await?;
 
-            Ok(bytes)
+            Ok((bytes, download.metadata))
         },
         &format!("download {remote_path:?}"),
         cancel,
     )
     .await?;
 
-    let index_part: IndexPart = serde_json::from_slice(&index_part_bytes)
-        .with_context(|| format!("deserialize index part file at {remote_path:?}"))
-        .map_err(DownloadError::Other)?;
+    let index_part = async {
+        let encoding = IndexPartEncoding::from_metadata(metadata.as_ref())?;
+        IndexPart::from_encoded_bytes(&index_part_bytes, encoding).await
+    }
+    .await
+    .with_context(|| format!("deserialize index part file at {remote_path:?}"))
+    .map_err(DownloadError::Other)?;
 
     Ok((index_part, index_generation))
 }
@@ -483,6 +512,128 @@ pub(crate) async fn download_index_part(
     }
 }
 
+/// Download the index_part.json of the most recent generation, regardless of our own generation.
+///
+/// Unlike [`download_index_part`], this is for locations which are not attached and just follow
+/// the remote state, so we always LIST the index objects instead of probing generations.
+pub(crate) async fn download_latest_index_part(
+    storage: &GenericRemoteStorage,
+    tenant_shard_id: &TenantShardId,
+    timeline_id: &TimelineId,
+    cancel: &CancellationToken,
+) -> Result<(IndexPart, Generation), DownloadError> {
+    let index_prefix = remote_index_path(tenant_shard_id, timeline_id, Generation::none());
+
+    let indices = download_retry(
+        || async {
+            storage
+                .list(Some(&index_prefix), ListingMode::NoDelimiter, None, cancel)
+                .await
+        },
+        "list index_part files",
+        cancel,
+    )
+    .await?
+    .keys;
+
+    let Some(latest_generation) = indices
+        .into_iter()
+        .filter_map(parse_remote_index_path)
+        .max()
+    else {
+        return Err(DownloadError::NotFound);
+    };
+
+    do_download_index_part(
+        storage,
+        tenant_shard_id,
+        timeline_id,
+        latest_generation,
+        cancel,
+    )
+    .await
+}
+
+/// The latest index generation of every timeline of the tenant shards whose ID starts with
+/// `tenant_id_prefix`, found with a single listing of all their objects.
+///
+/// The listing includes the layer files, so this is only cheaper than
+/// [`download_latest_index_part`] when many of the listed tenant shards are of interest: see
+/// the secondary index refresher.  Returns None if there are `max_keys` objects or more
+/// under the prefix, the caller should then list the tenant shards individually.
+pub(crate) async fn list_latest_index_generations(
+    storage: &GenericRemoteStorage,
+    tenant_id_prefix: &str,
+    max_keys: NonZeroU32,
+    cancel: &CancellationToken,
+) -> Result<Option<HashMap<TenantShardId, HashMap<TimelineId, Generation>>>, DownloadError> {
+    let prefix = RemotePath::from_string(&format!("{TENANTS_SEGMENT_NAME}/{tenant_id_prefix}"))
+        .expect("Failed to construct path");
+
+    let keys = download_retry(
+        || async {
+            storage
+                .list(
+                    Some(&prefix),
+                    ListingMode::NoDelimiter,
+                    Some(max_keys),
+                    cancel,
+                )
+                .await
+        },
+        "list tenant objects",
+        cancel,
+    )
+    .await?
+    .keys;
+    if keys.len() >= max_keys.get() as usize {
+        return Ok(None);
+    }
+
+    let mut generations = HashMap::<TenantShardId, HashMap<TimelineId, Generation>>::new();
+    for key in keys {
+        let segments = key.get_path().iter().collect::<Vec<_>>();
+        let [TENANTS_SEGMENT_NAME, tenant, TIMELINES_SEGMENT_NAME, timeline, file_name] =
+            segments[..]
+        else {
+            continue;
+        };
+        if !file_name.starts_with(IndexPart::FILE_NAME) {
+            continue;
+        }
+        let (Ok(tenant_shard_id), Ok(timeline_id)) = (
+            TenantShardId::from_str(tenant),
+            TimelineId::from_str(timeline),
+        ) else {
+            continue;
+        };
+        let Some(generation) = parse_remote_index_path(key) else {
+            continue;
+        };
+        let latest = generations
+            .entry(tenant_shard_id)
+            .or_default()
+            .entry(timeline_id)
+            .or_insert(generation);
+        *latest = (*latest).max(generation);
+    }
+    Ok(Some(generations))
+}
+
+/// Download the index_part.json of a known generation, as found by
+/// [`list_latest_index_generations`].
+pub(crate) async fn download_index_part_generation(
+    storage: &GenericRemoteStorage,
+    tenant_shard_id: &TenantShardId,
+    timeline_id: &TimelineId,
+    generation: Generation,
+    cancel: &CancellationToken,
+) -> Result<IndexPart, DownloadError> {
+    do_download_index_part(storage, tenant_shard_id, timeline_id, generation, cancel)
+        .await
+        .map(|(index_part, _)| index_part)
+}
+
 pub(crate) async fn download_initdb_tar_zst(
     conf: &'static PageServerConf,
     storage: &GenericRemoteStorage,
@@ -0,0 +1,229 @@
+//! Hot tier for recently written layers.
+//!
+//! Layers are read the most right after they have been written: by compaction, by GetPage
+//! requests for recent data and by secondary locations. With a hot tier configured, new layers
+//! are uploaded to a low-latency storage class or bucket, like S3 Express One Zone, instead of
+//! the standard remote storage, and migrated to the standard remote storage once they are older
+//! than [`HotTierConfig::max_age`].
+//!
+//! The layers that are in the hot tier, and since when, are tracked in
+//! [`IndexPart::hot_layers`](super::index::IndexPart::hot_layers). A layer is migrated by
+//! copying it to the standard remote storage, uploading an index that no longer lists it as
+//! hot, and only then deleting it from the hot tier. A download that went by an index from
+//! before a migration falls back to the other tier if the layer isn't found.
+//!
+//! Migrations run in a background task of each tenant, and the migrated layers are deleted
+//! from the hot tier through the deletion queue, which validates our generation first. Layers
+//! deleted while still in the hot tier are left there: the hot tier bucket is expected to have
+//! a lifecycle rule that expires objects well after `max_age`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use chrono::Utc;
+use once_cell::sync::OnceCell;
+use remote_storage::{GenericRemoteStorage, RemotePath, RemoteStorageConfig, TimeoutOrCancel};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use utils::backoff;
+
+use crate::metrics::REMOTE_HOT_TIER_MIGRATED_LAYERS;
+use crate::tenant::storage_layer::LayerName;
+
+use super::index::LayerFileMetadata;
+use super::{
+    remote_layer_path, RemoteTimelineClient, FAILED_REMOTE_OP_RETRIES, FAILED_UPLOAD_WARN_THRESHOLD,
+};
+
+static HOT_TIER: OnceCell<HotTier> = OnceCell::new();
+
+/// Configuration of the hot tier, see module-level comment.
+///
+/// ```toml
+/// [hot_tier]
+/// max_age = "1h"
+///
+/// [hot_tier.remote_storage]
+/// bucket_name = "pageserver-hot--use1-az4--x-s3"
+/// bucket_region = "us-east-1"
+/// ```
+///
+/// Layers that are in the hot tier can't be read without it, so the hot tier can only be
+/// removed from the configuration once all layers have been migrated out of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotTierConfig {
+    /// Where new layers are uploaded.
+    pub remote_storage: RemoteStorageConfig,
+    /// Age after which layers are migrated to the standard remote storage.
+    pub max_age: Duration,
+}
+
+impl HotTierConfig {
+    pub const DEFAULT_MAX_AGE: &'static str = "1h";
+
+    pub fn from_toml(item: &toml_edit::Item) -> anyhow::Result<Self> {
+        let table = item.as_table_like().context("hot_tier is not a table")?;
+
+        let mut remote_storage = None;
+        let mut max_age = humantime::parse_duration(Self::DEFAULT_MAX_AGE).unwrap();
+        for (key, item) in table.iter() {
+            match key {
+                "remote_storage" => {
+                    remote_storage = RemoteStorageConfig::from_toml(item)
+                        .context("parse hot_tier.remote_storage")?
+                }
+                "max_age" => {
+                    let s = item.as_str().context("hot_tier.max_age is not a string")?;
+                    max_age = humantime::parse_duration(s).context("parse hot_tier.max_age")?;
+                }
+                _ => bail!("unrecognized hot_tier option '{key}'"),
+            }
+        }
+
+        Ok(HotTierConfig {
+            remote_storage: remote_storage.context("hot_tier.remote_storage is not set")?,
+            max_age,
+        })
+    }
+}
+
+pub(crate) struct HotTier {
+    pub(crate) storage: GenericRemoteStorage,
+    pub(crate) max_age: Duration,
+}
+
+/// Initialize the hot tier. This must be called at most once, at page server startup.
+pub fn init(config: &HotTierConfig) -> anyhow::Result<()> {
+    let storage = GenericRemoteStorage::from_config(&config.remote_storage)
+        .context("create hot tier remote storage client")?;
+    if HOT_TIER
+        .set(HotTier {
+            storage,
+            max_age: config.max_age,
+        })
+        .is_err()
+    {
+        panic!("hot tier already initialized");
+    }
+    Ok(())
+}
+
+/// The hot tier, if one is configured.
+pub(crate) fn get() -> Option<&'static HotTier> {
+    HOT_TIER.get()
+}
+
+/// Copy the object at `from_path` in `from` to `to_path` in `to`, through the pageserver.
+///
+/// Unlike [`GenericRemoteStorage::copy_object`], this works across buckets and storage kinds.
+pub(super) async fn copy_layer(
+    from: &GenericRemoteStorage,
+    from_path: &RemotePath,
+    to: &GenericRemoteStorage,
+    to_path: &RemotePath,
+    size: u64,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let size = usize::try_from(size).with_context(|| format!("convert size {size} to usize"))?;
+    let download = from.download(from_path, cancel).await?;
+    to.upload(download.download_stream, size, to_path, None, cancel)
+        .await
+        .with_context(|| format!("copy layer {from_path} to {to_path}"))
+}
+
+impl RemoteTimelineClient {
+    /// Migrate the layers that have been in the hot tier for longer than its `max_age` to the
+    /// standard remote storage. Returns the number of layers migrated.
+    pub(crate) async fn migrate_hot_layers(
+        self: &Arc<Self>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<usize> {
+        let Some(hot_tier) = self.hot_tier else {
+            return Ok(0);
+        };
+        let max_age = chrono::Duration::from_std(hot_tier.max_age)?;
+        let now = Utc::now().naive_utc();
+
+        let due: Vec<(LayerName, LayerFileMetadata)> = {
+            let mut guard = self.upload_queue.lock().unwrap();
+            let upload_queue = guard.initialized_mut()?;
+            upload_queue
+                .latest_hot_layers
+                .iter()
+                .filter(|(_, hot_since)| now - **hot_since >= max_age)
+                .filter_map(|(name, _)| {
+                    let metadata = upload_queue.latest_files.get(name)?;
+                    Some((name.clone(), metadata.clone()))
+                })
+                .collect()
+        };
+        if due.is_empty() {
+            return Ok(0);
+        }
+
+        for (name, metadata) in &due {
+            let remote_path = remote_layer_path(
+                &self.tenant_shard_id.tenant_id,
+                &self.timeline_id,
+                metadata.shard,
+                name,
+                metadata.generation,
+            );
+            backoff::retry(
+                || {
+                    copy_layer(
+                        &hot_tier.storage,
+                        &remote_path,
+                        &self.storage_impl,
+                        &remote_path,
+                        metadata.file_size(),
+                        cancel,
+                    )
+                },
+                TimeoutOrCancel::caused_by_cancel,
+                FAILED_UPLOAD_WARN_THRESHOLD,
+                FAILED_REMOTE_OP_RETRIES,
+                "migrate layer out of the hot tier",
+                cancel,
+            )
+            .await
+            .ok_or_else(|| anyhow::Error::new(TimeoutOrCancel::Cancel))
+            .and_then(|x| x)
+            .with_context(|| format!("migrate layer {name} out of the hot tier"))?;
+        }
+
+        // A layer that was deleted while we copied it is no longer in `latest_hot_layers`, and
+        // its copy in the standard remote storage is left for the scrubber to find.
+        let migrated = {
+            let mut guard = self.upload_queue.lock().unwrap();
+            let upload_queue = guard.initialized_mut()?;
+            let migrated = due
+                .into_iter()
+                .filter(|(name, _)| upload_queue.latest_hot_layers.remove(name).is_some())
+                .collect::<Vec<_>>();
+            upload_queue.latest_files_changes_since_metadata_upload_scheduled +=
+                migrated.len() as u64;
+            self.schedule_index_upload(upload_queue);
+            self.launch_queued_tasks(upload_queue);
+            migrated
+        };
+        let count = migrated.len();
+
+        // Downloads go by the index, so the layers may only be deleted from the hot tier once
+        // the index that no longer lists them as hot is durable.
+        self.wait_completion().await?;
+        self.deletion_queue_client
+            .push_hot_tier_layers(
+                self.tenant_shard_id,
+                self.timeline_id,
+                self.generation,
+                migrated,
+            )
+            .await?;
+
+        info!("migrated {count} layers out of the hot tier");
+        REMOTE_HOT_TIER_MIGRATED_LAYERS.inc_by(count as u64);
+        Ok(count)
+    }
+}
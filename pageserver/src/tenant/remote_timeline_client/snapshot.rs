@@ -0,0 +1,246 @@
+//! Timelines created from snapshots.
+//!
+//! A snapshot is an immutable bundle of image layers at one LSN, prepared in the remote storage
+//! ahead of time, like a sample dataset or a template database:
+//!
+//! ```text
+//! snapshots/{snapshot_id}/snapshot.json                         the SnapshotManifest
+//! snapshots/{snapshot_id}/{layer_name}                          the image layers
+//! snapshots/{snapshot_id}/refs/{tenant_shard_id}/{timeline_id}  the references
+//! ```
+//!
+//! A timeline created from a snapshot refers to its layers in place, without copying them. They
+//! are in the index like the timeline's own layers, and
+//! [`IndexPart::snapshots`](super::index::IndexPart::snapshots) tells which snapshot to download
+//! them from. The pageserver never uploads nor deletes them: compaction and GC eventually replace
+//! them with layers of the timeline, which only unlinks them from the index.
+//!
+//! The timelines of any number of tenants can share a snapshot. Each of them holds a reference
+//! to it, recorded as a key of `IndexPart::snapshots` and as an empty object under `refs/`,
+//! which the owner of the snapshot lists before deleting it. The reference is taken before an
+//! index refers to the snapshot, and only released when the timeline is deleted, even if its
+//! index doesn't refer to any of the layers of the snapshot anymore, or when the creation of the
+//! timeline fails before its index refers to the snapshot.
+//!
+//! The layers of a snapshot are unsharded, so only the timelines of unsharded tenants can be
+//! created from snapshots.
+//!
+//! Secondary locations skip the layers of snapshots, they are downloaded on demand once the
+//! tenant is attached.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use camino::Utf8Path;
+use pageserver_api::shard::TenantShardId;
+use remote_storage::{DownloadError, GenericRemoteStorage, RemotePath, TimeoutOrCancel};
+use serde::{Deserialize, Serialize};
+use tokio_util::io::StreamReader;
+use tokio_util::sync::CancellationToken;
+use utils::backoff;
+use utils::id::TimelineId;
+use utils::lsn::Lsn;
+
+use crate::tenant::storage_layer::LayerName;
+
+use super::download::download_retry;
+use super::{FAILED_REMOTE_OP_RETRIES, FAILED_UPLOAD_WARN_THRESHOLD};
+
+const SNAPSHOTS_SEGMENT_NAME: &str = "snapshots";
+const MANIFEST_FILE_NAME: &str = "snapshot.json";
+const REFS_SEGMENT_NAME: &str = "refs";
+
+/// The name of a snapshot, made of ASCII letters, digits, `-` and `_`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SnapshotId(String);
+
+impl FromStr for SnapshotId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.len() > 64 {
+            bail!("snapshot id must be 1 to 64 characters long");
+        }
+        if !s
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("snapshot id {s:?} must only contain ASCII letters, digits, '-' and '_'");
+        }
+        Ok(SnapshotId(s.to_owned()))
+    }
+}
+
+impl fmt::Display for SnapshotId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The `snapshot.json` of a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub pg_version: u32,
+    /// The LSN of all the layers, which becomes the initial LSN of the timelines.
+    pub lsn: Lsn,
+    /// The image layers, with their size in bytes.
+    pub layers: HashMap<LayerName, u64>,
+}
+
+impl SnapshotManifest {
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        if self.layers.is_empty() {
+            bail!("snapshot has no layers");
+        }
+        if !self.lsn.is_valid() || !self.lsn.is_aligned() {
+            bail!("invalid snapshot lsn {}", self.lsn);
+        }
+        for name in self.layers.keys() {
+            match name {
+                LayerName::Image(image) if image.lsn == self.lsn => {}
+                LayerName::Image(_) => bail!("layer {name} is not at the snapshot lsn"),
+                LayerName::Delta(_) => bail!("layer {name} is not an image layer"),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn remote_snapshot_path(snapshot_id: &SnapshotId) -> RemotePath {
+    RemotePath::from_string(&format!("{SNAPSHOTS_SEGMENT_NAME}/{snapshot_id}"))
+        .expect("Failed to construct path")
+}
+
+pub(crate) fn remote_snapshot_layer_path(
+    snapshot_id: &SnapshotId,
+    layer_file_name: &LayerName,
+) -> RemotePath {
+    remote_snapshot_path(snapshot_id).join(Utf8Path::new(&layer_file_name.to_string()))
+}
+
+pub(crate) fn remote_snapshot_ref_path(
+    snapshot_id: &SnapshotId,
+    tenant_shard_id: &TenantShardId,
+    timeline_id: &TimelineId,
+) -> RemotePath {
+    remote_snapshot_path(snapshot_id).join(Utf8Path::new(&format!(
+        "{REFS_SEGMENT_NAME}/{tenant_shard_id}/{timeline_id}"
+    )))
+}
+
+/// Download the manifest of a snapshot. Fails with [`DownloadError::NotFound`] if the snapshot
+/// doesn't exist.
+pub(crate) async fn download_manifest(
+    storage: &GenericRemoteStorage,
+    snapshot_id: &SnapshotId,
+    cancel: &CancellationToken,
+) -> Result<SnapshotManifest, DownloadError> {
+    let remote_path = remote_snapshot_path(snapshot_id).join(Utf8Path::new(MANIFEST_FILE_NAME));
+    let bytes = download_retry(
+        || async {
+            let download = storage.download(&remote_path, cancel).await?;
+            let mut bytes = Vec::new();
+            let mut stream = StreamReader::new(download.download_stream);
+            tokio::io::copy_buf(&mut stream, &mut bytes).await?;
+            Ok(bytes)
+        },
+        &format!("download {remote_path:?}"),
+        cancel,
+    )
+    .await?;
+
+    serde_json::from_slice(&bytes)
+        .with_context(|| format!("deserialize snapshot manifest at {remote_path:?}"))
+        .map_err(DownloadError::Other)
+}
+
+/// Take a reference to a snapshot for a timeline. Taking it again is a no-op.
+pub(crate) async fn add_ref(
+    storage: &GenericRemoteStorage,
+    snapshot_id: &SnapshotId,
+    tenant_shard_id: &TenantShardId,
+    timeline_id: &TimelineId,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let remote_path = remote_snapshot_ref_path(snapshot_id, tenant_shard_id, timeline_id);
+    backoff::retry(
+        || async {
+            storage
+                .upload_storage_object(
+                    futures::stream::once(futures::future::ready(Ok(bytes::Bytes::new()))),
+                    0,
+                    &remote_path,
+                    cancel,
+                )
+                .await
+        },
+        TimeoutOrCancel::caused_by_cancel,
+        FAILED_UPLOAD_WARN_THRESHOLD,
+        FAILED_REMOTE_OP_RETRIES,
+        "upload snapshot reference",
+        cancel,
+    )
+    .await
+    .ok_or_else(|| anyhow::Error::new(TimeoutOrCancel::Cancel))
+    .and_then(|x| x)
+    .with_context(|| format!("take a reference to snapshot {snapshot_id}"))
+}
+
+/// Release the reference of a timeline to a snapshot, once no index of the timeline can refer to
+/// it. Releasing it again is a no-op.
+pub(crate) async fn remove_ref(
+    storage: &GenericRemoteStorage,
+    snapshot_id: &SnapshotId,
+    tenant_shard_id: &TenantShardId,
+    timeline_id: &TimelineId,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let remote_path = remote_snapshot_ref_path(snapshot_id, tenant_shard_id, timeline_id);
+    backoff::retry(
+        || async { storage.delete(&remote_path, cancel).await },
+        TimeoutOrCancel::caused_by_cancel,
+        FAILED_UPLOAD_WARN_THRESHOLD,
+        FAILED_REMOTE_OP_RETRIES,
+        "delete snapshot reference",
+        cancel,
+    )
+    .await
+    .ok_or_else(|| anyhow::Error::new(TimeoutOrCancel::Cancel))
+    .and_then(|x| x)
+    .with_context(|| format!("release the reference to snapshot {snapshot_id}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_ids() {
+        assert!(SnapshotId::from_str("sample-db_v2").is_ok());
+        assert!(SnapshotId::from_str("").is_err());
+        assert!(SnapshotId::from_str("../tenants").is_err());
+        assert!(SnapshotId::from_str(&"a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn manifest_validation() {
+        let image = "000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016B9150";
+        let delta = "000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016B59D8-00000000016B5A51";
+        let manifest = |lsn: &str, layers: &[&str]| -> SnapshotManifest {
+            serde_json::from_value(serde_json::json!({
+                "pg_version": 16,
+                "lsn": lsn,
+                "layers": layers.iter().map(|l| (l.to_string(), 8192)).collect::<HashMap<_, _>>(),
+            }))
+            .unwrap()
+        };
+
+        manifest("0/16B9150", &[image]).validate().unwrap();
+        assert!(manifest("0/16B9150", &[]).validate().is_err());
+        assert!(manifest("0/16B9158", &[image]).validate().is_err());
+        assert!(manifest("0/16B9150", &[image, delta]).validate().is_err());
+    }
+}
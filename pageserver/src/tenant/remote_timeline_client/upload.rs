@@ -12,17 +12,22 @@ use tokio_util::sync::CancellationToken;
 use utils::backoff;
 
 use super::Generation;
+use crate::config::PageServerConf;
 use crate::tenant::remote_timeline_client::{
-    index::IndexPart, remote_index_path, remote_initdb_archive_path,
-    remote_initdb_preserved_archive_path,
+    index::{IndexPart, IndexPartEncoding},
+    remote_index_path, remote_initdb_archive_path, remote_initdb_preserved_archive_path,
 };
-use remote_storage::{GenericRemoteStorage, RemotePath, TimeTravelError};
+use remote_storage::{GenericRemoteStorage, RemotePath, StorageMetadata, TimeTravelError};
 use utils::id::{TenantId, TimelineId};
 
 use tracing::info;
 
 /// Serializes and uploads the given index part data to the remote storage.
+///
+/// Index parts of at least `index_part_compression_threshold` bytes are compressed, see
+/// [`IndexPartEncoding`].
 pub(crate) async fn upload_index_part<'a>(
+    conf: &'static PageServerConf,
     storage: &'a GenericRemoteStorage,
     tenant_shard_id: &TenantShardId,
     timeline_id: &TimelineId,
@@ -37,21 +42,29 @@ pub(crate) async fn upload_index_part<'a>(
     });
     pausable_failpoint!("before-upload-index-pausable");
 
-    let index_part_bytes = index_part
-        .to_s3_bytes()
+    let (index_part_bytes, encoding) = index_part
+        .to_encoded_bytes(conf.index_part_compression_threshold)
+        .await
         .context("serialize index part file into bytes")?;
     let index_part_size = index_part_bytes.len();
     let index_part_bytes = bytes::Bytes::from(index_part_bytes);
+    let metadata = StorageMetadata::from([(IndexPartEncoding::METADATA_KEY, encoding.as_str())]);
 
     let remote_path = remote_index_path(tenant_shard_id, timeline_id, generation);
     storage
-        .upload_storage_object(
+        .upload(
             futures::stream::once(futures::future::ready(Ok(index_part_bytes))),
             index_part_size,
             &remote_path,
+            Some(metadata),
             cancel,
         )
         .await
+        .with_context(|| {
+            format!(
+                "Failed to upload data of length {index_part_size} to storage path {remote_path:?}"
+            )
+        })
         .with_context(|| format!("upload index part for '{tenant_shard_id} / {timeline_id}'"))
 }
 
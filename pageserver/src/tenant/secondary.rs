@@ -1,9 +1,13 @@
 mod downloader;
 pub mod heatmap;
 mod heatmap_uploader;
+mod index_refresher;
 mod scheduler;
 
-use std::{sync::Arc, time::SystemTime};
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 
 use crate::{
     config::PageServerConf,
@@ -16,6 +20,7 @@ use crate::{
 use self::{
     downloader::{downloader_task, SecondaryDetail},
     heatmap_uploader::heatmap_uploader_task,
+    index_refresher::index_refresher_task,
 };
 
 use super::{
@@ -102,6 +107,13 @@ pub(crate) struct SecondaryTenant {
 
     // Public state indicating overall progress of downloads relative to the last heatmap seen
     pub(crate) progress: std::sync::Mutex<models::SecondaryProgress>,
+
+    // Public state with what we last saw in the remote index of this tenant's timelines,
+    // maintained by the index refresher.
+    pub(crate) remote_index: std::sync::Mutex<models::SecondaryRemoteIndex>,
+
+    // Internal state used by the index refresher: when the next refresh is due.
+    next_index_refresh: std::sync::Mutex<Option<Instant>>,
 }
 
 impl SecondaryTenant {
@@ -126,6 +138,9 @@ impl SecondaryTenant {
             detail: std::sync::Mutex::new(SecondaryDetail::new(config.clone())),
 
             progress: std::sync::Mutex::default(),
+
+            remote_index: std::sync::Mutex::default(),
+            next_index_refresh: std::sync::Mutex::new(None),
         })
     }
 
@@ -148,6 +163,10 @@ impl SecondaryTenant {
         *(self.tenant_conf.lock().unwrap()) = config.clone();
     }
 
+    pub(crate) fn get_tenant_conf(&self) -> TenantConfOpt {
+        self.tenant_conf.lock().unwrap().clone()
+    }
+
     /// For API access: generate a LocationConfig equivalent to the one that would be used to
     /// create a Tenant in the same state.  Do not use this in hot paths: it's for relatively
     /// rare external API calls, like a reconciliation at startup.
@@ -349,6 +368,24 @@ pub fn spawn_tasks(
         },
     );
 
+    let mgr_clone = tenant_manager.clone();
+    let storage_clone = remote_storage.clone();
+    let cancel_clone = cancel.clone();
+    let bg_jobs_clone = background_jobs_can_start.clone();
+    task_mgr::spawn(
+        BACKGROUND_RUNTIME.handle(),
+        TaskKind::SecondaryIndexRefresh,
+        None,
+        None,
+        "secondary index refresh",
+        false,
+        async move {
+            index_refresher_task(mgr_clone, storage_clone, bg_jobs_clone, cancel_clone).await;
+
+            Ok(())
+        },
+    );
+
     task_mgr::spawn(
         BACKGROUND_RUNTIME.handle(),
         TaskKind::SecondaryUploads,
@@ -15,7 +15,7 @@ use crate::{
     metrics::SECONDARY_MODE,
     tenant::{
         config::SecondaryLocationConfig,
-        debug_assert_current_span_has_tenant_and_timeline_id,
+        data_dirs, debug_assert_current_span_has_tenant_and_timeline_id,
         ephemeral_file::is_ephemeral_file,
         remote_timeline_client::{
             index::LayerFileMetadata, is_temp_download_file, FAILED_DOWNLOAD_WARN_THRESHOLD,
@@ -40,7 +40,7 @@ use super::{
 
 use crate::tenant::{
     mgr::TenantManager,
-    remote_timeline_client::{download::download_layer_file, remote_heatmap_path},
+    remote_timeline_client::{download::download_layer_file, hot_tier, remote_heatmap_path},
 };
 
 use camino::Utf8PathBuf;
@@ -881,13 +881,19 @@ impl<'a> TenantDownloader<'a> {
             );
 
             // Note: no backoff::retry wrapper here because download_layer_file does its own retries internally
+            // Heatmaps don't say which layers are in the hot tier: fall back to it for layers
+            // that aren't in the standard remote storage yet.
             let downloaded_bytes = match download_layer_file(
                 self.conf,
                 self.remote_storage,
+                hot_tier::get().map(|hot_tier| &hot_tier.storage),
                 *tenant_shard_id,
                 timeline.timeline_id,
                 &layer.name,
                 &LayerFileMetadata::from(&layer.metadata),
+                // Heatmaps don't say which layers are in snapshots either: those are skipped
+                // as missing below.
+                None,
                 &self.secondary_state.cancel,
                 ctx,
             )
@@ -983,6 +989,10 @@ async fn init_timeline_state(
             if e.kind() == std::io::ErrorKind::NotFound {
                 let context = format!("Creating timeline directory {timeline_path}");
                 tracing::info!("{}", context);
+                // The tenant directory is placed in a data dir, if any are configured.
+                data_dirs::create_tenant_dir(conf, tenant_shard_id, None)
+                    .map_err(std::io::Error::other)
+                    .fatal_err(&context);
                 tokio::fs::create_dir_all(&timeline_path)
                     .await
                     .fatal_err(&context);
@@ -0,0 +1,424 @@
+//! Periodic refresh of the remote index_part of secondary tenants.
+//!
+//! A secondary location doesn't ingest WAL or upload anything, so the only way for it to learn
+//! about the state of its timelines is to read the index_part objects written by the attached
+//! location. With thousands of such passive tenants per pageserver, having each of them poll
+//! remote storage on its own timer would produce bursts of requests, so instead a single task
+//! walks all secondary tenants:
+//! - Each round picks up to `secondary_index_refresh_batch_size` tenants whose refresh is due,
+//!   most overdue first, and the other shards of their tenants.
+//! - Tenants of the batch are refreshed with at most `secondary_index_refresh_concurrency`
+//!   in flight, across the whole pageserver.
+//! - The tenants of a round are grouped by the first [`SHARED_LIST_PREFIX_LEN`] characters of
+//!   their ID. For each prefix shared by several tenants of the round, a single LIST of all the
+//!   objects under it finds the timelines of these tenants and the latest generation of their
+//!   indices, which then only cost a GET each. This LIST also returns the layer files, and the
+//!   objects of tenants not refreshed in this round, so it is given up past
+//!   [`SHARED_LIST_MAX_KEYS`] objects.
+//! - The other tenants discover their timelines with one delimited LIST of their timelines
+//!   prefix, shared by all shards of the tenant: the shards of a tenant have the same timelines.
+//!   Each timeline then costs a LIST of its index_part objects, to find the latest generation,
+//!   and a GET of that index.
+//! - Concurrent refreshes wait for a shared LIST in flight instead of issuing their own.
+//!
+//! The result is published in [`SecondaryTenant::remote_index`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures::StreamExt;
+use pageserver_api::{
+    models::{SecondaryRemoteIndex, SecondaryRemoteTimeline},
+    shard::TenantShardId,
+};
+use remote_storage::{DownloadError, GenericRemoteStorage};
+use tokio::sync::OnceCell;
+use tokio_util::sync::CancellationToken;
+use tracing::{info_span, Instrument};
+use utils::{
+    completion::Barrier,
+    id::{TenantId, TimelineId},
+    serde_system_time,
+};
+
+use crate::tenant::{
+    mgr::TenantManager,
+    remote_timeline_client::{
+        download_index_part_generation, download_latest_index_part, list_latest_index_generations,
+        list_remote_timelines,
+    },
+    Generation,
+};
+
+use super::{
+    scheduler::{period_jitter, period_warmup},
+    SecondaryTenant,
+};
+
+/// How long to sleep between rounds when no tenant is due.  Bounds the delay for tenants
+/// that become due, without walking the tenant list too often.
+const IDLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Length of the tenant ID prefixes by which the tenants of a round share their listings:
+/// each prefix covers 1/256th of the tenants in the bucket.
+const SHARED_LIST_PREFIX_LEN: usize = 2;
+
+/// Most objects listed under a shared prefix.  Past this, listing the tenants of the prefix
+/// individually is likely cheaper.
+const SHARED_LIST_MAX_KEYS: NonZeroU32 = match NonZeroU32::new(10_000) {
+    Some(max_keys) => max_keys,
+    None => unreachable!(),
+};
+
+pub(super) async fn index_refresher_task(
+    tenant_manager: Arc<TenantManager>,
+    remote_storage: GenericRemoteStorage,
+    background_jobs_can_start: Barrier,
+    cancel: CancellationToken,
+) {
+    let conf = tenant_manager.get_conf();
+    let period = conf.secondary_index_refresh_period;
+    let concurrency = conf.secondary_index_refresh_concurrency.max(1);
+    let batch_size = conf.secondary_index_refresh_batch_size.max(1);
+
+    tokio::select! {
+        _ = cancel.cancelled() => return,
+        _ = background_jobs_can_start.wait() => {}
+    }
+
+    while !cancel.is_cancelled() {
+        let mut tenants = Vec::new();
+        tenant_manager.foreach_secondary_tenants(|_id, secondary_state| {
+            tenants.push(secondary_state.clone());
+        });
+
+        let (batch, more_due) = pick_due(tenants, Instant::now(), period, batch_size);
+        if !batch.is_empty() {
+            tracing::debug!(
+                "Refreshing remote index of {} secondary tenants",
+                batch.len()
+            );
+        }
+
+        let listings = SharedListings::new(&batch, cancel.clone());
+        let next_refreshes = Mutex::new(HashMap::new());
+        futures::stream::iter(batch)
+            .for_each_concurrent(concurrency, |tenant| {
+                let remote_storage = &remote_storage;
+                let listings = &listings;
+                let next_refreshes = &next_refreshes;
+                async move {
+                    let span = info_span!("secondary_index_refresh",
+                        tenant_id=%tenant.tenant_shard_id.tenant_id,
+                        shard_id=%tenant.tenant_shard_id.shard_slug());
+                    refresh_tenant(remote_storage, listings, &tenant)
+                        .instrument(span)
+                        .await;
+
+                    // Errored tenants wait for the next period too: we don't want a tenant with
+                    // broken remote state to take priority over the others. The shards of a
+                    // tenant stay due together, to share their listings.
+                    let next = *next_refreshes
+                        .lock()
+                        .unwrap()
+                        .entry(tenant.tenant_shard_id.tenant_id)
+                        .or_insert_with(|| Instant::now() + period_jitter(period, 5));
+                    *tenant.next_index_refresh.lock().unwrap() = Some(next);
+                }
+            })
+            .await;
+
+        if !more_due {
+            tokio::select! {
+                _ = cancel.cancelled() => {}
+                _ = tokio::time::sleep(IDLE_INTERVAL) => {}
+            }
+        }
+    }
+}
+
+/// Pick the tenants whose refresh is due, most overdue first, and the other shards of their
+/// tenants.  Also returns whether more tenants than `batch_size` were due.
+fn pick_due(
+    tenants: Vec<Arc<SecondaryTenant>>,
+    now: Instant,
+    period: Duration,
+    batch_size: usize,
+) -> (Vec<Arc<SecondaryTenant>>, bool) {
+    let (mut due, not_due): (Vec<_>, Vec<_>) = tenants
+        .into_iter()
+        .map(|tenant| {
+            let next = *tenant
+                .next_index_refresh
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| {
+                    // Spread the first refreshes of tenants loaded at startup over a whole period.
+                    now + period_warmup(period)
+                });
+            (next, tenant)
+        })
+        .partition(|(next, _)| *next <= now);
+
+    due.sort_by_key(|(next, _)| *next);
+    let more_due = due.len() > batch_size;
+    due.truncate(batch_size);
+
+    // Refresh the other shards along, to share the listing of their timelines.
+    let picked = due
+        .iter()
+        .map(|(_, tenant)| tenant.tenant_shard_id.tenant_id)
+        .collect::<HashSet<_>>();
+    due.extend(
+        not_due
+            .into_iter()
+            .filter(|(_, tenant)| picked.contains(&tenant.tenant_shard_id.tenant_id)),
+    );
+
+    (
+        due.into_iter().map(|(_, tenant)| tenant).collect(),
+        more_due,
+    )
+}
+
+/// The latest index generation of each timeline, by tenant shard.
+type IndexGenerations = HashMap<TenantShardId, HashMap<TimelineId, Generation>>;
+
+/// The listings of a round, by tenant ID prefix and by tenant, see the module docs.
+struct SharedListings {
+    /// The tenant ID prefixes of more than one tenant of the round.
+    shared_prefixes: HashSet<String>,
+    generations: Mutex<HashMap<String, Arc<OnceCell<Option<IndexGenerations>>>>>,
+    timelines: Mutex<HashMap<TenantId, Arc<OnceCell<Option<HashSet<TimelineId>>>>>>,
+    /// Cancels the prefix listings, which are not owned by any single tenant.
+    cancel: CancellationToken,
+}
+
+fn tenant_id_prefix(tenant_id: &TenantId) -> String {
+    let mut prefix = tenant_id.to_string();
+    prefix.truncate(SHARED_LIST_PREFIX_LEN);
+    prefix
+}
+
+impl SharedListings {
+    fn new(batch: &[Arc<SecondaryTenant>], cancel: CancellationToken) -> Self {
+        let tenant_ids = batch
+            .iter()
+            .map(|tenant| tenant.tenant_shard_id.tenant_id)
+            .collect::<HashSet<_>>();
+        let mut tenants_by_prefix = HashMap::<String, usize>::new();
+        for tenant_id in &tenant_ids {
+            *tenants_by_prefix
+                .entry(tenant_id_prefix(tenant_id))
+                .or_default() += 1;
+        }
+        Self {
+            shared_prefixes: tenants_by_prefix
+                .into_iter()
+                .filter_map(|(prefix, tenants)| (tenants > 1).then_some(prefix))
+                .collect(),
+            generations: Mutex::default(),
+            timelines: Mutex::default(),
+            cancel,
+        }
+    }
+
+    /// The latest index generation of each remote timeline of `tenant`, from the listing of its
+    /// tenant ID prefix. None if the prefix is not shared with other tenants of the round, or if
+    /// its listing failed or was too large: the tenant is then listed on its own.
+    async fn index_generations(
+        &self,
+        remote_storage: &GenericRemoteStorage,
+        tenant: &SecondaryTenant,
+    ) -> Option<HashMap<TimelineId, Generation>> {
+        let prefix = tenant_id_prefix(&tenant.tenant_shard_id.tenant_id);
+        if !self.shared_prefixes.contains(&prefix) {
+            return None;
+        }
+        let listing = self
+            .generations
+            .lock()
+            .unwrap()
+            .entry(prefix.clone())
+            .or_default()
+            .clone();
+        let generations = listing
+            .get_or_init(|| async {
+                match list_latest_index_generations(
+                    remote_storage,
+                    &prefix,
+                    SHARED_LIST_MAX_KEYS,
+                    &self.cancel,
+                )
+                .await
+                {
+                    Ok(Some(generations)) => Some(generations),
+                    Ok(None) => {
+                        tracing::info!(
+                            %prefix,
+                            "Too many objects under tenant prefix, listing its tenants individually"
+                        );
+                        None
+                    }
+                    Err(e) => {
+                        if !self.cancel.is_cancelled() {
+                            tracing::warn!(%prefix, "Failed to list tenant prefix: {e:#}");
+                        }
+                        None
+                    }
+                }
+            })
+            .await
+            .as_ref()?;
+        Some(
+            generations
+                .get(&tenant.tenant_shard_id)
+                .cloned()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// The remote timelines of the tenant of `tenant`, listed by the first of its shards to ask.
+    /// None if that listing failed: the shards of the tenant then skip this round.
+    async fn timelines(
+        &self,
+        remote_storage: &GenericRemoteStorage,
+        tenant: &SecondaryTenant,
+    ) -> Option<HashSet<TimelineId>> {
+        let listing = self
+            .timelines
+            .lock()
+            .unwrap()
+            .entry(tenant.tenant_shard_id.tenant_id)
+            .or_default()
+            .clone();
+        listing
+            .get_or_init(|| async {
+                match list_remote_timelines(
+                    remote_storage,
+                    tenant.tenant_shard_id,
+                    tenant.cancel.clone(),
+                )
+                .await
+                {
+                    Ok((timeline_ids, _other_keys)) => Some(timeline_ids),
+                    Err(e) => {
+                        if !tenant.cancel.is_cancelled() {
+                            tracing::warn!("Failed to list remote timelines: {e:#}");
+                        }
+                        None
+                    }
+                }
+            })
+            .await
+            .clone()
+    }
+}
+
+async fn refresh_tenant(
+    remote_storage: &GenericRemoteStorage,
+    listings: &SharedListings,
+    tenant: &SecondaryTenant,
+) {
+    let Ok(_guard) = tenant.gate.enter() else {
+        return;
+    };
+
+    let timelines = match listings.index_generations(remote_storage, tenant).await {
+        Some(generations) => download_indices(remote_storage, tenant, generations).await,
+        None => download_latest_indices(remote_storage, listings, tenant).await,
+    };
+    let Some(timelines) = timelines else {
+        return;
+    };
+
+    *tenant.remote_index.lock().unwrap() = SecondaryRemoteIndex {
+        refreshed_at: Some(serde_system_time::SystemTime(SystemTime::now())),
+        timelines,
+    };
+}
+
+/// Download the indices of the timelines of `tenant` at their listed generation. None if the
+/// refresh was cancelled or failed.
+async fn download_indices(
+    remote_storage: &GenericRemoteStorage,
+    tenant: &SecondaryTenant,
+    generations: HashMap<TimelineId, Generation>,
+) -> Option<HashMap<TimelineId, SecondaryRemoteTimeline>> {
+    let mut timelines = HashMap::with_capacity(generations.len());
+    for (timeline_id, generation) in generations {
+        match download_index_part_generation(
+            remote_storage,
+            &tenant.tenant_shard_id,
+            &timeline_id,
+            generation,
+            &tenant.cancel,
+        )
+        .await
+        {
+            Ok(index_part) => {
+                timelines.insert(
+                    timeline_id,
+                    SecondaryRemoteTimeline {
+                        generation: generation.into(),
+                        disk_consistent_lsn: index_part.metadata.disk_consistent_lsn(),
+                        deleted: index_part.deleted_at.is_some(),
+                    },
+                );
+            }
+            // The timeline was deleted after our listing.
+            Err(DownloadError::NotFound) => {}
+            Err(DownloadError::Cancelled) => return None,
+            Err(e) => {
+                tracing::warn!(%timeline_id, "Failed to download remote index: {e:#}");
+                return None;
+            }
+        }
+    }
+    Some(timelines)
+}
+
+/// Download the latest index of each timeline of `tenant`, listing its timelines and then their
+/// indices. None if the refresh was cancelled or failed.
+async fn download_latest_indices(
+    remote_storage: &GenericRemoteStorage,
+    listings: &SharedListings,
+    tenant: &SecondaryTenant,
+) -> Option<HashMap<TimelineId, SecondaryRemoteTimeline>> {
+    let timeline_ids = listings.timelines(remote_storage, tenant).await?;
+
+    let mut timelines = HashMap::with_capacity(timeline_ids.len());
+    for timeline_id in timeline_ids {
+        match download_latest_index_part(
+            remote_storage,
+            &tenant.tenant_shard_id,
+            &timeline_id,
+            &tenant.cancel,
+        )
+        .await
+        {
+            Ok((index_part, generation)) => {
+                timelines.insert(
+                    timeline_id,
+                    SecondaryRemoteTimeline {
+                        generation: generation.into(),
+                        disk_consistent_lsn: index_part.metadata.disk_consistent_lsn(),
+                        deleted: index_part.deleted_at.is_some(),
+                    },
+                );
+            }
+            // The timeline was deleted after our listing, or it is still being created.
+            Err(DownloadError::NotFound) => {}
+            Err(DownloadError::Cancelled) => return None,
+            Err(e) => {
+                tracing::warn!(%timeline_id, "Failed to download remote index: {e:#}");
+                return None;
+            }
+        }
+    }
+    Some(timelines)
+}
@@ -0,0 +1,199 @@
+//! Smoke test of the read and write path of a tenant, for production monitoring.
+//!
+//! The test creates a scratch timeline, ingests two Postgres WAL records of a heap page through
+//! [`WalIngest`] into its in-memory layer, and reads the page back at the LSN of each record,
+//! which replays the records in the walredo process of the tenant. Optionally, it also
+//! round-trips the page through remote storage. Each stage is timed.
+//!
+//! The scratch timeline is never finished: it isn't in the timelines of the tenant, nor in
+//! remote storage, so it's invisible to the API, GC and compaction. Its in-memory layer is
+//! never flushed, and its directory is removed when the test ends, or purged on the next load
+//! after a crash. The remote copy of the page is deleted as soon as it is downloaded.
+//!
+//! The records were captured from Postgres 14, so the walredo process of Postgres 14 must be
+//! installed whatever the versions of the timelines of the tenant.
+
+use std::str::FromStr;
+use std::time::Instant;
+
+use anyhow::Context;
+use bytes::Bytes;
+use camino::Utf8Path;
+use pageserver_api::key::rel_block_to_key;
+use pageserver_api::models::{TenantSelftestResponse, TenantSelftestStage};
+use pageserver_api::reltag::RelTag;
+use postgres_ffi::relfile_utils::MAIN_FORKNUM;
+use postgres_ffi::v14::CheckPoint;
+use postgres_ffi::{page_get_lsn, page_is_new, BLCKSZ};
+use tokio_util::io::StreamReader;
+use utils::id::TimelineId;
+use utils::lsn::Lsn;
+
+use super::remote_timeline_client::remote_tenant_path;
+use super::Tenant;
+use crate::context::RequestContext;
+use crate::walingest::WalIngest;
+use crate::walrecord::DecodedWALRecord;
+
+/// The Postgres version that the records were captured from.
+const PG_VERSION: u32 = 14;
+
+/// The LSN of the empty timeline, before the first record.
+const BASE_LSN: Lsn = Lsn(0x16A9000);
+
+/// `pg_class` of the `postgres` database, the relation of the records.
+const REL: RelTag = RelTag {
+    spcnode: 1663,
+    dbnode: 13010,
+    relnode: 1259,
+    forknum: MAIN_FORKNUM,
+};
+
+/// A heap insert with a full page image of block 0 of [`REL`], then an in-place update of the
+/// page, as they appear in the WAL with their end LSNs.
+#[allow(clippy::octal_escapes)]
+fn records() -> [(Lsn, Bytes); 2] {
+    [
+        (
+            Lsn::from_str("0/16A9388").unwrap(),
+            Bytes::from_static(b"j\x03\0\0\0\x04\0\0\xe8\x7fj\x01\0\0\0\0\0\n\0\0\xd0\x16\x13Y\0\x10\0\04\x03\xd4\0\x05\x7f\x06\0\0\xd22\0\0\xeb\x04\0\0\0\0\0\0\xff\x03\0\0\0\0\x80\xeca\x01\0\0\x01\0\xd4\0\xa0\x1d\0 \x04 \0\0\0\0/\0\x01\0\xa0\x9dX\x01\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0.\0\x01\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\00\x9f\x9a\x01P\x9e\xb2\x01\0\x04\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x02\0!\0\x01\x08 \xff\xff\xff?\0\0\0\0\0\0@\0\0another_table\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x98\x08\0\0\x02@\0\0\0\0\0\0\n\0\0\0\x02\0\0\0\0@\0\0\0\0\0\0\0\0\0\0\0\0\x80\xbf\0\0\0\0\0\0\0\0\0\0pr\x01\0\0\0\0\0\0\0\0\x01d\0\0\0\0\0\0\x04\0\0\x01\0\0\0\0\0\0\0\x0c\x02\0\0\0\0\0\0\0\0\0\0\0\0\0\0/\0!\x80\x03+ \xff\xff\xff\x7f\0\0\0\0\0\xdf\x04\0\0pg_type\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x0b\0\0\0G\0\0\0\0\0\0\0\n\0\0\0\x02\0\0\0\0\0\0\0\0\0\0\0\x0e\0\0\0\0@\x16D\x0e\0\0\0K\x10\0\0\x01\0pr \0\0\0\0\0\0\0\0\x01n\0\0\0\0\0\xd6\x02\0\0\x01\0\0\0[\x01\0\0\0\0\0\0\0\t\x04\0\0\x02\0\0\0\x01\0\0\0\n\0\0\0\n\0\0\0\x7f\0\0\0\0\0\0\0\n\0\0\0\x02\0\0\0\0\0\0C\x01\0\0\x15\x01\0\0\0\0\0\0\0\0\0\0\0\0\0\0.\0!\x80\x03+ \xff\xff\xff\x7f\0\0\0\0\0;\n\0\0pg_statistic\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x0b\0\0\0\xfd.\0\0\0\0\0\0\n\0\0\0\x02\0\0\0;\n\0\0\0\0\0\0\x13\0\0\0\0\0\xcbC\x13\0\0\0\x18\x0b\0\0\x01\0pr\x1f\0\0\0\0\0\0\0\0\x01n\0\0\0\0\0\xd6\x02\0\0\x01\0\0\0C\x01\0\0\0\0\0\0\0\t\x04\0\0\x01\0\0\0\x01\0\0\0\n\0\0\0\n\0\0\0\x7f\0\0\0\0\0\0\x02\0\x01"),
+        ),
+        (
+            Lsn::from_str("0/16D4080").unwrap(),
+            Bytes::from_static(b"\xbc\0\0\0\0\0\0\0h?m\x01\0\0\0\0p\n\0\09\x08\xa3\xea\0 \x8c\0\x7f\x06\0\0\xd22\0\0\xeb\x04\0\0\0\0\0\0\xff\x02\0@\0\0another_table\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x98\x08\0\0\x02@\0\0\0\0\0\0\n\0\0\0\x02\0\0\0\0@\0\0\0\0\0\0\x05\0\0\0\0@zD\x05\0\0\0\0\0\0\0\0\0pr\x01\0\0\0\0\0\0\0\0\x01d\0\0\0\0\0\0\x04\0\0\x01\0\0\0\x02\0"),
+        ),
+    ]
+}
+
+struct Stages(Vec<TenantSelftestStage>);
+
+impl Stages {
+    async fn run<T>(
+        &mut self,
+        name: &str,
+        stage: impl std::future::Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let started = Instant::now();
+        let res = stage
+            .await
+            .with_context(|| format!("selftest stage {name}"));
+        self.0.push(TenantSelftestStage {
+            name: name.to_owned(),
+            duration: started.elapsed(),
+        });
+        res
+    }
+}
+
+impl Tenant {
+    /// Run the smoke test on a scratch timeline, see the module docs.
+    pub(crate) async fn selftest(
+        &self,
+        remote: bool,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<TenantSelftestResponse> {
+        let key = rel_block_to_key(REL, 0);
+        anyhow::ensure!(
+            self.shard_identity.is_key_local(&key),
+            "the test page is stored on shard {}",
+            self.shard_identity.get_shard_number(&key).0
+        );
+        let remote_storage = match (remote, &self.remote_storage) {
+            (false, _) => None,
+            (true, Some(remote_storage)) => Some(remote_storage),
+            (true, None) => anyhow::bail!("the pageserver has no remote storage"),
+        };
+
+        let timeline_id = TimelineId::generate();
+        // Dropping it removes the directory of the timeline, whatever the outcome.
+        let uninit = self
+            .create_empty_timeline(timeline_id, BASE_LSN, PG_VERSION, ctx)
+            .await?;
+        let timeline = uninit.raw_timeline()?;
+
+        let mut modification = timeline.begin_modification(BASE_LSN);
+        modification.init_empty()?;
+        // Creates the directory of the relations of the database, the relmap isn't read.
+        modification
+            .put_relmap_file(REL.spcnode, REL.dbnode, Bytes::new(), ctx)
+            .await?;
+        modification.put_checkpoint(CheckPoint::default().encode()?)?;
+        modification.commit(ctx).await?;
+
+        let mut stages = Stages(Vec::new());
+
+        stages
+            .run("ingest", async {
+                let mut walingest = WalIngest::new(timeline, BASE_LSN, ctx).await?;
+                let mut decoded = DecodedWALRecord::default();
+                for (lsn, record) in records() {
+                    let mut modification = timeline.begin_modification(lsn);
+                    walingest
+                        .ingest_record(record, lsn, &mut modification, &mut decoded, ctx)
+                        .await?;
+                    modification.commit(ctx).await?;
+                }
+                Ok(())
+            })
+            .await?;
+
+        let page = stages
+            .run("read", async {
+                let mut page = Bytes::new();
+                for (lsn, _) in records() {
+                    page = timeline.get(key, lsn, ctx).await?;
+                    anyhow::ensure!(
+                        page.len() == BLCKSZ as usize && !page_is_new(&page),
+                        "no page at {lsn}"
+                    );
+                    anyhow::ensure!(
+                        page_get_lsn(&page) == lsn,
+                        "the page at {lsn} has LSN {}",
+                        page_get_lsn(&page)
+                    );
+                }
+                Ok(page)
+            })
+            .await?;
+
+        if let Some(remote_storage) = remote_storage {
+            let remote_path = remote_tenant_path(&self.tenant_shard_id)
+                .join(Utf8Path::new(&format!("selftest/{timeline_id}")));
+
+            stages
+                .run("upload", async {
+                    let len = page.len();
+                    let stream = futures::stream::once(futures::future::ready(Ok(page.clone())));
+                    remote_storage
+                        .upload(stream, len, &remote_path, None, &self.cancel)
+                        .await
+                })
+                .await?;
+            let downloaded = stages
+                .run("download", async {
+                    let download = remote_storage.download(&remote_path, &self.cancel).await?;
+                    let mut downloaded = Vec::new();
+                    let mut stream = StreamReader::new(download.download_stream);
+                    tokio::io::copy_buf(&mut stream, &mut downloaded).await?;
+                    Ok(downloaded)
+                })
+                .await;
+            let deleted = stages
+                .run(
+                    "remote_delete",
+                    remote_storage.delete(&remote_path, &self.cancel),
+                )
+                .await;
+            anyhow::ensure!(
+                downloaded? == page[..],
+                "the downloaded page differs from the uploaded one"
+            );
+            deleted?;
+        }
+
+        Ok(TenantSelftestResponse {
+            timeline_id,
+            stages: stages.0,
+        })
+    }
+}
@@ -0,0 +1,113 @@
+//! Branches that are neither read nor written anymore, to automate their cleanup.
+//!
+//! A branch is stale when its [access log](super::timeline::access_log) has no page read nor
+//! ingest for the requested idle time. Accesses from before the access log started to track the
+//! timeline on this pageserver are unknown, so a timeline is only stale once it was tracked for
+//! the whole idle time: a tenant that was just attached or migrated has no stale branches. The
+//! idle time can't exceed the retention of the access log, `timeline_access_log_retention`.
+//!
+//! Sharded tenants are not supported: each shard only sees the reads of its own pages, and
+//! deleting a timeline on one shard would leave it on the others.
+//!
+//! Root timelines are never stale. The deletion of the stale branches skips those that have
+//! children, whether stale or not: the deletion of a stale parent is only attempted by the next
+//! call, once its stale children are gone.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use pageserver_api::models::{StaleTimeline, TimelineState};
+use tracing::info;
+
+use super::Tenant;
+
+/// The granularity of the access log.
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum StaleTimelinesError {
+    #[error("the access log of timelines is disabled, see timeline_access_log_retention")]
+    AccessLogDisabled,
+    #[error("the idle time {idle:?} exceeds the retention of the access log, {retention:?}")]
+    BeyondRetention { idle: Duration, retention: Duration },
+    #[error("stale timelines are not supported for sharded tenants")]
+    Sharded,
+}
+
+impl Tenant {
+    /// The active branches that were neither read nor written for `idle`.
+    pub(crate) fn stale_timelines(
+        &self,
+        idle: Duration,
+    ) -> Result<Vec<StaleTimeline>, StaleTimelinesError> {
+        if self.tenant_shard_id.shard_count.count() > 1 {
+            return Err(StaleTimelinesError::Sharded);
+        }
+        let retention = self.conf.timeline_access_log_retention;
+        if retention.is_zero() {
+            return Err(StaleTimelinesError::AccessLogDisabled);
+        }
+        if idle > retention {
+            return Err(StaleTimelinesError::BeyondRetention { idle, retention });
+        }
+
+        let now = SystemTime::now();
+        let Some(cutoff) = now.checked_sub(idle) else {
+            return Ok(Vec::new());
+        };
+        let accessed_since_cutoff =
+            |hour: Option<SystemTime>| hour.is_some_and(|h| h + HOUR > cutoff);
+
+        let timelines = self.list_timelines();
+        let mut stale = timelines
+            .iter()
+            .filter(|timeline| timeline.current_state() == TimelineState::Active)
+            .filter_map(|timeline| {
+                let ancestor_timeline_id = timeline.get_ancestor_timeline_id()?;
+                let activity = timeline.access_log.activity();
+                if activity.tracked_since > cutoff
+                    || accessed_since_cutoff(activity.last_read)
+                    || accessed_since_cutoff(activity.last_write)
+                {
+                    return None;
+                }
+                let has_children = timelines
+                    .iter()
+                    .any(|t| t.get_ancestor_timeline_id() == Some(timeline.timeline_id));
+                Some(StaleTimeline {
+                    timeline_id: timeline.timeline_id,
+                    ancestor_timeline_id,
+                    last_read: activity.last_read,
+                    last_write: activity.last_write,
+                    tracked_since: activity.tracked_since,
+                    has_children,
+                    deleted: false,
+                    delete_error: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        stale.sort_by_key(|timeline| timeline.timeline_id);
+        Ok(stale)
+    }
+
+    /// Start the deletion of the stale branches without children, unless `dry_run`.
+    pub(crate) async fn delete_stale_timelines(
+        self: &Arc<Self>,
+        idle: Duration,
+        dry_run: bool,
+    ) -> Result<Vec<StaleTimeline>, StaleTimelinesError> {
+        let mut stale = self.stale_timelines(idle)?;
+        for timeline in stale.iter_mut().filter(|timeline| !timeline.has_children) {
+            if dry_run {
+                timeline.deleted = true;
+                continue;
+            }
+            info!(timeline_id = %timeline.timeline_id, "deleting stale timeline, idle for {idle:?}");
+            match Arc::clone(self).delete_timeline(timeline.timeline_id).await {
+                Ok(()) => timeline.deleted = true,
+                Err(e) => timeline.delete_error = Some(e.to_string()),
+            }
+        }
+        Ok(stale)
+    }
+}
@@ -0,0 +1,53 @@
+//! Diagnosis of corrupt local state found on startup.
+//!
+//! Local storage of a tenant is mostly a cache of remote storage: the remote index_part of each
+//! timeline is the authority for its layers and metadata. When `startup_repair` is enabled, we
+//! use that to recover from local state that would otherwise make a tenant Broken on load:
+//! - An unreadable tenant config is replaced with a default one, with the mode and generation
+//!   from the re-attach response. The tenant's config overrides are lost: the control plane
+//!   is expected to set them again. This is impossible for sharded tenants, because the stripe
+//!   size is only known from the config.
+//! - Files in a timeline directory that we don't recognize are moved aside, and the timeline's
+//!   layers are loaded from the remote index as usual.
+//!
+//! Every corruption found, repaired or not, is recorded here and can be inspected through the
+//! `/v1/startup_repairs` API.
+
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use pageserver_api::{models::StartupRepair, shard::TenantShardId};
+use utils::id::TimelineId;
+
+static REPAIRS: Lazy<Mutex<Vec<StartupRepair>>> = Lazy::new(Default::default);
+
+/// Record a problem found on startup, and what was done about it: `recovered` is `None` if
+/// the tenant is left Broken.
+pub(crate) fn record(
+    tenant_shard_id: TenantShardId,
+    timeline_id: Option<TimelineId>,
+    problem: String,
+    recovered: Option<String>,
+) {
+    match &recovered {
+        Some(recovered) => tracing::warn!(
+            tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), timeline_id=?timeline_id,
+            "Repaired corrupt local state: {problem}; {recovered}"
+        ),
+        None => tracing::error!(
+            tenant_id=%tenant_shard_id.tenant_id, shard_id=%tenant_shard_id.shard_slug(), timeline_id=?timeline_id,
+            "Cannot repair corrupt local state: {problem}"
+        ),
+    }
+
+    REPAIRS.lock().unwrap().push(StartupRepair {
+        tenant_shard_id,
+        timeline_id,
+        problem,
+        recovered,
+    });
+}
+
+pub(crate) fn list() -> Vec<StartupRepair> {
+    REPAIRS.lock().unwrap().clone()
+}
@@ -0,0 +1,220 @@
+//! The transitions between the [`TenantState`]s of a tenant.
+//!
+//! ```text
+//!  Loading ───► Activating(Loading) ───┐
+//!                                      ├──► Active ──► Stopping ──► Broken
+//! Attaching ─► Activating(Attaching) ──┘
+//! ```
+//!
+//! Loading and Attaching can also turn Stopping or Broken directly: when the tenant is deleted
+//! while it loads, or when the load or attach fails. With the `testing` feature, Active can
+//! also turn Broken. [`TenantState::Activating`] is left for Active only:
+//! [`super::Tenant::set_stopping`] and [`super::Tenant::set_broken`] wait for it.
+//!
+//! Every change of the state of a [`super::Tenant`] goes through
+//! [`StateTransitions::apply`], which checks it against the table in [`check`]. An invalid
+//! transition is a bug: it panics in debug builds, like the assertions it replaces, but in
+//! release builds it leaves the state unchanged and returns an [`InvalidTransition`] for the
+//! caller to log, rather than bring down the pageserver with all its other tenants. These are
+//! counted in `pageserver_tenant_state_invalid_transitions_total`.
+//!
+//! The last [`HISTORY_LEN`] transitions are kept with their causes, and can be queried with the
+//! `/v1/tenant/:tenant_shard_id/state_transitions` API.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use pageserver_api::models::{ActivatingFrom, TenantState, TenantStateTransition};
+
+use crate::metrics::TENANT_STATE_INVALID_TRANSITIONS;
+
+/// How many transitions are kept. A tenant goes through four or five in its lifetime, unless
+/// something keeps failing.
+const HISTORY_LEN: usize = 16;
+
+/// What made the state of a tenant change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum_macros::IntoStaticStr, strum_macros::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum TransitionCause {
+    /// `Tenant::activate`, at the end of the load or attach.
+    Activate,
+    /// `Tenant::activate`, once the timelines are active.
+    Activated,
+    /// The load or attach failed or was cancelled.
+    AttachFailed,
+    /// `Tenant::set_stopping`: shutdown, detach or deletion.
+    SetStopping,
+    /// `Tenant::set_broken`: a failure during the shutdown or deletion, or the tests.
+    SetBroken,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid tenant state transition from {from} to {to}, caused by {cause}")]
+pub struct InvalidTransition {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub cause: TransitionCause,
+}
+
+/// Check that `from` may turn into `to`.
+pub(crate) fn check(
+    from: &TenantState,
+    to: &TenantState,
+    cause: TransitionCause,
+) -> Result<(), InvalidTransition> {
+    use TenantState::*;
+    let allowed = match (from, to) {
+        (Loading, Activating(ActivatingFrom::Loading)) => true,
+        (Attaching, Activating(ActivatingFrom::Attaching)) => true,
+        (Activating(_), Active) => true,
+        // The tenant is deleted while it loads, or it is shut down.
+        (Loading | Attaching | Active, Stopping { .. }) => true,
+        // The load or attach fails, or the shutdown or deletion does.
+        (Loading | Attaching | Stopping { .. }, Broken { .. }) => true,
+        // Only the tests break active tenants on purpose.
+        (Active, Broken { .. }) => cfg!(feature = "testing"),
+        _ => false,
+    };
+    if allowed {
+        Ok(())
+    } else {
+        Err(InvalidTransition {
+            from: from.into(),
+            to: to.into(),
+            cause,
+        })
+    }
+}
+
+/// The recent transitions of a [`super::Tenant`].
+#[derive(Default)]
+pub(crate) struct StateTransitions {
+    history: Mutex<VecDeque<TenantStateTransition>>,
+}
+
+impl StateTransitions {
+    /// Change `current` to `to`, if [`check`] allows it, and record the transition.
+    ///
+    /// Called from the closures passed to `watch::Sender::send_if_modified`, so that the check
+    /// and the change are atomic. On error, `current` is unchanged.
+    pub(crate) fn apply(
+        &self,
+        current: &mut TenantState,
+        to: TenantState,
+        cause: TransitionCause,
+    ) -> Result<(), InvalidTransition> {
+        if let Err(e) = check(current, &to, cause) {
+            TENANT_STATE_INVALID_TRANSITIONS.inc();
+            debug_assert!(false, "{e}");
+            return Err(e);
+        }
+
+        let from: &'static str = (&*current).into();
+        *current = to;
+
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(TenantStateTransition {
+            at: SystemTime::now(),
+            from: from.to_owned(),
+            to: <&'static str>::from(&*current).to_owned(),
+            cause: <&'static str>::from(cause).to_owned(),
+        });
+        Ok(())
+    }
+
+    /// The recorded transitions, oldest first.
+    pub(crate) fn get(&self) -> Vec<TenantStateTransition> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::completion;
+
+    use super::*;
+
+    fn stopping() -> TenantState {
+        TenantState::Stopping {
+            progress: completion::Barrier::default(),
+        }
+    }
+
+    #[test]
+    fn transition_table() {
+        use TenantState::*;
+        let broken = || TenantState::broken_from_reason("test".to_owned());
+        let cause = TransitionCause::SetStopping;
+
+        for (from, to) in [
+            (Loading, Activating(ActivatingFrom::Loading)),
+            (Attaching, Activating(ActivatingFrom::Attaching)),
+            (Activating(ActivatingFrom::Loading), Active),
+            (Attaching, stopping()),
+            (Active, stopping()),
+            (Attaching, broken()),
+            (stopping(), broken()),
+        ] {
+            check(&from, &to, cause).unwrap();
+        }
+
+        for (from, to) in [
+            (Loading, Activating(ActivatingFrom::Attaching)),
+            (Active, Activating(ActivatingFrom::Loading)),
+            (Loading, Active),
+            (Activating(ActivatingFrom::Attaching), stopping()),
+            (Activating(ActivatingFrom::Attaching), broken()),
+            (broken(), stopping()),
+            (stopping(), stopping()),
+            (broken(), Active),
+        ] {
+            let e = check(&from, &to, cause).unwrap_err();
+            assert_eq!(e.from, <&'static str>::from(&from));
+            assert_eq!(e.to, <&'static str>::from(&to));
+        }
+    }
+
+    #[test]
+    fn history_keeps_the_latest_transitions() {
+        let transitions = StateTransitions::default();
+        let mut state = TenantState::Loading;
+        transitions
+            .apply(
+                &mut state,
+                TenantState::Activating(ActivatingFrom::Loading),
+                TransitionCause::Activate,
+            )
+            .unwrap();
+        transitions
+            .apply(&mut state, TenantState::Active, TransitionCause::Activated)
+            .unwrap();
+        assert!(matches!(state, TenantState::Active));
+
+        let history = transitions.get();
+        let got: Vec<_> = history
+            .iter()
+            .map(|t| (t.from.as_str(), t.to.as_str(), t.cause.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("Loading", "Activating", "activate"),
+                ("Activating", "Active", "activated")
+            ]
+        );
+
+        for _ in 0..HISTORY_LEN {
+            let mut state = TenantState::Attaching;
+            transitions
+                .apply(&mut state, stopping(), TransitionCause::SetStopping)
+                .unwrap();
+        }
+        let history = transitions.get();
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert!(history.iter().all(|t| t.from == "Attaching"));
+    }
+}
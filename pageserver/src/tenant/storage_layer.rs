@@ -18,7 +18,8 @@ use once_cell::sync::Lazy;
 use pageserver_api::key::Key;
 use pageserver_api::keyspace::{KeySpace, KeySpaceRandomAccum};
 use pageserver_api::models::{
-    LayerAccessKind, LayerResidenceEvent, LayerResidenceEventReason, LayerResidenceStatus,
+    ExplainReadValueKind, LayerAccessKind, LayerResidenceEvent, LayerResidenceEventReason,
+    LayerResidenceStatus,
 };
 use std::borrow::Cow;
 use std::cmp::{Ordering, Reverse};
@@ -39,7 +40,7 @@ pub use inmemory_layer::InMemoryLayer;
 pub use layer_desc::{PersistentLayerDesc, PersistentLayerKey};
 pub use layer_name::{DeltaLayerName, ImageLayerName, LayerName};
 
-pub(crate) use layer::{EvictionError, Layer, ResidentLayer};
+pub(crate) use layer::{DownloadError, EvictionError, Layer, ResidentLayer};
 
 use self::inmemory_layer::InMemoryLayerFileId;
 
@@ -402,6 +403,15 @@ impl ReadableLayer {
     }
 }
 
+/// The kind of a value, for [`crate::tenant::timeline::explain_read`].
+pub(crate) fn explain_value_kind(value: &Value) -> ExplainReadValueKind {
+    match value {
+        Value::Image(_) => ExplainReadValueKind::Image,
+        Value::WalRecord(rec) if rec.will_init() => ExplainReadValueKind::InitWalRecord,
+        Value::WalRecord(_) => ExplainReadValueKind::WalRecord,
+    }
+}
+
 /// Return value from [`Layer::get_value_reconstruct_data`]
 #[derive(Clone, Copy, Debug)]
 pub enum ValueReconstructResult {
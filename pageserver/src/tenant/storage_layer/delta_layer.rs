@@ -28,13 +28,16 @@
 //! "values" part.
 //!
 use crate::config::PageServerConf;
+use crate::context::read_path::ReadPathClass;
 use crate::context::{PageContentKind, RequestContext, RequestContextBuilder};
 use crate::page_cache::{self, FileId, PAGE_SZ};
 use crate::repository::{Key, Value, KEY_SIZE};
 use crate::tenant::blob_io::BlobWriter;
 use crate::tenant::block_io::{BlockBuf, BlockCursor, BlockLease, BlockReader, FileBlockReader};
-use crate::tenant::disk_btree::{DiskBtreeBuilder, DiskBtreeReader, VisitDirection};
-use crate::tenant::storage_layer::{Layer, ValueReconstructResult, ValueReconstructState};
+use crate::tenant::disk_btree::{DiskBtreeBuilder, DiskBtreeReader, VerifyFinding, VisitDirection};
+use crate::tenant::storage_layer::{
+    explain_value_kind, Layer, ValueReconstructResult, ValueReconstructState,
+};
 use crate::tenant::timeline::GetVectoredError;
 use crate::tenant::vectored_blob_io::{
     BlobFlag, MaxVectoredReadBytes, VectoredBlobReader, VectoredRead, VectoredReadPlanner,
@@ -49,7 +52,7 @@ use camino::{Utf8Path, Utf8PathBuf};
 use futures::StreamExt;
 use itertools::Itertools;
 use pageserver_api::keyspace::KeySpace;
-use pageserver_api::models::LayerAccessKind;
+use pageserver_api::models::{ExplainReadValue, ExplainReadValueKind, LayerAccessKind};
 use pageserver_api::shard::TenantShardId;
 use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
@@ -262,6 +265,12 @@ impl DeltaLayer {
         inner.dump(ctx).await
     }
 
+    /// Check the structure of the index of the layer, see [`DiskBtreeReader::verify`].
+    pub(crate) async fn verify(&self, ctx: &RequestContext) -> Result<Vec<VerifyFinding>> {
+        let inner = self.load(LayerAccessKind::Dump, ctx).await?;
+        inner.verify_index(ctx).await
+    }
+
     fn temp_path_for(
         conf: &PageServerConf,
         tenant_shard_id: &TenantShardId,
@@ -470,11 +479,12 @@ impl DeltaLayerWriterInner {
     }
 
     ///
-    /// Finish writing the delta layer.
+    /// Finish writing the delta layer. The file is fsynced if `fsync` is set.
     ///
     async fn finish(
         self,
         key_end: Key,
+        fsync: bool,
         timeline: &Arc<Timeline>,
         ctx: &RequestContext,
     ) -> anyhow::Result<ResidentLayer> {
@@ -539,8 +549,9 @@ impl DeltaLayerWriterInner {
             metadata.len(),
         );
 
-        // fsync the file
-        file.sync_all().await?;
+        if fsync {
+            file.sync_all().await?;
+        }
 
         let layer = Layer::finish_creating(self.conf, timeline, desc, &self.path)?;
 
@@ -643,14 +654,35 @@ impl DeltaLayerWriter {
     /// Finish writing the delta layer.
     ///
     pub(crate) async fn finish(
+        self,
+        key_end: Key,
+        timeline: &Arc<Timeline>,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<ResidentLayer> {
+        self.finish_impl(key_end, true, timeline, ctx).await
+    }
+
+    /// Like [`Self::finish`], but without fsyncing the file. The caller must make the layer
+    /// durable with [`Timeline::fsync_new_layers`] before relying on it.
+    pub(crate) async fn finish_unsynced(
+        self,
+        key_end: Key,
+        timeline: &Arc<Timeline>,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<ResidentLayer> {
+        self.finish_impl(key_end, false, timeline, ctx).await
+    }
+
+    async fn finish_impl(
         mut self,
         key_end: Key,
+        fsync: bool,
         timeline: &Arc<Timeline>,
         ctx: &RequestContext,
     ) -> anyhow::Result<ResidentLayer> {
         let inner = self.inner.take().unwrap();
         let temp_path = inner.path.clone();
-        let result = inner.finish(key_end, timeline, ctx).await;
+        let result = inner.finish(key_end, fsync, timeline, ctx).await;
         // The delta layer files can sometimes be really large. Clean them up.
         if result.is_err() {
             tracing::warn!(
@@ -866,6 +898,78 @@ impl DeltaLayerInner {
         }
     }
 
+    /// The values of `key` in `lsn_range` that a read would use, newest first, without reading
+    /// them: only the value that ends the traversal, if any, is read to tell an image from a
+    /// WAL record that initializes the page.
+    pub(super) async fn explain_key(
+        &self,
+        key: Key,
+        lsn_range: Range<Lsn>,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<ExplainReadValue>> {
+        let block_reader = FileBlockReader::new(&self.file, self.file_id);
+        let tree_reader = DiskBtreeReader::<_, DELTA_KEY_SIZE>::new(
+            self.index_start_blk,
+            self.index_root_blk,
+            &block_reader,
+        );
+        let search_key = DeltaKey::from_key_lsn(&key, Lsn(lsn_range.end.0 - 1));
+
+        let mut values = Vec::new();
+        let mut init_pos = None;
+        tree_reader
+            .visit(
+                &search_key.0,
+                VisitDirection::Backwards,
+                |key, value| {
+                    let blob_ref = BlobRef(value);
+                    if key[..KEY_SIZE] != search_key.0[..KEY_SIZE] {
+                        return false;
+                    }
+                    let entry_lsn = DeltaKey::extract_lsn_from_buf(key);
+                    if entry_lsn < lsn_range.start {
+                        return false;
+                    }
+                    values.push(ExplainReadValue {
+                        lsn: entry_lsn,
+                        offset: blob_ref.pos(),
+                        kind: ExplainReadValueKind::WalRecord,
+                    });
+                    if blob_ref.will_init() {
+                        init_pos = Some(blob_ref.pos());
+                    }
+                    !blob_ref.will_init()
+                },
+                &RequestContextBuilder::extend(ctx)
+                    .page_content_kind(PageContentKind::DeltaLayerBtreeNode)
+                    .build(),
+            )
+            .await?;
+
+        if let (Some(pos), Some(last)) = (init_pos, values.last_mut()) {
+            let blob = block_reader
+                .block_cursor()
+                .read_blob(
+                    pos,
+                    &RequestContextBuilder::extend(ctx)
+                        .page_content_kind(PageContentKind::DeltaLayerValue)
+                        .build(),
+                )
+                .await
+                .with_context(|| {
+                    format!("Failed to read blob from virtual file {}", self.file.path)
+                })?;
+            let value = Value::des(&blob).with_context(|| {
+                format!(
+                    "Failed to deserialize file blob from virtual file {}",
+                    self.file.path
+                )
+            })?;
+            last.kind = explain_value_kind(&value);
+        }
+        Ok(values)
+    }
+
     // Look up the keys in the provided keyspace and update
     // the reconstruct state with whatever is found.
     //
@@ -908,6 +1012,10 @@ impl DeltaLayerInner {
         .await
         .map_err(GetVectoredError::Other)?;
 
+        if !reads.is_empty() {
+            // Vectored reads of values bypass the page cache.
+            ctx.read_path.record(ReadPathClass::LocalLayer);
+        }
         self.do_reads_and_update_state(reads, reconstruct_state)
             .await;
 
@@ -1099,6 +1207,8 @@ impl DeltaLayerInner {
         );
 
         let mut all_keys: Vec<DeltaEntry<'_>> = Vec::new();
+        // Values are stored in the order of their keys, so their offsets are increasing.
+        let mut unordered_offsets = false;
 
         tree_reader
             .visit(
@@ -1117,7 +1227,11 @@ impl DeltaLayerInner {
                         // subtract offset of the current and last entries to get the size
                         // of the value associated with this (key, lsn) tuple
                         let first_pos = last.size;
-                        last.size = pos - first_pos;
+                        let Some(size) = pos.checked_sub(first_pos) else {
+                            unordered_offsets = true;
+                            return false;
+                        };
+                        last.size = size;
                     }
                     let entry = DeltaEntry {
                         key: delta_key.key(),
@@ -1133,10 +1247,17 @@ impl DeltaLayerInner {
                     .build(),
             )
             .await?;
+        ensure!(
+            !unordered_offsets,
+            "value offsets in the index are not increasing"
+        );
         if let Some(last) = all_keys.last_mut() {
             // Last key occupies all space till end of value storage,
             // which corresponds to beginning of the index
-            last.size = self.index_start_offset() - last.size;
+            last.size = self
+                .index_start_offset()
+                .checked_sub(last.size)
+                .context("value offset beyond the start of the index")?;
         }
         Ok(all_keys)
     }
@@ -1336,6 +1457,23 @@ impl DeltaLayerInner {
         Ok(records)
     }
 
+    pub(super) async fn verify_index(
+        &self,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<VerifyFinding>> {
+        let block_reader = FileBlockReader::new(&self.file, self.file_id);
+        let tree_reader = DiskBtreeReader::<_, DELTA_KEY_SIZE>::new(
+            self.index_start_blk,
+            self.index_root_blk,
+            block_reader,
+        );
+        // The values are blob refs to the records, between the summary and the index: the
+        // offset shifted left by one bit, with the `will_init` flag in the lowest bit.
+        let values = BlobRef::new(PAGE_SZ as u64, false).0
+            ..BlobRef::new(self.index_start_blk as u64 * PAGE_SZ as u64, false).0;
+        Ok(tree_reader.verify(values, ctx).await?)
+    }
+
     pub(super) async fn dump(&self, ctx: &RequestContext) -> anyhow::Result<()> {
         println!(
             "index_start_blk: {}, root {}",
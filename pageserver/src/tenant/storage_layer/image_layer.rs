@@ -24,12 +24,13 @@
 //! mapping from Key to an offset in the "values" part.  The
 //! actual page images are stored in the "values" part.
 use crate::config::PageServerConf;
+use crate::context::read_path::ReadPathClass;
 use crate::context::{PageContentKind, RequestContext, RequestContextBuilder};
 use crate::page_cache::{self, FileId, PAGE_SZ};
 use crate::repository::{Key, Value, KEY_SIZE};
 use crate::tenant::blob_io::BlobWriter;
 use crate::tenant::block_io::{BlockBuf, BlockReader, FileBlockReader};
-use crate::tenant::disk_btree::{DiskBtreeBuilder, DiskBtreeReader, VisitDirection};
+use crate::tenant::disk_btree::{DiskBtreeBuilder, DiskBtreeReader, VerifyFinding, VisitDirection};
 use crate::tenant::storage_layer::{
     LayerAccessStats, ValueReconstructResult, ValueReconstructState,
 };
@@ -46,7 +47,7 @@ use camino::{Utf8Path, Utf8PathBuf};
 use hex;
 use itertools::Itertools;
 use pageserver_api::keyspace::KeySpace;
-use pageserver_api::models::LayerAccessKind;
+use pageserver_api::models::{ExplainReadValue, ExplainReadValueKind, LayerAccessKind};
 use pageserver_api::shard::TenantShardId;
 use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
@@ -176,6 +177,21 @@ impl std::fmt::Debug for ImageLayerInner {
 }
 
 impl ImageLayerInner {
+    pub(super) async fn verify_index(
+        &self,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<VerifyFinding>> {
+        let block_reader = FileBlockReader::new(&self.file, self.file_id);
+        let tree_reader = DiskBtreeReader::<_, KEY_SIZE>::new(
+            self.index_start_blk,
+            self.index_root_blk,
+            block_reader,
+        );
+        // The values are the offsets of the images, between the summary and the index.
+        let values = PAGE_SZ as u64..self.index_start_blk as u64 * PAGE_SZ as u64;
+        Ok(tree_reader.verify(values, ctx).await?)
+    }
+
     pub(super) async fn dump(&self, ctx: &RequestContext) -> anyhow::Result<()> {
         let block_reader = FileBlockReader::new(&self.file, self.file_id);
         let tree_reader = DiskBtreeReader::<_, KEY_SIZE>::new(
@@ -230,6 +246,12 @@ impl ImageLayer {
         Ok(())
     }
 
+    /// Check the structure of the index of the layer, see [`DiskBtreeReader::verify`].
+    pub(crate) async fn verify(&self, ctx: &RequestContext) -> Result<Vec<VerifyFinding>> {
+        let inner = self.load(LayerAccessKind::Dump, ctx).await?;
+        inner.verify_index(ctx).await
+    }
+
     fn temp_path_for(
         conf: &PageServerConf,
         timeline_id: TimelineId,
@@ -461,6 +483,36 @@ impl ImageLayerInner {
         }
     }
 
+    /// The image of `key`, if any, without reading it.
+    pub(super) async fn explain_key(
+        &self,
+        key: Key,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<ExplainReadValue>> {
+        let block_reader = FileBlockReader::new(&self.file, self.file_id);
+        let tree_reader =
+            DiskBtreeReader::new(self.index_start_blk, self.index_root_blk, &block_reader);
+
+        let mut keybuf: [u8; KEY_SIZE] = [0u8; KEY_SIZE];
+        key.write_to_byte_slice(&mut keybuf);
+        let offset = tree_reader
+            .get(
+                &keybuf,
+                &RequestContextBuilder::extend(ctx)
+                    .page_content_kind(PageContentKind::ImageLayerBtreeNode)
+                    .build(),
+            )
+            .await?;
+        Ok(offset
+            .map(|offset| ExplainReadValue {
+                lsn: self.lsn,
+                offset,
+                kind: ExplainReadValueKind::Image,
+            })
+            .into_iter()
+            .collect())
+    }
+
     // Look up the keys in the provided keyspace and update
     // the reconstruct state with whatever is found.
     pub(super) async fn get_values_reconstruct_data(
@@ -474,6 +526,10 @@ impl ImageLayerInner {
             .await
             .map_err(GetVectoredError::Other)?;
 
+        if !reads.is_empty() {
+            // Vectored reads of values bypass the page cache.
+            ctx.read_path.record(ReadPathClass::LocalLayer);
+        }
         self.do_reads_and_update_state(reads, reconstruct_state)
             .await;
 
@@ -699,10 +755,11 @@ impl ImageLayerWriterInner {
     }
 
     ///
-    /// Finish writing the image layer.
+    /// Finish writing the image layer. The file is fsynced if `fsync` is set.
     ///
     async fn finish(
         self,
+        fsync: bool,
         timeline: &Arc<Timeline>,
         ctx: &RequestContext,
     ) -> anyhow::Result<ResidentLayer> {
@@ -756,8 +813,9 @@ impl ImageLayerWriterInner {
         // reuse the same VirtualFile for reading later. That's why we don't
         // set inner.file here. The first read will have to re-open it.
 
-        // fsync the file
-        file.sync_all().await?;
+        if fsync {
+            file.sync_all().await?;
+        }
 
         // FIXME: why not carry the virtualfile here, it supports renaming?
         let layer = Layer::finish_creating(self.conf, timeline, desc, &self.path)?;
@@ -835,7 +893,21 @@ impl ImageLayerWriter {
         timeline: &Arc<Timeline>,
         ctx: &RequestContext,
     ) -> anyhow::Result<super::ResidentLayer> {
-        self.inner.take().unwrap().finish(timeline, ctx).await
+        self.inner.take().unwrap().finish(true, timeline, ctx).await
+    }
+
+    /// Like [`Self::finish`], but without fsyncing the file. The caller must make the layer
+    /// durable with [`Timeline::fsync_new_layers`] before relying on it.
+    pub(crate) async fn finish_unsynced(
+        mut self,
+        timeline: &Arc<Timeline>,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<super::ResidentLayer> {
+        self.inner
+            .take()
+            .unwrap()
+            .finish(false, timeline, ctx)
+            .await
     }
 }
 
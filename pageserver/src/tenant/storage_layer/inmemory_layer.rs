@@ -10,12 +10,13 @@ use crate::repository::{Key, Value};
 use crate::tenant::block_io::BlockReader;
 use crate::tenant::ephemeral_file::EphemeralFile;
 use crate::tenant::storage_layer::ValueReconstructResult;
+use crate::tenant::timeline::key_stats::{self, KeySpaceStatsAccum};
 use crate::tenant::timeline::GetVectoredError;
 use crate::tenant::{PageReconstructError, Timeline};
 use crate::{page_cache, walrecord};
 use anyhow::{anyhow, ensure, Result};
 use pageserver_api::keyspace::KeySpace;
-use pageserver_api::models::InMemoryLayerInfo;
+use pageserver_api::models::{ExplainReadValue, InMemoryLayerInfo};
 use pageserver_api::shard::TenantShardId;
 use std::collections::{BTreeMap, BinaryHeap, HashSet};
 use std::sync::{Arc, OnceLock};
@@ -24,7 +25,7 @@ use tracing::*;
 use utils::{bin_ser::BeSer, id::TimelineId, lsn::Lsn, vec_map::VecMap};
 // avoid binding to Write (conflicts with std::io::Write)
 // while being able to use std::fmt::Write's methods
-use crate::metrics::TIMELINE_EPHEMERAL_BYTES;
+use crate::metrics::{EPHEMERAL_STAGING_BYTES, TIMELINE_EPHEMERAL_BYTES};
 use std::cmp::Ordering;
 use std::fmt::Write;
 use std::ops::Range;
@@ -33,8 +34,8 @@ use std::sync::atomic::{AtomicU64, AtomicUsize};
 use tokio::sync::{RwLock, RwLockWriteGuard};
 
 use super::{
-    DeltaLayerWriter, ResidentLayer, ValueReconstructSituation, ValueReconstructState,
-    ValuesReconstructState,
+    explain_value_kind, DeltaLayerWriter, ResidentLayer, ValueReconstructSituation,
+    ValueReconstructState, ValuesReconstructState,
 };
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
@@ -97,12 +98,14 @@ impl std::fmt::Debug for InMemoryLayerInner {
     }
 }
 
-/// State shared by all in-memory (ephemeral) layers.  Updated infrequently during background ticks in Timeline,
-/// to minimize contention.
+/// State shared by a set of in-memory (ephemeral) layers.  Updated infrequently during background
+/// ticks in Timeline, to minimize contention.
 ///
-/// This global state is used to implement behaviors that require a global view of the system, e.g.
-/// rolling layers proactively to limit the total amount of dirty data.
-pub(crate) struct GlobalResources {
+/// The global instance [`GLOBAL_RESOURCES`] covers all layers and is used to implement behaviors
+/// that require a global view of the system, e.g. rolling layers proactively to limit the total
+/// amount of dirty data.  Each tenant shard also has its own instance, so that a tenant with many
+/// timelines can be limited before it pushes the whole pageserver over the global limit.
+pub(crate) struct EphemeralResources {
     // Limit on how high dirty_bytes may grow before we start freezing layers to reduce it.
     // Zero means unlimited.
     pub(crate) max_dirty_bytes: AtomicU64,
@@ -112,11 +115,63 @@ pub(crate) struct GlobalResources {
     dirty_layers: AtomicUsize,
 }
 
-// Per-timeline RAII struct for its contribution to [`GlobalResources`]
+impl EphemeralResources {
+    pub(crate) const fn new(max_dirty_bytes: u64) -> Self {
+        Self {
+            max_dirty_bytes: AtomicU64::new(max_dirty_bytes),
+            dirty_bytes: AtomicU64::new(0),
+            dirty_layers: AtomicUsize::new(0),
+        }
+    }
+
+    /// Replace a layer's contribution to `dirty_bytes` from `old` to `new` bytes.
+    /// Returns the updated total.
+    fn update_dirty_bytes(&self, old: u64, new: u64) -> u64 {
+        match new.cmp(&old) {
+            Ordering::Equal => self.dirty_bytes.load(AtomicOrdering::Relaxed),
+            Ordering::Greater => {
+                let delta = new - old;
+                let prev = self.dirty_bytes.fetch_add(delta, AtomicOrdering::Relaxed);
+                prev + delta
+            }
+            Ordering::Less => {
+                let delta = old - new;
+                let prev = self.dirty_bytes.fetch_sub(delta, AtomicOrdering::Relaxed);
+                prev - delta
+            }
+        }
+    }
+
+    /// Whether `dirty_bytes` is below `max_dirty_bytes`, if any.
+    pub(crate) fn has_room(&self) -> bool {
+        let max_dirty_bytes = self.max_dirty_bytes.load(AtomicOrdering::Relaxed);
+        max_dirty_bytes == 0 || self.dirty_bytes.load(AtomicOrdering::Relaxed) < max_dirty_bytes
+    }
+
+    /// The layer size limit that should be applied, if any, to bring `dirty_bytes` back
+    /// below `max_dirty_bytes`.
+    fn layer_size_limit(&self, dirty_bytes: u64) -> Option<u64> {
+        let max_dirty_bytes = self.max_dirty_bytes.load(AtomicOrdering::Relaxed);
+        if max_dirty_bytes > 0 && dirty_bytes > max_dirty_bytes {
+            // Set the layer file limit to the average layer size: this implies that all above-average
+            // sized layers will be elegible for freezing.  They will be frozen in the order they
+            // next enter publish_size.
+            let dirty_layers = self.dirty_layers.load(AtomicOrdering::Relaxed).max(1);
+            Some(dirty_bytes / dirty_layers as u64)
+        } else {
+            None
+        }
+    }
+}
+
+// Per-timeline RAII struct for its contribution to [`GLOBAL_RESOURCES`] and to the
+// [`EphemeralResources`] of its tenant, and to [`STAGING_RESOURCES`] if its file is staged.
 struct GlobalResourceUnits {
     // How many dirty bytes have I added to the global dirty_bytes: this guard object is responsible
     // for decrementing the global counter by this many bytes when dropped.
     dirty_bytes: u64,
+    tenant_resources: Arc<EphemeralResources>,
+    staged: bool,
 }
 
 impl GlobalResourceUnits {
@@ -125,36 +180,36 @@ impl GlobalResourceUnits {
     // updated when the Timeline "ticks" in the background.
     const MAX_SIZE_DRIFT: u64 = 10 * 1024 * 1024;
 
-    fn new() -> Self {
+    fn new(tenant_resources: Arc<EphemeralResources>, staged: bool) -> Self {
         GLOBAL_RESOURCES
             .dirty_layers
             .fetch_add(1, AtomicOrdering::Relaxed);
-        Self { dirty_bytes: 0 }
+        tenant_resources
+            .dirty_layers
+            .fetch_add(1, AtomicOrdering::Relaxed);
+        if staged {
+            STAGING_RESOURCES
+                .dirty_layers
+                .fetch_add(1, AtomicOrdering::Relaxed);
+        }
+        Self {
+            dirty_bytes: 0,
+            tenant_resources,
+            staged,
+        }
     }
 
     /// Do not call this frequently: all timelines will write to these same global atomics,
     /// so this is a relatively expensive operation.  Wait at least a few seconds between calls.
     ///
     /// Returns the effective layer size limit that should be applied, if any, to keep
-    /// the total number of dirty bytes below the configured maximum.
+    /// the total number of dirty bytes below the configured maximum, both globally and
+    /// for the tenant.
     fn publish_size(&mut self, size: u64) -> Option<u64> {
-        let new_global_dirty_bytes = match size.cmp(&self.dirty_bytes) {
-            Ordering::Equal => GLOBAL_RESOURCES.dirty_bytes.load(AtomicOrdering::Relaxed),
-            Ordering::Greater => {
-                let delta = size - self.dirty_bytes;
-                let old = GLOBAL_RESOURCES
-                    .dirty_bytes
-                    .fetch_add(delta, AtomicOrdering::Relaxed);
-                old + delta
-            }
-            Ordering::Less => {
-                let delta = self.dirty_bytes - size;
-                let old = GLOBAL_RESOURCES
-                    .dirty_bytes
-                    .fetch_sub(delta, AtomicOrdering::Relaxed);
-                old - delta
-            }
-        };
+        let new_global_dirty_bytes = GLOBAL_RESOURCES.update_dirty_bytes(self.dirty_bytes, size);
+        let new_tenant_dirty_bytes = self
+            .tenant_resources
+            .update_dirty_bytes(self.dirty_bytes, size);
 
         // This is a sloppy update: concurrent updates to the counter will race, and the exact
         // value of the metric might not be the exact latest value of GLOBAL_RESOURCES::dirty_bytes.
@@ -162,22 +217,25 @@ impl GlobalResourceUnits {
         // be literally the last update.
         TIMELINE_EPHEMERAL_BYTES.set(new_global_dirty_bytes);
 
-        self.dirty_bytes = size;
-
-        let max_dirty_bytes = GLOBAL_RESOURCES
-            .max_dirty_bytes
-            .load(AtomicOrdering::Relaxed);
-        if max_dirty_bytes > 0 && new_global_dirty_bytes > max_dirty_bytes {
-            // Set the layer file limit to the average layer size: this implies that all above-average
-            // sized layers will be elegible for freezing.  They will be frozen in the order they
-            // next enter publish_size.
-            Some(
-                new_global_dirty_bytes
-                    / GLOBAL_RESOURCES.dirty_layers.load(AtomicOrdering::Relaxed) as u64,
-            )
+        let staging_limit = if self.staged {
+            let new_staging_dirty_bytes =
+                STAGING_RESOURCES.update_dirty_bytes(self.dirty_bytes, size);
+            EPHEMERAL_STAGING_BYTES.set(new_staging_dirty_bytes);
+            STAGING_RESOURCES.layer_size_limit(new_staging_dirty_bytes)
         } else {
             None
-        }
+        };
+
+        self.dirty_bytes = size;
+
+        let global_limit = GLOBAL_RESOURCES.layer_size_limit(new_global_dirty_bytes);
+        let tenant_limit = self
+            .tenant_resources
+            .layer_size_limit(new_tenant_dirty_bytes);
+        [global_limit, tenant_limit, staging_limit]
+            .into_iter()
+            .flatten()
+            .min()
     }
 
     // Call publish_size if the input size differs from last published size by more than
@@ -200,17 +258,25 @@ impl Drop for GlobalResourceUnits {
         GLOBAL_RESOURCES
             .dirty_layers
             .fetch_sub(1, AtomicOrdering::Relaxed);
+        self.tenant_resources
+            .dirty_layers
+            .fetch_sub(1, AtomicOrdering::Relaxed);
+        if self.staged {
+            STAGING_RESOURCES
+                .dirty_layers
+                .fetch_sub(1, AtomicOrdering::Relaxed);
+        }
 
         // Subtract our contribution to the global total dirty bytes
         self.publish_size(0);
     }
 }
 
-pub(crate) static GLOBAL_RESOURCES: GlobalResources = GlobalResources {
-    max_dirty_bytes: AtomicU64::new(0),
-    dirty_bytes: AtomicU64::new(0),
-    dirty_layers: AtomicUsize::new(0),
-};
+pub(crate) static GLOBAL_RESOURCES: EphemeralResources = EphemeralResources::new(0);
+
+/// Covers the layers whose ephemeral file is in the staging area, `ephemeral_dir`, up to
+/// `ephemeral_dir_max_bytes`. See [`crate::tenant::ephemeral_file`].
+pub(crate) static STAGING_RESOURCES: EphemeralResources = EphemeralResources::new(0);
 
 impl InMemoryLayer {
     pub(crate) fn file_id(&self) -> InMemoryLayerFileId {
@@ -243,6 +309,19 @@ impl InMemoryLayer {
         self.end_lsn.get().copied().unwrap_or(Lsn::MAX)
     }
 
+    /// Whether the layer has a value of a key in `key_range`, at an LSN in `lsn_range`.
+    pub(crate) async fn has_values_in(
+        &self,
+        key_range: &Range<Key>,
+        lsn_range: &Range<Lsn>,
+    ) -> bool {
+        let inner = self.inner.read().await;
+        inner
+            .index
+            .range(key_range.start..key_range.end)
+            .any(|(_, vec_map)| !vec_map.slice_range(lsn_range.clone()).is_empty())
+    }
+
     pub(crate) fn get_lsn_range(&self) -> Range<Lsn> {
         self.start_lsn..self.end_lsn_or_max()
     }
@@ -356,6 +435,38 @@ impl InMemoryLayer {
         }
     }
 
+    /// The values of `key` in `lsn_range` that a read would use, newest first.
+    pub(crate) async fn explain_key(
+        &self,
+        key: Key,
+        lsn_range: Range<Lsn>,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<ExplainReadValue>> {
+        let ctx = RequestContextBuilder::extend(ctx)
+            .page_content_kind(PageContentKind::InMemoryLayer)
+            .build();
+
+        let inner = self.inner.read().await;
+        let reader = inner.file.block_cursor();
+
+        let mut values = Vec::new();
+        if let Some(vec_map) = inner.index.get(&key) {
+            for (entry_lsn, pos) in vec_map.slice_range(lsn_range).iter().rev() {
+                let buf = reader.read_blob(*pos, &ctx).await?;
+                let kind = explain_value_kind(&Value::des(&buf)?);
+                values.push(ExplainReadValue {
+                    lsn: *entry_lsn,
+                    offset: *pos,
+                    kind,
+                });
+                if kind.ends_traversal() {
+                    break;
+                }
+            }
+        }
+        Ok(values)
+    }
+
     // Look up the keys in the provided keyspace and update
     // the reconstruct state with whatever is found.
     //
@@ -473,11 +584,13 @@ impl InMemoryLayer {
         timeline_id: TimelineId,
         tenant_shard_id: TenantShardId,
         start_lsn: Lsn,
+        tenant_resources: Arc<EphemeralResources>,
     ) -> Result<InMemoryLayer> {
         trace!("initializing new empty InMemoryLayer for writing on timeline {timeline_id} at {start_lsn}");
 
         let file = EphemeralFile::create(conf, tenant_shard_id, timeline_id).await?;
         let key = InMemoryLayerFileId(file.page_cache_file_id());
+        let staged = file.is_staged();
 
         Ok(InMemoryLayer {
             file_id: key,
@@ -496,7 +609,7 @@ impl InMemoryLayer {
             inner: RwLock::new(InMemoryLayerInner {
                 index: BTreeMap::new(),
                 file,
-                resource_units: GlobalResourceUnits::new(),
+                resource_units: GlobalResourceUnits::new(tenant_resources, staged),
             }),
         })
     }
@@ -652,11 +765,13 @@ impl InMemoryLayer {
         let ctx = RequestContextBuilder::extend(ctx)
             .page_content_kind(PageContentKind::InMemoryLayer)
             .build();
+        let mut key_stats = KeySpaceStatsAccum::default();
         for (key, vec_map) in inner.index.iter() {
             // Write all page versions
             for (lsn, pos) in vec_map.as_slice() {
                 cursor.read_blob_into_buf(*pos, &mut buf, &ctx).await?;
                 let will_init = Value::des(&buf)?.will_init();
+                key_stats.add(key, *lsn, buf.len() as u64);
                 let res;
                 (buf, res) = delta_layer_writer
                     .put_value_bytes(*key, *lsn, buf, will_init, &ctx)
@@ -665,8 +780,14 @@ impl InMemoryLayer {
             }
         }
 
-        // MAX is used here because we identify L0 layers by full key range
-        let delta_layer = delta_layer_writer.finish(Key::MAX, timeline, &ctx).await?;
+        // MAX is used here because we identify L0 layers by full key range.
+        // The caller fsyncs the layer.
+        let delta_layer = delta_layer_writer
+            .finish_unsynced(Key::MAX, timeline, &ctx)
+            .await?;
+        timeline
+            .key_space_stats
+            .record(key_stats::Source::Flush, key_stats);
         Ok(Some(delta_layer))
     }
 }
@@ -2,7 +2,8 @@ use anyhow::Context;
 use camino::{Utf8Path, Utf8PathBuf};
 use pageserver_api::keyspace::KeySpace;
 use pageserver_api::models::{
-    HistoricLayerInfo, LayerAccessKind, LayerResidenceEventReason, LayerResidenceStatus,
+    ExplainReadValue, HistoricLayerInfo, LayerAccessKind, LayerResidenceEventReason,
+    LayerResidenceStatus,
 };
 use pageserver_api::shard::{ShardIndex, TenantShardId};
 use std::ops::Range;
@@ -15,10 +16,12 @@ use utils::lsn::Lsn;
 use utils::sync::heavier_once_cell;
 
 use crate::config::PageServerConf;
-use crate::context::{DownloadBehavior, RequestContext};
+use crate::context::read_path::ReadPathClass;
+use crate::context::{DownloadBehavior, ReadStage, RequestContext};
 use crate::repository::Key;
 use crate::span::debug_assert_current_span_has_tenant_and_timeline_id;
 use crate::task_mgr::TaskKind;
+use crate::tenant::timeline::layer_manifest::{ManifestOp, ManifestReason};
 use crate::tenant::timeline::GetVectoredError;
 use crate::tenant::{remote_timeline_client::LayerFileMetadata, Timeline};
 
@@ -305,6 +308,19 @@ impl Layer {
         self.0.evict_and_wait(timeout).await
     }
 
+    /// Whether the layer was downloaded again soon after its last eviction, and should stay
+    /// resident for a while, see [`PageServerConf::eviction_bounce_window`].
+    ///
+    /// Only the eviction tasks consider this: explicit evictions always go ahead.
+    pub(crate) fn is_bouncing(&self) -> bool {
+        self.0
+            .bounces
+            .lock()
+            .unwrap()
+            .exempt_until
+            .is_some_and(|until| until > std::time::Instant::now())
+    }
+
     /// Delete the layer file when the `self` gets dropped, also try to schedule a remote index upload
     /// then.
     ///
@@ -337,7 +353,7 @@ impl Layer {
     ) -> anyhow::Result<ValueReconstructResult> {
         use anyhow::ensure;
 
-        let layer = self.0.get_or_maybe_download(true, Some(ctx)).await?;
+        let layer = self.get_or_download_for_read(ctx).await?;
         self.0
             .access_stats
             .record_access(LayerAccessKind::GetValueReconstructData, ctx);
@@ -358,6 +374,22 @@ impl Layer {
             .with_context(|| format!("get_value_reconstruct_data for layer {self}"))
     }
 
+    /// [`LayerInner::get_or_maybe_download`] for a read, which stops waiting for an on-demand
+    /// download when the request is cancelled. The download itself continues, for the other
+    /// readers of the layer.
+    async fn get_or_download_for_read(
+        &self,
+        ctx: &RequestContext,
+    ) -> Result<Arc<DownloadedLayer>, DownloadError> {
+        tokio::select! {
+            res = self.0.get_or_maybe_download(true, Some(ctx)) => res,
+            _ = ctx.cancelled() => {
+                ctx.record_given_up(ReadStage::Download);
+                Err(DownloadError::RequestCancelled)
+            }
+        }
+    }
+
     pub(crate) async fn get_values_reconstruct_data(
         &self,
         keyspace: KeySpace,
@@ -366,10 +398,12 @@ impl Layer {
         ctx: &RequestContext,
     ) -> Result<(), GetVectoredError> {
         let layer = self
-            .0
-            .get_or_maybe_download(true, Some(ctx))
+            .get_or_download_for_read(ctx)
             .await
-            .map_err(|err| GetVectoredError::Other(anyhow::anyhow!(err)))?;
+            .map_err(|err| match err {
+                DownloadError::RequestCancelled => GetVectoredError::RequestCancelled,
+                err => GetVectoredError::Other(anyhow::anyhow!(err)),
+            })?;
 
         self.0
             .access_stats
@@ -399,7 +433,6 @@ impl Layer {
     /// while the guard exists.
     ///
     /// Returns None if the layer is currently evicted or becoming evicted.
-    #[cfg(test)]
     pub(crate) async fn keep_resident(&self) -> Option<ResidentLayer> {
         let downloaded = self.0.inner.get().and_then(|rowe| rowe.get())?;
 
@@ -440,6 +473,15 @@ impl Layer {
         &self.0.access_stats
     }
 
+    /// Time since the layer was last evicted, if it was evicted since it was loaded.
+    pub(crate) fn since_last_eviction(&self) -> Option<Duration> {
+        self.0
+            .last_evicted_at
+            .lock()
+            .unwrap()
+            .map(|evicted_at| evicted_at.elapsed())
+    }
+
     pub(crate) fn local_path(&self) -> &Utf8Path {
         &self.0.path
     }
@@ -569,6 +611,17 @@ impl ResidentOrWantedEvicted {
     }
 }
 
+/// Upper bound of the multiplier of `eviction_bounce_cooldown` for the bounces in a row.
+const MAX_BOUNCE_COOLDOWN_FACTOR: u32 = 4;
+
+#[derive(Default)]
+struct Bounces {
+    /// Downloads in a row that came within `eviction_bounce_window` of the eviction.
+    in_a_row: u32,
+    /// Until when the eviction tasks leave the layer resident.
+    exempt_until: Option<std::time::Instant>,
+}
+
 struct LayerInner {
     /// Only needed to check ondemand_download_behavior_treat_error_as_warn and creation of
     /// [`Self::path`].
@@ -645,9 +698,13 @@ struct LayerInner {
 
     /// When the Layer was last evicted but has not been downloaded since.
     ///
-    /// This is used solely for updating metrics. See [`LayerImplMetrics::redownload_after`].
+    /// This is used for updating metrics, see [`LayerImplMetrics::redownload_after`], and to
+    /// detect bounces.
     last_evicted_at: std::sync::Mutex<Option<std::time::Instant>>,
 
+    /// The downloads that came soon after an eviction, see [`Layer::is_bouncing`].
+    bounces: std::sync::Mutex<Bounces>,
+
     #[cfg(test)]
     failpoints: std::sync::Mutex<Vec<failpoints::Failpoint>>,
 }
@@ -799,6 +856,7 @@ impl LayerInner {
             generation,
             shard,
             last_evicted_at: std::sync::Mutex::default(),
+            bounces: std::sync::Mutex::default(),
             #[cfg(test)]
             failpoints: Default::default(),
         }
@@ -989,6 +1047,10 @@ impl LayerInner {
             return Err(DownloadError::DownloadRequired);
         }
 
+        if let Some(ctx) = ctx {
+            ctx.read_path.record(ReadPathClass::RemoteDownload);
+        }
+
         let download_ctx = ctx
             .map(|ctx| ctx.detached_child(TaskKind::LayerDownload, DownloadBehavior::Download))
             .unwrap_or(RequestContext::new(
@@ -1148,6 +1210,14 @@ impl LayerInner {
                 }
 
                 tracing::info!(size=%self.desc.file_size, "on-demand download successful");
+                timeline
+                    .layer_manifest
+                    .record(
+                        ManifestOp::Download,
+                        ManifestReason::OnDemandDownload,
+                        [self.desc.layer_name()],
+                    )
+                    .await;
                 timeline
                     .metrics
                     .resident_physical_size_add(self.desc.file_size);
@@ -1161,6 +1231,7 @@ impl LayerInner {
                     .map(|ts| ts.elapsed());
                 if let Some(since_last_eviction) = since_last_eviction {
                     LAYER_IMPL_METRICS.record_redownloaded_after(since_last_eviction);
+                    self.record_redownload(since_last_eviction);
                 }
 
                 self.access_stats.record_residence_event(
@@ -1496,6 +1567,11 @@ impl LayerInner {
                         tracing::info!("evicted layer after unknown residence period");
                     }
                 }
+                timeline.layer_manifest.record_blocking(
+                    ManifestOp::Evict,
+                    ManifestReason::Eviction,
+                    [self.desc.layer_name()],
+                );
                 timeline.metrics.evictions.inc();
                 timeline
                     .metrics
@@ -1531,6 +1607,24 @@ impl LayerInner {
         LayerFileMetadata::new(self.desc.file_size, self.generation, self.shard)
     }
 
+    /// A download within `eviction_bounce_window` of the eviction is a bounce: the layer gets
+    /// exempt from the eviction tasks for longer with each bounce in a row.
+    fn record_redownload(&self, since_last_eviction: Duration) {
+        let mut bounces = self.bounces.lock().unwrap();
+        if since_last_eviction >= self.conf.eviction_bounce_window {
+            *bounces = Bounces::default();
+            return;
+        }
+        bounces.in_a_row = (bounces.in_a_row + 1).min(MAX_BOUNCE_COOLDOWN_FACTOR);
+        let cooldown = self.conf.eviction_bounce_cooldown * bounces.in_a_row;
+        bounces.exempt_until = Some(std::time::Instant::now() + cooldown);
+        tracing::info!(
+            ?since_last_eviction,
+            ?cooldown,
+            "layer downloaded again soon after its eviction, keeping it resident"
+        );
+    }
+
     /// Needed to use entered runtime in tests, but otherwise use BACKGROUND_RUNTIME.
     ///
     /// Synchronizing with spawned tasks is very complicated otherwise.
@@ -1595,6 +1689,10 @@ pub(crate) enum DownloadError {
     DownloadFailed,
     #[error("downloading failed, possibly for shutdown")]
     DownloadCancelled,
+    /// The download continues, but the request waiting for it was cancelled or is past its
+    /// deadline.
+    #[error("request cancelled or past its deadline while waiting for the download")]
+    RequestCancelled,
     #[error("pre-condition: stat before download failed")]
     PreStatFailed(#[source] std::io::Error),
 
@@ -1846,6 +1944,23 @@ impl ResidentLayer {
         }
     }
 
+    /// The values of `key` in `lsn_range` that a read would use, see
+    /// [`crate::tenant::timeline::explain_read`].
+    pub(crate) async fn explain_key(
+        &self,
+        key: Key,
+        lsn_range: Range<Lsn>,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<ExplainReadValue>> {
+        use LayerKind::*;
+
+        match self.downloaded.get(&self.owner.0, ctx).await? {
+            Delta(ref d) => d.explain_key(key, lsn_range, ctx).await,
+            Image(ref i) => i.explain_key(key, ctx).await,
+        }
+        .with_context(|| format!("explain_key for layer {self}"))
+    }
+
     /// Returns the amount of keys and values written to the writer.
     pub(crate) async fn copy_delta_prefix(
         &self,
@@ -818,13 +818,45 @@ async fn eviction_cancellation_on_drop() {
     }
 }
 
+/// A layer that is downloaded again right after its eviction is left alone by the eviction tasks,
+/// but can still be evicted explicitly.
+#[tokio::test]
+async fn redownload_soon_after_eviction_is_bouncing() {
+    let h = TenantHarness::create("redownload_soon_after_eviction_is_bouncing").unwrap();
+    let (tenant, ctx) = h.load().await;
+
+    let timeline = tenant
+        .create_test_timeline(TimelineId::generate(), Lsn(0x10), 14, &ctx)
+        .await
+        .unwrap();
+
+    let layer = {
+        let mut layers = {
+            let layers = timeline.layers.read().await;
+            layers.likely_resident_layers().collect::<Vec<_>>()
+        };
+        assert_eq!(layers.len(), 1);
+        layers.swap_remove(0)
+    };
+    assert!(!layer.is_bouncing());
+
+    layer.evict_and_wait(FOREVER).await.unwrap();
+    assert!(!layer.is_bouncing());
+
+    drop(layer.download_and_keep_resident().await.unwrap());
+    assert!(layer.is_bouncing());
+
+    layer.evict_and_wait(FOREVER).await.unwrap();
+    assert!(layer.is_bouncing());
+}
+
 /// A test case to remind you the cost of these structures. You can bump the size limit
 /// below if it is really necessary to add more fields to the structures.
 #[test]
 fn layer_size() {
     assert_eq!(std::mem::size_of::<LayerAccessStats>(), 2040);
     assert_eq!(std::mem::size_of::<PersistentLayerDesc>(), 104);
-    assert_eq!(std::mem::size_of::<LayerInner>(), 2344);
+    assert_eq!(std::mem::size_of::<LayerInner>(), 2376);
     // it also has the utf8 path
 }
 
@@ -0,0 +1,212 @@
+//! Tenant tags: free-form labels in the tenant config, like the team, environment or tier
+//! of the customer.
+//!
+//! The tags whose keys are listed in the `tenant_tag_labels` pageserver config are
+//! propagated: they are recorded in the `tags` field of the tenant-scoped spans of the page
+//! service and the background loops, and slice a few key metrics so that dashboards can be
+//! split by tier. The other tags are only stored in the tenant config.
+//!
+//! To keep the cardinality of the metrics bounded by the allowlist rather than by the number
+//! of tenants, the tagged metrics have a `tag` and a `value` label and no tenant label, and
+//! only the first [`MAX_VALUES_PER_TAG`] values seen of each key get their own series: the
+//! later ones are counted as [`OVERFLOW_VALUE`].
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use metrics::IntCounter;
+use once_cell::sync::Lazy;
+
+use crate::config::PageServerConf;
+use crate::metrics::{TAGGED_GETPAGE_REQUESTS, TAGGED_WAL_BYTES_RECEIVED};
+
+use super::config::TenantConfOpt;
+
+/// Number of distinct values of a tag key that get their own metric series.
+pub(crate) const MAX_VALUES_PER_TAG: usize = 64;
+
+/// The metric label value of the values past [`MAX_VALUES_PER_TAG`].
+pub(crate) const OVERFLOW_VALUE: &str = "_other";
+
+const MAX_TAG_LEN: usize = 63;
+
+/// Tag keys are used as metric label values and span field contents, so they are restricted
+/// to what is safe for both.
+pub(crate) fn validate_tag_key(key: &str) -> anyhow::Result<()> {
+    if key.is_empty() || key.len() > MAX_TAG_LEN {
+        bail!("tag key {key:?} must be between 1 and {MAX_TAG_LEN} characters long");
+    }
+    if !key
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+    {
+        bail!("tag key {key:?} may only contain lowercase letters, digits and '_'");
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_tag(key: &str, value: &str) -> anyhow::Result<()> {
+    validate_tag_key(key)?;
+    if value.len() > MAX_TAG_LEN {
+        bail!("value of tag {key:?} must be at most {MAX_TAG_LEN} characters long");
+    }
+    if !value
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"-_.:/".contains(&b))
+    {
+        bail!("value of tag {key:?} may only contain letters, digits and '-', '_', '.', ':', '/'");
+    }
+    Ok(())
+}
+
+/// The tags of a tenant that are allowed by the `tenant_tag_labels` pageserver config, in
+/// the order of the config.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct TagLabels(Vec<(String, String)>);
+
+impl TagLabels {
+    pub(crate) fn select(conf: &PageServerConf, tenant_conf: &TenantConfOpt) -> Self {
+        let Some(tags) = &tenant_conf.tags else {
+            return Self::default();
+        };
+        Self(
+            conf.tenant_tag_labels
+                .iter()
+                .filter_map(|key| tags.get_key_value(key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The value of the `tags` span field, which is left out if no tag is propagated.
+    pub(crate) fn as_field(&self) -> Option<tracing::field::DisplayValue<&Self>> {
+        (!self.is_empty()).then(|| tracing::field::display(self))
+    }
+}
+
+/// Formats as `key=value` pairs separated by commas, for span fields.
+impl fmt::Display for TagLabels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The values of each tag key that have their own metric series.
+static TRACKED_VALUES: Lazy<Mutex<HashMap<String, HashSet<String>>>> = Lazy::new(Default::default);
+
+/// The metric label value for `value` of tag `key`, see module-level comment.
+fn bounded_value<'a>(key: &str, value: &'a str) -> &'a str {
+    let mut tracked = TRACKED_VALUES.lock().unwrap();
+    let values = tracked.entry(key.to_owned()).or_default();
+    if values.contains(value) {
+        return value;
+    }
+    if values.len() >= MAX_VALUES_PER_TAG {
+        return OVERFLOW_VALUE;
+    }
+    values.insert(value.to_owned());
+    value
+}
+
+/// Key metrics of a timeline, counted once per propagated tag of its tenant.
+#[derive(Default)]
+pub(crate) struct TaggedMetrics {
+    getpage_requests: Vec<IntCounter>,
+    wal_bytes_received: Vec<IntCounter>,
+}
+
+impl TaggedMetrics {
+    pub(crate) fn new(tags: &TagLabels) -> Self {
+        let mut metrics = Self::default();
+        for (key, value) in &tags.0 {
+            let labels = [key.as_str(), bounded_value(key, value)];
+            metrics
+                .getpage_requests
+                .push(TAGGED_GETPAGE_REQUESTS.with_label_values(&labels));
+            metrics
+                .wal_bytes_received
+                .push(TAGGED_WAL_BYTES_RECEIVED.with_label_values(&labels));
+        }
+        metrics
+    }
+
+    pub(crate) fn inc_getpage_requests(&self) {
+        for counter in &self.getpage_requests {
+            counter.inc();
+        }
+    }
+
+    pub(crate) fn inc_wal_bytes_received(&self, bytes: u64) {
+        for counter in &self.wal_bytes_received {
+            counter.inc_by(bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn tag_validation() {
+        validate_tag("tier", "enterprise").unwrap();
+        validate_tag("team", "storage.eu-west-1").unwrap();
+        validate_tag("environment", "").unwrap();
+        for (key, value) in [
+            ("", "a"),
+            ("Tier", "a"),
+            ("tier-1", "a"),
+            ("tier", "a b"),
+            ("tier", "a\"b"),
+        ] {
+            assert!(
+                validate_tag(key, value).is_err(),
+                "accepted {key:?}={value:?}"
+            );
+        }
+        assert!(validate_tag("tier", &"a".repeat(MAX_TAG_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn select_allowlisted_tags() {
+        let mut conf =
+            PageServerConf::dummy_conf(PageServerConf::test_repo_dir("select_allowlisted_tags"));
+        conf.tenant_tag_labels = vec!["tier".to_string(), "team".to_string()];
+        let tenant_conf = TenantConfOpt {
+            tags: Some(BTreeMap::from([
+                ("team".to_string(), "storage".to_string()),
+                ("owner".to_string(), "alice".to_string()),
+                ("tier".to_string(), "free".to_string()),
+            ])),
+            ..Default::default()
+        };
+        let tags = TagLabels::select(&conf, &tenant_conf);
+        assert_eq!(tags.to_string(), "tier=free,team=storage");
+        assert!(TagLabels::select(&conf, &TenantConfOpt::default()).is_empty());
+    }
+
+    #[test]
+    fn bounded_tag_values() {
+        let key = "bounded_tag_values";
+        for i in 0..MAX_VALUES_PER_TAG {
+            let value = format!("v{i}");
+            assert_eq!(bounded_value(key, &value), value);
+        }
+        assert_eq!(bounded_value(key, "one_too_many"), OVERFLOW_VALUE);
+        assert_eq!(bounded_value(key, "v0"), "v0");
+    }
+}
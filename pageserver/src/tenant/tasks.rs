@@ -11,6 +11,7 @@ use crate::metrics::TENANT_TASK_EVENTS;
 use crate::task_mgr;
 use crate::task_mgr::{TaskKind, BACKGROUND_RUNTIME};
 use crate::tenant::config::defaults::DEFAULT_COMPACTION_PERIOD;
+use crate::tenant::remote_timeline_client::hot_tier;
 use crate::tenant::throttle::Stats;
 use crate::tenant::timeline::CompactionError;
 use crate::tenant::{Tenant, TenantState};
@@ -48,6 +49,7 @@ pub(crate) enum BackgroundLoopKind {
     Gc,
     Eviction,
     IngestHouseKeeping,
+    HotTierMigration,
     ConsumptionMetricsCollectMetrics,
     ConsumptionMetricsSyntheticSizeWorker,
     InitialLogicalSizeCalculation,
@@ -105,9 +107,10 @@ pub fn start_background_loops(
                     _ = cancel.cancelled() => { return Ok(()) },
                     _ = completion::Barrier::maybe_wait(background_jobs_can_start) => {}
                 };
+                let tags = tenant.tag_labels();
                 compaction_loop(tenant, cancel)
                     // If you rename this span, change the RUST_LOG env variable in test_runner/performance/test_branch_creation.py
-                    .instrument(info_span!("compaction_loop", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug()))
+                    .instrument(info_span!("compaction_loop", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), tags = tags.as_field()))
                     .await;
                 Ok(())
             }
@@ -129,8 +132,9 @@ pub fn start_background_loops(
                     _ = cancel.cancelled() => { return Ok(()) },
                     _ = completion::Barrier::maybe_wait(background_jobs_can_start) => {}
                 };
+                let tags = tenant.tag_labels();
                 gc_loop(tenant, cancel)
-                    .instrument(info_span!("gc_loop", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug()))
+                    .instrument(info_span!("gc_loop", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), tags = tags.as_field()))
                     .await;
                 Ok(())
             }
@@ -153,13 +157,41 @@ pub fn start_background_loops(
                     _ = cancel.cancelled() => { return Ok(()) },
                     _ = completion::Barrier::maybe_wait(background_jobs_can_start) => {}
                 };
+                let tags = tenant.tag_labels();
                 ingest_housekeeping_loop(tenant, cancel)
-                    .instrument(info_span!("ingest_housekeeping_loop", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug()))
+                    .instrument(info_span!("ingest_housekeeping_loop", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), tags = tags.as_field()))
                     .await;
                 Ok(())
             }
         },
     );
+
+    if let Some(hot_tier) = hot_tier::get() {
+        task_mgr::spawn(
+            BACKGROUND_RUNTIME.handle(),
+            TaskKind::HotTierMigration,
+            Some(tenant_shard_id),
+            None,
+            &format!("hot tier migration for tenant {tenant_shard_id}"),
+            false,
+            {
+                let tenant = Arc::clone(tenant);
+                let background_jobs_can_start = background_jobs_can_start.cloned();
+                async move {
+                    let cancel = task_mgr::shutdown_token();
+                    tokio::select! {
+                        _ = cancel.cancelled() => { return Ok(()) },
+                        _ = completion::Barrier::maybe_wait(background_jobs_can_start) => {}
+                    };
+                    let tags = tenant.tag_labels();
+                    hot_tier_migration_loop(tenant, hot_tier.max_age, cancel)
+                        .instrument(info_span!("hot_tier_migration_loop", tenant_id = %tenant_shard_id.tenant_id, shard_id = %tenant_shard_id.shard_slug(), tags = tags.as_field()))
+                        .await;
+                    Ok(())
+                }
+            },
+        );
+    }
 }
 
 ///
@@ -228,6 +260,9 @@ async fn compaction_loop(tenant: Arc<Tenant>, cancel: CancellationToken) {
                 }
             };
 
+            // Also when compaction is disabled or failing, when the debt is most likely to grow.
+            tenant.update_compaction_debt().await;
+
             let elapsed = started_at.elapsed();
             warn_when_period_overrun(elapsed, period, BackgroundLoopKind::Compaction);
 
@@ -238,6 +273,7 @@ async fn compaction_loop(tenant: Arc<Tenant>, cancel: CancellationToken) {
             // give it a chance to shut down to avoid leaving walredo process running indefinitely.
             if let Some(walredo_mgr) = &tenant.walredo_mgr {
                 walredo_mgr.maybe_quiesce(period * 10);
+                walredo_mgr.maybe_recycle();
             }
 
             // TODO: move this (and walredo quiesce) to a separate task that isn't affected by the back-off,
@@ -462,6 +498,54 @@ async fn ingest_housekeeping_loop(tenant: Arc<Tenant>, cancel: CancellationToken
     TENANT_TASK_EVENTS.with_label_values(&["stop"]).inc();
 }
 
+/// Migrates the layers of a tenant out of the hot tier as they reach `max_age`.
+async fn hot_tier_migration_loop(
+    tenant: Arc<Tenant>,
+    max_age: Duration,
+    cancel: CancellationToken,
+) {
+    // Layers overstay max_age by at most a quarter of it, within reason.
+    let period = (max_age / 4).clamp(Duration::from_secs(10), Duration::from_secs(600));
+
+    TENANT_TASK_EVENTS.with_label_values(&["start"]).inc();
+    async {
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => {
+                    return;
+                },
+                tenant_wait_result = wait_for_active_tenant(&tenant) => match tenant_wait_result {
+                    ControlFlow::Break(()) => return,
+                    ControlFlow::Continue(()) => (),
+                },
+            }
+
+            // Jitter the period by +/- 5%
+            let period =
+                rand::thread_rng().gen_range((period * (95)) / 100..(period * (105)) / 100);
+
+            // New layers are not due for migration yet, so sleep first.
+            if tokio::time::timeout(period, cancel.cancelled())
+                .await
+                .is_ok()
+            {
+                break;
+            }
+
+            let started_at = Instant::now();
+            tenant.migrate_hot_layers().await;
+
+            warn_when_period_overrun(
+                started_at.elapsed(),
+                period,
+                BackgroundLoopKind::HotTierMigration,
+            );
+        }
+    }
+    .await;
+    TENANT_TASK_EVENTS.with_label_values(&["stop"]).inc();
+}
+
 async fn wait_for_active_tenant(tenant: &Arc<Tenant>) -> ControlFlow<()> {
     // if the tenant has a proper status already, no need to wait for anything
     if tenant.current_state() == TenantState::Active {
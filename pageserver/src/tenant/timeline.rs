@@ -1,13 +1,24 @@
+pub(crate) mod access_log;
+mod ancestor_prefetch;
+mod branch_images;
 mod compaction;
+mod compaction_checkpoint;
 pub mod delete;
 pub(crate) mod detach_ancestor;
 mod eviction_task;
+pub(crate) mod explain_read;
 mod init;
+pub(crate) mod invalidations;
+pub(crate) mod key_stats;
 pub mod layer_manager;
+pub(crate) mod layer_manifest;
 pub(crate) mod logical_size;
+pub(crate) mod lsn_time_index;
+pub(crate) mod overlap_analysis;
 pub mod span;
 pub mod uninit;
-mod walreceiver;
+pub(crate) mod upload_pacing;
+pub(crate) mod walreceiver;
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use arc_swap::ArcSwap;
@@ -25,7 +36,7 @@ use pageserver_api::{
     models::{
         AuxFilePolicy, CompactionAlgorithm, DownloadRemoteLayersTaskInfo,
         DownloadRemoteLayersTaskSpawnRequest, EvictionPolicy, InMemoryLayerInfo, LayerMapInfo,
-        TimelineState,
+        PinnedRelation, ReadOnlyReason, TimelineDeletionProgress, TimelineState,
     },
     reltag::BlockNumber,
     shard::{ShardIdentity, ShardNumber, TenantShardId},
@@ -64,9 +75,11 @@ use crate::tenant::timeline::init::LocalLayerFileMetadata;
 use crate::tenant::{
     layer_map::{LayerMap, SearchResult},
     metadata::TimelineMetadata,
+    par_fsync,
 };
 use crate::{
-    context::{DownloadBehavior, RequestContext},
+    context::read_path::{ReadPathClass, REDO_HEAVY_RECORDS},
+    context::{DownloadBehavior, ReadStage, RequestContext},
     disk_usage_eviction_task::DiskUsageEvictionInfo,
     pgdatadir_mapping::CollectKeySpaceError,
 };
@@ -74,7 +87,8 @@ use crate::{deletion_queue::DeletionQueueClient, metrics::GetKind};
 use crate::{
     disk_usage_eviction_task::finite_f32,
     tenant::storage_layer::{
-        AsLayerDesc, DeltaLayerWriter, EvictionError, ImageLayerWriter, InMemoryLayer, Layer,
+        inmemory_layer::EphemeralResources, range_overlaps, AsLayerDesc, DeltaLayerWriter,
+        DownloadError, EvictionError, ImageLayerWriter, InMemoryLayer, Layer,
         LayerAccessStatsReset, LayerName, ResidentLayer, ValueReconstructResult,
         ValueReconstructState, ValuesReconstructState,
     },
@@ -94,7 +108,8 @@ use crate::{
 use crate::config::PageServerConf;
 use crate::keyspace::{KeyPartitioning, KeySpace};
 use crate::metrics::{
-    TimelineMetrics, MATERIALIZED_PAGE_CACHE_HIT, MATERIALIZED_PAGE_CACHE_HIT_DIRECT,
+    LayerWritePhase, StorageTimeOperation, TimelineMetrics, LAYER_WRITE_PHASE_TIME,
+    MATERIALIZED_PAGE_CACHE_HIT, MATERIALIZED_PAGE_CACHE_HIT_DIRECT,
 };
 use crate::pgdatadir_mapping::CalculateLogicalSizeError;
 use crate::tenant::config::TenantConfOpt;
@@ -114,6 +129,7 @@ use utils::{
 };
 
 use crate::page_cache;
+use crate::read_priority::ForegroundReads;
 use crate::repository::GcResult;
 use crate::repository::{Key, Value};
 use crate::task_mgr;
@@ -124,13 +140,19 @@ use self::delete::DeleteTimelineFlow;
 pub(super) use self::eviction_task::EvictionTaskTenantState;
 use self::eviction_task::EvictionTaskTimelineState;
 use self::layer_manager::LayerManager;
+use self::layer_manifest::{LayerManifest, ManifestOp, ManifestReason};
 use self::logical_size::LogicalSize;
 use self::walreceiver::{WalReceiver, WalReceiverConf};
 
 use super::secondary::heatmap::{HeatMapLayer, HeatMapTimeline};
+use super::startup_repair;
+use super::tags::{TagLabels, TaggedMetrics};
 use super::{config::TenantConf, storage_layer::VectoredValueReconstructState};
 use super::{debug_assert_current_span_has_tenant_and_timeline_id, AttachedTenantConf};
-use super::{remote_timeline_client::index::IndexPart, storage_layer::LayerFringe};
+use super::{
+    remote_timeline_client::index::{IndexPart, LogicalSizeCheckpoint},
+    storage_layer::LayerFringe,
+};
 use super::{remote_timeline_client::RemoteTimelineClient, storage_layer::ReadableLayer};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -156,6 +178,9 @@ pub enum ImageLayerCreationMode {
     /// means that no metadata keys should be included in the partitions. Used in flush frozen layer
     /// code path.
     Initial,
+    /// Create image layers at the branch point of a new branch, for the partitions with deep
+    /// delta chains in the ancestor. See [`branch_images`].
+    Branch,
 }
 
 impl std::fmt::Display for ImageLayerCreationMode {
@@ -202,6 +227,8 @@ pub struct TimelineResources {
     pub timeline_get_throttle: Arc<
         crate::tenant::throttle::Throttle<&'static crate::metrics::tenant_throttling::TimelineGet>,
     >,
+    pub(crate) ephemeral_resources: Arc<EphemeralResources>,
+    pub(crate) foreground_reads: Arc<ForegroundReads>,
 }
 
 pub(crate) struct AuxFilesState {
@@ -260,6 +287,9 @@ pub struct Timeline {
     /// so that e.g. on-demand-download/eviction, and layer spreading, can operate just on `LayerFileManager`.
     pub(crate) layers: Arc<tokio::sync::RwLock<LayerManager>>,
 
+    /// Log of the changes to [`Self::layers`], see [`layer_manifest`].
+    pub(crate) layer_manifest: LayerManifest,
+
     last_freeze_at: AtomicLsn,
     // Atomic would be more appropriate here.
     last_freeze_ts: RwLock<Instant>,
@@ -306,6 +336,10 @@ pub struct Timeline {
     // in `crate::page_service` writes these metrics.
     pub(crate) query_metrics: crate::metrics::SmgrQueryTimePerTimeline,
 
+    /// Shared by the timelines of the tenant shard, whose lifetime it has, see
+    /// [`super::getpage_latency`]. Written by `crate::page_service` too.
+    pub(crate) getpage_latency: Arc<super::getpage_latency::GetPageLatency>,
+
     directory_metrics: [AtomicU64; DirectoryKind::KINDS_NUM],
 
     /// Ensures layers aren't frozen by checkpointer between
@@ -359,6 +393,14 @@ pub struct Timeline {
     /// yet.
     pub last_received_wal: Mutex<Option<WalReceiverInfo>>,
     pub walreceiver: Mutex<Option<WalReceiver>>,
+    /// See [`walreceiver::ingest_stats`].
+    pub(crate) wal_ingest_stats: walreceiver::ingest_stats::WalIngestStats,
+    /// See [`key_stats`].
+    pub(crate) key_space_stats: key_stats::KeySpaceStatsCollector,
+    /// See [`access_log`].
+    pub(crate) access_log: access_log::AccessLog,
+    /// See [`lsn_time_index`].
+    pub(crate) lsn_time_index: lsn_time_index::LsnTimeIndex,
 
     /// Relation size cache
     pub(crate) rel_size_cache: RwLock<RelSizeCache>,
@@ -367,12 +409,35 @@ pub struct Timeline {
 
     state: watch::Sender<TimelineState>,
 
+    /// The value of [`Self::get_read_only`], for the walreceiver to disconnect as soon as the
+    /// tenant becomes read-only.
+    read_only: watch::Sender<Option<ReadOnlyReason>>,
+
     /// Prevent two tasks from deleting the timeline at the same time. If held, the
     /// timeline is being deleted. If 'true', the timeline has already been deleted.
     pub delete_progress: Arc<tokio::sync::Mutex<DeleteTimelineFlow>>,
 
+    /// Step of the ongoing deletion, for reporting. Unlike [`Self::delete_progress`],
+    /// this is not held for the duration of the deletion.
+    pub(crate) deletion_progress: Mutex<Option<TimelineDeletionProgress>>,
+
     eviction_task_timeline_state: tokio::sync::Mutex<EvictionTaskTimelineState>,
 
+    /// Reads of this timeline in the layers of its ancestors, see [`ancestor_prefetch`].
+    ancestor_layer_hits: ancestor_prefetch::AncestorLayerHits,
+
+    /// Key ranges with stacked delta layers that need image layers, see [`overlap_analysis`].
+    overlap_repairs: overlap_analysis::OverlapRepairs,
+
+    /// Subscribers to the keys changed by ingest, see [`invalidations`].
+    pub(crate) invalidations: invalidations::Invalidations,
+
+    /// Whether flushes and compactions wait for the upload queue to drain, see [`upload_pacing`].
+    upload_pacing: upload_pacing::UploadPacing,
+
+    /// Key metrics by the propagated tags of the tenant, see [`super::tags`].
+    pub(crate) tagged_metrics: ArcSwap<TaggedMetrics>,
+
     /// Load or creation time information about the disk_consistent_lsn and when the loading
     /// happened. Used for consumption metrics.
     pub(crate) loaded_at: (Lsn, SystemTime),
@@ -407,6 +472,12 @@ pub struct Timeline {
         crate::tenant::throttle::Throttle<&'static crate::metrics::tenant_throttling::TimelineGet>,
     >,
 
+    /// Cloned from [`super::Tenant::ephemeral_resources`] on construction.
+    ephemeral_resources: Arc<EphemeralResources>,
+
+    /// Cloned from [`super::Tenant::foreground_reads`] on construction.
+    foreground_reads: Arc<ForegroundReads>,
+
     /// Keep aux directory cache to avoid it's reconstruction on each update
     pub(crate) aux_files: tokio::sync::Mutex<AuxFilesState>,
 }
@@ -430,6 +501,9 @@ pub(crate) struct GcInfo {
 
     /// The cutoff coordinates, which are combined by selecting the minimum.
     pub(crate) cutoffs: GcCutoffs,
+
+    /// Relations whose whole history is retained, from the `gc_pinned_relations` tenant config.
+    pub(crate) pinned_relations: Vec<PinnedRelation>,
 }
 
 impl GcInfo {
@@ -484,6 +558,10 @@ pub(crate) enum PageReconstructError {
     #[error("timeline shutting down")]
     Cancelled,
 
+    /// The request was cancelled or is past its deadline, see [`RequestContext::is_cancelled`].
+    #[error("request cancelled or past its deadline")]
+    RequestCancelled,
+
     /// The ancestor of this is being stopped
     #[error("ancestor timeline {0} is being stopped")]
     AncestorStopping(TimelineId),
@@ -546,6 +624,7 @@ impl PageReconstructError {
             Other(_) => false,
             AncestorLsnTimeout(_) => false,
             Cancelled | AncestorStopping(_) => true,
+            RequestCancelled => false,
             WalRedo(_) => false,
             MissingKey { .. } => false,
         }
@@ -585,6 +664,9 @@ pub(crate) enum GetVectoredError {
     #[error("timeline shutting down")]
     Cancelled,
 
+    #[error("request cancelled or past its deadline")]
+    RequestCancelled,
+
     #[error("Requested too many keys: {0} > {}", Timeline::MAX_GET_VECTORED_KEYS)]
     Oversized(u64),
 
@@ -699,6 +781,7 @@ impl From<GetVectoredError> for PageReconstructError {
     fn from(e: GetVectoredError) -> Self {
         match e {
             GetVectoredError::Cancelled => PageReconstructError::Cancelled,
+            GetVectoredError::RequestCancelled => PageReconstructError::RequestCancelled,
             GetVectoredError::InvalidLsn(_) => PageReconstructError::Other(anyhow!("Invalid LSN")),
             err @ GetVectoredError::Oversized(_) => PageReconstructError::Other(err.into()),
             GetVectoredError::MissingKey(err) => PageReconstructError::MissingKey(err),
@@ -758,6 +841,7 @@ pub(crate) enum WaitLsnWaiter<'a> {
     Timeline(&'a Timeline),
     Tenant,
     PageService,
+    HttpEndpoint,
 }
 
 /// Argument to [`Timeline::shutdown`].
@@ -797,7 +881,8 @@ impl Timeline {
     /// If a remote layer file is needed, it is downloaded as part of this
     /// call.
     ///
-    /// This method enforces [`Self::timeline_get_throttle`] internally.
+    /// This method enforces [`Self::timeline_get_throttle`] internally, and queues background
+    /// reads behind foreground reads, see [`crate::read_priority`].
     ///
     /// NOTE: It is considered an error to 'get' a key that doesn't exist. The
     /// abstraction above this needs to store suitable metadata to track what
@@ -826,6 +911,9 @@ impl Timeline {
         debug_assert!(!self.shard_identity.is_key_disposable(&key));
 
         self.timeline_get_throttle.throttle(ctx, 1).await;
+        let _read_permit = crate::read_priority::get()
+            .admit(ctx, &self.foreground_reads)
+            .await;
 
         // Check the page cache. We will get back the most recent page with lsn <= `lsn`.
         // The cached image can be returned directly if there is no WAL between the cached image
@@ -833,6 +921,8 @@ impl Timeline {
         // for redo.
         let cached_page_img = match self.lookup_cached_page(&key, lsn, ctx).await {
             Some((cached_lsn, cached_img)) => {
+                // Also when the image is the base of the reconstruction, by either read path.
+                ctx.read_path.record(ReadPathClass::PageCache);
                 match cached_lsn.cmp(&lsn) {
                     Ordering::Less => {} // there might be WAL between cached_lsn and lsn, we need to check
                     Ordering::Equal => {
@@ -936,7 +1026,9 @@ impl Timeline {
         timer.stop_and_record();
 
         let start = Instant::now();
-        let res = self.reconstruct_value(key, lsn, reconstruct_state).await;
+        let res = self
+            .reconstruct_value(key, lsn, reconstruct_state, ctx)
+            .await;
         let elapsed = start.elapsed();
         crate::metrics::RECONSTRUCT_TIME
             .for_get_kind(GetKind::Singular)
@@ -1012,6 +1104,9 @@ impl Timeline {
             .timeline_get_throttle
             .throttle(ctx, key_count as usize)
             .await;
+        let _read_permit = crate::read_priority::get()
+            .admit(ctx, &self.foreground_reads)
+            .await;
 
         let res = match self.conf.get_vectored_impl {
             GetVectoredImpl::Sequential => {
@@ -1140,6 +1235,7 @@ impl Timeline {
                     Err(Cancelled | AncestorStopping(_)) => {
                         return Err(GetVectoredError::Cancelled)
                     }
+                    Err(RequestCancelled) => return Err(GetVectoredError::RequestCancelled),
                     Err(MissingKey(_))
                         if NON_INHERITED_RANGE.contains(&key)
                             || NON_INHERITED_SPARSE_RANGE.contains(&key) =>
@@ -1213,7 +1309,7 @@ impl Timeline {
                 Ok(state) => {
                     let state = ValueReconstructState::from(state);
 
-                    let reconstruct_res = self.reconstruct_value(key, lsn, state).await;
+                    let reconstruct_res = self.reconstruct_value(key, lsn, state, ctx).await;
                     results.insert(key, reconstruct_res);
                 }
             }
@@ -1267,6 +1363,9 @@ impl Timeline {
         match (&sequential_res, vectored_res) {
             (Err(GetVectoredError::Cancelled), _) => {},
             (_, Err(GetVectoredError::Cancelled)) => {},
+            // The deadline may pass between the two.
+            (Err(GetVectoredError::RequestCancelled), _) => {},
+            (_, Err(GetVectoredError::RequestCancelled)) => {},
             (Err(seq_err), Ok(_)) => {
                 panic!(concat!("Sequential get failed with {}, but vectored get did not",
                                " - keyspace={:?} lsn={}"),
@@ -1420,6 +1519,18 @@ impl Timeline {
         lsn: Lsn,
         who_is_waiting: WaitLsnWaiter<'_>,
         ctx: &RequestContext, /* Prepare for use by cancellation */
+    ) -> Result<(), WaitLsnError> {
+        self.wait_lsn_timeout(lsn, who_is_waiting, self.conf.wait_lsn_timeout, ctx)
+            .await
+    }
+
+    /// Like [`Self::wait_lsn`], but with a timeout other than `wait_lsn_timeout`.
+    pub(crate) async fn wait_lsn_timeout(
+        &self,
+        lsn: Lsn,
+        who_is_waiting: WaitLsnWaiter<'_>,
+        timeout: Duration,
+        ctx: &RequestContext,
     ) -> Result<(), WaitLsnError> {
         if self.cancel.is_cancelled() {
             return Err(WaitLsnError::Shutdown);
@@ -1434,7 +1545,7 @@ impl Timeline {
                 | TaskKind::WalReceiverConnectionPoller => {
                     let is_myself = match who_is_waiting {
                         WaitLsnWaiter::Timeline(waiter) => Weak::ptr_eq(&waiter.myself, &self.myself),
-                        WaitLsnWaiter::Tenant | WaitLsnWaiter::PageService => unreachable!("tenant or page_service context are not expected to have task kind {:?}", ctx.task_kind()),
+                        WaitLsnWaiter::Tenant | WaitLsnWaiter::PageService | WaitLsnWaiter::HttpEndpoint => unreachable!("tenant, page_service or http endpoint context are not expected to have task kind {:?}", ctx.task_kind()),
                     };
                     if is_myself {
                         if let Err(current) = self.last_record_lsn.would_wait_for(lsn) {
@@ -1452,11 +1563,7 @@ impl Timeline {
 
         let _timer = crate::metrics::WAIT_LSN_TIME.start_timer();
 
-        match self
-            .last_record_lsn
-            .wait_for_timeout(lsn, self.conf.wait_lsn_timeout)
-            .await
-        {
+        match self.last_record_lsn.wait_for_timeout(lsn, timeout).await {
             Ok(()) => Ok(()),
             Err(e) => {
                 use utils::seqwait::SeqWaitError::*;
@@ -1620,6 +1727,15 @@ impl Timeline {
         }
     }
 
+    /// Bytes of L0 delta layers waiting for compaction.
+    pub(crate) async fn compaction_debt(&self) -> u64 {
+        let guard = self.layers.read().await;
+        guard
+            .layer_map()
+            .get_level0_deltas()
+            .map_or(0, |l0_deltas| l0_deltas.iter().map(|l| l.file_size).sum())
+    }
+
     /// Outermost timeline compaction operation; downloads needed layers.
     pub(crate) async fn compact(
         self: &Arc<Self>,
@@ -1748,6 +1864,11 @@ impl Timeline {
         self.last_record_lsn.shutdown();
 
         if try_freeze_and_flush {
+            // Keep the accesses since the last ingest housekeeping across the restart.
+            if let Err(e) = self.persist_access_log().await {
+                warn!("failed to persist the access log: {e:#}");
+            }
+
             // we shut down walreceiver above, so, we won't add anything more
             // to the InMemoryLayer; freeze it and wait for all frozen layers
             // to reach the disk & upload queue, then shut the upload queue and
@@ -2011,7 +2132,74 @@ impl Timeline {
         tenant_conf
             .tenant_conf
             .switch_aux_file_policy
-            .unwrap_or(self.conf.default_tenant_conf.switch_aux_file_policy)
+            .unwrap_or(self.conf.default_tenant_conf.load().switch_aux_file_policy)
+    }
+
+    /// Why the ingest of WAL is rejected, if it is, see [`crate::tenant::Tenant::set_read_only`].
+    pub(crate) fn get_read_only(&self) -> Option<ReadOnlyReason> {
+        self.tenant_conf.load().tenant_conf.read_only
+    }
+
+    pub(crate) fn subscribe_for_read_only_updates(
+        &self,
+    ) -> watch::Receiver<Option<ReadOnlyReason>> {
+        self.read_only.subscribe()
+    }
+
+    /// The relations whose history GC keeps, see [`TenantConfOpt::gc_pinned_relations`].
+    pub(crate) fn get_gc_pinned_relations(&self) -> Vec<PinnedRelation> {
+        let tenant_conf = self.tenant_conf.load();
+        tenant_conf
+            .tenant_conf
+            .gc_pinned_relations
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// The key ranges of [`Self::get_gc_pinned_relations`].
+    pub(crate) fn get_gc_pinned_key_ranges(&self) -> Vec<Range<Key>> {
+        self.get_gc_pinned_relations()
+            .iter()
+            .flat_map(PinnedRelation::key_ranges)
+            .collect()
+    }
+
+    /// The LSN down to which `key` may be read: `latest_gc_cutoff_lsn`, or lower if GC keeps
+    /// the history of the key for a pinned relation, see [`GcPinnedCutoff`].
+    ///
+    /// [`GcPinnedCutoff`]: crate::tenant::remote_timeline_client::index::GcPinnedCutoff
+    pub(crate) fn get_gc_cutoff_for_key(&self, key: &Key, latest_gc_cutoff_lsn: Lsn) -> Lsn {
+        let Some(remote_client) = &self.remote_client else {
+            return latest_gc_cutoff_lsn;
+        };
+        remote_client
+            .gc_pinned_cutoffs()
+            .iter()
+            .filter(|cutoff| {
+                cutoff
+                    .relation
+                    .key_ranges()
+                    .iter()
+                    .any(|range| range.contains(key))
+            })
+            .map(|cutoff| cutoff.lsn)
+            .fold(latest_gc_cutoff_lsn, Lsn::min)
+    }
+
+    fn get_ancestor_prefetch(&self) -> bool {
+        let tenant_conf = self.tenant_conf.load();
+        tenant_conf
+            .tenant_conf
+            .ancestor_prefetch
+            .unwrap_or(self.conf.default_tenant_conf.load().ancestor_prefetch)
+    }
+
+    pub(crate) fn get_sequential_prefetch(&self) -> bool {
+        let tenant_conf = self.tenant_conf.load();
+        tenant_conf
+            .tenant_conf
+            .sequential_prefetch
+            .unwrap_or(self.conf.default_tenant_conf.load().sequential_prefetch)
     }
 
     pub(crate) fn get_lazy_slru_download(&self) -> bool {
@@ -2019,7 +2207,28 @@ impl Timeline {
         tenant_conf
             .tenant_conf
             .lazy_slru_download
-            .unwrap_or(self.conf.default_tenant_conf.lazy_slru_download)
+            .unwrap_or(self.conf.default_tenant_conf.load().lazy_slru_download)
+    }
+
+    fn get_capture_wal(&self) -> bool {
+        let tenant_conf = self.tenant_conf.load();
+        tenant_conf
+            .tenant_conf
+            .capture_wal
+            .unwrap_or(self.conf.default_tenant_conf.load().capture_wal)
+    }
+
+    fn get_branch_image_creation_threshold(&self) -> u64 {
+        let tenant_conf = self.tenant_conf.load();
+        tenant_conf
+            .tenant_conf
+            .branch_image_creation_threshold
+            .unwrap_or(
+                self.conf
+                    .default_tenant_conf
+                    .load()
+                    .branch_image_creation_threshold,
+            )
     }
 
     fn get_checkpoint_distance(&self) -> u64 {
@@ -2027,7 +2236,7 @@ impl Timeline {
         tenant_conf
             .tenant_conf
             .checkpoint_distance
-            .unwrap_or(self.conf.default_tenant_conf.checkpoint_distance)
+            .unwrap_or(self.conf.default_tenant_conf.load().checkpoint_distance)
     }
 
     fn get_checkpoint_timeout(&self) -> Duration {
@@ -2035,7 +2244,7 @@ impl Timeline {
         tenant_conf
             .tenant_conf
             .checkpoint_timeout
-            .unwrap_or(self.conf.default_tenant_conf.checkpoint_timeout)
+            .unwrap_or(self.conf.default_tenant_conf.load().checkpoint_timeout)
     }
 
     fn get_compaction_target_size(&self) -> u64 {
@@ -2043,7 +2252,7 @@ impl Timeline {
         tenant_conf
             .tenant_conf
             .compaction_target_size
-            .unwrap_or(self.conf.default_tenant_conf.compaction_target_size)
+            .unwrap_or(self.conf.default_tenant_conf.load().compaction_target_size)
     }
 
     fn get_compaction_threshold(&self) -> usize {
@@ -2051,15 +2260,17 @@ impl Timeline {
         tenant_conf
             .tenant_conf
             .compaction_threshold
-            .unwrap_or(self.conf.default_tenant_conf.compaction_threshold)
+            .unwrap_or(self.conf.default_tenant_conf.load().compaction_threshold)
     }
 
     fn get_image_creation_threshold(&self) -> usize {
         let tenant_conf = self.tenant_conf.load();
-        tenant_conf
-            .tenant_conf
-            .image_creation_threshold
-            .unwrap_or(self.conf.default_tenant_conf.image_creation_threshold)
+        tenant_conf.tenant_conf.image_creation_threshold.unwrap_or(
+            self.conf
+                .default_tenant_conf
+                .load()
+                .image_creation_threshold,
+        )
     }
 
     fn get_compaction_algorithm(&self) -> CompactionAlgorithm {
@@ -2067,7 +2278,7 @@ impl Timeline {
         tenant_conf
             .tenant_conf
             .compaction_algorithm
-            .unwrap_or(self.conf.default_tenant_conf.compaction_algorithm)
+            .unwrap_or(self.conf.default_tenant_conf.load().compaction_algorithm)
     }
 
     fn get_eviction_policy(&self) -> EvictionPolicy {
@@ -2075,7 +2286,7 @@ impl Timeline {
         tenant_conf
             .tenant_conf
             .eviction_policy
-            .unwrap_or(self.conf.default_tenant_conf.eviction_policy)
+            .unwrap_or(self.conf.default_tenant_conf.load().eviction_policy)
     }
 
     fn get_evictions_low_residence_duration_metric_threshold(
@@ -2095,6 +2306,7 @@ impl Timeline {
             .unwrap_or(
                 self.conf
                     .default_tenant_conf
+                    .load()
                     .image_layer_creation_check_threshold,
             )
     }
@@ -2107,7 +2319,7 @@ impl Timeline {
         {
             let new_threshold = Self::get_evictions_low_residence_duration_metric_threshold(
                 new_conf,
-                &self.conf.default_tenant_conf,
+                &self.conf.default_tenant_conf.load(),
             );
 
             let tenant_id_str = self.tenant_shard_id.tenant_id.to_string();
@@ -2125,6 +2337,17 @@ impl Timeline {
                     new_threshold,
                 );
         }
+
+        // The tags are embedded in the metrics as well.
+        let tags = TagLabels::select(self.conf, new_conf);
+        self.tagged_metrics
+            .store(Arc::new(TaggedMetrics::new(&tags)));
+
+        self.read_only.send_if_modified(|read_only| {
+            let modified = *read_only != new_conf.read_only;
+            *read_only = new_conf.read_only;
+            modified
+        });
     }
 
     /// Open a Timeline handle.
@@ -2148,6 +2371,7 @@ impl Timeline {
     ) -> Arc<Self> {
         let disk_consistent_lsn = metadata.disk_consistent_lsn();
         let (state, _) = watch::channel(state);
+        let (read_only, _) = watch::channel(tenant_conf.load().tenant_conf.read_only);
 
         let (layer_flush_start_tx, _) = tokio::sync::watch::channel((0, disk_consistent_lsn));
         let (layer_flush_done_tx, _) = tokio::sync::watch::channel((0, Ok(())));
@@ -2156,9 +2380,13 @@ impl Timeline {
             let loaded_tenant_conf = tenant_conf.load();
             Self::get_evictions_low_residence_duration_metric_threshold(
                 &loaded_tenant_conf.tenant_conf,
-                &conf.default_tenant_conf,
+                &conf.default_tenant_conf.load(),
             )
         };
+        let tagged_metrics = ArcSwap::from_pointee(TaggedMetrics::new(&TagLabels::select(
+            conf,
+            &tenant_conf.load().tenant_conf,
+        )));
 
         Arc::new_cyclic(|myself| {
             let mut result = Timeline {
@@ -2171,6 +2399,9 @@ impl Timeline {
                 shard_identity,
                 pg_version,
                 layers: Default::default(),
+                layer_manifest: LayerManifest::new(
+                    &conf.timeline_path(&tenant_shard_id, &timeline_id),
+                ),
 
                 walredo_mgr,
                 walreceiver: Mutex::new(None),
@@ -2205,6 +2436,7 @@ impl Timeline {
                     &tenant_shard_id,
                     &timeline_id,
                 ),
+                getpage_latency: super::getpage_latency::for_tenant_shard(conf, tenant_shard_id),
 
                 directory_metrics: array::from_fn(|_| AtomicU64::new(0)),
 
@@ -2237,6 +2469,10 @@ impl Timeline {
                 last_image_layer_creation_check_at: AtomicLsn::new(0),
 
                 last_received_wal: Mutex::new(None),
+                wal_ingest_stats: Default::default(),
+                key_space_stats: Default::default(),
+                access_log: access_log::AccessLog::new(conf.timeline_access_log_retention),
+                lsn_time_index: Default::default(),
                 rel_size_cache: RwLock::new(RelSizeCache {
                     complete_as_of: disk_consistent_lsn,
                     map: HashMap::new(),
@@ -2245,11 +2481,18 @@ impl Timeline {
                 download_all_remote_layers_task_info: RwLock::new(None),
 
                 state,
+                read_only,
 
                 eviction_task_timeline_state: tokio::sync::Mutex::new(
                     EvictionTaskTimelineState::default(),
                 ),
+                ancestor_layer_hits: Default::default(),
+                overlap_repairs: Default::default(),
+                invalidations: Default::default(),
+                upload_pacing: Default::default(),
+                tagged_metrics,
                 delete_progress: Arc::new(tokio::sync::Mutex::new(DeleteTimelineFlow::default())),
+                deletion_progress: Mutex::new(None),
 
                 cancel,
                 gate: Gate::default(),
@@ -2258,6 +2501,8 @@ impl Timeline {
                 gc_lock: tokio::sync::Mutex::default(),
 
                 timeline_get_throttle: resources.timeline_get_throttle,
+                ephemeral_resources: resources.ephemeral_resources,
+                foreground_reads: resources.foreground_reads,
 
                 aux_files: tokio::sync::Mutex::new(AuxFilesState {
                     dir: None,
@@ -2347,15 +2592,20 @@ impl Timeline {
         let wal_connect_timeout = tenant_conf
             .tenant_conf
             .walreceiver_connect_timeout
-            .unwrap_or(self.conf.default_tenant_conf.walreceiver_connect_timeout);
+            .unwrap_or(
+                self.conf
+                    .default_tenant_conf
+                    .load()
+                    .walreceiver_connect_timeout,
+            );
         let lagging_wal_timeout = tenant_conf
             .tenant_conf
             .lagging_wal_timeout
-            .unwrap_or(self.conf.default_tenant_conf.lagging_wal_timeout);
+            .unwrap_or(self.conf.default_tenant_conf.load().lagging_wal_timeout);
         let max_lsn_wal_lag = tenant_conf
             .tenant_conf
             .max_lsn_wal_lag
-            .unwrap_or(self.conf.default_tenant_conf.max_lsn_wal_lag);
+            .unwrap_or(self.conf.default_tenant_conf.load().max_lsn_wal_lag);
 
         let mut guard = self.walreceiver.lock().unwrap();
         assert!(
@@ -2371,6 +2621,7 @@ impl Timeline {
                 auth_token: crate::config::SAFEKEEPER_AUTH_TOKEN.get().cloned(),
                 availability_zone: self.conf.availability_zone.clone(),
                 ingest_batch_size: self.conf.ingest_batch_size,
+                protocol: self.conf.wal_receiver_protocol,
             },
             broker_client,
             ctx,
@@ -2385,6 +2636,32 @@ impl Timeline {
         layers.initialize_empty(Lsn(start_lsn.0));
     }
 
+    /// Initialize the layer map of a new timeline with the layers of a snapshot, given with their
+    /// sizes. They are not resident: they are downloaded from the snapshot on demand. They are
+    /// unsharded, so the tenant must be too, see [`super::Tenant::snapshot_timeline`].
+    pub(super) fn init_layer_map_from_snapshot(
+        self: &Arc<Self>,
+        snapshot_layers: &HashMap<LayerName, u64>,
+        start_lsn: Lsn,
+    ) -> Vec<Layer> {
+        let layers: Vec<Layer> = snapshot_layers
+            .iter()
+            .map(|(name, file_size)| {
+                let metadata = crate::tenant::remote_timeline_client::LayerFileMetadata::new(
+                    *file_size,
+                    Generation::none(),
+                    ShardIndex::unsharded(),
+                );
+                Layer::for_evicted(self.conf, self, name.clone(), metadata)
+            })
+            .collect();
+        let mut guard = self.layers.try_write().expect(
+            "in the context where we call this function, no other task has access to the object",
+        );
+        guard.initialize_local_layers(layers.clone(), start_lsn);
+        layers
+    }
+
     /// Scan the timeline directory, cleanup, populate the layer map, and schedule uploads for local-only
     /// files.
     pub(super) async fn load_layer_map(
@@ -2408,14 +2685,26 @@ impl Timeline {
         let span = tracing::Span::current();
 
         // Copy to move into the task we're about to spawn
+        let tenant_shard_id = self.tenant_shard_id;
+        let timeline_id = self.timeline_id;
         let generation = self.generation;
         let shard = self.get_shard_index();
         let this = self.myself.upgrade().expect("&self method holds the arc");
 
+        if let Some(remote_client) = &self.remote_client {
+            self.lsn_time_index
+                .restore(remote_client.lsn_time_samples());
+        }
+
         let (loaded_layers, needs_cleanup, total_physical_size) = tokio::task::spawn_blocking({
             move || {
                 let _g = span.entered();
                 let discovered = init::scan_timeline_dir(&timeline_path)?;
+                let compaction_outputs = compaction_checkpoint::outputs_to_keep(&timeline_path);
+                if let Err(e) = access_log::load_blocking(&this.access_log, &timeline_path) {
+                    // The rollups start over, it is not worth failing the load for them.
+                    warn!("failed to load the access log: {e:#}");
+                }
                 let mut discovered_layers = Vec::with_capacity(discovered.len());
                 let mut unrecognized_files = Vec::new();
 
@@ -2431,7 +2720,10 @@ impl Timeline {
                             warn!("found legacy metadata file, these should have been removed in load_tenant_config");
                             continue;
                         }
-                        Discovered::IgnoredBackup => {
+                        Discovered::IgnoredBackup
+                        | Discovered::LayerManifest
+                        | Discovered::CompactionCheckpoint
+                        | Discovered::AccessLog => {
                             continue;
                         }
                         Discovered::Unknown(file_name) => {
@@ -2452,9 +2744,54 @@ impl Timeline {
                     // assume that if there are any there are many many.
                     let n = unrecognized_files.len();
                     let first = &unrecognized_files[..n.min(10)];
-                    anyhow::bail!(
-                        "unrecognized files in timeline dir (total {n}), first 10: {first:?}"
-                    );
+                    let problem =
+                        format!("unrecognized files in timeline dir (total {n}), first 10: {first:?}");
+                    if !conf.startup_repair {
+                        anyhow::bail!(problem);
+                    }
+
+                    // Move them out of the way: the layers are reconciled with the remote index below.
+                    let moved_aside = unrecognized_files.iter().try_for_each(|name| {
+                        path.push(Utf8Path::new(name));
+                        let res = rename_to_backup(&path);
+                        path.pop();
+                        res
+                    });
+                    match moved_aside {
+                        Ok(()) => startup_repair::record(
+                            tenant_shard_id,
+                            Some(timeline_id),
+                            problem,
+                            Some("unrecognized files renamed to *.old, layers loaded from the remote index".to_string()),
+                        ),
+                        Err(e) => {
+                            startup_repair::record(tenant_shard_id, Some(timeline_id), problem.clone(), None);
+                            return Err(e.context(problem));
+                        }
+                    }
+                }
+
+                match this.layer_manifest.read_blocking() {
+                    // first start with the manifest, nothing to compare with
+                    Ok(entries) if entries.is_empty() => {}
+                    Ok(entries) => {
+                        let mismatch = layer_manifest::compare_with_local(
+                            &entries,
+                            discovered_layers.iter().map(|(name, _, _)| name),
+                        );
+                        if !mismatch.is_empty() {
+                            let first_unexpected = &mismatch.unexpected[..mismatch.unexpected.len().min(10)];
+                            let first_missing = &mismatch.missing[..mismatch.missing.len().min(10)];
+                            warn!(
+                                "timeline directory doesn't match the layer manifest: {} unexpected layer files, first 10: {:?}; {} missing layer files, first 10: {:?}",
+                                mismatch.unexpected.len(),
+                                first_unexpected,
+                                mismatch.missing.len(),
+                                first_missing,
+                            );
+                        }
+                    }
+                    Err(e) => warn!("failed to read the layer manifest: {e:#}"),
                 }
 
                 let decided = init::reconcile(
@@ -2468,6 +2805,7 @@ impl Timeline {
                 let mut loaded_layers = Vec::new();
                 let mut needs_cleanup = Vec::new();
                 let mut total_physical_size = 0;
+                let mut manifest_snapshot = Vec::new();
 
                 for (name, decision) in decided {
                     let decision = match decision {
@@ -2496,6 +2834,10 @@ impl Timeline {
                             needs_cleanup.push(name);
                             continue;
                         }
+                        Err(DismissedLayer::LocalOnly(_)) if compaction_outputs.contains(&name) => {
+                            // kept for the interrupted compaction to resume, see compaction_checkpoint
+                            continue;
+                        }
                         Err(DismissedLayer::LocalOnly(local)) => {
                             init::cleanup_local_only_file(&name, &local)?;
                             // this file never existed remotely, we will have to do rework
@@ -2510,6 +2852,8 @@ impl Timeline {
 
                     tracing::debug!(layer=%name, ?decision, "applied");
 
+                    manifest_snapshot.push((name.clone(), matches!(decision, UseLocal(_))));
+
                     let layer = match decision {
                         UseLocal(local) => {
                             total_physical_size += local.metadata.file_size();
@@ -2522,6 +2866,11 @@ impl Timeline {
 
                     loaded_layers.push(layer);
                 }
+
+                if let Err(e) = this.layer_manifest.reset_blocking(manifest_snapshot) {
+                    warn!("failed to rewrite the layer manifest: {e}");
+                }
+
                 Ok((loaded_layers, needs_cleanup, total_physical_size))
             }
         })
@@ -2659,6 +3008,15 @@ impl Timeline {
             return;
         };
 
+        if let Some(size) = self.logical_size_checkpoint_at(initial_part_end) {
+            // The size was checkpointed at exactly this LSN before the restart.
+            use crate::metrics::initial_logical_size::{StartCircumstances, START_CALCULATION};
+            let metrics_guard = START_CALCULATION.first(StartCircumstances::FromCheckpoint);
+            self.set_initial_logical_size(size, metrics_guard);
+            self.current_logical_size.initialized.add_permits(1);
+            return;
+        }
+
         let cancel_wait_for_background_loop_concurrency_limit_semaphore = CancellationToken::new();
         let token = cancel_wait_for_background_loop_concurrency_limit_semaphore.clone();
         self.current_logical_size
@@ -2813,6 +3171,24 @@ impl Timeline {
             }
         };
 
+        self.set_initial_logical_size(calculated_size, metrics_guard);
+
+        if let Some(remote_client) = &self.remote_client {
+            let checkpoint = LogicalSizeCheckpoint {
+                lsn: initial_part_end,
+                size: calculated_size,
+            };
+            if let Err(e) = remote_client.update_logical_size_checkpoint(checkpoint) {
+                warn!("failed to checkpoint the initial logical size: {e:#}");
+            }
+        }
+    }
+
+    fn set_initial_logical_size(
+        &self,
+        size: u64,
+        metrics_guard: crate::metrics::initial_logical_size::OngoingCalculationGuard,
+    ) {
         // we cannot query current_logical_size.current_size() to know the current
         // *negative* value, only truncated to u64.
         let added = self
@@ -2820,16 +3196,23 @@ impl Timeline {
             .size_added_after_initial
             .load(AtomicOrdering::Relaxed);
 
-        let sum = calculated_size.saturating_add_signed(added);
+        let sum = size.saturating_add_signed(added);
 
         // set the gauge value before it can be set in `update_current_logical_size`.
         self.metrics.current_logical_size_gauge.set(sum);
 
         self.current_logical_size
             .initial_logical_size
-            .set((calculated_size, metrics_guard.calculation_result_saved()))
+            .set((size, metrics_guard.calculation_result_saved()))
             .ok()
-            .expect("only this task sets it");
+            .expect("the initial logical size is set only once");
+    }
+
+    /// The logical size at `lsn`, if it was checkpointed in the index part at exactly that LSN.
+    fn logical_size_checkpoint_at(&self, lsn: Lsn) -> Option<u64> {
+        self.remote_client
+            .as_ref()
+            .and_then(|remote_client| remote_client.logical_size_checkpoint_at(lsn))
     }
 
     pub(crate) fn spawn_ondemand_logical_size_calculation(
@@ -2908,13 +3291,15 @@ impl Timeline {
     /// Calculate the logical size of the database at the latest LSN.
     ///
     /// NOTE: counted incrementally, includes ancestors. This can be a slow operation,
-    /// especially if we need to download remote layers.
+    /// especially if we need to download remote layers, so the relation sizes are read in
+    /// parallel, except for the imitated calculations, see
+    /// [`Self::get_current_logical_size_parallel`].
     ///
     /// # Cancel-Safety
     ///
     /// This method is cancellation-safe.
     async fn calculate_logical_size(
-        &self,
+        self: &Arc<Self>,
         up_to_lsn: Lsn,
         cause: LogicalSizeCalculationCause,
         _guard: &GateGuard,
@@ -2932,6 +3317,9 @@ impl Timeline {
         if let Some(size) = self.current_logical_size.initialized_size(up_to_lsn) {
             return Ok(size);
         }
+        if let Some(size) = self.logical_size_checkpoint_at(up_to_lsn) {
+            return Ok(size);
+        }
         let storage_time_metrics = match cause {
             LogicalSizeCalculationCause::Initial
             | LogicalSizeCalculationCause::ConsumptionMetricsSyntheticSize
@@ -2941,9 +3329,23 @@ impl Timeline {
             }
         };
         let timer = storage_time_metrics.start_timer();
-        let logical_size = self
-            .get_current_logical_size_non_incremental(up_to_lsn, ctx)
-            .await?;
+        let logical_size = match cause {
+            LogicalSizeCalculationCause::Initial
+            | LogicalSizeCalculationCause::ConsumptionMetricsSyntheticSize
+            | LogicalSizeCalculationCause::TenantSizeHandler => {
+                let limit = self
+                    .conf
+                    .concurrent_tenant_size_logical_size_queries
+                    .inner();
+                self.get_current_logical_size_parallel(up_to_lsn, limit, ctx)
+                    .await?
+            }
+            // Only touches the layers, leave the workers to the real calculations.
+            LogicalSizeCalculationCause::EvictionTaskImitation => {
+                self.get_current_logical_size_non_incremental(up_to_lsn, ctx)
+                    .await?
+            }
+        };
         debug!("calculated logical size: {logical_size}");
         timer.stop_and_record();
         Ok(logical_size)
@@ -3006,6 +3408,53 @@ impl Timeline {
         None
     }
 
+    /// Whether a key in `key_range` may have been written after `since`, up to and including
+    /// `until`, judging by the layers of this timeline without reading them.
+    ///
+    /// Errs on the side of `true`: whenever `since` is below the branch point or the GC cutoff,
+    /// the WAL in between may be on the ancestor or compacted away.
+    pub(crate) async fn may_have_modified(
+        &self,
+        key_range: &Range<Key>,
+        since: Lsn,
+        until: Lsn,
+    ) -> bool {
+        if since > until
+            || since < self.get_ancestor_lsn()
+            || since < *self.get_latest_gc_cutoff_lsn()
+        {
+            return true;
+        }
+        // The records that a basebackup at `since` doesn't have yet.
+        let lsn_range = since + 1..until + 1;
+
+        let in_memory = {
+            let guard = self.layers.read().await;
+            let layer_map = guard.layer_map();
+            let delta_modified = layer_map.iter_historic_layers().any(|desc| {
+                desc.is_delta()
+                    && range_overlaps(&desc.key_range, key_range)
+                    && range_overlaps(&desc.lsn_range, &lsn_range)
+            });
+            if delta_modified {
+                return true;
+            }
+            layer_map
+                .open_layer
+                .iter()
+                .chain(layer_map.frozen_layers.iter())
+                .filter(|layer| range_overlaps(&layer.get_lsn_range(), &lsn_range))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        for layer in in_memory {
+            if layer.has_values_in(key_range, &lsn_range).await {
+                return true;
+            }
+        }
+        false
+    }
+
     /// The timeline heatmap is a hint to secondary locations from the primary location,
     /// indicating which layers are currently on-disk on the primary.
     ///
@@ -3115,10 +3564,16 @@ impl Timeline {
         let mut result = ValueReconstructResult::Continue;
         let mut cont_lsn = Lsn(request_lsn.0 + 1);
 
+        let ancestor_prefetch = self.get_ancestor_prefetch();
+
         'outer: loop {
             if self.cancel.is_cancelled() {
                 return Err(PageReconstructError::Cancelled);
             }
+            if ctx.is_cancelled() {
+                ctx.record_given_up(ReadStage::Traversal);
+                return Err(PageReconstructError::RequestCancelled);
+            }
 
             // The function should have updated 'state'
             //info!("CALLED for {} at {}: {:?} with {} records, cached {}", key, cont_lsn, result, reconstruct_state.records.len(), cached_lsn);
@@ -3243,6 +3698,10 @@ impl Timeline {
             if let Some(SearchResult { lsn_floor, layer }) = layers.search(key, cont_lsn) {
                 let layer = guard.get_from_desc(&layer);
                 drop(guard);
+                if ancestor_prefetch && !std::ptr::eq(timeline, self) {
+                    self.ancestor_layer_hits
+                        .record(timeline.timeline_id, layer.layer_desc().layer_name());
+                }
                 // Get all the data needed to reconstruct the page version from this layer.
                 // But if we have an older cached page image, no need to go past that.
                 let lsn_floor = max(cached_lsn + 1, lsn_floor);
@@ -3251,6 +3710,14 @@ impl Timeline {
                     .await
                 {
                     Ok(result) => result,
+                    Err(e)
+                        if matches!(
+                            e.downcast_ref::<DownloadError>(),
+                            Some(DownloadError::RequestCancelled)
+                        ) =>
+                    {
+                        return Err(PageReconstructError::RequestCancelled)
+                    }
                     Err(e) => return Err(PageReconstructError::from(e)),
                 };
                 cont_lsn = lsn_floor;
@@ -3292,16 +3759,21 @@ impl Timeline {
 
         let mut cont_lsn = Lsn(request_lsn.0 + 1);
 
+        let ancestor_prefetch = self.get_ancestor_prefetch();
+
         loop {
             if self.cancel.is_cancelled() {
                 return Err(GetVectoredError::Cancelled);
             }
 
+            let ancestor_layer_hits = (ancestor_prefetch && !std::ptr::eq(timeline, self))
+                .then_some(&self.ancestor_layer_hits);
             let completed = Self::get_vectored_reconstruct_data_timeline(
                 timeline,
                 keyspace.clone(),
                 cont_lsn,
                 reconstruct_state,
+                ancestor_layer_hits,
                 &self.cancel,
                 ctx,
             )
@@ -3361,11 +3833,15 @@ impl Timeline {
     ///
     /// At each iteration pop the top of the fringe (the layer with the highest Lsn)
     /// and get all the required reconstruct data from the layer in one go.
+    ///
+    /// If `timeline` is an ancestor of the timeline being read, the persistent layers visited
+    /// are counted in `ancestor_layer_hits`.
     async fn get_vectored_reconstruct_data_timeline(
         timeline: &Timeline,
         keyspace: KeySpace,
         mut cont_lsn: Lsn,
         reconstruct_state: &mut ValuesReconstructState,
+        ancestor_layer_hits: Option<&ancestor_prefetch::AncestorLayerHits>,
         cancel: &CancellationToken,
         ctx: &RequestContext,
     ) -> Result<KeySpace, GetVectoredError> {
@@ -3378,6 +3854,10 @@ impl Timeline {
             if cancel.is_cancelled() {
                 return Err(GetVectoredError::Cancelled);
             }
+            if ctx.is_cancelled() {
+                ctx.record_given_up(ReadStage::Traversal);
+                return Err(GetVectoredError::RequestCancelled);
+            }
 
             let keys_done_last_step = reconstruct_state.consume_done_keys();
             unmapped_keyspace.remove_overlapping_with(&keys_done_last_step);
@@ -3440,6 +3920,11 @@ impl Timeline {
             }
 
             if let Some((layer_to_read, keyspace_to_read, lsn_range)) = fringe.next_layer() {
+                if let (Some(hits), ReadableLayer::PersistentLayer(layer)) =
+                    (ancestor_layer_hits, &layer_to_read)
+                {
+                    hits.record(timeline.timeline_id, layer.layer_desc().layer_name());
+                }
                 let next_cont_lsn = lsn_range.start;
                 layer_to_read
                     .get_values_reconstruct_data(
@@ -3569,6 +4054,7 @@ impl Timeline {
                 self.conf,
                 self.timeline_id,
                 self.tenant_shard_id,
+                &self.ephemeral_resources,
             )
             .await?;
         Ok(layer)
@@ -3603,6 +4089,12 @@ impl Timeline {
         guard
             .try_freeze_in_memory_layer(at, &self.last_freeze_at)
             .await;
+        // The size is updated at the end of a commit, with the last record LSN. A layer rolled
+        // in the middle of a commit ends past the last record LSN, at a size we don't know: the
+        // size is checkpointed at a later flush then.
+        if self.tenant_shard_id.is_shard_zero() && at == self.get_last_record_lsn() {
+            self.current_logical_size.record_frozen_size(at);
+        }
     }
 
     /// Layer flusher task's main loop.
@@ -3635,6 +4127,13 @@ impl Timeline {
                     return;
                 }
 
+                let done_counter = self.layer_flush_done_tx.borrow().0;
+                self.wait_for_upload_backlog_to_flush(done_counter).await;
+                if self.cancel.is_cancelled() {
+                    info!("dropping out of flush loop for timeline shutdown");
+                    return;
+                }
+
                 let timer = self.metrics.flush_time_histo.start_timer();
 
                 let layer_to_flush = {
@@ -3717,6 +4216,7 @@ impl Timeline {
         self.layer_flush_start_tx.send_modify(|(counter, lsn)| {
             my_flush_request = *counter + 1;
             *counter = my_flush_request;
+            self.upload_pacing.flush_waited_for(my_flush_request);
             *lsn = std::cmp::max(last_record_lsn, *lsn);
         });
 
@@ -3882,6 +4382,16 @@ impl Timeline {
 
         let disk_consistent_lsn = Lsn(lsn_range.end.0 - 1);
 
+        self.layer_manifest
+            .record(
+                ManifestOp::Add,
+                ManifestReason::Flush,
+                delta_layer_to_add
+                    .iter()
+                    .map(|l| l.layer_desc().layer_name()),
+            )
+            .await;
+
         // The new on-disk layers are now in the layer map. We can remove the
         // in-memory layer from the map now. The flushed layer is stored in
         // the mapping in `create_delta_layer`.
@@ -3895,6 +4405,23 @@ impl Timeline {
             guard.finish_flush_l0_layer(delta_layer_to_add.as_ref(), &frozen_layer, &self.metrics);
 
             if self.set_disk_consistent_lsn(disk_consistent_lsn) {
+                // Checkpoint the logical size with the index part that reflects the flush
+                let logical_size = self
+                    .current_logical_size
+                    .take_frozen_size(disk_consistent_lsn);
+                if let (Some(size), Some(remote_client)) = (logical_size, &self.remote_client) {
+                    remote_client.update_logical_size_checkpoint(LogicalSizeCheckpoint {
+                        lsn: disk_consistent_lsn,
+                        size,
+                    })?;
+                }
+                // Along with the sampled commit timestamps, which ingest samples again after it
+                if let Some(remote_client) = &self.remote_client {
+                    remote_client.update_lsn_time_samples(
+                        self.lsn_time_index.samples_up_to(disk_consistent_lsn),
+                    )?;
+                }
+
                 // Schedule remote uploads that will reflect our new disk_consistent_lsn
                 self.schedule_uploads(disk_consistent_lsn, layers_to_upload)?;
             }
@@ -3972,6 +4499,52 @@ impl Timeline {
         Ok(())
     }
 
+    /// Make new layer files, which were finished without fsync, durable: fsync them in parallel,
+    /// then fsync the timeline directory they were renamed into. `write_time` is how long it took
+    /// to write them, for the breakdown in [`LAYER_WRITE_PHASE_TIME`].
+    ///
+    /// We use fatal_err() below because once the layer files were renamed into place, the
+    /// in-memory state of the filesystem already has them in their final place, and subsequent
+    /// pageserver code could think they're durable while they really aren't.
+    pub(crate) async fn fsync_new_layers(
+        &self,
+        layers: &[ResidentLayer],
+        operation: StorageTimeOperation,
+        write_time: Duration,
+    ) {
+        let operation: &'static str = operation.into();
+        let observe = |phase: LayerWritePhase, duration: Duration| {
+            LAYER_WRITE_PHASE_TIME
+                .with_label_values(&[operation, phase.into()])
+                .observe(duration.as_secs_f64())
+        };
+        observe(LayerWritePhase::Write, write_time);
+        if layers.is_empty() {
+            return;
+        }
+
+        let started_at = Instant::now();
+        let paths = layers.iter().map(|l| l.local_path()).collect::<Vec<_>>();
+        par_fsync::par_fsync(&paths)
+            .await
+            .fatal_err("fsync new layer files");
+        observe(LayerWritePhase::FsyncLayers, started_at.elapsed());
+
+        let started_at = Instant::now();
+        let timeline_dir = VirtualFile::open(
+            &self
+                .conf
+                .timeline_path(&self.tenant_shard_id, &self.timeline_id),
+        )
+        .await
+        .fatal_err("VirtualFile::open for timeline dir fsync");
+        timeline_dir
+            .sync_all()
+            .await
+            .fatal_err("VirtualFile::sync_all timeline dir");
+        observe(LayerWritePhase::FsyncDir, started_at.elapsed());
+    }
+
     pub(crate) async fn preserve_initdb_archive(&self) -> anyhow::Result<()> {
         if let Some(remote_client) = &self.remote_client {
             remote_client
@@ -3999,30 +4572,20 @@ impl Timeline {
         let frozen_layer = Arc::clone(frozen_layer);
         let ctx = ctx.attached_child();
         let work = async move {
+            let started_at = Instant::now();
             let Some(new_delta) = frozen_layer
                 .write_to_disk(&self_clone, &ctx, key_range)
                 .await?
             else {
                 return Ok(None);
             };
-            // The write_to_disk() above calls writer.finish() which already did the fsync of the inodes.
-            // We just need to fsync the directory in which these inodes are linked,
-            // which we know to be the timeline directory.
-            //
-            // We use fatal_err() below because the after write_to_disk returns with success,
-            // the in-memory state of the filesystem already has the layer file in its final place,
-            // and subsequent pageserver code could think it's durable while it really isn't.
-            let timeline_dir = VirtualFile::open(
-                &self_clone
-                    .conf
-                    .timeline_path(&self_clone.tenant_shard_id, &self_clone.timeline_id),
-            )
-            .await
-            .fatal_err("VirtualFile::open for timeline dir fsync");
-            timeline_dir
-                .sync_all()
-                .await
-                .fatal_err("VirtualFile::sync_all timeline dir");
+            self_clone
+                .fsync_new_layers(
+                    std::slice::from_ref(&new_delta),
+                    StorageTimeOperation::LayerFlush,
+                    started_at.elapsed(),
+                )
+                .await;
             anyhow::Ok(Some(new_delta))
         };
         // Before tokio-epoll-uring, we ran write_to_disk & the sync_all inside spawn_blocking.
@@ -4052,9 +4615,9 @@ impl Timeline {
         ctx: &RequestContext,
     ) -> anyhow::Result<((KeyPartitioning, SparseKeyPartitioning), Lsn)> {
         let Ok(mut partitioning_guard) = self.partitioning.try_lock() else {
-            // NB: there are two callers, one is the compaction task, of which there is only one per struct Tenant and hence Timeline.
-            // The other is the initdb optimization in flush_frozen_layer, used by `boostrap_timeline`, which runs before `.activate()`
-            // and hence before the compaction task starts.
+            // NB: there are three callers, one is the compaction task, of which there is only one per struct Tenant and hence Timeline.
+            // The second is the initdb optimization in flush_frozen_layer, used by `boostrap_timeline`, which runs before `.activate()`
+            // and hence before the compaction task starts. The third is branch image creation, which holds the compaction lock.
             anyhow::bail!("repartition() called concurrently, this should not happen");
         };
         let ((dense_partition, sparse_partition), partition_lsn) = &*partitioning_guard;
@@ -4079,7 +4642,7 @@ impl Timeline {
         }
 
         let (dense_ks, sparse_ks) = self.collect_keyspace(lsn, ctx).await?;
-        let dense_partitioning = dense_ks.partition(&self.shard_identity, partition_size);
+        let dense_partitioning = self.partition_around_pins(dense_ks, partition_size);
         let sparse_partitioning = SparseKeyPartitioning {
             parts: vec![sparse_ks],
         }; // no partitioning for metadata keys for now
@@ -4088,6 +4651,53 @@ impl Timeline {
         Ok((partitioning_guard.0.clone(), partitioning_guard.1))
     }
 
+    /// Partition `keyspace` like [`KeySpace::partition`], but so that no partition mixes the
+    /// keys of pinned relations with other keys: GC keeps the image layers of pinned keys.
+    fn partition_around_pins(&self, keyspace: KeySpace, partition_size: u64) -> KeyPartitioning {
+        let pinned_key_ranges = self.get_gc_pinned_key_ranges();
+        if pinned_key_ranges.is_empty() {
+            return keyspace.partition(&self.shard_identity, partition_size);
+        }
+
+        // Cut the ranges at the boundaries of the pinned ranges, and partition each run of
+        // pinned, or of unpinned, ranges on its own.
+        let mut boundaries = pinned_key_ranges
+            .iter()
+            .flat_map(|range| [range.start, range.end])
+            .collect::<Vec<_>>();
+        boundaries.sort();
+        boundaries.dedup();
+        let mut runs: Vec<(bool, Vec<Range<Key>>)> = Vec::new();
+        for range in keyspace.ranges {
+            let mut start = range.start;
+            let inner = boundaries
+                .iter()
+                .copied()
+                .filter(|boundary| range.start < *boundary && *boundary < range.end);
+            for end in inner.chain(std::iter::once(range.end)) {
+                let pinned = pinned_key_ranges
+                    .iter()
+                    .any(|pinned| pinned.contains(&start));
+                match runs.last_mut() {
+                    Some((run_pinned, ranges)) if *run_pinned == pinned => ranges.push(start..end),
+                    _ => runs.push((pinned, vec![start..end])),
+                }
+                start = end;
+            }
+        }
+
+        KeyPartitioning {
+            parts: runs
+                .into_iter()
+                .flat_map(|(_, ranges)| {
+                    KeySpace { ranges }
+                        .partition(&self.shard_identity, partition_size)
+                        .parts
+                })
+                .collect(),
+        }
+    }
+
     // Is it time to create a new image layer for the given partition?
     async fn time_for_new_image_layer(&self, partition: &KeySpace, lsn: Lsn) -> bool {
         let threshold = self.get_image_creation_threshold();
@@ -4148,6 +4758,7 @@ impl Timeline {
         ctx: &RequestContext,
     ) -> Result<Vec<ResidentLayer>, CreateImageLayersError> {
         let timer = self.metrics.create_images_time_histo.start_timer();
+        let started_at = Instant::now();
         let mut image_layers = Vec::new();
 
         // We need to avoid holes between generated image layers.
@@ -4160,6 +4771,8 @@ impl Timeline {
         // If there is delta layer <100000000..300000000> then it never be garbage collected because
         // image layers  <100000000..100000099> and <200000000..200000199> are not completely covering it.
         let mut start = Key::MIN;
+        // The partitions don't mix pinned and unpinned keys, see `partition_around_pins`.
+        let pinned_key_ranges = self.get_gc_pinned_key_ranges();
 
         let check_for_image_layers = {
             let last_checks_at = self.last_image_layer_creation_check_at.load();
@@ -4179,13 +4792,29 @@ impl Timeline {
         }
 
         for partition in partitioning.parts.iter() {
+            // GC keeps the image layers that overlap pinned relations, so an image layer of
+            // other keys starts after them. That leaves a hole, but only the layers that
+            // overlap the pinned relations span it, and GC keeps those anyway.
+            if !pinned_key_ranges
+                .iter()
+                .any(|pinned| pinned.contains(&partition.ranges[0].start))
+            {
+                while let Some(pinned) = pinned_key_ranges
+                    .iter()
+                    .find(|pinned| pinned.contains(&start))
+                {
+                    start = pinned.end;
+                }
+            }
             let img_range = start..partition.ranges.last().unwrap().end;
 
             if partition.overlaps(&Key::metadata_key_range()) {
                 // TODO(chi): The next patch will correctly create image layers for metadata keys, and it would be a
                 // rather big change. Keep this patch small for now.
                 match mode {
-                    ImageLayerCreationMode::Force | ImageLayerCreationMode::Try => {
+                    ImageLayerCreationMode::Force
+                    | ImageLayerCreationMode::Try
+                    | ImageLayerCreationMode::Branch => {
                         // skip image layer creation anyways for metadata keys.
                         start = img_range.end;
                         continue;
@@ -4195,9 +4824,24 @@ impl Timeline {
                     }
                 }
             } else if let ImageLayerCreationMode::Try = mode {
+                // scheduled by the overlap analysis -> generate
                 // check_for_image_layers = false -> skip
                 // check_for_image_layers = true -> check time_for_new_image_layer -> skip/generate
-                if !check_for_image_layers || !self.time_for_new_image_layer(partition, lsn).await {
+                if !self.overlap_repairs.overlaps(partition)
+                    && (!check_for_image_layers
+                        || !self.time_for_new_image_layer(partition, lsn).await)
+                {
+                    start = img_range.end;
+                    continue;
+                }
+            } else if let ImageLayerCreationMode::Branch = mode {
+                // The branch has no deltas of its own below the branch point: look at the
+                // ancestor's.
+                let ancestor = self
+                    .ancestor_timeline
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("branch image creation without an ancestor"))?;
+                if !ancestor.time_for_new_image_layer(partition, lsn).await {
                     start = img_range.end;
                     continue;
                 }
@@ -4291,7 +4935,7 @@ impl Timeline {
                 // Normal path: we have written some data into the new image layer for this
                 // partition, so flush it to disk.
                 start = img_range.end;
-                let image_layer = image_layer_writer.finish(self, ctx).await?;
+                let image_layer = image_layer_writer.finish_unsynced(self, ctx).await?;
                 image_layers.push(image_layer);
             } else {
                 // Special case: the image layer may be empty if this is a sharded tenant and the
@@ -4302,25 +4946,20 @@ impl Timeline {
             }
         }
 
-        // The writer.finish() above already did the fsync of the inodes.
-        // We just need to fsync the directory in which these inodes are linked,
-        // which we know to be the timeline directory.
-        if !image_layers.is_empty() {
-            // We use fatal_err() below because the after writer.finish() returns with success,
-            // the in-memory state of the filesystem already has the layer file in its final place,
-            // and subsequent pageserver code could think it's durable while it really isn't.
-            let timeline_dir = VirtualFile::open(
-                &self
-                    .conf
-                    .timeline_path(&self.tenant_shard_id, &self.timeline_id),
+        self.fsync_new_layers(
+            &image_layers,
+            StorageTimeOperation::CreateImages,
+            started_at.elapsed(),
+        )
+        .await;
+
+        self.layer_manifest
+            .record(
+                ManifestOp::Add,
+                ManifestReason::ImageCreation,
+                image_layers.iter().map(|l| l.layer_desc().layer_name()),
             )
-            .await
-            .fatal_err("VirtualFile::open for timeline dir fsync");
-            timeline_dir
-                .sync_all()
-                .await
-                .fatal_err("VirtualFile::sync_all timeline dir");
-        }
+            .await;
 
         let mut guard = self.layers.write().await;
 
@@ -4462,6 +5101,33 @@ impl Timeline {
         new_images: &[ResidentLayer],
         layers_to_remove: &[Layer],
     ) -> anyhow::Result<()> {
+        // Append to the manifest before taking the layer map lock. The inputs that are also
+        // outputs are the duplicated layers below, which stay in the layer map.
+        let new_delta_keys = new_deltas
+            .iter()
+            .map(|l| l.layer_desc().key())
+            .collect::<HashSet<_>>();
+        self.layer_manifest
+            .record(
+                ManifestOp::Add,
+                ManifestReason::Compaction,
+                new_images
+                    .iter()
+                    .chain(new_deltas.iter())
+                    .map(|l| l.layer_desc().layer_name()),
+            )
+            .await;
+        self.layer_manifest
+            .record(
+                ManifestOp::Remove,
+                ManifestReason::Compaction,
+                layers_to_remove
+                    .iter()
+                    .filter(|l| !new_delta_keys.contains(&l.layer_desc().key()))
+                    .map(|l| l.layer_desc().layer_name()),
+            )
+            .await;
+
         let mut guard = self.layers.write().await;
 
         let mut duplicated_layers = HashSet::new();
@@ -4513,6 +5179,14 @@ impl Timeline {
         replace_layers: Vec<(Layer, ResidentLayer)>,
         drop_layers: Vec<Layer>,
     ) -> anyhow::Result<()> {
+        self.layer_manifest
+            .record(
+                ManifestOp::Remove,
+                ManifestReason::Compaction,
+                drop_layers.iter().map(|l| l.layer_desc().layer_name()),
+            )
+            .await;
+
         let mut guard = self.layers.write().await;
 
         guard.rewrite_layers(&replace_layers, &drop_layers, &self.metrics);
@@ -4653,19 +5327,26 @@ impl Timeline {
             anyhow::bail!("timeline is Stopping");
         }
 
-        let (horizon_cutoff, pitr_cutoff, retain_lsns) = {
+        let (horizon_cutoff, pitr_cutoff, retain_lsns, pinned_relations) = {
             let gc_info = self.gc_info.read().unwrap();
 
             let horizon_cutoff = min(gc_info.cutoffs.horizon, self.get_disk_consistent_lsn());
             let pitr_cutoff = gc_info.cutoffs.pitr;
             let retain_lsns = gc_info.retain_lsns.clone();
-            (horizon_cutoff, pitr_cutoff, retain_lsns)
+            let pinned_relations = gc_info.pinned_relations.clone();
+            (horizon_cutoff, pitr_cutoff, retain_lsns, pinned_relations)
         };
 
         let new_gc_cutoff = Lsn::min(horizon_cutoff, pitr_cutoff);
 
         let res = self
-            .gc_timeline(horizon_cutoff, pitr_cutoff, retain_lsns, new_gc_cutoff)
+            .gc_timeline(
+                horizon_cutoff,
+                pitr_cutoff,
+                retain_lsns,
+                pinned_relations,
+                new_gc_cutoff,
+            )
             .instrument(
                 info_span!("gc_timeline", timeline_id = %self.timeline_id, cutoff = %new_gc_cutoff),
             )
@@ -4682,6 +5363,7 @@ impl Timeline {
         horizon_cutoff: Lsn,
         pitr_cutoff: Lsn,
         retain_lsns: Vec<Lsn>,
+        pinned_relations: Vec<PinnedRelation>,
         new_gc_cutoff: Lsn,
     ) -> anyhow::Result<GcResult> {
         // FIXME: if there is an ongoing detach_from_ancestor, we should just skip gc
@@ -4698,9 +5380,22 @@ impl Timeline {
             return Ok(result);
         }
 
+        // The pinned relations keep their history from here on: reads of them stay valid down
+        // to the current cutoff. This is persisted with the new cutoff, before any layer is
+        // removed.
+        if let Some(remote_client) = &self.remote_client {
+            remote_client.update_gc_pinned_cutoffs(&pinned_relations, latest_gc_cutoff)?;
+        }
+        let pinned_key_ranges = pinned_relations
+            .iter()
+            .flat_map(PinnedRelation::key_ranges)
+            .collect::<Vec<_>>();
+
         // We need to ensure that no one tries to read page versions or create
         // branches at a point before latest_gc_cutoff_lsn. See branch_timeline()
         // for details. This will block until the old value is no longer in use.
+        // That includes reads of pinned relations, which were checked against the GC cutoffs
+        // of the pinned relations while holding the old value.
         //
         // The GC cutoff should only ever move forwards.
         let waitlist = {
@@ -4714,6 +5409,11 @@ impl Timeline {
             write_guard.store_and_unlock(new_gc_cutoff)
         };
         waitlist.wait().await;
+        crate::timeline_events::notify_gc_cutoff_advanced(
+            self.tenant_shard_id,
+            self.timeline_id,
+            new_gc_cutoff,
+        );
 
         info!("GC starting");
 
@@ -4727,7 +5427,8 @@ impl Timeline {
         // 1. it is older than cutoff LSN;
         // 2. it is older than PITR interval;
         // 3. it doesn't need to be retained for 'retain_lsns';
-        // 4. newer on-disk image layers cover the layer's whole key range
+        // 4. it doesn't contain keys of relations pinned by the tenant config;
+        // 5. newer on-disk image layers cover the layer's whole key range
         //
         // TODO holding a write lock is too agressive and avoidable
         let mut guard = self.layers.write().await;
@@ -4778,7 +5479,25 @@ impl Timeline {
                 }
             }
 
-            // 4. Is there a later on-disk layer for this relation?
+            // 4. Does it contain the history of a pinned relation?
+            //
+            // L0 delta layers span the whole key space, but compaction turns them into L1
+            // layers that end at the boundaries of the pinned relations, and image layers are
+            // created the same way, so that only the layers of pinned keys are kept.
+            let key_range = l.get_key_range();
+            if pinned_key_ranges
+                .iter()
+                .any(|pinned| pinned.start < key_range.end && key_range.start < pinned.end)
+            {
+                debug!(
+                    "keeping {} because it overlaps a pinned relation",
+                    l.layer_name()
+                );
+                result.layers_needed_by_pinned_relations += 1;
+                continue 'outer;
+            }
+
+            // 5. Is there a later on-disk layer for this relation?
             //
             // The end-LSN is exclusive, while disk_consistent_lsn is
             // inclusive. For example, if disk_consistent_lsn is 100, it is
@@ -4833,6 +5552,17 @@ impl Timeline {
             }
 
             guard.finish_gc_timeline(&gc_layers);
+            drop_wlock(guard);
+
+            // The local files are deleted when the last references to the layers are dropped,
+            // after the manifest append.
+            self.layer_manifest
+                .record(
+                    ManifestOp::Remove,
+                    ManifestReason::Gc,
+                    gc_layers.iter().map(|l| l.layer_desc().layer_name()),
+                )
+                .await;
 
             #[cfg(feature = "testing")]
             {
@@ -4855,9 +5585,13 @@ impl Timeline {
         key: Key,
         request_lsn: Lsn,
         mut data: ValueReconstructState,
+        ctx: &RequestContext,
     ) -> Result<Bytes, PageReconstructError> {
         // Perform WAL redo if needed
         data.records.reverse();
+        if data.records.len() >= REDO_HEAVY_RECORDS {
+            ctx.read_path.record(ReadPathClass::RedoHeavy);
+        }
 
         // If we have a page image, and no WAL, we're all set
         if data.records.is_empty() {
@@ -4900,6 +5634,11 @@ impl Timeline {
 
                 let last_rec_lsn = data.records.last().unwrap().0;
 
+                if ctx.is_cancelled() {
+                    ctx.record_given_up(ReadStage::WalRedo);
+                    return Err(PageReconstructError::RequestCancelled);
+                }
+
                 let img = match self
                     .walredo_mgr
                     .as_ref()
@@ -4992,6 +5731,8 @@ impl Timeline {
             total_layer_count: 0,
             successful_download_count: 0,
             failed_download_count: 0,
+            total_bytes: 0,
+            downloaded_bytes: 0,
         };
         *status_guard = Some(initial_info.clone());
 
@@ -5013,6 +5754,10 @@ impl Timeline {
                 .collect::<Vec<_>>()
         };
         let total_layer_count = remaining.len();
+        let total_bytes = remaining
+            .iter()
+            .map(|layer| layer.metadata().file_size())
+            .sum::<u64>();
 
         macro_rules! lock_status {
             ($st:ident) => {
@@ -5034,6 +5779,7 @@ impl Timeline {
         {
             lock_status!(st);
             st.total_layer_count = total_layer_count as u64;
+            st.total_bytes = total_bytes;
         }
 
         let mut remaining = remaining.into_iter();
@@ -5044,6 +5790,11 @@ impl Timeline {
 
         let limit = request.max_concurrent_downloads;
 
+        // Downloads are started no faster than the bandwidth limit allows for the layers started
+        // before them.
+        let started_at = tokio::time::Instant::now();
+        let mut paced_bytes = 0;
+
         loop {
             while js.len() < limit.get() && have_remaining && !cancel.is_cancelled() {
                 let Some(next) = remaining.next() else {
@@ -5051,6 +5802,21 @@ impl Timeline {
                     break;
                 };
 
+                if let Some(max_bytes_per_second) = request.max_bytes_per_second {
+                    if !next.is_likely_resident() {
+                        let due = Duration::from_secs_f64(
+                            paced_bytes as f64 / max_bytes_per_second.get() as f64,
+                        );
+                        if tokio::time::timeout_at(started_at + due, cancel.cancelled())
+                            .await
+                            .is_ok()
+                        {
+                            break;
+                        }
+                        paced_bytes += next.metadata().file_size();
+                    }
+                }
+
                 let span = tracing::info_span!("download", layer = %next);
 
                 js.spawn(
@@ -5064,9 +5830,10 @@ impl Timeline {
 
             while let Some(res) = js.join_next().await {
                 match res {
-                    Ok((_, Ok(_))) => {
+                    Ok((layer, Ok(_))) => {
                         lock_status!(st);
                         st.successful_download_count += 1;
+                        st.downloaded_bytes += layer.metadata().file_size();
                     }
                     Ok((layer, Err(e))) => {
                         tracing::error!(%layer, "download failed: {e:#}");
@@ -5223,6 +5990,8 @@ impl<'a> TimelineWriter<'a> {
             // render the size tracking out of sync. That's ok because
             // the checkpoint distance should be significantly smaller
             // than the S3 single shot upload limit of 5GiB.
+            self.tl.access_log.record_write(&key, buf_size);
+
             let state = self.write_guard.as_mut().unwrap();
 
             state.current_size += buf_size;
@@ -0,0 +1,511 @@
+//! Hourly rollups of the accesses to a timeline, to tell the branches that are still used from
+//! the abandoned ones.
+//!
+//! Every page read by the page service and every value written by ingest is counted in the
+//! rollup of its hour: the pages read, the bytes ingested, and an estimate of the distinct keys
+//! read or written, from a HyperLogLog sketch. Hours without accesses have no rollup, and the
+//! rollups older than `timeline_access_log_retention` are dropped.
+//!
+//! The accesses are counted on the hot paths of reads and ingest, so the current hour is
+//! counted with atomics, and only the first access of an hour takes the lock, to close the
+//! previous one. Accesses racing with that can be counted in the hour next to theirs.
+//!
+//! The rollups are persisted in [`ACCESS_LOG_FILE_NAME`] in the timeline directory by the ingest
+//! housekeeping of the tenant and at graceful shutdown, along with the sketch of the last hour,
+//! and loaded with the layer map. They are returned by the `access_log` management API.
+//!
+//! The file also records since when the accesses are tracked: accesses from before it, e.g. on
+//! another pageserver before a migration, are unknown. See [`crate::tenant::stale_timelines`].
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use camino::Utf8Path;
+use pageserver_api::key::Key;
+use pageserver_api::models::{HourlyAccess, TimelineAccessLog};
+use serde::{Deserialize, Serialize};
+use utils::crashsafe::path_with_suffix_extension;
+
+use crate::virtual_file::VirtualFile;
+use crate::TEMP_FILE_SUFFIX;
+
+use super::Timeline;
+
+pub(crate) const ACCESS_LOG_FILE_NAME: &str = "access_log.json";
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// Number of registers of the sketch of distinct keys: the standard error of the estimate is
+/// about `1.04 / sqrt(SKETCH_REGISTERS)`, 6.5%.
+const SKETCH_REGISTERS: usize = 256;
+
+/// HyperLogLog sketch of the keys accessed in an hour.
+///
+/// The hash of the keys must not change, since the sketches are persisted.
+#[derive(Clone, PartialEq, Eq)]
+struct KeySketch([u8; SKETCH_REGISTERS]);
+
+impl Default for KeySketch {
+    fn default() -> Self {
+        KeySketch([0; SKETCH_REGISTERS])
+    }
+}
+
+impl KeySketch {
+    #[cfg(test)]
+    fn add(&mut self, key: &Key) {
+        let (register, rank) = sketch_register(key);
+        self.0[register] = self.0[register].max(rank);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|&rank| rank == 0)
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = SKETCH_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.0.iter().map(|&rank| (-(rank as f64)).exp2()).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.0.iter().filter(|&&rank| rank == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate for small cardinalities.
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// The register of `key` in a [`KeySketch`], and the rank to record in it.
+fn sketch_register(key: &Key) -> (usize, u8) {
+    let key = key.to_i128() as u128;
+    let hash = splitmix64(splitmix64(key as u64) ^ (key >> 64) as u64);
+    let register = hash as usize % SKETCH_REGISTERS;
+    // The index takes the low 8 bits, the rank is taken from the 56 others.
+    let rank = ((hash >> 8).leading_zeros() - 7) as u8;
+    (register, rank)
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// The start of the hour of `time`, in seconds since the epoch.
+fn hour_of(time: SystemTime) -> u64 {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch.as_secs() / HOUR.as_secs() * HOUR.as_secs()
+}
+
+fn hour_time(hour: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(hour)
+}
+
+/// Content of [`ACCESS_LOG_FILE_NAME`].
+#[derive(Serialize, Deserialize)]
+struct AccessLogFile {
+    hours: Vec<HourlyAccess>,
+    /// The registers of the sketch of the last hour, to keep counting its distinct keys after a
+    /// restart. Empty once the hour is over.
+    last_hour_keys: Vec<u8>,
+    /// Missing in the files written before it was tracked.
+    #[serde(default, with = "humantime_serde")]
+    tracked_since: Option<SystemTime>,
+}
+
+/// The last accesses to a timeline, see [`AccessLog::activity`].
+pub(crate) struct Activity {
+    /// Accesses from before are unknown.
+    pub(crate) tracked_since: SystemTime,
+    /// The start of the last hour with page reads, if within the retention.
+    pub(crate) last_read: Option<SystemTime>,
+    /// The start of the last hour with ingest, if within the retention.
+    pub(crate) last_write: Option<SystemTime>,
+}
+
+pub(crate) struct AccessLog {
+    retention: Duration,
+    current: CurrentHour,
+    /// Whether there were accesses since the rollups were last persisted.
+    dirty: AtomicBool,
+    inner: Mutex<Inner>,
+}
+
+/// The accesses of the hour being counted.
+struct CurrentHour {
+    /// The start of the hour, in seconds since the epoch.
+    hour: AtomicU64,
+    pages_read: AtomicU64,
+    bytes_ingested: AtomicU64,
+    keys: [AtomicU8; SKETCH_REGISTERS],
+}
+
+impl CurrentHour {
+    fn keys(&self) -> KeySketch {
+        KeySketch(std::array::from_fn(|i| {
+            self.keys[i].load(Ordering::Relaxed)
+        }))
+    }
+
+    /// The rollup of the hour, if it had accesses.
+    fn rollup(&self) -> Option<HourlyAccess> {
+        let pages_read = self.pages_read.load(Ordering::Relaxed);
+        let bytes_ingested = self.bytes_ingested.load(Ordering::Relaxed);
+        (pages_read > 0 || bytes_ingested > 0).then(|| HourlyAccess {
+            hour: hour_time(self.hour.load(Ordering::Relaxed)),
+            pages_read,
+            bytes_ingested,
+            unique_keys: self.keys().estimate(),
+        })
+    }
+
+    /// Take the rollup of the hour, and start counting `hour`.
+    fn close(&self, hour: u64) -> Option<HourlyAccess> {
+        let rollup = HourlyAccess {
+            hour: hour_time(self.hour.swap(hour, Ordering::Relaxed)),
+            pages_read: self.pages_read.swap(0, Ordering::Relaxed),
+            bytes_ingested: self.bytes_ingested.swap(0, Ordering::Relaxed),
+            unique_keys: KeySketch(std::array::from_fn(|i| {
+                self.keys[i].swap(0, Ordering::Relaxed)
+            }))
+            .estimate(),
+        };
+        (rollup.pages_read > 0 || rollup.bytes_ingested > 0).then_some(rollup)
+    }
+}
+
+struct Inner {
+    /// The rollups of the hours before the current one, oldest first.
+    hours: VecDeque<HourlyAccess>,
+    tracked_since: SystemTime,
+}
+
+impl Inner {
+    fn expire(&mut self, now: SystemTime, retention: Duration) {
+        let Some(cutoff) = now.checked_sub(retention) else {
+            return;
+        };
+        while matches!(self.hours.front(), Some(first) if first.hour + HOUR <= cutoff) {
+            self.hours.pop_front();
+        }
+    }
+}
+
+impl AccessLog {
+    pub(crate) fn new(retention: Duration) -> Self {
+        AccessLog {
+            retention,
+            current: CurrentHour {
+                hour: AtomicU64::new(0),
+                pages_read: AtomicU64::new(0),
+                bytes_ingested: AtomicU64::new(0),
+                keys: std::array::from_fn(|_| AtomicU8::new(0)),
+            },
+            dirty: AtomicBool::new(false),
+            inner: Mutex::new(Inner {
+                hours: VecDeque::new(),
+                tracked_since: SystemTime::now(),
+            }),
+        }
+    }
+
+    pub(crate) fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    /// Count a page read by a compute.
+    pub(crate) fn record_read(&self, key: &Key) {
+        self.record_at(SystemTime::now(), key, 1, 0);
+    }
+
+    /// Count a value written by ingest.
+    pub(crate) fn record_write(&self, key: &Key, bytes: u64) {
+        self.record_at(SystemTime::now(), key, 0, bytes);
+    }
+
+    fn record_at(&self, now: SystemTime, key: &Key, pages_read: u64, bytes_ingested: u64) {
+        if self.retention.is_zero() {
+            return;
+        }
+        let hour = hour_of(now);
+        // If the clock went backwards, keep counting in the current hour.
+        if hour > self.current.hour.load(Ordering::Relaxed) {
+            self.close_hours_before(&mut self.inner.lock().unwrap(), hour);
+        }
+        self.current
+            .pages_read
+            .fetch_add(pages_read, Ordering::Relaxed);
+        self.current
+            .bytes_ingested
+            .fetch_add(bytes_ingested, Ordering::Relaxed);
+        let (register, rank) = sketch_register(key);
+        self.current.keys[register].fetch_max(rank, Ordering::Relaxed);
+        if !self.dirty.load(Ordering::Relaxed) {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Move the current hour to the closed ones if it is before `hour`.
+    fn close_hours_before(&self, inner: &mut Inner, hour: u64) {
+        // Checked again under the lock, another access may have closed it.
+        if hour <= self.current.hour.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(rollup) = self.current.close(hour) {
+            inner.hours.push_back(rollup);
+        }
+    }
+
+    /// The rollups of the hours within the retention, the current one included.
+    fn hours_at(&self, inner: &mut Inner, now: SystemTime) -> Vec<HourlyAccess> {
+        self.close_hours_before(inner, hour_of(now));
+        inner.expire(now, self.retention);
+        inner
+            .hours
+            .iter()
+            .cloned()
+            .chain(self.current.rollup())
+            .collect()
+    }
+
+    pub(crate) fn get(&self) -> TimelineAccessLog {
+        self.get_at(SystemTime::now())
+    }
+
+    fn get_at(&self, now: SystemTime) -> TimelineAccessLog {
+        let mut inner = self.inner.lock().unwrap();
+        TimelineAccessLog {
+            retention: self.retention,
+            hours: self.hours_at(&mut inner, now),
+        }
+    }
+
+    pub(crate) fn activity(&self) -> Activity {
+        self.activity_at(SystemTime::now())
+    }
+
+    fn activity_at(&self, now: SystemTime) -> Activity {
+        let mut inner = self.inner.lock().unwrap();
+        let hours = self.hours_at(&mut inner, now);
+        let last_hour_with = |accessed: fn(&HourlyAccess) -> bool| {
+            hours.iter().rev().find(|h| accessed(h)).map(|h| h.hour)
+        };
+        Activity {
+            tracked_since: inner.tracked_since,
+            last_read: last_hour_with(|h| h.pages_read > 0),
+            last_write: last_hour_with(|h| h.bytes_ingested > 0),
+        }
+    }
+
+    /// The content of the file to persist, if there were accesses since the last time.
+    fn take_dirty(&self, now: SystemTime) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut inner = self.inner.lock().unwrap();
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let file = AccessLogFile {
+            hours: self.hours_at(&mut inner, now),
+            last_hour_keys: self.current.keys().0.to_vec(),
+            tracked_since: Some(inner.tracked_since),
+        };
+        match serde_json::to_vec(&file).context("serialize access log") {
+            Ok(content) => Ok(Some(content)),
+            Err(e) => {
+                self.mark_dirty();
+                Err(e)
+            }
+        }
+    }
+
+    fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Restore the rollups read from the timeline directory.
+    fn restore(&self, mut file: AccessLogFile) -> anyhow::Result<()> {
+        let registers = <[u8; SKETCH_REGISTERS]>::try_from(file.last_hour_keys)
+            .map_err(|v| anyhow::anyhow!("sketch has {} registers", v.len()))?;
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(tracked_since) = file.tracked_since {
+            inner.tracked_since = inner.tracked_since.min(tracked_since);
+        }
+        if !inner.hours.is_empty() || self.current.rollup().is_some() {
+            // There were accesses before the layer map was loaded: keep them rather than mix
+            // them with the restored sketch.
+            return Ok(());
+        }
+        // The last hour keeps being counted if it is still the current one.
+        let last_hour_keys = KeySketch(registers);
+        if !last_hour_keys.is_empty() {
+            if let Some(last) = file.hours.pop() {
+                let current = &self.current;
+                current.hour.store(hour_of(last.hour), Ordering::Relaxed);
+                current.pages_read.store(last.pages_read, Ordering::Relaxed);
+                current
+                    .bytes_ingested
+                    .store(last.bytes_ingested, Ordering::Relaxed);
+                for (register, rank) in current.keys.iter().zip(last_hour_keys.0) {
+                    register.store(rank, Ordering::Relaxed);
+                }
+            }
+        }
+        inner.hours = file.hours.into();
+        Ok(())
+    }
+}
+
+/// Read the access log of the timeline directory `timeline_path`, and restore it into `log`.
+pub(super) fn load_blocking(log: &AccessLog, timeline_path: &Utf8Path) -> anyhow::Result<()> {
+    let path = timeline_path.join(ACCESS_LOG_FILE_NAME);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            // Persist since when the accesses are tracked, even if there are none.
+            if !log.retention.is_zero() {
+                log.mark_dirty();
+            }
+            return Ok(());
+        }
+        Err(e) => return Err(e).with_context(|| format!("read {path}")),
+    };
+    let file = serde_json::from_slice(&bytes).with_context(|| format!("parse {path}"))?;
+    log.restore(file)
+}
+
+impl Timeline {
+    /// Persist the access log, if there were accesses since it was last persisted.
+    pub(crate) async fn persist_access_log(&self) -> anyhow::Result<()> {
+        let Some(content) = self.access_log.take_dirty(SystemTime::now())? else {
+            return Ok(());
+        };
+        let path = self
+            .conf
+            .timeline_path(&self.tenant_shard_id, &self.timeline_id)
+            .join(ACCESS_LOG_FILE_NAME);
+        let temp_path = path_with_suffix_extension(&path, TEMP_FILE_SUFFIX);
+        if let Err(e) = VirtualFile::crashsafe_overwrite(path, temp_path, content).await {
+            self.access_log.mark_dirty();
+            return Err(e).context("write access log");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(n: u32) -> Key {
+        Key::from_i128(0x1000 + n as i128)
+    }
+
+    #[test]
+    fn sketch_estimate() {
+        let mut sketch = KeySketch::default();
+        assert_eq!(sketch.estimate(), 0);
+        for n in 0..10 {
+            sketch.add(&key(n));
+            sketch.add(&key(n));
+        }
+        assert_eq!(sketch.estimate(), 10);
+
+        for n in 10..100_000 {
+            sketch.add(&key(n));
+        }
+        let estimate = sketch.estimate();
+        assert!(
+            (80_000..120_000).contains(&estimate),
+            "estimate {estimate} is more than 20% off"
+        );
+    }
+
+    #[test]
+    fn rollups() {
+        let log = AccessLog::new(2 * HOUR);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000 * 3600);
+        let minute = Duration::from_secs(60);
+
+        log.record_at(start + minute, &key(1), 1, 0);
+        log.record_at(start + 2 * minute, &key(1), 0, 100);
+        log.record_at(start + 59 * minute, &key(2), 1, 0);
+        log.record_at(start + 3 * HOUR, &key(3), 0, 10);
+
+        let hours = log.get_at(start + 3 * HOUR).hours;
+        assert_eq!(
+            hours,
+            vec![
+                HourlyAccess {
+                    hour: start,
+                    pages_read: 2,
+                    bytes_ingested: 100,
+                    unique_keys: 2,
+                },
+                HourlyAccess {
+                    hour: start + 3 * HOUR,
+                    pages_read: 0,
+                    bytes_ingested: 10,
+                    unique_keys: 1,
+                },
+            ]
+        );
+
+        // Persist and restore, then keep counting the distinct keys of the last hour.
+        let content = log.take_dirty(start + 3 * HOUR).unwrap().unwrap();
+        assert!(log.take_dirty(start + 3 * HOUR).unwrap().is_none());
+        let restored = AccessLog::new(2 * HOUR);
+        restored
+            .restore(serde_json::from_slice(&content).unwrap())
+            .unwrap();
+        restored.record_at(start + 3 * HOUR + minute, &key(3), 1, 0);
+        restored.record_at(start + 3 * HOUR + minute, &key(4), 1, 0);
+        let hours = restored.get_at(start + 3 * HOUR + minute).hours;
+        assert_eq!(hours[0], log.get_at(start + 3 * HOUR).hours[0]);
+        assert_eq!(hours[1].pages_read, 2);
+        assert_eq!(hours[1].unique_keys, 2);
+
+        // The first hour ends more than the retention ago.
+        assert_eq!(restored.get_at(start + 4 * HOUR).hours.len(), 1);
+        assert!(restored.get_at(start + 6 * HOUR).hours.is_empty());
+    }
+
+    #[test]
+    fn activity() {
+        let log = AccessLog::new(2 * HOUR);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000 * 3600);
+        log.record_at(start, &key(1), 1, 0);
+        log.record_at(start + HOUR, &key(1), 0, 10);
+
+        let activity = log.activity_at(start + HOUR);
+        assert_eq!(activity.last_read, Some(start));
+        assert_eq!(activity.last_write, Some(start + HOUR));
+
+        // The restored log keeps the oldest start of the tracking.
+        let content = log.take_dirty(start + HOUR).unwrap().unwrap();
+        let mut file: AccessLogFile = serde_json::from_slice(&content).unwrap();
+        assert!(file.tracked_since.is_some());
+        file.tracked_since = Some(start);
+        let restored = AccessLog::new(2 * HOUR);
+        restored.restore(file).unwrap();
+        assert_eq!(restored.activity_at(start + HOUR).tracked_since, start);
+
+        let activity = restored.activity_at(start + 4 * HOUR);
+        assert_eq!(activity.last_read, None);
+        assert_eq!(activity.last_write, None);
+    }
+
+    #[test]
+    fn disabled() {
+        let log = AccessLog::new(Duration::ZERO);
+        log.record_read(&key(1));
+        assert!(log.get().hours.is_empty());
+        assert!(log.take_dirty(SystemTime::now()).unwrap().is_none());
+    }
+}
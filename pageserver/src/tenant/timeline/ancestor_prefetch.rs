@@ -0,0 +1,164 @@
+//! Prefetch of the ancestor layers that reads on a branch depend on.
+//!
+//! Reads on a branch below its branch point are served from the layers of the ancestor
+//! timelines. Those layers are evicted by the ancestor's eviction task and by the disk usage
+//! eviction task like any other layer, and a read on the branch that needs an evicted one
+//! waits for its on-demand download. A branch that keeps reading the same ancestor layers pays
+//! for that download every time they get evicted.
+//!
+//! Each timeline counts the reads it does in the layers of its ancestors, in
+//! [`AncestorLayerHits`]. Its eviction task then periodically downloads the ancestor layers
+//! that were hit at least [`MIN_HITS`] times and aren't resident, and accounts their size to
+//! the branch, not to the ancestor, in `pageserver_ancestor_prefetch_bytes`. The hit counts
+//! are halved on every iteration, so that the layers a branch stopped reading are eventually
+//! left evicted. Layers evicted within `eviction_bounce_window` are left alone: they were
+//! most likely evicted by the disk usage based eviction, and downloading them again would
+//! work against it.
+//!
+//! Enabled with the `ancestor_prefetch` tenant config.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use utils::id::TimelineId;
+
+use crate::tenant::storage_layer::{AsLayerDesc as _, LayerName};
+
+use super::Timeline;
+
+/// Number of reads since the previous prefetch iteration, after decay, from which an ancestor
+/// layer is prefetched.
+const MIN_HITS: u64 = 8;
+
+/// Reads of a timeline in the layers of its ancestors. Recorded on every visit of an
+/// ancestor layer by a read, so the counts are sharded, and only the first read of a layer
+/// takes a shard for writing.
+#[derive(Default)]
+pub(crate) struct AncestorLayerHits {
+    hits: DashMap<(TimelineId, LayerName), AtomicU64>,
+}
+
+impl AncestorLayerHits {
+    pub(super) fn record(&self, ancestor_timeline_id: TimelineId, layer_name: LayerName) {
+        let key = (ancestor_timeline_id, layer_name);
+        if let Some(count) = self.hits.get(&key) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.hits
+            .entry(key)
+            .or_default()
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The layers hit at least `min_hits` times, by ancestor timeline. Halves all counts.
+    fn take_hot(&self, min_hits: u64) -> HashMap<TimelineId, Vec<LayerName>> {
+        let mut hot: HashMap<TimelineId, Vec<LayerName>> = HashMap::new();
+        self.hits.retain(|(timeline_id, layer_name), count| {
+            let count = count.get_mut();
+            if *count >= min_hits {
+                hot.entry(*timeline_id)
+                    .or_default()
+                    .push(layer_name.clone());
+            }
+            *count /= 2;
+            *count > 0
+        });
+        hot
+    }
+}
+
+impl Timeline {
+    /// Download the evicted ancestor layers that reads on this timeline hit often, see the
+    /// module-level comment. Returns the number of bytes downloaded.
+    pub(super) async fn prefetch_ancestor_layers(&self, cancel: &CancellationToken) -> u64 {
+        let hot = self.ancestor_layer_hits.take_hot(MIN_HITS);
+        if hot.is_empty() || !self.get_ancestor_prefetch() {
+            return 0;
+        }
+
+        let mut downloaded_bytes = 0;
+        let mut downloaded_layers = 0;
+        let mut ancestor = self.ancestor_timeline.clone();
+        while let Some(timeline) = ancestor {
+            for layer_name in hot.get(&timeline.timeline_id).into_iter().flatten() {
+                if cancel.is_cancelled() {
+                    return downloaded_bytes;
+                }
+                // The layer may have been compacted or garbage collected since it was hit.
+                let Some(layer) = timeline.find_layer(layer_name).await else {
+                    continue;
+                };
+                if layer.is_likely_resident() {
+                    continue;
+                }
+                if layer
+                    .since_last_eviction()
+                    .is_some_and(|since| since < self.conf.eviction_bounce_window)
+                {
+                    continue;
+                }
+                match layer.download().await {
+                    Ok(()) => {
+                        let size = layer.layer_desc().file_size;
+                        self.metrics.ancestor_prefetch_bytes.inc_by(size);
+                        downloaded_bytes += size;
+                        downloaded_layers += 1;
+                    }
+                    Err(e) => {
+                        warn!(ancestor_timeline_id=%timeline.timeline_id, %layer, "failed to prefetch ancestor layer: {e:#}");
+                    }
+                }
+            }
+            ancestor = timeline.ancestor_timeline.clone();
+        }
+
+        if downloaded_layers > 0 {
+            info!(
+                downloaded_layers,
+                downloaded_bytes, "prefetched ancestor layers"
+            );
+        }
+        downloaded_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn hits_decay() {
+        let hits = AncestorLayerHits::default();
+        let timeline_id = TimelineId::generate();
+        let layer_name = LayerName::from_str(
+            "000000000000000000000000000000000000-000000067F00000001000004DF0000000006__00000000014FED58-000000000154C481",
+        )
+        .unwrap();
+
+        for _ in 0..MIN_HITS * 2 {
+            hits.record(timeline_id, layer_name.clone());
+        }
+
+        // Hot in the first two iterations, then cold until it's hit again.
+        assert_eq!(
+            hits.take_hot(MIN_HITS)[&timeline_id],
+            vec![layer_name.clone()]
+        );
+        assert_eq!(
+            hits.take_hot(MIN_HITS)[&timeline_id],
+            vec![layer_name.clone()]
+        );
+        assert!(hits.take_hot(MIN_HITS).is_empty());
+
+        for _ in 0..MIN_HITS {
+            hits.record(timeline_id, layer_name.clone());
+        }
+        assert_eq!(hits.take_hot(MIN_HITS)[&timeline_id], vec![layer_name]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Image layers at the branch point of new branches.
+//!
+//! A new branch has no layers of its own: reads of pages that weren't modified on the branch
+//! go to the ancestor, at the branch point. If the branch was created at an old LSN, the
+//! ancestor's page versions there are often at the bottom of long delta chains, and reads on
+//! the branch pay for reconstructing them until compaction of the branch creates images,
+//! which only happens once enough WAL was written to the branch itself.
+//!
+//! With the `branch_image_creation_threshold` tenant config option, a new branch creates image
+//! layers at its branch point in the background: for the whole keyspace if it is smaller than
+//! the threshold, otherwise for the partitions where the ancestor's delta chains at the branch
+//! point are deep enough that compaction would create images for them.
+
+use std::sync::Arc;
+
+use tracing::{info, info_span, warn, Instrument};
+
+use super::{ImageLayerCreationMode, Timeline};
+use crate::context::{DownloadBehavior, RequestContext};
+use crate::page_cache::PAGE_SZ;
+use crate::task_mgr::{self, TaskKind, BACKGROUND_RUNTIME};
+
+impl Timeline {
+    /// Create image layers at the branch point of this new branch in the background, if
+    /// configured.
+    pub(crate) fn launch_branch_image_creation(self: &Arc<Self>, ctx: &RequestContext) {
+        if self.ancestor_timeline.is_none() || self.get_branch_image_creation_threshold() == 0 {
+            return;
+        }
+
+        let self_clone = Arc::clone(self);
+        let ctx = ctx.detached_child(TaskKind::BranchImageCreation, DownloadBehavior::Download);
+        task_mgr::spawn(
+            BACKGROUND_RUNTIME.handle(),
+            TaskKind::BranchImageCreation,
+            Some(self.tenant_shard_id),
+            Some(self.timeline_id),
+            "branch image creation",
+            false,
+            async move {
+                let Ok(_guard) = self_clone.gate.enter() else {
+                    return Ok(());
+                };
+                if let Err(e) = self_clone.create_branch_images(&ctx).await {
+                    if !self_clone.cancel.is_cancelled() {
+                        warn!("failed to create image layers at the branch point: {e:#}");
+                    }
+                }
+                Ok(())
+            }
+            .instrument(info_span!(
+                "branch_image_creation",
+                tenant_id = %self.tenant_shard_id.tenant_id,
+                shard_id = %self.tenant_shard_id.shard_slug(),
+                timeline_id = %self.timeline_id,
+            )),
+        );
+    }
+
+    async fn create_branch_images(self: &Arc<Self>, ctx: &RequestContext) -> anyhow::Result<()> {
+        let lsn = self.ancestor_lsn;
+
+        // Keeps compaction from repartitioning and creating images at the same time.
+        let _compaction_guard = tokio::select! {
+            guard = self.compaction_lock.lock() => guard,
+            _ = self.cancel.cancelled() => return Ok(()),
+        };
+
+        let ((dense_partitioning, _), _) = self
+            .repartition(
+                lsn,
+                self.get_compaction_target_size(),
+                Default::default(),
+                ctx,
+            )
+            .await?;
+        let keyspace_size = dense_partitioning
+            .parts
+            .iter()
+            .map(|part| part.total_raw_size() as u64)
+            .sum::<u64>()
+            * PAGE_SZ as u64;
+        let mode = if keyspace_size < self.get_branch_image_creation_threshold() {
+            ImageLayerCreationMode::Force
+        } else {
+            ImageLayerCreationMode::Branch
+        };
+
+        let image_layers = self
+            .create_image_layers(&dense_partitioning, lsn, mode, ctx)
+            .await?;
+        info!(
+            "created {} image layers at branch point {lsn}, keyspace size {keyspace_size}",
+            image_layers.len()
+        );
+        self.upload_new_image_layers(image_layers)?;
+        Ok(())
+    }
+}
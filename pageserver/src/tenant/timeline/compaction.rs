@@ -7,7 +7,9 @@
 use std::collections::BinaryHeap;
 use std::ops::{Deref, Range};
 use std::sync::Arc;
+use std::time::Instant;
 
+use super::key_stats::{self, KeySpaceStatsAccum};
 use super::layer_manager::LayerManager;
 use super::{CompactFlags, DurationRecorder, ImageLayerCreationMode, RecordedDuration, Timeline};
 
@@ -22,13 +24,13 @@ use tracing::{debug, info, info_span, trace, warn, Instrument};
 use utils::id::TimelineId;
 
 use crate::context::{AccessStatsBehavior, RequestContext, RequestContextBuilder};
+use crate::metrics::StorageTimeOperation;
 use crate::tenant::storage_layer::{AsLayerDesc, PersistentLayerDesc};
 use crate::tenant::timeline::{drop_rlock, is_rel_fsm_block_key, is_rel_vm_block_key, Hole};
 use crate::tenant::timeline::{DeltaLayerWriter, ImageLayerWriter};
 use crate::tenant::timeline::{Layer, ResidentLayer};
 use crate::tenant::DeltaLayer;
 use crate::tenant::PageReconstructError;
-use crate::virtual_file::{MaybeFatalIo, VirtualFile};
 use crate::{page_cache, ZERO_PAGE};
 
 use crate::keyspace::KeySpace;
@@ -115,7 +117,8 @@ impl Timeline {
                 timer.stop_and_record();
 
                 // 3. Create new image layers for partitions that have been modified
-                // "enough".
+                // "enough", or that have pathological stacks of delta layers.
+                self.schedule_overlap_repairs().await;
                 let dense_layers = self
                     .create_image_layers(
                         &dense_partitioning,
@@ -145,6 +148,7 @@ impl Timeline {
                     .await
                     .map_err(anyhow::Error::from)?;
                 assert!(sparse_layers.is_empty());
+                self.overlap_repairs.clear();
 
                 self.upload_new_image_layers(dense_layers)?;
                 dense_partitioning.parts.len()
@@ -343,6 +347,9 @@ impl Timeline {
 
         self.finish_compact_batch(&new_layers, &Vec::new(), &deltas_to_compact)
             .await?;
+        // Only now that the new layers are in the layer map, they are safe from the cleanup of
+        // local-only layers on restart.
+        self.remove_compaction_checkpoint().await?;
         Ok(())
     }
 
@@ -529,12 +536,33 @@ impl Timeline {
         holes.sort_unstable_by_key(|hole| hole.key_range.start);
         let mut next_hole = 0; // index of next hole in holes vector
 
+        // Resume an interrupted compaction of the same deltas, see [`super::compaction_checkpoint`].
+        // This reads the layer map if it has to abandon a checkpoint, so we must not hold the
+        // read lock anymore.
+        let (mut new_layers, resume_key, mut checkpointer) = self
+            .resume_level0_compaction(
+                deltas_to_compact
+                    .iter()
+                    .map(|l| l.layer_desc().layer_name())
+                    .collect(),
+                target_file_size,
+            )
+            .await?;
+        let resume_from = match resume_key {
+            Some(resume_key) => {
+                next_hole = holes.partition_point(|hole| hole.key_range.end <= resume_key);
+                all_keys.partition_point(|DeltaEntry { key, .. }| *key < resume_key)
+            }
+            None => 0,
+        };
+        let remaining_keys = &all_keys[resume_from..];
+
         // This iterator walks through all key-value pairs from all the layers
         // we're compacting, in key, LSN order.
-        let all_values_iter = all_keys.iter();
+        let all_values_iter = remaining_keys.iter();
 
         // This iterator walks through all keys and is needed to calculate size used by each key
-        let mut all_keys_iter = all_keys
+        let mut all_keys_iter = remaining_keys
             .iter()
             .map(|DeltaEntry { key, lsn, size, .. }| (*key, *lsn, *size))
             .coalesce(|mut prev, cur| {
@@ -596,15 +624,23 @@ impl Timeline {
         //
         // TODO: we should also opportunistically materialize and
         // garbage collect what we can.
-        let mut new_layers = Vec::new();
+        let write_started_at = Instant::now();
+        // GC keeps the layers that contain keys of pinned relations, so they get layers of
+        // their own.
+        let pinned_key_ranges = self.get_gc_pinned_key_ranges();
+        let is_pinned = |key: &Key| pinned_key_ranges.iter().any(|range| range.contains(key));
         let mut prev_key: Option<Key> = None;
         let mut writer: Option<DeltaLayerWriter> = None;
         let mut key_values_total_size = 0u64;
         let mut dup_start_lsn: Lsn = Lsn::INVALID; // start LSN of layer containing values of the single key
         let mut dup_end_lsn: Lsn = Lsn::INVALID; // end LSN of layer containing values of the single key
 
+        let mut key_stats = KeySpaceStatsAccum::default();
         for &DeltaEntry {
-            key, lsn, ref val, ..
+            key,
+            lsn,
+            size,
+            ref val,
         } in all_values_iter
         {
             let value = val.load(ctx).await?;
@@ -652,18 +688,23 @@ impl Timeline {
                     let written_size = writer.as_mut().unwrap().size();
                     let contains_hole =
                         next_hole < holes.len() && key >= holes[next_hole].key_range.end;
-                    // check if key cause layer overflow or contains hole...
+                    let crosses_pin = !same_key
+                        && prev_key
+                            .map_or(false, |prev_key| is_pinned(&prev_key) != is_pinned(&key));
+                    // check if key cause layer overflow, contains hole or crosses the boundary
+                    // of a pinned relation...
                     if is_dup_layer
                         || dup_end_lsn.is_valid()
                         || written_size + key_values_total_size > target_file_size
                         || contains_hole
+                        || crosses_pin
                     {
                         // ... if so, flush previous layer and prepare to write new one
                         new_layers.push(
                             writer
                                 .take()
                                 .unwrap()
-                                .finish(prev_key.unwrap().next(), self, ctx)
+                                .finish_unsynced(prev_key.unwrap().next(), self, ctx)
                                 .await?,
                         );
                         writer = None;
@@ -672,6 +713,11 @@ impl Timeline {
                             // skip hole
                             next_hole += 1;
                         }
+
+                        if !same_key {
+                            // All the versions of the keys below this one are in `new_layers`.
+                            checkpointer.maybe_save(self, &new_layers, key).await?;
+                        }
                     }
                 }
                 // Remember size of key value because at next iteration we will access next item
@@ -705,6 +751,7 @@ impl Timeline {
                     );
                 }
 
+                key_stats.add(&key, lsn, size);
                 writer
                     .as_mut()
                     .unwrap()
@@ -725,43 +772,35 @@ impl Timeline {
             prev_key = Some(key);
         }
         if let Some(writer) = writer {
-            new_layers.push(writer.finish(prev_key.unwrap().next(), self, ctx).await?);
+            new_layers.push(
+                writer
+                    .finish_unsynced(prev_key.unwrap().next(), self, ctx)
+                    .await?,
+            );
         }
 
         // Sync layers
-        if !new_layers.is_empty() {
-            // Print a warning if the created layer is larger than double the target size
-            // Add two pages for potential overhead. This should in theory be already
-            // accounted for in the target calculation, but for very small targets,
-            // we still might easily hit the limit otherwise.
-            let warn_limit = target_file_size * 2 + page_cache::PAGE_SZ as u64 * 2;
-            for layer in new_layers.iter() {
-                if layer.layer_desc().file_size > warn_limit {
-                    warn!(
-                        %layer,
-                        "created delta file of size {} larger than double of target of {target_file_size}", layer.layer_desc().file_size
-                    );
-                }
+        self.fsync_new_layers(
+            &new_layers,
+            StorageTimeOperation::Compact,
+            write_started_at.elapsed(),
+        )
+        .await;
+        self.key_space_stats
+            .record(key_stats::Source::Compaction, key_stats);
+
+        // Print a warning if the created layer is larger than double the target size
+        // Add two pages for potential overhead. This should in theory be already
+        // accounted for in the target calculation, but for very small targets,
+        // we still might easily hit the limit otherwise.
+        let warn_limit = target_file_size * 2 + page_cache::PAGE_SZ as u64 * 2;
+        for layer in new_layers.iter() {
+            if layer.layer_desc().file_size > warn_limit {
+                warn!(
+                    %layer,
+                    "created delta file of size {} larger than double of target of {target_file_size}", layer.layer_desc().file_size
+                );
             }
-
-            // The writer.finish() above already did the fsync of the inodes.
-            // We just need to fsync the directory in which these inodes are linked,
-            // which we know to be the timeline directory.
-            //
-            // We use fatal_err() below because the after writer.finish() returns with success,
-            // the in-memory state of the filesystem already has the layer file in its final place,
-            // and subsequent pageserver code could think it's durable while it really isn't.
-            let timeline_dir = VirtualFile::open(
-                &self
-                    .conf
-                    .timeline_path(&self.tenant_shard_id, &self.timeline_id),
-            )
-            .await
-            .fatal_err("VirtualFile::open for timeline dir fsync");
-            timeline_dir
-                .sync_all()
-                .await
-                .fatal_err("VirtualFile::sync_all timeline dir");
         }
 
         stats.write_layer_files_micros = stats.read_lock_drop_micros.till_now();
@@ -0,0 +1,427 @@
+//! Checkpoints of an in-progress L0 compaction, to resume it after a restart.
+//!
+//! Compacting a large backlog of L0 deltas can take hours. The output layers it writes are
+//! local-only until the compaction finishes and they are uploaded, and local-only layers are
+//! deleted when the layer map is loaded: a restart used to throw all of that work away.
+//!
+//! To resume instead, the L0 compaction periodically records its progress in
+//! [`COMPACTION_CHECKPOINT_FILE_NAME`] in the timeline directory: the L0 deltas it compacts,
+//! the output layers written so far, which are fsynced first, and the key to continue from.
+//! Checkpoints are only taken between two keys, when the output layers written so far contain
+//! all the versions of the keys below the key to continue from.
+//!
+//! When the layer map is loaded, the outputs listed in the checkpoint are kept on disk instead
+//! of being deleted as local-only layers. The next L0 compaction resumes from the checkpoint
+//! if it selects the same L0 deltas, with the same target file size. Otherwise the checkpoint
+//! is abandoned, and its outputs that didn't make it into the layer map are deleted. The
+//! checkpoint is removed once the result of the compaction is in the layer map.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utils::crashsafe::path_with_suffix_extension;
+use utils::fs_ext;
+
+use crate::repository::Key;
+use crate::tenant::remote_timeline_client::index::LayerFileMetadata;
+use crate::tenant::storage_layer::{AsLayerDesc as _, LayerName};
+use crate::tenant::{crash_points, par_fsync};
+use crate::virtual_file::VirtualFile;
+use crate::TEMP_FILE_SUFFIX;
+
+use super::{Layer, ResidentLayer, Timeline};
+
+pub(crate) const COMPACTION_CHECKPOINT_FILE_NAME: &str = "compaction_checkpoint.json";
+
+/// Minimum time between two checkpoints, which bounds the cost of the fsyncs they need.
+#[cfg(not(test))]
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+/// The unit tests checkpoint after every output layer.
+#[cfg(test)]
+const CHECKPOINT_INTERVAL: Duration = Duration::ZERO;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CompactionCheckpoint {
+    /// The L0 deltas being compacted, in LSN order.
+    pub(crate) inputs: Vec<LayerName>,
+    pub(crate) target_file_size: u64,
+    /// The output layers written so far, with their file sizes.
+    pub(crate) outputs: Vec<(LayerName, u64)>,
+    /// The compaction continues from the first key at or above this one.
+    pub(crate) resume_key: Key,
+}
+
+/// Read the checkpoint of the timeline directory `timeline_path`, if there is one.
+pub(super) fn read_blocking(
+    timeline_path: &Utf8Path,
+) -> anyhow::Result<Option<CompactionCheckpoint>> {
+    let path = timeline_path.join(COMPACTION_CHECKPOINT_FILE_NAME);
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("read {path}")),
+    };
+    let checkpoint = serde_json::from_slice(&bytes).with_context(|| format!("parse {path}"))?;
+    Ok(Some(checkpoint))
+}
+
+/// The output layers listed in the checkpoint of `timeline_path`, which must not be deleted
+/// as local-only layers when the layer map is loaded.
+pub(super) fn outputs_to_keep(timeline_path: &Utf8Path) -> HashSet<LayerName> {
+    match read_blocking(timeline_path) {
+        Ok(Some(checkpoint)) => checkpoint
+            .outputs
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+        Ok(None) => HashSet::new(),
+        Err(e) => {
+            // The next compaction will abandon it, and the outputs are just local-only layers.
+            warn!("failed to read the compaction checkpoint: {e:#}");
+            HashSet::new()
+        }
+    }
+}
+
+/// Takes the checkpoints of a running L0 compaction.
+pub(super) struct Checkpointer {
+    inputs: Vec<LayerName>,
+    target_file_size: u64,
+    /// Number of output layers already fsynced.
+    synced: usize,
+    last_saved_at: Instant,
+}
+
+impl Checkpointer {
+    /// Record that the compaction wrote `outputs`, and continues from `resume_key`, if the
+    /// previous checkpoint is old enough.
+    pub(super) async fn maybe_save(
+        &mut self,
+        timeline: &Timeline,
+        outputs: &[ResidentLayer],
+        resume_key: Key,
+    ) -> anyhow::Result<()> {
+        if self.last_saved_at.elapsed() < CHECKPOINT_INTERVAL {
+            return Ok(());
+        }
+
+        // The outputs must be durable before the checkpoint that lists them is. Writing the
+        // checkpoint fsyncs the timeline directory, which makes their renames durable.
+        let paths = outputs[self.synced..]
+            .iter()
+            .map(|l| l.local_path())
+            .collect::<Vec<_>>();
+        par_fsync::par_fsync(&paths)
+            .await
+            .context("fsync compaction outputs")?;
+        self.synced = outputs.len();
+
+        let checkpoint = CompactionCheckpoint {
+            inputs: self.inputs.clone(),
+            target_file_size: self.target_file_size,
+            outputs: outputs
+                .iter()
+                .map(|l| (l.layer_desc().layer_name(), l.layer_desc().file_size))
+                .collect(),
+            resume_key,
+        };
+        crash_points::check(&timeline.tenant_shard_id, "write compaction checkpoint")?;
+        let path = timeline.compaction_checkpoint_path();
+        let temp_path = path_with_suffix_extension(&path, TEMP_FILE_SUFFIX);
+        let content = serde_json::to_vec(&checkpoint).context("serialize compaction checkpoint")?;
+        VirtualFile::crashsafe_overwrite(path, temp_path, content)
+            .await
+            .context("write compaction checkpoint")?;
+
+        self.last_saved_at = Instant::now();
+        Ok(())
+    }
+}
+
+impl Timeline {
+    fn compaction_checkpoint_path(&self) -> Utf8PathBuf {
+        self.conf
+            .timeline_path(&self.tenant_shard_id, &self.timeline_id)
+            .join(COMPACTION_CHECKPOINT_FILE_NAME)
+    }
+
+    /// Start an L0 compaction of `inputs`: resume it from the checkpoint if there is one for
+    /// the same inputs, otherwise abandon the checkpoint.
+    ///
+    /// Returns the output layers of the resumed compaction and the key to continue from, and
+    /// the [`Checkpointer`] for the rest of the compaction.
+    pub(super) async fn resume_level0_compaction(
+        self: &Arc<Self>,
+        inputs: Vec<LayerName>,
+        target_file_size: u64,
+    ) -> anyhow::Result<(Vec<ResidentLayer>, Option<Key>, Checkpointer)> {
+        let timeline_path = self
+            .conf
+            .timeline_path(&self.tenant_shard_id, &self.timeline_id);
+        let checkpoint = tokio::task::spawn_blocking(move || read_blocking(&timeline_path))
+            .await
+            .context("spawn_blocking")?;
+
+        let mut resumed = Vec::new();
+        let mut resume_key = None;
+        match checkpoint {
+            Ok(Some(checkpoint))
+                if checkpoint.inputs == inputs
+                    && checkpoint.target_file_size == target_file_size =>
+            {
+                match self.open_checkpoint_outputs(&checkpoint).await {
+                    Ok(outputs) => {
+                        info!(
+                            outputs = outputs.len(),
+                            resume_key = %checkpoint.resume_key,
+                            "resuming L0 compaction from checkpoint"
+                        );
+                        resumed = outputs;
+                        resume_key = Some(checkpoint.resume_key);
+                    }
+                    Err(e) => {
+                        warn!("abandoning the compaction checkpoint: {e:#}");
+                        self.abandon_compaction_checkpoint(&checkpoint).await?;
+                    }
+                }
+            }
+            Ok(Some(checkpoint)) => {
+                info!("abandoning the compaction checkpoint, the L0 deltas to compact changed");
+                self.abandon_compaction_checkpoint(&checkpoint).await?;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!("abandoning the compaction checkpoint: {e:#}");
+                self.remove_compaction_checkpoint().await?;
+            }
+        }
+
+        let checkpointer = Checkpointer {
+            inputs,
+            target_file_size,
+            synced: resumed.len(),
+            last_saved_at: Instant::now(),
+        };
+        Ok((resumed, resume_key, checkpointer))
+    }
+
+    async fn open_checkpoint_outputs(
+        self: &Arc<Self>,
+        checkpoint: &CompactionCheckpoint,
+    ) -> anyhow::Result<Vec<ResidentLayer>> {
+        let timeline_path = self
+            .conf
+            .timeline_path(&self.tenant_shard_id, &self.timeline_id);
+        let mut outputs = Vec::with_capacity(checkpoint.outputs.len());
+        for (name, file_size) in &checkpoint.outputs {
+            let path = timeline_path.join(name.to_string());
+            let metadata = tokio::fs::metadata(&path)
+                .await
+                .with_context(|| format!("stat output layer {name}"))?;
+            anyhow::ensure!(
+                metadata.len() == *file_size,
+                "output layer {name} has size {}, expected {file_size}",
+                metadata.len()
+            );
+            let metadata =
+                LayerFileMetadata::new(*file_size, self.generation, self.get_shard_index());
+            outputs.push(Layer::for_resident(
+                self.conf,
+                self,
+                path,
+                name.clone(),
+                metadata,
+            ));
+        }
+        Ok(outputs)
+    }
+
+    /// Delete the outputs of an abandoned checkpoint that didn't make it into the layer map, and
+    /// the checkpoint itself.
+    async fn abandon_compaction_checkpoint(
+        &self,
+        checkpoint: &CompactionCheckpoint,
+    ) -> anyhow::Result<()> {
+        let orphans = {
+            let guard = self.layers.read().await;
+            let layer_map = guard.layer_map();
+            let in_layer_map = layer_map
+                .iter_historic_layers()
+                .map(|desc| desc.layer_name())
+                .collect::<HashSet<_>>();
+            checkpoint
+                .outputs
+                .iter()
+                .filter(|(name, _)| !in_layer_map.contains(name))
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let timeline_path = self
+            .conf
+            .timeline_path(&self.tenant_shard_id, &self.timeline_id);
+        for name in &orphans {
+            tokio::fs::remove_file(timeline_path.join(name.to_string()))
+                .await
+                .or_else(fs_ext::ignore_not_found)
+                .with_context(|| format!("delete orphan compaction output {name}"))?;
+        }
+        if !orphans.is_empty() {
+            info!(
+                "deleted {} orphan outputs of the abandoned compaction",
+                orphans.len()
+            );
+        }
+
+        self.remove_compaction_checkpoint().await
+    }
+
+    /// Remove the checkpoint once the compaction it tracks is done or abandoned.
+    pub(super) async fn remove_compaction_checkpoint(&self) -> anyhow::Result<()> {
+        tokio::fs::remove_file(self.compaction_checkpoint_path())
+            .await
+            .or_else(fs_ext::ignore_not_found)
+            .context("remove compaction checkpoint")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+    use std::str::FromStr;
+
+    use enumset::EnumSet;
+    use tokio_util::sync::CancellationToken;
+    use tracing::Instrument;
+    use utils::lsn::Lsn;
+
+    use super::*;
+    use crate::repository::Value;
+    use crate::tenant::config::TenantConf;
+    use crate::tenant::harness::{put_and_flush, test_img, TenantHarness, TIMELINE_ID};
+    use crate::tenant::timeline::ShutdownMode;
+    use crate::DEFAULT_PG_VERSION;
+
+    #[test]
+    fn outputs_of_checkpoint_are_kept() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        assert!(outputs_to_keep(dir.path()).is_empty());
+
+        let output = LayerName::from_str(
+            "000000000000000000000000000000000000-000000067F00000001000004DF0000000006__00000000014FED58-000000000154C481",
+        )
+        .unwrap();
+        let checkpoint = CompactionCheckpoint {
+            inputs: Vec::new(),
+            target_file_size: 128 * 1024 * 1024,
+            outputs: vec![(output.clone(), 8192)],
+            resume_key: Key::from_hex("000000067F00000001000004DF0000000007").unwrap(),
+        };
+        std::fs::write(
+            dir.path().join(COMPACTION_CHECKPOINT_FILE_NAME),
+            serde_json::to_vec(&checkpoint).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(read_blocking(dir.path()).unwrap(), Some(checkpoint));
+        assert_eq!(outputs_to_keep(dir.path()), HashSet::from([output]));
+    }
+
+    #[tokio::test]
+    async fn resume_interrupted_compaction() -> anyhow::Result<()> {
+        let tenant_conf = TenantConf {
+            gc_period: Duration::ZERO,
+            compaction_period: Duration::ZERO,
+            // Small output layers, for the compaction to take several checkpoints.
+            checkpoint_distance: 16 * 1024,
+            compaction_threshold: 4,
+            ..TenantConf::default()
+        };
+        let harness = TenantHarness::create_custom("resume_interrupted_compaction", tenant_conf)?;
+        let (tenant, ctx) = harness.load().await;
+        let timeline = tenant
+            .create_test_timeline(TIMELINE_ID, Lsn(0x10), DEFAULT_PG_VERSION, &ctx)
+            .await?;
+
+        // L0 deltas with a version of every key each.
+        let first_key = Key::from_hex("010000000033333333444444445500000000").unwrap();
+        let mut lsn = Lsn(0x20);
+        let mut latest = Vec::new();
+        for _ in 0..4 {
+            latest.clear();
+            let mut values = Vec::new();
+            for i in 0..512 {
+                let key = first_key.add(i);
+                values.push((key, lsn, Value::Image(test_img(&format!("{key} at {lsn}")))));
+                latest.push((key, lsn));
+                lsn += 0x10;
+            }
+            put_and_flush(&timeline, &values, &ctx).await?;
+        }
+        timeline
+            .remote_client
+            .as_ref()
+            .unwrap()
+            .wait_completion()
+            .await?;
+
+        // Crash when the compaction writes its second checkpoint.
+        let _crashed = crash_points::arm(harness.tenant_shard_id, 1);
+        let res = timeline
+            .compact(&CancellationToken::new(), EnumSet::empty(), &ctx)
+            .await;
+        assert!(res.is_err(), "compaction completed despite the crash");
+        assert!(tenant
+            .shutdown(Default::default(), ShutdownMode::Hard)
+            .instrument(harness.span())
+            .await
+            .is_ok());
+        crash_points::disarm(&harness.tenant_shard_id);
+        drop(timeline);
+        drop(tenant);
+
+        let tenant = harness.do_try_load(&ctx).await?;
+        let timeline = tenant.get_timeline(TIMELINE_ID, true)?;
+        let timeline_path = harness.timeline_path(&TIMELINE_ID);
+        let checkpoint = read_blocking(&timeline_path)?.expect("checkpoint of the compaction");
+        assert!(!checkpoint.outputs.is_empty());
+        let inodes = checkpoint
+            .outputs
+            .iter()
+            .map(|(name, _)| std::fs::metadata(timeline_path.join(name.to_string())))
+            .map(|metadata| metadata.map(|metadata| metadata.ino()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        timeline
+            .compact(&CancellationToken::new(), EnumSet::empty(), &ctx)
+            .await?;
+
+        assert_eq!(read_blocking(&timeline_path)?, None);
+        {
+            let guard = timeline.layers.read().await;
+            let layer_map = guard.layer_map();
+            assert!(layer_map.get_level0_deltas()?.is_empty());
+            let layers = layer_map
+                .iter_historic_layers()
+                .map(|desc| desc.layer_name())
+                .collect::<HashSet<_>>();
+            // The outputs of the interrupted compaction are used as they are, not rewritten.
+            for ((name, _), ino) in checkpoint.outputs.iter().zip(inodes) {
+                assert!(layers.contains(name), "output {name} was not used");
+                let metadata = std::fs::metadata(timeline_path.join(name.to_string()))?;
+                assert_eq!(metadata.ino(), ino, "output {name} was rewritten");
+            }
+        }
+        let read_lsn = latest.last().unwrap().1;
+        for (key, key_lsn) in latest {
+            let page = timeline.get(key, read_lsn, &ctx).await?;
+            assert_eq!(page, test_img(&format!("{key} at {key_lsn}")));
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
 use std::{
     ops::{Deref, DerefMut},
     sync::Arc,
+    time::SystemTime,
 };
 
 use anyhow::Context;
-use pageserver_api::{models::TimelineState, shard::TenantShardId};
+use pageserver_api::{
+    models::{TimelineDeletionProgress, TimelineDeletionStep, TimelineState},
+    shard::TenantShardId,
+};
 use tokio::sync::OwnedMutexGuard;
-use tracing::{error, info, instrument, Instrument};
+use tracing::{error, info, instrument, warn, Instrument};
 use utils::{crashsafe, fs_ext, id::TimelineId};
 
 use crate::{
@@ -14,14 +18,71 @@ use crate::{
     deletion_queue::DeletionQueueClient,
     task_mgr::{self, TaskKind},
     tenant::{
+        crash_points,
         metadata::TimelineMetadata,
         remote_timeline_client::{PersistIndexPartWithDeletedFlagError, RemoteTimelineClient},
         CreateTimelineCause, DeleteTimelineError, Tenant,
     },
+    virtual_file::VirtualFile,
+    TEMP_FILE_SUFFIX,
 };
 
 use super::{Timeline, TimelineResources};
 
+/// Persist the step the deletion is about to perform in the timeline's delete mark file,
+/// so that the deletion is resumed from it after a restart.
+async fn persist_deletion_step(
+    conf: &PageServerConf,
+    timeline: &Timeline,
+    step: TimelineDeletionStep,
+    started_at: SystemTime,
+) -> anyhow::Result<()> {
+    let progress = TimelineDeletionProgress { step, started_at };
+    info!(?step, "timeline deletion step");
+
+    let mark_path =
+        conf.timeline_delete_mark_file_path(timeline.tenant_shard_id, timeline.timeline_id);
+    let temp_path = crashsafe::path_with_suffix_extension(&mark_path, TEMP_FILE_SUFFIX);
+    let content = serde_json::to_vec(&progress).context("serialize deletion progress")?;
+    crash_points::check(&timeline.tenant_shard_id, "persist deletion step")?;
+    VirtualFile::crashsafe_overwrite(mark_path, temp_path, content)
+        .await
+        .context("write delete mark")?;
+
+    *timeline.deletion_progress.lock().unwrap() = Some(progress);
+    Ok(())
+}
+
+/// Read the deletion progress persisted by [`persist_deletion_step`].
+///
+/// Returns `None` if there is no delete mark, or if it's a legacy empty mark, which didn't
+/// record a deletion that should be resumed.
+pub(crate) async fn read_deletion_progress(
+    conf: &PageServerConf,
+    tenant_shard_id: TenantShardId,
+    timeline_id: TimelineId,
+) -> Option<TimelineDeletionProgress> {
+    let mark_path = conf.timeline_delete_mark_file_path(tenant_shard_id, timeline_id);
+    let content = match tokio::fs::read(&mark_path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!(%timeline_id, "failed to read delete mark {mark_path}: {e}");
+            return None;
+        }
+    };
+    if content.is_empty() {
+        return None;
+    }
+    match serde_json::from_slice(&content) {
+        Ok(progress) => Some(progress),
+        Err(e) => {
+            warn!(%timeline_id, "invalid delete mark {mark_path}: {e}");
+            None
+        }
+    }
+}
+
 /// Mark timeline as deleted in S3 so we won't pick it up next time
 /// during attach or pageserver restart.
 /// See comment in persist_index_part_with_deleted_flag.
@@ -79,8 +140,10 @@ pub(super) async fn delete_local_timeline_directory(
         Err(anyhow::anyhow!("failpoint: timeline-delete-before-rm"))?
     });
 
-    // NB: This need not be atomic because the deleted flag in the IndexPart
-    // will be observed during tenant/timeline load. The deletion will be resumed there.
+    crash_points::check(&tenant_shard_id, "remove local timeline directory")?;
+
+    // NB: This need not be atomic because the delete mark will be observed
+    // during tenant/timeline load. The deletion will be resumed there.
     //
     // Note that here we do not bail out on std::io::ErrorKind::NotFound.
     // This can happen if we're called a second time, e.g.,
@@ -117,6 +180,8 @@ pub(super) async fn delete_local_timeline_directory(
 
 /// Removes remote layers and an index file after them.
 async fn delete_remote_layers_and_index(timeline: &Timeline) -> anyhow::Result<()> {
+    crash_points::check(&timeline.tenant_shard_id, "delete remote timeline")?;
+
     if let Some(remote_client) = &timeline.remote_client {
         remote_client.delete_all().await.context("delete_all")?
     };
@@ -124,19 +189,17 @@ async fn delete_remote_layers_and_index(timeline: &Timeline) -> anyhow::Result<(
     Ok(())
 }
 
-// This function removs remaining traces of a timeline on disk.
-// Namely: metadata file, timeline directory, delete mark.
-// Note: io::ErrorKind::NotFound are ignored for metadata and timeline dir.
-// delete mark should be present because it is the last step during deletion.
-// (nothing can fail after its deletion)
+// This function removes remaining traces of a timeline on disk: the delete mark.
+// It is removed after the timeline directory, so that a restart in between resumes
+// the deletion. Its parent directory is fsynced in delete_local_timeline_directory,
+// so it can't outlive the timeline directory. If it reappears after a crash, the
+// deletion is resumed and finds nothing left to delete.
 async fn cleanup_remaining_timeline_fs_traces(
     conf: &PageServerConf,
     tenant_shard_id: TenantShardId,
     timeline_id: TimelineId,
 ) -> anyhow::Result<()> {
-    // Remove delete mark
-    // TODO: once we are confident that no more exist in the field, remove this
-    // line.  It cleans up a legacy marker file that might in rare cases be present.
+    crash_points::check(&tenant_shard_id, "remove delete mark")?;
     tokio::fs::remove_file(conf.timeline_delete_mark_file_path(tenant_shard_id, timeline_id))
         .await
         .or_else(fs_ext::ignore_not_found)
@@ -173,21 +236,25 @@ async fn remove_timeline_from_tenant(
 
 /// Orchestrates timeline shut down of all timeline tasks, removes its in-memory structures,
 /// and deletes its data from both disk and s3.
-/// The sequence of steps:
-/// 1. Set deleted_at in remote index part.
-/// 2. Create local mark file.
-/// 3. Delete local files except metadata (it is simpler this way, to be able to reuse timeline initialization code that expects metadata)
-/// 4. Delete remote layers
-/// 5. Delete index part
-/// 6. Delete meta, timeline directory
-/// 7. Delete mark file
-/// It is resumable from any step in case a crash/restart occurs.
-/// There are three entrypoints to the process:
+///
+/// The deletion is a sequence of [`TimelineDeletionStep`]s:
+/// 1. Mark: create the local delete mark file, which records the current step from now on.
+/// 2. StopTasks: shut down the timeline's tasks and its upload queue.
+/// 3. DeleteRemote: set deleted_at in the remote index part, delete remote layers, then the index part.
+/// 4. DeleteLocal: delete the local timeline directory.
+/// 5. Forget: delete the mark file and remove the timeline from the tenant.
+///
+/// Remote data is deleted before local data: once the index part is gone, a leftover local
+/// directory is purged on the next load anyway, while the reverse order could leave remote
+/// layers behind without anything locally telling us to delete them.
+///
+/// Every step is idempotent, and the deletion is resumable from the persisted step in case a
+/// crash/restart occurs. There are three entrypoints to the process:
 /// 1. [`DeleteTimelineFlow::run`] this is the main one called by a management api handler.
-/// 2. [`DeleteTimelineFlow::resume_deletion`] is called during restarts when local metadata is still present
-/// and we possibly neeed to continue deletion of remote files.
+/// 2. [`DeleteTimelineFlow::resume_deletion`] is called during tenant load for timelines
+/// that have a delete mark or are marked deleted in their remote index part.
 /// 3. [`DeleteTimelineFlow::cleanup_remaining_timeline_fs_traces`] is used when we deleted remote
-/// index but still have local metadata, timeline directory and delete mark.
+/// index but still have the delete mark.
 /// Note the only other place that messes around timeline delete mark is the logic that scans directory with timelines during tenant load.
 #[derive(Default)]
 pub enum DeleteTimelineFlow {
@@ -215,9 +282,43 @@ impl DeleteTimelineFlow {
 
         guard.mark_in_progress()?;
 
+        // A retry continues the reported deletion rather than starting a new one.
+        let started_at = timeline
+            .deletion_progress
+            .lock()
+            .unwrap()
+            .map(|progress| progress.started_at)
+            .unwrap_or_else(SystemTime::now);
+
+        persist_deletion_step(
+            tenant.conf,
+            &timeline,
+            TimelineDeletionStep::Mark,
+            started_at,
+        )
+        .await?;
+
+        persist_deletion_step(
+            tenant.conf,
+            &timeline,
+            TimelineDeletionStep::StopTasks,
+            started_at,
+        )
+        .await?;
+
         // Now that the Timeline is in Stopping state, request all the related tasks to shut down.
         timeline.shutdown(super::ShutdownMode::Hard).await;
 
+        persist_deletion_step(
+            tenant.conf,
+            &timeline,
+            TimelineDeletionStep::DeleteRemote,
+            started_at,
+        )
+        .await?;
+
+        // Mark the index part deleted before responding, so that the deletion is also resumed
+        // by another pageserver the tenant gets attached to.
         fail::fail_point!("timeline-delete-before-index-deleted-at", |_| {
             Err(anyhow::anyhow!(
                 "failpoint: timeline-delete-before-index-deleted-at"
@@ -232,10 +333,14 @@ impl DeleteTimelineFlow {
             ))?
         });
 
+        let progress = TimelineDeletionProgress {
+            step: TimelineDeletionStep::DeleteRemote,
+            started_at,
+        };
         if inplace {
-            Self::background(guard, tenant.conf, tenant, &timeline).await?
+            Self::background(guard, tenant.conf, tenant, &timeline, progress).await?
         } else {
-            Self::schedule_background(guard, tenant.conf, Arc::clone(tenant), timeline);
+            Self::schedule_background(guard, tenant.conf, Arc::clone(tenant), timeline, progress);
         }
 
         Ok(())
@@ -255,6 +360,10 @@ impl DeleteTimelineFlow {
 
     /// Shortcut to create Timeline in stopping state and spawn deletion task.
     /// See corresponding parts of [`crate::tenant::delete::DeleteTenantFlow`]
+    ///
+    /// `progress` is what was read from the delete mark, if any. The timeline's tasks were
+    /// never started, so the deletion continues at [`TimelineDeletionStep::DeleteRemote`]
+    /// at the earliest.
     #[instrument(skip_all, fields(%timeline_id))]
     pub async fn resume_deletion(
         tenant: Arc<Tenant>,
@@ -262,6 +371,7 @@ impl DeleteTimelineFlow {
         local_metadata: &TimelineMetadata,
         remote_client: Option<RemoteTimelineClient>,
         deletion_queue_client: DeletionQueueClient,
+        progress: Option<TimelineDeletionProgress>,
     ) -> anyhow::Result<()> {
         // Note: here we even skip populating layer map. Timeline is essentially uninitialized.
         // RemoteTimelineClient is the only functioning part.
@@ -274,6 +384,8 @@ impl DeleteTimelineFlow {
                     remote_client,
                     deletion_queue_client,
                     timeline_get_throttle: tenant.timeline_get_throttle.clone(),
+                    ephemeral_resources: tenant.ephemeral_resources.clone(),
+                    foreground_reads: tenant.foreground_reads.clone(),
                 },
                 // Important. We dont pass ancestor above because it can be missing.
                 // Thus we need to skip the validation here.
@@ -296,7 +408,19 @@ impl DeleteTimelineFlow {
 
         guard.mark_in_progress()?;
 
-        Self::schedule_background(guard, tenant.conf, tenant, timeline);
+        let progress = TimelineDeletionProgress {
+            step: progress
+                .map(|progress| progress.step)
+                .unwrap_or(TimelineDeletionStep::DeleteRemote)
+                .max(TimelineDeletionStep::DeleteRemote),
+            started_at: progress
+                .map(|progress| progress.started_at)
+                .unwrap_or_else(SystemTime::now),
+        };
+        info!(step=?progress.step, "resuming timeline deletion");
+        *timeline.deletion_progress.lock().unwrap() = Some(progress);
+
+        Self::schedule_background(guard, tenant.conf, tenant, timeline, progress);
 
         Ok(())
     }
@@ -378,6 +502,7 @@ impl DeleteTimelineFlow {
         conf: &'static PageServerConf,
         tenant: Arc<Tenant>,
         timeline: Arc<Timeline>,
+        progress: TimelineDeletionProgress,
     ) {
         let tenant_shard_id = timeline.tenant_shard_id;
         let timeline_id = timeline.timeline_id;
@@ -390,7 +515,7 @@ impl DeleteTimelineFlow {
             "timeline_delete",
             false,
             async move {
-                if let Err(err) = Self::background(guard, conf, &tenant, &timeline).await {
+                if let Err(err) = Self::background(guard, conf, &tenant, &timeline, progress).await {
                     error!("Error: {err:#}");
                     timeline.set_broken(format!("{err:#}"))
                 };
@@ -400,19 +525,46 @@ impl DeleteTimelineFlow {
         );
     }
 
+    /// Perform the steps starting with `progress.step`, which must be at least
+    /// [`TimelineDeletionStep::DeleteRemote`].
     async fn background(
         mut guard: DeletionGuard,
         conf: &PageServerConf,
         tenant: &Tenant,
         timeline: &Timeline,
+        progress: TimelineDeletionProgress,
     ) -> Result<(), DeleteTimelineError> {
-        delete_local_timeline_directory(conf, tenant.tenant_shard_id, timeline).await?;
+        let TimelineDeletionProgress { step, started_at } = progress;
+        debug_assert!(step >= TimelineDeletionStep::DeleteRemote);
+
+        if step <= TimelineDeletionStep::DeleteRemote {
+            // No-op if `run` has done it already.
+            set_deleted_in_remote_index(timeline).await?;
 
-        delete_remote_layers_and_index(timeline).await?;
+            delete_remote_layers_and_index(timeline).await?;
+        }
+
+        if step <= TimelineDeletionStep::DeleteLocal {
+            persist_deletion_step(
+                conf,
+                timeline,
+                TimelineDeletionStep::DeleteLocal,
+                started_at,
+            )
+            .await?;
+
+            delete_local_timeline_directory(conf, tenant.tenant_shard_id, timeline).await?;
+        }
+
+        persist_deletion_step(conf, timeline, TimelineDeletionStep::Forget, started_at).await?;
 
         pausable_failpoint!("in_progress_delete");
 
+        cleanup_remaining_timeline_fs_traces(conf, tenant.tenant_shard_id, timeline.timeline_id)
+            .await?;
+
         remove_timeline_from_tenant(tenant, timeline.timeline_id, &guard).await?;
+        crate::timeline_events::notify_deleted(tenant.tenant_shard_id, timeline.timeline_id);
 
         *guard = Self::Finished;
 
@@ -27,6 +27,7 @@ use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
 
 use crate::{
     context::{DownloadBehavior, RequestContext},
+    metrics::EVICTIONS_PREVENTED_BOUNCING,
     pgdatadir_mapping::CollectKeySpaceError,
     task_mgr::{self, TaskKind, BACKGROUND_RUNTIME},
     tenant::{
@@ -109,6 +110,10 @@ impl Timeline {
             match cf {
                 ControlFlow::Break(()) => break,
                 ControlFlow::Continue(sleep_until) => {
+                    // Bring back the ancestor layers that reads on this timeline keep hitting,
+                    // if our ancestors' eviction evicted them.
+                    self.prefetch_ancestor_layers(&self.cancel).await;
+
                     if tokio::time::timeout_at(sleep_until, self.cancel.cancelled())
                         .await
                         .is_ok()
@@ -199,6 +204,7 @@ impl Timeline {
             evicted: usize,
             errors: usize,
             not_evictable: usize,
+            bouncing: usize,
             timeouts: usize,
             #[allow(dead_code)]
             skipped_for_shutdown: usize,
@@ -256,6 +262,13 @@ impl Timeline {
                 };
 
                 if no_activity_for > p.threshold {
+                    if layer.is_bouncing() {
+                        EVICTIONS_PREVENTED_BOUNCING
+                            .with_label_values(&["eviction_task"])
+                            .inc();
+                        stats.bouncing += 1;
+                        continue;
+                    }
                     js.spawn(async move {
                         layer
                             .evict_and_wait(std::time::Duration::from_secs(5))
@@ -0,0 +1,145 @@
+//! Explanation of the layer traversal of a read, for debugging slow or failing reads.
+//!
+//! [`Timeline::explain_read`] walks the layers of a key like [`Timeline::get`] does, through the
+//! in-memory layers, the layer map and the ancestors, and lists the values that the read would
+//! collect in each layer, newest first. It doesn't redo nor cache anything, doesn't wait for
+//! ancestors to become active, and doesn't download layers: the walk stops at the first evicted
+//! layer, as the values below it depend on its content.
+
+use std::cmp::max;
+
+use anyhow::Context;
+use pageserver_api::key::{is_inherited_key, Key};
+use pageserver_api::models::{
+    ExplainReadLayer, ExplainReadLayerKind, ExplainReadOutcome, ExplainReadResponse,
+    ExplainReadValueKind,
+};
+use utils::lsn::Lsn;
+
+use crate::context::RequestContext;
+use crate::tenant::layer_map::SearchResult;
+use crate::tenant::storage_layer::AsLayerDesc;
+
+use super::Timeline;
+
+impl Timeline {
+    /// The layers that a read of `key` at `request_lsn` would visit, see the module docs.
+    pub(crate) async fn explain_read(
+        &self,
+        key: Key,
+        request_lsn: Lsn,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<ExplainReadResponse> {
+        let cached_image_lsn = self
+            .lookup_cached_page(&key, request_lsn, ctx)
+            .await
+            .map(|(lsn, _)| lsn);
+        let cached_lsn = cached_image_lsn.unwrap_or(Lsn(0));
+
+        let mut timeline = self;
+        let mut layers_visited = Vec::new();
+        let mut cont_lsn = request_lsn
+            .0
+            .checked_add(1)
+            .map(Lsn)
+            .context("the request LSN is Lsn::MAX")?;
+        // Like in `get_reconstruct_data`, guards against loops if the layer map is inconsistent.
+        let mut prev_lsn = None;
+
+        let outcome = loop {
+            if layers_visited
+                .last()
+                .and_then(|layer: &ExplainReadLayer| layer.values.last())
+                .is_some_and(|value| value.kind.ends_traversal())
+            {
+                break ExplainReadOutcome::Complete;
+            }
+            if cont_lsn == cached_lsn + 1 {
+                break ExplainReadOutcome::CachedImage;
+            }
+            if prev_lsn.is_some_and(|prev| prev <= cont_lsn) {
+                break ExplainReadOutcome::Missing;
+            }
+            prev_lsn = Some(cont_lsn);
+
+            if is_inherited_key(key) && Lsn(cont_lsn.0 - 1) <= timeline.ancestor_lsn {
+                let Some(ancestor) = timeline.ancestor_timeline.as_deref() else {
+                    break ExplainReadOutcome::Missing;
+                };
+                timeline = ancestor;
+                prev_lsn = None;
+                continue;
+            }
+
+            let guard = timeline.layers.read().await;
+            let layers = guard.layer_map();
+
+            let in_memory = layers
+                .open_layer
+                .iter()
+                .chain(layers.frozen_layers.iter().rev())
+                .find(|layer| cont_lsn > layer.get_lsn_range().start)
+                .cloned();
+            if let Some(layer) = in_memory {
+                drop(guard);
+                let lsn_range = layer.get_lsn_range();
+                let lsn_floor = max(cached_lsn + 1, lsn_range.start);
+                let values = layer.explain_key(key, lsn_floor..cont_lsn, ctx).await?;
+                layers_visited.push(ExplainReadLayer {
+                    timeline_id: timeline.timeline_id,
+                    layer: layer.to_string(),
+                    kind: ExplainReadLayerKind::InMemory,
+                    resident: true,
+                    lsn_start: lsn_range.start,
+                    lsn_end: lsn_range.end,
+                    values,
+                });
+                cont_lsn = lsn_floor;
+                continue;
+            }
+
+            if let Some(SearchResult { lsn_floor, layer }) = layers.search(key, cont_lsn) {
+                let layer = guard.get_from_desc(&layer);
+                drop(guard);
+                let desc = layer.layer_desc();
+                let mut visited = ExplainReadLayer {
+                    timeline_id: timeline.timeline_id,
+                    layer: desc.layer_name().to_string(),
+                    kind: if desc.is_delta() {
+                        ExplainReadLayerKind::Delta
+                    } else {
+                        ExplainReadLayerKind::Image
+                    },
+                    resident: false,
+                    lsn_start: desc.lsn_range.start,
+                    lsn_end: desc.lsn_range.end,
+                    values: Vec::new(),
+                };
+                let Some(resident) = layer.keep_resident().await else {
+                    layers_visited.push(visited);
+                    break ExplainReadOutcome::Evicted;
+                };
+                let lsn_floor = max(cached_lsn + 1, lsn_floor);
+                visited.resident = true;
+                visited.values = resident.explain_key(key, lsn_floor..cont_lsn, ctx).await?;
+                layers_visited.push(visited);
+                cont_lsn = lsn_floor;
+            } else if timeline.ancestor_timeline.is_some() {
+                cont_lsn = Lsn(timeline.ancestor_lsn.0 + 1);
+            } else {
+                break ExplainReadOutcome::Missing;
+            }
+        };
+
+        let redo_needed = layers_visited
+            .iter()
+            .flat_map(|layer| &layer.values)
+            .any(|value| value.kind != ExplainReadValueKind::Image);
+        Ok(ExplainReadResponse {
+            cached_image_lsn,
+            layers: layers_visited,
+            outcome,
+            redo_needed,
+        })
+    }
+}
@@ -1,3 +1,6 @@
+use super::access_log::ACCESS_LOG_FILE_NAME;
+use super::compaction_checkpoint::COMPACTION_CHECKPOINT_FILE_NAME;
+use super::layer_manifest::LAYER_MANIFEST_FILE_NAME;
 use crate::{
     is_temporary,
     tenant::{
@@ -29,6 +32,12 @@ pub(super) enum Discovered {
     TemporaryDownload(String),
     /// "metadata" file we persist locally and include in `index_part.json`
     Metadata,
+    /// Log of layer map changes, see [`super::layer_manifest`]
+    LayerManifest,
+    /// Progress of an interrupted L0 compaction, see [`super::compaction_checkpoint`]
+    CompactionCheckpoint,
+    /// Hourly rollups of the accesses, see [`super::access_log`]
+    AccessLog,
     /// Backup file from previously future layers
     IgnoredBackup,
     /// Unrecognized, warn about these
@@ -51,6 +60,12 @@ pub(super) fn scan_timeline_dir(path: &Utf8Path) -> anyhow::Result<Vec<Discovere
             Err(_) => {
                 if file_name == METADATA_FILE_NAME {
                     Discovered::Metadata
+                } else if file_name == LAYER_MANIFEST_FILE_NAME {
+                    Discovered::LayerManifest
+                } else if file_name == COMPACTION_CHECKPOINT_FILE_NAME {
+                    Discovered::CompactionCheckpoint
+                } else if file_name == ACCESS_LOG_FILE_NAME {
+                    Discovered::AccessLog
                 } else if file_name.ends_with(".old") {
                     // ignore these
                     Discovered::IgnoredBackup
@@ -0,0 +1,163 @@
+//! Stream of the keys changed by ingest, for external caches.
+//!
+//! Caches in front of the pageserver, like the local file cache of computes or caches in the
+//! proxy, can only tell that a cached page is stale by polling `last_record_lsn`, and then
+//! don't know which pages changed. Instead, they can subscribe to the [`Invalidations`] of a
+//! timeline: every commit of ingested WAL publishes the key ranges it changed, by LSN, once
+//! the changes are visible to reads. There is no cost to ingest while nobody subscribed.
+//!
+//! A subscriber that falls more than [`CAPACITY`] notifications behind misses some of them, and
+//! gets a notification for the whole keyspace instead, at the LSN of the next one it receives.
+//! Each shard of a sharded tenant only publishes the changes of its own keys.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::Stream;
+use pageserver_api::key::Key;
+use pageserver_api::keyspace::KeySpaceRandomAccum;
+use pageserver_api::models::KeyInvalidation;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use utils::lsn::Lsn;
+
+/// Number of notifications that a subscriber may fall behind before it misses some.
+const CAPACITY: usize = 1024;
+
+/// The subscribers to the changes ingested into a timeline.
+pub(crate) struct Invalidations {
+    tx: broadcast::Sender<Arc<KeyInvalidation>>,
+}
+
+impl Default for Invalidations {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(CAPACITY);
+        Invalidations { tx }
+    }
+}
+
+impl Invalidations {
+    pub(crate) fn has_subscribers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Arc<KeyInvalidation>> {
+        self.tx.subscribe()
+    }
+
+    /// Notify the subscribers of the changes in `changes`, once they are visible to reads.
+    pub(crate) fn publish(&self, changes: InvalidationAccum) {
+        for (lsn, keys) in changes.by_lsn {
+            let invalidation = KeyInvalidation {
+                lsn,
+                key_ranges: keys
+                    .to_keyspace()
+                    .ranges
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            };
+            // Nobody to notify if all subscribers went away in the meantime.
+            let _ = self.tx.send(Arc::new(invalidation));
+        }
+    }
+}
+
+/// The keys changed by a batch of ingested WAL, by LSN.
+#[derive(Default)]
+pub(crate) struct InvalidationAccum {
+    by_lsn: BTreeMap<Lsn, KeySpaceRandomAccum>,
+}
+
+impl InvalidationAccum {
+    pub(crate) fn add_key(&mut self, lsn: Lsn, key: Key) {
+        self.by_lsn.entry(lsn).or_default().add_key(key);
+    }
+
+    pub(crate) fn add_range(&mut self, lsn: Lsn, range: Range<Key>) {
+        self.by_lsn.entry(lsn).or_default().add_range(range);
+    }
+}
+
+/// The notifications received by `rx`, as newline-delimited JSON, until `cancel` is cancelled or
+/// the timeline goes away.
+pub(crate) fn json_lines(
+    mut rx: broadcast::Receiver<Arc<KeyInvalidation>>,
+    cancel: CancellationToken,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+    async_stream::try_stream! {
+        let mut lagged = false;
+        loop {
+            let invalidation = tokio::select! {
+                _ = cancel.cancelled() => break,
+                received = rx.recv() => match received {
+                    Ok(invalidation) => invalidation,
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        lagged = true;
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+            if lagged {
+                lagged = false;
+                let everything = KeyInvalidation {
+                    lsn: invalidation.lsn,
+                    key_ranges: vec![(Key::MIN..Key::MAX).into()],
+                };
+                yield to_json_line(&everything)?;
+            }
+            yield to_json_line(&invalidation)?;
+        }
+    }
+}
+
+fn to_json_line(invalidation: &KeyInvalidation) -> Result<Bytes, std::io::Error> {
+    let mut line = serde_json::to_vec(invalidation)?;
+    line.push(b'\n');
+    Ok(Bytes::from(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn subscriber_sees_changes_by_lsn() {
+        let invalidations = Invalidations::default();
+        assert!(!invalidations.has_subscribers());
+        let rx = invalidations.subscribe();
+        assert!(invalidations.has_subscribers());
+
+        let key = |i: u32| Key::from_i128(0x1000 + i as i128);
+        let mut changes = InvalidationAccum::default();
+        changes.add_key(Lsn(0x20), key(1));
+        changes.add_key(Lsn(0x10), key(2));
+        changes.add_key(Lsn(0x10), key(3));
+        changes.add_range(Lsn(0x20), key(5)..key(8));
+        invalidations.publish(changes);
+        drop(invalidations);
+
+        let lines = json_lines(rx, CancellationToken::new())
+            .map(|line| serde_json::from_slice::<KeyInvalidation>(&line.unwrap()).unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            lines,
+            vec![
+                KeyInvalidation {
+                    lsn: Lsn(0x10),
+                    key_ranges: vec![(key(2)..key(4)).into()],
+                },
+                KeyInvalidation {
+                    lsn: Lsn(0x20),
+                    key_ranges: vec![(key(1)..key(2)).into(), (key(5)..key(8)).into()],
+                },
+            ]
+        );
+    }
+}
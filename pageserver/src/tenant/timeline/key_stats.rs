@@ -0,0 +1,190 @@
+//! Statistics of the deltas written to the layers of a timeline, by relation, to find the
+//! tables that generate the most WAL churn on a branch.
+//!
+//! Flushes of in-memory layers and L0 compactions feed a [`KeySpaceStatsAccum`] with every
+//! delta they write, and merge it into the [`KeySpaceStatsCollector`] of the timeline once they
+//! have written their layers. The deltas are aggregated by relation, over all its forks: the
+//! keys of SLRUs, of directories and of other metadata are counted together. The statistics
+//! only live in memory, since the timeline was loaded, and are returned by the
+//! `key_space_stats` management API.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use pageserver_api::key::Key;
+use pageserver_api::models::{
+    DeltaStats, KeySpaceStats, RelationDeltaStats, TimelineKeySpaceStats,
+};
+use utils::lsn::Lsn;
+
+/// A relation, as its `(spcnode, dbnode, relnode)`, or `None` for the keys outside of
+/// relations.
+type RelationId = Option<(u32, u32, u32)>;
+
+fn relation_of(key: &Key) -> RelationId {
+    (key.field1 == 0x00 && key.field4 != 0).then_some((key.field2, key.field3, key.field4))
+}
+
+/// Where the deltas were written.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Source {
+    Flush,
+    Compaction,
+}
+
+/// The deltas written by a flush or a compaction.
+#[derive(Default)]
+pub(crate) struct KeySpaceStatsAccum {
+    relations: HashMap<RelationId, (DeltaStats, HashSet<Lsn>)>,
+}
+
+impl KeySpaceStatsAccum {
+    pub(crate) fn add(&mut self, key: &Key, lsn: Lsn, bytes: u64) {
+        let (stats, lsns) = self.relations.entry(relation_of(key)).or_default();
+        stats.deltas += 1;
+        stats.bytes += bytes;
+        if lsns.insert(lsn) {
+            stats.distinct_lsns += 1;
+        }
+    }
+}
+
+pub(crate) struct KeySpaceStatsCollector {
+    since: SystemTime,
+    flush: Mutex<HashMap<RelationId, DeltaStats>>,
+    compaction: Mutex<HashMap<RelationId, DeltaStats>>,
+}
+
+impl Default for KeySpaceStatsCollector {
+    fn default() -> Self {
+        KeySpaceStatsCollector {
+            since: SystemTime::now(),
+            flush: Default::default(),
+            compaction: Default::default(),
+        }
+    }
+}
+
+impl KeySpaceStatsCollector {
+    pub(crate) fn record(&self, source: Source, accum: KeySpaceStatsAccum) {
+        let mut totals = match source {
+            Source::Flush => self.flush.lock().unwrap(),
+            Source::Compaction => self.compaction.lock().unwrap(),
+        };
+        for (relation, (stats, _)) in accum.relations {
+            let total = totals.entry(relation).or_default();
+            total.deltas += stats.deltas;
+            total.bytes += stats.bytes;
+            total.distinct_lsns += stats.distinct_lsns;
+        }
+    }
+
+    /// The statistics of the `limit` relations with the most bytes of deltas, for each source.
+    pub(crate) fn get(&self, limit: usize) -> TimelineKeySpaceStats {
+        TimelineKeySpaceStats {
+            since: self.since,
+            flush: summarize(&self.flush.lock().unwrap(), limit),
+            compaction: summarize(&self.compaction.lock().unwrap(), limit),
+        }
+    }
+}
+
+fn summarize(totals: &HashMap<RelationId, DeltaStats>, limit: usize) -> KeySpaceStats {
+    let mut relations: Vec<_> = totals
+        .iter()
+        .filter_map(|(relation, stats)| {
+            let (spcnode, dbnode, relnode) = (*relation)?;
+            Some(RelationDeltaStats {
+                spcnode,
+                dbnode,
+                relnode,
+                stats: *stats,
+            })
+        })
+        .collect();
+    relations.sort_by_key(|r| (Reverse(r.stats.bytes), r.spcnode, r.dbnode, r.relnode));
+    relations.truncate(limit);
+    KeySpaceStats {
+        relations,
+        other: totals.get(&None).copied().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pageserver_api::key::{rel_block_to_key, rel_size_to_key, slru_block_to_key};
+    use pageserver_api::reltag::{RelTag, SlruKind};
+
+    use super::*;
+
+    fn rel(relnode: u32, forknum: u8) -> RelTag {
+        RelTag {
+            forknum,
+            spcnode: 1663,
+            dbnode: 5,
+            relnode,
+        }
+    }
+
+    #[test]
+    fn aggregates_by_relation() {
+        let collector = KeySpaceStatsCollector::default();
+
+        let mut accum = KeySpaceStatsAccum::default();
+        accum.add(&rel_block_to_key(rel(100, 0), 0), Lsn(0x10), 100);
+        accum.add(&rel_block_to_key(rel(100, 0), 1), Lsn(0x10), 100);
+        accum.add(&rel_block_to_key(rel(100, 1), 0), Lsn(0x20), 10);
+        accum.add(&rel_size_to_key(rel(100, 0)), Lsn(0x20), 4);
+        accum.add(&rel_block_to_key(rel(200, 0), 0), Lsn(0x30), 1000);
+        accum.add(&slru_block_to_key(SlruKind::Clog, 0, 0), Lsn(0x30), 8);
+        collector.record(Source::Flush, accum);
+
+        let mut accum = KeySpaceStatsAccum::default();
+        accum.add(&rel_block_to_key(rel(100, 0), 0), Lsn(0x20), 100);
+        collector.record(Source::Flush, accum);
+
+        let stats = collector.get(10);
+        assert!(stats.compaction.relations.is_empty());
+        let relations: Vec<_> = stats
+            .flush
+            .relations
+            .iter()
+            .map(|r| (r.relnode, r.stats))
+            .collect();
+        assert_eq!(
+            relations,
+            vec![
+                (
+                    200,
+                    DeltaStats {
+                        deltas: 1,
+                        bytes: 1000,
+                        distinct_lsns: 1
+                    }
+                ),
+                (
+                    100,
+                    DeltaStats {
+                        deltas: 5,
+                        bytes: 314,
+                        distinct_lsns: 3
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            stats.flush.other,
+            DeltaStats {
+                deltas: 1,
+                bytes: 8,
+                distinct_lsns: 1
+            }
+        );
+
+        let stats = collector.get(1);
+        assert_eq!(stats.flush.relations.len(), 1);
+        assert_eq!(stats.flush.relations[0].relnode, 200);
+    }
+}
@@ -0,0 +1,337 @@
+//! Append-only log of the changes to a timeline's set of layers.
+//!
+//! Every layer added to or removed from the layer map, and every eviction or download of a
+//! layer's local file, is appended to [`LAYER_MANIFEST_FILE_NAME`] in the timeline directory
+//! together with the reason for the change. Removals are appended before the local files are
+//! deleted.
+//!
+//! On startup, the log is replayed and compared with the layer files found in the timeline
+//! directory, to report files that appeared or disappeared without us knowing about it. After
+//! that, the log is rewritten to contain just the loaded layers, which keeps it from growing
+//! without bounds.
+//!
+//! The appends are fsynced, off the executor threads, and the callers append before taking the
+//! layer map lock where they can. The log is advisory: failures to write it are logged but don't
+//! fail the layer map change. The layer files and the remote index remain the source of truth.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use utils::crashsafe::{self, path_with_suffix_extension};
+
+use crate::tenant::storage_layer::LayerName;
+use crate::TEMP_FILE_SUFFIX;
+
+pub(crate) const LAYER_MANIFEST_FILE_NAME: &str = "layer_manifest.log";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ManifestOp {
+    /// The layer was added to the layer map, with its file present locally.
+    Add,
+    /// The layer was removed from the layer map, and its local file deleted.
+    Remove,
+    /// The local file of the layer was deleted, the layer stays in the layer map.
+    Evict,
+    /// The local file of an evicted layer was downloaded.
+    Download,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ManifestReason {
+    /// Snapshot of the layers loaded on startup.
+    Load,
+    Flush,
+    ImageCreation,
+    Compaction,
+    Gc,
+    Eviction,
+    OnDemandDownload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ManifestEntry {
+    #[serde(with = "humantime_serde")]
+    pub(crate) time: SystemTime,
+    pub(crate) op: ManifestOp,
+    pub(crate) reason: ManifestReason,
+    pub(crate) layer: LayerName,
+}
+
+/// Difference between the replayed manifest and the files in the timeline directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ManifestMismatch {
+    /// Layer files found locally that the manifest doesn't expect to exist.
+    pub(crate) unexpected: Vec<LayerName>,
+    /// Layer files the manifest expects to exist locally, but which were not found.
+    pub(crate) missing: Vec<LayerName>,
+}
+
+impl ManifestMismatch {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.unexpected.is_empty() && self.missing.is_empty()
+    }
+}
+
+pub(crate) struct LayerManifest {
+    path: Utf8PathBuf,
+    /// Opened lazily on first append, because the timeline directory might not exist yet
+    /// when the timeline is constructed.
+    file: Arc<Mutex<Option<std::fs::File>>>,
+}
+
+impl LayerManifest {
+    pub(crate) fn new(timeline_path: &Utf8Path) -> Self {
+        LayerManifest {
+            path: timeline_path.join(LAYER_MANIFEST_FILE_NAME),
+            file: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Append entries for `layers` to the log, and fsync it.
+    pub(crate) async fn record(
+        &self,
+        op: ManifestOp,
+        reason: ManifestReason,
+        layers: impl IntoIterator<Item = LayerName>,
+    ) {
+        let buf = serialize_entries(op, reason, layers);
+        if buf.is_empty() {
+            return;
+        }
+        let path = self.path.clone();
+        let file = Arc::clone(&self.file);
+        if let Err(e) = tokio::task::spawn_blocking(move || append(&path, &file, &buf)).await {
+            warn!(path=%self.path, "failed to append to layer manifest: {e}");
+        }
+    }
+
+    /// Blocking version of [`Self::record`], for callers already on a blocking thread.
+    pub(crate) fn record_blocking(
+        &self,
+        op: ManifestOp,
+        reason: ManifestReason,
+        layers: impl IntoIterator<Item = LayerName>,
+    ) {
+        let buf = serialize_entries(op, reason, layers);
+        if !buf.is_empty() {
+            append(&self.path, &self.file, &buf);
+        }
+    }
+
+    /// Read all entries of the log. A truncated last line, left by a crash, is ignored.
+    pub(crate) async fn read(&self) -> anyhow::Result<Vec<ManifestEntry>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => parse_entries(&contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Blocking version of [`Self::read`], for use during layer map loading.
+    pub(crate) fn read_blocking(&self) -> anyhow::Result<Vec<ManifestEntry>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => parse_entries(&contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the log with a snapshot of the given layers and whether they are resident.
+    pub(crate) fn reset_blocking(
+        &self,
+        layers: impl IntoIterator<Item = (LayerName, bool)>,
+    ) -> std::io::Result<()> {
+        let time = SystemTime::now();
+        let mut buf = Vec::new();
+        for (layer, resident) in layers {
+            let op = if resident {
+                ManifestOp::Add
+            } else {
+                ManifestOp::Evict
+            };
+            let entry = ManifestEntry {
+                time,
+                op,
+                reason: ManifestReason::Load,
+                layer,
+            };
+            serde_json::to_writer(&mut buf, &entry).expect("serialize manifest entry");
+            buf.push(b'\n');
+        }
+
+        let mut file = self.file.lock().unwrap();
+        *file = None;
+
+        let temp_path = path_with_suffix_extension(&self.path, TEMP_FILE_SUFFIX);
+        crashsafe::overwrite(&self.path, &temp_path, &buf)
+    }
+}
+
+fn serialize_entries(
+    op: ManifestOp,
+    reason: ManifestReason,
+    layers: impl IntoIterator<Item = LayerName>,
+) -> Vec<u8> {
+    let time = SystemTime::now();
+    let mut buf = Vec::new();
+    for layer in layers {
+        let entry = ManifestEntry {
+            time,
+            op,
+            reason,
+            layer,
+        };
+        serde_json::to_writer(&mut buf, &entry).expect("serialize manifest entry");
+        buf.push(b'\n');
+    }
+    buf
+}
+
+/// Append `buf` to the log at `path` and fsync it, and its directory if the log is new.
+fn append(path: &Utf8Path, file: &Mutex<Option<std::fs::File>>, buf: &[u8]) {
+    let mut file = file.lock().unwrap();
+    if file.is_none() {
+        let created = !path.exists();
+        let opened = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|opened| {
+                if created {
+                    crashsafe::fsync(path.parent().expect("timeline dir"))?;
+                }
+                Ok(opened)
+            });
+        match opened {
+            Ok(opened) => *file = Some(opened),
+            Err(e) => {
+                warn!(%path, "failed to open layer manifest: {e}");
+                return;
+            }
+        }
+    }
+    let res = file
+        .as_mut()
+        .unwrap()
+        .write_all(buf)
+        .and_then(|()| file.as_mut().unwrap().sync_data());
+    if let Err(e) = res {
+        warn!(%path, "failed to append to layer manifest: {e}");
+        // reopen on next append
+        *file = None;
+    }
+}
+
+fn parse_entries(contents: &str) -> anyhow::Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+    let mut lines = contents.split_terminator('\n').peekable();
+    while let Some(line) = lines.next() {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) if lines.peek().is_none() && !contents.ends_with('\n') => break,
+            Err(e) => anyhow::bail!("invalid layer manifest line {line:?}: {e}"),
+        }
+    }
+    Ok(entries)
+}
+
+/// Replay `entries` and compare the resulting set of local layers with `local_layers`.
+pub(crate) fn compare_with_local<'a>(
+    entries: &[ManifestEntry],
+    local_layers: impl IntoIterator<Item = &'a LayerName>,
+) -> ManifestMismatch {
+    let mut expected_local = HashMap::new();
+    for entry in entries {
+        let resident = match entry.op {
+            ManifestOp::Add | ManifestOp::Download => true,
+            ManifestOp::Remove | ManifestOp::Evict => false,
+        };
+        expected_local.insert(&entry.layer, resident);
+    }
+
+    let mut mismatch = ManifestMismatch::default();
+    for layer in local_layers {
+        match expected_local.insert(layer, false) {
+            Some(true) => {}
+            Some(false) | None => mismatch.unexpected.push(layer.clone()),
+        }
+    }
+    mismatch.missing = expected_local
+        .into_iter()
+        .filter_map(|(layer, resident)| resident.then(|| layer.clone()))
+        .collect();
+    mismatch.unexpected.sort_by_cached_key(LayerName::to_string);
+    mismatch.missing.sort_by_cached_key(LayerName::to_string);
+    mismatch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn layer(name: &str) -> LayerName {
+        LayerName::from_str(name).unwrap()
+    }
+
+    fn entry(op: ManifestOp, name: &str) -> ManifestEntry {
+        ManifestEntry {
+            time: SystemTime::UNIX_EPOCH,
+            op,
+            reason: ManifestReason::Flush,
+            layer: layer(name),
+        }
+    }
+
+    const A: &str = "000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__0000000001696070-00000000016960E9";
+    const B: &str = "000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016960E9-00000000016961E9";
+    const C: &str = "000000000000000000000000000000000000-FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF__00000000016961E9";
+
+    #[test]
+    fn replay_and_compare() {
+        let entries = vec![
+            entry(ManifestOp::Add, A),
+            entry(ManifestOp::Add, B),
+            entry(ManifestOp::Evict, B),
+            entry(ManifestOp::Add, C),
+            entry(ManifestOp::Remove, A),
+        ];
+
+        let mismatch = compare_with_local(&entries, [&layer(C)]);
+        assert!(mismatch.is_empty(), "{mismatch:?}");
+
+        let mismatch = compare_with_local(&entries, [&layer(A), &layer(B)]);
+        assert_eq!(
+            mismatch,
+            ManifestMismatch {
+                unexpected: vec![layer(A), layer(B)],
+                missing: vec![layer(C)],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_truncated_log() {
+        let mut contents = String::new();
+        for e in [entry(ManifestOp::Add, A), entry(ManifestOp::Add, B)] {
+            contents.push_str(&serde_json::to_string(&e).unwrap());
+            contents.push('\n');
+        }
+        assert_eq!(parse_entries(&contents).unwrap().len(), 2);
+
+        // a crash in the middle of an append leaves a partial line
+        let truncated = &contents[..contents.len() - 10];
+        assert_eq!(parse_entries(truncated).unwrap().len(), 1);
+
+        // garbage in the middle of the log is an error
+        let corrupted = format!("garbage\n{contents}");
+        assert!(parse_entries(&corrupted).is_err());
+    }
+}
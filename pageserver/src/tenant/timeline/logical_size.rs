@@ -4,7 +4,9 @@ use once_cell::sync::OnceCell;
 use tokio_util::sync::CancellationToken;
 use utils::lsn::Lsn;
 
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicBool, AtomicI64, Ordering as AtomicOrdering};
+use std::sync::Mutex;
 
 /// Internal structure to hold all data needed for logical size calculation.
 ///
@@ -64,6 +66,11 @@ pub(super) struct LogicalSize {
 
     /// For [`crate::metrics::initial_logical_size::TIMELINES_WHERE_WALRECEIVER_GOT_APPROXIMATE_SIZE`].
     pub(super) did_return_approximate_to_walreceiver: AtomicBool,
+
+    /// Exact sizes at the LSNs that in-memory layers were frozen at, oldest first. Once a frozen
+    /// layer is flushed, the size at its end is checkpointed in the index part, see
+    /// [`crate::tenant::remote_timeline_client::index::LogicalSizeCheckpoint`].
+    frozen_sizes: Mutex<VecDeque<(Lsn, u64)>>,
 }
 
 /// Normalized current size, that the data in pageserver occupies.
@@ -137,6 +144,7 @@ impl LogicalSize {
             size_added_after_initial: AtomicI64::new(0),
             did_return_approximate_to_walreceiver: AtomicBool::new(false),
             initialized: tokio::sync::Semaphore::new(0),
+            frozen_sizes: Mutex::new(VecDeque::new()),
         }
     }
 
@@ -148,6 +156,7 @@ impl LogicalSize {
             size_added_after_initial: AtomicI64::new(0),
             did_return_approximate_to_walreceiver: AtomicBool::new(false),
             initialized: tokio::sync::Semaphore::new(0),
+            frozen_sizes: Mutex::new(VecDeque::new()),
         }
     }
 
@@ -174,6 +183,35 @@ impl LogicalSize {
             .fetch_add(delta, AtomicOrdering::SeqCst);
     }
 
+    /// Remember the current size as the size at `lsn`, if it is exact. Must be called with the
+    /// timeline's write lock held and `lsn` the last record LSN, so that the size includes all
+    /// the WAL up to `lsn`, and no more.
+    pub(super) fn record_frozen_size(&self, lsn: Lsn) {
+        if let CurrentLogicalSize::Exact(size) = self.current_size() {
+            self.frozen_sizes
+                .lock()
+                .unwrap()
+                .push_back((lsn, (&size).into()));
+        }
+    }
+
+    /// The size recorded at `lsn` by [`Self::record_frozen_size`], if any. Forgets the sizes
+    /// recorded up to `lsn`.
+    pub(super) fn take_frozen_size(&self, lsn: Lsn) -> Option<u64> {
+        let mut frozen_sizes = self.frozen_sizes.lock().unwrap();
+        let mut found = None;
+        while let Some(&(frozen_at, size)) = frozen_sizes.front() {
+            if frozen_at > lsn {
+                break;
+            }
+            if frozen_at == lsn {
+                found = Some(size);
+            }
+            frozen_sizes.pop_front();
+        }
+        found
+    }
+
     /// Make the value computed by initial logical size computation
     /// available for re-use. This doesn't contain the incremental part.
     pub(super) fn initialized_size(&self, lsn: Lsn) -> Option<u64> {
@@ -0,0 +1,281 @@
+//! Sampled commit timestamps of a timeline, to resolve timestamps to LSNs and back without
+//! searching the CLOG.
+//!
+//! Ingest samples the commit records: a commit is sampled if its timestamp is at least
+//! [`SAMPLE_INTERVAL`] after that of the last sample. The index keeps at most [`MAX_SAMPLES`]:
+//! when it is full, every other sample of its older half is dropped. The recent history keeps
+//! one sample per interval, and the interval between samples doubles with each thinning of the
+//! older history, so the precision degrades with age, not with the size of the history.
+//!
+//! A lookup returns the samples right before and after the requested timestamp or LSN: the
+//! answer is between them, which is the precision guarantee of the `lsn_by_timestamp` and
+//! `timestamp_by_lsn` APIs. Postgres takes the commit timestamp before it inserts the commit
+//! record, so concurrent commits can be a few milliseconds out of order in the WAL: samples
+//! whose timestamp is earlier than that of the last sample are skipped, and the guarantee holds
+//! up to that jitter. A branch only samples its own commits, the lookups fall back to the
+//! samples of its ancestors up to the branch point.
+//!
+//! The samples up to the `disk_consistent_lsn` of each flush are persisted with the index part
+//! that reflects it, so that they survive restarts and migrations: the commits after it are
+//! ingested, and sampled, again. [`Timeline::find_lsn_for_timestamp`], and so the PITR cutoff of
+//! GC, only search the CLOG between the samples around the timestamp.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use pageserver_api::models::{LsnTimestampBracket, LsnTimestampSample};
+use postgres_ffi::TimestampTz;
+use serde::{Deserialize, Serialize};
+use utils::lsn::Lsn;
+
+use super::Timeline;
+
+/// The minimal interval between the commit timestamps of two samples.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// With one sample a minute, the last 8.5 hours of commits keep the full precision.
+const MAX_SAMPLES: usize = 1024;
+
+/// A sampled commit: the end of its record, and its timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LsnTimeSample {
+    pub(crate) lsn: Lsn,
+    pub(crate) timestamp: TimestampTz,
+}
+
+impl From<LsnTimeSample> for LsnTimestampSample {
+    fn from(sample: LsnTimeSample) -> Self {
+        LsnTimestampSample {
+            lsn: sample.lsn,
+            timestamp: postgres_ffi::from_pg_timestamp(sample.timestamp),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct LsnTimeIndex {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Ordered by LSN and by timestamp.
+    samples: Vec<LsnTimeSample>,
+}
+
+impl LsnTimeIndex {
+    /// Sample the commit record ending at `lsn`, see the module docs.
+    pub(crate) fn record_commit(&self, lsn: Lsn, timestamp: TimestampTz) {
+        // Before the Postgres epoch, the timestamp is bogus.
+        if timestamp < 0 {
+            return;
+        }
+        let interval = SAMPLE_INTERVAL.as_micros() as TimestampTz;
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(last) = inner.samples.last() {
+            if lsn <= last.lsn || timestamp < last.timestamp.saturating_add(interval) {
+                return;
+            }
+        }
+        if inner.samples.len() >= MAX_SAMPLES {
+            thin(&mut inner.samples);
+        }
+        inner.samples.push(LsnTimeSample { lsn, timestamp });
+    }
+
+    /// The samples at or below `max_lsn`.
+    pub(crate) fn samples_up_to(&self, max_lsn: Lsn) -> Vec<LsnTimeSample> {
+        let inner = self.inner.lock().unwrap();
+        let end = inner.samples.partition_point(|s| s.lsn <= max_lsn);
+        inner.samples[..end].to_vec()
+    }
+
+    /// Restore the samples of the index part, before those of the commits ingested since, if
+    /// any. The samples of the index part are dropped if they are out of order.
+    pub(crate) fn restore(&self, restored: Vec<LsnTimeSample>) {
+        let ordered = restored.first().map_or(true, |s| s.timestamp >= 0)
+            && restored
+                .windows(2)
+                .all(|w| w[0].lsn < w[1].lsn && w[0].timestamp <= w[1].timestamp);
+        if !ordered {
+            tracing::warn!("ignoring the LSN/timestamp samples of the index part: out of order");
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let first = inner.samples.first().copied();
+        let mut samples = match first {
+            Some(first) => restored
+                .into_iter()
+                .take_while(|s| s.lsn < first.lsn && s.timestamp <= first.timestamp)
+                .collect(),
+            None => restored,
+        };
+        samples.append(&mut inner.samples);
+        while samples.len() > MAX_SAMPLES {
+            thin(&mut samples);
+        }
+        inner.samples = samples;
+    }
+}
+
+/// Drop every other sample of the older half.
+fn thin(samples: &mut Vec<LsnTimeSample>) {
+    let older = samples.len() / 2;
+    let mut i = 0;
+    samples.retain(|_| {
+        i += 1;
+        i > older || i % 2 == 1
+    });
+}
+
+/// The samples right before and after `key`, in `samples` ordered by it.
+fn bracket<K: Ord>(
+    samples: &[LsnTimeSample],
+    key: K,
+    key_of: impl Fn(&LsnTimeSample) -> K,
+) -> LsnTimestampBracket {
+    let after = samples.partition_point(|s| key_of(s) <= key);
+    LsnTimestampBracket {
+        before: after.checked_sub(1).map(|before| samples[before].into()),
+        after: samples.get(after).map(|&s| s.into()),
+    }
+}
+
+impl Timeline {
+    /// The samples of this timeline and of its ancestors up to their branch points.
+    fn lsn_time_samples(&self) -> Vec<LsnTimeSample> {
+        let mut branches = vec![(self.lsn_time_index.samples_up_to(Lsn::MAX), Lsn::MAX)];
+        let mut timeline = self.ancestor_timeline.clone();
+        let mut branch_point = self.ancestor_lsn;
+        while let Some(ancestor) = timeline {
+            branches.push((
+                ancestor.lsn_time_index.samples_up_to(branch_point),
+                branch_point,
+            ));
+            branch_point = ancestor.ancestor_lsn;
+            timeline = ancestor.ancestor_timeline.clone();
+        }
+
+        // Oldest first, keeping the order by timestamp across the branch points.
+        let mut samples: Vec<LsnTimeSample> = Vec::new();
+        for (branch, _) in branches.into_iter().rev() {
+            for sample in branch {
+                if samples
+                    .last()
+                    .is_some_and(|last| sample.timestamp < last.timestamp)
+                {
+                    continue;
+                }
+                samples.push(sample);
+            }
+        }
+        samples
+    }
+
+    /// The sampled commits right before and after `timestamp`: the LSN of the last commit at or
+    /// before `timestamp` is between theirs.
+    pub(crate) fn lsn_time_bracket_by_timestamp(
+        &self,
+        timestamp: TimestampTz,
+    ) -> LsnTimestampBracket {
+        bracket(&self.lsn_time_samples(), timestamp, |s| s.timestamp)
+    }
+
+    /// The sampled commits right before and after `lsn`: the timestamp of the last commit at or
+    /// below `lsn` is between theirs.
+    pub(crate) fn lsn_time_bracket_by_lsn(&self, lsn: Lsn) -> LsnTimestampBracket {
+        bracket(&self.lsn_time_samples(), lsn, |s| s.lsn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: TimestampTz = 60_000_000;
+
+    fn samples(index: &LsnTimeIndex) -> Vec<LsnTimeSample> {
+        index.samples_up_to(Lsn::MAX)
+    }
+
+    #[test]
+    fn sampling() {
+        let index = LsnTimeIndex::default();
+        index.record_commit(Lsn(0x100), MINUTE);
+        // Too close to the last sample, then out of order.
+        index.record_commit(Lsn(0x200), MINUTE + MINUTE / 2);
+        index.record_commit(Lsn(0x300), 0);
+        index.record_commit(Lsn(0x400), 2 * MINUTE);
+        assert_eq!(
+            samples(&index),
+            vec![
+                LsnTimeSample {
+                    lsn: Lsn(0x100),
+                    timestamp: MINUTE
+                },
+                LsnTimeSample {
+                    lsn: Lsn(0x400),
+                    timestamp: 2 * MINUTE
+                },
+            ]
+        );
+
+        let s = samples(&index);
+        let by_lsn = bracket(&s, Lsn(0x200), |s| s.lsn);
+        assert_eq!(by_lsn.before.unwrap().lsn, Lsn(0x100));
+        assert_eq!(by_lsn.after.unwrap().lsn, Lsn(0x400));
+        let by_time = bracket(&s, 2 * MINUTE, |s| s.timestamp);
+        assert_eq!(by_time.before.unwrap().lsn, Lsn(0x400));
+        assert!(by_time.after.is_none());
+        let by_time = bracket(&s, 0, |s| s.timestamp);
+        assert!(by_time.before.is_none());
+        assert_eq!(by_time.after.unwrap().lsn, Lsn(0x100));
+    }
+
+    #[test]
+    fn thinning_keeps_recent_precision() {
+        let index = LsnTimeIndex::default();
+        let commits = 4 * MAX_SAMPLES as u64;
+        for i in 0..commits {
+            index.record_commit(Lsn(0x100 * (i + 1)), MINUTE * i as TimestampTz);
+        }
+        let s = samples(&index);
+        assert!(s.len() <= MAX_SAMPLES);
+        assert_eq!(s.first().unwrap().timestamp, 0);
+        assert_eq!(s.last().unwrap().lsn, Lsn(0x100 * commits));
+
+        // The recent half keeps one sample a minute, the interval grows with age.
+        let recent = &s[s.len() - MAX_SAMPLES / 4..];
+        assert!(recent
+            .windows(2)
+            .all(|w| w[1].timestamp - w[0].timestamp == MINUTE));
+        let oldest_gap = s[1].timestamp - s[0].timestamp;
+        assert!(oldest_gap > 2 * MINUTE, "oldest gap {oldest_gap}");
+    }
+
+    #[test]
+    fn restore() {
+        let index = LsnTimeIndex::default();
+        for i in 0..10 {
+            index.record_commit(Lsn(0x100 * (i + 1)), MINUTE * i as TimestampTz);
+        }
+        // As persisted in the index part.
+        let persisted = serde_json::to_string(&samples(&index)).unwrap();
+
+        // Commits ingested before the restore are kept after the restored samples.
+        let restored = LsnTimeIndex::default();
+        restored.record_commit(Lsn(0x2000), 100 * MINUTE);
+        restored.restore(serde_json::from_str(&persisted).unwrap());
+        let s = samples(&restored);
+        assert_eq!(s.len(), 11);
+        assert_eq!(s[..10], samples(&index)[..]);
+        assert_eq!(s[10].lsn, Lsn(0x2000));
+
+        // Samples out of order are not restored.
+        let restored = LsnTimeIndex::default();
+        let mut out_of_order = samples(&index);
+        out_of_order.swap(3, 4);
+        restored.restore(out_of_order);
+        assert!(samples(&restored).is_empty());
+    }
+}
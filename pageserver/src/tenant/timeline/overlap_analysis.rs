@@ -0,0 +1,227 @@
+//! Detection and repair of pathological stacks of delta layers.
+//!
+//! Compaction creates image layers for a partition of the keyspace once enough delta layers
+//! are stacked above its latest image, but it only checks every
+//! `image_layer_creation_check_threshold` checkpoint distances of ingested WAL, and counts the
+//! deltas of whole partitions. After ingest anomalies, hundreds of thin delta layers sometimes
+//! end up stacked over a narrow key range, and every read in it goes through all of them.
+//!
+//! The analyzer builds a histogram of the number of non-L0 delta layers stacked above the
+//! latest image layer, by key range, and reports the ranges deeper than a threshold as
+//! [`LayerOverlapHotspot`]s. Every compaction analyzes the timeline with the
+//! `overlap_repair_threshold` tenant config, and creates image layers for the partitions that
+//! overlap a hotspot, regardless of the usual image creation checks. The hotspots are also
+//! exposed by the `layer_overlap` management API.
+
+use std::ops::Range;
+use std::sync::Mutex;
+
+use pageserver_api::key::Key;
+use pageserver_api::keyspace::KeySpace;
+use pageserver_api::models::LayerOverlapHotspot;
+use tracing::info;
+use utils::lsn::Lsn;
+
+use crate::metrics::LAYER_OVERLAP_REPAIRS;
+use crate::tenant::layer_map::LayerMap;
+
+use super::Timeline;
+
+/// The key ranges whose image layers the next compaction creates.
+#[derive(Default)]
+pub(crate) struct OverlapRepairs {
+    pending: Mutex<Vec<Range<Key>>>,
+}
+
+impl OverlapRepairs {
+    pub(super) fn overlaps(&self, partition: &KeySpace) -> bool {
+        self.pending
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|range| partition.overlaps(range))
+    }
+
+    pub(super) fn clear(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+}
+
+/// The key ranges of `coverage` where more than `threshold` of the `deltas` are stacked above
+/// the image layer, deepest first.
+///
+/// `coverage` is the image coverage of the keyspace: contiguous key ranges, each with the LSN
+/// range of its latest image layer, if it has one.
+fn find_hotspots(
+    coverage: &[(Range<Key>, Option<Range<Lsn>>)],
+    deltas: &[(Range<Key>, Range<Lsn>)],
+    threshold: usize,
+) -> Vec<LayerOverlapHotspot> {
+    // Start and end events of the deltas above the image, by coverage segment.
+    let mut events: Vec<Vec<(Key, i64)>> = vec![Vec::new(); coverage.len()];
+    for (key_range, lsn_range) in deltas {
+        let first = coverage.partition_point(|(segment, _)| segment.end <= key_range.start);
+        for (i, (segment, image)) in coverage.iter().enumerate().skip(first) {
+            if segment.start >= key_range.end {
+                break;
+            }
+            let above_image = match image {
+                Some(image) => lsn_range.end > image.end,
+                None => true,
+            };
+            if above_image {
+                events[i].push((key_range.start.max(segment.start), 1));
+                events[i].push((key_range.end.min(segment.end), -1));
+            }
+        }
+    }
+
+    let mut hotspots = Vec::new();
+    for ((_, image), mut events) in coverage.iter().zip(events) {
+        // Ends sort before starts at the same key: the key ranges are exclusive.
+        events.sort_unstable();
+        let mut depth = 0;
+        let mut open: Option<LayerOverlapHotspot> = None;
+        let mut events = events.into_iter().peekable();
+        while let Some((key, delta)) = events.next() {
+            depth += delta;
+            if events.peek().is_some_and(|(next, _)| *next == key) {
+                continue;
+            }
+            // `depth` is now the depth of the keys from `key` to the next event.
+            if depth > threshold as i64 {
+                let hotspot = open.get_or_insert_with(|| LayerOverlapHotspot {
+                    key_start: key,
+                    key_end: key,
+                    depth: 0,
+                    image_lsn: image.as_ref().map(|lsn_range| lsn_range.start),
+                });
+                hotspot.depth = hotspot.depth.max(depth as usize);
+            } else if let Some(mut hotspot) = open.take() {
+                hotspot.key_end = key;
+                hotspots.push(hotspot);
+            }
+        }
+    }
+    hotspots.sort_by(|a, b| b.depth.cmp(&a.depth));
+    hotspots
+}
+
+impl Timeline {
+    fn get_overlap_repair_threshold(&self) -> usize {
+        let tenant_conf = self.tenant_conf.load();
+        tenant_conf.tenant_conf.overlap_repair_threshold.unwrap_or(
+            self.conf
+                .default_tenant_conf
+                .load()
+                .overlap_repair_threshold,
+        )
+    }
+
+    /// The key ranges with more than `threshold` delta layers stacked above their latest
+    /// image layer, deepest first. L0 deltas are left to L0 compaction and not counted.
+    pub(crate) async fn analyze_layer_overlap(&self, threshold: usize) -> Vec<LayerOverlapHotspot> {
+        let lsn = self.get_last_record_lsn();
+        let (coverage, deltas) = {
+            let guard = self.layers.read().await;
+            let layer_map = guard.layer_map();
+            let mut coverage = layer_map
+                .image_coverage(&(Key::MIN..Key::MAX), lsn)
+                .into_iter()
+                .map(|(key_range, image)| (key_range, image.map(|l| l.get_lsn_range())))
+                .collect::<Vec<_>>();
+            if coverage.is_empty() {
+                coverage.push((Key::MIN..Key::MAX, None));
+            }
+            let deltas = layer_map
+                .iter_historic_layers()
+                .filter(|l| l.is_delta() && !LayerMap::is_l0(l))
+                .map(|l| (l.get_key_range(), l.get_lsn_range()))
+                .collect::<Vec<_>>();
+            (coverage, deltas)
+        };
+        find_hotspots(&coverage, &deltas, threshold)
+    }
+
+    /// Schedule image layer creation for the hotspots deeper than the `overlap_repair_threshold`
+    /// tenant config, see module-level comment.
+    pub(super) async fn schedule_overlap_repairs(&self) {
+        let threshold = self.get_overlap_repair_threshold();
+        if threshold == 0 {
+            return;
+        }
+        // No image layers are created for metadata keys yet.
+        let metadata_keys = Key::metadata_key_range();
+        let hotspots = self
+            .analyze_layer_overlap(threshold)
+            .await
+            .into_iter()
+            .filter(|hotspot| hotspot.key_end <= metadata_keys.start)
+            .collect::<Vec<_>>();
+        if hotspots.is_empty() {
+            return;
+        }
+
+        info!(
+            hotspots = hotspots.len(),
+            max_depth = hotspots[0].depth,
+            "scheduling image layer creation for key ranges with stacked delta layers"
+        );
+        LAYER_OVERLAP_REPAIRS.inc_by(hotspots.len() as u64);
+        *self.overlap_repairs.pending.lock().unwrap() = hotspots
+            .into_iter()
+            .map(|hotspot| hotspot.key_start..hotspot.key_end)
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(i: i128) -> Key {
+        Key::from_i128(0x1000 + i)
+    }
+
+    #[test]
+    fn hotspot_above_image() {
+        let coverage = vec![
+            (key(0)..key(100), Some(Lsn(0x50)..Lsn(0x51))),
+            (key(100)..key(200), None),
+        ];
+        let mut deltas = Vec::new();
+        // Thin deltas stacked over 10..20, above the image.
+        for i in 0..5 {
+            deltas.push((key(10)..key(20), Lsn(0x60 + i)..Lsn(0x61 + i)));
+        }
+        // Below the image: not counted.
+        for i in 0..5 {
+            deltas.push((key(30)..key(40), Lsn(0x10 + i)..Lsn(0x11 + i)));
+        }
+        // Over 150..250 without an image, clipped to the coverage.
+        for i in 0..3 {
+            deltas.push((key(150)..key(250), Lsn(0x10 + i)..Lsn(0x11 + i)));
+        }
+        // One more over 15..160, crossing the coverage segments.
+        deltas.push((key(15)..key(160), Lsn(0x70)..Lsn(0x71)));
+
+        assert_eq!(
+            find_hotspots(&coverage, &deltas, 2),
+            vec![
+                LayerOverlapHotspot {
+                    key_start: key(10),
+                    key_end: key(20),
+                    depth: 6,
+                    image_lsn: Some(Lsn(0x50)),
+                },
+                LayerOverlapHotspot {
+                    key_start: key(150),
+                    key_end: key(200),
+                    depth: 4,
+                    image_lsn: None,
+                },
+            ]
+        );
+        assert!(find_hotspots(&coverage, &deltas, 6).is_empty());
+    }
+}
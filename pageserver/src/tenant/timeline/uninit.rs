@@ -5,7 +5,11 @@ use camino::Utf8PathBuf;
 use tracing::{error, info, info_span};
 use utils::{fs_ext, id::TimelineId, lsn::Lsn};
 
-use crate::{context::RequestContext, import_datadir, tenant::Tenant};
+use crate::{
+    context::RequestContext,
+    import_datadir,
+    tenant::{crash_points, Tenant},
+};
 
 use super::Timeline;
 
@@ -145,6 +149,10 @@ impl Drop for UninitializedTimeline<'_> {
 
 pub(crate) fn cleanup_timeline_directory(create_guard: TimelineCreateGuard) {
     let timeline_path = &create_guard.timeline_path;
+    if crash_points::crashed(&create_guard.owning_tenant.tenant_shard_id) {
+        // Leave the directory to be purged on the next load, like after a real crash.
+        return;
+    }
     match fs_ext::ignore_absent_files(|| fs::remove_dir_all(timeline_path)) {
         Ok(()) => {
             info!("Timeline dir {timeline_path:?} removed successfully")
@@ -0,0 +1,225 @@
+//! Pacing of the layer flushes and compactions by the upload backlog.
+//!
+//! Every layer that a flush or a compaction writes is uploaded to remote storage, and is only
+//! local state until then: when remote storage is slower than the timeline produces layers,
+//! the upload queue grows, and flushing and compacting more only makes it grow faster.
+//!
+//! With `upload_backlog_pacing` configured, a timeline whose backlog of layer uploads exceeds
+//! `high_bytes` stops flushing its frozen layers and compacting in the background, until the
+//! backlog is back under `low_bytes`. Flushes and compactions wait while the timeline is paced,
+//! and the time they spend waiting is counted in `pageserver_upload_backlog_paced_seconds_total`.
+//!
+//! The walreceiver keeps ingesting while flushes are paced: `disk_consistent_lsn` stops
+//! advancing, and the flush lag backpressure of computes eventually slows the writes down.
+//! The frozen layers pile up in the meantime, so once they exceed `max_paced_bytes` they are
+//! flushed regardless of the backlog. Flushes that someone waits for, such as the ones of the
+//! checkpoint API and of shutdown, and compactions requested through the API, aren't paced.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::metrics::{UPLOAD_BACKLOG_PACED_SECONDS, UPLOAD_BACKLOG_PACED_TIMELINES};
+
+use super::Timeline;
+
+/// How often a paced flush or compaction checks whether the backlog drained.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const DEFAULT_MAX_PACED_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Thresholds of the upload backlog of a timeline, see module-level comment.
+///
+/// ```toml
+/// upload_backlog_pacing = { high_bytes = 2147483648, low_bytes = 536870912 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadBacklogPacingConfig {
+    /// Start pacing when the layers waiting to be uploaded exceed this many bytes.
+    pub high_bytes: u64,
+    /// Stop pacing when the layers waiting to be uploaded are back under this many bytes.
+    pub low_bytes: u64,
+    /// Flush anyway once the frozen in-memory layers exceed this many bytes.
+    #[serde(default = "default_max_paced_bytes")]
+    pub max_paced_bytes: u64,
+}
+
+fn default_max_paced_bytes() -> u64 {
+    DEFAULT_MAX_PACED_BYTES
+}
+
+#[derive(Debug, Clone, Copy, strum_macros::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum PacedOperation {
+    Flush,
+    Compaction,
+}
+
+/// Whether the layer production of a timeline is paced.
+#[derive(Default)]
+pub(crate) struct UploadPacing {
+    paced: AtomicBool,
+    /// The highest flush request counter, as sent on `layer_flush_start_tx`, of a flush that
+    /// someone waits for.
+    waited_flush_request: AtomicU64,
+}
+
+impl UploadPacing {
+    /// Don't pace flushes until the flush loop completes request `counter`.
+    pub(super) fn flush_waited_for(&self, counter: u64) {
+        self.waited_flush_request
+            .fetch_max(counter, Ordering::Relaxed);
+    }
+
+    /// Whether someone waits for a flush request after `done_counter`.
+    fn is_flush_waited_for(&self, done_counter: u64) -> bool {
+        self.waited_flush_request.load(Ordering::Relaxed) > done_counter
+    }
+
+    /// Update the state with the current backlog: start pacing above `high_bytes`, stop at or
+    /// below `low_bytes`, and keep the previous state in between. Returns whether it's paced.
+    fn update(&self, config: &UploadBacklogPacingConfig, backlog_bytes: u64) -> bool {
+        let paced = if backlog_bytes > config.high_bytes {
+            true
+        } else if backlog_bytes <= config.low_bytes {
+            false
+        } else {
+            return self.paced.load(Ordering::Relaxed);
+        };
+        let was_paced = self.paced.swap(paced, Ordering::Relaxed);
+        match (was_paced, paced) {
+            (false, true) => {
+                UPLOAD_BACKLOG_PACED_TIMELINES.inc();
+                info!(
+                    backlog_bytes,
+                    "upload backlog is deep, pacing layer production"
+                );
+            }
+            (true, false) => {
+                UPLOAD_BACKLOG_PACED_TIMELINES.dec();
+                info!(
+                    backlog_bytes,
+                    "upload backlog drained, resuming layer production"
+                );
+            }
+            _ => {}
+        }
+        paced
+    }
+}
+
+impl Drop for UploadPacing {
+    fn drop(&mut self) {
+        if *self.paced.get_mut() {
+            UPLOAD_BACKLOG_PACED_TIMELINES.dec();
+        }
+    }
+}
+
+impl Timeline {
+    /// Wait until the upload backlog of the timeline allows the flush loop to flush its frozen
+    /// layers, see the module-level comment. `done_counter` is the counter of the last flush
+    /// request that the flush loop completed.
+    pub(super) async fn wait_for_upload_backlog_to_flush(&self, done_counter: u64) {
+        self.wait_for_upload_backlog(PacedOperation::Flush, &self.cancel, |config| async move {
+            if self.upload_pacing.is_flush_waited_for(done_counter) {
+                return true;
+            }
+            let frozen_layers = {
+                let guard = self.layers.read().await;
+                guard.layer_map().frozen_layers.clone()
+            };
+            let mut frozen_bytes = 0;
+            for layer in frozen_layers {
+                frozen_bytes += layer.size().await.unwrap_or(0);
+            }
+            frozen_bytes > config.max_paced_bytes
+        })
+        .await
+    }
+
+    /// Wait until the upload backlog of the timeline allows background compaction, see the
+    /// module-level comment. Returns early if `cancel` is cancelled.
+    pub(crate) async fn wait_for_upload_backlog_to_compact(&self, cancel: &CancellationToken) {
+        self.wait_for_upload_backlog(PacedOperation::Compaction, cancel, |_| async { false })
+            .await
+    }
+
+    /// Wait while the timeline is paced, unless `cancel` is cancelled or `bypass` returns true.
+    async fn wait_for_upload_backlog<F: Future<Output = bool>>(
+        &self,
+        operation: PacedOperation,
+        cancel: &CancellationToken,
+        mut bypass: impl FnMut(UploadBacklogPacingConfig) -> F,
+    ) {
+        let Some(config) = self.conf.upload_backlog_pacing else {
+            return;
+        };
+        let Some(remote_client) = self.remote_client.as_ref() else {
+            return;
+        };
+
+        let started_at = std::time::Instant::now();
+        let mut waited = false;
+        while self
+            .upload_pacing
+            .update(&config, remote_client.upload_backlog_bytes())
+        {
+            if bypass(config).await {
+                break;
+            }
+            waited = true;
+            if tokio::time::timeout(POLL_INTERVAL, cancel.cancelled())
+                .await
+                .is_ok()
+            {
+                break;
+            }
+        }
+        if waited {
+            let operation: &'static str = operation.into();
+            UPLOAD_BACKLOG_PACED_SECONDS
+                .with_label_values(&[operation])
+                .inc_by(started_at.elapsed().as_secs_f64());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hysteresis() {
+        let config = UploadBacklogPacingConfig {
+            high_bytes: 1000,
+            low_bytes: 100,
+            max_paced_bytes: DEFAULT_MAX_PACED_BYTES,
+        };
+        let pacing = UploadPacing::default();
+
+        assert!(!pacing.update(&config, 500));
+        assert!(!pacing.update(&config, 1000));
+        assert!(pacing.update(&config, 1001));
+        // Stays paced until the backlog is under the low threshold.
+        assert!(pacing.update(&config, 500));
+        assert!(!pacing.update(&config, 100));
+        assert!(!pacing.update(&config, 500));
+    }
+
+    #[test]
+    fn waited_flushes() {
+        let pacing = UploadPacing::default();
+        assert!(!pacing.is_flush_waited_for(0));
+        pacing.flush_waited_for(3);
+        pacing.flush_waited_for(2);
+        // Paced again once the flush loop completed the highest waited request.
+        assert!(pacing.is_flush_waited_for(2));
+        assert!(!pacing.is_flush_waited_for(3));
+    }
+}
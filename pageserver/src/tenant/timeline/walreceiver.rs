@@ -21,6 +21,8 @@
 //! The current module contains high-level primitives used in the submodules; general synchronization, timeline acknowledgement and shutdown logic.
 
 mod connection_manager;
+mod grpc_connection;
+pub(crate) mod ingest_stats;
 mod walreceiver_connection;
 
 use crate::context::{DownloadBehavior, RequestContext};
@@ -30,6 +32,7 @@ use crate::tenant::timeline::walreceiver::connection_manager::{
     connection_manager_loop_step, ConnectionManagerState,
 };
 
+use serde::{Deserialize, Serialize};
 use std::future::Future;
 use std::num::NonZeroU64;
 use std::sync::Arc;
@@ -54,6 +57,36 @@ pub struct WalReceiverConf {
     pub auth_token: Option<Arc<String>>,
     pub availability_zone: Option<String>,
     pub ingest_batch_size: u64,
+    pub protocol: WalReceiverProtocol,
+}
+
+/// The protocol to stream WAL from safekeepers with.
+///
+/// ```toml
+/// wal_receiver_protocol = { type = "grpc", compression = true, window_bytes = 16777216 }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum WalReceiverProtocol {
+    /// The postgres replication protocol.
+    #[default]
+    Postgres,
+    /// The gRPC WAL stream service of safekeepers, with explicit flow control: at most
+    /// `window_bytes` of WAL are in flight. Falls back to the replication protocol for the
+    /// safekeepers which don't advertise the service.
+    Grpc {
+        /// Compress the streamed WAL.
+        #[serde(default)]
+        compression: bool,
+        #[serde(default = "WalReceiverProtocol::default_window_bytes")]
+        window_bytes: u64,
+    },
+}
+
+impl WalReceiverProtocol {
+    fn default_window_bytes() -> u64 {
+        16 * 1024 * 1024
+    }
 }
 
 pub struct WalReceiver {
@@ -11,7 +11,8 @@
 
 use std::{collections::HashMap, num::NonZeroU64, ops::ControlFlow, sync::Arc, time::Duration};
 
-use super::{TaskStateUpdate, WalReceiverConf};
+use super::grpc_connection::GrpcStreamConf;
+use super::{TaskStateUpdate, WalReceiverConf, WalReceiverProtocol};
 use crate::context::{DownloadBehavior, RequestContext};
 use crate::metrics::{
     WALRECEIVER_ACTIVE_MANAGERS, WALRECEIVER_BROKER_UPDATES, WALRECEIVER_CANDIDATES_ADDED,
@@ -23,10 +24,11 @@ use anyhow::Context;
 use chrono::{NaiveDateTime, Utc};
 use pageserver_api::models::TimelineState;
 
+use storage_broker::delta::DeltaDecoder;
 use storage_broker::proto::TenantTimelineId as ProtoTenantTimelineId;
 use storage_broker::proto::{
-    FilterTenantTimelineId, MessageType, SafekeeperDiscoveryRequest, SafekeeperDiscoveryResponse,
-    SubscribeByFilterRequest, TypeSubscription, TypedMessage,
+    MessageType, SafekeeperDiscoveryRequest, SafekeeperDiscoveryResponse, SubscribeByFilterRequest,
+    TypeSubscription, TypedMessage,
 };
 use storage_broker::{BrokerClientChannel, Code, Streaming};
 use tokio_util::sync::CancellationToken;
@@ -95,6 +97,10 @@ pub(super) async fn connection_manager_loop_step(
         .timeline
         .subscribe_for_wait_lsn_updates();
 
+    let mut read_only_updates = connection_manager_state
+        .timeline
+        .subscribe_for_read_only_updates();
+
     // TODO: create a separate config option for discovery request interval
     let discovery_request_interval = connection_manager_state.conf.lagging_wal_timeout;
     let mut last_discovery_ts: Option<std::time::Instant> = None;
@@ -103,6 +109,7 @@ pub(super) async fn connection_manager_loop_step(
     // with other streams on this client (other connection managers). When
     // object goes out of scope, stream finishes in drop() automatically.
     let mut broker_subscription = subscribe_for_timeline_updates(broker_client, id, cancel).await?;
+    let mut broker_decoder = DeltaDecoder::default();
     debug!("Subscribed for broker timeline updates");
 
     loop {
@@ -159,7 +166,12 @@ pub(super) async fn connection_manager_loop_step(
             // Got a new update from the broker
             broker_update = broker_subscription.message() /* TODO: review cancellation-safety */ => {
                 match broker_update {
-                    Ok(Some(broker_update)) => connection_manager_state.register_timeline_update(broker_update),
+                    Ok(Some(broker_update)) => match broker_decoder.decode(broker_update) {
+                        Ok(Some(broker_update)) => connection_manager_state.register_timeline_update(broker_update),
+                        // A delta for a safekeeper we haven't got a full update from yet.
+                        Ok(None) => {}
+                        Err(status) => warn!("bad proto message from broker: {status}"),
+                    },
                     Err(status) => {
                         match status.code() {
                             Code::Unknown if status.message().contains("stream closed because of a broken pipe") || status.message().contains("connection reset") => {
@@ -215,6 +227,16 @@ pub(super) async fn connection_manager_loop_step(
                 }
             },
 
+            Ok(()) = read_only_updates.changed() => {
+                let read_only = *read_only_updates.borrow_and_update();
+                if let Some(reason) = read_only {
+                    if connection_manager_state.wal_connection.is_some() {
+                        info!(%reason, "tenant set read-only, disconnecting");
+                        connection_manager_state.drop_old_connection(true).await;
+                    }
+                }
+            },
+
             Some(()) = async {
                 match time_until_next_retry {
                     Some(sleep_time) => {
@@ -268,7 +290,8 @@ pub(super) async fn connection_manager_loop_step(
                     safekeeper_timeline_info: None,
                     safekeeper_discovery_request: Some(request),
                     safekeeper_discovery_response: None,
-                    };
+                    ..Default::default()
+                };
 
                 last_discovery_ts = Some(std::time::Instant::now());
                 debug!("No active connection and no candidates, sending discovery request to the broker");
@@ -288,11 +311,15 @@ pub(super) async fn connection_manager_loop_step(
             } => {}
         }
 
-        if let Some(new_candidate) = connection_manager_state.next_connection_candidate() {
-            info!("Switching to new connection candidate: {new_candidate:?}");
-            connection_manager_state
-                .change_connection(new_candidate, ctx)
-                .await
+        // The connection of a read-only tenant is dropped as soon as the mode is set, and isn't
+        // replaced until the tenant accepts WAL again.
+        if connection_manager_state.timeline.get_read_only().is_none() {
+            if let Some(new_candidate) = connection_manager_state.next_connection_candidate() {
+                info!("Switching to new connection candidate: {new_candidate:?}");
+                connection_manager_state
+                    .change_connection(new_candidate, ctx)
+                    .await
+            }
         }
         *manager_status.write().unwrap() = Some(connection_manager_state.manager_status());
     }
@@ -325,13 +352,12 @@ async fn subscribe_for_timeline_updates(
                     r#type: MessageType::SafekeeperDiscoveryResponse as i32,
                 },
             ],
-            tenant_timeline_id: Some(FilterTenantTimelineId {
-                enabled: true,
-                tenant_timeline_id: Some(ProtoTenantTimelineId {
-                    tenant_id: id.tenant_id.as_ref().to_owned(),
-                    timeline_id: id.timeline_id.as_ref().to_owned(),
-                }),
-            }),
+            tenant_timeline_id: None,
+            tenant_timeline_ids: vec![ProtoTenantTimelineId {
+                tenant_id: id.tenant_id.as_ref().to_owned(),
+                timeline_id: id.timeline_id.as_ref().to_owned(),
+            }],
+            delta_updates: true,
         };
 
         match {
@@ -527,13 +553,39 @@ impl ConnectionManagerState {
         WALRECEIVER_SWITCHES
             .with_label_values(&[new_sk.reason.name()])
             .inc();
+        if self
+            .timeline
+            .wal_ingest_stats
+            .observe_connection(new_sk.reason.name())
+        {
+            self.timeline.metrics.walreceiver_reconnects.inc();
+        }
 
         self.drop_old_connection(true).await;
 
         let node_id = new_sk.safekeeper_id;
         let connect_timeout = self.conf.wal_connect_timeout;
         let ingest_batch_size = self.conf.ingest_batch_size;
+        let auth_token = self.conf.auth_token.clone();
+        // Stream over gRPC if configured to, and the safekeeper advertises the service.
+        let grpc_stream = match self.conf.protocol {
+            WalReceiverProtocol::Postgres => None,
+            WalReceiverProtocol::Grpc {
+                compression,
+                window_bytes,
+            } => self
+                .wal_stream_candidates
+                .get(&node_id)
+                .map(|candidate| candidate.timeline.wal_grpc_connstr.clone())
+                .filter(|endpoint| !endpoint.is_empty())
+                .map(|endpoint| GrpcStreamConf {
+                    endpoint,
+                    compression,
+                    window_bytes,
+                }),
+        };
         let timeline = Arc::clone(&self.timeline);
+        let stats_timeline = Arc::clone(&self.timeline);
         let ctx = ctx.detached_child(
             TaskKind::WalReceiverConnectionHandler,
             DownloadBehavior::Download,
@@ -544,17 +596,36 @@ impl ConnectionManagerState {
             async move {
                 debug_assert_current_span_has_tenant_and_timeline_id();
 
-                let res = super::walreceiver_connection::handle_walreceiver_connection(
-                    timeline,
-                    new_sk.wal_source_connconf,
-                    events_sender,
-                    cancellation.clone(),
-                    connect_timeout,
-                    ctx,
-                    node_id,
-                    ingest_batch_size,
-                )
-                .await;
+                let res = match grpc_stream {
+                    Some(grpc_stream) => {
+                        super::grpc_connection::handle_grpc_walreceiver_connection(
+                            timeline,
+                            grpc_stream,
+                            new_sk.wal_source_connconf,
+                            auth_token,
+                            events_sender,
+                            cancellation.clone(),
+                            connect_timeout,
+                            ctx,
+                            node_id,
+                            ingest_batch_size,
+                        )
+                        .await
+                    }
+                    None => {
+                        super::walreceiver_connection::handle_walreceiver_connection(
+                            timeline,
+                            new_sk.wal_source_connconf,
+                            events_sender,
+                            cancellation.clone(),
+                            connect_timeout,
+                            ctx,
+                            node_id,
+                            ingest_batch_size,
+                        )
+                        .await
+                    }
+                };
 
                 match res {
                     Ok(()) => Ok(()),
@@ -568,12 +639,20 @@ impl ConnectionManagerState {
                                 info!("walreceiver connection handling ended: {e}");
                                 Ok(())
                             }
+                            WalReceiverError::ExpectedGrpcError(e) => {
+                                info!("walreceiver connection handling ended: {e}");
+                                Ok(())
+                            }
                             WalReceiverError::ClosedGate => {
                                 info!(
                                     "walreceiver connection handling ended because of closed gate"
                                 );
                                 Ok(())
                             }
+                            WalReceiverError::ReadOnly(reason) => {
+                                info!("walreceiver connection handling ended, the tenant is read-only: {reason}");
+                                Ok(())
+                            }
                             WalReceiverError::Other(e) => {
                                 // give out an error to have task_mgr give it a really verbose logging
                                 if cancellation.is_cancelled() {
@@ -582,6 +661,7 @@ impl ConnectionManagerState {
                                     // that only emit anyhow::Error
                                     Ok(())
                                 } else {
+                                    stats_timeline.wal_ingest_stats.observe_connection_failure();
                                     Err(e).context("walreceiver connection handling failure")
                                 }
                             }
@@ -705,6 +785,9 @@ impl ConnectionManagerState {
                     commit_lsn: info.commit_lsn,
                     safekeeper_connstr: info.safekeeper_connstr,
                     availability_zone: info.availability_zone,
+                    mconf_generation: info.mconf_generation,
+                    mconf_members: info.mconf_members,
+                    wal_grpc_connstr: info.wal_grpc_connstr,
                 }
             }
             MessageType::SafekeeperDiscoveryResponse => {
@@ -772,6 +855,18 @@ impl ConnectionManagerState {
                     self.select_connection_candidate(Some(connected_sk_node))?;
                 let new_availability_zone = new_safekeeper_broker_data.availability_zone.clone();
 
+                // The connected safekeeper was removed from the timeline's configuration.
+                if let Some(members) = self.latest_members() {
+                    if !members.contains(&connected_sk_node.0) {
+                        return Some(NewWalConnectionCandidate {
+                            safekeeper_id: new_sk_id,
+                            wal_source_connconf: new_wal_source_connconf,
+                            availability_zone: new_availability_zone,
+                            reason: ReconnectReason::NotMember,
+                        });
+                    }
+                }
+
                 let now = Utc::now().naive_utc();
                 if let Ok(latest_interaciton) =
                     (now - existing_wal_connection.status.latest_connection_update).to_std()
@@ -944,16 +1039,35 @@ impl ConnectionManagerState {
             .max_by_key(|(_, info, _)| info.commit_lsn)
     }
 
+    /// Members of the newest membership configuration of the timeline reported by safekeepers,
+    /// or `None` if no safekeeper reported one. Safekeepers removed from the timeline keep
+    /// publishing their state until they are cleaned up, so we can't rely on them to disappear.
+    fn latest_members(&self) -> Option<Vec<u64>> {
+        self.wal_stream_candidates
+            .values()
+            .map(|broker_info| &broker_info.timeline)
+            .filter(|info| info.mconf_generation > 0)
+            .max_by_key(|info| info.mconf_generation)
+            .map(|info| info.mconf_members.clone())
+    }
+
     /// Returns a list of safekeepers that have valid info and ready for connection.
-    /// Some safekeepers are filtered by the retry cooldown.
+    /// Some safekeepers are filtered by the retry cooldown, and those which are not
+    /// members of the timeline's latest membership configuration are skipped.
     fn applicable_connection_candidates(
         &self,
     ) -> impl Iterator<Item = (NodeId, &SafekeeperDiscoveryResponse, PgConnectionConfig)> {
         let now = Utc::now().naive_utc();
+        let latest_members = self.latest_members();
 
         self.wal_stream_candidates
             .iter()
             .filter(|(_, info)| Lsn(info.timeline.commit_lsn) != Lsn::INVALID)
+            .filter(move |(sk_id, _)| {
+                latest_members
+                    .as_ref()
+                    .map_or(true, |members| members.contains(&sk_id.0))
+            })
             .filter(move |(sk_id, _)| {
                 let next_retry_at = self
                     .wal_connection_retries
@@ -1062,6 +1176,7 @@ enum ReconnectReason {
         check_time: NaiveDateTime,
         threshold: Duration,
     },
+    NotMember,
 }
 
 impl ReconnectReason {
@@ -1072,6 +1187,7 @@ impl ReconnectReason {
             ReconnectReason::SwitchAvailabilityZone => "SwitchAvailabilityZone",
             ReconnectReason::NoWalTimeout { .. } => "NoWalTimeout",
             ReconnectReason::NoKeepAlives { .. } => "NoKeepAlives",
+            ReconnectReason::NotMember => "NotMember",
         }
     }
 }
@@ -1094,6 +1210,9 @@ mod tests {
                 commit_lsn,
                 safekeeper_connstr: safekeeper_connstr.to_owned(),
                 availability_zone: None,
+                mconf_generation: 0,
+                mconf_members: Vec::new(),
+                wal_grpc_connstr: String::new(),
             },
             latest_update,
         }
@@ -1300,6 +1419,42 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn candidates_outside_of_membership() -> anyhow::Result<()> {
+        let harness = TenantHarness::create("candidates_outside_of_membership")?;
+        let mut state = dummy_state(&harness).await;
+        let now = Utc::now().naive_utc();
+
+        let current_lsn = Lsn(100_000).align();
+        let bigger_lsn = Lsn(current_lsn.0 + 100).align();
+        let with_mconf = |commit_lsn: Lsn, generation: u32, members: &[u64]| {
+            let mut sk = dummy_broker_sk_timeline(commit_lsn.0, DUMMY_SAFEKEEPER_HOST, now);
+            sk.timeline.mconf_generation = generation;
+            sk.timeline.mconf_members = members.to_vec();
+            sk
+        };
+
+        // Node 0 has the most WAL, but it was removed from the timeline in generation 3,
+        // which it doesn't know about.
+        state.wal_connection = None;
+        state.wal_stream_candidates = HashMap::from([
+            (NodeId(0), with_mconf(bigger_lsn, 1, &[0, 1])),
+            (NodeId(1), with_mconf(current_lsn, 3, &[1, 2])),
+            (NodeId(2), with_mconf(current_lsn, 3, &[1, 2])),
+        ]);
+
+        let candidate = state
+            .next_connection_candidate()
+            .expect("Expected one candidate selected, but got none");
+        assert_ne!(
+            candidate.safekeeper_id,
+            NodeId(0),
+            "Should not select a node which is not a member of the latest configuration"
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn lsn_wal_over_threshold_current_candidate() -> anyhow::Result<()> {
         let harness = TenantHarness::create("lsn_wal_over_threshcurrent_candidate")?;
@@ -1520,6 +1675,7 @@ mod tests {
                 auth_token: None,
                 availability_zone: None,
                 ingest_batch_size: 1,
+                protocol: WalReceiverProtocol::Postgres,
             },
             wal_connection: None,
             wal_stream_candidates: HashMap::new(),
@@ -0,0 +1,253 @@
+//! Connection handler to stream WAL from the gRPC WAL stream service of a safekeeper.
+//!
+//! Unlike with the replication protocol, the amount of WAL in flight is explicit: the
+//! handler grants the safekeeper `window_bytes` of credit when it starts streaming, and
+//! grants back the size of every chunk of WAL once it ingested it. A slow ingest stalls the
+//! safekeeper, instead of filling up the socket buffers in between. Like with the
+//! replication protocol, the stream starts from the end of the last ingested record, so a
+//! new connection after a disconnect resumes where the previous one stopped.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::Utc;
+use postgres_connection::PgConnectionConfig;
+use storage_broker::wal_stream::proto::{
+    wal_stream_request, wal_stream_response, Credit, Feedback, StartStreaming, WalStreamRequest,
+};
+use storage_broker::wal_stream::{self, Status};
+use storage_broker::Code;
+use tokio::sync::{mpsc, watch};
+use tokio::{select, time};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, trace, warn};
+use utils::id::NodeId;
+use utils::lsn::Lsn;
+use utils::pageserver_feedback::PageserverFeedback;
+use utils::sync::gate::GateError;
+
+use super::walreceiver_connection::{
+    make_feedback, streaming_start_point, WalConnectionStatus, WalReceiverError, WalStreamIngest,
+};
+use super::TaskStateUpdate;
+use crate::context::RequestContext;
+use crate::metrics::{LIVE_CONNECTIONS_COUNT, WALRECEIVER_STARTED_CONNECTIONS, WAL_INGEST};
+use crate::tenant::{debug_assert_current_span_has_tenant_and_timeline_id, Timeline};
+
+/// Where and how to stream WAL over gRPC.
+#[derive(Debug)]
+pub(super) struct GrpcStreamConf {
+    /// The advertised endpoint of the service, in the form host:port.
+    pub(super) endpoint: String,
+    pub(super) compression: bool,
+    pub(super) window_bytes: u64,
+}
+
+impl From<Status> for WalReceiverError {
+    fn from(status: Status) -> Self {
+        match status.code() {
+            // The safekeeper went away, or the connection broke.
+            Code::Unavailable | Code::Cancelled | Code::Aborted => Self::ExpectedGrpcError(status),
+            _ => Self::Other(anyhow::Error::new(status)),
+        }
+    }
+}
+
+/// Open a gRPC WAL stream from the given safekeeper and ingest the WAL, granting credit and
+/// sending back progress messages as we go.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn handle_grpc_walreceiver_connection(
+    timeline: Arc<Timeline>,
+    stream_conf: GrpcStreamConf,
+    wal_source_connconf: PgConnectionConfig,
+    auth_token: Option<Arc<String>>,
+    events_sender: watch::Sender<TaskStateUpdate<WalConnectionStatus>>,
+    cancellation: CancellationToken,
+    connect_timeout: Duration,
+    ctx: RequestContext,
+    node: NodeId,
+    ingest_batch_size: u64,
+) -> Result<(), WalReceiverError> {
+    debug_assert_current_span_has_tenant_and_timeline_id();
+
+    // prevent timeline shutdown from finishing until we have exited
+    let _guard = timeline.gate.enter().map_err(|e| match e {
+        GateError::GateClosed => WalReceiverError::ClosedGate,
+    })?;
+
+    WALRECEIVER_STARTED_CONNECTIONS.inc();
+
+    info!("connecting to gRPC WAL stream service at {stream_conf:?}");
+
+    let mut client = match time::timeout(
+        connect_timeout,
+        wal_stream::connect(&stream_conf.endpoint, stream_conf.compression),
+    )
+    .await
+    {
+        Ok(client) => client?,
+        Err(_elapsed) => {
+            info!("Timed out while waiting {connect_timeout:?} for walreceiver connection to open");
+            return Ok(());
+        }
+    };
+
+    debug!("connected!");
+    let mut connection_status = WalConnectionStatus {
+        is_connected: true,
+        has_processed_wal: false,
+        latest_connection_update: Utc::now().naive_utc(),
+        latest_wal_update: Utc::now().naive_utc(),
+        streaming_lsn: None,
+        commit_lsn: None,
+        node,
+    };
+    if let Err(e) = events_sender.send(TaskStateUpdate::Progress(connection_status)) {
+        warn!("Wal connection event listener dropped right after connection init, aborting the connection: {e}");
+        return Ok(());
+    }
+
+    let gauge = LIVE_CONNECTIONS_COUNT.with_label_values(&["wal_receiver"]);
+    gauge.inc();
+    scopeguard::defer! {
+        gauge.dec();
+    }
+
+    let last_rec_lsn = timeline.get_last_record_lsn();
+    let startpoint = streaming_start_point(last_rec_lsn)?;
+
+    info!("last_record_lsn {last_rec_lsn} starting gRPC WAL stream from {startpoint}...");
+
+    // The requests are credit grants and feedback, one of each per message received at
+    // most, so the channel doesn't need a bound.
+    let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+    let send_request = |request: wal_stream_request::Request| {
+        // If the stream is gone, receiving tells us why.
+        let _ = requests_tx.send(WalStreamRequest {
+            request: Some(request),
+        });
+    };
+    send_request(wal_stream_request::Request::Start(StartStreaming {
+        tenant_id: timeline.tenant_shard_id.tenant_id.as_ref().to_owned(),
+        timeline_id: timeline.timeline_id.as_ref().to_owned(),
+        start_lsn: startpoint.0,
+        initial_credit: stream_conf.window_bytes,
+    }));
+    let request = wal_stream::authenticated_request(
+        UnboundedReceiverStream::new(requests_rx),
+        auth_token.as_deref().map(String::as_str),
+    )?;
+    let mut responses = client.stream_wal(request).await?.into_inner();
+
+    let mut ingest = WalStreamIngest::new(&timeline, startpoint, ingest_batch_size, &ctx).await?;
+    let mut streaming_lsn = startpoint;
+
+    loop {
+        let response = select! {
+            _ = cancellation.cancelled() => {
+                debug!("walreceiver interrupted");
+                return Ok(());
+            }
+            response = responses.message() => response?,
+        };
+        let Some(response) = response else {
+            return Err(WalReceiverError::SuccessfulCompletion(format!(
+                "safekeeper ended the gRPC WAL stream at {streaming_lsn}"
+            )));
+        };
+
+        let now = Utc::now().naive_utc();
+        let last_rec_lsn_before_msg = ingest.last_rec_lsn();
+
+        // Update the connection status before processing the message. If the message processing
+        // fails (e.g. in walingest), we still want to know latests LSNs from the safekeeper.
+        match &response.response {
+            Some(wal_stream_response::Response::Wal(chunk)) => {
+                connection_status.latest_connection_update = now;
+                connection_status.commit_lsn = Some(Lsn(chunk.commit_lsn));
+                connection_status.streaming_lsn =
+                    Some(Lsn(chunk.start_lsn + chunk.data.len() as u64));
+                if !chunk.data.is_empty() {
+                    connection_status.latest_wal_update = now;
+                }
+            }
+            Some(wal_stream_response::Response::Keepalive(keepalive)) => {
+                connection_status.latest_connection_update = now;
+                connection_status.commit_lsn = Some(Lsn(keepalive.commit_lsn));
+            }
+            None => {}
+        }
+        if let Err(e) = events_sender.send(TaskStateUpdate::Progress(connection_status)) {
+            warn!("Wal connection event listener dropped, aborting the connection: {e}");
+            return Ok(());
+        }
+
+        let status_update = match response.response {
+            Some(wal_stream_response::Response::Wal(chunk)) => {
+                let startlsn = Lsn(chunk.start_lsn);
+                let endlsn = startlsn + chunk.data.len() as u64;
+                if startlsn != streaming_lsn {
+                    return Err(WalReceiverError::Other(anyhow!(
+                        "safekeeper sent WAL from {startlsn}, expected {streaming_lsn}"
+                    )));
+                }
+
+                trace!("received WAL between {startlsn} and {endlsn}");
+
+                WAL_INGEST.bytes_received.inc_by(chunk.data.len() as u64);
+                ingest
+                    .ingest(&timeline, startlsn, &chunk.data, &ctx)
+                    .await?;
+                streaming_lsn = endlsn;
+
+                // The chunk is ingested, the safekeeper can send as much more.
+                send_request(wal_stream_request::Request::Credit(Credit {
+                    bytes: chunk.data.len() as u64,
+                }));
+
+                Some(endlsn)
+            }
+            Some(wal_stream_response::Response::Keepalive(keepalive)) => {
+                trace!("received KeepAlive(commit_lsn: {})", keepalive.commit_lsn);
+                Some(ingest.last_rec_lsn())
+            }
+            None => None,
+        };
+
+        if !connection_status.has_processed_wal && ingest.last_rec_lsn() > last_rec_lsn_before_msg {
+            // We have successfully processed at least one WAL record.
+            connection_status.has_processed_wal = true;
+            if let Err(e) = events_sender.send(TaskStateUpdate::Progress(connection_status)) {
+                warn!("Wal connection event listener dropped, aborting the connection: {e}");
+                return Ok(());
+            }
+        }
+
+        if let Some(last_lsn) = status_update {
+            let status_update = make_feedback(&timeline, &wal_source_connconf, last_lsn, &ctx);
+
+            debug!("neon_status_update {status_update:?}");
+
+            send_request(wal_stream_request::Request::Feedback(feedback_message(
+                &status_update,
+            )));
+        }
+    }
+}
+
+fn feedback_message(feedback: &PageserverFeedback) -> Feedback {
+    Feedback {
+        last_received_lsn: feedback.last_received_lsn.0,
+        disk_consistent_lsn: feedback.disk_consistent_lsn.0,
+        remote_consistent_lsn: feedback.remote_consistent_lsn.0,
+        current_timeline_size: feedback.current_timeline_size,
+        reply_time_micros: feedback
+            .replytime
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0),
+        shard_number: feedback.shard_number,
+    }
+}
@@ -0,0 +1,191 @@
+//! Breakdown of the WAL ingest of a timeline, to root-cause ingest stalls without the logs.
+//!
+//! [`WalIngestStats`] accumulates, over the lifetime of the timeline object:
+//! - the WAL bytes received and the records decoded from them, and their rates over the last
+//!   [`RATE_WINDOW`];
+//! - the time spent decoding the records, applying them to the timeline, and committing them;
+//! - the connections to safekeepers, the ones that failed, and the reason of the last switch.
+//!
+//! It is shown as `wal_ingest` in the timeline info of the mgmt API. The times and reconnects
+//! are also exported as the per-timeline `pageserver_wal_ingest_seconds_total` and
+//! `pageserver_walreceiver_reconnects_total` metrics.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use pageserver_api::models::WalIngestInfo;
+
+/// The period over which the rates are computed.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// The time spent on a batch of ingested WAL.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct IngestTimes {
+    pub(crate) decode: Duration,
+    pub(crate) apply: Duration,
+    pub(crate) commit: Duration,
+}
+
+#[derive(Default)]
+pub(crate) struct WalIngestStats {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    bytes_received: u64,
+    records_ingested: u64,
+    times: IngestTimes,
+    rates: Rates,
+    connections: u64,
+    connection_failures: u64,
+    last_switch: Option<(String, SystemTime)>,
+}
+
+#[derive(Default)]
+struct Rates {
+    window_start: Option<Instant>,
+    window_bytes: u64,
+    window_records: u64,
+    /// Bytes and records per second over the last complete window, and when it ended.
+    last: Option<(f64, f64, Instant)>,
+}
+
+impl Rates {
+    fn observe(&mut self, bytes: u64, records: u64, now: Instant) {
+        let window_start = *self.window_start.get_or_insert(now);
+        self.window_bytes += bytes;
+        self.window_records += records;
+
+        let elapsed = now.duration_since(window_start);
+        if elapsed >= RATE_WINDOW {
+            let secs = elapsed.as_secs_f64();
+            self.last = Some((
+                self.window_bytes as f64 / secs,
+                self.window_records as f64 / secs,
+                now,
+            ));
+            self.window_start = Some(now);
+            self.window_bytes = 0;
+            self.window_records = 0;
+        }
+    }
+
+    /// The rates of the last window, unless nothing completed a window since, as when the
+    /// ingest stalls.
+    fn get(&self, now: Instant) -> (f64, f64) {
+        match self.last {
+            Some((bytes, records, at)) if now.duration_since(at) < 2 * RATE_WINDOW => {
+                (bytes, records)
+            }
+            _ => (0.0, 0.0),
+        }
+    }
+}
+
+impl WalIngestStats {
+    pub(crate) fn observe_ingest(&self, bytes: u64, records: u64, times: IngestTimes) {
+        self.observe_ingest_at(bytes, records, times, Instant::now())
+    }
+
+    fn observe_ingest_at(&self, bytes: u64, records: u64, times: IngestTimes, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.bytes_received += bytes;
+        inner.records_ingested += records;
+        inner.times.decode += times.decode;
+        inner.times.apply += times.apply;
+        inner.times.commit += times.commit;
+        inner.rates.observe(bytes, records, now);
+    }
+
+    /// Record a new connection to a safekeeper, and the reason the previous one was replaced.
+    /// Returns whether it is a reconnect, that is, not the first connection.
+    pub(crate) fn observe_connection(&self, reason: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        inner.connections += 1;
+        inner.last_switch = Some((reason.to_owned(), SystemTime::now()));
+        inner.connections > 1
+    }
+
+    pub(crate) fn observe_connection_failure(&self) {
+        self.inner.lock().unwrap().connection_failures += 1;
+    }
+
+    /// `None` until the walreceiver connects.
+    pub(crate) fn info(&self) -> Option<WalIngestInfo> {
+        self.info_at(Instant::now())
+    }
+
+    fn info_at(&self, now: Instant) -> Option<WalIngestInfo> {
+        let inner = self.inner.lock().unwrap();
+        if inner.connections == 0 && inner.bytes_received == 0 {
+            return None;
+        }
+        let (bytes_per_second, records_per_second) = inner.rates.get(now);
+        let (last_switch_reason, last_switch_at) = match &inner.last_switch {
+            Some((reason, at)) => (Some(reason.clone()), Some(*at)),
+            None => (None, None),
+        };
+        Some(WalIngestInfo {
+            bytes_per_second,
+            records_per_second,
+            bytes_received: inner.bytes_received,
+            records_ingested: inner.records_ingested,
+            decode_seconds: inner.times.decode.as_secs_f64(),
+            apply_seconds: inner.times.apply.as_secs_f64(),
+            commit_seconds: inner.times.commit.as_secs_f64(),
+            connections: inner.connections,
+            connection_failures: inner.connection_failures,
+            last_switch_reason,
+            last_switch_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_cover_the_last_window() {
+        let stats = WalIngestStats::default();
+        let t0 = Instant::now();
+        let times = IngestTimes {
+            decode: Duration::from_millis(1),
+            apply: Duration::from_millis(2),
+            commit: Duration::from_millis(3),
+        };
+
+        stats.observe_ingest_at(1000, 10, times, t0);
+        // No window completed yet.
+        assert_eq!(stats.info_at(t0).unwrap().bytes_per_second, 0.0);
+
+        stats.observe_ingest_at(1000, 10, times, t0 + RATE_WINDOW);
+        let info = stats.info_at(t0 + RATE_WINDOW).unwrap();
+        assert_eq!(info.bytes_per_second, 200.0);
+        assert_eq!(info.records_per_second, 2.0);
+        assert_eq!(info.bytes_received, 2000);
+        assert_eq!(info.records_ingested, 20);
+        assert_eq!(info.commit_seconds, 0.006);
+
+        // A stall brings the rates down to zero.
+        let info = stats.info_at(t0 + 3 * RATE_WINDOW).unwrap();
+        assert_eq!(info.bytes_per_second, 0.0);
+        assert_eq!(info.bytes_received, 2000);
+    }
+
+    #[test]
+    fn reconnects_are_counted() {
+        let stats = WalIngestStats::default();
+        assert!(stats.info().is_none());
+        assert!(!stats.observe_connection("NoExistingConnection"));
+        stats.observe_connection_failure();
+        assert!(stats.observe_connection("LaggingWal"));
+
+        let info = stats.info().unwrap();
+        assert_eq!(info.connections, 2);
+        assert_eq!(info.connection_failures, 1);
+        assert_eq!(info.last_switch_reason.as_deref(), Some("LaggingWal"));
+        assert!(info.last_switch_at.is_some());
+    }
+}
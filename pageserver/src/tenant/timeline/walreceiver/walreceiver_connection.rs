@@ -5,7 +5,7 @@ use std::{
     pin::pin,
     str::FromStr,
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{anyhow, Context};
@@ -13,6 +13,7 @@ use bytes::BytesMut;
 use chrono::{NaiveDateTime, Utc};
 use fail::fail_point;
 use futures::StreamExt;
+use pageserver_api::models::ReadOnlyReason;
 use postgres::{error::SqlState, SimpleQueryMessage, SimpleQueryRow};
 use postgres_ffi::WAL_SEGMENT_SIZE;
 use postgres_ffi::{v14::xlog_utils::normalize_lsn, waldecoder::WalDecodeError};
@@ -23,6 +24,7 @@ use tokio_postgres::{replication::ReplicationStream, Client};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, trace, warn, Instrument};
 
+use super::ingest_stats::IngestTimes;
 use super::TaskStateUpdate;
 use crate::{
     context::RequestContext,
@@ -30,13 +32,18 @@ use crate::{
     task_mgr::TaskKind,
     task_mgr::WALRECEIVER_RUNTIME,
     tenant::{debug_assert_current_span_has_tenant_and_timeline_id, Timeline, WalReceiverInfo},
+    wal_capture::WalCaptureWriter,
     walingest::WalIngest,
     walrecord::DecodedWALRecord,
 };
 use postgres_backend::is_expected_io_error;
 use postgres_connection::PgConnectionConfig;
 use postgres_ffi::waldecoder::WalStreamDecoder;
-use utils::{id::NodeId, lsn::Lsn};
+use storage_broker::wal_stream::Status;
+use utils::{
+    id::{ConnectionId, NodeId},
+    lsn::Lsn,
+};
 use utils::{pageserver_feedback::PageserverFeedback, sync::gate::GateError};
 
 /// Status of the connection.
@@ -62,12 +69,16 @@ pub(super) struct WalConnectionStatus {
 pub(super) enum WalReceiverError {
     /// An error of a type that does not indicate an issue, e.g. a connection closing
     ExpectedSafekeeperError(postgres::Error),
+    /// Like `ExpectedSafekeeperError`, for the gRPC WAL stream
+    ExpectedGrpcError(Status),
     /// An "error" message that carries a SUCCESSFUL_COMPLETION status code.  Carries
     /// the message part of the original postgres error
     SuccessfulCompletion(String),
     /// Generic error
     Other(anyhow::Error),
     ClosedGate,
+    /// The tenant rejects the ingest of WAL, see [`crate::tenant::Tenant::set_read_only`]
+    ReadOnly(ReadOnlyReason),
 }
 
 impl From<tokio_postgres::Error> for WalReceiverError {
@@ -188,10 +199,16 @@ pub(super) async fn handle_walreceiver_connection(
                                 // silence, because most likely we've already exited the outer call
                                 // with a similar error.
                             },
+                            WalReceiverError::ExpectedGrpcError(_) => {
+                                // doesn't happen on a postgres connection
+                            }
                             WalReceiverError::SuccessfulCompletion(_) => {}
                             WalReceiverError::ClosedGate => {
                                 // doesn't happen at runtime
                             }
+                            WalReceiverError::ReadOnly(_) => {
+                                // doesn't happen on a postgres connection
+                            }
                             WalReceiverError::Other(err) => {
                                 warn!("Connection aborted: {err:#}")
                             }
@@ -231,28 +248,8 @@ pub(super) async fn handle_walreceiver_connection(
         return Ok(());
     }
 
-    //
-    // Start streaming the WAL, from where we left off previously.
-    //
-    // If we had previously received WAL up to some point in the middle of a WAL record, we
-    // better start from the end of last full WAL record, not in the middle of one.
-    let mut last_rec_lsn = timeline.get_last_record_lsn();
-    let mut startpoint = last_rec_lsn;
-
-    if startpoint == Lsn(0) {
-        return Err(WalReceiverError::Other(anyhow!("No previous WAL position")));
-    }
-
-    // There might be some padding after the last full record, skip it.
-    startpoint += startpoint.calc_padding(8u32);
-
-    // If the starting point is at a WAL page boundary, skip past the page header. We don't need the page headers
-    // for anything, and in some corner cases, the compute node might have never generated the WAL for page headers
-    //. That happens if you create a branch at page boundary: the start point of the branch is at the page boundary,
-    // but when the compute node first starts on the branch, we normalize the first REDO position to just after the page
-    // header (see generate_pg_control()), so the WAL for the page header is never streamed from the compute node
-    //  to the safekeepers.
-    startpoint = normalize_lsn(startpoint, WAL_SEGMENT_SIZE);
+    let last_rec_lsn = timeline.get_last_record_lsn();
+    let startpoint = streaming_start_point(last_rec_lsn)?;
 
     info!("last_record_lsn {last_rec_lsn} starting replication from {startpoint}, safekeeper is at {end_of_wal}...");
 
@@ -261,9 +258,7 @@ pub(super) async fn handle_walreceiver_connection(
     let copy_stream = replication_client.copy_both_simple(&query).await?;
     let mut physical_stream = pin!(ReplicationStream::new(copy_stream));
 
-    let mut waldecoder = WalStreamDecoder::new(startpoint, timeline.pg_version);
-
-    let mut walingest = WalIngest::new(timeline.as_ref(), startpoint, &ctx).await?;
+    let mut ingest = WalStreamIngest::new(&timeline, startpoint, ingest_batch_size, &ctx).await?;
 
     while let Some(replication_message) = {
         select! {
@@ -277,7 +272,7 @@ pub(super) async fn handle_walreceiver_connection(
         let replication_message = replication_message?;
 
         let now = Utc::now().naive_utc();
-        let last_rec_lsn_before_msg = last_rec_lsn;
+        let last_rec_lsn_before_msg = ingest.last_rec_lsn();
 
         // Update the connection status before processing the message. If the message processing
         // fails (e.g. in walingest), we still want to know latests LSNs from the safekeeper.
@@ -314,57 +309,7 @@ pub(super) async fn handle_walreceiver_connection(
                 trace!("received XLogData between {startlsn} and {endlsn}");
 
                 WAL_INGEST.bytes_received.inc_by(data.len() as u64);
-                waldecoder.feed_bytes(data);
-
-                {
-                    let mut decoded = DecodedWALRecord::default();
-                    let mut modification = timeline.begin_modification(startlsn);
-                    let mut uncommitted_records = 0;
-                    let mut filtered_records = 0;
-                    while let Some((lsn, recdata)) = waldecoder.poll_decode()? {
-                        // It is important to deal with the aligned records as lsn in getPage@LSN is
-                        // aligned and can be several bytes bigger. Without this alignment we are
-                        // at risk of hitting a deadlock.
-                        if !lsn.is_aligned() {
-                            return Err(WalReceiverError::Other(anyhow!("LSN not aligned")));
-                        }
-
-                        // Ingest the records without immediately committing them.
-                        let ingested = walingest
-                            .ingest_record(recdata, lsn, &mut modification, &mut decoded, &ctx)
-                            .await
-                            .with_context(|| format!("could not ingest record at {lsn}"))?;
-                        if !ingested {
-                            tracing::debug!("ingest: filtered out record @ LSN {lsn}");
-                            WAL_INGEST.records_filtered.inc();
-                            filtered_records += 1;
-                        }
-
-                        fail_point!("walreceiver-after-ingest");
-
-                        last_rec_lsn = lsn;
-
-                        // Commit every ingest_batch_size records. Even if we filtered out
-                        // all records, we still need to call commit to advance the LSN.
-                        uncommitted_records += 1;
-                        if uncommitted_records >= ingest_batch_size {
-                            WAL_INGEST
-                                .records_committed
-                                .inc_by(uncommitted_records - filtered_records);
-                            modification.commit(&ctx).await?;
-                            uncommitted_records = 0;
-                            filtered_records = 0;
-                        }
-                    }
-
-                    // Commit the remaining records.
-                    if uncommitted_records > 0 {
-                        WAL_INGEST
-                            .records_committed
-                            .inc_by(uncommitted_records - filtered_records);
-                        modification.commit(&ctx).await?;
-                    }
-                }
+                ingest.ingest(&timeline, startlsn, data, &ctx).await?;
 
                 if !caught_up && endlsn >= end_of_wal {
                     info!("caught up at LSN {endlsn}");
@@ -382,7 +327,7 @@ pub(super) async fn handle_walreceiver_connection(
                 trace!("received PrimaryKeepAlive(wal_end: {wal_end}, timestamp: {timestamp:?} reply: {reply_requested})");
 
                 if reply_requested {
-                    Some(last_rec_lsn)
+                    Some(ingest.last_rec_lsn())
                 } else {
                     None
                 }
@@ -391,7 +336,7 @@ pub(super) async fn handle_walreceiver_connection(
             _ => None,
         };
 
-        if !connection_status.has_processed_wal && last_rec_lsn > last_rec_lsn_before_msg {
+        if !connection_status.has_processed_wal && ingest.last_rec_lsn() > last_rec_lsn_before_msg {
             // We have successfully processed at least one WAL record.
             connection_status.has_processed_wal = true;
             if let Err(e) = events_sender.send(TaskStateUpdate::Progress(connection_status)) {
@@ -401,55 +346,7 @@ pub(super) async fn handle_walreceiver_connection(
         }
 
         if let Some(last_lsn) = status_update {
-            let timeline_remote_consistent_lsn = timeline
-                .get_remote_consistent_lsn_visible()
-                .unwrap_or(Lsn(0));
-
-            // The last LSN we processed. It is not guaranteed to survive pageserver crash.
-            let last_received_lsn = last_lsn;
-            // `disk_consistent_lsn` is the LSN at which page server guarantees local persistence of all received data
-            let disk_consistent_lsn = timeline.get_disk_consistent_lsn();
-            // The last LSN that is synced to remote storage and is guaranteed to survive pageserver crash
-            // Used by safekeepers to remove WAL preceding `remote_consistent_lsn`.
-            let remote_consistent_lsn = timeline_remote_consistent_lsn;
-            let ts = SystemTime::now();
-
-            // Update the status about what we just received. This is shown in the mgmt API.
-            let last_received_wal = WalReceiverInfo {
-                wal_source_connconf: wal_source_connconf.clone(),
-                last_received_msg_lsn: last_lsn,
-                last_received_msg_ts: ts
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .expect("Received message time should be before UNIX EPOCH!")
-                    .as_micros(),
-            };
-            *timeline.last_received_wal.lock().unwrap() = Some(last_received_wal);
-
-            // Send the replication feedback message.
-            // Regular standby_status_update fields are put into this message.
-            let current_timeline_size = if timeline.tenant_shard_id.is_shard_zero() {
-                timeline
-                    .get_current_logical_size(
-                        crate::tenant::timeline::GetLogicalSizePriority::User,
-                        &ctx,
-                    )
-                    // FIXME: https://github.com/neondatabase/neon/issues/5963
-                    .size_dont_care_about_accuracy()
-            } else {
-                // Non-zero shards send zero for logical size.  The safekeeper will ignore
-                // this number.  This is because in a sharded tenant, only shard zero maintains
-                // accurate logical size.
-                0
-            };
-
-            let status_update = PageserverFeedback {
-                current_timeline_size,
-                last_received_lsn,
-                disk_consistent_lsn,
-                remote_consistent_lsn,
-                replytime: ts,
-                shard_number: timeline.tenant_shard_id.shard_number.0 as u32,
-            };
+            let status_update = make_feedback(&timeline, &wal_source_connconf, last_lsn, &ctx);
 
             debug!("neon_status_update {status_update:?}");
 
@@ -465,6 +362,236 @@ pub(super) async fn handle_walreceiver_connection(
     Ok(())
 }
 
+/// The position to start streaming WAL from, for a timeline at `last_rec_lsn`.
+pub(super) fn streaming_start_point(last_rec_lsn: Lsn) -> Result<Lsn, WalReceiverError> {
+    // If we had previously received WAL up to some point in the middle of a WAL record, we
+    // better start from the end of last full WAL record, not in the middle of one.
+    let mut startpoint = last_rec_lsn;
+
+    if startpoint == Lsn(0) {
+        return Err(WalReceiverError::Other(anyhow!("No previous WAL position")));
+    }
+
+    // There might be some padding after the last full record, skip it.
+    startpoint += startpoint.calc_padding(8u32);
+
+    // If the starting point is at a WAL page boundary, skip past the page header. We don't need the page headers
+    // for anything, and in some corner cases, the compute node might have never generated the WAL for page headers
+    //. That happens if you create a branch at page boundary: the start point of the branch is at the page boundary,
+    // but when the compute node first starts on the branch, we normalize the first REDO position to just after the page
+    // header (see generate_pg_control()), so the WAL for the page header is never streamed from the compute node
+    //  to the safekeepers.
+    Ok(normalize_lsn(startpoint, WAL_SEGMENT_SIZE))
+}
+
+/// Decodes the WAL streamed from a safekeeper, and ingests its records into the timeline.
+pub(super) struct WalStreamIngest {
+    waldecoder: WalStreamDecoder,
+    walingest: WalIngest,
+    capture: Option<WalCaptureWriter>,
+    /// End of the last ingested record.
+    last_rec_lsn: Lsn,
+    ingest_batch_size: u64,
+}
+
+impl WalStreamIngest {
+    pub(super) async fn new(
+        timeline: &Timeline,
+        startpoint: Lsn,
+        ingest_batch_size: u64,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Self> {
+        let waldecoder = WalStreamDecoder::new(startpoint, timeline.pg_version);
+
+        let walingest = WalIngest::new(timeline, startpoint, ctx).await?;
+
+        let capture = timeline.get_capture_wal().then(|| {
+            let path = timeline.conf.wal_capture_path(
+                &timeline.tenant_shard_id,
+                &timeline.timeline_id,
+                &ConnectionId::generate(),
+            );
+            WalCaptureWriter::create(path, timeline.pg_version, startpoint)
+        });
+
+        Ok(WalStreamIngest {
+            waldecoder,
+            walingest,
+            capture,
+            last_rec_lsn: timeline.get_last_record_lsn(),
+            ingest_batch_size,
+        })
+    }
+
+    pub(super) fn last_rec_lsn(&self) -> Lsn {
+        self.last_rec_lsn
+    }
+
+    /// Pass the WAL `data` starting at `startlsn` to the decoder, and ingest the records
+    /// that it can decode as a result.
+    pub(super) async fn ingest(
+        &mut self,
+        timeline: &Timeline,
+        startlsn: Lsn,
+        data: &[u8],
+        ctx: &RequestContext,
+    ) -> Result<(), WalReceiverError> {
+        if let Some(reason) = timeline.get_read_only() {
+            return Err(WalReceiverError::ReadOnly(reason));
+        }
+        timeline
+            .tagged_metrics
+            .load()
+            .inc_wal_bytes_received(data.len() as u64);
+        self.waldecoder.feed_bytes(data);
+
+        let mut decoded = DecodedWALRecord::default();
+        let mut modification = timeline.begin_modification(startlsn);
+        let mut uncommitted_records = 0;
+        let mut filtered_records = 0;
+        let mut records = 0;
+        let mut times = IngestTimes::default();
+        loop {
+            let started_at = Instant::now();
+            let next = self.waldecoder.poll_decode()?;
+            times.decode += started_at.elapsed();
+            let Some((lsn, recdata)) = next else {
+                break;
+            };
+            records += 1;
+
+            // It is important to deal with the aligned records as lsn in getPage@LSN is
+            // aligned and can be several bytes bigger. Without this alignment we are
+            // at risk of hitting a deadlock.
+            if !lsn.is_aligned() {
+                return Err(WalReceiverError::Other(anyhow!("LSN not aligned")));
+            }
+
+            if let Some(capture) = &mut self.capture {
+                capture.record(lsn, &recdata);
+            }
+
+            // Ingest the records without immediately committing them.
+            let started_at = Instant::now();
+            let ingested = self
+                .walingest
+                .ingest_record(recdata, lsn, &mut modification, &mut decoded, ctx)
+                .await
+                .with_context(|| format!("could not ingest record at {lsn}"))?;
+            times.apply += started_at.elapsed();
+            if !ingested {
+                tracing::debug!("ingest: filtered out record @ LSN {lsn}");
+                WAL_INGEST.records_filtered.inc();
+                filtered_records += 1;
+            }
+
+            fail_point!("walreceiver-after-ingest");
+
+            self.last_rec_lsn = lsn;
+
+            // Commit every ingest_batch_size records. Even if we filtered out
+            // all records, we still need to call commit to advance the LSN.
+            uncommitted_records += 1;
+            if uncommitted_records >= self.ingest_batch_size {
+                WAL_INGEST
+                    .records_committed
+                    .inc_by(uncommitted_records - filtered_records);
+                let started_at = Instant::now();
+                modification.commit(ctx).await?;
+                times.commit += started_at.elapsed();
+                if let Some(capture) = &mut self.capture {
+                    capture.commit();
+                }
+                uncommitted_records = 0;
+                filtered_records = 0;
+            }
+        }
+
+        // Commit the remaining records.
+        if uncommitted_records > 0 {
+            WAL_INGEST
+                .records_committed
+                .inc_by(uncommitted_records - filtered_records);
+            let started_at = Instant::now();
+            modification.commit(ctx).await?;
+            times.commit += started_at.elapsed();
+            if let Some(capture) = &mut self.capture {
+                capture.commit();
+            }
+        }
+
+        let metrics = &timeline.metrics;
+        metrics
+            .wal_ingest_decode_seconds
+            .inc_by(times.decode.as_secs_f64());
+        metrics
+            .wal_ingest_apply_seconds
+            .inc_by(times.apply.as_secs_f64());
+        metrics
+            .wal_ingest_commit_seconds
+            .inc_by(times.commit.as_secs_f64());
+        timeline
+            .wal_ingest_stats
+            .observe_ingest(data.len() as u64, records, times);
+        Ok(())
+    }
+}
+
+/// The feedback to send to the safekeeper after receiving WAL up to `last_lsn`. Also
+/// updates the status about what we just received, shown in the mgmt API.
+pub(super) fn make_feedback(
+    timeline: &Timeline,
+    wal_source_connconf: &PgConnectionConfig,
+    last_lsn: Lsn,
+    ctx: &RequestContext,
+) -> PageserverFeedback {
+    let timeline_remote_consistent_lsn = timeline
+        .get_remote_consistent_lsn_visible()
+        .unwrap_or(Lsn(0));
+
+    // The last LSN we processed. It is not guaranteed to survive pageserver crash.
+    let last_received_lsn = last_lsn;
+    // `disk_consistent_lsn` is the LSN at which page server guarantees local persistence of all received data
+    let disk_consistent_lsn = timeline.get_disk_consistent_lsn();
+    // The last LSN that is synced to remote storage and is guaranteed to survive pageserver crash
+    // Used by safekeepers to remove WAL preceding `remote_consistent_lsn`.
+    let remote_consistent_lsn = timeline_remote_consistent_lsn;
+    let ts = SystemTime::now();
+
+    // Update the status about what we just received. This is shown in the mgmt API.
+    let last_received_wal = WalReceiverInfo {
+        wal_source_connconf: wal_source_connconf.clone(),
+        last_received_msg_lsn: last_lsn,
+        last_received_msg_ts: ts
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("Received message time should be before UNIX EPOCH!")
+            .as_micros(),
+    };
+    *timeline.last_received_wal.lock().unwrap() = Some(last_received_wal);
+
+    // Regular standby_status_update fields are put into this message.
+    let current_timeline_size = if timeline.tenant_shard_id.is_shard_zero() {
+        timeline
+            .get_current_logical_size(crate::tenant::timeline::GetLogicalSizePriority::User, ctx)
+            // FIXME: https://github.com/neondatabase/neon/issues/5963
+            .size_dont_care_about_accuracy()
+    } else {
+        // Non-zero shards send zero for logical size.  The safekeeper will ignore
+        // this number.  This is because in a sharded tenant, only shard zero maintains
+        // accurate logical size.
+        0
+    };
+
+    PageserverFeedback {
+        current_timeline_size,
+        last_received_lsn,
+        disk_consistent_lsn,
+        remote_consistent_lsn,
+        replytime: ts,
+        shard_number: timeline.tenant_shard_id.shard_number.0 as u32,
+    }
+}
+
 /// Data returned from the postgres `IDENTIFY_SYSTEM` command
 ///
 /// See the [postgres docs] for more details.
@@ -1,13 +1,20 @@
+use super::storage_layer::AsLayerDesc;
 use super::storage_layer::LayerName;
 use super::storage_layer::ResidentLayer;
 use crate::tenant::metadata::TimelineMetadata;
+use crate::tenant::remote_timeline_client::index::GcPinnedCutoff;
 use crate::tenant::remote_timeline_client::index::IndexPart;
 use crate::tenant::remote_timeline_client::index::LayerFileMetadata;
 use crate::tenant::remote_timeline_client::index::Lineage;
-use std::collections::{HashMap, VecDeque};
+use crate::tenant::remote_timeline_client::index::LogicalSizeCheckpoint;
+use crate::tenant::remote_timeline_client::snapshot::SnapshotId;
+use crate::tenant::timeline::lsn_time_index::LsnTimeSample;
+use pageserver_api::models::{InProgressUploadOp, LegalHold, QueuedUploadOp, UploadQueueInfo};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
 
 use chrono::NaiveDateTime;
+use itertools::Itertools;
 use std::sync::Arc;
 use tracing::info;
 use utils::lsn::AtomicLsn;
@@ -52,6 +59,10 @@ pub(crate) struct UploadQueueInitialized {
     /// last (scheduling of) metadata index upload?
     pub(crate) latest_files_changes_since_metadata_upload_scheduled: u64,
 
+    /// Layers removed from `latest_files` since the last index upload was scheduled. The last
+    /// scheduled index still references them, so they may not be deleted yet.
+    pub(crate) unlinked_since_metadata_upload_scheduled: HashSet<LayerName>,
+
     /// Metadata stored in the remote storage, taking into account all
     /// in-progress and queued operations.
     /// DANGER: do not return to outside world, e.g., safekeepers.
@@ -60,6 +71,27 @@ pub(crate) struct UploadQueueInitialized {
     /// Part of the flattened "next" `index_part.json`.
     pub(crate) latest_lineage: Lineage,
 
+    /// Part of the flattened "next" `index_part.json`.
+    pub(crate) latest_logical_size: Option<LogicalSizeCheckpoint>,
+
+    /// Part of the flattened "next" `index_part.json`: the layers in `latest_files` that are
+    /// in the hot tier, and since when.
+    pub(crate) latest_hot_layers: HashMap<LayerName, NaiveDateTime>,
+
+    /// Part of the flattened "next" `index_part.json`: the snapshots that the timeline holds a
+    /// reference to, and the layers in `latest_files` that are downloaded from each of them.
+    pub(crate) latest_snapshots: BTreeMap<SnapshotId, HashSet<LayerName>>,
+
+    /// Part of the flattened "next" `index_part.json`: the legal hold of the tenant.
+    pub(crate) latest_legal_hold: Option<LegalHold>,
+
+    /// Part of the flattened "next" `index_part.json`: the GC cutoffs of the pinned relations.
+    pub(crate) latest_gc_pinned_cutoffs: Vec<GcPinnedCutoff>,
+
+    /// Part of the flattened "next" `index_part.json`: the sampled commit timestamps up to the
+    /// `disk_consistent_lsn` of `latest_metadata`.
+    pub(crate) latest_lsn_time_samples: Vec<LsnTimeSample>,
+
     /// `disk_consistent_lsn` from the last metadata file that was successfully
     /// uploaded. `Lsn(0)` if nothing was uploaded yet.
     /// Unlike `latest_files` or `latest_metadata`, this value is never ahead.
@@ -84,8 +116,12 @@ pub(crate) struct UploadQueueInitialized {
 
     /// Queued operations that have not been launched yet. They might depend on previous
     /// tasks to finish. For example, metadata upload cannot be performed before all
-    /// preceding layer file uploads have completed.
-    pub(crate) queued_operations: VecDeque<UploadOp>,
+    /// preceding layer file uploads have completed. See [`UploadOp::depends_on`]:
+    /// operations which don't depend on each other are launched out of order.
+    ///
+    /// Operations get their task ID when scheduled, so that the order in which the queued
+    /// and in-progress operations were scheduled is known.
+    pub(crate) queued_operations: VecDeque<(u64, UploadOp)>,
 
     /// Files which have been unlinked but not yet had scheduled a deletion for. Only kept around
     /// for error logging.
@@ -109,6 +145,14 @@ impl UploadQueueInitialized {
         self.inprogress_tasks.is_empty() && self.queued_operations.is_empty()
     }
 
+    /// The snapshot that a layer of `latest_files` is downloaded from, if any.
+    pub(crate) fn snapshot_of(&self, layer_name: &LayerName) -> Option<&SnapshotId> {
+        self.latest_snapshots
+            .iter()
+            .find(|(_, layers)| layers.contains(layer_name))
+            .map(|(snapshot_id, _)| snapshot_id)
+    }
+
     pub(super) fn get_last_remote_consistent_lsn_visible(&self) -> Lsn {
         self.visible_remote_consistent_lsn.load()
     }
@@ -116,6 +160,184 @@ impl UploadQueueInitialized {
     pub(super) fn get_last_remote_consistent_lsn_projected(&self) -> Option<Lsn> {
         self.projected_remote_consistent_lsn
     }
+
+    /// Add an operation to the end of the queue. The caller is responsible for launching it.
+    pub(crate) fn push_op(&mut self, op: UploadOp) {
+        if let UploadOp::UploadMetadata(..) = op {
+            self.unlinked_since_metadata_upload_scheduled.clear();
+        }
+        self.task_counter += 1;
+        self.queued_operations.push_back((self.task_counter, op));
+    }
+
+    /// Remove the index upload at the end of the queue, if there is one. It hasn't started yet,
+    /// and the index upload that the caller schedules next supersedes it: the index updates made
+    /// while an index upload, or the layer uploads it waits for, are in progress are coalesced
+    /// into a single upload, e.g. those of a timeline being created.
+    ///
+    /// The superseding upload is pushed at the end of the queue, so that it waits for all the
+    /// layer uploads scheduled before it.
+    pub(crate) fn pop_queued_index_upload(&mut self) -> bool {
+        if let Some((_, UploadOp::UploadMetadata(..))) = self.queued_operations.back() {
+            self.queued_operations.pop_back();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Check the ordering policy for the deletion of a layer, before scheduling it: the layer
+    /// must no longer be referenced by `latest_files`, and the index upload that stopped
+    /// referencing it must be scheduled before the deletion.
+    ///
+    /// [`UploadOp::depends_on`] then makes the deletion wait for that index upload, which waits
+    /// for the uploads of the layers it references. For compaction, this means that the inputs
+    /// are only deleted once the outputs are uploaded and in the remote index, so that a crash
+    /// at any point leaves a remote index whose layers all exist.
+    pub(crate) fn check_deletion_ordering(
+        &self,
+        name: &LayerName,
+        metadata: &LayerFileMetadata,
+    ) -> Result<(), DeletionOrderingViolation> {
+        // A layer re-created with the same name in another generation has another remote path.
+        if self.latest_files.get(name).is_some_and(|latest| {
+            latest.generation == metadata.generation && latest.shard == metadata.shard
+        }) {
+            return Err(DeletionOrderingViolation::StillReferenced);
+        }
+        if self.unlinked_since_metadata_upload_scheduled.contains(name) {
+            return Err(DeletionOrderingViolation::IndexUploadNotScheduled);
+        }
+        Ok(())
+    }
+
+    /// The operations in progress and queued, in the order they were scheduled.
+    fn scheduled_ops(&self) -> impl Iterator<Item = (u64, &UploadOp, bool)> {
+        let mut inprogress = self
+            .inprogress_tasks
+            .values()
+            .map(|task| (task.task_id, &task.op, false))
+            .collect::<Vec<_>>();
+        inprogress.sort_by_key(|(task_id, _, _)| *task_id);
+        let queued = self
+            .queued_operations
+            .iter()
+            .map(|(task_id, op)| (*task_id, op, true));
+        inprogress
+            .into_iter()
+            .merge_by(queued, |(a, _, _), (b, _, _)| a < b)
+    }
+
+    /// Positions in `queued_operations` of the operations which can be launched now, because
+    /// they don't depend on any operation in progress or queued which was scheduled before
+    /// them.
+    pub(super) fn ready_operations(&self) -> Vec<usize> {
+        let mut pending = PendingOps::default();
+        let mut ready = Vec::new();
+        let mut position = 0;
+        for (_, op, queued) in self.scheduled_ops() {
+            if pending.barrier {
+                // everything after a barrier depends on it
+                break;
+            }
+            if queued {
+                if !pending.blocks(op) {
+                    ready.push(position);
+                }
+                position += 1;
+            }
+            pending.add(op);
+        }
+        ready
+    }
+
+    /// Describe the queue and the dependencies between its operations.
+    pub(crate) fn info(&self) -> UploadQueueInfo {
+        let scheduled = self.scheduled_ops().collect::<Vec<_>>();
+        let mut info = UploadQueueInfo {
+            in_progress: Vec::new(),
+            queued: Vec::new(),
+        };
+        for (i, (task_id, op, queued)) in scheduled.iter().enumerate() {
+            if *queued {
+                info.queued.push(QueuedUploadOp {
+                    task_id: *task_id,
+                    op: op.to_string(),
+                    waits_for: scheduled[..i]
+                        .iter()
+                        .filter(|(_, earlier, _)| op.depends_on(earlier))
+                        .map(|(task_id, _, _)| *task_id)
+                        .collect(),
+                });
+            } else {
+                info.in_progress.push(InProgressUploadOp {
+                    task_id: *task_id,
+                    op: op.to_string(),
+                    retries: self.inprogress_tasks[task_id]
+                        .retries
+                        .load(std::sync::atomic::Ordering::Relaxed),
+                });
+            }
+        }
+        info
+    }
+}
+
+/// Summary of the operations in progress or queued before an operation, to tell whether it
+/// can be launched without comparing it with each of them. Checking [`PendingOps::blocks`]
+/// is equivalent to checking [`UploadOp::depends_on`] against each operation added.
+#[derive(Default)]
+struct PendingOps {
+    layer_uploads: HashSet<LayerName>,
+    layer_deletions: HashSet<LayerName>,
+    index_uploads: bool,
+    barrier: bool,
+    any: bool,
+}
+
+impl PendingOps {
+    fn add(&mut self, op: &UploadOp) {
+        self.any = true;
+        match op {
+            UploadOp::UploadLayer(layer, _) => {
+                self.layer_uploads.insert(layer.layer_desc().layer_name());
+            }
+            UploadOp::UploadMetadata(..) => self.index_uploads = true,
+            UploadOp::Delete(delete) => self
+                .layer_deletions
+                .extend(delete.layers.iter().map(|(name, _)| name.clone())),
+            UploadOp::Barrier(_) | UploadOp::Shutdown => self.barrier = true,
+        }
+    }
+
+    fn blocks(&self, op: &UploadOp) -> bool {
+        if self.barrier {
+            return true;
+        }
+        match op {
+            UploadOp::UploadLayer(layer, _) => {
+                let name = layer.layer_desc().layer_name();
+                self.layer_uploads.contains(&name) || self.layer_deletions.contains(&name)
+            }
+            UploadOp::UploadMetadata(..) => self.index_uploads || !self.layer_uploads.is_empty(),
+            UploadOp::Delete(delete) => {
+                self.index_uploads
+                    || delete
+                        .layers
+                        .iter()
+                        .any(|(name, _)| self.layer_uploads.contains(name))
+            }
+            UploadOp::Barrier(_) | UploadOp::Shutdown => self.any,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub(crate) enum DeletionOrderingViolation {
+    #[error("layer is still referenced by the latest index")]
+    StillReferenced,
+    #[error("the index upload that unlinks the layer is not scheduled yet")]
+    IndexUploadNotScheduled,
 }
 
 #[derive(Clone, Copy)]
@@ -174,8 +396,15 @@ impl UploadQueue {
             // As described in the doc comment, it's ok for `latest_files` and `latest_metadata` to be ahead.
             latest_files: HashMap::new(),
             latest_files_changes_since_metadata_upload_scheduled: 0,
+            unlinked_since_metadata_upload_scheduled: HashSet::new(),
             latest_metadata: metadata.clone(),
             latest_lineage: Lineage::default(),
+            latest_logical_size: None,
+            latest_hot_layers: HashMap::new(),
+            latest_snapshots: BTreeMap::new(),
+            latest_legal_hold: None,
+            latest_gc_pinned_cutoffs: Vec::new(),
+            latest_lsn_time_samples: Vec::new(),
             projected_remote_consistent_lsn: None,
             visible_remote_consistent_lsn: Arc::new(AtomicLsn::new(0)),
             // what follows are boring default initializations
@@ -222,8 +451,15 @@ impl UploadQueue {
         let state = UploadQueueInitialized {
             latest_files: files,
             latest_files_changes_since_metadata_upload_scheduled: 0,
+            unlinked_since_metadata_upload_scheduled: HashSet::new(),
             latest_metadata: index_part.metadata.clone(),
             latest_lineage: index_part.lineage.clone(),
+            latest_logical_size: index_part.logical_size,
+            latest_hot_layers: index_part.hot_layers.clone(),
+            latest_snapshots: index_part.snapshots.clone(),
+            latest_legal_hold: index_part.legal_hold.clone(),
+            latest_gc_pinned_cutoffs: index_part.gc_pinned_cutoffs.clone(),
+            latest_lsn_time_samples: index_part.lsn_time_samples.clone(),
             projected_remote_consistent_lsn: Some(index_part.metadata.disk_consistent_lsn()),
             visible_remote_consistent_lsn: Arc::new(
                 index_part.metadata.disk_consistent_lsn().into(),
@@ -276,7 +512,8 @@ impl UploadQueue {
 /// An in-progress upload or delete task.
 #[derive(Debug)]
 pub(crate) struct UploadTask {
-    /// Unique ID of this task. Used as the key in `inprogress_tasks` above.
+    /// Unique ID of this task. Used as the key in `inprogress_tasks` above. IDs increase in
+    /// the order the operations were scheduled.
     pub(crate) task_id: u64,
     pub(crate) retries: AtomicU32,
 
@@ -309,6 +546,36 @@ pub(crate) enum UploadOp {
     Shutdown,
 }
 
+impl UploadOp {
+    /// Whether this operation must wait for `earlier`, which was scheduled before it, to
+    /// complete. The dependencies are:
+    /// - an index upload waits for the layer uploads before it, which it may reference, and
+    ///   for the index uploads before it, to upload the indices in order;
+    /// - a deletion waits for the index uploads before it, which stop referencing the layers,
+    ///   and for the uploads of the same layers;
+    /// - a layer upload waits only for the uploads and deletions of the same layer. It may
+    ///   complete before the index uploads scheduled before it: they don't reference it.
+    /// - a barrier waits for everything before it, and everything after it waits for it.
+    pub(crate) fn depends_on(&self, earlier: &UploadOp) -> bool {
+        use UploadOp::*;
+        match (self, earlier) {
+            (Barrier(_) | Shutdown, _) | (_, Barrier(_) | Shutdown) => true,
+            (UploadLayer(layer, _), UploadLayer(earlier, _)) => {
+                layer.layer_desc().layer_name() == earlier.layer_desc().layer_name()
+            }
+            (UploadLayer(layer, _), Delete(delete)) | (Delete(delete), UploadLayer(layer, _)) => {
+                let name = layer.layer_desc().layer_name();
+                delete.layers.iter().any(|(deleted, _)| *deleted == name)
+            }
+            (UploadLayer(..), UploadMetadata(..)) => false,
+            (UploadMetadata(..), UploadLayer(..) | UploadMetadata(..)) => true,
+            (UploadMetadata(..), Delete(_)) => false,
+            (Delete(_), UploadMetadata(..)) => true,
+            (Delete(_), Delete(_)) => false,
+        }
+    }
+}
+
 impl std::fmt::Display for UploadOp {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -0,0 +1,129 @@
+//! Notifications to computes about lifecycle events of timelines.
+//!
+//! Without them, computes learn that their timeline was deleted, or that GC removed the page
+//! versions at the LSN of a read replica, only from failing requests. The pageserver publishes
+//! these events to the storage broker as `TimelineEvent` messages, and computes can subscribe
+//! to them per timeline with SubscribeByFilter.
+//!
+//! Events are best-effort: they are queued in memory, and dropped if the queue is full or the
+//! broker is unavailable. Computes should still handle the errors.
+
+use once_cell::sync::OnceCell;
+use pageserver_api::shard::TenantShardId;
+use storage_broker::proto::{
+    MessageType, TenantTimelineId as ProtoTenantTimelineId, TimelineEvent, TimelineEventKind,
+    TypedMessage,
+};
+use storage_broker::BrokerClientChannel;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use utils::id::TimelineId;
+use utils::lsn::Lsn;
+
+use crate::config::PageServerConf;
+use crate::metrics::TIMELINE_EVENTS;
+use crate::task_mgr::{self, TaskKind, BACKGROUND_RUNTIME};
+
+const QUEUE_SIZE: usize = 1024;
+
+static QUEUE: OnceCell<mpsc::Sender<TimelineEvent>> = OnceCell::new();
+
+pub(crate) fn notify_gc_cutoff_advanced(
+    tenant_shard_id: TenantShardId,
+    timeline_id: TimelineId,
+    gc_cutoff: Lsn,
+) {
+    notify(
+        tenant_shard_id,
+        timeline_id,
+        TimelineEventKind::GcCutoffAdvanced,
+        gc_cutoff,
+    );
+}
+
+pub(crate) fn notify_deleted(tenant_shard_id: TenantShardId, timeline_id: TimelineId) {
+    notify(
+        tenant_shard_id,
+        timeline_id,
+        TimelineEventKind::TimelineDeleted,
+        Lsn::INVALID,
+    );
+}
+
+fn notify(
+    tenant_shard_id: TenantShardId,
+    timeline_id: TimelineId,
+    kind: TimelineEventKind,
+    lsn: Lsn,
+) {
+    // Not launched in unit tests.
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+
+    let event = TimelineEvent {
+        tenant_timeline_id: Some(ProtoTenantTimelineId {
+            tenant_id: tenant_shard_id.tenant_id.as_ref().to_owned(),
+            timeline_id: timeline_id.as_ref().to_owned(),
+        }),
+        pageserver_id: 0, // filled in by the publisher
+        shard_number: tenant_shard_id.shard_number.0 as u32,
+        shard_count: tenant_shard_id.shard_count.literal() as u32,
+        kind: kind as i32,
+        lsn: lsn.0,
+    };
+    if queue.try_send(event).is_err() {
+        TIMELINE_EVENTS.with_label_values(&["dropped"]).inc();
+    }
+}
+
+/// Start publishing the events to the broker.
+pub fn launch_publisher(conf: &'static PageServerConf, broker_client: BrokerClientChannel) {
+    let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+    if QUEUE.set(tx).is_err() {
+        panic!("timeline events publisher launched twice");
+    }
+
+    task_mgr::spawn(
+        BACKGROUND_RUNTIME.handle(),
+        TaskKind::TimelineEventPublisher,
+        None,
+        None,
+        "timeline events publisher",
+        false,
+        publisher_loop(conf, broker_client, rx),
+    );
+}
+
+async fn publisher_loop(
+    conf: &'static PageServerConf,
+    mut broker_client: BrokerClientChannel,
+    mut rx: mpsc::Receiver<TimelineEvent>,
+) -> anyhow::Result<()> {
+    let cancel = task_mgr::shutdown_token();
+    info!("publishing timeline events to the broker");
+
+    loop {
+        let mut event = tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            event = rx.recv() => match event {
+                Some(event) => event,
+                None => return Ok(()),
+            },
+        };
+        event.pageserver_id = conf.id.0;
+
+        let msg = TypedMessage {
+            r#type: MessageType::TimelineEvent as i32,
+            timeline_event: Some(event),
+            ..Default::default()
+        };
+        match broker_client.publish_one(msg).await {
+            Ok(_) => TIMELINE_EVENTS.with_label_values(&["published"]).inc(),
+            Err(status) => {
+                TIMELINE_EVENTS.with_label_values(&["dropped"]).inc();
+                warn!("failed to publish timeline event to the broker: {status}");
+            }
+        }
+    }
+}
@@ -4,33 +4,46 @@
 //! truth.
 
 use anyhow::Context;
-use std::path::Path;
+use camino::Utf8PathBuf;
+use std::collections::HashSet;
 
 use pageserver_api::models::PageserverUtilization;
 
-pub(crate) fn regenerate(tenants_path: &Path) -> anyhow::Result<PageserverUtilization> {
+/// Sums up the usage of the filesystems of `data_dirs`, counting each filesystem once.
+pub(crate) fn regenerate(data_dirs: &[Utf8PathBuf]) -> anyhow::Result<PageserverUtilization> {
     // TODO: currently the http api ratelimits this to 1Hz at most, which is probably good enough
 
-    let statvfs = nix::sys::statvfs::statvfs(tenants_path)
-        .map_err(std::io::Error::from)
-        .context("statvfs tenants directory")?;
-
-    // https://unix.stackexchange.com/a/703650
-    let blocksz = if statvfs.fragment_size() > 0 {
-        statvfs.fragment_size()
-    } else {
-        statvfs.block_size()
-    };
-
-    #[cfg_attr(not(target_os = "macos"), allow(clippy::unnecessary_cast))]
-    let free = statvfs.blocks_available() as u64 * blocksz;
-
-    #[cfg_attr(not(target_os = "macos"), allow(clippy::unnecessary_cast))]
-    let used = statvfs
-        .blocks()
-        // use blocks_free instead of available here to match df in case someone compares
-        .saturating_sub(statvfs.blocks_free()) as u64
-        * blocksz;
+    let mut free = 0;
+    let mut used = 0;
+    let mut filesystems = HashSet::new();
+    for data_dir in data_dirs {
+        let statvfs = nix::sys::statvfs::statvfs(data_dir.as_std_path())
+            .map_err(std::io::Error::from)
+            .with_context(|| format!("statvfs {data_dir}"))?;
+        if !filesystems.insert(statvfs.filesystem_id()) {
+            continue;
+        }
+
+        // https://unix.stackexchange.com/a/703650
+        let blocksz = if statvfs.fragment_size() > 0 {
+            statvfs.fragment_size()
+        } else {
+            statvfs.block_size()
+        };
+
+        #[cfg_attr(not(target_os = "macos"), allow(clippy::unnecessary_cast))]
+        let dir_free = statvfs.blocks_available() as u64 * blocksz;
+
+        #[cfg_attr(not(target_os = "macos"), allow(clippy::unnecessary_cast))]
+        let dir_used = statvfs
+            .blocks()
+            // use blocks_free instead of available here to match df in case someone compares
+            .saturating_sub(statvfs.blocks_free()) as u64
+            * blocksz;
+
+        free += dir_free;
+        used += dir_used;
+    }
 
     let captured_at = std::time::SystemTime::now();
 
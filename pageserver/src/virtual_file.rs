@@ -12,6 +12,7 @@
 //!
 use crate::context::RequestContext;
 use crate::metrics::{StorageIoOperation, STORAGE_IO_SIZE, STORAGE_IO_TIME_METRIC};
+use crate::task_mgr::resource_usage;
 
 use crate::page_cache::PageWriteGuard;
 use crate::tenant::TENANTS_SEGMENT_NAME;
@@ -435,6 +436,28 @@ impl VirtualFile {
         final_path: Utf8PathBuf,
         tmp_path: Utf8PathBuf,
         content: B,
+    ) -> std::io::Result<()> {
+        Self::crashsafe_overwrite_impl(final_path, tmp_path, content, true).await
+    }
+
+    /// Like [`Self::crashsafe_overwrite`], but leaves the fsync of the parent directory, which
+    /// makes the rename durable, to the caller.
+    pub async fn crashsafe_overwrite_no_parent_fsync<
+        B: BoundedBuf<Buf = Buf> + Send,
+        Buf: IoBuf + Send,
+    >(
+        final_path: Utf8PathBuf,
+        tmp_path: Utf8PathBuf,
+        content: B,
+    ) -> std::io::Result<()> {
+        Self::crashsafe_overwrite_impl(final_path, tmp_path, content, false).await
+    }
+
+    async fn crashsafe_overwrite_impl<B: BoundedBuf<Buf = Buf> + Send, Buf: IoBuf + Send>(
+        final_path: Utf8PathBuf,
+        tmp_path: Utf8PathBuf,
+        content: B,
+        fsync_parent: bool,
     ) -> std::io::Result<()> {
         // TODO: use tokio_epoll_uring if configured as `io_engine`.
         // See https://github.com/neondatabase/neon/issues/6663
@@ -448,7 +471,11 @@ impl VirtualFile {
             } else {
                 &[]
             };
-            utils::crashsafe::overwrite(&final_path, &tmp_path, content)
+            if fsync_parent {
+                utils::crashsafe::overwrite(&final_path, &tmp_path, content)
+            } else {
+                utils::crashsafe::overwrite_no_parent_fsync(&final_path, &tmp_path, content)
+            }
         })
         .await
         .expect("blocking task is never aborted")
@@ -719,6 +746,7 @@ impl VirtualFile {
                         &self.timeline_id,
                     ])
                     .add(size as i64);
+                resource_usage::record_read(size);
             }
             (buf, res)
         })
@@ -746,6 +774,7 @@ impl VirtualFile {
                         &self.timeline_id,
                     ])
                     .add(size as i64);
+                resource_usage::record_write(size);
             }
             (buf, result)
         })
@@ -0,0 +1,465 @@
+//! Capture of the WAL records ingested into a timeline, to reproduce ingest bugs locally.
+//!
+//! With the `capture_wal` tenant config option, each walreceiver connection of the tenant's
+//! timelines writes the records it ingests to a file under
+//! [`crate::config::PageServerConf::wal_capture_path`], in the order they were decoded, along
+//! with the points where the ingested records were committed. [`replay`] feeds the records
+//! of a capture to [`WalIngest`] of a timeline set up by the caller, in the same batches. A
+//! timeline bootstrapped with initdb only has the state of the captured timeline if the
+//! capture starts at the end of initdb.
+//!
+//! Capturing is best-effort and never holds up ingest: the file is written by a blocking
+//! task, and if it falls behind, if writing fails, or once the capture reaches
+//! [`MAX_CAPTURE_SIZE`], the capture stops. Only the captures of the last
+//! [`MAX_CAPTURES_PER_TIMELINE`] walreceiver connections of a timeline are kept.
+
+use std::fs::{create_dir_all, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+
+use anyhow::{ensure, Context};
+use bytes::Bytes;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use utils::bin_ser::BeSer;
+use utils::lsn::Lsn;
+
+use crate::context::RequestContext;
+use crate::tenant::Timeline;
+use crate::walingest::WalIngest;
+use crate::walrecord::DecodedWALRecord;
+
+const WAL_CAPTURE_MAGIC: u32 = 0x5741_4c43; // "WALC"
+const WAL_CAPTURE_FORMAT_VERSION: u16 = 1;
+
+/// A capture stops once its file reaches this size.
+pub const MAX_CAPTURE_SIZE: u64 = 1024 * 1024 * 1024;
+/// Captures of older walreceiver connections are removed beyond this many per timeline.
+pub const MAX_CAPTURES_PER_TIMELINE: usize = 4;
+/// A capture stops if this many entries, or records of this many bytes, wait to be written.
+const MAX_PENDING_ENTRIES: usize = 64 * 1024;
+const MAX_PENDING_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CaptureHeader {
+    magic: u32,
+    format_version: u16,
+    pub pg_version: u32,
+    /// The LSN the walreceiver started ingesting from.
+    pub start_lsn: Lsn,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaptureEntry {
+    /// A record, as decoded from the WAL stream and passed to [`WalIngest::ingest_record`].
+    Record { lsn: Lsn, data: Vec<u8> },
+    /// The records since the previous commit were committed.
+    Commit,
+}
+
+impl CaptureEntry {
+    fn data_len(&self) -> usize {
+        match self {
+            CaptureEntry::Record { data, .. } => data.len(),
+            CaptureEntry::Commit => 0,
+        }
+    }
+}
+
+/// Captures the records ingested by one walreceiver connection.
+pub struct WalCaptureWriter {
+    /// `None` once the capture stopped.
+    tx: Option<SyncSender<CaptureEntry>>,
+    /// Bytes of the records sent to the writer and not written yet.
+    pending_bytes: Arc<AtomicUsize>,
+    writer: JoinHandle<()>,
+}
+
+impl WalCaptureWriter {
+    /// Start writing a capture to `path`. Must be called within a tokio runtime.
+    pub fn create(path: Utf8PathBuf, pg_version: u32, start_lsn: Lsn) -> Self {
+        let header = CaptureHeader {
+            magic: WAL_CAPTURE_MAGIC,
+            format_version: WAL_CAPTURE_FORMAT_VERSION,
+            pg_version,
+            start_lsn,
+        };
+        let (tx, rx) = sync_channel(MAX_PENDING_ENTRIES);
+        let pending_bytes = Arc::new(AtomicUsize::new(0));
+        let span = tracing::Span::current();
+        let writer = tokio::task::spawn_blocking({
+            let pending_bytes = Arc::clone(&pending_bytes);
+            move || {
+                let _entered = span.entered();
+                match write_capture(&path, header, rx, &pending_bytes) {
+                    Ok(()) => {}
+                    Err(e) => warn!("stopped capturing WAL records to {path}: {e:#}"),
+                }
+            }
+        });
+        WalCaptureWriter {
+            tx: Some(tx),
+            pending_bytes,
+            writer,
+        }
+    }
+
+    pub fn record(&mut self, lsn: Lsn, data: &Bytes) {
+        self.send(CaptureEntry::Record {
+            lsn,
+            data: data.to_vec(),
+        });
+    }
+
+    /// The file is flushed at each commit, so that the capture is complete up to the last
+    /// commit even if the pageserver crashes.
+    pub fn commit(&mut self) {
+        self.send(CaptureEntry::Commit);
+    }
+
+    fn send(&mut self, entry: CaptureEntry) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        let len = entry.data_len();
+        if self.pending_bytes.fetch_add(len, Ordering::Relaxed) + len > MAX_PENDING_BYTES {
+            warn!("stopped capturing WAL records: writing the capture fell behind ingest");
+            self.tx = None;
+            return;
+        }
+        match tx.try_send(entry) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!("stopped capturing WAL records: writing the capture fell behind ingest");
+                self.tx = None;
+            }
+            // The writer logged why it stopped.
+            Err(TrySendError::Disconnected(_)) => self.tx = None,
+        }
+    }
+
+    /// Stop capturing and wait for the entries captured so far to be written.
+    pub async fn finish(mut self) {
+        self.tx = None;
+        if let Err(e) = self.writer.await {
+            warn!("wal capture writer failed: {e}");
+        }
+    }
+}
+
+fn write_capture(
+    path: &Utf8Path,
+    header: CaptureHeader,
+    rx: Receiver<CaptureEntry>,
+    pending_bytes: &AtomicUsize,
+) -> anyhow::Result<()> {
+    let dir = path.parent().context("wal capture path has no parent")?;
+    create_dir_all(dir).with_context(|| format!("create wal capture dir {dir}"))?;
+    remove_old_captures(dir)?;
+
+    let file = File::create(path).with_context(|| format!("create wal capture {path}"))?;
+    let mut writer = BufWriter::new(file);
+    header.ser_into(&mut writer)?;
+    let mut size = header.serialized_size()?;
+    info!("capturing ingested WAL records to {path}");
+
+    for entry in rx {
+        pending_bytes.fetch_sub(entry.data_len(), Ordering::Relaxed);
+        size += entry.serialized_size()?;
+        if size > MAX_CAPTURE_SIZE {
+            writer.flush()?;
+            info!("stopped capturing WAL records to {path}: the capture reached {MAX_CAPTURE_SIZE} bytes");
+            return Ok(());
+        }
+        entry.ser_into(&mut writer)?;
+        if entry == CaptureEntry::Commit {
+            writer.flush()?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Make room for a new capture in the captures directory of a timeline.
+fn remove_old_captures(dir: &Utf8Path) -> anyhow::Result<()> {
+    let mut captures = Vec::new();
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        captures.push((entry.metadata()?.modified()?, entry.into_path()));
+    }
+    captures.sort();
+    let excess = (captures.len() + 1).saturating_sub(MAX_CAPTURES_PER_TIMELINE);
+    for (_, path) in captures.into_iter().take(excess) {
+        std::fs::remove_file(&path).with_context(|| format!("remove old wal capture {path}"))?;
+    }
+    Ok(())
+}
+
+pub struct WalCaptureReader<R> {
+    reader: BufReader<R>,
+    header: CaptureHeader,
+}
+
+impl WalCaptureReader<File> {
+    pub fn open(path: &Utf8Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("open wal capture {path}"))?;
+        Self::new(file)
+    }
+}
+
+impl<R: Read> WalCaptureReader<R> {
+    pub fn new(reader: R) -> anyhow::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let header = CaptureHeader::des_from(&mut reader).context("read wal capture header")?;
+        ensure!(
+            header.magic == WAL_CAPTURE_MAGIC,
+            "not a wal capture file, magic {:#x}",
+            header.magic
+        );
+        ensure!(
+            header.format_version == WAL_CAPTURE_FORMAT_VERSION,
+            "unsupported wal capture format version {}",
+            header.format_version
+        );
+        Ok(WalCaptureReader { reader, header })
+    }
+
+    pub fn header(&self) -> &CaptureHeader {
+        &self.header
+    }
+
+    /// Returns `None` at the end of the capture.
+    pub fn next_entry(&mut self) -> anyhow::Result<Option<CaptureEntry>> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let entry = CaptureEntry::des_from(&mut self.reader).context("read wal capture entry")?;
+        Ok(Some(entry))
+    }
+}
+
+/// Ingest the captured records into `timeline`, committing them in the same batches as when
+/// they were captured. Returns the LSN of the last record.
+pub async fn replay<R: Read>(
+    timeline: &Timeline,
+    capture: &mut WalCaptureReader<R>,
+    ctx: &RequestContext,
+) -> anyhow::Result<Lsn> {
+    let CaptureHeader {
+        pg_version,
+        start_lsn,
+        ..
+    } = *capture.header();
+    ensure!(
+        pg_version == timeline.pg_version,
+        "captured from a timeline of postgres version {pg_version}, replaying into version {}",
+        timeline.pg_version
+    );
+
+    let mut walingest = WalIngest::new(timeline, start_lsn, ctx).await?;
+    let mut modification = timeline.begin_modification(start_lsn);
+    let mut decoded = DecodedWALRecord::default();
+    let mut last_lsn = start_lsn;
+    let mut uncommitted = false;
+
+    while let Some(entry) = capture.next_entry()? {
+        match entry {
+            CaptureEntry::Record { lsn, data } => {
+                walingest
+                    .ingest_record(Bytes::from(data), lsn, &mut modification, &mut decoded, ctx)
+                    .await
+                    .with_context(|| format!("could not ingest record at {lsn}"))?;
+                last_lsn = lsn;
+                uncommitted = true;
+            }
+            CaptureEntry::Commit => {
+                modification.commit(ctx).await?;
+                uncommitted = false;
+            }
+        }
+    }
+    // The capture ends with uncommitted records if the walreceiver failed to ingest them.
+    if uncommitted {
+        modification.commit(ctx).await?;
+    }
+    Ok(last_lsn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenant::harness::{TenantHarness, TIMELINE_ID};
+    use crate::tenant::remote_timeline_client::{remote_initdb_archive_path, INITDB_PATH};
+    use crate::tenant::Tenant;
+
+    /// Bootstrap a timeline from the initdb archive at `source_initdb_path`, if given.
+    async fn bootstrap_timeline(
+        harness: &TenantHarness,
+        tenant: &Tenant,
+        pg_version: u32,
+        source_initdb_path: Option<&str>,
+        ctx: &RequestContext,
+    ) -> std::sync::Arc<Timeline> {
+        let load_existing_initdb = source_initdb_path.map(|source_initdb_path| {
+            let remote_initdb_path =
+                remote_initdb_archive_path(&tenant.tenant_shard_id().tenant_id, &TIMELINE_ID);
+            let initdb_path = harness.remote_fs_dir.join(remote_initdb_path.get_path());
+            std::fs::create_dir_all(initdb_path.parent().unwrap()).unwrap();
+            std::fs::copy(source_initdb_path, initdb_path).unwrap();
+            TIMELINE_ID
+        });
+        tenant
+            .bootstrap_timeline_test(TIMELINE_ID, pg_version, load_existing_initdb, ctx)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn capture_roundtrip() {
+        let harness = TenantHarness::create("wal_capture_roundtrip").unwrap();
+        let dir = harness.conf.workdir.join("captures");
+        let path = dir.join("0");
+
+        let mut writer = WalCaptureWriter::create(path.clone(), 15, Lsn(0x1000));
+        writer.record(Lsn(0x1028), &Bytes::from_static(b"first"));
+        writer.record(Lsn(0x1050), &Bytes::from_static(b"second"));
+        writer.commit();
+        writer.record(Lsn(0x1078), &Bytes::from_static(b"third"));
+        writer.finish().await;
+
+        let mut reader = WalCaptureReader::open(&path).unwrap();
+        assert_eq!(reader.header().pg_version, 15);
+        assert_eq!(reader.header().start_lsn, Lsn(0x1000));
+        let mut entries = Vec::new();
+        while let Some(entry) = reader.next_entry().unwrap() {
+            entries.push(entry);
+        }
+        assert_eq!(
+            entries,
+            [
+                CaptureEntry::Record {
+                    lsn: Lsn(0x1028),
+                    data: b"first".to_vec()
+                },
+                CaptureEntry::Record {
+                    lsn: Lsn(0x1050),
+                    data: b"second".to_vec()
+                },
+                CaptureEntry::Commit,
+                CaptureEntry::Record {
+                    lsn: Lsn(0x1078),
+                    data: b"third".to_vec()
+                },
+            ]
+        );
+
+        assert!(WalCaptureReader::new(&b"garbage garbage"[..]).is_err());
+
+        // older captures make room for new ones
+        for i in 1..=MAX_CAPTURES_PER_TIMELINE {
+            let writer = WalCaptureWriter::create(dir.join(format!("{i}")), 15, Lsn(0x1000));
+            writer.finish().await;
+        }
+        assert_eq!(
+            dir.read_dir_utf8().unwrap().count(),
+            MAX_CAPTURES_PER_TIMELINE
+        );
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn replay_capture() {
+        use postgres_ffi::waldecoder::WalStreamDecoder;
+        use postgres_ffi::WAL_SEGMENT_SIZE;
+
+        // The WAL of `test_ingest_real_wal` in walingest.rs.
+        let pg_version = 15;
+        let path = "test_data/sk_wal_segment_from_pgbench";
+        let wal_segment_path = format!("{path}/000000010000000000000001.zst");
+        let source_initdb_path = format!("{path}/{INITDB_PATH}");
+        let startpoint = Lsn::from_hex("14AEC08").unwrap();
+
+        let harness = TenantHarness::create("wal_capture_replay").unwrap();
+        let (tenant, ctx) = harness.load().await;
+        let capture_path = harness.conf.workdir.join("captures").join("0");
+
+        // Capture the first records of the segment, as the walreceiver would.
+        let bytes = {
+            use async_compression::tokio::bufread::ZstdDecoder;
+            let file = tokio::fs::File::open(wal_segment_path).await.unwrap();
+            let mut decoder = ZstdDecoder::new(tokio::io::BufReader::new(file));
+            let mut buffer = Vec::new();
+            tokio::io::copy(&mut decoder, &mut buffer).await.unwrap();
+            buffer
+        };
+        let mut decoder = WalStreamDecoder::new(startpoint, pg_version);
+        let mut writer = WalCaptureWriter::create(capture_path.clone(), pg_version, startpoint);
+        let mut records = 0;
+        let mut last_lsn = startpoint;
+        for chunk in bytes[startpoint.segment_offset(WAL_SEGMENT_SIZE)..]
+            .chunks(8192)
+            .take(16)
+        {
+            decoder.feed_bytes(chunk);
+            while let Some((lsn, recdata)) = decoder.poll_decode().unwrap() {
+                writer.record(lsn, &recdata);
+                records += 1;
+                last_lsn = lsn;
+            }
+            writer.commit();
+        }
+        writer.finish().await;
+        assert!(records > 0);
+
+        let tline = bootstrap_timeline(
+            &harness,
+            &tenant,
+            pg_version,
+            Some(&source_initdb_path),
+            &ctx,
+        )
+        .await;
+        let mut capture = WalCaptureReader::open(&capture_path).unwrap();
+        assert_eq!(replay(&tline, &mut capture, &ctx).await.unwrap(), last_lsn);
+
+        // A capture of another postgres version can't be replayed.
+        let mut capture = WalCaptureReader::open(&capture_path).unwrap();
+        capture.header.pg_version = 14;
+        assert!(replay(&tline, &mut capture, &ctx).await.is_err());
+    }
+
+    /// Replay the capture at `WAL_CAPTURE` into a fresh timeline, bootstrapped from the
+    /// initdb archive at `WAL_CAPTURE_INITDB` if set.
+    #[tokio::test]
+    #[ignore]
+    async fn replay_wal_capture() {
+        let path = Utf8PathBuf::from(std::env::var("WAL_CAPTURE").expect("WAL_CAPTURE not set"));
+        let mut capture = WalCaptureReader::open(&path).unwrap();
+        let CaptureHeader {
+            pg_version,
+            start_lsn,
+            ..
+        } = *capture.header();
+        println!("replaying {path}, pg_version {pg_version}, starting at {start_lsn}");
+
+        let harness = TenantHarness::create("replay_wal_capture").unwrap();
+        let (tenant, ctx) = harness.load().await;
+        let source_initdb_path = std::env::var("WAL_CAPTURE_INITDB").ok();
+        let tline = bootstrap_timeline(
+            &harness,
+            &tenant,
+            pg_version,
+            source_initdb_path.as_deref(),
+            &ctx,
+        )
+        .await;
+
+        let started_at = std::time::Instant::now();
+        let last_lsn = replay(&tline, &mut capture, &ctx).await.unwrap();
+        println!("replayed up to {last_lsn} in {:?}", started_at.elapsed());
+    }
+}
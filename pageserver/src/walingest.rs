@@ -237,6 +237,12 @@ impl WalIngest {
                         ctx,
                     )
                     .await?;
+                    if info == pg_constants::XLOG_XACT_COMMIT {
+                        modification
+                            .tline
+                            .lsn_time_index
+                            .record_commit(lsn, parsed_xact.xact_time);
+                    }
                 } else if info == pg_constants::XLOG_XACT_COMMIT_PREPARED
                     || info == pg_constants::XLOG_XACT_ABORT_PREPARED
                 {
@@ -249,6 +255,12 @@ impl WalIngest {
                         ctx,
                     )
                     .await?;
+                    if info == pg_constants::XLOG_XACT_COMMIT_PREPARED {
+                        modification
+                            .tline
+                            .lsn_time_index
+                            .record_commit(lsn, parsed_xact.xact_time);
+                    }
                     // Remove twophase file. see RemoveTwoPhaseFile() in postgres code
                     trace!(
                         "Drop twophaseFile for xid {} parsed_xact.xid {} here at {}",
@@ -1910,6 +1922,80 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_relation_sizes() -> Result<()> {
+        let (tenant, ctx) = TenantHarness::create("test_relation_sizes")?.load().await;
+        let tline = tenant
+            .create_test_timeline(TIMELINE_ID, Lsn(8), DEFAULT_PG_VERSION, &ctx)
+            .await?;
+        let mut walingest = init_walingest_test(&tline, &ctx).await?;
+        let testrel_fsm = RelTag {
+            forknum: FSM_FORKNUM,
+            ..TESTREL_A
+        };
+        let testrel_b = RelTag {
+            dbnode: 222,
+            ..TESTREL_A
+        };
+
+        let mut m = tline.begin_modification(Lsn(0x20));
+        walingest.put_rel_creation(&mut m, TESTREL_A, &ctx).await?;
+        walingest
+            .put_rel_page_image(&mut m, TESTREL_A, 2, test_img("foo blk 2"), &ctx)
+            .await?;
+        walingest
+            .put_rel_creation(&mut m, testrel_fsm, &ctx)
+            .await?;
+        walingest
+            .put_rel_page_image(&mut m, testrel_fsm, 0, test_img("fsm blk 0"), &ctx)
+            .await?;
+        m.commit(&ctx).await?;
+        let mut m = tline.begin_modification(Lsn(0x30));
+        walingest.put_rel_creation(&mut m, testrel_b, &ctx).await?;
+        walingest
+            .put_rel_page_image(&mut m, testrel_b, 0, test_img("bar blk 0"), &ctx)
+            .await?;
+        m.commit(&ctx).await?;
+
+        let sizes = |databases: Vec<pageserver_api::models::DatabaseSize>| {
+            databases
+                .iter()
+                .map(|db| {
+                    let rels = db.relations.iter().map(|r| (r.relnode, r.forknum, r.size));
+                    (db.dbnode, db.size, rels.collect::<Vec<_>>())
+                })
+                .collect::<Vec<_>>()
+        };
+        let blcksz = BLCKSZ as u64;
+        assert_eq!(
+            sizes(tline.get_relation_sizes(Lsn(0x20), &ctx).await?),
+            [(
+                111,
+                4 * blcksz,
+                vec![
+                    (1000, MAIN_FORKNUM, 3 * blcksz),
+                    (1000, FSM_FORKNUM, blcksz)
+                ]
+            )]
+        );
+        assert_eq!(
+            sizes(tline.get_relation_sizes(Lsn(0x30), &ctx).await?),
+            [
+                (
+                    111,
+                    4 * blcksz,
+                    vec![
+                        (1000, MAIN_FORKNUM, 3 * blcksz),
+                        (1000, FSM_FORKNUM, blcksz)
+                    ]
+                ),
+                (222, blcksz, vec![(1000, MAIN_FORKNUM, blcksz)]),
+            ]
+        );
+
+        Ok(())
+    }
+
     // Test what happens if we dropped a relation
     // and then created it again within the same layer.
     #[tokio::test]
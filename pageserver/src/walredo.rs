@@ -27,9 +27,10 @@ pub(crate) mod apply_neon;
 
 use crate::config::PageServerConf;
 use crate::metrics::{
-    WAL_REDO_BYTES_HISTOGRAM, WAL_REDO_PROCESS_LAUNCH_DURATION_HISTOGRAM,
-    WAL_REDO_RECORDS_HISTOGRAM, WAL_REDO_TIME,
+    WalRedoRecycleReason, WAL_REDO_BYTES_HISTOGRAM, WAL_REDO_PROCESS_COUNTERS,
+    WAL_REDO_PROCESS_LAUNCH_DURATION_HISTOGRAM, WAL_REDO_RECORDS_HISTOGRAM, WAL_REDO_TIME,
 };
+use crate::pg_versions::{PgVersionError, SUPPORTED_PG_VERSIONS};
 use crate::repository::Key;
 use crate::walrecord::NeonWalRecord;
 use anyhow::Context;
@@ -37,6 +38,7 @@ use bytes::{Bytes, BytesMut};
 use pageserver_api::key::key_to_rel_block;
 use pageserver_api::models::{WalRedoManagerProcessStatus, WalRedoManagerStatus};
 use pageserver_api::shard::TenantShardId;
+use std::num::NonZeroU64;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
@@ -44,6 +46,31 @@ use tracing::*;
 use utils::lsn::Lsn;
 use utils::sync::heavier_once_cell;
 
+/// Limits after which a walredo process is replaced with a new one.
+///
+/// Long-running walredo processes accumulate memory in their caches. A process that reaches
+/// one of these limits is taken out of rotation: the requests that are using it finish, and the
+/// next request launches a new process. The RSS limit is checked periodically, the others after
+/// every request.
+///
+/// ```toml
+/// [walredo_recycle]
+/// max_requests = 1000000
+/// max_rss_bytes = 268435456
+/// max_lifetime = "1h"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecycleConfig {
+    /// Number of redo requests that a process serves.
+    pub max_requests: Option<NonZeroU64>,
+    /// Resident set size above which a process is recycled.
+    pub max_rss_bytes: Option<NonZeroU64>,
+    /// Time after which a process is recycled.
+    #[serde(with = "humantime_serde")]
+    pub max_lifetime: Option<Duration>,
+}
+
 ///
 /// This is the real implementation that uses a Postgres process to
 /// perform WAL replay. Only one thread can use the process at a time,
@@ -51,11 +78,16 @@ use utils::sync::heavier_once_cell;
 /// launch a pool of processes to allow concurrent replay of multiple
 /// records.
 ///
+/// The timelines of a tenant can have different Postgres versions, so
+/// there is a process for each version, launched by the first request
+/// for that version.
+///
 pub struct PostgresRedoManager {
     tenant_shard_id: TenantShardId,
     conf: &'static PageServerConf,
     last_redo_at: std::sync::Mutex<Option<Instant>>,
-    /// The current [`process::Process`] that is used by new redo requests.
+    /// The current [`process::Process`] of each of [`SUPPORTED_PG_VERSIONS`], that is used by
+    /// new redo requests.
     /// We use [`heavier_once_cell`] for coalescing the spawning, but the redo
     /// requests don't use the [`heavier_once_cell::Guard`] to keep ahold of the
     /// their process object; we use [`Arc::clone`] for that.
@@ -67,7 +99,8 @@ pub struct PostgresRedoManager {
     /// still be using the old redo process. But, those other tasks will most likely
     /// encounter an error as well, and errors are an unexpected condition anyway.
     /// So, probably we could get rid of the `Arc` in the future.
-    redo_process: heavier_once_cell::OnceCell<Arc<process::Process>>,
+    redo_processes:
+        [heavier_once_cell::OnceCell<Arc<process::Process>>; SUPPORTED_PG_VERSIONS.len()],
 }
 
 ///
@@ -141,6 +174,16 @@ impl PostgresRedoManager {
     }
 
     pub fn status(&self) -> WalRedoManagerStatus {
+        let processes: Vec<_> = self
+            .redo_processes
+            .iter()
+            .filter_map(|cell| cell.get())
+            .map(|p| WalRedoManagerProcessStatus {
+                pid: p.id(),
+                kind: std::borrow::Cow::Borrowed(p.kind().into()),
+                pg_version: p.pg_version(),
+            })
+            .collect();
         WalRedoManagerStatus {
             last_redo_at: {
                 let at = *self.last_redo_at.lock().unwrap();
@@ -150,13 +193,8 @@ impl PostgresRedoManager {
                     chrono::Utc::now().checked_sub_signed(chrono::Duration::from_std(age).ok()?)
                 })
             },
-            process: self
-                .redo_process
-                .get()
-                .map(|p| WalRedoManagerProcessStatus {
-                    pid: p.id(),
-                    kind: std::borrow::Cow::Borrowed(p.kind().into()),
-                }),
+            process: processes.first().cloned(),
+            processes,
         }
     }
 }
@@ -174,10 +212,21 @@ impl PostgresRedoManager {
             tenant_shard_id,
             conf,
             last_redo_at: std::sync::Mutex::default(),
-            redo_process: heavier_once_cell::OnceCell::default(),
+            redo_processes: Default::default(),
         }
     }
 
+    fn redo_process(
+        &self,
+        pg_version: u32,
+    ) -> Result<&heavier_once_cell::OnceCell<Arc<process::Process>>, PgVersionError> {
+        SUPPORTED_PG_VERSIONS
+            .iter()
+            .position(|v| *v == pg_version)
+            .map(|i| &self.redo_processes[i])
+            .ok_or(PgVersionError::Unsupported(pg_version))
+    }
+
     /// This type doesn't have its own background task to check for idleness: we
     /// rely on our owner calling this function periodically in its own housekeeping
     /// loops.
@@ -186,12 +235,68 @@ impl PostgresRedoManager {
             if let Some(last_redo_at) = *g {
                 if last_redo_at.elapsed() >= idle_timeout {
                     drop(g);
-                    drop(self.redo_process.get().map(|guard| guard.take_and_deinit()));
+                    for redo_process in &self.redo_processes {
+                        drop(redo_process.get().map(|guard| guard.take_and_deinit()));
+                    }
                 }
             }
         }
     }
 
+    /// Recycle the current processes that have reached one of the limits of
+    /// [`PageServerConf::walredo_recycle`]. Like [`Self::maybe_quiesce`], called periodically by
+    /// our owner, because the RSS limit isn't checked on every request.
+    pub(crate) fn maybe_recycle(&self) {
+        for redo_process in &self.redo_processes {
+            let proc = match redo_process.get() {
+                Some(guard) => Arc::clone(&guard),
+                None => continue,
+            };
+            self.maybe_recycle_process(&proc, true);
+        }
+    }
+
+    /// Take `proc` out of rotation if it has reached one of the recycling limits. Checking the
+    /// RSS limit requires reading `/proc`, so it is only done if `check_rss` is set.
+    fn maybe_recycle_process(&self, proc: &Arc<process::Process>, check_rss: bool) {
+        let config = &self.conf.walredo_recycle;
+        let reason = if config
+            .max_requests
+            .is_some_and(|max| proc.requests() >= max.get())
+        {
+            WalRedoRecycleReason::MaxRequests
+        } else if config.max_lifetime.is_some_and(|max| proc.age() >= max) {
+            WalRedoRecycleReason::MaxLifetime
+        } else if check_rss
+            && config
+                .max_rss_bytes
+                .is_some_and(|max| proc.rss_bytes().is_some_and(|rss| rss >= max.get()))
+        {
+            WalRedoRecycleReason::MaxRss
+        } else {
+            return;
+        };
+
+        let redo_process = self
+            .redo_process(proc.pg_version())
+            .expect("processes are only launched for supported versions");
+        match redo_process.get() {
+            Some(guard) if Arc::ptr_eq(proc, &*guard) => {
+                guard.take_and_deinit();
+            }
+            // Another task already replaced it.
+            _ => return,
+        }
+        info!(
+            pid = proc.id(),
+            requests = proc.requests(),
+            age_secs = proc.age().as_secs(),
+            ?reason,
+            "recycling walredo process"
+        );
+        WAL_REDO_PROCESS_COUNTERS.recycled_by_reason[reason].inc();
+    }
+
     ///
     /// Process one request for WAL redo using wal-redo postgres
     ///
@@ -212,13 +317,15 @@ impl PostgresRedoManager {
         *(self.last_redo_at.lock().unwrap()) = Some(Instant::now());
 
         let (rel, blknum) = key_to_rel_block(key).context("invalid record")?;
+        let redo_process = self.redo_process(pg_version)?;
         const MAX_RETRY_ATTEMPTS: u32 = 1;
         let mut n_attempts = 0u32;
         loop {
-            let proc: Arc<process::Process> = match self.redo_process.get_or_init_detached().await {
+            let proc: Arc<process::Process> = match redo_process.get_or_init_detached().await {
                 Ok(guard) => Arc::clone(&guard),
                 Err(permit) => {
                     // don't hold poison_guard, the launch code can bail
+                    crate::pg_versions::check_installed(self.conf, pg_version)?;
                     let start = Instant::now();
                     let proc = Arc::new(
                         process::Process::launch(self.conf, self.tenant_shard_id, pg_version)
@@ -229,9 +336,10 @@ impl PostgresRedoManager {
                     info!(
                         duration_ms = duration.as_millis(),
                         pid = proc.id(),
+                        pg_version,
                         "launched walredo process"
                     );
-                    self.redo_process.set(Arc::clone(&proc), permit);
+                    redo_process.set(Arc::clone(&proc), permit);
                     proc
                 }
             };
@@ -284,7 +392,7 @@ impl PostgresRedoManager {
                 // Avoid concurrent callers hitting the same issue by taking `proc` out of the rotation.
                 // Note that there may be other tasks concurrent with us that also hold `proc`.
                 // We have to deal with that here.
-                // Also read the doc comment on field `self.redo_process`.
+                // Also read the doc comment on field `self.redo_processes`.
                 //
                 // NB: there may still be other concurrent threads using `proc`.
                 // The last one will send SIGKILL when the underlying Arc reaches refcount 0.
@@ -296,7 +404,7 @@ impl PostgresRedoManager {
                 // than we can SIGKILL & `wait` for them to exit. By doing it the way we do here,
                 // we limit this risk of run-away to at most $num_runtimes * $num_executor_threads.
                 // This probably needs revisiting at some later point.
-                match self.redo_process.get() {
+                match redo_process.get() {
                     None => (),
                     Some(guard) => {
                         if Arc::ptr_eq(&proc, &*guard) {
@@ -310,8 +418,11 @@ impl PostgresRedoManager {
                 }
                 // The last task that does this `drop()` of `proc` will do a blocking `wait()` syscall.
                 drop(proc);
-            } else if n_attempts != 0 {
-                info!(n_attempts, "retried walredo succeeded");
+            } else {
+                if n_attempts != 0 {
+                    info!(n_attempts, "retried walredo succeeded");
+                }
+                self.maybe_recycle_process(&proc, false);
             }
             n_attempts += 1;
             if n_attempts > MAX_RETRY_ATTEMPTS || result.is_ok() {
@@ -376,11 +487,12 @@ impl PostgresRedoManager {
 
 #[cfg(test)]
 mod tests {
-    use super::PostgresRedoManager;
+    use super::{PostgresRedoManager, RecycleConfig};
     use crate::repository::Key;
     use crate::{config::PageServerConf, walrecord::NeonWalRecord};
     use bytes::Bytes;
     use pageserver_api::shard::TenantShardId;
+    use std::num::NonZeroU64;
     use std::str::FromStr;
     use tracing::Instrument;
     use utils::{id::TenantId, lsn::Lsn};
@@ -461,6 +573,48 @@ mod tests {
             .unwrap_err();
     }
 
+    #[tokio::test]
+    async fn recycle_after_max_requests() {
+        let h = RedoHarness::new_with_recycle(RecycleConfig {
+            max_requests: NonZeroU64::new(2),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut pids = Vec::new();
+        for _ in 0..3 {
+            h.manager
+                .request_redo(
+                    Key {
+                        field1: 0,
+                        field2: 1663,
+                        field3: 13010,
+                        field4: 1259,
+                        field5: 0,
+                        field6: 0,
+                    },
+                    Lsn::from_str("0/16E2408").unwrap(),
+                    None,
+                    short_records(),
+                    14,
+                )
+                .instrument(h.span())
+                .await
+                .unwrap();
+            pids.push(h.manager.status().process.map(|p| p.pid));
+        }
+
+        assert!(pids[0].is_some());
+        assert_eq!(
+            pids[1], None,
+            "process should be recycled after two requests"
+        );
+        assert!(
+            pids[2].is_some(),
+            "next request should launch a new process"
+        );
+    }
+
     #[allow(clippy::octal_escapes)]
     fn short_records() -> Vec<(Lsn, NeonWalRecord)> {
         vec![
@@ -490,10 +644,15 @@ mod tests {
 
     impl RedoHarness {
         fn new() -> anyhow::Result<Self> {
+            Self::new_with_recycle(RecycleConfig::default())
+        }
+
+        fn new_with_recycle(recycle: RecycleConfig) -> anyhow::Result<Self> {
             crate::tenant::harness::setup_logging();
 
             let repo_dir = camino_tempfile::tempdir()?;
-            let conf = PageServerConf::dummy_conf(repo_dir.path().to_path_buf());
+            let mut conf = PageServerConf::dummy_conf(repo_dir.path().to_path_buf());
+            conf.walredo_recycle = recycle;
             let conf = Box::leak(Box::new(conf));
             let tenant_shard_id = TenantShardId::unsharded(TenantId::generate());
 
@@ -1,4 +1,5 @@
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use pageserver_api::{reltag::RelTag, shard::TenantShardId};
@@ -34,7 +35,15 @@ pub enum Kind {
     Async,
 }
 
-pub(crate) enum Process {
+pub(crate) struct Process {
+    imp: ProcessImpl,
+    launched_at: Instant,
+    /// Number of redo requests sent to the process so far.
+    requests: AtomicU64,
+    pg_version: u32,
+}
+
+enum ProcessImpl {
     Sync(process_impl::process_std::WalRedoProcess),
     Async(process_impl::process_async::WalRedoProcess),
 }
@@ -46,17 +55,23 @@ impl Process {
         tenant_shard_id: TenantShardId,
         pg_version: u32,
     ) -> anyhow::Result<Self> {
-        Ok(match conf.walredo_process_kind {
-            Kind::Sync => Self::Sync(process_impl::process_std::WalRedoProcess::launch(
+        let imp = match conf.walredo_process_kind {
+            Kind::Sync => ProcessImpl::Sync(process_impl::process_std::WalRedoProcess::launch(
                 conf,
                 tenant_shard_id,
                 pg_version,
             )?),
-            Kind::Async => Self::Async(process_impl::process_async::WalRedoProcess::launch(
+            Kind::Async => ProcessImpl::Async(process_impl::process_async::WalRedoProcess::launch(
                 conf,
                 tenant_shard_id,
                 pg_version,
             )?),
+        };
+        Ok(Self {
+            imp,
+            launched_at: Instant::now(),
+            requests: AtomicU64::new(0),
+            pg_version,
         })
     }
 
@@ -69,12 +84,13 @@ impl Process {
         records: &[(Lsn, NeonWalRecord)],
         wal_redo_timeout: Duration,
     ) -> anyhow::Result<Bytes> {
-        match self {
-            Process::Sync(p) => {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        match &self.imp {
+            ProcessImpl::Sync(p) => {
                 p.apply_wal_records(rel, blknum, base_img, records, wal_redo_timeout)
                     .await
             }
-            Process::Async(p) => {
+            ProcessImpl::Async(p) => {
                 p.apply_wal_records(rel, blknum, base_img, records, wal_redo_timeout)
                     .await
             }
@@ -82,16 +98,41 @@ impl Process {
     }
 
     pub(crate) fn id(&self) -> u32 {
-        match self {
-            Process::Sync(p) => p.id(),
-            Process::Async(p) => p.id(),
+        match &self.imp {
+            ProcessImpl::Sync(p) => p.id(),
+            ProcessImpl::Async(p) => p.id(),
         }
     }
 
+    pub(crate) fn pg_version(&self) -> u32 {
+        self.pg_version
+    }
+
     pub(crate) fn kind(&self) -> Kind {
-        match self {
-            Process::Sync(_) => Kind::Sync,
-            Process::Async(_) => Kind::Async,
+        match &self.imp {
+            ProcessImpl::Sync(_) => Kind::Sync,
+            ProcessImpl::Async(_) => Kind::Async,
+        }
+    }
+
+    pub(crate) fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn age(&self) -> Duration {
+        self.launched_at.elapsed()
+    }
+
+    /// Resident set size of the process, if it can be determined on this platform.
+    pub(crate) fn rss_bytes(&self) -> Option<u64> {
+        #[cfg(target_os = "linux")]
+        {
+            let process = procfs::process::Process::new(self.id() as i32).ok()?;
+            process.status().ok()?.vmrss.map(|kb| kb * 1024)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            None
         }
     }
 }
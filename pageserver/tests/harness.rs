@@ -0,0 +1,32 @@
+//! Use of the tenant harness from outside of the pageserver crate, like other crates of the
+//! workspace do in their tests.
+
+use pageserver::tenant::harness::{TenantHarness, NEW_TIMELINE_ID, TIMELINE_ID};
+use pageserver::tenant::TenantState;
+use pageserver::DEFAULT_PG_VERSION;
+use utils::lsn::Lsn;
+
+#[tokio::test]
+async fn create_timelines() -> anyhow::Result<()> {
+    let harness = TenantHarness::create("harness_create_timelines")?;
+    let (tenant, ctx) = harness.load().await;
+    assert!(matches!(tenant.current_state(), TenantState::Active));
+
+    for timeline_id in [TIMELINE_ID, NEW_TIMELINE_ID] {
+        tenant
+            .create_test_timeline(timeline_id, Lsn(0x10), DEFAULT_PG_VERSION, &ctx)
+            .await?;
+    }
+
+    let mut timeline_ids = tenant
+        .list_timelines()
+        .iter()
+        .map(|timeline| timeline.timeline_id)
+        .collect::<Vec<_>>();
+    timeline_ids.sort();
+    let mut expected = vec![TIMELINE_ID, NEW_TIMELINE_ID];
+    expected.sort();
+    assert_eq!(timeline_ids, expected);
+    assert!(harness.timeline_path(&TIMELINE_ID).exists());
+    Ok(())
+}
@@ -36,6 +36,7 @@ typedef enum
 	T_NeonGetPageRequest,
 	T_NeonDbSizeRequest,
 	T_NeonGetSlruSegmentRequest,
+	T_NeonGetPageAtLeastRequest,	/* not sent yet, see PagestreamGetPageAtLeastRequest */
 
 	/* pagestore -> pagestore_client */
 	T_NeonExistsResponse = 100,
@@ -44,6 +45,7 @@ typedef enum
 	T_NeonErrorResponse,
 	T_NeonDbSizeResponse,
 	T_NeonGetSlruSegmentResponse,
+	T_NeonGetPageAtLeastResponse,
 } NeonMessageTag;
 
 /* base struct for c-style inheritance */
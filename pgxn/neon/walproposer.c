@@ -70,6 +70,8 @@ static bool SendAppendRequests(Safekeeper *sk);
 static bool RecvAppendResponses(Safekeeper *sk);
 static XLogRecPtr CalculateMinFlushLsn(WalProposer *wp);
 static XLogRecPtr GetAcknowledgedByQuorumWALPosition(WalProposer *wp);
+static XLogRecPtr GetMemberSetFlushLsn(WalProposer *wp, MemberSet *set);
+static bool ParseMemberSet(Safekeeper *sk, StringInfo s, MemberSet *set);
 static void HandleSafekeeperResponse(WalProposer *wp, Safekeeper *sk);
 static bool AsyncRead(Safekeeper *sk, char **buf, int *buf_size);
 static bool AsyncReadMessage(Safekeeper *sk, AcceptorProposerMessage *anymsg);
@@ -688,7 +690,20 @@ RecvAcceptorGreeting(Safekeeper *sk)
 	if (!AsyncReadMessage(sk, (AcceptorProposerMessage *) &sk->greetResponse))
 		return;
 
-	wp_log(LOG, "received AcceptorGreeting from safekeeper %s:%s, term=" INT64_FORMAT, sk->host, sk->port, sk->greetResponse.term);
+	wp_log(LOG, "received AcceptorGreeting from safekeeper %s:%s, term=" INT64_FORMAT ", mconf generation=%u", sk->host, sk->port, sk->greetResponse.term, sk->greetResponse.mconf.generation);
+
+	/*
+	 * Safekeepers close the connection when they switch to another membership
+	 * configuration, so we learn about it here, on reconnection.
+	 */
+	if (sk->greetResponse.mconf.generation > wp->mconf.generation)
+	{
+		wp_log(LOG, "switching to membership configuration generation %u: %u members, %u new members",
+			   sk->greetResponse.mconf.generation,
+			   sk->greetResponse.mconf.members.len,
+			   sk->greetResponse.mconf.new_members.len);
+		wp->mconf = sk->greetResponse.mconf;
+	}
 
 	/* Protocol is all good, move to voting. */
 	sk->state = SS_VOTING;
@@ -1557,6 +1572,20 @@ GetAcknowledgedByQuorumWALPosition(WalProposer *wp)
 {
 	XLogRecPtr	responses[MAX_SAFEKEEPERS];
 
+	/*
+	 * Once safekeepers told us the membership configuration, count the acks
+	 * of its members. During a change WAL is committed only once it is
+	 * flushed on a quorum of both the old and the new members.
+	 */
+	if (wp->mconf.generation != 0)
+	{
+		XLogRecPtr	lsn = GetMemberSetFlushLsn(wp, &wp->mconf.members);
+
+		if (wp->mconf.new_members.len > 0)
+			lsn = Min(lsn, GetMemberSetFlushLsn(wp, &wp->mconf.new_members));
+		return lsn;
+	}
+
 	/*
 	 * Sort acknowledged LSNs
 	 */
@@ -1576,6 +1605,58 @@ GetAcknowledgedByQuorumWALPosition(WalProposer *wp)
 	return responses[wp->n_safekeepers - wp->quorum];
 }
 
+/*
+ * The highest LSN flushed on a majority of the given members. Members which
+ * haven't greeted us haven't flushed anything.
+ */
+static XLogRecPtr
+GetMemberSetFlushLsn(WalProposer *wp, MemberSet *set)
+{
+	XLogRecPtr	responses[MAX_SAFEKEEPERS];
+
+	if (set->len == 0)
+		return InvalidXLogRecPtr;
+
+	for (int i = 0; i < set->len; i++)
+	{
+		responses[i] = InvalidXLogRecPtr;
+		for (int j = 0; j < wp->n_safekeepers; j++)
+		{
+			Safekeeper *sk = &wp->safekeeper[j];
+
+			if (sk->greetResponse.nodeId != set->m[i])
+				continue;
+			/* as above, ignore LSNs from previous terms */
+			if (sk->appendResponse.flushLsn >= wp->propEpochStartLsn)
+				responses[i] = sk->appendResponse.flushLsn;
+			break;
+		}
+	}
+	qsort(responses, set->len, sizeof(XLogRecPtr), CompareLsn);
+	return responses[set->len - (set->len / 2 + 1)];
+}
+
+/*
+ * Read a member set of AcceptorGreeting: the number of members, then their
+ * node ids.
+ */
+static bool
+ParseMemberSet(Safekeeper *sk, StringInfo s, MemberSet *set)
+{
+	WalProposer *wp = sk->wp;
+
+	set->len = pq_getmsgint32_le(s);
+	if (set->len > MAX_SAFEKEEPERS)
+	{
+		wp_log(WARNING, "safekeeper %s:%s sent a membership configuration with %u members, at most %d are supported",
+			   sk->host, sk->port, set->len, MAX_SAFEKEEPERS);
+		return false;
+	}
+	for (int i = 0; i < set->len; i++)
+		set->m[i] = pq_getmsgint64_le(s);
+	return true;
+}
+
 /*
  * Return safekeeper with active connection from which WAL can be downloaded, or
  * none if it doesn't exist. donor_lsn is set to end position of the donor to
@@ -1794,6 +1875,13 @@ AsyncReadMessage(Safekeeper *sk, AcceptorProposerMessage *anymsg)
 
 				msg->term = pq_getmsgint64_le(&s);
 				msg->nodeId = pq_getmsgint64_le(&s);
+				msg->mconf.generation = pq_getmsgint32_le(&s);
+				if (!ParseMemberSet(sk, &s, &msg->mconf.members) ||
+					!ParseMemberSet(sk, &s, &msg->mconf.new_members))
+				{
+					ResetConnection(sk);
+					return false;
+				}
 				pq_getmsgend(&s);
 				return true;
 			}
@@ -13,7 +13,7 @@
 #include "pagestore_client.h"
 
 #define SK_MAGIC 0xCafeCeefu
-#define SK_PROTOCOL_VERSION 2
+#define SK_PROTOCOL_VERSION 3
 
 #define MAX_SAFEKEEPERS 32
 #define MAX_SEND_SIZE (XLOG_BLCKSZ * 16)	/* max size of a single* WAL
@@ -143,6 +143,26 @@ typedef uint64 term_t;
 /* neon storage node id */
 typedef uint64 NNodeId;
 
+/* Set of safekeepers by node id. */
+typedef struct MemberSet
+{
+	uint32		len;
+	NNodeId		m[MAX_SAFEKEEPERS];
+} MemberSet;
+
+/*
+ * Membership configuration of the timeline, the set of safekeepers storing
+ * its WAL. During a change both members and new_members (non empty) must
+ * form a quorum. Generation 0 means the configuration is unknown: the
+ * configured safekeepers are the members.
+ */
+typedef struct MembershipConfiguration
+{
+	uint32		generation;
+	MemberSet	members;
+	MemberSet	new_members;
+} MembershipConfiguration;
+
 /*
  * Proposer <-> Acceptor messaging.
  */
@@ -174,6 +194,7 @@ typedef struct AcceptorGreeting
 	AcceptorProposerMessage apm;
 	term_t		term;
 	NNodeId		nodeId;
+	MembershipConfiguration mconf;
 } AcceptorGreeting;
 
 /*
@@ -669,6 +690,12 @@ typedef struct WalProposer
 	/* cached GetAcknowledgedByQuorumWALPosition result */
 	XLogRecPtr	commitLsn;
 
+	/*
+	 * Membership configuration with the highest generation seen in the
+	 * greetings, commitLsn requires a quorum of it.
+	 */
+	MembershipConfiguration mconf;
+
 	ProposerGreeting greetRequest;
 
 	/* Vote request for safekeeper */
@@ -19,7 +19,7 @@ use tokio::time::error::Elapsed;
 
 use crate::{
     console,
-    error::{ReportableError, UserFacingError},
+    error::{ErrorCode, ReportableError, UserFacingError},
 };
 use std::{io, net::IpAddr};
 use thiserror::Error;
@@ -128,6 +128,23 @@ impl UserFacingError for AuthError {
             UserTimeout(_) => self.to_string(),
         }
     }
+
+    fn get_error_code(&self) -> ErrorCode {
+        use AuthErrorImpl::*;
+        match self.0.as_ref() {
+            Link(e) => e.get_error_code(),
+            GetAuthInfo(e) => e.get_error_code(),
+            Sasl(e) => e.get_error_code(),
+            AuthFailed(_) => ErrorCode::AuthFailed,
+            BadAuthMethod(_) => ErrorCode::AuthProtocol,
+            MalformedPassword(_) => ErrorCode::AuthProtocol,
+            MissingEndpointName => ErrorCode::BadRequest,
+            Io(_) => ErrorCode::Internal,
+            IpAddressNotAllowed(_) => ErrorCode::AccessDenied,
+            TooManyConnections => ErrorCode::RateLimited,
+            UserTimeout(_) => ErrorCode::AuthTimeout,
+        }
+    }
 }
 
 impl ReportableError for AuthError {
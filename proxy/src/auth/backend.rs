@@ -19,6 +19,7 @@ use crate::console::errors::GetAuthInfoError;
 use crate::console::provider::{CachedRoleSecret, ConsoleBackend};
 use crate::console::{AuthSecret, NodeInfo};
 use crate::context::RequestMonitoring;
+use crate::endpoint_metrics::ENDPOINT_METRICS;
 use crate::intern::EndpointIdInt;
 use crate::metrics::Metrics;
 use crate::proxy::connect_compute::ComputeConnectBackend;
@@ -155,6 +156,11 @@ impl ComputeUserInfo {
     pub fn endpoint_cache_key(&self) -> EndpointCacheKey {
         self.options.get_cache_key(&self.endpoint)
     }
+
+    /// Whether the client connects to the `-pooler` endpoint, that is to PgBouncer.
+    pub fn is_pooler(&self) -> bool {
+        self.endpoint.as_str().ends_with(crate::POOLER_SUFFIX)
+    }
 }
 
 pub enum ComputeCredentialKeys {
@@ -333,6 +339,7 @@ async fn auth_quirks(
         }
     };
 
+    let endpoint = EndpointIdInt::from(&info.endpoint);
     match authenticate_with_secret(
         ctx,
         secret,
@@ -347,6 +354,7 @@ async fn auth_quirks(
         Ok(keys) => Ok(keys),
         Err(e) => {
             if e.is_auth_failed() {
+                ENDPOINT_METRICS.record_auth_failure(endpoint);
                 // The password could have been changed, so we invalidate the cache.
                 cached_entry.invalidate();
             }
@@ -499,6 +507,13 @@ impl ComputeConnectBackend for BackendType<'_, ComputeCredentials, NodeInfo> {
         }
     }
 
+    fn is_pooler(&self) -> bool {
+        match self {
+            BackendType::Console(_, creds) => creds.info.is_pooler(),
+            BackendType::Link(_, _) => false,
+        }
+    }
+
     fn get_keys(&self) -> Option<&ComputeCredentialKeys> {
         match self {
             BackendType::Console(_, creds) => Some(&creds.keys),
@@ -521,6 +536,13 @@ impl ComputeConnectBackend for BackendType<'_, ComputeCredentials, &()> {
         }
     }
 
+    fn is_pooler(&self) -> bool {
+        match self {
+            BackendType::Console(_, creds) => creds.info.is_pooler(),
+            BackendType::Link(_, _) => false,
+        }
+    }
+
     fn get_keys(&self) -> Option<&ComputeCredentialKeys> {
         match self {
             BackendType::Console(_, creds) => Some(&creds.keys),
@@ -122,5 +122,7 @@ pub(super) async fn authenticate(
         config,
         aux: db_info.aux,
         allow_self_signed_compute: false, // caller may override
+        replicas: Vec::new(),
+        session_policy: None,
     })
 }
@@ -11,24 +11,32 @@ use proxy::auth::backend::AuthRateLimiter;
 use proxy::auth::backend::MaybeOwned;
 use proxy::cancellation::CancelMap;
 use proxy::cancellation::CancellationHandler;
+use proxy::certs;
+use proxy::certs::acme::AcmeConfig;
 use proxy::config::remote_storage_from_toml;
 use proxy::config::AuthenticationConfig;
 use proxy::config::CacheOptions;
+use proxy::config::CertPaths;
 use proxy::config::HttpConfig;
 use proxy::config::ProjectInfoCacheOptions;
 use proxy::console;
 use proxy::context::parquet::ParquetUploadArgs;
+use proxy::endpoint_metrics;
 use proxy::http;
+use proxy::http::health_checks::HealthChecks;
 use proxy::http::health_server::AppMetrics;
 use proxy::metrics::Metrics;
+use proxy::proxy::sticky_routing::StickyRoutes;
 use proxy::rate_limiter::EndpointRateLimiter;
 use proxy::rate_limiter::RateBucketInfo;
+use proxy::redis::cancel_key_registry::{self, CancelKeyRegistry};
 use proxy::redis::cancellation_publisher::RedisPublisherClient;
 use proxy::redis::connection_with_credentials_provider::ConnectionWithCredentialsProvider;
 use proxy::redis::elasticache;
 use proxy::redis::notifications;
 use proxy::serverless::cancel_set::CancelSet;
 use proxy::serverless::GlobalConnPoolOptions;
+use proxy::session_policy::SessionPolicies;
 use proxy::usage_metrics;
 
 use anyhow::bail;
@@ -77,6 +85,10 @@ struct ProxyCliArgs {
     /// listen for management callback connection on ip:port
     #[clap(short, long, default_value = "127.0.0.1:7000")]
     mgmt: String,
+    /// listen for connections to the PgBouncer-compatible admin console of the serverless
+    /// connection pool on ip:port
+    #[clap(long)]
+    pool_admin: Option<String>,
     /// listen for incoming http connections (metrics, etc) on ip:port
     #[clap(long, default_value = "127.0.0.1:7001")]
     http: String,
@@ -106,15 +118,49 @@ struct ProxyCliArgs {
     /// path to directory with TLS certificates for client postgres connections
     #[clap(long)]
     certs_dir: Option<String>,
+    /// how often the TLS certificates are checked for changes, and reloaded if they changed
+    /// (use `0s` to only load them at startup)
+    #[clap(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    tls_reload_interval: tokio::time::Duration,
+    /// directory URL of an ACME server, e.g. https://acme-v02.api.letsencrypt.org/directory,
+    /// to issue and renew the certificates of the --acme-domain domains into --certs-dir.
+    /// Only set it on one replica of the domains, the others load the certificates from a shared
+    /// --certs-dir
+    #[clap(long)]
+    acme_directory: Option<String>,
+    /// domain whose certificate is issued by the ACME server. the domain is validated through
+    /// the wss listener, which must be reachable on port 443 of the domain.
+    /// Can be given multiple times for different domains.
+    #[clap(long)]
+    acme_domain: Vec<String>,
+    /// email address of the ACME account, for the expiration notices
+    #[clap(long)]
+    acme_contact: Option<String>,
     /// timeout for the TLS handshake
     #[clap(long, default_value = "15s", value_parser = humantime::parse_duration)]
     handshake_timeout: tokio::time::Duration,
+    /// domains for which TLS is not terminated by the proxy, but passed through to the destination
+    /// chosen by SNI. example: "external.domain=internal.domain,other.domain=other.internal.domain"
+    #[clap(long, default_value = "")]
+    tls_passthrough_domains: String,
+    /// how long new connections stick to the compute replica last used by the same endpoint,
+    /// user and database, when several replicas serve an endpoint (use `0s` to only route by hashing)
+    #[clap(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    compute_affinity_ttl: tokio::time::Duration,
+    /// path to a JSON file with the session policies of endpoints: the GUCs set on their compute
+    /// connections and the roles allowed to connect. the policies of the control plane take
+    /// precedence.
+    #[clap(long)]
+    session_policies: Option<String>,
     /// http endpoint to receive periodic metric updates
     #[clap(long)]
     metric_collection_endpoint: Option<String>,
     /// how often metrics should be sent to a collection endpoint
     #[clap(long)]
     metric_collection_interval: Option<String>,
+    /// window of the per-endpoint metrics reported by the http server at /v1/endpoints/top
+    #[clap(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    endpoint_metrics_window: tokio::time::Duration,
     /// cache for `wake_compute` api method (use `size=0` to disable)
     #[clap(long, default_value = config::CacheOptions::CACHE_DEFAULT_OPTIONS)]
     wake_compute_cache: String,
@@ -217,7 +263,8 @@ struct ProxyCliArgs {
 
 #[derive(clap::Args, Clone, Copy, Debug)]
 struct SqlOverHttpArgs {
-    /// timeout for http connection requests
+    /// timeout for http connection requests, e.g. how long the requests to an endpoint paused on
+    /// the pool admin console wait
     #[clap(long, default_value = "15s", value_parser = humantime::parse_duration)]
     sql_over_http_timeout: tokio::time::Duration,
 
@@ -242,6 +289,28 @@ struct SqlOverHttpArgs {
     #[clap(long, default_value = "10m", value_parser = humantime::parse_duration)]
     sql_over_http_pool_gc_epoch: tokio::time::Duration,
 
+    /// How long a transaction session can stay idle before its connection is closed,
+    /// rolling back the transaction
+    #[clap(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    sql_over_http_transaction_idle_timeout: tokio::time::Duration,
+
+    /// How many transaction sessions each endpoint can have at once
+    #[clap(long, default_value_t = 20)]
+    sql_over_http_max_transaction_sessions_per_endpoint: usize,
+
+    /// How many transaction sessions the proxy can have at once
+    #[clap(long, default_value_t = 2000)]
+    sql_over_http_max_transaction_sessions: usize,
+
+    /// How many responses of read-only queries to cache, for the requests that opt in. 0 disables
+    /// the cache
+    #[clap(long, default_value_t = 1000)]
+    sql_over_http_result_cache_size: usize,
+
+    /// How long responses of read-only queries are cached for
+    #[clap(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    sql_over_http_result_cache_ttl: tokio::time::Duration,
+
     /// How many shards should the global pool have. Must be a power of two.
     /// More shards will introduce less contention for pool operations, but can
     /// increase memory used by the pool
@@ -278,6 +347,7 @@ async fn main() -> anyhow::Result<()> {
 
     let args = ProxyCliArgs::parse();
     let config = build_config(&args)?;
+    let acme_config = acme_config(&args)?;
 
     info!("Authentication backend: {}", config.auth_backend);
     info!("Using region: {}", config.aws_region);
@@ -345,6 +415,15 @@ async fn main() -> anyhow::Result<()> {
     info!("Starting mgmt on {mgmt_address}");
     let mgmt_listener = TcpListener::bind(mgmt_address).await?;
 
+    let pool_admin_listener = match &args.pool_admin {
+        Some(pool_admin_address) => {
+            let pool_admin_address: SocketAddr = pool_admin_address.parse()?;
+            info!("Starting pool admin console on {pool_admin_address}");
+            Some(TcpListener::bind(pool_admin_address).await?)
+        }
+        None => None,
+    };
+
     let proxy_address: SocketAddr = args.proxy.parse()?;
     info!("Starting proxy on {proxy_address}");
     let proxy_listener = TcpListener::bind(proxy_address).await?;
@@ -360,13 +439,21 @@ async fn main() -> anyhow::Result<()> {
         )?))),
         None => None,
     };
-    let cancellation_handler = Arc::new(CancellationHandler::<
-        Option<Arc<tokio::sync::Mutex<RedisPublisherClient>>>,
-    >::new(
-        cancel_map.clone(),
-        redis_publisher,
-        proxy::metrics::CancellationSource::FromClient,
-    ));
+    let (cancel_key_registry, cancel_key_registry_rx) = match &regional_redis_client {
+        Some(_) => {
+            let (registry, rx) = CancelKeyRegistry::new();
+            (Some(registry), Some(rx))
+        }
+        None => (None, None),
+    };
+    let cancellation_handler = Arc::new(
+        CancellationHandler::<Option<Arc<tokio::sync::Mutex<RedisPublisherClient>>>>::new(
+            cancel_map.clone(),
+            redis_publisher,
+            proxy::metrics::CancellationSource::FromClient,
+        )
+        .with_registry(cancel_key_registry),
+    );
 
     let mut endpoint_rps_limit = args.endpoint_rps_limit.clone();
     RateBucketInfo::validate(&mut endpoint_rps_limit)?;
@@ -374,6 +461,7 @@ async fn main() -> anyhow::Result<()> {
 
     // client facing tasks. these will exit on error or on cancellation
     // cancellation returns Ok(())
+    let conn_pool = serverless::ConnPoolHandle::default();
     let mut client_tasks = JoinSet::new();
     client_tasks.spawn(proxy::proxy::task_main(
         config,
@@ -396,6 +484,7 @@ async fn main() -> anyhow::Result<()> {
             cancellation_token.clone(),
             cancellation_handler.clone(),
             endpoint_rate_limiter.clone(),
+            conn_pool.clone(),
         ));
     }
 
@@ -414,8 +503,35 @@ async fn main() -> anyhow::Result<()> {
             neon_metrics,
             proxy: proxy::metrics::Metrics::get(),
         },
+        HealthChecks::new(config, conn_pool.clone()),
     ));
     maintenance_tasks.spawn(console::mgmt::task_main(mgmt_listener));
+    maintenance_tasks.spawn(endpoint_metrics::task_main(args.endpoint_metrics_window));
+    if let (Some(client), Some(rx)) = (&regional_redis_client, cancel_key_registry_rx) {
+        maintenance_tasks.spawn(cancel_key_registry::task_main(
+            client.clone(),
+            args.region.clone(),
+            rx,
+        ));
+    }
+    if let Some(pool_admin_listener) = pool_admin_listener {
+        maintenance_tasks.spawn(serverless::pool_admin::task_main(
+            pool_admin_listener,
+            conn_pool,
+        ));
+    }
+    if let (Some(tls_config), Some(cert_paths)) = (&config.tls_config, cert_paths(&args)?) {
+        if !args.tls_reload_interval.is_zero() {
+            maintenance_tasks.spawn(certs::reload_task(
+                tls_config,
+                cert_paths.clone(),
+                args.tls_reload_interval,
+            ));
+        }
+        if let Some(acme_config) = acme_config {
+            maintenance_tasks.spawn(certs::acme::task_main(tls_config, cert_paths, acme_config));
+        }
+    }
 
     if let Some(metrics_config) = &config.metric_collection {
         // TODO: Add gc regardles of the metric collection being enabled.
@@ -487,17 +603,48 @@ async fn main() -> anyhow::Result<()> {
     match maintenance {}
 }
 
-/// ProxyConfig is created at proxy startup, and lives forever.
-fn build_config(args: &ProxyCliArgs) -> anyhow::Result<&'static ProxyConfig> {
-    let tls_config = match (&args.tls_key, &args.tls_cert) {
-        (Some(key_path), Some(cert_path)) => Some(config::configure_tls(
-            key_path,
-            cert_path,
-            args.certs_dir.as_ref(),
-        )?),
-        (None, None) => None,
+/// Where the TLS certificates are read from, if TLS is enabled.
+fn cert_paths(args: &ProxyCliArgs) -> anyhow::Result<Option<CertPaths>> {
+    match (&args.tls_key, &args.tls_cert) {
+        (Some(key_path), Some(cert_path)) => Ok(Some(CertPaths {
+            key_path: key_path.clone(),
+            cert_path: cert_path.clone(),
+            certs_dir: args.certs_dir.clone(),
+        })),
+        (None, None) => Ok(None),
         _ => bail!("either both or neither tls-key and tls-cert must be specified"),
+    }
+}
+
+/// The config of the built-in ACME client, if it's enabled.
+fn acme_config(args: &ProxyCliArgs) -> anyhow::Result<Option<AcmeConfig>> {
+    let Some(directory_url) = &args.acme_directory else {
+        if !args.acme_domain.is_empty() {
+            bail!("acme-domain requires acme-directory");
+        }
+        return Ok(None);
     };
+    if args.acme_domain.is_empty() {
+        bail!("acme-directory requires at least one acme-domain");
+    }
+    if args.tls_key.is_none() || args.certs_dir.is_none() {
+        bail!("acme-directory requires tls-key, tls-cert and certs-dir");
+    }
+    if args.wss.is_none() {
+        bail!("acme-directory requires wss, which answers the ACME challenges");
+    }
+    Ok(Some(AcmeConfig {
+        directory_url: directory_url.clone(),
+        domains: args.acme_domain.clone(),
+        contact: args.acme_contact.clone(),
+    }))
+}
+
+/// ProxyConfig is created at proxy startup, and lives forever.
+fn build_config(args: &ProxyCliArgs) -> anyhow::Result<&'static ProxyConfig> {
+    let tls_config = cert_paths(args)?
+        .map(|paths| config::configure_tls(&paths))
+        .transpose()?;
 
     if args.allow_self_signed_compute {
         warn!("allowing self-signed compute certificates");
@@ -619,6 +766,15 @@ fn build_config(args: &ProxyCliArgs) -> anyhow::Result<&'static ProxyConfig> {
             idle_timeout: args.sql_over_http.sql_over_http_idle_timeout,
             opt_in: args.sql_over_http.sql_over_http_pool_opt_in,
             max_total_conns: args.sql_over_http.sql_over_http_pool_max_total_conns,
+            transaction_idle_timeout: args.sql_over_http.sql_over_http_transaction_idle_timeout,
+            max_transaction_sessions_per_endpoint: args
+                .sql_over_http
+                .sql_over_http_max_transaction_sessions_per_endpoint,
+            max_transaction_sessions: args.sql_over_http.sql_over_http_max_transaction_sessions,
+        },
+        result_cache: CacheOptions {
+            size: args.sql_over_http.sql_over_http_result_cache_size,
+            ttl: args.sql_over_http.sql_over_http_result_cache_ttl,
         },
         cancel_set: CancelSet::new(args.sql_over_http.sql_over_http_cancel_set_shards),
         client_conn_threshold: args.sql_over_http.sql_over_http_client_conn_threshold,
@@ -633,6 +789,11 @@ fn build_config(args: &ProxyCliArgs) -> anyhow::Result<&'static ProxyConfig> {
     let mut redis_rps_limit = args.redis_rps_limit.clone();
     RateBucketInfo::validate(&mut redis_rps_limit)?;
 
+    let session_policies = match &args.session_policies {
+        Some(path) => SessionPolicies::load(path)?,
+        None => SessionPolicies::default(),
+    };
+
     let config = Box::leak(Box::new(ProxyConfig {
         tls_config,
         auth_backend,
@@ -651,9 +812,13 @@ fn build_config(args: &ProxyCliArgs) -> anyhow::Result<&'static ProxyConfig> {
         connect_to_compute_retry_config: config::RetryConfig::parse(
             &args.connect_to_compute_retry,
         )?,
+        tls_passthrough: args.tls_passthrough_domains.parse()?,
+        compute_routes: StickyRoutes::new(args.compute_affinity_ttl),
+        session_policies,
     }));
 
     tokio::spawn(config.connect_compute_locks.garbage_collect_worker());
+    tokio::spawn(config.compute_routes.garbage_collect_worker());
 
     Ok(config)
 }
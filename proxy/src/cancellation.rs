@@ -1,6 +1,12 @@
 use dashmap::DashMap;
 use pq_proto::CancelKeyData;
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use thiserror::Error;
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
@@ -11,8 +17,11 @@ use uuid::Uuid;
 use crate::{
     error::ReportableError,
     metrics::{CancellationRequest, CancellationSource, Metrics},
-    redis::cancellation_publisher::{
-        CancellationPublisher, CancellationPublisherMut, RedisPublisherClient,
+    redis::{
+        cancel_key_registry::CancelKeyRegistry,
+        cancellation_publisher::{
+            CancellationPublisher, CancellationPublisherMut, RedisPublisherClient,
+        },
     },
 };
 
@@ -26,6 +35,8 @@ pub type CancellationHandlerMainInternal = Option<Arc<Mutex<RedisPublisherClient
 pub struct CancellationHandler<P> {
     map: CancelMap,
     client: P,
+    /// Where the keys of the sessions are registered for the other proxy instances, if shared.
+    registry: Option<CancelKeyRegistry>,
     /// This field used for the monitoring purposes.
     /// Represents the source of the cancellation request.
     from: CancellationSource,
@@ -76,6 +87,7 @@ impl<P: CancellationPublisher> CancellationHandler<P> {
         info!("registered new query cancellation key {key}");
         Session {
             key,
+            registered: AtomicBool::new(false),
             cancellation_handler: self,
         }
     }
@@ -134,6 +146,7 @@ impl CancellationHandler<()> {
         Self {
             map,
             client: (),
+            registry: None,
             from,
         }
     }
@@ -141,7 +154,17 @@ impl CancellationHandler<()> {
 
 impl<P: CancellationPublisherMut> CancellationHandler<Option<Arc<Mutex<P>>>> {
     pub fn new(map: CancelMap, client: Option<Arc<Mutex<P>>>, from: CancellationSource) -> Self {
-        Self { map, client, from }
+        Self {
+            map,
+            client,
+            registry: None,
+            from,
+        }
+    }
+
+    /// Register the keys of the sessions in the shared `registry`.
+    pub fn with_registry(self, registry: Option<CancelKeyRegistry>) -> Self {
+        Self { registry, ..self }
     }
 }
 
@@ -174,6 +197,8 @@ impl CancelClosure {
 pub struct Session<P> {
     /// The user-facing key identifying this session.
     key: CancelKeyData,
+    /// Whether the key is in the shared registry, to unregister on drop.
+    registered: AtomicBool,
     /// The [`CancelMap`] this session belongs to.
     cancellation_handler: Arc<CancellationHandler<P>>,
 }
@@ -181,19 +206,35 @@ pub struct Session<P> {
 impl<P> Session<P> {
     /// Store the cancel token for the given session.
     /// This enables query cancellation in `crate::proxy::prepare_client_connection`.
+    ///
+    /// The key is also recorded in the shared cancellation registry (if any),
+    /// so that cancel requests arriving at other proxy instances are routed here.
     pub fn enable_query_cancellation(&self, cancel_closure: CancelClosure) -> CancelKeyData {
         info!("enabling query cancellation for this session");
         self.cancellation_handler
             .map
             .insert(self.key, Some(cancel_closure));
+        self.register();
 
         self.key
     }
+
+    fn register(&self) {
+        if let Some(registry) = &self.cancellation_handler.registry {
+            registry.register(self.key);
+            self.registered.store(true, Ordering::Relaxed);
+        }
+    }
 }
 
 impl<P> Drop for Session<P> {
     fn drop(&mut self) {
         self.cancellation_handler.map.remove(&self.key);
+        if let Some(registry) = &self.cancellation_handler.registry {
+            if *self.registered.get_mut() {
+                registry.unregister(self.key);
+            }
+        }
         info!("dropped query cancellation key {}", &self.key);
     }
 }
@@ -218,6 +259,33 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn session_registry() {
+        use crate::redis::cancel_key_registry::RegistryOp;
+
+        let (registry, mut rx) = CancelKeyRegistry::new();
+        let cancellation_handler = Arc::new(
+            CancellationHandler::<CancellationHandlerMainInternal>::new(
+                CancelMap::default(),
+                None,
+                CancellationSource::FromClient,
+            )
+            .with_registry(Some(registry)),
+        );
+
+        // Not registered until query cancellation is enabled.
+        drop(cancellation_handler.clone().get_session());
+        assert!(rx.try_recv().is_err());
+
+        let session = cancellation_handler.clone().get_session();
+        session.register();
+        assert_eq!(rx.try_recv().unwrap(), RegistryOp::Register(session.key));
+        let key = session.key;
+        drop(session);
+        assert_eq!(rx.try_recv().unwrap(), RegistryOp::Unregister(key));
+        assert!(rx.try_recv().is_err());
+    }
+
     #[tokio::test]
     async fn cancel_session_noop_regression() {
         let handler = CancellationHandler::<()>::new(Default::default(), CancellationSource::Local);
@@ -0,0 +1,143 @@
+//! Keeping the TLS certificates up to date without restarting the proxy.
+//!
+//! [`reload_task`] polls the files of the certificates, and reloads them all into the
+//! [`CertResolver`](crate::config::CertResolver) when any of them changed, e.g. when cert-manager
+//! renewed a certificate, or when [`acme`] issued one. The new connections get the new
+//! certificates, the established ones keep theirs.
+//!
+//! The common names of the certificates are only read at startup, as they decide how endpoints
+//! are parsed from the SNI: the certificates of new domains are served after a reload, but the
+//! endpoints of their subdomains are only routed by SNI after a restart.
+
+pub mod acme;
+
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use itertools::Itertools;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::config::{CertPaths, TlsConfig};
+
+/// The files of the certificates, with their modification time and size.
+type Fingerprint = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+fn fingerprint(paths: &CertPaths) -> anyhow::Result<Fingerprint> {
+    let mut files = vec![(
+        PathBuf::from(&paths.key_path),
+        PathBuf::from(&paths.cert_path),
+    )];
+    files.extend(paths.extra_certs()?);
+
+    let mut fingerprint = Vec::new();
+    for path in files.into_iter().flat_map(|(key, cert)| [key, cert]) {
+        let metadata = std::fs::metadata(&path)
+            .with_context(|| format!("failed to stat {}", path.display()))?;
+        fingerprint.push((path, metadata.modified().ok(), metadata.len()));
+    }
+    fingerprint.sort();
+    Ok(fingerprint)
+}
+
+/// Reload the certificates every `interval` if their files changed, see the module docs.
+pub async fn reload_task(
+    tls_config: &'static TlsConfig,
+    paths: CertPaths,
+    interval: Duration,
+) -> anyhow::Result<Infallible> {
+    let mut last_fingerprint = fingerprint(&paths)?;
+
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let fingerprint = match fingerprint(&paths) {
+            Ok(fingerprint) => fingerprint,
+            Err(e) => {
+                warn!("failed to check the TLS certificates: {e:#}");
+                continue;
+            }
+        };
+        // A failed reload isn't retried until the files change again, e.g. once they are
+        // completely written.
+        if fingerprint != last_fingerprint {
+            last_fingerprint = fingerprint;
+            reload(tls_config, &paths);
+        }
+    }
+}
+
+/// Reload the certificates, keeping the current ones if that fails.
+pub fn reload(tls_config: &TlsConfig, paths: &CertPaths) {
+    if let Err(e) = tls_config.cert_resolver.reload(paths) {
+        warn!("failed to reload the TLS certificates, keeping the current ones: {e:#}");
+        return;
+    }
+
+    let common_names = tls_config.cert_resolver.get_common_names();
+    info!(?common_names, "reloaded the TLS certificates");
+    let new_common_names = common_names
+        .difference(&tls_config.common_names)
+        .collect_vec();
+    if !new_common_names.is_empty() {
+        warn!(
+            ?new_common_names,
+            "endpoints are only routed by the SNI of new common names after a restart"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CertResolver;
+
+    fn write_cert(dir: &std::path::Path, common_name: &str) {
+        let cert = rcgen::Certificate::from_params({
+            let mut params = rcgen::CertificateParams::new(vec![common_name.into()]);
+            params.distinguished_name = rcgen::DistinguishedName::new();
+            params
+                .distinguished_name
+                .push(rcgen::DnType::CommonName, common_name);
+            params
+        })
+        .unwrap();
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("tls.key"), cert.serialize_private_key_pem()).unwrap();
+        std::fs::write(dir.join("tls.crt"), cert.serialize_pem().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reload_certs() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let default_dir = dir.path().join("default");
+        let certs_dir = dir.path().join("certs");
+        write_cert(default_dir.as_std_path(), "localhost");
+        std::fs::create_dir(&certs_dir).unwrap();
+
+        let paths = CertPaths {
+            key_path: default_dir.join("tls.key").to_string(),
+            cert_path: default_dir.join("tls.crt").to_string(),
+            certs_dir: Some(certs_dir.to_string()),
+        };
+        let resolver = CertResolver::load(&paths).unwrap();
+        let before = fingerprint(&paths).unwrap();
+        let (localhost, _) = resolver.resolve(Some("localhost")).unwrap();
+        assert!(resolver.resolve(Some("foo.example.com")).is_none());
+
+        write_cert(certs_dir.join("example").as_std_path(), "example.com");
+        assert_ne!(fingerprint(&paths).unwrap(), before);
+        resolver.reload(&paths).unwrap();
+        assert!(resolver.resolve(Some("foo.example.com")).is_some());
+        let (reloaded, _) = resolver.resolve(Some("localhost")).unwrap();
+        assert_eq!(reloaded.cert, localhost.cert);
+
+        // A broken certificate keeps the current ones.
+        std::fs::write(certs_dir.join("example/tls.crt"), "garbage").unwrap();
+        assert!(resolver.reload(&paths).is_err());
+        assert!(resolver.resolve(Some("foo.example.com")).is_some());
+    }
+}
@@ -0,0 +1,538 @@
+//! Built-in ACME client ([RFC 8555]), e.g. for Let's Encrypt, that issues and renews the
+//! certificates of the websocket and SQL-over-HTTP listener.
+//!
+//! The domains are validated with the TLS-ALPN-01 challenge ([RFC 8737]): the ACME server
+//! connects to port 443 of the domain with the `acme-tls/1` protocol, and the
+//! [`CertResolver`] answers with the challenge certificate. The listener must therefore be
+//! reachable on port 443 of the domains. Wildcard domains can't be validated this way.
+//!
+//! The certificates are written to `<certs_dir>/<domain>/tls.{key,crt}`, and loaded from there
+//! like the other certificates of the directory, see [`super`]. They are renewed when they expire
+//! in less than [`RENEW_BEFORE`]. The key of the ACME account is kept in
+//! `<certs_dir>/acme-account.key`.
+//!
+//! Only run the client on a single proxy replica of the domains. Each replica orders the
+//! certificates on its own: behind a load balancer, the ACME server validates the challenge on
+//! any of the replicas, which only answers it if it placed the order, and each order counts
+//! against the rate limits of the ACME server. The other replicas get the certificates by
+//! sharing the certificates directory, e.g. a volume, and reloading it.
+//!
+//! [RFC 8555]: https://www.rfc-editor.org/rfc/rfc8555
+//! [RFC 8737]: https://www.rfc-editor.org/rfc/rfc8737
+
+use std::convert::Infallible;
+use std::fmt;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, ensure, Context};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use rustls::crypto::ring::sign;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::sign::CertifiedKey;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use crate::config::{CertPaths, CertResolver, TlsConfig};
+use crate::http::{ClientWithMiddleware, Response};
+
+/// The protocol negotiated by the validation of the TLS-ALPN-01 challenges.
+pub const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// Let's Encrypt certificates are valid for 90 days, and it recommends renewing them after 60.
+const RENEW_BEFORE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often and how long to wait for the ACME server to validate the challenges and to issue
+/// the certificate.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_POLLS: usize = 60;
+
+const ACCOUNT_KEY_FILE: &str = "acme-account.key";
+
+#[derive(Clone, Debug)]
+pub struct AcmeConfig {
+    /// The directory URL of the ACME server.
+    pub directory_url: String,
+    pub domains: Vec<String>,
+    /// The email address of the ACME account, for the expiration notices.
+    pub contact: Option<String>,
+}
+
+/// Issue the missing certificates and renew the expiring ones, see the module docs.
+pub async fn task_main(
+    tls_config: &'static TlsConfig,
+    paths: CertPaths,
+    config: AcmeConfig,
+) -> anyhow::Result<Infallible> {
+    let certs_dir = paths
+        .certs_dir
+        .clone()
+        .context("ACME needs a certificates directory")?;
+    let certs_dir = Path::new(&certs_dir);
+
+    loop {
+        let mut next_check = CHECK_INTERVAL;
+        let mut client = None;
+        for domain in &config.domains {
+            let domain_dir = certs_dir.join(domain);
+            match needs_renewal(&domain_dir.join("tls.crt")) {
+                Ok(false) => continue,
+                Ok(true) => {}
+                Err(e) => error!(%domain, "failed to check the certificate, renewing it: {e:#}"),
+            }
+
+            info!(%domain, "requesting certificate from {}", config.directory_url);
+            let issued = async {
+                if client.is_none() {
+                    let account_key_path = certs_dir.join(ACCOUNT_KEY_FILE);
+                    client = Some(AcmeClient::new(&config, &account_key_path).await?);
+                }
+                let client = client.as_mut().expect("the client was just created");
+                let (key, cert_chain) = client.issue(&tls_config.cert_resolver, domain).await?;
+                std::fs::create_dir_all(&domain_dir)?;
+                write_private(&domain_dir.join("tls.key"), key.as_bytes())?;
+                write_private(&domain_dir.join("tls.crt"), cert_chain.as_bytes())?;
+                anyhow::Ok(())
+            }
+            .await;
+            match issued {
+                Ok(()) => {
+                    info!(%domain, "certificate issued");
+                    super::reload(tls_config, &paths);
+                }
+                Err(e) => {
+                    error!(%domain, "failed to issue certificate: {e:#}");
+                    next_check = RETRY_INTERVAL;
+                }
+            }
+        }
+        tokio::time::sleep(next_check).await;
+    }
+}
+
+/// Whether the certificate at `cert_path` is missing or expires within [`RENEW_BEFORE`].
+fn needs_renewal(cert_path: &Path) -> anyhow::Result<bool> {
+    let pem = match std::fs::read(cert_path) {
+        Ok(pem) => pem,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e.into()),
+    };
+    let cert = rustls_pemfile::certs(&mut &pem[..])
+        .next()
+        .context("no certificate")??;
+    let (_, cert) = x509_parser::parse_x509_certificate(&cert).context("invalid certificate")?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    Ok(cert.validity().not_after.timestamp() - now < RENEW_BEFORE.as_secs() as i64)
+}
+
+/// Write `contents` to `path` atomically, only readable by the owner.
+fn write_private(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp_path)
+        .with_context(|| format!("failed to create {}", temp_path.display()))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn base64url(data: impl AsRef<[u8]>) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pending,
+    Ready,
+    Processing,
+    Valid,
+    Invalid,
+    Deactivated,
+    Expired,
+    Revoked,
+}
+
+#[derive(Deserialize)]
+struct Order {
+    status: Status,
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+    error: Option<Problem>,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    status: Status,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    #[serde(default)]
+    token: String,
+    error: Option<Problem>,
+}
+
+/// The error of the ACME server ([RFC 7807](https://www.rfc-editor.org/rfc/rfc7807)).
+#[derive(Debug, Deserialize)]
+struct Problem {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    detail: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.detail)
+    }
+}
+
+async fn parse<T: DeserializeOwned>(response: Response) -> anyhow::Result<T> {
+    let body = response.bytes().await?;
+    serde_json::from_slice(&body).context("failed to parse the response of the ACME server")
+}
+
+fn header(response: &Response, name: &str) -> Option<String> {
+    let value = response.headers().get(name)?;
+    value.to_str().ok().map(str::to_owned)
+}
+
+struct AcmeClient {
+    http: ClientWithMiddleware,
+    directory: Directory,
+    rng: SystemRandom,
+    key: EcdsaKeyPair,
+    jwk: serde_json::Value,
+    /// The thumbprint of the account key ([RFC 7638](https://www.rfc-editor.org/rfc/rfc7638)).
+    thumbprint: String,
+    account_url: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    /// Register the account of `account_key_path`, with a new key if there is none.
+    async fn new(config: &AcmeConfig, account_key_path: &Path) -> anyhow::Result<Self> {
+        let http = crate::http::new_client();
+        let directory = parse(http.get(&config.directory_url).send().await?).await?;
+
+        let rng = SystemRandom::new();
+        let account_key = load_or_create_account_key(account_key_path)?;
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &account_key, &rng)
+            .map_err(|e| anyhow!("invalid ACME account key: {e}"))?;
+        // The uncompressed point: 0x04, then the coordinates.
+        let public_key = key.public_key().as_ref();
+        // The members of the thumbprint are required in lexicographic order.
+        let jwk = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            base64url(&public_key[1..33]),
+            base64url(&public_key[33..65]),
+        );
+        let thumbprint = base64url(Sha256::digest(jwk.as_bytes()));
+
+        let mut client = AcmeClient {
+            http,
+            directory,
+            rng,
+            key,
+            jwk: serde_json::from_str(&jwk)?,
+            thumbprint,
+            account_url: None,
+            nonce: None,
+        };
+
+        let mut account = json!({ "termsOfServiceAgreed": true });
+        if let Some(contact) = &config.contact {
+            account["contact"] = json!([format!("mailto:{contact}")]);
+        }
+        let new_account = client.directory.new_account.clone();
+        let response = client.post(&new_account, Some(&account)).await?;
+        let account_url = header(&response, "location").context("no account URL")?;
+        info!(%account_url, "registered ACME account");
+        client.account_url = Some(account_url);
+        Ok(client)
+    }
+
+    /// Order a certificate for `domain`, and return its private key and certificate chain, in
+    /// PEM.
+    async fn issue(
+        &mut self,
+        cert_resolver: &CertResolver,
+        domain: &str,
+    ) -> anyhow::Result<(String, String)> {
+        let new_order = self.directory.new_order.clone();
+        let identifiers = json!({ "identifiers": [{ "type": "dns", "value": domain }] });
+        let response = self.post(&new_order, Some(&identifiers)).await?;
+        let order_url = header(&response, "location").context("no order URL")?;
+        let order: Order = parse(response).await?;
+
+        for authorization_url in &order.authorizations {
+            let authorization: Authorization = self.post_as_get(authorization_url).await?;
+            if authorization.status == Status::Valid {
+                continue;
+            }
+            let challenge = authorization
+                .challenges
+                .iter()
+                .find(|challenge| challenge.kind == "tls-alpn-01")
+                .context("the ACME server doesn't offer the tls-alpn-01 challenge")?;
+
+            let key_authorization = format!("{}.{}", challenge.token, self.thumbprint);
+            cert_resolver
+                .set_acme_challenge(domain, Some(challenge_cert(domain, &key_authorization)?));
+            scopeguard::defer! {
+                cert_resolver.set_acme_challenge(domain, None);
+            }
+
+            self.post(&challenge.url, Some(&json!({}))).await?;
+            let authorization: Authorization = self
+                .poll(authorization_url, |a: &Authorization| {
+                    a.status != Status::Pending
+                })
+                .await?;
+            if authorization.status != Status::Valid {
+                let error = authorization
+                    .challenges
+                    .into_iter()
+                    .find_map(|challenge| challenge.error);
+                match error {
+                    Some(error) => bail!("the validation of {domain} failed: {error}"),
+                    None => bail!("the validation of {domain} failed"),
+                }
+            }
+        }
+
+        let order: Order = self
+            .poll(&order_url, |o: &Order| o.status != Status::Pending)
+            .await?;
+        ensure!(
+            order.status == Status::Ready,
+            "the order is {:?}: {:?}",
+            order.status,
+            order.error
+        );
+
+        let (key, csr) = certificate_request(domain)?;
+        self.post(&order.finalize, Some(&json!({ "csr": base64url(csr) })))
+            .await?;
+        let order: Order = self
+            .poll(&order_url, |o: &Order| {
+                !matches!(o.status, Status::Ready | Status::Processing)
+            })
+            .await?;
+        ensure!(
+            order.status == Status::Valid,
+            "the order is {:?}: {:?}",
+            order.status,
+            order.error
+        );
+
+        let certificate_url = order.certificate.context("no certificate URL")?;
+        let cert_chain = self.post(&certificate_url, None).await?.text().await?;
+        Ok((key, cert_chain))
+    }
+
+    /// POST-as-GET `url` until `done`.
+    async fn poll<T: DeserializeOwned>(
+        &mut self,
+        url: &str,
+        done: impl Fn(&T) -> bool,
+    ) -> anyhow::Result<T> {
+        for _ in 0..MAX_POLLS {
+            let resource = self.post_as_get(url).await?;
+            if done(&resource) {
+                return Ok(resource);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        bail!("timed out waiting for {url}")
+    }
+
+    async fn post_as_get<T: DeserializeOwned>(&mut self, url: &str) -> anyhow::Result<T> {
+        parse(self.post(url, None).await?).await
+    }
+
+    /// POST `payload` signed with the account key, or nothing for a POST-as-GET.
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<&serde_json::Value>,
+    ) -> anyhow::Result<Response> {
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => {
+                    let response = self.http.head(&self.directory.new_nonce).send().await?;
+                    header(&response, "replay-nonce").context("no nonce")?
+                }
+            };
+            let body = self.sign(url, &nonce, payload)?;
+            let response = self
+                .http
+                .post(url)
+                .header("content-type", "application/jose+json")
+                .body(body)
+                .send()
+                .await?;
+            self.nonce = header(&response, "replay-nonce");
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+            let problem: Problem = parse(response).await?;
+            // Nonces expire, the request is retried once with the new nonce of the error.
+            if problem.kind == "urn:ietf:params:acme:error:badNonce" && !retried {
+                retried = true;
+                continue;
+            }
+            bail!("{url} returned {status}: {problem}");
+        }
+    }
+
+    /// The JWS of `payload`, in the flattened JSON serialization.
+    fn sign(
+        &self,
+        url: &str,
+        nonce: &str,
+        payload: Option<&serde_json::Value>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match &self.account_url {
+            Some(account_url) => protected["kid"] = json!(account_url),
+            None => protected["jwk"] = self.jwk.clone(),
+        }
+        let protected = base64url(serde_json::to_vec(&protected)?);
+        let payload = match payload {
+            Some(payload) => base64url(serde_json::to_vec(payload)?),
+            None => String::new(),
+        };
+        let signature = self
+            .key
+            .sign(&self.rng, format!("{protected}.{payload}").as_bytes())
+            .map_err(|e| anyhow!("failed to sign the ACME request: {e}"))?;
+        Ok(serde_json::to_vec(&json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": base64url(signature),
+        }))?)
+    }
+}
+
+/// The PKCS#8 account key at `path`, created if there is none.
+fn load_or_create_account_key(path: &Path) -> anyhow::Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(pem) => {
+            let key = rustls_pemfile::pkcs8_private_keys(&mut &pem[..])
+                .next()
+                .with_context(|| format!("no private key in {}", path.display()))??;
+            Ok(key.secret_pkcs8_der().to_vec())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256)?;
+            write_private(path, key.serialize_pem().as_bytes())?;
+            info!("created ACME account key {}", path.display());
+            Ok(key.serialize_der())
+        }
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// The self-signed certificate of `domain` that answers the TLS-ALPN-01 challenge of
+/// `key_authorization`.
+fn challenge_cert(domain: &str, key_authorization: &str) -> anyhow::Result<Arc<CertifiedKey>> {
+    let digest = Sha256::digest(key_authorization.as_bytes());
+    let mut params = rcgen::CertificateParams::new(vec![domain.to_owned()]);
+    params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+    params.custom_extensions = vec![rcgen::CustomExtension::new_acme_identifier(&digest)];
+    let cert = rcgen::Certificate::from_params(params)?;
+
+    let key = PrivateKeyDer::Pkcs8(cert.serialize_private_key_der().into());
+    let key = sign::any_supported_type(&key).context("invalid private key")?;
+    let cert_chain = vec![CertificateDer::from(cert.serialize_der()?)];
+    Ok(Arc::new(CertifiedKey::new(cert_chain, key)))
+}
+
+/// A new private key of `domain` in PEM, and its certificate signing request in DER.
+fn certificate_request(domain: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    let mut params = rcgen::CertificateParams::new(vec![domain.to_owned()]);
+    params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, domain);
+    let cert = rcgen::Certificate::from_params(params)?;
+    Ok((
+        cert.serialize_private_key_pem(),
+        cert.serialize_request_der()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renewal() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("tls.crt");
+        assert!(needs_renewal(cert_path.as_std_path()).unwrap());
+
+        // The default validity of rcgen ends in 4096.
+        let params = rcgen::CertificateParams::new(vec!["example.com".into()]);
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        assert!(!needs_renewal(cert_path.as_std_path()).unwrap());
+
+        let mut params = rcgen::CertificateParams::new(vec!["example.com".into()]);
+        params.not_after = rcgen::date_time_ymd(2000, 1, 1);
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        assert!(needs_renewal(cert_path.as_std_path()).unwrap());
+    }
+
+    #[test]
+    fn challenge_certificate() {
+        let cert = challenge_cert("example.com", "token.thumbprint").unwrap();
+        let (_, parsed) = x509_parser::parse_x509_certificate(&cert.cert[0]).unwrap();
+        // id-pe-acmeIdentifier, critical, with the digest of the key authorization.
+        let extension = parsed
+            .extensions()
+            .iter()
+            .find(|e| e.oid.to_id_string() == "1.3.6.1.5.5.7.1.31")
+            .unwrap();
+        assert!(extension.critical);
+        let digest = Sha256::digest(b"token.thumbprint");
+        assert!(extension.value.ends_with(&digest));
+    }
+}
@@ -3,9 +3,10 @@ use crate::{
     cancellation::CancelClosure,
     console::{errors::WakeComputeError, messages::MetricsAuxInfo, provider::ApiLockError},
     context::RequestMonitoring,
-    error::{ReportableError, UserFacingError},
+    error::{ErrorCode, ReportableError, UserFacingError},
     metrics::{Metrics, NumDbConnectionsGuard},
     proxy::neon_option,
+    session_policy::RoleNotAllowed,
     Host,
 };
 use futures::{FutureExt, TryFutureExt};
@@ -37,6 +38,9 @@ pub enum ConnectionError {
 
     #[error("error acquiring resource permit: {0}")]
     TooManyConnectionAttempts(#[from] ApiLockError),
+
+    #[error("{0}")]
+    RoleNotAllowed(#[from] RoleNotAllowed),
 }
 
 impl UserFacingError for ConnectionError {
@@ -63,9 +67,21 @@ impl UserFacingError for ConnectionError {
             TooManyConnectionAttempts(_) => {
                 "Failed to acquire permit to connect to the database. Too many database connection attempts are currently ongoing.".to_owned()
             }
+            RoleNotAllowed(err) => err.to_string_client(),
             _ => COULD_NOT_CONNECT.to_owned(),
         }
     }
+
+    fn get_error_code(&self) -> ErrorCode {
+        match self {
+            ConnectionError::Postgres(e) => ErrorCode::from_postgres(e),
+            ConnectionError::CouldNotConnect(_) => ErrorCode::ComputeUnavailable,
+            ConnectionError::TlsError(_) => ErrorCode::ComputeUnavailable,
+            ConnectionError::WakeComputeError(e) => e.get_error_code(),
+            ConnectionError::TooManyConnectionAttempts(_) => ErrorCode::RateLimited,
+            ConnectionError::RoleNotAllowed(e) => e.get_error_code(),
+        }
+    }
 }
 
 impl ReportableError for ConnectionError {
@@ -79,6 +95,7 @@ impl ReportableError for ConnectionError {
             ConnectionError::TlsError(_) => crate::error::ErrorKind::Compute,
             ConnectionError::WakeComputeError(e) => e.get_error_kind(),
             ConnectionError::TooManyConnectionAttempts(e) => e.get_error_kind(),
+            ConnectionError::RoleNotAllowed(e) => e.get_error_kind(),
         }
     }
 }
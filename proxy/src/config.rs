@@ -1,12 +1,15 @@
 use crate::{
     auth::{self, backend::AuthRateLimiter},
     console::locks::ApiLocks,
+    proxy::sticky_routing::StickyRoutes,
     rate_limiter::RateBucketInfo,
     serverless::{cancel_set::CancelSet, GlobalConnPoolOptions},
+    session_policy::SessionPolicies,
     Host,
 };
 use anyhow::{bail, ensure, Context, Ok};
 use itertools::Itertools;
+use parking_lot::RwLock;
 use remote_storage::RemoteStorageConfig;
 use rustls::{
     crypto::ring::sign,
@@ -15,8 +18,9 @@ use rustls::{
 use sha2::{Digest, Sha256};
 use std::{
     collections::{HashMap, HashSet},
+    path::PathBuf,
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, OnceLock},
     time::Duration,
 };
 use tracing::{error, info};
@@ -38,6 +42,9 @@ pub struct ProxyConfig {
     pub wake_compute_retry_config: RetryConfig,
     pub connect_compute_locks: ApiLocks<Host>,
     pub connect_to_compute_retry_config: RetryConfig,
+    pub tls_passthrough: TlsPassthroughConfig,
+    pub compute_routes: StickyRoutes,
+    pub session_policies: SessionPolicies,
 }
 
 #[derive(Debug)]
@@ -56,6 +63,7 @@ pub struct TlsConfig {
 pub struct HttpConfig {
     pub request_timeout: tokio::time::Duration,
     pub pool_options: GlobalConnPoolOptions,
+    pub result_cache: CacheOptions,
     pub cancel_set: CancelSet,
     pub client_conn_threshold: u64,
 }
@@ -71,21 +79,39 @@ impl TlsConfig {
     pub fn to_server_config(&self) -> Arc<rustls::ServerConfig> {
         self.config.clone()
     }
+
+    /// The server config of a connection, and the channel binding of the certificate that it
+    /// served, set during the handshake. Resolving the certificate again after the handshake
+    /// could give another one, if the certificates were reloaded in between.
+    pub fn to_connection_server_config(
+        &self,
+    ) -> (Arc<rustls::ServerConfig>, Arc<OnceLock<TlsServerEndPoint>>) {
+        let served = Arc::new(OnceLock::new());
+        let mut config = (*self.config).clone();
+        config.cert_resolver = Arc::new(ServedCertResolver {
+            cert_resolver: self.cert_resolver.clone(),
+            served: served.clone(),
+        });
+        (Arc::new(config), served)
+    }
 }
 
-/// Configure TLS for the main endpoint.
-pub fn configure_tls(
-    key_path: &str,
-    cert_path: &str,
-    certs_dir: Option<&String>,
-) -> anyhow::Result<TlsConfig> {
-    let mut cert_resolver = CertResolver::new();
-
-    // add default certificate
-    cert_resolver.add_cert_path(key_path, cert_path, true)?;
-
-    // add extra certificates
-    if let Some(certs_dir) = certs_dir {
+/// Where the TLS certificates are read from.
+#[derive(Clone, Debug)]
+pub struct CertPaths {
+    pub key_path: String,
+    pub cert_path: String,
+    /// Each subdirectory holds a certificate, in `tls.key` and `tls.crt`.
+    pub certs_dir: Option<String>,
+}
+
+impl CertPaths {
+    /// The key and certificate files of the subdirectories of `certs_dir`.
+    pub fn extra_certs(&self) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+        let Some(certs_dir) = &self.certs_dir else {
+            return Ok(Vec::new());
+        };
+        let mut extra_certs = Vec::new();
         for entry in std::fs::read_dir(certs_dir)? {
             let entry = entry?;
             let path = entry.path();
@@ -94,15 +120,17 @@ pub fn configure_tls(
                 let key_path = path.join("tls.key");
                 let cert_path = path.join("tls.crt");
                 if key_path.exists() && cert_path.exists() {
-                    cert_resolver.add_cert_path(
-                        &key_path.to_string_lossy(),
-                        &cert_path.to_string_lossy(),
-                        false,
-                    )?;
+                    extra_certs.push((key_path, cert_path));
                 }
             }
         }
+        Ok(extra_certs)
     }
+}
+
+/// Configure TLS for the main endpoint.
+pub fn configure_tls(paths: &CertPaths) -> anyhow::Result<TlsConfig> {
+    let cert_resolver = CertResolver::load(paths)?;
 
     let common_names = cert_resolver.get_common_names();
 
@@ -186,6 +214,15 @@ impl TlsServerEndPoint {
 
 #[derive(Default, Debug)]
 pub struct CertResolver {
+    /// Replaced as a whole when the certificates are [reloaded](CertResolver::reload).
+    certs: RwLock<Certs>,
+    /// The certificates that answer the TLS-ALPN-01 challenges of the ACME server, by domain,
+    /// see [`crate::certs::acme`].
+    acme_challenges: RwLock<HashMap<String, Arc<rustls::sign::CertifiedKey>>>,
+}
+
+#[derive(Default, Debug)]
+struct Certs {
     certs: HashMap<String, (Arc<rustls::sign::CertifiedKey>, TlsServerEndPoint)>,
     default: Option<(Arc<rustls::sign::CertifiedKey>, TlsServerEndPoint)>,
 }
@@ -195,6 +232,33 @@ impl CertResolver {
         Self::default()
     }
 
+    /// Read the default certificate and the extra ones of [`CertPaths`].
+    pub fn load(paths: &CertPaths) -> anyhow::Result<Self> {
+        let mut cert_resolver = CertResolver::new();
+
+        // add default certificate
+        cert_resolver.add_cert_path(&paths.key_path, &paths.cert_path, true)?;
+
+        // add extra certificates
+        for (key_path, cert_path) in paths.extra_certs()? {
+            cert_resolver.add_cert_path(
+                &key_path.to_string_lossy(),
+                &cert_path.to_string_lossy(),
+                false,
+            )?;
+        }
+
+        Ok(cert_resolver)
+    }
+
+    /// Replace the certificates with those read again from `paths`. The established connections
+    /// keep the certificate of their handshake. If reading fails, the current certificates stay.
+    pub fn reload(&self, paths: &CertPaths) -> anyhow::Result<()> {
+        let loaded = Self::load(paths)?;
+        *self.certs.write() = loaded.certs.into_inner();
+        Ok(())
+    }
+
     fn add_cert_path(
         &mut self,
         key_path: &str,
@@ -236,7 +300,7 @@ impl CertResolver {
     ) -> anyhow::Result<()> {
         let key = sign::any_supported_type(&priv_key).context("invalid private key")?;
 
-        let first_cert = &cert_chain[0];
+        let first_cert = cert_chain.first().context("empty certificate chain")?;
         let tls_server_end_point = TlsServerEndPoint::new(first_cert)?;
         let pem = x509_parser::parse_x509_certificate(first_cert)
             .context("Failed to parse PEM object from cerficiate")?
@@ -259,17 +323,44 @@ impl CertResolver {
 
         let cert = Arc::new(rustls::sign::CertifiedKey::new(cert_chain, key));
 
+        let certs = self.certs.get_mut();
         if is_default {
-            self.default = Some((cert.clone(), tls_server_end_point));
+            certs.default = Some((cert.clone(), tls_server_end_point));
         }
 
-        self.certs.insert(common_name, (cert, tls_server_end_point));
+        certs
+            .certs
+            .insert(common_name, (cert, tls_server_end_point));
 
         Ok(())
     }
 
     pub fn get_common_names(&self) -> HashSet<String> {
-        self.certs.keys().map(|s| s.to_string()).collect()
+        self.certs
+            .read()
+            .certs
+            .keys()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    /// The certificates by common name.
+    pub fn certs(&self) -> Vec<(String, Arc<rustls::sign::CertifiedKey>)> {
+        self.certs
+            .read()
+            .certs
+            .iter()
+            .map(|(name, (cert, _))| (name.clone(), cert.clone()))
+            .collect()
+    }
+
+    /// Answer the TLS-ALPN-01 challenge of `domain` with `cert`, or stop answering it.
+    pub fn set_acme_challenge(&self, domain: &str, cert: Option<Arc<rustls::sign::CertifiedKey>>) {
+        let mut challenges = self.acme_challenges.write();
+        match cert {
+            Some(cert) => challenges.insert(domain.to_owned(), cert),
+            None => challenges.remove(domain),
+        };
     }
 }
 
@@ -278,11 +369,46 @@ impl rustls::server::ResolvesServerCert for CertResolver {
         &self,
         client_hello: rustls::server::ClientHello,
     ) -> Option<Arc<rustls::sign::CertifiedKey>> {
-        self.resolve(client_hello.server_name()).map(|x| x.0)
+        self.resolve_client_hello(client_hello).map(|x| x.0)
+    }
+}
+
+/// Resolves the certificate of a connection with [`CertResolver`], and records its channel
+/// binding, see [`TlsConfig::to_connection_server_config`].
+#[derive(Debug)]
+struct ServedCertResolver {
+    cert_resolver: Arc<CertResolver>,
+    served: Arc<OnceLock<TlsServerEndPoint>>,
+}
+
+impl rustls::server::ResolvesServerCert for ServedCertResolver {
+    fn resolve(
+        &self,
+        client_hello: rustls::server::ClientHello,
+    ) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        let (cert, tls_server_end_point) = self.cert_resolver.resolve_client_hello(client_hello)?;
+        let _ = self.served.set(tls_server_end_point);
+        Some(cert)
     }
 }
 
 impl CertResolver {
+    fn resolve_client_hello(
+        &self,
+        client_hello: rustls::server::ClientHello,
+    ) -> Option<(Arc<rustls::sign::CertifiedKey>, TlsServerEndPoint)> {
+        // The validation of a TLS-ALPN-01 challenge only offers its own protocol, and must get
+        // the challenge certificate.
+        if let Some(mut alpn) = client_hello.alpn() {
+            if alpn.any(|protocol| protocol == crate::certs::acme::ACME_TLS_ALPN) {
+                let server_name = client_hello.server_name()?;
+                let cert = self.acme_challenges.read().get(server_name).cloned()?;
+                return Some((cert, TlsServerEndPoint::Undefined));
+            }
+        }
+        self.resolve(client_hello.server_name())
+    }
+
     pub fn resolve(
         &self,
         server_name: Option<&str>,
@@ -293,9 +419,10 @@ impl CertResolver {
         //
         // With the current coding foo.com will match *.foo.com and that
         // repeats behavior of the old code.
+        let certs = self.certs.read();
         if let Some(mut sni_name) = server_name {
             loop {
-                if let Some(cert) = self.certs.get(sni_name) {
+                if let Some(cert) = certs.certs.get(sni_name) {
                     return Some(cert.clone());
                 }
                 if let Some((_, rest)) = sni_name.split_once('.') {
@@ -315,7 +442,7 @@ impl CertResolver {
             // a) Instead of multi-cert approach use single cert with extra
             //    domains listed in Subject Alternative Name (SAN).
             // b) Deploy separate proxy instances for extra domains.
-            self.default.as_ref().cloned()
+            certs.default.as_ref().cloned()
         }
     }
 }
@@ -577,6 +704,75 @@ impl RetryConfig {
     }
 }
 
+/// Destination domains for which the proxy doesn't terminate TLS.
+///
+/// Connections are routed by the SNI of the TLS ClientHello, pg_sni_router-style,
+/// and TLS is established end-to-end between the client and the destination,
+/// so the proxy never sees the credentials.
+#[derive(Debug, Default)]
+pub struct TlsPassthroughConfig {
+    /// SNI domain suffix -> destination domain suffix.
+    routes: HashMap<String, String>,
+}
+
+impl TlsPassthroughConfig {
+    /// Port used if the SNI hostname doesn't specify one.
+    pub const DEFAULT_PORT: u16 = 5432;
+
+    /// Parse passthrough routes passed via cmdline.
+    /// Example: "external.domain=internal.domain,other.external.domain=other.internal.domain".
+    pub fn parse(options: &str) -> anyhow::Result<Self> {
+        let mut routes = HashMap::new();
+
+        for option in options.split(',').filter(|o| !o.is_empty()) {
+            let (from, to) = option
+                .split_once('=')
+                .with_context(|| format!("bad key-value pair: {option}"))?;
+            ensure!(
+                !from.is_empty() && !to.is_empty(),
+                "empty passthrough domain: {option}"
+            );
+            if routes.insert(from.to_owned(), to.to_owned()).is_some() {
+                bail!("duplicate passthrough domain: {from}");
+            }
+        }
+
+        Ok(Self { routes })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.routes.is_empty()
+    }
+
+    /// Get the destination address for the SNI hostname, if its domain is configured for passthrough.
+    ///
+    /// The first label of the hostname is either `{name}` or `{name}--{namespace}--{port}`,
+    /// which is routed to `{name}.{dest}:5432` or `{name}.{namespace}.{dest}:{port}` respectively.
+    pub fn route(&self, sni: &str) -> Option<anyhow::Result<String>> {
+        let (label, domain) = sni.split_once('.')?;
+        let dest = self.routes.get(domain)?;
+
+        let parts = label.split("--").collect_vec();
+        Some(match parts[..] {
+            [name] if !name.is_empty() => Ok(format!("{name}.{dest}:{}", Self::DEFAULT_PORT)),
+            [name, namespace, port] => port
+                .parse::<u16>()
+                .map(|port| format!("{name}.{namespace}.{dest}:{port}"))
+                .with_context(|| format!("invalid port in SNI hostname: {sni}")),
+            _ => Err(anyhow::anyhow!("invalid SNI hostname: {sni}")),
+        })
+    }
+}
+
+impl FromStr for TlsPassthroughConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(options: &str) -> Result<Self, Self::Err> {
+        let error = || format!("failed to parse tls passthrough options '{options}'");
+        Self::parse(options).with_context(error)
+    }
+}
+
 /// Helper for cmdline cache options parsing.
 pub struct ConcurrencyLockOptions {
     /// The number of shards the lock map should have
@@ -715,4 +911,35 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_tls_passthrough_routes() -> anyhow::Result<()> {
+        let config: TlsPassthroughConfig =
+            "ext.domain=int.domain,ext2.domain=int2.domain".parse()?;
+        assert!(config.is_enabled());
+
+        assert_eq!(
+            config.route("ep-foo.ext.domain").transpose()?.as_deref(),
+            Some("ep-foo.int.domain:5432")
+        );
+        assert_eq!(
+            config
+                .route("svc--ns--6432.ext2.domain")
+                .transpose()?
+                .as_deref(),
+            Some("svc.ns.int2.domain:6432")
+        );
+        assert!(config.route("svc--ns--port.ext.domain").unwrap().is_err());
+        assert!(config.route("svc--ns.ext.domain").unwrap().is_err());
+        assert!(config.route("ep-foo.other.domain").is_none());
+        assert!(config.route("localhost").is_none());
+
+        let config: TlsPassthroughConfig = "".parse()?;
+        assert!(!config.is_enabled());
+
+        assert!("ext.domain".parse::<TlsPassthroughConfig>().is_err());
+        assert!("a=b,a=c".parse::<TlsPassthroughConfig>().is_err());
+
+        Ok(())
+    }
 }
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use crate::auth::IpPattern;
+use crate::session_policy::SessionPolicy;
 
 use crate::intern::{BranchIdInt, EndpointIdInt, ProjectIdInt};
 
@@ -34,7 +35,13 @@ impl fmt::Debug for GetRoleSecret {
 #[derive(Debug, Deserialize)]
 pub struct WakeCompute {
     pub address: Box<str>,
+    /// Addresses of the other compute replicas serving the endpoint, if there are several.
+    #[serde(default)]
+    pub replicas: Vec<Box<str>>,
     pub aux: MetricsAuxInfo,
+    /// The session policy of the endpoint, if it has one, see [`crate::session_policy`].
+    #[serde(default)]
+    pub session_policy: Option<SessionPolicy>,
 }
 
 /// Async response which concludes the link auth flow.
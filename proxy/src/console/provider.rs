@@ -15,7 +15,9 @@ use crate::{
     error::ReportableError,
     intern::ProjectIdInt,
     metrics::ApiLockMetrics,
-    scram, EndpointCacheKey,
+    scram,
+    session_policy::SessionPolicy,
+    EndpointCacheKey,
 };
 use dashmap::DashMap;
 use std::{hash::Hash, sync::Arc, time::Duration};
@@ -25,7 +27,7 @@ use tracing::info;
 
 pub mod errors {
     use crate::{
-        error::{io_error, ReportableError, UserFacingError},
+        error::{io_error, ErrorCode, ReportableError, UserFacingError},
         http,
         proxy::retry::ShouldRetry,
     };
@@ -86,6 +88,47 @@ pub mod errors {
                 _ => REQUEST_FAILED.to_owned(),
             }
         }
+
+        fn get_error_code(&self) -> ErrorCode {
+            match self {
+                ApiError::Console {
+                    status: http::StatusCode::NOT_FOUND,
+                    ..
+                } => ErrorCode::EndpointNotFound,
+                ApiError::Console {
+                    status: http::StatusCode::NOT_ACCEPTABLE,
+                    ..
+                } => ErrorCode::EndpointDisabled,
+                ApiError::Console {
+                    status: http::StatusCode::UNPROCESSABLE_ENTITY,
+                    text,
+                } if text.contains("compute time quota of non-primary branches is exceeded") => {
+                    ErrorCode::QuotaExceeded
+                }
+                ApiError::Console {
+                    status: http::StatusCode::LOCKED,
+                    text,
+                } if text.contains("quota exceeded")
+                    || text.contains("the limit for current plan reached") =>
+                {
+                    ErrorCode::QuotaExceeded
+                }
+                // The endpoint is in transition, see `could_retry`.
+                ApiError::Console {
+                    status:
+                        http::StatusCode::BAD_REQUEST
+                        | http::StatusCode::LOCKED
+                        | http::StatusCode::UNPROCESSABLE_ENTITY,
+                    ..
+                } => ErrorCode::ComputeStarting,
+                ApiError::Console {
+                    status: http::StatusCode::TOO_MANY_REQUESTS,
+                    ..
+                } => ErrorCode::RateLimited,
+                ApiError::Console { .. } => ErrorCode::ControlPlaneError,
+                ApiError::Transport(_) => ErrorCode::ControlPlaneError,
+            }
+        }
     }
 
     impl ReportableError for ApiError {
@@ -192,6 +235,13 @@ pub mod errors {
                 ApiError(e) => e.to_string_client(),
             }
         }
+
+        fn get_error_code(&self) -> ErrorCode {
+            match self {
+                GetAuthInfoError::BadSecret => ErrorCode::ControlPlaneError,
+                GetAuthInfoError::ApiError(e) => e.get_error_code(),
+            }
+        }
     }
 
     impl ReportableError for GetAuthInfoError {
@@ -242,6 +292,15 @@ pub mod errors {
                 }
             }
         }
+
+        fn get_error_code(&self) -> ErrorCode {
+            match self {
+                WakeComputeError::BadComputeAddress(_) => ErrorCode::ControlPlaneError,
+                WakeComputeError::ApiError(e) => e.get_error_code(),
+                WakeComputeError::TooManyConnections => ErrorCode::RateLimited,
+                WakeComputeError::TooManyConnectionAttempts(_) => ErrorCode::RateLimited,
+            }
+        }
     }
 
     impl ReportableError for WakeComputeError {
@@ -290,6 +349,15 @@ pub struct NodeInfo {
 
     /// Whether we should accept self-signed certificates (for testing)
     pub allow_self_signed_compute: bool,
+
+    /// Host and port of each compute replica serving the endpoint, if there are several.
+    /// `config` points at the first one until the connection is routed, see
+    /// [`crate::proxy::sticky_routing`].
+    pub replicas: Vec<(String, u16)>,
+
+    /// The session policy of the endpoint from the control plane, if it has one, see
+    /// [`crate::session_policy`].
+    pub session_policy: Option<Arc<SessionPolicy>>,
 }
 
 impl NodeInfo {
@@ -307,6 +375,18 @@ impl NodeInfo {
             )
             .await
     }
+    /// Connect to the replica at `index` in `replicas`, keeping the credentials.
+    pub fn use_replica(&mut self, index: usize) {
+        let (host, port) = &self.replicas[index];
+        let mut config = compute::ConnCfg::new();
+        config
+            .host(host)
+            .port(*port)
+            .ssl_mode(self.config.get_ssl_mode());
+        let previous = std::mem::replace(&mut self.config, config);
+        self.config.reuse_password(previous);
+    }
+
     pub fn reuse_settings(&mut self, other: Self) {
         self.allow_self_signed_compute = other.allow_self_signed_compute;
         self.config.reuse_password(other.config);
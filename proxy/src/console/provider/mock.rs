@@ -127,6 +127,8 @@ impl Api {
                 cold_start_info: crate::console::messages::ColdStartInfo::Warm,
             },
             allow_self_signed_compute: false,
+            replicas: Vec::new(),
+            session_policy: None,
         };
 
         Ok(node)
@@ -10,6 +10,7 @@ use crate::{
     auth::backend::ComputeUserInfo,
     compute,
     console::messages::ColdStartInfo,
+    endpoint_metrics::ENDPOINT_METRICS,
     http,
     metrics::{CacheOutcome, Metrics},
     rate_limiter::EndpointRateLimiter,
@@ -55,6 +56,17 @@ impl Api {
         self.endpoint.url().as_str()
     }
 
+    /// Check that the console is reachable: any HTTP response will do.
+    pub async fn probe(&self) -> Result<http::StatusCode, http::Error> {
+        let request = self
+            .endpoint
+            .get("")
+            .header("Authorization", format!("Bearer {}", &self.jwt))
+            .build()?;
+        let response = self.endpoint.execute(request).await?;
+        Ok(response.status())
+    }
+
     async fn do_get_auth_info(
         &self,
         ctx: &mut RequestMonitoring,
@@ -172,10 +184,23 @@ impl Api {
             let mut config = compute::ConnCfg::new();
             config.host(host).port(port).ssl_mode(SslMode::Disable); // TLS is not configured on compute nodes.
 
+            let mut replicas = Vec::new();
+            if !body.replicas.is_empty() {
+                replicas.push((host.to_owned(), port));
+                for address in body.replicas.iter() {
+                    let Some((host, port)) = parse_host_port(address) else {
+                        return Err(WakeComputeError::BadComputeAddress(address.clone()));
+                    };
+                    replicas.push((host.to_owned(), port));
+                }
+            }
+
             let node = NodeInfo {
                 config,
                 aux: body.aux,
                 allow_self_signed_compute: false,
+                replicas,
+                session_policy: body.session_policy.map(Arc::new),
             };
 
             Ok(node)
@@ -301,7 +326,10 @@ impl super::Api for Api {
             }
         }
 
-        let mut node = self.do_wake_compute(ctx, user_info).await?;
+        let started_at = Instant::now();
+        let res = self.do_wake_compute(ctx, user_info).await;
+        ENDPOINT_METRICS.record_wake_compute((&user_info.endpoint).into(), started_at.elapsed());
+        let mut node = res?;
         ctx.set_project(node.aux.clone());
         let cold_start_info = node.aux.cold_start_info;
         info!("woken up a compute node");
@@ -13,6 +13,7 @@ use crate::{
     error::ErrorKind,
     intern::{BranchIdInt, ProjectIdInt},
     metrics::{ConnectOutcome, InvalidEndpointsGroup, LatencyTimer, Metrics, Protocol},
+    proxy::sticky_routing::RouteReason,
     DbName, EndpointId, RoleName,
 };
 
@@ -81,6 +82,7 @@ impl RequestMonitoring {
             %peer_addr,
             ep = tracing::field::Empty,
             role = tracing::field::Empty,
+            compute_route = tracing::field::Empty,
         );
 
         Self {
@@ -168,6 +170,14 @@ impl RequestMonitoring {
         self.user = Some(user);
     }
 
+    /// Record which compute replica the connection was routed to, and why.
+    pub fn set_compute_route(&mut self, replica: &str, reason: RouteReason) {
+        self.span.record(
+            "compute_route",
+            display(format_args!("{replica} ({})", reason.as_str())),
+        );
+    }
+
     pub fn set_auth_method(&mut self, auth_method: AuthMethod) {
         self.auth_method = Some(auth_method);
     }
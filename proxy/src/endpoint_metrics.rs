@@ -0,0 +1,354 @@
+//! Metrics of the endpoints, reported for the top endpoints only.
+//!
+//! Labelling the Prometheus metrics with the endpoints would make their cardinality unbounded,
+//! so [`ENDPOINT_METRICS`] keeps the metrics of the endpoints in memory instead, and the HTTP
+//! server reports the top `k` endpoints by one of them, e.g. `/v1/endpoints/top?by=bytes&k=10`:
+//!
+//! - `active_connections`: the TCP and websocket connections proxied to the endpoint, and the
+//!   pooled connections checked out by SQL-over-HTTP requests or transaction sessions.
+//! - `connections`: the TCP and websocket connections established to the endpoint, and the
+//!   checkouts of pooled connections by SQL-over-HTTP requests. A transaction session checks
+//!   its connection out once, for all of its requests.
+//! - `bytes`: the bytes proxied by the TCP and websocket connections, in both directions, and
+//!   the SQL-over-HTTP response bodies.
+//! - `auth_failures`: the failed authentications of the endpoint's roles.
+//! - `wake_compute_latency`: the slowest request to the control plane to wake the compute of
+//!   the endpoint, the cached ones aside.
+//!
+//! Except for the active connections, the metrics are counted in windows of
+//! `--endpoint-metrics-window`, and the report is of the last complete window. The endpoints
+//! without activity in a window are forgotten, and at most [`MAX_ENDPOINTS`] endpoints are
+//! tracked: the metrics of the others are dropped until some are forgotten.
+
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::time::MissedTickBehavior;
+
+use crate::intern::EndpointIdInt;
+
+/// The most endpoints tracked at once, see the module docs.
+pub const MAX_ENDPOINTS: usize = 100_000;
+
+pub static ENDPOINT_METRICS: Lazy<EndpointMetrics> = Lazy::new(EndpointMetrics::default);
+
+#[derive(Default)]
+pub struct EndpointMetrics {
+    endpoints: DashMap<EndpointIdInt, Arc<EndpointCounters>>,
+    /// The start and end of the last complete window.
+    last_window: Mutex<Option<(DateTime<Utc>, DateTime<Utc>)>>,
+}
+
+#[derive(Default)]
+struct EndpointCounters {
+    active_connections: AtomicU64,
+    current: WindowCounters,
+    last: Mutex<WindowStats>,
+}
+
+#[derive(Default)]
+struct WindowCounters {
+    connections: AtomicU64,
+    bytes: AtomicU64,
+    auth_failures: AtomicU64,
+    wake_computes: AtomicU64,
+    wake_compute_total_us: AtomicU64,
+    wake_compute_max_us: AtomicU64,
+}
+
+impl WindowCounters {
+    fn take(&self) -> WindowStats {
+        WindowStats {
+            connections: self.connections.swap(0, Ordering::Relaxed),
+            bytes: self.bytes.swap(0, Ordering::Relaxed),
+            auth_failures: self.auth_failures.swap(0, Ordering::Relaxed),
+            wake_computes: self.wake_computes.swap(0, Ordering::Relaxed),
+            wake_compute_total_us: self.wake_compute_total_us.swap(0, Ordering::Relaxed),
+            wake_compute_max_us: self.wake_compute_max_us.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct WindowStats {
+    connections: u64,
+    bytes: u64,
+    auth_failures: u64,
+    wake_computes: u64,
+    wake_compute_total_us: u64,
+    wake_compute_max_us: u64,
+}
+
+/// Counts a connection of an endpoint as active until it's dropped.
+pub struct EndpointConnectionGuard {
+    counters: Option<Arc<EndpointCounters>>,
+}
+
+impl EndpointConnectionGuard {
+    /// Record that the connection proxied some bytes.
+    pub fn record_bytes(&self, bytes: u64) {
+        if let Some(counters) = &self.counters {
+            counters.current.bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for EndpointConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(counters) = &self.counters {
+            counters.active_connections.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl EndpointMetrics {
+    /// Run `f` on the counters of `endpoint`, unless it can't be tracked. The entry of the
+    /// endpoint stays locked meanwhile, so that [`Self::rotate`] doesn't forget it.
+    fn with_counters<R>(
+        &self,
+        endpoint: EndpointIdInt,
+        f: impl FnOnce(&Arc<EndpointCounters>) -> R,
+    ) -> Option<R> {
+        if let Some(counters) = self.endpoints.get(&endpoint) {
+            return Some(f(&counters));
+        }
+        if self.endpoints.len() >= MAX_ENDPOINTS {
+            return None;
+        }
+        Some(f(&self.endpoints.entry(endpoint).or_default()))
+    }
+
+    /// Count a new connection of `endpoint`, as active until the guard is dropped.
+    pub fn connection(&self, endpoint: EndpointIdInt) -> EndpointConnectionGuard {
+        let counters = self.with_counters(endpoint, |counters| {
+            counters.active_connections.fetch_add(1, Ordering::Relaxed);
+            counters.current.connections.fetch_add(1, Ordering::Relaxed);
+            counters.clone()
+        });
+        EndpointConnectionGuard { counters }
+    }
+
+    pub fn record_auth_failure(&self, endpoint: EndpointIdInt) {
+        self.with_counters(endpoint, |counters| {
+            counters
+                .current
+                .auth_failures
+                .fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    pub fn record_wake_compute(&self, endpoint: EndpointIdInt, latency: Duration) {
+        let latency_us = latency.as_micros() as u64;
+        self.with_counters(endpoint, |counters| {
+            let current = &counters.current;
+            current.wake_computes.fetch_add(1, Ordering::Relaxed);
+            current
+                .wake_compute_total_us
+                .fetch_add(latency_us, Ordering::Relaxed);
+            current
+                .wake_compute_max_us
+                .fetch_max(latency_us, Ordering::Relaxed);
+        });
+    }
+
+    #[cfg(test)]
+    pub(crate) fn active_connections(&self, endpoint: EndpointIdInt) -> u64 {
+        self.endpoints.get(&endpoint).map_or(0, |counters| {
+            counters.active_connections.load(Ordering::Relaxed)
+        })
+    }
+
+    /// End the current window, which started at `start`, and forget the endpoints without
+    /// activity in it.
+    fn rotate(&self, start: DateTime<Utc>, end: DateTime<Utc>) {
+        self.endpoints.retain(|_, counters| {
+            let stats = counters.current.take();
+            *counters.last.lock().unwrap() = stats;
+            stats != WindowStats::default()
+                || counters.active_connections.load(Ordering::Relaxed) > 0
+        });
+        *self.last_window.lock().unwrap() = Some((start, end));
+    }
+
+    /// The top `k` endpoints by `by` in the last complete window.
+    pub fn top(&self, by: SortBy, k: usize) -> TopEndpoints {
+        let mut endpoints = self
+            .endpoints
+            .iter()
+            .map(|entry| {
+                let stats = *entry.last.lock().unwrap();
+                let avg_us = stats
+                    .wake_compute_total_us
+                    .checked_div(stats.wake_computes)
+                    .unwrap_or(0);
+                EndpointReport {
+                    endpoint_id: *entry.key(),
+                    active_connections: entry.active_connections.load(Ordering::Relaxed),
+                    connections: stats.connections,
+                    bytes: stats.bytes,
+                    auth_failures: stats.auth_failures,
+                    wake_computes: stats.wake_computes,
+                    wake_compute_avg_ms: avg_us as f64 / 1000.0,
+                    wake_compute_max_ms: stats.wake_compute_max_us as f64 / 1000.0,
+                }
+            })
+            .filter(|report| by.value(report) > 0.0)
+            .collect::<Vec<_>>();
+        endpoints.sort_by(|a, b| by.value(b).total_cmp(&by.value(a)));
+        endpoints.truncate(k);
+
+        let last_window = *self.last_window.lock().unwrap();
+        TopEndpoints {
+            window_start: last_window.map(|(start, _)| start.to_rfc3339()),
+            window_end: last_window.map(|(_, end)| end.to_rfc3339()),
+            by,
+            endpoints,
+        }
+    }
+}
+
+/// The metric the endpoints are ranked by, see the module docs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    ActiveConnections,
+    Connections,
+    #[default]
+    Bytes,
+    AuthFailures,
+    WakeComputeLatency,
+}
+
+impl SortBy {
+    fn value(self, report: &EndpointReport) -> f64 {
+        match self {
+            SortBy::ActiveConnections => report.active_connections as f64,
+            SortBy::Connections => report.connections as f64,
+            SortBy::Bytes => report.bytes as f64,
+            SortBy::AuthFailures => report.auth_failures as f64,
+            SortBy::WakeComputeLatency => report.wake_compute_max_ms,
+        }
+    }
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active_connections" => Ok(SortBy::ActiveConnections),
+            "connections" => Ok(SortBy::Connections),
+            "bytes" => Ok(SortBy::Bytes),
+            "auth_failures" => Ok(SortBy::AuthFailures),
+            "wake_compute_latency" => Ok(SortBy::WakeComputeLatency),
+            _ => Err(format!(
+                "unknown metric '{s}', expected active_connections, connections, bytes, \
+                 auth_failures or wake_compute_latency"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopEndpoints {
+    /// The last complete window, none before the first one ends.
+    window_start: Option<String>,
+    window_end: Option<String>,
+    by: SortBy,
+    endpoints: Vec<EndpointReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct EndpointReport {
+    endpoint_id: EndpointIdInt,
+    active_connections: u64,
+    connections: u64,
+    bytes: u64,
+    auth_failures: u64,
+    wake_computes: u64,
+    wake_compute_avg_ms: f64,
+    wake_compute_max_ms: f64,
+}
+
+/// End the windows of [`ENDPOINT_METRICS`] every `window`.
+pub async fn task_main(window: Duration) -> anyhow::Result<Infallible> {
+    let mut ticker = tokio::time::interval(window);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes immediately.
+    ticker.tick().await;
+    let mut start = Utc::now();
+    loop {
+        ticker.tick().await;
+        let end = Utc::now();
+        ENDPOINT_METRICS.rotate(start, end);
+        start = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EndpointId;
+
+    fn endpoint(id: &str) -> EndpointIdInt {
+        (&EndpointId::from(id)).into()
+    }
+
+    fn ranking(metrics: &EndpointMetrics, by: SortBy, k: usize) -> Vec<&'static str> {
+        metrics
+            .top(by, k)
+            .endpoints
+            .iter()
+            .map(|report| report.endpoint_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn top_endpoints() {
+        let metrics = EndpointMetrics::default();
+        let (foo, bar, baz) = (endpoint("ep-foo"), endpoint("ep-bar"), endpoint("ep-baz"));
+
+        let foo_conn = metrics.connection(foo);
+        foo_conn.record_bytes(100);
+        let bar_conn = metrics.connection(bar);
+        bar_conn.record_bytes(1000);
+        drop(bar_conn);
+        metrics.record_auth_failure(baz);
+        metrics.record_wake_compute(baz, Duration::from_millis(300));
+        metrics.record_wake_compute(foo, Duration::from_millis(100));
+        metrics.record_wake_compute(foo, Duration::from_millis(200));
+
+        // Only the active connections are reported before the window ends.
+        assert!(ranking(&metrics, SortBy::Bytes, 10).is_empty());
+        assert_eq!(ranking(&metrics, SortBy::ActiveConnections, 10), ["ep-foo"]);
+
+        let now = Utc::now();
+        metrics.rotate(now, now);
+        assert_eq!(ranking(&metrics, SortBy::Bytes, 10), ["ep-bar", "ep-foo"]);
+        assert_eq!(ranking(&metrics, SortBy::Bytes, 1), ["ep-bar"]);
+        assert_eq!(ranking(&metrics, SortBy::AuthFailures, 10), ["ep-baz"]);
+        assert_eq!(
+            ranking(&metrics, SortBy::WakeComputeLatency, 10),
+            ["ep-baz", "ep-foo"]
+        );
+        let top = metrics.top(SortBy::WakeComputeLatency, 10);
+        assert_eq!(top.endpoints[1].connections, 1);
+        assert_eq!(top.endpoints[1].wake_compute_avg_ms, 150.0);
+        assert_eq!(top.endpoints[1].wake_compute_max_ms, 200.0);
+
+        // The endpoints without activity are forgotten, but not those with active connections.
+        metrics.rotate(now, now);
+        assert!(ranking(&metrics, SortBy::Bytes, 10).is_empty());
+        assert_eq!(metrics.endpoints.len(), 1);
+        drop(foo_conn);
+        metrics.rotate(now, now);
+        assert!(metrics.endpoints.is_empty());
+    }
+}
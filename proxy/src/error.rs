@@ -1,6 +1,7 @@
 use std::{error::Error as StdError, fmt, io};
 
 use measured::FixedCardinalityLabel;
+use tokio_postgres::error::SqlState;
 
 /// Upcast (almost) any error into an opaque [`io::Error`].
 pub fn io_error(e: impl Into<Box<dyn StdError + Send + Sync>>) -> io::Error {
@@ -29,6 +30,137 @@ pub trait UserFacingError: ReportableError {
     fn to_string_client(&self) -> String {
         self.to_string()
     }
+
+    /// The code of the error for the client, see [`ErrorCode`].
+    fn get_error_code(&self) -> ErrorCode {
+        self.get_error_kind().into()
+    }
+}
+
+/// The stable codes of the errors returned to clients, so that drivers can tell them apart, and
+/// decide whether to retry, without parsing the messages.
+///
+/// Postgres clients get the code in the DETAIL of the error, as `neon_error_code: <code>`, with
+/// the SQLSTATE of [`ErrorCode::sqlstate`]. The errors of SQL over HTTP have it in their
+/// `neonErrorCode` field. The codes and their SQLSTATE are part of the API of the proxy: they
+/// must not be renamed or remapped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The password is wrong.
+    AuthFailed,
+    /// The client violated the authentication protocol, or used an unsupported method.
+    AuthProtocol,
+    /// The client didn't authenticate in time. Retryable.
+    AuthTimeout,
+    /// The IP address or the role isn't allowed to connect to the endpoint.
+    AccessDenied,
+    /// The connection parameters or the request are invalid.
+    BadRequest,
+    /// The endpoint doesn't exist.
+    EndpointNotFound,
+    /// The endpoint is disabled.
+    EndpointDisabled,
+    /// A quota of the project is exceeded.
+    QuotaExceeded,
+    /// The compute of the endpoint is starting or in transition. Retryable.
+    ComputeStarting,
+    /// The compute of the endpoint can't be reached. Retryable.
+    ComputeUnavailable,
+    /// The control plane failed or can't be reached. Retryable.
+    ControlPlaneError,
+    /// A rate limit of the proxy was hit. Retryable, with a backoff.
+    RateLimited,
+    /// The request or its response exceeds a limit of the proxy.
+    LimitExceeded,
+    /// The query was cancelled.
+    QueryCancelled,
+    /// An error of Postgres, whose SQLSTATE decides whether to retry.
+    Postgres(SqlState),
+    /// An internal error of the proxy.
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::AuthFailed => "AUTH_FAILED",
+            ErrorCode::AuthProtocol => "AUTH_PROTOCOL",
+            ErrorCode::AuthTimeout => "AUTH_TIMEOUT",
+            ErrorCode::AccessDenied => "ACCESS_DENIED",
+            ErrorCode::BadRequest => "BAD_REQUEST",
+            ErrorCode::EndpointNotFound => "ENDPOINT_NOT_FOUND",
+            ErrorCode::EndpointDisabled => "ENDPOINT_DISABLED",
+            ErrorCode::QuotaExceeded => "QUOTA_EXCEEDED",
+            ErrorCode::ComputeStarting => "COMPUTE_STARTING",
+            ErrorCode::ComputeUnavailable => "COMPUTE_UNAVAILABLE",
+            ErrorCode::ControlPlaneError => "CONTROL_PLANE_ERROR",
+            ErrorCode::RateLimited => "RATE_LIMITED",
+            ErrorCode::LimitExceeded => "LIMIT_EXCEEDED",
+            ErrorCode::QueryCancelled => "QUERY_CANCELLED",
+            ErrorCode::Postgres(_) => "POSTGRES",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+
+    /// The SQLSTATE of the error: the closest standard one, so that drivers that only know
+    /// about those still behave sensibly.
+    pub fn sqlstate(&self) -> &str {
+        match self {
+            // invalid_password
+            ErrorCode::AuthFailed => "28P01",
+            // invalid_authorization_specification
+            ErrorCode::AuthProtocol | ErrorCode::AccessDenied => "28000",
+            // connection_failure
+            ErrorCode::AuthTimeout => "08006",
+            // protocol_violation
+            ErrorCode::BadRequest => "08P01",
+            // sqlserver_rejected_establishment_of_sqlconnection
+            ErrorCode::EndpointNotFound | ErrorCode::EndpointDisabled => "08004",
+            // configuration_limit_exceeded
+            ErrorCode::QuotaExceeded => "53400",
+            // cannot_connect_now
+            ErrorCode::ComputeStarting => "57P03",
+            // sqlclient_unable_to_establish_sqlconnection
+            ErrorCode::ComputeUnavailable | ErrorCode::ControlPlaneError => "08001",
+            // too_many_connections
+            ErrorCode::RateLimited => "53300",
+            // program_limit_exceeded
+            ErrorCode::LimitExceeded => "54000",
+            // query_canceled
+            ErrorCode::QueryCancelled => "57014",
+            ErrorCode::Postgres(sqlstate) => sqlstate.code(),
+            // internal_error
+            ErrorCode::Internal => "XX000",
+        }
+    }
+
+    /// The code of an error of a connection to a compute.
+    pub fn from_postgres(e: &tokio_postgres::Error) -> Self {
+        match e.as_db_error() {
+            Some(db_error) => ErrorCode::Postgres(db_error.code().clone()),
+            None => ErrorCode::ComputeUnavailable,
+        }
+    }
+
+    /// The DETAIL of the Postgres errors with the code, see [`ErrorCode`].
+    pub fn detail(&self) -> String {
+        format!("neon_error_code: {}", self.as_str())
+    }
+}
+
+impl From<ErrorKind> for ErrorCode {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::User => ErrorCode::BadRequest,
+            ErrorKind::ClientDisconnect => ErrorCode::Internal,
+            ErrorKind::RateLimit | ErrorKind::ServiceRateLimit => ErrorCode::RateLimited,
+            ErrorKind::Service => ErrorCode::Internal,
+            ErrorKind::ControlPlane => ErrorCode::ControlPlaneError,
+            // The errors of Postgres have their own code.
+            ErrorKind::Postgres => ErrorCode::Internal,
+            ErrorKind::Compute => ErrorCode::ComputeUnavailable,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, FixedCardinalityLabel)]
@@ -2,6 +2,7 @@
 //! Other modules should use stuff from this module instead of
 //! directly relying on deps like `reqwest` (think loose coupling).
 
+pub mod health_checks;
 pub mod health_server;
 
 use std::{str::FromStr, sync::Arc, time::Duration};
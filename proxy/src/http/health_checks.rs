@@ -0,0 +1,321 @@
+//! Checks of the dependencies of the proxy, for the `/healthz` and `/readyz` endpoints of the
+//! http server.
+//!
+//! Load balancers poll these to stop sending traffic to a proxy that can't serve it. Each
+//! dependency is a component with a [`ComponentStatus`]:
+//! - `tls`: certificates are loaded, and none of them has expired.
+//! - `control_plane`: the console answers http requests. The result is kept for
+//!   [`CONTROL_PLANE_CHECK_INTERVAL`], so that frequent polling doesn't add load on the console.
+//! - `connection_pool`: the connection pool of the serverless backend has room for more
+//!   connections. A full pool only degrades the proxy: connections are still served, just not
+//!   pooled.
+//!
+//! `/healthz` only checks the connection pool, `/readyz` runs all the checks. Both respond with
+//! `503 Service Unavailable` if a component is failing. Kubernetes restarts a proxy whose
+//! liveness probe fails, which doesn't help with a dependency: with an expired certificate, or
+//! with the console down, the proxy is only taken out of the load balancer until it recovers.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::auth;
+use crate::config::{CertResolver, ProxyConfig};
+use crate::console::provider::{neon, ConsoleBackend};
+use crate::serverless::ConnPoolHandle;
+
+/// How long the result of the control plane check is kept.
+pub const CONTROL_PLANE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How long the control plane has to answer.
+const CONTROL_PLANE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    /// The component isn't used by this proxy.
+    Disabled,
+    Ok,
+    /// The component works, with reduced capacity.
+    Degraded,
+    Failing,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ComponentHealth {
+    pub status: ComponentStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl ComponentHealth {
+    fn new(status: ComponentStatus) -> Self {
+        Self {
+            status,
+            message: None,
+        }
+    }
+
+    fn with_message(status: ComponentStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: Some(message.into()),
+        }
+    }
+}
+
+/// The response of `/healthz` and `/readyz`.
+#[derive(Debug, Serialize)]
+pub struct Health {
+    /// The worst status of the components.
+    pub status: ComponentStatus,
+    pub components: BTreeMap<&'static str, ComponentHealth>,
+}
+
+impl Health {
+    fn new(components: BTreeMap<&'static str, ComponentHealth>) -> Self {
+        let status = components
+            .values()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(ComponentStatus::Ok)
+            .max(ComponentStatus::Ok);
+        Self { status, components }
+    }
+
+    pub fn is_failing(&self) -> bool {
+        self.status == ComponentStatus::Failing
+    }
+}
+
+pub struct HealthChecks {
+    /// `None` without TLS.
+    cert_resolver: Option<Arc<CertResolver>>,
+    conn_pool: ConnPoolHandle,
+    /// `None` without a console.
+    console: Option<&'static neon::Api>,
+    control_plane: Mutex<Option<(Instant, ComponentHealth)>>,
+}
+
+impl HealthChecks {
+    pub fn new(config: &'static ProxyConfig, conn_pool: ConnPoolHandle) -> Self {
+        let console = match &config.auth_backend {
+            auth::BackendType::Console(api, _) => match &**api {
+                ConsoleBackend::Console(api) => Some(api),
+                #[cfg(any(test, feature = "testing"))]
+                ConsoleBackend::Postgres(_) => None,
+                #[cfg(test)]
+                ConsoleBackend::Test(_) => None,
+            },
+            auth::BackendType::Link(..) => None,
+        };
+        Self {
+            cert_resolver: config
+                .tls_config
+                .as_ref()
+                .map(|tls_config| tls_config.cert_resolver.clone()),
+            conn_pool,
+            console,
+            control_plane: Mutex::new(None),
+        }
+    }
+
+    /// The checks whose failure a restart of the proxy can fix.
+    pub fn liveness(&self) -> Health {
+        Health::new(BTreeMap::from([(
+            "connection_pool",
+            self.check_conn_pool(),
+        )]))
+    }
+
+    /// All the checks.
+    pub async fn readiness(&self) -> Health {
+        let mut health = self.liveness();
+        health.components.insert("tls", self.check_tls());
+        health
+            .components
+            .insert("control_plane", self.check_control_plane().await);
+        Health::new(health.components)
+    }
+
+    fn check_tls(&self) -> ComponentHealth {
+        let Some(cert_resolver) = &self.cert_resolver else {
+            return ComponentHealth::new(ComponentStatus::Disabled);
+        };
+        let mut certs = 0;
+        for (common_name, cert) in cert_resolver.certs() {
+            certs += 1;
+            let Some(end_entity) = cert.cert.first() else {
+                return ComponentHealth::with_message(
+                    ComponentStatus::Failing,
+                    format!("empty certificate chain for {common_name}"),
+                );
+            };
+            match x509_parser::parse_x509_certificate(end_entity) {
+                Ok((_, parsed)) if parsed.validity().is_valid() => {}
+                Ok((_, parsed)) => {
+                    return ComponentHealth::with_message(
+                        ComponentStatus::Failing,
+                        format!(
+                            "certificate for {common_name} is not valid now, it is valid from {} to {}",
+                            parsed.validity().not_before,
+                            parsed.validity().not_after
+                        ),
+                    );
+                }
+                Err(e) => {
+                    return ComponentHealth::with_message(
+                        ComponentStatus::Failing,
+                        format!("failed to parse certificate for {common_name}: {e}"),
+                    );
+                }
+            }
+        }
+        if certs == 0 {
+            return ComponentHealth::with_message(
+                ComponentStatus::Failing,
+                "no certificates loaded",
+            );
+        }
+        ComponentHealth::new(ComponentStatus::Ok)
+    }
+
+    fn check_conn_pool(&self) -> ComponentHealth {
+        match self.conn_pool.usage() {
+            None => ComponentHealth::new(ComponentStatus::Disabled),
+            Some((connections, max)) if connections >= max => ComponentHealth::with_message(
+                ComponentStatus::Degraded,
+                format!("pool is full with {connections} connections"),
+            ),
+            Some(_) => ComponentHealth::new(ComponentStatus::Ok),
+        }
+    }
+
+    async fn check_control_plane(&self) -> ComponentHealth {
+        let Some(api) = self.console else {
+            return ComponentHealth::new(ComponentStatus::Disabled);
+        };
+
+        // Concurrent checks wait for the one in progress, rather than sending their own.
+        let mut cached = self.control_plane.lock().await;
+        if let Some((checked_at, health)) = &*cached {
+            if checked_at.elapsed() < CONTROL_PLANE_CHECK_INTERVAL {
+                return health.clone();
+            }
+        }
+        let health = match tokio::time::timeout(CONTROL_PLANE_CHECK_TIMEOUT, api.probe()).await {
+            Ok(Ok(_)) => ComponentHealth::new(ComponentStatus::Ok),
+            Ok(Err(e)) => ComponentHealth::with_message(
+                ComponentStatus::Failing,
+                format!("request to {} failed: {e}", api.url()),
+            ),
+            Err(_) => ComponentHealth::with_message(
+                ComponentStatus::Failing,
+                format!(
+                    "request to {} timed out after {CONTROL_PLANE_CHECK_TIMEOUT:?}",
+                    api.url()
+                ),
+            ),
+        };
+        *cached = Some((Instant::now(), health.clone()));
+        health
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    use super::*;
+
+    fn expired_cert_resolver() -> CertResolver {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".into()]);
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "localhost");
+        params.not_before = rcgen::date_time_ymd(2000, 1, 1);
+        params.not_after = rcgen::date_time_ymd(2001, 1, 1);
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+
+        let mut cert_resolver = CertResolver::new();
+        cert_resolver
+            .add_cert(
+                PrivateKeyDer::Pkcs8(cert.serialize_private_key_der().into()),
+                vec![CertificateDer::from(cert.serialize_der().unwrap())],
+                true,
+            )
+            .unwrap();
+        cert_resolver
+    }
+
+    #[tokio::test]
+    async fn expired_certificate_only_fails_readiness() {
+        let checks = HealthChecks {
+            cert_resolver: Some(Arc::new(expired_cert_resolver())),
+            conn_pool: ConnPoolHandle::default(),
+            console: None,
+            control_plane: Mutex::new(None),
+        };
+
+        // A restart wouldn't renew the certificate.
+        let liveness = checks.liveness();
+        assert!(!liveness.is_failing());
+        assert!(!liveness.components.contains_key("tls"));
+
+        let readiness = checks.readiness().await;
+        assert!(readiness.is_failing());
+        assert_eq!(readiness.components["tls"].status, ComponentStatus::Failing);
+        assert_eq!(
+            readiness.components["control_plane"].status,
+            ComponentStatus::Disabled
+        );
+    }
+
+    #[test]
+    fn worst_component_status() {
+        let health = Health::new(BTreeMap::new());
+        assert_eq!(health.status, ComponentStatus::Ok);
+
+        // Disabled components don't count.
+        let health = Health::new(BTreeMap::from([(
+            "tls",
+            ComponentHealth::new(ComponentStatus::Disabled),
+        )]));
+        assert_eq!(health.status, ComponentStatus::Ok);
+
+        let health = Health::new(BTreeMap::from([
+            ("tls", ComponentHealth::new(ComponentStatus::Ok)),
+            (
+                "connection_pool",
+                ComponentHealth::with_message(ComponentStatus::Degraded, "full"),
+            ),
+        ]));
+        assert_eq!(health.status, ComponentStatus::Degraded);
+        assert!(!health.is_failing());
+
+        let health = Health::new(BTreeMap::from([
+            (
+                "connection_pool",
+                ComponentHealth::with_message(ComponentStatus::Degraded, "full"),
+            ),
+            (
+                "control_plane",
+                ComponentHealth::with_message(ComponentStatus::Failing, "timed out"),
+            ),
+        ]));
+        assert!(health.is_failing());
+        assert_eq!(
+            serde_json::to_value(&health).unwrap(),
+            serde_json::json!({
+                "status": "failing",
+                "components": {
+                    "connection_pool": {"status": "degraded", "message": "full"},
+                    "control_plane": {"status": "failing", "message": "timed out"},
+                },
+            })
+        );
+    }
+}
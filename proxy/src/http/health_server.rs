@@ -12,20 +12,59 @@ use utils::http::{
     endpoint::{self, request_span},
     error::ApiError,
     json::json_response,
+    request::parse_query_param,
     RouterBuilder, RouterService,
 };
 
+use super::health_checks::{Health, HealthChecks};
+use crate::endpoint_metrics::{SortBy, ENDPOINT_METRICS};
 use crate::jemalloc;
 
+const DEFAULT_TOP_ENDPOINTS: usize = 10;
+
 async fn status_handler(_: Request<Body>) -> Result<Response<Body>, ApiError> {
     json_response(StatusCode::OK, "")
 }
 
-fn make_router(metrics: AppMetrics) -> RouterBuilder<hyper::Body, ApiError> {
+fn health_response(health: Health) -> Result<Response<Body>, ApiError> {
+    let status = if health.is_failing() {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    json_response(status, health)
+}
+
+async fn healthz_handler(
+    _: Request<Body>,
+    checks: Arc<HealthChecks>,
+) -> Result<Response<Body>, ApiError> {
+    health_response(checks.liveness())
+}
+
+async fn readyz_handler(
+    _: Request<Body>,
+    checks: Arc<HealthChecks>,
+) -> Result<Response<Body>, ApiError> {
+    health_response(checks.readiness().await)
+}
+
+/// The top endpoints by one of their metrics, see [`crate::endpoint_metrics`].
+async fn top_endpoints_handler(req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let by: SortBy = parse_query_param(&req, "by")?.unwrap_or_default();
+    let k = parse_query_param(&req, "k")?.unwrap_or(DEFAULT_TOP_ENDPOINTS);
+    json_response(StatusCode::OK, ENDPOINT_METRICS.top(by, k))
+}
+
+fn make_router(
+    metrics: AppMetrics,
+    checks: Arc<HealthChecks>,
+) -> RouterBuilder<hyper::Body, ApiError> {
     let state = Arc::new(Mutex::new(PrometheusHandler {
         encoder: BufferedTextEncoder::new(),
         metrics,
     }));
+    let readyz_checks = checks.clone();
 
     endpoint::make_router()
         .get("/metrics", move |r| {
@@ -33,17 +72,22 @@ fn make_router(metrics: AppMetrics) -> RouterBuilder<hyper::Body, ApiError> {
             request_span(r, move |b| prometheus_metrics_handler(b, state))
         })
         .get("/v1/status", status_handler)
+        .get("/v1/endpoints/top", top_endpoints_handler)
+        .get("/healthz", move |r| healthz_handler(r, checks.clone()))
+        .get("/readyz", move |r| readyz_handler(r, readyz_checks.clone()))
 }
 
 pub async fn task_main(
     http_listener: TcpListener,
     metrics: AppMetrics,
+    checks: HealthChecks,
 ) -> anyhow::Result<Infallible> {
     scopeguard::defer! {
         info!("http has shut down");
     }
 
-    let service = || RouterService::new(make_router(metrics).build()?);
+    let checks = Arc::new(checks);
+    let service = || RouterService::new(make_router(metrics, checks).build()?);
 
     hyper::Server::from_tcp(http_listener)?
         .serve(service().map_err(|e| anyhow!(e))?)
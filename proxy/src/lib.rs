@@ -10,10 +10,12 @@ use tracing::warn;
 pub mod auth;
 pub mod cache;
 pub mod cancellation;
+pub mod certs;
 pub mod compute;
 pub mod config;
 pub mod console;
 pub mod context;
+pub mod endpoint_metrics;
 pub mod error;
 pub mod http;
 pub mod intern;
@@ -28,6 +30,7 @@ pub mod redis;
 pub mod sasl;
 pub mod scram;
 pub mod serverless;
+pub mod session_policy;
 pub mod stream;
 pub mod url;
 pub mod usage_metrics;
@@ -127,7 +130,7 @@ macro_rules! smol_str_wrapper {
     };
 }
 
-const POOLER_SUFFIX: &str = "-pooler";
+pub(crate) const POOLER_SUFFIX: &str = "-pooler";
 
 pub trait Normalize {
     fn normalize(&self) -> Self;
@@ -67,6 +67,12 @@ pub struct ProxyMetrics {
     #[metric(metadata = Thresholds::exponential_buckets(16.0, 4.0))]
     pub http_conn_content_length_bytes: HistogramVec<StaticLabelSet<HttpDirection>, 12>,
 
+    /// Size of the HTTP response body lengths (per output format).
+    // smallest bucket = 16 bytes
+    // largest bucket = 4^12 * 16 bytes = 256MB
+    #[metric(metadata = Thresholds::exponential_buckets(16.0, 4.0))]
+    pub http_response_format_bytes: HistogramVec<StaticLabelSet<HttpResponseFormat>, 12>,
+
     /// Time it takes to reclaim unused connection pools.
     #[metric(metadata = Thresholds::exponential_buckets(1e-6, 2.0))]
     pub http_pool_reclaimation_lag_seconds: Histogram<16>,
@@ -74,6 +80,15 @@ pub struct ProxyMetrics {
     /// Number of opened connections to a database.
     pub http_pool_opened_connections: Gauge,
 
+    /// Number of transaction sessions of SQL over HTTP.
+    pub http_transaction_sessions: Gauge,
+
+    /// Number of transaction sessions closed after being idle without commit or rollback.
+    pub http_transaction_sessions_leaked_total: Counter,
+
+    /// Number of lookups in the cache of responses of read-only queries (per outcome).
+    pub http_result_cache_lookups: CounterVec<StaticLabelSet<CacheOutcome>>,
+
     /// Number of cache hits/misses for allowed ips.
     pub allowed_ips_cache_misses: CounterVec<StaticLabelSet<CacheOutcome>>,
 
@@ -165,6 +180,15 @@ pub enum HttpDirection {
     Response,
 }
 
+#[derive(FixedCardinalityLabel, Copy, Clone)]
+#[label(singleton = "format")]
+pub enum HttpResponseFormat {
+    Json,
+    CompactJson,
+    PgBinary,
+    ArrowIpc,
+}
+
 #[derive(FixedCardinalityLabel, Copy, Clone)]
 #[label(singleton = "direction")]
 pub enum Direction {
@@ -6,6 +6,8 @@ mod copy_bidirectional;
 pub mod handshake;
 pub mod passthrough;
 pub mod retry;
+pub mod sticky_routing;
+pub mod tls_passthrough;
 pub mod wake_compute;
 pub use copy_bidirectional::copy_bidirectional_client_compute;
 
@@ -218,6 +220,8 @@ pub enum ClientRequestError {
     PrepareClient(#[from] std::io::Error),
     #[error("{0}")]
     ReportedError(#[from] crate::stream::ReportedError),
+    #[error("{0}")]
+    Passthrough(#[from] tls_passthrough::PassthroughError),
 }
 
 impl ReportableError for ClientRequestError {
@@ -228,6 +232,7 @@ impl ReportableError for ClientRequestError {
             ClientRequestError::HandshakeTimeout(_) => crate::error::ErrorKind::RateLimit,
             ClientRequestError::ReportedError(e) => e.get_error_kind(),
             ClientRequestError::PrepareClient(_) => crate::error::ErrorKind::ClientDisconnect,
+            ClientRequestError::Passthrough(_) => crate::error::ErrorKind::Compute,
         }
     }
 }
@@ -254,7 +259,12 @@ pub async fn handle_client<S: AsyncRead + AsyncWrite + Unpin>(
 
     let record_handshake_error = !ctx.has_private_peer_addr();
     let pause = ctx.latency_timer.pause(crate::metrics::Waiting::Client);
-    let do_handshake = handshake(stream, mode.handshake_tls(tls), record_handshake_error);
+    let do_handshake = handshake(
+        stream,
+        mode.handshake_tls(tls),
+        &config.tls_passthrough,
+        record_handshake_error,
+    );
     let (mut stream, params) =
         match tokio::time::timeout(config.handshake_timeout, do_handshake).await?? {
             HandshakeData::Startup(stream, params) => (stream, params),
@@ -264,6 +274,15 @@ pub async fn handle_client<S: AsyncRead + AsyncWrite + Unpin>(
                     .await
                     .map(|()| None)?)
             }
+            HandshakeData::Passthrough {
+                stream,
+                client_hello,
+                destination,
+            } => {
+                drop(pause);
+                tls_passthrough::proxy_pass(stream, client_hello, destination).await?;
+                return Ok(None);
+            }
         };
     drop(pause);
 
@@ -309,6 +328,8 @@ pub async fn handle_client<S: AsyncRead + AsyncWrite + Unpin>(
         &TcpMechanism {
             params: &params,
             locks: &config.connect_compute_locks,
+            routes: &config.compute_routes,
+            session_policies: &config.session_policies,
         },
         &user_info,
         mode.allow_self_signed_compute(config),
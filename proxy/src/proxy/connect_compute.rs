@@ -8,8 +8,10 @@ use crate::{
     metrics::{ConnectOutcome, ConnectionFailureKind, Metrics, RetriesMetricGroup, RetryType},
     proxy::{
         retry::{retry_after, ShouldRetry},
+        sticky_routing::StickyRoutes,
         wake_compute::wake_compute,
     },
+    session_policy::{self, RoleNotAllowed, SessionPolicies},
     Host,
 };
 use async_trait::async_trait;
@@ -50,6 +52,14 @@ pub trait ConnectMechanism {
     ) -> Result<Self::Connection, Self::ConnectError>;
 
     fn update_connect_config(&self, conf: &mut compute::ConnCfg);
+
+    /// Pick the compute replica to connect to, if the endpoint has several.
+    fn route(&self, _ctx: &mut RequestMonitoring, _node_info: &mut NodeInfo) {}
+
+    /// The session policies of the proxy config, see [`crate::session_policy`].
+    fn session_policies(&self) -> Option<&SessionPolicies> {
+        None
+    }
 }
 
 #[async_trait]
@@ -60,6 +70,9 @@ pub trait ComputeConnectBackend {
     ) -> Result<CachedNodeInfo, console::errors::WakeComputeError>;
 
     fn get_keys(&self) -> Option<&ComputeCredentialKeys>;
+
+    /// Whether the client connects to a `-pooler` endpoint, see [`crate::session_policy`].
+    fn is_pooler(&self) -> bool;
 }
 
 pub struct TcpMechanism<'a> {
@@ -68,6 +81,12 @@ pub struct TcpMechanism<'a> {
 
     /// connect_to_compute concurrency lock
     pub locks: &'static ApiLocks<Host>,
+
+    /// Routes to the compute replicas of endpoints
+    pub routes: &'static StickyRoutes,
+
+    /// Session policies of endpoints
+    pub session_policies: &'static SessionPolicies,
 }
 
 #[async_trait]
@@ -91,6 +110,18 @@ impl ConnectMechanism for TcpMechanism<'_> {
     fn update_connect_config(&self, config: &mut compute::ConnCfg) {
         config.set_startup_params(self.params);
     }
+
+    fn route(&self, ctx: &mut RequestMonitoring, node_info: &mut NodeInfo) {
+        if let Some(user) = self.params.get("user") {
+            // Postgres defaults the database to the user name.
+            let dbname = self.params.get("database").unwrap_or(user);
+            self.routes.route(ctx, node_info, user, dbname);
+        }
+    }
+
+    fn session_policies(&self) -> Option<&SessionPolicies> {
+        Some(self.session_policies)
+    }
 }
 
 /// Try to connect to the compute node, retrying if necessary.
@@ -105,7 +136,7 @@ pub async fn connect_to_compute<M: ConnectMechanism, B: ComputeConnectBackend>(
 ) -> Result<M::Connection, M::Error>
 where
     M::ConnectError: ShouldRetry + std::fmt::Debug,
-    M::Error: From<WakeComputeError>,
+    M::Error: From<WakeComputeError> + From<RoleNotAllowed>,
 {
     let mut num_retries = 0;
     let mut node_info =
@@ -115,7 +146,13 @@ where
     }
     node_info.allow_self_signed_compute = allow_self_signed_compute;
     // let mut node_info = credentials.get_node_info(ctx, user_info).await?;
+    mechanism.route(ctx, &mut node_info);
     mechanism.update_connect_config(&mut node_info.config);
+    session_policy::apply(
+        mechanism.session_policies(),
+        &mut node_info,
+        user_info.is_pooler(),
+    )?;
     let retry_type = RetryType::ConnectToCompute;
 
     // try once
@@ -161,7 +198,13 @@ where
             wake_compute(&mut num_retries, ctx, user_info, wake_compute_retry_config).await?;
         node_info.reuse_settings(old_node_info);
 
+        mechanism.route(ctx, &mut node_info);
         mechanism.update_connect_config(&mut node_info.config);
+        session_policy::apply(
+            mechanism.session_policies(),
+            &mut node_info,
+            user_info.is_pooler(),
+        )?;
         node_info
     };
 
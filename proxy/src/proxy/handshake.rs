@@ -1,12 +1,13 @@
+use bytes::BytesMut;
 use pq_proto::{BeMessage as Be, CancelKeyData, FeStartupPacket, StartupMessageParams};
 use thiserror::Error;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::info;
 
 use crate::{
-    config::TlsConfig,
+    config::{TlsConfig, TlsPassthroughConfig},
     error::ReportableError,
-    proxy::ERR_INSECURE_CONNECTION,
+    proxy::{tls_passthrough, ERR_INSECURE_CONNECTION},
     stream::{PqStream, Stream, StreamUpgradeError},
 };
 
@@ -21,6 +22,9 @@ pub enum HandshakeError {
     #[error("missing certificate")]
     MissingCertificate,
 
+    #[error("{0}")]
+    InvalidPassthroughDestination(anyhow::Error),
+
     #[error("{0}")]
     StreamUpgradeError(#[from] StreamUpgradeError),
 
@@ -40,6 +44,7 @@ impl ReportableError for HandshakeError {
             // the client sends no SNI extension.
             // If they provide SNI then we can be sure there is a certificate that matches.
             HandshakeError::MissingCertificate => crate::error::ErrorKind::Service,
+            HandshakeError::InvalidPassthroughDestination(_) => crate::error::ErrorKind::User,
             HandshakeError::StreamUpgradeError(upgrade) => match upgrade {
                 StreamUpgradeError::AlreadyTls => crate::error::ErrorKind::Service,
                 StreamUpgradeError::Io(_) => crate::error::ErrorKind::ClientDisconnect,
@@ -53,6 +58,12 @@ impl ReportableError for HandshakeError {
 pub enum HandshakeData<S> {
     Startup(PqStream<Stream<S>>, StartupMessageParams),
     Cancel(CancelKeyData),
+    /// The client's TLS session must be passed through to the destination as is.
+    Passthrough {
+        stream: S,
+        client_hello: BytesMut,
+        destination: String,
+    },
 }
 
 /// Establish a (most probably, secure) connection with the client.
@@ -63,6 +74,7 @@ pub enum HandshakeData<S> {
 pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
     stream: S,
     mut tls: Option<&TlsConfig>,
+    passthrough: &TlsPassthroughConfig,
     record_handshake_error: bool,
 ) -> Result<HandshakeData<S>, HandshakeError> {
     // Client may try upgrading to each protocol only once
@@ -86,7 +98,7 @@ pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
                         // Upgrade raw stream into a secure TLS-backed stream.
                         // NOTE: We've consumed `tls`; this fact will be used later.
 
-                        let (raw, read_buf) = stream.into_inner();
+                        let (mut raw, read_buf) = stream.into_inner();
                         // TODO: Normally, client doesn't send any data before
                         // server says TLS handshake is ok and read_buf is empy.
                         // However, you could imagine pipelining of postgres
@@ -96,14 +108,40 @@ pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
                         if !read_buf.is_empty() {
                             return Err(HandshakeError::EarlyData);
                         }
+
+                        // Peek at the SNI to see if we should terminate TLS at all.
+                        let client_hello = if passthrough.is_enabled() {
+                            tls_passthrough::read_client_hello(&mut raw).await?
+                        } else {
+                            BytesMut::new()
+                        };
+                        let route = tls_passthrough::parse_sni(&client_hello)
+                            .and_then(|sni| passthrough.route(sni));
+                        if let Some(destination) = route {
+                            let destination = destination
+                                .map_err(HandshakeError::InvalidPassthroughDestination)?;
+                            let Stream::Raw { raw } = raw else {
+                                return Err(HandshakeError::ProtocolViolation);
+                            };
+                            info!(session_type = "passthrough", "successful handshake");
+                            return Ok(HandshakeData::Passthrough {
+                                stream: raw,
+                                client_hello,
+                                destination,
+                            });
+                        }
+
+                        let (server_config, served) = tls.to_connection_server_config();
                         let tls_stream = raw
-                            .upgrade(tls.to_server_config(), record_handshake_error)
+                            .upgrade_with_client_hello(
+                                server_config,
+                                record_handshake_error,
+                                &client_hello,
+                            )
                             .await?;
 
-                        let (_, tls_server_end_point) = tls
-                            .cert_resolver
-                            .resolve(tls_stream.get_ref().1.server_name())
-                            .ok_or(HandshakeError::MissingCertificate)?;
+                        let tls_server_end_point =
+                            *served.get().ok_or(HandshakeError::MissingCertificate)?;
 
                         stream = PqStream::new(Stream::Tls {
                             tls: Box::new(tls_stream),
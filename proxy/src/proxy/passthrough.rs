@@ -2,6 +2,7 @@ use crate::{
     cancellation,
     compute::PostgresConnection,
     console::messages::MetricsAuxInfo,
+    endpoint_metrics::ENDPOINT_METRICS,
     metrics::{Direction, Metrics, NumClientConnectionsGuard, NumConnectionRequestsGuard},
     stream::Stream,
     usage_metrics::{Ids, MetricCounterRecorder, USAGE_METRICS},
@@ -21,6 +22,7 @@ pub async fn proxy_pass(
         endpoint_id: aux.endpoint_id,
         branch_id: aux.branch_id,
     });
+    let endpoint_conn = ENDPOINT_METRICS.connection(aux.endpoint_id);
 
     let metrics = &Metrics::get().proxy.io_bytes;
     let m_sent = metrics.with_labels(Direction::Tx);
@@ -31,6 +33,7 @@ pub async fn proxy_pass(
             // Number of bytes we sent to the client (outbound).
             metrics.get_metric(m_sent).inc_by(cnt as u64);
             usage.record_egress(cnt as u64);
+            endpoint_conn.record_bytes(cnt as u64);
         },
     );
 
@@ -41,6 +44,7 @@ pub async fn proxy_pass(
         |cnt| {
             // Number of bytes the client sent to the compute node (inbound).
             metrics.get_metric(m_recv).inc_by(cnt as u64);
+            endpoint_conn.record_bytes(cnt as u64);
         },
     );
 
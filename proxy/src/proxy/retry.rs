@@ -88,6 +88,7 @@ impl ShouldRetry for compute::ConnectionError {
             compute::ConnectionError::CouldNotConnect(err) => err.should_retry_database_address(),
             // the cache entry was not checked for validity
             compute::ConnectionError::TooManyConnectionAttempts(_) => false,
+            compute::ConnectionError::RoleNotAllowed(_) => false,
             _ => true,
         }
     }
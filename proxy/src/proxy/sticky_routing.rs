@@ -0,0 +1,219 @@
+//! Sticky routing of new connections to the compute replicas of an endpoint.
+//!
+//! When several compute replicas serve an endpoint, the console returns all their addresses
+//! from `wake_compute`. A new connection goes to the replica most recently used for the same
+//! endpoint, user and database, if that was within the affinity TTL: its caches are likely
+//! warm for the workload. Otherwise the replica is picked by rendezvous hashing of the
+//! endpoint, user and database with the replica addresses, so that all proxies pick the same
+//! one, and a change of the set of replicas only moves the connections of the replicas that
+//! went away.
+//!
+//! The routing table is in memory, and the decisions are recorded in the `compute_route`
+//! field of the connection span.
+
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::{
+    console::NodeInfo, context::RequestMonitoring, intern::EndpointIdInt, DbName, RoleName,
+};
+
+/// Why a connection was routed to a replica.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteReason {
+    /// The replica last used for the endpoint, user and database.
+    Warm,
+    /// The replica picked by hashing.
+    Hashed,
+}
+
+impl RouteReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteReason::Warm => "warm",
+            RouteReason::Hashed => "hashed",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct RouteKey {
+    endpoint: EndpointIdInt,
+    user: RoleName,
+    dbname: DbName,
+}
+
+struct Route {
+    replica: String,
+    last_used: Instant,
+}
+
+/// The routing table: the replica last used for each endpoint, user and database.
+pub struct StickyRoutes {
+    routes: DashMap<RouteKey, Route>,
+    /// Zero disables the affinity: connections are only routed by hashing.
+    affinity_ttl: Duration,
+}
+
+impl StickyRoutes {
+    pub fn new(affinity_ttl: Duration) -> Self {
+        Self {
+            routes: DashMap::new(),
+            affinity_ttl,
+        }
+    }
+
+    /// Point `node_info` at the replica a connection of `user` to `dbname` should use, if its
+    /// endpoint has several.
+    pub fn route(
+        &self,
+        ctx: &mut RequestMonitoring,
+        node_info: &mut NodeInfo,
+        user: &str,
+        dbname: &str,
+    ) {
+        if node_info.replicas.len() < 2 {
+            return;
+        }
+        let key = RouteKey {
+            endpoint: node_info.aux.endpoint_id,
+            user: user.into(),
+            dbname: dbname.into(),
+        };
+        let addresses = node_info
+            .replicas
+            .iter()
+            .map(|(host, port)| format!("{host}:{port}"))
+            .collect::<Vec<_>>();
+        let (index, reason) = self.pick(Instant::now(), key, &addresses);
+
+        info!(
+            replica = %addresses[index],
+            reason = reason.as_str(),
+            replicas = addresses.len(),
+            "routed connection to compute replica"
+        );
+        ctx.set_compute_route(&addresses[index], reason);
+        node_info.use_replica(index);
+    }
+
+    fn pick(&self, now: Instant, key: RouteKey, addresses: &[String]) -> (usize, RouteReason) {
+        let warm = self.routes.get(&key).and_then(|route| {
+            if now.duration_since(route.last_used) >= self.affinity_ttl {
+                return None;
+            }
+            addresses.iter().position(|a| *a == route.replica)
+        });
+        let (index, reason) = match warm {
+            Some(index) => (index, RouteReason::Warm),
+            None => (rendezvous(&key, addresses), RouteReason::Hashed),
+        };
+        self.routes.insert(
+            key,
+            Route {
+                replica: addresses[index].clone(),
+                last_used: now,
+            },
+        );
+        (index, reason)
+    }
+
+    /// Forget the routes that are too old to be warm.
+    pub async fn garbage_collect_worker(&self) {
+        let period = self.affinity_ttl.max(Duration::from_secs(60));
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            self.routes
+                .retain(|_, route| now.duration_since(route.last_used) < self.affinity_ttl);
+        }
+    }
+}
+
+/// The index of the address with the highest hash with `key`.
+fn rendezvous(key: &RouteKey, addresses: &[String]) -> usize {
+    let weight = |address: &String| {
+        let mut hasher = Sha256::new();
+        for part in [
+            key.endpoint.as_str(),
+            key.user.as_str(),
+            key.dbname.as_str(),
+            address.as_str(),
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let digest = hasher.finalize();
+        u64::from_be_bytes(digest[..8].try_into().unwrap())
+    };
+    addresses
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, address)| weight(address))
+        .map(|(index, _)| index)
+        .expect("routing among no replicas")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EndpointId;
+
+    fn key(user: &str) -> RouteKey {
+        RouteKey {
+            endpoint: (&EndpointId::from("endpoint")).into(),
+            user: user.into(),
+            dbname: "neondb".into(),
+        }
+    }
+
+    #[test]
+    fn sticky_routing() {
+        let routes = StickyRoutes::new(Duration::from_secs(60));
+        let addresses = ["a:5432", "b:5432", "c:5432"].map(String::from).to_vec();
+        let start = Instant::now();
+
+        // The first connection is routed by hashing, consistently.
+        let (hashed, reason) = routes.pick(start, key("alice"), &addresses);
+        assert_eq!(reason, RouteReason::Hashed);
+        assert_eq!(hashed, rendezvous(&key("alice"), &addresses));
+
+        // A replica that goes away only moves its own connections.
+        let mut fewer = addresses.clone();
+        let removed = (hashed + 1) % addresses.len();
+        fewer.remove(removed);
+        assert_eq!(
+            fewer[rendezvous(&key("alice"), &fewer)],
+            addresses[hashed],
+            "route moved when another replica went away"
+        );
+
+        // The next connections stick to the replica while it's warm, even if the replicas are
+        // listed in another order.
+        let mut reordered = addresses.clone();
+        reordered.reverse();
+        let now = start + Duration::from_secs(30);
+        let (index, reason) = routes.pick(now, key("alice"), &reordered);
+        assert_eq!(reason, RouteReason::Warm);
+        assert_eq!(reordered[index], addresses[hashed]);
+
+        // Once the replica is gone, the connection is routed by hashing again.
+        let remaining = addresses
+            .iter()
+            .filter(|a| **a != addresses[hashed])
+            .cloned()
+            .collect::<Vec<_>>();
+        let (index, reason) = routes.pick(now, key("alice"), &remaining);
+        assert_eq!(reason, RouteReason::Hashed);
+        assert_eq!(index, rendezvous(&key("alice"), &remaining));
+
+        // Or once it's cold.
+        let later = now + Duration::from_secs(120);
+        let (_, reason) = routes.pick(later, key("alice"), &remaining);
+        assert_eq!(reason, RouteReason::Hashed);
+    }
+}
@@ -10,7 +10,7 @@ use super::*;
 use crate::auth::backend::{
     ComputeCredentialKeys, ComputeCredentials, ComputeUserInfo, MaybeOwned, TestBackend,
 };
-use crate::config::{CertResolver, RetryConfig};
+use crate::config::{CertResolver, RetryConfig, TlsPassthroughConfig};
 use crate::console::caches::NodeInfoCache;
 use crate::console::messages::MetricsAuxInfo;
 use crate::console::provider::{CachedAllowedIps, CachedRoleSecret, ConsoleBackend};
@@ -23,6 +23,7 @@ use async_trait::async_trait;
 use rstest::rstest;
 use rustls::pki_types;
 use tokio_postgres::config::SslMode;
+use tokio_postgres::error::SqlState;
 use tokio_postgres::tls::{MakeTlsConnect, NoTls};
 use tokio_postgres_rustls::{MakeRustlsConnect, RustlsStream};
 
@@ -175,9 +176,17 @@ async fn dummy_proxy(
     auth: impl TestAuth + Send,
 ) -> anyhow::Result<()> {
     let (client, _) = read_proxy_protocol(client).await?;
-    let mut stream = match handshake(client, tls.as_ref(), false).await? {
+    let mut stream = match handshake(
+        client,
+        tls.as_ref(),
+        &TlsPassthroughConfig::default(),
+        false,
+    )
+    .await?
+    {
         HandshakeData::Startup(stream, _) => stream,
         HandshakeData::Cancel(_) => bail!("cancellation not supported"),
+        HandshakeData::Passthrough { .. } => bail!("passthrough not supported"),
     };
 
     auth.authenticate(&mut stream).await?;
@@ -207,6 +216,11 @@ async fn handshake_tls_is_enforced_by_proxy() -> anyhow::Result<()> {
         .context("client shouldn't be able to connect")?;
 
     assert!(client_err.to_string().contains(ERR_INSECURE_CONNECTION));
+    let db_error = client_err
+        .as_db_error()
+        .context("expected an error response")?;
+    assert_eq!(db_error.code(), &SqlState::PROTOCOL_VIOLATION);
+    assert_eq!(db_error.detail(), Some("neon_error_code: BAD_REQUEST"));
 
     let server_err = proxy
         .await?
@@ -253,6 +267,49 @@ async fn handshake_raw() -> anyhow::Result<()> {
     proxy.await?
 }
 
+#[tokio::test]
+async fn handshake_tls_passthrough() -> anyhow::Result<()> {
+    use tokio::net::TcpListener;
+
+    // The destination is a postgres server with its own certificate. The SNI routes to it:
+    // `127--0--{port}.ext.test` goes to `127.0.0.1:{port}` with the `ext.test=0.1` route.
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let sni = format!("127--0--{}.ext.test", listener.local_addr()?.port());
+    let (client_config, compute_config) = generate_tls_config(&sni, "compute")?;
+    let compute = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        dummy_proxy(stream, Some(compute_config), NoAuth).await
+    });
+
+    let (client, server) = tokio::io::duplex(1024);
+    let (_, proxy_config) = generate_tls_config("generic-project-name.localhost", "localhost")?;
+    let passthrough = TlsPassthroughConfig::parse("ext.test=0.1")?;
+    let proxy = tokio::spawn(async move {
+        match handshake(client, Some(&proxy_config), &passthrough, false).await? {
+            HandshakeData::Passthrough {
+                stream,
+                client_hello,
+                destination,
+            } => tls_passthrough::proxy_pass(stream, client_hello, destination).await?,
+            _ => bail!("expected passthrough"),
+        }
+        anyhow::Ok(())
+    });
+
+    // The client only trusts the certificate of the destination, so TLS must be established
+    // with the destination and not terminated by the proxy.
+    let (client, conn) = tokio_postgres::Config::new()
+        .user("john_doe")
+        .dbname("earth")
+        .ssl_mode(SslMode::Require)
+        .connect_raw(server, client_config.make_tls_connect()?)
+        .await?;
+    compute.await??;
+
+    drop((client, conn));
+    proxy.await?
+}
+
 #[tokio::test]
 async fn keepalive_is_inherited() -> anyhow::Result<()> {
     use tokio::net::{TcpListener, TcpStream};
@@ -524,6 +581,8 @@ fn helper_create_cached_node_info(cache: &'static NodeInfoCache) -> CachedNodeIn
             cold_start_info: crate::console::messages::ColdStartInfo::Warm,
         },
         allow_self_signed_compute: false,
+        replicas: Vec::new(),
+        session_policy: None,
     };
     let (_, node) = cache.insert("key".into(), node);
     node
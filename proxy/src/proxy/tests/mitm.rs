@@ -34,12 +34,18 @@ async fn proxy_mitm(
     tokio::spawn(async move {
         // begin handshake with end_server
         let end_server = connect_tls(server2, client_config2.make_tls_connect().unwrap()).await;
-        let (end_client, startup) = match handshake(client1, Some(&server_config1), false)
-            .await
-            .unwrap()
+        let (end_client, startup) = match handshake(
+            client1,
+            Some(&server_config1),
+            &TlsPassthroughConfig::default(),
+            false,
+        )
+        .await
+        .unwrap()
         {
             HandshakeData::Startup(stream, params) => (stream, params),
             HandshakeData::Cancel(_) => panic!("cancellation not supported"),
+            HandshakeData::Passthrough { .. } => panic!("passthrough not supported"),
         };
 
         let mut end_server = tokio_util::codec::Framed::new(end_server, PgFrame);
@@ -0,0 +1,271 @@
+//! Routing of TLS connections by SNI without terminating TLS.
+//!
+//! See [`crate::config::TlsPassthroughConfig`].
+
+use std::time::Duration;
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::info;
+
+use crate::proxy::copy_bidirectional_client_compute;
+
+/// TLS record content type of handshake messages.
+const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+/// TLS handshake message type of ClientHello.
+const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 1;
+/// TLS extension type of server_name (SNI).
+const EXTENSION_SERVER_NAME: u16 = 0;
+/// server_name entry type of a DNS hostname.
+const SERVER_NAME_HOST_NAME: u8 = 0;
+
+/// Size of a TLS record header.
+const RECORD_HEADER_LEN: usize = 5;
+/// Maximum size of a TLS record payload, including the allowed expansion.
+const MAX_RECORD_LEN: usize = 16384 + 2048;
+
+/// SSLRequest code, see <https://www.postgresql.org/docs/current/protocol-message-formats.html>.
+const SSL_REQUEST_CODE: u32 = (1234 << 16) | 5679;
+
+/// Timeout for connecting to the destination and negotiating TLS with it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, thiserror::Error)]
+pub enum PassthroughError {
+    #[error("destination {0} doesn't support TLS")]
+    TlsNotSupported(String),
+    #[error("timed out connecting to destination {0}")]
+    ConnectTimeout(String),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Read the first TLS record sent by the client. It's expected to contain the ClientHello.
+///
+/// The record is returned as is, so that it can be either forwarded to the destination,
+/// or fed into our own TLS acceptor.
+pub async fn read_client_hello<S: AsyncRead + Unpin>(stream: &mut S) -> std::io::Result<BytesMut> {
+    let mut buf = BytesMut::zeroed(RECORD_HEADER_LEN);
+    stream.read_exact(&mut buf).await?;
+
+    // Not a TLS handshake. Let the TLS acceptor report the error.
+    if buf[0] != CONTENT_TYPE_HANDSHAKE {
+        return Ok(buf);
+    }
+
+    let len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+    if len > MAX_RECORD_LEN {
+        return Ok(buf);
+    }
+
+    buf.resize(RECORD_HEADER_LEN + len, 0);
+    stream.read_exact(&mut buf[RECORD_HEADER_LEN..]).await?;
+    Ok(buf)
+}
+
+/// Extract the SNI hostname from a TLS record with ClientHello.
+///
+/// Returns `None` if the record is not a ClientHello, is malformed,
+/// or the ClientHello doesn't fit into a single record.
+pub fn parse_sni(record: &[u8]) -> Option<&str> {
+    fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        if buf.len() < n {
+            return None;
+        }
+        let (head, tail) = buf.split_at(n);
+        *buf = tail;
+        Some(head)
+    }
+    fn take_u8(buf: &mut &[u8]) -> Option<u8> {
+        take(buf, 1).map(|b| b[0])
+    }
+    fn take_u16(buf: &mut &[u8]) -> Option<u16> {
+        take(buf, 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+    fn take_u24(buf: &mut &[u8]) -> Option<usize> {
+        take(buf, 3).map(|b| u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    }
+    fn take_vec8<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = take_u8(buf)? as usize;
+        take(buf, len)
+    }
+    fn take_vec16<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = take_u16(buf)? as usize;
+        take(buf, len)
+    }
+
+    let mut buf = record;
+
+    // record header
+    if take_u8(&mut buf)? != CONTENT_TYPE_HANDSHAKE {
+        return None;
+    }
+    let _legacy_record_version = take_u16(&mut buf)?;
+    let mut fragment = take_vec16(&mut buf)?;
+
+    // handshake header
+    if take_u8(&mut fragment)? != HANDSHAKE_TYPE_CLIENT_HELLO {
+        return None;
+    }
+    let len = take_u24(&mut fragment)?;
+    let mut hello = take(&mut fragment, len)?;
+
+    // ClientHello body
+    let _legacy_version = take_u16(&mut hello)?;
+    let _random = take(&mut hello, 32)?;
+    let _legacy_session_id = take_vec8(&mut hello)?;
+    let _cipher_suites = take_vec16(&mut hello)?;
+    let _legacy_compression_methods = take_vec8(&mut hello)?;
+    let mut extensions = take_vec16(&mut hello)?;
+
+    while !extensions.is_empty() {
+        let extension_type = take_u16(&mut extensions)?;
+        let mut extension_data = take_vec16(&mut extensions)?;
+        if extension_type != EXTENSION_SERVER_NAME {
+            continue;
+        }
+
+        let mut server_names = take_vec16(&mut extension_data)?;
+        while !server_names.is_empty() {
+            let name_type = take_u8(&mut server_names)?;
+            let name = take_vec16(&mut server_names)?;
+            if name_type == SERVER_NAME_HOST_NAME {
+                return std::str::from_utf8(name).ok();
+            }
+        }
+        return None;
+    }
+
+    None
+}
+
+/// Forward the client's TLS session to the destination.
+///
+/// The destination must be a postgres server: we negotiate TLS with it on behalf of the client
+/// and then pass the client's ClientHello and all the following traffic as is.
+pub async fn proxy_pass(
+    mut client: impl AsyncRead + AsyncWrite + Unpin,
+    client_hello: BytesMut,
+    destination: String,
+) -> Result<(), PassthroughError> {
+    info!(%destination, "passing TLS connection through");
+
+    let mut compute = tokio::time::timeout(CONNECT_TIMEOUT, connect(&destination))
+        .await
+        .map_err(|_| PassthroughError::ConnectTimeout(destination.clone()))??;
+
+    compute.write_all(&client_hello).await?;
+
+    // Starting from here we only proxy the client's traffic.
+    info!("performing the proxy pass...");
+    let _ = copy_bidirectional_client_compute(&mut client, &mut compute).await?;
+
+    Ok(())
+}
+
+/// Connect to the destination and ask it for TLS.
+async fn connect(destination: &str) -> Result<TcpStream, PassthroughError> {
+    let mut compute = TcpStream::connect(destination).await?;
+    compute.set_nodelay(true)?;
+
+    let mut ssl_request = BytesMut::new();
+    ssl_request.extend_from_slice(&8u32.to_be_bytes());
+    ssl_request.extend_from_slice(&SSL_REQUEST_CODE.to_be_bytes());
+    compute.write_all(&ssl_request).await?;
+
+    // 'S' means the server is willing to perform the TLS handshake.
+    if compute.read_u8().await? != b'S' {
+        return Err(PassthroughError::TlsNotSupported(destination.to_owned()));
+    }
+
+    Ok(compute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a TLS record with a minimal ClientHello.
+    fn client_hello(sni: Option<&str>) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        // some other extension first: supported_versions
+        extensions.extend_from_slice(&[0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04]);
+        if let Some(sni) = sni {
+            let name = sni.as_bytes();
+            let list_len = 1 + 2 + name.len();
+            extensions.extend_from_slice(&EXTENSION_SERVER_NAME.to_be_bytes());
+            extensions.extend_from_slice(&((list_len + 2) as u16).to_be_bytes());
+            extensions.extend_from_slice(&(list_len as u16).to_be_bytes());
+            extensions.push(SERVER_NAME_HOST_NAME);
+            extensions.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            extensions.extend_from_slice(name);
+        }
+
+        let mut hello = Vec::new();
+        hello.extend_from_slice(&[0x03, 0x03]);
+        hello.extend_from_slice(&[0x42; 32]);
+        hello.extend_from_slice(&[0x00]);
+        hello.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        hello.extend_from_slice(&[0x01, 0x00]);
+        hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        hello.extend_from_slice(&extensions);
+
+        let mut handshake = vec![HANDSHAKE_TYPE_CLIENT_HELLO];
+        handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&hello);
+
+        let mut record = vec![CONTENT_TYPE_HANDSHAKE, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    #[test]
+    fn parse_client_hello_sni() {
+        let record = client_hello(Some("ep-foo.ext.domain"));
+        assert_eq!(parse_sni(&record), Some("ep-foo.ext.domain"));
+
+        let record = client_hello(None);
+        assert_eq!(parse_sni(&record), None);
+
+        // truncated records must not be accepted
+        let record = client_hello(Some("ep-foo.ext.domain"));
+        for len in 0..record.len() {
+            assert_eq!(parse_sni(&record[..len]), None);
+        }
+
+        // not a handshake
+        assert_eq!(parse_sni(&[23, 3, 3, 0, 0]), None);
+    }
+
+    #[tokio::test]
+    async fn read_whole_record() {
+        let mut record = client_hello(Some("ep-foo.ext.domain"));
+        let expected = record.clone();
+        // data following the record must not be consumed
+        record.extend_from_slice(b"trailing");
+
+        let mut stream = &record[..];
+        let read = read_client_hello(&mut stream).await.unwrap();
+        assert_eq!(&read[..], &expected[..]);
+        assert_eq!(stream, b"trailing");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn destination_not_responding() {
+        // The listener never accepts, so the destination never answers the SSLRequest.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let destination = listener.local_addr().unwrap().to_string();
+
+        let (client, _peer) = tokio::io::duplex(1024);
+        let hello = BytesMut::from(&client_hello(Some("ep-foo.ext.domain"))[..]);
+        let err = proxy_pass(client, hello, destination.clone())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, PassthroughError::ConnectTimeout(d) if *d == destination),
+            "{err}"
+        );
+    }
+}
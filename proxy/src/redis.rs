@@ -1,3 +1,4 @@
+pub mod cancel_key_registry;
 pub mod cancellation_publisher;
 pub mod connection_with_credentials_provider;
 pub mod elasticache;
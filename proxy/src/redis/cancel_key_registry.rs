@@ -0,0 +1,186 @@
+//! The shared registry of cancellation keys: which region owns the session of each key, so
+//! that a cancel request received by any proxy instance is routed to it.
+//!
+//! The sessions register and unregister their keys without waiting for redis: the changes
+//! are sent to [`task_main`], which writes them in pipelines of up to [`MAX_BATCH`] commands
+//! on a connection of its own.
+
+use std::{collections::HashMap, convert::Infallible, time::Duration};
+
+use pq_proto::CancelKeyData;
+use tokio::sync::mpsc;
+
+use super::connection_with_credentials_provider::ConnectionWithCredentialsProvider;
+
+/// Prefix of the redis keys used by the shared cancellation registry.
+const CANCEL_KEY_PREFIX: &str = "cancel_key";
+/// How long a registered cancellation key stays in the shared registry.
+///
+/// Sessions unregister their keys once the connection is closed, the TTL
+/// only protects the registry from leaking keys of crashed proxy instances.
+const CANCEL_KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// The most changes written to redis in one pipeline.
+const MAX_BATCH: usize = 1024;
+
+/// Redis key under which the owner of `cancel_key_data` is stored.
+pub(super) fn registry_key(cancel_key_data: CancelKeyData) -> String {
+    format!(
+        "{CANCEL_KEY_PREFIX}:{:08x}{:08x}",
+        cancel_key_data.backend_pid, cancel_key_data.cancel_key
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistryOp {
+    Register(CancelKeyData),
+    Unregister(CancelKeyData),
+}
+
+/// Handle to send the changes of the registry to [`task_main`].
+#[derive(Clone)]
+pub struct CancelKeyRegistry {
+    tx: mpsc::UnboundedSender<RegistryOp>,
+}
+
+impl CancelKeyRegistry {
+    /// The registry, and the receiving end of its changes for [`task_main`].
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<RegistryOp>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx }, rx)
+    }
+
+    /// Record that `cancel_key_data` is owned by this region.
+    pub fn register(&self, cancel_key_data: CancelKeyData) {
+        self.send(RegistryOp::Register(cancel_key_data));
+    }
+
+    pub fn unregister(&self, cancel_key_data: CancelKeyData) {
+        self.send(RegistryOp::Unregister(cancel_key_data));
+    }
+
+    fn send(&self, op: RegistryOp) {
+        if self.tx.send(op).is_err() {
+            tracing::warn!("cancellation key registry is closed, dropping {op:?}");
+        }
+    }
+}
+
+/// Write the changes of the registry to redis, in batches.
+///
+/// A batch that can't be written even after reconnecting is dropped: the cancel requests of
+/// its keys then go to the region of the proxy receiving them, like for unregistered keys, and
+/// the keys it failed to unregister expire after [`CANCEL_KEY_TTL`].
+pub async fn task_main(
+    mut client: ConnectionWithCredentialsProvider,
+    region_id: String,
+    mut rx: mpsc::UnboundedReceiver<RegistryOp>,
+) -> anyhow::Result<Infallible> {
+    let mut ops = Vec::with_capacity(MAX_BATCH);
+    loop {
+        if rx.recv_many(&mut ops, MAX_BATCH).await == 0 {
+            anyhow::bail!("cancellation key registry is closed");
+        }
+        let batch = coalesce(ops.drain(..));
+        if batch.is_empty() {
+            continue;
+        }
+        let pipe = pipeline(&batch, &region_id);
+        if let Err(e) = pipe.query_async::<_, ()>(&mut client).await {
+            tracing::error!("failed to write cancellation keys: {e}");
+            tracing::info!("Registry is disconnected. Reconnecting...");
+            let res = match client.connect().await {
+                Ok(()) => pipe
+                    .query_async::<_, ()>(&mut client)
+                    .await
+                    .map_err(anyhow::Error::from),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                tracing::error!("dropping {} cancellation key changes: {e}", batch.len());
+            }
+        }
+    }
+}
+
+/// The changes to write for `ops`: the last one of each key, and none for the keys registered
+/// and unregistered in `ops`, which were not in redis before.
+fn coalesce(ops: impl IntoIterator<Item = RegistryOp>) -> Vec<RegistryOp> {
+    // Whether the first change of the key was to unregister it, and its last change.
+    let mut changes = HashMap::<CancelKeyData, (bool, RegistryOp)>::new();
+    for op in ops {
+        let (RegistryOp::Register(key) | RegistryOp::Unregister(key)) = op;
+        changes
+            .entry(key)
+            .and_modify(|(_, last)| *last = op)
+            .or_insert((matches!(op, RegistryOp::Unregister(_)), op));
+    }
+    changes
+        .into_values()
+        .filter_map(|(was_registered, last)| match last {
+            RegistryOp::Register(_) => Some(last),
+            RegistryOp::Unregister(_) => was_registered.then_some(last),
+        })
+        .collect()
+}
+
+fn pipeline(ops: &[RegistryOp], region_id: &str) -> redis::Pipeline {
+    let mut pipe = redis::pipe();
+    for op in ops {
+        match *op {
+            RegistryOp::Register(key) => {
+                pipe.set_ex(registry_key(key), region_id, CANCEL_KEY_TTL.as_secs())
+                    .ignore();
+            }
+            RegistryOp::Unregister(key) => {
+                pipe.del(registry_key(key)).ignore();
+            }
+        }
+    }
+    pipe
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(n: i32) -> CancelKeyData {
+        CancelKeyData {
+            backend_pid: n,
+            cancel_key: n,
+        }
+    }
+
+    #[test]
+    fn registry_key_format() {
+        let key = CancelKeyData {
+            backend_pid: 42,
+            cancel_key: -1,
+        };
+        // The format is shared between proxy instances of different versions,
+        // so it must not change.
+        assert_eq!(registry_key(key), "cancel_key:0000002affffffff");
+    }
+
+    #[test]
+    fn coalesce_batch() {
+        use RegistryOp::*;
+        let mut batch = coalesce([
+            // A short session, both changes are skipped.
+            Register(key(1)),
+            Unregister(key(1)),
+            Register(key(2)),
+            // Registered in a previous batch.
+            Unregister(key(3)),
+            Unregister(key(4)),
+            Register(key(4)),
+            Unregister(key(4)),
+        ]);
+        batch.sort_by_key(|op| match op {
+            Register(key) | Unregister(key) => key.backend_pid,
+        });
+        assert_eq!(
+            batch,
+            [Register(key(2)), Unregister(key(3)), Unregister(key(4))]
+        );
+    }
+}
@@ -8,6 +8,7 @@ use uuid::Uuid;
 use crate::rate_limiter::{GlobalRateLimiter, RateBucketInfo};
 
 use super::{
+    cancel_key_registry::registry_key,
     connection_with_credentials_provider::ConnectionWithCredentialsProvider,
     notifications::{CancelSession, Notification, PROXY_CHANNEL_NAME},
 };
@@ -101,14 +102,29 @@ impl RedisPublisherClient {
         cancel_key_data: CancelKeyData,
         session_id: Uuid,
     ) -> anyhow::Result<()> {
+        // Route the message to the region which owns the session. If the key is
+        // not in the shared registry, assume that it belongs to our region.
+        let region_id = match self.lookup(cancel_key_data).await {
+            Ok(Some(region_id)) => region_id,
+            Ok(None) => self.region_id.clone(),
+            Err(e) => {
+                tracing::warn!("failed to look up cancellation key owner: {e}");
+                self.region_id.clone()
+            }
+        };
         let payload = serde_json::to_string(&Notification::Cancel(CancelSession {
-            region_id: Some(self.region_id.clone()),
+            region_id: Some(region_id),
             cancel_key_data,
             session_id,
         }))?;
         self.client.publish(PROXY_CHANNEL_NAME, payload).await?;
         Ok(())
     }
+    /// Find out which region owns `cancel_key_data`, if it was registered at all.
+    async fn lookup(&mut self, cancel_key_data: CancelKeyData) -> anyhow::Result<Option<String>> {
+        let region_id: Option<String> = self.client.get(registry_key(cancel_key_data)).await?;
+        Ok(region_id)
+    }
     pub async fn try_connect(&mut self) -> anyhow::Result<()> {
         match self.client.connect().await {
             Ok(()) => {}
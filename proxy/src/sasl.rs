@@ -10,7 +10,7 @@ mod channel_binding;
 mod messages;
 mod stream;
 
-use crate::error::{ReportableError, UserFacingError};
+use crate::error::{ErrorCode, ReportableError, UserFacingError};
 use std::io;
 use thiserror::Error;
 
@@ -49,6 +49,17 @@ impl UserFacingError for Error {
             _ => "authentication protocol violation".to_string(),
         }
     }
+
+    fn get_error_code(&self) -> ErrorCode {
+        match self {
+            Error::ChannelBindingFailed(_) => ErrorCode::AuthProtocol,
+            Error::ChannelBindingBadMethod(_) => ErrorCode::AuthProtocol,
+            Error::BadClientMessage(_) => ErrorCode::AuthProtocol,
+            Error::MissingBinding => ErrorCode::Internal,
+            Error::Base64(_) => ErrorCode::Internal,
+            Error::Io(_) => ErrorCode::Internal,
+        }
+    }
 }
 
 impl ReportableError for Error {
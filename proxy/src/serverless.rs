@@ -7,6 +7,10 @@ pub mod cancel_set;
 mod conn_pool;
 mod http_util;
 mod json;
+mod params;
+pub mod pool_admin;
+mod result_cache;
+mod result_format;
 mod sql_over_http;
 mod websocket;
 
@@ -31,6 +35,7 @@ use tokio_rustls::TlsAcceptor;
 use tokio_util::task::TaskTracker;
 
 use crate::cancellation::CancellationHandlerMain;
+use crate::certs::acme::ACME_TLS_ALPN;
 use crate::config::ProxyConfig;
 use crate::context::RequestMonitoring;
 use crate::metrics::Metrics;
@@ -39,10 +44,11 @@ use crate::proxy::run_until_cancelled;
 use crate::rate_limiter::EndpointRateLimiter;
 use crate::serverless::backend::PoolingBackend;
 use crate::serverless::http_util::{api_error_into_response, json_response};
+use crate::serverless::result_cache::ResultCache;
 
 use std::net::{IpAddr, SocketAddr};
 use std::pin::pin;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use tokio::net::{TcpListener, TcpStream};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn, Instrument};
@@ -50,12 +56,30 @@ use utils::http::error::ApiError;
 
 pub const SERVERLESS_DRIVER_SNI: &str = "api";
 
+/// A handle on the connection pool of the serverless backend, for the health checks and the
+/// admin console.
+#[derive(Clone, Default)]
+pub struct ConnPoolHandle(Arc<OnceLock<Arc<conn_pool::GlobalConnPool<tokio_postgres::Client>>>>);
+
+impl ConnPoolHandle {
+    /// The number of pooled connections and the maximum, or `None` if the serverless backend
+    /// isn't running.
+    pub fn usage(&self) -> Option<(usize, usize)> {
+        let pool = self.0.get()?;
+        Some((
+            pool.get_global_connections_count(),
+            pool.get_max_total_conns(),
+        ))
+    }
+}
+
 pub async fn task_main(
     config: &'static ProxyConfig,
     ws_listener: TcpListener,
     cancellation_token: CancellationToken,
     cancellation_handler: Arc<CancellationHandlerMain>,
     endpoint_rate_limiter: Arc<EndpointRateLimiter>,
+    conn_pool_handle: ConnPoolHandle,
 ) -> anyhow::Result<()> {
     scopeguard::defer! {
         info!("websocket server has shut down");
@@ -68,6 +92,12 @@ pub async fn task_main(
             conn_pool.gc_worker(StdRng::from_entropy()).await;
         });
     }
+    {
+        let conn_pool = Arc::clone(&conn_pool);
+        tokio::spawn(async move {
+            conn_pool.transactions.gc_worker().await;
+        });
+    }
 
     // shutdown the connection pool
     tokio::spawn({
@@ -83,6 +113,7 @@ pub async fn task_main(
 
     let backend = Arc::new(PoolingBackend {
         pool: Arc::clone(&conn_pool),
+        result_cache: ResultCache::new(&config.http_config.result_cache),
         config,
         endpoint_rate_limiter: Arc::clone(&endpoint_rate_limiter),
     });
@@ -94,9 +125,11 @@ pub async fn task_main(
             return Ok(());
         }
     };
+    let _ = conn_pool_handle.0.set(Arc::clone(&conn_pool));
     let mut tls_server_config = rustls::ServerConfig::clone(&tls_config.to_server_config());
-    // prefer http2, but support http/1.1
-    tls_server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    // prefer http2, but support http/1.1, and the validation of the ACME challenges
+    tls_server_config.alpn_protocols =
+        vec![b"h2".to_vec(), b"http/1.1".to_vec(), ACME_TLS_ALPN.to_vec()];
     let tls_acceptor: tokio_rustls::TlsAcceptor = Arc::new(tls_server_config).into();
 
     let connections = tokio_util::task::task_tracker::TaskTracker::new();
@@ -198,6 +231,11 @@ async fn connection_handler(
 
     // try upgrade to TLS, but with a timeout.
     let conn = match timeout(config.handshake_timeout, tls_acceptor.accept(conn)).await {
+        // The ACME server only validates the challenge certificate of the handshake.
+        Ok(Ok(conn)) if conn.get_ref().1.alpn_protocol() == Some(ACME_TLS_ALPN) => {
+            info!(?session_id, %peer_addr, "answered ACME challenge");
+            return;
+        }
         Ok(Ok(conn)) => {
             info!(?session_id, %peer_addr, "accepted new TLS connection");
             conn
@@ -335,7 +373,10 @@ async fn request_handler(
 
         // Return the response so the spawned future can continue.
         Ok(response)
-    } else if request.uri().path() == "/sql" && *request.method() == Method::POST {
+    } else if let (Some(endpoint), &Method::POST) = (
+        sql_over_http::SqlEndpoint::from_path(request.uri().path()),
+        request.method(),
+    ) {
         let ctx = RequestMonitoring::new(
             session_id,
             peer_addr,
@@ -344,16 +385,25 @@ async fn request_handler(
         );
         let span = ctx.span.clone();
 
-        sql_over_http::handle(config, ctx, request, backend, http_cancellation_token)
-            .instrument(span)
-            .await
-    } else if request.uri().path() == "/sql" && *request.method() == Method::OPTIONS {
+        sql_over_http::handle(
+            config,
+            ctx,
+            endpoint,
+            request,
+            backend,
+            http_cancellation_token,
+        )
+        .instrument(span)
+        .await
+    } else if sql_over_http::SqlEndpoint::from_path(request.uri().path()).is_some()
+        && *request.method() == Method::OPTIONS
+    {
         Response::builder()
             .header("Allow", "OPTIONS, POST")
             .header("Access-Control-Allow-Origin", "*")
             .header(
                 "Access-Control-Allow-Headers",
-                "Neon-Connection-String, Neon-Raw-Text-Output, Neon-Array-Mode, Neon-Pool-Opt-In, Neon-Batch-Read-Only, Neon-Batch-Isolation-Level",
+                "Neon-Connection-String, Neon-Raw-Text-Output, Neon-Array-Mode, Neon-Pool-Opt-In, Neon-Batch-Read-Only, Neon-Batch-Isolation-Level, Neon-Transaction-Session, Neon-Result-Cache",
             )
             .header("Access-Control-Max-Age", "86400" /* 24 hours */)
             .status(StatusCode::OK) // 204 is also valid, but see: https://developer.mozilla.org/en-US/docs/Web/HTTP/Methods/OPTIONS#status_code
@@ -11,19 +11,23 @@ use crate::{
         errors::{GetAuthInfoError, WakeComputeError},
         locks::ApiLocks,
         provider::ApiLockError,
-        CachedNodeInfo,
+        CachedNodeInfo, NodeInfo,
     },
     context::RequestMonitoring,
-    error::{ErrorKind, ReportableError, UserFacingError},
-    proxy::{connect_compute::ConnectMechanism, retry::ShouldRetry},
+    endpoint_metrics::ENDPOINT_METRICS,
+    error::{ErrorCode, ErrorKind, ReportableError, UserFacingError},
+    proxy::{connect_compute::ConnectMechanism, retry::ShouldRetry, sticky_routing::StickyRoutes},
     rate_limiter::EndpointRateLimiter,
+    session_policy::{RoleNotAllowed, SessionPolicies},
     Host,
 };
 
 use super::conn_pool::{poll_client, Client, ConnInfo, GlobalConnPool};
+use super::result_cache::ResultCache;
 
 pub struct PoolingBackend {
     pub pool: Arc<GlobalConnPool<tokio_postgres::Client>>,
+    pub result_cache: ResultCache,
     pub config: &'static ProxyConfig,
     pub endpoint_rate_limiter: Arc<EndpointRateLimiter>,
 }
@@ -63,6 +67,7 @@ impl PoolingBackend {
             None => {
                 // If we don't have an authentication secret, for the http flow we can just return an error.
                 info!("authentication info not found");
+                ENDPOINT_METRICS.record_auth_failure((&user_info.endpoint).into());
                 return Err(AuthError::auth_failed(&*user_info.user));
             }
         };
@@ -75,6 +80,7 @@ impl PoolingBackend {
             }
             crate::sasl::Outcome::Failure(reason) => {
                 info!("auth backend failed with an error: {reason}");
+                ENDPOINT_METRICS.record_auth_failure((&user_info.endpoint).into());
                 Err(AuthError::auth_failed(&*conn_info.user_info.user))
             }
         };
@@ -95,6 +101,12 @@ impl PoolingBackend {
         keys: ComputeCredentials,
         force_new: bool,
     ) -> Result<Client<tokio_postgres::Client>, HttpConnError> {
+        let request = self.pool.register_request(ctx, &conn_info);
+        self.pool
+            .wait_until_resumed(&conn_info.user_info.endpoint)
+            .await
+            .map_err(|_| HttpConnError::Paused(self.config.http_config.request_timeout))?;
+
         let maybe_client = if !force_new {
             info!("pool: looking for an existing connection");
             self.pool.get(ctx, &conn_info).await?
@@ -103,27 +115,33 @@ impl PoolingBackend {
             None
         };
 
-        if let Some(client) = maybe_client {
-            return Ok(client);
-        }
-        let conn_id = uuid::Uuid::new_v4();
-        tracing::Span::current().record("conn_id", display(conn_id));
-        info!(%conn_id, "pool: opening a new connection '{conn_info}'");
-        let backend = self.config.auth_backend.as_ref().map(|_| keys);
-        crate::proxy::connect_compute::connect_to_compute(
-            ctx,
-            &TokioMechanism {
-                conn_id,
-                conn_info,
-                pool: self.pool.clone(),
-                locks: &self.config.connect_compute_locks,
-            },
-            &backend,
-            false, // do not allow self signed compute for http flow
-            self.config.wake_compute_retry_config,
-            self.config.connect_to_compute_retry_config,
-        )
-        .await
+        let mut client = match maybe_client {
+            Some(client) => client,
+            None => {
+                let conn_id = uuid::Uuid::new_v4();
+                tracing::Span::current().record("conn_id", display(conn_id));
+                info!(%conn_id, "pool: opening a new connection '{conn_info}'");
+                let backend = self.config.auth_backend.as_ref().map(|_| keys);
+                crate::proxy::connect_compute::connect_to_compute(
+                    ctx,
+                    &TokioMechanism {
+                        conn_id,
+                        conn_info,
+                        pool: self.pool.clone(),
+                        locks: &self.config.connect_compute_locks,
+                        routes: &self.config.compute_routes,
+                        session_policies: &self.config.session_policies,
+                    },
+                    &backend,
+                    false, // do not allow self signed compute for http flow
+                    self.config.wake_compute_retry_config,
+                    self.config.connect_to_compute_retry_config,
+                )
+                .await?
+            }
+        };
+        client.attach_request(request);
+        Ok(client)
     }
 }
 
@@ -142,6 +160,10 @@ pub enum HttpConnError {
     WakeCompute(#[from] WakeComputeError),
     #[error("error acquiring resource permit: {0}")]
     TooManyConnectionAttempts(#[from] ApiLockError),
+    #[error("{0}")]
+    RoleNotAllowed(#[from] RoleNotAllowed),
+    #[error("endpoint is paused, gave up waiting for a connection after {0:?}")]
+    Paused(Duration),
 }
 
 impl ReportableError for HttpConnError {
@@ -153,6 +175,8 @@ impl ReportableError for HttpConnError {
             HttpConnError::AuthError(a) => a.get_error_kind(),
             HttpConnError::WakeCompute(w) => w.get_error_kind(),
             HttpConnError::TooManyConnectionAttempts(w) => w.get_error_kind(),
+            HttpConnError::RoleNotAllowed(r) => r.get_error_kind(),
+            HttpConnError::Paused(_) => ErrorKind::Service,
         }
     }
 }
@@ -168,6 +192,21 @@ impl UserFacingError for HttpConnError {
             HttpConnError::TooManyConnectionAttempts(_) => {
                 "Failed to acquire permit to connect to the database. Too many database connection attempts are currently ongoing.".to_owned()
             }
+            HttpConnError::RoleNotAllowed(r) => r.to_string_client(),
+            HttpConnError::Paused(_) => self.to_string(),
+        }
+    }
+
+    fn get_error_code(&self) -> ErrorCode {
+        match self {
+            HttpConnError::ConnectionClosedAbruptly(_) => ErrorCode::ComputeUnavailable,
+            HttpConnError::ConnectionError(p) => ErrorCode::from_postgres(p),
+            HttpConnError::GetAuthInfo(c) => c.get_error_code(),
+            HttpConnError::AuthError(c) => c.get_error_code(),
+            HttpConnError::WakeCompute(c) => c.get_error_code(),
+            HttpConnError::TooManyConnectionAttempts(_) => ErrorCode::RateLimited,
+            HttpConnError::RoleNotAllowed(r) => r.get_error_code(),
+            HttpConnError::Paused(_) => ErrorCode::ComputeUnavailable,
         }
     }
 }
@@ -181,6 +220,8 @@ impl ShouldRetry for HttpConnError {
             HttpConnError::AuthError(_) => false,
             HttpConnError::WakeCompute(_) => false,
             HttpConnError::TooManyConnectionAttempts(_) => false,
+            HttpConnError::RoleNotAllowed(_) => false,
+            HttpConnError::Paused(_) => false,
         }
     }
     fn should_retry_database_address(&self) -> bool {
@@ -188,6 +229,8 @@ impl ShouldRetry for HttpConnError {
             HttpConnError::ConnectionError(e) => e.should_retry_database_address(),
             // we never checked cache validity
             HttpConnError::TooManyConnectionAttempts(_) => false,
+            HttpConnError::RoleNotAllowed(_) => false,
+            HttpConnError::Paused(_) => false,
             _ => true,
         }
     }
@@ -200,6 +243,12 @@ struct TokioMechanism {
 
     /// connect_to_compute concurrency lock
     locks: &'static ApiLocks<Host>,
+
+    /// Routes to the compute replicas of endpoints
+    routes: &'static StickyRoutes,
+
+    /// Session policies of endpoints
+    session_policies: &'static SessionPolicies,
 }
 
 #[async_trait]
@@ -241,5 +290,21 @@ impl ConnectMechanism for TokioMechanism {
         ))
     }
 
-    fn update_connect_config(&self, _config: &mut compute::ConnCfg) {}
+    fn update_connect_config(&self, config: &mut compute::ConnCfg) {
+        // The session policy checks the role.
+        config.user(&self.conn_info.user_info.user);
+    }
+
+    fn route(&self, ctx: &mut RequestMonitoring, node_info: &mut NodeInfo) {
+        self.routes.route(
+            ctx,
+            node_info,
+            &self.conn_info.user_info.user,
+            &self.conn_info.dbname,
+        );
+    }
+
+    fn session_policies(&self) -> Option<&SessionPolicies> {
+        Some(self.session_policies)
+    }
 }
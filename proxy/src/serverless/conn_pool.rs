@@ -1,9 +1,17 @@
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use futures::{future::poll_fn, Future};
 use parking_lot::RwLock;
 use rand::Rng;
 use smallvec::SmallVec;
-use std::{collections::HashMap, pin::pin, sync::Arc, sync::Weak, time::Duration};
+use std::net::IpAddr;
+use std::{
+    collections::{HashMap, HashSet},
+    pin::pin,
+    sync::Arc,
+    sync::Weak,
+    time::Duration,
+};
 use std::{
     fmt,
     task::{ready, Poll},
@@ -18,10 +26,13 @@ use tokio_postgres::{AsyncMessage, ReadyForQueryStatus, Socket};
 use tokio_util::sync::CancellationToken;
 
 use crate::console::messages::{ColdStartInfo, MetricsAuxInfo};
+use crate::endpoint_metrics::{EndpointConnectionGuard, ENDPOINT_METRICS};
+use crate::error::{ErrorKind, ReportableError, UserFacingError};
 use crate::metrics::{HttpEndpointPoolsGuard, Metrics};
-use crate::usage_metrics::{Ids, MetricCounter, USAGE_METRICS};
+use crate::usage_metrics::{Ids, MetricCounter, MetricCounterRecorder, USAGE_METRICS};
 use crate::{
-    auth::backend::ComputeUserInfo, context::RequestMonitoring, DbName, EndpointCacheKey, RoleName,
+    auth::backend::ComputeUserInfo, context::RequestMonitoring, DbName, EndpointCacheKey,
+    EndpointId, RoleName,
 };
 
 use tracing::{debug, error, warn, Span};
@@ -42,6 +53,14 @@ impl ConnInfo {
         (self.dbname.clone(), self.user_info.user.clone())
     }
 
+    /// Whether `other` connects as the same user to the same database.
+    pub fn same_credentials(&self, other: &ConnInfo) -> bool {
+        self.user_info.user == other.user_info.user
+            && self.user_info.endpoint == other.user_info.endpoint
+            && self.dbname == other.dbname
+            && self.password == other.password
+    }
+
     pub fn endpoint_cache_key(&self) -> Option<EndpointCacheKey> {
         // We don't want to cache http connections for ephemeral endpoints.
         if self.user_info.options.is_ephemeral() {
@@ -122,6 +141,21 @@ impl<C: ClientInnerExt> EndpointConnPool<C> {
         }
     }
 
+    /// Close the idle connections, returning how many.
+    fn clear_idle(&mut self) -> usize {
+        let removed = self.pools.values().map(|pool| pool.conns.len()).sum();
+        self.pools.clear();
+        self.total_conns -= removed;
+        self.global_connections_count
+            .fetch_sub(removed, atomic::Ordering::Relaxed);
+        Metrics::get()
+            .proxy
+            .http_pool_opened_connections
+            .get_metric()
+            .dec_by(removed as i64);
+        removed
+    }
+
     fn put(pool: &RwLock<Self>, conn_info: &ConnInfo, client: ClientInner<C>) {
         let conn_id = client.conn_id;
 
@@ -252,9 +286,88 @@ pub struct GlobalConnPool<C: ClientInnerExt> {
     /// Total number of connections in the pool
     global_connections_count: Arc<AtomicUsize>,
 
+    /// Connections in the middle of a transaction that spans several requests.
+    pub transactions: TransactionSessions<C>,
+
+    /// The compute connections opened by the pool, whether pooled or not, for the admin
+    /// console, see [`super::pool_admin`].
+    servers: DashMap<uuid::Uuid, ServerConn>,
+
+    /// The requests that wait for or use a connection, for the admin console.
+    clients: DashMap<uuid::Uuid, ClientInfo>,
+
+    /// The endpoints whose requests wait for a connection until they are resumed.
+    paused: tokio::sync::watch::Sender<HashSet<EndpointId>>,
+
     config: &'static crate::config::HttpConfig,
 }
 
+struct ServerConn {
+    endpoint: EndpointId,
+    dbname: DbName,
+    user: RoleName,
+    pid: i32,
+    connected_at: DateTime<Utc>,
+}
+
+/// A compute connection opened by the pool, see [`GlobalConnPool::servers`].
+pub struct ServerInfo {
+    pub conn_id: uuid::Uuid,
+    pub endpoint: EndpointId,
+    pub dbname: DbName,
+    pub user: RoleName,
+    pub pid: i32,
+    pub connected_at: DateTime<Utc>,
+    pub state: ServerState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerState {
+    /// Used by a request.
+    Active,
+    /// In the pool.
+    Idle,
+    /// Kept for a transaction session between its requests.
+    IdleInTransaction,
+}
+
+impl ServerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerState::Active => "active",
+            ServerState::Idle => "idle",
+            ServerState::IdleInTransaction => "idle in transaction",
+        }
+    }
+}
+
+/// A request that waits for or uses a connection, see [`GlobalConnPool::register_request`].
+#[derive(Clone)]
+pub struct ClientInfo {
+    pub session_id: uuid::Uuid,
+    pub endpoint: EndpointId,
+    pub dbname: DbName,
+    pub user: RoleName,
+    pub addr: IpAddr,
+    pub requested_at: DateTime<Utc>,
+    /// The connection used by the request, `None` while it waits for one.
+    pub conn_id: Option<uuid::Uuid>,
+}
+
+/// Lists a request in [`GlobalConnPool::clients`] until dropped.
+pub struct RequestGuard<C: ClientInnerExt> {
+    pool: Weak<GlobalConnPool<C>>,
+    session_id: uuid::Uuid,
+}
+
+impl<C: ClientInnerExt> Drop for RequestGuard<C> {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.upgrade() {
+            pool.clients.remove(&self.session_id);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct GlobalConnPoolOptions {
     // Maximum number of connections per one endpoint.
@@ -273,6 +386,16 @@ pub struct GlobalConnPoolOptions {
 
     // Total number of connections in the pool.
     pub max_total_conns: usize,
+
+    /// How long a transaction session can stay idle before it's considered leaked and its
+    /// connection is closed.
+    pub transaction_idle_timeout: Duration,
+
+    /// Maximum number of transaction sessions per endpoint.
+    pub max_transaction_sessions_per_endpoint: usize,
+
+    /// Total number of transaction sessions.
+    pub max_transaction_sessions: usize,
 }
 
 impl<C: ClientInnerExt> GlobalConnPool<C> {
@@ -283,10 +406,17 @@ impl<C: ClientInnerExt> GlobalConnPool<C> {
             global_pool_size: AtomicUsize::new(0),
             config,
             global_connections_count: Arc::new(AtomicUsize::new(0)),
+            transactions: TransactionSessions::new(
+                config.pool_options.transaction_idle_timeout,
+                config.pool_options.max_transaction_sessions_per_endpoint,
+                config.pool_options.max_transaction_sessions,
+            ),
+            servers: DashMap::new(),
+            clients: DashMap::new(),
+            paused: tokio::sync::watch::Sender::new(HashSet::new()),
         })
     }
 
-    #[cfg(test)]
     pub fn get_global_connections_count(&self) -> usize {
         self.global_connections_count
             .load(atomic::Ordering::Relaxed)
@@ -296,9 +426,135 @@ impl<C: ClientInnerExt> GlobalConnPool<C> {
         self.config.pool_options.idle_timeout
     }
 
+    pub fn get_max_total_conns(&self) -> usize {
+        self.config.pool_options.max_total_conns
+    }
+
+    /// The compute connections opened by the pool, with their current state.
+    pub fn servers(&self) -> Vec<ServerInfo> {
+        let mut idle = HashSet::new();
+        for pool in self.global_pool.iter() {
+            for db_user_pool in pool.read().pools.values() {
+                idle.extend(db_user_pool.conns.iter().map(|entry| entry.conn.conn_id));
+            }
+        }
+        let in_transaction = self.transactions.idle_conn_ids();
+
+        self.servers
+            .iter()
+            .map(|entry| {
+                let conn_id = *entry.key();
+                let state = if idle.contains(&conn_id) {
+                    ServerState::Idle
+                } else if in_transaction.contains(&conn_id) {
+                    ServerState::IdleInTransaction
+                } else {
+                    ServerState::Active
+                };
+                let server = entry.value();
+                ServerInfo {
+                    conn_id,
+                    endpoint: server.endpoint.clone(),
+                    dbname: server.dbname.clone(),
+                    user: server.user.clone(),
+                    pid: server.pid,
+                    connected_at: server.connected_at,
+                    state,
+                }
+            })
+            .collect()
+    }
+
+    /// The requests that wait for or use a connection.
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        self.clients
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    /// List the request in [`Self::clients`], as waiting for a connection until one is
+    /// attached with [`Client::attach_request`].
+    pub fn register_request(
+        self: &Arc<Self>,
+        ctx: &RequestMonitoring,
+        conn_info: &ConnInfo,
+    ) -> RequestGuard<C> {
+        self.clients.insert(
+            ctx.session_id,
+            ClientInfo {
+                session_id: ctx.session_id,
+                endpoint: conn_info.user_info.endpoint.clone(),
+                dbname: conn_info.dbname.clone(),
+                user: conn_info.user_info.user.clone(),
+                addr: ctx.peer_addr,
+                requested_at: Utc::now(),
+                conn_id: None,
+            },
+        );
+        RequestGuard {
+            pool: Arc::downgrade(self),
+            session_id: ctx.session_id,
+        }
+    }
+
+    /// Make the requests to `endpoint` wait for a connection until [`Self::resume`], and close
+    /// its idle connections, returning how many. The connections in use are left alone.
+    pub fn pause(&self, endpoint: &EndpointId) -> usize {
+        self.paused.send_modify(|paused| {
+            paused.insert(endpoint.clone());
+        });
+        let mut closed = 0;
+        for pool in self.global_pool.iter() {
+            // The cache key is the endpoint followed by the options, see `get_cache_key`.
+            let of_endpoint = pool
+                .key()
+                .strip_prefix(endpoint.as_str())
+                .is_some_and(|options| options.is_empty() || options.starts_with(' '));
+            if of_endpoint {
+                closed += pool.write().clear_idle();
+            }
+        }
+        info!("pool: paused endpoint {endpoint}, closed {closed} idle connections");
+        closed
+    }
+
+    /// Let the requests to `endpoint` through again, returning whether it was paused.
+    pub fn resume(&self, endpoint: &EndpointId) -> bool {
+        let resumed = self
+            .paused
+            .send_if_modified(|paused| paused.remove(endpoint));
+        if resumed {
+            info!("pool: resumed endpoint {endpoint}");
+        }
+        resumed
+    }
+
+    pub fn is_paused(&self, endpoint: &EndpointId) -> bool {
+        self.paused.borrow().contains(endpoint)
+    }
+
+    /// Wait until the requests to `endpoint` aren't paused, for at most the request timeout,
+    /// like the `query_wait_timeout` of PgBouncer.
+    pub async fn wait_until_resumed(
+        &self,
+        endpoint: &EndpointId,
+    ) -> Result<(), tokio::time::error::Elapsed> {
+        let mut paused = self.paused.subscribe();
+        if paused.borrow().contains(endpoint) {
+            info!("pool: endpoint {endpoint} is paused, waiting");
+        }
+        let resumed = paused.wait_for(|paused| !paused.contains(endpoint));
+        // The sender lives as long as the pool.
+        tokio::time::timeout(self.config.request_timeout, resumed)
+            .await
+            .map(|_| ())
+    }
+
     pub fn shutdown(&self) {
         // drops all strong references to endpoint-pools
         self.global_pool.clear();
+        self.transactions.shutdown();
     }
 
     pub async fn gc_worker(&self, mut rng: impl Rng) {
@@ -487,6 +743,18 @@ pub fn poll_client<C: ClientInnerExt>(
     };
     let pool_clone = pool.clone();
 
+    global_pool.servers.insert(
+        conn_id,
+        ServerConn {
+            endpoint: conn_info.user_info.endpoint.clone(),
+            dbname: conn_info.dbname.clone(),
+            user: conn_info.user_info.user.clone(),
+            pid: client.get_process_id(),
+            connected_at: Utc::now(),
+        },
+    );
+    let servers_pool = Arc::downgrade(&global_pool);
+
     let db_user = conn_info.db_and_user();
     let idle = global_pool.get_idle_timeout();
     let cancel = CancellationToken::new();
@@ -564,6 +832,9 @@ pub fn poll_client<C: ClientInnerExt>(
             Poll::Ready(())
         }).await;
 
+        if let Some(global_pool) = servers_pool.upgrade() {
+            global_pool.servers.remove(&conn_id);
+        }
     }
     .instrument(span));
     let inner = ClientInner {
@@ -612,12 +883,29 @@ impl<C: ClientInnerExt> ClientInner<C> {
 }
 
 impl<C: ClientInnerExt> Client<C> {
-    pub fn metrics(&self) -> Arc<MetricCounter> {
+    pub fn metrics(&self) -> ClientMetrics {
         let aux = &self.inner.as_ref().unwrap().aux;
-        USAGE_METRICS.register(Ids {
-            endpoint_id: aux.endpoint_id,
-            branch_id: aux.branch_id,
-        })
+        ClientMetrics {
+            usage: USAGE_METRICS.register(Ids {
+                endpoint_id: aux.endpoint_id,
+                branch_id: aux.branch_id,
+            }),
+            endpoint_conn: self.endpoint_conn.clone(),
+        }
+    }
+}
+
+/// The metrics of the requests that use a connection, which outlive its checkin.
+pub struct ClientMetrics {
+    usage: Arc<MetricCounter>,
+    endpoint_conn: Arc<EndpointConnectionGuard>,
+}
+
+impl ClientMetrics {
+    /// Record that some bytes were sent from the proxy to the client
+    pub fn record_egress(&self, bytes: u64) {
+        self.usage.record_egress(bytes);
+        self.endpoint_conn.record_bytes(bytes);
     }
 }
 
@@ -626,11 +914,19 @@ pub struct Client<C: ClientInnerExt> {
     inner: Option<ClientInner<C>>,
     conn_info: ConnInfo,
     pool: Weak<RwLock<EndpointConnPool<C>>>,
+    /// The connection is kept in [`TransactionSessions`] between requests.
+    in_transaction_session: bool,
+    /// The request using the connection, see [`GlobalConnPool::register_request`].
+    request: Option<RequestGuard<C>>,
+    /// Counts the connection as active for its endpoint while it's checked out, see
+    /// [`crate::endpoint_metrics`].
+    endpoint_conn: Arc<EndpointConnectionGuard>,
 }
 
 pub struct Discard<'a, C: ClientInnerExt> {
     conn_info: &'a ConnInfo,
     pool: &'a mut Weak<RwLock<EndpointConnPool<C>>>,
+    in_transaction_session: bool,
 }
 
 impl<C: ClientInnerExt> Client<C> {
@@ -639,11 +935,15 @@ impl<C: ClientInnerExt> Client<C> {
         conn_info: ConnInfo,
         pool: Weak<RwLock<EndpointConnPool<C>>>,
     ) -> Self {
+        let endpoint_conn = Arc::new(ENDPOINT_METRICS.connection(inner.aux.endpoint_id));
         Self {
             inner: Some(inner),
             span: Span::current(),
             conn_info,
             pool,
+            in_transaction_session: false,
+            request: None,
+            endpoint_conn,
         }
     }
     pub fn inner(&mut self) -> (&mut C, Discard<'_, C>) {
@@ -652,15 +952,47 @@ impl<C: ClientInnerExt> Client<C> {
             pool,
             conn_info,
             span: _,
+            in_transaction_session,
+            request: _,
+            endpoint_conn: _,
         } = self;
         let inner = inner.as_mut().expect("client inner should not be removed");
-        (&mut inner.inner, Discard { pool, conn_info })
+        (
+            &mut inner.inner,
+            Discard {
+                pool,
+                conn_info,
+                in_transaction_session: *in_transaction_session,
+            },
+        )
+    }
+}
+
+impl<C: ClientInnerExt> Client<C> {
+    /// Mark the request of `guard` as using this connection, until the client is dropped.
+    pub fn attach_request(&mut self, guard: RequestGuard<C>) {
+        let conn_id = self
+            .inner
+            .as_ref()
+            .expect("client inner should not be removed")
+            .conn_id;
+        if let Some(pool) = guard.pool.upgrade() {
+            if let Some(mut client) = pool.clients.get_mut(&guard.session_id) {
+                client.conn_id = Some(conn_id);
+            }
+        }
+        self.request = Some(guard);
     }
 }
 
 impl<C: ClientInnerExt> Discard<'_, C> {
     pub fn check_idle(&mut self, status: ReadyForQueryStatus) {
         let conn_info = &self.conn_info;
+        // A transaction session is expected to be in a transaction. Whether the connection can
+        // go back to the pool is checked once the session ends.
+        if self.in_transaction_session {
+            return;
+        }
         if status != ReadyForQueryStatus::Idle && std::mem::take(self.pool).strong_count() > 0 {
             info!("pool: throwing away connection '{conn_info}' because connection is not idle")
         }
@@ -712,6 +1044,240 @@ impl<C: ClientInnerExt> Drop for Client<C> {
     }
 }
 
+/// Transactions that span several SQL-over-HTTP requests.
+///
+/// `POST /sql/transaction` begins a transaction on a connection, and keeps the connection here
+/// under a random session token. Requests with the token run on that connection, one at a
+/// time, until the transaction is committed or rolled back. A session that stays idle for
+/// longer than the idle timeout was leaked by its client: its connection is closed, which rolls
+/// the transaction back, rather than returned to the pool.
+///
+/// The connections of the sessions are outside of the pool and its limits, so the sessions are
+/// limited for each endpoint and in total.
+pub struct TransactionSessions<C: ClientInnerExt> {
+    sessions: DashMap<uuid::Uuid, TransactionSession<C>>,
+    /// The number of sessions of each endpoint.
+    endpoint_sessions: DashMap<EndpointId, usize>,
+    total_sessions: AtomicUsize,
+    idle_timeout: Duration,
+    max_per_endpoint: usize,
+    max_total: usize,
+}
+
+struct TransactionSession<C: ClientInnerExt> {
+    conn_info: ConnInfo,
+    /// `None` while a request uses the connection.
+    client: Option<Client<C>>,
+    last_used: Instant,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransactionSessionError {
+    #[error("transaction session not found, it might have timed out")]
+    NotFound,
+    #[error("transaction session is in use by another request")]
+    Busy,
+    #[error("too many transaction sessions for this endpoint")]
+    TooManyForEndpoint,
+    #[error("too many transaction sessions")]
+    TooMany,
+}
+
+impl ReportableError for TransactionSessionError {
+    fn get_error_kind(&self) -> ErrorKind {
+        match self {
+            TransactionSessionError::NotFound => ErrorKind::User,
+            TransactionSessionError::Busy => ErrorKind::User,
+            TransactionSessionError::TooManyForEndpoint => ErrorKind::RateLimit,
+            TransactionSessionError::TooMany => ErrorKind::ServiceRateLimit,
+        }
+    }
+}
+
+impl UserFacingError for TransactionSessionError {
+    fn to_string_client(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<C: ClientInnerExt> TransactionSessions<C> {
+    fn new(idle_timeout: Duration, max_per_endpoint: usize, max_total: usize) -> Self {
+        Self {
+            sessions: DashMap::new(),
+            endpoint_sessions: DashMap::new(),
+            total_sessions: AtomicUsize::new(0),
+            idle_timeout,
+            max_per_endpoint,
+            max_total,
+        }
+    }
+
+    /// Keep the connection of a transaction that was just begun, returning the session token.
+    /// Over the limits, the connection is closed, which rolls the transaction back.
+    pub fn begin(&self, mut client: Client<C>) -> Result<uuid::Uuid, TransactionSessionError> {
+        if let Err(e) = self.acquire(&client.conn_info.user_info.endpoint) {
+            client.inner().1.discard();
+            return Err(e);
+        }
+        client.in_transaction_session = true;
+        // The requests of the session use the connection one after the other.
+        client.request = None;
+        let token = uuid::Uuid::new_v4();
+        let conn_info = client.conn_info.clone();
+        info!(%token, "pool: transaction session for '{conn_info}' begun");
+        self.sessions.insert(
+            token,
+            TransactionSession {
+                conn_info,
+                client: Some(client),
+                last_used: Instant::now(),
+            },
+        );
+        Metrics::get()
+            .proxy
+            .http_transaction_sessions
+            .get_metric()
+            .inc();
+        Ok(token)
+    }
+
+    /// Count a new session against the limits.
+    fn acquire(&self, endpoint: &EndpointId) -> Result<(), TransactionSessionError> {
+        self.total_sessions
+            .fetch_update(atomic::Ordering::Relaxed, atomic::Ordering::Relaxed, |n| {
+                (n < self.max_total).then_some(n + 1)
+            })
+            .map_err(|_| TransactionSessionError::TooMany)?;
+        let mut sessions = self.endpoint_sessions.entry(endpoint.clone()).or_default();
+        if *sessions >= self.max_per_endpoint {
+            drop(sessions);
+            self.total_sessions.fetch_sub(1, atomic::Ordering::Relaxed);
+            return Err(TransactionSessionError::TooManyForEndpoint);
+        }
+        *sessions += 1;
+        Ok(())
+    }
+
+    /// Count an ended session out of the limits, and of the metric.
+    fn release(&self, endpoint: &EndpointId) {
+        self.total_sessions.fetch_sub(1, atomic::Ordering::Relaxed);
+        self.endpoint_sessions
+            .remove_if_mut(endpoint, |_, sessions| {
+                *sessions -= 1;
+                *sessions == 0
+            });
+        Metrics::get()
+            .proxy
+            .http_transaction_sessions
+            .get_metric()
+            .dec();
+    }
+
+    /// Take the connection of a session for a request, which must give it back with
+    /// [`Self::checkin`]. Sessions of other credentials are not found.
+    pub fn checkout(
+        &self,
+        token: uuid::Uuid,
+        conn_info: &ConnInfo,
+    ) -> Result<Client<C>, TransactionSessionError> {
+        let mut session = self
+            .sessions
+            .get_mut(&token)
+            .ok_or(TransactionSessionError::NotFound)?;
+        if !session.conn_info.same_credentials(conn_info) {
+            return Err(TransactionSessionError::NotFound);
+        }
+        session.client.take().ok_or(TransactionSessionError::Busy)
+    }
+
+    /// Give back the connection of a session after a request.
+    pub fn checkin(&self, token: uuid::Uuid, mut client: Client<C>) {
+        if client.is_closed() {
+            info!(%token, "pool: transaction session ended because its connection is closed");
+            self.end(token);
+            return;
+        }
+        match self.sessions.get_mut(&token) {
+            Some(mut session) => {
+                session.client = Some(client);
+                session.last_used = Instant::now();
+            }
+            // Ended while the request was running.
+            None => client.inner().1.discard(),
+        }
+    }
+
+    /// Take the connection of a session to end its transaction. Once the transaction is over,
+    /// dropping the client returns the connection to the pool, if it's idle.
+    pub fn end_checkout(
+        &self,
+        token: uuid::Uuid,
+        conn_info: &ConnInfo,
+    ) -> Result<Client<C>, TransactionSessionError> {
+        let mut client = self.checkout(token, conn_info)?;
+        self.end(token);
+        client.in_transaction_session = false;
+        Ok(client)
+    }
+
+    fn end(&self, token: uuid::Uuid) {
+        if let Some((_, session)) = self.sessions.remove(&token) {
+            self.release(&session.conn_info.user_info.endpoint);
+        }
+    }
+
+    pub async fn gc_worker(&self) {
+        let mut interval = tokio::time::interval(self.idle_timeout / 2);
+        loop {
+            interval.tick().await;
+            self.gc(Instant::now());
+        }
+    }
+
+    /// The connections of the sessions between their requests.
+    fn idle_conn_ids(&self) -> HashSet<uuid::Uuid> {
+        self.sessions
+            .iter()
+            .filter_map(|session| Some(session.client.as_ref()?.inner.as_ref()?.conn_id))
+            .collect()
+    }
+
+    /// Close the connections of the sessions that have been idle for too long.
+    fn gc(&self, now: Instant) {
+        self.sessions.retain(|token, session| {
+            // In use by a request.
+            let Some(client) = &mut session.client else {
+                return true;
+            };
+            let idle = now.saturating_duration_since(session.last_used);
+            if !client.is_closed() && idle < self.idle_timeout {
+                return true;
+            }
+            if !client.is_closed() {
+                warn!(
+                    %token,
+                    "pool: transaction session for '{}' leaked, closing its connection after it was idle for {idle:?} without commit or rollback",
+                    session.conn_info
+                );
+                Metrics::get().proxy.http_transaction_sessions_leaked_total.inc();
+            }
+            client.inner().1.discard();
+            self.release(&session.conn_info.user_info.endpoint);
+            false
+        });
+    }
+
+    fn shutdown(&self) {
+        self.sessions.retain(|_, session| {
+            if let Some(client) = &mut session.client {
+                client.inner().1.discard();
+            }
+            self.release(&session.conn_info.user_info.endpoint);
+            false
+        });
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{mem, sync::atomic::AtomicBool};
@@ -765,8 +1331,15 @@ mod tests {
                 idle_timeout: Duration::from_secs(1),
                 opt_in: false,
                 max_total_conns: 3,
+                transaction_idle_timeout: Duration::from_secs(1),
+                max_transaction_sessions_per_endpoint: 2,
+                max_transaction_sessions: 3,
             },
             request_timeout: Duration::from_secs(1),
+            result_cache: crate::config::CacheOptions {
+                size: 0,
+                ttl: Duration::ZERO,
+            },
             cancel_set: CancelSet::new(0),
             client_conn_threshold: u64::MAX,
         }));
@@ -863,4 +1436,178 @@ mod tests {
         // Closed client should be removed from the pool.
         assert_eq!(2, pool.get_global_connections_count());
     }
+
+    #[test]
+    fn test_endpoint_metrics() {
+        let mut inner = create_inner();
+        inner.aux.endpoint_id = (&EndpointId::from("endpoint-metrics")).into();
+        let endpoint = inner.aux.endpoint_id;
+        let conn_info = ConnInfo {
+            user_info: ComputeUserInfo {
+                user: "user".into(),
+                endpoint: "endpoint-metrics".into(),
+                options: Default::default(),
+            },
+            dbname: "dbname".into(),
+            password: "password".as_bytes().into(),
+        };
+
+        let client = Client::new(inner, conn_info, Weak::new());
+        assert_eq!(ENDPOINT_METRICS.active_connections(endpoint), 1);
+        // The response is recorded after the connection is checked in.
+        let metrics = client.metrics();
+        drop(client);
+        metrics.record_egress(100);
+        assert_eq!(ENDPOINT_METRICS.active_connections(endpoint), 1);
+        drop(metrics);
+        assert_eq!(ENDPOINT_METRICS.active_connections(endpoint), 0);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_sessions() {
+        let sessions = TransactionSessions::new(Duration::from_secs(60), 2, 3);
+        let conn_info = ConnInfo {
+            user_info: ComputeUserInfo {
+                user: "user".into(),
+                endpoint: "endpoint".into(),
+                options: Default::default(),
+            },
+            dbname: "dbname".into(),
+            password: "password".as_bytes().into(),
+        };
+        let other_user = ConnInfo {
+            password: "other".as_bytes().into(),
+            ..conn_info.clone()
+        };
+
+        let token = sessions
+            .begin(Client::new(create_inner(), conn_info.clone(), Weak::new()))
+            .unwrap();
+        assert!(matches!(
+            sessions.checkout(token, &other_user),
+            Err(TransactionSessionError::NotFound)
+        ));
+        assert!(matches!(
+            sessions.checkout(uuid::Uuid::new_v4(), &conn_info),
+            Err(TransactionSessionError::NotFound)
+        ));
+
+        // One request at a time.
+        let client = sessions.checkout(token, &conn_info).unwrap();
+        assert!(client.in_transaction_session);
+        assert!(matches!(
+            sessions.checkout(token, &conn_info),
+            Err(TransactionSessionError::Busy)
+        ));
+        sessions.checkin(token, client);
+
+        // Ending the session gives the connection back to the pool.
+        let client = sessions.end_checkout(token, &conn_info).unwrap();
+        assert!(!client.in_transaction_session);
+        assert!(matches!(
+            sessions.checkout(token, &conn_info),
+            Err(TransactionSessionError::NotFound)
+        ));
+        drop(client);
+
+        // Idle sessions are closed, unless in use.
+        let begin = |conn_info: &ConnInfo| {
+            sessions.begin(Client::new(create_inner(), conn_info.clone(), Weak::new()))
+        };
+        let leaked = begin(&conn_info).unwrap();
+        let in_use = begin(&conn_info).unwrap();
+        let client = sessions.checkout(in_use, &conn_info).unwrap();
+        sessions.gc(Instant::now() + Duration::from_secs(120));
+        assert!(matches!(
+            sessions.checkout(leaked, &conn_info),
+            Err(TransactionSessionError::NotFound)
+        ));
+        sessions.checkin(in_use, client);
+        assert!(sessions.checkout(in_use, &conn_info).is_ok());
+
+        // The sessions are limited per endpoint and in total.
+        let other_endpoint = ConnInfo {
+            user_info: ComputeUserInfo {
+                endpoint: "other".into(),
+                ..conn_info.user_info.clone()
+            },
+            ..conn_info.clone()
+        };
+        begin(&conn_info).unwrap();
+        assert!(matches!(
+            begin(&conn_info),
+            Err(TransactionSessionError::TooManyForEndpoint)
+        ));
+        begin(&other_endpoint).unwrap();
+        assert!(matches!(
+            begin(&other_endpoint),
+            Err(TransactionSessionError::TooMany)
+        ));
+        sessions.end(in_use);
+        begin(&other_endpoint).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pause() {
+        let config = Box::leak(Box::new(crate::config::HttpConfig {
+            pool_options: GlobalConnPoolOptions {
+                max_conns_per_endpoint: 2,
+                gc_epoch: Duration::from_secs(1),
+                pool_shards: 2,
+                idle_timeout: Duration::from_secs(1),
+                opt_in: false,
+                max_total_conns: 3,
+                transaction_idle_timeout: Duration::from_secs(1),
+                max_transaction_sessions_per_endpoint: 2,
+                max_transaction_sessions: 3,
+            },
+            request_timeout: Duration::from_secs(1),
+            result_cache: crate::config::CacheOptions {
+                size: 0,
+                ttl: Duration::ZERO,
+            },
+            cancel_set: CancelSet::new(0),
+            client_conn_threshold: u64::MAX,
+        }));
+        let pool = GlobalConnPool::new(config);
+        let endpoint = EndpointId::from("endpoint");
+        let conn_info_for = |endpoint: &str| ConnInfo {
+            user_info: ComputeUserInfo {
+                user: "user".into(),
+                endpoint: endpoint.into(),
+                options: Default::default(),
+            },
+            dbname: "dbname".into(),
+            password: "password".as_bytes().into(),
+        };
+        for conn_info in [conn_info_for("endpoint"), conn_info_for("endpoint-2")] {
+            let ep_pool = Arc::downgrade(
+                &pool.get_or_create_endpoint_pool(&conn_info.endpoint_cache_key().unwrap()),
+            );
+            let mut client = Client::new(create_inner(), conn_info, ep_pool);
+            client.do_drop().unwrap()();
+            mem::forget(client); // drop the client
+        }
+        assert_eq!(2, pool.get_global_connections_count());
+
+        // Only the idle connections of the endpoint are closed.
+        assert_eq!(1, pool.pause(&endpoint));
+        assert_eq!(1, pool.get_global_connections_count());
+        assert!(pool.is_paused(&endpoint));
+
+        let resumed = pool.wait_until_resumed(&endpoint);
+        let mut resumed = pin!(resumed);
+        assert!(futures::poll!(resumed.as_mut()).is_pending());
+        pool.wait_until_resumed(&"endpoint-2".into()).await.unwrap();
+
+        assert!(pool.resume(&endpoint));
+        resumed.await.unwrap();
+        assert!(!pool.resume(&endpoint));
+
+        // The requests give up after the request timeout.
+        pool.pause(&endpoint);
+        let started_at = tokio::time::Instant::now();
+        assert!(pool.wait_until_resumed(&endpoint).await.is_err());
+        assert!(started_at.elapsed() >= config.request_timeout);
+    }
 }
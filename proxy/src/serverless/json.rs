@@ -8,11 +8,7 @@ use tokio_postgres::Row;
 // Convert json non-string types to strings, so that they can be passed to Postgres
 // as parameters.
 //
-pub fn json_to_pg_text(json: Vec<Value>) -> Vec<Option<String>> {
-    json.iter().map(json_value_to_pg_text).collect()
-}
-
-fn json_value_to_pg_text(value: &Value) -> Option<String> {
+pub fn json_value_to_pg_text(value: &Value) -> Option<String> {
     match value {
         // special care for nulls
         Value::Null => None,
@@ -259,6 +255,10 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    fn json_to_pg_text(json: Vec<Value>) -> Vec<Option<String>> {
+        json.iter().map(json_value_to_pg_text).collect()
+    }
+
     #[test]
     fn test_atomic_types_to_pg_params() {
         let json = vec![Value::Bool(true), Value::Bool(false)];
@@ -0,0 +1,236 @@
+//! Explicitly typed and binary parameters of SQL over HTTP queries.
+//!
+//! The parameters are passed to Postgres as text, and their types are inferred from the query,
+//! which fails when they can't be inferred, e.g. `select $1`, or when the text form of a value is
+//! impractical, e.g. for `bytea`. Like with the `paramTypes` and `paramFormats` of libpq's
+//! `PQexecParams`, a query can set the type OIDs of its parameters, `null` to infer them, and
+//! send `bytea` values in base64:
+//!
+//! ```json
+//! {
+//!   "query": "insert into files values ($1, $2, $3)",
+//!   "params": ["logo.png", "iVBORw0KGgo=", [1, 2]],
+//!   "paramTypes": [null, 17, 1016],
+//!   "paramFormats": ["text", "base64", "text"]
+//! }
+//! ```
+//!
+//! The base64 parameters are `bytea`, their type can be omitted. A query with types is prepared
+//! with them in its Parse message, then bound and executed, which takes one more round trip than
+//! the queries whose parameter types are all inferred.
+
+use bytes::{BufMut, BytesMut};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_postgres::types::{to_sql_checked, Format, IsNull, Kind, Oid, ToSql, Type};
+
+use crate::error::{ErrorKind, ReportableError, UserFacingError};
+
+use super::json::json_value_to_pg_text;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamFormat {
+    /// The JSON value, see [`json_value_to_pg_text`].
+    #[default]
+    Text,
+    /// A `bytea` value, as a base64 string.
+    Base64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParamError {
+    #[error("{field} has {got} entries, but there are {expected} parameters")]
+    Length {
+        field: &'static str,
+        got: usize,
+        expected: usize,
+    },
+    #[error("base64 parameter ${0} is not a string")]
+    NotAString(usize),
+    #[error("base64 parameter ${0} is invalid: {1}")]
+    Base64(usize, base64::DecodeError),
+    #[error("base64 parameter ${index} must be of type bytea, not {type_}")]
+    NotBytea { index: usize, type_: String },
+}
+
+impl ReportableError for ParamError {
+    fn get_error_kind(&self) -> ErrorKind {
+        ErrorKind::User
+    }
+}
+
+impl UserFacingError for ParamError {
+    fn to_string_client(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Check that the types and formats, if set, match the parameters.
+pub fn check_lengths(
+    params: &[Value],
+    types: &[Option<Oid>],
+    formats: &[ParamFormat],
+) -> Result<(), ParamError> {
+    for (field, got) in [("paramTypes", types.len()), ("paramFormats", formats.len())] {
+        if got != 0 && got != params.len() {
+            return Err(ParamError::Length {
+                field,
+                got,
+                expected: params.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The text values of the parameters, the base64 ones in the hex format of `bytea`.
+pub fn to_pg_text(
+    params: &[Value],
+    formats: &[ParamFormat],
+) -> Result<Vec<Option<String>>, ParamError> {
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let format = formats.get(i).copied().unwrap_or_default();
+            match (format, value) {
+                (ParamFormat::Text, value) | (ParamFormat::Base64, value @ Value::Null) => {
+                    Ok(json_value_to_pg_text(value))
+                }
+                (ParamFormat::Base64, Value::String(s)) => {
+                    let bytes = base64::decode(s).map_err(|e| ParamError::Base64(i + 1, e))?;
+                    Ok(Some(format!("\\x{}", hex::encode(bytes))))
+                }
+                (ParamFormat::Base64, _) => Err(ParamError::NotAString(i + 1)),
+            }
+        })
+        .collect()
+}
+
+/// A text value of [`to_pg_text`], sent in the text format whatever the type of the parameter,
+/// for the queries whose results are requested in binary format.
+#[derive(Debug)]
+pub struct TextParam(pub Option<String>);
+
+impl ToSql for TextParam {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        match &self.0 {
+            Some(value) => {
+                out.put_slice(value.as_bytes());
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn encode_format(&self, _ty: &Type) -> Format {
+        Format::Text
+    }
+
+    to_sql_checked!();
+}
+
+/// The types of the parameters, for the Parse message of the query, or `None` if all of them are
+/// inferred.
+pub fn param_types(
+    types: &[Option<Oid>],
+    formats: &[ParamFormat],
+) -> Result<Option<Vec<Type>>, ParamError> {
+    let mut param_types = Vec::with_capacity(types.len().max(formats.len()));
+    for i in 0..types.len().max(formats.len()) {
+        let oid = types.get(i).copied().flatten();
+        let oid = match formats.get(i) {
+            Some(ParamFormat::Base64) => match oid {
+                None => Some(Type::BYTEA.oid()),
+                Some(oid) if oid == Type::BYTEA.oid() => Some(oid),
+                Some(oid) => {
+                    let type_ =
+                        Type::from_oid(oid).map_or_else(|| oid.to_string(), |t| t.to_string());
+                    return Err(ParamError::NotBytea {
+                        index: i + 1,
+                        type_,
+                    });
+                }
+            },
+            _ => oid,
+        };
+        // Postgres looks the OIDs up, and infers the `unknown` ones.
+        param_types.push(oid.map_or(Type::UNKNOWN, |oid| {
+            Type::from_oid(oid)
+                .unwrap_or_else(|| Type::new(oid.to_string(), oid, Kind::Simple, String::new()))
+        }));
+    }
+
+    if param_types.iter().all(|t| *t == Type::UNKNOWN) {
+        Ok(None)
+    } else {
+        Ok(Some(param_types))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn types_of_params() {
+        assert_eq!(param_types(&[None, None], &[]).unwrap(), None);
+        assert_eq!(param_types(&[], &[ParamFormat::Text]).unwrap(), None);
+
+        let types = param_types(
+            &[None, Some(1016), None, Some(123456)],
+            &[
+                ParamFormat::Base64,
+                ParamFormat::Text,
+                ParamFormat::Text,
+                ParamFormat::Text,
+            ],
+        )
+        .unwrap()
+        .unwrap();
+        let oids: Vec<Oid> = types.iter().map(Type::oid).collect();
+        assert_eq!(oids, [Type::BYTEA.oid(), 1016, Type::UNKNOWN.oid(), 123456]);
+
+        let err = param_types(&[Some(Type::TEXT.oid())], &[ParamFormat::Base64]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "base64 parameter $1 must be of type bytea, not text"
+        );
+    }
+
+    #[test]
+    fn param_text() {
+        let params = [json!("aGVsbG8="), json!(null), json!([1, 2])];
+        let formats = [ParamFormat::Base64, ParamFormat::Base64, ParamFormat::Text];
+        assert_eq!(
+            to_pg_text(&params, &formats).unwrap(),
+            [
+                Some("\\x68656c6c6f".to_owned()),
+                None,
+                Some("{1,2}".to_owned())
+            ]
+        );
+
+        let err = to_pg_text(&[json!(1)], &[ParamFormat::Base64]).unwrap_err();
+        assert_eq!(err.to_string(), "base64 parameter $1 is not a string");
+        assert!(to_pg_text(&[json!("not base64!")], &[ParamFormat::Base64]).is_err());
+
+        let err = check_lengths(&params, &[Some(17)], &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "paramTypes has 1 entries, but there are 3 parameters"
+        );
+        check_lengths(&params, &[], &formats).unwrap();
+    }
+}
@@ -0,0 +1,437 @@
+//! PgBouncer-compatible admin console of the connection pool of the serverless backend.
+//!
+//! The console speaks the libpq protocol, and answers a subset of the commands of the admin
+//! console of PgBouncer, with the same column names where they apply, so that the existing
+//! tooling and dashboards work against the proxy:
+//!
+//! - `SHOW POOLS`: the connections and requests per endpoint, database and user. The columns of
+//!   PgBouncer come first, in its order, as tools read them by position, and the ones of the
+//!   proxy after them. The counters of what the proxy doesn't do, like cancel requests, are 0.
+//! - `SHOW CLIENTS`: the requests that wait for a connection or use one.
+//! - `SHOW SERVERS`: the compute connections opened by the pool.
+//! - `PAUSE <endpoint>`: the requests to the endpoint wait for a connection until
+//!   `RESUME <endpoint>`, and its idle connections are closed. Like with the `query_wait_timeout`
+//!   of PgBouncer, the requests fail after waiting for `--sql-over-http-timeout`. Unlike
+//!   PgBouncer, it doesn't wait for the connections in use to be released, `SHOW SERVERS` lists
+//!   them.
+//!
+//! Like the management API of [`crate::console::mgmt`], the console doesn't authenticate its
+//! clients: it must only listen on an internal address.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use postgres_backend::{AuthType, PostgresBackend, PostgresBackendTCP, QueryError};
+use pq_proto::{BeMessage, RowDescriptor};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info, info_span, Instrument};
+
+use super::conn_pool::{ClientInfo, GlobalConnPool, ServerInfo, ServerState};
+use super::ConnPoolHandle;
+use crate::{DbName, EndpointId, RoleName};
+
+/// Admin console listener task.
+pub async fn task_main(
+    listener: TcpListener,
+    conn_pool: ConnPoolHandle,
+) -> anyhow::Result<Infallible> {
+    scopeguard::defer! {
+        info!("pool admin console has shut down");
+    }
+
+    loop {
+        let (socket, peer_addr) = listener.accept().await?;
+        info!("accepted connection from {peer_addr}");
+
+        socket
+            .set_nodelay(true)
+            .context("failed to set client socket option")?;
+
+        let conn_pool = conn_pool.clone();
+        tokio::task::spawn(
+            async move {
+                if let Err(e) = handle_connection(socket, conn_pool).await {
+                    error!("serving failed with an error: {e}");
+                }
+            }
+            .instrument(info_span!("pool_admin", peer = %peer_addr)),
+        );
+    }
+}
+
+async fn handle_connection(socket: TcpStream, conn_pool: ConnPoolHandle) -> Result<(), QueryError> {
+    let pgbackend = PostgresBackend::new(socket, AuthType::Trust, None)?;
+    pgbackend
+        .run(&mut PoolAdminHandler { conn_pool }, future::pending::<()>)
+        .await
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    ShowPools,
+    ShowClients,
+    ShowServers,
+    Pause(EndpointId),
+    Resume(EndpointId),
+}
+
+impl Command {
+    fn parse(query: &str) -> anyhow::Result<Self> {
+        let query = query.trim().trim_end_matches(';');
+        let words = query.split_whitespace().collect::<Vec<_>>();
+        let keyword = |i: usize| words.get(i).map(|word| word.to_ascii_uppercase());
+        match (keyword(0).as_deref(), keyword(1).as_deref(), words.len()) {
+            (Some("SHOW"), Some("POOLS"), 2) => Ok(Command::ShowPools),
+            (Some("SHOW"), Some("CLIENTS"), 2) => Ok(Command::ShowClients),
+            (Some("SHOW"), Some("SERVERS"), 2) => Ok(Command::ShowServers),
+            (Some("PAUSE"), Some(_), 2) => Ok(Command::Pause(words[1].into())),
+            (Some("RESUME"), Some(_), 2) => Ok(Command::Resume(words[1].into())),
+            _ => anyhow::bail!(
+                "unsupported command '{query}', expected SHOW POOLS, SHOW CLIENTS, SHOW SERVERS, \
+                 PAUSE <endpoint> or RESUME <endpoint>"
+            ),
+        }
+    }
+}
+
+struct PoolAdminHandler {
+    conn_pool: ConnPoolHandle,
+}
+
+#[async_trait::async_trait]
+impl postgres_backend::Handler<TcpStream> for PoolAdminHandler {
+    async fn process_query(
+        &mut self,
+        pgb: &mut PostgresBackendTCP,
+        query: &str,
+    ) -> Result<(), QueryError> {
+        let command = Command::parse(query)?;
+        let pool = self
+            .conn_pool
+            .0
+            .get()
+            .context("the serverless backend isn't running")?;
+        match command {
+            Command::ShowPools => show_pools(pgb, pool),
+            Command::ShowClients => show_clients(pgb, pool),
+            Command::ShowServers => show_servers(pgb, pool),
+            Command::Pause(endpoint) => {
+                pool.pause(&endpoint);
+                pgb.write_message_noflush(&BeMessage::CommandComplete(b"PAUSE"))?;
+                Ok(())
+            }
+            Command::Resume(endpoint) => {
+                if !pool.resume(&endpoint) {
+                    return Err(QueryError::Other(anyhow::anyhow!(
+                        "endpoint {endpoint} is not paused"
+                    )));
+                }
+                pgb.write_message_noflush(&BeMessage::CommandComplete(b"RESUME"))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+type Pool = Arc<GlobalConnPool<tokio_postgres::Client>>;
+
+/// The columns of `SHOW POOLS` of PgBouncer, in its order.
+const POOLS_COLUMNS: [&str; 16] = [
+    "database",
+    "user",
+    "cl_active",
+    "cl_waiting",
+    "cl_active_cancel_req",
+    "cl_waiting_cancel_req",
+    "sv_active",
+    "sv_active_cancel",
+    "sv_being_canceled",
+    "sv_idle",
+    "sv_used",
+    "sv_tested",
+    "sv_login",
+    "maxwait",
+    "maxwait_us",
+    "pool_mode",
+];
+
+/// The columns of `SHOW POOLS` that PgBouncer doesn't have.
+const POOLS_EXTRA_COLUMNS: [&str; 3] = ["endpoint", "sv_idle_in_transaction", "paused"];
+
+/// The connections and requests of an endpoint, database and user.
+#[derive(Default, Debug, PartialEq, Eq)]
+struct PoolRow {
+    cl_active: usize,
+    cl_waiting: usize,
+    sv_active: usize,
+    sv_idle: usize,
+    sv_idle_in_transaction: usize,
+    /// How long the oldest waiting request has been waiting.
+    maxwait: Duration,
+}
+
+fn pool_rows(
+    clients: Vec<ClientInfo>,
+    servers: Vec<ServerInfo>,
+    now: DateTime<Utc>,
+) -> BTreeMap<(DbName, RoleName, EndpointId), PoolRow> {
+    let mut rows = BTreeMap::<(DbName, RoleName, EndpointId), PoolRow>::new();
+    for client in clients {
+        let row = rows
+            .entry((client.dbname, client.user, client.endpoint))
+            .or_default();
+        match client.conn_id {
+            Some(_) => row.cl_active += 1,
+            None => {
+                row.cl_waiting += 1;
+                let waited = (now - client.requested_at).to_std().unwrap_or_default();
+                row.maxwait = row.maxwait.max(waited);
+            }
+        }
+    }
+    for server in servers {
+        let row = rows
+            .entry((server.dbname, server.user, server.endpoint))
+            .or_default();
+        match server.state {
+            ServerState::Active => row.sv_active += 1,
+            ServerState::Idle => row.sv_idle += 1,
+            ServerState::IdleInTransaction => row.sv_idle_in_transaction += 1,
+        }
+    }
+    rows
+}
+
+/// The values of a row of `SHOW POOLS`, in the order of [`POOLS_COLUMNS`] and
+/// [`POOLS_EXTRA_COLUMNS`].
+fn pool_row_values(
+    (dbname, user, endpoint): &(DbName, RoleName, EndpointId),
+    row: &PoolRow,
+    paused: bool,
+) -> Vec<String> {
+    vec![
+        dbname.to_string(),
+        user.to_string(),
+        row.cl_active.to_string(),
+        row.cl_waiting.to_string(),
+        // The proxy doesn't handle cancel requests as clients or servers of the pool.
+        "0".to_string(),
+        "0".to_string(),
+        row.sv_active.to_string(),
+        "0".to_string(),
+        "0".to_string(),
+        row.sv_idle.to_string(),
+        // The pooled connections are neither tested nor reset before they are reused, and
+        // they are opened by the requests.
+        "0".to_string(),
+        "0".to_string(),
+        "0".to_string(),
+        row.maxwait.as_secs().to_string(),
+        row.maxwait.subsec_micros().to_string(),
+        // Connections go back to the pool after each request, unless in a session.
+        "transaction".to_string(),
+        endpoint.to_string(),
+        row.sv_idle_in_transaction.to_string(),
+        (paused as u8).to_string(),
+    ]
+}
+
+fn show_pools(pgb: &mut PostgresBackendTCP, pool: &Pool) -> Result<(), QueryError> {
+    let rows = pool_rows(pool.clients(), pool.servers(), Utc::now());
+
+    let columns = POOLS_COLUMNS
+        .iter()
+        .chain(&POOLS_EXTRA_COLUMNS)
+        .map(|&name| match name {
+            "database" | "user" | "pool_mode" | "endpoint" => {
+                RowDescriptor::text_col(name.as_bytes())
+            }
+            _ => RowDescriptor::int8_col(name.as_bytes()),
+        })
+        .collect::<Vec<_>>();
+    pgb.write_message_noflush(&BeMessage::RowDescription(&columns))?;
+    for (key, row) in &rows {
+        let values = pool_row_values(key, row, pool.is_paused(&key.2));
+        let values = values
+            .iter()
+            .map(|value| Some(value.as_bytes()))
+            .collect::<Vec<_>>();
+        pgb.write_message_noflush(&BeMessage::DataRow(&values))?;
+    }
+    pgb.write_message_noflush(&BeMessage::CommandComplete(b"SHOW"))?;
+    Ok(())
+}
+
+fn show_clients(pgb: &mut PostgresBackendTCP, pool: &Pool) -> Result<(), QueryError> {
+    let mut clients = pool.clients();
+    clients.sort_by_key(|client| client.requested_at);
+
+    pgb.write_message_noflush(&BeMessage::RowDescription(&[
+        RowDescriptor::text_col(b"user"),
+        RowDescriptor::text_col(b"database"),
+        RowDescriptor::text_col(b"endpoint"),
+        RowDescriptor::text_col(b"state"),
+        RowDescriptor::text_col(b"addr"),
+        RowDescriptor::text_col(b"request_time"),
+        RowDescriptor::text_col(b"session_id"),
+        RowDescriptor::text_col(b"link"),
+    ]))?;
+    for client in clients {
+        let state = match client.conn_id {
+            Some(_) => "active",
+            None => "waiting",
+        };
+        let link = client.conn_id.map(|conn_id| conn_id.to_string());
+        pgb.write_message_noflush(&BeMessage::DataRow(&[
+            Some(client.user.as_bytes()),
+            Some(client.dbname.as_bytes()),
+            Some(client.endpoint.as_bytes()),
+            Some(state.as_bytes()),
+            Some(client.addr.to_string().as_bytes()),
+            Some(format_time(client.requested_at).as_bytes()),
+            Some(client.session_id.to_string().as_bytes()),
+            link.as_deref().map(str::as_bytes),
+        ]))?;
+    }
+    pgb.write_message_noflush(&BeMessage::CommandComplete(b"SHOW"))?;
+    Ok(())
+}
+
+fn show_servers(pgb: &mut PostgresBackendTCP, pool: &Pool) -> Result<(), QueryError> {
+    let mut servers = pool.servers();
+    servers.sort_by_key(|server| server.connected_at);
+
+    pgb.write_message_noflush(&BeMessage::RowDescription(&[
+        RowDescriptor::text_col(b"user"),
+        RowDescriptor::text_col(b"database"),
+        RowDescriptor::text_col(b"endpoint"),
+        RowDescriptor::text_col(b"state"),
+        RowDescriptor::text_col(b"connect_time"),
+        RowDescriptor::text_col(b"id"),
+        RowDescriptor::int8_col(b"remote_pid"),
+    ]))?;
+    for server in servers {
+        pgb.write_message_noflush(&BeMessage::DataRow(&[
+            Some(server.user.as_bytes()),
+            Some(server.dbname.as_bytes()),
+            Some(server.endpoint.as_bytes()),
+            Some(server.state.as_str().as_bytes()),
+            Some(format_time(server.connected_at).as_bytes()),
+            Some(server.conn_id.to_string().as_bytes()),
+            Some(server.pid.to_string().as_bytes()),
+        ]))?;
+    }
+    pgb.write_message_noflush(&BeMessage::CommandComplete(b"SHOW"))?;
+    Ok(())
+}
+
+/// The format of the timestamps of PgBouncer.
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse("SHOW POOLS;").unwrap(), Command::ShowPools);
+        assert_eq!(
+            Command::parse("show clients").unwrap(),
+            Command::ShowClients
+        );
+        assert_eq!(
+            Command::parse("  Show Servers ; ").unwrap(),
+            Command::ShowServers
+        );
+        assert_eq!(
+            Command::parse("PAUSE ep-foo-123").unwrap(),
+            Command::Pause("ep-foo-123".into())
+        );
+        assert_eq!(
+            Command::parse("resume ep-foo-123;").unwrap(),
+            Command::Resume("ep-foo-123".into())
+        );
+
+        assert!(Command::parse("SHOW STATS").is_err());
+        assert!(Command::parse("PAUSE").is_err());
+        assert!(Command::parse("SHOW POOLS extra").is_err());
+    }
+
+    #[test]
+    fn pools() {
+        let now = Utc::now();
+        let client = |endpoint: &str, waited_secs: i64, conn_id: Option<uuid::Uuid>| ClientInfo {
+            session_id: uuid::Uuid::new_v4(),
+            endpoint: endpoint.into(),
+            dbname: "neondb".into(),
+            user: "alice".into(),
+            addr: [127, 0, 0, 1].into(),
+            requested_at: now - chrono::Duration::seconds(waited_secs),
+            conn_id,
+        };
+        let server = |endpoint: &str, state: ServerState| ServerInfo {
+            conn_id: uuid::Uuid::new_v4(),
+            endpoint: endpoint.into(),
+            dbname: "neondb".into(),
+            user: "alice".into(),
+            pid: 1,
+            connected_at: now,
+            state,
+        };
+        let rows = pool_rows(
+            vec![
+                client("ep-1", 1, None),
+                client("ep-1", 3, None),
+                client("ep-1", 10, Some(uuid::Uuid::new_v4())),
+                client("ep-2", 1, Some(uuid::Uuid::new_v4())),
+            ],
+            vec![
+                server("ep-1", ServerState::Active),
+                server("ep-2", ServerState::Active),
+                server("ep-2", ServerState::Idle),
+                server("ep-2", ServerState::IdleInTransaction),
+            ],
+            now,
+        );
+        assert_eq!(rows.len(), 2);
+
+        let key_of = |endpoint: &str| -> (DbName, RoleName, EndpointId) {
+            ("neondb".into(), "alice".into(), endpoint.into())
+        };
+        let key = key_of("ep-1");
+        let row = &rows[&key];
+        assert_eq!((row.cl_active, row.cl_waiting), (1, 2));
+        assert_eq!(row.maxwait, Duration::from_secs(3));
+        let values = pool_row_values(&key, row, true);
+        assert_eq!(
+            values.len(),
+            POOLS_COLUMNS.len() + POOLS_EXTRA_COLUMNS.len()
+        );
+        let value = |column: &str| {
+            let mut columns = POOLS_COLUMNS.iter().chain(&POOLS_EXTRA_COLUMNS);
+            &values[columns.position(|&c| c == column).unwrap()]
+        };
+        assert_eq!(value("database"), "neondb");
+        assert_eq!(value("cl_waiting"), "2");
+        assert_eq!(value("sv_active"), "1");
+        assert_eq!(value("maxwait"), "3");
+        assert_eq!(value("maxwait_us"), "0");
+        assert_eq!(value("pool_mode"), "transaction");
+        assert_eq!(value("endpoint"), "ep-1");
+        assert_eq!(value("paused"), "1");
+
+        let row = &rows[&key_of("ep-2")];
+        assert_eq!(
+            (row.sv_active, row.sv_idle, row.sv_idle_in_transaction),
+            (1, 1, 1)
+        );
+        assert_eq!(row.maxwait, Duration::ZERO);
+    }
+}
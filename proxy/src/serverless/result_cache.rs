@@ -0,0 +1,378 @@
+//! Cache of the responses of read-only SQL over HTTP queries, to absorb the hot queries of
+//! dashboards polled from edge functions.
+//!
+//! A request opts in with the `Neon-Result-Cache: true` header. Its query then runs in a read
+//! only transaction, and the response is cached under the endpoint, user and database, the
+//! normalized query and its parameters with their types and formats, the output options, and the
+//! LSN the compute had applied when the query ran. An identical request first asks the compute
+//! for its applied LSN: as long as nothing was written since, it's the same, and the cached
+//! response is returned without running the query.
+//!
+//! So a hit is not free: it still needs a connection to the compute, and a round trip for
+//! [`APPLIED_LSN_QUERY`]. It saves the execution of the query, which is what matters for the
+//! expensive queries of dashboards, but not the latency of a cheap one.
+//!
+//! The same query at the same LSN only gives the same response if it calls immutable functions
+//! only: the queries that call `now()`, `random()` or any other stable or volatile function, see
+//! [`calls_only_immutable_functions`], are not cached.
+//!
+//! Entries expire after the TTL of the cache. `POST /sql/cache/invalidate` drops all the entries
+//! of an endpoint, by bumping the generation of the endpoint that is part of the keys.
+//!
+//! Only single queries outside of transaction sessions are cached, and only if their response
+//! is at most [`MAX_CACHED_RESPONSE_SIZE`].
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use tokio_postgres::types::Oid;
+
+use crate::cache::TimedLru;
+use crate::config::CacheOptions;
+use crate::{DbName, EndpointCacheKey, RoleName};
+
+use super::conn_pool::ConnInfo;
+use super::params::ParamFormat;
+use super::result_format::ResultFormat;
+
+/// Returns the number of functions of `$1` that are not immutable.
+const NOT_IMMUTABLE_FUNCTIONS_QUERY: &str = "SELECT count(*) FROM pg_catalog.pg_proc \
+    WHERE proname = ANY($1) AND provolatile <> 'i'";
+
+/// The SQL functions called without parentheses whose values change between queries.
+const NILADIC_FUNCTIONS: &[&str] = &[
+    "current_date",
+    "current_time",
+    "current_timestamp",
+    "localtime",
+    "localtimestamp",
+];
+
+/// Larger responses are not cached.
+pub const MAX_CACHED_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// Returns the LSN the compute has applied, as text: the replay LSN on a replica.
+pub const APPLIED_LSN_QUERY: &str = "SELECT (CASE WHEN pg_catalog.pg_is_in_recovery() \
+    THEN pg_catalog.pg_last_wal_replay_lsn() ELSE pg_catalog.pg_current_wal_lsn() END)::text";
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ResultCacheKey {
+    endpoint: EndpointCacheKey,
+    generation: u64,
+    user: RoleName,
+    dbname: DbName,
+    query: String,
+    params: Vec<Option<String>>,
+    param_types: Vec<Option<Oid>>,
+    param_formats: Vec<ParamFormat>,
+    array_mode: bool,
+    raw_output: bool,
+    result_format: ResultFormat,
+    lsn: String,
+}
+
+pub struct ResultCache {
+    /// `None` if the cache is disabled.
+    responses: Option<TimedLru<ResultCacheKey, Bytes>>,
+    /// Bumped by each invalidation of an endpoint.
+    generations: DashMap<EndpointCacheKey, u64>,
+}
+
+impl ResultCache {
+    pub fn new(options: &CacheOptions) -> Self {
+        Self {
+            responses: (options.size > 0).then(|| {
+                TimedLru::new(
+                    "sql_over_http_result_cache",
+                    options.size,
+                    options.ttl,
+                    false,
+                )
+            }),
+            generations: DashMap::new(),
+        }
+    }
+
+    /// The key of a query, or `None` if its results can't be cached.
+    #[allow(clippy::too_many_arguments)]
+    pub fn key(
+        &self,
+        conn_info: &ConnInfo,
+        query: &str,
+        params: &[Option<String>],
+        param_types: &[Option<Oid>],
+        param_formats: &[ParamFormat],
+        array_mode: bool,
+        raw_output: bool,
+        result_format: ResultFormat,
+        lsn: String,
+    ) -> Option<ResultCacheKey> {
+        self.responses.as_ref()?;
+        let endpoint = conn_info.endpoint_cache_key()?;
+        let generation = self.generations.get(&endpoint).map_or(0, |g| *g);
+        Some(ResultCacheKey {
+            endpoint,
+            generation,
+            user: conn_info.user_info.user.clone(),
+            dbname: conn_info.dbname.clone(),
+            query: normalize_query(query),
+            params: params.to_vec(),
+            param_types: param_types.to_vec(),
+            param_formats: param_formats.to_vec(),
+            array_mode,
+            raw_output,
+            result_format,
+            lsn,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.responses.is_some()
+    }
+
+    pub fn get(&self, key: &ResultCacheKey) -> Option<Bytes> {
+        self.responses.as_ref()?.get(key).map(|cached| cached.value)
+    }
+
+    pub fn insert(&self, key: ResultCacheKey, response: Bytes) {
+        if response.len() > MAX_CACHED_RESPONSE_SIZE {
+            return;
+        }
+        if let Some(responses) = &self.responses {
+            responses.insert(key, response);
+        }
+    }
+
+    /// Drop the cached responses of the endpoint. They are not removed right away, but can't be
+    /// looked up anymore, and eventually get evicted.
+    pub fn invalidate(&self, conn_info: &ConnInfo) {
+        if let Some(endpoint) = conn_info.endpoint_cache_key() {
+            *self.generations.entry(endpoint).or_default() += 1;
+        }
+    }
+}
+
+/// Whether the functions the query calls are all immutable, according to the compute. Functions
+/// called through views or operators are not seen.
+pub async fn calls_only_immutable_functions(
+    client: &tokio_postgres::Client,
+    query: &str,
+) -> Result<bool, tokio_postgres::Error> {
+    let Some(functions) = function_calls(query) else {
+        return Ok(false);
+    };
+    if functions.is_empty() {
+        return Ok(true);
+    }
+    let row = client
+        .query_one(NOT_IMMUTABLE_FUNCTIONS_QUERY, &[&functions])
+        .await?;
+    Ok(row.try_get::<_, i64>(0)? == 0)
+}
+
+/// The names of the functions the query calls, outside of its literals and comments, or `None`
+/// if it calls one of [`NILADIC_FUNCTIONS`] or has dollar quotes. Unquoted names are folded to
+/// lower case. The names of the other identifiers followed by a parenthesis, like `in` or
+/// `values`, are included too.
+fn function_calls(query: &str) -> Option<Vec<String>> {
+    let mut functions = Vec::new();
+    // The identifier right before the position, skipping whitespace.
+    let mut name = None;
+    let mut rest = query;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '(' => {
+                functions.extend(name.take());
+                1
+            }
+            c if c.is_whitespace() => c.len_utf8(),
+            '"' => {
+                let len = quoted_len(rest, '"', false);
+                let ident = &rest[1..len];
+                let ident = ident.strip_suffix('"').unwrap_or(ident);
+                name = Some(ident.replace("\"\"", "\""));
+                rest = &rest[len..];
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+                    .unwrap_or(rest.len());
+                let ident = rest[..len].to_lowercase();
+                if NILADIC_FUNCTIONS.contains(&ident.as_str()) {
+                    return None;
+                }
+                rest = &rest[len..];
+                // Backslashes escape in E'' strings.
+                if ident == "e" && rest.starts_with('\'') {
+                    rest = &rest[quoted_len(rest, '\'', true)..];
+                    name = None;
+                } else {
+                    name = Some(ident);
+                }
+                continue;
+            }
+            '\'' => quoted_len(rest, '\'', false),
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest[2..].find("*/").map_or(rest.len(), |i| i + 4),
+            '$' if !rest[1..].starts_with(|c: char| c.is_ascii_digit()) => return None,
+            c => c.len_utf8(),
+        };
+        if !c.is_whitespace() && c != '(' {
+            name = None;
+        }
+        rest = &rest[len..];
+    }
+    Some(functions)
+}
+
+/// The length of the literal or quoted identifier at the start of `s`, up to the end of `s` if
+/// it's unterminated. A doubled quote is part of it.
+fn quoted_len(s: &str, quote: char, escapes: bool) -> usize {
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        if escapes && c == '\\' {
+            chars.next();
+        } else if c == quote {
+            if s[i + 1..].starts_with(quote) {
+                chars.next();
+            } else {
+                return i + 1;
+            }
+        }
+    }
+    s.len()
+}
+
+/// Trim the query and collapse runs of whitespace outside of quotes, so that the same query
+/// formatted differently hits the same entry. Queries with dollar quotes are only trimmed.
+fn normalize_query(query: &str) -> String {
+    let query = query.trim().trim_end_matches(';').trim_end();
+    let dollar_quoted = query
+        .match_indices('$')
+        .any(|(i, _)| !query[i + 1..].starts_with(|c: char| c.is_ascii_digit()));
+    if dollar_quoted {
+        return query.to_owned();
+    }
+    let mut normalized = String::with_capacity(query.len());
+    let mut quote = None;
+    let mut whitespace = false;
+    for c in query.chars() {
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+                normalized.push(c);
+            }
+            None if c.is_whitespace() => whitespace = true,
+            None => {
+                if whitespace {
+                    normalized.push(' ');
+                    whitespace = false;
+                }
+                if c == '\'' || c == '"' {
+                    quote = Some(c);
+                }
+                normalized.push(c);
+            }
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::backend::ComputeUserInfo;
+
+    use super::*;
+
+    fn conn_info(endpoint: &str, user: &str) -> ConnInfo {
+        ConnInfo {
+            user_info: ComputeUserInfo {
+                user: user.into(),
+                endpoint: endpoint.into(),
+                options: Default::default(),
+            },
+            dbname: "neondb".into(),
+            password: "password".as_bytes().into(),
+        }
+    }
+
+    #[test]
+    fn query_normalization() {
+        assert_eq!(
+            normalize_query("  select\n  a,   b\tfrom t ;  "),
+            "select a, b from t"
+        );
+        // Quoted whitespace matters.
+        assert_eq!(
+            normalize_query("select  'a  b',  \"c  d\"  from t"),
+            "select 'a  b', \"c  d\" from t"
+        );
+        assert_eq!(normalize_query(" select $$a  b$$ "), "select $$a  b$$");
+        assert_eq!(normalize_query("select  $1"), "select $1");
+    }
+
+    #[test]
+    fn function_names() {
+        assert_eq!(
+            function_calls("select count(*), Lower (\"Name\"), pg_catalog.upper(b) from t")
+                .unwrap(),
+            ["count", "lower", "upper"]
+        );
+        assert_eq!(
+            function_calls("select \"My\"\"Func\"(1) where a in ($1)").unwrap(),
+            ["My\"Func", "in"]
+        );
+        // Not in literals or comments.
+        assert_eq!(
+            function_calls("select 'now()', E'\\' now()', a -- now()\n/* now() */").unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(function_calls("select current_timestamp"), None);
+        assert_eq!(function_calls("select $$ now() $$"), None);
+    }
+
+    #[test]
+    fn cache_keys() {
+        let cache = ResultCache::new(&CacheOptions {
+            size: 10,
+            ttl: std::time::Duration::from_secs(60),
+        });
+        let key = |conn_info: &ConnInfo, query: &str, lsn: &str| {
+            cache
+                .key(
+                    conn_info,
+                    query,
+                    &[Some("1".to_owned())],
+                    &[],
+                    &[],
+                    false,
+                    false,
+                    ResultFormat::Json,
+                    lsn.to_owned(),
+                )
+                .unwrap()
+        };
+        let alice = conn_info("endpoint", "alice");
+        let bob = conn_info("endpoint", "bob");
+
+        cache.insert(key(&alice, "select $1", "0/10"), Bytes::from_static(b"1"));
+        assert_eq!(
+            cache.get(&key(&alice, " select  $1;", "0/10")),
+            Some(Bytes::from_static(b"1"))
+        );
+        // Other users might see other rows.
+        assert_eq!(cache.get(&key(&bob, "select $1", "0/10")), None);
+        // Writes move the LSN.
+        assert_eq!(cache.get(&key(&alice, "select $1", "0/20")), None);
+
+        cache.invalidate(&bob);
+        assert_eq!(cache.get(&key(&alice, "select $1", "0/10")), None);
+
+        // Large responses are not cached.
+        let large = Bytes::from(vec![0; MAX_CACHED_RESPONSE_SIZE + 1]);
+        cache.insert(key(&alice, "select $1", "0/10"), large);
+        assert_eq!(cache.get(&key(&alice, "select $1", "0/10")), None);
+    }
+}
@@ -0,0 +1,503 @@
+//! Encoders for the results of SQL-over-HTTP queries.
+//!
+//! The output format is negotiated with the `Accept` request header. JSON compatible with
+//! node-postgres results stays the default; other formats are opt-in.
+
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    BooleanBuilder, Float32Builder, Float64Builder, Int16Builder, Int32Builder, Int64Builder,
+    StringBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use bytes::BytesMut;
+use hyper1::header;
+use hyper1::http::HeaderMap;
+use pq_proto::{BeMessage, RowDescriptor};
+use serde_json::json;
+use serde_json::Value;
+use tokio_postgres::types::{FromSql, Type};
+use tokio_postgres::Row;
+
+use crate::metrics::HttpResponseFormat;
+
+use super::json::pg_text_row_to_json;
+use super::json::JsonConversionError;
+
+/// Description of a single column of a query result.
+pub struct FieldInfo {
+    pub name: String,
+    pub type_: Type,
+    pub table_oid: Option<u32>,
+    pub column_id: Option<i16>,
+    pub type_size: i16,
+    pub type_modifier: i32,
+}
+
+/// Result of a single query, before it was encoded into the response format.
+pub struct QueryResult {
+    pub command_tag: String,
+    pub row_count: Option<i64>,
+    pub fields: Vec<FieldInfo>,
+    pub rows: Vec<Row>,
+    pub array_mode: bool,
+    pub raw_output: bool,
+}
+
+impl QueryResult {
+    fn command_tag_name(&self) -> &str {
+        self.command_tag.split(' ').next().unwrap_or_default()
+    }
+
+    fn types(&self) -> Vec<Type> {
+        self.fields.iter().map(|f| f.type_.clone()).collect()
+    }
+}
+
+/// Results of all the queries of a request.
+pub enum QueryResults {
+    Single(QueryResult),
+    Batch(Vec<QueryResult>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResultEncodingError {
+    #[error("{0}")]
+    JsonConversion(#[from] JsonConversionError),
+    #[error("internal error compute returned invalid data: {0}")]
+    AsText(tokio_postgres::Error),
+    #[error("internal error compute returned invalid data: {0}")]
+    Binary(tokio_postgres::Error),
+    #[error("could not convert value {value:?} of column {column:?}")]
+    InvalidValue { column: String, value: String },
+    #[error("could not encode the postgres response: {0}")]
+    PgProtocol(#[from] pq_proto::ProtocolError),
+    #[error("could not encode arrow response: {0}")]
+    Arrow(#[from] ArrowError),
+    #[error("{0} output format does not support batch queries")]
+    BatchNotSupported(&'static str),
+}
+
+/// Output formats of SQL-over-HTTP responses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResultFormat {
+    /// node-postgres compatible JSON.
+    Json,
+    /// JSON with the rows in array mode and only the essential field metadata.
+    CompactJson,
+    /// Sequence of Postgres protocol `RowDescription`, `DataRow` and `CommandComplete`
+    /// messages, exactly as compute would have sent them, with the values in binary format.
+    PgBinary,
+    /// Apache Arrow IPC stream.
+    ArrowIpc,
+}
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+const COMPACT_JSON_CONTENT_TYPE: &str = "application/vnd.neon.compact+json";
+const PG_BINARY_CONTENT_TYPE: &str = "application/vnd.neon.pg-binary";
+const ARROW_IPC_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+
+impl ResultFormat {
+    /// Pick the output format based on the `Accept` header of the request.
+    ///
+    /// Media types are tried in the order of their quality values. Returns `None` if the
+    /// client accepts none of the supported formats.
+    pub fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        let Some(accept) = headers.get(header::ACCEPT) else {
+            return Some(ResultFormat::Json);
+        };
+        let accept = accept.to_str().ok()?;
+
+        let mut candidates = accept
+            .split(',')
+            .filter_map(|media_range| {
+                let mut parts = media_range.split(';').map(str::trim);
+                let media_type = parts.next()?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map(|q| q.parse::<f32>().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+                Some((media_type, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect::<Vec<_>>();
+        // stable sort keeps the client order between the media types of the same quality
+        candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        candidates
+            .into_iter()
+            .find_map(|(media_type, _)| Self::from_media_type(media_type))
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            JSON_CONTENT_TYPE | "application/*" | "*/*" => Some(ResultFormat::Json),
+            COMPACT_JSON_CONTENT_TYPE => Some(ResultFormat::CompactJson),
+            PG_BINARY_CONTENT_TYPE => Some(ResultFormat::PgBinary),
+            ARROW_IPC_CONTENT_TYPE => Some(ResultFormat::ArrowIpc),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        self.encoder().content_type()
+    }
+
+    pub fn encoder(self) -> &'static dyn ResultEncoder {
+        match self {
+            ResultFormat::Json => &JsonEncoder,
+            ResultFormat::CompactJson => &CompactJsonEncoder,
+            ResultFormat::PgBinary => &PgBinaryEncoder,
+            ResultFormat::ArrowIpc => &ArrowIpcEncoder,
+        }
+    }
+
+    /// Whether the rows are requested from compute in binary format rather than text, see
+    /// [`PgBinaryEncoder`].
+    pub fn binary_values(self) -> bool {
+        self == ResultFormat::PgBinary
+    }
+
+    pub fn metric_label(self) -> HttpResponseFormat {
+        match self {
+            ResultFormat::Json => HttpResponseFormat::Json,
+            ResultFormat::CompactJson => HttpResponseFormat::CompactJson,
+            ResultFormat::PgBinary => HttpResponseFormat::PgBinary,
+            ResultFormat::ArrowIpc => HttpResponseFormat::ArrowIpc,
+        }
+    }
+}
+
+/// Turns query results into the body of the HTTP response.
+pub trait ResultEncoder: Sync {
+    fn content_type(&self) -> &'static str;
+
+    fn encode(&self, results: &QueryResults) -> Result<Vec<u8>, ResultEncodingError>;
+}
+
+/// node-postgres compatible JSON.
+struct JsonEncoder;
+
+impl JsonEncoder {
+    fn result_to_json(result: &QueryResult) -> Result<Value, ResultEncodingError> {
+        let fields = result
+            .fields
+            .iter()
+            .map(|f| {
+                json!({
+                    "name": Value::String(f.name.clone()),
+                    "dataTypeID": Value::Number(f.type_.oid().into()),
+                    "tableID": f.table_oid,
+                    "columnID": f.column_id,
+                    "dataTypeSize": f.type_size,
+                    "dataTypeModifier": f.type_modifier,
+                    "format": "text",
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let types = result.types();
+        let rows = result
+            .rows
+            .iter()
+            .map(|row| pg_text_row_to_json(row, &types, result.raw_output, result.array_mode))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // resulting JSON format is based on the format of node-postgres result
+        Ok(json!({
+            "command": result.command_tag_name(),
+            "rowCount": result.row_count,
+            "rows": rows,
+            "fields": fields,
+            "rowAsArray": result.array_mode,
+        }))
+    }
+}
+
+impl ResultEncoder for JsonEncoder {
+    fn content_type(&self) -> &'static str {
+        JSON_CONTENT_TYPE
+    }
+
+    fn encode(&self, results: &QueryResults) -> Result<Vec<u8>, ResultEncodingError> {
+        let value = match results {
+            QueryResults::Single(result) => Self::result_to_json(result)?,
+            QueryResults::Batch(results) => {
+                let results = results
+                    .iter()
+                    .map(Self::result_to_json)
+                    .collect::<Result<Vec<_>, _>>()?;
+                json!({ "results": results })
+            }
+        };
+        // how could this possibly fail
+        Ok(serde_json::to_vec(&value).expect("json serialization should not fail"))
+    }
+}
+
+/// JSON without the per-row keys and most of the field metadata.
+struct CompactJsonEncoder;
+
+impl CompactJsonEncoder {
+    fn result_to_json(result: &QueryResult) -> Result<Value, ResultEncodingError> {
+        let fields = result
+            .fields
+            .iter()
+            .map(|f| json!([f.name, f.type_.oid()]))
+            .collect::<Vec<_>>();
+
+        let types = result.types();
+        let rows = result
+            .rows
+            .iter()
+            .map(|row| pg_text_row_to_json(row, &types, result.raw_output, true))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(json!({
+            "command": result.command_tag_name(),
+            "rowCount": result.row_count,
+            "fields": fields,
+            "rows": rows,
+        }))
+    }
+}
+
+impl ResultEncoder for CompactJsonEncoder {
+    fn content_type(&self) -> &'static str {
+        COMPACT_JSON_CONTENT_TYPE
+    }
+
+    fn encode(&self, results: &QueryResults) -> Result<Vec<u8>, ResultEncodingError> {
+        let value = match results {
+            QueryResults::Single(result) => Self::result_to_json(result)?,
+            QueryResults::Batch(results) => Value::Array(
+                results
+                    .iter()
+                    .map(Self::result_to_json)
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+        };
+        Ok(serde_json::to_vec(&value).expect("json serialization should not fail"))
+    }
+}
+
+/// Postgres protocol messages, with the values in binary format (format code 1): the rows are
+/// requested from compute in binary format, and their values are passed through unchanged.
+struct PgBinaryEncoder;
+
+/// A value of any type, in the format compute sent it.
+struct RawValue<'a>(&'a [u8]);
+
+impl<'a> FromSql<'a> for RawValue<'a> {
+    fn from_sql(
+        _ty: &Type,
+        raw: &'a [u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(RawValue(raw))
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+}
+
+impl PgBinaryEncoder {
+    fn write_result(buf: &mut BytesMut, result: &QueryResult) -> Result<(), ResultEncodingError> {
+        let descriptors = result
+            .fields
+            .iter()
+            .map(|f| RowDescriptor {
+                name: f.name.as_bytes(),
+                tableoid: f.table_oid.unwrap_or(0),
+                attnum: f.column_id.unwrap_or(0),
+                typoid: f.type_.oid(),
+                typlen: f.type_size,
+                typmod: f.type_modifier,
+                formatcode: 1,
+            })
+            .collect::<Vec<_>>();
+        BeMessage::write(buf, &BeMessage::RowDescription(&descriptors))?;
+
+        let mut values = Vec::with_capacity(result.fields.len());
+        for row in &result.rows {
+            values.clear();
+            for i in 0..result.fields.len() {
+                let value = row
+                    .try_get::<_, Option<RawValue>>(i)
+                    .map_err(ResultEncodingError::Binary)?;
+                values.push(value.map(|RawValue(raw)| raw));
+            }
+            BeMessage::write(buf, &BeMessage::DataRow(&values))?;
+        }
+
+        BeMessage::write(
+            buf,
+            &BeMessage::CommandComplete(result.command_tag.as_bytes()),
+        )?;
+        Ok(())
+    }
+}
+
+impl ResultEncoder for PgBinaryEncoder {
+    fn content_type(&self) -> &'static str {
+        PG_BINARY_CONTENT_TYPE
+    }
+
+    fn encode(&self, results: &QueryResults) -> Result<Vec<u8>, ResultEncodingError> {
+        let mut buf = BytesMut::new();
+        match results {
+            QueryResults::Single(result) => Self::write_result(&mut buf, result)?,
+            QueryResults::Batch(results) => {
+                for result in results {
+                    Self::write_result(&mut buf, result)?;
+                }
+            }
+        }
+        Ok(buf.to_vec())
+    }
+}
+
+/// Apache Arrow IPC stream with a single record batch.
+///
+/// Booleans, integers and floats are mapped to the native arrow types, everything else
+/// is passed as utf8 in the postgres text format.
+struct ArrowIpcEncoder;
+
+impl ArrowIpcEncoder {
+    fn data_type(pg_type: &Type) -> DataType {
+        match *pg_type {
+            Type::BOOL => DataType::Boolean,
+            Type::INT2 => DataType::Int16,
+            Type::INT4 => DataType::Int32,
+            Type::INT8 => DataType::Int64,
+            Type::FLOAT4 => DataType::Float32,
+            Type::FLOAT8 => DataType::Float64,
+            _ => DataType::Utf8,
+        }
+    }
+
+    fn column(result: &QueryResult, i: usize) -> Result<ArrayRef, ResultEncodingError> {
+        let field = &result.fields[i];
+        let mut values = Vec::with_capacity(result.rows.len());
+        for row in &result.rows {
+            values.push(row.as_text(i).map_err(ResultEncodingError::AsText)?);
+        }
+
+        fn parse<T: std::str::FromStr>(
+            field: &FieldInfo,
+            value: Option<&str>,
+        ) -> Result<Option<T>, ResultEncodingError> {
+            value
+                .map(|v| {
+                    v.parse::<T>()
+                        .map_err(|_| ResultEncodingError::InvalidValue {
+                            column: field.name.clone(),
+                            value: v.to_owned(),
+                        })
+                })
+                .transpose()
+        }
+
+        macro_rules! build {
+            ($builder:ty, $parse:expr) => {{
+                let mut builder = <$builder>::with_capacity(values.len());
+                for value in &values {
+                    builder.append_option($parse(*value)?);
+                }
+                Arc::new(builder.finish()) as ArrayRef
+            }};
+        }
+
+        let array = match Self::data_type(&field.type_) {
+            DataType::Boolean => build!(BooleanBuilder, |v: Option<&str>| {
+                Ok::<_, ResultEncodingError>(v.map(|v| v == "t"))
+            }),
+            DataType::Int16 => build!(Int16Builder, |v| parse::<i16>(field, v)),
+            DataType::Int32 => build!(Int32Builder, |v| parse::<i32>(field, v)),
+            DataType::Int64 => build!(Int64Builder, |v| parse::<i64>(field, v)),
+            DataType::Float32 => build!(Float32Builder, |v| parse::<f32>(field, v)),
+            DataType::Float64 => build!(Float64Builder, |v| parse::<f64>(field, v)),
+            _ => {
+                let mut builder = StringBuilder::new();
+                for value in &values {
+                    builder.append_option(*value);
+                }
+                Arc::new(builder.finish()) as ArrayRef
+            }
+        };
+        Ok(array)
+    }
+}
+
+impl ResultEncoder for ArrowIpcEncoder {
+    fn content_type(&self) -> &'static str {
+        ARROW_IPC_CONTENT_TYPE
+    }
+
+    fn encode(&self, results: &QueryResults) -> Result<Vec<u8>, ResultEncodingError> {
+        let result = match results {
+            QueryResults::Single(result) => result,
+            QueryResults::Batch(_) => return Err(ResultEncodingError::BatchNotSupported("arrow")),
+        };
+
+        let schema = Arc::new(Schema::new(
+            result
+                .fields
+                .iter()
+                .map(|f| Field::new(&f.name, Self::data_type(&f.type_), true))
+                .collect::<Vec<_>>(),
+        ));
+        let columns = (0..result.fields.len())
+            .map(|i| Self::column(result, i))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut buf = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut buf, &schema)?;
+        if !columns.is_empty() {
+            writer.write(&RecordBatch::try_new(schema, columns)?)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper1::http::HeaderValue;
+
+    fn negotiate(accept: &str) -> Option<ResultFormat> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+        ResultFormat::negotiate(&headers)
+    }
+
+    #[test]
+    fn negotiate_result_format() {
+        assert_eq!(
+            ResultFormat::negotiate(&HeaderMap::new()),
+            Some(ResultFormat::Json)
+        );
+        assert_eq!(negotiate("*/*"), Some(ResultFormat::Json));
+        assert_eq!(
+            negotiate("application/vnd.apache.arrow.stream"),
+            Some(ResultFormat::ArrowIpc)
+        );
+        assert_eq!(
+            negotiate("text/html, application/vnd.neon.pg-binary"),
+            Some(ResultFormat::PgBinary)
+        );
+        assert_eq!(
+            negotiate("application/json;q=0.5, application/vnd.neon.compact+json"),
+            Some(ResultFormat::CompactJson)
+        );
+        assert_eq!(
+            negotiate("application/vnd.apache.arrow.stream;q=0, application/json"),
+            Some(ResultFormat::Json)
+        );
+        assert_eq!(negotiate("text/html"), None);
+    }
+}
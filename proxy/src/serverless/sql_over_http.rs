@@ -23,11 +23,11 @@ use tokio::time;
 use tokio_postgres::error::DbError;
 use tokio_postgres::error::ErrorPosition;
 use tokio_postgres::error::SqlState;
+use tokio_postgres::types::Oid;
 use tokio_postgres::GenericClient;
 use tokio_postgres::IsolationLevel;
 use tokio_postgres::NoTls;
 use tokio_postgres::ReadyForQueryStatus;
-use tokio_postgres::Transaction;
 use tokio_util::sync::CancellationToken;
 use tracing::error;
 use tracing::info;
@@ -40,36 +40,89 @@ use crate::auth::ComputeUserInfoParseError;
 use crate::config::ProxyConfig;
 use crate::config::TlsConfig;
 use crate::context::RequestMonitoring;
+use crate::error::ErrorCode;
 use crate::error::ErrorKind;
 use crate::error::ReportableError;
 use crate::error::UserFacingError;
+use crate::metrics::CacheOutcome;
 use crate::metrics::HttpDirection;
 use crate::metrics::Metrics;
 use crate::proxy::run_until_cancelled;
 use crate::proxy::NeonOptions;
 use crate::serverless::backend::HttpConnError;
-use crate::usage_metrics::MetricCounterRecorder;
 use crate::DbName;
 use crate::RoleName;
 
 use super::backend::PoolingBackend;
 use super::conn_pool::Client;
+use super::conn_pool::ClientMetrics;
 use super::conn_pool::ConnInfo;
+use super::conn_pool::TransactionSessionError;
 use super::http_util::json_response;
-use super::json::json_to_pg_text;
-use super::json::pg_text_row_to_json;
-use super::json::JsonConversionError;
+use super::params;
+use super::params::ParamError;
+use super::params::ParamFormat;
+use super::params::TextParam;
+use super::result_cache;
+use super::result_cache::ResultCache;
+use super::result_cache::APPLIED_LSN_QUERY;
+use super::result_format::FieldInfo;
+use super::result_format::QueryResult;
+use super::result_format::QueryResults;
+use super::result_format::ResultEncodingError;
+use super::result_format::ResultFormat;
+
+/// The SQL over HTTP endpoints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SqlEndpoint {
+    /// `/sql`: run a query or a batch of queries, in the transaction session of the request
+    /// if it has one.
+    Query,
+    /// `/sql/transaction`: begin a transaction session.
+    Begin,
+    /// `/sql/transaction/commit`: commit the transaction of a session, and end it.
+    Commit,
+    /// `/sql/transaction/rollback`: roll back the transaction of a session, and end it.
+    Rollback,
+    /// `/sql/cache/invalidate`: drop the cached responses of the endpoint.
+    InvalidateCache,
+}
+
+impl SqlEndpoint {
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path {
+            "/sql" => Some(SqlEndpoint::Query),
+            "/sql/transaction" => Some(SqlEndpoint::Begin),
+            "/sql/transaction/commit" => Some(SqlEndpoint::Commit),
+            "/sql/transaction/rollback" => Some(SqlEndpoint::Rollback),
+            "/sql/cache/invalidate" => Some(SqlEndpoint::InvalidateCache),
+            _ => None,
+        }
+    }
+}
 
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct QueryData {
     query: String,
-    #[serde(deserialize_with = "bytes_to_pg_text")]
-    params: Vec<Option<String>>,
+    params: Vec<Value>,
+    /// The type OIDs of the parameters, see [`super::params`].
+    #[serde(default)]
+    param_types: Vec<Option<Oid>>,
+    #[serde(default)]
+    param_formats: Vec<ParamFormat>,
     #[serde(default)]
     array_mode: Option<bool>,
 }
 
+impl QueryData {
+    /// The text values of the parameters.
+    fn pg_params(&self) -> Result<Vec<Option<String>>, ParamError> {
+        params::check_lengths(&self.params, &self.param_types, &self.param_formats)?;
+        params::to_pg_text(&self.params, &self.param_formats)
+    }
+}
+
 #[derive(serde::Deserialize)]
 struct BatchQueryData {
     queries: Vec<QueryData>,
@@ -91,18 +144,11 @@ static ALLOW_POOL: HeaderName = HeaderName::from_static("neon-pool-opt-in");
 static TXN_ISOLATION_LEVEL: HeaderName = HeaderName::from_static("neon-batch-isolation-level");
 static TXN_READ_ONLY: HeaderName = HeaderName::from_static("neon-batch-read-only");
 static TXN_DEFERRABLE: HeaderName = HeaderName::from_static("neon-batch-deferrable");
+static TXN_SESSION: HeaderName = HeaderName::from_static("neon-transaction-session");
+static RESULT_CACHE: HeaderName = HeaderName::from_static("neon-result-cache");
 
 static HEADER_VALUE_TRUE: HeaderValue = HeaderValue::from_static("true");
 
-fn bytes_to_pg_text<'de, D>(deserializer: D) -> Result<Vec<Option<String>>, D::Error>
-where
-    D: serde::de::Deserializer<'de>,
-{
-    // TODO: consider avoiding the allocation here.
-    let json: Vec<Value> = serde::de::Deserialize::deserialize(deserializer)?;
-    Ok(json_to_pg_text(json))
-}
-
 #[derive(Debug, thiserror::Error)]
 pub enum ConnInfoError {
     #[error("invalid header: {0}")]
@@ -222,11 +268,12 @@ fn get_conn_info(
 pub async fn handle(
     config: &'static ProxyConfig,
     mut ctx: RequestMonitoring,
+    endpoint: SqlEndpoint,
     request: Request<Incoming>,
     backend: Arc<PoolingBackend>,
     cancel: CancellationToken,
 ) -> Result<Response<Full<Bytes>>, ApiError> {
-    let result = handle_inner(cancel, config, &mut ctx, request, backend).await;
+    let result = handle_inner(cancel, config, &mut ctx, endpoint, request, backend).await;
 
     let mut response = match result {
         Ok(r) => {
@@ -248,12 +295,17 @@ pub async fn handle(
 
             json_response(
                 StatusCode::BAD_REQUEST,
-                json!({ "message": message, "code": SqlState::PROTOCOL_VIOLATION.code() }),
+                json!({
+                    "message": message,
+                    "code": SqlState::PROTOCOL_VIOLATION.code(),
+                    "neonErrorCode": e.get_error_code().as_str(),
+                }),
             )?
         }
         Err(e) => {
             let error_kind = e.get_error_kind();
             ctx.set_error_kind(error_kind);
+            let error_code = e.get_error_code();
 
             let mut message = e.to_string_client();
             let db_error = match &e {
@@ -289,7 +341,6 @@ pub async fn handle(
                 None => (Value::Null, Value::Null, Value::Null),
             };
 
-            let code = get(db_error, |db| db.code().code());
             let severity = get(db_error, |db| db.severity());
             let detail = get(db_error, |db| db.detail());
             let hint = get(db_error, |db| db.hint());
@@ -305,6 +356,7 @@ pub async fn handle(
 
             tracing::info!(
                 kind=error_kind.to_metric_label(),
+                code=error_code.as_str(),
                 error=%e,
                 msg=message,
                 "forwarding error to user"
@@ -315,7 +367,9 @@ pub async fn handle(
                 StatusCode::BAD_REQUEST,
                 json!({
                     "message": message,
-                    "code": code,
+                    // That of Postgres for its errors, see `ErrorCode::from_postgres`.
+                    "code": error_code.sqlstate(),
+                    "neonErrorCode": error_code.as_str(),
                     "detail": detail,
                     "hint": hint,
                     "position": position,
@@ -350,16 +404,28 @@ pub enum SqlOverHttpError {
     ConnectCompute(#[from] HttpConnError),
     #[error("{0}")]
     ConnInfo(#[from] ConnInfoError),
+    #[error("{0}")]
+    Params(#[from] ParamError),
     #[error("request is too large (max is {MAX_REQUEST_SIZE} bytes)")]
     RequestTooLarge,
     #[error("response is too large (max is {MAX_RESPONSE_SIZE} bytes)")]
     ResponseTooLarge,
     #[error("invalid isolation level")]
     InvalidIsolationLevel,
+    #[error("invalid transaction session token")]
+    InvalidTransactionSession,
+    #[error("transaction session token is missing")]
+    MissingTransactionSession,
+    #[error("request is already in a transaction session")]
+    AlreadyInTransactionSession,
+    #[error("{0}")]
+    TransactionSession(#[from] TransactionSessionError),
+    #[error("none of the requested output formats is supported")]
+    UnsupportedResultFormat,
     #[error("{0}")]
     Postgres(#[from] tokio_postgres::Error),
     #[error("{0}")]
-    JsonConversion(#[from] JsonConversionError),
+    ResultEncoding(#[from] ResultEncodingError),
     #[error("{0}")]
     Cancelled(SqlOverHttpCancel),
 }
@@ -370,11 +436,20 @@ impl ReportableError for SqlOverHttpError {
             SqlOverHttpError::ReadPayload(e) => e.get_error_kind(),
             SqlOverHttpError::ConnectCompute(e) => e.get_error_kind(),
             SqlOverHttpError::ConnInfo(e) => e.get_error_kind(),
+            SqlOverHttpError::Params(e) => e.get_error_kind(),
             SqlOverHttpError::RequestTooLarge => ErrorKind::User,
             SqlOverHttpError::ResponseTooLarge => ErrorKind::User,
             SqlOverHttpError::InvalidIsolationLevel => ErrorKind::User,
+            SqlOverHttpError::InvalidTransactionSession => ErrorKind::User,
+            SqlOverHttpError::MissingTransactionSession => ErrorKind::User,
+            SqlOverHttpError::AlreadyInTransactionSession => ErrorKind::User,
+            SqlOverHttpError::TransactionSession(e) => e.get_error_kind(),
+            SqlOverHttpError::UnsupportedResultFormat => ErrorKind::User,
             SqlOverHttpError::Postgres(p) => p.get_error_kind(),
-            SqlOverHttpError::JsonConversion(_) => ErrorKind::Postgres,
+            SqlOverHttpError::ResultEncoding(ResultEncodingError::BatchNotSupported(_)) => {
+                ErrorKind::User
+            }
+            SqlOverHttpError::ResultEncoding(_) => ErrorKind::Postgres,
             SqlOverHttpError::Cancelled(c) => c.get_error_kind(),
         }
     }
@@ -386,14 +461,46 @@ impl UserFacingError for SqlOverHttpError {
             SqlOverHttpError::ReadPayload(p) => p.to_string(),
             SqlOverHttpError::ConnectCompute(c) => c.to_string_client(),
             SqlOverHttpError::ConnInfo(c) => c.to_string_client(),
+            SqlOverHttpError::Params(p) => p.to_string_client(),
             SqlOverHttpError::RequestTooLarge => self.to_string(),
             SqlOverHttpError::ResponseTooLarge => self.to_string(),
             SqlOverHttpError::InvalidIsolationLevel => self.to_string(),
+            SqlOverHttpError::InvalidTransactionSession => self.to_string(),
+            SqlOverHttpError::MissingTransactionSession => self.to_string(),
+            SqlOverHttpError::AlreadyInTransactionSession => self.to_string(),
+            SqlOverHttpError::TransactionSession(e) => e.to_string_client(),
+            SqlOverHttpError::UnsupportedResultFormat => self.to_string(),
             SqlOverHttpError::Postgres(p) => p.to_string(),
-            SqlOverHttpError::JsonConversion(_) => "could not parse postgres response".to_string(),
+            SqlOverHttpError::ResultEncoding(e @ ResultEncodingError::BatchNotSupported(_)) => {
+                e.to_string()
+            }
+            SqlOverHttpError::ResultEncoding(_) => "could not parse postgres response".to_string(),
             SqlOverHttpError::Cancelled(_) => self.to_string(),
         }
     }
+
+    fn get_error_code(&self) -> ErrorCode {
+        match self {
+            SqlOverHttpError::ReadPayload(_) => ErrorCode::BadRequest,
+            SqlOverHttpError::ConnectCompute(e) => e.get_error_code(),
+            SqlOverHttpError::ConnInfo(e) => e.get_error_code(),
+            SqlOverHttpError::Params(e) => e.get_error_code(),
+            SqlOverHttpError::RequestTooLarge => ErrorCode::LimitExceeded,
+            SqlOverHttpError::ResponseTooLarge => ErrorCode::LimitExceeded,
+            SqlOverHttpError::InvalidIsolationLevel => ErrorCode::BadRequest,
+            SqlOverHttpError::InvalidTransactionSession => ErrorCode::BadRequest,
+            SqlOverHttpError::MissingTransactionSession => ErrorCode::BadRequest,
+            SqlOverHttpError::AlreadyInTransactionSession => ErrorCode::BadRequest,
+            SqlOverHttpError::TransactionSession(e) => e.get_error_code(),
+            SqlOverHttpError::UnsupportedResultFormat => ErrorCode::BadRequest,
+            SqlOverHttpError::Postgres(p) => ErrorCode::from_postgres(p),
+            SqlOverHttpError::ResultEncoding(ResultEncodingError::BatchNotSupported(_)) => {
+                ErrorCode::BadRequest
+            }
+            SqlOverHttpError::ResultEncoding(_) => ErrorCode::Internal,
+            SqlOverHttpError::Cancelled(_) => ErrorCode::QueryCancelled,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -437,6 +544,9 @@ struct HttpHeaders {
     txn_isolation_level: Option<IsolationLevel>,
     txn_read_only: bool,
     txn_deferrable: bool,
+    txn_session: Option<uuid::Uuid>,
+    result_cache: bool,
+    result_format: ResultFormat,
 }
 
 impl HttpHeaders {
@@ -457,12 +567,30 @@ impl HttpHeaders {
         let txn_read_only = headers.get(&TXN_READ_ONLY) == Some(&HEADER_VALUE_TRUE);
         let txn_deferrable = headers.get(&TXN_DEFERRABLE) == Some(&HEADER_VALUE_TRUE);
 
+        let txn_session = match headers.get(&TXN_SESSION) {
+            Some(x) => Some(
+                x.to_str()
+                    .ok()
+                    .and_then(|x| x.parse().ok())
+                    .ok_or(SqlOverHttpError::InvalidTransactionSession)?,
+            ),
+            None => None,
+        };
+
+        let result_cache = headers.get(&RESULT_CACHE) == Some(&HEADER_VALUE_TRUE);
+
+        let result_format =
+            ResultFormat::negotiate(headers).ok_or(SqlOverHttpError::UnsupportedResultFormat)?;
+
         Ok(Self {
             raw_output,
             default_array_mode,
             txn_isolation_level,
             txn_read_only,
             txn_deferrable,
+            txn_session,
+            result_cache,
+            result_format,
         })
     }
 }
@@ -491,6 +619,7 @@ async fn handle_inner(
     cancel: CancellationToken,
     config: &'static ProxyConfig,
     ctx: &mut RequestMonitoring,
+    endpoint: SqlEndpoint,
     request: Request<Incoming>,
     backend: Arc<PoolingBackend>,
 ) -> Result<Response<Full<Bytes>>, SqlOverHttpError> {
@@ -515,6 +644,7 @@ async fn handle_inner(
         || headers.get(&ALLOW_POOL) == Some(&HEADER_VALUE_TRUE);
 
     let parsed_headers = HttpHeaders::try_parse(headers)?;
+    let result_format = parsed_headers.result_format;
 
     let request_content_length = match request.body().size_hint().upper() {
         Some(v) => v,
@@ -532,13 +662,72 @@ async fn handle_inner(
         return Err(SqlOverHttpError::RequestTooLarge);
     }
 
-    let fetch_and_process_request = async {
-        let body = request.into_body().collect().await?.to_bytes();
-        info!(length = body.len(), "request payload read");
-        let payload: Payload = serde_json::from_slice(&body)?;
-        Ok::<Payload, ReadPayloadError>(payload) // Adjust error type accordingly
+    match (endpoint, parsed_headers.txn_session) {
+        (SqlEndpoint::Query, None) => {}
+        (SqlEndpoint::Query, Some(token)) => {
+            return query_in_session(
+                cancel,
+                config,
+                ctx,
+                request,
+                backend,
+                conn_info,
+                token,
+                parsed_headers,
+                result_format,
+            )
+            .await
+        }
+        (SqlEndpoint::Begin, None) => {
+            return begin_transaction(
+                cancel,
+                config,
+                ctx,
+                backend,
+                conn_info,
+                allow_pool,
+                parsed_headers,
+            )
+            .await
+        }
+        (SqlEndpoint::Begin, Some(_)) => return Err(SqlOverHttpError::AlreadyInTransactionSession),
+        (SqlEndpoint::Commit | SqlEndpoint::Rollback, Some(token)) => {
+            return end_transaction(
+                config,
+                ctx,
+                backend,
+                conn_info,
+                token,
+                endpoint == SqlEndpoint::Commit,
+                parsed_headers,
+                result_format,
+            )
+            .await
+        }
+        (SqlEndpoint::Commit | SqlEndpoint::Rollback, None) => {
+            return Err(SqlOverHttpError::MissingTransactionSession)
+        }
+        (SqlEndpoint::InvalidateCache, _) => {
+            backend
+                .authenticate(ctx, &config.authentication_config, &conn_info)
+                .await
+                .map_err(HttpConnError::from)?;
+            ctx.latency_timer.success();
+            backend.result_cache.invalidate(&conn_info);
+            info!("invalidated result cache");
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Full::new(Bytes::from_static(b"{}")))
+                .expect("building response payload should not fail");
+            return Ok(response);
+        }
     }
-    .map_err(SqlOverHttpError::from);
+
+    let cache_conn_info = (parsed_headers.result_cache && backend.result_cache.is_enabled())
+        .then(|| conn_info.clone());
+
+    let fetch_and_process_request = read_payload(request).map_err(SqlOverHttpError::from);
 
     let authenticate_and_connect = async {
         let keys = backend
@@ -570,13 +759,31 @@ async fn handle_inner(
 
     let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/json");
+        .header(header::CONTENT_TYPE, result_format.content_type());
 
     //
     // Now execute the query and return the result
     //
+    let payload = match (payload, cache_conn_info) {
+        (Payload::Single(stmt), Some(conn_info)) => {
+            return query_with_result_cache(
+                cancel,
+                &backend.result_cache,
+                &mut client,
+                &conn_info,
+                stmt,
+                parsed_headers,
+                result_format,
+                response,
+            )
+            .await
+        }
+        (payload, _) => payload,
+    };
     let result = match payload {
-        Payload::Single(stmt) => stmt.process(cancel, &mut client, parsed_headers).await?,
+        Payload::Single(stmt) => {
+            QueryResults::Single(stmt.process(cancel, &mut client, parsed_headers).await?)
+        }
         Payload::Batch(statements) => {
             if parsed_headers.txn_read_only {
                 response = response.header(TXN_READ_ONLY.clone(), &HEADER_VALUE_TRUE);
@@ -591,19 +798,49 @@ async fn handle_inner(
                 response = response.header(TXN_ISOLATION_LEVEL.clone(), txn_isolation_level);
             }
 
-            statements
-                .process(cancel, &mut client, parsed_headers)
-                .await?
+            QueryResults::Batch(
+                statements
+                    .process(cancel, &mut client, parsed_headers)
+                    .await?,
+            )
         }
     };
 
     let metrics = client.metrics();
+    query_response(response, result_format, &result, metrics)
+}
+
+async fn read_payload(request: Request<Incoming>) -> Result<Payload, ReadPayloadError> {
+    let body = request.into_body().collect().await?.to_bytes();
+    info!(length = body.len(), "request payload read");
+    let payload: Payload = serde_json::from_slice(&body)?;
+    Ok(payload)
+}
 
-    // how could this possibly fail
-    let body = serde_json::to_string(&result).expect("json serialization should not fail");
+fn query_response(
+    response: hyper1::http::response::Builder,
+    result_format: ResultFormat,
+    result: &QueryResults,
+    metrics: ClientMetrics,
+) -> Result<Response<Full<Bytes>>, SqlOverHttpError> {
+    let body = result_format.encoder().encode(result)?;
+    Ok(body_response(
+        response,
+        result_format,
+        Bytes::from(body),
+        metrics,
+    ))
+}
+
+fn body_response(
+    response: hyper1::http::response::Builder,
+    result_format: ResultFormat,
+    body: Bytes,
+    metrics: ClientMetrics,
+) -> Response<Full<Bytes>> {
     let len = body.len();
     let response = response
-        .body(Full::new(Bytes::from(body)))
+        .body(Full::new(body))
         // only fails if invalid status code or invalid header/values are given.
         // these are not user configurable so it cannot fail dynamically
         .expect("building response payload should not fail");
@@ -615,22 +852,293 @@ async fn handle_inner(
         .proxy
         .http_conn_content_length_bytes
         .observe(HttpDirection::Response, len as f64);
+    Metrics::get()
+        .proxy
+        .http_response_format_bytes
+        .observe(result_format.metric_label(), len as f64);
+
+    response
+}
+
+/// Run a single query with the result cache, see [`super::result_cache`].
+#[allow(clippy::too_many_arguments)]
+async fn query_with_result_cache(
+    cancel: CancellationToken,
+    cache: &ResultCache,
+    client: &mut Client<tokio_postgres::Client>,
+    conn_info: &ConnInfo,
+    stmt: QueryData,
+    parsed_headers: HttpHeaders,
+    result_format: ResultFormat,
+    response: hyper1::http::response::Builder,
+) -> Result<Response<Full<Bytes>>, SqlOverHttpError> {
+    let metrics = client.metrics();
+
+    let (inner, mut discard) = client.inner();
+    let lsn = match inner.query_one(APPLIED_LSN_QUERY, &[]).await {
+        Ok(row) => row.try_get::<_, Option<String>>(0)?,
+        Err(e) => {
+            discard.discard();
+            return Err(e.into());
+        }
+    };
+    let array_mode = stmt.array_mode.unwrap_or(parsed_headers.default_array_mode);
+    let params = stmt.pg_params()?;
+    // No LSN while a replica starts up.
+    let key = lsn.and_then(|lsn| {
+        cache.key(
+            conn_info,
+            &stmt.query,
+            &params,
+            &stmt.param_types,
+            &stmt.param_formats,
+            array_mode,
+            parsed_headers.raw_output,
+            result_format,
+            lsn,
+        )
+    });
+
+    if let Some(body) = key.as_ref().and_then(|key| cache.get(key)) {
+        info!("result cache hit");
+        Metrics::get()
+            .proxy
+            .http_result_cache_lookups
+            .inc(CacheOutcome::Hit);
+        let response = response.header(RESULT_CACHE.clone(), "hit");
+        return Ok(body_response(response, result_format, body, metrics));
+    }
+    Metrics::get()
+        .proxy
+        .http_result_cache_lookups
+        .inc(CacheOutcome::Miss);
+
+    let key = match key {
+        Some(key) => {
+            let (inner, mut discard) = client.inner();
+            match result_cache::calls_only_immutable_functions(inner, &stmt.query).await {
+                Ok(true) => Some(key),
+                Ok(false) => {
+                    info!("result cache: the query calls functions that are not immutable");
+                    None
+                }
+                Err(e) => {
+                    discard.discard();
+                    return Err(e.into());
+                }
+            }
+        }
+        None => None,
+    };
+
+    // The query must not write anything, as it won't run again for the next requests.
+    let parsed_headers = HttpHeaders {
+        txn_read_only: true,
+        ..parsed_headers
+    };
+    let batch = BatchQueryData {
+        queries: vec![stmt],
+    };
+    let mut results = batch.process(cancel, client, parsed_headers).await?;
+    let result = QueryResults::Single(results.pop().expect("a result for each query"));
+    let body = Bytes::from(result_format.encoder().encode(&result)?);
+    if let Some(key) = key {
+        cache.insert(key, body.clone());
+    }
+    let response = response.header(RESULT_CACHE.clone(), "miss");
+    Ok(body_response(response, result_format, body, metrics))
+}
+
+/// Begin a transaction session, see [`super::conn_pool::TransactionSessions`]. The transaction
+/// has the isolation level, read only and deferrable options of the batch headers.
+async fn begin_transaction(
+    cancel: CancellationToken,
+    config: &'static ProxyConfig,
+    ctx: &mut RequestMonitoring,
+    backend: Arc<PoolingBackend>,
+    conn_info: ConnInfo,
+    allow_pool: bool,
+    parsed_headers: HttpHeaders,
+) -> Result<Response<Full<Bytes>>, SqlOverHttpError> {
+    let authenticate_and_connect = async {
+        let keys = backend
+            .authenticate(ctx, &config.authentication_config, &conn_info)
+            .await?;
+        let client = backend
+            .connect_to_compute(ctx, conn_info, keys, !allow_pool)
+            .await?;
+        ctx.latency_timer.success();
+        Ok::<_, HttpConnError>(client)
+    };
+    let mut client = match run_until_cancelled(authenticate_and_connect, &cancel).await {
+        Some(result) => result?,
+        None => return Err(SqlOverHttpError::Cancelled(SqlOverHttpCancel::Connect)),
+    };
 
+    let (inner, mut discard) = client.inner();
+    if let Err(e) = inner.batch_execute(&begin_statement(parsed_headers)).await {
+        discard.discard();
+        return Err(e.into());
+    }
+    let token = backend.pool.transactions.begin(client)?;
+
+    let body = serde_json::to_vec(&json!({ "session": token.to_string() }))
+        .expect("serializing a string should not fail");
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(TXN_SESSION.clone(), token.to_string())
+        .body(Full::new(Bytes::from(body)))
+        // only fails if invalid status code or invalid header/values are given.
+        // these are not user configurable so it cannot fail dynamically
+        .expect("building response payload should not fail");
     Ok(response)
 }
 
+fn begin_statement(parsed_headers: HttpHeaders) -> String {
+    let mut statement = "BEGIN".to_owned();
+    if let Some(isolation_level) = parsed_headers.txn_isolation_level {
+        statement.push_str(match isolation_level {
+            IsolationLevel::ReadUncommitted => " ISOLATION LEVEL READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => " ISOLATION LEVEL READ COMMITTED",
+            IsolationLevel::RepeatableRead => " ISOLATION LEVEL REPEATABLE READ",
+            IsolationLevel::Serializable => " ISOLATION LEVEL SERIALIZABLE",
+            _ => "",
+        });
+    }
+    if parsed_headers.txn_read_only {
+        statement.push_str(" READ ONLY");
+    }
+    if parsed_headers.txn_deferrable {
+        statement.push_str(" DEFERRABLE");
+    }
+    statement
+}
+
+/// Run the queries of the request on the connection of its transaction session. A batch runs
+/// in the transaction of the session rather than in its own.
+#[allow(clippy::too_many_arguments)]
+async fn query_in_session(
+    cancel: CancellationToken,
+    config: &'static ProxyConfig,
+    ctx: &mut RequestMonitoring,
+    request: Request<Incoming>,
+    backend: Arc<PoolingBackend>,
+    conn_info: ConnInfo,
+    token: uuid::Uuid,
+    parsed_headers: HttpHeaders,
+    result_format: ResultFormat,
+) -> Result<Response<Full<Bytes>>, SqlOverHttpError> {
+    let authenticate = async {
+        backend
+            .authenticate(ctx, &config.authentication_config, &conn_info)
+            .await?;
+        ctx.latency_timer.success();
+        Ok::<_, HttpConnError>(())
+    }
+    .map_err(SqlOverHttpError::from);
+    let (payload, ()) = match run_until_cancelled(
+        try_join(
+            pin!(read_payload(request).map_err(SqlOverHttpError::from)),
+            pin!(authenticate),
+        ),
+        &cancel,
+    )
+    .await
+    {
+        Some(result) => result?,
+        None => return Err(SqlOverHttpError::Cancelled(SqlOverHttpCancel::Connect)),
+    };
+
+    let sessions = &backend.pool.transactions;
+    let mut client = sessions.checkout(token, &conn_info)?;
+    info!(%token, "running queries in transaction session");
+    let result = match payload {
+        Payload::Single(stmt) => stmt
+            .process(cancel, &mut client, parsed_headers)
+            .await
+            .map(QueryResults::Single),
+        Payload::Batch(statements) => statements
+            .process_in_session(cancel, &mut client, parsed_headers)
+            .await
+            .map(QueryResults::Batch),
+    };
+    let metrics = client.metrics();
+    // Also after an error: the client can still roll back.
+    sessions.checkin(token, client);
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, result_format.content_type())
+        .header(TXN_SESSION.clone(), token.to_string());
+    query_response(response, result_format, &result?, metrics)
+}
+
+/// Commit or roll back the transaction of a session, and end the session.
+#[allow(clippy::too_many_arguments)]
+async fn end_transaction(
+    config: &'static ProxyConfig,
+    ctx: &mut RequestMonitoring,
+    backend: Arc<PoolingBackend>,
+    conn_info: ConnInfo,
+    token: uuid::Uuid,
+    commit: bool,
+    parsed_headers: HttpHeaders,
+    result_format: ResultFormat,
+) -> Result<Response<Full<Bytes>>, SqlOverHttpError> {
+    backend
+        .authenticate(ctx, &config.authentication_config, &conn_info)
+        .await
+        .map_err(HttpConnError::from)?;
+    ctx.latency_timer.success();
+
+    let mut client = backend.pool.transactions.end_checkout(token, &conn_info)?;
+    let statement = if commit { "COMMIT" } else { "ROLLBACK" };
+    info!(%token, "ending transaction session with {statement}");
+    let (inner, mut discard) = client.inner();
+    let query = QueryData {
+        query: statement.to_owned(),
+        params: vec![],
+        param_types: vec![],
+        param_formats: vec![],
+        array_mode: None,
+    };
+    // The connection goes back to the pool if the transaction ended cleanly.
+    let result = match query_to_result(&*inner, query, &mut 0, parsed_headers).await {
+        Ok((status, result)) => {
+            discard.check_idle(status);
+            result
+        }
+        Err(e) => {
+            discard.discard();
+            return Err(e);
+        }
+    };
+    let metrics = client.metrics();
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, result_format.content_type());
+    query_response(
+        response,
+        result_format,
+        &QueryResults::Single(result),
+        metrics,
+    )
+}
+
 impl QueryData {
     async fn process(
         self,
         cancel: CancellationToken,
         client: &mut Client<tokio_postgres::Client>,
         parsed_headers: HttpHeaders,
-    ) -> Result<Value, SqlOverHttpError> {
+    ) -> Result<QueryResult, SqlOverHttpError> {
         let (inner, mut discard) = client.inner();
         let cancel_token = inner.cancel_token();
 
         let res = match select(
-            pin!(query_to_json(&*inner, self, &mut 0, parsed_headers)),
+            pin!(query_to_result(&*inner, self, &mut 0, parsed_headers)),
             pin!(cancel.cancelled()),
         )
         .await
@@ -690,7 +1198,7 @@ impl BatchQueryData {
         cancel: CancellationToken,
         client: &mut Client<tokio_postgres::Client>,
         parsed_headers: HttpHeaders,
-    ) -> Result<Value, SqlOverHttpError> {
+    ) -> Result<Vec<QueryResult>, SqlOverHttpError> {
         info!("starting transaction");
         let (inner, mut discard) = client.inner();
         let cancel_token = inner.cancel_token();
@@ -747,21 +1255,42 @@ impl BatchQueryData {
                 }
             };
 
-        Ok(json!({ "results": results }))
+        Ok(results)
+    }
+
+    async fn process_in_session(
+        self,
+        cancel: CancellationToken,
+        client: &mut Client<tokio_postgres::Client>,
+        parsed_headers: HttpHeaders,
+    ) -> Result<Vec<QueryResult>, SqlOverHttpError> {
+        let (inner, mut discard) = client.inner();
+        let cancel_token = inner.cancel_token();
+        match query_batch(cancel.child_token(), &*inner, self, parsed_headers).await {
+            Ok(results) => Ok(results),
+            Err(SqlOverHttpError::Cancelled(_)) => {
+                if let Err(err) = cancel_token.cancel_query(NoTls).await {
+                    tracing::error!(?err, "could not cancel query");
+                }
+                discard.discard();
+                Err(SqlOverHttpError::Cancelled(SqlOverHttpCancel::Postgres))
+            }
+            Err(err) => Err(err),
+        }
     }
 }
 
-async fn query_batch(
+async fn query_batch<T: GenericClient>(
     cancel: CancellationToken,
-    transaction: &Transaction<'_>,
+    client: &T,
     queries: BatchQueryData,
     parsed_headers: HttpHeaders,
-) -> Result<Vec<Value>, SqlOverHttpError> {
+) -> Result<Vec<QueryResult>, SqlOverHttpError> {
     let mut results = Vec::with_capacity(queries.queries.len());
     let mut current_size = 0;
     for stmt in queries.queries {
-        let query = pin!(query_to_json(
-            transaction,
+        let query = pin!(query_to_result(
+            client,
             stmt,
             &mut current_size,
             parsed_headers,
@@ -784,15 +1313,37 @@ async fn query_batch(
     Ok(results)
 }
 
-async fn query_to_json<T: GenericClient>(
+async fn query_to_result<T: GenericClient>(
     client: &T,
     data: QueryData,
     current_size: &mut usize,
     parsed_headers: HttpHeaders,
-) -> Result<(ReadyForQueryStatus, Value), SqlOverHttpError> {
+) -> Result<(ReadyForQueryStatus, QueryResult), SqlOverHttpError> {
+    let query_params = data.pg_params()?;
+    let types = params::param_types(&data.param_types, &data.param_formats)?;
     info!("executing query");
-    let query_params = data.params;
-    let mut row_stream = std::pin::pin!(client.query_raw_txt(&data.query, query_params).await?);
+    let row_stream = if parsed_headers.result_format.binary_values() {
+        // The rows are requested in binary format, and the query is prepared first, for the
+        // types of the results.
+        let query_params = query_params.into_iter().map(TextParam);
+        match types {
+            None => client.query_raw(data.query.as_str(), query_params).await?,
+            Some(types) => {
+                let statement = client.prepare_typed(&data.query, &types).await?;
+                client.query_raw(&statement, query_params).await?
+            }
+        }
+    } else {
+        match types {
+            // Parsed, bound and executed in one round trip.
+            None => client.query_raw_txt(&data.query, query_params).await?,
+            Some(types) => {
+                let statement = client.prepare_typed(&data.query, &types).await?;
+                client.query_raw_txt(&statement, query_params).await?
+            }
+        }
+    };
+    let mut row_stream = std::pin::pin!(row_stream);
     info!("finished executing query");
 
     // Manually drain the stream into a vector to leave row_stream hanging
@@ -833,38 +1384,28 @@ async fn query_to_json<T: GenericClient>(
     );
 
     let mut fields = vec![];
-    let mut columns = vec![];
-
     for c in row_stream.columns() {
-        fields.push(json!({
-            "name": Value::String(c.name().to_owned()),
-            "dataTypeID": Value::Number(c.type_().oid().into()),
-            "tableID": c.table_oid(),
-            "columnID": c.column_id(),
-            "dataTypeSize": c.type_size(),
-            "dataTypeModifier": c.type_modifier(),
-            "format": "text",
-        }));
-        columns.push(client.get_type(c.type_oid()).await?);
+        fields.push(FieldInfo {
+            name: c.name().to_owned(),
+            type_: client.get_type(c.type_oid()).await?,
+            table_oid: c.table_oid(),
+            column_id: c.column_id(),
+            type_size: c.type_size(),
+            type_modifier: c.type_modifier(),
+        });
     }
 
     let array_mode = data.array_mode.unwrap_or(parsed_headers.default_array_mode);
 
-    // convert rows to JSON
-    let rows = rows
-        .iter()
-        .map(|row| pg_text_row_to_json(row, &columns, parsed_headers.raw_output, array_mode))
-        .collect::<Result<Vec<_>, _>>()?;
-
-    // resulting JSON format is based on the format of node-postgres result
     Ok((
         ready,
-        json!({
-            "command": command_tag_name,
-            "rowCount": command_tag_count,
-            "rows": rows,
-            "fields": fields,
-            "rowAsArray": array_mode,
-        }),
+        QueryResult {
+            command_tag,
+            row_count: command_tag_count,
+            fields,
+            rows,
+            array_mode,
+            raw_output: parsed_headers.raw_output,
+        },
     ))
 }
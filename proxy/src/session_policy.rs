@@ -0,0 +1,265 @@
+//! Session policies of endpoints: the GUCs set on their compute connections, and the roles
+//! allowed to connect to them.
+//!
+//! The policy of an endpoint comes from the control plane, in the response of
+//! `proxy_wake_compute`, or else from the `--session-policies` file of the proxy, which has
+//! policies per endpoint and a default one:
+//!
+//! ```json
+//! {
+//!   "default": { "gucs": { "statement_timeout": "5min" } },
+//!   "endpoints": {
+//!     "ep-foo-123": { "gucs": { "search_path": "app, public" }, "allowed_roles": ["app"] }
+//!   }
+//! }
+//! ```
+//!
+//! The policy is applied when a compute connection is established, by
+//! [`connect_to_compute`](crate::proxy::connect_compute::connect_to_compute), so the same way
+//! for the TCP, websocket and HTTP clients:
+//!
+//! - The role of the connection must be one of `allowed_roles`, if they are set.
+//! - The `gucs` are set with the `options` startup parameter, after the options of the client,
+//!   so that they take precedence. The client can still change them with `SET`. The pooled
+//!   connections of the HTTP clients keep the GUCs of the policy of their establishment.
+//!   PgBouncer rejects the `options` parameter, so the GUCs are not set on the connections to
+//!   `-pooler` endpoints, only the allowed roles apply to them.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{ensure, Context};
+use itertools::Itertools;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::console::NodeInfo;
+use crate::error::{ErrorCode, ErrorKind, ReportableError, UserFacingError};
+use crate::{EndpointId, RoleName};
+
+/// The session policy of an endpoint, see the module docs.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+pub struct SessionPolicy {
+    /// The GUCs set on the compute connections, e.g. `statement_timeout`.
+    #[serde(default)]
+    pub gucs: BTreeMap<String, String>,
+    /// The roles allowed to connect, all of them if unset.
+    #[serde(default)]
+    pub allowed_roles: Option<Vec<RoleName>>,
+}
+
+impl SessionPolicy {
+    fn allows_role(&self, role: &str) -> bool {
+        match &self.allowed_roles {
+            Some(allowed_roles) => allowed_roles.iter().any(|allowed| allowed == role),
+            None => true,
+        }
+    }
+
+    /// The `-c name=value` switches of the `options` startup parameter that set the GUCs.
+    fn options(&self) -> String {
+        self.gucs
+            .iter()
+            .filter(|(name, _)| {
+                let valid = is_valid_guc_name(name);
+                if !valid {
+                    warn!("skipping invalid GUC name '{name}' of the session policy");
+                }
+                valid
+            })
+            .map(|(name, value)| format!("-c {name}={}", escape_option(value)))
+            .join(" ")
+    }
+}
+
+fn is_valid_guc_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Postgres splits `options` at whitespace, unless it's escaped with a backslash.
+fn escape_option(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_whitespace() || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The session policies of the proxy config, see the module docs.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionPolicies {
+    /// The policy of the endpoints without their own.
+    #[serde(default)]
+    default: Option<SessionPolicy>,
+    #[serde(default)]
+    endpoints: HashMap<EndpointId, SessionPolicy>,
+}
+
+impl SessionPolicies {
+    /// Read the policies from the JSON file at `path`.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let content = std::fs::read(path).with_context(|| format!("failed to read {path}"))?;
+        let policies: Self = serde_json::from_slice(&content)
+            .with_context(|| format!("failed to parse session policies {path}"))?;
+        for policy in policies.default.iter().chain(policies.endpoints.values()) {
+            for name in policy.gucs.keys() {
+                ensure!(is_valid_guc_name(name), "invalid GUC name '{name}'");
+            }
+        }
+        Ok(policies)
+    }
+
+    /// The policy of `endpoint` in the config, if it has one or there is a default one.
+    fn get(&self, endpoint: &str) -> Option<&SessionPolicy> {
+        self.endpoints
+            .get(&EndpointId::from(endpoint))
+            .or(self.default.as_ref())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("role \"{role}\" is not allowed to connect to endpoint \"{endpoint}\"")]
+pub struct RoleNotAllowed {
+    role: RoleName,
+    endpoint: EndpointId,
+}
+
+impl ReportableError for RoleNotAllowed {
+    fn get_error_kind(&self) -> ErrorKind {
+        ErrorKind::User
+    }
+}
+
+impl UserFacingError for RoleNotAllowed {
+    fn to_string_client(&self) -> String {
+        self.to_string()
+    }
+
+    fn get_error_code(&self) -> ErrorCode {
+        ErrorCode::AccessDenied
+    }
+}
+
+/// Apply the session policy of the endpoint of `node_info` to its connection config, whose user
+/// must be set: the policy of the control plane if there is one, else that of `policies`.
+/// `pooler` is whether the client connects to the `-pooler` endpoint, which gets no GUCs.
+pub fn apply(
+    policies: Option<&SessionPolicies>,
+    node_info: &mut NodeInfo,
+    pooler: bool,
+) -> Result<(), RoleNotAllowed> {
+    let endpoint = node_info.aux.endpoint_id.as_str();
+    let policy = match &node_info.session_policy {
+        Some(policy) => policy.as_ref(),
+        None => match policies.and_then(|policies| policies.get(endpoint)) {
+            Some(policy) => policy,
+            None => return Ok(()),
+        },
+    };
+
+    if let Some(role) = node_info.config.get_user() {
+        if !policy.allows_role(role) {
+            return Err(RoleNotAllowed {
+                role: role.into(),
+                endpoint: endpoint.into(),
+            });
+        }
+    }
+
+    if pooler {
+        if !policy.gucs.is_empty() {
+            info!("not applying the GUCs of the session policy to a pooler endpoint");
+        }
+    } else if !policy.gucs.is_empty() {
+        let policy_options = policy.options();
+        let options = match node_info.config.get_options() {
+            Some(options) if !options.is_empty() => format!("{options} {policy_options}"),
+            _ => policy_options,
+        };
+        info!(gucs = ?policy.gucs.keys().collect_vec(), "applying the session policy");
+        node_info.config.options(&options);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::ConnCfg;
+    use crate::console::messages::{ColdStartInfo, MetricsAuxInfo};
+    use crate::{BranchId, ProjectId};
+
+    fn node_info(endpoint: &str, user: &str, options: Option<&str>) -> NodeInfo {
+        let mut config = ConnCfg::new();
+        config.user(user);
+        if let Some(options) = options {
+            config.options(options);
+        }
+        NodeInfo {
+            config,
+            aux: MetricsAuxInfo {
+                endpoint_id: (&EndpointId::from(endpoint)).into(),
+                project_id: (&ProjectId::from("project")).into(),
+                branch_id: (&BranchId::from("branch")).into(),
+                cold_start_info: ColdStartInfo::Warm,
+            },
+            allow_self_signed_compute: false,
+            replicas: Vec::new(),
+            session_policy: None,
+        }
+    }
+
+    #[test]
+    fn apply_policies() {
+        let policies: SessionPolicies = serde_json::from_str(
+            r#"{
+                "default": { "gucs": { "statement_timeout": "5min" } },
+                "endpoints": {
+                    "ep-foo": { "gucs": { "search_path": "app, public" }, "allowed_roles": ["app"] }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        // The GUCs come after the client's options, with the whitespace escaped.
+        let mut node = node_info("ep-foo", "app", Some("-c search_path=x"));
+        apply(Some(&policies), &mut node, false).unwrap();
+        assert_eq!(
+            node.config.get_options(),
+            Some("-c search_path=x -c search_path=app,\\ public")
+        );
+
+        let mut node = node_info("ep-foo", "admin", None);
+        let err = apply(Some(&policies), &mut node, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "role \"admin\" is not allowed to connect to endpoint \"ep-foo\""
+        );
+
+        let mut node = node_info("ep-bar", "admin", None);
+        apply(Some(&policies), &mut node, false).unwrap();
+        assert_eq!(node.config.get_options(), Some("-c statement_timeout=5min"));
+
+        // PgBouncer rejects the options, but the roles still apply.
+        let mut node = node_info("ep-bar", "admin", None);
+        apply(Some(&policies), &mut node, true).unwrap();
+        assert_eq!(node.config.get_options(), None);
+        let mut node = node_info("ep-foo", "admin", None);
+        assert!(apply(Some(&policies), &mut node, true).is_err());
+
+        // The policy of the control plane replaces that of the config.
+        let mut node = node_info("ep-foo", "admin", None);
+        node.session_policy = Some(Default::default());
+        apply(Some(&policies), &mut node, false).unwrap();
+        assert_eq!(node.config.get_options(), None);
+
+        assert!(serde_json::from_str::<SessionPolicies>(r#"{"unknown": {}}"#).is_err());
+    }
+}
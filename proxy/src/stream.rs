@@ -1,5 +1,5 @@
 use crate::config::TlsServerEndPoint;
-use crate::error::{ErrorKind, ReportableError, UserFacingError};
+use crate::error::{ErrorCode, ErrorKind, ReportableError, UserFacingError};
 use crate::metrics::Metrics;
 use bytes::BytesMut;
 
@@ -134,8 +134,9 @@ impl<S: AsyncWrite + Unpin> PqStream<S> {
         );
 
         // already error case, ignore client IO error
+        let code = ErrorCode::from(error_kind);
         let _: Result<_, std::io::Error> = self
-            .write_message(&BeMessage::ErrorResponse(msg, None))
+            .write_message(&error_response(msg, &code, &code.detail()))
             .await;
 
         Err(ReportedError {
@@ -151,9 +152,11 @@ impl<S: AsyncWrite + Unpin> PqStream<S> {
         E: UserFacingError + Into<anyhow::Error>,
     {
         let error_kind = error.get_error_kind();
+        let code = error.get_error_code();
         let msg = error.to_string_client();
         tracing::info!(
             kind=error_kind.to_metric_label(),
+            code=code.as_str(),
             error=%error,
             msg,
             "forwarding error to user"
@@ -161,7 +164,7 @@ impl<S: AsyncWrite + Unpin> PqStream<S> {
 
         // already error case, ignore client IO error
         let _: Result<_, std::io::Error> = self
-            .write_message(&BeMessage::ErrorResponse(&msg, None))
+            .write_message(&error_response(&msg, &code, &code.detail()))
             .await;
 
         Err(ReportedError {
@@ -171,6 +174,19 @@ impl<S: AsyncWrite + Unpin> PqStream<S> {
     }
 }
 
+/// The ErrorResponse of an error, with its code, see [`ErrorCode`].
+fn error_response<'a>(msg: &'a str, code: &'a ErrorCode, detail: &'a str) -> BeMessage<'a> {
+    match code.sqlstate().as_bytes().try_into() {
+        Ok(sqlstate) => BeMessage::ErrorResponseWithDetail {
+            message: msg,
+            code: sqlstate,
+            detail,
+        },
+        // SQLSTATEs are always 5 characters.
+        Err(_) => BeMessage::ErrorResponse(msg, None),
+    }
+}
+
 /// Wrapper for upgrading raw streams into secure streams.
 pub enum Stream<S> {
     /// We always begin with a raw stream,
@@ -240,6 +256,37 @@ impl<S: AsyncRead + AsyncWrite + Unpin> Stream<S> {
             Stream::Tls { .. } => Err(StreamUpgradeError::AlreadyTls),
         }
     }
+
+    /// Same as [`Self::upgrade`], but the first TLS record sent by the client
+    /// (`client_hello`) has already been read from the stream.
+    pub async fn upgrade_with_client_hello(
+        self,
+        cfg: Arc<ServerConfig>,
+        record_handshake_error: bool,
+        client_hello: &[u8],
+    ) -> Result<TlsStream<S>, StreamUpgradeError> {
+        match self {
+            Stream::Raw { raw } => Ok(tokio_rustls::TlsAcceptor::from(cfg)
+                .accept_with(raw, |conn| {
+                    let mut client_hello = client_hello;
+                    while !client_hello.is_empty() {
+                        if conn.read_tls(&mut client_hello).is_err() {
+                            break;
+                        }
+                    }
+                    // rustls remembers the error (if any), so it will be
+                    // reported by the rest of the handshake.
+                    let _ = conn.process_new_packets();
+                })
+                .await
+                .inspect_err(|_| {
+                    if record_handshake_error {
+                        Metrics::get().proxy.tls_handshake_failures.inc()
+                    }
+                })?),
+            Stream::Tls { .. } => Err(StreamUpgradeError::AlreadyTls),
+        }
+    }
 }
 
 impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for Stream<S> {
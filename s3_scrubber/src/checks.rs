@@ -364,7 +364,7 @@ pub(crate) async fn list_timeline_blobs(
         .await
         .context("index_part.json download")?;
 
-        match serde_json::from_slice(&index_part_bytes) {
+        match IndexPart::from_encoded_bytes(&index_part_bytes, None).await {
             Ok(index_part) => {
                 return Ok(S3TimelineBlobData {
                     blob_data: BlobDataParseResult::Parsed {
@@ -376,7 +376,7 @@ pub(crate) async fn list_timeline_blobs(
                 })
             }
             Err(index_parse_error) => errors.push(format!(
-                "index_part.json body parsing error: {index_parse_error}"
+                "index_part.json body parsing error: {index_parse_error:#}"
             )),
         }
     } else {
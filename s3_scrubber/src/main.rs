@@ -1,5 +1,6 @@
 use anyhow::bail;
 use camino::Utf8PathBuf;
+use pageserver::{page_cache, virtual_file};
 use pageserver_api::shard::TenantShardId;
 use s3_scrubber::garbage::{find_garbage, purge_garbage, PurgeMode};
 use s3_scrubber::scan_pageserver_metadata::scan_metadata;
@@ -61,6 +62,9 @@ enum Command {
         concurrency: usize,
         #[arg(short, long)]
         output_path: Utf8PathBuf,
+        /// Check the index of each layer after its download
+        #[arg(long, default_value_t = false)]
+        verify_layers: bool,
     },
 }
 
@@ -173,9 +177,20 @@ async fn main() -> anyhow::Result<()> {
             tenant_id,
             output_path,
             concurrency,
+            verify_layers,
         } => {
-            let downloader =
-                SnapshotDownloader::new(bucket_config, tenant_id, output_path, concurrency)?;
+            if verify_layers {
+                // Layers are read through the pageserver's file and page caches.
+                virtual_file::init(concurrency * 2, virtual_file::api::IoEngineKind::StdFs);
+                page_cache::init(100);
+            }
+            let downloader = SnapshotDownloader::new(
+                bucket_config,
+                tenant_id,
+                output_path,
+                concurrency,
+                verify_layers,
+            )?;
             downloader.download().await
         }
     }
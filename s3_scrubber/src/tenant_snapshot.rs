@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use crate::checks::{list_timeline_blobs, BlobDataParseResult, S3TimelineBlobData};
 use crate::metadata_stream::{stream_tenant_shards, stream_tenant_timelines};
@@ -11,13 +11,21 @@ use async_stream::stream;
 use aws_sdk_s3::Client;
 use camino::Utf8PathBuf;
 use futures::{StreamExt, TryStreamExt};
+use pageserver::context::{DownloadBehavior, RequestContext};
+use pageserver::task_mgr::TaskKind;
+use pageserver::tenant::disk_btree::VerifyFinding;
 use pageserver::tenant::remote_timeline_client::index::IndexLayerMetadata;
 use pageserver::tenant::storage_layer::LayerName;
+use pageserver::tenant::verify_layerfile_from_path;
 use pageserver::tenant::IndexPart;
 use pageserver_api::shard::TenantShardId;
+use serde::Serialize;
 use utils::generation::Generation;
 use utils::id::TenantId;
 
+/// Written next to the snapshot when the verification of the downloaded layers found problems.
+const LAYER_FINDINGS_FILE_NAME: &str = "layer_findings.json";
+
 pub struct SnapshotDownloader {
     s3_client: Arc<Client>,
     s3_root: RootTarget,
@@ -25,6 +33,17 @@ pub struct SnapshotDownloader {
     tenant_id: TenantId,
     output_path: Utf8PathBuf,
     concurrency: usize,
+    /// Whether to check the index of each layer after the download, see
+    /// [`pageserver::tenant::disk_btree::DiskBtreeReader::verify`].
+    verify_layers: bool,
+    layer_findings: Mutex<Vec<LayerFindings>>,
+}
+
+/// The problems found in the index of a downloaded layer.
+#[derive(Serialize)]
+struct LayerFindings {
+    path: Utf8PathBuf,
+    findings: Vec<VerifyFinding>,
 }
 
 impl SnapshotDownloader {
@@ -33,6 +52,7 @@ impl SnapshotDownloader {
         tenant_id: TenantId,
         output_path: Utf8PathBuf,
         concurrency: usize,
+        verify_layers: bool,
     ) -> anyhow::Result<Self> {
         let (s3_client, s3_root) = init_remote(bucket_config.clone(), NodeKind::Pageserver)?;
         Ok(Self {
@@ -42,9 +62,28 @@ impl SnapshotDownloader {
             tenant_id,
             output_path,
             concurrency,
+            verify_layers,
+            layer_findings: Mutex::new(Vec::new()),
         })
     }
 
+    async fn verify_layer(&self, local_path: &Utf8PathBuf) -> anyhow::Result<()> {
+        let ctx = RequestContext::new(TaskKind::DebugTool, DownloadBehavior::Error);
+        let findings = verify_layerfile_from_path(local_path, &ctx)
+            .await
+            .with_context(|| format!("verifying {local_path}"))?;
+        if !findings.is_empty() {
+            for finding in &findings {
+                tracing::error!("Problem in the index of {local_path}: {finding}");
+            }
+            self.layer_findings.lock().unwrap().push(LayerFindings {
+                path: local_path.clone(),
+                findings,
+            });
+        }
+        Ok(())
+    }
+
     async fn download_layer(
         &self,
         ttid: TenantShardTimelineId,
@@ -68,7 +107,6 @@ impl SnapshotDownloader {
         // already exists on local disk, we assume it is fully correct and skip it.
         if tokio::fs::try_exists(&local_path).await? {
             tracing::debug!("{} already exists", local_path);
-            return Ok((layer_name, layer_metadata));
         } else {
             tracing::debug!("{} requires download...", local_path);
 
@@ -103,6 +141,10 @@ impl SnapshotDownloader {
             tracing::debug!("Downloaded successfully to {local_path}");
         }
 
+        if self.verify_layers {
+            self.verify_layer(&local_path).await?;
+        }
+
         Ok((layer_name, layer_metadata))
     }
 
@@ -288,6 +330,18 @@ impl SnapshotDownloader {
                 .await?;
         }
 
+        let layer_findings = std::mem::take(&mut *self.layer_findings.lock().unwrap());
+        if !layer_findings.is_empty() {
+            let path = self.output_path.join(LAYER_FINDINGS_FILE_NAME);
+            tokio::fs::write(&path, serde_json::to_vec_pretty(&layer_findings)?)
+                .await
+                .with_context(|| format!("writing {path}"))?;
+            anyhow::bail!(
+                "Found problems in the index of {} layers, see {path}",
+                layer_findings.len()
+            );
+        }
+
         Ok(())
     }
 }
@@ -30,6 +30,7 @@ use safekeeper::defaults::{
     DEFAULT_HEARTBEAT_TIMEOUT, DEFAULT_HTTP_LISTEN_ADDR, DEFAULT_MAX_OFFLOADER_LAG_BYTES,
     DEFAULT_PARTIAL_BACKUP_TIMEOUT, DEFAULT_PG_LISTEN_ADDR,
 };
+use safekeeper::send_wal_grpc;
 use safekeeper::wal_service;
 use safekeeper::GlobalTimelines;
 use safekeeper::SafeKeeperConf;
@@ -99,6 +100,14 @@ struct Args {
     /// specified, listen_pg is used to advertise instead.
     #[arg(long, default_value = None)]
     advertise_pg: Option<String>,
+    /// Listen endpoint for streaming WAL to pageservers over gRPC in the form
+    /// host:port. The gRPC WAL stream service is disabled if not specified.
+    #[arg(long, default_value = None, verbatim_doc_comment)]
+    listen_grpc: Option<String>,
+    /// Advertised endpoint for streaming WAL over gRPC in the form host:port. If
+    /// not specified, listen_grpc is used to advertise instead.
+    #[arg(long, default_value = None, verbatim_doc_comment)]
+    advertise_grpc: Option<String>,
     /// Availability zone of the safekeeper.
     #[arg(long)]
     availability_zone: Option<String>,
@@ -116,6 +125,10 @@ struct Args {
     /// Broker keepalive interval.
     #[arg(long, value_parser= humantime::parse_duration, default_value = storage_broker::DEFAULT_KEEPALIVE_INTERVAL)]
     broker_keepalive_interval: Duration,
+    /// Other instances of the broker cluster that broker_endpoint belongs to,
+    /// comma separated. Requests are balanced between the reachable instances.
+    #[arg(long, value_delimiter = ',', verbatim_doc_comment)]
+    broker_fallback_endpoints: Vec<Uri>,
     /// Peer safekeeper is considered dead after not receiving heartbeats from
     /// it during this period passed as a human readable duration.
     #[arg(long, value_parser= humantime::parse_duration, default_value = DEFAULT_HEARTBEAT_TIMEOUT, verbatim_doc_comment)]
@@ -181,6 +194,12 @@ struct Args {
     /// be used in tests.
     #[arg(long)]
     disable_periodic_broker_push: bool,
+    /// Keep only this many bytes of WAL behind commit_lsn on local disk once it
+    /// is offloaded to remote storage, instead of waiting for all pageservers and
+    /// peers to consume it. Older WAL is then read from remote storage. Has no
+    /// effect without remote storage and WAL backup.
+    #[arg(long, verbatim_doc_comment)]
+    local_wal_horizon_bytes: Option<u64>,
 }
 
 // Like PathBufValueParser, but allows empty string.
@@ -296,10 +315,13 @@ async fn main() -> anyhow::Result<()> {
         listen_pg_addr_tenant_only: args.listen_pg_tenant_only,
         listen_http_addr: args.listen_http,
         advertise_pg_addr: args.advertise_pg,
+        listen_grpc_addr: args.listen_grpc,
+        advertise_grpc_addr: args.advertise_grpc,
         availability_zone: args.availability_zone,
         no_sync: args.no_sync,
         broker_endpoint: args.broker_endpoint,
         broker_keepalive_interval: args.broker_keepalive_interval,
+        broker_fallback_endpoints: args.broker_fallback_endpoints,
         heartbeat_timeout: args.heartbeat_timeout,
         peer_recovery_enabled: args.peer_recovery,
         remote_storage: args.remote_storage,
@@ -314,6 +336,7 @@ async fn main() -> anyhow::Result<()> {
         partial_backup_enabled: args.partial_backup_enabled,
         partial_backup_timeout: args.partial_backup_timeout,
         disable_periodic_broker_push: args.disable_periodic_broker_push,
+        local_wal_horizon_bytes: args.local_wal_horizon_bytes,
     };
 
     // initialize sentry if SENTRY_DSN is provided
@@ -363,6 +386,17 @@ async fn start_safekeeper(conf: SafeKeeperConf) -> Result<()> {
             None
         };
 
+    let grpc_listener = if let Some(listen_grpc_addr) = &conf.listen_grpc_addr {
+        info!("starting safekeeper gRPC WAL stream service on {listen_grpc_addr}");
+        let listener = tcp_listener::bind(listen_grpc_addr.clone()).map_err(|e| {
+            error!("failed to bind to address {}: {}", listen_grpc_addr, e);
+            e
+        })?;
+        Some(listener)
+    } else {
+        None
+    };
+
     info!(
         "starting safekeeper HTTP service on {}",
         conf.listen_http_addr
@@ -438,6 +472,16 @@ async fn start_safekeeper(conf: SafeKeeperConf) -> Result<()> {
         tasks_handles.push(Box::pin(wal_service_handle));
     }
 
+    if let Some(grpc_listener) = grpc_listener {
+        let conf_ = conf.clone();
+        let grpc_handle = current_thread_rt
+            .as_ref()
+            .unwrap_or_else(|| WAL_SERVICE_RUNTIME.handle())
+            .spawn(send_wal_grpc::task_main(conf_, grpc_listener))
+            .map(|res| ("gRPC WAL stream service main".to_owned(), res));
+        tasks_handles.push(Box::pin(grpc_handle));
+    }
+
     let conf_ = conf.clone();
     let http_handle = current_thread_rt
         .as_ref()
@@ -17,6 +17,7 @@ use storage_broker::proto::SubscribeByFilterRequest;
 use storage_broker::proto::SubscribeSafekeeperInfoRequest;
 use storage_broker::proto::TypeSubscription;
 use storage_broker::proto::TypedMessage;
+use storage_broker::BrokerClientChannel;
 use storage_broker::Request;
 
 use std::sync::atomic::AtomicU64;
@@ -38,6 +39,12 @@ use crate::SafeKeeperConf;
 const RETRY_INTERVAL_MSEC: u64 = 1000;
 const PUSH_INTERVAL_MSEC: u64 = 1000;
 
+fn connect(conf: &SafeKeeperConf) -> anyhow::Result<BrokerClientChannel> {
+    let mut endpoints = vec![conf.broker_endpoint.clone()];
+    endpoints.extend(conf.broker_fallback_endpoints.iter().cloned());
+    storage_broker::connect_cluster(&endpoints, conf.broker_keepalive_interval)
+}
+
 /// Push once in a while data about all active timelines to the broker.
 async fn push_loop(conf: SafeKeeperConf) -> anyhow::Result<()> {
     if conf.disable_periodic_broker_push {
@@ -46,8 +53,7 @@ async fn push_loop(conf: SafeKeeperConf) -> anyhow::Result<()> {
         return Ok(());
     }
 
-    let mut client =
-        storage_broker::connect(conf.broker_endpoint.clone(), conf.broker_keepalive_interval)?;
+    let mut client = connect(&conf)?;
     let push_interval = Duration::from_millis(PUSH_INTERVAL_MSEC);
 
     let outbound = async_stream::stream! {
@@ -91,7 +97,7 @@ async fn push_loop(conf: SafeKeeperConf) -> anyhow::Result<()> {
 
 /// Subscribe and fetch all the interesting data from the broker.
 async fn pull_loop(conf: SafeKeeperConf, stats: Arc<BrokerStats>) -> Result<()> {
-    let mut client = storage_broker::connect(conf.broker_endpoint, conf.broker_keepalive_interval)?;
+    let mut client = connect(&conf)?;
 
     // TODO: subscribe only to local timelines instead of all
     let request = SubscribeSafekeeperInfoRequest {
@@ -139,8 +145,7 @@ async fn pull_loop(conf: SafeKeeperConf, stats: Arc<BrokerStats>) -> Result<()>
 /// Process incoming discover requests. This is done in a separate task to avoid
 /// interfering with the normal pull/push loops.
 async fn discover_loop(conf: SafeKeeperConf, stats: Arc<BrokerStats>) -> Result<()> {
-    let mut client =
-        storage_broker::connect(conf.broker_endpoint.clone(), conf.broker_keepalive_interval)?;
+    let mut client = connect(&conf)?;
 
     let request = SubscribeByFilterRequest {
         types: vec![TypeSubscription {
@@ -150,6 +155,7 @@ async fn discover_loop(conf: SafeKeeperConf, stats: Arc<BrokerStats>) -> Result<
             enabled: false,
             tenant_timeline_id: None,
         }),
+        ..Default::default()
     };
 
     let mut stream = client
@@ -186,6 +192,9 @@ async fn discover_loop(conf: SafeKeeperConf, stats: Arc<BrokerStats>) -> Result<
                         commit_lsn: sk_info.commit_lsn,
                         safekeeper_connstr: sk_info.safekeeper_connstr,
                         availability_zone: sk_info.availability_zone,
+                        mconf_generation: sk_info.mconf_generation,
+                        mconf_members: sk_info.mconf_members,
+                        wal_grpc_connstr: sk_info.wal_grpc_connstr,
                     };
 
                     // note this is a blocking call
@@ -195,6 +204,7 @@ async fn discover_loop(conf: SafeKeeperConf, stats: Arc<BrokerStats>) -> Result<
                             safekeeper_timeline_info: None,
                             safekeeper_discovery_request: None,
                             safekeeper_discovery_response: Some(response),
+                            ..Default::default()
                         })
                         .await?;
                 }
@@ -28,9 +28,10 @@ use crate::safekeeper::Term;
 use crate::safekeeper::{ServerInfo, TermLsn};
 use crate::send_wal::WalSenderState;
 use crate::timeline::PeerInfo;
-use crate::{copy_timeline, debug_dump, patch_control_file, pull_timeline};
+use crate::{copy_timeline, debug_dump, membership, patch_control_file, pull_timeline};
 
 use crate::timelines_global_map::TimelineDeleteForceResult;
+use crate::wal_export::WalExport;
 use crate::GlobalTimelines;
 use crate::SafeKeeperConf;
 use utils::{
@@ -110,6 +111,8 @@ pub struct TimelineStatus {
     pub peers: Vec<PeerInfo>,
     pub walsenders: Vec<WalSenderState>,
     pub walreceivers: Vec<WalReceiverState>,
+    #[serde(default)]
+    pub mconf: membership::Configuration,
 }
 
 fn check_permission(request: &Request<Body>, tenant_id: Option<TenantId>) -> Result<(), ApiError> {
@@ -164,6 +167,7 @@ async fn timeline_status_handler(request: Request<Body>) -> Result<Response<Body
         peers: tli.get_peers(conf).await,
         walsenders: tli.get_walsenders().get_all(),
         walreceivers: tli.get_walreceivers().get_all(),
+        mconf: state.mconf,
     };
     json_response(StatusCode::OK, status)
 }
@@ -256,6 +260,50 @@ async fn timeline_digest_handler(request: Request<Body>) -> Result<Response<Body
     json_response(StatusCode::OK, response)
 }
 
+/// Export the WAL between from_lsn and until_lsn as a tar archive of WAL segment files.
+async fn timeline_wal_export_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let ttid = TenantTimelineId::new(
+        parse_request_param(&request, "tenant_id")?,
+        parse_request_param(&request, "timeline_id")?,
+    );
+    check_permission(&request, Some(ttid.tenant_id))?;
+
+    let from_lsn: Lsn = parse_query_param(&request, "from_lsn")?.ok_or(ApiError::BadRequest(
+        anyhow::anyhow!("from_lsn is required"),
+    ))?;
+    let until_lsn: Lsn = parse_query_param(&request, "until_lsn")?.ok_or(ApiError::BadRequest(
+        anyhow::anyhow!("until_lsn is required"),
+    ))?;
+
+    let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
+    let export = WalExport::new(&tli, from_lsn, until_lsn)
+        .await
+        .map_err(ApiError::BadRequest)?;
+
+    let (tx, rx) = mpsc::channel(1);
+    tokio::spawn(
+        async move {
+            if let Err(e) = export.write_tar(&tx).await {
+                tracing::warn!("failed to export WAL: {e:#}");
+                // Abort the response, for the client to see that it's incomplete.
+                let _ = tx
+                    .send(Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        e.to_string(),
+                    )))
+                    .await;
+            }
+        }
+        .instrument(info_span!("wal_export", %ttid, %from_lsn, %until_lsn)),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/x-tar")
+        .body(Body::wrap_stream(ReceiverStream::new(rx)))
+        .map_err(|e| ApiError::InternalServerError(e.into()))
+}
+
 /// Download a file from the timeline directory.
 // TODO: figure out a better way to copy files between safekeepers
 async fn timeline_files_handler(request: Request<Body>) -> Result<Response<Body>, ApiError> {
@@ -350,6 +398,9 @@ async fn record_safekeeper_info(mut request: Request<Body>) -> Result<Response<B
         backup_lsn: sk_info.backup_lsn.0,
         local_start_lsn: sk_info.local_start_lsn.0,
         availability_zone: None,
+        mconf_generation: 0,
+        mconf_members: Vec::new(),
+        wal_grpc_connstr: "".to_owned(),
     };
 
     let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
@@ -485,6 +536,23 @@ async fn patch_control_file_handler(
     json_response(StatusCode::OK, response)
 }
 
+/// Switch the membership configuration of the timeline, see [`membership`].
+async fn timeline_membership_handler(
+    mut request: Request<Body>,
+) -> Result<Response<Body>, ApiError> {
+    let ttid = TenantTimelineId::new(
+        parse_request_param(&request, "tenant_id")?,
+        parse_request_param(&request, "timeline_id")?,
+    );
+    check_permission(&request, Some(ttid.tenant_id))?;
+
+    let new_mconf: membership::Configuration = json_request(&mut request).await?;
+    let tli = GlobalTimelines::get(ttid).map_err(ApiError::from)?;
+    let mconf = tli.update_membership(get_conf(&request), new_mconf).await?;
+
+    json_response(StatusCode::OK, mconf)
+}
+
 /// Safekeeper http router.
 pub fn make_router(conf: SafeKeeperConf) -> RouterBuilder<hyper::Body, ApiError> {
     let mut router = endpoint::make_router();
@@ -552,6 +620,10 @@ pub fn make_router(conf: SafeKeeperConf) -> RouterBuilder<hyper::Body, ApiError>
             "/v1/tenant/:tenant_id/timeline/:timeline_id/control_file",
             |r| request_span(r, patch_control_file_handler),
         )
+        .put(
+            "/v1/tenant/:tenant_id/timeline/:timeline_id/membership",
+            |r| request_span(r, timeline_membership_handler),
+        )
         // for tests
         .post("/v1/record_safekeeper_info/:tenant_id/:timeline_id", |r| {
             request_span(r, record_safekeeper_info)
@@ -560,6 +632,9 @@ pub fn make_router(conf: SafeKeeperConf) -> RouterBuilder<hyper::Body, ApiError>
         .get("/v1/tenant/:tenant_id/timeline/:timeline_id/digest", |r| {
             request_span(r, timeline_digest_handler)
         })
+        .get("/v1/tenant/:tenant_id/timeline/:timeline_id/wal", |r| {
+            request_span(r, timeline_wal_export_handler)
+        })
 }
 
 #[cfg(test)]
@@ -21,6 +21,7 @@ pub mod debug_dump;
 pub mod handler;
 pub mod http;
 pub mod json_ctrl;
+pub mod membership;
 pub mod metrics;
 pub mod patch_control_file;
 pub mod pull_timeline;
@@ -29,10 +30,12 @@ pub mod recovery;
 pub mod remove_wal;
 pub mod safekeeper;
 pub mod send_wal;
+pub mod send_wal_grpc;
 pub mod state;
 pub mod timeline;
 pub mod wal_backup;
 pub mod wal_backup_partial;
+pub mod wal_export;
 pub mod wal_service;
 pub mod wal_storage;
 
@@ -66,10 +69,13 @@ pub struct SafeKeeperConf {
     pub listen_pg_addr_tenant_only: Option<String>,
     pub listen_http_addr: String,
     pub advertise_pg_addr: Option<String>,
+    pub listen_grpc_addr: Option<String>,
+    pub advertise_grpc_addr: Option<String>,
     pub availability_zone: Option<String>,
     pub no_sync: bool,
     pub broker_endpoint: Uri,
     pub broker_keepalive_interval: Duration,
+    pub broker_fallback_endpoints: Vec<Uri>,
     pub heartbeat_timeout: Duration,
     pub peer_recovery_enabled: bool,
     pub remote_storage: Option<RemoteStorageConfig>,
@@ -84,6 +90,7 @@ pub struct SafeKeeperConf {
     pub partial_backup_enabled: bool,
     pub partial_backup_timeout: Duration,
     pub disable_periodic_broker_push: bool,
+    pub local_wal_horizon_bytes: Option<u64>,
 }
 
 impl SafeKeeperConf {
@@ -111,6 +118,8 @@ impl SafeKeeperConf {
             listen_pg_addr_tenant_only: None,
             listen_http_addr: defaults::DEFAULT_HTTP_LISTEN_ADDR.to_string(),
             advertise_pg_addr: None,
+            listen_grpc_addr: None,
+            advertise_grpc_addr: None,
             availability_zone: None,
             remote_storage: None,
             my_id: NodeId(0),
@@ -118,6 +127,7 @@ impl SafeKeeperConf {
                 .parse()
                 .expect("failed to parse default broker endpoint"),
             broker_keepalive_interval: Duration::from_secs(5),
+            broker_fallback_endpoints: Vec::new(),
             peer_recovery_enabled: true,
             wal_backup_enabled: true,
             backup_parallel_jobs: 1,
@@ -131,6 +141,7 @@ impl SafeKeeperConf {
             partial_backup_enabled: false,
             partial_backup_timeout: Duration::from_secs(0),
             disable_periodic_broker_push: false,
+            local_wal_horizon_bytes: None,
         }
     }
 }
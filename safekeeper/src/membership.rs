@@ -0,0 +1,233 @@
+//! Membership configuration of a timeline, i.e. the set of safekeepers which store its WAL.
+//!
+//! Safekeepers are added to and removed from a timeline online, with joint consensus: the
+//! configuration first switches to a joint one, in which both the old and the new set of
+//! members must form a quorum, and only then to the new set alone. Every switch bumps the
+//! generation, and a safekeeper never goes back to an older generation.
+//!
+//! The switches are driven from outside through the HTTP API, in this order:
+//! 1. `{members: old, new_members: new}` on all safekeepers of both sets;
+//! 2. new members catch up, with pull_timeline or peer recovery;
+//! 3. `{members: new}` on all safekeepers of both sets. A safekeeper refuses this step until
+//!    a quorum of the new members reported, through the broker, that they have WAL up to its
+//!    commit_lsn.
+//!
+//! Compute must be connected to the safekeepers of both sets between steps 1 and 3. The
+//! configuration is sent to it in the greeting, and safekeepers close compute connections on
+//! every switch, so that it greets again and learns the new one; walproposer then commits
+//! only WAL flushed on a quorum of the configuration with the highest generation it has seen.
+//! As a safeguard against a compute that hasn't learned about the switch yet, safekeepers in
+//! a joint configuration cap its commit_lsn by the LSN which peers of both sets reported
+//! flushed.
+//! Safekeepers which are not members refuse connections from compute, and the configuration
+//! is published to the broker, so that pageservers stream WAL only from members.
+
+use std::cmp::min;
+
+use anyhow::{anyhow, bail, ensure};
+use serde::{Deserialize, Serialize};
+use utils::http::error::ApiError;
+use utils::id::NodeId;
+use utils::lsn::Lsn;
+
+/// Generation of a membership configuration, increases with every switch.
+pub type Generation = u32;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Configuration {
+    /// Generation 0 is the configuration of timelines created before membership was
+    /// tracked: every safekeeper is considered a member of it.
+    pub generation: Generation,
+    pub members: Vec<NodeId>,
+    /// Members of the new configuration, while a change is in progress.
+    pub new_members: Option<Vec<NodeId>>,
+}
+
+impl Configuration {
+    pub fn is_unknown(&self) -> bool {
+        self.generation == 0
+    }
+
+    pub fn is_joint(&self) -> bool {
+        self.new_members.is_some()
+    }
+
+    pub fn contains(&self, sk: NodeId) -> bool {
+        self.is_unknown() || self.all_members().contains(&sk)
+    }
+
+    /// Members of both the old and the new configuration during a change.
+    pub fn all_members(&self) -> Vec<NodeId> {
+        let mut all = self.members.clone();
+        all.extend(self.new_members.iter().flatten());
+        all.sort();
+        all.dedup();
+        all
+    }
+
+    /// Whether `acked` contains a majority of the members, and of the new members during
+    /// a change.
+    pub fn is_quorum(&self, acked: &[NodeId]) -> bool {
+        is_majority(&self.members, acked)
+            && self
+                .new_members
+                .as_ref()
+                .map_or(true, |new_members| is_majority(new_members, acked))
+    }
+
+    /// The highest LSN flushed on a quorum, as [`Self::is_quorum`] defines it, given
+    /// `(member, flush_lsn)` pairs. Members without a pair have flushed nothing.
+    pub fn quorum_lsn(&self, flushed: &[(NodeId, Lsn)]) -> Lsn {
+        let lsn = majority_lsn(&self.members, flushed);
+        match &self.new_members {
+            Some(new_members) => min(lsn, majority_lsn(new_members, flushed)),
+            None => lsn,
+        }
+    }
+
+    /// Check that switching from `self` to `new` is allowed. Repeating the current switch
+    /// is allowed too, to make retries of the driving side simple.
+    pub fn validate_switch(&self, new: &Configuration) -> anyhow::Result<()> {
+        if new == self {
+            return Ok(());
+        }
+        if new.generation <= self.generation {
+            bail!(
+                "configuration generation {} is not newer than the current {}",
+                new.generation,
+                self.generation
+            );
+        }
+
+        for set in std::iter::once(&new.members).chain(new.new_members.as_ref()) {
+            ensure!(!set.is_empty(), "configuration must have members");
+            let mut sorted = set.clone();
+            sorted.sort();
+            sorted.dedup();
+            ensure!(sorted.len() == set.len(), "duplicate members in {set:?}");
+        }
+
+        // Members can't be dropped without a quorum of the new configuration having acked
+        // the switch to the joint configuration, so a change must go through it.
+        if !self.is_unknown() && !new.is_joint() && new.members != self.members {
+            ensure!(
+                self.new_members.as_ref() == Some(&new.members),
+                "switch from {self:?} to {new:?} must go through a joint configuration"
+            );
+        }
+        if new.is_joint() {
+            ensure!(
+                self.is_unknown() || new.members == self.members,
+                "joint configuration {new:?} must start from the current members {:?}",
+                self.members
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SwitchError {
+    #[error("{0:#}")]
+    Rejected(anyhow::Error),
+    #[error("failed to persist membership configuration: {0:#}")]
+    Persist(anyhow::Error),
+}
+
+impl From<SwitchError> for ApiError {
+    fn from(e: SwitchError) -> ApiError {
+        match e {
+            SwitchError::Rejected(e) => ApiError::Conflict(format!("{e:#}")),
+            e @ SwitchError::Persist(_) => ApiError::InternalServerError(anyhow!("{e}")),
+        }
+    }
+}
+
+fn is_majority(set: &[NodeId], acked: &[NodeId]) -> bool {
+    let acked = set.iter().filter(|sk| acked.contains(sk)).count();
+    acked * 2 > set.len()
+}
+
+fn majority_lsn(set: &[NodeId], flushed: &[(NodeId, Lsn)]) -> Lsn {
+    let mut lsns = set
+        .iter()
+        .map(|sk| {
+            flushed
+                .iter()
+                .find(|(id, _)| id == sk)
+                .map_or(Lsn::INVALID, |(_, lsn)| *lsn)
+        })
+        .collect::<Vec<_>>();
+    lsns.sort_unstable_by(|a, b| b.cmp(a));
+    // the majority is set.len() / 2 + 1 members
+    lsns.get(set.len() / 2).copied().unwrap_or(Lsn::INVALID)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conf(generation: Generation, members: &[u64], new_members: Option<&[u64]>) -> Configuration {
+        let ids = |ids: &[u64]| ids.iter().copied().map(NodeId).collect::<Vec<_>>();
+        Configuration {
+            generation,
+            members: ids(members),
+            new_members: new_members.map(ids),
+        }
+    }
+
+    #[test]
+    fn joint_quorum() {
+        let joint = conf(2, &[1, 2, 3], Some(&[4, 5, 6]));
+        let ids = |ids: &[u64]| ids.iter().copied().map(NodeId).collect::<Vec<_>>();
+
+        // majority of the union, but not of the old members
+        assert!(!joint.is_quorum(&ids(&[1, 4, 5, 6])));
+        assert!(joint.is_quorum(&ids(&[1, 2, 4, 5])));
+        assert!(conf(3, &[4, 5, 6], None).is_quorum(&ids(&[4, 5])));
+
+        assert!(joint.contains(NodeId(1)) && joint.contains(NodeId(6)));
+        assert!(!joint.contains(NodeId(7)));
+        assert!(Configuration::default().contains(NodeId(7)));
+    }
+
+    #[test]
+    fn joint_quorum_lsn() {
+        let joint = conf(2, &[1, 2, 3], Some(&[3, 4, 5]));
+        let flushed = [1, 2, 3, 4]
+            .iter()
+            .map(|id| (NodeId(*id), Lsn(*id * 10)))
+            .collect::<Vec<_>>();
+
+        // old members have 20 on a majority, new members only 30 and 40 flushed
+        assert_eq!(joint.quorum_lsn(&flushed), Lsn(20));
+        assert_eq!(conf(3, &[3, 4, 5], None).quorum_lsn(&flushed), Lsn(30));
+        assert_eq!(conf(3, &[4, 5, 6], None).quorum_lsn(&flushed), Lsn::INVALID);
+    }
+
+    #[test]
+    fn switches() {
+        let initial = conf(1, &[1, 2, 3], None);
+        let joint = conf(2, &[1, 2, 3], Some(&[2, 3, 4]));
+        let new = conf(3, &[2, 3, 4], None);
+
+        Configuration::default().validate_switch(&initial).unwrap();
+        initial.validate_switch(&joint).unwrap();
+        joint.validate_switch(&joint).unwrap();
+        joint.validate_switch(&new).unwrap();
+
+        // no skipping the joint configuration
+        initial
+            .validate_switch(&conf(2, &[2, 3, 4], None))
+            .unwrap_err();
+        // no going back
+        new.validate_switch(&joint).unwrap_err();
+        // joint configuration must start from the current one
+        initial
+            .validate_switch(&conf(2, &[1, 2], Some(&[2, 3, 4])))
+            .unwrap_err();
+        initial
+            .validate_switch(&conf(2, &[1, 1, 2], None))
+            .unwrap_err();
+    }
+}
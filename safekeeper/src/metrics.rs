@@ -75,6 +75,14 @@ pub static PG_IO_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
     )
     .expect("Failed to register safekeeper_pg_io_bytes gauge")
 });
+pub static WAL_READER_SEGMENT_OPENS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "safekeeper_wal_reader_segment_opens_total",
+        "WAL segments opened for reading, by the tier they were read from",
+        &["tier"]
+    )
+    .expect("Failed to register safekeeper_wal_reader_segment_opens_total counter")
+});
 pub static BROKER_PUSHED_UPDATES: Lazy<IntCounter> = Lazy::new(|| {
     register_int_counter!(
         "safekeeper_broker_pushed_updates_total",
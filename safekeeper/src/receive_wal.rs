@@ -9,7 +9,7 @@ use crate::safekeeper::ServerInfo;
 use crate::timeline::Timeline;
 use crate::wal_service::ConnectionId;
 use crate::GlobalTimelines;
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use bytes::BytesMut;
 use parking_lot::MappedMutexGuard;
 use parking_lot::Mutex;
@@ -455,6 +455,11 @@ impl WalAcceptor {
         // we will send keepalives by replying to these requests once per second.
         let mut next_keepalive = Instant::now();
 
+        // The proposer counts acks against the membership configuration it got
+        // in the greeting, so it must greet again after a switch.
+        let mconf_generation_rx = self.tli.get_mconf_generation_watch_rx();
+        let mut greeted_generation = None;
+
         loop {
             let opt_msg = self.msg_rx.recv().await;
             if opt_msg.is_none() {
@@ -462,6 +467,17 @@ impl WalAcceptor {
             }
             let mut next_msg = opt_msg.unwrap();
 
+            if let Some(generation) = greeted_generation {
+                let current = *mconf_generation_rx.borrow();
+                if current != generation {
+                    bail!(
+                        "membership configuration switched from generation {} to {}, closing the connection",
+                        generation,
+                        current
+                    );
+                }
+            }
+
             // Update walreceiver state in shmem for reporting.
             if let ProposerAcceptorMessage::Elected(_) = &next_msg {
                 walreceiver_guard.get().status = WalReceiverStatus::Streaming;
@@ -504,6 +520,12 @@ impl WalAcceptor {
                 self.tli.process_msg(&next_msg).await?
             };
 
+            if let Some(AcceptorProposerMessage::Greeting(greeting)) = &reply_msg {
+                if self.conn_id.is_some() {
+                    greeted_generation = greeting.mconf_generation();
+                }
+            }
+
             if let Some(reply) = reply_msg {
                 if self.reply_tx.send(reply).await.is_err() {
                     return Ok(()); // chan closed, streaming terminated
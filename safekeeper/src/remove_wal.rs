@@ -11,6 +11,11 @@ const ALLOW_INACTIVE_TIMELINES: bool = true;
 
 pub async fn task_main(conf: SafeKeeperConf) -> anyhow::Result<()> {
     let wal_removal_interval = Duration::from_millis(5000);
+    // Evicting WAL which pageservers still need is safe only if it can be read back
+    // from remote storage.
+    let local_wal_horizon_bytes = conf
+        .local_wal_horizon_bytes
+        .filter(|_| conf.is_wal_backup_enabled());
     loop {
         let now = tokio::time::Instant::now();
         let mut active_timelines = 0;
@@ -29,7 +34,10 @@ pub async fn task_main(conf: SafeKeeperConf) -> anyhow::Result<()> {
                 if let Err(e) = tli.maybe_persist_control_file().await {
                     warn!("failed to persist control file: {e}");
                 }
-                if let Err(e) = tli.remove_old_wal(conf.wal_backup_enabled).await {
+                if let Err(e) = tli
+                    .remove_old_wal(conf.wal_backup_enabled, local_wal_horizon_bytes)
+                    .await
+                {
                     error!("failed to remove WAL: {}", e);
                 }
             }
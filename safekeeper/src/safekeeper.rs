@@ -16,6 +16,7 @@ use storage_broker::proto::SafekeeperTimelineInfo;
 use tracing::*;
 
 use crate::control_file;
+use crate::membership;
 use crate::send_wal::HotStandbyFeedback;
 
 use crate::state::TimelineState;
@@ -28,7 +29,9 @@ use utils::{
     lsn::Lsn,
 };
 
-const SK_PROTOCOL_VERSION: u32 = 2;
+const SK_PROTOCOL_VERSION: u32 = 3;
+/// Version 2 proposers don't get the membership configuration in the greeting.
+const MIN_SK_PROTOCOL_VERSION: u32 = 2;
 pub const UNKNOWN_SERVER_VERSION: u32 = 0;
 
 /// Consensus logical timestamp.
@@ -261,6 +264,15 @@ pub struct ProposerGreeting {
 pub struct AcceptorGreeting {
     term: u64,
     node_id: NodeId,
+    /// Membership configuration, for proposers speaking protocol version 3 and later.
+    mconf: Option<membership::Configuration>,
+}
+
+impl AcceptorGreeting {
+    /// Generation of the membership configuration sent to the proposer, if any.
+    pub fn mconf_generation(&self) -> Option<membership::Generation> {
+        self.mconf.as_ref().map(|mconf| mconf.generation)
+    }
 }
 
 /// Vote request sent from proposer to safekeepers
@@ -439,6 +451,19 @@ impl AcceptorProposerMessage {
                 buf.put_u64_le('g' as u64);
                 buf.put_u64_le(msg.term);
                 buf.put_u64_le(msg.node_id.0);
+                if let Some(mconf) = &msg.mconf {
+                    buf.put_u32_le(mconf.generation);
+                    // An empty set of new members means no change is in progress.
+                    for set in [
+                        &mconf.members[..],
+                        mconf.new_members.as_deref().unwrap_or_default(),
+                    ] {
+                        buf.put_u32_le(set.len() as u32);
+                        for sk in set {
+                            buf.put_u64_le(sk.0);
+                        }
+                    }
+                }
             }
             AcceptorProposerMessage::VoteResponse(msg) => {
                 buf.put_u64_le('v' as u64);
@@ -489,6 +514,10 @@ pub struct SafeKeeper<CTRL: control_file::Storage, WAL: wal_storage::Storage> {
     pub wal_store: WAL,
 
     node_id: NodeId, // safekeeper's node id
+
+    /// Upper bound of commit_lsn in a joint configuration, see
+    /// [`Self::update_commit_limit`].
+    commit_limit: Option<Lsn>,
 }
 
 impl<CTRL, WAL> SafeKeeper<CTRL, WAL>
@@ -515,6 +544,7 @@ where
             state: TimelineState::new(state),
             wal_store,
             node_id,
+            commit_limit: None,
         })
     }
 
@@ -567,10 +597,11 @@ where
         msg: &ProposerGreeting,
     ) -> Result<Option<AcceptorProposerMessage>> {
         // Check protocol compatibility
-        if msg.protocol_version != SK_PROTOCOL_VERSION {
+        if !(MIN_SK_PROTOCOL_VERSION..=SK_PROTOCOL_VERSION).contains(&msg.protocol_version) {
             bail!(
-                "incompatible protocol version {}, expected {}",
+                "incompatible protocol version {}, expected {}..={}",
                 msg.protocol_version,
+                MIN_SK_PROTOCOL_VERSION,
                 SK_PROTOCOL_VERSION
             );
         }
@@ -602,6 +633,14 @@ where
                 self.state.timeline_id
             );
         }
+        if !self.state.mconf.contains(self.node_id) {
+            bail!(
+                "safekeeper {} is not a member of configuration generation {}: {:?}",
+                self.node_id,
+                self.state.mconf.generation,
+                self.state.mconf.all_members()
+            );
+        }
         if self.state.server.wal_seg_size != msg.wal_seg_size {
             bail!(
                 "invalid wal_seg_size, got {}, expected {}",
@@ -636,6 +675,7 @@ where
         Ok(Some(AcceptorProposerMessage::Greeting(AcceptorGreeting {
             term: self.state.acceptor_state.term,
             node_id: self.node_id,
+            mconf: (msg.protocol_version >= 3).then(|| self.state.mconf.clone()),
         })))
     }
 
@@ -804,7 +844,10 @@ where
         // Both peers and walproposer communicate this value, we might already
         // have a fresher (higher) version.
         candidate = max(candidate, self.state.inmem.commit_lsn);
-        let commit_lsn = min(candidate, self.flush_lsn());
+        let mut commit_lsn = min(candidate, self.flush_lsn());
+        if let Some(limit) = self.commit_limit {
+            commit_lsn = max(min(commit_lsn, limit), self.state.inmem.commit_lsn);
+        }
         assert!(
             commit_lsn >= self.state.inmem.commit_lsn,
             "commit_lsn monotonicity violated: old={} new={}",
@@ -825,6 +868,19 @@ where
         Ok(())
     }
 
+    /// In a joint configuration WAL is committed only once a quorum of both the old and the
+    /// new members flushed it, while compute may still count acks against the previous
+    /// configuration until it greets again. Cap commit_lsn by what the peers with our
+    /// history, given as `(id, flush_lsn)`, reported to have flushed; stale reports only
+    /// lower the cap.
+    pub fn update_commit_limit(&mut self, peers: &[(NodeId, Lsn)]) {
+        self.commit_limit = self.state.mconf.is_joint().then(|| {
+            let mut flushed = vec![(self.node_id, self.flush_lsn())];
+            flushed.extend_from_slice(peers);
+            self.state.mconf.quorum_lsn(&flushed)
+        });
+    }
+
     /// Persist control file if there is something to save and enough time
     /// passed after the last save.
     pub async fn maybe_persist_inmem_control_file(&mut self) -> Result<()> {
@@ -1197,6 +1253,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_greeting_serialize_mconf() {
+        let greeting = |mconf| {
+            let mut buf = BytesMut::new();
+            AcceptorProposerMessage::Greeting(AcceptorGreeting {
+                term: 2,
+                node_id: NodeId(1),
+                mconf,
+            })
+            .serialize(&mut buf)
+            .unwrap();
+            buf
+        };
+
+        // Version 2 proposers get tag, term and node id only.
+        assert_eq!(greeting(None).len(), 24);
+
+        let buf = greeting(Some(membership::Configuration {
+            generation: 3,
+            members: vec![NodeId(1), NodeId(2)],
+            new_members: Some(vec![NodeId(3)]),
+        }));
+        let mut mconf = &buf[24..];
+        assert_eq!(mconf.get_u32_le(), 3);
+        assert_eq!(mconf.get_u32_le(), 2);
+        assert_eq!(mconf.get_u64_le(), 1);
+        assert_eq!(mconf.get_u64_le(), 2);
+        assert_eq!(mconf.get_u32_le(), 1);
+        assert_eq!(mconf.get_u64_le(), 3);
+        assert!(mconf.is_empty());
+
+        // No change in progress: empty set of new members.
+        let buf = greeting(Some(membership::Configuration {
+            generation: 4,
+            members: vec![NodeId(3)],
+            new_members: None,
+        }));
+        assert_eq!(
+            &buf[24..],
+            [4, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
     #[test]
     fn test_sk_state_bincode_serde_roundtrip() {
         use utils::Hex;
@@ -1238,6 +1337,7 @@ mod tests {
                 },
             )]),
             partial_backup: crate::wal_backup_partial::State::default(),
+            mconf: crate::membership::Configuration::default(),
         };
 
         let ser = state.ser().unwrap();
@@ -1285,6 +1385,10 @@ mod tests {
             0xb0, 0x01, 0x96, 0x49, 0x00, 0x00, 0x00, 0x00,
             // partial_backup
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // mconf: generation, members, new_members
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00,
         ];
 
         assert_eq!(Hex(&ser), Hex(&expected));
@@ -105,7 +105,7 @@ impl WalSenders {
 
     /// Register new walsender. Returned guard provides access to the slot and
     /// automatically deregisters in Drop.
-    fn register(
+    pub(crate) fn register(
         self: &Arc<WalSenders>,
         ttid: TenantTimelineId,
         addr: SocketAddr,
@@ -324,6 +324,18 @@ pub struct WalSenderGuard {
     walsenders: Arc<WalSenders>,
 }
 
+impl WalSenderGuard {
+    /// Record new pageserver feedback of this walsender.
+    pub(crate) fn record_ps_feedback(&self, feedback: &PageserverFeedback) {
+        self.walsenders.record_ps_feedback(self.id, feedback);
+    }
+
+    /// Get remote_consistent_lsn reported by the pageserver of this walsender.
+    pub(crate) fn remote_consistent_lsn(&self) -> Option<Lsn> {
+        self.walsenders.get_ws_remote_consistent_lsn(self.id)
+    }
+}
+
 impl Drop for WalSenderGuard {
     fn drop(&mut self) {
         self.walsenders.unregister(self.id);
@@ -0,0 +1,296 @@
+//! This module implements the gRPC WAL stream service, see
+//! storage_broker/proto/wal_stream.proto: like the walsender of send_wal.rs, it
+//! streams committed WAL to pageservers, but with explicit flow control.
+//!
+//! A stream sends WAL from the position requested by the receiver, and only as
+//! many bytes as the receiver granted credit for; the receiver grants more as it
+//! ingests. While there is no WAL or no credit to send, keepalives are sent
+//! instead, and the stream ends once the receiver is caught up and there are no
+//! computes, like the walsender does.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures::Stream;
+use postgres_ffi::MAX_SEND_SIZE;
+use storage_broker::wal_stream::proto::{
+    wal_stream_request, wal_stream_response, Feedback, KeepAlive, WalChunk, WalStreamRequest,
+    WalStreamResponse,
+};
+use storage_broker::wal_stream::{self, Response, Status, WalStreamService};
+use storage_broker::{Request, Streaming};
+use tokio::sync::watch::Receiver;
+use tracing::*;
+use utils::id::{TenantId, TenantTimelineId, TimelineId};
+use utils::lsn::Lsn;
+use utils::pageserver_feedback::PageserverFeedback;
+
+use crate::auth::check_permission;
+use crate::send_wal::WalSenderGuard;
+use crate::timeline::Timeline;
+use crate::wal_storage::WalReader;
+use crate::{GlobalTimelines, SafeKeeperConf};
+
+/// How long to wait for WAL or credit before sending a keepalive.
+const POLL_STATE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Serve the gRPC WAL stream service on `listener`.
+pub async fn task_main(
+    conf: SafeKeeperConf,
+    listener: std::net::TcpListener,
+) -> anyhow::Result<()> {
+    let service = WalStream {
+        conf,
+        connection_count: AtomicU32::new(0),
+    };
+    wal_stream::serve(listener, service).await
+}
+
+struct WalStream {
+    conf: SafeKeeperConf,
+    connection_count: AtomicU32,
+}
+
+#[async_trait::async_trait]
+impl WalStreamService for WalStream {
+    type StreamWalStream =
+        Pin<Box<dyn Stream<Item = Result<WalStreamResponse, Status>> + Send + 'static>>;
+
+    async fn stream_wal(
+        &self,
+        request: Request<Streaming<WalStreamRequest>>,
+    ) -> Result<Response<Self::StreamWalStream>, Status> {
+        let peer_addr = request
+            .remote_addr()
+            .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+        // Pageservers use the same tokens as for the replication protocol.
+        let claims = match &self.conf.pg_auth {
+            None => None,
+            Some(auth) => {
+                let token = wal_stream::auth_token(&request)
+                    .ok_or_else(|| Status::unauthenticated("missing auth token"))?;
+                let data = auth
+                    .decode(token)
+                    .map_err(|e| Status::unauthenticated(e.0))?;
+                Some(data.claims)
+            }
+        };
+
+        let mut requests = request.into_inner();
+        let start = match requests.message().await? {
+            Some(WalStreamRequest {
+                request: Some(wal_stream_request::Request::Start(start)),
+            }) => start,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "the first message must start streaming",
+                ))
+            }
+        };
+        let ttid = TenantTimelineId {
+            tenant_id: TenantId::from_slice(&start.tenant_id)
+                .map_err(|e| Status::invalid_argument(format!("malformed tenant_id: {e}")))?,
+            timeline_id: TimelineId::from_slice(&start.timeline_id)
+                .map_err(|e| Status::invalid_argument(format!("malformed timeline_id: {e}")))?,
+        };
+        if let Some(claims) = &claims {
+            check_permission(claims, Some(ttid.tenant_id))
+                .map_err(|e| Status::permission_denied(e.0))?;
+        }
+
+        let tli = GlobalTimelines::get(ttid).map_err(|e| Status::not_found(e.to_string()))?;
+        let conn_id = self.connection_count.fetch_add(1, Ordering::Relaxed);
+        let ws_guard =
+            tli.get_walsenders()
+                .register(ttid, peer_addr, conn_id, Some("pageserver".to_owned()));
+
+        let start_pos = Lsn(start.start_lsn);
+        let (_, persisted_state) = tli.get_state().await;
+        let wal_reader = WalReader::new(
+            self.conf.workdir.clone(),
+            self.conf.timeline_dir(&ttid),
+            &persisted_state,
+            start_pos,
+            self.conf.is_wal_backup_enabled(),
+        )
+        .map_err(|e| Status::internal(format!("{e:#}")))?;
+
+        info!(
+            %ttid,
+            %peer_addr,
+            "starting gRPC WAL stream from {start_pos}, initial credit {}",
+            start.initial_credit
+        );
+        let sender = GrpcWalSender {
+            commit_lsn_rx: tli.get_commit_lsn_watch_rx(),
+            tli,
+            ws_guard,
+            requests,
+            wal_reader,
+            start_pos,
+            granted: start.initial_credit,
+            sent: 0,
+        };
+        Ok(Response::new(Box::pin(sender.into_stream())))
+    }
+}
+
+/// Sends the WAL of one stream, and handles the credit grants and feedback of the receiver.
+struct GrpcWalSender {
+    tli: Arc<Timeline>,
+    ws_guard: WalSenderGuard,
+    requests: Streaming<WalStreamRequest>,
+    commit_lsn_rx: Receiver<Lsn>,
+    wal_reader: WalReader,
+    // Position since which we are sending next chunk.
+    start_pos: Lsn,
+    // Bytes of WAL the receiver granted credit for since the start of the stream. The
+    // grants come from the receiver, so this saturates rather than overflows: a receiver
+    // granting u64::MAX in total gets unlimited credit.
+    granted: u64,
+    // Bytes of WAL sent since the start of the stream.
+    sent: u64,
+}
+
+enum Event {
+    Request(Option<WalStreamRequest>),
+    Send(usize),
+    CommitLsnChanged,
+    Idle,
+}
+
+/// Size of the next chunk to send from `start_pos`, with WAL available up to `end_pos`
+/// and `credit` bytes of credit. Zero if there's nothing to send, or no credit to send it.
+fn chunk_size(start_pos: Lsn, end_pos: Lsn, credit: u64) -> usize {
+    let available = end_pos.0.saturating_sub(start_pos.0);
+    available.min(credit).min(MAX_SEND_SIZE as u64) as usize
+}
+
+impl GrpcWalSender {
+    fn into_stream(self) -> impl Stream<Item = Result<WalStreamResponse, Status>> {
+        let mut sender = self;
+        async_stream::try_stream! {
+            let mut send_buf = vec![0; MAX_SEND_SIZE];
+            loop {
+                let commit_lsn = *sender.commit_lsn_rx.borrow();
+                let send_size = chunk_size(sender.start_pos, commit_lsn, sender.granted.saturating_sub(sender.sent));
+
+                // Requests go first, so that credit and feedback are handled promptly
+                // while streaming.
+                let event = tokio::select! {
+                    biased;
+                    request = sender.requests.message() => Event::Request(request?),
+                    _ = std::future::ready(()), if send_size > 0 => Event::Send(send_size),
+                    changed = sender.commit_lsn_rx.changed() => {
+                        changed.map_err(|_| Status::unavailable("timeline is shutting down"))?;
+                        Event::CommitLsnChanged
+                    }
+                    _ = tokio::time::sleep(POLL_STATE_TIMEOUT) => Event::Idle,
+                };
+
+                match event {
+                    Event::Request(None) => {
+                        info!("receiver closed the gRPC WAL stream at {}", sender.start_pos);
+                        break;
+                    }
+                    Event::Request(Some(request)) => sender.handle_request(request).await?,
+                    Event::Send(send_size) => {
+                        // Read WAL into buffer. send_size can be additionally capped to
+                        // segment boundary here.
+                        let send_size = sender
+                            .wal_reader
+                            .read(&mut send_buf[..send_size])
+                            .await
+                            .map_err(|e| Status::internal(format!("{e:#}")))?;
+                        yield WalStreamResponse {
+                            response: Some(wal_stream_response::Response::Wal(WalChunk {
+                                start_lsn: sender.start_pos.0,
+                                data: send_buf[..send_size].to_vec(),
+                                commit_lsn: commit_lsn.0,
+                            })),
+                        };
+                        trace!(
+                            "sent {} bytes of WAL {}-{}",
+                            send_size,
+                            sender.start_pos,
+                            sender.start_pos + send_size as u64
+                        );
+                        sender.start_pos += send_size as u64;
+                        sender.sent += send_size as u64;
+                    }
+                    Event::CommitLsnChanged => {}
+                    Event::Idle => {
+                        if let Some(remote_consistent_lsn) = sender.ws_guard.remote_consistent_lsn() {
+                            if sender.tli.should_walsender_stop(remote_consistent_lsn).await {
+                                info!(
+                                    "ending gRPC WAL stream at {}, receiver is caughtup and there is no computes",
+                                    sender.start_pos
+                                );
+                                break;
+                            }
+                        }
+                        yield WalStreamResponse {
+                            response: Some(wal_stream_response::Response::Keepalive(KeepAlive {
+                                commit_lsn: commit_lsn.0,
+                            })),
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_request(&mut self, request: WalStreamRequest) -> Result<(), Status> {
+        match request.request {
+            Some(wal_stream_request::Request::Credit(credit)) => {
+                self.granted = self.granted.saturating_add(credit.bytes);
+            }
+            Some(wal_stream_request::Request::Feedback(feedback)) => {
+                let ps_feedback = ps_feedback(&feedback);
+                trace!("PageserverFeedback is {:?}", ps_feedback);
+                self.ws_guard.record_ps_feedback(&ps_feedback);
+                self.tli
+                    .update_remote_consistent_lsn(ps_feedback.remote_consistent_lsn)
+                    .await;
+            }
+            Some(wal_stream_request::Request::Start(_)) | None => {
+                return Err(Status::invalid_argument("unexpected message"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn ps_feedback(feedback: &Feedback) -> PageserverFeedback {
+    PageserverFeedback {
+        current_timeline_size: feedback.current_timeline_size,
+        last_received_lsn: Lsn(feedback.last_received_lsn),
+        disk_consistent_lsn: Lsn(feedback.disk_consistent_lsn),
+        remote_consistent_lsn: Lsn(feedback.remote_consistent_lsn),
+        replytime: UNIX_EPOCH + Duration::from_micros(feedback.reply_time_micros),
+        shard_number: feedback.shard_number,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_size() {
+        let start = Lsn(0x1000);
+        // Capped by the available WAL, the credit, and MAX_SEND_SIZE.
+        assert_eq!(chunk_size(start, Lsn(0x1100), u64::MAX), 0x100);
+        assert_eq!(chunk_size(start, Lsn(0x1100), 0x10), 0x10);
+        assert_eq!(
+            chunk_size(start, Lsn(0x1000 + 10 * MAX_SEND_SIZE as u64), u64::MAX),
+            MAX_SEND_SIZE
+        );
+        // Nothing to send without WAL or credit.
+        assert_eq!(chunk_size(start, start, u64::MAX), 0);
+        assert_eq!(chunk_size(start, Lsn(0x1100), 0), 0);
+    }
+}
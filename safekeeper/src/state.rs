@@ -11,7 +11,7 @@ use utils::{
 };
 
 use crate::{
-    control_file,
+    control_file, membership,
     safekeeper::{AcceptorState, PersistedPeerInfo, PgUuid, ServerInfo, TermHistory},
     wal_backup_partial::{self},
 };
@@ -63,6 +63,8 @@ pub struct TimelinePersistentState {
     /// Holds names of partial segments uploaded to remote storage. Used to
     /// clean up old objects without leaving garbage in remote storage.
     pub partial_backup: wal_backup_partial::State,
+    /// Membership configuration of the timeline, see [`membership`].
+    pub mconf: membership::Configuration,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -98,6 +100,7 @@ impl TimelinePersistentState {
                     .collect(),
             ),
             partial_backup: wal_backup_partial::State::default(),
+            mconf: membership::Configuration::default(),
         }
     }
 
@@ -36,6 +36,7 @@ use crate::state::{TimelineMemState, TimelinePersistentState};
 use crate::wal_backup::{self};
 use crate::{control_file, safekeeper::UNKNOWN_SERVER_VERSION};
 
+use crate::membership::{self, SwitchError};
 use crate::metrics::FullTimelineInfo;
 use crate::wal_storage::Storage as wal_storage_iface;
 use crate::{debug_dump, wal_backup_partial, wal_storage};
@@ -270,6 +271,20 @@ impl SharedState {
             backup_lsn: self.sk.state.inmem.backup_lsn.0,
             local_start_lsn: self.sk.state.local_start_lsn.0,
             availability_zone: conf.availability_zone.clone(),
+            mconf_generation: self.sk.state.mconf.generation,
+            mconf_members: self
+                .sk
+                .state
+                .mconf
+                .all_members()
+                .into_iter()
+                .map(|sk| sk.0)
+                .collect(),
+            wal_grpc_connstr: conf
+                .advertise_grpc_addr
+                .clone()
+                .or_else(|| conf.listen_grpc_addr.clone())
+                .unwrap_or_default(),
         }
     }
 
@@ -287,6 +302,20 @@ impl SharedState {
             .collect()
     }
 
+    /// Recompute the commit_lsn cap of a joint configuration from the peers' reports,
+    /// including stale ones: flush_lsn only grows, so they make the cap lower, never wrong.
+    fn update_commit_limit(&mut self) {
+        let epoch = self.sk.get_epoch();
+        let peers = self
+            .peers_info
+            .0
+            .iter()
+            .filter(|p| p.last_log_term == epoch)
+            .map(|p| (p.sk_id, p.flush_lsn))
+            .collect::<Vec<_>>();
+        self.sk.update_commit_limit(&peers);
+    }
+
     /// Get oldest segno we still need to keep. We hold WAL till it is consumed
     /// by all of 1) pageserver (remote_consistent_lsn) 2) peers 3) s3
     /// offloading.
@@ -296,10 +325,11 @@ impl SharedState {
         &self,
         wal_backup_enabled: bool,
         extra_horizon_lsn: Option<Lsn>,
+        local_wal_horizon_bytes: Option<u64>,
     ) -> XLogSegNo {
         let state = &self.sk.state;
 
-        use std::cmp::min;
+        use std::cmp::{max, min};
         let mut horizon_lsn = min(state.remote_consistent_lsn, state.peer_horizon_lsn);
         if wal_backup_enabled {
             horizon_lsn = min(horizon_lsn, state.backup_lsn);
@@ -307,6 +337,15 @@ impl SharedState {
         if let Some(extra_horizon_lsn) = extra_horizon_lsn {
             horizon_lsn = min(horizon_lsn, extra_horizon_lsn);
         }
+        // Local disk is only a hot tier for WAL which is already offloaded: lagging
+        // readers fetch it from remote storage once it falls behind the local horizon.
+        if let Some(local_wal_horizon_bytes) = local_wal_horizon_bytes {
+            let local_horizon_lsn = state
+                .commit_lsn
+                .checked_sub(local_wal_horizon_bytes)
+                .unwrap_or(Lsn(0));
+            horizon_lsn = max(horizon_lsn, min(state.backup_lsn, local_horizon_lsn));
+        }
         horizon_lsn.segment_number(state.server.wal_seg_size as usize)
     }
 }
@@ -360,6 +399,11 @@ pub struct Timeline {
     term_flush_lsn_watch_tx: watch::Sender<TermLsn>,
     term_flush_lsn_watch_rx: watch::Receiver<TermLsn>,
 
+    /// Broadcasts the generation of the membership configuration; connections
+    /// from compute are closed on a switch, see [`membership`].
+    mconf_generation_watch_tx: watch::Sender<membership::Generation>,
+    mconf_generation_watch_rx: watch::Receiver<membership::Generation>,
+
     /// Safekeeper and other state, that should remain consistent and
     /// synchronized with the disk. This is tokio mutex as we write WAL to disk
     /// while holding it, ensuring that consensus checks are in order.
@@ -400,6 +444,8 @@ impl Timeline {
             shared_state.sk.get_term(),
             shared_state.sk.flush_lsn(),
         )));
+        let (mconf_generation_watch_tx, mconf_generation_watch_rx) =
+            watch::channel(shared_state.sk.state.mconf.generation);
         let (cancellation_tx, cancellation_rx) = watch::channel(false);
 
         let walreceivers = WalReceivers::new();
@@ -410,6 +456,8 @@ impl Timeline {
             commit_lsn_watch_rx,
             term_flush_lsn_watch_tx,
             term_flush_lsn_watch_rx,
+            mconf_generation_watch_tx,
+            mconf_generation_watch_rx,
             mutex: Mutex::new(shared_state),
             walsenders: WalSenders::new(walreceivers.clone()),
             walreceivers,
@@ -435,6 +483,8 @@ impl Timeline {
         let (cancellation_tx, cancellation_rx) = watch::channel(false);
         let state =
             TimelinePersistentState::new(&ttid, server_info, vec![], commit_lsn, local_start_lsn);
+        let (mconf_generation_watch_tx, mconf_generation_watch_rx) =
+            watch::channel(state.mconf.generation);
 
         let walreceivers = WalReceivers::new();
         Ok(Timeline {
@@ -444,6 +494,8 @@ impl Timeline {
             commit_lsn_watch_rx,
             term_flush_lsn_watch_tx,
             term_flush_lsn_watch_rx,
+            mconf_generation_watch_tx,
+            mconf_generation_watch_rx,
             mutex: Mutex::new(SharedState::create_new(conf, &ttid, state)?),
             walsenders: WalSenders::new(walreceivers.clone()),
             walreceivers,
@@ -645,6 +697,11 @@ impl Timeline {
         self.term_flush_lsn_watch_rx.clone()
     }
 
+    /// Returns the membership configuration generation watch channel.
+    pub fn get_mconf_generation_watch_rx(&self) -> watch::Receiver<membership::Generation> {
+        self.mconf_generation_watch_rx.clone()
+    }
+
     /// Pass arrived message to the safekeeper.
     pub async fn process_msg(
         &self,
@@ -659,6 +716,7 @@ impl Timeline {
         let term_flush_lsn: TermLsn;
         {
             let mut shared_state = self.write_shared_state().await;
+            shared_state.update_commit_limit();
             rmsg = shared_state.sk.process_msg(msg).await?;
 
             // if this is AppendResponse, fill in proper hot standby feedback.
@@ -725,9 +783,10 @@ impl Timeline {
         let commit_lsn: Lsn;
         {
             let mut shared_state = self.write_shared_state().await;
-            shared_state.sk.record_safekeeper_info(&sk_info).await?;
             let peer_info = PeerInfo::from_sk_info(&sk_info, Instant::now());
             shared_state.peers_info.upsert(&peer_info);
+            shared_state.update_commit_limit();
+            shared_state.sk.record_safekeeper_info(&sk_info).await?;
             is_wal_backup_action_pending = self.update_status(&mut shared_state).await;
             commit_lsn = shared_state.sk.state.inmem.commit_lsn;
         }
@@ -845,7 +904,11 @@ impl Timeline {
 
     /// Delete WAL segments from disk that are no longer needed. This is determined
     /// based on pageserver's remote_consistent_lsn and local backup_lsn/peer_lsn.
-    pub async fn remove_old_wal(&self, wal_backup_enabled: bool) -> Result<()> {
+    pub async fn remove_old_wal(
+        &self,
+        wal_backup_enabled: bool,
+        local_wal_horizon_bytes: Option<u64>,
+    ) -> Result<()> {
         if self.is_cancelled() {
             bail!(TimelineError::Cancelled(self.ttid));
         }
@@ -862,8 +925,11 @@ impl Timeline {
         let horizon_segno: XLogSegNo;
         let remover = {
             let shared_state = self.write_shared_state().await;
-            horizon_segno =
-                shared_state.get_horizon_segno(wal_backup_enabled, replication_horizon_lsn);
+            horizon_segno = shared_state.get_horizon_segno(
+                wal_backup_enabled,
+                replication_horizon_lsn,
+                local_wal_horizon_bytes,
+            );
             if horizon_segno <= 1 || horizon_segno <= shared_state.last_removed_segno {
                 return Ok(()); // nothing to do
             }
@@ -946,6 +1012,70 @@ impl Timeline {
         }
     }
 
+    /// Switch to a new membership configuration, see [`membership`]. Returns the
+    /// configuration in effect afterwards.
+    pub async fn update_membership(
+        &self,
+        conf: &SafeKeeperConf,
+        new_mconf: membership::Configuration,
+    ) -> Result<membership::Configuration, SwitchError> {
+        if self.is_cancelled() {
+            return Err(SwitchError::Rejected(
+                TimelineError::Cancelled(self.ttid).into(),
+            ));
+        }
+
+        let mut state = self.write_shared_state().await;
+        let current = state.sk.state.mconf.clone();
+        current
+            .validate_switch(&new_mconf)
+            .map_err(SwitchError::Rejected)?;
+        if current == new_mconf {
+            return Ok(current);
+        }
+
+        // Leaving the joint configuration drops the old members, so the new ones must
+        // have all the WAL committed so far.
+        if current.is_joint() && !new_mconf.is_joint() && new_mconf.members != current.members {
+            let commit_lsn = state.sk.state.inmem.commit_lsn;
+            let mut caught_up = state
+                .get_peers(conf.heartbeat_timeout)
+                .into_iter()
+                .filter(|peer| peer.flush_lsn >= commit_lsn)
+                .map(|peer| peer.sk_id)
+                .collect::<Vec<_>>();
+            if state.sk.flush_lsn() >= commit_lsn {
+                caught_up.push(conf.my_id);
+            }
+            if !new_mconf.is_quorum(&caught_up) {
+                return Err(SwitchError::Rejected(anyhow!(
+                    "new members {:?} haven't caught up to commit_lsn {}, only {:?} have",
+                    new_mconf.members,
+                    commit_lsn,
+                    caught_up
+                )));
+            }
+        }
+
+        info!(
+            "switching membership configuration from {:?} to {:?}",
+            current, new_mconf
+        );
+        let mut persistent_state = state.sk.state.start_change();
+        persistent_state.mconf = new_mconf;
+        state
+            .sk
+            .state
+            .finish_change(&persistent_state)
+            .await
+            .map_err(SwitchError::Persist)?;
+        // Sent under the lock, so that a greeting either carries the new
+        // configuration or its connection sees the switch.
+        self.mconf_generation_watch_tx
+            .send_replace(state.sk.state.mconf.generation);
+        Ok(state.sk.state.mconf.clone())
+    }
+
     /// Apply a function to the control file state and persist it.
     pub async fn map_control_file<T>(
         &self,
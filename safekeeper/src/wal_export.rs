@@ -0,0 +1,123 @@
+//! Export of a range of the WAL of a timeline as standard WAL segment files, for consumers
+//! outside of the storage, like logical decoding pipelines and external auditors.
+//!
+//! The WAL is read with [`WalReader`], so segments already removed locally are fetched from
+//! the WAL backup in remote storage. The export is a tar archive of the segment files
+//! covering the range, in the layout of a `pg_wal` directory, as `pg_waldump` and the
+//! `import wal` command of the pageserver read them. Segments are always complete: the first
+//! one starts at its segment boundary rather than at `from_lsn`, and the last one is
+//! zero-filled after `until_lsn`, like a segment Postgres is still writing.
+
+use std::io;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use postgres_ffi::{XLogFileName, MAX_SEND_SIZE, PG_TLI};
+use tokio::sync::mpsc;
+use utils::lsn::Lsn;
+
+use crate::timeline::Timeline;
+use crate::wal_storage::WalReader;
+use crate::GlobalTimelines;
+
+static ZEROES: [u8; MAX_SEND_SIZE] = [0; MAX_SEND_SIZE];
+
+/// An export of the WAL between `from_lsn` and `until_lsn`, checked to be available.
+pub struct WalExport {
+    wal_reader: WalReader,
+    wal_seg_size: usize,
+    /// Start of the segment of `from_lsn`.
+    start_lsn: Lsn,
+    until_lsn: Lsn,
+}
+
+impl WalExport {
+    /// Check that the range is committed WAL of the timeline. Errors are the fault of the
+    /// request.
+    pub async fn new(tli: &Arc<Timeline>, from_lsn: Lsn, until_lsn: Lsn) -> Result<Self> {
+        if from_lsn >= until_lsn {
+            bail!("from_lsn {from_lsn} must be lower than until_lsn {until_lsn}");
+        }
+        let (_, persisted_state) = tli.get_state().await;
+        if from_lsn < persisted_state.timeline_start_lsn {
+            bail!(
+                "from_lsn {from_lsn} is before the start of the timeline {}",
+                persisted_state.timeline_start_lsn
+            );
+        }
+        let commit_lsn = *tli.get_commit_lsn_watch_rx().borrow();
+        if until_lsn > commit_lsn {
+            bail!("until_lsn {until_lsn} is after the commit_lsn {commit_lsn}");
+        }
+
+        let wal_seg_size = persisted_state.server.wal_seg_size as usize;
+        let start_lsn = from_lsn.segment_lsn(wal_seg_size);
+        let conf = GlobalTimelines::get_global_config();
+        let wal_reader = WalReader::new(
+            conf.workdir.clone(),
+            tli.timeline_dir.clone(),
+            &persisted_state,
+            start_lsn,
+            conf.is_wal_backup_enabled(),
+        )?;
+        Ok(WalExport {
+            wal_reader,
+            wal_seg_size,
+            start_lsn,
+            until_lsn,
+        })
+    }
+
+    /// Write the tar archive to `tx`, until done or the receiver goes away.
+    pub async fn write_tar(mut self, tx: &mpsc::Sender<io::Result<Bytes>>) -> Result<()> {
+        let send = |bytes: Bytes| async move {
+            tx.send(Ok(bytes))
+                .await
+                .map_err(|_| anyhow!("receiver of the WAL export went away"))
+        };
+
+        let mut buf = vec![0u8; MAX_SEND_SIZE];
+        let mut pos = self.start_lsn;
+        while pos < self.until_lsn {
+            let segno = pos.segment_number(self.wal_seg_size);
+            let file_name = XLogFileName(PG_TLI, segno, self.wal_seg_size);
+            send(Bytes::copy_from_slice(
+                segment_header(&file_name, self.wal_seg_size)?.as_bytes(),
+            ))
+            .await?;
+
+            let segment_end = pos + self.wal_seg_size as u64;
+            while pos < segment_end {
+                if pos < self.until_lsn {
+                    let to_read = buf.len().min((self.until_lsn.0 - pos.0) as usize);
+                    // Stops at the segment boundary.
+                    let read = self.wal_reader.read(&mut buf[..to_read]).await?;
+                    if read == 0 {
+                        bail!("wal_reader.read returned 0 bytes at {pos}");
+                    }
+                    send(Bytes::copy_from_slice(&buf[..read])).await?;
+                    pos += read as u64;
+                } else {
+                    let zeroes = ZEROES.len().min((segment_end.0 - pos.0) as usize);
+                    send(Bytes::from_static(&ZEROES[..zeroes])).await?;
+                    pos += zeroes as u64;
+                }
+            }
+        }
+
+        // Segment sizes are multiples of the tar block size, so there is no padding to add
+        // after the contents: the archive ends with two empty blocks.
+        send(Bytes::from_static(&ZEROES[..1024])).await
+    }
+}
+
+fn segment_header(file_name: &str, wal_seg_size: usize) -> Result<tokio_tar::Header> {
+    let mut header = tokio_tar::Header::new_gnu();
+    header.set_path(file_name)?;
+    header.set_size(wal_seg_size as u64);
+    header.set_mode(0o600);
+    header.set_mtime(0);
+    header.set_cksum();
+    Ok(header)
+}
@@ -23,7 +23,9 @@ use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tracing::*;
 use utils::crashsafe::durable_rename;
 
-use crate::metrics::{time_io_closure, WalStorageMetrics, REMOVED_WAL_SEGMENTS};
+use crate::metrics::{
+    time_io_closure, WalStorageMetrics, REMOVED_WAL_SEGMENTS, WAL_READER_SEGMENT_OPENS,
+};
 use crate::state::TimelinePersistentState;
 use crate::wal_backup::read_object;
 use crate::SafeKeeperConf;
@@ -692,6 +694,7 @@ impl WalReader {
             match res {
                 Ok(mut file) => {
                     file.seek(SeekFrom::Start(xlogoff as u64)).await?;
+                    WAL_READER_SEGMENT_OPENS.with_label_values(&["local"]).inc();
                     return Ok(Box::pin(file));
                 }
                 Err(e) => {
@@ -722,7 +725,11 @@ impl WalReader {
                         wal_file_path, self.workdir,
                     )
                 })?;
-            return read_object(&remote_wal_file_path, xlogoff as u64).await;
+            let reader = read_object(&remote_wal_file_path, xlogoff as u64).await?;
+            WAL_READER_SEGMENT_OPENS
+                .with_label_values(&["remote"])
+                .inc();
+            return Ok(reader);
         }
 
         bail!("WAL segment is not found")
@@ -740,11 +747,15 @@ impl WalReader {
         // If that failed, try it without the .partial extension.
         tokio::fs::File::open(&wal_file_path)
             .await
-            .with_context(|| format!("Failed to open WAL file {:?}", wal_file_path))
             .map_err(|e| {
-                warn!("{}", e);
+                // Segments evicted from local disk are read from remote storage,
+                // not finding them is not worth a warning.
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Failed to open WAL file {:?}: {}", wal_file_path, e);
+                }
                 e
             })
+            .with_context(|| format!("Failed to open WAL file {:?}", wal_file_path))
     }
 }
 
@@ -162,12 +162,15 @@ pub fn run_server(os: NodeOs, disk: Arc<SafekeeperDisk>) -> Result<()> {
         no_sync: false,
         broker_endpoint: "/".parse::<Uri>().unwrap(),
         broker_keepalive_interval: Duration::from_secs(0),
+        broker_fallback_endpoints: Vec::new(),
         heartbeat_timeout: Duration::from_secs(0),
         remote_storage: None,
         max_offloader_lag_bytes: 0,
         wal_backup_enabled: false,
         listen_pg_addr_tenant_only: None,
         advertise_pg_addr: None,
+        listen_grpc_addr: None,
+        advertise_grpc_addr: None,
         availability_zone: None,
         peer_recovery_enabled: false,
         backup_parallel_jobs: 0,
@@ -179,6 +182,7 @@ pub fn run_server(os: NodeOs, disk: Arc<SafekeeperDisk>) -> Result<()> {
         partial_backup_enabled: false,
         partial_backup_timeout: Duration::from_secs(0),
         disable_periodic_broker_push: false,
+        local_wal_horizon_bytes: None,
     };
 
     let mut global = GlobalMap::new(disk, conf.clone())?;
@@ -107,6 +107,7 @@ async fn subscribe(client: Option<BrokerClientChannel>, counter: Arc<AtomicU64>,
             enabled: true,
             tenant_timeline_id: Some(ttid),
         }),
+        ..Default::default()
     };
 
     let mut stream: tonic::Streaming<TypedMessage> = client
@@ -147,6 +148,9 @@ async fn publish(client: Option<BrokerClientChannel>, n_keys: u64) {
                 http_connstr: "zenith-1-sk-1.local:7677".to_owned(),
                 local_start_lsn: 0,
                 availability_zone: None,
+                mconf_generation: 0,
+                mconf_members: Vec::new(),
+                wal_grpc_connstr: "".to_owned(),
             };
             counter += 1;
             yield info;
@@ -7,5 +7,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // anywhere but $OUT_DIR.
     tonic_build::compile_protos("proto/broker.proto")
         .unwrap_or_else(|e| panic!("failed to compile protos {:?}", e));
+    tonic_build::compile_protos("proto/wal_stream.proto")
+        .unwrap_or_else(|e| panic!("failed to compile protos {:?}", e));
     Ok(())
 }
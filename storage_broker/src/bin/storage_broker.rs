@@ -1,9 +1,23 @@
 //! Simple pub-sub based on grpc (tonic) and Tokio broadcast channel for storage
 //! nodes messaging.
 //!
-//! Subscriptions to 1) single timeline 2) all timelines are possible. We could
-//! add subscription to the set of timelines to save grpc streams, but testing
-//! shows many individual streams is also ok.
+//! Subscriptions to 1) single timeline 2) all timelines 3) a set of timelines
+//! are possible. The latter is served from the all timelines channel, filtered
+//! per subscriber.
+//!
+//! SubscribeByFilter subscribers can ask for safekeeper timeline info to be
+//! delta encoded, see [`storage_broker::delta`].
+//!
+//! Several brokers can run as a cluster, so clients can use any of them, see
+//! [`storage_broker::connect_cluster`]. Each broker subscribes to its `--peers`
+//! like any other client, and broadcasts their messages to its own subscribers,
+//! peers included. Messages carry the ids of the brokers they went through, and
+//! a broker doesn't relay those that went through it already, so the peers only
+//! need to connect the cluster, not to form a full mesh. With several paths
+//! between two brokers, a message can reach subscribers more than once. The
+//! latest safekeeper timeline info is kept for a while and sent to new
+//! SubscribeByFilter subscribers right away, so that a client failing over to
+//! another instance doesn't wait for the next round of publications.
 //!
 //! Message is dropped if subscriber can't consume it, not affecting other
 //! subscribers.
@@ -18,12 +32,14 @@ use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Method, StatusCode};
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
+use std::hash::{Hash, Hasher};
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
 use tokio::sync::broadcast::error::RecvError;
 use tokio::time;
@@ -35,18 +51,21 @@ use tracing::*;
 use utils::signals::ShutdownSignals;
 
 use metrics::{Encoder, TextEncoder};
+use storage_broker::delta::DeltaEncoder;
 use storage_broker::metrics::{
-    BROADCASTED_MESSAGES_TOTAL, BROADCAST_DROPPED_MESSAGES_TOTAL, NUM_PUBS, NUM_SUBS_ALL,
-    NUM_SUBS_TIMELINE, PROCESSED_MESSAGES_TOTAL, PUBLISHED_ONEOFF_MESSAGES_TOTAL,
+    BROADCASTED_DELTA_MESSAGES_TOTAL, BROADCASTED_MESSAGES_TOTAL, BROADCAST_DROPPED_MESSAGES_TOTAL,
+    NUM_PUBS, NUM_SUBS_ALL, NUM_SUBS_TIMELINE, PROCESSED_MESSAGES_TOTAL,
+    PUBLISHED_ONEOFF_MESSAGES_TOTAL, RELAYED_MESSAGES_TOTAL,
 };
 use storage_broker::proto::broker_service_server::{BrokerService, BrokerServiceServer};
 use storage_broker::proto::subscribe_safekeeper_info_request::SubscriptionKey as ProtoSubscriptionKey;
 use storage_broker::proto::{
     FilterTenantTimelineId, MessageType, SafekeeperDiscoveryRequest, SafekeeperDiscoveryResponse,
-    SafekeeperTimelineInfo, SubscribeByFilterRequest, SubscribeSafekeeperInfoRequest, TypedMessage,
+    SafekeeperTimelineInfo, SubscribeByFilterRequest, SubscribeSafekeeperInfoRequest,
+    TimelineEvent, TypeSubscription, TypedMessage,
 };
 use storage_broker::{
-    parse_proto_ttid, EitherBody, DEFAULT_KEEPALIVE_INTERVAL, DEFAULT_LISTEN_ADDR,
+    parse_proto_ttid, EitherBody, Uri, DEFAULT_KEEPALIVE_INTERVAL, DEFAULT_LISTEN_ADDR,
 };
 use utils::id::TenantTimelineId;
 use utils::logging::{self, LogFormat};
@@ -58,6 +77,10 @@ project_build_tag!(BUILD_TAG);
 
 const DEFAULT_CHAN_SIZE: usize = 32;
 const DEFAULT_ALL_KEYS_CHAN_SIZE: usize = 16384;
+const DEFAULT_DELTA_FULL_UPDATE_INTERVAL: &str = "30s";
+const DEFAULT_LATEST_STATE_TTL: &str = "10s";
+const PEER_RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const LATEST_STATE_SHARDS: usize = 64;
 
 #[derive(Parser, Debug)]
 #[command(version = GIT_VERSION, about = "Broker for neon storage nodes communication", long_about = None)]
@@ -74,6 +97,18 @@ struct Args {
     /// HTTP/2 keepalive interval.
     #[arg(long, value_parser= humantime::parse_duration, default_value = DEFAULT_KEEPALIVE_INTERVAL)]
     http2_keepalive_interval: Duration,
+    /// How often subscribers with delta updates get the full safekeeper timeline
+    /// info, per safekeeper and timeline.
+    #[arg(long, value_parser= humantime::parse_duration, default_value = DEFAULT_DELTA_FULL_UPDATE_INTERVAL)]
+    delta_full_update_interval: Duration,
+    /// Other brokers of the cluster, comma separated. Messages going through them
+    /// are relayed to our subscribers. To survive the loss of a broker, each one
+    /// should have at least two peers.
+    #[arg(long, value_delimiter = ',')]
+    peers: Vec<Uri>,
+    /// For how long the latest safekeeper timeline info is sent to new subscribers.
+    #[arg(long, value_parser= humantime::parse_duration, default_value = DEFAULT_LATEST_STATE_TTL)]
+    latest_state_ttl: Duration,
     /// Format for logging, either 'plain' or 'json'.
     #[arg(long, default_value = "plain")]
     log_format: String,
@@ -92,6 +127,7 @@ enum Message {
     SafekeeperTimelineInfo(SafekeeperTimelineInfo),
     SafekeeperDiscoveryRequest(SafekeeperDiscoveryRequest),
     SafekeeperDiscoveryResponse(SafekeeperDiscoveryResponse),
+    TimelineEvent(TimelineEvent),
 }
 
 impl Message {
@@ -119,6 +155,11 @@ impl Message {
                     )
                 })?,
             )),
+            MessageType::TimelineEvent => Ok(Message::TimelineEvent(
+                proto_msg
+                    .timeline_event
+                    .ok_or_else(|| Status::new(Code::InvalidArgument, "missing timeline_event"))?,
+            )),
             MessageType::Unknown => Err(Status::new(
                 Code::InvalidArgument,
                 format!("invalid message type: {:?}", proto_msg.r#type),
@@ -144,6 +185,11 @@ impl Message {
                 .as_ref()
                 .map(parse_proto_ttid)
                 .transpose()?),
+            Message::TimelineEvent(msg) => Ok(msg
+                .tenant_timeline_id
+                .as_ref()
+                .map(parse_proto_ttid)
+                .transpose()?),
         }
     }
 
@@ -163,6 +209,7 @@ impl Message {
             Message::SafekeeperDiscoveryResponse(msg) => {
                 res.safekeeper_discovery_response = Some(msg.clone())
             }
+            Message::TimelineEvent(msg) => res.timeline_event = Some(msg.clone()),
         }
         res
     }
@@ -173,10 +220,19 @@ impl Message {
             Message::SafekeeperTimelineInfo(_) => MessageType::SafekeeperTimelineInfo,
             Message::SafekeeperDiscoveryRequest(_) => MessageType::SafekeeperDiscoveryRequest,
             Message::SafekeeperDiscoveryResponse(_) => MessageType::SafekeeperDiscoveryResponse,
+            Message::TimelineEvent(_) => MessageType::TimelineEvent,
         }
     }
 }
 
+/// Message as broadcasted to subscribers.
+#[derive(Clone, Debug, PartialEq)]
+struct Envelope {
+    msg: Message,
+    /// Ids of the brokers the message went through, ending with us.
+    broker_path: Vec<u64>,
+}
+
 #[derive(Copy, Clone, Debug)]
 enum SubscriptionKey {
     All,
@@ -217,7 +273,7 @@ impl SubscriptionKey {
 
 /// Channel to timeline subscribers.
 struct ChanToTimelineSub {
-    chan: broadcast::Sender<Message>,
+    chan: broadcast::Sender<Envelope>,
     /// Tracked separately to know when delete the shmem entry. receiver_count()
     /// is unhandy for that as unregistering and dropping the receiver side
     /// happens at different moments.
@@ -231,7 +287,7 @@ struct SharedState {
     num_subs_to_timelines: i64,
     chans_to_timeline_subs: HashMap<TenantTimelineId, ChanToTimelineSub>,
     num_subs_to_all: i64,
-    chan_to_all_subs: broadcast::Sender<Message>,
+    chan_to_all_subs: broadcast::Sender<Envelope>,
 }
 
 impl SharedState {
@@ -267,7 +323,7 @@ impl SharedState {
         &mut self,
         sub_key: SubscriptionKey,
         timeline_chan_size: usize,
-    ) -> (SubId, broadcast::Receiver<Message>) {
+    ) -> (SubId, broadcast::Receiver<Envelope>) {
         let sub_id = self.next_sub_id;
         self.next_sub_id += 1;
         let sub_rx = match sub_key {
@@ -323,11 +379,91 @@ impl SharedState {
     }
 }
 
+/// Latest safekeeper timeline info per safekeeper and timeline, for new subscribers.
+struct LatestState {
+    ttl: Duration,
+    infos: HashMap<(u64, TenantTimelineId), (Envelope, Instant)>,
+    last_prune: Instant,
+}
+
+impl LatestState {
+    pub fn new(ttl: Duration) -> Self {
+        LatestState {
+            ttl,
+            infos: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    pub fn update(&mut self, ttid: TenantTimelineId, safekeeper_id: u64, envelope: &Envelope) {
+        let now = Instant::now();
+        if now.duration_since(self.last_prune) > self.ttl {
+            let ttl = self.ttl;
+            self.infos
+                .retain(|_, (_, updated_at)| now.duration_since(*updated_at) <= ttl);
+            self.last_prune = now;
+        }
+        self.infos
+            .insert((safekeeper_id, ttid), (envelope.clone(), now));
+    }
+
+    /// Fresh infos of timelines matching `filter`.
+    pub fn snapshot(&self, filter: impl Fn(&TenantTimelineId) -> bool) -> Vec<Envelope> {
+        let now = Instant::now();
+        self.infos
+            .iter()
+            .filter(|((_, ttid), (_, updated_at))| {
+                now.duration_since(*updated_at) <= self.ttl && filter(ttid)
+            })
+            .map(|(_, (envelope, _))| envelope.clone())
+            .collect()
+    }
+}
+
+/// [`LatestState`] sharded by timeline, not to take a single lock for every message.
+struct LatestStates {
+    shards: Vec<parking_lot::Mutex<LatestState>>,
+}
+
+impl LatestStates {
+    pub fn new(ttl: Duration) -> Self {
+        LatestStates {
+            shards: (0..LATEST_STATE_SHARDS)
+                .map(|_| parking_lot::Mutex::new(LatestState::new(ttl)))
+                .collect(),
+        }
+    }
+
+    fn shard(&self, ttid: &TenantTimelineId) -> &parking_lot::Mutex<LatestState> {
+        let mut hasher = DefaultHasher::new();
+        ttid.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    pub fn update(&self, ttid: TenantTimelineId, safekeeper_id: u64, envelope: &Envelope) {
+        self.shard(&ttid)
+            .lock()
+            .update(ttid, safekeeper_id, envelope);
+    }
+
+    /// Fresh infos of timelines matching `filter`.
+    pub fn snapshot(&self, filter: impl Fn(&TenantTimelineId) -> bool) -> Vec<Envelope> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.lock().snapshot(&filter))
+            .collect()
+    }
+}
+
 // SharedState wrapper.
 #[derive(Clone)]
 struct Registry {
+    /// Id of this broker in the cluster, random.
+    id: u64,
     shared_state: Arc<RwLock<SharedState>>,
     timeline_chan_size: usize,
+    // Separate from shared_state, not to take its write lock for each message.
+    latest_state: Arc<LatestStates>,
 }
 
 impl Registry {
@@ -386,21 +522,45 @@ impl Registry {
 
     /// Send msg to relevant subscribers.
     pub fn send_msg(&self, msg: &Message) -> Result<(), Status> {
+        self.broadcast(msg, vec![self.id])
+    }
+
+    /// Send msg received from a peer broker, which went through the brokers of
+    /// `broker_path`, to relevant subscribers. Skipped if it went through us already.
+    pub fn send_relayed_msg(&self, msg: &Message, mut broker_path: Vec<u64>) -> Result<(), Status> {
+        if broker_path.contains(&self.id) {
+            return Ok(());
+        }
+        RELAYED_MESSAGES_TOTAL.inc();
+        broker_path.push(self.id);
+        self.broadcast(msg, broker_path)
+    }
+
+    fn broadcast(&self, msg: &Message, broker_path: Vec<u64>) -> Result<(), Status> {
         PROCESSED_MESSAGES_TOTAL.inc();
+        let ttid = msg.tenant_timeline_id()?;
+        let envelope = Envelope {
+            msg: msg.clone(),
+            broker_path,
+        };
+
+        if let (Some(ttid), Message::SafekeeperTimelineInfo(info)) = (ttid, msg) {
+            self.latest_state
+                .update(ttid, info.safekeeper_id, &envelope);
+        }
 
         // send message to subscribers for everything
         let shared_state = self.shared_state.read();
         // Err means there is no subscribers, it is fine.
-        shared_state.chan_to_all_subs.send(msg.clone()).ok();
+        shared_state.chan_to_all_subs.send(envelope.clone()).ok();
 
         // send message to per timeline subscribers, if there is ttid
-        let ttid = msg.tenant_timeline_id()?;
         if let Some(ttid) = ttid {
             if let Some(subs) = shared_state.chans_to_timeline_subs.get(&ttid) {
                 // Err can't happen here, as tx is destroyed only after removing
                 // from the map the last subscriber along with tx.
                 subs.chan
-                    .send(msg.clone())
+                    .send(envelope)
                     .expect("rx is still in the map with zero subscribers");
             }
         }
@@ -413,7 +573,7 @@ struct Subscriber {
     id: SubId,
     key: SubscriptionKey,
     // Subscriber receives messages from publishers here.
-    sub_rx: broadcast::Receiver<Message>,
+    sub_rx: broadcast::Receiver<Envelope>,
     // to unregister itself from shared state in Drop
     registry: Registry,
     // for logging
@@ -449,6 +609,7 @@ impl Drop for Publisher {
 
 struct Broker {
     registry: Registry,
+    delta_full_update_interval: Duration,
 }
 
 #[tonic::async_trait]
@@ -498,8 +659,8 @@ impl BrokerService for Broker {
             let mut missed_msgs: u64 = 0;
             loop {
                 match subscriber.sub_rx.recv().await {
-                    Ok(info) => {
-                        match info {
+                    Ok(Envelope { msg, .. }) => {
+                        match msg {
                             Message::SafekeeperTimelineInfo(info) => yield info,
                             _ => {},
                         }
@@ -541,27 +702,87 @@ impl BrokerService for Broker {
         let proto_filter = request.into_inner();
         let ttid_filter = proto_filter.tenant_timeline_id.as_ref();
 
-        let sub_key = SubscriptionKey::from_proto_filter_tenant_timeline_id(ttid_filter)?;
+        let mut sub_key = SubscriptionKey::from_proto_filter_tenant_timeline_id(ttid_filter)?;
         let types_set = proto_filter
             .types
             .iter()
             .map(|t| t.r#type)
-            .collect::<std::collections::HashSet<_>>();
+            .collect::<HashSet<_>>();
+        let ttids_set = proto_filter
+            .tenant_timeline_ids
+            .iter()
+            .map(parse_proto_ttid)
+            .collect::<Result<HashSet<_>, _>>()?;
+        if !ttids_set.is_empty() {
+            if let SubscriptionKey::Timeline(_) = sub_key {
+                return Err(Status::new(
+                    Code::InvalidArgument,
+                    "tenant_timeline_id and tenant_timeline_ids are mutually exclusive",
+                ));
+            }
+            if ttids_set.len() == 1 {
+                sub_key = SubscriptionKey::Timeline(*ttids_set.iter().next().unwrap());
+            }
+        }
+        let mut delta_encoder = proto_filter
+            .delta_updates
+            .then(|| DeltaEncoder::new(self.delta_full_update_interval));
 
         let mut subscriber = self.registry.register_subscriber(sub_key, remote_addr);
 
+        // Taken after subscribing, not to miss anything in between.
+        let latest_state = if types_set.contains(&(MessageType::SafekeeperTimelineInfo as i32)) {
+            self.registry.latest_state.snapshot(|ttid| match sub_key {
+                SubscriptionKey::All => ttids_set.is_empty() || ttids_set.contains(ttid),
+                SubscriptionKey::Timeline(key_ttid) => key_ttid == *ttid,
+            })
+        } else {
+            Vec::new()
+        };
+
         // transform rx into stream with item = Result, as method result demands
         let output = async_stream::try_stream! {
+            for Envelope { msg, broker_path } in latest_state {
+                let mut typed_msg = match (&msg, delta_encoder.as_mut()) {
+                    (Message::SafekeeperTimelineInfo(info), Some(encoder)) => {
+                        encoder.encode(info)?.0
+                    }
+                    _ => msg.as_typed_message(),
+                };
+                typed_msg.broker_path = broker_path;
+                yield typed_msg;
+                BROADCASTED_MESSAGES_TOTAL.inc();
+            }
+
             let mut warn_interval = time::interval(Duration::from_millis(1000));
             let mut missed_msgs: u64 = 0;
             loop {
                 match subscriber.sub_rx.recv().await {
-                    Ok(msg) => {
+                    Ok(Envelope { msg, broker_path }) => {
                         let msg_type = msg.message_type() as i32;
-                        if types_set.contains(&msg_type) {
-                            yield msg.as_typed_message();
-                            BROADCASTED_MESSAGES_TOTAL.inc();
+                        if !types_set.contains(&msg_type) {
+                            continue;
                         }
+                        // Subscriptions to a single timeline are filtered by their channel.
+                        if ttids_set.len() > 1 {
+                            let ttid = msg.tenant_timeline_id().ok().flatten();
+                            if !ttid.is_some_and(|ttid| ttids_set.contains(&ttid)) {
+                                continue;
+                            }
+                        }
+                        let mut typed_msg = match (&msg, delta_encoder.as_mut()) {
+                            (Message::SafekeeperTimelineInfo(info), Some(encoder)) => {
+                                let (typed_msg, is_delta) = encoder.encode(info)?;
+                                if is_delta {
+                                    BROADCASTED_DELTA_MESSAGES_TOTAL.inc();
+                                }
+                                typed_msg
+                            }
+                            _ => msg.as_typed_message(),
+                        };
+                        typed_msg.broker_path = broker_path;
+                        yield typed_msg;
+                        BROADCASTED_MESSAGES_TOTAL.inc();
                     },
                     Err(RecvError::Lagged(skipped_msg)) => {
                         BROADCAST_DROPPED_MESSAGES_TOTAL.inc_by(skipped_msg);
@@ -597,6 +818,49 @@ impl BrokerService for Broker {
     }
 }
 
+/// Relay the messages going through the peer broker to our subscribers, forever.
+async fn relay_from_peer(peer: Uri, registry: Registry, keepalive_interval: Duration) {
+    loop {
+        if let Err(e) = relay_from_peer_once(&peer, &registry, keepalive_interval).await {
+            warn!("relaying from peer {peer} failed: {e:#}");
+        }
+        time::sleep(PEER_RECONNECT_INTERVAL).await;
+    }
+}
+
+async fn relay_from_peer_once(
+    peer: &Uri,
+    registry: &Registry,
+    keepalive_interval: Duration,
+) -> anyhow::Result<()> {
+    let mut client = storage_broker::connect(peer.clone(), keepalive_interval)?;
+    let request = SubscribeByFilterRequest {
+        types: [
+            MessageType::SafekeeperTimelineInfo,
+            MessageType::SafekeeperDiscoveryRequest,
+            MessageType::SafekeeperDiscoveryResponse,
+            MessageType::TimelineEvent,
+        ]
+        .into_iter()
+        .map(|t| TypeSubscription { r#type: t as i32 })
+        .collect(),
+        ..Default::default()
+    };
+    let mut stream = client.subscribe_by_filter(request).await?.into_inner();
+    info!("relaying messages from peer {peer}");
+
+    while let Some(mut typed_msg) = stream.message().await? {
+        let broker_path = std::mem::take(&mut typed_msg.broker_path);
+        // A malformed message doesn't break the subscription, skip it.
+        let res =
+            Message::from(typed_msg).and_then(|msg| registry.send_relayed_msg(&msg, broker_path));
+        if let Err(e) = res {
+            warn!("failed to relay message from peer {peer}: {e}");
+        }
+    }
+    anyhow::bail!("end of stream")
+}
+
 // We serve only metrics and healthcheck through http1.
 async fn http1_handler(
     req: hyper::Request<hyper::body::Body>,
@@ -655,11 +919,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let registry = Registry {
+        id: rand::random(),
         shared_state: Arc::new(RwLock::new(SharedState::new(args.all_keys_chan_size))),
         timeline_chan_size: args.timeline_chan_size,
+        latest_state: Arc::new(LatestStates::new(args.latest_state_ttl)),
     };
+    info!("broker id: {}", registry.id);
+    for peer in args.peers {
+        tokio::spawn(relay_from_peer(
+            peer,
+            registry.clone(),
+            args.http2_keepalive_interval,
+        ));
+    }
     let storage_broker_impl = Broker {
         registry: registry.clone(),
+        delta_full_update_interval: args.delta_full_update_interval,
     };
     let storage_broker_server = BrokerServiceServer::new(storage_broker_impl);
 
@@ -714,7 +989,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use storage_broker::proto::TenantTimelineId as ProtoTenantTimelineId;
+    use storage_broker::proto::{TenantTimelineId as ProtoTenantTimelineId, TimelineEventKind};
     use tokio::sync::broadcast::error::TryRecvError;
     use utils::id::{TenantId, TimelineId};
 
@@ -736,6 +1011,9 @@ mod tests {
             http_connstr: "neon-1-sk-1.local:7677".to_owned(),
             local_start_lsn: 0,
             availability_zone: None,
+            mconf_generation: 0,
+            mconf_members: Vec::new(),
+            wal_grpc_connstr: "".to_owned(),
         })
     }
 
@@ -749,12 +1027,18 @@ mod tests {
         "127.0.0.1:8080".parse().unwrap()
     }
 
-    #[tokio::test]
-    async fn test_registry() {
-        let registry = Registry {
+    fn mock_registry() -> Registry {
+        Registry {
+            id: 1,
             shared_state: Arc::new(RwLock::new(SharedState::new(16))),
             timeline_chan_size: 16,
-        };
+            latest_state: Arc::new(LatestStates::new(Duration::from_secs(10))),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry() {
+        let registry = mock_registry();
 
         // subscribe to timeline 2
         let ttid_2 = TenantTimelineId {
@@ -773,7 +1057,7 @@ mod tests {
         publisher.send_msg(&msg_2).expect("failed to send msg");
 
         // msg with key 2 should arrive to subscriber_2
-        assert_eq!(subscriber_2.sub_rx.try_recv().unwrap(), msg_2);
+        assert_eq!(subscriber_2.sub_rx.try_recv().unwrap().msg, msg_2);
 
         // but nothing more
         assert_eq!(
@@ -782,11 +1066,103 @@ mod tests {
         );
 
         // subscriber_all should receive both messages
-        assert_eq!(subscriber_all.sub_rx.try_recv().unwrap(), msg_1);
-        assert_eq!(subscriber_all.sub_rx.try_recv().unwrap(), msg_2);
+        assert_eq!(subscriber_all.sub_rx.try_recv().unwrap().msg, msg_1);
+        assert_eq!(subscriber_all.sub_rx.try_recv().unwrap().msg, msg_2);
         assert_eq!(
             subscriber_all.sub_rx.try_recv().unwrap_err(),
             TryRecvError::Empty
         );
+
+        // relayed messages reach subscribers too, with the brokers they went through
+        registry
+            .send_relayed_msg(&msg_2, vec![7])
+            .expect("failed to relay msg");
+        let relayed = Envelope {
+            msg: msg_2.clone(),
+            broker_path: vec![7, registry.id],
+        };
+        assert_eq!(subscriber_2.sub_rx.try_recv().unwrap(), relayed);
+
+        // but not those which went through us already
+        registry
+            .send_relayed_msg(&msg_2, vec![7, registry.id, 8])
+            .expect("failed to relay msg");
+        assert_eq!(
+            subscriber_2.sub_rx.try_recv().unwrap_err(),
+            TryRecvError::Empty
+        );
+
+        // latest state of each timeline is kept for new subscribers
+        let ttid_2_only = registry.latest_state.snapshot(|ttid| *ttid == ttid_2);
+        assert_eq!(ttid_2_only, vec![relayed]);
+        assert_eq!(registry.latest_state.snapshot(|_| true).len(), 2);
+    }
+
+    fn timeline_event(timeline_id: Vec<u8>) -> TimelineEvent {
+        TimelineEvent {
+            tenant_timeline_id: Some(ProtoTenantTimelineId {
+                tenant_id: vec![0x00; 16],
+                timeline_id,
+            }),
+            pageserver_id: 1,
+            shard_number: 0,
+            shard_count: 0,
+            kind: TimelineEventKind::GcCutoffAdvanced as i32,
+            lsn: 0x1000,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_timeline_events() {
+        let broker = Broker {
+            registry: mock_registry(),
+            delta_full_update_interval: Duration::from_secs(10),
+        };
+
+        let mut request = Request::new(SubscribeByFilterRequest {
+            types: vec![TypeSubscription {
+                r#type: MessageType::TimelineEvent as i32,
+            }],
+            tenant_timeline_ids: vec![ProtoTenantTimelineId {
+                tenant_id: vec![0x00; 16],
+                timeline_id: tli_from_u64(1),
+            }],
+            ..Default::default()
+        });
+        // Request::remote_addr expects the connection info inserted by the server.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        request.extensions_mut().insert(stream.connect_info());
+        let mut subscription = broker
+            .subscribe_by_filter(request)
+            .await
+            .unwrap()
+            .into_inner();
+
+        // An event of another timeline, and another message type of our timeline, are
+        // filtered out.
+        for msg in [
+            Message::TimelineEvent(timeline_event(tli_from_u64(2))),
+            msg(tli_from_u64(1)),
+            Message::TimelineEvent(timeline_event(tli_from_u64(1))),
+        ] {
+            broker
+                .publish_one(Request::new(msg.as_typed_message()))
+                .await
+                .unwrap();
+        }
+
+        let received = time::timeout(Duration::from_secs(10), subscription.next())
+            .await
+            .expect("no message received")
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.r#type, MessageType::TimelineEvent as i32);
+        assert_eq!(
+            received.timeline_event,
+            Some(timeline_event(tli_from_u64(1)))
+        );
     }
 }
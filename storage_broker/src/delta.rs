@@ -0,0 +1,254 @@
+//! Delta encoding of safekeeper timeline info for broker subscriptions.
+//!
+//! Safekeepers publish their full state of every timeline about once a second, even
+//! when nothing but one LSN has changed. Subscribers which asked for delta updates get
+//! instead only the fields which changed since the previous message for the same
+//! safekeeper and timeline, plus a full message now and then, so that a subscriber that
+//! missed the start of the stream, or lost track of the state, recovers quickly.
+//!
+//! [`DeltaEncoder`] runs in the broker, per subscription; [`DeltaDecoder`] in the
+//! subscriber turns the messages back into full ones.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tonic::Status;
+use utils::id::TenantTimelineId;
+
+use crate::parse_proto_ttid;
+use crate::proto::{MessageType, SafekeeperTimelineInfo, TypedMessage};
+
+type Key = (u64, TenantTimelineId);
+
+fn key(info: &SafekeeperTimelineInfo) -> Result<Option<Key>, Status> {
+    Ok(info
+        .tenant_timeline_id
+        .as_ref()
+        .map(parse_proto_ttid)
+        .transpose()?
+        .map(|ttid| (info.safekeeper_id, ttid)))
+}
+
+/// Fields which can be sent in a delta, with their protobuf field numbers. The key
+/// fields, `safekeeper_id` and `tenant_timeline_id`, are always sent.
+macro_rules! delta_fields {
+    ($($field:ident = $num:literal),* $(,)?) => {
+        fn diff(
+            prev: &SafekeeperTimelineInfo,
+            cur: &SafekeeperTimelineInfo,
+        ) -> (SafekeeperTimelineInfo, Vec<u32>) {
+            let mut delta = SafekeeperTimelineInfo {
+                safekeeper_id: cur.safekeeper_id,
+                tenant_timeline_id: cur.tenant_timeline_id.clone(),
+                ..Default::default()
+            };
+            let mut changed = Vec::new();
+            $(
+                if prev.$field != cur.$field {
+                    delta.$field = cur.$field.clone();
+                    changed.push($num);
+                }
+            )*
+            (delta, changed)
+        }
+
+        fn apply(base: &mut SafekeeperTimelineInfo, mut delta: SafekeeperTimelineInfo, changed: &[u32]) {
+            for num in changed {
+                match *num {
+                    $( $num => base.$field = std::mem::take(&mut delta.$field), )*
+                    // Field added in a newer version of the broker.
+                    _ => {}
+                }
+            }
+        }
+    };
+}
+
+delta_fields!(
+    term = 12,
+    last_log_term = 3,
+    flush_lsn = 4,
+    commit_lsn = 5,
+    backup_lsn = 6,
+    remote_consistent_lsn = 7,
+    peer_horizon_lsn = 8,
+    local_start_lsn = 9,
+    safekeeper_connstr = 10,
+    http_connstr = 13,
+    availability_zone = 11,
+    mconf_generation = 14,
+    mconf_members = 15,
+    wal_grpc_connstr = 16,
+);
+
+struct Sent {
+    info: SafekeeperTimelineInfo,
+    last_full_at: Instant,
+}
+
+/// Encodes the safekeeper timeline info sent to one subscriber as deltas from what was
+/// sent to it before.
+pub struct DeltaEncoder {
+    full_update_interval: Duration,
+    sent: HashMap<Key, Sent>,
+    last_prune: Instant,
+}
+
+impl DeltaEncoder {
+    /// A full message is sent for a safekeeper and timeline at least once per
+    /// `full_update_interval`.
+    pub fn new(full_update_interval: Duration) -> Self {
+        DeltaEncoder {
+            full_update_interval,
+            sent: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Make the message to send for `info`. Returns whether the message is a delta.
+    pub fn encode(
+        &mut self,
+        info: &SafekeeperTimelineInfo,
+    ) -> Result<(TypedMessage, bool), Status> {
+        let mut msg = TypedMessage {
+            r#type: MessageType::SafekeeperTimelineInfo as i32,
+            ..Default::default()
+        };
+        let Some(key) = key(info)? else {
+            msg.safekeeper_timeline_info = Some(info.clone());
+            return Ok((msg, false));
+        };
+
+        let now = Instant::now();
+        self.prune(now);
+
+        match self.sent.get_mut(&key) {
+            Some(sent) if now.duration_since(sent.last_full_at) < self.full_update_interval => {
+                let (delta, changed) = diff(&sent.info, info);
+                sent.info = info.clone();
+                msg.safekeeper_timeline_info = Some(delta);
+                msg.delta = true;
+                msg.delta_fields = changed;
+                Ok((msg, true))
+            }
+            _ => {
+                self.sent.insert(
+                    key,
+                    Sent {
+                        info: info.clone(),
+                        last_full_at: now,
+                    },
+                );
+                msg.safekeeper_timeline_info = Some(info.clone());
+                Ok((msg, false))
+            }
+        }
+    }
+
+    /// Forget about timelines which were not updated for a while: the next message for
+    /// them would be a full one anyway.
+    fn prune(&mut self, now: Instant) {
+        if now.duration_since(self.last_prune) < self.full_update_interval {
+            return;
+        }
+        self.last_prune = now;
+        let interval = self.full_update_interval;
+        self.sent
+            .retain(|_, sent| now.duration_since(sent.last_full_at) < interval);
+    }
+}
+
+/// Reconstructs full messages from a subscription with delta updates.
+#[derive(Default)]
+pub struct DeltaDecoder {
+    last: HashMap<Key, SafekeeperTimelineInfo>,
+}
+
+impl DeltaDecoder {
+    /// Returns the full message, or `None` for a delta which can't be applied because
+    /// the full message it is based on was not seen; the next full message fixes that.
+    pub fn decode(&mut self, mut msg: TypedMessage) -> Result<Option<TypedMessage>, Status> {
+        let Some(info) = msg.safekeeper_timeline_info.take() else {
+            return Ok(Some(msg));
+        };
+        let Some(key) = key(&info)? else {
+            msg.safekeeper_timeline_info = Some(info);
+            return Ok(Some(msg));
+        };
+
+        let full = if msg.delta {
+            let Some(base) = self.last.get_mut(&key) else {
+                return Ok(None);
+            };
+            apply(base, info, &msg.delta_fields);
+            base.clone()
+        } else {
+            self.last.insert(key, info.clone());
+            info
+        };
+
+        msg.safekeeper_timeline_info = Some(full);
+        msg.delta = false;
+        msg.delta_fields = Vec::new();
+        Ok(Some(msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::TenantTimelineId as ProtoTenantTimelineId;
+
+    fn info(safekeeper_id: u64, commit_lsn: u64) -> SafekeeperTimelineInfo {
+        SafekeeperTimelineInfo {
+            safekeeper_id,
+            tenant_timeline_id: Some(ProtoTenantTimelineId {
+                tenant_id: vec![0x00; 16],
+                timeline_id: vec![0xFF; 16],
+            }),
+            term: 1,
+            flush_lsn: commit_lsn,
+            commit_lsn,
+            safekeeper_connstr: "neon-1-sk-1.local:7676".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delta_roundtrip() {
+        let mut encoder = DeltaEncoder::new(Duration::from_secs(3600));
+        let mut decoder = DeltaDecoder::default();
+
+        let updates = [info(1, 10), info(2, 10), info(1, 20), info(1, 20), {
+            let mut i = info(1, 30);
+            i.availability_zone = Some("az-1".to_owned());
+            i
+        }];
+        let mut deltas = 0;
+        for update in &updates {
+            let (msg, is_delta) = encoder.encode(update).unwrap();
+            if is_delta {
+                deltas += 1;
+                let sent = msg.safekeeper_timeline_info.as_ref().unwrap();
+                assert!(sent.safekeeper_connstr.is_empty());
+            }
+            let decoded = decoder.decode(msg).unwrap().unwrap();
+            assert_eq!(decoded.safekeeper_timeline_info.as_ref(), Some(update));
+        }
+        assert_eq!(deltas, 3);
+
+        // a decoder which missed the full message can't apply deltas
+        let (msg, is_delta) = encoder.encode(&info(2, 40)).unwrap();
+        assert!(is_delta);
+        assert_eq!(DeltaDecoder::default().decode(msg).unwrap(), None);
+    }
+
+    #[test]
+    fn periodic_full_updates() {
+        let mut encoder = DeltaEncoder::new(Duration::ZERO);
+        for _ in 0..3 {
+            let (_, is_delta) = encoder.encode(&info(1, 10)).unwrap();
+            assert!(!is_delta);
+        }
+    }
+}
@@ -2,9 +2,13 @@ use hyper::body::HttpBody;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
 use tonic::codegen::StdError;
 use tonic::transport::{ClientTlsConfig, Endpoint};
 use tonic::{transport::Channel, Status};
+use tower::discover::Change;
+use tracing::{info, warn};
 use utils::id::{TenantId, TenantTimelineId, TimelineId};
 
 use proto::{
@@ -20,7 +24,9 @@ pub mod proto {
     tonic::include_proto!("storage_broker");
 }
 
+pub mod delta;
 pub mod metrics;
+pub mod wal_stream;
 
 // Re-exports to avoid direct tonic dependency in user crates.
 pub use tonic::Code;
@@ -35,6 +41,10 @@ pub const DEFAULT_ENDPOINT: &str = const_format::formatcp!("http://{DEFAULT_LIST
 pub const DEFAULT_KEEPALIVE_INTERVAL: &str = "5000 ms";
 pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_millis(5000);
 
+// How often the instances of a broker cluster are checked to be reachable, see
+// connect_cluster().
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
 // BrokerServiceClient charged with tonic provided Channel transport; helps to
 // avoid depending on tonic directly in user crates.
 pub type BrokerClientChannel = BrokerServiceClient<Channel>;
@@ -51,6 +61,98 @@ where
     U::Error: std::error::Error + Send + Sync + 'static,
 {
     let uri: Uri = endpoint.try_into()?;
+    let channel = make_endpoint(uri, keepalive_interval)?.connect_lazy();
+    Ok(BrokerClientChannel::new(channel))
+}
+
+// Like connect(), but to a cluster of broker instances, which relay messages
+// to each other. Requests are balanced between the instances which accept TCP
+// connections; a background task checks that and lives as long as the channel.
+// If an instance fails, streams through it fail, and resubscribing picks a
+// healthy one.
+//
+// NB: must be run on a tokio runtime thread, like connect().
+pub fn connect_cluster(
+    endpoints: &[Uri],
+    keepalive_interval: Duration,
+) -> anyhow::Result<BrokerClientChannel> {
+    anyhow::ensure!(!endpoints.is_empty(), "no broker endpoints");
+    if endpoints.len() == 1 {
+        return connect(endpoints[0].clone(), keepalive_interval);
+    }
+
+    let endpoints = endpoints
+        .iter()
+        .map(|uri| make_endpoint(uri.clone(), keepalive_interval))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let (channel, changes) = Channel::balance_channel(endpoints.len());
+    tokio::spawn(health_check_loop(endpoints, changes));
+    Ok(BrokerClientChannel::new(channel))
+}
+
+async fn health_check_loop(
+    endpoints: Vec<Endpoint>,
+    changes: mpsc::Sender<Change<usize, Endpoint>>,
+) {
+    // All instances start as healthy, so that requests don't wait for the first check.
+    for (i, endpoint) in endpoints.iter().enumerate() {
+        if changes
+            .send(Change::Insert(i, endpoint.clone()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+    let mut healthy = vec![true; endpoints.len()];
+
+    loop {
+        tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+        for (i, endpoint) in endpoints.iter().enumerate() {
+            let reachable = is_reachable(endpoint.uri()).await;
+            if reachable == healthy[i] {
+                continue;
+            }
+            // Never remove the last instance: with no instances at all, requests would
+            // hang instead of failing.
+            if !reachable && healthy.iter().filter(|h| **h).count() == 1 {
+                continue;
+            }
+
+            let change = if reachable {
+                info!("broker {} is reachable again", endpoint.uri());
+                Change::Insert(i, endpoint.clone())
+            } else {
+                warn!("broker {} is unreachable, failing over", endpoint.uri());
+                Change::Remove(i)
+            };
+            // The channel was dropped.
+            if changes.send(change).await.is_err() {
+                return;
+            }
+            healthy[i] = reachable;
+        }
+    }
+}
+
+async fn is_reachable(uri: &Uri) -> bool {
+    let Some(host) = uri.host() else {
+        return false;
+    };
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+    matches!(
+        tokio::time::timeout(DEFAULT_CONNECT_TIMEOUT, TcpStream::connect((host, port))).await,
+        Ok(Ok(_))
+    )
+}
+
+fn make_endpoint(uri: Uri, keepalive_interval: Duration) -> anyhow::Result<Endpoint> {
     let mut tonic_endpoint: Endpoint = uri.into();
     // If schema starts with https, start encrypted connection; do plain text
     // otherwise.
@@ -63,8 +165,7 @@ where
         .keep_alive_while_idle(true)
         .connect_timeout(DEFAULT_CONNECT_TIMEOUT);
     //  keep_alive_timeout is 20s by default on both client and server side
-    let channel = tonic_endpoint.connect_lazy();
-    Ok(BrokerClientChannel::new(channel))
+    Ok(tonic_endpoint)
 }
 
 impl BrokerClientChannel {
@@ -40,6 +40,14 @@ pub static BROADCASTED_MESSAGES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     .expect("Failed to register metric")
 });
 
+pub static BROADCASTED_DELTA_MESSAGES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "storage_broker_broadcasted_delta_messages_total",
+        "Number of broadcasted messages which were delta encoded, included in storage_broker_broadcasted_messages_total"
+    )
+    .expect("Failed to register metric")
+});
+
 pub static BROADCAST_DROPPED_MESSAGES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     register_int_counter!(
         "storage_broker_broadcast_dropped_messages_total",
@@ -48,6 +56,14 @@ pub static BROADCAST_DROPPED_MESSAGES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     .expect("Failed to register metric")
 });
 
+pub static RELAYED_MESSAGES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "storage_broker_relayed_messages_total",
+        "Number of messages received from peer brokers, included in storage_broker_processed_messages_total"
+    )
+    .expect("Failed to register metric")
+});
+
 pub static PUBLISHED_ONEOFF_MESSAGES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     register_int_counter!(
         "storage_broker_published_oneoff_messages_total",
@@ -0,0 +1,91 @@
+//! gRPC service streaming WAL from safekeepers to pageservers, see proto/wal_stream.proto.
+//!
+//! The safekeeper serves it with [`serve`] on a dedicated port, and advertises the port in
+//! the `wal_grpc_connstr` of its timeline info; pageservers configured to use it connect with
+//! [`connect`]. Messages are gzip-compressed when the client asks for it with `compression`.
+use tonic::codec::CompressionEncoding;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, Endpoint, Server};
+use tonic::Request;
+
+use proto::wal_stream_service_client::WalStreamServiceClient;
+use proto::wal_stream_service_server::WalStreamServiceServer;
+
+// Code generated by protobuf.
+pub mod proto {
+    #![allow(clippy::derive_partial_eq_without_eq)]
+    tonic::include_proto!("wal_stream");
+}
+
+pub use proto::wal_stream_service_server::WalStreamService;
+// Re-exports for implementing the service without a direct tonic dependency.
+pub use tonic::{Response, Status};
+
+pub type WalStreamClient = WalStreamServiceClient<Channel>;
+
+/// Connect to the WAL stream service at `endpoint`, in the form host:port.
+pub async fn connect(endpoint: &str, compression: bool) -> anyhow::Result<WalStreamClient> {
+    let channel = Endpoint::from_shared(format!("http://{endpoint}"))?
+        .tcp_nodelay(true)
+        .connect()
+        .await?;
+    let mut client = WalStreamServiceClient::new(channel);
+    if compression {
+        client = client
+            .send_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Gzip);
+    }
+    Ok(client)
+}
+
+/// Make a request, authenticated with `auth_token` if there's one.
+pub fn authenticated_request<T>(
+    message: T,
+    auth_token: Option<&str>,
+) -> anyhow::Result<Request<T>> {
+    let mut request = Request::new(message);
+    if let Some(token) = auth_token {
+        let value: MetadataValue<_> = format!("Bearer {token}").parse()?;
+        request.metadata_mut().insert("authorization", value);
+    }
+    Ok(request)
+}
+
+/// The JWT the request is authenticated with, if any.
+pub fn auth_token<T>(request: &Request<T>) -> Option<&str> {
+    request
+        .metadata()
+        .get("authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Serve `service` on `listener` until an error occurs.
+pub async fn serve<S: WalStreamService>(
+    listener: std::net::TcpListener,
+    service: S,
+) -> anyhow::Result<()> {
+    // Tokio's from_std won't do this for us, per its comment.
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let incoming = async_stream::stream! {
+        loop {
+            let accepted = listener.accept().await.map(|(stream, _)| {
+                // Chunks are small, don't delay them.
+                let _ = stream.set_nodelay(true);
+                stream
+            });
+            yield accepted;
+        }
+    };
+
+    let service = WalStreamServiceServer::new(service)
+        .accept_compressed(CompressionEncoding::Gzip)
+        .send_compressed(CompressionEncoding::Gzip);
+    Server::builder()
+        .add_service(service)
+        .serve_with_incoming(incoming)
+        .await?;
+    Ok(())
+}
@@ -11,8 +11,8 @@ use hyper::{Body, Request, Response};
 use hyper::{StatusCode, Uri};
 use metrics::{BuildInfo, NeonMetrics};
 use pageserver_api::models::{
-    TenantConfigRequest, TenantCreateRequest, TenantLocationConfigRequest, TenantShardSplitRequest,
-    TenantTimeTravelRequest, TimelineCreateRequest,
+    ReadOnlyRequest, TenantConfigRequest, TenantCreateRequest, TenantLocationConfigRequest,
+    TenantShardSplitRequest, TenantTimeTravelRequest, TimelineCreateRequest,
 };
 use pageserver_api::shard::TenantShardId;
 use pageserver_client::mgmt_api;
@@ -215,6 +215,36 @@ async fn handle_tenant_config_set(
     json_response(StatusCode::OK, service.tenant_config_set(config_req).await?)
 }
 
+async fn handle_tenant_read_only_put(
+    service: Arc<Service>,
+    mut req: Request<Body>,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
+    check_permissions(&req, Scope::PageServerApi)?;
+
+    let read_only_req = json_request::<ReadOnlyRequest>(&mut req).await?;
+
+    json_response(
+        StatusCode::OK,
+        service
+            .tenant_read_only(tenant_id, Some(read_only_req.reason))
+            .await?,
+    )
+}
+
+async fn handle_tenant_read_only_delete(
+    service: Arc<Service>,
+    req: Request<Body>,
+) -> Result<Response<Body>, ApiError> {
+    let tenant_id: TenantId = parse_request_param(&req, "tenant_id")?;
+    check_permissions(&req, Scope::PageServerApi)?;
+
+    json_response(
+        StatusCode::OK,
+        service.tenant_read_only(tenant_id, None).await?,
+    )
+}
+
 async fn handle_tenant_config_get(
     service: Arc<Service>,
     req: Request<Body>,
@@ -876,6 +906,20 @@ pub fn make_router(
         .get("/v1/tenant/:tenant_id/config", |r| {
             tenant_service_handler(r, handle_tenant_config_get, RequestName("v1_tenant_config"))
         })
+        .put("/v1/tenant/:tenant_id/read_only", |r| {
+            tenant_service_handler(
+                r,
+                handle_tenant_read_only_put,
+                RequestName("v1_tenant_read_only"),
+            )
+        })
+        .delete("/v1/tenant/:tenant_id/read_only", |r| {
+            tenant_service_handler(
+                r,
+                handle_tenant_read_only_delete,
+                RequestName("v1_tenant_read_only"),
+            )
+        })
         .put("/v1/tenant/:tenant_shard_id/location_config", |r| {
             tenant_service_handler(
                 r,
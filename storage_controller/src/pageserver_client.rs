@@ -1,8 +1,8 @@
 use pageserver_api::{
     models::{
         LocationConfig, LocationConfigListResponse, PageserverUtilization, SecondaryProgress,
-        TenantScanRemoteStorageResponse, TenantShardSplitRequest, TenantShardSplitResponse,
-        TimelineCreateRequest, TimelineInfo,
+        TenantConfigTemplate, TenantScanRemoteStorageResponse, TenantShardSplitRequest,
+        TenantShardSplitResponse, TimelineCreateRequest, TimelineInfo,
     },
     shard::TenantShardId,
 };
@@ -73,6 +73,18 @@ impl PageserverClient {
         )
     }
 
+    pub(crate) async fn tenant_config_template(
+        &self,
+        name: &str,
+    ) -> Result<Vec<TenantConfigTemplate>> {
+        measured_request!(
+            "tenant_config_template",
+            crate::metrics::Method::Get,
+            &self.node_id_label,
+            self.inner.tenant_config_template(name).await
+        )
+    }
+
     pub(crate) async fn tenant_time_travel_remote_storage(
         &self,
         tenant_shard_id: TenantShardId,
@@ -41,10 +41,10 @@ use crate::pageserver_client::PageserverClient;
 use pageserver_api::{
     models::{
         self, LocationConfig, LocationConfigListResponse, LocationConfigMode,
-        PageserverUtilization, ShardParameters, TenantConfig, TenantCreateRequest,
-        TenantLocationConfigRequest, TenantLocationConfigResponse, TenantShardLocation,
-        TenantShardSplitRequest, TenantShardSplitResponse, TenantTimeTravelRequest,
-        TimelineCreateRequest, TimelineInfo,
+        PageserverUtilization, ReadOnlyReason, ShardParameters, TenantConfig, TenantConfigTemplate,
+        TenantCreateRequest, TenantLocationConfigRequest, TenantLocationConfigResponse,
+        TenantShardLocation, TenantShardSplitRequest, TenantShardSplitResponse,
+        TenantTimeTravelRequest, TimelineCreateRequest, TimelineInfo,
     },
     shard::{ShardCount, ShardIdentity, ShardNumber, ShardStripeSize, TenantShardId},
     upcall_api::{
@@ -1604,7 +1604,7 @@ impl Service {
 
     pub(crate) async fn tenant_create(
         &self,
-        create_req: TenantCreateRequest,
+        mut create_req: TenantCreateRequest,
     ) -> Result<TenantCreateResponse, ApiError> {
         let tenant_id = create_req.new_tenant_id.tenant_id;
 
@@ -1615,6 +1615,7 @@ impl Service {
             TenantOperations::Create,
         )
         .await;
+        create_req.config = self.resolve_config_template(create_req.config).await?;
         let (response, waiters) = self.do_tenant_create(create_req).await?;
 
         if let Err(e) = self.await_waiters(waiters, RECONCILE_TIMEOUT).await {
@@ -1895,10 +1896,16 @@ impl Service {
                 _ => None,
             };
 
+            // Only the read-only API unsets the read-only mode, see `tenant_read_only`.
+            let mut tenant_config = req.config.tenant_conf.clone();
+            if tenant_config.read_only.is_none() {
+                tenant_config.read_only = shard.config.read_only;
+            }
+
             updates.push(ShardUpdate {
                 tenant_shard_id: *shard_id,
                 placement_policy: placement_policy.clone(),
-                tenant_config: req.config.tenant_conf.clone(),
+                tenant_config,
                 generation: set_generation,
             });
         }
@@ -1950,7 +1957,7 @@ impl Service {
     pub(crate) async fn tenant_location_config(
         &self,
         tenant_shard_id: TenantShardId,
-        req: TenantLocationConfigRequest,
+        mut req: TenantLocationConfigRequest,
     ) -> Result<TenantLocationConfigResponse, ApiError> {
         // We require an exclusive lock, because we are updating both persistent and in-memory state
         let _tenant_lock = trace_exclusive_lock(
@@ -1966,6 +1973,8 @@ impl Service {
             )));
         }
 
+        req.config.tenant_conf = self.resolve_config_template(req.config.tenant_conf).await?;
+
         // First check if this is a creation or an update
         let create_or_update = self.tenant_location_config_prepare(tenant_shard_id.tenant_id, req);
 
@@ -2074,6 +2083,62 @@ impl Service {
         Ok(result)
     }
 
+    /// Resolve the template that `config` references, if any, so that the stored config is
+    /// complete and pinned to a version, see `pageserver::tenant::config_templates`. The
+    /// template is fetched from an available pageserver.
+    async fn resolve_config_template(
+        &self,
+        config: TenantConfig,
+    ) -> Result<TenantConfig, ApiError> {
+        let Some(reference) = &config.template else {
+            return Ok(config);
+        };
+        let (name, version) =
+            TenantConfigTemplate::parse_reference(reference).map_err(ApiError::BadRequest)?;
+
+        let node = {
+            let locked = self.inner.read().unwrap();
+            locked
+                .nodes
+                .values()
+                .find(|node| node.is_available())
+                .cloned()
+        };
+        let Some(node) = node else {
+            return Err(ApiError::ResourceUnavailable(
+                "no available pageserver to resolve the config template".into(),
+            ));
+        };
+        let versions = node
+            .with_client_retries(
+                |client| async move { client.tenant_config_template(name).await },
+                &self.config.jwt_token,
+                1,
+                3,
+                SHORT_RECONCILE_TIMEOUT,
+                &self.cancel,
+            )
+            .await;
+        let versions = match versions {
+            None => return Err(ApiError::ShuttingDown),
+            Some(Err(mgmt_api::Error::ApiError(StatusCode::NOT_FOUND, _))) => Vec::new(),
+            Some(Err(e)) => {
+                return Err(ApiError::InternalServerError(anyhow::anyhow!(
+                    "fetch config template {name:?} from {node}: {e}"
+                )))
+            }
+            Some(Ok(versions)) => versions,
+        };
+        let Some(template) = TenantConfigTemplate::find(&versions, version) else {
+            return Err(ApiError::NotFound(
+                anyhow::anyhow!("no tenant config template {reference:?}").into(),
+            ));
+        };
+        template
+            .apply(&config)
+            .map_err(ApiError::InternalServerError)
+    }
+
     pub(crate) async fn tenant_config_set(&self, req: TenantConfigRequest) -> Result<(), ApiError> {
         // We require an exclusive lock, because we are updating persistent and in-memory state
         let _tenant_lock = trace_exclusive_lock(
@@ -2084,11 +2149,60 @@ impl Service {
         .await;
 
         let tenant_id = req.tenant_id;
-        let config = req.config;
+        let mut config = self.resolve_config_template(req.config).await?;
+        // Only the read-only API unsets the read-only mode, see `tenant_read_only`.
+        if config.read_only.is_none() {
+            config.read_only = self.tenant_shared_config(tenant_id)?.read_only;
+        }
+
+        self.apply_tenant_config(tenant_id, config).await
+    }
 
+    /// Reject the ingest of WAL into the tenant while `reason` is set, or accept it again. The
+    /// mode is carried in the config of the tenant, so that it follows the tenant through
+    /// migrations and splits.
+    pub(crate) async fn tenant_read_only(
+        &self,
+        tenant_id: TenantId,
+        reason: Option<ReadOnlyReason>,
+    ) -> Result<(), ApiError> {
+        let _tenant_lock = trace_exclusive_lock(
+            &self.tenant_op_locks,
+            tenant_id,
+            TenantOperations::ConfigSet,
+        )
+        .await;
+
+        let mut config = self.tenant_shared_config(tenant_id)?;
+        config.read_only = reason;
+        self.apply_tenant_config(tenant_id, config).await
+    }
+
+    /// The config of the tenant, the same for all its shards.
+    fn tenant_shared_config(&self, tenant_id: TenantId) -> Result<TenantConfig, ApiError> {
+        let locked = self.inner.read().unwrap();
+        match locked
+            .tenants
+            .range(TenantShardId::tenant_range(tenant_id))
+            .next()
+        {
+            Some((_tenant_shard_id, shard)) => Ok(shard.config.clone()),
+            None => Err(ApiError::NotFound(
+                anyhow::anyhow!("Tenant not found").into(),
+            )),
+        }
+    }
+
+    /// Store `config` for all the shards of the tenant and reconcile them. The caller holds the
+    /// exclusive lock of the tenant.
+    async fn apply_tenant_config(
+        &self,
+        tenant_id: TenantId,
+        config: TenantConfig,
+    ) -> Result<(), ApiError> {
         self.persistence
             .update_tenant_shard(
-                TenantFilter::Tenant(req.tenant_id),
+                TenantFilter::Tenant(tenant_id),
                 None,
                 Some(config.clone()),
                 None,
@@ -2123,22 +2237,7 @@ impl Service {
         &self,
         tenant_id: TenantId,
     ) -> Result<HashMap<&str, serde_json::Value>, ApiError> {
-        let config = {
-            let locked = self.inner.read().unwrap();
-
-            match locked
-                .tenants
-                .range(TenantShardId::tenant_range(tenant_id))
-                .next()
-            {
-                Some((_tenant_shard_id, shard)) => shard.config.clone(),
-                None => {
-                    return Err(ApiError::NotFound(
-                        anyhow::anyhow!("Tenant not found").into(),
-                    ))
-                }
-            }
-        };
+        let config = self.tenant_shared_config(tenant_id)?;
 
         // Unlike the pageserver, we do not have a set of global defaults: the config is
         // entirely per-tenant.  Therefore the distinction between `tenant_specific_overrides`
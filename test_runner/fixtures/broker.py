@@ -1,8 +1,8 @@
 import subprocess
 import time
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 from pathlib import Path
-from typing import Any, Optional
+from typing import Any, List, Optional
 
 from fixtures.log_helper import log
 
@@ -15,6 +15,8 @@ class NeonBroker:
     port: int
     neon_binpath: Path
     handle: Optional[subprocess.Popen[Any]] = None  # handle of running daemon
+    # client urls of the other brokers of the cluster, see storage_broker --peers
+    peers: List[str] = field(default_factory=list)
 
     def listen_addr(self):
         return f"127.0.0.1:{self.port}"
@@ -37,6 +39,8 @@ class NeonBroker:
                 str(self.neon_binpath / "storage_broker"),
                 f"--listen-addr={listen_addr}",
             ]
+            if self.peers:
+                args.append(f"--peers={','.join(self.peers)}")
             self.handle = subprocess.Popen(args, stdout=logfile, stderr=logfile)
 
         # wait for start
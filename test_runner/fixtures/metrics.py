@@ -134,6 +134,8 @@ PAGESERVER_GLOBAL_METRICS: Tuple[str, ...] = (
     *histogram("pageserver_wait_lsn_seconds"),
     *histogram("pageserver_remote_operation_seconds"),
     *histogram("pageserver_io_operations_seconds"),
+    *histogram("pageserver_layer_write_phase_seconds"),
+    *histogram("pageserver_background_read_wait_seconds"),
     "pageserver_tenant_states_count",
 )
 
@@ -149,6 +151,15 @@ PAGESERVER_PER_TENANT_METRICS: Tuple[str, ...] = (
     "pageserver_storage_operations_seconds_sum_total",
     "pageserver_evictions_total",
     "pageserver_evictions_with_low_residence_duration_total",
+    "pageserver_ancestor_prefetch_bytes_total",
+    "pageserver_wal_ingest_seconds_total",
+    "pageserver_walreceiver_reconnects_total",
+    "pageserver_task_cpu_seconds_total",
+    "pageserver_task_io_bytes_total",
+    *histogram("pageserver_getpage_latency_by_read_path_seconds"),
+    "pageserver_getpage_slo_requests_total",
+    "pageserver_getpage_slo_burn_rate",
+    "pageserver_compaction_debt_bytes",
     *PAGESERVER_PER_TENANT_REMOTE_TIMELINE_CLIENT_METRICS,
     # "pageserver_directory_entries_count", -- only used if above a certain threshold
     # "pageserver_broken_tenants_count" -- used only for broken
@@ -1861,6 +1861,7 @@ class NeonCli(AbstractNeonCli):
         remote_ext_config: Optional[str] = None,
         pageserver_id: Optional[int] = None,
         allow_multiple=False,
+        keep_pgdata=False,
     ) -> "subprocess.CompletedProcess[str]":
         args = [
             "endpoint",
@@ -1877,6 +1878,8 @@ class NeonCli(AbstractNeonCli):
             args.extend(["--pageserver-id", str(pageserver_id)])
         if allow_multiple:
             args.extend(["--allow-multiple"])
+        if keep_pgdata:
+            args.extend(["--keep-pgdata"])
 
         res = self.raw_cli(args)
         res.check_returncode()
@@ -3406,6 +3409,7 @@ class Endpoint(PgProtocol):
         remote_ext_config: Optional[str] = None,
         pageserver_id: Optional[int] = None,
         allow_multiple: bool = False,
+        keep_pgdata: bool = False,
     ) -> "Endpoint":
         """
         Start the Postgres instance.
@@ -3422,6 +3426,7 @@ class Endpoint(PgProtocol):
             remote_ext_config=remote_ext_config,
             pageserver_id=pageserver_id,
             allow_multiple=allow_multiple,
+            keep_pgdata=keep_pgdata,
         )
         self.running = True
 
@@ -3852,11 +3857,11 @@ class S3Scrubber:
             log.error(stdout)
             raise
 
-    def tenant_snapshot(self, tenant_id: TenantId, output_path: Path):
-        stdout = self.scrubber_cli(
-            ["tenant-snapshot", "--tenant-id", str(tenant_id), "--output-path", str(output_path)],
-            timeout=30,
-        )
+    def tenant_snapshot(self, tenant_id: TenantId, output_path: Path, verify_layers: bool = False):
+        args = ["tenant-snapshot", "--tenant-id", str(tenant_id), "--output-path", str(output_path)]
+        if verify_layers:
+            args.append("--verify-layers")
+        stdout = self.scrubber_cli(args, timeout=30)
         log.info(f"tenant-snapshot output: {stdout}")
 
 
@@ -3908,7 +3913,7 @@ def pytest_addoption(parser: Parser):
 
 
 SMALL_DB_FILE_NAME_REGEX: re.Pattern = re.compile(  # type: ignore[type-arg]
-    r"config-v1|heatmap-v1|metadata|.+\.(?:toml|pid|json|sql|conf)"
+    r"config-v1|heatmap-v1|format_version|metadata|.+\.(?:toml|pid|json|sql|conf)"
 )
 
 
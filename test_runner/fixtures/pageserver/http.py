@@ -5,7 +5,7 @@ import time
 from collections import defaultdict
 from dataclasses import dataclass
 from datetime import datetime
-from typing import Any, Dict, List, Optional, Set, Tuple, Union
+from typing import Any, Dict, Iterator, List, Optional, Set, Tuple, Union
 
 import requests
 from requests.adapters import HTTPAdapter
@@ -116,12 +116,14 @@ class LayerMapInfo:
 class TenantConfig:
     tenant_specific_overrides: Dict[str, Any]
     effective_config: Dict[str, Any]
+    template_overrides: Optional[Dict[str, Any]] = None
 
     @classmethod
     def from_json(cls, d: Dict[str, Any]) -> TenantConfig:
         return TenantConfig(
             tenant_specific_overrides=d["tenant_specific_overrides"],
             effective_config=d["effective_config"],
+            template_overrides=d.get("template_overrides"),
         )
 
 
@@ -180,6 +182,13 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
     def check_status(self):
         self.get(f"http://localhost:{self.port}/v1/status").raise_for_status()
 
+    def page_service_protocol(self) -> List[Dict[str, Any]]:
+        res = self.get(f"http://localhost:{self.port}/v1/page_service/protocol")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return res_json
+
     def configure_failpoints(self, config_strings: Tuple[str, str] | List[Tuple[str, str]]):
         self.is_testing_enabled_or_skip()
 
@@ -204,6 +213,18 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         res = self.post(f"http://localhost:{self.port}/v1/reload_auth_validation_keys")
         self.verbose_error(res)
 
+    def reload_config(self) -> Dict[str, Any]:
+        """
+        Returns the report of changes both when they were applied and when some of them
+        require a restart: check `rejected`.
+        """
+        res = self.post(f"http://localhost:{self.port}/v1/reload_config")
+        if res.status_code != 409:
+            self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
     def tenant_list(self) -> List[Dict[Any, Any]]:
         res = self.get(f"http://localhost:{self.port}/v1/tenant")
         self.verbose_error(res)
@@ -263,10 +284,14 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         )
         self.verbose_error(res)
 
-    def tenant_detach(self, tenant_id: TenantId, detach_ignored=False, timeout_secs=None):
+    def tenant_detach(
+        self, tenant_id: TenantId, detach_ignored=False, timeout_secs=None, keep_local=False
+    ):
         params = {}
         if detach_ignored:
             params["detach_ignored"] = "true"
+        if keep_local:
+            params["keep_local"] = "true"
 
         kwargs = {}
         if timeout_secs is not None:
@@ -366,6 +391,162 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         self.verbose_error(res)
         return TenantConfig.from_json(res.json())
 
+    def timeline_upload_queue(
+        self, tenant_id: Union[TenantId, TenantShardId], timeline_id: TimelineId
+    ) -> Dict[str, Any]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/upload_queue"
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def tenant_resource_usage(self, tenant_id: Union[TenantId, TenantShardId]) -> Dict[str, Any]:
+        """
+        Returns the usage by task kind, e.g. `{"Compaction": {"cpu_seconds": 0.1, ...}}`.
+        """
+        res = self.get(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/resource_usage")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return {task.pop("task_kind"): task for task in res_json["tasks"]}
+
+    def tenant_activation_profile(
+        self, tenant_id: Union[TenantId, TenantShardId]
+    ) -> Dict[str, Any]:
+        """
+        Returns `{"activated_after_millis": ..., "phases": {"list_timelines": 12, ...}}`.
+        """
+        res = self.get(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/activation_profile")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        res_json["phases"] = {p["phase"]: p["millis"] for p in res_json["phases"]}
+        return res_json
+
+    def tenant_state_transitions(
+        self, tenant_id: Union[TenantId, TenantShardId]
+    ) -> List[Dict[str, Any]]:
+        res = self.get(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/state_transitions")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return res_json
+
+    def tenant_legal_hold(self, tenant_id: Union[TenantId, TenantShardId]) -> Dict[str, Any]:
+        res = self.get(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/legal_hold")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def tenant_legal_hold_set(
+        self, tenant_id: Union[TenantId, TenantShardId], reason: str
+    ) -> Dict[str, Any]:
+        res = self.put(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/legal_hold",
+            json={"reason": reason},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def tenant_legal_hold_release(self, tenant_id: Union[TenantId, TenantShardId]):
+        res = self.delete(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/legal_hold")
+        self.verbose_error(res)
+
+    def tenant_read_only_set(self, tenant_id: Union[TenantId, TenantShardId], reason: str):
+        res = self.put(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/read_only",
+            json={"reason": reason},
+        )
+        self.verbose_error(res)
+
+    def tenant_read_only_unset(self, tenant_id: Union[TenantId, TenantShardId]):
+        res = self.delete(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/read_only")
+        self.verbose_error(res)
+
+    def tenant_selftest(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        remote: bool = False,
+    ) -> Dict[str, Any]:
+        body: Dict[str, Any] = {"remote": remote}
+        res = self.post(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/selftest", json=body)
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def tenant_stale_timelines(
+        self, tenant_id: Union[TenantId, TenantShardId], idle_days: int
+    ) -> List[Dict[str, Any]]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/stale_timelines",
+            params={"idle_days": idle_days},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return res_json
+
+    def tenant_stale_timelines_delete(
+        self, tenant_id: Union[TenantId, TenantShardId], idle_days: int, dry_run: bool = False
+    ) -> List[Dict[str, Any]]:
+        res = self.delete(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/stale_timelines",
+            params={"idle_days": idle_days, "dry_run": str(dry_run).lower()},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return res_json
+
+    def tenant_export(self, tenant_id: Union[TenantId, TenantShardId], path: str) -> Dict[str, Any]:
+        res = self.post(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/export",
+            json={"path": path},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def tenant_import(self, tenant_id: TenantId, path: str, generation: int) -> Dict[str, Any]:
+        res = self.post(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/import",
+            json={"path": path, "generation": generation},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def tenant_config_templates(self) -> List[Dict[str, Any]]:
+        res = self.get(f"http://localhost:{self.port}/v1/tenant_config_template")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return res_json
+
+    def tenant_config_template(self, name: str) -> List[Dict[str, Any]]:
+        res = self.get(f"http://localhost:{self.port}/v1/tenant_config_template/{name}")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return res_json
+
+    def tenant_config_template_put(self, name: str, config: Dict[str, Any]) -> Dict[str, Any]:
+        res = self.put(
+            f"http://localhost:{self.port}/v1/tenant_config_template/{name}", json=config
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
     def tenant_heatmap_upload(self, tenant_id: Union[TenantId, TenantShardId]):
         res = self.post(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/heatmap_upload")
         self.verbose_error(res)
@@ -380,11 +561,72 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         self.verbose_error(res)
         return (res.status_code, res.json())
 
-    def set_tenant_config(self, tenant_id: Union[TenantId, TenantShardId], config: dict[str, Any]):
+    def load_scores(self) -> Dict[str, Dict[str, Any]]:
+        """
+        Returns the load scores by tenant shard id, e.g. `{"<id>": {"resident_bytes": 1, ...}}`.
+        """
+        res = self.get(f"http://localhost:{self.port}/v1/load_scores")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return {score.pop("tenant_shard_id"): score for score in res_json["tenants"]}
+
+    def pg_versions(self) -> Dict[int, Dict[str, Any]]:
+        """
+        Returns the usage of the Postgres versions by version, e.g. `{15: {"installed": True, ...}}`.
+        """
+        res = self.get(f"http://localhost:{self.port}/v1/pg_versions")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return {usage.pop("pg_version"): usage for usage in res_json}
+
+    def tenant_rebalance_prepare_give_up(self, tenant_id: Union[TenantId, TenantShardId]):
+        res = self.post(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/rebalance/prepare_give_up"
+        )
+        self.verbose_error(res)
+
+    def tenant_rebalance_prepare_receive(
+        self, tenant_id: Union[TenantId, TenantShardId], wait_ms: Optional[int] = None
+    ) -> tuple[int, dict[Any, Any]]:
+        url = f"http://localhost:{self.port}/v1/tenant/{tenant_id}/rebalance/prepare_receive"
+        if wait_ms is not None:
+            url = url + f"?wait_ms={wait_ms}"
+        res = self.post(url)
+        self.verbose_error(res)
+        return (res.status_code, res.json())
+
+    def startup_repairs(self) -> list[dict[str, Any]]:
+        res = self.get(f"http://localhost:{self.port}/v1/startup_repairs")
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, list)
+        return res_json
+
+    def tenant_secondary_remote_index(
+        self, tenant_id: Union[TenantId, TenantShardId]
+    ) -> dict[Any, Any]:
+        res = self.get(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/secondary/remote_index")
+        self.verbose_error(res)
+        return res.json()
+
+    def tenant_config_etag(self, tenant_id: Union[TenantId, TenantShardId]) -> str:
+        res = self.get(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/config")
+        self.verbose_error(res)
+        return res.headers["ETag"]
+
+    def set_tenant_config(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        config: dict[str, Any],
+        if_match: Optional[str] = None,
+    ):
         assert "tenant_id" not in config.keys()
         res = self.put(
             f"http://localhost:{self.port}/v1/tenant/config",
             json={**config, "tenant_id": str(tenant_id)},
+            headers={"If-Match": if_match} if if_match is not None else None,
         )
         self.verbose_error(res)
 
@@ -394,13 +636,14 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         inserts: Optional[Dict[str, Any]] = None,
         removes: Optional[List[str]] = None,
     ):
+        etag = self.tenant_config_etag(tenant_id)
         current = self.tenant_config(tenant_id).tenant_specific_overrides
         if inserts is not None:
             current.update(inserts)
         if removes is not None:
             for key in removes:
                 del current[key]
-        self.set_tenant_config(tenant_id, current)
+        self.set_tenant_config(tenant_id, current, if_match=etag)
 
     def tenant_size(self, tenant_id: Union[TenantId, TenantShardId]) -> int:
         return self.tenant_size_and_modelinputs(tenant_id)[0]
@@ -482,6 +725,7 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         ancestor_timeline_id: Optional[TimelineId] = None,
         ancestor_start_lsn: Optional[Lsn] = None,
         existing_initdb_timeline_id: Optional[TimelineId] = None,
+        snapshot_id: Optional[str] = None,
         **kwargs,
     ) -> Dict[Any, Any]:
         body: Dict[str, Any] = {
@@ -491,6 +735,7 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
             "existing_initdb_timeline_id": str(existing_initdb_timeline_id)
             if existing_initdb_timeline_id
             else None,
+            "snapshot_id": snapshot_id,
         }
         if pg_version != PgVersion.NOT_SET:
             body["pg_version"] = int(pg_version)
@@ -639,6 +884,137 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         res_json = res.json()
         return res_json
 
+    def timeline_lsn_by_timestamp(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+        timestamp: datetime,
+    ) -> dict[str, Any]:
+        """
+        The sampled commits around the naive UTC `timestamp`, see `LsnTimestampBracket`.
+        """
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/lsn_by_timestamp",
+            params={"timestamp": f"{timestamp.isoformat()}Z"},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_timestamp_by_lsn(
+        self, tenant_id: Union[TenantId, TenantShardId], timeline_id: TimelineId, lsn: Lsn
+    ) -> dict[str, Any]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/timestamp_by_lsn",
+            params={"lsn": str(lsn)},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_explain_read(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+        key: str,
+        lsn: Lsn,
+    ) -> dict[str, Any]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/explain_read",
+            params={"key": key, "lsn": str(lsn)},
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_invalidations(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+        timeout: float = 30,
+    ) -> Iterator[dict[str, Any]]:
+        """
+        Subscribe to the key ranges changed by ingest. The subscription is active once this
+        returns, and the iterator yields the notifications as they arrive.
+        """
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/invalidations",
+            stream=True,
+            timeout=timeout,
+        )
+        self.verbose_error(res)
+        return (json.loads(line) for line in res.iter_lines() if line)
+
+    def timeline_relation_sizes(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+        lsn: Optional[Lsn] = None,
+    ) -> dict[str, Any]:
+        params: dict[str, str] = {}
+        if lsn is not None:
+            params["lsn"] = str(lsn)
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/relation_sizes",
+            params=params,
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_key_space_stats(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+        limit: Optional[int] = None,
+    ) -> dict[str, Any]:
+        params: dict[str, str] = {}
+        if limit is not None:
+            params["limit"] = str(limit)
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/key_space_stats",
+            params=params,
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_access_log(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+    ) -> dict[str, Any]:
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/access_log",
+        )
+        self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
+    def timeline_rel_page(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+        rel: str,
+        blkno: int,
+        lsn: Lsn,
+    ) -> bytes:
+        """
+        `rel` is formatted as <spcnode>/<dbnode>/<relnode>[_fsm|_vm|_init].
+        """
+        res = self.get(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/rel_page",
+            params={"rel": rel, "blkno": str(blkno), "lsn": str(lsn)},
+        )
+        self.verbose_error(res)
+        return res.content
+
     def timeline_layer_map_info(
         self, tenant_id: Union[TenantId, TenantShardId], timeline_id: TimelineId
     ):
@@ -674,17 +1050,45 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         res_json = res.json()
         assert res_json is None
 
+    def timeline_wait_lsn(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+        lsn: Lsn,
+        timeout: Optional[str] = None,
+        flush: bool = False,
+    ) -> dict[str, Any]:
+        """
+        Returns the response both when the LSN was reached and when the timeout expired:
+        check `reached`.
+        """
+        body: dict[str, Any] = {"lsn": str(lsn), "flush": flush}
+        if timeout is not None:
+            body["timeout"] = timeout
+        res = self.post(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/wait_lsn",
+            json=body,
+        )
+        if res.status_code != 408:
+            self.verbose_error(res)
+        res_json = res.json()
+        assert isinstance(res_json, dict)
+        return res_json
+
     def timeline_spawn_download_remote_layers(
         self,
         tenant_id: Union[TenantId, TenantShardId],
         timeline_id: TimelineId,
         max_concurrent_downloads: int,
+        max_bytes_per_second: Optional[int] = None,
     ) -> dict[str, Any]:
-        body = {
+        body: dict[str, Any] = {
             "max_concurrent_downloads": max_concurrent_downloads,
         }
+        if max_bytes_per_second is not None:
+            body["max_bytes_per_second"] = max_bytes_per_second
         res = self.post(
-            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/download_remote_layers",
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/download_layers",
             json=body,
         )
         self.verbose_error(res)
@@ -701,7 +1105,7 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         poll_state=None,
     ) -> None | dict[str, Any]:
         res = self.get(
-            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/download_remote_layers",
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/download_layers",
         )
         self.verbose_error(res)
         res_json = res.json()
@@ -722,9 +1126,10 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         max_concurrent_downloads: int,
         errors_ok=False,
         at_least_one_download=True,
+        max_bytes_per_second: Optional[int] = None,
     ):
         res = self.timeline_spawn_download_remote_layers(
-            tenant_id, timeline_id, max_concurrent_downloads
+            tenant_id, timeline_id, max_concurrent_downloads, max_bytes_per_second
         )
         while True:
             completed = self.timeline_poll_download_remote_layers_status(
@@ -859,6 +1264,11 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         self.verbose_error(res)
         return res.json()
 
+    def disk_usage_eviction_offloaded(self) -> list[dict[str, Any]]:
+        res = self.get(f"http://localhost:{self.port}/v1/disk_usage_eviction/offloaded")
+        self.verbose_error(res)
+        return res.json()
+
     def tenant_break(self, tenant_id: Union[TenantId, TenantShardId]):
         res = self.put(f"http://localhost:{self.port}/v1/tenant/{tenant_id}/break")
         self.verbose_error(res)
@@ -10,6 +10,7 @@ from typing import Any, Dict, List, Optional, Union
 
 import boto3
 import toml
+import zstandard
 from mypy_boto3_s3 import S3Client
 
 from fixtures.log_helper import log
@@ -79,6 +80,19 @@ class MockS3Server:
         self.subprocess.kill()
 
 
+STORAGE_METADATA_SUFFIX = ".metadata"
+ZSTD_MAGIC = b"\x28\xb5\x2f\xfd"
+
+
+def decode_index_part(content: bytes) -> Any:
+    """
+    Index parts are zstd-compressed above the pageserver's `index_part_compression_threshold`.
+    """
+    if content.startswith(ZSTD_MAGIC):
+        content = zstandard.ZstdDecompressor().decompressobj().decompress(content)
+    return json.loads(content)
+
+
 @dataclass
 class LocalFsStorage:
     root: Path
@@ -91,7 +105,11 @@ class LocalFsStorage:
 
     def timeline_latest_generation(self, tenant_id, timeline_id):
         timeline_files = os.listdir(self.timeline_path(tenant_id, timeline_id))
-        index_parts = [f for f in timeline_files if f.startswith("index_part")]
+        index_parts = [
+            f
+            for f in timeline_files
+            if f.startswith("index_part") and not f.endswith(STORAGE_METADATA_SUFFIX)
+        ]
 
         def parse_gen(filename):
             log.info(f"parsing index_part '{filename}'")
@@ -131,7 +149,17 @@ class LocalFsStorage:
         return self.timeline_path(tenant_id, timeline_id) / filename
 
     def index_content(self, tenant_id: TenantId, timeline_id: TimelineId):
-        with self.index_path(tenant_id, timeline_id).open("r") as f:
+        return decode_index_part(self.index_path(tenant_id, timeline_id).read_bytes())
+
+    def index_metadata(self, tenant_id: TenantId, timeline_id: TimelineId) -> Dict[str, str]:
+        """
+        The storage metadata of the latest index part, which LocalFs keeps next to it.
+        """
+        path = self.index_path(tenant_id, timeline_id)
+        metadata_path = path.with_name(path.name + STORAGE_METADATA_SUFFIX)
+        if not metadata_path.exists():
+            return {}
+        with metadata_path.open("r") as f:
             return json.load(f)
 
     def heatmap_path(self, tenant_id: TenantId) -> Path:
@@ -170,7 +170,8 @@ def print_gc_result(row: Dict[str, Any]):
     log.info("GC duration {elapsed} ms".format_map(row))
     log.info(
         "  total: {layers_total}, needed_by_cutoff {layers_needed_by_cutoff}, needed_by_pitr {layers_needed_by_pitr}"
-        " needed_by_branches: {layers_needed_by_branches}, not_updated: {layers_not_updated}, removed: {layers_removed}".format_map(
+        " needed_by_branches: {layers_needed_by_branches},"
+        " needed_by_pinned_relations: {layers_needed_by_pinned_relations}, not_updated: {layers_not_updated}, removed: {layers_removed}".format_map(
             row
         )
     )
@@ -188,9 +188,18 @@ def test_fully_custom_config(positive_env: NeonEnv):
             "max": 1000,
         },
         "trace_read_requests": True,
+        "capture_wal": True,
+        "branch_image_creation_threshold": 1048576,
         "walreceiver_connect_timeout": "13m",
         "image_layer_creation_check_threshold": 1,
         "switch_aux_file_policy": "CrossValidation",
+        "ephemeral_bytes_limit": 64 * 1024 * 1024,
+        "ancestor_prefetch": False,
+        "overlap_repair_threshold": 50,
+        "page_request_deadline": "30s",
+        "sequential_prefetch": True,
+        "branch_creation_max_compaction_debt": 1024 * 1024 * 1024,
+        "branch_creation_debt_wait": "1m",
     }
 
     ps_http = env.pageserver.http_client()
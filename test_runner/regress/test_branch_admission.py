@@ -0,0 +1,63 @@
+from concurrent.futures import ThreadPoolExecutor
+
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.types import TimelineId
+from fixtures.utils import wait_until
+from fixtures.workload import Workload
+
+
+# Check that branches can't be created while the compaction debt of the tenant is above the
+# limit, and that their creation waits for compaction when configured to.
+def test_branch_admission(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start(
+        initial_tenant_conf={
+            # The test runs compaction manually.
+            "gc_period": "0s",
+            "compaction_period": "0s",
+            # Small checkpoint distance to create a few L0 layers.
+            "checkpoint_distance": 1024**2,
+            "compaction_target_size": 1024**2,
+            "compaction_threshold": 2,
+            "branch_creation_max_compaction_debt": 1,
+        }
+    )
+    env.pageserver.allowed_errors.append(".*refusing to create a branch.*")
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+
+    workload = Workload(env, tenant_id, timeline_id)
+    workload.init(env.pageserver.id)
+    for _ in range(4):
+        workload.write_rows(10000, env.pageserver.id)
+
+    with pytest.raises(PageserverApiException, match="compaction debt") as exc:
+        ps_http.timeline_create(env.pg_version, tenant_id, TimelineId.generate(), timeline_id)
+    assert exc.value.status_code == 429
+    debt = ps_http.get_metrics().query_one(
+        "pageserver_compaction_debt_bytes", filter={"tenant_id": str(tenant_id)}
+    )
+    assert debt.value > 1
+
+    ps_http.patch_tenant_config_client_side(tenant_id, inserts={"branch_creation_debt_wait": "60s"})
+    with ThreadPoolExecutor(max_workers=1) as executor:
+        branch_id = TimelineId.generate()
+        creation = executor.submit(
+            ps_http.timeline_create, env.pg_version, tenant_id, branch_id, timeline_id
+        )
+        wait_until(
+            20,
+            0.5,
+            lambda: env.pageserver.assert_log_contains(
+                "waiting for compaction before creating a branch"
+            ),
+        )
+        ps_http.timeline_compact(tenant_id, timeline_id)
+        assert creation.result()["timeline_id"] == str(branch_id)
+
+    debt = ps_http.get_metrics().query_one(
+        "pageserver_compaction_debt_bytes", filter={"tenant_id": str(tenant_id)}
+    )
+    assert debt.value <= 1
@@ -3,10 +3,11 @@ import time
 
 import pytest
 from fixtures.log_helper import log
-from fixtures.neon_fixtures import NeonEnv
+from fixtures.neon_fixtures import NeonEnv, wait_for_last_flush_lsn
 from fixtures.pageserver.http import TimelineCreate406
+from fixtures.pageserver.utils import timeline_delete_wait_completed
 from fixtures.types import Lsn, TimelineId
-from fixtures.utils import query_scalar
+from fixtures.utils import query_scalar, wait_until
 
 
 # Test the GC implementation when running with branching.
@@ -186,3 +187,31 @@ def test_branch_creation_before_gc(neon_simple_env: NeonEnv):
         pageserver_http_client.timeline_create(env.pg_version, tenant, new_timeline_id, b0, lsn)
 
     thread.join()
+
+
+# GC cutoff advances and timeline deletions are published to the broker, for computes.
+def test_timeline_events(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    pageserver_http_client = env.pageserver.http_client()
+
+    tenant, _ = env.neon_cli.create_tenant(conf={"gc_period": "0s", "pitr_interval": "0s"})
+    timeline = env.neon_cli.create_timeline("test_timeline_events", tenant_id=tenant)
+    with env.endpoints.create_start("test_timeline_events", tenant_id=tenant) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT generate_series(1, 10000) AS x")
+        wait_for_last_flush_lsn(env, endpoint, tenant, timeline)
+
+    def published_at_least(n: int):
+        def check():
+            published = pageserver_http_client.get_metric_value(
+                "pageserver_timeline_events_total", {"result": "published"}
+            )
+            assert published is not None and published >= n
+
+        return check
+
+    pageserver_http_client.timeline_checkpoint(tenant, timeline)
+    pageserver_http_client.timeline_gc(tenant, timeline, 0)
+    wait_until(20, 0.5, published_at_least(1))
+
+    timeline_delete_wait_completed(pageserver_http_client, tenant, timeline)
+    wait_until(20, 0.5, published_at_least(2))
@@ -0,0 +1,49 @@
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.utils import wait_for_last_record_lsn
+from fixtures.types import Lsn
+from fixtures.utils import query_scalar, wait_until
+
+
+def test_branch_image_creation(neon_env_builder: NeonEnvBuilder):
+    """
+    With `branch_image_creation_threshold` set, a new branch gets image layers at its branch
+    point, so that its reads don't have to reconstruct pages from the ancestor's deltas.
+    """
+    env = neon_env_builder.init_start(
+        initial_tenant_conf={
+            # The whole keyspace is smaller than this: create images for all of it.
+            "branch_image_creation_threshold": f"{1024 * 1024 * 1024}",
+        }
+    )
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    pageserver_http = env.pageserver.http_client()
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE foo (t text)")
+        endpoint.safe_psql(
+            "INSERT INTO foo SELECT 'long string to consume some space' || g"
+            " FROM generate_series(1, 10000) g"
+        )
+        branch_lsn = Lsn(
+            query_scalar(endpoint.connect().cursor(), "SELECT pg_current_wal_flush_lsn()")
+        )
+        wait_for_last_record_lsn(pageserver_http, tenant_id, timeline_id, branch_lsn)
+        pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+
+    branch_id = env.neon_cli.create_branch(
+        "branch", "main", tenant_id=tenant_id, ancestor_start_lsn=branch_lsn
+    )
+
+    def branch_point_images():
+        layers = pageserver_http.layer_map_info(tenant_id, branch_id)
+        images = [layer for layer in layers.image_layers() if Lsn(layer.lsn_start) == branch_lsn]
+        log.info(f"image layers at the branch point: {[i.layer_file_name for i in images]}")
+        assert len(images) > 0
+        assert len(layers.delta_layers()) == 0
+
+    wait_until(30, 1, branch_point_images)
+
+    with env.endpoints.create_start("branch", tenant_id=tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT count(*) FROM foo")[0][0] == 10000
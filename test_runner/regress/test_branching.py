@@ -1,4 +1,6 @@
+import json
 import random
+import shutil
 import threading
 import time
 from concurrent.futures import ThreadPoolExecutor
@@ -11,9 +13,12 @@ from fixtures.neon_fixtures import (
     NeonEnv,
     NeonEnvBuilder,
     PgBin,
+    last_flush_lsn_upload,
 )
 from fixtures.pageserver.http import PageserverApiException
-from fixtures.pageserver.utils import wait_until_tenant_active
+from fixtures.pageserver.types import ImageLayerName, parse_layer_file_name
+from fixtures.pageserver.utils import wait_for_upload_queue_empty, wait_until_tenant_active
+from fixtures.remote_storage import LocalFsStorage, RemoteStorageKind
 from fixtures.types import Lsn, TimelineId
 from fixtures.utils import query_scalar
 from performance.test_perf_pgbench import get_scales_matrix
@@ -406,6 +411,104 @@ def test_duplicate_creation(neon_env_builder: NeonEnvBuilder):
     assert len(ps_http.timeline_list(tenant_id=env.initial_tenant)) == 1
 
 
+def test_create_from_invalid_snapshot(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+
+    with pytest.raises(PageserverApiException, match="must only contain") as e:
+        ps_http.timeline_create(
+            env.pg_version, env.initial_tenant, TimelineId.generate(), snapshot_id="../tenants"
+        )
+    assert e.value.status_code == 400
+
+    with pytest.raises(PageserverApiException, match="snapshot sample-db not found") as e:
+        ps_http.timeline_create(
+            env.pg_version, env.initial_tenant, TimelineId.generate(), snapshot_id="sample-db"
+        )
+    assert e.value.status_code == 400
+
+    with pytest.raises(PageserverApiException, match="both from a snapshot and an ancestor") as e:
+        ps_http.timeline_create(
+            env.pg_version,
+            env.initial_tenant,
+            TimelineId.generate(),
+            ancestor_timeline_id=env.initial_timeline,
+            snapshot_id="sample-db",
+        )
+    assert e.value.status_code == 400
+
+    # None of the failed creations left a timeline behind
+    assert len(ps_http.timeline_list(tenant_id=env.initial_tenant)) == 1
+
+
+def test_create_from_snapshot(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.LOCAL_FS)
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+    remote = env.pageserver_remote_storage
+    assert isinstance(remote, LocalFsStorage)
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    # Build a snapshot from the image layers of a timeline at its last LSN.
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+        last_flush_lsn_upload(env, endpoint, tenant_id, timeline_id)
+    ps_http.timeline_checkpoint(tenant_id, timeline_id, force_image_layer_creation=True)
+    wait_for_upload_queue_empty(ps_http, tenant_id, timeline_id)
+
+    images = {}
+    for name, metadata in remote.index_content(tenant_id, timeline_id)["layer_metadata"].items():
+        layer_name = parse_layer_file_name(name)
+        if isinstance(layer_name, ImageLayerName):
+            images[name] = (layer_name.lsn, metadata)
+    snapshot_lsn = max(lsn for lsn, _ in images.values())
+    snapshot_dir = remote.root / "snapshots" / "sample-db"
+    snapshot_dir.mkdir(parents=True)
+    layers = {}
+    for name, (lsn, metadata) in images.items():
+        if lsn == snapshot_lsn:
+            shutil.copy(
+                remote.remote_layer_path(tenant_id, timeline_id, name, metadata["generation"]),
+                snapshot_dir / name,
+            )
+            layers[name] = metadata["file_size"]
+    (snapshot_dir / "snapshot.json").write_text(
+        json.dumps({"pg_version": int(env.pg_version), "lsn": str(snapshot_lsn), "layers": layers})
+    )
+
+    # Another tenant creates a timeline from the snapshot, and reads its pages.
+    new_tenant_id, _ = env.neon_cli.create_tenant()
+    new_timeline_id = TimelineId.generate()
+    ps_http.timeline_create(
+        env.pg_version, new_tenant_id, new_timeline_id, snapshot_id="sample-db"
+    )
+    env.neon_cli.map_branch("from-snapshot", new_tenant_id, new_timeline_id)
+    with env.endpoints.create_start("from-snapshot", tenant_id=new_tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT count(*), sum(g) FROM t")[0] == (10000, 50005000)
+
+    # The layers were downloaded from the snapshot, not copied, and the timeline holds a
+    # reference to it.
+    assert remote.index_content(new_tenant_id, new_timeline_id)["snapshots"].keys() == {
+        "sample-db"
+    }
+    new_timeline_dir = remote.timeline_path(new_tenant_id, new_timeline_id)
+    assert not any((new_timeline_dir / name).exists() for name in layers)
+    refs_dir = snapshot_dir / "refs" / str(new_tenant_id)
+    assert (refs_dir / str(new_timeline_id)).exists()
+
+    # A creation that fails before the index refers to the snapshot releases its reference.
+    env.pageserver.allowed_errors.append(".*failpoint after-timeline-dir-creation.*")
+    failed_timeline_id = TimelineId.generate()
+    ps_http.configure_failpoints(("after-timeline-dir-creation", "return"))
+    with pytest.raises(PageserverApiException, match="after-timeline-dir-creation"):
+        ps_http.timeline_create(
+            env.pg_version, new_tenant_id, failed_timeline_id, snapshot_id="sample-db"
+        )
+    ps_http.configure_failpoints(("after-timeline-dir-creation", "off"))
+    assert not (refs_dir / str(failed_timeline_id)).exists()
+
+
 def test_branching_while_stuck_find_gc_cutoffs(neon_env_builder: NeonEnvBuilder):
     env = neon_env_builder.init_start()
 
@@ -429,6 +532,42 @@ def test_branching_while_stuck_find_gc_cutoffs(neon_env_builder: NeonEnvBuilder)
         completion.result()
 
 
+def test_branch_after_wait_lsn(neon_simple_env: NeonEnv):
+    """
+    Create a branch right after a write, using the wait_lsn API to make sure that the
+    pageserver has it.
+    """
+    env = neon_simple_env
+    client = env.pageserver.http_client()
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+
+    res = client.timeline_wait_lsn(env.initial_tenant, env.initial_timeline, lsn, timeout="30s")
+    assert res["reached"]
+    assert Lsn(res["last_record_lsn"]) >= lsn
+    assert res["lag_bytes"] == 0
+
+    res = client.timeline_wait_lsn(
+        env.initial_tenant, env.initial_timeline, lsn, timeout="30s", flush=True
+    )
+    assert res["reached"]
+    assert Lsn(res["disk_consistent_lsn"]) >= lsn
+    assert res["lag_bytes"] == 0
+
+    env.neon_cli.create_branch("after_write", "main", ancestor_start_lsn=lsn)
+    branch = env.endpoints.create_start("after_write")
+    assert branch.safe_psql("SELECT count(*) FROM t")[0][0] == 10000
+
+    # An LSN which is never written times out, reporting the lag
+    res = client.timeline_wait_lsn(
+        env.initial_tenant, env.initial_timeline, lsn + 1024 * 1024 * 1024, timeout="1s"
+    )
+    assert not res["reached"]
+    assert res["lag_bytes"] >= 1024 * 1024 * 1024
+
+
 def wait_until_paused(env: NeonEnv, failpoint: str):
     found = False
     msg = f"at failpoint {failpoint}"
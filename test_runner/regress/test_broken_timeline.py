@@ -10,6 +10,7 @@ from fixtures.neon_fixtures import (
     NeonEnvBuilder,
     wait_for_last_flush_lsn,
 )
+from fixtures.pageserver.utils import wait_until_tenant_active
 from fixtures.pg_version import PgVersion
 from fixtures.types import TenantId, TimelineId
 
@@ -96,6 +97,90 @@ def test_local_corruption(neon_env_builder: NeonEnvBuilder):
     )
 
 
+def test_startup_repair(neon_env_builder: NeonEnvBuilder):
+    """
+    With startup_repair enabled, corrupt local state which would otherwise make the tenant
+    Broken on load is repaired from the remote index, and the repairs are reported.
+    """
+    neon_env_builder.pageserver_config_override = "startup_repair=true"
+    env = neon_env_builder.init_start()
+    env.pageserver.allowed_errors.append(".*Repaired corrupt local state.*")
+
+    tenant_timelines: List[Tuple[TenantId, TimelineId, Endpoint]] = []
+    for _ in range(2):
+        tenant_id, timeline_id = env.neon_cli.create_tenant()
+
+        endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+        with endpoint.cursor() as cur:
+            cur.execute("CREATE TABLE t(key int primary key, value text)")
+            cur.execute("INSERT INTO t SELECT generate_series(1,100), 'payload'")
+            wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+        endpoint.stop()
+        tenant_timelines.append((tenant_id, timeline_id, endpoint))
+
+    env.pageserver.stop()
+
+    (tenant1, timeline1, pg1) = tenant_timelines[0]
+    with open(env.pageserver.tenant_dir(tenant1) / "config-v1", "w") as f:
+        f.write("overwritten with garbage!")
+
+    (tenant2, timeline2, pg2) = tenant_timelines[1]
+    unknown_file = env.pageserver.timeline_dir(tenant2, timeline2) / "garbage"
+    with open(unknown_file, "w") as f:
+        f.write("garbage")
+
+    env.pageserver.start()
+
+    for pg in [pg1, pg2]:
+        pg.start()
+        assert pg.safe_psql("SELECT COUNT(*) FROM t")[0][0] == 100
+
+    repairs = env.pageserver.http_client().startup_repairs()
+    log.info(f"Startup repairs: {repairs}")
+    assert len(repairs) == 2
+    assert all(r["recovered"] is not None for r in repairs)
+    config_repair = next(r for r in repairs if r["tenant_shard_id"] == str(tenant1))
+    assert "timeline_id" not in config_repair
+    files_repair = next(r for r in repairs if r["tenant_shard_id"] == str(tenant2))
+    assert files_repair["timeline_id"] == str(timeline2)
+    assert "garbage" in files_repair["problem"]
+    assert not unknown_file.exists()
+
+
+def test_format_migrations(neon_env_builder: NeonEnvBuilder):
+    """
+    Tenant directories record their on-disk format version, and a tenant with a format
+    newer than the pageserver supports is refused rather than loaded. Even with startup_repair,
+    its config is kept rather than reset.
+    """
+    neon_env_builder.pageserver_config_override = "startup_repair=true"
+    env = neon_env_builder.init_start()
+    tenant_id, _ = env.neon_cli.create_tenant(conf={"gc_horizon": "1048576"})
+
+    format_version = env.pageserver.tenant_dir(tenant_id) / "format_version"
+    version = int(format_version.read_text())
+    assert version >= 1
+
+    env.pageserver.stop()
+    format_version.write_text(f"{version + 1}\n")
+    env.pageserver.allowed_errors.append(".*newer than version.*")
+    env.pageserver.start()
+
+    ps_http = env.pageserver.http_client()
+    tenant_status = ps_http.tenant_status(tenant_id)
+    assert tenant_status["state"]["slug"] == "Broken"
+    assert "refusing to use it" in tenant_status["state"]["data"]["reason"]
+    # The initial tenant is not affected
+    assert ps_http.tenant_status(env.initial_tenant)["state"]["slug"] == "Active"
+
+    # Back at a supported version, the tenant loads with its config.
+    env.pageserver.stop()
+    format_version.write_text(f"{version}\n")
+    env.pageserver.start()
+    wait_until_tenant_active(ps_http, tenant_id)
+    assert ps_http.tenant_config(tenant_id).tenant_specific_overrides["gc_horizon"] == 1048576
+
+
 def test_create_multiple_timelines_parallel(neon_simple_env: NeonEnv):
     env = neon_simple_env
 
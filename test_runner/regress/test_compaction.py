@@ -5,6 +5,8 @@ from typing import Optional
 import pytest
 from fixtures.log_helper import log
 from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.utils import wait_until
 from fixtures.workload import Workload
 
 AGGRESIVE_COMPACTION_TENANT_CONF = {
@@ -190,3 +192,38 @@ def test_sharding_compaction(
 
     # Assert that everything is still readable
     workload.validate()
+
+
+def test_compaction_resource_usage(neon_env_builder: NeonEnvBuilder):
+    """
+    The CPU time and IO of background compaction and layer flushes is accounted to the tenant.
+    """
+    env = neon_env_builder.init_start(
+        initial_tenant_conf={
+            **AGGRESIVE_COMPACTION_TENANT_CONF,
+            "compaction_period": "1s",
+        }
+    )
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+
+    workload = Workload(env, tenant_id, timeline_id)
+    workload.init(env.pageserver.id)
+    for _ in range(5):
+        workload.churn_rows(10000, env.pageserver.id)
+        ps_http.timeline_checkpoint(tenant_id, timeline_id)
+
+    def compacted():
+        usage = ps_http.tenant_resource_usage(tenant_id)
+        log.info(f"resource usage: {json.dumps(usage, indent=2)}")
+        assert usage["Compaction"]["cpu_seconds"] > 0
+        assert usage["LayerFlushTask"]["written_bytes"] > 0
+        return usage
+
+    wait_until(30, 1, compacted)
+
+    ps_http.tenant_detach(tenant_id)
+    with pytest.raises(PageserverApiException) as exc:
+        ps_http.tenant_resource_usage(tenant_id)
+    assert exc.value.status_code == 404
@@ -1,8 +1,9 @@
 import enum
+import json
 import time
 from collections import Counter
 from dataclasses import dataclass
-from typing import Any, Dict, Iterable, Tuple
+from typing import Any, Dict, Iterable, Optional, Tuple
 
 import pytest
 from fixtures.log_helper import log
@@ -152,6 +153,7 @@ class EvictionEnv:
         min_avail_bytes,
         mock_behavior,
         eviction_order: EvictionOrder,
+        offload: Optional[Dict[str, Any]] = None,
     ):
         """
         Starts pageserver up with mocked statvfs setup. The startup is
@@ -168,15 +170,19 @@ class EvictionEnv:
         # is able to evict.
         pageserver.allowed_errors.append(".*WARN.* disk usage still high.*")
 
+        disk_usage_based_eviction = {
+            "period": period,
+            "max_usage_pct": max_usage_pct,
+            "min_avail_bytes": min_avail_bytes,
+            "mock_statvfs": mock_behavior,
+            "eviction_order": eviction_order.config(),
+        }
+        if offload is not None:
+            disk_usage_based_eviction["offload"] = offload
+
         pageserver.patch_config_toml_nonrecursive(
             {
-                "disk_usage_based_eviction": {
-                    "period": period,
-                    "max_usage_pct": max_usage_pct,
-                    "min_avail_bytes": min_avail_bytes,
-                    "mock_statvfs": mock_behavior,
-                    "eviction_order": eviction_order.config(),
-                },
+                "disk_usage_based_eviction": disk_usage_based_eviction,
                 # Disk usage based eviction runs as a background task.
                 # But pageserver startup delays launch of background tasks for some time, to prioritize initial logical size calculations during startup.
                 # But, initial logical size calculation may not be triggered if safekeepers don't publish new broker messages.
@@ -242,6 +248,8 @@ def _eviction_env(
 
     neon_env_builder.num_pageservers = num_pageservers
     neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.LOCAL_FS)
+    # The tests evict layers and read them back on purpose, which must not make them bouncing.
+    neon_env_builder.pageserver_config_override = 'eviction_bounce_window="0s"'
 
     # initial tenant will not be present on this pageserver
     env = neon_env_builder.init_configs()
@@ -853,6 +861,113 @@ def test_statvfs_pressure_min_avail_bytes(eviction_env: EvictionEnv):
     wait_until(2, 2, more_than_min_avail_bytes_freed)
 
 
+def test_offload_cold_tenants(eviction_env: EvictionEnv):
+    """
+    If evictions can't relieve the pressure, the eviction task offloads the coldest tenants,
+    which keep serving reads by downloading their layers on demand. An offloaded tenant is
+    reactivated when a compute writes to it, or once the pressure ends.
+    """
+    env = eviction_env
+    ps_http = env.pageserver_http
+
+    env.neon_env.pageserver.stop()
+    env.pageserver.allowed_errors.append(".*WARN.* offloading tenant idle since.*")
+
+    # Make the tenants idle for two days, as far as their access logs know.
+    now = time.time()
+    idle_since = time.strftime("%Y-%m-%dT%H:00:00Z", time.gmtime(now - 2 * 24 * 3600))
+    tracked_since = time.strftime("%Y-%m-%dT%H:%M:%SZ", time.gmtime(now - 3 * 24 * 3600))
+    for tenant_id, timeline_id in env.timelines:
+        access_log = {
+            "hours": [
+                {"hour": idle_since, "pages_read": 1, "bytes_ingested": 1, "unique_keys": 1}
+            ],
+            "last_hour_keys": [0] * 256,
+            "tracked_since": tracked_since,
+        }
+        path = env.pageserver.timeline_dir(tenant_id, timeline_id) / "access_log.json"
+        path.write_text(json.dumps(access_log))
+
+    # The pressure is permanent: less than min_avail_bytes is ever available.
+    total_size, _, _ = env.timelines_du(env.pageserver)
+    blocksize = 512
+    total_blocks = (2 * total_size + (blocksize - 1)) // blocksize
+    mock_behavior = {
+        "type": "Success",
+        "blocksize": blocksize,
+        "total_blocks": total_blocks,
+        "name_filter": ".*__.*",
+    }
+
+    env.pageserver_start_with_disk_usage_eviction(
+        env.pageserver,
+        period="1s",
+        max_usage_pct=100,
+        min_avail_bytes=4 * total_size,
+        mock_behavior=mock_behavior,
+        eviction_order=EvictionOrder.ABSOLUTE_ORDER,
+        offload={"min_idle": "1h", "max_tenants_per_iteration": 1},
+    )
+
+    tenant_ids = {tenant_id for tenant_id, _ in env.timelines}
+
+    def all_offloaded():
+        offloaded = ps_http.disk_usage_eviction_offloaded()
+        assert {TenantId(t["tenant_shard_id"]) for t in offloaded} == tenant_ids
+        for t in offloaded:
+            assert t["details"] is not None
+            assert t["details"]["avail_bytes"] < 4 * total_size
+
+    wait_until(30, 1, all_offloaded)
+
+    for tenant_id in tenant_ids:
+        config = ps_http.tenant_config(tenant_id)
+        assert config.tenant_specific_overrides["read_only"] == "offloaded"
+
+    # Reads download the layers on demand.
+    tenant_id = next(iter(tenant_ids))
+    env.warm_up_tenant(tenant_id)
+
+    # The details of the offloading are only known until a restart.
+    env.pageserver.restart()
+    offloaded = ps_http.disk_usage_eviction_offloaded()
+    assert {TenantId(t["tenant_shard_id"]) for t in offloaded} == tenant_ids
+    assert all(t["details"] is None for t in offloaded)
+
+    # After a compute wrote, the next one waits for the WAL in its basebackup, which
+    # reactivates the tenant, and the tenant isn't idle anymore.
+    for sk in env.neon_env.safekeepers:
+        sk.start()
+    with env.neon_env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 1000) g")
+        endpoint.stop()
+        endpoint.start()
+        assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 1000
+    assert ps_http.tenant_config(tenant_id).tenant_specific_overrides.get("read_only") is None
+    offloaded = ps_http.disk_usage_eviction_offloaded()
+    assert {TenantId(t["tenant_shard_id"]) for t in offloaded} == tenant_ids - {tenant_id}
+
+    # Once the pressure ends, the other tenants are reactivated.
+    env.pageserver.stop()
+    env.pageserver_start_with_disk_usage_eviction(
+        env.pageserver,
+        period="1s",
+        max_usage_pct=100,
+        min_avail_bytes=0,
+        mock_behavior=mock_behavior,
+        eviction_order=EvictionOrder.ABSOLUTE_ORDER,
+        offload={"min_idle": "1h", "max_tenants_per_iteration": 1},
+    )
+
+    def none_offloaded():
+        assert ps_http.disk_usage_eviction_offloaded() == []
+
+    wait_until(30, 1, none_offloaded)
+    for tenant_id in tenant_ids:
+        config = ps_http.tenant_config(tenant_id)
+        assert config.tenant_specific_overrides.get("read_only") is None
+
+
 def test_secondary_mode_eviction(eviction_env_ha: EvictionEnv):
     env = eviction_env_ha
 
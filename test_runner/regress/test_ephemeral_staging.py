@@ -0,0 +1,28 @@
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+
+
+# Check that the ephemeral files of the open layers are created in the staging area when one
+# is configured, rather than in the timeline directory.
+def test_ephemeral_staging_dir(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.pageserver_config_override = 'ephemeral_dir="scratch"'
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    def ephemeral_files(path):
+        return [p.name for p in path.iterdir() if p.name.startswith("ephemeral-")]
+
+    # Each pageserver has a subdirectory of its own in the staging area.
+    staging_dir = env.pageserver.workdir / "scratch" / f"pageserver-{env.pageserver.id}"
+    assert len(ephemeral_files(staging_dir)) > 0
+    assert ephemeral_files(env.pageserver.timeline_dir(tenant_id, timeline_id)) == []
+
+    # The layers are flushed from the staging area like from the timeline directory.
+    ps_http = env.pageserver.http_client()
+    ps_http.timeline_checkpoint(tenant_id, timeline_id)
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 10000
@@ -0,0 +1,50 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.types import Lsn
+
+
+def rel_block_key(dbnode: int, relnode: int, blkno: int) -> str:
+    # Key::from_hex: field1 (u8), spcnode, dbnode, relnode, forknum (u8), blkno
+    return f"00{1663:08X}{dbnode:08X}{relnode:08X}00{blkno:08X}"
+
+
+# Check that the traversal of a read goes through the layers holding the key, and stops at
+# the first evicted one.
+def test_explain_read(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+
+    with env.endpoints.create_start("main") as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 1000) g")
+        dbnode, relnode = endpoint.safe_psql(
+            "SELECT d.oid, pg_relation_filenode('t') FROM pg_database d"
+            " WHERE datname = 'postgres'"
+        )[0]
+        lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    key = rel_block_key(dbnode, relnode, 0)
+
+    explained = ps_http.timeline_explain_read(tenant_id, timeline_id, key, lsn)
+    assert explained["outcome"] == "complete"
+    assert explained["redo_needed"]
+    values = [v for layer in explained["layers"] for v in layer["values"]]
+    assert len(values) > 0
+    assert values[-1]["kind"] in ("image", "init_wal_record")
+    assert all(layer["timeline_id"] == str(timeline_id) for layer in explained["layers"])
+
+    # The LSNs that haven't arrived yet are rejected.
+    for future_lsn in (lsn + 1024 * 1024 * 1024, Lsn(0xFFFFFFFF_FFFFFFFF)):
+        with pytest.raises(PageserverApiException, match="above the last record LSN") as exc:
+            ps_http.timeline_explain_read(tenant_id, timeline_id, key, future_lsn)
+        assert exc.value.status_code == 400
+
+    ps_http.timeline_checkpoint(tenant_id, timeline_id)
+    ps_http.evict_all_layers(tenant_id, timeline_id)
+
+    explained = ps_http.timeline_explain_read(tenant_id, timeline_id, key, lsn)
+    assert explained["outcome"] == "evicted"
+    last = explained["layers"][-1]
+    assert not last["resident"]
+    assert last["values"] == []
@@ -21,7 +21,7 @@ from fixtures.pageserver.utils import (
 )
 from fixtures.remote_storage import RemoteStorageKind
 from fixtures.types import Lsn, TenantId, TimelineId
-from fixtures.utils import subprocess_capture
+from fixtures.utils import subprocess_capture, wait_until
 
 
 def test_import_from_vanilla(test_output_dir, pg_bin, vanilla_pg, neon_env_builder):
@@ -152,6 +152,95 @@ def test_import_from_vanilla(test_output_dir, pg_bin, vanilla_pg, neon_env_build
     vanilla_pg.stop()
 
 
+def test_import_from_vanilla_wal_archive(test_output_dir, pg_bin, vanilla_pg, neon_env_builder):
+    """Import a pg_basebackup tarball, with the WAL after it fetched from a WAL archive."""
+    archive_dir = test_output_dir / "wal_archive"
+    archive_dir.mkdir()
+    vanilla_pg.configure(["archive_mode = on", f"archive_command = 'cp %p {archive_dir}/%f'"])
+    vanilla_pg.start()
+    vanilla_pg.safe_psql("create user cloud_admin with password 'postgres' superuser")
+    vanilla_pg.safe_psql("create table t as select g from generate_series(1,1000) g")
+
+    basebackup_dir = test_output_dir / "basebackup"
+    basebackup_dir.mkdir()
+    vanilla_pg.safe_psql("CHECKPOINT")
+    pg_bin.run(
+        [
+            "pg_basebackup",
+            "-F",
+            "tar",
+            "-X",
+            "none",
+            "-d",
+            vanilla_pg.connstr(),
+            "-D",
+            str(basebackup_dir),
+        ]
+    )
+    with open(basebackup_dir / "backup_manifest") as f:
+        start_lsn = json.load(f)["WAL-Ranges"][0]["Start-LSN"]
+
+    # Write some more after the backup, and make sure that all of it is archived.
+    vanilla_pg.safe_psql("insert into t select g from generate_series(1001,2000) g")
+    end_lsn = vanilla_pg.safe_psql("select pg_current_wal_insert_lsn()")[0][0]
+    last_segment = vanilla_pg.safe_psql("select pg_walfile_name(pg_switch_wal())")[0][0]
+
+    def archived():
+        assert (archive_dir / last_segment).exists()
+
+    wait_until(30, 1, archived)
+    vanilla_pg.stop()
+
+    neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.LOCAL_FS)
+    env = neon_env_builder.init_start()
+    tenant = TenantId.generate()
+    timeline = TimelineId.generate()
+    env.pageserver.tenant_create(tenant)
+    env.pageserver.allowed_errors.append(".*error importing WAL archive.*")
+
+    def import_from_archive(restore_command):
+        # The restore command is part of the pageserver config, never of the request.
+        env.pageserver.stop()
+        env.pageserver.patch_config_toml_nonrecursive(
+            {"wal_archive_restore_command": restore_command}
+        )
+        env.pageserver.start()
+        env.neon_cli.raw_cli(
+            [
+                "timeline",
+                "import",
+                "--tenant-id",
+                str(tenant),
+                "--timeline-id",
+                str(timeline),
+                "--node-name",
+                "imported",
+                "--base-lsn",
+                start_lsn,
+                "--base-tarfile",
+                str(basebackup_dir / "base.tar"),
+                "--end-lsn",
+                end_lsn,
+                "--wal-archive",
+                "--pg-version",
+                env.pg_version,
+            ]
+        )
+
+    # A restore_command that finds nothing fails the import.
+    with pytest.raises(RuntimeError):
+        import_from_archive(f"cp {test_output_dir}/nonexistent/%f %p")
+    client = env.pageserver.http_client()
+    timeline_delete_wait_completed(client, tenant, timeline)
+
+    import_from_archive(f"cp {archive_dir}/%f %p")
+    wait_for_last_record_lsn(client, tenant, timeline, Lsn(end_lsn))
+    wait_for_upload(client, tenant, timeline, Lsn(end_lsn))
+
+    endpoint = env.endpoints.create_start("imported", tenant_id=tenant)
+    assert endpoint.safe_psql("select count(*) from t") == [(2000,)]
+
+
 def test_import_from_pageserver_small(
     pg_bin: PgBin, neon_env_builder: NeonEnvBuilder, test_output_dir: Path
 ):
@@ -0,0 +1,130 @@
+import tarfile
+import os
+from pathlib import Path
+from typing import List, Optional
+
+from fixtures.log_helper import log
+from fixtures.neon_fixtures import NeonEnv, NeonEnvBuilder, PgBin
+from fixtures.types import Lsn, TimelineId
+from fixtures.utils import query_scalar
+
+
+def take_basebackup(
+    env: NeonEnv,
+    pg_bin: PgBin,
+    timeline_id: TimelineId,
+    lsn: Lsn,
+    manifest: Optional[str],
+    output: Path,
+) -> List[str]:
+    """
+    Take a basebackup, incremental to `manifest` if one is given, and return
+    the names of the files in the tarball.
+    """
+    query = f"basebackup {env.initial_tenant} {timeline_id} {lsn}"
+    if manifest is not None:
+        query += f" --incremental {manifest}"
+    pg_bin.run_capture(
+        ["psql", "--no-psqlrc", env.pageserver.connstr(), "-c", query, "-o", str(output)]
+    )
+    with tarfile.open(output) as tar:
+        return [member.name for member in tar.getmembers() if member.isfile()]
+
+
+def read_manifest(output: Path) -> str:
+    with tarfile.open(output) as tar:
+        manifest = tar.extractfile("basebackup.manifest")
+        assert manifest is not None
+        return ",".join(manifest.read().decode().splitlines())
+
+
+# Check that an incremental basebackup leaves out the files the compute already has
+def test_incremental_basebackup(
+    neon_env_builder: NeonEnvBuilder, pg_bin: PgBin, test_output_dir: Path
+):
+    env = neon_env_builder.init_start()
+    endpoint = env.endpoints.create_start("main")
+
+    with endpoint.cursor() as cur:
+        timeline_id = TimelineId(query_scalar(cur, "SHOW neon.timeline_id"))
+        cur.execute("CREATE TABLE t AS SELECT g FROM generate_series(1, 1000) g")
+        cur.execute("CHECKPOINT")
+        lsn = Lsn(query_scalar(cur, "SELECT pg_current_wal_insert_lsn()"))
+
+    # A manifest that matches nothing gets all the files, and the manifest of them.
+    full_tar = test_output_dir / "full.tar"
+    full = take_basebackup(env, pg_bin, timeline_id, lsn, "nothing:0:0:0/0", full_tar)
+    assert "global/pg_control" in full
+    assert "basebackup.manifest" in full
+    manifest = read_manifest(full_tar)
+    assert len(manifest.split(",")) == len(full) - 1
+
+    # At the same LSN, nothing has changed.
+    same_tar = test_output_dir / "same.tar"
+    same = take_basebackup(env, pg_bin, timeline_id, lsn, manifest, same_tar)
+    assert same == ["basebackup.manifest"]
+    assert read_manifest(same_tar) == manifest
+
+    # Later on, the control file and the WAL segment always change, but most files don't.
+    with endpoint.cursor() as cur:
+        cur.execute("INSERT INTO t SELECT g FROM generate_series(1, 1000) g")
+        cur.execute("CHECKPOINT")
+        later_lsn = Lsn(query_scalar(cur, "SELECT pg_current_wal_insert_lsn()"))
+
+    later_tar = test_output_dir / "later.tar"
+    later = take_basebackup(env, pg_bin, timeline_id, later_lsn, manifest, later_tar)
+    log.info(f"incremental basebackup at {later_lsn}: {later}")
+    assert "global/pg_control" in later
+    assert len(later) < len(full)
+
+    skipped = (
+        env.pageserver.http_client()
+        .get_metrics()
+        .query_one("pageserver_basebackup_incremental_skipped_files_total")
+        .value
+    )
+    assert skipped > len(full) - 1
+
+
+def skipped_files(env: NeonEnv) -> float:
+    return (
+        env.pageserver.http_client()
+        .get_metrics()
+        .query_one("pageserver_basebackup_incremental_skipped_files_total")
+        .value
+    )
+
+
+# Check that compute_ctl reuses the data directory of the previous start, and
+# removes the files that are no longer part of the basebackup
+def test_incremental_basebackup_compute(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    endpoint = env.endpoints.create_start("main")
+
+    with endpoint.cursor() as cur:
+        cur.execute("CREATE TABLE t AS SELECT g FROM generate_series(1, 1000) g")
+        # The init fork of an unlogged table is part of the basebackup.
+        cur.execute("CREATE UNLOGGED TABLE u (g int)")
+        init_fork = query_scalar(cur, "SELECT pg_relation_filepath('u')") + "_init"
+    assert endpoint.pgdata_dir is not None
+    init_fork_path = os.path.join(endpoint.pgdata_dir, init_fork)
+    assert os.path.exists(init_fork_path)
+
+    endpoint.stop()
+    skipped_before = skipped_files(env)
+    endpoint.start(keep_pgdata=True)
+    assert skipped_files(env) > skipped_before
+    assert os.path.exists(init_fork_path)
+    with endpoint.cursor() as cur:
+        assert query_scalar(cur, "SELECT count(*) FROM t") == 1000
+        assert query_scalar(cur, "SELECT count(*) FROM u") == 0
+        cur.execute("DROP TABLE u")
+        cur.execute("INSERT INTO t SELECT g FROM generate_series(1, 1000) g")
+
+    endpoint.stop()
+    skipped_before = skipped_files(env)
+    endpoint.start(keep_pgdata=True)
+    assert skipped_files(env) > skipped_before
+    assert not os.path.exists(init_fork_path)
+    with endpoint.cursor() as cur:
+        assert query_scalar(cur, "SELECT count(*) FROM t") == 2000
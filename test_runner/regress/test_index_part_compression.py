@@ -0,0 +1,48 @@
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.utils import wait_for_upload_queue_empty, wait_until_tenant_active
+from fixtures.remote_storage import ZSTD_MAGIC, LocalFsStorage, RemoteStorageKind
+
+
+# Check that index parts above the compression threshold are uploaded zstd-compressed, that
+# tenants attach from them, and that they are read back after the compression is disabled.
+def test_index_part_compression(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.LOCAL_FS)
+    neon_env_builder.pageserver_config_override = "index_part_compression_threshold=1"
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+    assert isinstance(env.pageserver_remote_storage, LocalFsStorage)
+    remote = env.pageserver_remote_storage
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    ps_http.timeline_checkpoint(tenant_id, timeline_id)
+    wait_for_upload_queue_empty(ps_http, tenant_id, timeline_id)
+
+    assert remote.index_path(tenant_id, timeline_id).read_bytes().startswith(ZSTD_MAGIC)
+    assert remote.index_metadata(tenant_id, timeline_id) == {"content_encoding": "zstd"}
+    layers = remote.index_content(tenant_id, timeline_id)["layer_metadata"]
+    assert len(layers) > 0
+
+    env.pageserver.tenant_detach(tenant_id)
+    env.pageserver.tenant_attach(tenant_id)
+    wait_until_tenant_active(ps_http, tenant_id)
+    assert remote.index_content(tenant_id, timeline_id)["layer_metadata"].keys() == layers.keys()
+
+    # Without compression, the compressed index parts are still read, and replaced by JSON ones.
+    env.pageserver.stop()
+    env.pageserver.patch_config_toml_nonrecursive({"index_part_compression_threshold": 0})
+    env.pageserver.start()
+    wait_until_tenant_active(ps_http, tenant_id)
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 10000
+        endpoint.safe_psql("INSERT INTO t SELECT g FROM generate_series(1, 10000) g")
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    ps_http.timeline_checkpoint(tenant_id, timeline_id)
+    wait_for_upload_queue_empty(ps_http, tenant_id, timeline_id)
+
+    assert not remote.index_path(tenant_id, timeline_id).read_bytes().startswith(ZSTD_MAGIC)
+    assert remote.index_metadata(tenant_id, timeline_id) == {"content_encoding": "identity"}
+    assert remote.index_content(tenant_id, timeline_id)["layer_metadata"].keys() > layers.keys()
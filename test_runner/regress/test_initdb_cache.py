@@ -0,0 +1,26 @@
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.types import TenantId, TimelineId
+
+
+# Check that the initdb output of the first root timeline is cached and reused for the next
+# ones, and that a corrupt cached archive is replaced.
+def test_initdb_cache(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    cache_dir = env.pageserver.workdir / "initdb_cache"
+    cached = list(cache_dir.iterdir())
+    assert len(cached) == 1
+    assert not env.pageserver.log_contains("bootstrapping from the cached initdb output")
+
+    tenant_id, _ = env.neon_cli.create_tenant(TenantId.generate(), TimelineId.generate())
+    env.pageserver.assert_log_contains("bootstrapping from the cached initdb output")
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT 1")[0][0] == 1
+
+    cached[0].write_bytes(b"corrupt")
+    env.pageserver.allowed_errors.append(".*content doesn't match its checksum.*")
+    env.neon_cli.create_tenant(TenantId.generate(), TimelineId.generate())
+    env.pageserver.assert_log_contains("content doesn't match its checksum")
+    # The new initdb output has another system identifier, hence another checksum.
+    replaced = list(cache_dir.iterdir())
+    assert len(replaced) == 1
+    assert replaced[0].read_bytes() != b"corrupt"
@@ -0,0 +1,47 @@
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+
+
+# Check that the relations with the most WAL churn come first in the key space statistics of
+# a timeline, for both flushes and compactions.
+def test_key_space_stats(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start(
+        initial_tenant_conf={
+            # The test runs compaction manually, once it has flushed a few L0 layers.
+            "compaction_period": "0s",
+            "compaction_threshold": 10,
+        }
+    )
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql_many(
+            [
+                "CREATE TABLE hot (i int, t text)",
+                "CREATE TABLE cold (i int, t text)",
+                "INSERT INTO cold VALUES (1, 'cold')",
+            ]
+        )
+        hot = endpoint.safe_psql("SELECT pg_relation_filenode('hot')")[0][0]
+        cold = endpoint.safe_psql("SELECT pg_relation_filenode('cold')")[0][0]
+        for _ in range(3):
+            endpoint.safe_psql(
+                "INSERT INTO hot SELECT g, repeat('x', 100) FROM generate_series(1, 10000) g"
+            )
+            wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+            ps_http.timeline_checkpoint(tenant_id, timeline_id)
+
+    stats = ps_http.timeline_key_space_stats(tenant_id, timeline_id)
+    flushed = {r["relnode"]: r for r in stats["flush"]["relations"]}
+    assert stats["flush"]["relations"][0]["relnode"] == hot
+    assert flushed[hot]["bytes"] > 100 * flushed[cold]["bytes"]
+    assert flushed[hot]["distinct_lsns"] <= flushed[hot]["deltas"]
+    assert stats["flush"]["other"]["deltas"] > 0
+    assert stats["compaction"]["relations"] == []
+
+    ps_http.patch_tenant_config_client_side(tenant_id, inserts={"compaction_threshold": 2})
+    ps_http.timeline_compact(tenant_id, timeline_id)
+    stats = ps_http.timeline_key_space_stats(tenant_id, timeline_id, limit=1)
+    assert [r["relnode"] for r in stats["compaction"]["relations"]] == [hot]
+    assert len(stats["flush"]["relations"]) == 1
@@ -0,0 +1,51 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_until_tenant_active
+
+
+# Check that a legal hold blocks the deletion of the tenant and of its timelines, and GC,
+# and that it survives a restart even without the local copy of it.
+def test_legal_hold(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    env.pageserver.allowed_errors.append(".*blocked by legal hold.*")
+    tenant_id = env.initial_tenant
+    ps_http = env.pageserver.http_client()
+
+    branch_id = env.neon_cli.create_branch("held", tenant_id=tenant_id)
+
+    hold = ps_http.tenant_legal_hold_set(tenant_id, "litigation 1234")
+    assert hold["reason"] == "litigation 1234"
+
+    with pytest.raises(PageserverApiException) as exc:
+        ps_http.timeline_delete(tenant_id, branch_id)
+    assert exc.value.status_code == 409
+    with pytest.raises(PageserverApiException) as exc:
+        ps_http.tenant_delete(tenant_id)
+    assert exc.value.status_code == 409
+    gc_result = ps_http.timeline_gc(tenant_id, env.initial_timeline, 0)
+    assert gc_result["layers_removed"] == 0
+    assert env.pageserver.log_contains("Skipping GC: tenant is under legal hold: litigation 1234")
+
+    status = ps_http.tenant_legal_hold(tenant_id)
+    assert status["hold"] == hold
+    # The background GC loop may have run into the hold as well.
+    operations = {v["operation"] for v in status["violations"]}
+    assert operations == {"timeline_deletion", "tenant_deletion", "gc"}
+
+    # The index parts hold the tenant as well.
+    env.pageserver.stop()
+    (env.pageserver.tenant_dir(tenant_id) / "legal_hold.json").unlink()
+    env.pageserver.start()
+    wait_until_tenant_active(ps_http, tenant_id)
+    assert ps_http.tenant_legal_hold(tenant_id)["hold"] == hold
+
+    ps_http.tenant_legal_hold_release(tenant_id)
+    assert ps_http.tenant_legal_hold(tenant_id)["hold"] is None
+    assert not (env.pageserver.tenant_dir(tenant_id) / "legal_hold.json").exists()
+    ps_http.timeline_delete(tenant_id, branch_id)
+
+    # Released in the index parts too.
+    env.pageserver.restart()
+    wait_until_tenant_active(ps_http, tenant_id)
+    assert ps_http.tenant_legal_hold(tenant_id)["hold"] is None
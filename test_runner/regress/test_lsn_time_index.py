@@ -0,0 +1,61 @@
+from datetime import datetime, timedelta
+
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_for_upload_queue_empty, wait_until_tenant_active
+from fixtures.types import Lsn
+
+
+# Check that the commits sampled by ingest bracket the requested timestamps and LSNs, across a
+# pageserver restart and a re-attach, and on a branch, which falls back to the samples of its
+# parent.
+def test_lsn_time_index(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t (x integer)")
+        endpoint.safe_psql("INSERT INTO t VALUES (1)")
+        last_flush_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    def check(timeline_id):
+        # Commits are sampled at most once a minute, the first one of the test is.
+        result = ps_http.timeline_lsn_by_timestamp(
+            tenant_id, timeline_id, datetime.utcnow() + timedelta(hours=1)
+        )
+        assert result["after"] is None
+        sampled_lsn = Lsn(result["before"]["lsn"])
+        assert sampled_lsn <= last_flush_lsn
+
+        result = ps_http.timeline_lsn_by_timestamp(tenant_id, timeline_id, datetime(2001, 1, 1))
+        assert result["before"] is None
+        assert result["after"] is not None
+
+        result = ps_http.timeline_timestamp_by_lsn(tenant_id, timeline_id, last_flush_lsn)
+        assert Lsn(result["before"]["lsn"]) == sampled_lsn
+        result = ps_http.timeline_timestamp_by_lsn(tenant_id, timeline_id, Lsn(0))
+        assert result["before"] is None
+
+    check(timeline_id)
+
+    # The samples are persisted with the index part uploaded by the flush at shutdown.
+    env.pageserver.restart()
+    env.pageserver.quiesce_tenants()
+    check(timeline_id)
+
+    # A re-attach starts from the remote storage only.
+    ps_http.timeline_checkpoint(tenant_id, timeline_id)
+    wait_for_upload_queue_empty(ps_http, tenant_id, timeline_id)
+    env.pageserver.tenant_detach(tenant_id)
+    env.pageserver.tenant_attach(tenant_id)
+    wait_until_tenant_active(ps_http, tenant_id)
+    check(timeline_id)
+
+    branch_id = env.neon_cli.create_branch("branch", tenant_id=tenant_id)
+    check(branch_id)
+
+    with pytest.raises(PageserverApiException, match="before the Postgres epoch"):
+        ps_http.timeline_lsn_by_timestamp(tenant_id, timeline_id, datetime(1990, 1, 1))
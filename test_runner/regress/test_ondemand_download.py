@@ -135,6 +135,13 @@ def test_ondemand_download_large_rel(neon_env_builder: NeonEnvBuilder):
     log.info(f"layers downloaded before {before_downloads} and after {after_downloads}")
     assert after_downloads > before_downloads
 
+    # The GetPage requests that downloaded layers are accounted to the remote download path.
+    remote_download_requests = client.get_metrics().query_one(
+        "pageserver_getpage_latency_by_read_path_seconds_count",
+        {"read_path": "remote_download", "tenant_id": str(tenant_id)},
+    )
+    assert remote_download_requests.value > 0
+
 
 #
 # If you have a relation with a long history of updates, the pageserver downloads the layer
@@ -453,6 +460,7 @@ def test_download_remote_layers_api(
         # allow some concurrency to unveil potential concurrency bugs
         max_concurrent_downloads=10,
         errors_ok=False,
+        max_bytes_per_second=100 * 1024**2,
     )
     log.info(f"info={info}")
 
@@ -464,6 +472,8 @@ def test_download_remote_layers_api(
         info["total_layer_count"]
         == info["successful_download_count"] + info["failed_download_count"]
     )
+    assert info["total_bytes"] > 0
+    assert info["downloaded_bytes"] == info["total_bytes"]
 
     refilled_size = get_resident_physical_size()
     log.info(refilled_size)
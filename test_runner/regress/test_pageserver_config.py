@@ -1,8 +1,13 @@
+import os
+import signal
+from typing import Any, Dict
+
 import pytest
 from fixtures.neon_fixtures import (
     NeonEnvBuilder,
     last_flush_lsn_upload,
 )
+from fixtures.utils import wait_until
 
 
 @pytest.mark.parametrize("kind", ["sync", "async"])
@@ -33,3 +38,67 @@ def test_walredo_process_kind_config(neon_env_builder: NeonEnvBuilder, kind: str
 
     status = ps_http.tenant_status(env.initial_tenant)
     assert status["walredo"]["process"]["kind"] == kind
+    [process] = status["walredo"]["processes"]
+    assert process["pg_version"] == int(env.pg_version)
+
+    usage = ps_http.pg_versions()[int(env.pg_version)]
+    assert usage["installed"]
+    assert usage["timelines"] == 1
+    assert usage["walredo_processes"] == 1
+
+
+def test_config_reload(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+
+    assert ps_http.reload_config() == {"applied": [], "rejected": []}
+
+    def set_gc_period(config: Dict[str, Any]):
+        config.setdefault("tenant_config", {})["gc_period"] = "42s"
+
+    env.pageserver.edit_config_toml(set_gc_period)
+    res = ps_http.reload_config()
+    assert [change["key"] for change in res["applied"]] == ["tenant_config"]
+    assert res["rejected"] == []
+    effective_config = ps_http.tenant_config(env.initial_tenant).effective_config
+    assert effective_config["gc_period"] == "42s"
+
+    # A change which requires a restart rejects the whole reload
+    def set_page_cache_size_and_compaction_period(config: Dict[str, Any]):
+        config["page_cache_size"] = 1234
+        config["tenant_config"]["compaction_period"] = "7s"
+
+    env.pageserver.edit_config_toml(set_page_cache_size_and_compaction_period)
+    res = ps_http.reload_config()
+    assert res["applied"] == []
+    assert [(change["key"], change["new"]) for change in res["rejected"]] == [
+        ("page_cache_size", 1234)
+    ]
+    assert ps_http.tenant_config(env.initial_tenant).effective_config == effective_config
+
+
+def test_config_reload_sighup(neon_env_builder: NeonEnvBuilder):
+    """
+    SIGHUP rejects the changes which require a restart like the API does.
+    """
+    env = neon_env_builder.init_start()
+    env.pageserver.allowed_errors.append(".*Config reload rejected.*")
+    ps_http = env.pageserver.http_client()
+
+    def set_page_cache_size(config: Dict[str, Any]):
+        config["page_cache_size"] = 1234
+
+    env.pageserver.edit_config_toml(set_page_cache_size)
+    pid = int((env.pageserver.workdir / "pageserver.pid").read_text())
+    os.kill(pid, signal.SIGHUP)
+    wait_until(
+        20,
+        0.5,
+        lambda: env.pageserver.assert_log_contains(
+            "Config reload rejected.*page_cache_size.*require a restart"
+        ),
+    )
+
+    # The rejected change was not taken as the current config
+    res = ps_http.reload_config()
+    assert [change["key"] for change in res["rejected"]] == ["page_cache_size"]
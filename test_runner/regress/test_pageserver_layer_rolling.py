@@ -311,3 +311,44 @@ def test_total_size_limit(neon_env_builder: NeonEnvBuilder):
         assert dirty_bytes < max_dirty_data
 
     wait_until(compaction_period_s * 2, 1, lambda: assert_dirty_data_limited())  # type: ignore
+
+
+def test_tenant_size_limit(neon_env_builder: NeonEnvBuilder):
+    """
+    Test that layers are rolled once the tenant's `ephemeral_bytes_limit` is exceeded, even
+    if the pageserver-wide limit and the checkpoint thresholds are far away.
+    """
+
+    max_dirty_data = 4 * 1024 * 1024
+    compaction_period_s = 10
+
+    tenant_conf = {
+        # Large space + time thresholds: effectively disable these limits
+        "checkpoint_distance": f"{1024 ** 4}",
+        "checkpoint_timeout": "3600s",
+        "compaction_period": f"{compaction_period_s}s",
+        "ephemeral_bytes_limit": f"{max_dirty_data}",
+    }
+
+    env = neon_env_builder.init_configs()
+    env.start()
+
+    # This is about 8MiB of data
+    entries = 400_000
+
+    [(tenant, timeline, last_flush_lsn)] = asyncio.run(workload(env, tenant_conf, 1, entries))
+    wait_until_pageserver_is_caught_up(env, [(tenant, timeline, last_flush_lsn)])
+
+    http_client = env.pageserver.http_client()
+    initdb_lsn = Lsn(http_client.timeline_detail(tenant, timeline)["initdb_lsn"])
+    assert last_flush_lsn - initdb_lsn > max_dirty_data
+
+    def assert_dirty_data_limited():
+        dirty_bytes = get_dirty_bytes(env)
+        assert dirty_bytes < max_dirty_data
+
+    wait_until(compaction_period_s * 2, 1, assert_dirty_data_limited)
+
+    # The layers were rolled by the tenant limit, not by the checkpoint thresholds
+    layer_map = http_client.layer_map_info(tenant, timeline)
+    assert any(Lsn(layer.lsn_start) > initdb_lsn for layer in layer_map.historic_layers)
@@ -145,6 +145,36 @@ def test_pageserver_restart(neon_env_builder: NeonEnvBuilder):
         for bucket in metrics.query_all("pageserver_tenant_activation_seconds_bucket")
     )
 
+    # The activation profile breaks the activation time down into phases, and also includes
+    # the injected delay in the total.
+    profile = pageserver_http.tenant_activation_profile(env.initial_tenant)
+    log.info(f"activation profile: {profile}")
+    assert profile["activated_after_millis"] >= tenant_load_delay_ms
+    for phase in [
+        "config_load",
+        "queued",
+        "list_timelines",
+        "download_index_parts",
+        "load_layer_maps",
+        "reconcile",
+        "activate",
+    ]:
+        assert phase in profile["phases"], f"No time recorded for phase {phase}"
+    assert any(
+        bucket.value > 0
+        for bucket in metrics.query_all(
+            "pageserver_tenant_activation_phase_seconds_bucket", {"phase": "list_timelines"}
+        )
+    )
+
+    # The tenant went through activation, and nothing else since the restart.
+    transitions = pageserver_http.tenant_state_transitions(env.initial_tenant)
+    log.info(f"state transitions: {transitions}")
+    assert [(t["from"], t["to"], t["cause"]) for t in transitions] == [
+        ("Attaching", "Activating", "activate"),
+        ("Activating", "Active", "activated"),
+    ]
+
 
 # Test that repeatedly kills and restarts the page server, while the
 # safekeeper and compute node keep running.
@@ -7,6 +7,7 @@ from typing import Any, Dict, Optional
 import pytest
 from fixtures.log_helper import log
 from fixtures.neon_fixtures import NeonEnvBuilder, NeonPageserver, S3Scrubber
+from fixtures.pageserver.http import PageserverApiException
 from fixtures.pageserver.types import parse_layer_file_name
 from fixtures.pageserver.utils import (
     assert_prefix_empty,
@@ -363,6 +364,69 @@ def test_live_migration(neon_env_builder: NeonEnvBuilder):
     tenant_delete_wait_completed(pageserver_b.http_client(), tenant_id, iterations)
 
 
+def test_rebalance_hooks(neon_env_builder: NeonEnvBuilder):
+    """
+    Test the load scores and the prepare verbs that an external rebalancer uses to move
+    a tenant between pageservers.
+    """
+    neon_env_builder.num_pageservers = 2
+    neon_env_builder.enable_pageserver_remote_storage(
+        remote_storage_kind=RemoteStorageKind.MOCK_S3,
+    )
+    env = neon_env_builder.init_start(initial_tenant_conf=TENANT_CONF)
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    ps_origin = env.pageservers[0]
+    ps_destination = env.pageservers[1]
+
+    # Rates are only known from the second sample on.
+    scores = ps_origin.http_client().load_scores()
+    assert scores[str(tenant_id)]["ingest_bytes_per_second"] is None
+
+    workload = Workload(env, tenant_id, timeline_id)
+    workload.init(ps_origin.id)
+    workload.write_rows(256, ps_origin.id)
+    workload.validate(ps_origin.id)
+
+    def rates_known():
+        score = ps_origin.http_client().load_scores()[str(tenant_id)]
+        log.info(f"Load score: {score}")
+        assert score["ingest_bytes_per_second"] is not None
+        assert score["getpage_requests_per_second"] is not None
+        return score
+
+    score = wait_until(15, 1, rates_known)
+    assert score["resident_bytes"] > 0
+    assert score["ingest_bytes_per_second"] > 0
+    assert ps_destination.http_client().load_scores() == {}
+
+    ps_destination.tenant_location_configure(
+        tenant_id,
+        {
+            "mode": "Secondary",
+            "secondary_conf": {"warm": True},
+            "tenant_conf": {},
+        },
+    )
+
+    # The origin can't receive a tenant that is attached to it.
+    with pytest.raises(PageserverApiException, match="already attached"):
+        ps_origin.http_client().tenant_rebalance_prepare_receive(tenant_id)
+
+    # Unflushed writes are flushed and uploaded by the origin, then downloaded by the
+    # destination.
+    workload.churn_rows(64, ps_origin.id, upload=False)
+    ps_origin.http_client().tenant_rebalance_prepare_give_up(tenant_id)
+    (status, progress) = ps_destination.http_client().tenant_rebalance_prepare_receive(tenant_id)
+    assert status == 200
+    assert progress["layers_downloaded"] == progress["layers_total"]
+    assert ps_origin.list_layers(tenant_id, timeline_id) == ps_destination.list_layers(
+        tenant_id, timeline_id
+    )
+
+
 def test_heatmap_uploads(neon_env_builder: NeonEnvBuilder):
     """
     Test the sequence of location states that are used in a live migration.
@@ -646,6 +710,85 @@ def test_secondary_background_downloads(neon_env_builder: NeonEnvBuilder):
     assert download_rate < expect_download_rate * 2
 
 
+def test_secondary_index_refresh(neon_env_builder: NeonEnvBuilder):
+    """
+    Check that secondary locations pick up the remote index_part of their timelines
+    in the background, including deletions.
+    """
+    neon_env_builder.num_pageservers = 2
+    neon_env_builder.pageserver_config_override = "secondary_index_refresh_period='1s'"
+    env = neon_env_builder.init_configs()
+    env.start()
+
+    tenant_id = TenantId.generate()
+    timeline_a = TimelineId.generate()
+    timeline_b = TimelineId.generate()
+    env.neon_cli.create_tenant(tenant_id, timeline_a, placement_policy='{"Attached":1}')
+    env.neon_cli.create_timeline("main2", tenant_id, timeline_b)
+
+    attached_to_id = env.storage_controller.locate(tenant_id)[0]["node_id"]
+    ps_attached = env.get_pageserver(attached_to_id)
+    ps_secondary = next(p for p in env.pageservers if p != ps_attached)
+
+    def remote_timelines():
+        remote_index = ps_secondary.http_client().tenant_secondary_remote_index(tenant_id)
+        log.info(f"Secondary remote index: {remote_index}")
+        assert remote_index["refreshed_at"] is not None
+        return remote_index["timelines"]
+
+    def both_timelines_seen():
+        timelines = remote_timelines()
+        assert set(timelines.keys()) == {str(timeline_a), str(timeline_b)}
+        assert not any(t["deleted"] for t in timelines.values())
+
+    # The first refresh of a tenant is spread over one period, then it's refreshed every period.
+    wait_until(20, 1, both_timelines_seen)
+
+    env.storage_controller.pageserver_api().timeline_delete(tenant_id, timeline_b)
+
+    def deletion_seen():
+        timelines = remote_timelines()
+        assert str(timeline_a) in timelines
+        assert str(timeline_b) not in timelines or timelines[str(timeline_b)]["deleted"]
+
+    wait_until(20, 1, deletion_seen)
+
+
+def test_secondary_index_refresh_shared_prefix(neon_env_builder: NeonEnvBuilder):
+    """
+    Check that secondary tenants whose IDs share a prefix, and whose indices are found with
+    a single listing of that prefix, each get the timelines of their own.
+    """
+    neon_env_builder.num_pageservers = 2
+    neon_env_builder.pageserver_config_override = "secondary_index_refresh_period='1s'"
+    env = neon_env_builder.init_configs()
+    env.start()
+
+    # The refresher groups tenants by the first two characters of their ID.
+    timelines = {}
+    for i in range(3):
+        tenant_id = TenantId("ab" + str(TenantId.generate())[2:])
+        timeline_ids = [TimelineId.generate() for _ in range(i + 1)]
+        env.neon_cli.create_tenant(tenant_id, timeline_ids[0], placement_policy='{"Attached":1}')
+        for j, timeline_id in enumerate(timeline_ids[1:]):
+            env.neon_cli.create_timeline(f"branch{j}", tenant_id, timeline_id)
+        timelines[tenant_id] = {str(timeline_id) for timeline_id in timeline_ids}
+
+    def all_timelines_seen():
+        for tenant_id, timeline_ids in timelines.items():
+            attached_to_id = env.storage_controller.locate(tenant_id)[0]["node_id"]
+            ps_secondary = next(p for p in env.pageservers if p.id != attached_to_id)
+            remote_index = ps_secondary.http_client().tenant_secondary_remote_index(tenant_id)
+            log.info(f"Secondary remote index of {tenant_id}: {remote_index}")
+            assert remote_index["refreshed_at"] is not None
+            assert set(remote_index["timelines"].keys()) == timeline_ids
+
+    wait_until(20, 1, all_timelines_seen)
+
+    for ps in env.pageservers:
+        assert not ps.log_contains("Failed to list tenant prefix")
+
+
 @pytest.mark.skipif(os.environ.get("BUILD_TYPE") == "debug", reason="only run with release build")
 @pytest.mark.parametrize("via_controller", [True, False])
 def test_slow_secondary_downloads(neon_env_builder: NeonEnvBuilder, via_controller: bool):
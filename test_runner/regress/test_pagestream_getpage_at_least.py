@@ -0,0 +1,77 @@
+import json
+import subprocess
+import time
+from typing import List
+
+from fixtures.neon_fixtures import NeonEnv, NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.types import Lsn
+
+WAIT_LSN_TIMEOUT = 10
+WAL_SEGMENT_SIZE = 16 * 1024 * 1024
+
+
+def getpage_at_least_cmd(env: NeonEnv, rel: str, min_lsn: Lsn) -> List[str]:
+    return [
+        str(env.neon_binpath / "pagebench"),
+        "get-page-at-least",
+        "--page-service-connstring",
+        env.pageserver.connstr(password=None),
+        "--rel",
+        rel,
+        "--blkno",
+        "0",
+        "--min-lsn",
+        str(min_lsn),
+        # Far above the wait_lsn_timeout of the pageserver, which caps it.
+        "--wait-budget-ms",
+        "60000",
+        f"{env.initial_tenant}/{env.initial_timeline}",
+    ]
+
+
+# GetPageAtLeast requests wait for their min_lsn to arrive, and then return the page at an LSN
+# at or above it. A min_lsn that never arrives times out within wait_lsn_timeout.
+def test_pagestream_getpage_at_least(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.pageserver_config_override = f"wait_lsn_timeout = '{WAIT_LSN_TIMEOUT}s'"
+    env = neon_env_builder.init_start()
+    env.pageserver.allowed_errors.append(".*Timed out while waiting for WAL record at LSN.*")
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 100) g")
+    dbnode, relnode = endpoint.safe_psql(
+        "SELECT oid, pg_relation_filenode('t') FROM pg_database WHERE datname = current_database()"
+    )[0]
+    rel = f"1663/{dbnode}/{relnode}"
+    last_flush_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    # In the next WAL segment, which the idle endpoint doesn't reach on its own.
+    min_lsn = Lsn((last_flush_lsn.lsn_int // WAL_SEGMENT_SIZE + 1) * WAL_SEGMENT_SIZE + 1)
+    request = subprocess.Popen(
+        getpage_at_least_cmd(env, rel, min_lsn),
+        stdout=subprocess.PIPE,
+        stderr=subprocess.PIPE,
+        text=True,
+    )
+    time.sleep(1)
+    assert request.poll() is None, "the request should wait for min_lsn"
+
+    endpoint.safe_psql("SELECT pg_switch_wal()")
+    endpoint.safe_psql("INSERT INTO t VALUES (0)")
+    assert wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id) >= min_lsn
+    stdout, stderr = request.communicate(timeout=WAIT_LSN_TIMEOUT)
+    assert request.returncode == 0, stderr
+    response = json.loads(stdout)
+    assert Lsn(response["lsn"]) >= min_lsn
+    assert response["page_size"] == 8192
+
+    never_lsn = min_lsn + 1024 * 1024 * 1024 * 1024
+    started_at = time.monotonic()
+    request = subprocess.run(
+        getpage_at_least_cmd(env, rel, never_lsn), capture_output=True, text=True, timeout=60
+    )
+    elapsed = time.monotonic() - started_at
+    assert request.returncode != 0
+    assert f"Timed out while waiting for WAL record at LSN {never_lsn}" in request.stderr
+    assert WAIT_LSN_TIMEOUT <= elapsed < 30, "the wait should be capped at wait_lsn_timeout"
@@ -0,0 +1,42 @@
+from contextlib import closing
+
+import psycopg2
+import psycopg2.extras
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder
+
+
+# Check that clients agree with the pageserver on the newest common pagestream protocol version,
+# that unknown features are ignored, and that the versions are listed in the diagnostics.
+def test_pagestream_negotiation(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+
+    versions = env.pageserver.http_client().page_service_protocol()
+    assert [v["version"] for v in versions] == [1, 2, 3]
+    assert versions[1]["command"] == "pagestream_v2"
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor(cursor_factory=psycopg2.extras.RealDictCursor) as pscur:
+            pscur.execute("pagestream_negotiate 1 2")
+            assert pscur.fetchone() == {"version": 2, "command": "pagestream_v2", "features": ""}
+
+            # A client from the future.
+            pscur.execute("pagestream_negotiate 2 9 teleportation")
+            res = pscur.fetchone()
+            assert res is not None
+            assert res["version"] == 3
+            assert res["features"] == ""
+
+            pscur.execute("pagestream_negotiate 2 3 teleportation,lsn_at_least")
+            assert pscur.fetchone() == {
+                "version": 3,
+                "command": "pagestream_v3",
+                "features": "lsn_at_least",
+            }
+
+            # GetPageAtLeast requests need V2.
+            pscur.execute("pagestream_negotiate 1 1 lsn_at_least")
+            assert pscur.fetchone() == {"version": 1, "command": "pagestream", "features": ""}
+
+            with pytest.raises(psycopg2.Error, match="no common pagestream protocol version"):
+                pscur.execute("pagestream_negotiate 4 9")
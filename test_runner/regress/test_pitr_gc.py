@@ -1,5 +1,8 @@
+import pytest
 from fixtures.log_helper import log
-from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_until_tenant_active
 from fixtures.types import TimelineId
 from fixtures.utils import print_gc_result, query_scalar
 
@@ -72,3 +75,81 @@ def test_pitr_gc(neon_env_builder: NeonEnvBuilder):
     # All the rows are visible on the main branch
     main_cur.execute("SELECT count(*) FROM foo")
     assert main_cur.fetchone() == (10000,)
+
+
+#
+# Check that GC keeps the history of a relation pinned in the tenant config,
+# even with no GC horizon and no PITR interval, and that it stays readable.
+#
+def test_gc_pinned_relation(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.pageserver_config_override = (
+        "tenant_config={pitr_interval = '0 sec', gc_horizon = 0}"
+    )
+
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    endpoint = env.endpoints.create_start("main")
+    timeline = TimelineId(endpoint.safe_psql("SHOW neon.timeline_id")[0][0])
+    pageserver_http = env.pageserver.http_client()
+
+    endpoint.safe_psql_many(
+        [
+            "CREATE TABLE audit (t text)",
+            "CREATE TABLE other (t text)",
+            "INSERT INTO audit VALUES ('before')",
+            "INSERT INTO other VALUES ('before')",
+        ]
+    )
+    [(dbnode, relnode, other_relnode)] = endpoint.safe_psql(
+        "SELECT d.oid, pg_relation_filenode('audit'), pg_relation_filenode('other')"
+        " FROM pg_database d WHERE datname = 'postgres'"
+    )
+    rel = f"1663/{dbnode}/{relnode}"
+    other_rel = f"1663/{dbnode}/{other_relnode}"
+
+    pageserver_http.set_tenant_config(
+        tenant_id,
+        {"gc_pinned_relations": [{"spcnode": 1663, "dbnode": dbnode, "relnode": relnode}]},
+    )
+    # The history of the pinned relation is kept from the first GC that sees the pin.
+    pageserver_http.timeline_gc(tenant_id, timeline, 0)
+
+    lsn_before = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline)
+    page_before = pageserver_http.timeline_rel_page(tenant_id, timeline, rel, 0, lsn_before)
+    assert b"before" in page_before
+
+    for _ in range(10):
+        endpoint.safe_psql_many(
+            [
+                "INSERT INTO audit SELECT 'long string to consume some space' FROM generate_series(1, 1000)",
+                "UPDATE audit SET t = t || '.'",
+                "UPDATE other SET t = 'after'",
+            ]
+        )
+        pageserver_http.timeline_checkpoint(tenant_id, timeline)
+
+    pageserver_http.timeline_compact(tenant_id, timeline)
+    gc_result = pageserver_http.timeline_gc(tenant_id, timeline, 0)
+    print_gc_result(gc_result)
+    assert gc_result["layers_needed_by_pinned_relations"] > 0
+    assert gc_result["layers_removed"] > 0
+
+    # The pinned relation can still be read below the GC cutoff, the other one can't.
+    assert (
+        pageserver_http.timeline_rel_page(tenant_id, timeline, rel, 0, lsn_before) == page_before
+    )
+    with pytest.raises(PageserverApiException, match="below the GC cutoff"):
+        pageserver_http.timeline_rel_page(tenant_id, timeline, other_rel, 0, lsn_before)
+
+    # The pinned history survives a restart of the pageserver.
+    env.pageserver.restart()
+    wait_until_tenant_active(pageserver_http, tenant_id)
+    assert (
+        pageserver_http.timeline_rel_page(tenant_id, timeline, rel, 0, lsn_before) == page_before
+    )
+
+    # Without the pin, GC no longer keeps layers for it.
+    pageserver_http.set_tenant_config(tenant_id, {})
+    gc_result = pageserver_http.timeline_gc(tenant_id, timeline, 0)
+    print_gc_result(gc_result)
+    assert gc_result["layers_needed_by_pinned_relations"] == 0
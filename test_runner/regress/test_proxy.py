@@ -1,5 +1,6 @@
 import asyncio
 import json
+import struct
 import subprocess
 import time
 from typing import Any, List, Optional, Tuple
@@ -275,6 +276,56 @@ def test_sql_over_http(static_proxy: NeonProxy):
     assert res["rowCount"] is None
 
 
+def test_sql_over_http_param_types(static_proxy: NeonProxy):
+    static_proxy.safe_psql("create role http with login password 'http' superuser")
+
+    def q(sql: str, params: List[Any], **types: Any) -> requests.Response:
+        connstr = f"postgresql://http:http@{static_proxy.domain}:{static_proxy.proxy_port}/postgres"
+        return requests.post(
+            f"https://{static_proxy.domain}:{static_proxy.external_http_port}/sql",
+            data=json.dumps({"query": sql, "params": params, **types}),
+            headers={"Content-Type": "application/sql", "Neon-Connection-String": connstr},
+            verify=str(static_proxy.test_output_dir / "proxy.crt"),
+        )
+
+    # int8 = 20, text = 25, _int8 = 1016
+    res = q("select $1 as answer, pg_typeof($1)::text as type", [42], paramTypes=[20])
+    assert res.status_code == 200, res.text
+    # int8 values are encoded as JSON strings.
+    assert res.json()["rows"] == [{"answer": "42", "type": "bigint"}]
+
+    res = q("select $1 as data, length($1) as len", ["AAH/"], paramFormats=["base64"])
+    assert res.status_code == 200, res.text
+    assert res.json()["rows"] == [{"data": "\\x0001ff", "len": 3}]
+
+    # The types are sent with the Parse message, the query text is left alone.
+    res = q(
+        "select $2[1] as answer, '$1' as s /* $2 */",
+        [None, [1, 2]],
+        paramTypes=[25, 1016],
+    )
+    assert res.status_code == 200, res.text
+    assert res.json()["rows"] == [{"answer": "1", "s": "$1"}]
+
+    res = q("select $1", [1], paramTypes=[20, 20])
+    assert res.status_code == 400
+    assert res.json()["message"] == "paramTypes has 2 entries, but there are 1 parameters"
+    assert res.json()["neonErrorCode"] == "BAD_REQUEST"
+    assert res.json()["code"] == "08P01"
+
+    res = q("select $1", [1], paramTypes=[20], paramFormats=["base64"])
+    assert res.status_code == 400
+    assert "must be of type bytea" in res.json()["message"]
+
+    res = q("select $1", ["not base64!"], paramFormats=["base64"])
+    assert res.status_code == 400
+    assert "base64 parameter $1 is invalid" in res.json()["message"]
+
+    res = q("select $1", [1], paramTypes=[4000000000])
+    assert res.status_code == 400
+    assert res.json()["message"] == "type OID 4000000000 of parameter $1 does not exist"
+
+
 def test_sql_over_http_output_options(static_proxy: NeonProxy):
     static_proxy.safe_psql("create role http2 with login password 'http2' superuser")
 
@@ -310,6 +361,52 @@ def test_sql_over_http_output_options(static_proxy: NeonProxy):
     assert rows == [["1", "a", "{1,2,3}"]]
 
 
+def test_sql_over_http_pg_binary(static_proxy: NeonProxy):
+    static_proxy.safe_psql("create role http with login password 'http' superuser")
+
+    connstr = f"postgresql://http:http@{static_proxy.domain}:{static_proxy.proxy_port}/postgres"
+    response = requests.post(
+        f"https://{static_proxy.domain}:{static_proxy.external_http_port}/sql",
+        data=json.dumps({"query": "select $1::int4 as n, null::text as s", "params": [42]}),
+        headers={
+            "Content-Type": "application/sql",
+            "Neon-Connection-String": connstr,
+            "Accept": "application/vnd.neon.pg-binary",
+        },
+        verify=str(static_proxy.test_output_dir / "proxy.crt"),
+    )
+    assert response.status_code == 200, response.text
+    assert response.headers["Content-Type"] == "application/vnd.neon.pg-binary"
+
+    messages = []
+    body = response.content
+    while body:
+        tag, length = body[:1], struct.unpack(">i", body[1:5])[0]
+        messages.append((tag, body[5 : 1 + length]))
+        body = body[1 + length :]
+    assert [tag for tag, _ in messages] == [b"T", b"D", b"C"]
+
+    # Both columns are described with the binary format code.
+    row_description = messages[0][1]
+    assert struct.unpack(">h", row_description[:2])[0] == 2
+    fields = row_description[2:]
+    format_codes = []
+    for _ in range(2):
+        fields = fields[fields.index(b"\0") + 1 :]
+        format_codes.append(struct.unpack(">h", fields[16:18])[0])
+        fields = fields[18:]
+    assert format_codes == [1, 1]
+
+    # The int4 value is in the binary format of postgres, not the text one.
+    data_row = messages[1][1]
+    assert struct.unpack(">h", data_row[:2])[0] == 2
+    assert struct.unpack(">i", data_row[2:6])[0] == 4
+    assert struct.unpack(">i", data_row[6:10])[0] == 42
+    assert struct.unpack(">i", data_row[10:14])[0] == -1
+
+    assert messages[2][1] == b"SELECT 1\0"
+
+
 def test_sql_over_http_batch(static_proxy: NeonProxy):
     static_proxy.safe_psql("create role http with login password 'http' superuser")
 
@@ -423,6 +520,117 @@ def test_sql_over_http_batch_output_options(static_proxy: NeonProxy):
     assert results[1]["rows"] == [{"answer": "42"}]
 
 
+def test_sql_over_http_transaction_session(static_proxy: NeonProxy):
+    static_proxy.safe_psql("create role http with login password 'http' superuser")
+    static_proxy.safe_psql("create table t(id int)")
+
+    base_url = f"https://{static_proxy.domain}:{static_proxy.external_http_port}"
+    connstr = f"postgresql://http:http@{static_proxy.domain}:{static_proxy.proxy_port}/postgres"
+
+    def post(path: str, query: Optional[str] = None, session: Optional[str] = None) -> Any:
+        headers = {"Content-Type": "application/sql", "Neon-Connection-String": connstr}
+        if session is not None:
+            headers["Neon-Transaction-Session"] = session
+        return requests.post(
+            f"{base_url}{path}",
+            data=json.dumps({"query": query, "params": []}) if query is not None else None,
+            headers=headers,
+            verify=str(static_proxy.test_output_dir / "proxy.crt"),
+        )
+
+    def count() -> int:
+        res = post("/sql", "select count(*) as n from t")
+        assert res.status_code == 200
+        return int(res.json()["rows"][0]["n"])
+
+    # Rolled back
+    res = post("/sql/transaction")
+    assert res.status_code == 200
+    session = res.json()["session"]
+    assert res.headers["Neon-Transaction-Session"] == session
+
+    res = post("/sql", "insert into t values (1)", session)
+    assert res.status_code == 200
+    res = post("/sql", "select count(*) as n from t", session)
+    # bigint values are encoded as JSON strings.
+    assert res.json()["rows"] == [{"n": "1"}]
+    assert count() == 0, "uncommitted row is visible outside of the session"
+
+    res = post("/sql/transaction/rollback", session=session)
+    assert res.status_code == 200
+    assert res.json()["command"] == "ROLLBACK"
+    assert count() == 0
+
+    # The session is over
+    res = post("/sql", "select 1", session)
+    assert res.status_code == 400
+    assert "transaction session not found" in res.json()["message"]
+
+    # Committed
+    session = post("/sql/transaction").json()["session"]
+    assert post("/sql", "insert into t values (1)", session).status_code == 200
+    res = post("/sql/transaction/commit", session=session)
+    assert res.status_code == 200
+    assert res.json()["command"] == "COMMIT"
+    assert count() == 1
+
+    # Ending a transaction needs a session
+    res = post("/sql/transaction/commit")
+    assert res.status_code == 400
+    assert "transaction session token is missing" in res.json()["message"]
+
+
+def test_sql_over_http_result_cache(static_proxy: NeonProxy):
+    static_proxy.safe_psql("create role http with login password 'http' superuser")
+    static_proxy.safe_psql("create table t(id int)")
+
+    base_url = f"https://{static_proxy.domain}:{static_proxy.external_http_port}"
+    connstr = f"postgresql://http:http@{static_proxy.domain}:{static_proxy.proxy_port}/postgres"
+
+    def post(path: str, query: Optional[str] = None) -> Any:
+        return requests.post(
+            f"{base_url}{path}",
+            data=json.dumps({"query": query, "params": []}) if query is not None else None,
+            headers={
+                "Content-Type": "application/sql",
+                "Neon-Connection-String": connstr,
+                "Neon-Result-Cache": "true",
+            },
+            verify=str(static_proxy.test_output_dir / "proxy.crt"),
+        )
+
+    res = post("/sql", "select count(*) as n from t")
+    assert res.status_code == 200
+    assert res.headers["Neon-Result-Cache"] == "miss"
+    res = post("/sql", "select  count(*) as n\n from t;")
+    assert res.status_code == 200
+    assert res.headers["Neon-Result-Cache"] == "hit"
+    # bigint values are encoded as JSON strings.
+    assert res.json()["rows"] == [{"n": "0"}]
+
+    # Queries that call functions that are not immutable are not cached
+    for _ in range(2):
+        res = post("/sql", "select now() as t")
+        assert res.status_code == 200
+        assert res.headers["Neon-Result-Cache"] == "miss"
+
+    # Cached queries run in read only transactions
+    res = post("/sql", "insert into t values (1)")
+    assert res.status_code == 400
+    assert "read-only transaction" in res.json()["message"]
+
+    # Writes move the LSN
+    static_proxy.safe_psql("insert into t values (1)")
+    res = post("/sql", "select count(*) as n from t")
+    assert res.headers["Neon-Result-Cache"] == "miss"
+    assert res.json()["rows"] == [{"n": "1"}]
+
+    res = post("/sql/cache/invalidate")
+    assert res.status_code == 200
+    res = post("/sql", "select count(*) as n from t")
+    assert res.headers["Neon-Result-Cache"] == "miss"
+
+
 def test_sql_over_http_pool(static_proxy: NeonProxy):
     static_proxy.safe_psql("create user http_auth with password 'http' superuser")
 
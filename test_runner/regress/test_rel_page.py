@@ -0,0 +1,117 @@
+import pytest
+from fixtures.neon_fixtures import (
+    NeonEnv,
+    NeonEnvBuilder,
+    tenant_get_shards,
+    wait_for_last_flush_lsn,
+)
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_for_last_record_lsn
+from fixtures.types import Lsn
+
+
+def test_rel_page(neon_simple_env: NeonEnv):
+    """
+    Pages can be read at past LSNs over the management API, without a compute.
+    """
+    env = neon_simple_env
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    client = env.pageserver.http_client()
+
+    endpoint = env.endpoints.create_start("main")
+
+    def flush_lsn() -> Lsn:
+        lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+        wait_for_last_record_lsn(client, tenant_id, timeline_id, lsn)
+        return lsn
+
+    endpoint.safe_psql_many(
+        [
+            "CREATE TABLE t (i int, s text)",
+            "INSERT INTO t VALUES (1, 'before')",
+        ]
+    )
+    lsn_before = flush_lsn()
+    endpoint.safe_psql("UPDATE t SET s = 'after'")
+    lsn_after = flush_lsn()
+
+    [(dbnode, relnode)] = endpoint.safe_psql(
+        "SELECT d.oid, pg_relation_filenode('t') FROM pg_database d WHERE datname = 'postgres'"
+    )
+    rel = f"1663/{dbnode}/{relnode}"
+
+    page_before = client.timeline_rel_page(tenant_id, timeline_id, rel, 0, lsn_before)
+    page_after = client.timeline_rel_page(tenant_id, timeline_id, rel, 0, lsn_after)
+    assert len(page_before) == len(page_after) == 8192
+    assert b"before" in page_before and b"after" not in page_before
+    assert b"after" in page_after
+    # reads at an LSN are repeatable
+    assert client.timeline_rel_page(tenant_id, timeline_id, rel, 0, lsn_before) == page_before
+
+    with pytest.raises(PageserverApiException, match="beyond the end"):
+        client.timeline_rel_page(tenant_id, timeline_id, rel, 1, lsn_after)
+    missing_rel = f"1663/{dbnode}/{relnode + 1000}"
+    with pytest.raises(PageserverApiException, match="does not exist"):
+        client.timeline_rel_page(tenant_id, timeline_id, missing_rel, 0, lsn_after)
+    with pytest.raises(PageserverApiException, match="is not ingested yet"):
+        client.timeline_rel_page(tenant_id, timeline_id, rel, 0, lsn_after + 0x1000000)
+    with pytest.raises(PageserverApiException, match="invalid relation"):
+        client.timeline_rel_page(tenant_id, timeline_id, "1663/foo", 0, lsn_after)
+
+
+def test_rel_page_sharded(neon_env_builder: NeonEnvBuilder):
+    """
+    Each page is read from the shard which stores it. Only shard zero, which stores
+    relation sizes, checks that the relation and the block exist.
+    """
+    # With a stripe of one page, consecutive blocks are on different shards.
+    env = neon_env_builder.init_start(
+        initial_tenant_shard_count=2, initial_tenant_shard_stripe_size=1
+    )
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql_many(
+        [
+            "CREATE TABLE t (i int, s text) WITH (fillfactor = 10)",
+            "INSERT INTO t SELECT g, 'row' FROM generate_series(1, 100) g",
+        ]
+    )
+    lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    [(dbnode, relnode, nblocks)] = endpoint.safe_psql(
+        "SELECT d.oid, pg_relation_filenode('t'), pg_relation_size('t') / 8192 "
+        "FROM pg_database d WHERE datname = 'postgres'"
+    )
+    assert nblocks >= 2
+    rel = f"1663/{dbnode}/{relnode}"
+
+    shards = tenant_get_shards(env, tenant_id)
+    read_from = set()
+    for blkno in range(nblocks):
+        pages = []
+        for shard_id, pageserver in shards:
+            try:
+                pages.append(
+                    pageserver.http_client().timeline_rel_page(
+                        shard_id, timeline_id, rel, blkno, lsn
+                    )
+                )
+                read_from.add(shard_id.shard_number)
+            except PageserverApiException as e:
+                assert "is stored on shard" in str(e)
+        assert len(pages) == 1
+        assert len(pages[0]) == 8192 and b"row" in pages[0]
+    assert read_from == {0, 1}
+
+    # shard zero checks the relation for the blocks it stores
+    [(shard_zero, pageserver)] = [s for s in shards if s[0].shard_number == 0]
+    errors = []
+    for blkno in range(2):
+        with pytest.raises(PageserverApiException) as e:
+            pageserver.http_client().timeline_rel_page(
+                shard_zero, timeline_id, f"1663/{dbnode}/{relnode + 1000}", blkno, lsn
+            )
+        errors.append(str(e.value))
+    assert any("does not exist" in err for err in errors), errors
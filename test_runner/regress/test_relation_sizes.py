@@ -0,0 +1,64 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnv
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_for_last_record_lsn
+from fixtures.types import Lsn
+
+
+def test_relation_sizes(neon_simple_env: NeonEnv):
+    """
+    The pageserver reports the relation sizes at past LSNs, which tell which tables grew.
+    """
+    env = neon_simple_env
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    client = env.pageserver.http_client()
+
+    endpoint = env.endpoints.create_start("main")
+
+    def flush_lsn() -> Lsn:
+        lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+        wait_for_last_record_lsn(client, tenant_id, timeline_id, lsn)
+        return lsn
+
+    endpoint.safe_psql_many(
+        [
+            "CREATE TABLE small (i int)",
+            "CREATE TABLE big (i int)",
+            "INSERT INTO small SELECT generate_series(1, 100)",
+        ]
+    )
+    lsn_before = flush_lsn()
+    endpoint.safe_psql("INSERT INTO big SELECT generate_series(1, 100000)")
+    lsn_after = flush_lsn()
+
+    dbnode = endpoint.safe_psql("SELECT oid FROM pg_database WHERE datname = 'postgres'")[0][0]
+    relnodes = dict(
+        endpoint.safe_psql(
+            "SELECT relname, pg_relation_filenode(oid) FROM pg_class "
+            "WHERE relname IN ('small', 'big')"
+        )
+    )
+
+    def main_fork_sizes(lsn: Lsn) -> dict[str, int]:
+        sizes = client.timeline_relation_sizes(tenant_id, timeline_id, lsn)
+        assert Lsn(sizes["lsn"]) == lsn
+        [db] = [db for db in sizes["databases"] if db["dbnode"] == dbnode]
+        assert db["size"] == sum(rel["size"] for rel in db["relations"])
+        by_relnode = {
+            rel["relnode"]: rel["size"] for rel in db["relations"] if rel["forknum"] == 0
+        }
+        return {name: by_relnode.get(relnode, 0) for name, relnode in relnodes.items()}
+
+    before = main_fork_sizes(lsn_before)
+    after = main_fork_sizes(lsn_after)
+    assert before["big"] == 0
+    assert after["big"] > 1024 * 1024
+    assert after["small"] == before["small"] > 0
+
+    # the sizes at the last record LSN by default
+    latest = client.timeline_relation_sizes(tenant_id, timeline_id)
+    assert Lsn(latest["lsn"]) >= lsn_after
+
+    with pytest.raises(PageserverApiException, match="is not ingested yet"):
+        client.timeline_relation_sizes(tenant_id, timeline_id, Lsn(latest["lsn"]) + 0x1000000)
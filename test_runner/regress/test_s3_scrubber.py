@@ -1,3 +1,4 @@
+import json
 import os
 import shutil
 from typing import Optional
@@ -109,3 +110,49 @@ def test_scrubber_tenant_snapshot(neon_env_builder: NeonEnvBuilder, shard_count:
 
     # Check we can read everything
     workload.validate()
+
+
+def test_scrubber_tenant_snapshot_verify_layers(neon_env_builder: NeonEnvBuilder):
+    """
+    Test the `--verify-layers` option of `tenant-snapshot`, which checks the indices of the
+    downloaded layers, and reports the problems in `layer_findings.json`.
+    """
+
+    neon_env_builder.enable_pageserver_remote_storage(s3_storage())
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    workload = Workload(env, tenant_id, timeline_id, "main")
+    workload.init()
+    workload.write_rows(128)
+    workload.stop()
+
+    scrubber = S3Scrubber(neon_env_builder)
+    output_path = neon_env_builder.test_output_dir / "snapshot"
+    os.makedirs(output_path)
+    scrubber.tenant_snapshot(tenant_id, output_path, verify_layers=True)
+    assert not os.path.exists(output_path / "layer_findings.json")
+
+    # Zero the last block of a layer, which is the root of its index.
+    assert isinstance(env.pageserver_remote_storage, S3Storage)
+    bucket = env.pageserver_remote_storage.bucket_name
+    remote_client = env.pageserver_remote_storage.client
+    objects = remote_client.list_objects_v2(
+        Bucket=bucket, Prefix=env.pageserver_remote_storage.tenant_path(tenant_id)
+    )["Contents"]
+    layer_key = next(o["Key"] for o in objects if "__" in o["Key"].split("/")[-1])
+    layer = remote_client.get_object(Bucket=bucket, Key=layer_key)["Body"].read()
+    block_size = 8192
+    corrupted = layer[:-block_size] + bytes(block_size)
+    remote_client.put_object(Bucket=bucket, Key=layer_key, Body=corrupted)
+
+    # The problems are reported as findings, rather than aborting the download.
+    output_path = neon_env_builder.test_output_dir / "corrupted_snapshot"
+    os.makedirs(output_path)
+    with pytest.raises(Exception):
+        scrubber.tenant_snapshot(tenant_id, output_path, verify_layers=True)
+    with open(output_path / "layer_findings.json") as f:
+        layer_findings = json.load(f)
+    assert len(layer_findings) == 1
+    assert layer_findings[0]["findings"][0]["problem"]["kind"] == "malformed"
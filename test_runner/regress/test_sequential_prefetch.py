@@ -0,0 +1,23 @@
+from fixtures.neon_fixtures import NeonEnvBuilder
+
+
+# Check that the pageserver reads ahead of a sequential scan, when the tenant enables it.
+def test_sequential_prefetch(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start(
+        initial_tenant_conf={
+            "sequential_prefetch": "true",
+        }
+    )
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 100000) g")
+
+    # Restart the compute, so that the scan has to read the pages from the pageserver.
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 100000
+
+    metrics = env.pageserver.http_client().get_metrics()
+    prefetched = metrics.query_one("pageserver_page_service_prefetch_blocks_total").value
+    hits = metrics.query_one("pageserver_page_service_prefetch_hits_total").value
+    assert prefetched > 0
+    assert hits > 0
@@ -0,0 +1,50 @@
+import pytest
+from fixtures.common_types import TenantShardId
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import wait_timeline_detail_404
+
+
+# Check that the branches without accesses are listed as stale, that their deletion skips
+# the parents until their children are gone, and that sharded tenants are rejected.
+def test_stale_timelines(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    ps_http = env.pageserver.http_client()
+
+    used = env.neon_cli.create_branch("used", tenant_id=tenant_id)
+    unused = env.neon_cli.create_branch("unused", tenant_id=tenant_id)
+    unused_child = env.neon_cli.create_branch(
+        "unused_child", ancestor_branch_name="unused", tenant_id=tenant_id
+    )
+    with env.endpoints.create_start("used", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 1000) g")
+
+    # With zero idle days, any access of the current hour keeps a branch.
+    stale = {t["timeline_id"]: t for t in ps_http.tenant_stale_timelines(tenant_id, 0)}
+    assert set(stale.keys()) == {str(unused), str(unused_child)}
+    assert stale[str(unused)]["has_children"]
+    assert not stale[str(unused_child)]["has_children"]
+    assert str(used) not in stale
+
+    with pytest.raises(PageserverApiException, match="exceeds the retention"):
+        ps_http.tenant_stale_timelines(tenant_id, 365)
+
+    dry_run = ps_http.tenant_stale_timelines_delete(tenant_id, 0, dry_run=True)
+    assert {t["timeline_id"] for t in dry_run if t["deleted"]} == {str(unused_child)}
+    ps_http.timeline_detail(tenant_id, unused_child)
+
+    deleted = ps_http.tenant_stale_timelines_delete(tenant_id, 0)
+    assert {t["timeline_id"] for t in deleted if t["deleted"]} == {str(unused_child)}
+    wait_timeline_detail_404(ps_http, tenant_id, unused_child, iterations=20)
+
+    # The parent is deleted once its child is gone.
+    deleted = ps_http.tenant_stale_timelines_delete(tenant_id, 0)
+    assert {t["timeline_id"] for t in deleted if t["deleted"]} == {str(unused)}
+    wait_timeline_detail_404(ps_http, tenant_id, unused, iterations=20)
+    ps_http.timeline_detail(tenant_id, used)
+
+    # Each shard only sees its own reads, so sharded tenants are rejected.
+    sharded_tenant_id, _ = env.neon_cli.create_tenant(shard_count=2)
+    with pytest.raises(PageserverApiException, match="sharded tenants"):
+        ps_http.tenant_stale_timelines(TenantShardId(sharded_tenant_id, 0, 2), 0)
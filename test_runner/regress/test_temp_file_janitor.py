@@ -0,0 +1,43 @@
+import os
+import time
+
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.utils import wait_until
+
+
+def test_temp_file_janitor(neon_env_builder: NeonEnvBuilder):
+    """
+    Stale temporaries left in tenant and timeline directories are removed in the background,
+    without a restart, while recent ones are left alone.
+    """
+    neon_env_builder.pageserver_config_override = """
+temp_file_janitor_period='1s'
+temp_file_max_age='1h'
+"""
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+
+    timeline_dir = env.pageserver.timeline_dir(env.initial_tenant, env.initial_timeline)
+    timelines_dir = timeline_dir.parent
+    two_hours_ago = time.time() - 2 * 3600
+
+    stale_layer = timeline_dir / "some-layer.abcdefgh.___temp"
+    stale_layer.write_bytes(b"\0" * 1000)
+    os.utime(stale_layer, (two_hours_ago, two_hours_ago))
+
+    stale_basebackup = timelines_dir / "basebackup-somewhere.___temp"
+    (stale_basebackup / "global").mkdir(parents=True)
+    (stale_basebackup / "global" / "pg_control").write_bytes(b"\0" * 500)
+    os.utime(stale_basebackup, (two_hours_ago, two_hours_ago))
+
+    fresh_download = timeline_dir / "other-layer.temp_download"
+    fresh_download.write_bytes(b"\0" * 100)
+
+    def removed():
+        assert not stale_layer.exists()
+        assert not stale_basebackup.exists()
+        assert ps_http.get_metric_value("pageserver_temp_files_removed_total") == 2
+        assert ps_http.get_metric_value("pageserver_temp_files_removed_bytes_total") == 1500
+
+    wait_until(20, 0.5, removed)
+    assert fresh_download.exists()
@@ -0,0 +1,90 @@
+import pytest
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.http import PageserverApiException
+from fixtures.pageserver.utils import (
+    assert_prefix_empty,
+    tenant_delete_wait_completed,
+    wait_until_tenant_active,
+)
+from fixtures.remote_storage import RemoteStorageKind
+
+
+# Check that a tenant exported to a bundle can be imported back, with its branches and config,
+# once it is gone from remote storage, and that corrupt bundles are refused.
+def test_tenant_bundle(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.LOCAL_FS)
+    neon_env_builder.pageserver_config_override = 'bundle_dir="bundles"'
+    env = neon_env_builder.init_start(initial_tenant_conf={"gc_horizon": 1024**2})
+    env.pageserver.allowed_errors.extend(
+        [".*invalid bundle.*", ".*already has timelines of tenant.*"]
+    )
+    tenant_id = env.initial_tenant
+    ps_http = env.pageserver.http_client()
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, env.initial_timeline)
+    branch_id = env.neon_cli.create_branch("child", "main", tenant_id=tenant_id)
+    with env.endpoints.create_start("child", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("INSERT INTO t SELECT g FROM generate_series(1, 10000) g")
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, branch_id)
+
+    # Bundle paths are relative to bundle_dir, and cannot leave it.
+    for path in [str(env.repo_dir / "bundle"), "../bundle"]:
+        with pytest.raises(PageserverApiException, match="invalid bundle path") as exc:
+            ps_http.tenant_export(tenant_id, path)
+        assert exc.value.status_code == 400
+
+    bundle = env.pageserver.workdir / "bundles" / "bundle"
+    manifest = ps_http.tenant_export(tenant_id, "bundle")
+    assert manifest["tenant_id"] == str(tenant_id)
+    assert manifest["config"]["gc_horizon"] == 1024**2
+    assert {t["timeline_id"] for t in manifest["timelines"]} == {
+        str(env.initial_timeline),
+        str(branch_id),
+    }
+    assert (bundle / "manifest.json").exists()
+
+    # The bundle directory must be empty.
+    with pytest.raises(PageserverApiException, match="not empty") as exc:
+        ps_http.tenant_export(tenant_id, "bundle")
+    assert exc.value.status_code == 400
+
+    attached = env.storage_controller.inspect(tenant_id)
+    assert attached is not None
+    with pytest.raises(PageserverApiException, match="attached at this pageserver"):
+        ps_http.tenant_import(tenant_id, "bundle", attached[0])
+
+    tenant_delete_wait_completed(ps_http, tenant_id, 10)
+    assert_prefix_empty(
+        neon_env_builder.pageserver_remote_storage,
+        prefix="/".join(("tenants", str(tenant_id))),
+    )
+
+    # A corrupt layer fails the import before anything is uploaded.
+    layer = bundle / manifest["timelines"][0]["layers"][0]["path"]
+    contents = layer.read_bytes()
+    layer.write_bytes(bytes([contents[0] ^ 1]) + contents[1:])
+    generation = env.storage_controller.attach_hook_issue(tenant_id, env.pageserver.id)
+    with pytest.raises(PageserverApiException, match="invalid bundle") as exc:
+        ps_http.tenant_import(tenant_id, "bundle", generation)
+    assert exc.value.status_code == 400
+    assert_prefix_empty(
+        neon_env_builder.pageserver_remote_storage,
+        prefix="/".join(("tenants", str(tenant_id))),
+    )
+    layer.write_bytes(contents)
+
+    imported = ps_http.tenant_import(tenant_id, "bundle", generation)
+    assert imported["timelines"] == manifest["timelines"]
+    with pytest.raises(PageserverApiException) as exc:
+        ps_http.tenant_import(tenant_id, "bundle", generation)
+    assert exc.value.status_code == 409
+
+    env.pageserver.tenant_attach(tenant_id, config=imported["config"], generation=generation)
+    wait_until_tenant_active(ps_http, tenant_id)
+    assert ps_http.tenant_config(tenant_id).tenant_specific_overrides["gc_horizon"] == 1024**2
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 10000
+    with env.endpoints.create_start("child", tenant_id=tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 20000
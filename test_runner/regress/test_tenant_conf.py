@@ -1,14 +1,17 @@
 import json
 from contextlib import closing
+from typing import Any
 
 import psycopg2.extras
+import pytest
 from fixtures.log_helper import log
 from fixtures.neon_fixtures import (
     NeonEnvBuilder,
 )
+from fixtures.pageserver.http import PageserverApiException
 from fixtures.pageserver.utils import assert_tenant_state, wait_for_upload
 from fixtures.remote_storage import LocalFsStorage, RemoteStorageKind
-from fixtures.types import Lsn
+from fixtures.types import Lsn, TenantId
 from fixtures.utils import wait_until
 
 
@@ -412,3 +415,160 @@ def test_live_reconfig_get_evictions_low_residence_duration_metric_threshold(
     metric = get_metric()
     assert int(metric.labels["low_threshold_secs"]) == 24 * 60 * 60, "label resets to default"
     assert int(metric.value) == 0, "value resets to default"
+
+
+def test_tenant_aliases(neon_env_builder: NeonEnvBuilder):
+    """
+    Tenants and timelines can be addressed by the aliases set in the tenant config.
+    """
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    ps_http.set_tenant_config(
+        tenant_id, {"alias": "staging", "timeline_aliases": {"main": str(timeline_id)}}
+    )
+
+    def get(path: str) -> Any:
+        res = ps_http.get(f"http://localhost:{ps_http.port}/v1/tenant/{path}")
+        ps_http.verbose_error(res)
+        return res.json()
+
+    assert get("staging")["id"] == str(tenant_id)
+    assert get("staging/timeline/main")["timeline_id"] == str(timeline_id)
+    assert get(f"{tenant_id}/timeline/main")["timeline_id"] == str(timeline_id)
+    assert get(f"staging/timeline/{timeline_id}")["timeline_id"] == str(timeline_id)
+
+    with pytest.raises(PageserverApiException, match="no timeline with ID or alias") as exc:
+        get("staging/timeline/unknown")
+    assert exc.value.status_code == 404
+    with pytest.raises(PageserverApiException, match="no tenant with ID or alias") as exc:
+        get("production")
+    assert exc.value.status_code == 404
+
+    # An alias belongs to a single tenant
+    (other_tenant_id, _) = env.neon_cli.create_tenant()
+    with pytest.raises(PageserverApiException, match="already used by tenant") as exc:
+        ps_http.set_tenant_config(other_tenant_id, {"alias": "staging"})
+    assert exc.value.status_code == 409
+
+    # Aliases that look like IDs would be ambiguous
+    with pytest.raises(PageserverApiException, match="can't be told apart from an ID") as exc:
+        ps_http.set_tenant_config(other_tenant_id, {"alias": str(other_tenant_id)})
+    assert exc.value.status_code == 400
+
+    # Dropping the alias from the config releases it
+    ps_http.set_tenant_config(tenant_id, {})
+    ps_http.set_tenant_config(other_tenant_id, {"alias": "staging"})
+    assert get("staging")["id"] == str(other_tenant_id)
+
+
+def test_tenant_tags(neon_env_builder: NeonEnvBuilder):
+    """
+    The tenant tags allowed by the pageserver config slice the tagged metrics.
+    """
+    neon_env_builder.pageserver_config_override = 'tenant_tag_labels = ["tier"]'
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+
+    ps_http.set_tenant_config(tenant_id, {"tags": {"tier": "enterprise", "team": "storage"}})
+    assert ps_http.tenant_config(tenant_id).tenant_specific_overrides["tags"] == {
+        "tier": "enterprise",
+        "team": "storage",
+    }
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT generate_series(1, 1000) AS x")
+        endpoint.safe_psql("SELECT count(*) FROM t")
+
+    metrics = ps_http.get_metrics()
+    getpage = metrics.query_one(
+        "pageserver_tagged_getpage_requests_total", {"tag": "tier", "value": "enterprise"}
+    )
+    assert getpage.value > 0
+    wal = metrics.query_one(
+        "pageserver_tagged_wal_bytes_received_total", {"tag": "tier", "value": "enterprise"}
+    )
+    assert wal.value > 0
+    # Only the allowlisted tags become labels
+    assert metrics.query_all("pageserver_tagged_getpage_requests_total", {"tag": "team"}) == []
+
+    with pytest.raises(PageserverApiException, match="may only contain") as exc:
+        ps_http.set_tenant_config(tenant_id, {"tags": {"Tier": "enterprise"}})
+    assert exc.value.status_code == 400
+
+
+def test_tenant_config_if_match(neon_env_builder: NeonEnvBuilder):
+    """
+    Tenant config updates with an If-Match header only apply to the version they name.
+    """
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+
+    etag = ps_http.tenant_config_etag(tenant_id)
+    ps_http.set_tenant_config(tenant_id, {"gc_horizon": 1024}, if_match=etag)
+    new_etag = ps_http.tenant_config_etag(tenant_id)
+    assert new_etag != etag
+
+    # Somebody else changed the config since we read it
+    with pytest.raises(PageserverApiException, match="tenant config version is") as exc:
+        ps_http.set_tenant_config(tenant_id, {"gc_horizon": 2048}, if_match=etag)
+    assert exc.value.status_code == 412
+    assert ps_http.tenant_config(tenant_id).tenant_specific_overrides == {"gc_horizon": 1024}
+
+    ps_http.set_tenant_config(tenant_id, {"gc_horizon": 2048}, if_match="*")
+    assert ps_http.tenant_config(tenant_id).tenant_specific_overrides == {"gc_horizon": 2048}
+
+    # Without If-Match, the update applies whatever the version
+    ps_http.set_tenant_config(tenant_id, {"gc_horizon": 4096})
+    assert ps_http.tenant_config(tenant_id).tenant_specific_overrides == {"gc_horizon": 4096}
+    assert ps_http.tenant_config_etag(tenant_id) not in (etag, new_etag)
+
+
+def test_tenant_aliases_auth(neon_env_builder: NeonEnvBuilder):
+    """
+    Resolving an alias doesn't tell callers whether a tenant they may not access exists.
+    """
+    neon_env_builder.auth_enabled = True
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    pageserver_token = env.auth_keys.generate_pageserver_token()
+    ps_http = env.pageserver.http_client(pageserver_token)
+    ps_http.set_tenant_config(tenant_id, {"alias": "staging"})
+
+    def get_status(token: str, path: str) -> int:
+        client = env.pageserver.http_client(token)
+        res = client.get(f"http://localhost:{client.port}/v1/tenant/{path}")
+        return res.status_code
+
+    tenant_token = env.auth_keys.generate_tenant_token(tenant_id)
+    assert get_status(tenant_token, "staging") == 200
+
+    other_tenant_token = env.auth_keys.generate_tenant_token(TenantId.generate())
+    assert get_status(other_tenant_token, "staging") == 403
+    assert get_status(other_tenant_token, "production") == 403
+    assert get_status(pageserver_token, "production") == 404
+
+
+def test_tenant_aliases_sharded(neon_env_builder: NeonEnvBuilder):
+    """
+    The shards of a tenant on the same pageserver are addressed by appending their suffix to its
+    alias.
+    """
+    env = neon_env_builder.init_start(initial_tenant_shard_count=2)
+    ps_http = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+    env.storage_controller.pageserver_api().set_tenant_config(tenant_id, {"alias": "staging"})
+
+    def get(path: str) -> Any:
+        res = ps_http.get(f"http://localhost:{ps_http.port}/v1/tenant/{path}")
+        ps_http.verbose_error(res)
+        return res.json()
+
+    assert get("staging-0102")["id"] == f"{tenant_id}-0102"
+    with pytest.raises(PageserverApiException, match="add the shard suffix") as exc:
+        get("staging")
+    assert exc.value.status_code == 400
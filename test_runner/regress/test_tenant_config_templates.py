@@ -0,0 +1,62 @@
+import pytest
+from fixtures.common_types import TenantId
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.http import PageserverApiException
+
+
+# Check that tenants take the fields they don't set from their template, pinned to the
+# version they were created from, also through the storage controller, and that the templates
+# survive a restart.
+def test_tenant_config_templates(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+
+    v1 = ps_http.tenant_config_template_put(
+        "free-tier", {"gc_horizon": 1024, "pitr_interval": "1d"}
+    )
+    assert v1["version"] == 1
+
+    tenant_id = TenantId.generate()
+    env.pageserver.tenant_create(tenant_id, conf={"template": "free-tier", "gc_horizon": 2048})
+    config = ps_http.tenant_config(tenant_id)
+    assert config.tenant_specific_overrides["template"] == "free-tier@1"
+    assert config.effective_config["gc_horizon"] == 2048
+    assert config.effective_config["pitr_interval"] == "1day"
+    assert config.template_overrides == {"gc_horizon": {"template": 1024, "tenant": 2048}}
+
+    # A new version doesn't change the tenants of the old one.
+    v2 = ps_http.tenant_config_template_put("free-tier", {"pitr_interval": "2d"})
+    assert v2["version"] == 2
+    assert ps_http.tenant_config(tenant_id).effective_config["pitr_interval"] == "1day"
+
+    # The storage controller resolves the template once, and stores the resolved config, so
+    # that moving the tenant doesn't change it.
+    sc_tenant_id = TenantId.generate()
+    env.storage_controller.tenant_create(sc_tenant_id, tenant_config={"template": "free-tier"})
+    config = ps_http.tenant_config(sc_tenant_id)
+    assert config.tenant_specific_overrides["template"] == "free-tier@2"
+    assert config.effective_config["pitr_interval"] == "2days"
+
+    # Location configs must reference a version.
+    with pytest.raises(PageserverApiException) as exc:
+        ps_http.tenant_location_conf(
+            TenantId.generate(),
+            {
+                "mode": "AttachedSingle",
+                "secondary_conf": None,
+                "tenant_conf": {"template": "free-tier"},
+                "generation": 1,
+            },
+        )
+    assert exc.value.status_code == 400
+
+    with pytest.raises(PageserverApiException) as exc:
+        ps_http.tenant_config_template_put("enterprise", {"alias": "prod"})
+    assert exc.value.status_code == 400
+    with pytest.raises(PageserverApiException) as exc:
+        env.pageserver.tenant_create(TenantId.generate(), conf={"template": "enterprise"})
+    assert exc.value.status_code == 404
+
+    env.pageserver.restart()
+    assert [t["version"] for t in ps_http.tenant_config_template("free-tier")] == [1, 2]
+    assert [t["name"] for t in ps_http.tenant_config_templates()] == ["free-tier"]
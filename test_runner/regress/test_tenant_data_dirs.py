@@ -0,0 +1,53 @@
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.utils import wait_until_tenant_active
+from fixtures.types import TenantId, TimelineId
+from fixtures.utils import wait_until
+from fixtures.workload import Workload
+
+
+# Check that tenants are spread across the configured data dirs and linked from the tenants
+# directory, that they are loaded from there after a restart, and that their data is removed
+# with them.
+def test_tenant_data_dirs(neon_env_builder: NeonEnvBuilder):
+    data_dirs = [neon_env_builder.repo_dir / f"disk{i}" for i in range(2)]
+    neon_env_builder.pageserver_config_override = f"""
+tenant_data_dirs = [{", ".join(f'"{d}"' for d in data_dirs)}]
+tenant_data_dir_placement = "round-robin"
+"""
+    env = neon_env_builder.init_start()
+    ps_http = env.pageserver.http_client()
+
+    tenants = [env.initial_tenant]
+    for _ in range(3):
+        tenant_id, _ = env.neon_cli.create_tenant(TenantId.generate(), TimelineId.generate())
+        tenants.append(tenant_id)
+
+    def data_dir_of(tenant_id: TenantId):
+        tenant_dir = env.pageserver.tenant_dir(tenant_id)
+        assert tenant_dir.is_symlink()
+        return tenant_dir.resolve().parent
+
+    placements = {tenant_id: data_dir_of(tenant_id) for tenant_id in tenants}
+    assert set(placements.values()) == set(data_dirs)
+
+    workload = Workload(env, env.initial_tenant, env.initial_timeline)
+    workload.init(env.pageserver.id)
+    workload.write_rows(1000, env.pageserver.id)
+    ps_http.timeline_checkpoint(env.initial_tenant, env.initial_timeline)
+
+    env.pageserver.restart()
+    for tenant_id in tenants:
+        wait_until_tenant_active(ps_http, tenant_id)
+        assert data_dir_of(tenant_id) == placements[tenant_id]
+    workload.validate(env.pageserver.id)
+
+    # The data of a detached tenant is removed from its data dir.
+    detached = tenants[1]
+    target = env.pageserver.tenant_dir(detached).resolve()
+    env.pageserver.tenant_detach(detached)
+    assert not env.pageserver.tenant_dir(detached).exists()
+
+    def removed():
+        assert not target.exists()
+
+    wait_until(20, 0.5, removed)
@@ -793,3 +793,58 @@ def test_metrics_while_ignoring_broken_tenant_and_reloading(
         assert only_int(active) == 1 and len(broken_set) == 0
 
     wait_until(10, 0.5, found_active)
+
+
+def test_tenant_detach_keep_local(neon_env_builder: NeonEnvBuilder):
+    """
+    Detaching with keep_local leaves the tenant's local data in place: it is not loaded
+    on restart, is reused by a re-attach, and is purged after detached_tenant_retention.
+    """
+    env = neon_env_builder.init_start()
+    client = env.pageserver.http_client()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql_many(
+            queries=[
+                "CREATE TABLE t(key int primary key, value text)",
+                "INSERT INTO t SELECT generate_series(1,100000), 'payload'",
+            ]
+        )
+        current_lsn = Lsn(query_scalar(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")))
+    wait_for_last_record_lsn(client, tenant_id, timeline_id, current_lsn)
+    client.timeline_checkpoint(tenant_id, timeline_id)
+    wait_for_upload(client, tenant_id, timeline_id, current_lsn)
+
+    timeline_dir = env.pageserver.timeline_dir(tenant_id, timeline_id)
+    local_layers = {p.name for p in timeline_dir.iterdir()}
+
+    client.tenant_detach(tenant_id, keep_local=True)
+    assert tenant_id not in [TenantId(t["id"]) for t in client.tenant_list()]
+    detached_mark = env.pageserver.tenant_dir(tenant_id) / "___detached_tenant"
+    assert detached_mark.exists()
+
+    # The kept data is not loaded on restart
+    env.pageserver.restart()
+    assert tenant_id not in [TenantId(t["id"]) for t in client.tenant_list()]
+    assert detached_mark.exists()
+
+    # Re-attach picks the local layers up
+    env.pageserver.tenant_attach(tenant_id)
+    wait_until_tenant_state(client, tenant_id, "Active", 10)
+    assert not detached_mark.exists()
+    assert local_layers <= {p.name for p in timeline_dir.iterdir()}
+
+    # Detach again with a short retention: the janitor purges the data
+    env.pageserver.stop()
+    env.pageserver.patch_config_toml_nonrecursive({"detached_tenant_retention": "1s"})
+    env.pageserver.start()
+    wait_until_tenant_state(client, tenant_id, "Active", 10)
+    client.tenant_detach(tenant_id, keep_local=True)
+
+    def tenant_dir_purged():
+        assert not env.pageserver.tenant_dir(tenant_id).exists()
+
+    wait_until(20, 0.5, tenant_dir_purged)
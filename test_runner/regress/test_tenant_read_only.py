@@ -0,0 +1,78 @@
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.utils import wait_for_last_record_lsn, wait_until_tenant_active
+from fixtures.types import Lsn, TenantShardId
+from fixtures.utils import wait_until
+
+
+# Check that a read-only tenant rejects WAL but serves reads, that the mode survives config
+# updates and restarts, and that the tenant catches up once it is unset.
+def test_tenant_read_only(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+    # The mode is set through the storage controller, which carries it in the tenant config.
+    controller_http = env.storage_controller.pageserver_api()
+
+    def last_record_lsn() -> Lsn:
+        return Lsn(ps_http.timeline_detail(tenant_id, timeline_id)["last_record_lsn"])
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 1000) g")
+        read_only_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+        controller_http.tenant_read_only_set(tenant_id, "billing")
+        config = ps_http.tenant_config(tenant_id)
+        assert config.tenant_specific_overrides["read_only"] == "billing"
+
+        # The walreceiver disconnects without waiting for more WAL.
+        def disconnected():
+            env.pageserver.assert_log_contains("tenant set read-only, disconnecting")
+
+        wait_until(20, 0.5, disconnected)
+
+        endpoint.safe_psql("INSERT INTO t SELECT g FROM generate_series(1, 1000) g")
+        flush_lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+        assert last_record_lsn() < flush_lsn
+
+        # Reads as of the last ingested LSN are served.
+        with env.endpoints.create_start(
+            "main", tenant_id=tenant_id, lsn=read_only_lsn
+        ) as static_endpoint:
+            assert static_endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 1000
+
+        # Neither config updates that don't set the mode nor restarts lift it.
+        controller_http.set_tenant_config(tenant_id, {"gc_horizon": 1024 * 1024})
+        env.pageserver.restart()
+        wait_until_tenant_active(ps_http, tenant_id)
+        config = ps_http.tenant_config(tenant_id)
+        assert config.tenant_specific_overrides["read_only"] == "billing"
+        assert last_record_lsn() < flush_lsn
+
+        controller_http.tenant_read_only_unset(tenant_id)
+        assert "read_only" not in ps_http.tenant_config(tenant_id).tenant_specific_overrides
+        wait_for_last_record_lsn(ps_http, tenant_id, timeline_id, flush_lsn)
+        assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 2000
+
+
+# Check that the read-only mode follows the tenant when the storage controller migrates it.
+def test_tenant_read_only_migration(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_pageservers = 2
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    controller_http = env.storage_controller.pageserver_api()
+
+    controller_http.tenant_read_only_set(tenant_id, "billing")
+
+    origin = env.get_tenant_pageserver(tenant_id)
+    assert origin is not None
+    dest = next(ps for ps in env.pageservers if ps.id != origin.id)
+    env.storage_controller.tenant_shard_migrate(TenantShardId(tenant_id, 0, 0), dest.id)
+    env.storage_controller.reconcile_until_idle()
+
+    config = dest.http_client().tenant_config(tenant_id)
+    assert config.tenant_specific_overrides["read_only"] == "billing"
+
+    controller_http.tenant_read_only_unset(tenant_id)
+    config = dest.http_client().tenant_config(tenant_id)
+    assert "read_only" not in config.tenant_specific_overrides
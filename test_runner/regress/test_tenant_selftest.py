@@ -0,0 +1,39 @@
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.remote_storage import LocalFsStorage, RemoteStorageKind
+
+
+# Check that the selftest runs all its stages, and leaves no file behind, neither locally nor
+# in remote storage.
+def test_tenant_selftest(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.LOCAL_FS)
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    ps_http = env.pageserver.http_client()
+    timelines_dir = env.pageserver.tenant_dir(tenant_id) / "timelines"
+    local_timelines = set(timelines_dir.iterdir())
+    walredo_before = ps_http.get_metric_value("pageserver_wal_redo_records_histogram_count") or 0
+
+    result = ps_http.tenant_selftest(tenant_id)
+    assert [s["name"] for s in result["stages"]] == ["ingest", "read"]
+    # The records were replayed by the walredo process.
+    walredo_after = ps_http.get_metric_value("pageserver_wal_redo_records_histogram_count") or 0
+    assert walredo_after > walredo_before
+
+    result = ps_http.tenant_selftest(tenant_id, remote=True)
+    assert [s["name"] for s in result["stages"]] == [
+        "ingest",
+        "read",
+        "upload",
+        "download",
+        "remote_delete",
+    ]
+    scratch_timeline_id = result["timeline_id"]
+    assert scratch_timeline_id not in [t["timeline_id"] for t in ps_http.timeline_list(tenant_id)]
+
+    assert set(timelines_dir.iterdir()) == local_timelines
+    assert isinstance(env.pageserver_remote_storage, LocalFsStorage)
+    selftest_dir = env.pageserver_remote_storage.tenant_path(tenant_id) / "selftest"
+    assert not selftest_dir.exists() or list(selftest_dir.iterdir()) == []
+    assert not (
+        env.pageserver_remote_storage.tenant_path(tenant_id) / "timelines" / scratch_timeline_id
+    ).exists()
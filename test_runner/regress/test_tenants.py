@@ -231,6 +231,14 @@ def test_metrics_normal_work(neon_env_builder: NeonEnvBuilder):
         value = ps_metrics.query_all(metric, filter={"operation": "layer flush"})
         assert value
 
+    # Test that layer flushes are broken down by phase
+    for phase in ["write", "fsync_layers", "fsync_dir"]:
+        value = ps_metrics.query_all(
+            "pageserver_layer_write_phase_seconds_count",
+            filter={"operation": "layer flush", "phase": phase},
+        )
+        assert value, f"no layer flush samples for phase {phase}"
+
 
 def test_pageserver_metrics_removed_after_detach(neon_env_builder: NeonEnvBuilder):
     """Tests that when a tenant is detached, the tenant specific metrics are not left behind"""
@@ -0,0 +1,37 @@
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.pageserver.utils import wait_until_tenant_active
+
+
+# Check that the page reads and the ingest of a timeline are counted in its hourly access log,
+# that an unused branch has none, and that the access log survives a restart.
+def test_timeline_access_log(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+
+    unused = env.neon_cli.create_branch("unused", tenant_id=tenant_id)
+
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    # A new compute reads the table from the pageserver.
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 10000
+
+    log = ps_http.timeline_access_log(tenant_id, timeline_id)
+    assert len(log["hours"]) > 0
+    assert sum(h["pages_read"] for h in log["hours"]) > 0
+    assert sum(h["bytes_ingested"] for h in log["hours"]) > 0
+    assert log["hours"][-1]["unique_keys"] > 0
+
+    assert ps_http.timeline_access_log(tenant_id, unused)["hours"] == []
+
+    env.pageserver.restart()
+    wait_until_tenant_active(ps_http, tenant_id)
+    restored = ps_http.timeline_access_log(tenant_id, timeline_id)
+    # The restart may have crossed into a new hour with new accesses, but not lost any.
+    for before, after in zip(log["hours"], restored["hours"]):
+        assert before["hour"] == after["hour"]
+        assert after["pages_read"] >= before["pages_read"]
+        assert after["bytes_ingested"] >= before["bytes_ingested"]
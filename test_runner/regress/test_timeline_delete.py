@@ -254,14 +254,9 @@ def test_delete_timeline_exercise_crash_safety_failpoints(
 
         wait_until_tenant_active(ps_http, env.initial_tenant, iterations=iterations)
 
-        if failpoint == "timeline-delete-before-index-deleted-at":
-            # We crashed before persisting this to remote storage, need to retry delete request
-            timeline_delete_wait_completed(ps_http, env.initial_tenant, timeline_id)
-        else:
-            # Pageserver should've resumed deletion after restart.
-            wait_timeline_detail_404(
-                ps_http, env.initial_tenant, timeline_id, iterations=iterations
-            )
+        # Pageserver should've resumed deletion after restart, even if it was interrupted
+        # before reaching remote storage: the local delete mark records it.
+        wait_timeline_detail_404(ps_http, env.initial_tenant, timeline_id, iterations=iterations)
 
     elif check is Check.RETRY_WITHOUT_RESTART:
         # this should succeed
@@ -384,7 +379,7 @@ def test_timeline_resurrection_on_attach(
 
 def test_timeline_delete_fail_before_local_delete(neon_env_builder: NeonEnvBuilder):
     """
-    When deleting a timeline, if we succeed in setting the deleted flag remotely
+    When deleting a timeline, if we succeed in deleting the remote state
     but fail to delete the local state, restarting the pageserver should resume
     the deletion of the local state.
     """
@@ -558,6 +553,11 @@ def test_concurrent_timeline_delete_stuck_on(
         # ensure it is not 404 and stopping
         detail = ps_http.timeline_detail(env.initial_tenant, child_timeline_id)
         assert detail["state"] == "Stopping"
+        expected_step = {
+            "persist_deleted_index_part": "delete_remote",
+            "in_progress_delete": "forget",
+        }[stuck_failpoint]
+        assert detail["deletion_progress"]["step"] == expected_step
 
         # by now we know that the second call failed, let's ensure the first call will finish
         ps_http.configure_failpoints((stuck_failpoint, "off"))
@@ -801,7 +801,7 @@ def test_timeline_delete_resumed_on_attach(
         )
 
     # failpoint before we remove index_part from s3
-    failpoint = "timeline-delete-after-rm"
+    failpoint = "timeline-delete-before-index-delete"
     ps_http.configure_failpoints((failpoint, "return"))
 
     env.pageserver.allowed_errors.extend(
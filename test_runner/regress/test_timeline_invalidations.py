@@ -0,0 +1,32 @@
+from fixtures.neon_fixtures import NeonEnv
+from fixtures.types import Lsn
+
+
+def test_timeline_invalidations(neon_simple_env: NeonEnv):
+    """
+    The pageserver streams the keys changed by ingest, so that caches can invalidate them.
+    """
+    env = neon_simple_env
+    client = env.pageserver.http_client()
+    endpoint = env.endpoints.create_start("main")
+
+    endpoint.safe_psql("CREATE TABLE t (i int)")
+    dbnode, relnode = endpoint.safe_psql(
+        "SELECT d.oid, pg_relation_filenode('t') FROM pg_database d WHERE datname = 'postgres'"
+    )[0]
+    # Block 0 of the main fork of the table, in the default tablespace.
+    block0 = int(f"00{1663:08x}{dbnode:08x}{relnode:08x}00{0:08x}", 16)
+
+    invalidations = client.timeline_invalidations(env.initial_tenant, env.initial_timeline)
+    endpoint.safe_psql("INSERT INTO t VALUES (1)")
+    insert_lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+
+    for invalidation in invalidations:
+        if any(
+            int(key_range["start"], 16) <= block0 < int(key_range["end"], 16)
+            for key_range in invalidation["key_ranges"]
+        ):
+            assert Lsn(invalidation["lsn"]) <= insert_lsn
+            break
+    else:
+        raise AssertionError("no invalidation of the inserted block")
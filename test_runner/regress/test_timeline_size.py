@@ -345,6 +345,49 @@ def test_timeline_initial_logical_size_calculation_cancellation(
     # matter because it's a pausable_failpoint, which can be cancelled by drop.
 
 
+def test_timeline_logical_size_checkpoint(neon_env_builder: NeonEnvBuilder):
+    """
+    The logical size is checkpointed in the index part when layers are flushed, so that it
+    doesn't need to be calculated again after a restart.
+    """
+    env = neon_env_builder.init_start()
+    client = env.pageserver.http_client()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+    endpoint.safe_psql_many(
+        [
+            "CREATE TABLE foo (x INTEGER)",
+            "INSERT INTO foo SELECT g FROM generate_series(1, 10000) g",
+        ]
+    )
+    wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+    endpoint.stop()
+    client.timeline_checkpoint(tenant_id, timeline_id)
+    wait_for_upload_queue_empty(client, tenant_id, timeline_id)
+    size_before_restart = client.timeline_detail(tenant_id, timeline_id)["current_logical_size"]
+
+    # The calculation would hang on the failpoint: the size must come from the checkpoint.
+    env.pageserver.stop()
+    env.pageserver.start(
+        extra_env_vars={"FAILPOINTS": "timeline-calculate-logical-size-pause=pause"}
+    )
+    wait_until_tenant_active(client, tenant_id)
+
+    details = client.timeline_detail(tenant_id, timeline_id)
+    assert details["current_logical_size_is_accurate"]
+    assert details["current_logical_size"] == size_before_restart
+    assert (
+        client.get_metric_value(
+            "pageserver_initial_logical_size_start_calculation_total",
+            {"attempt": "first", "circumstances": "FromCheckpoint"},
+        )
+        or 0
+    ) > 0
+
+
 def test_timeline_physical_size_init(neon_env_builder: NeonEnvBuilder):
     neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.LOCAL_FS)
 
@@ -1,10 +1,13 @@
 import filecmp
+import hashlib
+import io
 import os
 import random
 import shutil
 import signal
 import subprocess
 import sys
+import tarfile
 import threading
 import time
 from contextlib import closing
@@ -17,6 +20,7 @@ import psycopg2
 import psycopg2.errors
 import psycopg2.extras
 import pytest
+import requests
 from fixtures.broker import NeonBroker
 from fixtures.log_helper import log
 from fixtures.metrics import parse_metrics
@@ -30,6 +34,7 @@ from fixtures.neon_fixtures import (
     Safekeeper,
     SafekeeperPort,
     last_flush_lsn_upload,
+    wait_for_last_flush_lsn,
 )
 from fixtures.pageserver.utils import (
     assert_prefix_empty,
@@ -48,7 +53,7 @@ from fixtures.remote_storage import (
 from fixtures.safekeeper.http import SafekeeperHttpClient
 from fixtures.safekeeper.utils import are_walreceivers_absent
 from fixtures.types import Lsn, TenantId, TimelineId
-from fixtures.utils import get_dir_size, query_scalar, start_in_background
+from fixtures.utils import get_dir_size, query_scalar, start_in_background, wait_until
 
 
 def wait_lsn_force_checkpoint(
@@ -327,6 +332,63 @@ def test_broker(neon_env_builder: NeonEnvBuilder):
     assert all([s.remote_consistent_lsn >= new_rcl for s in stat_after_restart])
 
 
+# Test that brokers relay messages through each other, and that the pageserver fails over to
+# another broker of the cluster when one is killed.
+def test_broker_cluster_failover(
+    neon_env_builder: NeonEnvBuilder,
+    port_distributor: PortDistributor,
+    test_output_dir: Path,
+    neon_binpath: Path,
+):
+    env = neon_env_builder.init_start()
+
+    # The brokers form a chain, not a full mesh: safekeepers publish to the first one, and the
+    # last one gets their messages through the one in between.
+    brokers = [env.broker]
+    for i in range(1, 3):
+        broker = NeonBroker(
+            logfile=test_output_dir / f"storage_broker_{i}.log",
+            port=port_distributor.get_port(),
+            neon_binpath=neon_binpath,
+            peers=[brokers[-1].client_url()],
+        )
+        broker.try_start()
+        brokers.append(broker)
+
+    def relayed_twice():
+        metrics = parse_metrics(requests.get(f"{brokers[2].client_url()}/metrics").text)
+        assert metrics.query_one("storage_broker_relayed_messages_total").value > 0
+
+    try:
+        # Safekeepers publish the state of the timelines with a compute only.
+        with env.endpoints.create_start("main") as endpoint:
+            endpoint.safe_psql("CREATE TABLE t(x int)")
+            wait_until(20, 0.5, relayed_twice)
+
+        env.pageserver.stop()
+        env.pageserver.patch_config_toml_nonrecursive(
+            {
+                "broker_endpoint": brokers[2].client_url(),
+                "broker_fallback_endpoints": [brokers[1].client_url()],
+            }
+        )
+        env.pageserver.start()
+
+        # New timelines need the broker to discover the safekeepers.
+        def ingest(branch_name: str):
+            timeline_id = env.neon_cli.create_branch(branch_name, "main")
+            with env.endpoints.create_start(branch_name) as endpoint:
+                endpoint.safe_psql("CREATE TABLE t AS SELECT generate_series(1, 10000) AS x")
+                wait_for_last_flush_lsn(env, endpoint, env.initial_tenant, timeline_id)
+
+        ingest("before_kill")
+        brokers[2].stop(immediate=True)
+        ingest("after_kill")
+    finally:
+        for broker in brokers[1:]:
+            broker.stop()
+
+
 # Test that old WAL consumed by peers and pageserver is removed from safekeepers.
 @pytest.mark.parametrize("auth_enabled", [False, True])
 def test_wal_removal(neon_env_builder: NeonEnvBuilder, auth_enabled: bool):
@@ -517,6 +579,55 @@ def test_wal_backup(neon_env_builder: NeonEnvBuilder):
     assert_prefix_empty(neon_env_builder.safekeepers_remote_storage, prefix)
 
 
+def test_wal_local_horizon(neon_env_builder: NeonEnvBuilder):
+    """
+    With --local-wal-horizon-bytes, offloaded WAL is removed from the safekeeper's disk
+    even though the pageserver hasn't persisted it yet, and is served from remote storage.
+    """
+    neon_env_builder.enable_safekeeper_remote_storage(default_remote_storage())
+
+    # Keep remote_consistent_lsn from advancing, which would allow WAL removal anyway.
+    env = neon_env_builder.init_start(
+        initial_tenant_conf={"checkpoint_distance": f"{1024 ** 3}", "checkpoint_timeout": "1h"}
+    )
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    sk = env.safekeepers[0]
+    sk.stop()
+    sk.start(extra_opts=["--local-wal-horizon-bytes=0"])
+
+    endpoint = env.endpoints.create_start("main")
+    with closing(endpoint.connect()) as conn:
+        with conn.cursor() as cur:
+            cur.execute("create table t(key int, value text)")
+            # roughly fills two segments
+            cur.execute("insert into t select generate_series(1,500000), 'payload'")
+    last_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    offloaded_seg_end = Lsn("0/3000000")
+    wait(
+        partial(is_segment_offloaded, sk, tenant_id, timeline_id, offloaded_seg_end),
+        f"segment ending at {offloaded_seg_end} get offloaded",
+    )
+    target_size_mb = 32 * 1.5
+    wait(
+        partial(is_wal_trimmed, sk, tenant_id, timeline_id, target_size_mb),
+        f"sk_id={sk.id} to trim WAL to {target_size_mb:.2f}MB",
+    )
+
+    # The pageserver loses its in-memory layer and has to re-ingest the evicted WAL.
+    env.pageserver.stop(immediate=True)
+    env.pageserver.start()
+    wait_for_last_record_lsn(env.pageserver.http_client(), tenant_id, timeline_id, last_lsn)
+
+    metrics = parse_metrics(sk.http_client().get_metrics_str())
+    remote_reads = metrics.query_one(
+        "safekeeper_wal_reader_segment_opens_total", {"tier": "remote"}
+    ).value
+    assert remote_reads > 0
+
+
 def test_s3_wal_replay(neon_env_builder: NeonEnvBuilder):
     neon_env_builder.num_safekeepers = 3
 
@@ -1812,6 +1923,89 @@ def test_pull_timeline(neon_env_builder: NeonEnvBuilder):
     show_statuses(env.safekeepers, tenant_id, timeline_id)
 
 
+# Replace safekeeper 3 with safekeeper 4 in the timeline's membership, going through
+# the joint configuration, while compute keeps writing.
+def test_membership_change(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 4
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_membership_change")
+
+    # The compute is connected to all safekeepers, and stays up while the membership
+    # changes from [1, 2, 3] to [3, 4] under it.
+    endpoint = env.endpoints.create_start("test_membership_change")
+    endpoint.safe_psql("CREATE TABLE t(key int, value text)")
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,10000), 'payload'")
+
+    def switch(sk_ids: List[int], mconf: Dict[str, Any]):
+        for sk in env.safekeepers:
+            if sk.id in sk_ids:
+                sk.http_client().timeline_membership_switch(tenant_id, timeline_id, mconf)
+
+    # Safekeepers close compute connections on a switch; the compute learns the new
+    # configuration from the greeting when it reconnects.
+    def wait_compute_generation(generation: int):
+        def check():
+            with open(endpoint.endpoint_path() / "compute.log") as f:
+                assert f"switching to membership configuration generation {generation}" in f.read()
+
+        wait_until(30, 1, check)
+
+    old_conf = {"generation": 1, "members": [1, 2, 3]}
+    joint_conf = {"generation": 2, "members": [1, 2, 3], "new_members": [3, 4]}
+    new_conf = {"generation": 3, "members": [3, 4]}
+    switch([1, 2, 3], old_conf)
+    wait_compute_generation(1)
+
+    # removing a member without the joint configuration is refused
+    with pytest.raises(SafekeeperHttpClient.HTTPError, match="409"):
+        switch([1], {"generation": 2, "members": [3, 4]})
+
+    switch([1, 2, 3, 4], joint_conf)
+    wait_compute_generation(2)
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,10000), 'payload'")
+
+    # Three of the four safekeepers the compute is connected to are a majority of them,
+    # but not a quorum of the joint configuration: commit waits for the new members.
+    env.safekeepers[3].stop()
+    insert = threading.Thread(
+        target=endpoint.safe_psql,
+        args=("INSERT INTO t SELECT generate_series(1,10000), 'payload'",),
+    )
+    insert.start()
+    time.sleep(5)
+    assert insert.is_alive(), "commit without a quorum of the new members"
+    env.safekeepers[3].start()
+    insert.join(timeout=30)
+    assert not insert.is_alive()
+
+    # Leaving the joint configuration needs a quorum of the new members to be caught up,
+    # which they report through the broker.
+    def switch_to_new():
+        switch([1, 2, 3, 4], new_conf)
+
+    wait_until(30, 1, switch_to_new)
+    wait_compute_generation(3)
+
+    # generation can't go back
+    with pytest.raises(SafekeeperHttpClient.HTTPError, match="409"):
+        switch([1], joint_conf)
+
+    # all safekeepers agree on the new configuration, which works without the removed
+    # ones, although they are the majority of the safekeepers the compute is connected to
+    for sk in env.safekeepers:
+        http_cli = sk.http_client()
+        mconf = http_cli.get(
+            f"http://localhost:{sk.port.http}/v1/tenant/{tenant_id}/timeline/{timeline_id}"
+        ).json()["mconf"]
+        assert mconf["generation"] == 3
+        assert mconf["members"] == [3, 4]
+    env.safekeepers[0].stop()
+    env.safekeepers[1].stop()
+    endpoint.safe_psql("INSERT INTO t SELECT generate_series(1,10000), 'payload'")
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 40000
+
+
 # In this test we check for excessive START_REPLICATION and START_WAL_PUSH queries
 # when compute is active, but there are no writes to the timeline. In that case
 # pageserver should maintain a single connection to safekeeper and don't attempt
@@ -1961,6 +2155,41 @@ def test_timeline_copy(neon_env_builder: NeonEnvBuilder, insert_rows: int):
     # TODO: test timelines can start after copy
 
 
+def test_wal_export(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 1
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("create table t(key int, value text)")
+    endpoint.safe_psql("insert into t select generate_series(1, 100000), repeat('payload!', 10)")
+
+    sk_http = env.safekeepers[0].http_client()
+    status = sk_http.timeline_status(tenant_id, timeline_id)
+    from_lsn = status.timeline_start_lsn + 100
+    until_lsn = status.commit_lsn
+
+    exported = sk_http.timeline_export_wal(tenant_id, timeline_id, from_lsn, until_lsn)
+    segment_size = 16 * 1024 * 1024
+    with tarfile.open(fileobj=io.BytesIO(exported)) as tar:
+        members = tar.getmembers()
+        names = [m.name for m in members]
+        assert names == sorted(names)
+        assert all(m.size == segment_size for m in members)
+        wal = b"".join(tar.extractfile(m).read() for m in members)  # type: ignore
+    # The segments cover the range, and the WAL in them is the same as in the safekeeper.
+    start_lsn = Lsn(from_lsn.lsn_int - from_lsn.lsn_int % segment_size)
+    assert len(names) == (until_lsn - start_lsn + segment_size - 1) // segment_size
+    wal = wal[from_lsn - start_lsn : until_lsn - start_lsn]
+    digest = sk_http.timeline_digest(tenant_id, timeline_id, from_lsn, until_lsn)
+    assert hashlib.sha256(wal).hexdigest() == digest["sha256"]
+
+    # Only committed WAL can be exported.
+    with pytest.raises(requests.HTTPError, match="400 Client Error"):
+        sk_http.timeline_export_wal(tenant_id, timeline_id, from_lsn, until_lsn + 1024 * 1024)
+
+
 def test_patch_control_file(neon_env_builder: NeonEnvBuilder):
     neon_env_builder.num_safekeepers = 1
     env = neon_env_builder.init_start()
@@ -0,0 +1,31 @@
+from fixtures.neon_fixtures import NeonEnvBuilder
+from fixtures.pageserver.utils import wait_for_last_record_lsn
+from fixtures.types import Lsn
+
+
+# Replaying the capture is done with the `replay_wal_capture` unit test of the pageserver,
+# see pageserver/src/wal_capture.rs.
+def test_wal_capture(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start(
+        initial_tenant_conf={
+            "capture_wal": "true",
+        }
+    )
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql_many(
+        [
+            "create table t (i integer)",
+            "insert into t values (generate_series(1, 10000))",
+        ]
+    )
+    current_lsn = Lsn(endpoint.safe_psql("SELECT pg_current_wal_flush_lsn()")[0][0])
+    wait_for_last_record_lsn(env.pageserver.http_client(), tenant_id, timeline_id, current_lsn)
+
+    capture_path = env.pageserver.workdir / "wal_captures" / str(tenant_id) / str(timeline_id)
+    captures = list(capture_path.iterdir())
+    assert len(captures) > 0
+    # the capture is flushed on each commit of the ingested records
+    assert sum(capture.stat().st_size for capture in captures) > 0
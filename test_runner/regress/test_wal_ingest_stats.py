@@ -0,0 +1,28 @@
+from fixtures.neon_fixtures import NeonEnvBuilder, wait_for_last_flush_lsn
+
+
+# Check that the timeline info and the metrics break down the WAL ingest of a timeline.
+def test_wal_ingest_stats(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+    ps_http = env.pageserver.http_client()
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    wal_ingest = ps_http.timeline_detail(tenant_id, timeline_id)["wal_ingest"]
+    assert wal_ingest["bytes_received"] > 0
+    assert wal_ingest["records_ingested"] > 0
+    assert wal_ingest["apply_seconds"] > 0
+    assert wal_ingest["connections"] >= 1
+    assert wal_ingest["last_switch_reason"] is not None
+
+    metrics = ps_http.get_metrics()
+    filter = {"tenant_id": str(tenant_id), "timeline_id": str(timeline_id)}
+    for phase in ["decode", "apply", "commit"]:
+        sample = metrics.query_one(
+            "pageserver_wal_ingest_seconds_total", filter={**filter, "phase": phase}
+        )
+        assert sample.value > 0
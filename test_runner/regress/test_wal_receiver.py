@@ -1,7 +1,8 @@
 import time
 
 from fixtures.log_helper import log
-from fixtures.neon_fixtures import NeonEnv, NeonEnvBuilder
+from fixtures.neon_fixtures import NeonEnv, NeonEnvBuilder, wait_for_last_flush_lsn
+from fixtures.port_distributor import PortDistributor
 from fixtures.types import Lsn, TenantId
 
 
@@ -99,6 +100,35 @@ def test_pageserver_lsn_wait_error_safekeeper_stop(neon_env_builder: NeonEnvBuil
                 ), f"Should have safekeeper {safekeeper.id} printed in walreceiver state after 2nd WAL wait timeout"
 
 
+# Checks that the pageserver streams WAL over gRPC from the safekeepers which serve it,
+# with a flow control window much smaller than the inserted data.
+def test_wal_stream_over_grpc(neon_env_builder: NeonEnvBuilder, port_distributor: PortDistributor):
+    neon_env_builder.pageserver_config_override = (
+        'wal_receiver_protocol = { type = "grpc", compression = true, window_bytes = 65536 }'
+    )
+    neon_env_builder.num_safekeepers = 3
+    env = neon_env_builder.init_start()
+
+    for safekeeper in env.safekeepers:
+        safekeeper.stop()
+        safekeeper.start(extra_opts=[f"--listen-grpc=127.0.0.1:{port_distributor.get_port()}"])
+
+    tenant_id, timeline_id = env.neon_cli.create_tenant()
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        with endpoint.cursor() as cur:
+            cur.execute("CREATE TABLE t(key int primary key, value text)")
+            cur.execute(
+                "INSERT INTO t SELECT i, CONCAT('payload_', i) FROM generate_series(1, 100000) as i"
+            )
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    assert env.pageserver.log_contains("starting gRPC WAL stream from")
+
+    # Read the data back from the pageserver.
+    with env.endpoints.create_start("main", tenant_id=tenant_id) as endpoint:
+        assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 100000
+
+
 def insert_test_elements(env: NeonEnv, tenant_id: TenantId, start: int, count: int):
     first_element_id = start
     last_element_id = first_element_id + count
@@ -63,6 +63,7 @@ fn analyze_trace<R: std::io::Read>(mut reader: R) {
             PagestreamFeMessage::Exists(_) => {}
             PagestreamFeMessage::Nblocks(_) => {}
             PagestreamFeMessage::GetSlruSegment(_) => {}
+            PagestreamFeMessage::GetPageAtLeast(_) => {}
             PagestreamFeMessage::GetPage(req) => {
                 total += 1;
 